@@ -1,3 +1,36 @@
+// Mount path when served behind a reverse proxy under a prefix (e.g. /esp32), injected by the server
+const BASE_PATH = (document.querySelector('meta[name="base-path"]')?.content || '').replace(/\/+$/, '');
+window.BASE_PATH = BASE_PATH;
+
+// Prefix app-absolute URLs ("/api/...") with the base path
+function withBasePath(url) {
+    if (!BASE_PATH || typeof url !== 'string' || !url.startsWith('/') || url.startsWith('//')) {
+        return url;
+    }
+    if (url === BASE_PATH || url.startsWith(BASE_PATH + '/')) {
+        return url;
+    }
+    return BASE_PATH + url;
+}
+window.withBasePath = withBasePath;
+
+// Strip the base path, e.g. "/esp32/devices/x" -> "/devices/x"
+function stripBasePath(path) {
+    if (BASE_PATH && (path === BASE_PATH || path.startsWith(BASE_PATH + '/'))) {
+        return path.slice(BASE_PATH.length) || '/';
+    }
+    return path;
+}
+
+// Path of the current page inside the app (without base path)
+window.appPathname = () => stripBasePath(window.location.pathname);
+
+// All existing fetch('/api/...') calls keep working under a prefix
+if (BASE_PATH) {
+    const originalFetch = window.fetch.bind(window);
+    window.fetch = (input, init) => originalFetch(withBasePath(input), init);
+}
+
 // Information about available pages
 const pages = {
     'index': {
@@ -24,6 +57,14 @@ const pages = {
         styles: [],
         requiresAuth: false
     },
+    'reset_password': {
+        title: 'Passwort zurücksetzen',
+        template: 'reset_password.html',
+        defaultPath: 'reset-password.html',
+        scripts: ['reset_password.js'],
+        styles: [],
+        requiresAuth: false
+    },
     'debug': {
         title: 'Debug',
         template: 'debug.html',
@@ -145,11 +186,11 @@ async function renderPage() {
     const contentContainer = document.getElementById('content-container');
     
     // Analyze URL to determine the current page
-    const url = new URL(window.location.href);
-    let path = url.pathname.split('/').pop() || 'index.html';
+    const pathname = window.appPathname();
+    let path = pathname.split('/').pop() || 'index.html';
     
     // Handle root path
-    if (url.pathname === '/') {
+    if (pathname === '/') {
         path = 'index.html';
     }
     
@@ -161,6 +202,8 @@ async function renderPage() {
         pageName = 'login';
     } else if (path === 'register') {
         pageName = 'register';
+    } else if (path === 'reset-password') {
+        pageName = 'reset_password';
     } else if (path === 'docs') {
         pageName = 'docs';
     } else if (path === 'debug') {
@@ -173,13 +216,13 @@ async function renderPage() {
 
 
     // Handle device pages - redirect to device control
-    if (url.pathname.startsWith('/devices')) {
+    if (pathname.startsWith('/devices')) {
         pageName = 'device_control';
     }
     
     // Debug logging
     console.log('SPA Navigation Debug:', {
-        pathname: url.pathname,
+        pathname: pathname,
         path: path,
         pageName: pageName,
         pageExists: !!pages[pageName]
@@ -221,7 +264,7 @@ async function renderPage() {
     finalPageInfo.styles.forEach(style => {
         const styleLink = document.createElement('link');
         styleLink.rel = 'stylesheet';
-        styleLink.href = `/styles/${style}`;
+        styleLink.href = withBasePath(`/styles/${style}`);
         styleLink.setAttribute('data-dynamic-style', 'true');
         document.head.appendChild(styleLink);
     });
@@ -251,7 +294,7 @@ async function renderPage() {
         for (const scriptSrc of finalPageInfo.scripts) {
             await new Promise((resolve, reject) => {
                 const scriptElement = document.createElement('script');
-                scriptElement.src = `/scripts/${scriptSrc}`;
+                scriptElement.src = withBasePath(`/scripts/${scriptSrc}`);
                 scriptElement.setAttribute('data-dynamic-script', 'true');
                 scriptElement.onload = () => resolve();
                 scriptElement.onerror = (e) => reject(e);
@@ -271,9 +314,9 @@ async function navigateTo(url) {
   console.log(`NavigateTo called: ${window.location.pathname} → ${url}`);
   
   // Canvas cleanup when leaving canvas
-  const currentPath = window.location.pathname;
+  const currentPath = window.appPathname();
   const currentCanvasMatch = currentPath.match(/^\/canvas\/([^\/]+)$/);
-  const newCanvasMatch = url.match(/^\/canvas\/([^\/]+)$/);
+  const newCanvasMatch = stripBasePath(url).match(/^\/canvas\/([^\/]+)$/);
   
   const currentCanvasId = currentCanvasMatch ? currentCanvasMatch[1] : null;
   const newCanvasId = newCanvasMatch ? newCanvasMatch[1] : null;
@@ -296,7 +339,7 @@ async function navigateTo(url) {
   }
   
   // Update browser history
-  history.pushState(null, null, url);
+  history.pushState(null, null, withBasePath(url));
   // Render the new page
   renderPage();
   
@@ -12,7 +12,7 @@
 
 // Get device ID from URL parameter
 function getDeviceIdFromUrl() {
-    const pathParts = window.location.pathname.split('/');
+    const pathParts = (window.appPathname ? window.appPathname() : window.location.pathname).split('/');
     if (pathParts[1] === 'devices' && pathParts[2]) {
         return pathParts[2];
     }
@@ -216,7 +216,7 @@ async function loadAvailableDevices() {
 
 async function initializeWebSocket() {
     const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-    const wsUrl = `${protocol}//${window.location.host}/channel`;
+    const wsUrl = `${protocol}//${window.location.host}${window.BASE_PATH || ''}/channel`;
     
     try {
         deviceWebsocket = new WebSocket(wsUrl);
@@ -412,6 +412,7 @@ function registerAllDevicesLight() {
     console.log('Available devices:', availableDevices);
     console.log('Open tabs:', Array.from(openTabs));
 
+    const lightDeviceIds = [];
     availableDevices.forEach(device => {
         // Only register devices that are not already opened in tabs
         if (!openTabs.has(device.deviceId)) {
@@ -437,20 +438,73 @@ function registerAllDevicesLight() {
                 console.log('Created device object for light subscription:', device.deviceId);
             }
 
-            registerForDevice(device.deviceId, 'light');
+            lightDeviceIds.push(device.deviceId);
         } else {
             console.log('Skipping device (already in tab):', device.deviceId);
         }
     });
+
+    // One batch subscription message instead of one registration per device
+    if (lightDeviceIds.length > 0) {
+        subscribeDevices(lightDeviceIds, 'light');
+    }
+}
+
+function subscribeDevices(deviceIds, subscriptionType = 'light') {
+    if (deviceWebsocket && deviceWebsocket.readyState === WebSocket.OPEN) {
+        deviceWebsocket.send(JSON.stringify({
+            subscribe: deviceIds,
+            subscriptionType: subscriptionType
+        }));
+    } else {
+        console.error('WebSocket not ready, readyState:', deviceWebsocket?.readyState);
+    }
 }
 
 async function handleWebSocketMessage(message) {
-    if (message.deviceId && message.eventsForDevice) {
+    if (message.type === 'error') {
+        handleServerError(message);
+    } else if (message.type === 'deviceListChanged') {
+        // Sidebar names/aliases or available devices changed via the REST API
+        await loadAvailableDevices();
+    } else if (message.type === 'mention') {
+        showMentionNotification(message);
+    } else if (message.deviceId && message.eventsForDevice) {
         await handleDeviceEvents(message.deviceId, message.eventsForDevice);
     } else {
     }
 }
 
+// Error frame from the server: {type: 'error', code, message, requestId, deviceId}
+function handleServerError(error) {
+    console.error(`Server rejected request (${error.code}):`, error.message);
+
+    // Show it in the monitor of the affected device (or the one from the URL)
+    const deviceId = error.deviceId || getDeviceIdFromUrl();
+    const device = deviceId ? deviceDevices.get(deviceId) : null;
+    if (!device) {
+        return;
+    }
+
+    const messageObj = {
+        timestamp: new Date(),
+        rawMessage: JSON.stringify(error),
+        parsed: error,
+        category: 'ERROR',
+        categoryKey: 'message',
+        displayText: `${error.code}: ${error.message}`
+    };
+    device.udpMessages.push(messageObj);
+
+    const isNewCategory = !device.messageCategories.has(messageObj.category);
+    device.messageCategories.add(messageObj.category);
+    if (!device.messageFilters.has(messageObj.category)) {
+        device.messageFilters.set(messageObj.category, true);
+    }
+
+    appendToMonitor(deviceId, messageObj, isNewCategory);
+}
+
 async function handleDeviceEvents(deviceId, events) {
     // Ensure device exists in our UI
     if (!deviceDevices.has(deviceId)) {
@@ -785,11 +839,90 @@ function processDeviceEvent(deviceId, event) {
             }
             break;
 
+        case 'DeviceChatMessage':
+            appendChatToMonitor(deviceId, eventData, 'CHAT');
+            break;
+
+        case 'DeviceMention':
+            appendChatToMonitor(deviceId, eventData, 'MENTION');
+            break;
+
         default:
             console.log('Unknown device event type:', eventType, eventData);
     }
 }
 
+// Chat note or mention on the device channel: shown in the monitor under its own category
+function appendChatToMonitor(deviceId, message, category) {
+    const device = deviceDevices.get(deviceId);
+    if (!device) return;
+    if (!device.messageFilters) device.messageFilters = new Map();
+    if (!device.messageCategories) device.messageCategories = new Set();
+
+    const author = message.displayName || message.fromDisplayName;
+    const messageObj = {
+        timestamp: message.sentAt ? new Date(message.sentAt) : new Date(),
+        rawMessage: JSON.stringify(message),
+        parsed: message,
+        category: category,
+        categoryKey: category.toLowerCase(),
+        displayText: `${author}: ${message.text}`
+    };
+    device.udpMessages.push(messageObj);
+
+    const isNewCategory = !device.messageCategories.has(messageObj.category);
+    device.messageCategories.add(messageObj.category);
+    if (!device.messageFilters.has(messageObj.category)) {
+        device.messageFilters.set(messageObj.category, true);
+    }
+    appendToMonitor(deviceId, messageObj, isNewCategory);
+}
+
+// The user was mentioned (also for devices that are not open): browser notification or toast
+function showMentionNotification(mention) {
+    const title = `${mention.fromDisplayName} mentioned you on ${mention.deviceId}`;
+    if ('Notification' in window && Notification.permission === 'granted') {
+        new Notification(title, { body: mention.text });
+        return;
+    }
+
+    const toast = document.createElement('div');
+    toast.className = 'alert alert-info shadow position-fixed bottom-0 end-0 m-3';
+    toast.style.zIndex = 2000;
+    toast.innerHTML = `<strong>${escapeHtml(title)}</strong><div>${escapeHtml(mention.text)}</div>`;
+    toast.onclick = () => toast.remove();
+    document.body.appendChild(toast);
+    setTimeout(() => toast.remove(), 10000);
+}
+
+// Post a note to the device channel; notes with an @handle go out as mentions
+function sendChatMessage(idPrefix, deviceId) {
+    const input = document.getElementById(`${idPrefix}-chat-input`);
+    const text = input ? input.value.trim() : '';
+    if (!text || !deviceWebsocket || deviceWebsocket.readyState !== WebSocket.OPEN) {
+        return;
+    }
+    const isMention = /(^|[^\w.])@[A-Za-z]\w{2,29}/.test(text);
+    deviceWebsocket.send(JSON.stringify({
+        type: 'deviceEvent',
+        deviceId: deviceId,
+        eventsForDevice: [{
+            event: isMention ? 'DeviceMention' : 'DeviceChatMessage',
+            deviceId: deviceId,
+            text: text
+        }]
+    }));
+    input.value = '';
+    // The channel broadcast skips the sender, so show the own note right away
+    appendChatToMonitor(deviceId, {
+        displayName: currentUser?.display_name || 'Guest User',
+        text: text
+    }, isMention ? 'MENTION' : 'CHAT');
+    if (isMention && 'Notification' in window && Notification.permission === 'default') {
+        Notification.requestPermission();
+    }
+}
+
 function renderDevices() {
     // Clear existing content
     document.getElementById('deviceTabs').innerHTML = '';
@@ -983,8 +1116,21 @@ function createDeviceContent(device, suffix = '') {
                 <div class="panel-resizer" id="${idPrefix}-resizer"></div>
 
                 <div class="right-panel">
+                    <ul class="nav nav-tabs nav-sm mb-2">
+                        <li class="nav-item">
+                            <button class="nav-link active" id="${idPrefix}-monitor-tab" onclick="showDevicePanelTab('${idPrefix}', '${device.id}', 'monitor')">
+                                <i class="bi bi-terminal"></i> Monitor
+                            </button>
+                        </li>
+                        <li class="nav-item">
+                            <button class="nav-link" id="${idPrefix}-activity-tab" onclick="showDevicePanelTab('${idPrefix}', '${device.id}', 'activity')">
+                                <i class="bi bi-clock-history"></i> Activity
+                            </button>
+                        </li>
+                    </ul>
+
                     <!-- UDP Monitor -->
-                    <div class="udp-monitor-section">
+                    <div class="udp-monitor-section" id="${idPrefix}-monitor-pane">
                         <div class="monitor-header">
                             <h6><i class="bi bi-terminal"></i> Debug Monitor</h6>
                             <div class="monitor-filters" id="${idPrefix}-monitor-filters">
@@ -992,6 +1138,23 @@ function createDeviceContent(device, suffix = '') {
                             </div>
                         </div>
                         <div class="monitor-area" id="${idPrefix}-udp-monitor"></div>
+                        <div class="input-group input-group-sm mt-2">
+                            <input type="text" class="form-control" id="${idPrefix}-chat-input" maxlength="1000"
+                                   placeholder="Note for everyone on this device, e.g. re-flashed at 14:32 or @bob your turn"
+                                   onkeypress="if (event.key === 'Enter') sendChatMessage('${idPrefix}', '${device.id}')">
+                            <button class="btn btn-outline-primary" onclick="sendChatMessage('${idPrefix}', '${device.id}')">
+                                <i class="bi bi-chat-left-text"></i> Send
+                            </button>
+                        </div>
+                    </div>
+
+                    <!-- Activity feed (GET /api/devices/:id/activity) -->
+                    <div class="activity-section" id="${idPrefix}-activity-pane" style="display: none;">
+                        <ul class="list-group list-group-flush" id="${idPrefix}-activity"></ul>
+                        <button class="btn btn-outline-secondary btn-sm mt-2" id="${idPrefix}-activity-more" style="display: none;"
+                                onclick="loadDeviceActivity('${idPrefix}', '${device.id}', true)">
+                            Load more
+                        </button>
                     </div>
                 </div>
             </div>
@@ -1631,7 +1794,60 @@ function updateTcpButton(deviceId, isConnected) {
     });
 }
 
+// Switch the right panel between the debug monitor and the activity feed
+function showDevicePanelTab(idPrefix, deviceId, tab) {
+    const showActivity = tab === 'activity';
+    document.getElementById(`${idPrefix}-monitor-pane`).style.display = showActivity ? 'none' : '';
+    document.getElementById(`${idPrefix}-activity-pane`).style.display = showActivity ? '' : 'none';
+    document.getElementById(`${idPrefix}-monitor-tab`).classList.toggle('active', !showActivity);
+    document.getElementById(`${idPrefix}-activity-tab`).classList.toggle('active', showActivity);
+    if (showActivity) {
+        loadDeviceActivity(idPrefix, deviceId, false);
+    }
+}
+
+// Load the activity feed; `more` appends the next (older) page
+async function loadDeviceActivity(idPrefix, deviceId, more) {
+    const list = document.getElementById(`${idPrefix}-activity`);
+    const moreBtn = document.getElementById(`${idPrefix}-activity-more`);
+    if (!list) return;
+
+    let url = `/api/devices/${encodeURIComponent(deviceId)}/activity`;
+    if (more && list.dataset.nextBefore) {
+        url += `?before=${encodeURIComponent(list.dataset.nextBefore)}`;
+    }
+
+    try {
+        const response = await fetch(url, { credentials: 'include' });
+        const data = await response.json();
+        if (!response.ok || !data.success) {
+            list.innerHTML = `<li class="list-group-item text-muted">${escapeHtml(data.message || 'Activity not available')}</li>`;
+            moreBtn.style.display = 'none';
+            return;
+        }
+
+        const items = data.entries.map(entry => `
+            <li class="list-group-item small">
+                <span class="text-muted">${escapeHtml(new Date(entry.createdAt).toLocaleString())}</span>
+                ${escapeHtml(entry.message)}
+            </li>
+        `).join('');
+        if (more) {
+            list.insertAdjacentHTML('beforeend', items);
+        } else {
+            list.innerHTML = items || '<li class="list-group-item text-muted">No activity yet</li>';
+        }
+        list.dataset.nextBefore = data.nextBefore ?? '';
+        moreBtn.style.display = data.nextBefore ? '' : 'none';
+    } catch (e) {
+        console.error('Failed to load device activity:', e);
+    }
+}
+
 // Expose functions to global scope for HTML onclick handlers
+window.showDevicePanelTab = showDevicePanelTab;
+window.sendChatMessage = sendChatMessage;
+window.loadDeviceActivity = loadDeviceActivity;
 window.sendReset = sendReset;
 window.sendStartOption = sendStartOption;
 window.sendVariable = sendVariable;
@@ -22,27 +22,33 @@ function loadDocumentation(docPath) {
     
     document.getElementById('docs-content').innerHTML = '<p>Laden der Dokumentation...</p>';
     
-    fetch(apiUrl)
+    fetch(apiUrl, { headers: { 'Accept': 'application/json' } })
         .then(response => {
             if (!response.ok) {
                 throw new Error(`HTTP ${response.status}: ${response.statusText}`);
             }
-            return response.text();
+            return response.json();
         })
-        .then(html => {
-            // Extract content from the documentation HTML
-            const parser = new DOMParser();
-            const doc = parser.parseFromString(html, 'text/html');
-            const body = doc.body;
-            
-            // Remove navigation div (zurück zur dokumentation links)
-            const navDiv = body.querySelector('div[style*="margin-bottom: 20px"]');
-            if (navDiv) {
-                navDiv.remove();
-            }
-            
-            // Insert the content
-            document.getElementById('docs-content').innerHTML = body.innerHTML;
+        .then(doc => {
+            // Server renders and sanitizes the markdown, links to other pages point to /docs/<page>
+            const content = document.getElementById('docs-content');
+            content.innerHTML = doc.html;
+            document.title = `${doc.title} - Dokumentation`;
+
+            content.querySelectorAll('a[href^="/docs/"]').forEach(link => {
+                link.addEventListener('click', function(e) {
+                    e.preventDefault();
+                    const target = this.getAttribute('href').slice('/docs/'.length);
+                    const [page, anchor] = target.split('#');
+                    document.querySelectorAll('.doc-link').forEach(l => {
+                        l.classList.toggle('active', l.getAttribute('data-doc') === page);
+                    });
+                    loadDocumentation(page);
+                    if (anchor) {
+                        setTimeout(() => document.getElementById(anchor)?.scrollIntoView(), 100);
+                    }
+                });
+            });
         })
         .catch(error => {
             console.error('Documentation load error:', error);
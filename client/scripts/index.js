@@ -119,7 +119,7 @@
     // WebSocket setup for live Device device discovery
     function setupWebSocketForDeviceDiscovery() {
         const protocol = window.location.protocol === 'https:' ? 'wss:' : 'ws:';
-        const wsUrl = `${protocol}//${window.location.host}/channel`;
+        const wsUrl = `${protocol}//${window.location.host}${window.BASE_PATH || ''}/channel`;
         let websocket = null;
         
         function connectWebSocket() {
@@ -160,6 +160,12 @@
         }
         
         function handleDeviceDiscoveryMessage(message) {
+            if (message.type === 'deviceListChanged') {
+                // Device created/renamed/deleted or permissions changed by someone else
+                console.log('Device list changed via WebSocket:', message.change, message.deviceId);
+                loadDeviceDevicesList();
+                return;
+            }
             if (message.deviceId === 'system' && message.eventsForDevice) {
                 message.eventsForDevice.forEach(event => {
                     if (event.event === 'deviceDeviceDiscovered') {
@@ -13,6 +13,7 @@ setTimeout(function() {
         
         const email = document.getElementById('email').value;
         const displayName = document.getElementById('display-name').value;
+        const handle = document.getElementById('handle').value.trim();
         const password = document.getElementById('password').value;
         const passwordConfirm = document.getElementById('password-confirm').value;
         const submitButton = form.querySelector('button[type="submit"]');
@@ -40,7 +41,7 @@ setTimeout(function() {
                 headers: {
                     'Content-Type': 'application/json',
                 },
-                body: JSON.stringify({ email, display_name: displayName, password }),
+                body: JSON.stringify({ email, display_name: displayName, password, handle: handle || null }),
             });
             
             console.log('Response status:', response.status);
@@ -55,7 +56,7 @@ setTimeout(function() {
                 if (window.navigateTo) {
                     window.navigateTo('/');  // A 5.1 requirement: home is at /
                 } else {
-                    window.location.href = '/';
+                    window.location.href = (window.BASE_PATH || '') + '/';
                 }
             } else {
                 showMessage(data.message, 'error');
@@ -0,0 +1,82 @@
+// Use setTimeout to ensure DOM elements are available after template injection
+setTimeout(function() {
+    const requestForm = document.getElementById('reset-request-form');
+    const confirmForm = document.getElementById('reset-confirm-form');
+    const messageDiv = document.getElementById('auth-message');
+
+    if (!requestForm || !confirmForm || !messageDiv) {
+        console.error('Password reset form elements not found');
+        return;
+    }
+
+    // The emailed link carries the token: show the new-password form instead
+    const token = new URLSearchParams(window.location.search).get('token');
+    if (token) {
+        requestForm.style.display = 'none';
+        confirmForm.style.display = '';
+    }
+
+    requestForm.addEventListener('submit', async function(e) {
+        e.preventDefault();
+
+        const email = document.getElementById('email').value;
+        const submitButton = requestForm.querySelector('button[type="submit"]');
+        submitButton.disabled = true;
+
+        try {
+            const response = await fetch('/api/password-reset', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/json',
+                },
+                body: JSON.stringify({ email })
+            });
+            const data = await response.json();
+            showMessage(data.message, data.success ? 'success' : 'error');
+        } catch (error) {
+            showMessage('Ein Fehler ist aufgetreten. Bitte versuchen Sie es erneut.', 'error');
+        } finally {
+            submitButton.disabled = false;
+        }
+    });
+
+    confirmForm.addEventListener('submit', async function(e) {
+        e.preventDefault();
+
+        const password = document.getElementById('password').value;
+        const passwordConfirm = document.getElementById('password-confirm').value;
+        if (password !== passwordConfirm) {
+            showMessage('Die Passwörter stimmen nicht überein.', 'error');
+            return;
+        }
+
+        const submitButton = confirmForm.querySelector('button[type="submit"]');
+        submitButton.disabled = true;
+
+        try {
+            const response = await fetch('/api/password-reset/confirm', {
+                method: 'POST',
+                headers: {
+                    'Content-Type': 'application/json',
+                },
+                body: JSON.stringify({ token, password })
+            });
+            const data = await response.json();
+            showMessage(data.message, data.success ? 'success' : 'error');
+            if (data.success) {
+                confirmForm.style.display = 'none';
+            }
+        } catch (error) {
+            showMessage('Ein Fehler ist aufgetreten. Bitte versuchen Sie es erneut.', 'error');
+        } finally {
+            submitButton.disabled = false;
+        }
+    });
+
+    function showMessage(message, type) {
+        messageDiv.textContent = message;
+        messageDiv.className = `auth-message ${type}`;
+        messageDiv.style.display = 'block';
+    }
+
+}, 100); // Wait 100ms for DOM to be ready
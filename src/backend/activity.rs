@@ -0,0 +1,197 @@
+// Device activity - audit log of what users did to a device and the readable feed built from it
+//
+// Handlers write one row per action to device_audit_log: TCP connects and disconnects, commands
+// (WebSocket and POST /api/devices/:id/command), renames, permission changes and expiries,
+// firmware updates the device reports after an OTA run, and quarantines and their release.
+// GET /api/devices/:id/activity turns the rows into sentences ("alice sent setVariable
+// brightness = 50"), newest first, for the Activity tab of the device page. Rows older than RETENTION are pruned once an hour.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::database::DatabaseManager;
+
+/// How long audit rows are kept
+pub const RETENTION: chrono::Duration = chrono::Duration::days(90);
+
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Default and largest page of GET /api/devices/:id/activity
+pub const DEFAULT_PAGE_SIZE: u32 = 50;
+pub const MAX_PAGE_SIZE: u32 = 200;
+
+/// Actor of entries the server writes itself (expiries, firmware reports, quarantines)
+pub const SYSTEM_ACTOR: &str = "system";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AuditAction {
+    Connected,
+    Disconnected,
+    Command,
+    Renamed,
+    PermissionChanged,
+    PermissionExpired,
+    FirmwareUpdated,
+    Quarantined,
+    QuarantineReleased,
+}
+
+impl AuditAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Connected => "connected",
+            Self::Disconnected => "disconnected",
+            Self::Command => "command",
+            Self::Renamed => "renamed",
+            Self::PermissionChanged => "permissionChanged",
+            Self::PermissionExpired => "permissionExpired",
+            Self::FirmwareUpdated => "firmwareUpdated",
+            Self::Quarantined => "quarantined",
+            Self::QuarantineReleased => "quarantineReleased",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [
+            Self::Connected, Self::Disconnected, Self::Command, Self::Renamed,
+            Self::PermissionChanged, Self::PermissionExpired, Self::FirmwareUpdated, Self::Quarantined,
+            Self::QuarantineReleased,
+        ].into_iter().find(|action| action.as_str() == value)
+    }
+}
+
+/// Row of device_audit_log
+#[derive(Debug, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub device_id: String,
+    pub actor_id: String,
+    pub action: AuditAction,
+    pub details: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Feed entry of GET /api/devices/:id/activity
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActivityEntry {
+    pub id: i64,
+    pub action: AuditAction,
+    pub actor_id: String,
+    pub message: String,
+    pub details: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Short form of a command, e.g. "setVariable brightness = 50" or "reset"
+pub fn command_summary(command: &Value) -> String {
+    if let Some(set_variable) = command.get("setVariable") {
+        let name = set_variable.get("name").and_then(Value::as_str).unwrap_or("?");
+        let value = set_variable.get("value").map(Value::to_string).unwrap_or_default();
+        return format!("setVariable {} = {}", name, value);
+    }
+    if let Some(option) = command.get("startOption").and_then(Value::as_str) {
+        return format!("startOption {}", option);
+    }
+    let keys: Vec<&str> = command.as_object()
+        .map(|object| object.keys().map(String::as_str).filter(|key| *key != "requestId").collect())
+        .unwrap_or_default();
+    if keys.is_empty() { "a command".to_string() } else { keys.join(", ") }
+}
+
+/// Sentence for an entry; `names` maps user IDs to display names
+pub fn describe(entry: &AuditEntry, names: &HashMap<String, String>) -> String {
+    let name = |user_id: &str| names.get(user_id).cloned().unwrap_or_else(|| user_id.to_string());
+    let detail = |key: &str| entry.details.get(key).and_then(Value::as_str).unwrap_or("?").to_string();
+    let actor = name(&entry.actor_id);
+    match entry.action {
+        AuditAction::Connected => format!("{} connected the device", actor),
+        AuditAction::Disconnected => format!("{} disconnected the device", actor),
+        AuditAction::Command => format!("{} sent {}", actor, command_summary(entry.details.get("command").unwrap_or(&Value::Null))),
+        AuditAction::Renamed => {
+            let field = if entry.details.get("field").and_then(Value::as_str) == Some("alias") { "alias" } else { "name" };
+            let from = entry.details.get("from").and_then(Value::as_str).unwrap_or("(none)");
+            let to = entry.details.get("to").and_then(Value::as_str).unwrap_or("(none)");
+            format!("{} changed the {} from \"{}\" to \"{}\"", actor, field, from, to)
+        }
+        AuditAction::PermissionChanged => {
+            let target = name(&detail("userId"));
+            match entry.details.get("permission").and_then(Value::as_str) {
+                Some("REMOVE") | None => format!("{} removed the permission of {}", actor, target),
+                Some(permission) => match entry.details.get("expiresAt").and_then(Value::as_str) {
+                    Some(expires_at) => format!("{} gave {} permission {} until {}", actor, target, permission, expires_at),
+                    None => format!("{} gave {} permission {}", actor, target, permission),
+                },
+            }
+        }
+        AuditAction::PermissionExpired => format!("Permission {} of {} expired", detail("permission"), name(&detail("userId"))),
+        AuditAction::FirmwareUpdated => format!("Firmware updated from {} to {}", detail("from"), detail("to")),
+        AuditAction::Quarantined => format!("Device quarantined: {}", detail("reason")),
+        AuditAction::QuarantineReleased => format!("{} released the device from quarantine", actor),
+    }
+}
+
+/// User IDs an entry mentions (actor and permission target), for the name lookup
+pub fn mentioned_users(entry: &AuditEntry) -> Vec<String> {
+    let mut users = vec![entry.actor_id.clone()];
+    if let Some(user_id) = entry.details.get("userId").and_then(Value::as_str) {
+        users.push(user_id.to_string());
+    }
+    users
+}
+
+/// Write an audit row; failures are logged, the action itself already happened
+pub async fn record(db: &DatabaseManager, device_id: &str, actor_id: &str, action: AuditAction, details: Value) {
+    if let Err(e) = db.insert_audit_entry(device_id, actor_id, action, &details, Utc::now()).await {
+        warn!("Failed to record {} activity for device {}: {}", action.as_str(), device_id, e);
+    }
+}
+
+/// Hourly removal of rows older than RETENTION
+pub fn start(db: Arc<DatabaseManager>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(PRUNE_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = db.prune_audit_log(Utc::now() - RETENTION).await {
+                warn!("Failed to prune the device audit log: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(action: AuditAction, details: Value) -> AuditEntry {
+        AuditEntry { id: 1, device_id: "AA-01".to_string(), actor_id: "u1".to_string(), action, details, created_at: Utc::now() }
+    }
+
+    #[test]
+    fn test_describe_entries() {
+        let names = HashMap::from([("u1".to_string(), "Alice".to_string()), ("u2".to_string(), "Bob".to_string())]);
+
+        let command = entry(AuditAction::Command, json!({"command": {"setVariable": {"name": "brightness", "value": 50}, "requestId": "r1"}}));
+        assert_eq!(describe(&command, &names), "Alice sent setVariable brightness = 50");
+        assert_eq!(command_summary(&json!({"reset": true, "requestId": "r2"})), "reset");
+
+        let granted = entry(AuditAction::PermissionChanged, json!({"userId": "u2", "permission": "W", "expiresAt": "2026-01-01T10:00:00Z"}));
+        assert_eq!(describe(&granted, &names), "Alice gave Bob permission W until 2026-01-01T10:00:00Z");
+        let removed = entry(AuditAction::PermissionChanged, json!({"userId": "u3", "permission": "REMOVE"}));
+        assert_eq!(describe(&removed, &names), "Alice removed the permission of u3");
+
+        let renamed = entry(AuditAction::Renamed, json!({"field": "alias", "from": null, "to": "Lab lamp"}));
+        assert_eq!(describe(&renamed, &names), "Alice changed the alias from \"(none)\" to \"Lab lamp\"");
+        assert_eq!(mentioned_users(&granted), vec!["u1".to_string(), "u2".to_string()]);
+        assert_eq!(AuditAction::parse("firmwareUpdated"), Some(AuditAction::FirmwareUpdated));
+    }
+}
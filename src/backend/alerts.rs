@@ -0,0 +1,580 @@
+// Alerts - user-defined rules on device variables and connectivity
+//
+// A rule watches one device with a condition such as "variable temp > 80 for 30s" or
+// "device offline > 5m". The engine follows the device event feed; once a condition has
+// held for the rule's duration the alert fires: it is stored in the alerts table,
+// broadcast as DeviceAlert event, optionally sent to the owner's webhooks and emailed
+// to the owner (notification preferences). It resolves as soon as the condition no
+// longer holds.
+//
+// Anomaly rules ("variable temp anomaly 3") need no threshold of their own: the engine keeps an
+// exponentially weighted mean and standard deviation of the variable and flags readings more
+// than N standard deviations away, or a value that stops changing for STUCK_READINGS readings
+// after it used to vary. The baseline is learned from the first ANOMALY_WARMUP readings (again
+// after a restart or a changed condition).
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use serde_json::json;
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use crate::database::{Alert, AlertRule, DatabaseManager};
+use crate::device_store::{FeedEvent, SharedDeviceStore};
+use crate::email::{EmailTemplate, Mailer, NotificationKind};
+use crate::events::DeviceEvent;
+use crate::webhooks::{WebhookDispatcher, WebhookEvent};
+
+/// Longest accepted "for"/offline duration
+pub const MAX_DURATION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// How often pending conditions are checked against their duration
+const CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Weight of a new reading in the anomaly baseline (roughly the last 40 readings count)
+const ANOMALY_ALPHA: f64 = 0.05;
+
+/// Readings an anomaly rule learns from before it evaluates
+const ANOMALY_WARMUP: u32 = 20;
+
+/// Identical readings in a row that count as a stuck sensor
+const STUCK_READINGS: u32 = 30;
+
+/// Signalled by the API after rules were created, changed or deleted
+static RULES_CHANGED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Make the running engine reload its rules
+pub fn rules_changed() {
+    RULES_CHANGED.notify_one();
+}
+
+// ============================================================================
+// CONDITIONS
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Greater,
+    GreaterOrEqual,
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+}
+
+impl Comparison {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            ">" => Some(Self::Greater),
+            ">=" => Some(Self::GreaterOrEqual),
+            "<" => Some(Self::Less),
+            "<=" => Some(Self::LessOrEqual),
+            "==" | "=" => Some(Self::Equal),
+            "!=" => Some(Self::NotEqual),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Greater => ">",
+            Self::GreaterOrEqual => ">=",
+            Self::Less => "<",
+            Self::LessOrEqual => "<=",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+        }
+    }
+
+    pub fn holds(&self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Greater => value > threshold,
+            Self::GreaterOrEqual => value >= threshold,
+            Self::Less => value < threshold,
+            Self::LessOrEqual => value <= threshold,
+            Self::Equal => value == threshold,
+            Self::NotEqual => value != threshold,
+        }
+    }
+}
+
+/// Parsed rule condition
+#[derive(Debug, Clone, PartialEq)]
+pub enum AlertCondition {
+    /// `variable <name> <op> <number> [for <duration>]`
+    Variable { name: String, comparison: Comparison, threshold: f64, duration: Duration },
+    /// `variable <name> anomaly <sigmas> [for <duration>]`
+    Anomaly { name: String, sigmas: f64, duration: Duration },
+    /// `device offline [> <duration>]` (also `for <duration>`)
+    Offline { duration: Duration },
+}
+
+impl AlertCondition {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        let for_duration = |rest: &[&str]| match rest {
+            [] => Ok(Duration::ZERO),
+            ["for", duration] => parse_duration(duration),
+            _ => Err("Expected 'for <duration>' after the threshold".to_string()),
+        };
+        match tokens.as_slice() {
+            ["variable", name, "anomaly", sigmas, rest @ ..] => {
+                let sigmas: f64 = sigmas.parse().ok().filter(|sigmas: &f64| sigmas.is_finite() && *sigmas > 0.0)
+                    .ok_or_else(|| format!("Anomaly threshold '{}' must be a positive number of standard deviations", sigmas))?;
+                Ok(Self::Anomaly { name: name.to_string(), sigmas, duration: for_duration(rest)? })
+            }
+            ["variable", name, op, threshold, rest @ ..] => {
+                let comparison = Comparison::parse(op).ok_or_else(|| format!("Unknown comparison '{}' (use >, >=, <, <=, ==, !=)", op))?;
+                let threshold: f64 = threshold.parse().map_err(|_| format!("Threshold '{}' is not a number", threshold))?;
+                Ok(Self::Variable { name: name.to_string(), comparison, threshold, duration: for_duration(rest)? })
+            }
+            ["device", "offline", rest @ ..] => {
+                let duration = match rest {
+                    [] => Duration::ZERO,
+                    [">" | "for", duration] => parse_duration(duration)?,
+                    _ => return Err("Expected 'device offline > <duration>'".to_string()),
+                };
+                Ok(Self::Offline { duration })
+            }
+            _ => Err("Condition must be 'variable <name> <op> <number> [for <duration>]', 'variable <name> anomaly <sigmas> [for <duration>]' or 'device offline [> <duration>]'".to_string()),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        match self {
+            Self::Variable { duration, .. } | Self::Anomaly { duration, .. } | Self::Offline { duration } => *duration,
+        }
+    }
+}
+
+/// Canonical text form (stored in alert_rules.condition)
+impl fmt::Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variable { name, comparison, threshold, duration } => {
+                write!(f, "variable {} {} {}", name, comparison.as_str(), threshold)?;
+                if !duration.is_zero() {
+                    write!(f, " for {}", format_duration(*duration))?;
+                }
+                Ok(())
+            }
+            Self::Anomaly { name, sigmas, duration } => {
+                write!(f, "variable {} anomaly {}", name, sigmas)?;
+                if !duration.is_zero() {
+                    write!(f, " for {}", format_duration(*duration))?;
+                }
+                Ok(())
+            }
+            Self::Offline { duration } if duration.is_zero() => write!(f, "device offline"),
+            Self::Offline { duration } => write!(f, "device offline > {}", format_duration(*duration)),
+        }
+    }
+}
+
+/// `30s`, `5m`, `2h`, `1d` or plain seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("Invalid duration '{}'", text))?;
+    let unit_seconds = match unit {
+        "" | "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "d" => 86400,
+        _ => return Err(format!("Invalid duration unit in '{}' (use s, m, h or d)", text)),
+    };
+    let duration = Duration::from_secs(number.saturating_mul(unit_seconds));
+    if duration > MAX_DURATION {
+        return Err(format!("Duration '{}' exceeds 7 days", text));
+    }
+    Ok(duration)
+}
+
+pub fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs();
+    match seconds {
+        s if s % 86400 == 0 => format!("{}d", s / 86400),
+        s if s % 3600 == 0 => format!("{}h", s / 3600),
+        s if s % 60 == 0 => format!("{}m", s / 60),
+        s => format!("{}s", s),
+    }
+}
+
+// ============================================================================
+// ANOMALY BASELINE
+// ============================================================================
+
+/// How far a reading is off the baseline
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Deviation {
+    /// Distance from the mean in standard deviations
+    Sigmas(f64),
+    /// Same value this many readings in a row, after the variable used to vary
+    Stuck(u32),
+}
+
+/// Exponentially weighted mean and variance of one variable
+#[derive(Debug, Clone, Default)]
+struct Baseline {
+    samples: u32,
+    mean: f64,
+    variance: f64,
+    last: Option<f64>,
+    repeated: u32,
+    /// Standard deviation when the current run of identical readings started
+    spread_before_repeat: f64,
+    deviation: Option<Deviation>,
+}
+
+impl Baseline {
+    /// Score a reading against the readings before it, then learn from it; None while warming up
+    fn observe(&mut self, value: f64) -> Option<Deviation> {
+        let stddev = self.variance.sqrt();
+        // A variable that never varied: any change is far off
+        let sigmas = (value - self.mean).abs() / stddev.max(f64::EPSILON * self.mean.abs().max(1.0));
+
+        if self.last == Some(value) {
+            self.repeated += 1;
+        } else {
+            self.repeated = 1;
+            self.spread_before_repeat = stddev;
+        }
+        self.last = Some(value);
+
+        // Plain average while warming up, then the weighted one
+        let alpha = ANOMALY_ALPHA.max(1.0 / (self.samples as f64 + 1.0));
+        let difference = value - self.mean;
+        self.mean += alpha * difference;
+        self.variance = (1.0 - alpha) * (self.variance + alpha * difference * difference);
+        self.samples = self.samples.saturating_add(1);
+
+        if self.samples <= ANOMALY_WARMUP {
+            return None;
+        }
+        let deviation = if self.repeated >= STUCK_READINGS && self.spread_before_repeat > 0.0 {
+            Deviation::Stuck(self.repeated)
+        } else {
+            Deviation::Sigmas(sigmas)
+        };
+        self.deviation = Some(deviation);
+        Some(deviation)
+    }
+}
+
+// ============================================================================
+// ENGINE
+// ============================================================================
+
+/// Evaluation state of one rule
+struct RuleState {
+    rule: AlertRule,
+    condition: AlertCondition,
+    /// Condition holds since (not yet long enough to fire)
+    holding_since: Option<Instant>,
+    last_value: Option<f64>,
+    /// ID of the firing alert
+    firing: Option<String>,
+    /// Learned readings of anomaly rules
+    baseline: Baseline,
+}
+
+impl RuleState {
+    /// Apply an event of the rule's device; Some(false) when the condition stopped holding
+    fn observe(&mut self, event: &DeviceEvent, now: Instant) -> Option<bool> {
+        let holds = match (&self.condition, event) {
+            (AlertCondition::Variable { name, comparison, threshold, .. }, DeviceEvent::DeviceVariableUpdate { variable_name, variable_value, .. }) if name == variable_name => {
+                let value: f64 = variable_value.trim().parse().ok()?;
+                self.last_value = Some(value);
+                comparison.holds(value, *threshold)
+            }
+            (AlertCondition::Anomaly { name, sigmas, .. }, DeviceEvent::DeviceVariableUpdate { variable_name, variable_value, .. }) if name == variable_name => {
+                let value: f64 = variable_value.trim().parse().ok().filter(|value: &f64| value.is_finite())?;
+                let deviation = self.baseline.observe(value)?;
+                self.last_value = Some(value);
+                match deviation {
+                    Deviation::Sigmas(distance) => distance > *sigmas,
+                    Deviation::Stuck(_) => true,
+                }
+            }
+            (AlertCondition::Offline { .. }, DeviceEvent::DeviceConnectionStatus { connected, .. }) => !connected,
+            _ => return None,
+        };
+        if holds {
+            self.holding_since.get_or_insert(now);
+        } else {
+            self.holding_since = None;
+        }
+        Some(holds)
+    }
+
+    /// Condition held for the whole duration and no alert is firing yet
+    fn due(&self, now: Instant) -> bool {
+        self.firing.is_none() && self.holding_since.is_some_and(|since| now.duration_since(since) >= self.condition.duration())
+    }
+
+    fn message(&self) -> String {
+        match (&self.condition, self.last_value) {
+            (AlertCondition::Variable { .. }, Some(value)) => format!("{} (value {})", self.condition, value),
+            (AlertCondition::Anomaly { .. }, Some(value)) => match self.baseline.deviation {
+                Some(Deviation::Stuck(readings)) => format!("{} (value {} unchanged for {} readings)", self.condition, value, readings),
+                Some(Deviation::Sigmas(distance)) => {
+                    format!("{} (value {}, {:.1} standard deviations from the mean {:.3})", self.condition, value, distance, self.baseline.mean)
+                }
+                None => self.condition.to_string(),
+            },
+            _ => self.condition.to_string(),
+        }
+    }
+}
+
+/// Evaluates all enabled alert rules against the device event feed
+pub struct AlertEngine {
+    db: Arc<DatabaseManager>,
+    device_store: SharedDeviceStore,
+    webhooks: Arc<WebhookDispatcher>,
+    mailer: Arc<Mailer>,
+}
+
+impl AlertEngine {
+    pub fn new(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, webhooks: Arc<WebhookDispatcher>, mailer: Arc<Mailer>) -> Self {
+        Self { db, device_store, webhooks, mailer }
+    }
+
+    pub fn start(self) {
+        let mut feed = self.device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut rules = self.load_rules(HashMap::new()).await;
+            let mut check = tokio::time::interval(CHECK_INTERVAL);
+            info!("Alert engine started with {} rule(s)", rules.len());
+
+            loop {
+                tokio::select! {
+                    received = feed.recv() => match received {
+                        Ok(feed_event) => self.handle_event(&mut rules, &feed_event).await,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Alert engine lagged behind the event feed, skipped {} events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = check.tick() => self.fire_due(&mut rules).await,
+                    _ = RULES_CHANGED.notified() => {
+                        rules = self.load_rules(rules).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// (Re)load enabled rules; unchanged rules keep their state, alerts of removed rules are resolved
+    async fn load_rules(&self, mut previous: HashMap<String, RuleState>) -> HashMap<String, RuleState> {
+        let stored = match self.db.list_enabled_alert_rules().await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to load alert rules: {}", e);
+                return previous;
+            }
+        };
+
+        let mut rules = HashMap::new();
+        for rule in stored {
+            let condition = match AlertCondition::parse(&rule.condition) {
+                Ok(condition) => condition,
+                Err(e) => {
+                    warn!("Skipping alert rule {} with invalid condition '{}': {}", rule.id, rule.condition, e);
+                    continue;
+                }
+            };
+            let state = match previous.remove(&rule.id) {
+                Some(mut state) if state.rule.device_id == rule.device_id && state.condition == condition => {
+                    state.rule = rule;
+                    state
+                }
+                Some(state) => {
+                    self.resolve(&state).await;
+                    RuleState { rule, condition, holding_since: None, last_value: None, firing: None, baseline: Baseline::default() }
+                }
+                None => RuleState { rule, condition, holding_since: None, last_value: None, firing: None, baseline: Baseline::default() },
+            };
+            rules.insert(state.rule.id.clone(), state);
+        }
+        for state in previous.values() {
+            self.resolve(state).await;
+        }
+
+        // Alerts still firing from before a restart stay open until their condition clears
+        let firing = self.db.list_firing_alerts().await.unwrap_or_default();
+        for alert in firing {
+            match rules.get_mut(&alert.rule_id) {
+                Some(state) if state.firing.is_none() => state.firing = Some(alert.id),
+                Some(_) => {}
+                None => {
+                    let _ = self.db.resolve_alert(&alert.id, Utc::now()).await;
+                }
+            }
+        }
+        rules
+    }
+
+    async fn handle_event(&self, rules: &mut HashMap<String, RuleState>, feed_event: &FeedEvent) {
+        let now = Instant::now();
+        let mut cleared = Vec::new();
+        for state in rules.values_mut().filter(|state| state.rule.device_id == feed_event.device_id) {
+            if state.observe(&feed_event.event, now) == Some(false) && state.firing.is_some() {
+                cleared.push(state.rule.id.clone());
+            }
+        }
+        for rule_id in cleared {
+            if let Some(state) = rules.get_mut(&rule_id) {
+                self.resolve(state).await;
+                state.firing = None;
+            }
+        }
+        // Rules without duration fire right away
+        self.fire_due(rules).await;
+    }
+
+    async fn fire_due(&self, rules: &mut HashMap<String, RuleState>) {
+        let now = Instant::now();
+        for state in rules.values_mut().filter(|state| state.due(now)) {
+            let alert = Alert {
+                id: uuid::Uuid::new_v4().to_string(),
+                rule_id: state.rule.id.clone(),
+                rule_name: state.rule.name.clone(),
+                owner_id: state.rule.owner_id.clone(),
+                device_id: state.rule.device_id.clone(),
+                state: "firing".to_string(),
+                message: state.message(),
+                value: state.last_value,
+                triggered_at: Utc::now(),
+                resolved_at: None,
+            };
+            if let Err(e) = self.db.insert_alert(&alert).await {
+                warn!("Failed to store alert of rule {}: {}", alert.rule_id, e);
+            }
+            info!("Alert '{}' firing for device {}: {}", alert.rule_name, alert.device_id, alert.message);
+            state.firing = Some(alert.id.clone());
+            self.publish(&state.rule, &alert.id, "firing", &alert.message, alert.value).await;
+        }
+    }
+
+    /// Resolve the firing alert of a rule (if any)
+    async fn resolve(&self, state: &RuleState) {
+        let Some(alert_id) = &state.firing else { return };
+        if let Err(e) = self.db.resolve_alert(alert_id, Utc::now()).await {
+            warn!("Failed to resolve alert {}: {}", alert_id, e);
+        }
+        info!("Alert '{}' resolved for device {}", state.rule.name, state.rule.device_id);
+        self.publish(&state.rule, alert_id, "resolved", &state.message(), state.last_value).await;
+    }
+
+    /// DeviceAlert event on the device channel, webhook notification if enabled, email
+    async fn publish(&self, rule: &AlertRule, alert_id: &str, alert_state: &str, message: &str, value: Option<f64>) {
+        let event = DeviceEvent::device_alert(
+            rule.device_id.clone(),
+            alert_id.to_string(),
+            rule.id.clone(),
+            rule.name.clone(),
+            alert_state.to_string(),
+            message.to_string(),
+            value,
+        );
+        if let Err(e) = self.device_store.add_event(rule.device_id.clone(), event, "ALERT_SYSTEM".to_string(), "ALERT_ENGINE".to_string()).await {
+            warn!("Failed to broadcast alert {} of device {}: {}", alert_id, rule.device_id, e);
+        }
+
+        if rule.notify_webhook {
+            let webhook_event = if alert_state == "firing" { WebhookEvent::AlertTriggered } else { WebhookEvent::AlertResolved };
+            let data = json!({
+                "alertId": alert_id,
+                "ruleId": rule.id,
+                "ruleName": rule.name,
+                "condition": rule.condition,
+                "message": message,
+                "value": value,
+            });
+            self.webhooks.notify(webhook_event, Some(&rule.device_id), Some(&rule.owner_id), data).await;
+        }
+
+        let rule_name = rule.name.clone();
+        let device_id = rule.device_id.clone();
+        let message = message.to_string();
+        let link = self.mailer.link(&format!("/devices/{}", rule.device_id));
+        let (kind, template) = if alert_state == "firing" {
+            (NotificationKind::AlertTriggered, EmailTemplate::AlertTriggered { rule: rule_name, device_id, message, link })
+        } else {
+            (NotificationKind::AlertResolved, EmailTemplate::AlertResolved { rule: rule_name, device_id, message, link })
+        };
+        self.mailer.notify_user(&rule.owner_id, kind, &template).await;
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_parsing_and_evaluation() {
+        let condition = AlertCondition::parse("variable temp > 80 for 30s").unwrap();
+        assert_eq!(condition, AlertCondition::Variable {
+            name: "temp".to_string(),
+            comparison: Comparison::Greater,
+            threshold: 80.0,
+            duration: Duration::from_secs(30),
+        });
+        assert_eq!(AlertCondition::parse("device  offline for 300").unwrap().to_string(), "device offline > 5m");
+        assert_eq!(AlertCondition::parse("variable hum <= 12.5").unwrap().to_string(), "variable hum <= 12.5");
+        assert!(AlertCondition::parse("variable temp ~ 80").is_err());
+        assert!(AlertCondition::parse("device offline > 5y").is_err());
+        assert!(AlertCondition::parse("device offline > 30d").is_err());
+
+        let rule = AlertRule {
+            id: "rule-1".to_string(),
+            owner_id: "user-1".to_string(),
+            device_id: "AA-01".to_string(),
+            name: "Hot".to_string(),
+            condition: condition.to_string(),
+            notify_webhook: false,
+            enabled: true,
+            created_at: Utc::now(),
+        };
+        let mut state = RuleState { rule, condition, holding_since: None, last_value: None, firing: None, baseline: Baseline::default() };
+        let update = |value: &str| DeviceEvent::device_variable_update("AA-01".to_string(), "temp".to_string(), value.to_string());
+        let start = Instant::now();
+
+        assert_eq!(state.observe(&update("85"), start), Some(true));
+        assert_eq!(state.observe(&update("90"), start + Duration::from_secs(10)), Some(true));
+        assert!(!state.due(start + Duration::from_secs(29)));
+        assert!(state.due(start + Duration::from_secs(30)));
+        assert_eq!(state.message(), "variable temp > 80 for 30s (value 90)");
+
+        assert_eq!(state.observe(&update("70"), start + Duration::from_secs(31)), Some(false));
+        assert!(!state.due(start + Duration::from_secs(60)));
+        assert_eq!(state.observe(&update("n/a"), start), None);
+        assert_eq!(state.observe(&DeviceEvent::device_variable_update("AA-01".to_string(), "hum".to_string(), "99".to_string()), start), None);
+
+        let condition = AlertCondition::parse("variable temp anomaly 3 for 1m").unwrap();
+        assert_eq!(condition.to_string(), "variable temp anomaly 3 for 1m");
+        assert!(AlertCondition::parse("variable temp anomaly -1").is_err());
+        state.condition = condition;
+        state.holding_since = None;
+        // Learns 20 +/- 0.5, then a jump to 35 is far off and a stuck reading is flagged too
+        for i in 0..ANOMALY_WARMUP {
+            assert_eq!(state.observe(&update(if i % 2 == 0 { "19.5" } else { "20.5" }), start), None);
+        }
+        assert_eq!(state.observe(&update("20.4"), start), Some(false));
+        assert_eq!(state.observe(&update("35"), start), Some(true));
+        assert!(state.message().contains("standard deviations"), "{}", state.message());
+        let holds: Vec<bool> = (0..STUCK_READINGS).filter_map(|_| state.observe(&update("20"), start)).collect();
+        assert_eq!(holds.last(), Some(&true));
+        assert!(!holds[0]);
+        assert!(state.message().ends_with("unchanged for 30 readings)"), "{}", state.message());
+    }
+}
@@ -0,0 +1,237 @@
+// ============================================================================
+// API ERROR - Uniform JSON error responses of the REST handlers
+// ============================================================================
+//
+// Every failing REST request answers with the same body:
+//   {"success": false, "code": "NOT_FOUND", "message": "...", "details": ...}
+// `success` stays in the body so the frontend can keep checking `data.success`.
+
+use axum::{
+    async_trait,
+    extract::{rejection::JsonRejection, FromRequest, Request},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+
+use crate::device_types::DeviceError;
+use crate::events::{ErrorCode, WsError};
+
+/// Error of a REST handler, rendered as JSON with the matching HTTP status
+#[derive(Debug, Clone)]
+pub struct ApiError {
+    pub status: StatusCode,
+    pub code: String,
+    pub message: String,
+    pub details: Option<Value>,
+}
+
+impl ApiError {
+    pub fn new(status: StatusCode, code: impl Into<String>, message: impl Into<String>) -> Self {
+        Self { status, code: code.into(), message: message.into(), details: None }
+    }
+
+    pub fn bad_request(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_REQUEST, "BAD_REQUEST", message)
+    }
+
+    pub fn unauthorized(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", message)
+    }
+
+    pub fn forbidden(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::FORBIDDEN, "FORBIDDEN", message)
+    }
+
+    pub fn not_found(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::NOT_FOUND, "NOT_FOUND", message)
+    }
+
+    pub fn conflict(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::CONFLICT, "CONFLICT", message)
+    }
+
+    pub fn internal(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR", message)
+    }
+
+    /// Failure reported by a device, a port or another external service
+    pub fn bad_gateway(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::BAD_GATEWAY, "UPSTREAM_ERROR", message)
+    }
+
+    /// Outbound call refused because the server runs in offline mode
+    pub fn offline(message: impl Into<String>) -> Self {
+        Self::new(StatusCode::SERVICE_UNAVAILABLE, "OFFLINE_MODE", message)
+    }
+
+    /// Device/connection failure with context, e.g. ("TCP connect failed", error)
+    pub fn from_device_error(context: &str, error: &DeviceError) -> Self {
+        let message = format!("{}: {}", context, error);
+        match error {
+            DeviceError::DeviceNotFound(_) => Self::new(StatusCode::NOT_FOUND, "UNKNOWN_DEVICE", message),
+            DeviceError::InvalidCommand(_) => Self::new(StatusCode::BAD_REQUEST, "INVALID_COMMAND", message),
+            DeviceError::ProfileViolation(_) => Self::new(StatusCode::BAD_REQUEST, "PROFILE_VIOLATION", message),
+            DeviceError::QueueFull(_) => Self::new(StatusCode::TOO_MANY_REQUESTS, "LIMIT_EXCEEDED", message),
+            DeviceError::RateLimited(_) => Self::new(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED", message),
+            DeviceError::Timeout => Self::new(StatusCode::GATEWAY_TIMEOUT, "DEVICE_TIMEOUT", message),
+            DeviceError::JsonError(_) => Self::internal(message),
+            DeviceError::ConnectionFailed(_) | DeviceError::TcpError(_) => Self::new(StatusCode::BAD_GATEWAY, "CONNECTION_FAILED", message),
+        }
+    }
+
+    pub fn with_details(mut self, details: Value) -> Self {
+        self.details = Some(details);
+        self
+    }
+
+    /// JSON body as sent to the client
+    pub fn body(&self) -> Value {
+        json!({
+            "success": false,
+            "code": self.code,
+            "message": self.message,
+            "details": self.details
+        })
+    }
+}
+
+impl std::fmt::Display for ApiError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({}): {}", self.code, self.status.as_u16(), self.message)
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self.body())).into_response()
+    }
+}
+
+/// Bare status codes (e.g. from shared helpers) get a generic message
+impl From<StatusCode> for ApiError {
+    fn from(status: StatusCode) -> Self {
+        let code = match status {
+            StatusCode::BAD_REQUEST => "BAD_REQUEST",
+            StatusCode::UNAUTHORIZED => "UNAUTHORIZED",
+            StatusCode::FORBIDDEN => "FORBIDDEN",
+            StatusCode::NOT_FOUND => "NOT_FOUND",
+            StatusCode::CONFLICT => "CONFLICT",
+            _ if status.is_server_error() => "INTERNAL_ERROR",
+            _ => "REQUEST_FAILED",
+        };
+        Self::new(status, code, status.canonical_reason().unwrap_or("Request failed"))
+    }
+}
+
+/// WebSocket protocol errors keep their meaning on the REST side
+impl From<WsError> for ApiError {
+    fn from(error: WsError) -> Self {
+        let (status, code) = match error.code {
+            ErrorCode::PermissionDenied => (StatusCode::FORBIDDEN, "FORBIDDEN"),
+            ErrorCode::UnknownDevice => (StatusCode::NOT_FOUND, "UNKNOWN_DEVICE"),
+            ErrorCode::InvalidMessage => (StatusCode::BAD_REQUEST, "INVALID_COMMAND"),
+            ErrorCode::LimitExceeded => (StatusCode::TOO_MANY_REQUESTS, "LIMIT_EXCEEDED"),
+            ErrorCode::CommandFailed => (StatusCode::BAD_GATEWAY, "COMMAND_FAILED"),
+            _ => (StatusCode::INTERNAL_SERVER_ERROR, "INTERNAL_ERROR"),
+        };
+        Self::new(status, code, error.message)
+    }
+}
+
+/// Malformed request bodies answer 400 with the parser message as details
+impl From<JsonRejection> for ApiError {
+    fn from(rejection: JsonRejection) -> Self {
+        let code = match rejection {
+            JsonRejection::MissingJsonContentType(_) => "UNSUPPORTED_CONTENT_TYPE",
+            _ => "INVALID_JSON",
+        };
+        Self::new(StatusCode::BAD_REQUEST, code, "Invalid JSON request body")
+            .with_details(json!({ "reason": rejection.body_text() }))
+    }
+}
+
+/// `Json` extractor whose rejections are `ApiError`s
+pub struct ApiJson<T>(pub T);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for ApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(Self(value))
+    }
+}
+
+/// Optional JSON body: empty body -> None, malformed body -> 400
+/// (axum's `Option<Json<T>>` silently turns malformed bodies into None)
+pub struct OptionalApiJson<T>(pub Option<T>);
+
+#[async_trait]
+impl<T, S> FromRequest<S> for OptionalApiJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let bytes = axum::body::Bytes::from_request(req, state).await
+            .map_err(|rejection| ApiError::bad_request(rejection.body_text()))?;
+        if bytes.iter().all(u8::is_ascii_whitespace) {
+            return Ok(Self(None));
+        }
+        serde_json::from_slice(&bytes)
+            .map(|value| Self(Some(value)))
+            .map_err(|e| {
+                ApiError::new(StatusCode::BAD_REQUEST, "INVALID_JSON", "Invalid JSON request body")
+                    .with_details(json!({ "reason": e.to_string() }))
+            })
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_invalid_json_body_is_bad_request() {
+        let request = Request::builder()
+            .method("POST")
+            .header("content-type", "application/json")
+            .body(axum::body::Body::from("{\"name\": 5"))
+            .unwrap();
+
+        let error = match ApiJson::<Value>::from_request(request, &()).await {
+            Ok(_) => panic!("malformed body must be rejected"),
+            Err(error) => error,
+        };
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.code, "INVALID_JSON");
+
+        let response = error.into_response();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["success"], false);
+        assert_eq!(body["code"], "INVALID_JSON");
+        assert!(body["details"]["reason"].is_string());
+
+        // Optional bodies: empty is fine, malformed is not
+        let empty = Request::builder().method("POST").body(axum::body::Body::empty()).unwrap();
+        assert!(OptionalApiJson::<Value>::from_request(empty, &()).await.unwrap().0.is_none());
+        let malformed = Request::builder().method("POST").body(axum::body::Body::from("{")).unwrap();
+        assert!(OptionalApiJson::<Value>::from_request(malformed, &()).await.is_err());
+    }
+}
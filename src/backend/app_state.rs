@@ -10,6 +10,8 @@ use crate::device_manager;
 use crate::device_discovery;
 use crate::mdns_server;
 use crate::uart_connection;
+use crate::device_simulator;
+use crate::email;
 
 /// Central application state shared across all handlers and services
 ///
@@ -25,6 +27,8 @@ use crate::uart_connection;
 /// * `device_discovery` - Device discovery service for finding devices on the network
 /// * `mdns_server` - mDNS server for service discovery (esp-server.local)
 /// * `uart_connection` - UART connection manager for serial-connected devices
+/// * `device_simulators` - Simulated ESP32 devices for development and tests
+/// * `mailer` - Email notifications (alerts, password reset) via the SMTP send queue
 #[derive(Clone)]
 pub struct AppState {
     pub db: Arc<DatabaseManager>,
@@ -33,6 +37,8 @@ pub struct AppState {
     pub device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>,
     pub mdns_server: Arc<tokio::sync::Mutex<mdns_server::MdnsServer>>,
     pub uart_connection: Arc<tokio::sync::Mutex<uart_connection::UartConnection>>,
+    pub device_simulators: Arc<device_simulator::SimulatorManager>,
+    pub mailer: Arc<email::Mailer>,
 }
 
 impl AppState {
@@ -46,7 +52,10 @@ impl AppState {
     /// * `device_discovery` - Device discovery service instance
     /// * `mdns_server` - mDNS server instance
     /// * `uart_connection` - UART connection manager instance
+    /// * `device_simulators` - Device simulator manager instance
+    /// * `mailer` - Email notification queue
     #[allow(dead_code)]
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         db: Arc<DatabaseManager>,
         device_store: SharedDeviceStore,
@@ -54,6 +63,8 @@ impl AppState {
         device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>,
         mdns_server: Arc<tokio::sync::Mutex<mdns_server::MdnsServer>>,
         uart_connection: Arc<tokio::sync::Mutex<uart_connection::UartConnection>>,
+        device_simulators: Arc<device_simulator::SimulatorManager>,
+        mailer: Arc<email::Mailer>,
     ) -> Self {
         Self {
             db,
@@ -62,6 +73,8 @@ impl AppState {
             device_discovery,
             mdns_server,
             uart_connection,
+            device_simulators,
+            mailer,
         }
     }
 }
@@ -69,8 +82,6 @@ impl AppState {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::collections::HashMap;
-    use tokio::sync::RwLock;
     use crate::device_store::create_shared_store;
 
     async fn create_test_app_state() -> AppState {
@@ -84,15 +95,13 @@ mod tests {
             mdns_server::MdnsServer::new().unwrap(),
         ));
         let uart = Arc::new(tokio::sync::Mutex::new(
-            uart_connection::UartConnection::new(
-                device_store.clone(),
-                Arc::new(RwLock::new(HashMap::new())),
-                Arc::new(RwLock::new(HashMap::new())),
-                Arc::new(RwLock::new(HashMap::new())),
-            ),
+            uart_connection::UartConnection::new(device_manager.transport_context()),
         ));
 
-        AppState::new(db, device_store, device_manager, device_discovery, mdns, uart)
+        let simulators = Arc::new(device_simulator::SimulatorManager::new(Some(device_manager.clone())));
+        let mailer = Arc::new(email::Mailer::new(db.clone(), &crate::config::AppConfig::default().email).unwrap());
+
+        AppState::new(db, device_store, device_manager, device_discovery, mdns, uart, simulators, mailer)
     }
 
     #[tokio::test]
@@ -3,7 +3,9 @@
 use axum::http::HeaderValue;
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
 use serde::{Deserialize, Deserializer, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::sync::{LazyLock, RwLock};
 
 // JWT secret key - should be loaded from environment variable in production
 const JWT_SECRET: &[u8] = b"your-secret-key-should-be-much-longer-and-random";
@@ -11,6 +13,7 @@ const JWT_SECRET: &[u8] = b"your-secret-key-should-be-much-longer-and-random";
 // Data structures for authentication
 
 // Device representation with permissions and status  
+#[allow(dead_code)]
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Device {
     pub id: String,
@@ -31,19 +34,14 @@ pub struct Device {
 // - Not updated (field not in JSON)
 // - Cleared/deleted (field is null in JSON)
 // - Set to a value (field has a value in JSON)
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub enum MaybeAbsent<T> {
+    #[default]
     Absent,        // Field not in JSON
     Null,          // Field is null in JSON
     Value(T),      // Field has a value in JSON
 }
 
-impl<T> Default for MaybeAbsent<T> {
-    fn default() -> Self {
-        MaybeAbsent::Absent
-    }
-}
-
 impl<'de, T: Deserialize<'de>> Deserialize<'de> for MaybeAbsent<T> {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
@@ -63,6 +61,15 @@ pub struct CreateDeviceRequest {
     pub mac_address: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AdoptDeviceRequest {
+    /// ID from /api/devices/discovered
+    pub device_id: String,
+    /// Optional name; defaults to the name announced by the device
+    #[serde(default)]
+    pub name: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct UpdateDeviceRequest {
     #[serde(default)]
@@ -71,12 +78,314 @@ pub struct UpdateDeviceRequest {
     pub alias: MaybeAbsent<String>,
     #[serde(default)]
     pub maintenance_mode: MaybeAbsent<bool>,
+    #[serde(default)]
+    pub notes: MaybeAbsent<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceTagsRequest {
+    /// Replaces all tags of the device
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceLocationRequest {
+    /// Replaces the whole location; all fields empty clears it
+    pub building: Option<String>,
+    pub room: Option<String>,
+    pub bench: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceGroupRequest {
+    pub name: String,
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceGroupRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Replaces the member list when present
+    #[serde(default)]
+    pub device_ids: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeviceGroupPermissionRequest {
+    pub user_id: String,
+    /// R, W, V or M, passed on to every device of the group the owner manages
+    pub permission: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    /// Event types, e.g. ["device.offline", "alert.triggered"]
+    pub events: Vec<String>,
+    /// Only events of these devices; empty = all devices
+    #[serde(default)]
+    pub device_ids: Vec<String>,
+    /// Signing secret; generated when absent
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookRequest {
+    #[serde(default)]
+    pub url: Option<String>,
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    #[serde(default)]
+    pub device_ids: Option<Vec<String>>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateAlertRuleRequest {
+    pub device_id: String,
+    /// Defaults to the condition text
+    #[serde(default)]
+    pub name: Option<String>,
+    /// e.g. "variable temp > 80 for 30s", "device offline > 5m"
+    pub condition: String,
+    #[serde(default)]
+    pub notify_webhook: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateAlertRuleRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub condition: Option<String>,
+    #[serde(default)]
+    pub notify_webhook: Option<bool>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateRoutingRuleRequest {
+    /// Defaults to "<trigger> -> <target>"
+    #[serde(default)]
+    pub name: Option<String>,
+    pub source_device_id: String,
+    /// e.g. "variable button == 1", "variable temp changes", "device offline"
+    pub trigger: String,
+    pub target_device_id: String,
+    /// Same JSON as POST /api/devices/:id/command
+    pub command: serde_json::Value,
+    #[serde(default)]
+    pub max_per_minute: Option<u32>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateRoutingRuleRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub source_device_id: Option<String>,
+    #[serde(default)]
+    pub trigger: Option<String>,
+    #[serde(default)]
+    pub target_device_id: Option<String>,
+    #[serde(default)]
+    pub command: Option<serde_json::Value>,
+    #[serde(default)]
+    pub max_per_minute: Option<u32>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateScheduleRequest {
+    /// Defaults to the schedule text
+    #[serde(default)]
+    pub name: Option<String>,
+    /// e.g. "every day at 18:00", "every 15m", "cron 0 18 * * 1-5"
+    pub schedule: String,
+    /// Target: either a device or a device group
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    /// Same JSON as POST /api/devices/:id/command
+    pub command: serde_json::Value,
+    /// Run missed executions after downtime (default true)
+    #[serde(default)]
+    pub catch_up: Option<bool>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateScheduleRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub schedule: Option<String>,
+    /// Setting one target replaces the other
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub group_id: Option<String>,
+    #[serde(default)]
+    pub command: Option<serde_json::Value>,
+    #[serde(default)]
+    pub catch_up: Option<bool>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateScriptRequest {
+    pub name: String,
+    /// Device whose events run the script
+    pub device_id: String,
+    /// Event types, e.g. ["DeviceVariableUpdate"] (default: all)
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Rhai source
+    pub source: String,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateScriptRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub device_id: Option<String>,
+    #[serde(default)]
+    pub events: Option<Vec<String>>,
+    #[serde(default)]
+    pub source: Option<String>,
+    #[serde(default)]
+    pub enabled: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateOrganizationRequest {
+    pub name: String,
+    /// Defaults to a slug of the name
+    #[serde(default)]
+    pub slug: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AddOrgMemberRequest {
+    pub email: String,
+    /// member (default), admin or owner
+    #[serde(default)]
+    pub role: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateOrgMemberRequest {
+    pub role: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MoveDeviceOrgRequest {
+    /// Target organization (ID or slug), null moves the device out
+    pub org_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DefaultPermissionRequest {
+    /// R, W or V for plain organization members; null restores the role default (W)
+    pub permission: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateDeviceProfileRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Variables with optional min/max/enum
+    #[serde(default)]
+    pub variables: Vec<crate::device_profiles::VariableSpec>,
+    /// Accepted start options (empty = any)
+    #[serde(default)]
+    pub start_options: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateDeviceProfileRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub variables: Option<Vec<crate::device_profiles::VariableSpec>>,
+    #[serde(default)]
+    pub start_options: Option<Vec<String>>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AssignDeviceProfileRequest {
+    /// Profile to assign, null removes the assignment
+    pub profile_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCommandTemplateRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Device commands with optional {{param}} placeholders
+    pub steps: Vec<crate::command_templates::TemplateStep>,
+    /// Default parameter values
+    #[serde(default)]
+    pub parameters: std::collections::BTreeMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub continue_on_error: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateCommandTemplateRequest {
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub steps: Option<Vec<crate::command_templates::TemplateStep>>,
+    #[serde(default)]
+    pub parameters: Option<std::collections::BTreeMap<String, serde_json::Value>>,
+    #[serde(default)]
+    pub continue_on_error: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RunTemplateRequest {
+    /// Parameter values, override the template defaults
+    #[serde(default)]
+    pub params: std::collections::BTreeMap<String, serde_json::Value>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BulkCommandRequest {
+    pub device_ids: Vec<String>,
+    /// Same JSON as POST /api/devices/:id/command
+    pub command: serde_json::Value,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UpdatePermissionRequest {
     pub user_id: String,
     pub permission: String,
+    /// End of a time-limited grant (RFC 3339); omitted = permanent
+    #[serde(default)]
+    pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
 // Registered user representation
@@ -103,6 +412,9 @@ pub struct RegisterRequest {
     pub email: String,
     pub display_name: String,
     pub password: String,
+    /// Optional unique @handle for mentions
+    #[serde(default)]
+    pub handle: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -116,6 +428,43 @@ pub struct UpdateDisplayNameRequest {
     pub display_name: String,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct UpdateHandleRequest {
+    /// New handle, null or empty removes it
+    #[serde(default)]
+    pub handle: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetRequest {
+    pub email: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PasswordResetConfirmRequest {
+    /// Token from the reset link
+    pub token: String,
+    /// New password
+    pub password: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateNotificationPreferencesRequest {
+    #[serde(default)]
+    pub email_alerts: Option<bool>,
+    #[serde(default)]
+    pub email_alert_resolved: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountRequest {
+    /// Current password as confirmation
+    pub password: String,
+    /// User ID or email that takes over the owned devices (default: archive to the guest account)
+    #[serde(default)]
+    pub transfer_to: Option<String>,
+}
+
 // Response structure for authentication APIs
 #[derive(Debug, Serialize, Deserialize)]
 pub struct AuthResponse {
@@ -125,11 +474,14 @@ pub struct AuthResponse {
 }
 
 
+/// Validity of an issued token (and of the auth cookie)
+pub const TOKEN_LIFETIME_HOURS: i64 = 24;
+
 // JWT token creation and validation
 pub fn create_jwt(user: &User) -> Result<String, jsonwebtoken::errors::Error> {
     // Token expires after 24 hours
     let expiration = chrono::Utc::now()
-        .checked_add_signed(chrono::Duration::hours(24))
+        .checked_add_signed(chrono::Duration::hours(TOKEN_LIFETIME_HOURS))
         .expect("valid timestamp")
         .timestamp() as usize;
 
@@ -164,12 +516,47 @@ pub fn create_jwt(user: &User) -> Result<String, jsonwebtoken::errors::Error> {
 // Website feature: Checks if a user is still logged in
 pub fn validate_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error> {
     // Decrypt token and verify signature
-    decode::<Claims>(
+    let claims = decode::<Claims>(
         token,                                    // JWT string
         &DecodingKey::from_secret(JWT_SECRET),   // Verification with secret
         &Validation::default(),                  // Standard validation (expiration date etc.)
     )
-    .map(|data| data.claims)  // Only return claims, not the whole token
+    .map(|data| data.claims)?;  // Only return claims, not the whole token
+
+    // Tokens of deleted accounts are rejected until they would have expired anyway
+    if is_user_revoked(&claims.user_id) {
+        return Err(jsonwebtoken::errors::ErrorKind::InvalidToken.into());
+    }
+    Ok(claims)
+}
+
+// Users whose tokens are no longer accepted (user ID -> unix time when the last token expires).
+// The database keeps a copy (revoked_sessions) that is loaded again after a restart.
+static REVOKED_USERS: LazyLock<RwLock<HashMap<String, usize>>> = LazyLock::new(|| RwLock::new(HashMap::new()));
+
+/// Invalidate all issued tokens of a user (e.g. after account deletion)
+/// JWTs are stateless, so the user stays on this list for one token lifetime
+pub fn revoke_user_sessions(user_id: &str) {
+    let until = chrono::Utc::now() + chrono::Duration::hours(TOKEN_LIFETIME_HOURS);
+    load_revoked_sessions([(user_id.to_string(), until)]);
+}
+
+/// Add revocations stored in the database (startup); expired entries are dropped
+pub fn load_revoked_sessions(entries: impl IntoIterator<Item = (String, chrono::DateTime<chrono::Utc>)>) {
+    let now = chrono::Utc::now().timestamp() as usize;
+    let mut revoked = REVOKED_USERS.write().unwrap_or_else(|e| e.into_inner());
+    revoked.retain(|_, expires| *expires > now);
+    for (user_id, until) in entries {
+        let until = until.timestamp().max(0) as usize;
+        if until > now {
+            revoked.insert(user_id, until);
+        }
+    }
+}
+
+fn is_user_revoked(user_id: &str) -> bool {
+    let revoked = REVOKED_USERS.read().unwrap_or_else(|e| e.into_inner());
+    revoked.get(user_id).is_some_and(|expires| *expires > chrono::Utc::now().timestamp() as usize)
 }
 
 // ============================================================================
@@ -177,6 +564,22 @@ pub fn validate_jwt(token: &str) -> Result<Claims, jsonwebtoken::errors::Error>
 // Website feature: Secure password storage
 // ============================================================================
 
+/// Minimum length of a password set via the reset link
+pub const MIN_PASSWORD_LENGTH: usize = 8;
+
+/// Validity of a password reset link
+pub const PASSWORD_RESET_VALIDITY_MINUTES: i64 = 60;
+
+/// Random token for a password reset link (sent by email, never stored)
+pub fn generate_reset_token() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// Stored form of a reset token: a leaked database does not reveal usable links
+pub fn hash_reset_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
 
 // ============================================================================
 // USER IMPLEMENTATION - Methods for user objects
@@ -194,24 +597,40 @@ impl User {}
 
 // Erstellt ein sicheres Auth-Cookie mit JWT Token
 // Website-Feature: Wird nach erfolgreichem Login gesetzt
-pub fn create_auth_cookie(token: &str) -> HeaderValue {
+// `secure` = Anfrage kam per HTTPS (auch über einen Reverse Proxy)
+pub fn create_auth_cookie(token: &str, secure: bool) -> HeaderValue {
     let cookie_value = format!(
-        "auth_token={}; HttpOnly; Path=/; Max-Age=86400; SameSite=Strict",
-        token
+        "auth_token={}; HttpOnly; Path={}; Max-Age=86400; SameSite=Strict{}",
+        token,
+        cookie_path(),
+        if secure { "; Secure" } else { "" }
     );
     // HttpOnly = JavaScript kann nicht auf Cookie zugreifen (XSS-Schutz)
-    // Path=/ = Cookie gilt für ganze Website
+    // Path = Cookie gilt für ganze Website (bzw. den Mount-Pfad hinter einem Proxy)
     // Max-Age=86400 = Cookie läuft nach 24h ab (86400 Sekunden)
     // SameSite=Strict = Schutz vor CSRF-Attacken
+    // Secure = Cookie nur über HTTPS senden
     HeaderValue::from_str(&cookie_value).unwrap()
 }
 
 // Erstellt ein Logout-Cookie (löscht das Auth-Cookie)
 // Website-Feature: Wird beim Logout aufgerufen
-pub fn create_logout_cookie() -> HeaderValue {
-    // Max-Age=0 = Cookie sofort löschen
-    let cookie_value = "auth_token=; HttpOnly; Path=/; Max-Age=0; SameSite=Strict";
-    HeaderValue::from_str(cookie_value).unwrap()
+pub fn create_logout_cookie(secure: bool) -> HeaderValue {
+    // Max-Age=0 = Cookie sofort löschen (Path muss zum gesetzten Cookie passen)
+    let cookie_value = format!(
+        "auth_token=; HttpOnly; Path={}; Max-Age=0; SameSite=Strict{}",
+        cookie_path(),
+        if secure { "; Secure" } else { "" }
+    );
+    HeaderValue::from_str(&cookie_value).unwrap()
+}
+
+// Cookie-Pfad = Mount-Pfad der App ("/" ohne Reverse-Proxy-Präfix)
+fn cookie_path() -> &'static str {
+    match crate::proxy::base_path() {
+        "" => "/",
+        path => path,
+    }
 }
 
 // ============================================================================
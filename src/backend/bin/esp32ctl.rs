@@ -0,0 +1,440 @@
+// esp32ctl - command line client for the REST API
+//
+// For headless machines (the lab Pi over SSH) where no browser is available. Talks to a running
+// server like the web client does: `esp32ctl login` stores the session cookie in ~/.esp32ctl/session
+// (ESP32CTL_SESSION), later commands send it along. The server URL comes from --url, ESP32CTL_URL or
+// the session, default http://localhost:3000. Passwords are read from ESP32CTL_PASSWORD or prompted.
+
+use std::io::{BufRead, Write};
+use std::path::PathBuf;
+use std::time::Duration;
+
+use drawing_app_backend::events::{ClientMessage, ReplayRequest};
+use futures::{SinkExt, StreamExt};
+use reqwest::{Method, StatusCode};
+use serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::client::IntoClientRequest;
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_URL: &str = "http://localhost:3000";
+
+const USAGE: &str = "\
+Usage: esp32ctl [--url URL] [--json] <command> [args]
+
+Commands:
+  login <email>                        Log in and store the session
+  logout                               Forget the stored session
+  devices                              List devices
+  discovered                           List discovered devices waiting for adoption
+  adopt <device-id> [--name NAME]      Adopt a discovered device
+  send <device-id> <json> [--timeout-ms MS]
+                                       Send a command, e.g. '{\"type\":\"reboot\"}'
+  events <device-id> [--follow] [--limit N]
+                                       Show recent events, --follow keeps streaming new ones
+  users create <email> <display-name>  Create a user (password from ESP32CTL_PASSWORD or prompt)
+  backup                               Write a database backup on the server (admin)
+
+Options:
+  --url URL    Server URL (ESP32CTL_URL, default http://localhost:3000)
+  --json       Print the raw JSON responses";
+
+/// Stored login: server URL and the auth_token cookie
+struct Session {
+    url: String,
+    token: String,
+}
+
+impl Session {
+    fn path() -> PathBuf {
+        if let Ok(path) = std::env::var("ESP32CTL_SESSION") {
+            return PathBuf::from(path);
+        }
+        let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE")).unwrap_or_else(|_| ".".to_string());
+        PathBuf::from(home).join(".esp32ctl").join("session")
+    }
+
+    fn load() -> Option<Self> {
+        let content = std::fs::read_to_string(Self::path()).ok()?;
+        let mut lines = content.lines();
+        Some(Self { url: lines.next()?.to_string(), token: lines.next()?.to_string() })
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let path = Self::path();
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(&path, format!("{}\n{}\n", self.url, self.token)).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let _ = std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600));
+        }
+        Ok(())
+    }
+}
+
+struct Client {
+    http: reqwest::Client,
+    url: String,
+    token: Option<String>,
+    raw_json: bool,
+}
+
+impl Client {
+    async fn request(&self, method: Method, path: &str, body: Option<Value>) -> Result<Value, String> {
+        let mut request = self.http.request(method, format!("{}{}", self.url, path));
+        if let Some(token) = &self.token {
+            request = request.header(reqwest::header::COOKIE, format!("auth_token={}", token));
+        }
+        if let Some(body) = body {
+            request = request.json(&body);
+        }
+        let response = request.send().await.map_err(|e| format!("Cannot reach {}: {}", self.url, e))?;
+        let status = response.status();
+        let value: Value = response.json().await.unwrap_or(Value::Null);
+        if !status.is_success() && status != StatusCode::ACCEPTED {
+            let message = value.get("message").and_then(Value::as_str).unwrap_or(status.canonical_reason().unwrap_or("request failed"));
+            let code = value.get("code").and_then(Value::as_str).map(|code| format!(" ({})", code)).unwrap_or_default();
+            let hint = if status == StatusCode::UNAUTHORIZED { ", run `esp32ctl login <email>` first" } else { "" };
+            return Err(format!("{}{}{}", message, code, hint));
+        }
+        Ok(value)
+    }
+
+    async fn get(&self, path: &str) -> Result<Value, String> {
+        self.request(Method::GET, path, None).await
+    }
+
+    async fn post(&self, path: &str, body: Value) -> Result<Value, String> {
+        self.request(Method::POST, path, Some(body)).await
+    }
+
+    fn print_raw(&self, value: &Value) -> bool {
+        if self.raw_json {
+            println!("{}", serde_json::to_string_pretty(value).unwrap_or_default());
+        }
+        self.raw_json
+    }
+}
+
+fn text(value: &Value, keys: &[&str]) -> String {
+    keys.iter()
+        .filter_map(|key| value.get(*key))
+        .find(|field| !field.is_null())
+        .map(|field| field.as_str().map(str::to_string).unwrap_or_else(|| field.to_string()))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+/// Rows as left-aligned columns
+fn print_table(header: &[&str], rows: Vec<Vec<String>>) {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    let line = |cells: Vec<String>| {
+        cells.iter().zip(&widths).map(|(cell, width)| format!("{:<width$}", cell, width = width)).collect::<Vec<_>>().join("  ").trim_end().to_string()
+    };
+    println!("{}", line(header.iter().map(|h| h.to_string()).collect()));
+    for row in rows {
+        println!("{}", line(row));
+    }
+}
+
+fn read_password(prompt: &str) -> Result<String, String> {
+    if let Ok(password) = std::env::var("ESP32CTL_PASSWORD") {
+        return Ok(password);
+    }
+    eprint!("{}", prompt);
+    let _ = std::io::stderr().flush();
+    let echo = EchoGuard::off();
+    let mut line = String::new();
+    std::io::stdin().lock().read_line(&mut line).map_err(|e| format!("Cannot read password: {}", e))?;
+    drop(echo);
+    eprintln!();
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// Terminal echo switched off while a password is typed (restored on drop)
+struct EchoGuard {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl EchoGuard {
+    #[cfg(unix)]
+    fn off() -> Self {
+        // SAFETY: tcgetattr/tcsetattr on stdin with a zeroed termios they fill in
+        unsafe {
+            let mut term: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut term) != 0 {
+                return Self { saved: None };
+            }
+            let saved = term;
+            term.c_lflag &= !libc::ECHO;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term);
+            Self { saved: Some(saved) }
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn off() -> Self {
+        Self {}
+    }
+}
+
+impl Drop for EchoGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = &self.saved {
+            // SAFETY: restores the attributes read in off()
+            unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, saved) };
+        }
+    }
+}
+
+/// Value of `--name VALUE` in the arguments (removed from them)
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let index = args.iter().position(|arg| arg == name)?;
+    args.remove(index);
+    (index < args.len()).then(|| args.remove(index))
+}
+
+fn take_flag(args: &mut Vec<String>, names: &[&str]) -> bool {
+    let before = args.len();
+    args.retain(|arg| !names.contains(&arg.as_str()));
+    args.len() != before
+}
+
+fn positional(args: &[String], index: usize, name: &str) -> Result<String, String> {
+    args.get(index).cloned().ok_or_else(|| format!("Missing <{}>\n\n{}", name, USAGE))
+}
+
+async fn login(client: &Client, email: &str) -> Result<(), String> {
+    let password = read_password(&format!("Password for {}: ", email))?;
+    let response = client.http.post(format!("{}/api/login", client.url))
+        .json(&json!({ "email": email, "password": password }))
+        .send().await
+        .map_err(|e| format!("Cannot reach {}: {}", client.url, e))?;
+    let token = response.headers().get_all(reqwest::header::SET_COOKIE).iter()
+        .filter_map(|header| header.to_str().ok())
+        .find_map(|cookie| cookie.strip_prefix("auth_token="))
+        .map(|cookie| cookie.split(';').next().unwrap_or_default().to_string());
+    let status = response.status();
+    let body: Value = response.json().await.unwrap_or(Value::Null);
+    let Some(token) = token.filter(|_| status.is_success()) else {
+        return Err(format!("Login failed: {}", text(&body, &["message", "error"])));
+    };
+    Session { url: client.url.clone(), token }.save()?;
+    println!("Logged in as {} at {}", email, client.url);
+    Ok(())
+}
+
+async fn list_devices(client: &Client) -> Result<(), String> {
+    let response = client.get("/api/devices").await?;
+    if client.print_raw(&response) {
+        return Ok(());
+    }
+    let devices = response["devices"].as_array().cloned().unwrap_or_default();
+    let rows = devices.iter().map(|device| vec![
+        text(device, &["id"]),
+        text(device, &["alias", "name"]),
+        text(device, &["status"]),
+        if device["connected"].as_bool() == Some(true) { "yes".to_string() } else { "no".to_string() },
+        text(device, &["ip_address"]),
+        text(device, &["firmware_version"]),
+    ]).collect();
+    print_table(&["ID", "NAME", "STATUS", "CONNECTED", "IP", "FIRMWARE"], rows);
+    Ok(())
+}
+
+async fn list_discovered(client: &Client) -> Result<(), String> {
+    let response = client.get("/api/devices/discovered").await?;
+    if client.print_raw(&response) {
+        return Ok(());
+    }
+    let devices = response["devices"].as_array().cloned().unwrap_or_default();
+    let rows = devices.iter().map(|device| vec![
+        text(device, &["deviceId", "id"]),
+        text(device, &["name", "mdnsHostname"]),
+        text(device, &["macAddress"]),
+        text(device, &["deviceIp"]),
+        text(device, &["connectionType"]),
+        text(device, &["freshness"]),
+    ]).collect();
+    print_table(&["ID", "NAME", "MAC", "IP", "TYPE", "SEEN"], rows);
+    Ok(())
+}
+
+async fn show_events(client: &Client, device_id: &str, limit: usize, follow: bool) -> Result<(), String> {
+    let response = client.get(&format!("/api/devices/{}/events?limit={}", device_id, limit)).await?;
+    let mut cursor = response["nextCursor"].as_str().map(str::to_string);
+    for event in response["events"].as_array().cloned().unwrap_or_default() {
+        print_event(client, &event);
+    }
+    if !follow {
+        return Ok(());
+    }
+    if client.url.starts_with("http://") {
+        follow_websocket(client, device_id).await
+    } else {
+        // No TLS for the WebSocket client: poll the stored events instead
+        loop {
+            tokio::time::sleep(Duration::from_secs(2)).await;
+            let path = match &cursor {
+                Some(cursor) => format!("/api/devices/{}/events?cursor={}", device_id, cursor),
+                None => format!("/api/devices/{}/events", device_id),
+            };
+            let page = client.get(&path).await?;
+            for event in page["events"].as_array().cloned().unwrap_or_default() {
+                print_event(client, &event);
+            }
+            if let Some(next) = page["nextCursor"].as_str() {
+                cursor = Some(next.to_string());
+            }
+        }
+    }
+}
+
+fn print_event(client: &Client, event: &Value) {
+    if client.raw_json {
+        println!("{}", event);
+        return;
+    }
+    let time = event["timestamp"].as_i64()
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|time| time.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M:%S%.3f").to_string())
+        .unwrap_or_else(|| "-".to_string());
+    let body = event.get("event").unwrap_or(event);
+    println!("{}  {}", time, body);
+}
+
+/// Live events over the WebSocket channel the web client uses
+async fn follow_websocket(client: &Client, device_id: &str) -> Result<(), String> {
+    let ws_url = format!("ws://{}/channel", client.url.trim_start_matches("http://"));
+    let mut request = ws_url.as_str().into_client_request().map_err(|e| format!("Invalid URL {}: {}", ws_url, e))?;
+    if let Some(token) = &client.token {
+        let cookie = format!("auth_token={}", token).parse().map_err(|_| "Invalid session token".to_string())?;
+        request.headers_mut().insert("Cookie", cookie);
+    }
+    let (mut socket, _) = tokio_tungstenite::connect_async(request).await.map_err(|e| format!("WebSocket connection failed: {}", e))?;
+
+    // Replay nothing: the recent history was printed already
+    let register = ClientMessage::RegisterForDevice {
+        device_id: device_id.to_string(),
+        subscription_type: Default::default(),
+        replay: Some(ReplayRequest { since: Some(chrono::Utc::now().timestamp_millis()), limit: None, cursor: None }),
+        events: None,
+    };
+    let register = serde_json::to_string(&register).map_err(|e| e.to_string())?;
+    socket.send(Message::Text(register)).await.map_err(|e| format!("WebSocket send failed: {}", e))?;
+    eprintln!("Following events of {} (Ctrl+C to stop)", device_id);
+
+    while let Some(message) = socket.next().await {
+        let text = match message.map_err(|e| format!("WebSocket closed: {}", e))? {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            _ => continue,
+        };
+        let Ok(value) = serde_json::from_str::<Value>(&text) else { continue };
+        if value["deviceId"].as_str() != Some(device_id) {
+            continue;
+        }
+        if let Some(events) = value["eventsForDevice"].as_array() {
+            let now = json!(chrono::Utc::now().timestamp_millis());
+            for event in events {
+                print_event(client, &json!({ "timestamp": now, "event": event }));
+            }
+        } else if let Some(error) = value.get("error").or_else(|| value.get("reason")) {
+            return Err(format!("Subscription rejected: {}", error));
+        }
+    }
+    Ok(())
+}
+
+async fn run(mut args: Vec<String>) -> Result<(), String> {
+    let url_option = take_option(&mut args, "--url");
+    let raw_json = take_flag(&mut args, &["--json"]);
+    if args.is_empty() || take_flag(&mut args, &["-h", "--help", "help"]) {
+        println!("{}", USAGE);
+        return Ok(());
+    }
+
+    let session = Session::load();
+    let url = url_option
+        .or_else(|| std::env::var("ESP32CTL_URL").ok())
+        .or_else(|| session.as_ref().map(|session| session.url.clone()))
+        .unwrap_or_else(|| DEFAULT_URL.to_string())
+        .trim_end_matches('/')
+        .to_string();
+    // The stored session only belongs to the server it was created for
+    let token = session.filter(|session| session.url == url).map(|session| session.token);
+    let http = reqwest::Client::builder().timeout(Duration::from_secs(30)).build().map_err(|e| e.to_string())?;
+    let client = Client { http, url, token, raw_json };
+
+    let command = args.remove(0);
+    match command.as_str() {
+        "login" => login(&client, &positional(&args, 0, "email")?).await,
+        "logout" => {
+            let _ = std::fs::remove_file(Session::path());
+            println!("Session removed");
+            Ok(())
+        }
+        "devices" => list_devices(&client).await,
+        "discovered" => list_discovered(&client).await,
+        "adopt" => {
+            let name = take_option(&mut args, "--name");
+            let device_id = positional(&args, 0, "device-id")?;
+            let response = client.post("/api/devices/adopt", json!({ "device_id": device_id, "name": name })).await?;
+            if !client.print_raw(&response) {
+                println!("Adopted {}", device_id);
+            }
+            Ok(())
+        }
+        "send" => {
+            let timeout_ms = take_option(&mut args, "--timeout-ms");
+            let device_id = positional(&args, 0, "device-id")?;
+            let command: Value = serde_json::from_str(&positional(&args, 1, "json")?).map_err(|e| format!("Command is not valid JSON: {}", e))?;
+            let query = timeout_ms.map(|ms| format!("?timeout_ms={}", ms)).unwrap_or_default();
+            let response = client.post(&format!("/api/devices/{}/command{}", device_id, query), command).await?;
+            if !client.print_raw(&response) {
+                println!("{}: {}", text(&response, &["status"]), response.get("response").map(Value::to_string).unwrap_or_else(|| text(&response, &["message"])));
+            }
+            Ok(())
+        }
+        "events" => {
+            let limit = take_option(&mut args, "--limit").map(|limit| limit.parse::<usize>().map_err(|_| "--limit must be a number".to_string())).transpose()?;
+            let follow = take_flag(&mut args, &["-f", "--follow"]);
+            show_events(&client, &positional(&args, 0, "device-id")?, limit.unwrap_or(20), follow).await
+        }
+        "users" if args.first().map(String::as_str) == Some("create") => {
+            let email = positional(&args, 1, "email")?;
+            let display_name = positional(&args, 2, "display-name")?;
+            let password = read_password(&format!("Password for {}: ", email))?;
+            let response = client.post("/api/register", json!({ "email": email, "display_name": display_name, "password": password })).await?;
+            if !client.print_raw(&response) {
+                println!("Created user {}", email);
+            }
+            Ok(())
+        }
+        "backup" => {
+            let response = client.post("/api/admin/backup", json!({})).await?;
+            if !client.print_raw(&response) {
+                println!("Backup written on the server: {} ({} bytes)", text(&response, &["path"]), text(&response, &["sizeBytes"]));
+            }
+            Ok(())
+        }
+        other => Err(format!("Unknown command '{}'\n\n{}", other, USAGE)),
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if let Err(e) = run(args).await {
+        eprintln!("esp32ctl: {}", e);
+        std::process::exit(1);
+    }
+}
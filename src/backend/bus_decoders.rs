@@ -0,0 +1,300 @@
+// Bus decoders - turn raw industrial bus frames forwarded by a board into named variables
+//
+// Some boards pass Modbus or CAN frames through verbatim, either as a bare hex line
+// ("01 03 04 00 EB 00 64 xx xx") or as `{"frame": "010304..."}`. A decoder plugin selected per
+// device in [bus_decoders] translates such a frame into variables before it reaches the event
+// store; frames of devices without a decoder, and frames a decoder rejects, take the normal path.
+//
+//   [bus_decoders]
+//   devices = ["AA-BB-CC-DD-EE-FF=modbus_rtu:config/boiler.modbus.json"]
+//
+// Built in is `modbus_rtu`: responses to Read Holding/Input Registers (function 3/4) are checked
+// (CRC) and mapped through a JSON register map of the polled blocks:
+//
+//   {"blocks": [{"unit": 1, "function": 3, "start": 100, "registers": [
+//       {"register": 100, "name": "boiler_temp", "type": "i16", "scale": 0.1},
+//       {"register": 102, "name": "energy_kwh", "type": "u32"}]}]}
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+use serde::Deserialize;
+
+/// Decoder plugin: named variables (name, value) of one raw bus frame
+pub trait BusDecoder: Send + Sync {
+    fn decode(&self, frame: &[u8]) -> Result<Vec<(String, String)>, String>;
+}
+
+/// One `devices` entry of [bus_decoders]: "<device ID>=<decoder>[:<register map file>]"
+#[derive(Debug, Clone, PartialEq)]
+pub struct DecoderAssignment {
+    pub device_id: String,
+    pub decoder: String,
+    pub map_path: Option<String>,
+}
+
+impl DecoderAssignment {
+    pub fn parse(entry: &str) -> Result<Self, String> {
+        let (device_id, spec) = entry.split_once('=')
+            .ok_or_else(|| format!("expected \"<device ID>=<decoder>:<map file>\": {}", entry))?;
+        let (decoder, map_path) = match spec.split_once(':') {
+            Some((decoder, path)) => (decoder.trim(), Some(path.trim().to_string())),
+            None => (spec.trim(), None),
+        };
+        let device_id = device_id.trim();
+        if device_id.is_empty() {
+            return Err(format!("missing device ID: {}", entry));
+        }
+        match decoder {
+            "modbus_rtu" if map_path.as_deref().is_some_and(|path| !path.is_empty()) => {}
+            "modbus_rtu" => return Err(format!("modbus_rtu needs a register map file: {}", entry)),
+            _ => return Err(format!("unknown decoder '{}' (modbus_rtu)", decoder)),
+        }
+        Ok(Self { device_id: device_id.to_string(), decoder: decoder.to_string(), map_path })
+    }
+
+    /// Create the decoder, loading its register map
+    pub fn build(&self) -> Result<Arc<dyn BusDecoder>, String> {
+        let path = self.map_path.as_deref().unwrap_or_default();
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let decoder = ModbusRtuDecoder::from_json(&text).map_err(|e| format!("{}: {}", path, e))?;
+        Ok(Arc::new(decoder))
+    }
+}
+
+/// Decoders of all devices, shared with the transports
+#[derive(Default)]
+pub struct BusDecoders {
+    decoders: RwLock<HashMap<String, Arc<dyn BusDecoder>>>,
+}
+
+impl std::fmt::Debug for BusDecoders {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let devices = self.decoders.read().map(|d| d.len()).unwrap_or_default();
+        f.debug_struct("BusDecoders").field("devices", &devices).finish()
+    }
+}
+
+impl BusDecoders {
+    /// Replace the decoders with those of the [bus_decoders] entries; returns how many are active
+    pub fn configure(&self, entries: &[String]) -> Result<usize, String> {
+        let mut decoders = HashMap::new();
+        for entry in entries {
+            let assignment = DecoderAssignment::parse(entry)?;
+            decoders.insert(assignment.device_id.clone(), assignment.build()?);
+        }
+        let count = decoders.len();
+        *self.decoders.write().unwrap_or_else(|e| e.into_inner()) = decoders;
+        Ok(count)
+    }
+
+    /// Variables of a received message; None if the device has no decoder or the message is no raw frame
+    pub fn decode(&self, device_id: &str, message: &str) -> Option<Result<Vec<(String, String)>, String>> {
+        let decoder = self.decoders.read().unwrap_or_else(|e| e.into_inner()).get(device_id).cloned()?;
+        let frame = raw_frame(message)?;
+        Some(decoder.decode(&frame))
+    }
+}
+
+/// Bytes of a raw frame message: a hex line (spaces allowed) or `{"frame": "<hex>"}`
+pub fn raw_frame(message: &str) -> Option<Vec<u8>> {
+    let message = message.trim();
+    let hex = if message.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(message).ok()?;
+        value.get("frame")?.as_str()?.to_string()
+    } else {
+        message.to_string()
+    };
+    let digits: String = hex.chars().filter(|c| !c.is_whitespace()).collect();
+    if digits.is_empty() || !digits.len().is_multiple_of(2) || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..digits.len()).step_by(2).map(|i| u8::from_str_radix(&digits[i..i + 2], 16).ok()).collect()
+}
+
+// ============================================================================
+// MODBUS RTU
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum RegisterType {
+    U16,
+    I16,
+    /// Two registers, high word first
+    U32,
+    I32,
+    F32,
+}
+
+impl RegisterType {
+    fn words(self) -> usize {
+        match self {
+            RegisterType::U16 | RegisterType::I16 => 1,
+            RegisterType::U32 | RegisterType::I32 | RegisterType::F32 => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RegisterDef {
+    register: u16,
+    name: String,
+    #[serde(rename = "type", default = "default_register_type")]
+    kind: RegisterType,
+    #[serde(default = "default_scale")]
+    scale: f64,
+}
+
+fn default_register_type() -> RegisterType {
+    RegisterType::U16
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+/// A register block the board polls (one request, one response)
+#[derive(Debug, Clone, Deserialize)]
+struct RegisterBlock {
+    unit: u8,
+    #[serde(default = "default_function")]
+    function: u8,
+    start: u16,
+    registers: Vec<RegisterDef>,
+}
+
+fn default_function() -> u8 {
+    3
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct RegisterMap {
+    blocks: Vec<RegisterBlock>,
+}
+
+/// Built-in decoder for Modbus RTU register responses
+#[derive(Debug, Clone)]
+pub struct ModbusRtuDecoder {
+    blocks: Vec<RegisterBlock>,
+}
+
+impl ModbusRtuDecoder {
+    /// Decoder for a JSON register map
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let map: RegisterMap = serde_json::from_str(text).map_err(|e| format!("invalid register map: {}", e))?;
+        for block in &map.blocks {
+            if !matches!(block.function, 3 | 4) {
+                return Err(format!("unit {}: only functions 3 and 4 are supported, not {}", block.unit, block.function));
+            }
+            if let Some(register) = block.registers.iter().find(|r| r.register < block.start) {
+                return Err(format!("unit {}: register {} lies before the block start {}", block.unit, register.register, block.start));
+            }
+        }
+        Ok(Self { blocks: map.blocks })
+    }
+}
+
+/// Modbus CRC-16 (polynomial 0xA001, initial 0xFFFF)
+fn modbus_crc(bytes: &[u8]) -> u16 {
+    bytes.iter().fold(0xFFFF, |mut crc, &byte| {
+        crc ^= byte as u16;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xA001 } else { crc >> 1 };
+        }
+        crc
+    })
+}
+
+/// Value as sent to the frontend: integers stay integers, scaled values lose float noise
+fn format_value(value: f64) -> String {
+    let rounded = (value * 1e6).round() / 1e6;
+    format!("{}", rounded)
+}
+
+impl BusDecoder for ModbusRtuDecoder {
+    fn decode(&self, frame: &[u8]) -> Result<Vec<(String, String)>, String> {
+        if frame.len() < 5 {
+            return Err(format!("frame too short ({} bytes)", frame.len()));
+        }
+        let (body, crc) = frame.split_at(frame.len() - 2);
+        if modbus_crc(body) != u16::from_le_bytes([crc[0], crc[1]]) {
+            return Err("CRC mismatch".to_string());
+        }
+        let (unit, function) = (body[0], body[1]);
+        if function & 0x80 != 0 {
+            return Err(format!("unit {} answered function {} with exception {}", unit, function & 0x7F, body[2]));
+        }
+        let block = self.blocks.iter()
+            .find(|block| block.unit == unit && block.function == function)
+            .ok_or_else(|| format!("no register block for unit {} function {}", unit, function))?;
+        let data = &body[3..];
+        if body[2] as usize != data.len() || !data.len().is_multiple_of(2) {
+            return Err(format!("byte count {} does not match {} data bytes", body[2], data.len()));
+        }
+        let words: Vec<u16> = data.chunks(2).map(|w| u16::from_be_bytes([w[0], w[1]])).collect();
+
+        let mut variables = Vec::new();
+        for def in &block.registers {
+            let offset = (def.register - block.start) as usize;
+            let Some(raw) = words.get(offset..offset + def.kind.words()) else {
+                continue; // Not part of this response
+            };
+            let wide = raw.iter().fold(0u32, |acc, &w| (acc << 16) | w as u32);
+            let value = match def.kind {
+                RegisterType::U16 | RegisterType::U32 => wide as f64,
+                RegisterType::I16 => raw[0] as i16 as f64,
+                RegisterType::I32 => wide as i32 as f64,
+                RegisterType::F32 => f32::from_bits(wide) as f64,
+            };
+            variables.push((def.name.clone(), format_value(value * def.scale)));
+        }
+        Ok(variables)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn with_crc(body: &[u8]) -> Vec<u8> {
+        let mut frame = body.to_vec();
+        frame.extend_from_slice(&modbus_crc(body).to_le_bytes());
+        frame
+    }
+
+    #[test]
+    fn test_modbus_register_map_decoding() {
+        // Reference request "read 1 holding register from unit 1": 01 03 00 00 00 01 84 0A
+        assert_eq!(with_crc(&[0x01, 0x03, 0x00, 0x00, 0x00, 0x01])[6..], [0x84, 0x0A]);
+
+        let decoder = ModbusRtuDecoder::from_json(r#"{"blocks": [{"unit": 1, "start": 100, "registers": [
+            {"register": 100, "name": "boiler_temp", "type": "i16", "scale": 0.1},
+            {"register": 101, "name": "pump"},
+            {"register": 102, "name": "energy_kwh", "type": "u32"},
+            {"register": 110, "name": "outside"}
+        ]}]}"#).unwrap();
+
+        // -23.5 °C, pump 1, 70000 kWh; register 110 was not polled
+        let frame = with_crc(&[0x01, 0x03, 0x08, 0xFF, 0x15, 0x00, 0x01, 0x00, 0x01, 0x11, 0x70]);
+        let hex: String = frame.iter().map(|b| format!("{:02X} ", b)).collect();
+        let bytes = raw_frame(&format!("{{\"frame\": \"{}\"}}", hex.replace(' ', ""))).unwrap();
+        assert_eq!(raw_frame(&hex).unwrap(), bytes);
+        assert_eq!(decoder.decode(&bytes).unwrap(), vec![
+            ("boiler_temp".to_string(), "-23.5".to_string()),
+            ("pump".to_string(), "1".to_string()),
+            ("energy_kwh".to_string(), "70000".to_string()),
+        ]);
+
+        let mut corrupted = frame.clone();
+        corrupted[4] ^= 1;
+        assert!(decoder.decode(&corrupted).unwrap_err().contains("CRC"));
+        assert!(decoder.decode(&with_crc(&[0x01, 0x83, 0x02])).unwrap_err().contains("exception 2"));
+        assert!(decoder.decode(&with_crc(&[0x02, 0x03, 0x02, 0x00, 0x01])).is_err());
+        assert!(raw_frame(r#"{"temp": 21}"#).is_none());
+
+        assert_eq!(DecoderAssignment::parse("AA-01=modbus_rtu:maps/boiler.json").unwrap().map_path.as_deref(), Some("maps/boiler.json"));
+        assert!(DecoderAssignment::parse("AA-01=modbus_rtu").is_err());
+        assert!(DecoderAssignment::parse("AA-01=can_j1939:x").is_err());
+    }
+}
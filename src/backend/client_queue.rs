@@ -0,0 +1,599 @@
+// ============================================================================
+// CLIENT QUEUE - Bounded outgoing WebSocket queue with backpressure handling
+// ============================================================================
+
+use crate::events::{DeviceEvent, ServerMessage, PROTOCOL_VERSION, PROTOCOL_VERSION_LEGACY};
+use bytes::Bytes;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, Notify};
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+use tracing::warn;
+
+/// Messages buffered per client before the drop/coalesce policy applies
+pub const CLIENT_QUEUE_CAPACITY: usize = 256;
+
+/// Clients saturated for longer than this are disconnected
+pub const SATURATION_DISCONNECT_AFTER: Duration = Duration::from_secs(10);
+
+/// Default batching window of the WebSocket writer (websocket.batch_window_ms, 0 = off)
+pub const DEFAULT_BATCH_WINDOW: Duration = Duration::from_millis(50);
+
+/// Upper bound of messages collected into one batch
+pub const MAX_BATCH_MESSAGES: usize = 256;
+
+// [websocket] batch_window_ms; replaced by a config reload, read when a client connects
+static BATCH_WINDOW_MS: AtomicU64 = AtomicU64::new(DEFAULT_BATCH_WINDOW.as_millis() as u64);
+
+pub fn set_batch_window(window: Duration) {
+    BATCH_WINDOW_MS.store(window.as_millis() as u64, Ordering::Relaxed);
+}
+
+/// Batching window for a new connection
+pub fn batch_window() -> Duration {
+    Duration::from_millis(BATCH_WINDOW_MS.load(Ordering::Relaxed))
+}
+
+/// Frame compression a client can ask for (in `hello` or `?compression=deflate`);
+/// tungstenite has no permessage-deflate, so frames are compressed here
+pub const COMPRESSION_DEFLATE: &str = "deflate";
+
+/// Shorter frames are sent uncompressed, deflate would barely shrink them
+pub const COMPRESSION_MIN_BYTES: usize = 256;
+
+/// zlib level of compressed frames (fast; a broadcast is compressed once for all its clients)
+const COMPRESSION_LEVEL: u8 = 3;
+
+// [websocket] compression; replaced by a config reload, read when a client negotiates
+static COMPRESSION_ENABLED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_compression_enabled(enabled: bool) {
+    COMPRESSION_ENABLED.store(enabled, Ordering::Relaxed);
+}
+
+/// Compression for a client offering `offered`; None when disabled or nothing in common
+pub fn negotiate_compression(offered: &[String]) -> Option<&'static str> {
+    let enabled = COMPRESSION_ENABLED.load(Ordering::Relaxed);
+    (enabled && offered.iter().any(|name| name.eq_ignore_ascii_case(COMPRESSION_DEFLATE))).then_some(COMPRESSION_DEFLATE)
+}
+
+/// One WebSocket frame of a queued message
+#[derive(Debug, Clone, PartialEq)]
+pub enum WireFrame {
+    /// JSON text frame
+    Text(Bytes),
+    /// zlib-compressed JSON, sent as binary frame; compressed on its own (no shared window),
+    /// so a browser can inflate it with `new DecompressionStream("deflate")`
+    Deflate(Bytes),
+}
+
+/// Queued message with its serialized wire form, cached on first use. A broadcast is shared
+/// by the queues of all its clients as Arc<OutboundMessage>
+#[derive(Debug)]
+pub struct OutboundMessage {
+    message: ServerMessage,
+    // Legacy and current protocol version
+    wire: [OnceLock<Bytes>; 2],
+    // Compressed wire form, same slots
+    deflated: [OnceLock<Bytes>; 2],
+}
+
+impl OutboundMessage {
+    pub fn new(message: ServerMessage) -> Self {
+        Self { message, wire: Default::default(), deflated: Default::default() }
+    }
+
+    /// JSON for a client speaking `version`; serialized once, then shared by every recipient
+    pub fn to_wire(&self, version: u32) -> Result<Bytes, serde_json::Error> {
+        let slot = match version {
+            PROTOCOL_VERSION_LEGACY => &self.wire[0],
+            PROTOCOL_VERSION => &self.wire[1],
+            _ => return self.message.to_wire(version).map(Bytes::from),
+        };
+        if let Some(wire) = slot.get() {
+            return Ok(wire.clone());
+        }
+        let wire = Bytes::from(self.message.to_wire(version)?);
+        Ok(slot.get_or_init(|| wire).clone())
+    }
+
+    /// Frame for a client speaking `version`, compressed if it negotiated compression
+    pub fn to_frame(&self, version: u32, compress: bool) -> Result<WireFrame, serde_json::Error> {
+        let wire = self.to_wire(version)?;
+        if !compress || wire.len() < COMPRESSION_MIN_BYTES {
+            return Ok(WireFrame::Text(wire));
+        }
+        let deflate = || Bytes::from(miniz_oxide::deflate::compress_to_vec_zlib(&wire, COMPRESSION_LEVEL));
+        let deflated = match version {
+            PROTOCOL_VERSION_LEGACY => self.deflated[0].get_or_init(deflate).clone(),
+            PROTOCOL_VERSION => self.deflated[1].get_or_init(deflate).clone(),
+            _ => deflate(),
+        };
+        Ok(WireFrame::Deflate(deflated))
+    }
+
+    /// Mutable access for a client-local copy; drops the cached wire forms
+    fn message_mut(&mut self) -> &mut ServerMessage {
+        self.wire = Default::default();
+        self.deflated = Default::default();
+        &mut self.message
+    }
+}
+
+impl Deref for OutboundMessage {
+    type Target = ServerMessage;
+
+    fn deref(&self) -> &ServerMessage {
+        &self.message
+    }
+}
+
+// A copy is modified afterwards (batch merge), so the cache is not carried over
+impl Clone for OutboundMessage {
+    fn clone(&self) -> Self {
+        Self::new(self.message.clone())
+    }
+}
+
+impl From<ServerMessage> for OutboundMessage {
+    fn from(message: ServerMessage) -> Self {
+        Self::new(message)
+    }
+}
+
+/// Why a message did not reach the client queue
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientSendError {
+    /// Client connection is gone
+    Closed,
+    /// Queue full, message dropped
+    Dropped,
+}
+
+impl std::fmt::Display for ClientSendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ClientSendError::Closed => write!(f, "client channel closed"),
+            ClientSendError::Dropped => write!(f, "client queue full, message dropped"),
+        }
+    }
+}
+
+/// Queue metrics of one client
+#[derive(Debug, Clone, Serialize)]
+pub struct ClientQueueMetrics {
+    pub capacity: usize,
+    pub queued: usize,
+    pub pending_coalesced: usize,
+    pub sent: u64,
+    /// WebSocket frames written after batching (<= sent)
+    pub frames: u64,
+    pub dropped: u64,
+    /// Device broadcasts missed because the client fell behind the device channel
+    pub lagged: u64,
+    pub coalesced: u64,
+    pub saturated_for_ms: Option<u64>,
+    pub disconnecting: bool,
+}
+
+#[derive(Debug)]
+struct QueueShared {
+    capacity: usize,
+    saturation_timeout: Duration,
+    // Latest variable update per state key, delivered once the queue drains
+    coalesced: Mutex<VecDeque<(String, Arc<OutboundMessage>)>>,
+    coalesced_ready: Notify,
+    sent: AtomicU64,
+    frames: AtomicU64,
+    dropped: AtomicU64,
+    lagged: AtomicU64,
+    coalesced_count: AtomicU64,
+    saturated_since: Mutex<Option<Instant>>,
+    disconnect: AtomicBool,
+    disconnect_notify: Notify,
+}
+
+impl QueueShared {
+    fn pop_coalesced(&self) -> Option<Arc<OutboundMessage>> {
+        self.coalesced.lock().unwrap().pop_front().map(|(_, message)| message)
+    }
+}
+
+/// Sending half, cloned into every device registration of the client
+#[derive(Debug, Clone)]
+pub struct ClientSender {
+    tx: mpsc::Sender<Arc<OutboundMessage>>,
+    shared: Arc<QueueShared>,
+}
+
+/// Receiving half, drained by the WebSocket writer task
+#[derive(Debug)]
+pub struct ClientReceiver {
+    rx: mpsc::Receiver<Arc<OutboundMessage>>,
+    shared: Arc<QueueShared>,
+}
+
+/// Create a bounded client queue. When full, variable updates are coalesced (latest value per
+/// variable), other messages dropped, and a client saturated past `saturation_timeout` disconnected
+pub fn client_queue(capacity: usize, saturation_timeout: Duration) -> (ClientSender, ClientReceiver) {
+    let (tx, rx) = mpsc::channel(capacity.max(1));
+    let shared = Arc::new(QueueShared {
+        capacity: capacity.max(1),
+        saturation_timeout,
+        coalesced: Mutex::new(VecDeque::new()),
+        coalesced_ready: Notify::new(),
+        sent: AtomicU64::new(0),
+        frames: AtomicU64::new(0),
+        dropped: AtomicU64::new(0),
+        lagged: AtomicU64::new(0),
+        coalesced_count: AtomicU64::new(0),
+        saturated_since: Mutex::new(None),
+        disconnect: AtomicBool::new(false),
+        disconnect_notify: Notify::new(),
+    });
+    (
+        ClientSender { tx, shared: shared.clone() },
+        ClientReceiver { rx, shared },
+    )
+}
+
+/// Key under which a message may be coalesced (single variable update only)
+fn coalesce_key(message: &ServerMessage) -> Option<String> {
+    match message {
+        ServerMessage::DeviceEvents { events_for_device, .. } => match events_for_device.as_slice() {
+            [event @ DeviceEvent::DeviceVariableUpdate { .. }] => event.state_key(),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+impl ClientSender {
+    /// Queue a message without waiting
+    pub fn send(&self, message: ServerMessage) -> Result<(), ClientSendError> {
+        self.send_shared(Arc::new(OutboundMessage::new(message)))
+    }
+
+    /// Queue a message shared with other clients without waiting
+    pub fn send_shared(&self, message: Arc<OutboundMessage>) -> Result<(), ClientSendError> {
+        let key = coalesce_key(&message);
+
+        // A newer value for an already coalesced variable replaces it in place,
+        // so it is not overtaken by the stale one
+        let message = match &key {
+            Some(key) => match self.replace_coalesced(key, message) {
+                None => return Ok(()),
+                Some(message) => message,
+            },
+            None => message,
+        };
+
+        match self.tx.try_send(message) {
+            Ok(()) => {
+                *self.shared.saturated_since.lock().unwrap() = None;
+                Ok(())
+            }
+            Err(TrySendError::Closed(_)) => Err(ClientSendError::Closed),
+            Err(TrySendError::Full(message)) => {
+                self.mark_saturated();
+                match key {
+                    Some(key) => {
+                        self.shared.coalesced.lock().unwrap().push_back((key, message));
+                        self.shared.coalesced_count.fetch_add(1, Ordering::Relaxed);
+                        self.shared.coalesced_ready.notify_one();
+                        Ok(())
+                    }
+                    None => {
+                        self.shared.dropped.fetch_add(1, Ordering::Relaxed);
+                        Err(ClientSendError::Dropped)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Replace a pending coalesced value; hands the message back if there is none
+    fn replace_coalesced(&self, key: &str, message: Arc<OutboundMessage>) -> Option<Arc<OutboundMessage>> {
+        let mut coalesced = self.shared.coalesced.lock().unwrap();
+        match coalesced.iter_mut().find(|(pending_key, _)| pending_key == key) {
+            Some(entry) => {
+                entry.1 = message;
+                self.shared.coalesced_count.fetch_add(1, Ordering::Relaxed);
+                None
+            }
+            None => Some(message),
+        }
+    }
+
+    /// Count device broadcasts the client missed by lagging behind the device channel
+    pub fn record_lagged(&self, missed: u64) {
+        self.shared.lagged.fetch_add(missed, Ordering::Relaxed);
+    }
+
+    fn mark_saturated(&self) {
+        let mut saturated_since = self.shared.saturated_since.lock().unwrap();
+        let since = *saturated_since.get_or_insert_with(Instant::now);
+        if since.elapsed() >= self.shared.saturation_timeout && !self.shared.disconnect.swap(true, Ordering::Relaxed) {
+            warn!("Client queue saturated for {:?}, forcing disconnect", since.elapsed());
+            self.shared.disconnect_notify.notify_one();
+        }
+    }
+
+    /// Check whether the receiving side is gone
+    pub fn is_closed(&self) -> bool {
+        self.tx.is_closed()
+    }
+
+    /// Resolves once the client stayed saturated past the threshold
+    pub async fn saturated_disconnect(&self) {
+        if !self.shared.disconnect.load(Ordering::Relaxed) {
+            self.shared.disconnect_notify.notified().await;
+        }
+    }
+
+    /// Current queue metrics
+    pub fn metrics(&self) -> ClientQueueMetrics {
+        ClientQueueMetrics {
+            capacity: self.shared.capacity,
+            queued: self.shared.capacity - self.tx.capacity(),
+            pending_coalesced: self.shared.coalesced.lock().unwrap().len(),
+            sent: self.shared.sent.load(Ordering::Relaxed),
+            frames: self.shared.frames.load(Ordering::Relaxed),
+            dropped: self.shared.dropped.load(Ordering::Relaxed),
+            lagged: self.shared.lagged.load(Ordering::Relaxed),
+            coalesced: self.shared.coalesced_count.load(Ordering::Relaxed),
+            saturated_for_ms: self.shared.saturated_since.lock().unwrap()
+                .map(|since| since.elapsed().as_millis() as u64),
+            disconnecting: self.shared.disconnect.load(Ordering::Relaxed),
+        }
+    }
+}
+
+impl ClientReceiver {
+    /// Next message: queued messages first, then coalesced variable updates
+    pub async fn recv(&mut self) -> Option<Arc<OutboundMessage>> {
+        loop {
+            match self.rx.try_recv() {
+                Ok(message) => return Some(self.delivered(message)),
+                Err(TryRecvError::Disconnected) => {
+                    return self.shared.pop_coalesced().map(|message| self.delivered(message));
+                }
+                Err(TryRecvError::Empty) => {}
+            }
+
+            if let Some(message) = self.shared.pop_coalesced() {
+                return Some(self.delivered(message));
+            }
+
+            tokio::select! {
+                message = self.rx.recv() => match message {
+                    Some(message) => return Some(self.delivered(message)),
+                    None => return self.shared.pop_coalesced().map(|message| self.delivered(message)),
+                },
+                _ = self.shared.coalesced_ready.notified() => {}
+            }
+        }
+    }
+
+    /// Next batch of frames: device events arriving within `window` after the
+    /// first one are collected and merged; other messages end the window
+    pub async fn recv_batch(&mut self, window: Duration, max_messages: usize) -> Option<Vec<Arc<OutboundMessage>>> {
+        let first = self.recv().await?;
+        let mut batch = vec![first];
+
+        if !window.is_zero() && is_batchable(&batch[0]) {
+            let deadline = tokio::time::Instant::now() + window;
+            while batch.len() < max_messages.max(1) {
+                match tokio::time::timeout_at(deadline, self.recv()).await {
+                    Ok(Some(message)) => {
+                        let batchable = is_batchable(&message);
+                        batch.push(message);
+                        if !batchable {
+                            break;
+                        }
+                    }
+                    // Window elapsed or channel closed (next call returns None)
+                    _ => break,
+                }
+            }
+        }
+
+        let frames = merge_device_events(batch);
+        self.shared.frames.fetch_add(frames.len() as u64, Ordering::Relaxed);
+        Some(frames)
+    }
+
+    fn delivered(&self, message: Arc<OutboundMessage>) -> Arc<OutboundMessage> {
+        self.shared.sent.fetch_add(1, Ordering::Relaxed);
+        message
+    }
+}
+
+/// Only device events are worth holding back for a batch
+fn is_batchable(message: &ServerMessage) -> bool {
+    matches!(message, ServerMessage::DeviceEvents { .. })
+}
+
+/// Merge consecutive device events of the same device into one message.
+/// Within a merged message only the latest value per state key is kept.
+/// Messages that are not merged stay shared with the other recipients.
+pub fn merge_device_events(messages: Vec<Arc<OutboundMessage>>) -> Vec<Arc<OutboundMessage>> {
+    let mut merged: Vec<Arc<OutboundMessage>> = Vec::with_capacity(messages.len());
+
+    for message in messages {
+        let same_device = match (merged.last().map(|last| &***last), &**message) {
+            (
+                Some(ServerMessage::DeviceEvents { device_id, .. }),
+                ServerMessage::DeviceEvents { device_id: next_device_id, .. },
+            ) => device_id == next_device_id,
+            _ => false,
+        };
+        if !same_device {
+            merged.push(message);
+            continue;
+        }
+
+        // Copy-on-write: the merged frame belongs to this client only
+        let last = merged.last_mut().expect("checked above");
+        if let (ServerMessage::DeviceEvents { events_for_device, .. }, ServerMessage::DeviceEvents { events_for_device: next_events, .. })
+            = (Arc::make_mut(last).message_mut(), &**message)
+        {
+            for event in next_events {
+                if let Some(key) = event.state_key() {
+                    events_for_device.retain(|existing| existing.state_key().as_deref() != Some(key.as_str()));
+                }
+                events_for_device.push(event.clone());
+            }
+        }
+    }
+
+    merged
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variable_update(value: &str) -> ServerMessage {
+        ServerMessage::device_events(
+            "dev-1".to_string(),
+            vec![DeviceEvent::device_variable_update("dev-1".to_string(), "speed".to_string(), value.to_string())],
+        )
+    }
+
+    fn variable_value(message: &ServerMessage) -> String {
+        match message {
+            ServerMessage::DeviceEvents { events_for_device, .. } => match &events_for_device[0] {
+                DeviceEvent::DeviceVariableUpdate { variable_value, .. } => variable_value.clone(),
+                other => panic!("unexpected event {:?}", other),
+            },
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_full_queue_drops_and_coalesces() {
+        let (tx, mut rx) = client_queue(1, Duration::from_secs(60));
+
+        tx.send(ServerMessage::pong(None)).unwrap();
+        assert_eq!(tx.send(ServerMessage::pong(None)), Err(ClientSendError::Dropped));
+
+        // Variable updates collapse to the latest value while the queue is full
+        tx.send(variable_update("1")).unwrap();
+        tx.send(variable_update("2")).unwrap();
+
+        let metrics = tx.metrics();
+        assert_eq!(metrics.dropped, 1);
+        assert_eq!(metrics.pending_coalesced, 1);
+        assert!(metrics.saturated_for_ms.is_some());
+
+        assert!(matches!(**rx.recv().await.unwrap(), ServerMessage::Pong { .. }));
+        assert_eq!(variable_value(&rx.recv().await.unwrap()), "2");
+        assert_eq!(tx.metrics().sent, 2);
+    }
+
+    #[tokio::test]
+    async fn test_newer_value_does_not_overtake_coalesced() {
+        let (tx, mut rx) = client_queue(1, Duration::from_secs(60));
+
+        tx.send(variable_update("1")).unwrap();
+        tx.send(variable_update("2")).unwrap(); // coalesced
+        assert_eq!(variable_value(&rx.recv().await.unwrap()), "1");
+
+        // Queue has room again, but the pending coalesced value is replaced instead
+        tx.send(variable_update("3")).unwrap();
+        assert_eq!(variable_value(&rx.recv().await.unwrap()), "3");
+        assert_eq!(tx.metrics().pending_coalesced, 0);
+    }
+
+    #[tokio::test]
+    async fn test_batch_merges_device_events() {
+        let (tx, mut rx) = client_queue(16, Duration::from_secs(60));
+
+        tx.send(variable_update("1")).unwrap();
+        tx.send(variable_update("2")).unwrap();
+        tx.send(ServerMessage::device_events(
+            "dev-1".to_string(),
+            vec![DeviceEvent::device_variable_update("dev-1".to_string(), "mode".to_string(), "auto".to_string())],
+        )).unwrap();
+        tx.send(ServerMessage::pong(None)).unwrap();
+        tx.send(variable_update("3")).unwrap();
+
+        // Device events collapse into one frame; the pong ends the window
+        let batch = rx.recv_batch(Duration::from_millis(50), MAX_BATCH_MESSAGES).await.unwrap();
+        assert_eq!(batch.len(), 2);
+        match &**batch[0] {
+            ServerMessage::DeviceEvents { events_for_device, .. } => {
+                assert_eq!(events_for_device.len(), 2);
+                assert_eq!(variable_value(&batch[0]), "2");
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert!(matches!(**batch[1], ServerMessage::Pong { .. }));
+
+        let batch = rx.recv_batch(Duration::ZERO, MAX_BATCH_MESSAGES).await.unwrap();
+        assert_eq!(variable_value(&batch[0]), "3");
+
+        let metrics = tx.metrics();
+        assert_eq!(metrics.sent, 5);
+        assert_eq!(metrics.frames, 3);
+    }
+
+    #[tokio::test]
+    async fn test_saturated_client_is_disconnected() {
+        let (tx, _rx) = client_queue(1, Duration::ZERO);
+
+        tx.send(ServerMessage::pong(None)).unwrap();
+        let _ = tx.send(ServerMessage::pong(None));
+        let _ = tx.send(ServerMessage::pong(None));
+
+        assert!(tx.metrics().disconnecting);
+        tokio::time::timeout(Duration::from_secs(1), tx.saturated_disconnect())
+            .await
+            .expect("disconnect signal");
+    }
+
+    #[test]
+    fn test_wire_form_is_serialized_once_per_version() {
+        let message = Arc::new(OutboundMessage::new(variable_update("7")));
+        let first = message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap();
+        let again = message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap();
+        assert_eq!(first.as_ptr(), again.as_ptr(), "cached bytes are shared");
+        assert_eq!(first, message.message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap().as_bytes());
+
+        let enveloped = message.to_wire(PROTOCOL_VERSION).unwrap();
+        assert_ne!(first, enveloped);
+        assert_eq!(enveloped.as_ptr(), message.to_wire(PROTOCOL_VERSION).unwrap().as_ptr());
+
+        // Merging edits a private copy; the shared message keeps its cached form
+        let merged = merge_device_events(vec![message.clone(), Arc::new(OutboundMessage::new(variable_update("8")))]);
+        assert_eq!(variable_value(&merged[0]), "8");
+        assert_eq!(message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap().as_ptr(), first.as_ptr());
+    }
+
+    #[test]
+    fn test_large_frames_are_deflated() {
+        let small = OutboundMessage::new(variable_update("7"));
+        assert!(matches!(small.to_frame(PROTOCOL_VERSION, true).unwrap(), WireFrame::Text(_)));
+
+        let large = OutboundMessage::new(variable_update(&"x".repeat(COMPRESSION_MIN_BYTES * 4)));
+        let wire = large.to_wire(PROTOCOL_VERSION).unwrap();
+        assert_eq!(large.to_frame(PROTOCOL_VERSION, false).unwrap(), WireFrame::Text(wire.clone()));
+        let WireFrame::Deflate(deflated) = large.to_frame(PROTOCOL_VERSION, true).unwrap() else {
+            panic!("large frame was not compressed");
+        };
+        assert!(deflated.len() < wire.len());
+        assert_eq!(miniz_oxide::inflate::decompress_to_vec_zlib(&deflated).unwrap(), wire.to_vec());
+
+        assert_eq!(negotiate_compression(&["br".to_string(), "Deflate".to_string()]), Some(COMPRESSION_DEFLATE));
+        assert_eq!(negotiate_compression(&["br".to_string()]), None);
+    }
+}
@@ -0,0 +1,520 @@
+// Outbound command queue - buffers device commands while a device is disconnected
+
+use crate::device_types::{DeviceCommand, DeviceError, DeviceResult};
+
+use serde::Serialize;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Mutex, OwnedMutexGuard, RwLock};
+use tracing::{info, warn};
+
+// ============================================================================
+// COMMAND QUEUE CONFIGURATION
+// ============================================================================
+
+/// Limits of the per-device command queue
+#[derive(Debug, Clone)]
+pub struct CommandQueueConfig {
+    /// Maximum number of pending commands per device
+    pub max_depth: usize,
+    /// Commands older than this are discarded instead of being sent
+    pub ttl: Duration,
+    /// Send attempts per command before it is dropped
+    pub max_attempts: u32,
+}
+
+impl Default for CommandQueueConfig {
+    fn default() -> Self {
+        Self {
+            max_depth: 50,
+            ttl: Duration::from_secs(300),
+            max_attempts: 3,
+        }
+    }
+}
+
+// ============================================================================
+// QUEUED COMMAND
+// ============================================================================
+
+/// Command waiting for delivery to a device
+#[derive(Debug, Clone)]
+pub struct QueuedCommand {
+    pub id: String,
+    pub command: DeviceCommand,
+    /// Correlation ID sent with the command (if a client awaits the reply)
+    pub correlation_id: Option<String>,
+    pub enqueued_at: Instant,
+    pub attempts: u32,
+}
+
+impl QueuedCommand {
+    fn new(command: DeviceCommand, correlation_id: Option<String>) -> Self {
+        Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            command,
+            correlation_id,
+            enqueued_at: Instant::now(),
+            attempts: 0,
+        }
+    }
+}
+
+/// Outcome of submitting a command to a device
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "status", rename_all = "camelCase")]
+pub enum CommandDispatch {
+    /// Command was written to the device connection
+    Sent,
+    /// Device is not reachable (or older commands are pending); command waits in the queue
+    #[serde(rename_all = "camelCase")]
+    Queued { command_id: String, queue_depth: usize },
+}
+
+/// Client command handed to a device, see `DeviceManager::dispatch_client_command`
+#[derive(Debug)]
+pub struct ClientDispatch {
+    /// Request ID reported back to the client
+    pub request_id: String,
+    /// Server-generated ID the device reply carries
+    pub correlation_id: String,
+    pub dispatch: CommandDispatch,
+    /// Device reply, if the caller asked to wait for it
+    pub reply: Option<oneshot::Receiver<serde_json::Value>>,
+}
+
+/// API view of a queued command
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct QueuedCommandInfo {
+    pub id: String,
+    pub command: DeviceCommand,
+    pub age_seconds: u64,
+    pub attempts: u32,
+}
+
+/// API view of a device queue
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommandQueueSnapshot {
+    pub device_id: String,
+    pub depth: usize,
+    pub max_depth: usize,
+    pub ttl_seconds: u64,
+    pub max_attempts: u32,
+    pub commands: Vec<QueuedCommandInfo>,
+}
+
+// ============================================================================
+// COMMAND QUEUE
+// ============================================================================
+
+/// Bounded FIFO queue of outbound commands per device.
+/// Order is preserved: a failed command stays at the head until it is
+/// delivered, expires or runs out of attempts.
+#[derive(Debug)]
+pub struct CommandQueue {
+    queues: RwLock<HashMap<String, VecDeque<QueuedCommand>>>,
+    // One lock per device, held by whoever writes commands to it
+    delivery_locks: RwLock<HashMap<String, Arc<Mutex<()>>>>,
+    config: CommandQueueConfig,
+}
+
+impl CommandQueue {
+    pub fn new(config: CommandQueueConfig) -> Self {
+        Self {
+            queues: RwLock::new(HashMap::new()),
+            delivery_locks: RwLock::new(HashMap::new()),
+            config,
+        }
+    }
+
+    /// Exclusive right to send to a device. Direct sends and queue flushes hold it,
+    /// so concurrent senders cannot reorder the device's commands.
+    pub async fn delivery_lock(&self, device_id: &str) -> OwnedMutexGuard<()> {
+        let lock = Arc::clone(self.delivery_locks.write().await.entry(device_id.to_string()).or_default());
+        lock.lock_owned().await
+    }
+
+    /// Append a command to the device queue. Returns the command ID and new depth.
+    pub async fn enqueue(
+        &self,
+        device_id: &str,
+        command: DeviceCommand,
+        correlation_id: Option<String>,
+    ) -> DeviceResult<(String, usize)> {
+        let mut queues = self.queues.write().await;
+        let queue = queues.entry(device_id.to_string()).or_default();
+
+        Self::drop_expired(device_id, queue, self.config.ttl);
+
+        if queue.len() >= self.config.max_depth {
+            warn!("Command queue for device {} is full ({} commands)", device_id, queue.len());
+            return Err(DeviceError::QueueFull(device_id.to_string()));
+        }
+
+        let queued = QueuedCommand::new(command, correlation_id);
+        let id = queued.id.clone();
+        queue.push_back(queued);
+        info!("Queued command {} for device {} (depth {})", id, device_id, queue.len());
+
+        Ok((id, queue.len()))
+    }
+
+    /// Take the oldest non-expired command of a device
+    pub async fn pop_front(&self, device_id: &str) -> Option<QueuedCommand> {
+        let mut queues = self.queues.write().await;
+        let queue = queues.get_mut(device_id)?;
+        Self::drop_expired(device_id, queue, self.config.ttl);
+        queue.pop_front()
+    }
+
+    /// Put a command whose delivery failed back at the head of the queue.
+    /// Returns false if the command exhausted its attempts and was dropped.
+    pub async fn requeue_front(&self, device_id: &str, mut queued: QueuedCommand) -> bool {
+        queued.attempts += 1;
+        if queued.attempts >= self.config.max_attempts {
+            warn!("Dropping command {} for device {} after {} failed attempts",
+                  queued.id, device_id, queued.attempts);
+            return false;
+        }
+
+        let mut queues = self.queues.write().await;
+        queues.entry(device_id.to_string()).or_default().push_front(queued);
+        true
+    }
+
+    /// Number of pending commands for a device
+    pub async fn depth(&self, device_id: &str) -> usize {
+        let queues = self.queues.read().await;
+        queues.get(device_id).map(|q| q.len()).unwrap_or(0)
+    }
+
+    /// Device IDs that currently have pending commands
+    pub async fn devices_with_pending(&self) -> Vec<String> {
+        let queues = self.queues.read().await;
+        queues
+            .iter()
+            .filter(|(_, queue)| !queue.is_empty())
+            .map(|(device_id, _)| device_id.clone())
+            .collect()
+    }
+
+    /// Remove expired commands from all queues
+    pub async fn purge_expired(&self) {
+        let mut queues = self.queues.write().await;
+        for (device_id, queue) in queues.iter_mut() {
+            Self::drop_expired(device_id, queue, self.config.ttl);
+        }
+        queues.retain(|_, queue| !queue.is_empty());
+    }
+
+    /// Drop all pending commands of a device (e.g. when the device is removed)
+    pub async fn clear(&self, device_id: &str) -> usize {
+        let mut queues = self.queues.write().await;
+        queues.remove(device_id).map(|q| q.len()).unwrap_or(0)
+    }
+
+    /// Snapshot of a device queue for the REST API
+    pub async fn snapshot(&self, device_id: &str) -> CommandQueueSnapshot {
+        let queues = self.queues.read().await;
+        let commands: Vec<QueuedCommandInfo> = queues
+            .get(device_id)
+            .map(|queue| {
+                queue
+                    .iter()
+                    .filter(|queued| queued.enqueued_at.elapsed() < self.config.ttl)
+                    .map(|queued| QueuedCommandInfo {
+                        id: queued.id.clone(),
+                        command: queued.command.clone(),
+                        age_seconds: queued.enqueued_at.elapsed().as_secs(),
+                        attempts: queued.attempts,
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        CommandQueueSnapshot {
+            device_id: device_id.to_string(),
+            depth: commands.len(),
+            max_depth: self.config.max_depth,
+            ttl_seconds: self.config.ttl.as_secs(),
+            max_attempts: self.config.max_attempts,
+            commands,
+        }
+    }
+
+    fn drop_expired(device_id: &str, queue: &mut VecDeque<QueuedCommand>, ttl: Duration) {
+        let before = queue.len();
+        queue.retain(|queued| queued.enqueued_at.elapsed() < ttl);
+        let expired = before - queue.len();
+        if expired > 0 {
+            warn!("Discarded {} expired queued command(s) for device {}", expired, device_id);
+        }
+    }
+}
+
+impl Default for CommandQueue {
+    fn default() -> Self {
+        Self::new(CommandQueueConfig::default())
+    }
+}
+
+// ============================================================================
+// REQUEST/RESPONSE CORRELATION
+// ============================================================================
+
+/// Command sent on behalf of a WebSocket client that awaits a device reply
+#[derive(Debug, Clone)]
+pub struct PendingRequest {
+    /// Server-generated ID the command carries to the device
+    pub correlation_id: String,
+    /// ID the client chose (or was given) for the request; used in replies to the client
+    pub request_id: String,
+    pub device_id: String,
+    pub user_id: String,
+    pub client_id: String,
+    pub command: DeviceCommand,
+    pub created_at: Instant,
+    /// When the command reached the device; `None` while it waits in the command queue
+    pub sent_at: Option<Instant>,
+}
+
+impl PendingRequest {
+    /// New request with a fresh correlation ID, so equal client request IDs never collide
+    pub fn new(request_id: String, device_id: &str, user_id: &str, client_id: &str, command: DeviceCommand) -> Self {
+        Self {
+            correlation_id: uuid::Uuid::new_v4().to_string(),
+            request_id,
+            device_id: device_id.to_string(),
+            user_id: user_id.to_string(),
+            client_id: client_id.to_string(),
+            command,
+            created_at: Instant::now(),
+            sent_at: None,
+        }
+    }
+}
+
+/// Registry of in-flight requests, keyed by correlation ID.
+/// Device replies (TCP, UDP or UART) carrying the same `id` resolve the request;
+/// requests without a reply within the timeout after sending are reported as failed.
+/// Requests still queued are given up after the queue TTL.
+#[derive(Debug)]
+pub struct PendingRequests {
+    requests: RwLock<HashMap<String, PendingRequest>>,
+    // Synchronous callers (REST) waiting for the device reply of a request
+    reply_waiters: RwLock<HashMap<String, oneshot::Sender<serde_json::Value>>>,
+    timeout: Duration,
+    queue_ttl: Duration,
+}
+
+impl PendingRequests {
+    pub fn new(timeout: Duration, queue_ttl: Duration) -> Self {
+        Self {
+            requests: RwLock::new(HashMap::new()),
+            reply_waiters: RwLock::new(HashMap::new()),
+            timeout,
+            queue_ttl,
+        }
+    }
+
+    /// Wait for the device reply of a request; register before the command is sent.
+    /// The receiver fails if the request is cancelled or times out.
+    pub async fn wait_for_reply(&self, correlation_id: &str) -> oneshot::Receiver<serde_json::Value> {
+        let (tx, rx) = oneshot::channel();
+        self.reply_waiters.write().await.insert(correlation_id.to_string(), tx);
+        rx
+    }
+
+    /// Hand a device reply to a waiting caller; false if nobody waits
+    pub async fn deliver_reply(&self, correlation_id: &str, reply: serde_json::Value) -> bool {
+        match self.reply_waiters.write().await.remove(correlation_id) {
+            Some(waiter) => waiter.send(reply).is_ok(),
+            None => false,
+        }
+    }
+
+    /// Register a request before its command is sent
+    pub async fn register(&self, request: PendingRequest) {
+        let mut requests = self.requests.write().await;
+        requests.insert(request.correlation_id.clone(), request);
+    }
+
+    /// Start the reply timeout of a request once its command reached the device
+    pub async fn mark_sent(&self, correlation_id: &str) {
+        if let Some(request) = self.requests.write().await.get_mut(correlation_id) {
+            request.sent_at.get_or_insert_with(Instant::now);
+        }
+    }
+
+    /// Match a device reply to its request. The device must be the one the request was sent to.
+    pub async fn resolve(&self, device_id: &str, correlation_id: &str) -> Option<PendingRequest> {
+        let mut requests = self.requests.write().await;
+        match requests.get(correlation_id) {
+            Some(request) if request.device_id == device_id => requests.remove(correlation_id),
+            _ => None,
+        }
+    }
+
+    /// Remove a request without a reply (e.g. sending failed)
+    pub async fn cancel(&self, correlation_id: &str) -> Option<PendingRequest> {
+        self.reply_waiters.write().await.remove(correlation_id);
+        self.requests.write().await.remove(correlation_id)
+    }
+
+    /// Remove and return all requests that exceeded the timeout since sending,
+    /// or were not sent within the queue TTL
+    pub async fn take_expired(&self) -> Vec<PendingRequest> {
+        let mut requests = self.requests.write().await;
+        let expired_ids: Vec<String> = requests
+            .values()
+            .filter(|request| match request.sent_at {
+                Some(sent_at) => sent_at.elapsed() >= self.timeout,
+                None => request.created_at.elapsed() >= self.queue_ttl,
+            })
+            .map(|request| request.correlation_id.clone())
+            .collect();
+
+        let mut reply_waiters = self.reply_waiters.write().await;
+        reply_waiters.retain(|id, waiter| !waiter.is_closed() && !expired_ids.contains(id));
+
+        expired_ids
+            .iter()
+            .filter_map(|id| requests.remove(id))
+            .collect()
+    }
+
+    pub fn timeout(&self) -> Duration {
+        self.timeout
+    }
+}
+
+impl Default for PendingRequests {
+    fn default() -> Self {
+        Self::new(Duration::from_secs(15), CommandQueueConfig::default().ttl)
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_enqueue_preserves_order() {
+        let queue = CommandQueue::default();
+        queue.enqueue("dev-1", DeviceCommand::start_option("a".to_string()), None).await.unwrap();
+        queue.enqueue("dev-1", DeviceCommand::start_option("b".to_string()), None).await.unwrap();
+
+        let first = queue.pop_front("dev-1").await.unwrap();
+        assert!(matches!(first.command, DeviceCommand::StartOption { ref start_option } if start_option == "a"));
+        assert_eq!(queue.depth("dev-1").await, 1);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_rejects_when_full() {
+        let queue = CommandQueue::new(CommandQueueConfig { max_depth: 1, ..Default::default() });
+        queue.enqueue("dev-1", DeviceCommand::get_status(), None).await.unwrap();
+
+        let result = queue.enqueue("dev-1", DeviceCommand::get_status(), None).await;
+        assert!(matches!(result, Err(DeviceError::QueueFull(_))));
+    }
+
+    #[tokio::test]
+    async fn test_requeue_drops_after_max_attempts() {
+        let queue = CommandQueue::new(CommandQueueConfig { max_attempts: 2, ..Default::default() });
+        queue.enqueue("dev-1", DeviceCommand::get_status(), None).await.unwrap();
+
+        let cmd = queue.pop_front("dev-1").await.unwrap();
+        assert!(queue.requeue_front("dev-1", cmd).await);
+        let cmd = queue.pop_front("dev-1").await.unwrap();
+        assert_eq!(cmd.attempts, 1);
+        assert!(!queue.requeue_front("dev-1", cmd).await);
+        assert_eq!(queue.depth("dev-1").await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_expired_commands_are_discarded() {
+        let queue = CommandQueue::new(CommandQueueConfig { ttl: Duration::from_millis(0), ..Default::default() });
+        queue.enqueue("dev-1", DeviceCommand::get_status(), None).await.unwrap();
+
+        assert!(queue.pop_front("dev-1").await.is_none());
+    }
+
+    fn pending_request(request_id: &str, device_id: &str, client_id: &str) -> PendingRequest {
+        PendingRequest::new(request_id.to_string(), device_id, "user", client_id, DeviceCommand::get_status())
+    }
+
+    #[tokio::test]
+    async fn test_pending_request_resolves_only_for_same_device() {
+        let pending = PendingRequests::default();
+        let request = pending_request("req-1", "dev-1", "client");
+        let correlation_id = request.correlation_id.clone();
+        pending.register(request).await;
+
+        assert!(pending.resolve("dev-2", &correlation_id).await.is_none());
+        assert!(pending.resolve("dev-1", &correlation_id).await.is_some());
+        assert!(pending.resolve("dev-1", &correlation_id).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_equal_client_request_ids_do_not_collide() {
+        let pending = PendingRequests::default();
+        let first = pending_request("req-1", "dev-1", "client-a");
+        let second = pending_request("req-1", "dev-1", "client-b");
+        assert_ne!(first.correlation_id, second.correlation_id);
+
+        let (first_id, second_id) = (first.correlation_id.clone(), second.correlation_id.clone());
+        pending.register(first).await;
+        pending.register(second).await;
+
+        // The client request ID is not a key
+        assert!(pending.resolve("dev-1", "req-1").await.is_none());
+
+        let resolved = pending.resolve("dev-1", &second_id).await.unwrap();
+        assert_eq!((resolved.client_id.as_str(), resolved.request_id.as_str()), ("client-b", "req-1"));
+        let resolved = pending.resolve("dev-1", &first_id).await.unwrap();
+        assert_eq!((resolved.client_id.as_str(), resolved.request_id.as_str()), ("client-a", "req-1"));
+    }
+
+    #[tokio::test]
+    async fn test_timeout_starts_when_command_is_sent() {
+        let pending = PendingRequests::new(Duration::from_millis(0), Duration::from_secs(300));
+        let request = pending_request("req-1", "dev-1", "client");
+        let correlation_id = request.correlation_id.clone();
+        pending.register(request).await;
+
+        // Still queued: the reply timeout does not run yet
+        assert!(pending.take_expired().await.is_empty());
+
+        pending.mark_sent(&correlation_id).await;
+        let expired = pending.take_expired().await;
+        assert_eq!(expired.len(), 1);
+        assert_eq!(expired[0].correlation_id, correlation_id);
+
+        // Requests that never leave the queue expire with the queue TTL
+        let pending = PendingRequests::new(Duration::from_secs(15), Duration::from_millis(0));
+        pending.register(pending_request("req-2", "dev-1", "client")).await;
+        assert_eq!(pending.take_expired().await.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_reply_waiter_receives_device_reply() {
+        let pending = PendingRequests::default();
+
+        let reply = pending.wait_for_reply("req-1").await;
+        assert!(pending.deliver_reply("req-1", serde_json::json!({"id": "req-1", "ok": true})).await);
+        assert_eq!(reply.await.unwrap()["ok"], true);
+
+        // Cancelled requests close the waiter
+        let reply = pending.wait_for_reply("req-2").await;
+        pending.cancel("req-2").await;
+        assert!(reply.await.is_err());
+        assert!(!pending.deliver_reply("req-2", serde_json::Value::Null).await);
+    }
+}
@@ -0,0 +1,243 @@
+// Command rate limiting - token buckets per device and per user ([command_rate_limit])
+//
+// A frontend stuck in a loop can send setVariable hundreds of times per second and wear out
+// relays or flood a device's serial console. Every client command (WebSocket DEVICE commands and
+// POST /api/devices/:id/command) takes one token from the bucket of the target device and one from
+// the bucket of the sending user. A bucket holds up to `burst` tokens and refills at `per_second`;
+// without a token in both buckets the command is rejected with limitExceeded (HTTP 429) and the
+// time until the next token. All guests share the "guest" user bucket. Automation (schedules,
+// scripts, routing rules, bulk commands) is not limited here.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+/// Buckets kept before full (idle) ones are dropped
+const PRUNE_THRESHOLD: usize = 4096;
+
+/// Rate and burst of one scope; a rate of 0 disables the scope
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BucketLimit {
+    pub per_second: u32,
+    pub burst: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimits {
+    pub device: BucketLimit,
+    pub user: BucketLimit,
+}
+
+impl Default for RateLimits {
+    fn default() -> Self {
+        Self {
+            device: BucketLimit { per_second: 20, burst: 40 },
+            user: BucketLimit { per_second: 50, burst: 100 },
+        }
+    }
+}
+
+/// Which bucket ran empty
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Scope {
+    Device,
+    User,
+}
+
+/// Rejected command
+#[derive(Debug, Clone, PartialEq)]
+pub struct RateLimited {
+    pub scope: Scope,
+    pub retry_after: Duration,
+}
+
+impl std::fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let scope = match self.scope {
+            Scope::Device => "device",
+            Scope::User => "user",
+        };
+        write!(f, "{} command rate limit exceeded, retry in {} ms", scope, self.retry_after.as_millis().max(1))
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn full(limit: BucketLimit, now: Instant) -> Self {
+        Self { tokens: limit.burst as f64, updated: now }
+    }
+
+    fn refill(&mut self, limit: BucketLimit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second as f64).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    /// Time until one token is available (zero if there is one)
+    fn wait(&self, limit: BucketLimit) -> Duration {
+        if self.tokens >= 1.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64((1.0 - self.tokens) / limit.per_second as f64)
+        }
+    }
+}
+
+/// Counters for /api/websocket/stats
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RateLimitStats {
+    pub limits: RateLimits,
+    pub allowed: u64,
+    pub limited_by_device: u64,
+    pub limited_by_user: u64,
+    /// Rejected commands per device
+    pub limited_devices: HashMap<String, u64>,
+}
+
+#[derive(Debug)]
+pub struct CommandRateLimiter {
+    limits: Mutex<RateLimits>,
+    buckets: Mutex<HashMap<(Scope, String), Bucket>>,
+    limited_devices: Mutex<HashMap<String, u64>>,
+    allowed: AtomicU64,
+    limited_by_device: AtomicU64,
+    limited_by_user: AtomicU64,
+}
+
+impl Default for CommandRateLimiter {
+    fn default() -> Self {
+        Self::new(RateLimits::default())
+    }
+}
+
+impl CommandRateLimiter {
+    pub fn new(limits: RateLimits) -> Self {
+        Self {
+            limits: Mutex::new(limits),
+            buckets: Mutex::new(HashMap::new()),
+            limited_devices: Mutex::new(HashMap::new()),
+            allowed: AtomicU64::new(0),
+            limited_by_device: AtomicU64::new(0),
+            limited_by_user: AtomicU64::new(0),
+        }
+    }
+
+    /// Replace the limits; existing buckets start over full
+    pub fn configure(&self, limits: RateLimits) {
+        *self.limits.lock().unwrap() = limits;
+        self.buckets.lock().unwrap().clear();
+    }
+
+    /// Take a token for a command of `user_id` to `device_id`
+    pub fn check(&self, device_id: &str, user_id: &str) -> Result<(), RateLimited> {
+        self.check_at(device_id, user_id, Instant::now())
+    }
+
+    fn check_at(&self, device_id: &str, user_id: &str, now: Instant) -> Result<(), RateLimited> {
+        let limits = *self.limits.lock().unwrap();
+        let scopes = [(Scope::Device, device_id, limits.device), (Scope::User, user_id, limits.user)];
+
+        let mut buckets = self.buckets.lock().unwrap();
+        if buckets.len() > PRUNE_THRESHOLD {
+            buckets.retain(|(scope, _), bucket| {
+                let limit = if *scope == Scope::Device { limits.device } else { limits.user };
+                bucket.refill(limit, now);
+                bucket.tokens < limit.burst as f64
+            });
+        }
+
+        // Both buckets need a token before either is charged
+        for (scope, key, limit) in scopes {
+            if limit.per_second == 0 {
+                continue;
+            }
+            let bucket = buckets.entry((scope, key.to_string())).or_insert_with(|| Bucket::full(limit, now));
+            bucket.refill(limit, now);
+            let retry_after = bucket.wait(limit);
+            if !retry_after.is_zero() {
+                drop(buckets);
+                self.record_limited(scope, device_id);
+                return Err(RateLimited { scope, retry_after });
+            }
+        }
+        for (scope, key, limit) in scopes {
+            if limit.per_second > 0 {
+                if let Some(bucket) = buckets.get_mut(&(scope, key.to_string())) {
+                    bucket.tokens -= 1.0;
+                }
+            }
+        }
+        self.allowed.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn record_limited(&self, scope: Scope, device_id: &str) {
+        let counter = match scope {
+            Scope::Device => &self.limited_by_device,
+            Scope::User => &self.limited_by_user,
+        };
+        counter.fetch_add(1, Ordering::Relaxed);
+        *self.limited_devices.lock().unwrap().entry(device_id.to_string()).or_default() += 1;
+    }
+
+    pub fn stats(&self) -> RateLimitStats {
+        RateLimitStats {
+            limits: *self.limits.lock().unwrap(),
+            allowed: self.allowed.load(Ordering::Relaxed),
+            limited_by_device: self.limited_by_device.load(Ordering::Relaxed),
+            limited_by_user: self.limited_by_user.load(Ordering::Relaxed),
+            limited_devices: self.limited_devices.lock().unwrap().clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_device_and_user_buckets() {
+        let limiter = CommandRateLimiter::new(RateLimits {
+            device: BucketLimit { per_second: 10, burst: 3 },
+            user: BucketLimit { per_second: 1, burst: 5 },
+        });
+        let start = Instant::now();
+
+        for _ in 0..3 {
+            limiter.check_at("lamp", "alice", start).unwrap();
+        }
+        let limited = limiter.check_at("lamp", "alice", start).unwrap_err();
+        assert_eq!(limited.scope, Scope::Device);
+        assert_eq!(limited.retry_after, Duration::from_millis(100));
+
+        // Another device still has tokens, the user bucket runs out after five commands
+        limiter.check_at("fan", "alice", start).unwrap();
+        limiter.check_at("fan", "alice", start).unwrap();
+        assert_eq!(limiter.check_at("fan", "alice", start).unwrap_err().scope, Scope::User);
+        limiter.check_at("fan", "bob", start).unwrap();
+
+        // A rejected command charges neither bucket
+        let later = start + Duration::from_millis(1000);
+        limiter.check_at("lamp", "alice", later).unwrap();
+
+        let stats = limiter.stats();
+        assert_eq!((stats.allowed, stats.limited_by_device, stats.limited_by_user), (7, 1, 1));
+        assert_eq!(stats.limited_devices.get("fan"), Some(&1));
+
+        limiter.configure(RateLimits { device: BucketLimit { per_second: 0, burst: 0 }, user: BucketLimit { per_second: 0, burst: 0 } });
+        for _ in 0..100 {
+            limiter.check_at("lamp", "alice", later).unwrap();
+        }
+    }
+}
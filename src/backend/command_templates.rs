@@ -0,0 +1,145 @@
+// Command templates - named command sequences with parameters (POST /api/command-templates)
+//
+// A template is a list of steps, each the same JSON as POST /api/devices/:id/command, e.g.
+// "calibrate" = [{"setVariable": {"name": "gain", "value": "{{gain}}"}}, {"startOption": "calib"}].
+// Placeholders are filled from the run request's params or the template's defaults: a string that
+// is exactly one placeholder takes the parameter's JSON value (so numbers stay numbers), inside
+// longer strings the value is inserted as text. Steps run in order; by default the first failed
+// step stops the run and the remaining steps are reported as skipped.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// Most steps a template may have
+pub const MAX_STEPS: usize = 50;
+/// Longest pause a step may request before it is sent
+pub const MAX_STEP_DELAY_MS: u64 = 60_000;
+
+/// One command of a template
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TemplateStep {
+    /// Device command with optional {{param}} placeholders
+    pub command: Value,
+    /// Pause before this step is sent
+    #[serde(default)]
+    pub delay_ms: u64,
+}
+
+/// Outcome of one step of a template run
+#[derive(Debug, Clone, Serialize)]
+pub struct StepResult {
+    pub step: usize,
+    /// ok, failed or skipped
+    pub status: &'static str,
+    /// Command as sent (placeholders filled)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub command: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub response: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Names of all {{placeholders}} used in the steps
+pub fn placeholders(steps: &[TemplateStep]) -> Vec<String> {
+    fn collect(value: &Value, names: &mut Vec<String>) {
+        match value {
+            Value::String(text) => {
+                let mut rest = text.as_str();
+                while let Some(start) = rest.find("{{") {
+                    let Some(end) = rest[start..].find("}}") else { break };
+                    let name = rest[start + 2..start + end].trim().to_string();
+                    if !names.contains(&name) {
+                        names.push(name);
+                    }
+                    rest = &rest[start + end + 2..];
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect(item, names)),
+            Value::Object(map) => map.values().for_each(|item| collect(item, names)),
+            _ => {}
+        }
+    }
+    let mut names = Vec::new();
+    steps.iter().for_each(|step| collect(&step.command, &mut names));
+    names
+}
+
+/// Check the steps and that every placeholder has a name
+pub fn validate_steps(steps: &[TemplateStep]) -> Result<(), String> {
+    if steps.is_empty() || steps.len() > MAX_STEPS {
+        return Err(format!("A template needs between 1 and {} steps", MAX_STEPS));
+    }
+    for (index, step) in steps.iter().enumerate() {
+        if step.command.as_object().is_none_or(|command| command.is_empty()) {
+            return Err(format!("Step {}: command must be a non-empty JSON object", index + 1));
+        }
+        if step.delay_ms > MAX_STEP_DELAY_MS {
+            return Err(format!("Step {}: delay_ms must be at most {}", index + 1, MAX_STEP_DELAY_MS));
+        }
+    }
+    if placeholders(steps).iter().any(|name| name.is_empty()) {
+        return Err("Placeholders need a name, e.g. {{gain}}".to_string());
+    }
+    Ok(())
+}
+
+/// Fill the placeholders of a command; unknown parameter names are an error
+pub fn render(command: &Value, params: &BTreeMap<String, Value>) -> Result<Value, String> {
+    match command {
+        Value::String(text) => {
+            let trimmed = text.trim();
+            if let Some(name) = trimmed.strip_prefix("{{").and_then(|t| t.strip_suffix("}}")).filter(|name| !name.contains("{{")) {
+                return params.get(name.trim()).cloned().ok_or_else(|| format!("Missing parameter: {}", name.trim()));
+            }
+            let mut rendered = String::new();
+            let mut rest = text.as_str();
+            while let Some(start) = rest.find("{{") {
+                let Some(end) = rest[start..].find("}}") else { break };
+                let name = rest[start + 2..start + end].trim();
+                let value = params.get(name).ok_or_else(|| format!("Missing parameter: {}", name))?;
+                rendered.push_str(&rest[..start]);
+                match value {
+                    Value::String(s) => rendered.push_str(s),
+                    other => rendered.push_str(&other.to_string()),
+                }
+                rest = &rest[start + end + 2..];
+            }
+            rendered.push_str(rest);
+            Ok(Value::String(rendered))
+        }
+        Value::Array(items) => items.iter().map(|item| render(item, params)).collect::<Result<_, _>>().map(Value::Array),
+        Value::Object(map) => map.iter()
+            .map(|(key, item)| Ok((key.clone(), render(item, params)?)))
+            .collect::<Result<_, String>>()
+            .map(Value::Object),
+        other => Ok(other.clone()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_placeholders_keep_parameter_types() {
+        let steps: Vec<TemplateStep> = serde_json::from_value(json!([
+            {"command": {"setVariable": {"name": "gain", "value": "{{gain}}"}}},
+            {"command": {"startOption": "calib-{{ mode }}"}, "delay_ms": 500},
+        ])).unwrap();
+        assert!(validate_steps(&steps).is_ok());
+        assert_eq!(placeholders(&steps), vec!["gain", "mode"]);
+
+        let params: BTreeMap<String, Value> = [("gain".to_string(), json!(5)), ("mode".to_string(), json!("fast"))].into();
+        assert_eq!(render(&steps[0].command, &params).unwrap(), json!({"setVariable": {"name": "gain", "value": 5}}));
+        assert_eq!(render(&steps[1].command, &params).unwrap(), json!({"startOption": "calib-fast"}));
+        assert_eq!(render(&steps[0].command, &BTreeMap::new()).unwrap_err(), "Missing parameter: gain");
+
+        assert!(validate_steps(&[]).is_err());
+        let bad: Vec<TemplateStep> = serde_json::from_value(json!([{"command": {"reset": true}, "delay_ms": 120_000}])).unwrap();
+        assert!(validate_steps(&bad).is_err());
+    }
+}
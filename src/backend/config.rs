@@ -0,0 +1,1048 @@
+// ============================================================================
+// CONFIG.RS - Runtime configuration (config.toml + environment overrides)
+// ============================================================================
+//
+// Load order: built-in defaults -> config file -> environment variables.
+// The file is `config.toml` in the working directory (or CONFIG_FILE); a
+// missing file is fine, an invalid one aborts the startup.
+//
+// Supported TOML subset: [sections], `key = value` with strings, integers,
+// booleans and one-line arrays, `#` comments. See config.example.toml.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+/// Default config file in the working directory
+pub const DEFAULT_CONFIG_FILE: &str = "config.toml";
+
+// ============================================================================
+// CONFIG TYPES
+// ============================================================================
+
+/// HTTP server settings ([server])
+#[derive(Debug, Clone, PartialEq)]
+pub struct ServerConfig {
+    pub bind_address: String,
+    pub http_port: u16,
+    /// Allowed CORS origins; empty = no CORS headers (same-origin frontend), "*" = any origin
+    pub cors_origins: Vec<String>,
+    /// Path prefix the whole app is mounted under behind a reverse proxy, e.g. "/esp32" ("" = root)
+    pub base_path: String,
+    /// Proxies whose X-Forwarded-For/-Proto headers are trusted (IP addresses or "*")
+    pub trusted_proxies: Vec<String>,
+    /// Directory relative paths (data/, client/, docs/) resolve against ("" = current directory)
+    pub working_directory: String,
+    /// PID file written at startup and removed on shutdown ("" = none)
+    pub pid_file: String,
+}
+
+/// Built-in HTTPS ([tls]) for installs without a reverse proxy
+#[derive(Debug, Clone, PartialEq)]
+pub struct TlsConfig {
+    pub enabled: bool,
+    pub https_port: u16,
+    /// PEM certificate chain and private key
+    pub cert_path: String,
+    pub key_path: String,
+    /// Redirect server.http_port to HTTPS (otherwise only HTTPS is served)
+    pub redirect_http: bool,
+}
+
+/// Database settings ([database])
+#[derive(Debug, Clone, PartialEq)]
+pub struct DatabaseConfig {
+    /// SQLite file, created if missing
+    pub path: String,
+}
+
+/// Discovery toggles ([discovery])
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiscoveryConfig {
+    /// Browse the network for devices (mDNS/UDP discovery service)
+    pub enabled: bool,
+    /// Advertise device-manager.local via mDNS
+    pub mdns_advertise: bool,
+    /// Device ports the on-demand UDP discovery probe is broadcast to
+    pub probe_ports: Vec<u16>,
+}
+
+/// Device connection defaults ([devices])
+#[derive(Debug, Clone, PartialEq)]
+pub struct DeviceDefaultsConfig {
+    /// Inactivity timeout of TCP devices
+    pub tcp_timeout_seconds: u64,
+    /// Inactivity timeout of UDP and UART devices
+    pub udp_timeout_seconds: u64,
+    /// TCP keep-alive of device connections: idle time before the first probe, time between probes
+    pub keepalive_idle_seconds: u64,
+    pub keepalive_interval_seconds: u64,
+    /// Limit of a TCP connect (and TLS handshake) to a device
+    pub connect_timeout_seconds: u64,
+    /// Directory holding the PEM files of per-device TLS (the API only accepts file names inside it)
+    pub tls_certs_dir: String,
+    /// UDP listener ports used while none are stored in the database
+    pub udp_listen_ports: Vec<u16>,
+    /// TCP connects running at the same time (other devices wait for a slot)
+    pub max_concurrent_connects: usize,
+    /// Workers parsing received UDP datagrams off the receive loop
+    pub udp_workers: usize,
+    /// Minimum interval between updates of one device variable reaching the store and clients (0 = off)
+    pub variable_min_interval_ms: u64,
+    /// Publish coalesced samples on the server-side event feed anyway (alerts, scripts, webhooks)
+    pub record_raw_variables: bool,
+    /// Largest accepted device message; longer frames are dropped
+    pub max_message_bytes: usize,
+    /// Deepest accepted JSON nesting of a device message
+    pub max_json_depth: usize,
+}
+
+/// Logging settings ([logging])
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggingConfig {
+    /// tracing filter directive, e.g. "info" or "info,drawing_app_backend=debug" (RUST_LOG wins)
+    pub level: String,
+    /// "text", "json" (one object per line, for Loki/ELK) or "journal" (systemd)
+    pub format: String,
+}
+
+/// Email notifications via SMTP ([email])
+#[derive(Clone, PartialEq)]
+pub struct EmailConfig {
+    pub enabled: bool,
+    pub smtp_host: String,
+    pub smtp_port: u16,
+    /// "starttls", "tls" (implicit TLS, usually port 465) or "none"
+    pub smtp_security: String,
+    /// Empty = no SMTP authentication
+    pub smtp_username: String,
+    pub smtp_password: String,
+    /// Sender, e.g. "ESP32 Manager <esp32@example.org>"
+    pub from_address: String,
+    /// External URL of the app used in links, e.g. "https://esp32.example.org"
+    pub public_url: String,
+}
+
+// The configuration is logged at startup: keep the SMTP password out of it
+impl std::fmt::Debug for EmailConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("EmailConfig")
+            .field("enabled", &self.enabled)
+            .field("smtp_host", &self.smtp_host)
+            .field("smtp_port", &self.smtp_port)
+            .field("smtp_security", &self.smtp_security)
+            .field("smtp_username", &self.smtp_username)
+            .field("smtp_password", &if self.smtp_password.is_empty() { "" } else { "***" })
+            .field("from_address", &self.from_address)
+            .field("public_url", &self.public_url)
+            .finish()
+    }
+}
+
+/// Time service for devices ([time_service])
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeServiceConfig {
+    pub enabled: bool,
+    /// UDP port answering SNTP and `{"timeRequest"}` requests
+    pub udp_port: u16,
+    /// Push the server time to connected devices with setTime support this often (0 = never)
+    pub broadcast_interval_seconds: u64,
+}
+
+/// Token buckets for client device commands ([command_rate_limit]); a rate of 0 disables the bucket
+#[derive(Debug, Clone, PartialEq)]
+pub struct CommandRateLimitConfig {
+    /// Commands per second to one device, and how many may arrive at once
+    pub device_per_second: u32,
+    pub device_burst: u32,
+    /// Commands per second of one user over all devices
+    pub user_per_second: u32,
+    pub user_burst: u32,
+}
+
+impl DeviceDefaultsConfig {
+    pub fn inbound_limits(&self) -> crate::inbound_validation::InboundLimits {
+        crate::inbound_validation::InboundLimits {
+            max_message_bytes: self.max_message_bytes,
+            max_json_depth: self.max_json_depth,
+        }
+    }
+}
+
+impl CommandRateLimitConfig {
+    pub fn limits(&self) -> crate::command_rate_limit::RateLimits {
+        use crate::command_rate_limit::{BucketLimit, RateLimits};
+        RateLimits {
+            device: BucketLimit { per_second: self.device_per_second, burst: self.device_burst },
+            user: BucketLimit { per_second: self.user_per_second, burst: self.user_burst },
+        }
+    }
+}
+
+/// Calls leaving the local network ([outbound], see outbound.rs)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OutboundConfig {
+    /// Switch off webhooks, email and firmware downloads (isolated networks)
+    pub offline: bool,
+    /// Proxy URL for HTTP calls ("" = HTTP_PROXY/HTTPS_PROXY from the environment)
+    pub proxy: String,
+    /// Webhook URLs may point to loopback, link-local and private network addresses
+    pub allow_private_webhooks: bool,
+}
+
+/// Encryption of secrets stored in the database ([secrets], see secrets.rs)
+#[derive(Clone, PartialEq)]
+pub struct SecretsConfig {
+    /// Hex keys, one per line, the first one active; created on first start if missing
+    pub key_file: String,
+    /// Active key (64 hex characters) instead of the first line of the key file
+    pub key: String,
+}
+
+impl Default for SecretsConfig {
+    fn default() -> Self {
+        Self { key_file: "data/secrets.key".to_string(), key: String::new() }
+    }
+}
+
+// Keep the key out of the logged configuration as well
+impl std::fmt::Debug for SecretsConfig {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SecretsConfig")
+            .field("key_file", &self.key_file)
+            .field("key", &if self.key.is_empty() { "" } else { "***" })
+            .finish()
+    }
+}
+
+/// Decoder plugins for devices forwarding raw bus frames ([bus_decoders])
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct BusDecoderConfig {
+    /// "<device ID>=<decoder>:<register map file>" entries
+    pub devices: Vec<String>,
+}
+
+/// Per-device message parser chains ([message_parsers], see message_parsers.rs)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct MessageParserConfig {
+    /// "<device ID>=<parser>,..." entries
+    pub devices: Vec<String>,
+}
+
+/// Firmware inventory ([firmware], see fleet.rs)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct FirmwareConfig {
+    /// Devices reporting an older version are listed as outdated ("" = no minimum)
+    pub minimum_version: String,
+}
+
+/// Automatic quarantine of misbehaving devices ([quarantine], see quarantine.rs)
+#[derive(Debug, Clone, PartialEq)]
+pub struct QuarantineConfig {
+    pub enabled: bool,
+    /// Messages of one device within a minute that trigger the quarantine
+    pub max_messages_per_minute: u32,
+    /// Rejected messages (size, UTF-8, JSON depth) and undecodable bus frames within a minute
+    pub max_errors_per_minute: u32,
+    /// One message of a quarantined device still passes this often, for diagnostics (0 = none)
+    pub trickle_seconds: u64,
+}
+
+impl QuarantineConfig {
+    pub fn settings(&self) -> crate::quarantine::QuarantineSettings {
+        crate::quarantine::QuarantineSettings {
+            enabled: self.enabled,
+            max_messages_per_minute: self.max_messages_per_minute,
+            max_errors_per_minute: self.max_errors_per_minute,
+            trickle_interval: std::time::Duration::from_secs(self.trickle_seconds),
+        }
+    }
+}
+
+/// WebSocket clients of /channel ([websocket], see keepalive.rs and client_queue.rs)
+#[derive(Debug, Clone, PartialEq)]
+pub struct WebSocketConfig {
+    /// Device events within this window go out as one frame per device (0 = off)
+    pub batch_window_ms: u64,
+    /// Time between two server pings
+    pub ping_interval_seconds: u64,
+    /// Unanswered pings before a client counts as dead
+    pub max_missed_pongs: u32,
+    /// Send large frames deflate-compressed to clients that ask for it in `hello`
+    pub compression: bool,
+}
+
+impl WebSocketConfig {
+    pub fn keepalive(&self) -> crate::keepalive::KeepaliveConfig {
+        crate::keepalive::KeepaliveConfig {
+            ping_interval: std::time::Duration::from_secs(self.ping_interval_seconds),
+            max_missed_pongs: self.max_missed_pongs,
+        }
+    }
+
+    pub fn batch_window(&self) -> std::time::Duration {
+        std::time::Duration::from_millis(self.batch_window_ms)
+    }
+}
+
+/// Simulated devices started with the server ([simulators], see device_simulator.rs)
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SimulatorConfig {
+    pub count: usize,
+}
+
+/// Complete runtime configuration
+#[derive(Debug, Clone, PartialEq)]
+pub struct AppConfig {
+    pub server: ServerConfig,
+    pub tls: TlsConfig,
+    pub database: DatabaseConfig,
+    pub discovery: DiscoveryConfig,
+    pub devices: DeviceDefaultsConfig,
+    pub logging: LoggingConfig,
+    pub email: EmailConfig,
+    pub time_service: TimeServiceConfig,
+    pub command_rate_limit: CommandRateLimitConfig,
+    pub outbound: OutboundConfig,
+    pub secrets: SecretsConfig,
+    pub bus_decoders: BusDecoderConfig,
+    pub message_parsers: MessageParserConfig,
+    pub firmware: FirmwareConfig,
+    pub quarantine: QuarantineConfig,
+    pub websocket: WebSocketConfig,
+    pub simulators: SimulatorConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            server: ServerConfig {
+                bind_address: "0.0.0.0".to_string(),
+                http_port: 3000,
+                cors_origins: Vec::new(),
+                base_path: String::new(),
+                trusted_proxies: Vec::new(),
+                working_directory: String::new(),
+                pid_file: String::new(),
+            },
+            tls: TlsConfig {
+                enabled: false,
+                https_port: 3443,
+                cert_path: String::new(),
+                key_path: String::new(),
+                redirect_http: true,
+            },
+            database: DatabaseConfig {
+                path: "data/users.db".to_string(),
+            },
+            discovery: DiscoveryConfig {
+                enabled: true,
+                mdns_advertise: true,
+                probe_ports: crate::device_types::DEFAULT_DISCOVERY_PROBE_PORTS.to_vec(),
+            },
+            devices: DeviceDefaultsConfig {
+                tcp_timeout_seconds: 10,
+                udp_timeout_seconds: 30,
+                keepalive_idle_seconds: 600,
+                keepalive_interval_seconds: 60,
+                connect_timeout_seconds: 5,
+                tls_certs_dir: crate::device_types::DEFAULT_TLS_CERTS_DIR.to_string(),
+                udp_listen_ports: vec![3232],
+                max_concurrent_connects: 8,
+                udp_workers: 4,
+                variable_min_interval_ms: 100,
+                record_raw_variables: false,
+                max_message_bytes: crate::inbound_validation::DEFAULT_MAX_MESSAGE_BYTES,
+                max_json_depth: crate::inbound_validation::DEFAULT_MAX_JSON_DEPTH,
+            },
+            logging: LoggingConfig {
+                level: "info".to_string(),
+                format: "text".to_string(),
+            },
+            email: EmailConfig {
+                enabled: false,
+                smtp_host: String::new(),
+                smtp_port: 587,
+                smtp_security: "starttls".to_string(),
+                smtp_username: String::new(),
+                smtp_password: String::new(),
+                from_address: String::new(),
+                public_url: "http://localhost:3000".to_string(),
+            },
+            time_service: TimeServiceConfig {
+                enabled: false,
+                udp_port: 3123,
+                broadcast_interval_seconds: 3600,
+            },
+            command_rate_limit: CommandRateLimitConfig {
+                device_per_second: 20,
+                device_burst: 40,
+                user_per_second: 50,
+                user_burst: 100,
+            },
+            outbound: OutboundConfig::default(),
+            secrets: SecretsConfig::default(),
+            bus_decoders: BusDecoderConfig::default(),
+            message_parsers: MessageParserConfig::default(),
+            firmware: FirmwareConfig::default(),
+            quarantine: QuarantineConfig {
+                enabled: true,
+                max_messages_per_minute: 6000,
+                max_errors_per_minute: 120,
+                trickle_seconds: 30,
+            },
+            websocket: WebSocketConfig {
+                batch_window_ms: crate::client_queue::DEFAULT_BATCH_WINDOW.as_millis() as u64,
+                ping_interval_seconds: crate::keepalive::DEFAULT_PING_INTERVAL.as_secs(),
+                max_missed_pongs: crate::keepalive::DEFAULT_MAX_MISSED_PONGS,
+                compression: true,
+            },
+            simulators: SimulatorConfig::default(),
+        }
+    }
+}
+
+/// Environment variables and the config keys they override
+const ENV_OVERRIDES: &[(&str, &str)] = &[
+    ("BIND_ADDRESS", "server.bind_address"),
+    ("HTTP_PORT", "server.http_port"),
+    ("CORS_ORIGINS", "server.cors_origins"),
+    ("BASE_PATH", "server.base_path"),
+    ("TRUSTED_PROXIES", "server.trusted_proxies"),
+    ("WORKING_DIRECTORY", "server.working_directory"),
+    ("PID_FILE", "server.pid_file"),
+    ("TLS_ENABLED", "tls.enabled"),
+    ("HTTPS_PORT", "tls.https_port"),
+    ("TLS_CERT_PATH", "tls.cert_path"),
+    ("TLS_KEY_PATH", "tls.key_path"),
+    ("TLS_REDIRECT_HTTP", "tls.redirect_http"),
+    ("DATABASE_PATH", "database.path"),
+    ("DISCOVERY_ENABLED", "discovery.enabled"),
+    ("MDNS_ADVERTISE", "discovery.mdns_advertise"),
+    ("DISCOVERY_PROBE_PORTS", "discovery.probe_ports"),
+    ("TCP_TIMEOUT_SECS", "devices.tcp_timeout_seconds"),
+    ("UDP_TIMEOUT_SECS", "devices.udp_timeout_seconds"),
+    ("KEEPALIVE_IDLE_SECS", "devices.keepalive_idle_seconds"),
+    ("KEEPALIVE_INTERVAL_SECS", "devices.keepalive_interval_seconds"),
+    ("CONNECT_TIMEOUT_SECS", "devices.connect_timeout_seconds"),
+    ("DEVICE_TLS_CERTS_DIR", "devices.tls_certs_dir"),
+    ("UDP_LISTEN_PORTS", "devices.udp_listen_ports"),
+    ("MAX_CONCURRENT_CONNECTS", "devices.max_concurrent_connects"),
+    ("UDP_WORKERS", "devices.udp_workers"),
+    ("VARIABLE_MIN_INTERVAL_MS", "devices.variable_min_interval_ms"),
+    ("RECORD_RAW_VARIABLES", "devices.record_raw_variables"),
+    ("MAX_MESSAGE_BYTES", "devices.max_message_bytes"),
+    ("MAX_JSON_DEPTH", "devices.max_json_depth"),
+    ("LOG_LEVEL", "logging.level"),
+    ("LOG_FORMAT", "logging.format"),
+    ("EMAIL_ENABLED", "email.enabled"),
+    ("SMTP_HOST", "email.smtp_host"),
+    ("SMTP_PORT", "email.smtp_port"),
+    ("SMTP_SECURITY", "email.smtp_security"),
+    ("SMTP_USERNAME", "email.smtp_username"),
+    ("SMTP_PASSWORD", "email.smtp_password"),
+    ("EMAIL_FROM", "email.from_address"),
+    ("PUBLIC_URL", "email.public_url"),
+    ("TIME_SERVICE_ENABLED", "time_service.enabled"),
+    ("TIME_SERVICE_PORT", "time_service.udp_port"),
+    ("TIME_BROADCAST_INTERVAL_SECS", "time_service.broadcast_interval_seconds"),
+    ("COMMAND_RATE_DEVICE_PER_SEC", "command_rate_limit.device_per_second"),
+    ("COMMAND_RATE_DEVICE_BURST", "command_rate_limit.device_burst"),
+    ("COMMAND_RATE_USER_PER_SEC", "command_rate_limit.user_per_second"),
+    ("COMMAND_RATE_USER_BURST", "command_rate_limit.user_burst"),
+    ("OFFLINE_MODE", "outbound.offline"),
+    ("OUTBOUND_PROXY", "outbound.proxy"),
+    ("WEBHOOK_ALLOW_PRIVATE", "outbound.allow_private_webhooks"),
+    ("SECRETS_KEY_FILE", "secrets.key_file"),
+    ("SECRETS_KEY", "secrets.key"),
+    ("BUS_DECODERS", "bus_decoders.devices"),
+    ("FIRMWARE_MINIMUM_VERSION", "firmware.minimum_version"),
+    ("QUARANTINE_ENABLED", "quarantine.enabled"),
+    ("QUARANTINE_MAX_MESSAGES_PER_MIN", "quarantine.max_messages_per_minute"),
+    ("QUARANTINE_MAX_ERRORS_PER_MIN", "quarantine.max_errors_per_minute"),
+    ("QUARANTINE_TRICKLE_SECS", "quarantine.trickle_seconds"),
+    ("WS_BATCH_WINDOW_MS", "websocket.batch_window_ms"),
+    ("WS_PING_INTERVAL_SECS", "websocket.ping_interval_seconds"),
+    ("WS_MAX_MISSED_PONGS", "websocket.max_missed_pongs"),
+    ("WS_COMPRESSION", "websocket.compression"),
+    ("DEVICE_SIMULATORS", "simulators.count"),
+];
+
+impl AppConfig {
+    /// Load the config file (CONFIG_FILE or config.toml) and apply environment overrides
+    pub fn load() -> Result<Self, String> {
+        let (path, required) = Self::file_location();
+        Self::load_from(&path, required)
+    }
+
+    /// Config file to read and whether it must exist (only an explicit CONFIG_FILE must)
+    pub fn file_location() -> (PathBuf, bool) {
+        match std::env::var("CONFIG_FILE") {
+            Ok(path) => (PathBuf::from(path), true),
+            Err(_) => (PathBuf::from(DEFAULT_CONFIG_FILE), false),
+        }
+    }
+
+    /// Defaults, then `path`, then environment overrides (also used by the config reload)
+    pub fn load_from(path: &Path, required: bool) -> Result<Self, String> {
+        let mut config = Self::default();
+        match std::fs::read_to_string(path) {
+            Ok(text) => config.apply_toml(&text).map_err(|e| format!("{}: {}", path.display(), e))?,
+            Err(e) if required => return Err(format!("{}: {}", path.display(), e)),
+            Err(_) => {}
+        }
+
+        config.apply_env(|name| std::env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Apply the values of a config file
+    pub fn apply_toml(&mut self, text: &str) -> Result<(), String> {
+        for (key, (line, value)) in parse_toml(text)? {
+            self.set(&key, value).map_err(|e| format!("line {}: {}", line, e))?;
+        }
+        Ok(())
+    }
+
+    /// Apply environment overrides, `lookup` returns the variable value if set
+    pub fn apply_env(&mut self, lookup: impl Fn(&str) -> Option<String>) -> Result<(), String> {
+        for (name, key) in ENV_OVERRIDES {
+            if let Some(raw) = lookup(name) {
+                let value = env_value(key, &raw);
+                self.set(key, value).map_err(|e| format!("{}: {}", name, e))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the combined configuration; lists every problem at once
+    pub fn validate(&self) -> Result<(), String> {
+        let mut problems = Vec::new();
+
+        if self.server.bind_address.parse::<IpAddr>().is_err() {
+            problems.push(format!("server.bind_address is not an IP address: {}", self.server.bind_address));
+        }
+        if self.server.http_port == 0 {
+            problems.push("server.http_port must not be 0".to_string());
+        }
+        for origin in &self.server.cors_origins {
+            if origin != "*" && !(origin.starts_with("http://") || origin.starts_with("https://")) {
+                problems.push(format!("server.cors_origins entry must be \"*\" or start with http:// or https://: {}", origin));
+            }
+        }
+        if self.server.base_path.contains(['?', '#', ' ']) {
+            problems.push(format!("server.base_path must be a plain path like \"/esp32\": {}", self.server.base_path));
+        }
+        if let Err(e) = self.proxy() {
+            problems.push(format!("server.trusted_proxies: {}", e));
+        }
+        if self.tls.enabled {
+            if self.tls.cert_path.trim().is_empty() || self.tls.key_path.trim().is_empty() {
+                problems.push("tls.cert_path and tls.key_path are required when tls.enabled = true".to_string());
+            }
+            if self.tls.https_port == 0 || self.tls.https_port == self.server.http_port {
+                problems.push("tls.https_port must not be 0 or equal to server.http_port".to_string());
+            }
+        }
+        if self.database.path.trim().is_empty() {
+            problems.push("database.path must not be empty".to_string());
+        }
+        for (key, secs) in [("tcp_timeout_seconds", self.devices.tcp_timeout_seconds), ("udp_timeout_seconds", self.devices.udp_timeout_seconds)] {
+            if !(1..=3600).contains(&secs) {
+                problems.push(format!("devices.{} must be between 1 and 3600", key));
+            }
+        }
+        // Same ranges as PATCH /api/devices/:id/connection-settings
+        if !(1..=7200).contains(&self.devices.keepalive_idle_seconds) {
+            problems.push("devices.keepalive_idle_seconds must be between 1 and 7200".to_string());
+        }
+        if !(1..=600).contains(&self.devices.keepalive_interval_seconds) {
+            problems.push("devices.keepalive_interval_seconds must be between 1 and 600".to_string());
+        }
+        if !(1..=60).contains(&self.devices.connect_timeout_seconds) {
+            problems.push("devices.connect_timeout_seconds must be between 1 and 60".to_string());
+        }
+        if self.devices.tls_certs_dir.trim().is_empty() {
+            problems.push("devices.tls_certs_dir must not be empty".to_string());
+        }
+        if self.discovery.probe_ports.is_empty() || self.discovery.probe_ports.len() > 16 || self.discovery.probe_ports.contains(&0) {
+            problems.push("discovery.probe_ports must list 1 to 16 ports, none of them 0".to_string());
+        }
+        if self.devices.udp_listen_ports.is_empty() || self.devices.udp_listen_ports.contains(&0) {
+            problems.push("devices.udp_listen_ports must list at least one port, none of them 0".to_string());
+        }
+        if !(1..=256).contains(&self.devices.max_concurrent_connects) {
+            problems.push("devices.max_concurrent_connects must be between 1 and 256".to_string());
+        }
+        if !(1..=64).contains(&self.devices.udp_workers) {
+            problems.push("devices.udp_workers must be between 1 and 64".to_string());
+        }
+        if self.devices.variable_min_interval_ms > 60_000 {
+            problems.push("devices.variable_min_interval_ms must be between 0 and 60000".to_string());
+        }
+        if !(256..=1_048_576).contains(&self.devices.max_message_bytes) {
+            problems.push("devices.max_message_bytes must be between 256 and 1048576".to_string());
+        }
+        if !(1..=128).contains(&self.devices.max_json_depth) {
+            problems.push("devices.max_json_depth must be between 1 and 128".to_string());
+        }
+        if tracing_subscriber::EnvFilter::try_new(&self.logging.level).is_err() {
+            problems.push(format!("logging.level is not a valid filter: {}", self.logging.level));
+        }
+        if crate::logging::LogFormat::parse(&self.logging.format).is_none() {
+            problems.push(format!("logging.format must be \"text\", \"json\" or \"journal\": {}", self.logging.format));
+        }
+        if self.email.enabled {
+            if let Err(e) = crate::email::SmtpSecurity::parse(&self.email.smtp_security) {
+                problems.push(format!("email.smtp_security: {}", e));
+            }
+            if self.email.smtp_host.trim().is_empty() || self.email.smtp_port == 0 {
+                problems.push("email.smtp_host and email.smtp_port are required when email.enabled = true".to_string());
+            }
+            if self.email.from_address.parse::<lettre::message::Mailbox>().is_err() {
+                problems.push(format!("email.from_address is not a valid address: {:?}", self.email.from_address));
+            }
+        }
+        if !(self.email.public_url.starts_with("http://") || self.email.public_url.starts_with("https://")) {
+            problems.push(format!("email.public_url must start with http:// or https://: {}", self.email.public_url));
+        }
+        if self.time_service.enabled {
+            if self.time_service.udp_port == 0 || self.devices.udp_listen_ports.contains(&self.time_service.udp_port) {
+                problems.push("time_service.udp_port must not be 0 or one of devices.udp_listen_ports".to_string());
+            }
+            if self.time_service.broadcast_interval_seconds != 0 && !(10..=86_400).contains(&self.time_service.broadcast_interval_seconds) {
+                problems.push("time_service.broadcast_interval_seconds must be 0 (off) or between 10 and 86400".to_string());
+            }
+        }
+        for (scope, per_second, burst) in [
+            ("device", self.command_rate_limit.device_per_second, self.command_rate_limit.device_burst),
+            ("user", self.command_rate_limit.user_per_second, self.command_rate_limit.user_burst),
+        ] {
+            if per_second > 10_000 {
+                problems.push(format!("command_rate_limit.{}_per_second must be between 0 (off) and 10000", scope));
+            }
+            if per_second > 0 && !(1..=100_000).contains(&burst) {
+                problems.push(format!("command_rate_limit.{}_burst must be between 1 and 100000", scope));
+            }
+        }
+        if !self.firmware.minimum_version.is_empty() && crate::fleet::parse_version(&self.firmware.minimum_version).is_none() {
+            problems.push(format!("firmware.minimum_version must be a version like 1.4.0: {}", self.firmware.minimum_version));
+        }
+        if self.quarantine.max_messages_per_minute == 0 || self.quarantine.max_errors_per_minute == 0 {
+            problems.push("quarantine.max_messages_per_minute and max_errors_per_minute must be at least 1".to_string());
+        }
+        if self.quarantine.trickle_seconds > 3600 {
+            problems.push("quarantine.trickle_seconds must be between 0 and 3600".to_string());
+        }
+        if self.websocket.batch_window_ms > 1000 {
+            problems.push("websocket.batch_window_ms must be between 0 (off) and 1000".to_string());
+        }
+        if !(1..=3600).contains(&self.websocket.ping_interval_seconds) {
+            problems.push("websocket.ping_interval_seconds must be between 1 and 3600".to_string());
+        }
+        if !(1..=100).contains(&self.websocket.max_missed_pongs) {
+            problems.push("websocket.max_missed_pongs must be between 1 and 100".to_string());
+        }
+        if self.simulators.count > 100 {
+            problems.push("simulators.count must be between 0 and 100".to_string());
+        }
+        if !self.outbound.proxy.is_empty() {
+            match reqwest::Url::parse(&self.outbound.proxy) {
+                Ok(url) if matches!(url.scheme(), "http" | "https") && url.host_str().is_some_and(|host| !host.is_empty()) => {}
+                _ => problems.push(format!("outbound.proxy must be an http:// or https:// URL: {}", self.outbound.proxy)),
+            }
+        }
+        if self.secrets.key_file.trim().is_empty() {
+            problems.push("secrets.key_file must not be empty".to_string());
+        }
+        let key = &self.secrets.key;
+        if !key.is_empty() && (key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit())) {
+            problems.push("secrets.key must be 64 hex characters (32 bytes)".to_string());
+        }
+        for entry in &self.bus_decoders.devices {
+            if let Err(e) = crate::bus_decoders::DecoderAssignment::parse(entry) {
+                problems.push(format!("bus_decoders.devices: {}", e));
+            }
+        }
+        for entry in &self.message_parsers.devices {
+            if let Err(e) = crate::message_parsers::parse_assignment(entry) {
+                problems.push(format!("message_parsers.devices: {}", e));
+            }
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(format!("invalid configuration:\n  - {}", problems.join("\n  - ")))
+        }
+    }
+
+    /// Reverse proxy settings (trusted proxies, normalized base path)
+    pub fn proxy(&self) -> Result<crate::proxy::ProxyConfig, String> {
+        crate::proxy::ProxyConfig::new(&self.server.trusted_proxies, &self.server.base_path)
+    }
+
+    /// HTTP listen address, e.g. "0.0.0.0:3000"
+    pub fn listen_address(&self) -> String {
+        format!("{}:{}", self.server.bind_address, self.server.http_port)
+    }
+
+    /// Log output format (validated, falls back to text)
+    pub fn log_format(&self) -> crate::logging::LogFormat {
+        crate::logging::LogFormat::parse(&self.logging.format).unwrap_or(crate::logging::LogFormat::Text)
+    }
+
+    /// HTTPS listen address, e.g. "0.0.0.0:3443"
+    pub fn https_listen_address(&self) -> String {
+        format!("{}:{}", self.server.bind_address, self.tls.https_port)
+    }
+
+    fn set(&mut self, key: &str, value: TomlValue) -> Result<(), String> {
+        match key {
+            "server.bind_address" => self.server.bind_address = value.into_string(key)?,
+            "server.http_port" => self.server.http_port = value.into_int(key)?,
+            "server.cors_origins" => self.server.cors_origins = value.into_string_list(key)?,
+            "server.base_path" => self.server.base_path = value.into_string(key)?,
+            "server.trusted_proxies" => self.server.trusted_proxies = value.into_string_list(key)?,
+            "server.working_directory" => self.server.working_directory = value.into_string(key)?,
+            "server.pid_file" => self.server.pid_file = value.into_string(key)?,
+            "tls.enabled" => self.tls.enabled = value.into_bool(key)?,
+            "tls.https_port" => self.tls.https_port = value.into_int(key)?,
+            "tls.cert_path" => self.tls.cert_path = value.into_string(key)?,
+            "tls.key_path" => self.tls.key_path = value.into_string(key)?,
+            "tls.redirect_http" => self.tls.redirect_http = value.into_bool(key)?,
+            "database.path" => self.database.path = value.into_string(key)?,
+            "discovery.enabled" => self.discovery.enabled = value.into_bool(key)?,
+            "discovery.mdns_advertise" => self.discovery.mdns_advertise = value.into_bool(key)?,
+            "discovery.probe_ports" => {
+                self.discovery.probe_ports = value.into_list(key)?.into_iter()
+                    .map(|v| v.into_int(key))
+                    .collect::<Result<_, _>>()?;
+            }
+            "devices.tcp_timeout_seconds" => self.devices.tcp_timeout_seconds = value.into_int(key)?,
+            "devices.udp_timeout_seconds" => self.devices.udp_timeout_seconds = value.into_int(key)?,
+            "devices.keepalive_idle_seconds" => self.devices.keepalive_idle_seconds = value.into_int(key)?,
+            "devices.keepalive_interval_seconds" => self.devices.keepalive_interval_seconds = value.into_int(key)?,
+            "devices.connect_timeout_seconds" => self.devices.connect_timeout_seconds = value.into_int(key)?,
+            "devices.tls_certs_dir" => self.devices.tls_certs_dir = value.into_string(key)?,
+            "devices.udp_listen_ports" => {
+                self.devices.udp_listen_ports = value.into_list(key)?.into_iter()
+                    .map(|v| v.into_int(key))
+                    .collect::<Result<_, _>>()?;
+            }
+            "devices.max_concurrent_connects" => self.devices.max_concurrent_connects = value.into_int(key)?,
+            "devices.udp_workers" => self.devices.udp_workers = value.into_int(key)?,
+            "devices.variable_min_interval_ms" => self.devices.variable_min_interval_ms = value.into_int(key)?,
+            "devices.max_message_bytes" => self.devices.max_message_bytes = value.into_int(key)?,
+            "devices.max_json_depth" => self.devices.max_json_depth = value.into_int(key)?,
+            "devices.record_raw_variables" => self.devices.record_raw_variables = value.into_bool(key)?,
+            "logging.level" => self.logging.level = value.into_string(key)?,
+            "logging.format" => self.logging.format = value.into_string(key)?,
+            "email.enabled" => self.email.enabled = value.into_bool(key)?,
+            "email.smtp_host" => self.email.smtp_host = value.into_string(key)?,
+            "email.smtp_port" => self.email.smtp_port = value.into_int(key)?,
+            "email.smtp_security" => self.email.smtp_security = value.into_string(key)?,
+            "email.smtp_username" => self.email.smtp_username = value.into_string(key)?,
+            "email.smtp_password" => self.email.smtp_password = value.into_string(key)?,
+            "email.from_address" => self.email.from_address = value.into_string(key)?,
+            "email.public_url" => self.email.public_url = value.into_string(key)?,
+            "time_service.enabled" => self.time_service.enabled = value.into_bool(key)?,
+            "time_service.udp_port" => self.time_service.udp_port = value.into_int(key)?,
+            "time_service.broadcast_interval_seconds" => self.time_service.broadcast_interval_seconds = value.into_int(key)?,
+            "command_rate_limit.device_per_second" => self.command_rate_limit.device_per_second = value.into_int(key)?,
+            "command_rate_limit.device_burst" => self.command_rate_limit.device_burst = value.into_int(key)?,
+            "command_rate_limit.user_per_second" => self.command_rate_limit.user_per_second = value.into_int(key)?,
+            "command_rate_limit.user_burst" => self.command_rate_limit.user_burst = value.into_int(key)?,
+            "outbound.offline" => self.outbound.offline = value.into_bool(key)?,
+            "outbound.proxy" => self.outbound.proxy = value.into_string(key)?,
+            "outbound.allow_private_webhooks" => self.outbound.allow_private_webhooks = value.into_bool(key)?,
+            "secrets.key_file" => self.secrets.key_file = value.into_string(key)?,
+            "secrets.key" => self.secrets.key = value.into_string(key)?,
+            "bus_decoders.devices" => self.bus_decoders.devices = value.into_string_list(key)?,
+            "message_parsers.devices" => self.message_parsers.devices = value.into_string_list(key)?,
+            "firmware.minimum_version" => self.firmware.minimum_version = value.into_string(key)?,
+            "quarantine.enabled" => self.quarantine.enabled = value.into_bool(key)?,
+            "quarantine.max_messages_per_minute" => self.quarantine.max_messages_per_minute = value.into_int(key)?,
+            "quarantine.max_errors_per_minute" => self.quarantine.max_errors_per_minute = value.into_int(key)?,
+            "quarantine.trickle_seconds" => self.quarantine.trickle_seconds = value.into_int(key)?,
+            "websocket.batch_window_ms" => self.websocket.batch_window_ms = value.into_int(key)?,
+            "websocket.ping_interval_seconds" => self.websocket.ping_interval_seconds = value.into_int(key)?,
+            "websocket.max_missed_pongs" => self.websocket.max_missed_pongs = value.into_int(key)?,
+            "websocket.compression" => self.websocket.compression = value.into_bool(key)?,
+            "simulators.count" => self.simulators.count = value.into_int(key)?,
+            _ => return Err(format!("unknown setting '{}'", key)),
+        }
+        Ok(())
+    }
+}
+
+/// Environment values are plain text; lists are comma-separated
+fn env_value(key: &str, raw: &str) -> TomlValue {
+    let raw = raw.trim();
+    let scalar = |s: &str| s.parse::<i64>().map(TomlValue::Int).unwrap_or_else(|_| TomlValue::String(s.to_string()));
+    match key {
+        // Same switch semantics as before: everything except 0/false/off/no enables
+        "discovery.enabled" | "discovery.mdns_advertise" | "tls.enabled" | "tls.redirect_http" | "email.enabled" | "time_service.enabled" | "outbound.offline"
+        | "outbound.allow_private_webhooks" | "websocket.compression" => {
+            TomlValue::Bool(!matches!(raw.to_lowercase().as_str(), "0" | "false" | "off" | "no"))
+        }
+        "server.cors_origins" | "server.trusted_proxies" | "devices.udp_listen_ports" | "discovery.probe_ports" | "bus_decoders.devices" => TomlValue::Array(
+            raw.split(',').map(str::trim).filter(|s| !s.is_empty()).map(scalar).collect()
+        ),
+        "server.bind_address" | "server.base_path" | "server.working_directory" | "server.pid_file" | "tls.cert_path" | "tls.key_path" | "database.path" | "devices.tls_certs_dir" | "logging.level" | "logging.format"
+        | "email.smtp_host" | "email.smtp_security" | "email.smtp_username" | "email.smtp_password" | "email.from_address" | "email.public_url" | "outbound.proxy"
+        | "secrets.key_file" | "secrets.key" | "firmware.minimum_version" => {
+            TomlValue::String(raw.to_string())
+        }
+        _ => scalar(raw),
+    }
+}
+
+// ============================================================================
+// TOML SUBSET PARSER
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+enum TomlValue {
+    String(String),
+    Int(i64),
+    Bool(bool),
+    Array(Vec<TomlValue>),
+}
+
+impl TomlValue {
+    fn into_string(self, key: &str) -> Result<String, String> {
+        match self {
+            TomlValue::String(s) => Ok(s),
+            other => Err(format!("{} must be a string, got {:?}", key, other)),
+        }
+    }
+
+    fn into_bool(self, key: &str) -> Result<bool, String> {
+        match self {
+            TomlValue::Bool(b) => Ok(b),
+            other => Err(format!("{} must be true or false, got {:?}", key, other)),
+        }
+    }
+
+    fn into_int<T: TryFrom<i64>>(self, key: &str) -> Result<T, String> {
+        match self {
+            TomlValue::Int(i) => T::try_from(i).map_err(|_| format!("{} is out of range: {}", key, i)),
+            other => Err(format!("{} must be an integer, got {:?}", key, other)),
+        }
+    }
+
+    fn into_list(self, key: &str) -> Result<Vec<TomlValue>, String> {
+        match self {
+            TomlValue::Array(items) => Ok(items),
+            other => Err(format!("{} must be an array, got {:?}", key, other)),
+        }
+    }
+
+    fn into_string_list(self, key: &str) -> Result<Vec<String>, String> {
+        self.into_list(key)?.into_iter().map(|v| v.into_string(key)).collect()
+    }
+}
+
+/// Parse into "section.key" -> (line number, value)
+fn parse_toml(text: &str) -> Result<HashMap<String, (usize, TomlValue)>, String> {
+    let mut values = HashMap::new();
+    let mut section = String::new();
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = name.trim().to_string();
+            continue;
+        }
+
+        let (key, value) = line.split_once('=')
+            .ok_or_else(|| format!("line {}: expected `key = value`", line_number))?;
+        let value = parse_value(value.trim()).map_err(|e| format!("line {}: {}", line_number, e))?;
+        let full_key = if section.is_empty() { key.trim().to_string() } else { format!("{}.{}", section, key.trim()) };
+        if values.insert(full_key.clone(), (line_number, value)).is_some() {
+            return Err(format!("line {}: duplicate key '{}'", line_number, full_key));
+        }
+    }
+
+    Ok(values)
+}
+
+/// Cut a `#` comment that is not inside a string
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(text: &str) -> Result<TomlValue, String> {
+    if let Some(inner) = text.strip_prefix('[').and_then(|t| t.strip_suffix(']')) {
+        return split_array(inner)?.into_iter()
+            .map(parse_value)
+            .collect::<Result<Vec<_>, _>>()
+            .map(TomlValue::Array);
+    }
+    if let Some(inner) = text.strip_prefix('"').and_then(|t| t.strip_suffix('"')) {
+        return unescape(inner).map(TomlValue::String);
+    }
+    match text {
+        "true" => Ok(TomlValue::Bool(true)),
+        "false" => Ok(TomlValue::Bool(false)),
+        _ => text.replace('_', "").parse::<i64>()
+            .map(TomlValue::Int)
+            .map_err(|_| format!("unsupported value: {}", text)),
+    }
+}
+
+/// Split array items at commas outside of strings
+fn split_array(inner: &str) -> Result<Vec<&str>, String> {
+    let mut items = Vec::new();
+    let mut start = 0;
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in inner.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match c {
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            ',' if !in_string => {
+                items.push(inner[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    if in_string {
+        return Err("unterminated string in array".to_string());
+    }
+    items.push(inner[start..].trim());
+    // Allow a trailing comma and empty arrays
+    items.retain(|item| !item.is_empty());
+    Ok(items)
+}
+
+fn unescape(text: &str) -> Result<String, String> {
+    let mut result = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('"') => result.push('"'),
+            Some('\\') => result.push('\\'),
+            Some('n') => result.push('\n'),
+            Some('t') => result.push('\t'),
+            other => return Err(format!("unsupported escape: \\{}", other.map(String::from).unwrap_or_default())),
+        }
+    }
+    Ok(result)
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_then_env_overrides() {
+        let mut config = AppConfig::default();
+        config.apply_toml(r#"
+            # Lab server
+            [server]
+            bind_address = "127.0.0.1"
+            http_port = 8080   # behind nginx
+            cors_origins = ["http://localhost:5173", "https://lab.example.org"]
+
+            [devices]
+            udp_listen_ports = [3232, 8266]
+
+            [discovery]
+            enabled = false
+        "#).unwrap();
+
+        assert_eq!(config.listen_address(), "127.0.0.1:8080");
+        assert_eq!(config.server.cors_origins.len(), 2);
+        assert_eq!(config.devices.udp_listen_ports, vec![3232, 8266]);
+        assert!(!config.discovery.enabled);
+
+        let env = HashMap::from([
+            ("HTTP_PORT", "9000"), ("UDP_LISTEN_PORTS", "4000, 4001"), ("MDNS_ADVERTISE", "off"),
+            ("WS_BATCH_WINDOW_MS", "0"), ("DEVICE_SIMULATORS", "2"),
+        ]);
+        config.apply_env(|name| env.get(name).map(|v| v.to_string())).unwrap();
+        assert_eq!(config.server.http_port, 9000);
+        assert_eq!(config.websocket.batch_window(), std::time::Duration::ZERO);
+        assert_eq!(config.simulators.count, 2);
+        assert_eq!(config.devices.udp_listen_ports, vec![4000, 4001]);
+        assert!(!config.discovery.mdns_advertise);
+        config.validate().unwrap();
+    }
+
+    #[test]
+    fn test_example_file_matches_defaults() {
+        let mut config = AppConfig::default();
+        config.apply_toml(include_str!("../../config.example.toml")).unwrap();
+        assert_eq!(config, AppConfig::default());
+    }
+
+    #[test]
+    fn test_invalid_config_is_rejected() {
+        let mut config = AppConfig::default();
+        assert!(config.apply_toml("[server]\nhttp_prot = 80").unwrap_err().contains("unknown setting"));
+        assert!(config.apply_toml("[server]\nhttp_port = \"80\"").is_err());
+        assert!(config.apply_toml("[server]\nhttp_port = 70000").is_err());
+
+        config.server.bind_address = "localhost:3000".to_string();
+        config.devices.udp_timeout_seconds = 0;
+        config.devices.max_concurrent_connects = 0;
+        config.devices.udp_workers = 0;
+        config.devices.variable_min_interval_ms = 120_000;
+        config.tls.enabled = true;
+        config.time_service.enabled = true;
+        config.time_service.udp_port = 3232;
+        config.command_rate_limit.user_burst = 0;
+        config.outbound.proxy = "socks5://proxy.lan".to_string();
+        config.bus_decoders.devices = vec!["AA-01=modbus".to_string()];
+        config.message_parsers.devices = vec!["AA-01=json,xml".to_string()];
+        config.websocket.ping_interval_seconds = 0;
+        let error = config.validate().unwrap_err();
+        assert!(error.contains("bind_address"));
+        assert!(error.contains("tls.cert_path"));
+        assert!(error.contains("udp_timeout_seconds"));
+        assert!(error.contains("max_concurrent_connects"));
+        assert!(error.contains("udp_workers"));
+        assert!(error.contains("variable_min_interval_ms"));
+        assert!(error.contains("time_service.udp_port"));
+        assert!(error.contains("command_rate_limit.user_burst"));
+        assert!(error.contains("outbound.proxy"));
+        assert!(error.contains("bus_decoders.devices"));
+        assert!(error.contains("message_parsers.devices"));
+        assert!(error.contains("websocket.ping_interval_seconds"));
+    }
+}
@@ -0,0 +1,316 @@
+// Config reload - apply config.toml changes without a restart (SIGHUP or POST /api/admin/reload-config)
+//
+// A restart drops every device TCP connection and WebSocket client, so the settings that can
+// change safely at runtime are re-applied from the config file (plus environment overrides):
+// log level, discovery and mDNS advertisement toggles, default device timeouts and TCP
+// keep-alive, variable coalescing, device message limits, command rate limits, the minimum
+// firmware version, the quarantine limits and the WebSocket batching/keepalive of new
+// connections. Other changed settings are reported as needing a restart and keep their
+// running values. An invalid file changes nothing.
+
+use std::path::PathBuf;
+use std::sync::{Arc, OnceLock};
+
+use tokio::sync::Mutex;
+
+use crate::config::AppConfig;
+use crate::device_discovery::DeviceDiscovery;
+use crate::device_manager::DeviceManager;
+use crate::mdns_server::MdnsServer;
+use crate::{client_queue, device_types, fleet, inbound_validation, keepalive, logging, quarantine};
+
+static RELOADER: OnceLock<Reloader> = OnceLock::new();
+
+/// Result of a reload: applied keys, keys that need a restart, keys that failed
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ReloadReport {
+    pub applied: Vec<String>,
+    pub restart_required: Vec<String>,
+    pub failed: Vec<String>,
+}
+
+/// Applies reloaded settings to the running services
+pub struct Reloader {
+    config_file: PathBuf,
+    config_required: bool,
+    /// Settings the server currently runs with
+    running: Mutex<AppConfig>,
+    device_manager: Arc<DeviceManager>,
+    device_discovery: Arc<Mutex<DeviceDiscovery>>,
+    mdns_server: Arc<Mutex<MdnsServer>>,
+}
+
+impl Reloader {
+    /// `config_file` should be absolute, server.working_directory changes the CWD after startup
+    pub fn new(
+        config_file: PathBuf,
+        config_required: bool,
+        running: AppConfig,
+        device_manager: Arc<DeviceManager>,
+        device_discovery: Arc<Mutex<DeviceDiscovery>>,
+        mdns_server: Arc<Mutex<MdnsServer>>,
+    ) -> Self {
+        Self {
+            config_file,
+            config_required,
+            running: Mutex::new(running),
+            device_manager,
+            device_discovery,
+            mdns_server,
+        }
+    }
+
+    /// Read the config again and apply what changed; Err if the file is invalid
+    pub async fn reload(&self) -> Result<ReloadReport, String> {
+        let new = AppConfig::load_from(&self.config_file, self.config_required)?;
+        let mut running = self.running.lock().await;
+        let mut report = ReloadReport {
+            restart_required: restart_required(&running, &new),
+            ..Default::default()
+        };
+
+        if new.logging.level != running.logging.level {
+            match logging::set_level(&new.logging.level) {
+                Ok(()) => {
+                    running.logging.level = new.logging.level.clone();
+                    report.applied.push("logging.level".to_string());
+                }
+                Err(e) => report.failed.push(format!("logging.level: {}", e)),
+            }
+        }
+
+        if new.discovery.enabled != running.discovery.enabled {
+            let mut discovery = self.device_discovery.lock().await;
+            if new.discovery.enabled {
+                if let Err(e) = discovery.start_discovery().await {
+                    report.failed.push(format!("discovery.enabled: {}", e));
+                }
+            } else {
+                discovery.stop_discovery().await;
+            }
+            running.discovery.enabled = discovery.is_running();
+            if running.discovery.enabled == new.discovery.enabled {
+                report.applied.push("discovery.enabled".to_string());
+            }
+        }
+
+        if new.discovery.mdns_advertise != running.discovery.mdns_advertise {
+            let mut server = self.mdns_server.lock().await;
+            server.set_enabled(new.discovery.mdns_advertise);
+            if new.discovery.mdns_advertise {
+                if let Err(e) = server.start_advertising(running.server.http_port).await {
+                    report.failed.push(format!("discovery.mdns_advertise: {}", e));
+                }
+            } else {
+                server.stop_advertising().await;
+            }
+            if server.is_running() == new.discovery.mdns_advertise {
+                running.discovery.mdns_advertise = new.discovery.mdns_advertise;
+                report.applied.push("discovery.mdns_advertise".to_string());
+            } else {
+                server.set_enabled(running.discovery.mdns_advertise);
+            }
+        }
+
+        if new.discovery.probe_ports != running.discovery.probe_ports {
+            device_types::set_discovery_probe_ports(&new.discovery.probe_ports);
+            running.discovery.probe_ports = new.discovery.probe_ports.clone();
+            report.applied.push("discovery.probe_ports".to_string());
+        }
+
+        // Defaults for devices added from now on; stored devices keep their own timeouts
+        let devices = &new.devices;
+        if devices.tcp_timeout_seconds != running.devices.tcp_timeout_seconds || devices.udp_timeout_seconds != running.devices.udp_timeout_seconds {
+            device_types::set_default_timeouts(devices.tcp_timeout_seconds, devices.udp_timeout_seconds);
+            for (key, changed) in [
+                ("devices.tcp_timeout_seconds", devices.tcp_timeout_seconds != running.devices.tcp_timeout_seconds),
+                ("devices.udp_timeout_seconds", devices.udp_timeout_seconds != running.devices.udp_timeout_seconds),
+            ] {
+                if changed {
+                    report.applied.push(key.to_string());
+                }
+            }
+            running.devices.tcp_timeout_seconds = devices.tcp_timeout_seconds;
+            running.devices.udp_timeout_seconds = devices.udp_timeout_seconds;
+        }
+
+        let tcp_settings = |devices: &crate::config::DeviceDefaultsConfig| {
+            (devices.keepalive_idle_seconds, devices.keepalive_interval_seconds, devices.connect_timeout_seconds)
+        };
+        if tcp_settings(devices) != tcp_settings(&running.devices) {
+            device_types::set_default_tcp_settings(devices.keepalive_idle_seconds, devices.keepalive_interval_seconds, devices.connect_timeout_seconds);
+            for (key, changed) in [
+                ("devices.keepalive_idle_seconds", devices.keepalive_idle_seconds != running.devices.keepalive_idle_seconds),
+                ("devices.keepalive_interval_seconds", devices.keepalive_interval_seconds != running.devices.keepalive_interval_seconds),
+                ("devices.connect_timeout_seconds", devices.connect_timeout_seconds != running.devices.connect_timeout_seconds),
+            ] {
+                if changed {
+                    report.applied.push(key.to_string());
+                }
+            }
+            running.devices.keepalive_idle_seconds = devices.keepalive_idle_seconds;
+            running.devices.keepalive_interval_seconds = devices.keepalive_interval_seconds;
+            running.devices.connect_timeout_seconds = devices.connect_timeout_seconds;
+        }
+
+        if devices.tls_certs_dir != running.devices.tls_certs_dir {
+            device_types::set_tls_certs_dir(&devices.tls_certs_dir);
+            report.applied.push("devices.tls_certs_dir".to_string());
+            running.devices.tls_certs_dir = devices.tls_certs_dir.clone();
+        }
+
+        if devices.variable_min_interval_ms != running.devices.variable_min_interval_ms || devices.record_raw_variables != running.devices.record_raw_variables {
+            self.device_manager.set_variable_coalescing(
+                std::time::Duration::from_millis(devices.variable_min_interval_ms),
+                devices.record_raw_variables,
+            );
+            for (key, changed) in [
+                ("devices.variable_min_interval_ms", devices.variable_min_interval_ms != running.devices.variable_min_interval_ms),
+                ("devices.record_raw_variables", devices.record_raw_variables != running.devices.record_raw_variables),
+            ] {
+                if changed {
+                    report.applied.push(key.to_string());
+                }
+            }
+            running.devices.variable_min_interval_ms = devices.variable_min_interval_ms;
+            running.devices.record_raw_variables = devices.record_raw_variables;
+        }
+
+        if devices.inbound_limits() != running.devices.inbound_limits() {
+            inbound_validation::configure(devices.inbound_limits());
+            for (key, changed) in [
+                ("devices.max_message_bytes", devices.max_message_bytes != running.devices.max_message_bytes),
+                ("devices.max_json_depth", devices.max_json_depth != running.devices.max_json_depth),
+            ] {
+                if changed {
+                    report.applied.push(key.to_string());
+                }
+            }
+            running.devices.max_message_bytes = devices.max_message_bytes;
+            running.devices.max_json_depth = devices.max_json_depth;
+        }
+
+        if new.command_rate_limit != running.command_rate_limit {
+            self.device_manager.set_command_rate_limits(new.command_rate_limit.limits());
+            running.command_rate_limit = new.command_rate_limit.clone();
+            report.applied.push("command_rate_limit".to_string());
+        }
+
+        if new.firmware.minimum_version != running.firmware.minimum_version {
+            fleet::set_minimum_firmware_version(&new.firmware.minimum_version);
+            running.firmware.minimum_version = new.firmware.minimum_version.clone();
+            report.applied.push("firmware.minimum_version".to_string());
+        }
+
+        if new.quarantine != running.quarantine {
+            quarantine::configure(new.quarantine.settings());
+            running.quarantine = new.quarantine.clone();
+            report.applied.push("quarantine".to_string());
+        }
+
+        // Connected clients keep their settings, new connections get the reloaded ones
+        if new.websocket != running.websocket {
+            keepalive::configure(new.websocket.keepalive());
+            client_queue::set_batch_window(new.websocket.batch_window());
+            client_queue::set_compression_enabled(new.websocket.compression);
+            running.websocket = new.websocket.clone();
+            report.applied.push("websocket".to_string());
+        }
+
+        Ok(report)
+    }
+}
+
+/// Changed settings that only take effect after a restart (sections, or keys of mixed sections)
+fn restart_required(running: &AppConfig, new: &AppConfig) -> Vec<String> {
+    let mut keys = Vec::new();
+    let mut check = |key: &str, changed: bool| {
+        if changed {
+            keys.push(key.to_string());
+        }
+    };
+    check("server", running.server != new.server);
+    check("tls", running.tls != new.tls);
+    check("database", running.database != new.database);
+    check("logging.format", running.logging.format != new.logging.format);
+    check("devices.udp_listen_ports", running.devices.udp_listen_ports != new.devices.udp_listen_ports);
+    check("devices.max_concurrent_connects", running.devices.max_concurrent_connects != new.devices.max_concurrent_connects);
+    check("devices.udp_workers", running.devices.udp_workers != new.devices.udp_workers);
+    check("email", running.email != new.email);
+    check("time_service", running.time_service != new.time_service);
+    check("outbound", running.outbound != new.outbound);
+    check("secrets", running.secrets != new.secrets);
+    check("bus_decoders", running.bus_decoders != new.bus_decoders);
+    check("message_parsers", running.message_parsers != new.message_parsers);
+    check("simulators", running.simulators != new.simulators);
+    keys
+}
+
+pub fn configure(reloader: Reloader) {
+    if RELOADER.set(reloader).is_err() {
+        tracing::warn!("Config reloader was already configured, keeping the first one");
+    }
+}
+
+/// None until main() configured it (tests, tools)
+pub fn reloader() -> Option<&'static Reloader> {
+    RELOADER.get()
+}
+
+/// Reload on every SIGHUP (`systemctl reload`, `kill -HUP`)
+pub fn spawn_sighup_handler() {
+    #[cfg(unix)]
+    tokio::spawn(async {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::warn!("Cannot listen for SIGHUP: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            let Some(reloader) = reloader() else { continue };
+            match reloader.reload().await {
+                Ok(report) => log_report(&report),
+                Err(e) => tracing::error!("Config reload failed, keeping the running settings: {}", e),
+            }
+        }
+    });
+}
+
+pub fn log_report(report: &ReloadReport) {
+    if report.applied.is_empty() {
+        tracing::info!("Config reloaded, no runtime settings changed");
+    } else {
+        tracing::info!("Config reloaded, applied: {}", report.applied.join(", "));
+    }
+    if !report.restart_required.is_empty() {
+        tracing::warn!("Changed settings that need a restart: {}", report.restart_required.join(", "));
+    }
+    for failure in &report.failed {
+        tracing::warn!("Config reload: {}", failure);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restart_required_ignores_reloadable_settings() {
+        let running = AppConfig::default();
+        let mut new = running.clone();
+        new.logging.level = "debug".to_string();
+        new.discovery.enabled = false;
+        new.devices.udp_timeout_seconds = 60;
+        new.command_rate_limit.device_per_second = 5;
+        new.websocket.ping_interval_seconds = 30;
+        assert!(restart_required(&running, &new).is_empty());
+
+        new.server.http_port = 8080;
+        new.devices.udp_workers = 2;
+        new.logging.format = "json".to_string();
+        assert_eq!(restart_required(&running, &new), vec!["server", "logging.format", "devices.udp_workers"]);
+    }
+}
@@ -0,0 +1,390 @@
+// Crash reports - reset reasons and core dumps of ESP32 devices
+//
+// Reports reach the server two ways: the firmware uploads one after an abnormal reset
+// (POST /api/devices/:id/crash-report, JSON with optional base64 core dump, or the raw core dump
+// as application/octet-stream), or the collector finds a `resetReason` in the hello message a
+// device sends after booting. Hello messages only produce a report for crash resets (panic,
+// watchdogs, brownout) and once per boot. The per-reason counts of the listing make recurring
+// brownouts of a badly powered board easy to spot.
+//
+// Uploads are signed with the device's identity secret (see device_identity.rs), limited to
+// MAX_UPLOAD_BYTES and to MAX_UPLOADS_PER_HOUR per device, so a board stuck in a crash loop or a
+// stranger on the network cannot fill the database.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::database::DatabaseManager;
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+
+/// Largest accepted core dump (the ESP32 coredump partition is usually 64 KiB)
+pub const MAX_CORE_DUMP_BYTES: usize = 1024 * 1024;
+
+/// Backtrace and panic reason are cut to this length
+pub const MAX_TEXT_CHARS: usize = 16 * 1024;
+
+/// Largest accepted upload body: a base64 core dump plus backtrace, panic reason and metadata
+pub const MAX_UPLOAD_BYTES: usize = MAX_CORE_DUMP_BYTES.div_ceil(3) * 4 + 2 * MAX_TEXT_CHARS * 4 + 4096;
+
+/// Uploads accepted per device within UPLOAD_WINDOW
+pub const MAX_UPLOADS_PER_HOUR: usize = 10;
+const UPLOAD_WINDOW: Duration = Duration::from_secs(3600);
+
+static UPLOAD_LIMITER: LazyLock<UploadLimiter> = LazyLock::new(UploadLimiter::default);
+
+/// Hellos whose computed boot times differ by less than this belong to the same boot
+const SAME_BOOT_TOLERANCE_MS: i64 = 10_000;
+
+/// Reset reason as reported by esp_reset_reason()
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResetReason {
+    Unknown,
+    PowerOn,
+    External,
+    Software,
+    Panic,
+    InterruptWatchdog,
+    TaskWatchdog,
+    Watchdog,
+    DeepSleep,
+    Brownout,
+    Sdio,
+}
+
+impl ResetReason {
+    /// esp_reset_reason_t value, its name with or without ESP_RST_, or the names used here
+    pub fn parse(value: &Value) -> Option<Self> {
+        if let Some(code) = value.as_u64() {
+            return Some(match code {
+                1 => ResetReason::PowerOn,
+                2 => ResetReason::External,
+                3 => ResetReason::Software,
+                4 => ResetReason::Panic,
+                5 => ResetReason::InterruptWatchdog,
+                6 => ResetReason::TaskWatchdog,
+                7 => ResetReason::Watchdog,
+                8 => ResetReason::DeepSleep,
+                9 => ResetReason::Brownout,
+                10 => ResetReason::Sdio,
+                _ => ResetReason::Unknown,
+            });
+        }
+        let name = value.as_str()?.trim().to_ascii_lowercase();
+        let name = name.strip_prefix("esp_rst_").unwrap_or(&name);
+        Some(match name.replace(['-', ' '], "_").as_str() {
+            "poweron" | "power_on" => ResetReason::PowerOn,
+            "ext" | "external" => ResetReason::External,
+            "sw" | "software" => ResetReason::Software,
+            "panic" => ResetReason::Panic,
+            "int_wdt" | "interrupt_watchdog" => ResetReason::InterruptWatchdog,
+            "task_wdt" | "task_watchdog" => ResetReason::TaskWatchdog,
+            "wdt" | "watchdog" => ResetReason::Watchdog,
+            "deepsleep" | "deep_sleep" => ResetReason::DeepSleep,
+            "brownout" | "brown_out" => ResetReason::Brownout,
+            "sdio" => ResetReason::Sdio,
+            "unknown" => ResetReason::Unknown,
+            _ => return None,
+        })
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ResetReason::Unknown => "unknown",
+            ResetReason::PowerOn => "power_on",
+            ResetReason::External => "external",
+            ResetReason::Software => "software",
+            ResetReason::Panic => "panic",
+            ResetReason::InterruptWatchdog => "interrupt_watchdog",
+            ResetReason::TaskWatchdog => "task_watchdog",
+            ResetReason::Watchdog => "watchdog",
+            ResetReason::DeepSleep => "deep_sleep",
+            ResetReason::Brownout => "brownout",
+            ResetReason::Sdio => "sdio",
+        }
+    }
+
+    /// Resets that point at a problem (not power-on, reset button, esp_restart or deep sleep)
+    pub fn is_crash(&self) -> bool {
+        matches!(self, ResetReason::Panic | ResetReason::InterruptWatchdog | ResetReason::TaskWatchdog
+            | ResetReason::Watchdog | ResetReason::Brownout)
+    }
+}
+
+/// Body of POST /api/devices/:id/crash-report (JSON)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReportUpload {
+    /// Name or esp_reset_reason_t value; missing = panic
+    #[serde(default)]
+    pub reset_reason: Option<Value>,
+    pub firmware_version: Option<String>,
+    /// Uptime before the reset (seconds)
+    pub uptime: Option<u64>,
+    pub panic_reason: Option<String>,
+    pub backtrace: Option<String>,
+    /// Core dump (ELF or binary partition image) as base64
+    pub core_dump: Option<String>,
+}
+
+/// Stored crash report (the core dump itself is only returned by the download)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CrashReport {
+    pub id: String,
+    pub device_id: String,
+    pub reset_reason: ResetReason,
+    /// "upload" or "hello"
+    pub source: String,
+    pub firmware_version: Option<String>,
+    pub uptime_secs: Option<u64>,
+    pub panic_reason: Option<String>,
+    pub backtrace: Option<String>,
+    pub core_dump_size: Option<usize>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl CrashReport {
+    pub fn new(device_id: &str, reset_reason: ResetReason, source: &str) -> Self {
+        CrashReport {
+            id: uuid::Uuid::new_v4().to_string(),
+            device_id: device_id.to_string(),
+            reset_reason,
+            source: source.to_string(),
+            firmware_version: None,
+            uptime_secs: None,
+            panic_reason: None,
+            backtrace: None,
+            core_dump_size: None,
+            created_at: Utc::now(),
+        }
+    }
+}
+
+/// Report count of one reset reason (listing summary)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReasonCount {
+    pub reset_reason: ResetReason,
+    pub count: u64,
+    pub last_at: DateTime<Utc>,
+}
+
+fn limit_text(text: Option<String>) -> Option<String> {
+    text.map(|text| text.chars().take(MAX_TEXT_CHARS).collect::<String>()).filter(|text| !text.trim().is_empty())
+}
+
+/// Report and decoded core dump of an upload
+pub fn report_from_upload(device_id: &str, upload: CrashReportUpload) -> Result<(CrashReport, Option<Vec<u8>>), String> {
+    use base64::Engine;
+
+    let reset_reason = match &upload.reset_reason {
+        Some(value) => ResetReason::parse(value).ok_or_else(|| format!("Unknown reset reason {}", value))?,
+        None => ResetReason::Panic,
+    };
+    let core_dump = upload.core_dump.as_deref()
+        .map(|dump| base64::engine::general_purpose::STANDARD.decode(dump.trim()))
+        .transpose()
+        .map_err(|e| format!("coreDump is not valid base64: {}", e))?;
+    check_core_dump_size(core_dump.as_deref())?;
+
+    let mut report = CrashReport::new(device_id, reset_reason, "upload");
+    report.firmware_version = upload.firmware_version.filter(|version| !version.is_empty());
+    report.uptime_secs = upload.uptime;
+    report.panic_reason = limit_text(upload.panic_reason);
+    report.backtrace = limit_text(upload.backtrace);
+    report.core_dump_size = core_dump.as_ref().map(Vec::len);
+    Ok((report, core_dump))
+}
+
+pub fn check_core_dump_size(core_dump: Option<&[u8]>) -> Result<(), String> {
+    match core_dump {
+        Some(dump) if dump.len() > MAX_CORE_DUMP_BYTES => Err(format!("Core dump exceeds {} bytes", MAX_CORE_DUMP_BYTES)),
+        _ => Ok(()),
+    }
+}
+
+/// Upload times per device within the last hour
+#[derive(Debug, Default)]
+pub struct UploadLimiter {
+    uploads: Mutex<HashMap<String, VecDeque<Instant>>>,
+}
+
+impl UploadLimiter {
+    /// Count an upload of the device; Err with the time until the next one is accepted
+    pub fn check(&self, device_id: &str, now: Instant) -> Result<(), Duration> {
+        let mut uploads = self.uploads.lock().unwrap_or_else(|e| e.into_inner());
+        uploads.retain(|_, times| {
+            while times.front().is_some_and(|at| now.duration_since(*at) >= UPLOAD_WINDOW) {
+                times.pop_front();
+            }
+            !times.is_empty()
+        });
+
+        let times = uploads.entry(device_id.to_string()).or_default();
+        if times.len() >= MAX_UPLOADS_PER_HOUR {
+            return Err(UPLOAD_WINDOW.saturating_sub(now.duration_since(times[0])));
+        }
+        times.push_back(now);
+        Ok(())
+    }
+}
+
+/// Count an upload against the per-device limit
+pub fn check_upload_rate(device_id: &str) -> Result<(), Duration> {
+    UPLOAD_LIMITER.check(device_id, Instant::now())
+}
+
+/// Reset reason fields of a hello message
+#[derive(Debug, Clone, PartialEq)]
+pub struct HelloReset {
+    pub reset_reason: ResetReason,
+    pub firmware_version: Option<String>,
+    pub uptime_secs: Option<u64>,
+}
+
+impl HelloReset {
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.contains("resetReason") && !message.contains("reset_reason") {
+            return None;
+        }
+        let value: Value = serde_json::from_str(message).ok()?;
+        let reset_reason = ResetReason::parse(value.get("resetReason").or_else(|| value.get("reset_reason"))?)?;
+        Some(HelloReset {
+            reset_reason,
+            firmware_version: value.get("firmwareVersion").and_then(Value::as_str).map(str::to_string),
+            uptime_secs: value.get("uptime").and_then(Value::as_u64),
+        })
+    }
+}
+
+/// Boots already reported per device, so repeated hellos of one boot are stored once
+#[derive(Debug, Default)]
+struct BootTracker {
+    /// Boot time computed from the hello's uptime (None: the hello carried no uptime)
+    boots: HashMap<String, Option<i64>>,
+}
+
+impl BootTracker {
+    fn is_new_boot(&mut self, device_id: &str, boot_ms: Option<i64>) -> bool {
+        let same_boot = match (self.boots.get(device_id), boot_ms) {
+            (None, _) => false,
+            (Some(Some(previous)), Some(boot)) => (previous - boot).abs() < SAME_BOOT_TOLERANCE_MS,
+            // Without uptimes a repeated hello on the same link counts as the same boot
+            (Some(_), _) => true,
+        };
+        self.boots.insert(device_id.to_string(), boot_ms);
+        !same_boot
+    }
+
+    fn link_down(&mut self, device_id: &str) {
+        self.boots.remove(device_id);
+    }
+}
+
+/// Follows the event feed and stores crash resets announced in hello messages
+pub struct CrashReportCollector {
+    db: Arc<DatabaseManager>,
+}
+
+impl CrashReportCollector {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self { db }
+    }
+
+    pub fn start(self, device_store: SharedDeviceStore) {
+        let mut feed = device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut boots = BootTracker::default();
+            loop {
+                match feed.recv().await {
+                    Ok(feed_event) => self.handle_event(&mut boots, &feed_event.device_id, &feed_event.event, feed_event.timestamp).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Crash report collector lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle_event(&self, boots: &mut BootTracker, device_id: &str, event: &DeviceEvent, timestamp: i64) {
+        let message = match event {
+            DeviceEvent::DeviceConnectionStatus { connected: false, .. } => {
+                boots.link_down(device_id);
+                return;
+            }
+            DeviceEvent::DeviceUdpBroadcast { message, .. } => message,
+            _ => return,
+        };
+        let Some(hello) = HelloReset::parse(message) else { return };
+        let boot_ms = hello.uptime_secs.map(|secs| timestamp - secs as i64 * 1000);
+        if !boots.is_new_boot(device_id, boot_ms) || !hello.reset_reason.is_crash() {
+            return;
+        }
+
+        let mut report = CrashReport::new(device_id, hello.reset_reason, "hello");
+        report.firmware_version = hello.firmware_version;
+        info!("Device {} restarted after {} reset", device_id, hello.reset_reason.as_str());
+        if let Err(e) = self.db.insert_crash_report(&report, None).await {
+            warn!("Failed to store crash report of device {}: {}", device_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_reset_reasons_uploads_and_boot_dedup() {
+        assert_eq!(ResetReason::parse(&json!("ESP_RST_BROWNOUT")), Some(ResetReason::Brownout));
+        assert_eq!(ResetReason::parse(&json!(6)), Some(ResetReason::TaskWatchdog));
+        assert_eq!(ResetReason::parse(&json!("sw")), Some(ResetReason::Software));
+        assert_eq!(ResetReason::parse(&json!("meteor")), None);
+        assert!(ResetReason::Brownout.is_crash() && !ResetReason::PowerOn.is_crash());
+
+        let hello = HelloReset::parse(r#"{"deviceName":"Lamp","resetReason":"brownout","uptime":3}"#).unwrap();
+        assert_eq!((hello.reset_reason, hello.uptime_secs), (ResetReason::Brownout, Some(3)));
+        assert!(HelloReset::parse(r#"{"deviceName":"Lamp"}"#).is_none());
+
+        let upload = CrashReportUpload { core_dump: Some("AAEC".to_string()), backtrace: Some(" ".to_string()), ..Default::default() };
+        let (report, dump) = report_from_upload("dev-1", upload).unwrap();
+        assert_eq!((report.reset_reason, report.core_dump_size, report.backtrace), (ResetReason::Panic, Some(3), None));
+        assert_eq!(dump.unwrap(), [0, 1, 2]);
+        assert!(report_from_upload("dev-1", CrashReportUpload { core_dump: Some("%%".to_string()), ..Default::default() }).is_err());
+
+        let mut boots = BootTracker::default();
+        assert!(boots.is_new_boot("dev-1", Some(100_000)));
+        assert!(!boots.is_new_boot("dev-1", Some(104_000)), "same boot, reported again");
+        assert!(boots.is_new_boot("dev-1", Some(500_000)));
+        assert!(boots.is_new_boot("dev-2", None));
+        assert!(!boots.is_new_boot("dev-2", None));
+        boots.link_down("dev-2");
+        assert!(boots.is_new_boot("dev-2", None));
+    }
+
+    #[test]
+    fn test_upload_limiter() {
+        let limiter = UploadLimiter::default();
+        let start = Instant::now();
+        for _ in 0..MAX_UPLOADS_PER_HOUR {
+            assert!(limiter.check("dev-1", start).is_ok());
+        }
+        let wait = limiter.check("dev-1", start + Duration::from_secs(60)).unwrap_err();
+        assert_eq!(wait, UPLOAD_WINDOW - Duration::from_secs(60));
+
+        // Other devices have their own budget; the window frees up again
+        assert!(limiter.check("dev-2", start).is_ok());
+        assert!(limiter.check("dev-1", start + UPLOAD_WINDOW).is_ok());
+    }
+}
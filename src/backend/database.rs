@@ -8,6 +8,24 @@ use uuid::Uuid;
 use bcrypt::{hash, verify, DEFAULT_COST};
 use serde::{Deserialize, Serialize};
 use std::fs;
+use crate::device_types::{ConnectionSettings, DeviceTlsConfig, TcpScanConfig};
+use crate::mdns_discovery::MdnsServiceConfig;
+use crate::device_discovery::DiscoveryAgingConfig;
+use crate::device_lifecycle::LifecycleState;
+use crate::device_supervisor::ReconnectPolicy;
+use crate::device_capabilities::{CapabilityDiff, DeviceCapabilities, StoredCapabilities};
+use crate::crash_reports::{CrashReport, ReasonCount, ResetReason};
+use crate::recordings::{RecordedEvent, Recording};
+use crate::variable_snapshots::DeviceSnapshot;
+use crate::activity::{AuditAction, AuditEntry};
+use crate::device_availability::AvailabilityState;
+use crate::enrollment::EnrollmentToken;
+use crate::secrets;
+use crate::command_templates::TemplateStep;
+use crate::device_profiles::{DeviceProfile, VariableSpec};
+use crate::organizations::{OrgMember, OrgRole, Organization};
+use crate::permissions::PermissionResolver;
+use std::collections::BTreeMap;
 
 // ============================================================================
 // DATABASE STRUCTS
@@ -43,21 +61,35 @@ pub struct Device {
     pub alias: Option<String>, // User-defined display name
     pub owner_id: String,
     pub ip_address: Option<String>,
-    pub status: DeviceStatus,
+    pub status: LifecycleState, // Last lifecycle state (written by device_lifecycle)
     pub maintenance_mode: bool,
     pub firmware_version: Option<String>,
     pub last_seen: DateTime<Utc>,
     pub created_at: DateTime<Utc>,
     pub connection_type: String, // "tcp" or "uart"
+    pub notes: Option<String>, // Free-form operator notes
+    pub tags: Vec<String>,     // Labels like "room 204" for filtering
+    pub profile_id: Option<String>, // Device type profile (see device_profiles.rs)
+    pub org_id: Option<String>, // Organization the device belongs to (see organizations.rs)
+    pub default_permission: Option<String>, // Permission of plain org members without a direct one (see permissions.rs)
+    pub location: Option<DeviceLocation>, // Physical placement in the lab (floor plan / map view)
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub enum DeviceStatus {
-    Online,
-    Offline,
-    Error,
-    Updating,
-    Maintenance,
+/// Where a device physically sits; every field is optional
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct DeviceLocation {
+    pub building: Option<String>,
+    pub room: Option<String>,
+    /// Bench, rack or shelf inside the room
+    pub bench: Option<String>,
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+impl DeviceLocation {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -65,6 +97,299 @@ pub struct DevicePermission {
     pub device_id: String,
     pub user_id: String,
     pub permission: String,
+    /// Grant ends at this time (None = permanent, see permission_expiry.rs)
+    pub expires_at: Option<DateTime<Utc>>,
+}
+
+/// Named set of devices for bulk operations
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceGroup {
+    pub id: String,
+    pub name: String,
+    pub owner_id: String,
+    /// Organization whose members share the group (see organizations.rs)
+    pub org_id: Option<String>,
+    pub device_ids: Vec<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Outbound webhook of a user (see webhooks.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    pub id: String,
+    pub owner_id: String,
+    pub url: String,
+    /// HMAC key for the X-Webhook-Signature header; only returned on create
+    #[serde(skip_serializing)]
+    pub secret: String,
+    /// Subscribed event types, e.g. "device.offline"
+    pub events: Vec<String>,
+    /// Only events of these devices; empty = all devices
+    pub device_ids: Vec<String>,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One delivery of an event to a webhook, including its retries
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub device_id: Option<String>,
+    /// pending, success or failed
+    pub status: String,
+    pub attempts: u32,
+    /// HTTP status of the last attempt
+    pub response_status: Option<u16>,
+    pub error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Alert rule of a user on one device (condition syntax: see alerts.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    pub owner_id: String,
+    pub device_id: String,
+    pub name: String,
+    /// e.g. "variable temp > 80 for 30s", "device offline > 5m"
+    pub condition: String,
+    /// Send alert.triggered/alert.resolved to the owner's webhooks
+    pub notify_webhook: bool,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Device-to-device routing rule of a user (trigger syntax: see routing.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RoutingRule {
+    pub id: String,
+    pub owner_id: String,
+    pub name: String,
+    pub source_device_id: String,
+    /// e.g. "variable button == 1", "variable temp changes", "device offline"
+    pub trigger: String,
+    pub target_device_id: String,
+    /// Same JSON as POST /api/devices/:id/command, "{value}" is replaced by the triggering value
+    pub command: serde_json::Value,
+    /// Commands per minute, further triggers are skipped
+    pub max_per_minute: u32,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+/// One firing of an alert rule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub id: String,
+    pub rule_id: String,
+    pub rule_name: String,
+    pub owner_id: String,
+    pub device_id: String,
+    /// firing or resolved
+    pub state: String,
+    pub message: String,
+    /// Variable value that triggered the alert
+    pub value: Option<f64>,
+    pub triggered_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+/// Notification settings of a user; users without a row get `NotificationPreferences::new`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NotificationPreferences {
+    pub user_id: String,
+    /// Email when one of the user's alert rules fires
+    pub email_alerts: bool,
+    /// Also email when the alert is resolved again
+    pub email_alert_resolved: bool,
+}
+
+impl NotificationPreferences {
+    pub fn new(user_id: &str) -> Self {
+        Self {
+            user_id: user_id.to_string(),
+            email_alerts: true,
+            email_alert_resolved: true,
+        }
+    }
+}
+
+/// Entry of the outgoing email queue (see email.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueuedEmail {
+    pub id: String,
+    pub recipient: String,
+    pub subject: String,
+    pub body: String,
+    /// pending, sent or failed
+    pub status: String,
+    pub attempts: u32,
+    pub next_attempt_at: DateTime<Utc>,
+    pub last_error: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub sent_at: Option<DateTime<Utc>>,
+}
+
+/// Recurring command to a device or device group (see schedules.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Schedule {
+    pub id: String,
+    pub owner_id: String,
+    pub name: String,
+    /// Normalized schedule text, e.g. "every day at 18:00"
+    pub schedule: String,
+    /// Target: exactly one of device_id and group_id is set
+    pub device_id: Option<String>,
+    pub group_id: Option<String>,
+    /// Same JSON as POST /api/devices/:id/command
+    pub command: serde_json::Value,
+    pub enabled: bool,
+    /// Run once after downtime if a run was missed
+    pub catch_up: bool,
+    /// None = disabled or never matches again
+    pub next_run_at: Option<DateTime<Utc>>,
+    pub last_run_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Execution history entry of a schedule
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduleRun {
+    pub id: String,
+    pub schedule_id: String,
+    /// scheduled, catch_up or manual
+    pub trigger: String,
+    /// success, partial, failed or skipped
+    pub status: String,
+    pub scheduled_for: Option<DateTime<Utc>>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub total: u32,
+    pub succeeded: u32,
+    pub error: Option<String>,
+    /// Per-device results of the bulk command
+    pub results: serde_json::Value,
+}
+
+/// Automation script on the events of one device (see scripts.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Script {
+    pub id: String,
+    pub owner_id: String,
+    pub name: String,
+    /// Device whose events trigger the script
+    pub device_id: String,
+    /// Event types that trigger it (empty = all)
+    pub events: Vec<String>,
+    /// Rhai source
+    pub source: String,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Named command sequence of a user (see command_templates.rs)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandTemplate {
+    pub id: String,
+    pub owner_id: String,
+    /// Unique per owner, used in POST /api/devices/:id/run-template/:name
+    pub name: String,
+    pub description: Option<String>,
+    pub steps: Vec<TemplateStep>,
+    /// Default values of the {{placeholders}}
+    pub parameters: BTreeMap<String, serde_json::Value>,
+    /// Run the remaining steps after a failed one
+    pub continue_on_error: bool,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Log line of a script (output, sent commands, errors)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScriptLog {
+    pub id: i64,
+    pub script_id: String,
+    pub timestamp: DateTime<Utc>,
+    /// debug, info, warn or error
+    pub level: String,
+    pub message: String,
+}
+
+/// Sort column of the device list
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DeviceSort {
+    #[default]
+    CreatedAt,
+    LastSeen,
+    Name,
+}
+
+impl DeviceSort {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "created_at" => Some(Self::CreatedAt),
+            "last_seen" => Some(Self::LastSeen),
+            "name" => Some(Self::Name),
+            _ => None,
+        }
+    }
+
+    fn column(self) -> &'static str {
+        match self {
+            Self::CreatedAt => "d.created_at",
+            Self::LastSeen => "d.last_seen",
+            Self::Name => "COALESCE(d.alias, d.name) COLLATE NOCASE",
+        }
+    }
+}
+
+/// Filter, sort order and page of a device list query
+#[derive(Debug, Clone, Default)]
+pub struct DeviceListQuery {
+    /// Only devices of this connection type ("tcp", "uart", ...)
+    pub connection_type: Option<String>,
+    /// Substring of name, alias or MAC address (case-insensitive)
+    pub search: Option<String>,
+    /// Only devices carrying this tag (exact match)
+    pub tag: Option<String>,
+    /// Only devices in this building / room (exact match on the stored location)
+    pub building: Option<String>,
+    pub room: Option<String>,
+    /// Some(true): only `connected_ids`, Some(false): all but `connected_ids`
+    pub connected: Option<bool>,
+    /// Live connection state comes from the device manager, not from the DB
+    pub connected_ids: Vec<String>,
+    pub sort: DeviceSort,
+    pub descending: bool,
+    /// Devices of this organization (membership is checked by the caller); None = devices without one
+    pub org_id: Option<String>,
+    /// Page size (None = all matching devices)
+    pub limit: Option<u32>,
+    pub offset: u32,
+}
+
+/// Current time in the format of sortable_timestamp, for comparisons with expires_at in SQL
+const NOW_SQL: &str = "strftime('%Y-%m-%dT%H:%M:%fZ', 'now')";
+
+/// FROM/WHERE part selecting group grants (gp) for group members (gm): a grant only reaches
+/// devices the group owner manages, directly (M, O) or as organization admin
+const GROUP_GRANTS_SQL: &str = "FROM device_group_permissions gp \
+    INNER JOIN device_group_members gm ON gm.group_id = gp.group_id \
+    INNER JOIN device_groups g ON g.id = gp.group_id \
+    WHERE (EXISTS (SELECT 1 FROM device_permissions op WHERE op.device_id = gm.device_id AND op.user_id = g.owner_id AND op.permission IN ('M', 'O') \
+    AND (op.expires_at IS NULL OR op.expires_at > strftime('%Y-%m-%dT%H:%M:%fZ', 'now'))) \
+    OR EXISTS (SELECT 1 FROM devices od INNER JOIN organization_members om ON om.org_id = od.org_id \
+    WHERE od.mac_address = gm.device_id AND om.user_id = g.owner_id AND om.role IN ('admin', 'owner')))";
+
+/// Highest group grant of a user on device d, NULL without one
+fn push_group_permission(builder: &mut sqlx::QueryBuilder<'_, sqlx::Sqlite>, user_id: &str) {
+    builder.push(format!("(SELECT gp.permission {} AND gm.device_id = d.mac_address AND gp.user_id = ", GROUP_GRANTS_SQL));
+    builder.push_bind(user_id.to_string());
+    builder.push(" ORDER BY instr('RWVM', gp.permission) DESC LIMIT 1)");
 }
 
 impl DatabaseUser {
@@ -94,12 +419,18 @@ impl Device {
             alias: None, // Initially no alias set
             owner_id,
             ip_address: None,
-            status: DeviceStatus::Offline,
+            status: LifecycleState::Adopted,
             maintenance_mode: false,
             firmware_version: None,
             last_seen: now,
             created_at: now,
             connection_type: "tcp".to_string(), // Default to TCP
+            notes: None,
+            tags: Vec::new(),
+            profile_id: None,
+            org_id: None,
+            default_permission: None,
+            location: None,
         }
     }
 
@@ -111,16 +442,22 @@ impl Device {
             alias: None, // Initially no alias set
             owner_id,
             ip_address: None,
-            status: DeviceStatus::Offline,
+            status: LifecycleState::Adopted,
             maintenance_mode: false,
             firmware_version: None,
             last_seen: now,
             created_at: now,
             connection_type: "uart".to_string(),
+            notes: None,
+            tags: Vec::new(),
+            profile_id: None,
+            org_id: None,
+            default_permission: None,
+            location: None,
         }
     }
 
-    pub fn update_status(&mut self, status: DeviceStatus, ip_address: Option<String>) {
+    pub fn update_status(&mut self, status: LifecycleState, ip_address: Option<String>) {
         self.status = status;
         self.ip_address = ip_address;
         self.last_seen = Utc::now();
@@ -137,7 +474,7 @@ pub struct DatabaseManager {
 }
 
 impl DatabaseManager {
-    #[cfg(test)]
+    /// In-memory database for tests (unit tests and create_test_app)
     pub async fn new_memory() -> Result<Self, Box<dyn std::error::Error>> {
         let pool = SqlitePool::connect("sqlite::memory:").await?;
         let db_manager = Self { pool };
@@ -145,12 +482,15 @@ impl DatabaseManager {
         Ok(db_manager)
     }
 
-    pub async fn new() -> Result<Self, Box<dyn std::error::Error>> {
+    /// Open (or create) the SQLite database at `path`
+    pub async fn open(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
         // Erstelle SQLite-Datenbankdatei wenn sie nicht existiert
-        std::fs::create_dir_all("data").ok();
-        
-        let database_url = "sqlite:data/users.db?mode=rwc";
-        let pool = SqlitePool::connect(database_url).await?;
+        if let Some(parent) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(parent).ok();
+        }
+
+        let database_url = format!("sqlite:{}?mode=rwc", path);
+        let pool = SqlitePool::connect(&database_url).await?;
         
         let db_manager = Self { pool };
         
@@ -208,6 +548,8 @@ impl DatabaseManager {
                 last_seen TEXT NOT NULL,
                 created_at TEXT NOT NULL,
                 connection_type TEXT NOT NULL DEFAULT 'tcp',
+                notes TEXT,
+                tags TEXT NOT NULL DEFAULT '[]',
                 FOREIGN KEY (owner_id) REFERENCES users (id)
             )
             "#
@@ -231,14 +573,15 @@ impl DatabaseManager {
         .execute(&self.pool)
         .await?;
 
-        // UART Settings Tabelle erstellen
+        // Device TLS Settings Tabelle erstellen (TLS für den TCP-Kanal pro Device)
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS uart_settings (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                port TEXT,
-                baud_rate INTEGER NOT NULL DEFAULT 115200,
-                auto_connect BOOLEAN NOT NULL DEFAULT FALSE,
+            CREATE TABLE IF NOT EXISTS device_tls_settings (
+                device_id TEXT PRIMARY KEY,
+                ca_cert_path TEXT NOT NULL,
+                server_name TEXT,
+                client_cert_path TEXT,
+                client_key_path TEXT,
                 updated_at TEXT NOT NULL
             )
             "#
@@ -246,22 +589,25 @@ impl DatabaseManager {
         .execute(&self.pool)
         .await?;
 
-        // Insert default UART settings if not exists
+        // Identity-Secrets pro Device (Challenge-Response nach dem TCP-Connect, siehe device_identity.rs)
         sqlx::query(
             r#"
-            INSERT OR IGNORE INTO uart_settings (id, port, baud_rate, auto_connect, updated_at)
-            VALUES (1, NULL, 115200, FALSE, datetime('now'))
+            CREATE TABLE IF NOT EXISTS device_identity_secrets (
+                device_id TEXT PRIMARY KEY,
+                secret TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
             "#
         )
         .execute(&self.pool)
         .await?;
 
-        // Debug Settings Tabelle erstellen
+        // Reconnect-Policy pro Device (JSON, siehe device_supervisor::ReconnectPolicy)
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS debug_settings (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                max_debug_messages INTEGER NOT NULL DEFAULT 200,
+            CREATE TABLE IF NOT EXISTS device_connection_policies (
+                device_id TEXT PRIMARY KEY,
+                policy TEXT NOT NULL,
                 updated_at TEXT NOT NULL
             )
             "#
@@ -269,22 +615,26 @@ impl DatabaseManager {
         .execute(&self.pool)
         .await?;
 
-        // Insert default Debug settings if not exists
+        // Ports, Timeouts und Keep-Alive pro Device (JSON, siehe device_types::ConnectionSettings)
         sqlx::query(
             r#"
-            INSERT OR IGNORE INTO debug_settings (id, max_debug_messages, updated_at)
-            VALUES (1, 200, datetime('now'))
+            CREATE TABLE IF NOT EXISTS device_connection_settings (
+                device_id TEXT PRIMARY KEY,
+                settings TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
             "#
         )
         .execute(&self.pool)
         .await?;
 
-        // Create GitHub settings table (single-row config)
+        // Zuletzt gemeldete Capabilities pro Device (JSON, siehe device_capabilities)
         sqlx::query(
             r#"
-            CREATE TABLE IF NOT EXISTS github_settings (
-                id INTEGER PRIMARY KEY CHECK (id = 1),
-                token TEXT,
+            CREATE TABLE IF NOT EXISTS device_capabilities (
+                device_id TEXT PRIMARY KEY,
+                capabilities TEXT NOT NULL,
+                last_changes TEXT,
                 updated_at TEXT NOT NULL
             )
             "#
@@ -292,808 +642,4267 @@ impl DatabaseManager {
         .execute(&self.pool)
         .await?;
 
+        // Absturzberichte der Geräte (Reset-Grund, Backtrace, optional Core-Dump, siehe crash_reports)
         sqlx::query(
             r#"
-            INSERT OR IGNORE INTO github_settings (id, token, updated_at)
-            VALUES (1, NULL, datetime('now'))
+            CREATE TABLE IF NOT EXISTS crash_reports (
+                id TEXT PRIMARY KEY,
+                device_id TEXT NOT NULL,
+                reset_reason TEXT NOT NULL,
+                source TEXT NOT NULL,
+                firmware_version TEXT,
+                uptime_secs INTEGER,
+                panic_reason TEXT,
+                backtrace TEXT,
+                core_dump BLOB,
+                core_dump_size INTEGER,
+                created_at TEXT NOT NULL
+            )
             "#
         )
         .execute(&self.pool)
         .await?;
 
-        // Migration: Add owner/repo/asset columns to github_settings if not present
-        for col in &["owner", "repo", "asset"] {
-            let _ = sqlx::query(&format!(
-                "ALTER TABLE github_settings ADD COLUMN {} TEXT", col
-            ))
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_crash_reports_device ON crash_reports (device_id, created_at)")
             .execute(&self.pool)
-            .await;
-        }
+            .await?;
 
-        // Migration: Add connection_type column if it doesn't exist (for existing databases)
-        let migration_result = sqlx::query(
+        // Aufzeichnungen von Geräte-Sitzungen (Ereignisse als JSON mit Zeitversatz)
+        sqlx::query(
             r#"
-            ALTER TABLE devices ADD COLUMN connection_type TEXT NOT NULL DEFAULT 'tcp'
+            CREATE TABLE IF NOT EXISTS device_recordings (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                started_at TEXT NOT NULL,
+                duration_ms INTEGER NOT NULL,
+                event_count INTEGER NOT NULL,
+                truncated BOOLEAN NOT NULL DEFAULT 0,
+                events TEXT NOT NULL
+            )
             "#
         )
         .execute(&self.pool)
-        .await;
-
-        // Ignore error if column already exists
-        match migration_result {
-            Ok(_) => tracing::info!("Database migration: Added connection_type column to devices"),
-            Err(e) => {
-                let error_msg = e.to_string();
-                if error_msg.contains("duplicate column") || error_msg.contains("already exists") {
-                    tracing::debug!("Database migration: connection_type column already exists");
-                } else {
-                    tracing::warn!("Database migration warning: {}", error_msg);
-                }
-            }
-        }
+        .await?;
 
-        // Migration: Add alias column if it doesn't exist (for existing databases)
-        let migration_result = sqlx::query(
+        // Gespeicherte Variablenstände von Geräten (Variablen als JSON)
+        sqlx::query(
             r#"
-            ALTER TABLE devices ADD COLUMN alias TEXT
+            CREATE TABLE IF NOT EXISTS device_variable_snapshots (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                variables TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
             "#
         )
         .execute(&self.pool)
-        .await;
+        .await?;
 
-        // Ignore error if column already exists
-        match migration_result {
-            Ok(_) => tracing::info!("Database migration: Added alias column to devices"),
-            Err(e) => {
-                let error_msg = e.to_string();
-                if error_msg.contains("duplicate column") || error_msg.contains("already exists") {
-                    tracing::debug!("Database migration: alias column already exists");
-                } else {
-                    tracing::warn!("Database migration warning: {}", error_msg);
-                }
-            }
-        }
+        // Audit-Log pro Gerät (Quelle des Aktivitäts-Feeds, Details als JSON)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id TEXT NOT NULL,
+                actor_id TEXT NOT NULL,
+                action TEXT NOT NULL,
+                details TEXT NOT NULL DEFAULT '{}',
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_device_audit_log_device ON device_audit_log (device_id, id)")
+            .execute(&self.pool)
+            .await?;
 
-        Ok(())
-    }
+        // Erreichbarkeitswechsel pro Gerät (up/down/maintenance/unknown) für die Verfügbarkeitsstatistik
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_availability_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id TEXT NOT NULL,
+                state TEXT NOT NULL,
+                at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_device_availability_events_device ON device_availability_events (device_id, at)")
+            .execute(&self.pool)
+            .await?;
 
-    pub async fn create_user(&self, user: DatabaseUser) -> Result<(), Box<dyn std::error::Error>> {
+        // Letztes Lebenszeichen des Servers (Zeit bis zum Neustart gilt als unbekannt)
         sqlx::query(
-            "INSERT INTO users (id, email, display_name, password_hash, created_at, is_admin) VALUES (?, ?, ?, ?, ?, ?)"
+            r#"
+            CREATE TABLE IF NOT EXISTS availability_heartbeat (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                at TEXT NOT NULL
+            )
+            "#
         )
-        .bind(&user.id)
-        .bind(&user.email)
-        .bind(&user.display_name)
-        .bind(&user.password_hash)
-        .bind(user.created_at.to_rfc3339())
-        .bind(user.is_admin)
         .execute(&self.pool)
         .await?;
 
-        Ok(())
-    }
+        // Enrollment-Tokens: vorab erzeugte Tokens pro Gerät für die automatische Übernahme (nur Hash gespeichert)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS enrollment_tokens (
+                id TEXT PRIMARY KEY,
+                token_hash TEXT NOT NULL UNIQUE,
+                device_id TEXT NOT NULL,
+                owner_id TEXT NOT NULL,
+                org_id TEXT,
+                created_at TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                used_at TEXT
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
 
-    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<DatabaseUser>, Box<dyn std::error::Error>> {
-        let row = sqlx::query("SELECT * FROM users WHERE email = ?")
-            .bind(email)
-            .fetch_optional(&self.pool)
+        // Chat-Nachrichten und Erwähnungen pro Gerät (Event als JSON, begrenzte Historie)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_chat_messages (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                device_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_device_chat_messages_device ON device_chat_messages (device_id, id)")
+            .execute(&self.pool)
             .await?;
 
-        match row {
-            Some(row) => {
-                let created_at_str: String = row.get("created_at");
-                let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-                
-                Ok(Some(DatabaseUser {
-                    id: row.get("id"),
-                    email: row.get("email"),
-                    display_name: row.get("display_name"),
+        // Routing-Regeln: Ereignis eines Geräts löst Befehl an ein anderes aus
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS routing_rules (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                source_device_id TEXT NOT NULL,
+                trigger TEXT NOT NULL,
+                target_device_id TEXT NOT NULL,
+                command TEXT NOT NULL,
+                max_per_minute INTEGER NOT NULL DEFAULT 60,
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Device Groups Tabellen erstellen (für Bulk-Kommandos)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_groups (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                owner_id TEXT NOT NULL,
+                org_id TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_group_members (
+                group_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                position INTEGER NOT NULL,
+                PRIMARY KEY (group_id, device_id),
+                FOREIGN KEY (group_id) REFERENCES device_groups (id) ON DELETE CASCADE
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Berechtigungen, die über eine Device-Gruppe an deren Geräte vererbt werden
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_group_permissions (
+                group_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                permission TEXT NOT NULL CHECK (permission IN ('R', 'W', 'V', 'M')),
+                PRIMARY KEY (group_id, user_id),
+                FOREIGN KEY (group_id) REFERENCES device_groups (id) ON DELETE CASCADE
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Webhooks und Zustell-Log erstellen
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhooks (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                url TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                events TEXT NOT NULL DEFAULT '[]',
+                device_ids TEXT NOT NULL DEFAULT '[]',
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id TEXT PRIMARY KEY,
+                webhook_id TEXT NOT NULL,
+                event TEXT NOT NULL,
+                device_id TEXT,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                response_status INTEGER,
+                error TEXT,
+                created_at TEXT NOT NULL,
+                completed_at TEXT,
+                FOREIGN KEY (webhook_id) REFERENCES webhooks (id) ON DELETE CASCADE
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_webhook_deliveries_webhook ON webhook_deliveries (webhook_id, created_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Alert-Regeln und ausgelöste Alerts erstellen
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS alert_rules (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                condition TEXT NOT NULL,
+                notify_webhook BOOLEAN NOT NULL DEFAULT 0,
+                enabled BOOLEAN NOT NULL DEFAULT 1,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS alerts (
+                id TEXT PRIMARY KEY,
+                rule_id TEXT NOT NULL,
+                rule_name TEXT NOT NULL,
+                owner_id TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                state TEXT NOT NULL,
+                message TEXT NOT NULL,
+                value REAL,
+                triggered_at TEXT NOT NULL,
+                resolved_at TEXT
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_alerts_owner ON alerts (owner_id, triggered_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Benachrichtigungs-Einstellungen pro User
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS notification_preferences (
+                user_id TEXT PRIMARY KEY,
+                email_alerts BOOLEAN NOT NULL DEFAULT TRUE,
+                email_alert_resolved BOOLEAN NOT NULL DEFAULT TRUE
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Ausgehende Emails (Warteschlange, übersteht Neustarts und SMTP-Ausfälle)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS email_queue (
+                id TEXT PRIMARY KEY,
+                recipient TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                body TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at TEXT NOT NULL,
+                last_error TEXT,
+                created_at TEXT NOT NULL,
+                sent_at TEXT
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_email_queue_due ON email_queue (status, next_attempt_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Zeitpläne für wiederkehrende Befehle
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS schedules (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                device_id TEXT,
+                group_id TEXT,
+                command TEXT NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                catch_up BOOLEAN NOT NULL DEFAULT TRUE,
+                next_run_at TEXT,
+                last_run_at TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedules_next_run ON schedules (enabled, next_run_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Ausführungs-Historie der Zeitpläne
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS schedule_runs (
+                id TEXT PRIMARY KEY,
+                schedule_id TEXT NOT NULL,
+                trigger TEXT NOT NULL,
+                status TEXT NOT NULL,
+                scheduled_for TEXT,
+                started_at TEXT NOT NULL,
+                finished_at TEXT,
+                total INTEGER NOT NULL DEFAULT 0,
+                succeeded INTEGER NOT NULL DEFAULT 0,
+                error TEXT,
+                results TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_schedule_runs_schedule ON schedule_runs (schedule_id, started_at)")
+            .execute(&self.pool)
+            .await?;
+
+        // Automatisierungs-Skripte auf Geräte-Events
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS scripts (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                device_id TEXT NOT NULL,
+                events TEXT NOT NULL,
+                source TEXT NOT NULL,
+                enabled BOOLEAN NOT NULL DEFAULT TRUE,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Organisationen (Arbeitsbereiche mehrerer Kurse) und ihre Mitglieder
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS organizations (
+                id TEXT PRIMARY KEY,
+                name TEXT NOT NULL,
+                slug TEXT NOT NULL UNIQUE,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS organization_members (
+                org_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                role TEXT NOT NULL,
+                joined_at TEXT NOT NULL,
+                PRIMARY KEY (org_id, user_id),
+                FOREIGN KEY (org_id) REFERENCES organizations (id) ON DELETE CASCADE
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Geräteprofile (Variablen mit Wertebereichen und Start-Optionen eines Gerätetyps)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS device_profiles (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                variables TEXT NOT NULL DEFAULT '[]',
+                start_options TEXT NOT NULL DEFAULT '[]',
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Befehlsvorlagen (benannte Befehlsfolgen mit Parametern)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS command_templates (
+                id TEXT PRIMARY KEY,
+                owner_id TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT,
+                steps TEXT NOT NULL,
+                parameters TEXT NOT NULL DEFAULT '{}',
+                continue_on_error BOOLEAN NOT NULL DEFAULT FALSE,
+                created_at TEXT NOT NULL,
+                updated_at TEXT NOT NULL,
+                UNIQUE (owner_id, name)
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Log-Ausgaben der Skripte
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS script_logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                script_id TEXT NOT NULL,
+                timestamp TEXT NOT NULL,
+                level TEXT NOT NULL,
+                message TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query("CREATE INDEX IF NOT EXISTS idx_script_logs_script ON script_logs (script_id, id)")
+            .execute(&self.pool)
+            .await?;
+
+        // Passwort-Reset-Tokens (nur der SHA-256-Hash wird gespeichert)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS password_resets (
+                token_hash TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                expires_at TEXT NOT NULL,
+                used_at TEXT,
+                created_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Widerrufene Sessions gelöschter User (JWTs bleiben bis expires_at ungültig, auch nach einem Neustart)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS revoked_sessions (
+                user_id TEXT PRIMARY KEY,
+                expires_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // UART Settings Tabelle erstellen
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS uart_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                port TEXT,
+                baud_rate INTEGER NOT NULL DEFAULT 115200,
+                auto_connect BOOLEAN NOT NULL DEFAULT FALSE,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Insert default UART settings if not exists
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO uart_settings (id, port, baud_rate, auto_connect, updated_at)
+            VALUES (1, NULL, 115200, FALSE, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Debug Settings Tabelle erstellen
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS debug_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                max_debug_messages INTEGER NOT NULL DEFAULT 200,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Insert default Debug settings if not exists
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO debug_settings (id, max_debug_messages, updated_at)
+            VALUES (1, 200, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // UDP Settings Tabelle erstellen (kommagetrennte Listener-Ports)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS udp_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                listen_ports TEXT NOT NULL DEFAULT '3232',
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Insert default UDP settings if not exists
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO udp_settings (id, listen_ports, updated_at)
+            VALUES (1, '3232', datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // mDNS Settings Tabelle erstellen (JSON-Liste der Service-Typen, NULL = Standard)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS mdns_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                services TEXT,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO mdns_settings (id, services, updated_at)
+            VALUES (1, NULL, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Discovery Settings Tabelle erstellen (Alterung der Discovery-Ergebnisse)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS discovery_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                stale_after_seconds INTEGER NOT NULL DEFAULT 300,
+                expire_after_seconds INTEGER NOT NULL DEFAULT 1800,
+                remove_expired BOOLEAN NOT NULL DEFAULT TRUE,
+                mdns_reresolve_seconds INTEGER NOT NULL DEFAULT 120,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO discovery_settings (id, updated_at)
+            VALUES (1, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // TCP Scan Settings Tabelle erstellen (opt-in Subnetz-Scan)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS tcp_scan_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                enabled BOOLEAN NOT NULL DEFAULT FALSE,
+                cidr TEXT NOT NULL DEFAULT '',
+                port INTEGER NOT NULL DEFAULT 3232,
+                concurrency INTEGER NOT NULL DEFAULT 32,
+                timeout_ms INTEGER NOT NULL DEFAULT 300,
+                interval_seconds INTEGER NOT NULL DEFAULT 300,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO tcp_scan_settings (id, updated_at)
+            VALUES (1, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Create GitHub settings table (single-row config)
+        sqlx::query(
+            r#"
+            CREATE TABLE IF NOT EXISTS github_settings (
+                id INTEGER PRIMARY KEY CHECK (id = 1),
+                token TEXT,
+                updated_at TEXT NOT NULL
+            )
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO github_settings (id, token, updated_at)
+            VALUES (1, NULL, datetime('now'))
+            "#
+        )
+        .execute(&self.pool)
+        .await?;
+
+        // Migration: Add owner/repo/asset columns to github_settings if not present
+        for col in &["owner", "repo", "asset"] {
+            let _ = sqlx::query(&format!(
+                "ALTER TABLE github_settings ADD COLUMN {} TEXT", col
+            ))
+            .execute(&self.pool)
+            .await;
+        }
+
+        // Migration: Add connection_type column if it doesn't exist (for existing databases)
+        let migration_result = sqlx::query(
+            r#"
+            ALTER TABLE devices ADD COLUMN connection_type TEXT NOT NULL DEFAULT 'tcp'
+            "#
+        )
+        .execute(&self.pool)
+        .await;
+
+        // Ignore error if column already exists
+        match migration_result {
+            Ok(_) => tracing::info!("Database migration: Added connection_type column to devices"),
+            Err(e) => {
+                let error_msg = e.to_string();
+                if error_msg.contains("duplicate column") || error_msg.contains("already exists") {
+                    tracing::debug!("Database migration: connection_type column already exists");
+                } else {
+                    tracing::warn!("Database migration warning: {}", error_msg);
+                }
+            }
+        }
+
+        // Migration: Add alias column if it doesn't exist (for existing databases)
+        let migration_result = sqlx::query(
+            r#"
+            ALTER TABLE devices ADD COLUMN alias TEXT
+            "#
+        )
+        .execute(&self.pool)
+        .await;
+
+        // Ignore error if column already exists
+        match migration_result {
+            Ok(_) => tracing::info!("Database migration: Added alias column to devices"),
+            Err(e) => {
+                let error_msg = e.to_string();
+                if error_msg.contains("duplicate column") || error_msg.contains("already exists") {
+                    tracing::debug!("Database migration: alias column already exists");
+                } else {
+                    tracing::warn!("Database migration warning: {}", error_msg);
+                }
+            }
+        }
+
+        // Migration: Add notes, tags, profile_id and org_id columns if they don't exist (for existing databases)
+        let added_columns = [
+            ("devices", "notes", "TEXT"),
+            ("devices", "tags", "TEXT NOT NULL DEFAULT '[]'"),
+            ("devices", "profile_id", "TEXT"),
+            ("devices", "org_id", "TEXT"),
+            ("devices", "default_permission", "TEXT"),
+            ("devices", "location", "TEXT"),
+            ("device_permissions", "expires_at", "TEXT"),
+            ("device_groups", "org_id", "TEXT"),
+            ("users", "handle", "TEXT"),
+        ];
+        for (table, column, definition) in added_columns {
+            let migration_result = sqlx::query(&format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition))
+                .execute(&self.pool)
+                .await;
+
+            match migration_result {
+                Ok(_) => tracing::info!("Database migration: Added {} column to {}", column, table),
+                Err(e) => {
+                    let error_msg = e.to_string();
+                    if error_msg.contains("duplicate column") || error_msg.contains("already exists") {
+                        tracing::debug!("Database migration: {}.{} column already exists", table, column);
+                    } else {
+                        tracing::warn!("Database migration warning: {}", error_msg);
+                    }
+                }
+            }
+        }
+
+        // Handles are optional (NULL) and unique once set
+        sqlx::query("CREATE UNIQUE INDEX IF NOT EXISTS idx_users_handle ON users (handle)")
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Cheap round trip to check that the database answers (readiness probe)
+    pub async fn ping(&self) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("SELECT 1").execute(&self.pool).await?;
+        Ok(())
+    }
+
+    /// Consistent copy of the database into `backups/` next to the database file (VACUUM INTO),
+    /// taken while the server keeps running. Returns the path of the copy.
+    pub async fn backup(&self) -> Result<std::path::PathBuf, Box<dyn std::error::Error>> {
+        let file: String = sqlx::query("PRAGMA database_list")
+            .fetch_all(&self.pool)
+            .await?
+            .iter()
+            .find(|row| row.get::<String, _>("name") == "main")
+            .map(|row| row.get("file"))
+            .unwrap_or_default();
+        if file.is_empty() {
+            return Err("An in-memory database cannot be backed up".into());
+        }
+        let dir = std::path::Path::new(&file).parent().unwrap_or(std::path::Path::new(".")).join("backups");
+        std::fs::create_dir_all(&dir)?;
+        let target = dir.join(format!("users-{}.db", Utc::now().format("%Y%m%d-%H%M%S")));
+        if target.exists() {
+            return Err(format!("{} already exists", target.display()).into());
+        }
+        sqlx::query("VACUUM INTO ?")
+            .bind(target.to_string_lossy().to_string())
+            .execute(&self.pool)
+            .await?;
+        Ok(target)
+    }
+
+    pub async fn create_user(&self, user: DatabaseUser) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            "INSERT INTO users (id, email, display_name, password_hash, created_at, is_admin) VALUES (?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&user.id)
+        .bind(&user.email)
+        .bind(&user.display_name)
+        .bind(&user.password_hash)
+        .bind(user.created_at.to_rfc3339())
+        .bind(user.is_admin)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_user_by_email(&self, email: &str) -> Result<Option<DatabaseUser>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM users WHERE email = ?")
+            .bind(email)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let created_at_str: String = row.get("created_at");
+                let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+                
+                Ok(Some(DatabaseUser {
+                    id: row.get("id"),
+                    email: row.get("email"),
+                    display_name: row.get("display_name"),
+                    password_hash: row.get("password_hash"),
+                    created_at,
+                    is_admin: row.get("is_admin"),
+                }))
+            }
+            None => Ok(None)
+        }
+    }
+
+    pub async fn get_user_by_id(&self, user_id: &str) -> Result<Option<DatabaseUser>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => {
+                let created_at_str: String = row.get("created_at");
+                let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+                
+                Ok(Some(DatabaseUser {
+                    id: row.get("id"),
+                    email: row.get("email"),
+                    display_name: row.get("display_name"),
                     password_hash: row.get("password_hash"),
                     created_at,
                     is_admin: row.get("is_admin"),
                 }))
             }
-            None => Ok(None)
+            None => Ok(None)
+        }
+    }
+
+    pub async fn update_user_display_name(&self, user_id: &str, display_name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE users SET display_name = ? WHERE id = ?")
+            .bind(display_name)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Handle of a user (None = not set)
+    pub async fn get_user_handle(&self, user_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let handle: Option<Option<String>> = sqlx::query_scalar("SELECT handle FROM users WHERE id = ?")
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(handle.flatten())
+    }
+
+    /// User with a handle (handles are stored lowercase)
+    pub async fn get_user_id_by_handle(&self, handle: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let user_id = sqlx::query_scalar("SELECT id FROM users WHERE handle = ?")
+            .bind(handle)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(user_id)
+    }
+
+    /// Set or clear the handle of a user; false if another user holds it
+    pub async fn set_user_handle(&self, user_id: &str, handle: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE users SET handle = ? WHERE id = ?")
+            .bind(handle)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await;
+        match result {
+            Ok(_) => Ok(true),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Replace the password hash of a user (password reset)
+    pub async fn update_user_password(&self, user_id: &str, password_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE users SET password_hash = ? WHERE id = ?")
+            .bind(password_hash)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    pub async fn get_all_users(&self) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM users ORDER BY created_at DESC")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut users = Vec::new();
+        for row in rows {
+            let created_at_str: String = row.get("created_at");
+            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+            
+            users.push(DatabaseUser {
+                id: row.get("id"),
+                email: row.get("email"),
+                display_name: row.get("display_name"),
+                password_hash: row.get("password_hash"),
+                created_at,
+                is_admin: row.get("is_admin"),
+            });
+        }
+
+        Ok(users)
+    }
+
+    pub async fn search_users(&self, query: &str) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
+        let search_pattern = format!("%{}%", query);
+        let rows = sqlx::query("SELECT * FROM users WHERE email LIKE ? OR display_name LIKE ? ORDER BY display_name LIMIT 20")
+            .bind(&search_pattern)
+            .bind(&search_pattern)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut users = Vec::new();
+        for row in rows {
+            let created_at_str: String = row.get("created_at");
+            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+            
+            users.push(DatabaseUser {
+                id: row.get("id"),
+                email: row.get("email"),
+                display_name: row.get("display_name"),
+                password_hash: row.get("password_hash"),
+                created_at,
+                is_admin: row.get("is_admin"),
+            });
+        }
+
+        Ok(users)
+    }
+
+    pub async fn get_users_paginated(&self, offset: i32, limit: i32) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM users ORDER BY display_name LIMIT ? OFFSET ?")
+            .bind(limit)
+            .bind(offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut users = Vec::new();
+        for row in rows {
+            let created_at_str: String = row.get("created_at");
+            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+            
+            users.push(DatabaseUser {
+                id: row.get("id"),
+                email: row.get("email"),
+                display_name: row.get("display_name"),
+                password_hash: row.get("password_hash"),
+                created_at,
+                is_admin: row.get("is_admin"),
+            });
+        }
+
+        Ok(users)
+    }
+
+    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.delete_user_account(user_id, "guest").await?;
+        Ok(())
+    }
+
+    /// Delete a user with everything attached to it in one transaction:
+    /// permissions and device groups are removed, owned devices go to `new_owner_id`
+    /// ("guest" archives them). Returns the IDs of the handed-over devices.
+    pub async fn delete_user_account(&self, user_id: &str, new_owner_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+
+        let owned_devices: Vec<String> = sqlx::query_scalar("SELECT mac_address FROM devices WHERE owner_id = ?")
+            .bind(user_id)
+            .fetch_all(&mut *tx)
+            .await?;
+
+        // Zuerst Permissions löschen
+        sqlx::query("DELETE FROM device_permissions WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Devices des Users übertragen (FK-Constraint: owner_id muss existieren)
+        sqlx::query("UPDATE devices SET owner_id = ? WHERE owner_id = ?")
+            .bind(new_owner_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        if new_owner_id != "guest" {
+            for device_id in &owned_devices {
+                sqlx::query("INSERT OR REPLACE INTO device_permissions (device_id, user_id, permission) VALUES (?, ?, 'O')")
+                    .bind(device_id)
+                    .bind(new_owner_id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        }
+
+        // Device-Gruppen des Users und seine Gruppen-Berechtigungen löschen
+        sqlx::query("DELETE FROM device_group_permissions WHERE user_id = ? OR group_id IN (SELECT id FROM device_groups WHERE owner_id = ?)")
+            .bind(user_id)
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM device_group_members WHERE group_id IN (SELECT id FROM device_groups WHERE owner_id = ?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM device_groups WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Alert-Regeln und Alerts des Users löschen
+        sqlx::query("DELETE FROM alerts WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM alert_rules WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Zeitpläne des Users löschen
+        sqlx::query("DELETE FROM schedule_runs WHERE schedule_id IN (SELECT id FROM schedules WHERE owner_id = ?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM schedules WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Skripte des Users löschen
+        sqlx::query("DELETE FROM script_logs WHERE script_id IN (SELECT id FROM scripts WHERE owner_id = ?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM scripts WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Routing-Regeln des Users löschen
+        sqlx::query("DELETE FROM routing_rules WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Aufzeichnungen des Users löschen
+        sqlx::query("DELETE FROM device_recordings WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Variablen-Snapshots des Users löschen
+        sqlx::query("DELETE FROM device_variable_snapshots WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Aktivitäten des Users bleiben im Geräte-Verlauf, aber ohne User-ID
+        sqlx::query("UPDATE device_audit_log SET actor_id = 'deleted-user' WHERE actor_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Enrollment-Tokens des Users verfallen mit ihm
+        sqlx::query("DELETE FROM enrollment_tokens WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Chat-Nachrichten des Users löschen (enthalten den Anzeigenamen)
+        sqlx::query("DELETE FROM device_chat_messages WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Befehlsvorlagen des Users löschen
+        sqlx::query("DELETE FROM command_templates WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Mitgliedschaften in Organisationen entfernen
+        sqlx::query("DELETE FROM organization_members WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Geräteprofile des Users löschen und Zuordnungen entfernen
+        sqlx::query("UPDATE devices SET profile_id = NULL WHERE profile_id IN (SELECT id FROM device_profiles WHERE owner_id = ?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM device_profiles WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Benachrichtigungs-Einstellungen und Reset-Tokens löschen
+        sqlx::query("DELETE FROM notification_preferences WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM password_resets WHERE user_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Webhooks des Users löschen
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id IN (SELECT id FROM webhooks WHERE owner_id = ?)")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM webhooks WHERE owner_id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Dann User löschen
+        sqlx::query("DELETE FROM users WHERE id = ?")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await?;
+
+        // Ausgestellte Tokens bleiben bis zu ihrem Ablauf ungültig (siehe auth::revoke_user_sessions)
+        let now = Utc::now();
+        sqlx::query("DELETE FROM revoked_sessions WHERE expires_at < ?")
+            .bind(Self::sortable_timestamp(now))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT OR REPLACE INTO revoked_sessions (user_id, expires_at) VALUES (?, ?)")
+            .bind(user_id)
+            .bind(Self::sortable_timestamp(now + chrono::Duration::hours(crate::auth::TOKEN_LIFETIME_HOURS)))
+            .execute(&mut *tx)
+            .await?;
+
+        tx.commit().await?;
+        Ok(owned_devices)
+    }
+
+    pub async fn update_user_admin_status(&self, user_id: &str, is_admin: bool) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE users SET is_admin = ? WHERE id = ?")
+            .bind(is_admin)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    // ============================================================================
+    // INITIAL USERS MANAGEMENT - Lädt und erstellt initiale User aus Konfiguration
+    // ============================================================================
+
+    fn load_initial_users() -> Result<InitialUsersFile, Box<dyn std::error::Error>> {
+        let config_path = "data/initial_users.json";
+        
+        if !std::path::Path::new(config_path).exists() {
+            tracing::warn!("Initial users config file not found: {}", config_path);
+            // Fallback zu Standard Admin-User
+            return Ok(InitialUsersFile {
+                users: vec![InitialUserConfig {
+                    email: "admin@drawing-app.local".to_string(),
+                    display_name: "Administrator".to_string(),
+                    password: "admin123".to_string(),
+                    is_admin: true,
+                }],
+            });
+        }
+
+        let config_content = fs::read_to_string(config_path)?;
+        let config: InitialUsersFile = serde_json::from_str(&config_content)?;
+        
+        tracing::info!("Loaded {} initial users from config", config.users.len());
+        Ok(config)
+    }
+
+    async fn create_initial_users(&self) -> Result<(), Box<dyn std::error::Error>> {
+        // Prüfen ob bereits User existieren
+        let user_count = sqlx::query("SELECT COUNT(*) as count FROM users")
+            .fetch_one(&self.pool)
+            .await?
+            .get::<i64, _>("count");
+
+        if user_count > 0 {
+            tracing::info!("Database contains {} existing users, skipping initial user creation", user_count);
+            return Ok(());
+        }
+
+        // Initiale User aus Konfiguration laden
+        let config = Self::load_initial_users()?;
+        let mut created_count = 0;
+
+        for user_config in config.users {
+            tracing::debug!("Creating initial user: {}", user_config.email);
+            
+            let db_user = DatabaseUser {
+                id: Uuid::new_v4().to_string(),
+                email: user_config.email.clone(),
+                display_name: user_config.display_name,
+                password_hash: hash(&user_config.password, DEFAULT_COST)?,
+                created_at: Utc::now(),
+                is_admin: user_config.is_admin,
+            };
+
+            match self.create_user(db_user).await {
+                Ok(_) => {
+                    created_count += 1;
+                    if user_config.is_admin {
+                        tracing::info!("Created initial admin user: {} / {}", user_config.email, user_config.password);
+                    } else {
+                        tracing::info!("Created initial user: {} / {}", user_config.email, user_config.password);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("Failed to create initial user {}: {:?}", user_config.email, e);
+                }
+            }
+        }
+
+        if created_count > 0 {
+            tracing::info!("Successfully created {} initial users", created_count);
+        }
+
+        Ok(())
+    }
+
+    // ============================================================================
+    // DEVICE MANAGEMENT - CRUD Operationen für Devices
+    // ============================================================================
+
+    pub async fn create_device(&self, device: Device) -> Result<(), Box<dyn std::error::Error>> {
+        let status_str = device.status.as_str();
+        
+        sqlx::query(
+            "INSERT INTO devices (mac_address, name, alias, owner_id, ip_address, status, maintenance_mode, firmware_version, last_seen, created_at, connection_type, notes, tags, org_id) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        )
+        .bind(&device.mac_address)
+        .bind(&device.name)
+        .bind(&device.alias)
+        .bind(&device.owner_id)
+        .bind(&device.ip_address)
+        .bind(status_str)
+        .bind(device.maintenance_mode)
+        .bind(&device.firmware_version)
+        .bind(device.last_seen.to_rfc3339())
+        .bind(device.created_at.to_rfc3339())
+        .bind(&device.connection_type)
+        .bind(&device.notes)
+        .bind(serde_json::to_string(&device.tags)?)
+        .bind(&device.org_id)
+        .execute(&self.pool)
+        .await?;
+
+        // Owner-Berechtigung hinzufügen
+        self.set_device_permission(&device.mac_address, &device.owner_id, "O").await?;
+
+        Ok(())
+    }
+
+    pub async fn get_device_by_id(&self, device_id: &str) -> Result<Option<Device>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM devices WHERE mac_address = ?")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(Self::row_to_device(&row)?)),
+            None => Ok(None)
+        }
+    }
+
+    /// Devices with the user's permission, filtered/sorted/paged in SQL; returns the page and the total match count
+    pub async fn list_user_devices(&self, user_id: &str, query: &DeviceListQuery) -> Result<(Vec<(Device, String)>, u64), Box<dyn std::error::Error>> {
+        let (rows, total) = self.query_device_page(Some(user_id), query).await?;
+        let mut device_list = Vec::new();
+        for row in rows {
+            let permission: String = row.get("permission");
+            device_list.push((Self::row_to_device(&row)?, permission));
+        }
+        Ok((device_list, total))
+    }
+
+    /// All devices (guest view), filtered/sorted/paged in SQL; returns the page and the total match count
+    pub async fn list_all_devices(&self, query: &DeviceListQuery) -> Result<(Vec<Device>, u64), Box<dyn std::error::Error>> {
+        let (rows, total) = self.query_device_page(None, query).await?;
+        let device_list = rows.iter().map(Self::row_to_device).collect::<Result<Vec<_>, _>>()?;
+        Ok((device_list, total))
+    }
+
+    async fn query_device_page(&self, user_id: Option<&str>, query: &DeviceListQuery) -> Result<(Vec<sqlx::sqlite::SqliteRow>, u64), Box<dyn std::error::Error>> {
+        // FROM/WHERE part shared by the count and the page query
+        let push_filters = |builder: &mut sqlx::QueryBuilder<'_, sqlx::Sqlite>| {
+            match (user_id, &query.org_id) {
+                // Members see all devices of the organization, explicit and group permissions take precedence over the role
+                (Some(user_id), Some(org_id)) => {
+                    builder.push(" FROM devices d INNER JOIN organization_members m ON m.org_id = d.org_id AND m.user_id = ");
+                    builder.push_bind(user_id.to_string());
+                    builder.push(format!(" LEFT JOIN device_permissions dp ON dp.device_id = d.mac_address AND dp.user_id = m.user_id AND (dp.expires_at IS NULL OR dp.expires_at > {}) WHERE d.org_id = ", NOW_SQL));
+                    builder.push_bind(org_id.clone());
+                }
+                (Some(user_id), None) => {
+                    builder.push(" FROM devices d LEFT JOIN device_permissions dp ON dp.device_id = d.mac_address AND dp.user_id = ");
+                    builder.push_bind(user_id.to_string());
+                    builder.push(format!(" AND (dp.expires_at IS NULL OR dp.expires_at > {})", NOW_SQL));
+                    builder.push(" WHERE d.org_id IS NULL AND COALESCE(dp.permission, ");
+                    push_group_permission(builder, user_id);
+                    builder.push(") IS NOT NULL");
+                }
+                (None, Some(org_id)) => {
+                    builder.push(" FROM devices d WHERE d.org_id = ");
+                    builder.push_bind(org_id.clone());
+                }
+                // Hardware of organizations is never part of the guest view
+                (None, None) => {
+                    builder.push(" FROM devices d WHERE d.org_id IS NULL");
+                }
+            }
+            if let Some(connection_type) = &query.connection_type {
+                builder.push(" AND d.connection_type = ");
+                builder.push_bind(connection_type.clone());
+            }
+            if let Some(search) = query.search.as_deref().filter(|s| !s.is_empty()) {
+                let pattern = format!("%{}%", search.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+                builder.push(" AND (d.name LIKE ");
+                builder.push_bind(pattern.clone());
+                builder.push(" ESCAPE '\\' OR d.alias LIKE ");
+                builder.push_bind(pattern.clone());
+                builder.push(" ESCAPE '\\' OR d.mac_address LIKE ");
+                builder.push_bind(pattern);
+                builder.push(" ESCAPE '\\')");
+            }
+            if let Some(tag) = &query.tag {
+                builder.push(" AND EXISTS (SELECT 1 FROM json_each(d.tags) WHERE json_each.value = ");
+                builder.push_bind(tag.clone());
+                builder.push(")");
+            }
+            if let Some(building) = &query.building {
+                builder.push(" AND json_extract(d.location, '$.building') = ");
+                builder.push_bind(building.clone());
+            }
+            if let Some(room) = &query.room {
+                builder.push(" AND json_extract(d.location, '$.room') = ");
+                builder.push_bind(room.clone());
+            }
+            match query.connected {
+                Some(true) if query.connected_ids.is_empty() => {
+                    builder.push(" AND 0");
+                }
+                Some(connected) if !query.connected_ids.is_empty() => {
+                    builder.push(if connected { " AND d.mac_address IN (" } else { " AND d.mac_address NOT IN (" });
+                    let mut ids = builder.separated(", ");
+                    for id in &query.connected_ids {
+                        ids.push_bind(id.clone());
+                    }
+                    builder.push(")");
+                }
+                _ => {}
+            }
+        };
+
+        let mut count_query = sqlx::QueryBuilder::new("SELECT COUNT(*) AS total");
+        push_filters(&mut count_query);
+        let total: i64 = count_query.build().fetch_one(&self.pool).await?.get("total");
+
+        // Same order as PermissionResolver: direct > group > organization default
+        let mut page_query = sqlx::QueryBuilder::new("SELECT d.*");
+        if let Some(user_id) = user_id {
+            page_query.push(", COALESCE(dp.permission, ");
+            push_group_permission(&mut page_query, user_id);
+            if query.org_id.is_some() {
+                page_query.push(", CASE WHEN m.role = 'member' THEN COALESCE(d.default_permission, 'W') ELSE 'M' END");
+            }
+            page_query.push(") AS permission");
+        }
+        push_filters(&mut page_query);
+        page_query.push(format!(
+            " ORDER BY {} {}, d.mac_address",
+            query.sort.column(),
+            if query.descending { "DESC" } else { "ASC" }
+        ));
+        if let Some(limit) = query.limit {
+            page_query.push(" LIMIT ");
+            page_query.push_bind(limit as i64);
+            page_query.push(" OFFSET ");
+            page_query.push_bind(query.offset as i64);
+        }
+        let rows = page_query.build().fetch_all(&self.pool).await?;
+
+        Ok((rows, total as u64))
+    }
+
+    fn row_to_device(row: &sqlx::sqlite::SqliteRow) -> Result<Device, Box<dyn std::error::Error>> {
+        let created_at_str: String = row.get("created_at");
+        let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
+        let last_seen_str: String = row.get("last_seen");
+        let last_seen = DateTime::parse_from_rfc3339(&last_seen_str)?.with_timezone(&Utc);
+
+        let status_str: String = row.get("status");
+        let status = LifecycleState::parse(&status_str);
+
+        Ok(Device {
+            mac_address: row.get("mac_address"),
+            name: row.get("name"),
+            alias: row.try_get::<Option<String>, _>("alias").unwrap_or(None),
+            owner_id: row.get("owner_id"),
+            ip_address: row.get("ip_address"),
+            status,
+            maintenance_mode: row.get("maintenance_mode"),
+            firmware_version: row.get("firmware_version"),
+            last_seen,
+            created_at,
+            connection_type: row.try_get("connection_type").unwrap_or_else(|_| "tcp".to_string()),
+            notes: row.try_get::<Option<String>, _>("notes").unwrap_or(None),
+            tags: row.try_get::<String, _>("tags").ok()
+                .and_then(|tags| serde_json::from_str(&tags).ok())
+                .unwrap_or_default(),
+            profile_id: row.try_get::<Option<String>, _>("profile_id").unwrap_or(None),
+            org_id: row.try_get::<Option<String>, _>("org_id").unwrap_or(None),
+            default_permission: row.try_get::<Option<String>, _>("default_permission").unwrap_or(None),
+            location: row.try_get::<Option<String>, _>("location").ok().flatten()
+                .and_then(|location| serde_json::from_str(&location).ok()),
+        })
+    }
+
+    pub async fn update_device(
+        &self,
+        device_id: &str,
+        name: Option<Option<&str>>,
+        alias: Option<Option<&str>>,
+        maintenance_mode: Option<bool>,
+        notes: Option<Option<&str>>
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Handle name: Some(Some(value)) = set value, Some(None) = clear (not allowed for name), None = don't update
+        if let Some(name_value) = name {
+            sqlx::query("UPDATE devices SET name = ? WHERE mac_address = ?")
+                .bind(name_value) // This binds Option<&str>
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Handle alias: Some(Some(value)) = set value, Some(None) = clear alias, None = don't update
+        if let Some(alias_value) = alias {
+            sqlx::query("UPDATE devices SET alias = ? WHERE mac_address = ?")
+                .bind(alias_value) // This binds Option<&str> which can be None to clear the field
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        if let Some(maintenance_mode) = maintenance_mode {
+            sqlx::query("UPDATE devices SET maintenance_mode = ? WHERE mac_address = ?")
+                .bind(maintenance_mode)
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        // Handle notes: same semantics as alias
+        if let Some(notes_value) = notes {
+            sqlx::query("UPDATE devices SET notes = ? WHERE mac_address = ?")
+                .bind(notes_value)
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    /// Change how the device is attached ("tcp" or "uart"); false if the device does not exist
+    pub async fn set_device_connection_type(&self, device_id: &str, connection_type: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET connection_type = ? WHERE mac_address = ?")
+            .bind(connection_type)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Replace the tags of a device; false if the device does not exist
+    pub async fn set_device_tags(&self, device_id: &str, tags: &[String]) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET tags = ? WHERE mac_address = ?")
+            .bind(serde_json::to_string(tags)?)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Set or clear (None) the physical location of a device; false if the device does not exist
+    pub async fn set_device_location(&self, device_id: &str, location: Option<&DeviceLocation>) -> Result<bool, Box<dyn std::error::Error>> {
+        let location = location.map(serde_json::to_string).transpose()?;
+        let result = sqlx::query("UPDATE devices SET location = ? WHERE mac_address = ?")
+            .bind(location)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn update_device_status(&self, device_id: &str, status: &LifecycleState, ip_address: Option<&str>, firmware_version: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+        let status_str = status.as_str();
+
+        let now = Utc::now().to_rfc3339();
+        
+        sqlx::query("UPDATE devices SET status = ?, ip_address = ?, firmware_version = ?, last_seen = ? WHERE mac_address = ?")
+            .bind(status_str)
+            .bind(ip_address)
+            .bind(firmware_version)
+            .bind(now)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Firmware version from a device info message; true if it differed from the stored one
+    pub async fn set_device_firmware_version(&self, device_id: &str, firmware_version: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET firmware_version = ? WHERE mac_address = ? AND firmware_version IS NOT ?")
+            .bind(firmware_version)
+            .bind(device_id)
+            .bind(firmware_version)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Persist a lifecycle transition; connected states also refresh last_seen
+    pub async fn update_device_lifecycle_state(&self, device_id: &str, state: LifecycleState) -> Result<(), Box<dyn std::error::Error>> {
+        if state.is_connected() {
+            sqlx::query("UPDATE devices SET status = ?, last_seen = ? WHERE mac_address = ?")
+                .bind(state.as_str())
+                .bind(Utc::now().to_rfc3339())
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        } else {
+            sqlx::query("UPDATE devices SET status = ? WHERE mac_address = ?")
+                .bind(state.as_str())
+                .bind(device_id)
+                .execute(&self.pool)
+                .await?;
+        }
+        Ok(())
+    }
+
+    pub async fn delete_device(&self, device_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        // Zuerst Berechtigungen löschen
+        sqlx::query("DELETE FROM device_permissions WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_connection_policies WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_connection_settings WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM crash_reports WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_variable_snapshots WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_audit_log WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_availability_events WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM device_chat_messages WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        // Dann Device löschen
+        sqlx::query("DELETE FROM devices WHERE mac_address = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Get all Devices by connection type (e.g., "tcp" or "uart")
+    pub async fn get_devices_by_connection_type(
+        &self,
+        connection_type: &str,
+    ) -> Result<Vec<Device>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            "SELECT * FROM devices WHERE connection_type = ? ORDER BY last_seen DESC"
+        )
+        .bind(connection_type)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let devices = rows.iter().map(Self::row_to_device).collect::<Result<Vec<_>, _>>()?;
+        Ok(devices)
+    }
+
+    /// Create or update device from discovery (auto-save discovered devices)
+    /// If device exists, update IP and last_seen. If not, create as guest-owned device.
+    pub async fn upsert_discovered_device(
+        &self,
+        mac_address: String,
+        device_name: String,
+        ip_address: Option<String>,
+        connection_type: Option<String>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        // Check if device already exists
+        let existing = self.get_device_by_id(&mac_address).await?;
+
+        if let Some(mut device) = existing {
+            // Device exists - update IP and last_seen
+            device.ip_address = ip_address;
+            device.last_seen = Utc::now();
+
+            sqlx::query(
+                "UPDATE devices SET ip_address = ?, last_seen = ? WHERE mac_address = ?"
+            )
+            .bind(&device.ip_address)
+            .bind(device.last_seen.to_rfc3339())
+            .bind(&mac_address)
+            .execute(&self.pool)
+            .await?;
+
+            tracing::debug!("Updated existing device in DB: {}", mac_address);
+        } else {
+            // Device doesn't exist - create new one with guest owner
+            let new_device = Device {
+                mac_address: mac_address.clone(),
+                name: device_name,
+                alias: None,
+                owner_id: "guest".to_string(),
+                ip_address,
+                status: LifecycleState::Discovered,
+                maintenance_mode: false,
+                firmware_version: None,
+                last_seen: Utc::now(),
+                created_at: Utc::now(),
+                connection_type: connection_type.unwrap_or_else(|| "tcp".to_string()),
+                notes: None,
+                tags: Vec::new(),
+                profile_id: None,
+                org_id: None,
+                default_permission: None,
+                location: None,
+            };
+
+            self.create_device(new_device).await?;
+            tracing::info!("Auto-saved new discovered device to DB: {}", mac_address);
+        }
+
+        Ok(())
+    }
+
+    // ============================================================================
+    // DEVICE PERMISSIONS - Berechtigungsverwaltung
+    // ============================================================================
+
+    pub async fn set_device_permission(&self, device_id: &str, user_id: &str, permission: &str) -> Result<(), Box<dyn std::error::Error>> {
+        self.set_device_permission_until(device_id, user_id, permission, None).await
+    }
+
+    /// Grant a permission that ends at `expires_at` (None = permanent); replaces an existing grant
+    pub async fn set_device_permission_until(&self, device_id: &str, user_id: &str, permission: &str, expires_at: Option<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            "INSERT OR REPLACE INTO device_permissions (device_id, user_id, permission, expires_at) VALUES (?, ?, ?, ?)"
+        )
+        .bind(device_id)
+        .bind(user_id)
+        .bind(permission)
+        .bind(expires_at.map(Self::sortable_timestamp))
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove grants that ended before `now` and return them
+    pub async fn delete_expired_device_permissions(&self, now: DateTime<Utc>) -> Result<Vec<DevicePermission>, Box<dyn std::error::Error>> {
+        let now = Self::sortable_timestamp(now);
+        let mut tx = self.pool.begin().await?;
+        let rows = sqlx::query("SELECT * FROM device_permissions WHERE expires_at IS NOT NULL AND expires_at <= ?")
+            .bind(&now)
+            .fetch_all(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM device_permissions WHERE expires_at IS NOT NULL AND expires_at <= ?")
+            .bind(&now)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(rows.iter().map(Self::row_to_device_permission).collect())
+    }
+
+    fn row_to_device_permission(row: &sqlx::sqlite::SqliteRow) -> DevicePermission {
+        DevicePermission {
+            device_id: row.get("device_id"),
+            user_id: row.get("user_id"),
+            permission: row.get("permission"),
+            expires_at: row.try_get::<Option<String>, _>("expires_at").ok().flatten()
+                .and_then(|value| DateTime::parse_from_rfc3339(&value).ok())
+                .map(|value| value.with_timezone(&Utc)),
+        }
+    }
+
+    /// Hand a device to a new owner (e.g. auto-saved discovery entry adopted by a user)
+    pub async fn transfer_device_owner(&self, device_id: &str, new_owner_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let Some(device) = self.get_device_by_id(device_id).await? else {
+            return Err(format!("Device not found: {}", device_id).into());
+        };
+
+        sqlx::query("UPDATE devices SET owner_id = ? WHERE mac_address = ?")
+            .bind(new_owner_id)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        if device.owner_id != new_owner_id {
+            self.remove_device_permission(device_id, &device.owner_id).await?;
+        }
+        self.set_device_permission(device_id, new_owner_id, "O").await?;
+
+        Ok(())
+    }
+
+    pub async fn remove_device_permission(&self, device_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM device_permissions WHERE device_id = ? AND user_id = ?")
+            .bind(device_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(())
+    }
+
+    /// Active (not expired) permissions on a device
+    pub async fn get_device_permissions(&self, device_id: &str) -> Result<Vec<DevicePermission>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(&format!("SELECT * FROM device_permissions WHERE device_id = ? AND (expires_at IS NULL OR expires_at > {})", NOW_SQL))
+            .bind(device_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(Self::row_to_device_permission).collect())
+    }
+
+    /// All active device permissions granted to a user
+    pub async fn get_user_permissions(&self, user_id: &str) -> Result<Vec<DevicePermission>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(&format!("SELECT * FROM device_permissions WHERE user_id = ? AND (expires_at IS NULL OR expires_at > {}) ORDER BY device_id", NOW_SQL))
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(rows.iter().map(Self::row_to_device_permission).collect())
+    }
+
+    /// Direct permission of a user; expired grants count as none even before they are removed
+    pub async fn get_user_device_permission(&self, device_id: &str, user_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(&format!("SELECT permission FROM device_permissions WHERE device_id = ? AND user_id = ? AND (expires_at IS NULL OR expires_at > {})", NOW_SQL))
+            .bind(device_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(row.get("permission"))),
+            None => Ok(None),
+        }
+    }
+
+    /// Effective permission check (direct > group > organization default, see permissions.rs)
+    pub async fn user_has_device_permission(&self, device_id: &str, user_id: &str, required_permission: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        PermissionResolver::new(self).has_permission(device_id, user_id, required_permission).await
+    }
+
+    /// Permission plain organization members get on a device without a direct one (None = W)
+    pub async fn set_device_default_permission(&self, device_id: &str, permission: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET default_permission = ? WHERE mac_address = ?")
+            .bind(permission)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // ========================================================================
+    // UART SETTINGS METHODS
+    // ========================================================================
+
+    /// Get UART settings from database
+    pub async fn get_uart_settings(&self) -> Result<Option<(Option<String>, u32, bool)>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(
+            "SELECT port, baud_rate, auto_connect FROM uart_settings WHERE id = 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => {
+                let port: Option<String> = row.try_get("port")?;
+                let baud_rate: i64 = row.try_get("baud_rate")?;
+                let auto_connect: bool = row.try_get("auto_connect")?;
+                Ok(Some((port, baud_rate as u32, auto_connect)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Update UART settings in database
+    pub async fn update_uart_settings(
+        &self,
+        port: Option<&str>,
+        baud_rate: u32,
+        auto_connect: bool
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            UPDATE uart_settings
+            SET port = ?, baud_rate = ?, auto_connect = ?, updated_at = datetime('now')
+            WHERE id = 1
+            "#
+        )
+        .bind(port)
+        .bind(baud_rate as i64)
+        .bind(auto_connect)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // DEBUG SETTINGS METHODS
+    // ========================================================================
+
+    /// Get debug settings from database
+    pub async fn get_debug_settings(&self) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(
+            "SELECT max_debug_messages FROM debug_settings WHERE id = 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => {
+                let max_messages: i64 = row.try_get("max_debug_messages")?;
+                Ok(Some(max_messages as u32))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Update debug settings in database
+    pub async fn update_debug_settings(
+        &self,
+        max_debug_messages: u32
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            UPDATE debug_settings
+            SET max_debug_messages = ?, updated_at = datetime('now')
+            WHERE id = 1
+            "#
+        )
+        .bind(max_debug_messages as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // DEVICE TLS SETTINGS METHODS
+    // ========================================================================
+
+    fn row_to_device_tls(row: &sqlx::sqlite::SqliteRow) -> Result<DeviceTlsConfig, sqlx::Error> {
+        Ok(DeviceTlsConfig {
+            ca_cert_path: row.try_get("ca_cert_path")?,
+            server_name: row.try_get("server_name")?,
+            client_cert_path: row.try_get("client_cert_path")?,
+            client_key_path: row.try_get("client_key_path")?,
+        })
+    }
+
+    /// Get TLS settings of a device (None = plaintext TCP)
+    pub async fn get_device_tls_settings(&self, device_id: &str) -> Result<Option<DeviceTlsConfig>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(
+            "SELECT ca_cert_path, server_name, client_cert_path, client_key_path FROM device_tls_settings WHERE device_id = ?"
+        )
+        .bind(device_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => Ok(Some(Self::row_to_device_tls(&row)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Get TLS settings of all devices
+    pub async fn get_all_device_tls_settings(&self) -> Result<Vec<(String, DeviceTlsConfig)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(
+            "SELECT device_id, ca_cert_path, server_name, client_cert_path, client_key_path FROM device_tls_settings"
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut settings = Vec::new();
+        for row in rows {
+            let device_id: String = row.try_get("device_id")?;
+            settings.push((device_id, Self::row_to_device_tls(&row)?));
+        }
+        Ok(settings)
+    }
+
+    /// Create or replace TLS settings of a device
+    pub async fn set_device_tls_settings(
+        &self,
+        device_id: &str,
+        tls: &DeviceTlsConfig
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO device_tls_settings (device_id, ca_cert_path, server_name, client_cert_path, client_key_path, updated_at)
+            VALUES (?, ?, ?, ?, ?, datetime('now'))
+            ON CONFLICT(device_id) DO UPDATE SET
+                ca_cert_path = excluded.ca_cert_path,
+                server_name = excluded.server_name,
+                client_cert_path = excluded.client_cert_path,
+                client_key_path = excluded.client_key_path,
+                updated_at = excluded.updated_at
+            "#
+        )
+        .bind(device_id)
+        .bind(&tls.ca_cert_path)
+        .bind(&tls.server_name)
+        .bind(&tls.client_cert_path)
+        .bind(&tls.client_key_path)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Reconnect policies of all devices that have one stored
+    pub async fn get_all_device_connection_policies(&self) -> Result<Vec<(String, ReconnectPolicy)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT device_id, policy FROM device_connection_policies")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut policies = Vec::new();
+        for row in rows {
+            let device_id: String = row.try_get("device_id")?;
+            let policy: String = row.try_get("policy")?;
+            policies.push((device_id, serde_json::from_str(&policy)?));
+        }
+        Ok(policies)
+    }
+
+    /// Create or replace the reconnect policy of a device
+    pub async fn set_device_connection_policy(&self, device_id: &str, policy: &ReconnectPolicy) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO device_connection_policies (device_id, policy, updated_at)
+            VALUES (?, ?, datetime('now'))
+            ON CONFLICT(device_id) DO UPDATE SET policy = excluded.policy, updated_at = excluded.updated_at
+            "#
+        )
+        .bind(device_id)
+        .bind(serde_json::to_string(policy)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Stored connection settings of a device
+    pub async fn get_device_connection_settings(&self, device_id: &str) -> Result<Option<ConnectionSettings>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT settings FROM device_connection_settings WHERE device_id = ?")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(serde_json::from_str(&row.try_get::<String, _>("settings")?)?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Connection settings of all devices that have them stored
+    pub async fn get_all_device_connection_settings(&self) -> Result<Vec<(String, ConnectionSettings)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT device_id, settings FROM device_connection_settings")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut settings = Vec::new();
+        for row in rows {
+            let device_id: String = row.try_get("device_id")?;
+            let stored: String = row.try_get("settings")?;
+            settings.push((device_id, serde_json::from_str(&stored)?));
+        }
+        Ok(settings)
+    }
+
+    pub async fn set_device_connection_settings(&self, device_id: &str, settings: &ConnectionSettings) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO device_connection_settings (device_id, settings, updated_at)
+            VALUES (?, ?, datetime('now'))
+            ON CONFLICT(device_id) DO UPDATE SET settings = excluded.settings, updated_at = excluded.updated_at
+            "#
+        )
+        .bind(device_id)
+        .bind(serde_json::to_string(settings)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // DEVICE CAPABILITY METHODS
+    // ========================================================================
+
+    /// Cached capabilities of a device
+    pub async fn get_device_capabilities(&self, device_id: &str) -> Result<Option<StoredCapabilities>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT capabilities, last_changes, updated_at FROM device_capabilities WHERE device_id = ?")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        let Some(row) = row else { return Ok(None) };
+        let capabilities: String = row.try_get("capabilities")?;
+        let last_changes: Option<String> = row.try_get("last_changes")?;
+        Ok(Some(StoredCapabilities {
+            device_id: device_id.to_string(),
+            capabilities: serde_json::from_str(&capabilities)?,
+            last_changes: last_changes.map(|changes| serde_json::from_str(&changes)).transpose()?,
+            updated_at: row.try_get("updated_at")?,
+        }))
+    }
+
+    /// Cached capabilities of all devices
+    pub async fn get_all_device_capabilities(&self) -> Result<Vec<(String, DeviceCapabilities)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT device_id, capabilities FROM device_capabilities")
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut capabilities = Vec::new();
+        for row in rows {
+            let device_id: String = row.try_get("device_id")?;
+            let stored: String = row.try_get("capabilities")?;
+            capabilities.push((device_id, serde_json::from_str(&stored)?));
+        }
+        Ok(capabilities)
+    }
+
+    /// Create or replace the capabilities of a device; `changes` (diff to the previous ones) replaces
+    /// the stored diff, None keeps it
+    pub async fn set_device_capabilities(
+        &self,
+        device_id: &str,
+        capabilities: &DeviceCapabilities,
+        changes: Option<&CapabilityDiff>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO device_capabilities (device_id, capabilities, last_changes, updated_at)
+            VALUES (?, ?, ?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET
+                capabilities = excluded.capabilities,
+                last_changes = COALESCE(excluded.last_changes, device_capabilities.last_changes),
+                updated_at = excluded.updated_at
+            "#
+        )
+        .bind(device_id)
+        .bind(serde_json::to_string(capabilities)?)
+        .bind(changes.map(serde_json::to_string).transpose()?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove TLS settings of a device (back to plaintext TCP)
+    pub async fn delete_device_tls_settings(&self, device_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_tls_settings WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// All identity secrets (device_id, secret), loaded into the device manager at startup
+    pub async fn get_all_device_identity_secrets(&self) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT device_id, secret FROM device_identity_secrets")
+            .fetch_all(&self.pool)
+            .await?;
+
+        rows.iter()
+            .map(|row| Ok((row.get("device_id"), secrets::open(secrets::DEVICE_IDENTITY_SECRET, row.get("secret"))?)))
+            .collect()
+    }
+
+    /// Identity secret of one device (signed uploads)
+    pub async fn get_device_identity_secret(&self, device_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT secret FROM device_identity_secrets WHERE device_id = ?")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        row.map(|row| secrets::open(secrets::DEVICE_IDENTITY_SECRET, row.get("secret"))).transpose().map_err(Into::into)
+    }
+
+    pub async fn has_device_identity_secret(&self, device_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT 1 FROM device_identity_secrets WHERE device_id = ?")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        Ok(row.is_some())
+    }
+
+    /// Create or replace the identity secret of a device
+    pub async fn set_device_identity_secret(&self, device_id: &str, secret: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            INSERT INTO device_identity_secrets (device_id, secret, updated_at)
+            VALUES (?, ?, ?)
+            ON CONFLICT(device_id) DO UPDATE SET secret = excluded.secret, updated_at = excluded.updated_at
+            "#
+        )
+        .bind(device_id)
+        .bind(secrets::seal(secrets::DEVICE_IDENTITY_SECRET, secret)?)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    /// Remove the identity secret of a device (no identity check on connect)
+    pub async fn delete_device_identity_secret(&self, device_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_identity_secrets WHERE device_id = ?")
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    // ========================================================================
+    // DEVICE GROUP METHODS
+    // ========================================================================
+
+    /// Members of a group in insertion order
+    async fn get_device_group_members(&self, group_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT device_id FROM device_group_members WHERE group_id = ? ORDER BY position")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut device_ids = Vec::with_capacity(rows.len());
+        for row in rows {
+            device_ids.push(row.try_get("device_id")?);
+        }
+        Ok(device_ids)
+    }
+
+    async fn row_to_device_group(&self, row: &sqlx::sqlite::SqliteRow) -> Result<DeviceGroup, Box<dyn std::error::Error>> {
+        let id: String = row.try_get("id")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(DeviceGroup {
+            device_ids: self.get_device_group_members(&id).await?,
+            id,
+            name: row.try_get("name")?,
+            owner_id: row.try_get("owner_id")?,
+            org_id: row.try_get("org_id")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
+
+    /// Create a device group, optionally shared with an organization
+    pub async fn create_device_group(&self, name: &str, owner_id: &str, org_id: Option<&str>, device_ids: &[String]) -> Result<DeviceGroup, Box<dyn std::error::Error>> {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO device_groups (id, name, owner_id, org_id, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(&id)
+            .bind(name)
+            .bind(owner_id)
+            .bind(org_id)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        self.set_device_group_members(&id, device_ids).await?;
+        self.get_device_group(&id).await?.ok_or_else(|| "Device group vanished after insert".into())
+    }
+
+    /// Get a device group with its members
+    pub async fn get_device_group(&self, group_id: &str) -> Result<Option<DeviceGroup>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT id, name, owner_id, org_id, created_at FROM device_groups WHERE id = ?")
+            .bind(group_id)
+            .fetch_optional(&self.pool)
+            .await?;
+
+        match row {
+            Some(row) => Ok(Some(self.row_to_device_group(&row).await?)),
+            None => Ok(None),
+        }
+    }
+
+    /// Personal device groups of an owner (without organization)
+    pub async fn list_device_groups(&self, owner_id: &str) -> Result<Vec<DeviceGroup>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT id, name, owner_id, org_id, created_at FROM device_groups WHERE owner_id = ? AND org_id IS NULL ORDER BY name")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut groups = Vec::with_capacity(rows.len());
+        for row in rows {
+            groups.push(self.row_to_device_group(&row).await?);
+        }
+        Ok(groups)
+    }
+
+    /// Device groups shared within an organization
+    pub async fn list_org_device_groups(&self, org_id: &str) -> Result<Vec<DeviceGroup>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT id, name, owner_id, org_id, created_at FROM device_groups WHERE org_id = ? ORDER BY name")
+            .bind(org_id)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut groups = Vec::with_capacity(rows.len());
+        for row in rows {
+            groups.push(self.row_to_device_group(&row).await?);
+        }
+        Ok(groups)
+    }
+
+    /// Rename a device group
+    pub async fn rename_device_group(&self, group_id: &str, name: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE device_groups SET name = ? WHERE id = ?")
+            .bind(name)
+            .bind(group_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Replace the members of a device group (duplicates are ignored)
+    pub async fn set_device_group_members(&self, group_id: &str, device_ids: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM device_group_members WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&mut *tx)
+            .await?;
+        for (position, device_id) in device_ids.iter().enumerate() {
+            sqlx::query("INSERT OR IGNORE INTO device_group_members (group_id, device_id, position) VALUES (?, ?, ?)")
+                .bind(group_id)
+                .bind(device_id)
+                .bind(position as i64)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete a device group and its member list
+    pub async fn delete_device_group(&self, group_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM device_group_permissions WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM device_group_members WHERE group_id = ?")
+            .bind(group_id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM device_groups WHERE id = ?")
+            .bind(group_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Permissions a device group passes on to its devices
+    pub async fn list_device_group_permissions(&self, group_id: &str) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT user_id, permission FROM device_group_permissions WHERE group_id = ? ORDER BY user_id")
+            .bind(group_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| (row.get("user_id"), row.get("permission"))).collect())
+    }
+
+    pub async fn set_device_group_permission(&self, group_id: &str, user_id: &str, permission: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT OR REPLACE INTO device_group_permissions (group_id, user_id, permission) VALUES (?, ?, ?)")
+            .bind(group_id)
+            .bind(user_id)
+            .bind(permission)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_device_group_permission(&self, group_id: &str, user_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_group_permissions WHERE group_id = ? AND user_id = ?")
+            .bind(group_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Users holding a group grant that reaches a device
+    pub async fn list_device_group_grantees(&self, device_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(&format!("SELECT DISTINCT gp.user_id {} AND gm.device_id = ?", GROUP_GRANTS_SQL))
+            .bind(device_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.get("user_id")).collect())
+    }
+
+    /// Group grants of a user that reach a device: (group id, permission)
+    pub async fn group_permissions_for_device(&self, device_id: &str, user_id: &str) -> Result<Vec<(String, String)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query(&format!(
+            "SELECT gp.group_id, gp.permission {} AND gm.device_id = ? AND gp.user_id = ? ORDER BY gp.group_id",
+            GROUP_GRANTS_SQL
+        ))
+            .bind(device_id)
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| (row.get("group_id"), row.get("permission"))).collect())
+    }
+
+    // ========================================================================
+    // SECRET METHODS
+    // ========================================================================
+
+    /// Seal stored secrets again that are plaintext or sealed with a retired key (startup, key rotation).
+    /// Returns the number of updated values.
+    pub async fn reseal_secrets(&self) -> Result<usize, Box<dyn std::error::Error>> {
+        let columns = [
+            ("device_identity_secrets", "device_id", secrets::DEVICE_IDENTITY_SECRET),
+            ("webhooks", "id", secrets::WEBHOOK_SECRET),
+        ];
+        let mut updated = 0;
+        let mut tx = self.pool.begin().await?;
+        for (table, key, context) in columns {
+            let rows = sqlx::query(&format!("SELECT {key}, secret FROM {table}"))
+                .fetch_all(&mut *tx)
+                .await?;
+            for row in rows {
+                let stored: String = row.get("secret");
+                if !secrets::needs_reseal(&stored) {
+                    continue;
+                }
+                let sealed = secrets::seal(context, &secrets::open(context, &stored)?)?;
+                sqlx::query(&format!("UPDATE {table} SET secret = ? WHERE {key} = ?"))
+                    .bind(sealed)
+                    .bind(row.get::<String, _>(key))
+                    .execute(&mut *tx)
+                    .await?;
+                updated += 1;
+            }
+        }
+        tx.commit().await?;
+        Ok(updated)
+    }
+
+    // ========================================================================
+    // WEBHOOK METHODS
+    // ========================================================================
+
+    fn row_to_webhook(row: &sqlx::sqlite::SqliteRow) -> Result<Webhook, Box<dyn std::error::Error>> {
+        let events: String = row.try_get("events")?;
+        let device_ids: String = row.try_get("device_ids")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(Webhook {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            url: row.try_get("url")?,
+            secret: secrets::open(secrets::WEBHOOK_SECRET, row.try_get("secret")?)?,
+            events: serde_json::from_str(&events)?,
+            device_ids: serde_json::from_str(&device_ids)?,
+            enabled: row.try_get("enabled")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
+
+    /// Create a webhook
+    pub async fn create_webhook(&self, owner_id: &str, url: &str, secret: &str, events: &[String], device_ids: &[String]) -> Result<Webhook, Box<dyn std::error::Error>> {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO webhooks (id, owner_id, url, secret, events, device_ids, enabled, created_at) VALUES (?, ?, ?, ?, ?, ?, 1, ?)")
+            .bind(&id)
+            .bind(owner_id)
+            .bind(url)
+            .bind(secrets::seal(secrets::WEBHOOK_SECRET, secret)?)
+            .bind(serde_json::to_string(events)?)
+            .bind(serde_json::to_string(device_ids)?)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        self.get_webhook(&id).await?.ok_or_else(|| "Webhook vanished after insert".into())
+    }
+
+    /// Get a webhook
+    pub async fn get_webhook(&self, webhook_id: &str) -> Result<Option<Webhook>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM webhooks WHERE id = ?")
+            .bind(webhook_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_webhook).transpose()
+    }
+
+    /// Webhooks of an owner
+    pub async fn list_webhooks(&self, owner_id: &str) -> Result<Vec<Webhook>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM webhooks WHERE owner_id = ? ORDER BY created_at")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_webhook).collect()
+    }
+
+    /// Enabled webhooks subscribed to an event (device filter applied by the caller)
+    pub async fn get_webhooks_for_event(&self, event: &str) -> Result<Vec<Webhook>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM webhooks WHERE enabled = 1 AND EXISTS (SELECT 1 FROM json_each(webhooks.events) WHERE json_each.value = ?)")
+            .bind(event)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_webhook).collect()
+    }
+
+    /// Replace URL, events, device filter and enabled flag of a webhook
+    pub async fn update_webhook(&self, webhook: &Webhook) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE webhooks SET url = ?, events = ?, device_ids = ?, enabled = ? WHERE id = ?")
+            .bind(&webhook.url)
+            .bind(serde_json::to_string(&webhook.events)?)
+            .bind(serde_json::to_string(&webhook.device_ids)?)
+            .bind(webhook.enabled)
+            .bind(&webhook.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a webhook and its delivery log
+    pub async fn delete_webhook(&self, webhook_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?")
+            .bind(webhook_id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM webhooks WHERE id = ?")
+            .bind(webhook_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Log a new (pending) delivery; keeps only the newest `keep` deliveries of the webhook
+    pub async fn insert_webhook_delivery(&self, delivery: &WebhookDelivery, keep: usize) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO webhook_deliveries (id, webhook_id, event, device_id, status, attempts, created_at) VALUES (?, ?, ?, ?, ?, ?, ?)")
+            .bind(&delivery.id)
+            .bind(&delivery.webhook_id)
+            .bind(&delivery.event)
+            .bind(&delivery.device_id)
+            .bind(&delivery.status)
+            .bind(delivery.attempts as i64)
+            .bind(delivery.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ? AND id NOT IN (SELECT id FROM webhook_deliveries WHERE webhook_id = ? ORDER BY created_at DESC LIMIT ?)")
+            .bind(&delivery.webhook_id)
+            .bind(&delivery.webhook_id)
+            .bind(keep as i64)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Record the outcome of a delivery attempt
+    pub async fn update_webhook_delivery(&self, delivery: &WebhookDelivery) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE webhook_deliveries SET status = ?, attempts = ?, response_status = ?, error = ?, completed_at = ? WHERE id = ?")
+            .bind(&delivery.status)
+            .bind(delivery.attempts as i64)
+            .bind(delivery.response_status.map(|s| s as i64))
+            .bind(&delivery.error)
+            .bind(delivery.completed_at.map(|t| t.to_rfc3339()))
+            .bind(&delivery.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Newest deliveries of a webhook first
+    pub async fn list_webhook_deliveries(&self, webhook_id: &str, limit: usize) -> Result<Vec<WebhookDelivery>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM webhook_deliveries WHERE webhook_id = ? ORDER BY created_at DESC LIMIT ?")
+            .bind(webhook_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+
+        let mut deliveries = Vec::with_capacity(rows.len());
+        for row in rows {
+            let created_at: String = row.try_get("created_at")?;
+            let completed_at: Option<String> = row.try_get("completed_at")?;
+            deliveries.push(WebhookDelivery {
+                id: row.try_get("id")?,
+                webhook_id: row.try_get("webhook_id")?,
+                event: row.try_get("event")?,
+                device_id: row.try_get("device_id")?,
+                status: row.try_get("status")?,
+                attempts: row.try_get::<i64, _>("attempts")? as u32,
+                response_status: row.try_get::<Option<i64>, _>("response_status")?.map(|s| s as u16),
+                error: row.try_get("error")?,
+                created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+                completed_at: completed_at.map(|t| DateTime::parse_from_rfc3339(&t)).transpose()?.map(|t| t.with_timezone(&Utc)),
+            });
+        }
+        Ok(deliveries)
+    }
+
+    // ========================================================================
+    // ALERT METHODS
+    // ========================================================================
+
+    fn row_to_alert_rule(row: &sqlx::sqlite::SqliteRow) -> Result<AlertRule, Box<dyn std::error::Error>> {
+        let created_at: String = row.try_get("created_at")?;
+        Ok(AlertRule {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            device_id: row.try_get("device_id")?,
+            name: row.try_get("name")?,
+            condition: row.try_get("condition")?,
+            notify_webhook: row.try_get("notify_webhook")?,
+            enabled: row.try_get("enabled")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
+
+    fn row_to_alert(row: &sqlx::sqlite::SqliteRow) -> Result<Alert, Box<dyn std::error::Error>> {
+        let triggered_at: String = row.try_get("triggered_at")?;
+        let resolved_at: Option<String> = row.try_get("resolved_at")?;
+        Ok(Alert {
+            id: row.try_get("id")?,
+            rule_id: row.try_get("rule_id")?,
+            rule_name: row.try_get("rule_name")?,
+            owner_id: row.try_get("owner_id")?,
+            device_id: row.try_get("device_id")?,
+            state: row.try_get("state")?,
+            message: row.try_get("message")?,
+            value: row.try_get("value")?,
+            triggered_at: DateTime::parse_from_rfc3339(&triggered_at)?.with_timezone(&Utc),
+            resolved_at: resolved_at.map(|t| DateTime::parse_from_rfc3339(&t)).transpose()?.map(|t| t.with_timezone(&Utc)),
+        })
+    }
+
+    /// Create an alert rule
+    pub async fn create_alert_rule(&self, owner_id: &str, device_id: &str, name: &str, condition: &str, notify_webhook: bool) -> Result<AlertRule, Box<dyn std::error::Error>> {
+        let id = Uuid::new_v4().to_string();
+        sqlx::query("INSERT INTO alert_rules (id, owner_id, device_id, name, condition, notify_webhook, enabled, created_at) VALUES (?, ?, ?, ?, ?, ?, 1, ?)")
+            .bind(&id)
+            .bind(owner_id)
+            .bind(device_id)
+            .bind(name)
+            .bind(condition)
+            .bind(notify_webhook)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+
+        self.get_alert_rule(&id).await?.ok_or_else(|| "Alert rule vanished after insert".into())
+    }
+
+    /// Get an alert rule
+    pub async fn get_alert_rule(&self, rule_id: &str) -> Result<Option<AlertRule>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM alert_rules WHERE id = ?")
+            .bind(rule_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_alert_rule).transpose()
+    }
+
+    /// Alert rules of an owner
+    pub async fn list_alert_rules(&self, owner_id: &str) -> Result<Vec<AlertRule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM alert_rules WHERE owner_id = ? ORDER BY created_at")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_alert_rule).collect()
+    }
+
+    /// Enabled alert rules of all users (loaded by the alert engine)
+    pub async fn list_enabled_alert_rules(&self) -> Result<Vec<AlertRule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM alert_rules WHERE enabled = 1")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_alert_rule).collect()
+    }
+
+    /// Replace name, condition and flags of an alert rule
+    pub async fn update_alert_rule(&self, rule: &AlertRule) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE alert_rules SET name = ?, condition = ?, notify_webhook = ?, enabled = ? WHERE id = ?")
+            .bind(&rule.name)
+            .bind(&rule.condition)
+            .bind(rule.notify_webhook)
+            .bind(rule.enabled)
+            .bind(&rule.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete an alert rule and its alerts
+    pub async fn delete_alert_rule(&self, rule_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM alerts WHERE rule_id = ?")
+            .bind(rule_id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM alert_rules WHERE id = ?")
+            .bind(rule_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Store a new alert
+    pub async fn insert_alert(&self, alert: &Alert) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO alerts (id, rule_id, rule_name, owner_id, device_id, state, message, value, triggered_at, resolved_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&alert.id)
+            .bind(&alert.rule_id)
+            .bind(&alert.rule_name)
+            .bind(&alert.owner_id)
+            .bind(&alert.device_id)
+            .bind(&alert.state)
+            .bind(&alert.message)
+            .bind(alert.value)
+            .bind(alert.triggered_at.to_rfc3339())
+            .bind(alert.resolved_at.map(|t| t.to_rfc3339()))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Mark a firing alert as resolved
+    pub async fn resolve_alert(&self, alert_id: &str, resolved_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE alerts SET state = 'resolved', resolved_at = ? WHERE id = ? AND state = 'firing'")
+            .bind(resolved_at.to_rfc3339())
+            .bind(alert_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Alerts still firing (restored by the alert engine after a restart)
+    pub async fn list_firing_alerts(&self) -> Result<Vec<Alert>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM alerts WHERE state = 'firing'")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_alert).collect()
+    }
+
+    /// Alerts of an owner, newest first, optionally filtered by device and state
+    pub async fn list_alerts(&self, owner_id: &str, device_id: Option<&str>, state: Option<&str>, limit: usize) -> Result<Vec<Alert>, Box<dyn std::error::Error>> {
+        let mut builder = sqlx::QueryBuilder::new("SELECT * FROM alerts WHERE owner_id = ");
+        builder.push_bind(owner_id);
+        if let Some(device_id) = device_id {
+            builder.push(" AND device_id = ").push_bind(device_id);
+        }
+        if let Some(state) = state {
+            builder.push(" AND state = ").push_bind(state);
         }
+        builder.push(" ORDER BY triggered_at DESC LIMIT ").push_bind(limit as i64);
+
+        let rows = builder.build().fetch_all(&self.pool).await?;
+        rows.iter().map(Self::row_to_alert).collect()
     }
 
-    pub async fn get_user_by_id(&self, user_id: &str) -> Result<Option<DatabaseUser>, Box<dyn std::error::Error>> {
-        let row = sqlx::query("SELECT * FROM users WHERE id = ?")
+    // ========================================================================
+    // NOTIFICATION / EMAIL METHODS
+    // ========================================================================
+
+    /// Notification settings of a user (defaults if never saved)
+    pub async fn get_notification_preferences(&self, user_id: &str) -> Result<NotificationPreferences, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM notification_preferences WHERE user_id = ?")
             .bind(user_id)
             .fetch_optional(&self.pool)
             .await?;
 
         match row {
-            Some(row) => {
-                let created_at_str: String = row.get("created_at");
-                let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-                
-                Ok(Some(DatabaseUser {
-                    id: row.get("id"),
-                    email: row.get("email"),
-                    display_name: row.get("display_name"),
-                    password_hash: row.get("password_hash"),
-                    created_at,
-                    is_admin: row.get("is_admin"),
-                }))
-            }
-            None => Ok(None)
+            Some(row) => Ok(NotificationPreferences {
+                user_id: row.try_get("user_id")?,
+                email_alerts: row.try_get("email_alerts")?,
+                email_alert_resolved: row.try_get("email_alert_resolved")?,
+            }),
+            None => Ok(NotificationPreferences::new(user_id)),
         }
     }
 
-    pub async fn update_user_display_name(&self, user_id: &str, display_name: &str) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query("UPDATE users SET display_name = ? WHERE id = ?")
-            .bind(display_name)
+    /// Save the notification settings of a user
+    pub async fn set_notification_preferences(&self, preferences: &NotificationPreferences) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT OR REPLACE INTO notification_preferences (user_id, email_alerts, email_alert_resolved) VALUES (?, ?, ?)")
+            .bind(&preferences.user_id)
+            .bind(preferences.email_alerts)
+            .bind(preferences.email_alert_resolved)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    // Fixed-width timestamps, so that TEXT columns compare chronologically in SQL
+    fn sortable_timestamp(time: DateTime<Utc>) -> String {
+        time.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+    }
+
+    fn row_to_queued_email(row: &sqlx::sqlite::SqliteRow) -> Result<QueuedEmail, Box<dyn std::error::Error>> {
+        let next_attempt_at: String = row.try_get("next_attempt_at")?;
+        let created_at: String = row.try_get("created_at")?;
+        let sent_at: Option<String> = row.try_get("sent_at")?;
+        Ok(QueuedEmail {
+            id: row.try_get("id")?,
+            recipient: row.try_get("recipient")?,
+            subject: row.try_get("subject")?,
+            body: row.try_get("body")?,
+            status: row.try_get("status")?,
+            attempts: row.try_get::<i64, _>("attempts")? as u32,
+            next_attempt_at: DateTime::parse_from_rfc3339(&next_attempt_at)?.with_timezone(&Utc),
+            last_error: row.try_get("last_error")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+            sent_at: sent_at.map(|t| DateTime::parse_from_rfc3339(&t)).transpose()?.map(|t| t.with_timezone(&Utc)),
+        })
+    }
+
+    /// Add an email to the send queue
+    pub async fn enqueue_email(&self, email: &QueuedEmail) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO email_queue (id, recipient, subject, body, status, attempts, next_attempt_at, last_error, created_at, sent_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&email.id)
+            .bind(&email.recipient)
+            .bind(&email.subject)
+            .bind(&email.body)
+            .bind(&email.status)
+            .bind(email.attempts as i64)
+            .bind(Self::sortable_timestamp(email.next_attempt_at))
+            .bind(&email.last_error)
+            .bind(Self::sortable_timestamp(email.created_at))
+            .bind(email.sent_at.map(Self::sortable_timestamp))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Pending emails whose next attempt is due, oldest first
+    pub async fn due_emails(&self, now: DateTime<Utc>, limit: usize) -> Result<Vec<QueuedEmail>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM email_queue WHERE status = 'pending' AND next_attempt_at <= ? ORDER BY next_attempt_at LIMIT ?")
+            .bind(Self::sortable_timestamp(now))
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_queued_email).collect()
+    }
+
+    /// Record the outcome of a send attempt
+    pub async fn update_queued_email(&self, email: &QueuedEmail) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE email_queue SET status = ?, attempts = ?, next_attempt_at = ?, last_error = ?, sent_at = ? WHERE id = ?")
+            .bind(&email.status)
+            .bind(email.attempts as i64)
+            .bind(Self::sortable_timestamp(email.next_attempt_at))
+            .bind(&email.last_error)
+            .bind(email.sent_at.map(Self::sortable_timestamp))
+            .bind(&email.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Drop sent and failed emails created before `before`; returns the number removed
+    pub async fn prune_email_queue(&self, before: DateTime<Utc>) -> Result<u64, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM email_queue WHERE status != 'pending' AND created_at < ?")
+            .bind(Self::sortable_timestamp(before))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Store a password reset token hash; older tokens of the user become invalid
+    pub async fn create_password_reset(&self, user_id: &str, token_hash: &str, expires_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("DELETE FROM password_resets WHERE user_id = ? OR expires_at < ?")
+            .bind(user_id)
+            .bind(Self::sortable_timestamp(Utc::now()))
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO password_resets (token_hash, user_id, expires_at, created_at) VALUES (?, ?, ?, ?)")
+            .bind(token_hash)
             .bind(user_id)
+            .bind(Self::sortable_timestamp(expires_at))
+            .bind(Self::sortable_timestamp(Utc::now()))
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Mark an unused, unexpired reset token as used; returns its user ID
+    pub async fn consume_password_reset(&self, token_hash: &str, now: DateTime<Utc>) -> Result<Option<String>, Box<dyn std::error::Error>> {
+        let user_id = sqlx::query_scalar("UPDATE password_resets SET used_at = ? WHERE token_hash = ? AND used_at IS NULL AND expires_at > ? RETURNING user_id")
+            .bind(Self::sortable_timestamp(now))
+            .bind(token_hash)
+            .bind(Self::sortable_timestamp(now))
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(user_id)
+    }
+
+    /// Users deleted within the last token lifetime and when their last token expires
+    pub async fn get_revoked_sessions(&self) -> Result<Vec<(String, DateTime<Utc>)>, Box<dyn std::error::Error>> {
+        let rows: Vec<(String, String)> = sqlx::query_as("SELECT user_id, expires_at FROM revoked_sessions WHERE expires_at > ?")
+            .bind(Self::sortable_timestamp(Utc::now()))
+            .fetch_all(&self.pool)
+            .await?;
+        rows.into_iter()
+            .map(|(user_id, expires_at)| Ok((user_id, DateTime::parse_from_rfc3339(&expires_at)?.with_timezone(&Utc))))
+            .collect()
+    }
+
+    // ========================================================================
+    // SCHEDULE METHODS
+    // ========================================================================
+
+    fn parse_optional_timestamp(value: Option<String>) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        Ok(value.map(|t| DateTime::parse_from_rfc3339(&t)).transpose()?.map(|t| t.with_timezone(&Utc)))
+    }
+
+    fn row_to_schedule(row: &sqlx::sqlite::SqliteRow) -> Result<Schedule, Box<dyn std::error::Error>> {
+        let command: String = row.try_get("command")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(Schedule {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            name: row.try_get("name")?,
+            schedule: row.try_get("schedule")?,
+            device_id: row.try_get("device_id")?,
+            group_id: row.try_get("group_id")?,
+            command: serde_json::from_str(&command)?,
+            enabled: row.try_get("enabled")?,
+            catch_up: row.try_get("catch_up")?,
+            next_run_at: Self::parse_optional_timestamp(row.try_get("next_run_at")?)?,
+            last_run_at: Self::parse_optional_timestamp(row.try_get("last_run_at")?)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
+
+    /// Store a new schedule (ID and timestamps are set by the caller)
+    pub async fn create_schedule(&self, schedule: &Schedule) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO schedules (id, owner_id, name, schedule, device_id, group_id, command, enabled, catch_up, next_run_at, last_run_at, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&schedule.id)
+            .bind(&schedule.owner_id)
+            .bind(&schedule.name)
+            .bind(&schedule.schedule)
+            .bind(&schedule.device_id)
+            .bind(&schedule.group_id)
+            .bind(serde_json::to_string(&schedule.command)?)
+            .bind(schedule.enabled)
+            .bind(schedule.catch_up)
+            .bind(schedule.next_run_at.map(Self::sortable_timestamp))
+            .bind(schedule.last_run_at.map(Self::sortable_timestamp))
+            .bind(Self::sortable_timestamp(schedule.created_at))
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
+
+    pub async fn get_schedule(&self, schedule_id: &str) -> Result<Option<Schedule>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM schedules WHERE id = ?")
+            .bind(schedule_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_schedule).transpose()
+    }
+
+    /// Schedules of a user, oldest first
+    pub async fn list_schedules(&self, owner_id: &str) -> Result<Vec<Schedule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM schedules WHERE owner_id = ? ORDER BY created_at")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_schedule).collect()
+    }
 
+    /// Save name, timing, target, command and flags of a schedule (not last_run_at)
+    pub async fn update_schedule(&self, schedule: &Schedule) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE schedules SET name = ?, schedule = ?, device_id = ?, group_id = ?, command = ?, enabled = ?, catch_up = ?, next_run_at = ? WHERE id = ?")
+            .bind(&schedule.name)
+            .bind(&schedule.schedule)
+            .bind(&schedule.device_id)
+            .bind(&schedule.group_id)
+            .bind(serde_json::to_string(&schedule.command)?)
+            .bind(schedule.enabled)
+            .bind(schedule.catch_up)
+            .bind(schedule.next_run_at.map(Self::sortable_timestamp))
+            .bind(&schedule.id)
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
-    pub async fn get_all_users(&self) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
-        let rows = sqlx::query("SELECT * FROM users ORDER BY created_at DESC")
+    /// Delete a schedule and its history; false if it did not exist
+    pub async fn delete_schedule(&self, schedule_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM schedule_runs WHERE schedule_id = ?")
+            .bind(schedule_id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM schedules WHERE id = ?")
+            .bind(schedule_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Enabled schedules whose next run is at or before `now`
+    pub async fn list_due_schedules(&self, now: DateTime<Utc>) -> Result<Vec<Schedule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM schedules WHERE enabled = 1 AND next_run_at IS NOT NULL AND next_run_at <= ? ORDER BY next_run_at")
+            .bind(Self::sortable_timestamp(now))
             .fetch_all(&self.pool)
             .await?;
+        rows.iter().map(Self::row_to_schedule).collect()
+    }
 
-        let mut users = Vec::new();
-        for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            
-            users.push(DatabaseUser {
-                id: row.get("id"),
-                email: row.get("email"),
-                display_name: row.get("display_name"),
-                password_hash: row.get("password_hash"),
-                created_at,
-                is_admin: row.get("is_admin"),
-            });
-        }
+    /// Earliest next run of all enabled schedules
+    pub async fn next_schedule_run(&self) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        let next: Option<String> = sqlx::query_scalar("SELECT MIN(next_run_at) FROM schedules WHERE enabled = 1")
+            .fetch_one(&self.pool)
+            .await?;
+        Self::parse_optional_timestamp(next)
+    }
 
-        Ok(users)
+    pub async fn set_schedule_next_run(&self, schedule_id: &str, next_run_at: Option<DateTime<Utc>>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE schedules SET next_run_at = ? WHERE id = ?")
+            .bind(next_run_at.map(Self::sortable_timestamp))
+            .bind(schedule_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
     }
 
-    pub async fn search_users(&self, query: &str) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
-        let search_pattern = format!("%{}%", query);
-        let rows = sqlx::query("SELECT * FROM users WHERE email LIKE ? OR display_name LIKE ? ORDER BY display_name LIMIT 20")
-            .bind(&search_pattern)
-            .bind(&search_pattern)
+    /// Add a run to the history (keeping the newest `keep`) and set the schedule's last_run_at
+    pub async fn insert_schedule_run(&self, run: &ScheduleRun, keep: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO schedule_runs (id, schedule_id, trigger, status, scheduled_for, started_at, finished_at, total, succeeded, error, results) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&run.id)
+            .bind(&run.schedule_id)
+            .bind(&run.trigger)
+            .bind(&run.status)
+            .bind(run.scheduled_for.map(Self::sortable_timestamp))
+            .bind(Self::sortable_timestamp(run.started_at))
+            .bind(run.finished_at.map(Self::sortable_timestamp))
+            .bind(run.total as i64)
+            .bind(run.succeeded as i64)
+            .bind(&run.error)
+            .bind(serde_json::to_string(&run.results)?)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("DELETE FROM schedule_runs WHERE schedule_id = ? AND id NOT IN (SELECT id FROM schedule_runs WHERE schedule_id = ? ORDER BY started_at DESC LIMIT ?)")
+            .bind(&run.schedule_id)
+            .bind(&run.schedule_id)
+            .bind(keep as i64)
+            .execute(&mut *tx)
+            .await?;
+
+        sqlx::query("UPDATE schedules SET last_run_at = ? WHERE id = ?")
+            .bind(Self::sortable_timestamp(run.started_at))
+            .bind(&run.schedule_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Newest runs of a schedule first
+    pub async fn list_schedule_runs(&self, schedule_id: &str, limit: usize) -> Result<Vec<ScheduleRun>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM schedule_runs WHERE schedule_id = ? ORDER BY started_at DESC LIMIT ?")
+            .bind(schedule_id)
+            .bind(limit as i64)
             .fetch_all(&self.pool)
             .await?;
 
-        let mut users = Vec::new();
+        let mut runs = Vec::with_capacity(rows.len());
         for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            
-            users.push(DatabaseUser {
-                id: row.get("id"),
-                email: row.get("email"),
-                display_name: row.get("display_name"),
-                password_hash: row.get("password_hash"),
-                created_at,
-                is_admin: row.get("is_admin"),
+            let started_at: String = row.try_get("started_at")?;
+            let results: String = row.try_get("results")?;
+            runs.push(ScheduleRun {
+                id: row.try_get("id")?,
+                schedule_id: row.try_get("schedule_id")?,
+                trigger: row.try_get("trigger")?,
+                status: row.try_get("status")?,
+                scheduled_for: Self::parse_optional_timestamp(row.try_get("scheduled_for")?)?,
+                started_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+                finished_at: Self::parse_optional_timestamp(row.try_get("finished_at")?)?,
+                total: row.try_get::<i64, _>("total")? as u32,
+                succeeded: row.try_get::<i64, _>("succeeded")? as u32,
+                error: row.try_get("error")?,
+                results: serde_json::from_str(&results)?,
             });
         }
+        Ok(runs)
+    }
 
-        Ok(users)
+    // ========================================================================
+    // SCRIPT METHODS
+    // ========================================================================
+
+    fn row_to_script(row: &sqlx::sqlite::SqliteRow) -> Result<Script, Box<dyn std::error::Error>> {
+        let events: String = row.try_get("events")?;
+        let created_at: String = row.try_get("created_at")?;
+        let updated_at: String = row.try_get("updated_at")?;
+        Ok(Script {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            name: row.try_get("name")?,
+            device_id: row.try_get("device_id")?,
+            events: serde_json::from_str(&events)?,
+            source: row.try_get("source")?,
+            enabled: row.try_get("enabled")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at)?.with_timezone(&Utc),
+        })
     }
 
-    pub async fn get_users_paginated(&self, offset: i32, limit: i32) -> Result<Vec<DatabaseUser>, Box<dyn std::error::Error>> {
-        let rows = sqlx::query("SELECT * FROM users ORDER BY display_name LIMIT ? OFFSET ?")
-            .bind(limit)
-            .bind(offset)
+    pub async fn create_script(&self, script: &Script) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO scripts (id, owner_id, name, device_id, events, source, enabled, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&script.id)
+            .bind(&script.owner_id)
+            .bind(&script.name)
+            .bind(&script.device_id)
+            .bind(serde_json::to_string(&script.events)?)
+            .bind(&script.source)
+            .bind(script.enabled)
+            .bind(script.created_at.to_rfc3339())
+            .bind(script.updated_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn get_script(&self, script_id: &str) -> Result<Option<Script>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM scripts WHERE id = ?")
+            .bind(script_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_script).transpose()
+    }
+
+    /// Scripts of a user, oldest first
+    pub async fn list_scripts(&self, owner_id: &str) -> Result<Vec<Script>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM scripts WHERE owner_id = ? ORDER BY created_at")
+            .bind(owner_id)
             .fetch_all(&self.pool)
             .await?;
+        rows.iter().map(Self::row_to_script).collect()
+    }
 
-        let mut users = Vec::new();
-        for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            
-            users.push(DatabaseUser {
-                id: row.get("id"),
-                email: row.get("email"),
-                display_name: row.get("display_name"),
-                password_hash: row.get("password_hash"),
-                created_at,
-                is_admin: row.get("is_admin"),
-            });
+    /// All enabled scripts (loaded by the script engine)
+    pub async fn list_enabled_scripts(&self) -> Result<Vec<Script>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM scripts WHERE enabled = 1 ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_script).collect()
+    }
+
+    pub async fn update_script(&self, script: &Script) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE scripts SET name = ?, device_id = ?, events = ?, source = ?, enabled = ?, updated_at = ? WHERE id = ?")
+            .bind(&script.name)
+            .bind(&script.device_id)
+            .bind(serde_json::to_string(&script.events)?)
+            .bind(&script.source)
+            .bind(script.enabled)
+            .bind(script.updated_at.to_rfc3339())
+            .bind(&script.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Delete a script and its log; false if it did not exist
+    pub async fn delete_script(&self, script_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        sqlx::query("DELETE FROM script_logs WHERE script_id = ?")
+            .bind(script_id)
+            .execute(&self.pool)
+            .await?;
+        let result = sqlx::query("DELETE FROM scripts WHERE id = ?")
+            .bind(script_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    // ========================================================================
+    // ORGANIZATION METHODS
+    // ========================================================================
+
+    fn row_to_organization(row: &sqlx::sqlite::SqliteRow) -> Result<Organization, Box<dyn std::error::Error>> {
+        let created_at: String = row.try_get("created_at")?;
+        Ok(Organization {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            slug: row.try_get("slug")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
+
+    /// Create an organization with `owner_id` as its first owner
+    pub async fn create_organization(&self, name: &str, slug: &str, owner_id: &str) -> Result<Organization, Box<dyn std::error::Error>> {
+        let organization = Organization { id: Uuid::new_v4().to_string(), name: name.to_string(), slug: slug.to_string(), created_at: Utc::now() };
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO organizations (id, name, slug, created_at) VALUES (?, ?, ?, ?)")
+            .bind(&organization.id)
+            .bind(&organization.name)
+            .bind(&organization.slug)
+            .bind(organization.created_at.to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("INSERT INTO organization_members (org_id, user_id, role, joined_at) VALUES (?, ?, ?, ?)")
+            .bind(&organization.id)
+            .bind(owner_id)
+            .bind(OrgRole::Owner.as_str())
+            .bind(organization.created_at.to_rfc3339())
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(organization)
+    }
+
+    /// Organization by ID or slug
+    pub async fn get_organization(&self, id_or_slug: &str) -> Result<Option<Organization>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM organizations WHERE id = ? OR slug = ?")
+            .bind(id_or_slug)
+            .bind(id_or_slug)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_organization).transpose()
+    }
+
+    /// Organizations a user is a member of, with the user's role
+    pub async fn list_user_organizations(&self, user_id: &str) -> Result<Vec<(Organization, OrgRole)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT o.*, m.role FROM organizations o JOIN organization_members m ON m.org_id = o.id WHERE m.user_id = ? ORDER BY o.name")
+            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(|row| {
+            let role: String = row.try_get("role")?;
+            Ok((Self::row_to_organization(row)?, OrgRole::parse(&role).unwrap_or(OrgRole::Member)))
+        }).collect()
+    }
+
+    /// Role of a user in an organization; None for non-members
+    pub async fn get_org_role(&self, org_id: &str, user_id: &str) -> Result<Option<OrgRole>, Box<dyn std::error::Error>> {
+        let role: Option<String> = sqlx::query_scalar("SELECT role FROM organization_members WHERE org_id = ? AND user_id = ?")
+            .bind(org_id)
+            .bind(user_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(role.as_deref().and_then(OrgRole::parse))
+    }
+
+    pub async fn list_org_members(&self, org_id: &str) -> Result<Vec<OrgMember>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT m.user_id, m.role, m.joined_at, u.email, u.display_name FROM organization_members m JOIN users u ON u.id = m.user_id WHERE m.org_id = ? ORDER BY u.email")
+            .bind(org_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(|row| {
+            let role: String = row.try_get("role")?;
+            let joined_at: String = row.try_get("joined_at")?;
+            Ok(OrgMember {
+                user_id: row.try_get("user_id")?,
+                email: row.try_get("email")?,
+                display_name: row.try_get("display_name")?,
+                role: OrgRole::parse(&role).unwrap_or(OrgRole::Member),
+                joined_at: DateTime::parse_from_rfc3339(&joined_at)?.with_timezone(&Utc),
+            })
+        }).collect()
+    }
+
+    /// Add a member or change the role of an existing one
+    pub async fn set_org_member(&self, org_id: &str, user_id: &str, role: OrgRole) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO organization_members (org_id, user_id, role, joined_at) VALUES (?, ?, ?, ?) ON CONFLICT (org_id, user_id) DO UPDATE SET role = excluded.role")
+            .bind(org_id)
+            .bind(user_id)
+            .bind(role.as_str())
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove a member; false if the user was no member
+    pub async fn remove_org_member(&self, org_id: &str, user_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM organization_members WHERE org_id = ? AND user_id = ?")
+            .bind(org_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    pub async fn count_org_devices(&self, org_id: &str) -> Result<u64, Box<dyn std::error::Error>> {
+        let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM devices WHERE org_id = ?")
+            .bind(org_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(count as u64)
+    }
+
+    /// Delete an organization with its memberships and groups (devices must have been moved out)
+    pub async fn delete_organization(&self, org_id: &str) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+        for statement in [
+            "DELETE FROM device_group_permissions WHERE group_id IN (SELECT id FROM device_groups WHERE org_id = ?)",
+            "DELETE FROM device_group_members WHERE group_id IN (SELECT id FROM device_groups WHERE org_id = ?)",
+            "DELETE FROM device_groups WHERE org_id = ?",
+            "DELETE FROM organization_members WHERE org_id = ?",
+            "DELETE FROM organizations WHERE id = ?",
+        ] {
+            sqlx::query(statement).bind(org_id).execute(&mut *tx).await?;
         }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Move a device into an organization (Some) or back to its owner only (None)
+    pub async fn set_device_org(&self, device_id: &str, org_id: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET org_id = ? WHERE mac_address = ?")
+            .bind(org_id)
+            .bind(device_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Organization of a device and whether the user is a member of it; None for devices without one
+    pub async fn device_org_membership(&self, device_id: &str, user_id: &str) -> Result<Option<(String, bool)>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT d.org_id, m.user_id AS member FROM devices d LEFT JOIN organization_members m ON m.org_id = d.org_id AND m.user_id = ? WHERE d.mac_address = ? AND d.org_id IS NOT NULL")
+            .bind(user_id)
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|row| (row.get("org_id"), row.get::<Option<String>, _>("member").is_some())))
+    }
+
+    /// Whether `recipient_id` may take over the devices of `user_id` on account deletion:
+    /// both share an organization and the recipient is a member of every organization
+    /// the devices belong to
+    pub async fn can_take_over_devices(&self, user_id: &str, recipient_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let shares_org: bool = sqlx::query_scalar("SELECT EXISTS(SELECT 1 FROM organization_members a JOIN organization_members b ON b.org_id = a.org_id WHERE a.user_id = ? AND b.user_id = ?)")
+            .bind(user_id)
+            .bind(recipient_id)
+            .fetch_one(&self.pool)
+            .await?;
+        let foreign_devices: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM devices d WHERE d.owner_id = ? AND d.org_id IS NOT NULL AND NOT EXISTS (SELECT 1 FROM organization_members m WHERE m.org_id = d.org_id AND m.user_id = ?)")
+            .bind(user_id)
+            .bind(recipient_id)
+            .fetch_one(&self.pool)
+            .await?;
+        Ok(shares_org && foreign_devices == 0)
+    }
 
-        Ok(users)
+    // ========================================================================
+    // DEVICE PROFILE METHODS
+    // ========================================================================
+
+    fn row_to_device_profile(row: &sqlx::sqlite::SqliteRow) -> Result<DeviceProfile, Box<dyn std::error::Error>> {
+        let variables: String = row.try_get("variables")?;
+        let start_options: String = row.try_get("start_options")?;
+        let created_at: String = row.try_get("created_at")?;
+        let updated_at: String = row.try_get("updated_at")?;
+        Ok(DeviceProfile {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            variables: serde_json::from_str::<Vec<VariableSpec>>(&variables)?,
+            start_options: serde_json::from_str(&start_options)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at)?.with_timezone(&Utc),
+        })
     }
 
-    pub async fn delete_user(&self, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        // Zuerst Permissions löschen
-        sqlx::query("DELETE FROM device_permissions WHERE user_id = ?")
-            .bind(user_id)
+    pub async fn create_device_profile(&self, profile: &DeviceProfile) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO device_profiles (id, owner_id, name, description, variables, start_options, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&profile.id)
+            .bind(&profile.owner_id)
+            .bind(&profile.name)
+            .bind(&profile.description)
+            .bind(serde_json::to_string(&profile.variables)?)
+            .bind(serde_json::to_string(&profile.start_options)?)
+            .bind(profile.created_at.to_rfc3339())
+            .bind(profile.updated_at.to_rfc3339())
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
 
-        // Devices des Users auf Guest übertragen (FK-Constraint: owner_id muss existieren)
-        sqlx::query("UPDATE devices SET owner_id = 'guest' WHERE owner_id = ?")
-            .bind(user_id)
-            .execute(&self.pool)
+    pub async fn get_device_profile(&self, profile_id: &str) -> Result<Option<DeviceProfile>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM device_profiles WHERE id = ?")
+            .bind(profile_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_device_profile).transpose()
+    }
+
+    /// Profiles of a user by name
+    pub async fn list_device_profiles(&self, owner_id: &str) -> Result<Vec<DeviceProfile>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM device_profiles WHERE owner_id = ? ORDER BY name")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
             .await?;
+        rows.iter().map(Self::row_to_device_profile).collect()
+    }
 
-        // Dann User löschen
-        sqlx::query("DELETE FROM users WHERE id = ?")
-            .bind(user_id)
+    pub async fn update_device_profile(&self, profile: &DeviceProfile) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE device_profiles SET name = ?, description = ?, variables = ?, start_options = ?, updated_at = ? WHERE id = ?")
+            .bind(&profile.name)
+            .bind(&profile.description)
+            .bind(serde_json::to_string(&profile.variables)?)
+            .bind(serde_json::to_string(&profile.start_options)?)
+            .bind(profile.updated_at.to_rfc3339())
+            .bind(&profile.id)
             .execute(&self.pool)
             .await?;
-
         Ok(())
     }
 
-    pub async fn update_user_admin_status(&self, user_id: &str, is_admin: bool) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query("UPDATE users SET is_admin = ? WHERE id = ?")
-            .bind(is_admin)
-            .bind(user_id)
+    /// Delete a profile and unassign it; returns the devices it was assigned to
+    pub async fn delete_device_profile(&self, profile_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let devices = self.list_devices_with_profile(profile_id).await?;
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("UPDATE devices SET profile_id = NULL WHERE profile_id = ?")
+            .bind(profile_id)
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query("DELETE FROM device_profiles WHERE id = ?")
+            .bind(profile_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        Ok(devices)
+    }
+
+    /// Assign (Some) or remove (None) the profile of a device; false if the device does not exist
+    pub async fn set_device_profile(&self, device_id: &str, profile_id: Option<&str>) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE devices SET profile_id = ? WHERE mac_address = ?")
+            .bind(profile_id)
+            .bind(device_id)
             .execute(&self.pool)
             .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
-        Ok(())
+    pub async fn list_devices_with_profile(&self, profile_id: &str) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT mac_address FROM devices WHERE profile_id = ? ORDER BY mac_address")
+            .bind(profile_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(rows.iter().map(|row| row.get("mac_address")).collect())
     }
 
-    // ============================================================================
-    // INITIAL USERS MANAGEMENT - Lädt und erstellt initiale User aus Konfiguration
-    // ============================================================================
+    /// All devices with an assigned profile, with that profile (loaded into the device manager)
+    pub async fn list_device_profile_assignments(&self) -> Result<Vec<(String, DeviceProfile)>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT d.mac_address AS device_id, p.* FROM devices d JOIN device_profiles p ON p.id = d.profile_id")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter()
+            .map(|row| Ok((row.try_get("device_id")?, Self::row_to_device_profile(row)?)))
+            .collect()
+    }
 
-    fn load_initial_users() -> Result<InitialUsersFile, Box<dyn std::error::Error>> {
-        let config_path = "data/initial_users.json";
-        
-        if !std::path::Path::new(config_path).exists() {
-            tracing::warn!("Initial users config file not found: {}", config_path);
-            // Fallback zu Standard Admin-User
-            return Ok(InitialUsersFile {
-                users: vec![InitialUserConfig {
-                    email: "admin@drawing-app.local".to_string(),
-                    display_name: "Administrator".to_string(),
-                    password: "admin123".to_string(),
-                    is_admin: true,
-                }],
-            });
-        }
+    // ========================================================================
+    // COMMAND TEMPLATE METHODS
+    // ========================================================================
 
-        let config_content = fs::read_to_string(config_path)?;
-        let config: InitialUsersFile = serde_json::from_str(&config_content)?;
-        
-        tracing::info!("Loaded {} initial users from config", config.users.len());
-        Ok(config)
+    fn row_to_command_template(row: &sqlx::sqlite::SqliteRow) -> Result<CommandTemplate, Box<dyn std::error::Error>> {
+        let steps: String = row.try_get("steps")?;
+        let parameters: String = row.try_get("parameters")?;
+        let created_at: String = row.try_get("created_at")?;
+        let updated_at: String = row.try_get("updated_at")?;
+        Ok(CommandTemplate {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            steps: serde_json::from_str(&steps)?,
+            parameters: serde_json::from_str(&parameters)?,
+            continue_on_error: row.try_get("continue_on_error")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+            updated_at: DateTime::parse_from_rfc3339(&updated_at)?.with_timezone(&Utc),
+        })
     }
 
-    async fn create_initial_users(&self) -> Result<(), Box<dyn std::error::Error>> {
-        // Prüfen ob bereits User existieren
-        let user_count = sqlx::query("SELECT COUNT(*) as count FROM users")
-            .fetch_one(&self.pool)
-            .await?
-            .get::<i64, _>("count");
+    /// Store a new template; fails if the owner already has one with this name
+    pub async fn create_command_template(&self, template: &CommandTemplate) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO command_templates (id, owner_id, name, description, steps, parameters, continue_on_error, created_at, updated_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&template.id)
+            .bind(&template.owner_id)
+            .bind(&template.name)
+            .bind(&template.description)
+            .bind(serde_json::to_string(&template.steps)?)
+            .bind(serde_json::to_string(&template.parameters)?)
+            .bind(template.continue_on_error)
+            .bind(template.created_at.to_rfc3339())
+            .bind(template.updated_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 
-        if user_count > 0 {
-            tracing::info!("Database contains {} existing users, skipping initial user creation", user_count);
-            return Ok(());
-        }
+    pub async fn get_command_template(&self, template_id: &str) -> Result<Option<CommandTemplate>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM command_templates WHERE id = ?")
+            .bind(template_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_command_template).transpose()
+    }
 
-        // Initiale User aus Konfiguration laden
-        let config = Self::load_initial_users()?;
-        let mut created_count = 0;
+    pub async fn get_command_template_by_name(&self, owner_id: &str, name: &str) -> Result<Option<CommandTemplate>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM command_templates WHERE owner_id = ? AND name = ?")
+            .bind(owner_id)
+            .bind(name)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_command_template).transpose()
+    }
 
-        for user_config in config.users {
-            tracing::debug!("Creating initial user: {}", user_config.email);
-            
-            let db_user = DatabaseUser {
-                id: Uuid::new_v4().to_string(),
-                email: user_config.email.clone(),
-                display_name: user_config.display_name,
-                password_hash: hash(&user_config.password, DEFAULT_COST)?,
-                created_at: Utc::now(),
-                is_admin: user_config.is_admin,
-            };
+    /// Templates of a user by name
+    pub async fn list_command_templates(&self, owner_id: &str) -> Result<Vec<CommandTemplate>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM command_templates WHERE owner_id = ? ORDER BY name")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_command_template).collect()
+    }
 
-            match self.create_user(db_user).await {
-                Ok(_) => {
-                    created_count += 1;
-                    if user_config.is_admin {
-                        tracing::info!("Created initial admin user: {} / {}", user_config.email, user_config.password);
-                    } else {
-                        tracing::info!("Created initial user: {} / {}", user_config.email, user_config.password);
-                    }
-                }
-                Err(e) => {
-                    tracing::error!("Failed to create initial user {}: {:?}", user_config.email, e);
-                }
-            }
-        }
+    pub async fn update_command_template(&self, template: &CommandTemplate) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE command_templates SET name = ?, description = ?, steps = ?, parameters = ?, continue_on_error = ?, updated_at = ? WHERE id = ?")
+            .bind(&template.name)
+            .bind(&template.description)
+            .bind(serde_json::to_string(&template.steps)?)
+            .bind(serde_json::to_string(&template.parameters)?)
+            .bind(template.continue_on_error)
+            .bind(template.updated_at.to_rfc3339())
+            .bind(&template.id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 
-        if created_count > 0 {
-            tracing::info!("Successfully created {} initial users", created_count);
-        }
+    /// Delete a template; false if it did not exist
+    pub async fn delete_command_template(&self, template_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM command_templates WHERE id = ?")
+            .bind(template_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
+    /// Append a log line and keep only the newest `keep` lines of the script
+    pub async fn insert_script_log(&self, log: &ScriptLog, keep: usize) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO script_logs (script_id, timestamp, level, message) VALUES (?, ?, ?, ?)")
+            .bind(&log.script_id)
+            .bind(log.timestamp.to_rfc3339())
+            .bind(&log.level)
+            .bind(&log.message)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM script_logs WHERE script_id = ? AND id <= (SELECT id FROM script_logs WHERE script_id = ? ORDER BY id DESC LIMIT 1 OFFSET ?)")
+            .bind(&log.script_id)
+            .bind(&log.script_id)
+            .bind(keep as i64)
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
-    // ============================================================================
-    // DEVICE MANAGEMENT - CRUD Operationen für Devices
-    // ============================================================================
+    /// Newest log lines of a script first
+    pub async fn list_script_logs(&self, script_id: &str, limit: usize) -> Result<Vec<ScriptLog>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM script_logs WHERE script_id = ? ORDER BY id DESC LIMIT ?")
+            .bind(script_id)
+            .bind(limit as i64)
+            .fetch_all(&self.pool)
+            .await?;
 
-    pub async fn create_device(&self, device: Device) -> Result<(), Box<dyn std::error::Error>> {
-        let status_str = match device.status {
-            DeviceStatus::Online => "Online",
-            DeviceStatus::Offline => "Offline", 
-            DeviceStatus::Error => "Error",
-            DeviceStatus::Updating => "Updating",
-            DeviceStatus::Maintenance => "Maintenance",
-        };
-        
+        let mut logs = Vec::with_capacity(rows.len());
+        for row in rows {
+            let timestamp: String = row.try_get("timestamp")?;
+            logs.push(ScriptLog {
+                id: row.try_get("id")?,
+                script_id: row.try_get("script_id")?,
+                timestamp: DateTime::parse_from_rfc3339(&timestamp)?.with_timezone(&Utc),
+                level: row.try_get("level")?,
+                message: row.try_get("message")?,
+            });
+        }
+        Ok(logs)
+    }
+
+    // ========================================================================
+    // CRASH REPORT METHODS
+    // ========================================================================
+
+    pub async fn insert_crash_report(&self, report: &CrashReport, core_dump: Option<&[u8]>) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query(
-            "INSERT INTO devices (mac_address, name, alias, owner_id, ip_address, status, maintenance_mode, firmware_version, last_seen, created_at, connection_type) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+            r#"
+            INSERT INTO crash_reports (id, device_id, reset_reason, source, firmware_version, uptime_secs, panic_reason, backtrace, core_dump, core_dump_size, created_at)
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+            "#
         )
-        .bind(&device.mac_address)
-        .bind(&device.name)
-        .bind(&device.alias)
-        .bind(&device.owner_id)
-        .bind(&device.ip_address)
-        .bind(status_str)
-        .bind(device.maintenance_mode)
-        .bind(&device.firmware_version)
-        .bind(device.last_seen.to_rfc3339())
-        .bind(device.created_at.to_rfc3339())
-        .bind(&device.connection_type)
+        .bind(&report.id)
+        .bind(&report.device_id)
+        .bind(report.reset_reason.as_str())
+        .bind(&report.source)
+        .bind(&report.firmware_version)
+        .bind(report.uptime_secs.map(|secs| secs as i64))
+        .bind(&report.panic_reason)
+        .bind(&report.backtrace)
+        .bind(core_dump)
+        .bind(report.core_dump_size.map(|size| size as i64))
+        .bind(report.created_at.to_rfc3339())
         .execute(&self.pool)
         .await?;
-
-        // Owner-Berechtigung hinzufügen
-        self.set_device_permission(&device.mac_address, &device.owner_id, "O").await?;
-
         Ok(())
     }
 
-    pub async fn get_device_by_id(&self, device_id: &str) -> Result<Option<Device>, Box<dyn std::error::Error>> {
-        let row = sqlx::query("SELECT * FROM devices WHERE mac_address = ?")
-            .bind(device_id)
-            .fetch_optional(&self.pool)
-            .await?;
-
-        match row {
-            Some(row) => {
-                let created_at_str: String = row.get("created_at");
-                let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-                let last_seen_str: String = row.get("last_seen");
-                let last_seen = DateTime::parse_from_rfc3339(&last_seen_str)?.with_timezone(&Utc);
-                
-                let status_str: String = row.get("status");
-                let status = match status_str.as_str() {
-                    "Online" => DeviceStatus::Online,
-                    "Offline" => DeviceStatus::Offline,
-                    "Error" => DeviceStatus::Error,
-                    "Updating" => DeviceStatus::Updating,
-                    "Maintenance" => DeviceStatus::Maintenance,
-                    _ => DeviceStatus::Offline,
-                };
-                
-                Ok(Some(Device {
-                    mac_address: row.get("mac_address"),
-                    name: row.get("name"),
-                    alias: row.try_get::<Option<String>, _>("alias").unwrap_or(None),
-                    owner_id: row.get("owner_id"),
-                    ip_address: row.get("ip_address"),
-                    status,
-                    maintenance_mode: row.get("maintenance_mode"),
-                    firmware_version: row.get("firmware_version"),
-                    last_seen,
-                    created_at,
-                    connection_type: row.try_get("connection_type").unwrap_or_else(|_| "tcp".to_string()),
-                }))
-            }
-            None => Ok(None)
-        }
+    fn row_to_crash_report(row: &sqlx::sqlite::SqliteRow) -> Result<CrashReport, Box<dyn std::error::Error>> {
+        let reset_reason: String = row.try_get("reset_reason")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(CrashReport {
+            id: row.try_get("id")?,
+            device_id: row.try_get("device_id")?,
+            reset_reason: ResetReason::parse(&serde_json::Value::String(reset_reason)).unwrap_or(ResetReason::Unknown),
+            source: row.try_get("source")?,
+            firmware_version: row.try_get("firmware_version")?,
+            uptime_secs: row.try_get::<Option<i64>, _>("uptime_secs")?.map(|secs| secs as u64),
+            panic_reason: row.try_get("panic_reason")?,
+            backtrace: row.try_get("backtrace")?,
+            core_dump_size: row.try_get::<Option<i64>, _>("core_dump_size")?.map(|size| size as usize),
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
     }
 
-    pub async fn list_user_devices(&self, user_id: &str) -> Result<Vec<(Device, String)>, Box<dyn std::error::Error>> {
+    /// Newest reports of a device first, optionally of one reset reason
+    pub async fn list_crash_reports(&self, device_id: &str, reason: Option<ResetReason>, limit: usize) -> Result<Vec<CrashReport>, Box<dyn std::error::Error>> {
         let rows = sqlx::query(
             r#"
-            SELECT d.*, dp.permission
-            FROM devices d
-            INNER JOIN device_permissions dp ON d.mac_address = dp.device_id
-            WHERE dp.user_id = ?
-            ORDER BY d.created_at DESC
+            SELECT id, device_id, reset_reason, source, firmware_version, uptime_secs, panic_reason, backtrace, core_dump_size, created_at
+            FROM crash_reports WHERE device_id = ? AND (? IS NULL OR reset_reason = ?)
+            ORDER BY created_at DESC LIMIT ?
             "#
         )
-        .bind(user_id)
+        .bind(device_id)
+        .bind(reason.map(|reason| reason.as_str()))
+        .bind(reason.map(|reason| reason.as_str()))
+        .bind(limit as i64)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut device_list = Vec::new();
-        for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            let last_seen_str: String = row.get("last_seen");
-            let last_seen = DateTime::parse_from_rfc3339(&last_seen_str)?.with_timezone(&Utc);
-            
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Online" => DeviceStatus::Online,
-                "Offline" => DeviceStatus::Offline,
-                "Error" => DeviceStatus::Error,
-                "Updating" => DeviceStatus::Updating,
-                "Maintenance" => DeviceStatus::Maintenance,
-                _ => DeviceStatus::Offline,
-            };
-
-            let device = Device {
-                mac_address: row.get("mac_address"),
-                name: row.get("name"),
-                alias: row.try_get::<Option<String>, _>("alias").unwrap_or(None),
-                owner_id: row.get("owner_id"),
-                ip_address: row.get("ip_address"),
-                status,
-                maintenance_mode: row.get("maintenance_mode"),
-                firmware_version: row.get("firmware_version"),
-                last_seen,
-                created_at,
-                connection_type: row.try_get("connection_type").unwrap_or_else(|_| "tcp".to_string()),
-            };
-
-            let permission: String = row.get("permission");
-            device_list.push((device, permission));
-        }
-
-        Ok(device_list)
+        rows.iter().map(Self::row_to_crash_report).collect()
     }
 
-    pub async fn list_all_devices(&self) -> Result<Vec<Device>, Box<dyn std::error::Error>> {
+    /// Number of reports and the latest report time per reset reason
+    pub async fn count_crash_reports(&self, device_id: &str) -> Result<Vec<ReasonCount>, Box<dyn std::error::Error>> {
         let rows = sqlx::query(
-            r#"
-            SELECT *
-            FROM devices
-            ORDER BY created_at DESC
-            "#
+            "SELECT reset_reason, COUNT(*) AS count, MAX(created_at) AS last_at FROM crash_reports WHERE device_id = ? GROUP BY reset_reason ORDER BY count DESC"
         )
+        .bind(device_id)
         .fetch_all(&self.pool)
         .await?;
 
-        let mut device_list = Vec::new();
+        let mut counts = Vec::with_capacity(rows.len());
         for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            let last_seen_str: String = row.get("last_seen");
-            let last_seen = DateTime::parse_from_rfc3339(&last_seen_str)?.with_timezone(&Utc);
-            
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Online" => DeviceStatus::Online,
-                "Offline" => DeviceStatus::Offline,
-                "Error" => DeviceStatus::Error,
-                "Updating" => DeviceStatus::Updating,
-                "Maintenance" => DeviceStatus::Maintenance,
-                _ => DeviceStatus::Offline,
-            };
+            let reset_reason: String = row.try_get("reset_reason")?;
+            let last_at: String = row.try_get("last_at")?;
+            counts.push(ReasonCount {
+                reset_reason: ResetReason::parse(&serde_json::Value::String(reset_reason)).unwrap_or(ResetReason::Unknown),
+                count: row.try_get::<i64, _>("count")? as u64,
+                last_at: DateTime::parse_from_rfc3339(&last_at)?.with_timezone(&Utc),
+            });
+        }
+        Ok(counts)
+    }
 
-            let device = Device {
-                mac_address: row.get("mac_address"),
-                name: row.get("name"),
-                alias: row.try_get::<Option<String>, _>("alias").unwrap_or(None),
-                owner_id: row.get("owner_id"),
-                ip_address: row.get("ip_address"),
-                status,
-                maintenance_mode: row.get("maintenance_mode"),
-                firmware_version: row.get("firmware_version"),
-                last_seen,
-                created_at,
-                connection_type: row.try_get("connection_type").unwrap_or_else(|_| "tcp".to_string()),
-            };
+    /// Report with its core dump (None if the report has none)
+    pub async fn get_crash_report(&self, device_id: &str, report_id: &str) -> Result<Option<(CrashReport, Option<Vec<u8>>)>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM crash_reports WHERE id = ? AND device_id = ?")
+            .bind(report_id)
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
 
-            device_list.push(device);
-        }
+        let Some(row) = row else { return Ok(None) };
+        Ok(Some((Self::row_to_crash_report(&row)?, row.try_get("core_dump")?)))
+    }
 
-        Ok(device_list)
+    // ========================================================================
+    // ROUTING RULE METHODS
+    // ========================================================================
+
+    fn row_to_routing_rule(row: &sqlx::sqlite::SqliteRow) -> Result<RoutingRule, Box<dyn std::error::Error>> {
+        let command: String = row.try_get("command")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(RoutingRule {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            name: row.try_get("name")?,
+            source_device_id: row.try_get("source_device_id")?,
+            trigger: row.try_get("trigger")?,
+            target_device_id: row.try_get("target_device_id")?,
+            command: serde_json::from_str(&command)?,
+            max_per_minute: row.try_get::<i64, _>("max_per_minute")? as u32,
+            enabled: row.try_get("enabled")?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
     }
 
-    pub async fn update_device(
-        &self,
-        device_id: &str,
-        name: Option<Option<&str>>,
-        alias: Option<Option<&str>>,
-        maintenance_mode: Option<bool>
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Handle name: Some(Some(value)) = set value, Some(None) = clear (not allowed for name), None = don't update
-        if let Some(name_value) = name {
-            sqlx::query("UPDATE devices SET name = ? WHERE mac_address = ?")
-                .bind(name_value) // This binds Option<&str>
-                .bind(device_id)
-                .execute(&self.pool)
-                .await?;
-        }
+    /// Store a new routing rule (ID and timestamp are set by the caller)
+    pub async fn create_routing_rule(&self, rule: &RoutingRule) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO routing_rules (id, owner_id, name, source_device_id, trigger, target_device_id, command, max_per_minute, enabled, created_at) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&rule.id)
+            .bind(&rule.owner_id)
+            .bind(&rule.name)
+            .bind(&rule.source_device_id)
+            .bind(&rule.trigger)
+            .bind(&rule.target_device_id)
+            .bind(serde_json::to_string(&rule.command)?)
+            .bind(rule.max_per_minute as i64)
+            .bind(rule.enabled)
+            .bind(rule.created_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
 
-        // Handle alias: Some(Some(value)) = set value, Some(None) = clear alias, None = don't update
-        if let Some(alias_value) = alias {
-            sqlx::query("UPDATE devices SET alias = ? WHERE mac_address = ?")
-                .bind(alias_value) // This binds Option<&str> which can be None to clear the field
-                .bind(device_id)
-                .execute(&self.pool)
-                .await?;
-        }
+    pub async fn get_routing_rule(&self, rule_id: &str) -> Result<Option<RoutingRule>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM routing_rules WHERE id = ?")
+            .bind(rule_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_routing_rule).transpose()
+    }
 
-        if let Some(maintenance_mode) = maintenance_mode {
-            sqlx::query("UPDATE devices SET maintenance_mode = ? WHERE mac_address = ?")
-                .bind(maintenance_mode)
-                .bind(device_id)
-                .execute(&self.pool)
-                .await?;
-        }
+    /// Routing rules of a user, oldest first
+    pub async fn list_routing_rules(&self, owner_id: &str) -> Result<Vec<RoutingRule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM routing_rules WHERE owner_id = ? ORDER BY created_at")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_routing_rule).collect()
+    }
 
-        Ok(())
+    /// Enabled routing rules of all users (loaded by the routing engine)
+    pub async fn list_enabled_routing_rules(&self) -> Result<Vec<RoutingRule>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM routing_rules WHERE enabled = 1 ORDER BY created_at")
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_routing_rule).collect()
     }
 
-    pub async fn update_device_status(&self, device_id: &str, status: &DeviceStatus, ip_address: Option<&str>, firmware_version: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
-        let status_str = match status {
-            DeviceStatus::Online => "Online",
-            DeviceStatus::Offline => "Offline",
-            DeviceStatus::Error => "Error", 
-            DeviceStatus::Updating => "Updating",
-            DeviceStatus::Maintenance => "Maintenance",
-        };
-        
-        let now = Utc::now().to_rfc3339();
-        
-        sqlx::query("UPDATE devices SET status = ?, ip_address = ?, firmware_version = ?, last_seen = ? WHERE mac_address = ?")
-            .bind(status_str)
-            .bind(ip_address)
-            .bind(firmware_version)
-            .bind(now)
-            .bind(device_id)
+    /// Save name, devices, trigger, command, rate limit and enabled flag of a rule
+    pub async fn update_routing_rule(&self, rule: &RoutingRule) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("UPDATE routing_rules SET name = ?, source_device_id = ?, trigger = ?, target_device_id = ?, command = ?, max_per_minute = ?, enabled = ? WHERE id = ?")
+            .bind(&rule.name)
+            .bind(&rule.source_device_id)
+            .bind(&rule.trigger)
+            .bind(&rule.target_device_id)
+            .bind(serde_json::to_string(&rule.command)?)
+            .bind(rule.max_per_minute as i64)
+            .bind(rule.enabled)
+            .bind(&rule.id)
             .execute(&self.pool)
             .await?;
-
         Ok(())
     }
 
-    pub async fn delete_device(&self, device_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        // Zuerst Berechtigungen löschen
-        sqlx::query("DELETE FROM device_permissions WHERE device_id = ?")
-            .bind(device_id)
+    pub async fn delete_routing_rule(&self, rule_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM routing_rules WHERE id = ?")
+            .bind(rule_id)
             .execute(&self.pool)
             .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
-        // Dann Device löschen
-        sqlx::query("DELETE FROM devices WHERE mac_address = ?")
-            .bind(device_id)
+    // ========================================================================
+    // RECORDING METHODS
+    // ========================================================================
+
+    fn row_to_recording(row: &sqlx::sqlite::SqliteRow) -> Result<Recording, Box<dyn std::error::Error>> {
+        let started_at: String = row.try_get("started_at")?;
+        Ok(Recording {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            device_id: row.try_get("device_id")?,
+            name: row.try_get("name")?,
+            started_at: DateTime::parse_from_rfc3339(&started_at)?.with_timezone(&Utc),
+            duration_ms: row.try_get("duration_ms")?,
+            event_count: row.try_get::<i64, _>("event_count")? as usize,
+            truncated: row.try_get("truncated")?,
+        })
+    }
+
+    pub async fn insert_recording(&self, recording: &Recording, events: &[RecordedEvent]) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO device_recordings (id, owner_id, device_id, name, started_at, duration_ms, event_count, truncated, events) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?)")
+            .bind(&recording.id)
+            .bind(&recording.owner_id)
+            .bind(&recording.device_id)
+            .bind(&recording.name)
+            .bind(Self::sortable_timestamp(recording.started_at))
+            .bind(recording.duration_ms)
+            .bind(recording.event_count as i64)
+            .bind(recording.truncated)
+            .bind(serde_json::to_string(events)?)
             .execute(&self.pool)
             .await?;
-
         Ok(())
     }
 
-    /// Get all Devices by connection type (e.g., "tcp" or "uart")
-    pub async fn get_devices_by_connection_type(
-        &self,
-        connection_type: &str,
-    ) -> Result<Vec<Device>, Box<dyn std::error::Error>> {
+    /// Recordings of a user (optionally of one device), newest first, without events
+    pub async fn list_recordings(&self, owner_id: &str, device_id: Option<&str>) -> Result<Vec<Recording>, Box<dyn std::error::Error>> {
         let rows = sqlx::query(
-            "SELECT * FROM devices WHERE connection_type = ? ORDER BY last_seen DESC"
+            "SELECT id, owner_id, device_id, name, started_at, duration_ms, event_count, truncated FROM device_recordings WHERE owner_id = ? AND (? IS NULL OR device_id = ?) ORDER BY started_at DESC"
         )
-        .bind(connection_type)
+        .bind(owner_id)
+        .bind(device_id)
+        .bind(device_id)
         .fetch_all(&self.pool)
         .await?;
+        rows.iter().map(Self::row_to_recording).collect()
+    }
 
-        let mut devices = Vec::new();
-        for row in rows {
-            let created_at_str: String = row.get("created_at");
-            let created_at = DateTime::parse_from_rfc3339(&created_at_str)?.with_timezone(&Utc);
-            let last_seen_str: String = row.get("last_seen");
-            let last_seen = DateTime::parse_from_rfc3339(&last_seen_str)?.with_timezone(&Utc);
-
-            let status_str: String = row.get("status");
-            let status = match status_str.as_str() {
-                "Online" => DeviceStatus::Online,
-                "Offline" => DeviceStatus::Offline,
-                "Error" => DeviceStatus::Error,
-                "Updating" => DeviceStatus::Updating,
-                "Maintenance" => DeviceStatus::Maintenance,
-                _ => DeviceStatus::Offline,
-            };
-
-            let device = Device {
-                mac_address: row.get("mac_address"),
-                name: row.get("name"),
-                alias: row.try_get::<Option<String>, _>("alias").unwrap_or(None),
-                owner_id: row.get("owner_id"),
-                ip_address: row.get("ip_address"),
-                status,
-                maintenance_mode: row.get("maintenance_mode"),
-                firmware_version: row.get("firmware_version"),
-                last_seen,
-                created_at,
-                connection_type: row.try_get("connection_type").unwrap_or_else(|_| "tcp".to_string()),
-            };
+    pub async fn get_recording(&self, recording_id: &str) -> Result<Option<Recording>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT id, owner_id, device_id, name, started_at, duration_ms, event_count, truncated FROM device_recordings WHERE id = ?")
+            .bind(recording_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_recording).transpose()
+    }
 
-            devices.push(device);
+    pub async fn get_recording_events(&self, recording_id: &str) -> Result<Vec<RecordedEvent>, Box<dyn std::error::Error>> {
+        let events: Option<String> = sqlx::query_scalar("SELECT events FROM device_recordings WHERE id = ?")
+            .bind(recording_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        match events {
+            Some(events) => Ok(serde_json::from_str(&events)?),
+            None => Ok(Vec::new()),
         }
+    }
 
-        Ok(devices)
+    pub async fn delete_recording(&self, recording_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_recordings WHERE id = ?")
+            .bind(recording_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
     }
 
-    /// Create or update device from discovery (auto-save discovered devices)
-    /// If device exists, update IP and last_seen. If not, create as guest-owned device.
-    pub async fn upsert_discovered_device(
-        &self,
-        mac_address: String,
-        device_name: String,
-        ip_address: Option<String>,
-        connection_type: Option<String>,
-    ) -> Result<(), Box<dyn std::error::Error>> {
-        // Check if device already exists
-        let existing = self.get_device_by_id(&mac_address).await?;
+    // ========================================================================
+    // VARIABLE SNAPSHOT METHODS
+    // ========================================================================
 
-        if let Some(mut device) = existing {
-            // Device exists - update IP and last_seen
-            device.ip_address = ip_address;
-            device.last_seen = Utc::now();
+    fn row_to_variable_snapshot(row: &sqlx::sqlite::SqliteRow) -> Result<DeviceSnapshot, Box<dyn std::error::Error>> {
+        let variables: String = row.try_get("variables")?;
+        let created_at: String = row.try_get("created_at")?;
+        Ok(DeviceSnapshot {
+            id: row.try_get("id")?,
+            owner_id: row.try_get("owner_id")?,
+            device_id: row.try_get("device_id")?,
+            name: row.try_get("name")?,
+            variables: serde_json::from_str(&variables)?,
+            created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
+        })
+    }
 
-            sqlx::query(
-                "UPDATE devices SET ip_address = ?, last_seen = ? WHERE mac_address = ?"
-            )
-            .bind(&device.ip_address)
-            .bind(device.last_seen.to_rfc3339())
-            .bind(&mac_address)
+    pub async fn insert_variable_snapshot(&self, snapshot: &DeviceSnapshot) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO device_variable_snapshots (id, owner_id, device_id, name, variables, created_at) VALUES (?, ?, ?, ?, ?, ?)")
+            .bind(&snapshot.id)
+            .bind(&snapshot.owner_id)
+            .bind(&snapshot.device_id)
+            .bind(&snapshot.name)
+            .bind(serde_json::to_string(&snapshot.variables)?)
+            .bind(Self::sortable_timestamp(snapshot.created_at))
             .execute(&self.pool)
             .await?;
+        Ok(())
+    }
 
-            tracing::debug!("Updated existing device in DB: {}", mac_address);
-        } else {
-            // Device doesn't exist - create new one with guest owner
-            let new_device = Device {
-                mac_address: mac_address.clone(),
-                name: device_name,
-                alias: None,
-                owner_id: "guest".to_string(),
-                ip_address,
-                status: DeviceStatus::Offline,
-                maintenance_mode: false,
-                firmware_version: None,
-                last_seen: Utc::now(),
-                created_at: Utc::now(),
-                connection_type: connection_type.unwrap_or_else(|| "tcp".to_string()),
-            };
+    /// Snapshots a user took of a device, newest first
+    pub async fn list_variable_snapshots(&self, owner_id: &str, device_id: &str) -> Result<Vec<DeviceSnapshot>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM device_variable_snapshots WHERE owner_id = ? AND device_id = ? ORDER BY created_at DESC")
+            .bind(owner_id)
+            .bind(device_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_variable_snapshot).collect()
+    }
 
-            self.create_device(new_device).await?;
-            tracing::info!("Auto-saved new discovered device to DB: {}", mac_address);
-        }
+    pub async fn get_variable_snapshot(&self, snapshot_id: &str) -> Result<Option<DeviceSnapshot>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM device_variable_snapshots WHERE id = ?")
+            .bind(snapshot_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_variable_snapshot).transpose()
+    }
 
-        Ok(())
+    pub async fn delete_variable_snapshot(&self, snapshot_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_variable_snapshots WHERE id = ?")
+            .bind(snapshot_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
     }
 
-    // ============================================================================
-    // DEVICE PERMISSIONS - Berechtigungsverwaltung
-    // ============================================================================
+    // ========================================================================
+    // DEVICE CHAT METHODS
+    // ========================================================================
 
-    pub async fn set_device_permission(&self, device_id: &str, user_id: &str, permission: &str) -> Result<(), Box<dyn std::error::Error>> {
+    /// Store a chat event and keep only the newest `keep` messages of the device
+    pub async fn insert_chat_message(&self, device_id: &str, user_id: &str, event: &serde_json::Value, keep: usize) -> Result<(), Box<dyn std::error::Error>> {
+        let mut tx = self.pool.begin().await?;
+        sqlx::query("INSERT INTO device_chat_messages (device_id, user_id, event, created_at) VALUES (?, ?, ?, ?)")
+            .bind(device_id)
+            .bind(user_id)
+            .bind(event.to_string())
+            .bind(Self::sortable_timestamp(Utc::now()))
+            .execute(&mut *tx)
+            .await?;
         sqlx::query(
-            "INSERT OR REPLACE INTO device_permissions (device_id, user_id, permission) VALUES (?, ?, ?)"
+            "DELETE FROM device_chat_messages WHERE device_id = ? AND id NOT IN \
+             (SELECT id FROM device_chat_messages WHERE device_id = ? ORDER BY id DESC LIMIT ?)"
         )
-        .bind(device_id)
-        .bind(user_id)
-        .bind(permission)
-        .execute(&self.pool)
-        .await?;
-
+            .bind(device_id)
+            .bind(device_id)
+            .bind(keep as i64)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
         Ok(())
     }
 
-    pub async fn remove_device_permission(&self, device_id: &str, user_id: &str) -> Result<(), Box<dyn std::error::Error>> {
-        sqlx::query("DELETE FROM device_permissions WHERE device_id = ? AND user_id = ?")
+    /// Stored chat events of a device, oldest first
+    pub async fn list_chat_messages(&self, device_id: &str) -> Result<Vec<serde_json::Value>, Box<dyn std::error::Error>> {
+        let events: Vec<String> = sqlx::query_scalar("SELECT event FROM device_chat_messages WHERE device_id = ? ORDER BY id")
             .bind(device_id)
-            .bind(user_id)
+            .fetch_all(&self.pool)
+            .await?;
+        Ok(events.iter().filter_map(|event| serde_json::from_str(event).ok()).collect())
+    }
+
+    // ========================================================================
+    // ENROLLMENT TOKEN METHODS
+    // ========================================================================
+
+    pub async fn insert_enrollment_token(&self, token: &EnrollmentToken, token_hash: &str) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            "INSERT INTO enrollment_tokens (id, token_hash, device_id, owner_id, org_id, created_at, expires_at, used_at) VALUES (?, ?, ?, ?, ?, ?, ?, NULL)"
+        )
+            .bind(&token.id)
+            .bind(token_hash)
+            .bind(&token.device_id)
+            .bind(&token.owner_id)
+            .bind(&token.org_id)
+            .bind(token.created_at.to_rfc3339())
+            .bind(token.expires_at.to_rfc3339())
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    fn row_to_enrollment_token(row: &sqlx::sqlite::SqliteRow) -> Result<EnrollmentToken, Box<dyn std::error::Error>> {
+        let parse = |value: String| DateTime::parse_from_rfc3339(&value).map(|time| time.with_timezone(&Utc));
+        Ok(EnrollmentToken {
+            id: row.try_get("id")?,
+            device_id: row.try_get("device_id")?,
+            owner_id: row.try_get("owner_id")?,
+            org_id: row.try_get("org_id")?,
+            created_at: parse(row.try_get("created_at")?)?,
+            expires_at: parse(row.try_get("expires_at")?)?,
+            used_at: row.try_get::<Option<String>, _>("used_at")?.map(parse).transpose()?,
+        })
+    }
+
+    pub async fn get_enrollment_token_by_hash(&self, token_hash: &str) -> Result<Option<EnrollmentToken>, Box<dyn std::error::Error>> {
+        let row = sqlx::query("SELECT * FROM enrollment_tokens WHERE token_hash = ?")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        row.as_ref().map(Self::row_to_enrollment_token).transpose()
+    }
+
+    /// Tokens a user minted, newest first
+    pub async fn list_enrollment_tokens(&self, owner_id: &str) -> Result<Vec<EnrollmentToken>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM enrollment_tokens WHERE owner_id = ? ORDER BY created_at DESC")
+            .bind(owner_id)
+            .fetch_all(&self.pool)
+            .await?;
+        rows.iter().map(Self::row_to_enrollment_token).collect()
+    }
+
+    /// Spend a token; false if it was used already
+    pub async fn mark_enrollment_token_used(&self, id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("UPDATE enrollment_tokens SET used_at = ? WHERE id = ? AND used_at IS NULL")
+            .bind(Utc::now().to_rfc3339())
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Revoke a token of the user; false if there is none with this ID
+    pub async fn delete_enrollment_token(&self, id: &str, owner_id: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM enrollment_tokens WHERE id = ? AND owner_id = ?")
+            .bind(id)
+            .bind(owner_id)
             .execute(&self.pool)
             .await?;
+        Ok(result.rows_affected() > 0)
+    }
 
+    // ========================================================================
+    // DEVICE AUDIT LOG METHODS
+    // ========================================================================
+
+    pub async fn insert_audit_entry(&self, device_id: &str, actor_id: &str, action: AuditAction, details: &serde_json::Value, created_at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO device_audit_log (device_id, actor_id, action, details, created_at) VALUES (?, ?, ?, ?, ?)")
+            .bind(device_id)
+            .bind(actor_id)
+            .bind(action.as_str())
+            .bind(details.to_string())
+            .bind(Self::sortable_timestamp(created_at))
+            .execute(&self.pool)
+            .await?;
         Ok(())
     }
 
-    pub async fn get_device_permissions(&self, device_id: &str) -> Result<Vec<DevicePermission>, Box<dyn std::error::Error>> {
-        let rows = sqlx::query("SELECT * FROM device_permissions WHERE device_id = ?")
+    /// Audit rows of a device, newest first; `before` continues after the last ID of a page
+    pub async fn list_audit_entries(&self, device_id: &str, before: Option<i64>, limit: u32) -> Result<Vec<AuditEntry>, Box<dyn std::error::Error>> {
+        let rows = sqlx::query("SELECT * FROM device_audit_log WHERE device_id = ? AND id < ? ORDER BY id DESC LIMIT ?")
             .bind(device_id)
+            .bind(before.unwrap_or(i64::MAX))
+            .bind(limit as i64)
             .fetch_all(&self.pool)
             .await?;
-
-        let mut permissions = Vec::new();
+        let mut entries = Vec::with_capacity(rows.len());
         for row in rows {
-            permissions.push(DevicePermission {
-                device_id: row.get("device_id"),
-                user_id: row.get("user_id"),
-                permission: row.get("permission"),
+            let action: String = row.try_get("action")?;
+            // Rows of actions a newer version wrote are skipped
+            let Some(action) = AuditAction::parse(&action) else { continue };
+            let details: String = row.try_get("details")?;
+            let created_at: String = row.try_get("created_at")?;
+            entries.push(AuditEntry {
+                id: row.try_get("id")?,
+                device_id: row.try_get("device_id")?,
+                actor_id: row.try_get("actor_id")?,
+                action,
+                details: serde_json::from_str(&details).unwrap_or_default(),
+                created_at: DateTime::parse_from_rfc3339(&created_at)?.with_timezone(&Utc),
             });
         }
+        Ok(entries)
+    }
 
-        Ok(permissions)
+    pub async fn prune_audit_log(&self, older_than: DateTime<Utc>) -> Result<u64, Box<dyn std::error::Error>> {
+        let result = sqlx::query("DELETE FROM device_audit_log WHERE created_at < ?")
+            .bind(Self::sortable_timestamp(older_than))
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
     }
 
-    pub async fn get_user_device_permission(&self, device_id: &str, user_id: &str) -> Result<Option<String>, Box<dyn std::error::Error>> {
-        let row = sqlx::query("SELECT permission FROM device_permissions WHERE device_id = ? AND user_id = ?")
+    // ========================================================================
+    // DEVICE AVAILABILITY METHODS
+    // ========================================================================
+
+    pub async fn insert_availability_event(&self, device_id: &str, state: AvailabilityState, at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO device_availability_events (device_id, state, at) VALUES (?, ?, ?)")
             .bind(device_id)
-            .bind(user_id)
+            .bind(state.as_str())
+            .bind(Self::sortable_timestamp(at))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn latest_availability_state(&self, device_id: &str) -> Result<Option<AvailabilityState>, Box<dyn std::error::Error>> {
+        let state: Option<String> = sqlx::query_scalar("SELECT state FROM device_availability_events WHERE device_id = ? ORDER BY at DESC, id DESC LIMIT 1")
+            .bind(device_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(state.as_deref().and_then(AvailabilityState::parse))
+    }
+
+    /// State at `from` and the changes within (from, to], oldest first
+    pub async fn availability_history(&self, device_id: &str, from: DateTime<Utc>, to: DateTime<Utc>) -> Result<(Option<AvailabilityState>, Vec<(DateTime<Utc>, AvailabilityState)>), Box<dyn std::error::Error>> {
+        let initial: Option<String> = sqlx::query_scalar("SELECT state FROM device_availability_events WHERE device_id = ? AND at <= ? ORDER BY at DESC, id DESC LIMIT 1")
+            .bind(device_id)
+            .bind(Self::sortable_timestamp(from))
+            .fetch_optional(&self.pool)
+            .await?;
+        let rows = sqlx::query("SELECT state, at FROM device_availability_events WHERE device_id = ? AND at > ? AND at <= ? ORDER BY at, id")
+            .bind(device_id)
+            .bind(Self::sortable_timestamp(from))
+            .bind(Self::sortable_timestamp(to))
+            .fetch_all(&self.pool)
+            .await?;
+        let mut changes = Vec::with_capacity(rows.len());
+        for row in rows {
+            let state: String = row.try_get("state")?;
+            let at: String = row.try_get("at")?;
+            if let Some(state) = AvailabilityState::parse(&state) {
+                changes.push((DateTime::parse_from_rfc3339(&at)?.with_timezone(&Utc), state));
+            }
+        }
+        Ok((initial.as_deref().and_then(AvailabilityState::parse), changes))
+    }
+
+    /// Mark every device as unknown from `at` on (not before its own latest change)
+    pub async fn mark_availability_unknown(&self, at: DateTime<Utc>) -> Result<u64, Box<dyn std::error::Error>> {
+        let result = sqlx::query(
+            r#"
+            INSERT INTO device_availability_events (device_id, state, at)
+            SELECT device_id, 'unknown', MAX(?, at) FROM device_availability_events AS latest
+            WHERE id = (SELECT id FROM device_availability_events WHERE device_id = latest.device_id ORDER BY at DESC, id DESC LIMIT 1)
+              AND state != 'unknown'
+            "#
+        )
+        .bind(Self::sortable_timestamp(at))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    pub async fn get_availability_heartbeat(&self) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        let at: Option<String> = sqlx::query_scalar("SELECT at FROM availability_heartbeat WHERE id = 1")
             .fetch_optional(&self.pool)
             .await?;
+        Ok(at.map(|at| DateTime::parse_from_rfc3339(&at)).transpose()?.map(|at| at.with_timezone(&Utc)))
+    }
+
+    pub async fn set_availability_heartbeat(&self, at: DateTime<Utc>) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query("INSERT INTO availability_heartbeat (id, at) VALUES (1, ?) ON CONFLICT(id) DO UPDATE SET at = excluded.at")
+            .bind(Self::sortable_timestamp(at))
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Remove changes older than `older_than`, keeping the last one of each device (its state at the cutoff)
+    pub async fn prune_availability_events(&self, older_than: DateTime<Utc>) -> Result<u64, Box<dyn std::error::Error>> {
+        let result = sqlx::query(
+            r#"
+            DELETE FROM device_availability_events
+            WHERE at < ?1
+              AND EXISTS (
+                SELECT 1 FROM device_availability_events AS newer
+                WHERE newer.device_id = device_availability_events.device_id AND newer.at < ?1
+                  AND (newer.at > device_availability_events.at OR (newer.at = device_availability_events.at AND newer.id > device_availability_events.id))
+              )
+            "#
+        )
+        .bind(Self::sortable_timestamp(older_than))
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    // ========================================================================
+    // UDP SETTINGS METHODS
+    // ========================================================================
+
+    /// Get configured UDP listener ports from database
+    pub async fn get_udp_settings(&self) -> Result<Option<Vec<u16>>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(
+            "SELECT listen_ports FROM udp_settings WHERE id = 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
+
+        match row {
+            Some(row) => {
+                let listen_ports: String = row.try_get("listen_ports")?;
+                let ports = listen_ports
+                    .split(',')
+                    .map(|p| p.trim())
+                    .filter(|p| !p.is_empty())
+                    .map(|p| p.parse::<u16>())
+                    .collect::<Result<Vec<u16>, _>>()?;
+                Ok(Some(ports))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Update UDP listener ports in database
+    pub async fn update_udp_settings(
+        &self,
+        listen_ports: &[u16]
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let listen_ports = listen_ports
+            .iter()
+            .map(|p| p.to_string())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        sqlx::query(
+            r#"
+            UPDATE udp_settings
+            SET listen_ports = ?, updated_at = datetime('now')
+            WHERE id = 1
+            "#
+        )
+        .bind(listen_ports)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    // ========================================================================
+    // MDNS SETTINGS METHODS
+    // ========================================================================
+
+    /// Get configured mDNS service types (None = built-in defaults)
+    pub async fn get_mdns_services(&self) -> Result<Option<Vec<MdnsServiceConfig>>, Box<dyn std::error::Error>> {
+        let row = sqlx::query(
+            "SELECT services FROM mdns_settings WHERE id = 1"
+        )
+        .fetch_optional(&self.pool)
+        .await?;
 
-        match row {
-            Some(row) => Ok(Some(row.get("permission"))),
+        let services: Option<String> = match row {
+            Some(row) => row.try_get("services")?,
+            None => None,
+        };
+
+        match services {
+            Some(json) => Ok(Some(serde_json::from_str(&json)?)),
             None => Ok(None),
         }
     }
 
-    pub async fn user_has_device_permission(&self, device_id: &str, user_id: &str, required_permission: &str) -> Result<bool, Box<dyn std::error::Error>> {
-        let user_permission = self.get_user_device_permission(device_id, user_id).await?;
-        
-        match user_permission {
-            Some(permission) => {
-                let has_permission = match required_permission {
-                    "R" => ["R", "W", "V", "M", "O"].contains(&permission.as_str()),
-                    "W" => {
-                        // Prüfen ob Device im Wartungsmodus ist
-                        let device = self.get_device_by_id(device_id).await?;
-                        if let Some(device) = device {
-                            if device.maintenance_mode {
-                                ["V", "M", "O"].contains(&permission.as_str())
-                            } else {
-                                ["W", "V", "M", "O"].contains(&permission.as_str())
-                            }
-                        } else {
-                            false
-                        }
-                    },
-                    "V" => ["V", "M", "O"].contains(&permission.as_str()),
-                    "M" => ["M", "O"].contains(&permission.as_str()),
-                    "O" => permission == "O",
-                    _ => false,
-                };
-                Ok(has_permission)
-            }
-            None => Ok(false),
-        }
+    /// Update mDNS service types in database
+    pub async fn update_mdns_services(
+        &self,
+        services: &[MdnsServiceConfig]
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        sqlx::query(
+            r#"
+            UPDATE mdns_settings
+            SET services = ?, updated_at = datetime('now')
+            WHERE id = 1
+            "#
+        )
+        .bind(serde_json::to_string(services)?)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
     }
 
     // ========================================================================
-    // UART SETTINGS METHODS
+    // DISCOVERY SETTINGS METHODS
     // ========================================================================
 
-    /// Get UART settings from database
-    pub async fn get_uart_settings(&self) -> Result<Option<(Option<String>, u32, bool)>, Box<dyn std::error::Error>> {
+    /// Get discovery aging settings
+    pub async fn get_discovery_settings(&self) -> Result<DiscoveryAgingConfig, Box<dyn std::error::Error>> {
         let row = sqlx::query(
-            "SELECT port, baud_rate, auto_connect FROM uart_settings WHERE id = 1"
+            "SELECT stale_after_seconds, expire_after_seconds, remove_expired, mdns_reresolve_seconds FROM discovery_settings WHERE id = 1"
         )
         .fetch_optional(&self.pool)
         .await?;
 
         match row {
-            Some(row) => {
-                let port: Option<String> = row.try_get("port")?;
-                let baud_rate: i64 = row.try_get("baud_rate")?;
-                let auto_connect: bool = row.try_get("auto_connect")?;
-                Ok(Some((port, baud_rate as u32, auto_connect)))
-            }
-            None => Ok(None),
+            Some(row) => Ok(DiscoveryAgingConfig {
+                stale_after_seconds: row.try_get::<i64, _>("stale_after_seconds")? as u64,
+                expire_after_seconds: row.try_get::<i64, _>("expire_after_seconds")? as u64,
+                remove_expired: row.try_get("remove_expired")?,
+                mdns_reresolve_seconds: row.try_get::<i64, _>("mdns_reresolve_seconds")? as u64,
+            }),
+            None => Ok(DiscoveryAgingConfig::default()),
         }
     }
 
-    /// Update UART settings in database
-    pub async fn update_uart_settings(
+    /// Update discovery aging settings
+    pub async fn update_discovery_settings(
         &self,
-        port: Option<&str>,
-        baud_rate: u32,
-        auto_connect: bool
+        config: &DiscoveryAgingConfig
     ) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query(
             r#"
-            UPDATE uart_settings
-            SET port = ?, baud_rate = ?, auto_connect = ?, updated_at = datetime('now')
+            UPDATE discovery_settings
+            SET stale_after_seconds = ?, expire_after_seconds = ?, remove_expired = ?,
+                mdns_reresolve_seconds = ?, updated_at = datetime('now')
             WHERE id = 1
             "#
         )
-        .bind(port)
-        .bind(baud_rate as i64)
-        .bind(auto_connect)
+        .bind(config.stale_after_seconds as i64)
+        .bind(config.expire_after_seconds as i64)
+        .bind(config.remove_expired)
+        .bind(config.mdns_reresolve_seconds as i64)
         .execute(&self.pool)
         .await?;
 
@@ -1101,39 +4910,49 @@ impl DatabaseManager {
     }
 
     // ========================================================================
-    // DEBUG SETTINGS METHODS
+    // TCP SCAN SETTINGS METHODS
     // ========================================================================
 
-    /// Get debug settings from database
-    pub async fn get_debug_settings(&self) -> Result<Option<u32>, Box<dyn std::error::Error>> {
+    /// Get TCP range scan settings
+    pub async fn get_tcp_scan_settings(&self) -> Result<TcpScanConfig, Box<dyn std::error::Error>> {
         let row = sqlx::query(
-            "SELECT max_debug_messages FROM debug_settings WHERE id = 1"
+            "SELECT enabled, cidr, port, concurrency, timeout_ms, interval_seconds FROM tcp_scan_settings WHERE id = 1"
         )
         .fetch_optional(&self.pool)
         .await?;
 
         match row {
-            Some(row) => {
-                let max_messages: i64 = row.try_get("max_debug_messages")?;
-                Ok(Some(max_messages as u32))
-            }
-            None => Ok(None),
+            Some(row) => Ok(TcpScanConfig {
+                enabled: row.try_get("enabled")?,
+                cidr: row.try_get("cidr")?,
+                port: row.try_get::<i64, _>("port")? as u16,
+                concurrency: row.try_get::<i64, _>("concurrency")? as usize,
+                timeout_ms: row.try_get::<i64, _>("timeout_ms")? as u64,
+                interval_seconds: row.try_get::<i64, _>("interval_seconds")? as u64,
+            }),
+            None => Ok(TcpScanConfig::default()),
         }
     }
 
-    /// Update debug settings in database
-    pub async fn update_debug_settings(
+    /// Update TCP range scan settings
+    pub async fn update_tcp_scan_settings(
         &self,
-        max_debug_messages: u32
+        config: &TcpScanConfig
     ) -> Result<(), Box<dyn std::error::Error>> {
         sqlx::query(
             r#"
-            UPDATE debug_settings
-            SET max_debug_messages = ?, updated_at = datetime('now')
+            UPDATE tcp_scan_settings
+            SET enabled = ?, cidr = ?, port = ?, concurrency = ?, timeout_ms = ?,
+                interval_seconds = ?, updated_at = datetime('now')
             WHERE id = 1
             "#
         )
-        .bind(max_debug_messages as i64)
+        .bind(config.enabled)
+        .bind(&config.cidr)
+        .bind(config.port as i64)
+        .bind(config.concurrency as i64)
+        .bind(config.timeout_ms as i64)
+        .bind(config.interval_seconds as i64)
         .execute(&self.pool)
         .await?;
 
@@ -1260,7 +5079,7 @@ mod tests {
 
         assert_eq!(user.email, "test@example.com");
         assert_eq!(user.display_name, "Test User");
-        assert_eq!(user.is_admin, false);
+        assert!(!user.is_admin);
         assert!(!user.id.is_empty());
         assert!(!user.password_hash.is_empty());
         assert_ne!(user.password_hash, "password123");
@@ -1304,8 +5123,8 @@ mod tests {
         assert_eq!(device.owner_id, "user-123");
         assert_eq!(device.connection_type, "tcp");
         assert_eq!(device.alias, None);
-        assert!(matches!(device.status, DeviceStatus::Offline));
-        assert_eq!(device.maintenance_mode, false);
+        assert!(matches!(device.status, LifecycleState::Adopted));
+        assert!(!device.maintenance_mode);
     }
 
     #[test]
@@ -1328,11 +5147,11 @@ mod tests {
         std::thread::sleep(std::time::Duration::from_millis(10));
 
         device.update_status(
-            DeviceStatus::Online,
+            LifecycleState::Online,
             Some("192.168.1.100".to_string()),
         );
 
-        assert!(matches!(device.status, DeviceStatus::Online));
+        assert!(matches!(device.status, LifecycleState::Online));
         assert_eq!(device.ip_address, Some("192.168.1.100".to_string()));
         assert!(device.last_seen > initial_time);
     }
@@ -1481,12 +5300,12 @@ mod tests {
         db.create_user(user).await.unwrap();
 
         let user_before = db.get_user_by_id(&user_id).await.unwrap().unwrap();
-        assert_eq!(user_before.is_admin, false);
+        assert!(!user_before.is_admin);
 
         db.update_user_admin_status(&user_id, true).await.unwrap();
 
         let user_after = db.get_user_by_id(&user_id).await.unwrap().unwrap();
-        assert_eq!(user_after.is_admin, true);
+        assert!(user_after.is_admin);
     }
 
     #[tokio::test]
@@ -1545,12 +5364,102 @@ mod tests {
         db.create_device(device1).await.unwrap();
         db.create_device(device2).await.unwrap();
 
-        let devices = db.list_user_devices(&user_id).await.unwrap();
+        let (devices, total) = db.list_user_devices(&user_id, &DeviceListQuery::default()).await.unwrap();
         assert_eq!(devices.len(), 2);
+        assert_eq!(total, 2);
         assert_eq!(devices[0].1, "O");
         assert_eq!(devices[1].1, "O");
     }
 
+    #[tokio::test]
+    async fn test_list_devices_filter_sort_page() {
+        let db = create_test_db().await;
+
+        for (mac, name, connection_type) in [("AA-00", "Kitchen", "tcp"), ("AA-01", "Garage_1", "uart"), ("AA-02", "kitchen lamp", "tcp")] {
+            let mut device = create_test_device(mac, "guest");
+            device.name = name.to_string();
+            device.connection_type = connection_type.to_string();
+            db.create_device(device).await.unwrap();
+        }
+
+        let db = &db;
+        let list = |query: DeviceListQuery| async move { db.list_all_devices(&query).await.unwrap() };
+
+        // Case-insensitive search, LIKE wildcards in the term are literal
+        let (devices, total) = list(DeviceListQuery { search: Some("KITCHEN".into()), ..Default::default() }).await;
+        assert_eq!((devices.len(), total), (2, 2));
+        let (devices, _) = list(DeviceListQuery { search: Some("e_1".into()), ..Default::default() }).await;
+        assert_eq!(devices[0].mac_address, "AA-01");
+        assert_eq!(list(DeviceListQuery { search: Some("%".into()), ..Default::default() }).await.1, 0);
+
+        let (devices, _) = list(DeviceListQuery { connection_type: Some("uart".into()), ..Default::default() }).await;
+        assert_eq!(devices.len(), 1);
+
+        let connected_ids = vec!["AA-02".to_string()];
+        let (online, _) = list(DeviceListQuery { connected: Some(true), connected_ids: connected_ids.clone(), ..Default::default() }).await;
+        assert_eq!(online[0].mac_address, "AA-02");
+        let (_, offline_total) = list(DeviceListQuery { connected: Some(false), connected_ids, ..Default::default() }).await;
+        assert_eq!(offline_total, 2);
+
+        // Second page of one, sorted by name
+        let (page, total) = list(DeviceListQuery { sort: DeviceSort::Name, limit: Some(1), offset: 1, ..Default::default() }).await;
+        assert_eq!(total, 3);
+        assert_eq!(page.len(), 1);
+        assert_eq!(page[0].name, "Kitchen");
+    }
+
+    #[tokio::test]
+    async fn test_device_notes_and_tags() {
+        let db = create_test_db().await;
+        db.create_device(create_test_device("AA-00", "guest")).await.unwrap();
+        db.create_device(create_test_device("AA-01", "guest")).await.unwrap();
+
+        let tags = vec!["room 204".to_string(), "flaky power supply".to_string()];
+        assert!(db.set_device_tags("AA-00", &tags).await.unwrap());
+        assert!(!db.set_device_tags("unknown", &tags).await.unwrap());
+        db.update_device("AA-00", None, None, None, Some(Some("Replace USB cable"))).await.unwrap();
+
+        let device = db.get_device_by_id("AA-00").await.unwrap().unwrap();
+        assert_eq!(device.tags, tags);
+        assert_eq!(device.notes.as_deref(), Some("Replace USB cable"));
+
+        let (devices, total) = db.list_all_devices(&DeviceListQuery { tag: Some("room 204".into()), ..Default::default() }).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(devices[0].mac_address, "AA-00");
+        assert_eq!(db.list_all_devices(&DeviceListQuery { tag: Some("room".into()), ..Default::default() }).await.unwrap().1, 0);
+
+        // Clearing notes
+        db.update_device("AA-00", None, None, None, Some(None)).await.unwrap();
+        assert!(db.get_device_by_id("AA-00").await.unwrap().unwrap().notes.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_device_location_filter() {
+        let db = create_test_db().await;
+        db.create_device(create_test_device("AA-00", "guest")).await.unwrap();
+        db.create_device(create_test_device("AA-01", "guest")).await.unwrap();
+
+        let location = DeviceLocation {
+            building: Some("B".into()),
+            room: Some("204".into()),
+            bench: Some("3".into()),
+            latitude: Some(48.137),
+            longitude: Some(11.575),
+        };
+        assert!(db.set_device_location("AA-00", Some(&location)).await.unwrap());
+        assert!(!db.set_device_location("unknown", Some(&location)).await.unwrap());
+        assert_eq!(db.get_device_by_id("AA-00").await.unwrap().unwrap().location, Some(location));
+
+        let (devices, total) = db.list_all_devices(&DeviceListQuery { room: Some("204".into()), ..Default::default() }).await.unwrap();
+        assert_eq!(total, 1);
+        assert_eq!(devices[0].mac_address, "AA-00");
+        let query = DeviceListQuery { building: Some("A".into()), room: Some("204".into()), ..Default::default() };
+        assert_eq!(db.list_all_devices(&query).await.unwrap().1, 0);
+
+        assert!(db.set_device_location("AA-00", None).await.unwrap());
+        assert!(db.get_device_by_id("AA-00").await.unwrap().unwrap().location.is_none());
+    }
+
     #[tokio::test]
     async fn test_list_all_devices() {
         let db = create_test_db().await;
@@ -1561,7 +5470,7 @@ mod tests {
         db.create_device(device1).await.unwrap();
         db.create_device(device2).await.unwrap();
 
-        let all_devices = db.list_all_devices().await.unwrap();
+        let (all_devices, _) = db.list_all_devices(&DeviceListQuery::default()).await.unwrap();
         assert_eq!(all_devices.len(), 2);
     }
 
@@ -1571,7 +5480,7 @@ mod tests {
         let device = create_test_device("AA:BB:CC:DD:EE:FF", "guest");
         db.create_device(device).await.unwrap();
 
-        db.update_device("AA:BB:CC:DD:EE:FF", Some(Some("New Device Name")), None, None)
+        db.update_device("AA:BB:CC:DD:EE:FF", Some(Some("New Device Name")), None, None, None)
             .await.unwrap();
 
         let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
@@ -1584,7 +5493,7 @@ mod tests {
         let device = create_test_device("AA:BB:CC:DD:EE:FF", "guest");
         db.create_device(device).await.unwrap();
 
-        db.update_device("AA:BB:CC:DD:EE:FF", None, Some(Some("My Custom Alias")), None)
+        db.update_device("AA:BB:CC:DD:EE:FF", None, Some(Some("My Custom Alias")), None, None)
             .await.unwrap();
 
         let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
@@ -1598,7 +5507,7 @@ mod tests {
         device.alias = Some("Initial Alias".to_string());
         db.create_device(device).await.unwrap();
 
-        db.update_device("AA:BB:CC:DD:EE:FF", None, Some(None), None)
+        db.update_device("AA:BB:CC:DD:EE:FF", None, Some(None), None, None)
             .await.unwrap();
 
         let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
@@ -1611,11 +5520,11 @@ mod tests {
         let device = create_test_device("AA:BB:CC:DD:EE:FF", "guest");
         db.create_device(device).await.unwrap();
 
-        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true))
+        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true), None)
             .await.unwrap();
 
         let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
-        assert_eq!(updated.maintenance_mode, true);
+        assert!(updated.maintenance_mode);
     }
 
     #[tokio::test]
@@ -1626,15 +5535,20 @@ mod tests {
 
         db.update_device_status(
             "AA:BB:CC:DD:EE:FF",
-            &DeviceStatus::Online,
+            &LifecycleState::Online,
             Some("192.168.1.50"),
             Some("v1.2.3"),
         ).await.unwrap();
 
         let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
-        assert!(matches!(updated.status, DeviceStatus::Online));
+        assert!(matches!(updated.status, LifecycleState::Online));
         assert_eq!(updated.ip_address, Some("192.168.1.50".to_string()));
         assert_eq!(updated.firmware_version, Some("v1.2.3".to_string()));
+
+        assert!(db.set_device_firmware_version("AA:BB:CC:DD:EE:FF", "v1.3.0").await.unwrap());
+        assert!(!db.set_device_firmware_version("AA:BB:CC:DD:EE:FF", "v1.3.0").await.unwrap());
+        let updated = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
+        assert_eq!(updated.firmware_version, Some("v1.3.0".to_string()));
     }
 
     #[tokio::test]
@@ -1731,6 +5645,24 @@ mod tests {
         assert_eq!(permission, Some("O".to_string()));
     }
 
+    #[tokio::test]
+    async fn test_transfer_device_owner_from_guest() {
+        let db = create_test_db().await;
+
+        let user = create_test_user("user@example.com", "pass");
+        let user_id = user.id.clone();
+        db.create_user(user).await.unwrap();
+
+        db.upsert_discovered_device("AA-BB-CC-DD-EE-FF".to_string(), "esp32".to_string(), None, None)
+            .await.unwrap();
+        db.transfer_device_owner("AA-BB-CC-DD-EE-FF", &user_id).await.unwrap();
+
+        let device = db.get_device_by_id("AA-BB-CC-DD-EE-FF").await.unwrap().unwrap();
+        assert_eq!(device.owner_id, user_id);
+        assert_eq!(db.get_user_device_permission("AA-BB-CC-DD-EE-FF", &user_id).await.unwrap(), Some("O".to_string()));
+        assert_eq!(db.get_user_device_permission("AA-BB-CC-DD-EE-FF", "guest").await.unwrap(), None);
+    }
+
     #[tokio::test]
     async fn test_set_device_permission_manual() {
         let db = create_test_db().await;
@@ -1853,7 +5785,7 @@ mod tests {
             .await.unwrap();
         assert!(can_write);
 
-        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true))
+        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true), None)
             .await.unwrap();
 
         let can_write_maintenance = db.user_has_device_permission("AA:BB:CC:DD:EE:FF", &user.id, "W")
@@ -1874,7 +5806,7 @@ mod tests {
         let (port, baud_rate, auto_connect) = settings.unwrap();
         assert_eq!(port, None);
         assert_eq!(baud_rate, 115200);
-        assert_eq!(auto_connect, false);
+        assert!(!auto_connect);
     }
 
     #[tokio::test]
@@ -1887,7 +5819,7 @@ mod tests {
         let settings = db.get_uart_settings().await.unwrap().unwrap();
         assert_eq!(settings.0, Some("COM3".to_string()));
         assert_eq!(settings.1, 9600);
-        assert_eq!(settings.2, true);
+        assert!(settings.2);
     }
 
     #[tokio::test]
@@ -1927,6 +5859,166 @@ mod tests {
         assert_eq!(max_messages, 500);
     }
 
+    // ========================================================================
+    // DATABASE TESTS - Device TLS Settings
+    // ========================================================================
+
+    #[tokio::test]
+    async fn test_device_tls_settings_roundtrip() {
+        let db = create_test_db().await;
+        assert!(db.get_device_tls_settings("dev-1").await.unwrap().is_none());
+
+        let tls = DeviceTlsConfig {
+            ca_cert_path: "certs/lab-ca.pem".to_string(),
+            server_name: Some("esp32-lab.local".to_string()),
+            client_cert_path: None,
+            client_key_path: None,
+        };
+        db.set_device_tls_settings("dev-1", &tls).await.unwrap();
+
+        assert_eq!(db.get_device_tls_settings("dev-1").await.unwrap(), Some(tls));
+        assert_eq!(db.get_all_device_tls_settings().await.unwrap().len(), 1);
+
+        assert!(db.delete_device_tls_settings("dev-1").await.unwrap());
+        assert!(db.get_device_tls_settings("dev-1").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_device_connection_settings_roundtrip() {
+        let db = create_test_db().await;
+        assert!(db.get_device_connection_settings("dev-1").await.unwrap().is_none());
+
+        let mut settings = ConnectionSettings {
+            tcp_port: 3232,
+            udp_port: 3232,
+            udp_timeout_seconds: 30,
+            keepalive_idle_seconds: 600,
+            keepalive_interval_seconds: 60,
+            connect_timeout_seconds: 5,
+        };
+        db.set_device_connection_settings("dev-1", &settings).await.unwrap();
+        settings.udp_timeout_seconds = 120;
+        db.set_device_connection_settings("dev-1", &settings).await.unwrap();
+
+        assert_eq!(db.get_device_connection_settings("dev-1").await.unwrap(), Some(settings));
+        assert_eq!(db.get_all_device_connection_settings().await.unwrap(), vec![("dev-1".to_string(), settings)]);
+    }
+
+    #[tokio::test]
+    async fn test_backup_copies_file_database() {
+        assert!(create_test_db().await.backup().await.is_err(), "in-memory database");
+
+        let dir = std::env::temp_dir().join(format!("backup-test-{}", Uuid::new_v4()));
+        let db = DatabaseManager::open(&dir.join("users.db").to_string_lossy()).await.unwrap();
+        db.set_device_identity_secret("dev-1", "first-secret-0123").await.unwrap();
+
+        let path = db.backup().await.unwrap();
+        assert_eq!(path.parent().unwrap(), dir.join("backups"));
+        let copy = DatabaseManager::open(&path.to_string_lossy()).await.unwrap();
+        assert!(copy.has_device_identity_secret("dev-1").await.unwrap());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_device_identity_secret_roundtrip() {
+        let db = create_test_db().await;
+        assert!(!db.has_device_identity_secret("dev-1").await.unwrap());
+
+        db.set_device_identity_secret("dev-1", "first-secret-0123").await.unwrap();
+        db.set_device_identity_secret("dev-1", "second-secret-456").await.unwrap();
+        assert!(db.has_device_identity_secret("dev-1").await.unwrap());
+        assert_eq!(db.get_all_device_identity_secrets().await.unwrap(), vec![("dev-1".to_string(), "second-secret-456".to_string())]);
+
+        assert!(db.delete_device_identity_secret("dev-1").await.unwrap());
+        assert!(!db.delete_device_identity_secret("dev-1").await.unwrap());
+    }
+
+    // ========================================================================
+    // DATABASE TESTS - UDP Settings
+    // ========================================================================
+
+    #[tokio::test]
+    async fn test_get_udp_settings_default() {
+        let db = create_test_db().await;
+        let ports = db.get_udp_settings().await.unwrap();
+
+        assert_eq!(ports, Some(vec![3232]));
+    }
+
+    #[tokio::test]
+    async fn test_update_udp_settings_multiple_ports() {
+        let db = create_test_db().await;
+
+        db.update_udp_settings(&[3232, 3233, 8266]).await.unwrap();
+
+        let ports = db.get_udp_settings().await.unwrap().unwrap();
+        assert_eq!(ports, vec![3232, 3233, 8266]);
+    }
+
+    // ========================================================================
+    // DATABASE TESTS - mDNS Settings
+    // ========================================================================
+
+    #[tokio::test]
+    async fn test_mdns_services_roundtrip() {
+        let db = create_test_db().await;
+        assert!(db.get_mdns_services().await.unwrap().is_none());
+
+        let mut esp32 = MdnsServiceConfig::new("_esp32._tcp", true);
+        esp32.txt_filter.insert("board".to_string(), "esp32".to_string());
+        let services = vec![esp32, MdnsServiceConfig::new("_http._tcp", false)];
+        db.update_mdns_services(&services).await.unwrap();
+
+        assert_eq!(db.get_mdns_services().await.unwrap(), Some(services));
+    }
+
+    // ========================================================================
+    // DATABASE TESTS - Discovery Settings
+    // ========================================================================
+
+    #[tokio::test]
+    async fn test_discovery_settings_roundtrip() {
+        let db = create_test_db().await;
+        assert_eq!(db.get_discovery_settings().await.unwrap(), DiscoveryAgingConfig::default());
+
+        let config = DiscoveryAgingConfig {
+            stale_after_seconds: 60,
+            expire_after_seconds: 600,
+            remove_expired: false,
+            mdns_reresolve_seconds: 30,
+        };
+        db.update_discovery_settings(&config).await.unwrap();
+        assert_eq!(db.get_discovery_settings().await.unwrap(), config);
+    }
+
+    // ========================================================================
+    // DATABASE TESTS - TCP Scan Settings
+    // ========================================================================
+
+    #[tokio::test]
+    async fn test_tcp_scan_settings_default_disabled() {
+        let db = create_test_db().await;
+        let settings = db.get_tcp_scan_settings().await.unwrap();
+
+        assert!(!settings.enabled);
+        assert_eq!(settings.port, 3232);
+    }
+
+    #[tokio::test]
+    async fn test_update_tcp_scan_settings() {
+        let db = create_test_db().await;
+        let config = TcpScanConfig {
+            enabled: true,
+            cidr: "10.0.0.0/24".to_string(),
+            concurrency: 64,
+            ..TcpScanConfig::default()
+        };
+
+        db.update_tcp_scan_settings(&config).await.unwrap();
+        assert_eq!(db.get_tcp_scan_settings().await.unwrap(), config);
+        assert_eq!(config.hosts().unwrap().len(), 254);
+    }
+
     // ========================================================================
     // EDGE CASE TESTS
     // ========================================================================
@@ -1952,6 +6044,61 @@ mod tests {
         assert_eq!(permissions.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_delete_user_account_transfers_devices() {
+        let db = create_test_db().await;
+
+        let user = create_test_user("user@example.com", "pass");
+        let heir = create_test_user("heir@example.com", "pass");
+        db.create_user(user.clone()).await.unwrap();
+        db.create_user(heir.clone()).await.unwrap();
+        db.create_device(create_test_device("AA:BB:CC:DD:EE:FF", &user.id)).await.unwrap();
+        db.create_device_group("Lab", &user.id, None, &["AA:BB:CC:DD:EE:FF".to_string()]).await.unwrap();
+
+        let moved = db.delete_user_account(&user.id, &heir.id).await.unwrap();
+        assert_eq!(moved, vec!["AA:BB:CC:DD:EE:FF".to_string()]);
+
+        assert!(db.get_user_by_id(&user.id).await.unwrap().is_none());
+        assert!(db.get_user_permissions(&user.id).await.unwrap().is_empty());
+        assert!(db.list_device_groups(&user.id).await.unwrap().is_empty());
+        let device = db.get_device_by_id("AA:BB:CC:DD:EE:FF").await.unwrap().unwrap();
+        assert_eq!(device.owner_id, heir.id);
+        assert_eq!(db.get_user_device_permission("AA:BB:CC:DD:EE:FF", &heir.id).await.unwrap().as_deref(), Some("O"));
+
+        // Tokens of the deleted user stay revoked across a restart
+        let revoked = db.get_revoked_sessions().await.unwrap();
+        assert_eq!(revoked.len(), 1);
+        assert_eq!(revoked[0].0, user.id);
+        assert!(revoked[0].1 > Utc::now() + chrono::Duration::hours(23));
+    }
+
+    #[tokio::test]
+    async fn test_device_takeover_needs_shared_organization() {
+        let db = create_test_db().await;
+        let user = create_test_user("user@example.com", "pass");
+        let colleague = create_test_user("colleague@example.com", "pass");
+        let stranger = create_test_user("stranger@example.com", "pass");
+        for account in [&user, &colleague, &stranger] {
+            db.create_user(account.clone()).await.unwrap();
+        }
+        db.create_device(create_test_device("AA:BB:CC:DD:EE:FF", &user.id)).await.unwrap();
+
+        // No shared organization
+        assert!(!db.can_take_over_devices(&user.id, &colleague.id).await.unwrap());
+
+        let lab = db.create_organization("Lab", "lab", &user.id).await.unwrap();
+        db.set_org_member(&lab.id, &colleague.id, OrgRole::Member).await.unwrap();
+        assert!(db.can_take_over_devices(&user.id, &colleague.id).await.unwrap());
+        assert!(!db.can_take_over_devices(&user.id, &stranger.id).await.unwrap());
+
+        // Devices of an organization only go to its members
+        let school = db.create_organization("School", "school", &user.id).await.unwrap();
+        db.set_device_org("AA:BB:CC:DD:EE:FF", Some(&school.id)).await.unwrap();
+        assert!(!db.can_take_over_devices(&user.id, &colleague.id).await.unwrap());
+        db.set_org_member(&school.id, &colleague.id, OrgRole::Member).await.unwrap();
+        assert!(db.can_take_over_devices(&user.id, &colleague.id).await.unwrap());
+    }
+
     #[tokio::test]
     async fn test_delete_device_cascades_permissions() {
         let db = create_test_db().await;
@@ -1971,15 +6118,9 @@ mod tests {
 
     #[test]
     fn test_device_status_enum_serialization() {
-        let status = DeviceStatus::Online;
-        let status_str = match status {
-            DeviceStatus::Online => "Online",
-            DeviceStatus::Offline => "Offline",
-            DeviceStatus::Error => "Error",
-            DeviceStatus::Updating => "Updating",
-            DeviceStatus::Maintenance => "Maintenance",
-        };
-        assert_eq!(status_str, "Online");
+        let status = LifecycleState::Online;
+        assert_eq!(status.as_str(), "Online");
+        assert_eq!(serde_json::to_value(status).unwrap(), "Online");
     }
 
     #[tokio::test]
@@ -1997,7 +6138,7 @@ mod tests {
         let db = create_test_db().await;
         let results = db.search_users("").await.unwrap();
 
-        assert!(results.len() >= 1);
+        assert!(!results.is_empty());
     }
 
     #[tokio::test]
@@ -2105,7 +6246,7 @@ mod tests {
         db.set_device_permission("AA:BB:CC:DD:EE:FF", &user_m.id, "M").await.unwrap();
 
         // Maintenance-Mode aktivieren
-        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true)).await.unwrap();
+        db.update_device("AA:BB:CC:DD:EE:FF", None, None, Some(true), None).await.unwrap();
 
         // V, M, O können trotz Maintenance-Mode schreiben
         assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:FF", &user_v.id, "W").await.unwrap(),
@@ -2171,4 +6312,342 @@ mod tests {
         let result = db.get_device_by_id("non-existent-mac").await.unwrap();
         assert!(result.is_none(), "Non-existent device should return None");
     }
+
+    #[tokio::test]
+    async fn test_device_group_members() {
+        let db = create_test_db().await;
+
+        let ids = vec!["AA-01".to_string(), "AA-02".to_string(), "AA-01".to_string()];
+        let group = db.create_device_group("Hall", "user-1", None, &ids).await.unwrap();
+        assert_eq!(group.device_ids, vec!["AA-01", "AA-02"], "Duplicates are ignored, order kept");
+
+        db.set_device_group_members(&group.id, &["AA-03".to_string()]).await.unwrap();
+        db.rename_device_group(&group.id, "Lab").await.unwrap();
+        let group = db.get_device_group(&group.id).await.unwrap().unwrap();
+        assert_eq!(group.name, "Lab");
+        assert_eq!(group.device_ids, vec!["AA-03"]);
+
+        assert_eq!(db.list_device_groups("user-1").await.unwrap().len(), 1);
+        assert!(db.list_device_groups("user-2").await.unwrap().is_empty());
+
+        assert!(db.delete_device_group(&group.id).await.unwrap());
+        assert!(db.get_device_group(&group.id).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_group_and_default_permissions() {
+        let db = create_test_db().await;
+        let owner = create_test_user("owner@example.com", "pass");
+        let student = create_test_user("student@example.com", "pass");
+        db.create_user(owner.clone()).await.unwrap();
+        let other = create_test_user("other@example.com", "pass");
+        db.create_user(student.clone()).await.unwrap();
+        db.create_user(other.clone()).await.unwrap();
+        db.create_device(create_test_device("AA:BB:CC:DD:EE:01", &owner.id)).await.unwrap();
+        db.create_device(create_test_device("AA:BB:CC:DD:EE:02", &other.id)).await.unwrap();
+
+        // The owner manages only the first device, the grant does not reach the second
+        let ids = vec!["AA:BB:CC:DD:EE:01".to_string(), "AA:BB:CC:DD:EE:02".to_string()];
+        let group = db.create_device_group("Lab", &owner.id, None, &ids).await.unwrap();
+        db.set_device_group_permission(&group.id, &student.id, "W").await.unwrap();
+        assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:01", &student.id, "W").await.unwrap());
+        assert!(!db.user_has_device_permission("AA:BB:CC:DD:EE:01", &student.id, "M").await.unwrap());
+        assert!(!db.user_has_device_permission("AA:BB:CC:DD:EE:02", &student.id, "R").await.unwrap());
+        let (devices, _) = db.list_user_devices(&student.id, &DeviceListQuery::default()).await.unwrap();
+        assert_eq!(devices.iter().map(|(d, p)| (d.mac_address.as_str(), p.as_str())).collect::<Vec<_>>(), vec![("AA:BB:CC:DD:EE:01", "W")]);
+
+        // A direct permission overrides the group grant
+        db.set_device_permission("AA:BB:CC:DD:EE:01", &student.id, "R").await.unwrap();
+        assert!(!db.user_has_device_permission("AA:BB:CC:DD:EE:01", &student.id, "W").await.unwrap());
+
+        // Organization default for plain members
+        let org = db.create_organization("School", "school", &owner.id).await.unwrap();
+        db.set_org_member(&org.id, &student.id, OrgRole::Member).await.unwrap();
+        db.set_device_org("AA:BB:CC:DD:EE:02", Some(&org.id)).await.unwrap();
+        db.set_device_default_permission("AA:BB:CC:DD:EE:02", Some("R")).await.unwrap();
+        let query = DeviceListQuery { org_id: Some(org.id.clone()), ..Default::default() };
+        let (devices, _) = db.list_user_devices(&student.id, &query).await.unwrap();
+        assert_eq!(devices[0].1, "W", "The org owner manages the device now, so the group grant reaches it");
+        assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:02", &student.id, "W").await.unwrap());
+
+        assert!(db.delete_device_group(&group.id).await.unwrap());
+        assert!(db.group_permissions_for_device("AA:BB:CC:DD:EE:02", &student.id).await.unwrap().is_empty());
+        assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:02", &student.id, "R").await.unwrap());
+        assert!(!db.user_has_device_permission("AA:BB:CC:DD:EE:02", &student.id, "W").await.unwrap());
+        let (devices, _) = db.list_user_devices(&student.id, &query).await.unwrap();
+        assert_eq!(devices[0].1, "R");
+    }
+
+    #[tokio::test]
+    async fn test_expiring_device_permissions() {
+        let db = create_test_db().await;
+        let owner = create_test_user("owner@example.com", "pass");
+        let student = create_test_user("student@example.com", "pass");
+        db.create_user(owner.clone()).await.unwrap();
+        db.create_user(student.clone()).await.unwrap();
+        db.create_device(create_test_device("AA:BB:CC:DD:EE:FF", &owner.id)).await.unwrap();
+
+        let now = Utc::now();
+        db.set_device_permission_until("AA:BB:CC:DD:EE:FF", &student.id, "W", Some(now + chrono::Duration::hours(1))).await.unwrap();
+        assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:FF", &student.id, "W").await.unwrap());
+        assert!(db.delete_expired_device_permissions(now).await.unwrap().is_empty());
+
+        // An ended grant no longer counts, even before the sweep removes it
+        db.set_device_permission_until("AA:BB:CC:DD:EE:FF", &student.id, "W", Some(now - chrono::Duration::seconds(1))).await.unwrap();
+        assert!(!db.user_has_device_permission("AA:BB:CC:DD:EE:FF", &student.id, "R").await.unwrap());
+        let (devices, _) = db.list_user_devices(&student.id, &DeviceListQuery::default()).await.unwrap();
+        assert!(devices.is_empty());
+        assert_eq!(db.get_device_permissions("AA:BB:CC:DD:EE:FF").await.unwrap().len(), 1, "Only the owner");
+
+        let expired = db.delete_expired_device_permissions(now).await.unwrap();
+        assert_eq!(expired.len(), 1);
+        assert_eq!((expired[0].user_id.as_str(), expired[0].permission.as_str()), (student.id.as_str(), "W"));
+        assert!(expired[0].expires_at.is_some());
+        assert!(db.user_has_device_permission("AA:BB:CC:DD:EE:FF", &owner.id, "O").await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_pages_and_pruning() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+        for minutes in [30, 20, 10] {
+            db.insert_audit_entry("AA-01", "u1", AuditAction::Connected, &serde_json::json!({}), now - chrono::Duration::minutes(minutes)).await.unwrap();
+        }
+        db.insert_audit_entry("AA-02", "u1", AuditAction::Connected, &serde_json::json!({}), now).await.unwrap();
+
+        let first = db.list_audit_entries("AA-01", None, 2).await.unwrap();
+        assert_eq!(first.len(), 2);
+        assert!(first[0].id > first[1].id, "Newest first");
+        let rest = db.list_audit_entries("AA-01", Some(first[1].id), 2).await.unwrap();
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].action, AuditAction::Connected);
+
+        db.prune_audit_log(now - chrono::Duration::minutes(15)).await.unwrap();
+        assert_eq!(db.list_audit_entries("AA-01", None, 10).await.unwrap().len(), 1);
+        assert_eq!(db.list_audit_entries("AA-02", None, 10).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_availability_history_unknown_and_pruning() {
+        use chrono::SubsecRound;
+        let db = create_test_db().await;
+        let now = Utc::now();
+        let ago = |hours: i64| now - chrono::Duration::hours(hours);
+        db.insert_availability_event("AA-01", AvailabilityState::Up, ago(10)).await.unwrap();
+        db.insert_availability_event("AA-01", AvailabilityState::Down, ago(6)).await.unwrap();
+        db.insert_availability_event("AA-01", AvailabilityState::Up, ago(4)).await.unwrap();
+        db.insert_availability_event("AA-02", AvailabilityState::Down, ago(3)).await.unwrap();
+
+        let (initial, changes) = db.availability_history("AA-01", ago(8), now).await.unwrap();
+        assert_eq!(initial, Some(AvailabilityState::Up));
+        assert_eq!(changes.iter().map(|(_, state)| *state).collect::<Vec<_>>(), vec![AvailabilityState::Down, AvailabilityState::Up]);
+
+        // Server restart: unknown from the last heartbeat, but not before a device's latest change
+        assert_eq!(db.mark_availability_unknown(ago(5)).await.unwrap(), 2);
+        assert_eq!(db.latest_availability_state("AA-01").await.unwrap(), Some(AvailabilityState::Unknown));
+        let (_, changes) = db.availability_history("AA-02", ago(8), now).await.unwrap();
+        assert_eq!(changes.last().unwrap().0, ago(3).trunc_subsecs(3));
+        assert_eq!(db.mark_availability_unknown(now).await.unwrap(), 0);
+
+        // The last change before the cutoff survives as the state at the cutoff
+        db.prune_availability_events(ago(5)).await.unwrap();
+        let (initial, changes) = db.availability_history("AA-01", ago(9), now).await.unwrap();
+        assert_eq!(initial, None);
+        assert_eq!(changes[0], (ago(6).trunc_subsecs(3), AvailabilityState::Down));
+    }
+
+    #[tokio::test]
+    async fn test_chat_history_is_bounded() {
+        let db = create_test_db().await;
+        for n in 0..5 {
+            db.insert_chat_message("AA-01", "u1", &serde_json::json!({"text": format!("note {}", n)}), 3).await.unwrap();
+        }
+        db.insert_chat_message("AA-02", "u2", &serde_json::json!({"text": "other"}), 3).await.unwrap();
+
+        let texts: Vec<String> = db.list_chat_messages("AA-01").await.unwrap().iter()
+            .map(|event| event["text"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(texts, vec!["note 2", "note 3", "note 4"], "Newest three, oldest first");
+        assert_eq!(db.list_chat_messages("AA-02").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhooks_and_delivery_log() {
+        let db = create_test_db().await;
+
+        let events = vec!["device.offline".to_string(), "device.online".to_string()];
+        let mut webhook = db.create_webhook("user-1", "https://example.com/hook", "s3cret", &events, &[]).await.unwrap();
+        assert!(webhook.enabled);
+        assert_eq!(db.get_webhooks_for_event("device.offline").await.unwrap().len(), 1);
+        assert!(db.get_webhooks_for_event("firmware.updated").await.unwrap().is_empty());
+
+        webhook.enabled = false;
+        db.update_webhook(&webhook).await.unwrap();
+        assert!(db.get_webhooks_for_event("device.offline").await.unwrap().is_empty());
+        assert_eq!(db.list_webhooks("user-1").await.unwrap()[0].secret, "s3cret");
+
+        for attempt in 0..3 {
+            let mut delivery = WebhookDelivery {
+                id: format!("delivery-{}", attempt),
+                webhook_id: webhook.id.clone(),
+                event: "device.offline".to_string(),
+                device_id: Some("AA-01".to_string()),
+                status: "pending".to_string(),
+                attempts: 0,
+                response_status: None,
+                error: None,
+                created_at: Utc::now() + chrono::Duration::seconds(attempt),
+                completed_at: None,
+            };
+            db.insert_webhook_delivery(&delivery, 2).await.unwrap();
+            delivery.status = "success".to_string();
+            delivery.attempts = 1;
+            delivery.response_status = Some(204);
+            delivery.completed_at = Some(Utc::now());
+            db.update_webhook_delivery(&delivery).await.unwrap();
+        }
+
+        let deliveries = db.list_webhook_deliveries(&webhook.id, 10).await.unwrap();
+        assert_eq!(deliveries.iter().map(|d| d.id.as_str()).collect::<Vec<_>>(), vec!["delivery-2", "delivery-1"]);
+        assert_eq!(deliveries[0].response_status, Some(204));
+
+        assert!(db.delete_webhook(&webhook.id).await.unwrap());
+        assert!(db.list_webhook_deliveries(&webhook.id, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_email_queue_and_password_resets() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+
+        let mut email = QueuedEmail {
+            id: "mail-1".to_string(),
+            recipient: "user@example.com".to_string(),
+            subject: "Alert".to_string(),
+            body: "Temperature too high".to_string(),
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            sent_at: None,
+        };
+        db.enqueue_email(&email).await.unwrap();
+        assert_eq!(db.due_emails(now, 10).await.unwrap().len(), 1);
+
+        // Retry later: not due now, due after the backoff
+        email.attempts = 1;
+        email.next_attempt_at = now + chrono::Duration::seconds(30);
+        email.last_error = Some("451 try again".to_string());
+        db.update_queued_email(&email).await.unwrap();
+        assert!(db.due_emails(now, 10).await.unwrap().is_empty());
+        let due = db.due_emails(now + chrono::Duration::seconds(31), 10).await.unwrap();
+        assert_eq!(due[0].attempts, 1);
+        assert_eq!(due[0].last_error.as_deref(), Some("451 try again"));
+
+        email.status = "sent".to_string();
+        email.sent_at = Some(now);
+        db.update_queued_email(&email).await.unwrap();
+        assert_eq!(db.prune_email_queue(now + chrono::Duration::seconds(1)).await.unwrap(), 1);
+
+        let prefs = db.get_notification_preferences("user-1").await.unwrap();
+        assert!(prefs.email_alerts);
+        db.set_notification_preferences(&NotificationPreferences { email_alerts: false, ..prefs }).await.unwrap();
+        assert!(!db.get_notification_preferences("user-1").await.unwrap().email_alerts);
+
+        // Tokens work once and only until they expire
+        db.create_password_reset("user-1", "hash-1", now + chrono::Duration::hours(1)).await.unwrap();
+        db.create_password_reset("user-2", "hash-2", now - chrono::Duration::seconds(1)).await.unwrap();
+        assert_eq!(db.consume_password_reset("hash-1", now).await.unwrap().as_deref(), Some("user-1"));
+        assert_eq!(db.consume_password_reset("hash-1", now).await.unwrap(), None);
+        assert_eq!(db.consume_password_reset("hash-2", now).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_schedules_due_and_run_history() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+
+        let mut schedule = Schedule {
+            id: "schedule-1".to_string(),
+            owner_id: "user-1".to_string(),
+            name: "Night mode".to_string(),
+            schedule: "every day at 18:00".to_string(),
+            device_id: None,
+            group_id: Some("group-1".to_string()),
+            command: serde_json::json!({"startOption": "night_mode"}),
+            enabled: true,
+            catch_up: true,
+            next_run_at: Some(now + chrono::Duration::minutes(5)),
+            last_run_at: None,
+            created_at: now,
+        };
+        db.create_schedule(&schedule).await.unwrap();
+        assert!(db.list_due_schedules(now).await.unwrap().is_empty());
+        assert_eq!(db.list_due_schedules(now + chrono::Duration::minutes(5)).await.unwrap().len(), 1);
+        assert_eq!(db.next_schedule_run().await.unwrap().map(|t| t.timestamp_millis()), schedule.next_run_at.map(|t| t.timestamp_millis()));
+
+        schedule.enabled = false;
+        db.update_schedule(&schedule).await.unwrap();
+        assert_eq!(db.next_schedule_run().await.unwrap(), None);
+
+        for index in 0..3 {
+            let run = ScheduleRun {
+                id: format!("run-{}", index),
+                schedule_id: schedule.id.clone(),
+                trigger: "manual".to_string(),
+                status: "success".to_string(),
+                scheduled_for: None,
+                started_at: now + chrono::Duration::seconds(index),
+                finished_at: Some(now + chrono::Duration::seconds(index)),
+                total: 2,
+                succeeded: 2,
+                error: None,
+                results: serde_json::json!([{"deviceId": "AA-01", "success": true}]),
+            };
+            db.insert_schedule_run(&run, 2).await.unwrap();
+        }
+        let runs = db.list_schedule_runs(&schedule.id, 10).await.unwrap();
+        assert_eq!(runs.iter().map(|r| r.id.as_str()).collect::<Vec<_>>(), vec!["run-2", "run-1"]);
+        assert_eq!(runs[0].results[0]["deviceId"], "AA-01");
+        assert!(db.get_schedule(&schedule.id).await.unwrap().unwrap().last_run_at.is_some());
+
+        assert!(db.delete_schedule(&schedule.id).await.unwrap());
+        assert!(db.list_schedule_runs(&schedule.id, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_scripts_and_log_rotation() {
+        let db = create_test_db().await;
+        let now = Utc::now();
+
+        let mut script = Script {
+            id: "script-1".to_string(),
+            owner_id: "user-1".to_string(),
+            name: "Door light".to_string(),
+            device_id: "AA-01".to_string(),
+            events: vec!["DeviceVariableUpdate".to_string()],
+            source: "print(event.variableName);".to_string(),
+            enabled: true,
+            created_at: now,
+            updated_at: now,
+        };
+        db.create_script(&script).await.unwrap();
+        assert_eq!(db.list_enabled_scripts().await.unwrap().len(), 1);
+
+        script.enabled = false;
+        db.update_script(&script).await.unwrap();
+        assert!(db.list_enabled_scripts().await.unwrap().is_empty());
+        assert_eq!(db.list_scripts("user-1").await.unwrap()[0].events, vec!["DeviceVariableUpdate"]);
+
+        for index in 0..5 {
+            let log = ScriptLog { id: 0, script_id: script.id.clone(), timestamp: now, level: "info".to_string(), message: format!("line {}", index) };
+            db.insert_script_log(&log, 3).await.unwrap();
+        }
+        let logs = db.list_script_logs(&script.id, 10).await.unwrap();
+        assert_eq!(logs.iter().map(|l| l.message.as_str()).collect::<Vec<_>>(), vec!["line 4", "line 3", "line 2"]);
+
+        assert!(db.delete_script(&script.id).await.unwrap());
+        assert!(db.list_script_logs(&script.id, 10).await.unwrap().is_empty());
+    }
 }
@@ -1,53 +1,204 @@
-use std::fs::{OpenOptions, create_dir_all};
-use std::io::Write;
+// ============================================================================
+// DEBUG_LOGGER.RS - In-memory debug event bus (ring buffer + live stream)
+// ============================================================================
+//
+// Diagnostic entries (device management, TCP commands/reconnects, mDNS, ...) are
+// kept in a bounded ring buffer instead of log files; logging never touches the
+// disk or blocks the caller. The capacity follows the max_debug_messages setting.
+// GET /api/debug/logs pages through the buffer, the `debug` WebSocket channel
+// streams new entries as they are published.
+
+use std::collections::VecDeque;
+use std::sync::{LazyLock, Mutex};
+
 use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
 
-pub struct DebugLogger;
+/// Ring size until the stored max_debug_messages setting is loaded
+pub const DEFAULT_CAPACITY: usize = 200;
 
-impl DebugLogger {
-    const LOG_DIR: &'static str = "logs";
-    const LOG_FILE: &'static str = "logs/debug_events.log";
-    const TCP_LOG_FILE: &'static str = "logs/tcp_messages.log";
-    const TEMP_LOG_FILE: &'static str = "logs/templog.log";
+/// Upper bound of one GET /api/debug/logs page
+pub const MAX_PAGE_SIZE: usize = 500;
+
+/// Live subscribers lagging further behind skip entries (they can re-read them via the API)
+const LIVE_CHANNEL_CAPACITY: usize = 1024;
+
+static DEBUG_BUS: LazyLock<DebugBus> = LazyLock::new(|| DebugBus::new(DEFAULT_CAPACITY));
+
+/// Process-wide debug event bus
+pub fn bus() -> &'static DebugBus {
+    &DEBUG_BUS
+}
 
-    fn ensure_log_dir() {
-        let _ = create_dir_all(Self::LOG_DIR);
+// ============================================================================
+// ENTRIES AND FILTERS
+// ============================================================================
+
+/// One debug entry
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugEntry {
+    /// Increasing sequence number, used as pagination cursor
+    pub id: u64,
+    /// ms since epoch
+    pub timestamp: i64,
+    /// Upper-case category, e.g. TCP_COMMAND, MDNS_DISCOVERY
+    pub category: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_id: Option<String>,
+    pub message: String,
+}
+
+/// Category/device filter of a page request or live subscription
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DebugFilter {
+    /// Upper-case categories; empty = all
+    pub categories: Vec<String>,
+    pub device_id: Option<String>,
+}
+
+impl DebugFilter {
+    pub fn new<S: AsRef<str>>(categories: impl IntoIterator<Item = S>, device_id: Option<String>) -> Self {
+        let categories = categories.into_iter()
+            .map(|c| c.as_ref().trim().to_uppercase())
+            .filter(|c| !c.is_empty())
+            .collect();
+        Self { categories, device_id }
     }
 
-    pub fn log_event(category: &str, message: &str) {
-        Self::ensure_log_dir();
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let log_entry = format!("[{}] {}: {}\n", timestamp, category, message);
-
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(Self::LOG_FILE) {
-            let _ = file.write_all(log_entry.as_bytes());
-            let _ = file.flush();
+    pub fn matches(&self, entry: &DebugEntry) -> bool {
+        (self.categories.is_empty() || self.categories.contains(&entry.category))
+            && self.device_id.as_ref().is_none_or(|id| entry.device_id.as_ref() == Some(id))
+    }
+}
+
+/// One page of entries after a cursor
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DebugPage {
+    pub entries: Vec<DebugEntry>,
+    /// Pass as `after` to continue (also advances past filtered-out entries)
+    pub next_cursor: u64,
+    pub has_more: bool,
+    /// Entries after the requested cursor were already overwritten
+    pub truncated: bool,
+}
+
+// ============================================================================
+// DEBUG BUS
+// ============================================================================
+
+struct Ring {
+    entries: VecDeque<DebugEntry>,
+    capacity: usize,
+    last_id: u64,
+}
+
+/// Bounded ring buffer plus broadcast channel for live streaming
+pub struct DebugBus {
+    ring: Mutex<Ring>,
+    live: broadcast::Sender<DebugEntry>,
+}
+
+impl DebugBus {
+    pub fn new(capacity: usize) -> Self {
+        let (live, _) = broadcast::channel(LIVE_CHANNEL_CAPACITY);
+        Self {
+            ring: Mutex::new(Ring { entries: VecDeque::new(), capacity: capacity.max(1), last_id: 0 }),
+            live,
         }
     }
 
-    pub fn log_tcp_message(device_id: &str, direction: &str, message: &str) {
-        Self::ensure_log_dir();
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let log_entry = format!("[{}] TCP_{}: Device {} - {}\n", timestamp, direction, device_id, message);
-
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(Self::TCP_LOG_FILE) {
-            let _ = file.write_all(log_entry.as_bytes());
-            let _ = file.flush();
+    /// Append an entry; the oldest entries are dropped beyond the capacity
+    pub fn publish(&self, category: &str, device_id: Option<&str>, message: String) {
+        let entry = {
+            let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+            ring.last_id += 1;
+            let entry = DebugEntry {
+                id: ring.last_id,
+                timestamp: Utc::now().timestamp_millis(),
+                category: category.to_uppercase(),
+                device_id: device_id.map(str::to_string),
+                message,
+            };
+            while ring.entries.len() >= ring.capacity {
+                ring.entries.pop_front();
+            }
+            ring.entries.push_back(entry.clone());
+            entry
+        };
+        // No receivers is fine (nobody streams the debug channel)
+        let _ = self.live.send(entry);
+    }
+
+    /// Resize the ring (max_debug_messages setting), dropping the oldest entries if it shrinks
+    pub fn set_capacity(&self, capacity: usize) {
+        let mut ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        ring.capacity = capacity.max(1);
+        while ring.entries.len() > ring.capacity {
+            ring.entries.pop_front();
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.ring.lock().unwrap_or_else(|e| e.into_inner()).capacity
+    }
+
+    /// Up to `limit` matching entries with an id above `after` (from the oldest retained entry if None)
+    pub fn page(&self, after: Option<u64>, limit: usize, filter: &DebugFilter) -> DebugPage {
+        let ring = self.ring.lock().unwrap_or_else(|e| e.into_inner());
+        let limit = limit.clamp(1, MAX_PAGE_SIZE);
+        let start = after.unwrap_or(0);
+        let truncated = after.is_some_and(|after| ring.entries.front().is_some_and(|oldest| oldest.id > after + 1));
+
+        let mut cursor = start;
+        let mut entries = Vec::new();
+        for entry in ring.entries.iter().filter(|e| e.id > start) {
+            if entries.len() == limit {
+                break;
+            }
+            cursor = entry.id;
+            if filter.matches(entry) {
+                entries.push(entry.clone());
+            }
+        }
+
+        DebugPage {
+            entries,
+            next_cursor: cursor,
+            has_more: ring.entries.back().is_some_and(|newest| newest.id > cursor),
+            truncated,
         }
     }
 
+    /// Receiver for entries published from now on
+    pub fn subscribe(&self) -> broadcast::Receiver<DebugEntry> {
+        self.live.subscribe()
+    }
+}
+
+// ============================================================================
+// DEBUG LOGGER - Category helpers used throughout the device code
+// ============================================================================
+
+pub struct DebugLogger;
+
+impl DebugLogger {
+    pub fn log_event(category: &str, message: &str) {
+        bus().publish(category, None, message.to_string());
+    }
+
+    pub fn log_tcp_message(device_id: &str, direction: &str, message: &str) {
+        bus().publish(&format!("TCP_{}", direction), Some(device_id), message.to_string());
+    }
+
     pub fn log_device_add(device_id: &str) {
-        Self::log_event("DEVICE_MANAGEMENT", &format!("ADD_DEVICE called for {}", device_id));
+        bus().publish("DEVICE_MANAGEMENT", Some(device_id), format!("ADD_DEVICE called for {}", device_id));
     }
 
     pub fn log_device_already_exists(device_id: &str) {
-        Self::log_event("DEVICE_MANAGEMENT", &format!("DEVICE_ALREADY_EXISTS {}", device_id));
+        bus().publish("DEVICE_MANAGEMENT", Some(device_id), format!("DEVICE_ALREADY_EXISTS {}", device_id));
     }
 
     pub fn log_device_connection_event_send(device_id: &str, is_closed: bool, success: bool, error: Option<&str>) {
@@ -57,27 +208,27 @@ impl DebugLogger {
         } else {
             String::new()
         };
-        Self::log_event("Device_CONNECTION", &format!("EVENT_SEND {} for device {} (channel_closed: {}){}", status, device_id, is_closed, details));
+        bus().publish("DEVICE_CONNECTION", Some(device_id), format!("EVENT_SEND {} for device {} (channel_closed: {}){}", status, device_id, is_closed, details));
     }
 
     pub fn log_tcp_command_send(device_id: &str, command: &str, tcp_available: bool) {
-        Self::log_event("TCP_COMMAND", &format!("SENDING command '{}' to device {} - TCP_AVAILABLE: {}", command, device_id, tcp_available));
+        bus().publish("TCP_COMMAND", Some(device_id), format!("SENDING command '{}' to device {} - TCP_AVAILABLE: {}", command, device_id, tcp_available));
     }
 
     pub fn log_tcp_command_success(device_id: &str, command: &str) {
-        Self::log_event("TCP_COMMAND", &format!("SUCCESS sent command '{}' to device {}", command, device_id));
+        bus().publish("TCP_COMMAND", Some(device_id), format!("SUCCESS sent command '{}' to device {}", command, device_id));
     }
 
     pub fn log_tcp_command_failed(device_id: &str, command: &str, error: &str) {
-        Self::log_event("TCP_COMMAND", &format!("FAILED to send command '{}' to device {}: {}", command, device_id, error));
+        bus().publish("TCP_COMMAND", Some(device_id), format!("FAILED to send command '{}' to device {}: {}", command, device_id, error));
     }
 
     pub fn log_tcp_connection_status(device_id: &str, status: &str, details: &str) {
-        Self::log_event("TCP_CONNECTION", &format!("STATUS for device {}: {} - {}", device_id, status, details));
+        bus().publish("TCP_CONNECTION", Some(device_id), format!("STATUS for device {}: {} - {}", device_id, status, details));
     }
 
     pub fn log_tcp_reconnect_attempt(device_id: &str, reason: &str) {
-        Self::log_event("TCP_RECONNECT", &format!("ATTEMPTING reconnect for device {} - reason: {}", device_id, reason));
+        bus().publish("TCP_RECONNECT", Some(device_id), format!("ATTEMPTING reconnect for device {} - reason: {}", device_id, reason));
     }
 
     pub fn log_tcp_reconnect_result(device_id: &str, success: bool, error: Option<&str>) {
@@ -87,47 +238,66 @@ impl DebugLogger {
         } else {
             String::new()
         };
-        Self::log_event("TCP_RECONNECT", &format!("RESULT for device {}: {}{}", device_id, status, details));
-    }
-
-    pub fn clear_log() {
-        Self::ensure_log_dir();
-        let _ = std::fs::remove_file(Self::LOG_FILE);
-        let _ = std::fs::remove_file(Self::TCP_LOG_FILE);
-        let _ = std::fs::remove_file(Self::TEMP_LOG_FILE);
+        bus().publish("TCP_RECONNECT", Some(device_id), format!("RESULT for device {}: {}{}", device_id, status, details));
     }
 
     pub fn log_reset_attempt(device_id: &str, attempt_number: u32) {
-        Self::log_to_temp_log(&format!("RESET_ATTEMPT_{}: Device {} - Reset command initiated", attempt_number, device_id));
+        bus().publish("RESET", Some(device_id), format!("RESET_ATTEMPT_{}: Device {} - Reset command initiated", attempt_number, device_id));
     }
 
     pub fn log_reset_success(device_id: &str, attempt_number: u32) {
-        Self::log_to_temp_log(&format!("RESET_SUCCESS_{}: Device {} - Reset command sent successfully", attempt_number, device_id));
+        bus().publish("RESET", Some(device_id), format!("RESET_SUCCESS_{}: Device {} - Reset command sent successfully", attempt_number, device_id));
     }
 
     pub fn log_reset_failure(device_id: &str, attempt_number: u32, error: &str) {
-        Self::log_to_temp_log(&format!("RESET_FAILURE_{}: Device {} - Reset failed: {}", attempt_number, device_id, error));
+        bus().publish("RESET", Some(device_id), format!("RESET_FAILURE_{}: Device {} - Reset failed: {}", attempt_number, device_id, error));
     }
 
     pub fn log_connection_drop(device_id: &str, reason: &str) {
-        Self::log_to_temp_log(&format!("CONNECTION_DROP: Device {} - Connection dropped: {}", device_id, reason));
+        bus().publish("CONNECTION_DROP", Some(device_id), format!("CONNECTION_DROP: Device {} - Connection dropped: {}", device_id, reason));
     }
 
     pub fn log_device_manager_state(device_id: &str, state: &str) {
-        Self::log_to_temp_log(&format!("DEVICE_MANAGER_STATE: Device {} - {}", device_id, state));
+        bus().publish("DEVICE_MANAGER_STATE", Some(device_id), format!("DEVICE_MANAGER_STATE: Device {} - {}", device_id, state));
     }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
 
-    fn log_to_temp_log(message: &str) {
-        Self::ensure_log_dir();
-        let timestamp = Utc::now().format("%Y-%m-%d %H:%M:%S%.3f");
-        let log_entry = format!("[{}] {}\n", timestamp, message);
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-        if let Ok(mut file) = OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(Self::TEMP_LOG_FILE) {
-            let _ = file.write_all(log_entry.as_bytes());
-            let _ = file.flush();
+    #[test]
+    fn test_ring_buffer_paging_and_filters() {
+        let bus = DebugBus::new(5);
+        let mut live = bus.subscribe();
+        for i in 1..=7 {
+            let device = if i % 2 == 0 { Some("dev-A") } else { Some("dev-B") };
+            bus.publish(if i <= 4 { "tcp_command" } else { "MDNS_DISCOVERY" }, device, format!("entry {}", i));
         }
+
+        // Only the newest 5 entries are retained
+        let page = bus.page(None, 2, &DebugFilter::default());
+        assert_eq!(page.entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![3, 4]);
+        assert!(page.has_more);
+        let page = bus.page(Some(page.next_cursor), 10, &DebugFilter::default());
+        assert_eq!(page.entries.len(), 3);
+        assert!(!page.has_more);
+
+        // Cursor older than the ring reports truncation
+        assert!(bus.page(Some(1), 10, &DebugFilter::default()).truncated);
+
+        let filter = DebugFilter::new(["Tcp_Command"], Some("dev-A".to_string()));
+        let page = bus.page(None, 10, &filter);
+        assert_eq!(page.entries.iter().map(|e| e.id).collect::<Vec<_>>(), vec![4]);
+        assert_eq!(page.next_cursor, 7);
+
+        bus.set_capacity(2);
+        assert_eq!(bus.page(None, 10, &DebugFilter::default()).entries.len(), 2);
+
+        assert_eq!(live.try_recv().unwrap().message, "entry 1");
     }
 }
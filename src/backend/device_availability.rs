@@ -0,0 +1,296 @@
+// Device availability - uptime statistics from recorded link state changes
+//
+// Every lifecycle transition that changes whether a device is reachable is written to
+// device_availability_events: up (Online, Degraded), down (Adopted, Connecting, Offline),
+// maintenance, and unknown while the server itself was not running (marked at startup from
+// the last heartbeat). GET /api/devices/:id/availability?window=7d computes uptime %,
+// disconnects (up -> down) per day and the mean time between disconnects from these rows;
+// maintenance and unknown time count neither as up nor as down.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::database::DatabaseManager;
+use crate::device_lifecycle::LifecycleState;
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+
+/// How long state changes are kept (also the largest window)
+pub const RETENTION: chrono::Duration = chrono::Duration::days(90);
+
+pub const DEFAULT_WINDOW: chrono::Duration = chrono::Duration::days(7);
+
+/// Liveness of the server, used to mark the time it was not running as unknown
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Pruning runs every this many heartbeats (hourly)
+const PRUNE_EVERY_HEARTBEATS: u32 = 60;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AvailabilityState {
+    Up,
+    Down,
+    Maintenance,
+    /// Server not running, nothing known about the device
+    Unknown,
+}
+
+impl AvailabilityState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Up => "up",
+            Self::Down => "down",
+            Self::Maintenance => "maintenance",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        [Self::Up, Self::Down, Self::Maintenance, Self::Unknown].into_iter().find(|state| state.as_str() == value)
+    }
+
+    /// None for devices that are only discovered (not adopted, nothing to report)
+    pub fn from_lifecycle(state: LifecycleState) -> Option<Self> {
+        match state {
+            LifecycleState::Discovered => None,
+            LifecycleState::Online | LifecycleState::Degraded => Some(Self::Up),
+            LifecycleState::Adopted | LifecycleState::Connecting | LifecycleState::Offline => Some(Self::Down),
+            LifecycleState::Maintenance => Some(Self::Maintenance),
+            // Out of service until released, whatever the link does
+            LifecycleState::Quarantined => Some(Self::Down),
+        }
+    }
+}
+
+/// Uptime of one UTC day of the window
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyAvailability {
+    pub date: String,
+    pub uptime_percent: Option<f64>,
+    pub disconnects: u32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AvailabilityStats {
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    pub up_seconds: i64,
+    pub down_seconds: i64,
+    pub maintenance_seconds: i64,
+    /// Before the first recorded state or while the server was not running
+    pub unknown_seconds: i64,
+    /// Share of up in up + down; None without any up or down time
+    pub uptime_percent: Option<f64>,
+    pub disconnects: u32,
+    pub disconnects_per_day: Option<f64>,
+    /// Up time divided by disconnects; None without a disconnect
+    pub mean_time_between_disconnects_seconds: Option<f64>,
+    pub current_state: Option<AvailabilityState>,
+    pub daily: Vec<DailyAvailability>,
+}
+
+/// `24h` or `7d` (default 7d), at most RETENTION
+pub fn parse_window(text: Option<&str>) -> Result<chrono::Duration, String> {
+    let Some(text) = text.map(str::trim).filter(|text| !text.is_empty()) else {
+        return Ok(DEFAULT_WINDOW);
+    };
+    let split = text.find(|c: char| !c.is_ascii_digit()).unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: i64 = number.parse().map_err(|_| format!("Invalid window '{}' (use e.g. 24h or 7d)", text))?;
+    let window = match unit {
+        "h" => chrono::Duration::hours(number),
+        "d" => chrono::Duration::days(number),
+        _ => return Err(format!("Invalid window unit in '{}' (use h or d)", text)),
+    };
+    if window <= chrono::Duration::zero() || window > RETENTION {
+        return Err(format!("Window must be between 1h and {}d", RETENTION.num_days()));
+    }
+    Ok(window)
+}
+
+/// Time spent per state and disconnects within (from, to]
+#[derive(Debug, Default)]
+struct Totals {
+    seconds: HashMap<Option<AvailabilityState>, i64>,
+    disconnects: u32,
+}
+
+fn totals(initial: Option<AvailabilityState>, changes: &[(DateTime<Utc>, AvailabilityState)], from: DateTime<Utc>, to: DateTime<Utc>) -> Totals {
+    let mut result = Totals::default();
+    let mut state = initial;
+    let mut since = from;
+    for &(at, next) in changes.iter().filter(|(at, _)| *at > from && *at <= to) {
+        *result.seconds.entry(state).or_default() += (at - since).num_seconds();
+        if state == Some(AvailabilityState::Up) && next == AvailabilityState::Down {
+            result.disconnects += 1;
+        }
+        state = Some(next);
+        since = at;
+    }
+    *result.seconds.entry(state).or_default() += (to - since).num_seconds();
+    result
+}
+
+fn uptime_percent(up: i64, down: i64) -> Option<f64> {
+    (up + down > 0).then(|| up as f64 * 100.0 / (up + down) as f64)
+}
+
+/// Statistics of (from, to]: `initial` is the state at `from`, `changes` are sorted by time
+pub fn compute(initial: Option<AvailabilityState>, changes: &[(DateTime<Utc>, AvailabilityState)], from: DateTime<Utc>, to: DateTime<Utc>) -> AvailabilityStats {
+    let window = totals(initial, changes, from, to);
+    let seconds = |state: AvailabilityState| window.seconds.get(&Some(state)).copied().unwrap_or(0);
+    let (up, down) = (seconds(AvailabilityState::Up), seconds(AvailabilityState::Down));
+    let unknown = seconds(AvailabilityState::Unknown) + window.seconds.get(&None).copied().unwrap_or(0);
+
+    // One entry per UTC day touched by the window
+    let mut daily = Vec::new();
+    let mut day_start = from;
+    let mut state = initial;
+    while day_start < to {
+        let next_midnight = (day_start.date_naive() + chrono::Days::new(1)).and_hms_opt(0, 0, 0).expect("midnight exists").and_utc();
+        let day_end = next_midnight.min(to);
+        let day = totals(state, changes, day_start, day_end);
+        let day_seconds = |state: AvailabilityState| day.seconds.get(&Some(state)).copied().unwrap_or(0);
+        daily.push(DailyAvailability {
+            date: day_start.format("%Y-%m-%d").to_string(),
+            uptime_percent: uptime_percent(day_seconds(AvailabilityState::Up), day_seconds(AvailabilityState::Down)),
+            disconnects: day.disconnects,
+        });
+        state = changes.iter().rev().find(|(at, _)| *at <= day_end).map(|(_, state)| *state).or(state);
+        day_start = day_end;
+    }
+
+    AvailabilityStats {
+        from,
+        to,
+        up_seconds: up,
+        down_seconds: down,
+        maintenance_seconds: seconds(AvailabilityState::Maintenance),
+        unknown_seconds: unknown,
+        uptime_percent: uptime_percent(up, down),
+        disconnects: window.disconnects,
+        disconnects_per_day: (up + down > 0).then(|| window.disconnects as f64 * 86400.0 / (up + down) as f64),
+        mean_time_between_disconnects_seconds: (window.disconnects > 0).then(|| up as f64 / window.disconnects as f64),
+        current_state: changes.iter().rev().find(|(at, _)| *at <= to).map(|(_, state)| *state).or(initial),
+        daily,
+    }
+}
+
+/// Record state changes from the event feed, mark server downtime, prune hourly
+pub fn start(db: Arc<DatabaseManager>, device_store: SharedDeviceStore) {
+    let mut feed = device_store.subscribe_events();
+    tokio::spawn(async move {
+        // Whatever happened since the last heartbeat is unknown
+        match db.get_availability_heartbeat().await.map_err(|e| e.to_string()) {
+            Ok(Some(last_alive)) => {
+                if let Err(e) = db.mark_availability_unknown(last_alive).await {
+                    warn!("Failed to mark server downtime in the availability history: {}", e);
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read the availability heartbeat: {}", e),
+        }
+
+        let mut last_states: HashMap<String, AvailabilityState> = HashMap::new();
+        let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+        let mut beats = 0u32;
+        loop {
+            tokio::select! {
+                _ = heartbeat.tick() => {
+                    if let Err(e) = db.set_availability_heartbeat(Utc::now()).await {
+                        warn!("Failed to write the availability heartbeat: {}", e);
+                    }
+                    beats += 1;
+                    if beats.is_multiple_of(PRUNE_EVERY_HEARTBEATS) {
+                        if let Err(e) = db.prune_availability_events(Utc::now() - RETENTION).await {
+                            warn!("Failed to prune the availability history: {}", e);
+                        }
+                    }
+                }
+                received = feed.recv() => match received {
+                    Ok(feed_event) => {
+                        let DeviceEvent::DeviceLifecycle { device_id, state, .. } = feed_event.event else {
+                            continue;
+                        };
+                        let Some(state) = AvailabilityState::from_lifecycle(LifecycleState::parse(&state)) else {
+                            continue;
+                        };
+                        let last = match last_states.get(&device_id) {
+                            Some(last) => Some(*last),
+                            None => db.latest_availability_state(&device_id).await.ok().flatten(),
+                        };
+                        if last == Some(state) {
+                            last_states.insert(device_id, state);
+                            continue;
+                        }
+                        let at = DateTime::from_timestamp_millis(feed_event.timestamp).unwrap_or_else(Utc::now);
+                        match db.insert_availability_event(&device_id, state, at).await {
+                            Ok(()) => {
+                                last_states.insert(device_id, state);
+                            }
+                            Err(e) => warn!("Failed to record availability of device {}: {}", device_id, e),
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Availability recording lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                },
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_compute_uptime_disconnects_and_days() {
+        let at = |day: u32, hour: u32| Utc.with_ymd_and_hms(2026, 3, day, hour, 0, 0).unwrap();
+        let changes = vec![
+            (at(1, 6), AvailabilityState::Down),
+            (at(1, 12), AvailabilityState::Up),
+            (at(1, 18), AvailabilityState::Maintenance),
+            (at(2, 0), AvailabilityState::Up),
+            (at(2, 12), AvailabilityState::Down),
+        ];
+        let stats = compute(Some(AvailabilityState::Up), &changes, at(1, 0), at(2, 18));
+
+        // Up 6h + 6h + 12h, down 6h + 6h, maintenance 6h
+        assert_eq!(stats.up_seconds, 24 * 3600);
+        assert_eq!(stats.down_seconds, 12 * 3600);
+        assert_eq!(stats.maintenance_seconds, 6 * 3600);
+        assert_eq!(stats.unknown_seconds, 0);
+        assert_eq!(stats.disconnects, 2);
+        assert_eq!(stats.mean_time_between_disconnects_seconds, Some(12.0 * 3600.0));
+        assert!((stats.uptime_percent.unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert_eq!(stats.current_state, Some(AvailabilityState::Down));
+
+        assert_eq!(stats.daily.len(), 2);
+        assert_eq!(stats.daily[0].date, "2026-03-01");
+        assert_eq!(stats.daily[0].disconnects, 1);
+        assert!((stats.daily[0].uptime_percent.unwrap() - 200.0 / 3.0).abs() < 1e-9);
+        assert!((stats.daily[1].uptime_percent.unwrap() - 200.0 / 3.0).abs() < 1e-9);
+
+        // Nothing recorded before the window: that time is unknown, not down
+        let stats = compute(None, &changes, at(1, 0), at(1, 12));
+        assert_eq!(stats.unknown_seconds, 6 * 3600);
+        assert_eq!(stats.uptime_percent, Some(0.0));
+
+        assert_eq!(parse_window(None).unwrap(), DEFAULT_WINDOW);
+        assert_eq!(parse_window(Some("24h")).unwrap(), chrono::Duration::hours(24));
+        assert!(parse_window(Some("365d")).is_err());
+        assert!(parse_window(Some("7w")).is_err());
+    }
+}
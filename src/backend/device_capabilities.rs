@@ -0,0 +1,284 @@
+// Device capabilities - cached changeable variables, start options, firmware and features
+//
+// When a device's TCP link comes up the tracker asks it for its status (getStatus); the firmware
+// answers with device info, start options and changeable variables. The tracker follows the event
+// feed, keeps the latest capabilities per device and writes them to the device_capabilities table
+// whenever they differ from the stored ones (values of variables are not compared). The diff of
+// the last change is stored with them, so the UI can render controls before the device broadcasts
+// anything and point out what a firmware update changed.
+
+use std::collections::{BTreeSet, HashMap};
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::activity;
+use crate::database::DatabaseManager;
+use crate::device_manager::DeviceManager;
+use crate::device_store::SharedDeviceStore;
+use crate::device_types::DeviceCommand;
+use crate::events::DeviceEvent;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceCapabilities {
+    pub device_name: Option<String>,
+    pub firmware_version: Option<String>,
+    pub start_options: Vec<String>,
+    /// As announced: name, value and optional min/max
+    pub changeable_variables: Vec<Value>,
+    /// Optional firmware features, e.g. "setTime"
+    pub features: Vec<String>,
+}
+
+impl DeviceCapabilities {
+    /// Take over what a device event tells about the device; false if it carries no capabilities
+    pub fn apply(&mut self, event: &DeviceEvent) -> bool {
+        match event {
+            DeviceEvent::DeviceStartOptions { options, .. } => self.start_options = options.clone(),
+            DeviceEvent::DeviceChangeableVariables { variables, .. } => self.changeable_variables = variables.clone(),
+            DeviceEvent::DeviceDeviceInfo { device_name, firmware_version, .. } => {
+                if device_name.is_some() {
+                    self.device_name = device_name.clone();
+                }
+                if let Some(version) = firmware_version.as_ref().filter(|v| *v != "unknown") {
+                    self.firmware_version = Some(version.clone());
+                }
+            }
+            DeviceEvent::DeviceUdpBroadcast { message, .. } if message.contains("\"features\"") => {
+                let Some(features) = serde_json::from_str::<Value>(message).ok()
+                    .and_then(|value| value.get("features").and_then(Value::as_array).cloned()) else {
+                    return false;
+                };
+                self.features = features.iter().filter_map(Value::as_str).map(str::to_string).collect();
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    /// Copy without the current variable values (those change all the time)
+    fn without_values(&self) -> DeviceCapabilities {
+        let mut copy = self.clone();
+        for variable in &mut copy.changeable_variables {
+            if let Some(variable) = variable.as_object_mut() {
+                variable.remove("value");
+            }
+        }
+        copy
+    }
+
+    /// Variable name -> (min, max)
+    fn variable_ranges(&self) -> HashMap<&str, (Option<&Value>, Option<&Value>)> {
+        self.changeable_variables.iter()
+            .filter_map(|v| Some((v.get("name")?.as_str()?, (v.get("min"), v.get("max")))))
+            .collect()
+    }
+}
+
+/// Firmware version before and after a change
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FirmwareChange {
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+/// Difference between two capability sets
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CapabilityDiff {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub firmware: Option<FirmwareChange>,
+    pub added_variables: Vec<String>,
+    pub removed_variables: Vec<String>,
+    /// Variables whose min/max changed
+    pub changed_variables: Vec<String>,
+    pub added_start_options: Vec<String>,
+    pub removed_start_options: Vec<String>,
+    pub added_features: Vec<String>,
+    pub removed_features: Vec<String>,
+}
+
+fn added_and_removed(before: &[String], after: &[String]) -> (Vec<String>, Vec<String>) {
+    let before: BTreeSet<&String> = before.iter().collect();
+    let after: BTreeSet<&String> = after.iter().collect();
+    (
+        after.difference(&before).map(|s| s.to_string()).collect(),
+        before.difference(&after).map(|s| s.to_string()).collect(),
+    )
+}
+
+impl CapabilityDiff {
+    /// Changes from `before` to `after`; sections `before` has no report of yet are not compared
+    pub fn between(before: &DeviceCapabilities, after: &DeviceCapabilities) -> Self {
+        let mut diff = CapabilityDiff::default();
+        if !before.start_options.is_empty() {
+            (diff.added_start_options, diff.removed_start_options) = added_and_removed(&before.start_options, &after.start_options);
+        }
+        if !before.features.is_empty() {
+            (diff.added_features, diff.removed_features) = added_and_removed(&before.features, &after.features);
+        }
+        if before.firmware_version.is_some() && before.firmware_version != after.firmware_version {
+            diff.firmware = Some(FirmwareChange { from: before.firmware_version.clone(), to: after.firmware_version.clone() });
+        }
+        if before.changeable_variables.is_empty() {
+            return diff;
+        }
+
+        let (old_vars, new_vars) = (before.variable_ranges(), after.variable_ranges());
+        for (name, range) in &new_vars {
+            match old_vars.get(name) {
+                None => diff.added_variables.push(name.to_string()),
+                Some(old_range) if old_range != range => diff.changed_variables.push(name.to_string()),
+                Some(_) => {}
+            }
+        }
+        diff.removed_variables = old_vars.keys().filter(|name| !new_vars.contains_key(*name)).map(|name| name.to_string()).collect();
+        for names in [&mut diff.added_variables, &mut diff.removed_variables, &mut diff.changed_variables] {
+            names.sort();
+        }
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CapabilityDiff::default()
+    }
+}
+
+/// Row of the device_capabilities table (GET /api/devices/:id/capabilities)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StoredCapabilities {
+    pub device_id: String,
+    pub capabilities: DeviceCapabilities,
+    /// Diff against the previously stored capabilities (None for the first snapshot)
+    pub last_changes: Option<CapabilityDiff>,
+    pub updated_at: String,
+}
+
+/// Follows the event feed and keeps the device_capabilities table current
+pub struct CapabilityTracker {
+    db: Arc<DatabaseManager>,
+    device_manager: Arc<DeviceManager>,
+}
+
+impl CapabilityTracker {
+    pub fn new(db: Arc<DatabaseManager>, device_manager: Arc<DeviceManager>) -> Self {
+        Self { db, device_manager }
+    }
+
+    pub fn start(self, device_store: SharedDeviceStore) {
+        let mut feed = device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut known: HashMap<String, DeviceCapabilities> = match self.db.get_all_device_capabilities().await {
+                Ok(stored) => stored.into_iter().collect(),
+                Err(e) => {
+                    warn!("Failed to load device capabilities: {}", e);
+                    HashMap::new()
+                }
+            };
+            info!("Capability tracker started ({} device(s) cached)", known.len());
+
+            loop {
+                match feed.recv().await {
+                    Ok(feed_event) => self.handle_event(&mut known, &feed_event.device_id, &feed_event.event).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Capability tracker lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle_event(&self, known: &mut HashMap<String, DeviceCapabilities>, device_id: &str, event: &DeviceEvent) {
+        // TCP link up: ask for info, start options and variables instead of waiting for a broadcast
+        if let DeviceEvent::DeviceConnectionStatus { connected: true, tcp_port, .. } = event {
+            if *tcp_port != 0 {
+                let (manager, device_id) = (Arc::clone(&self.device_manager), device_id.to_string());
+                tokio::spawn(async move {
+                    if let Err(e) = manager.send_command(&device_id, DeviceCommand::get_status()).await {
+                        debug!("Capability request to {} failed: {}", device_id, e);
+                    }
+                });
+            }
+            return;
+        }
+
+        let before = known.get(device_id).cloned().unwrap_or_default();
+        let mut capabilities = before.clone();
+        if !capabilities.apply(event) {
+            return;
+        }
+        // The device list and firmware inventory read devices.firmware_version; only written when it differs
+        if let DeviceEvent::DeviceDeviceInfo { firmware_version: Some(version), .. } = event {
+            if !version.is_empty() && version != "unknown" {
+                if let Err(e) = self.db.set_device_firmware_version(device_id, version).await {
+                    warn!("Failed to store firmware version of device {}: {}", device_id, e);
+                }
+            }
+        }
+        if capabilities.without_values() == before.without_values() {
+            // Same capabilities, possibly new variable values: keep those in memory only
+            known.insert(device_id.to_string(), capabilities);
+            return;
+        }
+        let changes = Some(CapabilityDiff::between(&before, &capabilities)).filter(|diff| !diff.is_empty());
+        if let Some(changes) = &changes {
+            info!("Capabilities of device {} changed: {}", device_id, serde_json::to_string(changes).unwrap_or_default());
+            if let Some(firmware) = &changes.firmware {
+                let details = serde_json::to_value(firmware).unwrap_or_default();
+                activity::record(&self.db, device_id, activity::SYSTEM_ACTOR, activity::AuditAction::FirmwareUpdated, details).await;
+            }
+        }
+
+        // Without changes (first report of a section) the stored diff is kept
+        if let Err(e) = self.db.set_device_capabilities(device_id, &capabilities, changes.as_ref()).await {
+            warn!("Failed to store capabilities of device {}: {}", device_id, e);
+        }
+        known.insert(device_id.to_string(), capabilities);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_apply_and_diff_ignore_variable_values() {
+        let id = "AA-BB".to_string();
+        let mut before = DeviceCapabilities::default();
+        assert!(before.apply(&DeviceEvent::device_changeable_variables(id.clone(), vec![
+            json!({"name": "speed", "value": 3, "min": 1, "max": 10}),
+            json!({"name": "mode", "value": 0}),
+        ])));
+        assert!(before.apply(&DeviceEvent::device_device_info(id.clone(), Some("Board".to_string()), Some("1.0".to_string()), Some(5))));
+        assert!(!before.apply(&DeviceEvent::device_variable_update(id.clone(), "speed".to_string(), "4".to_string())));
+
+        let mut after = before.clone();
+        after.apply(&DeviceEvent::device_changeable_variables(id.clone(), vec![
+            json!({"name": "speed", "value": 7, "min": 1, "max": 10}),
+            json!({"name": "mode", "value": 1}),
+        ]));
+        assert!(CapabilityDiff::between(&before, &after).is_empty(), "only values changed");
+
+        after.apply(&DeviceEvent::device_changeable_variables(id.clone(), vec![
+            json!({"name": "speed", "value": 7, "min": 1, "max": 20}),
+            json!({"name": "gain", "value": 1}),
+        ]));
+        after.apply(&DeviceEvent::device_device_info(id.clone(), None, Some("1.1".to_string()), None));
+        after.apply(&DeviceEvent::device_udp_broadcast(id, r#"{"features":["setTime"]}"#.to_string(), "10.0.0.2".to_string(), 3232));
+        let diff = CapabilityDiff::between(&before, &after);
+        assert_eq!(diff.added_variables, vec!["gain"]);
+        assert_eq!(diff.removed_variables, vec!["mode"]);
+        assert_eq!(diff.changed_variables, vec!["speed"]);
+        assert!(diff.added_features.is_empty(), "first report of a section is no change");
+        assert_ne!(before.without_values(), after.without_values());
+        assert_eq!(diff.firmware, Some(FirmwareChange { from: Some("1.0".to_string()), to: Some("1.1".to_string()) }));
+        assert_eq!(after.device_name.as_deref(), Some("Board"));
+    }
+}
@@ -0,0 +1,88 @@
+// Device chat - notes operators leave on the channel of a device
+//
+// A client sends a DeviceChatMessage on the device channel ("re-flashed at 14:32"); the server
+// fills in sender, message ID and time, broadcasts it like any other device event and keeps the
+// newest CHAT_HISTORY_LIMIT messages of each device in device_chat_messages. Full subscriptions
+// get the history with the replay on registration, so notes outlive the in-memory event store,
+// which forgets a device once nobody watches it. Mentions (mentions.rs) share the history.
+
+use tracing::warn;
+
+use crate::database::DatabaseManager;
+use crate::events::DeviceEvent;
+
+/// Messages kept per device
+pub const CHAT_HISTORY_LIMIT: usize = 100;
+
+/// Longest message text (also for mentions)
+pub const MAX_CHAT_TEXT: usize = 1000;
+
+/// Trimmed text of a chat message or mention, or why it is rejected
+pub fn validate_text(text: &str) -> Result<&str, String> {
+    let text = text.trim();
+    if text.is_empty() || text.chars().count() > MAX_CHAT_TEXT {
+        return Err(format!("Message text must be between 1 and {} characters", MAX_CHAT_TEXT));
+    }
+    Ok(text)
+}
+
+/// Display name shown next to a message (guests and unknown IDs: "Guest User")
+pub async fn sender_display_name(db: &DatabaseManager, user_id: &str) -> Result<String, String> {
+    let user = db.get_user_by_id(user_id).await
+        .map_err(|e| format!("Database error loading user {}: {}", user_id, e))?;
+    Ok(user.map(|user| user.display_name).unwrap_or_else(|| "Guest User".to_string()))
+}
+
+/// Build the DeviceChatMessage of a sender; client-supplied sender fields are replaced
+pub async fn build_chat_message(db: &DatabaseManager, device_id: &str, sender_id: &str, text: &str) -> Result<DeviceEvent, String> {
+    let text = validate_text(text)?;
+    Ok(DeviceEvent::DeviceChatMessage {
+        device_id: device_id.to_string(),
+        message_id: uuid::Uuid::new_v4().to_string(),
+        text: text.to_string(),
+        user_id: sender_id.to_string(),
+        display_name: sender_display_name(db, sender_id).await?,
+        sent_at: chrono::Utc::now().timestamp_millis(),
+    })
+}
+
+/// Keep a chat message or mention in the history; failures are logged, the broadcast goes out anyway
+pub async fn store(db: &DatabaseManager, device_id: &str, user_id: &str, event: &DeviceEvent) {
+    let stored = match serde_json::to_value(event) {
+        Ok(value) => db.insert_chat_message(device_id, user_id, &value, CHAT_HISTORY_LIMIT).await.map_err(|e| e.to_string()),
+        Err(e) => Err(e.to_string()),
+    };
+    if let Err(e) = stored {
+        warn!("Failed to store chat message for device {}: {}", device_id, e);
+    }
+}
+
+/// Stored messages of a device, oldest first, for the registration replay
+pub async fn history(db: &DatabaseManager, device_id: &str) -> Vec<DeviceEvent> {
+    match db.list_chat_messages(device_id).await {
+        Ok(events) => events.into_iter().filter_map(|event| serde_json::from_value(event).ok()).collect(),
+        Err(e) => {
+            warn!("Failed to load chat history of device {}: {}", device_id, e);
+            Vec::new()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_text() {
+        assert_eq!(validate_text("  re-flashed at 14:32 \n").unwrap(), "re-flashed at 14:32");
+        assert!(validate_text("   ").is_err());
+        assert!(validate_text(&"ä".repeat(MAX_CHAT_TEXT)).is_ok());
+        assert!(validate_text(&"a".repeat(MAX_CHAT_TEXT + 1)).is_err());
+
+        // Sender fields sent by a client are optional in the wire format
+        let event: DeviceEvent = serde_json::from_value(serde_json::json!({
+            "event": "DeviceChatMessage", "deviceId": "AA-01", "text": "hi"
+        })).unwrap();
+        assert!(matches!(event, DeviceEvent::DeviceChatMessage { ref user_id, sent_at: 0, .. } if user_id.is_empty()));
+    }
+}
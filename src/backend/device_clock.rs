@@ -0,0 +1,218 @@
+// Device clock tracking - server receive time vs. device-reported uptime/timestamp
+//
+// Firmware reports `uptime` (seconds since boot) and, when it has a wall clock, `timestamp`
+// (epoch seconds or milliseconds). Every report is paired with the server receive time. From the
+// pairs the tracker derives the boot time, the offset of the device wall clock and its drift
+// against the server since the first report of the current boot. Stored events are stamped with
+// the device time extrapolated to their receive time, so exports of several boards line up.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// Shortest baseline for a drift estimate (uptime has a resolution of one second)
+const MIN_DRIFT_BASELINE_MS: i64 = 60_000;
+
+/// Offset change between two reports that counts as the device clock being set
+const CLOCK_STEP_MS: i64 = 5_000;
+
+/// Epoch timestamps below this are seconds, above milliseconds
+const EPOCH_SECONDS_LIMIT: i64 = 100_000_000_000;
+
+/// Time values of one device report
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClockReport {
+    pub uptime_ms: Option<u64>,
+    pub epoch_ms: Option<i64>,
+    /// Firmware accepts `setTime` (announced via `"features": ["setTime"]`)
+    pub set_time: bool,
+}
+
+impl ClockReport {
+    /// Time fields of a device message; None if it carries neither uptime nor timestamp
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.contains("\"uptime\"") && !message.contains("\"timestamp\"") {
+            return None;
+        }
+        let value: serde_json::Value = serde_json::from_str(message).ok()?;
+        let report = ClockReport {
+            uptime_ms: value.get("uptime").and_then(|v| v.as_u64()).map(|secs| secs.saturating_mul(1000)),
+            epoch_ms: value.get("timestamp").and_then(|v| v.as_i64()).filter(|t| *t > 0)
+                .map(|t| if t < EPOCH_SECONDS_LIMIT { t.saturating_mul(1000) } else { t }),
+            set_time: value.get("features").and_then(|v| v.as_array())
+                .is_some_and(|features| features.iter().any(|f| f.as_str() == Some("setTime"))),
+        };
+        (report.uptime_ms.is_some() || report.epoch_ms.is_some()).then_some(report)
+    }
+}
+
+/// Device time of a stored event, extrapolated from the device's last report
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTimestamp {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub uptime_ms: Option<u64>,
+    /// Device wall clock (ms since epoch)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_time: Option<i64>,
+}
+
+/// Clock state of one device (GET /api/devices/:id/clock)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClockEstimate {
+    pub device_id: String,
+    pub reports: u64,
+    /// Server time of the last report (ms since epoch)
+    pub last_report_at: i64,
+    pub uptime_ms: Option<u64>,
+    /// Estimated boot time (server clock, ms since epoch)
+    pub booted_at: Option<i64>,
+    /// Server clock minus device wall clock at the last report
+    pub offset_ms: Option<i64>,
+    /// Rate error of the device clock: positive = runs fast (parts per million)
+    pub drift_ppm: Option<f64>,
+    pub supports_set_time: bool,
+    pub last_sync_at: Option<i64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    server_ms: i64,
+    report: ClockReport,
+}
+
+#[derive(Debug)]
+struct DeviceClock {
+    /// First report since boot, clock step or sync (drift baseline)
+    baseline: Sample,
+    last: Sample,
+    reports: u64,
+    supports_set_time: bool,
+    last_sync_at: Option<i64>,
+}
+
+impl DeviceClock {
+    fn estimate(&self, device_id: &str) -> ClockEstimate {
+        let last = self.last;
+        let elapsed = last.server_ms - self.baseline.server_ms;
+        let device_elapsed = match (self.baseline.report.epoch_ms, last.report.epoch_ms) {
+            (Some(first), Some(latest)) => Some(latest - first),
+            _ => self.baseline.report.uptime_ms.zip(last.report.uptime_ms).map(|(first, latest)| latest as i64 - first as i64),
+        };
+        ClockEstimate {
+            device_id: device_id.to_string(),
+            reports: self.reports,
+            last_report_at: last.server_ms,
+            uptime_ms: last.report.uptime_ms,
+            booted_at: last.report.uptime_ms.map(|uptime| last.server_ms - uptime as i64),
+            offset_ms: last.report.epoch_ms.map(|epoch| last.server_ms - epoch),
+            drift_ppm: device_elapsed.filter(|_| elapsed >= MIN_DRIFT_BASELINE_MS)
+                .map(|device_elapsed| (device_elapsed - elapsed) as f64 / elapsed as f64 * 1e6),
+            supports_set_time: self.supports_set_time,
+            last_sync_at: self.last_sync_at,
+        }
+    }
+}
+
+/// Clock state of all devices, owned by the event store
+#[derive(Debug, Default)]
+pub struct DeviceClocks {
+    clocks: Mutex<HashMap<String, DeviceClock>>,
+}
+
+impl DeviceClocks {
+    /// Record a report received at `server_ms`
+    pub fn observe(&self, device_id: &str, report: ClockReport, server_ms: i64) {
+        let sample = Sample { server_ms, report };
+        let mut clocks = self.clocks.lock().unwrap();
+        let Some(clock) = clocks.get_mut(device_id) else {
+            clocks.insert(device_id.to_string(), DeviceClock {
+                baseline: sample, last: sample, reports: 1, supports_set_time: report.set_time || report.epoch_ms.is_some(), last_sync_at: None,
+            });
+            return;
+        };
+
+        let rebooted = report.uptime_ms.zip(clock.last.report.uptime_ms).is_some_and(|(now, before)| now < before);
+        let stepped = report.epoch_ms.zip(clock.last.report.epoch_ms).is_some_and(|(now, before)| {
+            ((server_ms - now) - (clock.last.server_ms - before)).abs() > CLOCK_STEP_MS
+        });
+        // Baseline without the fields of this report cannot give a drift
+        let incomparable = report.epoch_ms.is_some() != clock.baseline.report.epoch_ms.is_some()
+            || report.uptime_ms.is_some() != clock.baseline.report.uptime_ms.is_some();
+        if rebooted || stepped || incomparable {
+            clock.baseline = sample;
+        }
+        clock.last = sample;
+        clock.reports += 1;
+        clock.supports_set_time |= report.set_time || report.epoch_ms.is_some();
+    }
+
+    /// Device time at server time `server_ms`; None for devices that never reported
+    pub fn stamp(&self, device_id: &str, server_ms: i64) -> Option<DeviceTimestamp> {
+        let clocks = self.clocks.lock().unwrap();
+        let last = clocks.get(device_id)?.last;
+        let since = (server_ms - last.server_ms).max(0);
+        Some(DeviceTimestamp {
+            uptime_ms: last.report.uptime_ms.map(|uptime| uptime + since as u64),
+            device_time: last.report.epoch_ms.map(|epoch| epoch + since),
+        })
+    }
+
+    pub fn estimate(&self, device_id: &str) -> Option<ClockEstimate> {
+        self.clocks.lock().unwrap().get(device_id).map(|clock| clock.estimate(device_id))
+    }
+
+    pub fn estimates(&self) -> Vec<ClockEstimate> {
+        let mut estimates: Vec<ClockEstimate> = self.clocks.lock().unwrap().iter()
+            .map(|(device_id, clock)| clock.estimate(device_id))
+            .collect();
+        estimates.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        estimates
+    }
+
+    /// Server time was pushed to the device: the next report starts a new drift baseline
+    pub fn mark_synced(&self, device_id: &str, server_ms: i64) {
+        if let Some(clock) = self.clocks.lock().unwrap().get_mut(device_id) {
+            clock.last_sync_at = Some(server_ms);
+            clock.baseline.report.epoch_ms = None;
+        }
+    }
+
+    pub fn forget(&self, device_id: &str) {
+        self.clocks.lock().unwrap().remove(device_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_drift_offset_and_event_stamps() {
+        let report = ClockReport::parse(r#"{"deviceName":"Board","uptime":100,"timestamp":1700000000}"#).unwrap();
+        assert_eq!(report, ClockReport { uptime_ms: Some(100_000), epoch_ms: Some(1_700_000_000_000), set_time: false });
+        assert!(ClockReport::parse(r#"{"temperature":21}"#).is_none());
+        assert!(ClockReport::parse(r#"{"uptime":5,"features":["setTime"]}"#).unwrap().set_time);
+
+        let clocks = DeviceClocks::default();
+        let server = 1_700_000_002_000;
+        clocks.observe("dev", report, server);
+        // Device wall clock gains 10 ms over 100 s: +100 ppm
+        clocks.observe("dev", ClockReport { uptime_ms: Some(200_000), epoch_ms: Some(1_700_000_100_010), set_time: false }, server + 100_000);
+        let estimate = clocks.estimate("dev").unwrap();
+        assert_eq!(estimate.offset_ms, Some(1_990));
+        assert_eq!(estimate.booted_at, Some(server + 100_000 - 200_000));
+        assert!((estimate.drift_ppm.unwrap() - 100.0).abs() < 0.01, "{:?}", estimate.drift_ppm);
+        assert!(estimate.supports_set_time);
+
+        let stamp = clocks.stamp("dev", server + 100_500).unwrap();
+        assert_eq!(stamp, DeviceTimestamp { uptime_ms: Some(200_500), device_time: Some(1_700_000_100_510) });
+        assert!(clocks.stamp("other", server).is_none());
+
+        // Reboot restarts the drift baseline
+        clocks.observe("dev", ClockReport { uptime_ms: Some(1_000), epoch_ms: Some(1_700_000_101_010), set_time: false }, server + 101_000);
+        assert_eq!(clocks.estimate("dev").unwrap().drift_ppm, None);
+    }
+}
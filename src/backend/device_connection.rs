@@ -1,22 +1,138 @@
 // Device TCP/UDP connection management
 
 use crate::device_types::{
-    DeviceCommand, DeviceEvent, DeviceConfig, ConnectionState, DeviceResult, DeviceError
+    DeviceCommand, DeviceEvent, DeviceConfig, DeviceTlsConfig, ConnectionSettings, ConnectionState, DeviceResult, DeviceError, tls_file_path
 };
-use crate::device_store::SharedDeviceStore;
-
+use crate::device_manager::{DeviceConnectionType, MessageSource};
+use crate::device_transport::{DeviceTransport, TransportContext};
+use crate::device_trace::{Direction, Transport};
+use crate::device_identity::{Challenge, IDENTITY_TIMEOUT};
+use crate::inbound_validation;
+
+use futures::future::BoxFuture;
+use std::pin::Pin;
 use std::sync::Arc;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::task::{Context, Poll};
 use std::time::Duration;
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
 use tokio::net::TcpStream;
 use tokio::sync::{mpsc, RwLock, Mutex};
 use tokio::time::{timeout, sleep};
-use tracing::{info, warn, error, debug};
+use tracing::{info, warn, error, debug, Instrument};
 
 // Global reset attempt counter
 static RESET_COUNTER: AtomicU32 = AtomicU32::new(0);
 
+// ============================================================================
+// DEVICE STREAM - plaintext TCP or TLS over TCP
+// ============================================================================
+
+/// TCP channel to a device, optionally wrapped in TLS
+pub enum DeviceStream {
+    Plain(TcpStream),
+    Tls(Box<tokio_rustls::client::TlsStream<TcpStream>>),
+}
+
+impl std::fmt::Debug for DeviceStream {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DeviceStream::Plain(stream) => f.debug_tuple("Plain").field(stream).finish(),
+            DeviceStream::Tls(stream) => f.debug_tuple("Tls").field(stream.get_ref().0).finish(),
+        }
+    }
+}
+
+impl DeviceStream {
+    /// Underlying TCP socket (socket options)
+    fn tcp(&self) -> &TcpStream {
+        match self {
+            DeviceStream::Plain(stream) => stream,
+            DeviceStream::Tls(stream) => stream.get_ref().0,
+        }
+    }
+}
+
+impl AsyncRead for DeviceStream {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            DeviceStream::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            DeviceStream::Tls(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for DeviceStream {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        match self.get_mut() {
+            DeviceStream::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            DeviceStream::Tls(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            DeviceStream::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            DeviceStream::Tls(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        match self.get_mut() {
+            DeviceStream::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            DeviceStream::Tls(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Build a rustls connector from the per-device TLS settings
+fn build_tls_connector(tls: &DeviceTlsConfig) -> DeviceResult<tokio_rustls::TlsConnector> {
+    fn read_pem(name: &str) -> DeviceResult<std::io::BufReader<std::fs::File>> {
+        let path = tls_file_path(name)
+            .ok_or_else(|| DeviceError::ConnectionFailed(format!("TLS file {} is not a file name in the certificate directory", name)))?;
+        std::fs::File::open(path)
+            .map(std::io::BufReader::new)
+            .map_err(|e| DeviceError::ConnectionFailed(format!("Cannot read TLS file {}: {}", name, e)))
+    }
+
+    let mut roots = rustls::RootCertStore::empty();
+    let ca_certs = rustls_pemfile::certs(&mut read_pem(&tls.ca_cert_path)?)?;
+    if ca_certs.is_empty() {
+        return Err(DeviceError::ConnectionFailed(format!("No certificate found in {}", tls.ca_cert_path)));
+    }
+    for der in ca_certs {
+        roots.add(&rustls::Certificate(der))
+            .map_err(|e| DeviceError::ConnectionFailed(format!("Invalid TLS trust anchor: {}", e)))?;
+    }
+
+    let builder = rustls::ClientConfig::builder()
+        .with_safe_defaults()
+        .with_root_certificates(roots);
+
+    let client_config = match (&tls.client_cert_path, &tls.client_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let certs = rustls_pemfile::certs(&mut read_pem(cert_path)?)?
+                .into_iter()
+                .map(rustls::Certificate)
+                .collect();
+            let key = rustls_pemfile::read_all(&mut read_pem(key_path)?)?
+                .into_iter()
+                .find_map(|item| match item {
+                    rustls_pemfile::Item::PKCS8Key(key)
+                    | rustls_pemfile::Item::RSAKey(key)
+                    | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+                    _ => None,
+                })
+                .ok_or_else(|| DeviceError::ConnectionFailed(format!("No private key found in {}", key_path)))?;
+            builder.with_client_auth_cert(certs, key)
+                .map_err(|e| DeviceError::ConnectionFailed(format!("Invalid TLS client certificate: {}", e)))?
+        }
+        _ => builder.with_no_client_auth(),
+    };
+
+    Ok(tokio_rustls::TlsConnector::from(Arc::new(client_config)))
+}
+
 // ============================================================================
 // DEVICE CONNECTION MANAGER
 // ============================================================================
@@ -24,16 +140,13 @@ static RESET_COUNTER: AtomicU32 = AtomicU32::new(0);
 #[derive(Debug)]
 pub struct DeviceConnection {
     config: DeviceConfig,
-    tcp_stream: Arc<Mutex<Option<TcpStream>>>,
+    tcp_stream: Arc<Mutex<Option<DeviceStream>>>,
     connection_state: Arc<RwLock<ConnectionState>>,
     event_sender: mpsc::UnboundedSender<DeviceEvent>,
     tcp_buffer: Arc<Mutex<String>>,
     shutdown_sender: Option<mpsc::UnboundedSender<()>>,
-    device_store: SharedDeviceStore,
-    /// Unified connection states (shared with DeviceManager)
-    unified_connection_states: Arc<RwLock<std::collections::HashMap<String, bool>>>,
-    /// Device connection types map (shared with DeviceManager)
-    DEVICE_CONNECTION_types: Arc<RwLock<std::collections::HashMap<String, crate::device_manager::DeviceConnectionType>>>,
+    /// Event store, lifecycle and trackers shared with DeviceManager
+    context: TransportContext,
 }
 
 impl DeviceConnection {
@@ -41,9 +154,7 @@ impl DeviceConnection {
     pub fn new(
         config: DeviceConfig,
         event_sender: mpsc::UnboundedSender<DeviceEvent>,
-        device_store: SharedDeviceStore,
-        unified_connection_states: Arc<RwLock<std::collections::HashMap<String, bool>>>,
-        DEVICE_CONNECTION_types: Arc<RwLock<std::collections::HashMap<String, crate::device_manager::DeviceConnectionType>>>,
+        context: TransportContext,
     ) -> Self {
         info!("DEVICE_CONNECTION CREATION DEBUG: Creating new DeviceConnection for device {}", config.device_id);
         crate::debug_logger::DebugLogger::log_event("DEVICE_CONNECTION", &format!("NEW_CONNECTION_CREATED: {} - sender_closed: {}", config.device_id, event_sender.is_closed()));
@@ -55,9 +166,7 @@ impl DeviceConnection {
             event_sender,
             tcp_buffer: Arc::new(Mutex::new(String::new())),
             shutdown_sender: None,
-            device_store,
-            unified_connection_states,
-            DEVICE_CONNECTION_types,
+            context,
         }
     }
     
@@ -169,9 +278,15 @@ impl DeviceConnection {
         Ok(())
     }
     
-    /// Send command to Device via TCP
-    pub async fn send_command(&self, command: DeviceCommand) -> DeviceResult<()> {
-        debug!("Sending command to Device {}: {:?}", self.config.device_id, command);
+    /// Capture an outgoing TCP frame if the device is being traced
+    fn trace_outgoing(&self, json_str: &str) {
+        let peer = format!("{}:{}", self.config.ip_address, self.config.tcp_port);
+        crate::device_trace::record(&self.config.device_id, Direction::Out, Transport::Tcp, Some(peer), json_str.as_bytes());
+    }
+
+    /// Send command to Device via TCP, optionally tagged with a correlation ID for reply matching
+    pub async fn send_command_with_correlation(&self, command: DeviceCommand, correlation_id: Option<&str>) -> DeviceResult<()> {
+        debug!("Sending command to Device {}: {:?} (correlation: {:?})", self.config.device_id, command, correlation_id);
 
         // Check if this is a reset command (which will close the TCP connection)
         let is_reset_command = matches!(command, DeviceCommand::Reset { .. });
@@ -184,7 +299,7 @@ impl DeviceConnection {
             0
         };
 
-        let json_str = command.to_json()?;
+        let json_str = command.to_json_with_correlation(correlation_id)?;
         let command_name = format!("{:?}", command);
 
         // Log command attempt to debug file
@@ -198,6 +313,7 @@ impl DeviceConnection {
 
             // Send the command
             crate::debug_logger::DebugLogger::log_tcp_message(&self.config.device_id, "SENT", &json_str);
+            self.trace_outgoing(&json_str);
             let write_result = stream.write_all(json_str.as_bytes()).await;
             if let Err(e) = write_result {
                 if is_reset_command {
@@ -239,6 +355,7 @@ impl DeviceConnection {
                     let mut state = self.connection_state.write().await;
                     *state = ConnectionState::Connecting; // This prevents the connection from being removed from HashMap
                 }
+                self.context.lifecycle.connecting(&self.config.device_id, "Reset, waiting for reconnect").await;
 
                 // Do NOT send disconnect event for reset commands - this is a temporary state
                 // The Device will reconnect automatically and we want to keep the connection object alive
@@ -283,6 +400,7 @@ impl DeviceConnection {
             if let Some(stream) = tcp.as_mut() {
                 crate::debug_logger::DebugLogger::log_tcp_connection_status(&self.config.device_id, "AVAILABLE_AFTER_RECONNECT", "TCP stream available after reconnect, sending command");
 
+                self.trace_outgoing(&json_str);
                 match stream.write_all(json_str.as_bytes()).await {
                     Ok(()) => {
                         match stream.flush().await {
@@ -302,6 +420,7 @@ impl DeviceConnection {
                                         let mut state = self.connection_state.write().await;
                                         *state = ConnectionState::Connecting;
                                     }
+                                    self.context.lifecycle.connecting(&self.config.device_id, "Reset, waiting for reconnect").await;
                                     info!("RESET COMMAND: TCP stream closed after reconnect reset for device {}, connection kept alive for automatic reconnect", self.config.device_id);
                                 }
 
@@ -333,13 +452,40 @@ impl DeviceConnection {
     // TCP CONNECTION HANDLING
     // ========================================================================
     
+    /// Send an identity challenge and wait for its response; returns what the device sent after it
+    async fn exchange_challenge(&self, stream: &mut DeviceStream, challenge: &Challenge, secret: &str) -> Result<String, String> {
+        let message = format!("{}\n", challenge.message());
+        self.trace_outgoing(&message);
+        stream.write_all(message.as_bytes()).await.map_err(|e| format!("sending challenge failed: {}", e))?;
+
+        let mut pending = String::new();
+        let mut undecoded = Vec::new();
+        let mut buffer = [0u8; 1024];
+        loop {
+            let n = stream.read(&mut buffer).await.map_err(|e| format!("reading response failed: {}", e))?;
+            if n == 0 {
+                return Err("connection closed before the identity response".to_string());
+            }
+            let text = inbound_validation::decode_stream_chunk(&mut undecoded, &buffer[..n]).map_err(|e| format!("invalid response: {}", e))?;
+            pending.push_str(&text);
+            while let Some(message) = extract_complete_json(&mut pending) {
+                match challenge.verify_line(&message, secret, &self.config.device_id) {
+                    Some(result) => return result.map(|_| pending),
+                    None => debug!("Skipping message from device {} while waiting for its identity response", self.config.device_id),
+                }
+            }
+            inbound_validation::check_size(pending.len()).map_err(|e| format!("invalid response: {}", e))?;
+        }
+    }
+
     /// Establish TCP connection to Device
     async fn connect_tcp(&self) -> DeviceResult<()> {
         let tcp_addr = self.config.tcp_addr();
         debug!("Connecting to TCP address: {}", tcp_addr);
 
         // Try to connect with timeout
-        let stream = timeout(Duration::from_secs(5), TcpStream::connect(tcp_addr))
+        let connect_timeout = Duration::from_secs(self.config.connect_timeout_seconds);
+        let stream = timeout(connect_timeout, TcpStream::connect(tcp_addr))
             .await
             .map_err(|_| DeviceError::Timeout)?
             .map_err(|e| DeviceError::ConnectionFailed(format!("TCP connection failed: {}", e)))?;
@@ -357,20 +503,8 @@ impl DeviceConnection {
             warn!("Failed to enable TCP keep-alive for device {}: {}", self.config.device_id, e);
         }
 
-        // Set TCP keep-alive for 10 minute disconnect detection
-        #[cfg(any(target_os = "linux", target_os = "windows"))]
-        {
-            use socket2::TcpKeepalive;
-            let keepalive = TcpKeepalive::new()
-                .with_time(Duration::from_secs(600))     // Start after 10 minutes of inactivity
-                .with_interval(Duration::from_secs(60)); // Send probe every 60 seconds
-
-            if let Err(e) = socket2_socket.set_tcp_keepalive(&keepalive) {
-                warn!("Failed to set TCP keep-alive parameters for device {}: {}", self.config.device_id, e);
-            } else {
-                info!("TCP keep-alive enabled for device {} (10min idle, 60s interval)", self.config.device_id);
-            }
-        }
+        // Keep-alive probes detect a silently vanished device ([devices] keepalive_*, or per device)
+        set_keepalive(socket2::SockRef::from(&socket2_socket), &self.config);
 
         // Note: Additional Windows TCP optimizations would require more complex winapi setup
 
@@ -378,6 +512,48 @@ impl DeviceConnection {
 
         // Convert back to tokio TcpStream
         let stream = TcpStream::from_std(socket2_socket.into())?;
+
+        // Wrap in TLS if configured for this device
+        let stream = match &self.config.tls {
+            Some(tls) => {
+                let connector = build_tls_connector(tls)?;
+                let name = tls.server_name.clone().unwrap_or_else(|| self.config.ip_address.to_string());
+                let server_name = rustls::ServerName::try_from(name.as_str())
+                    .map_err(|e| DeviceError::ConnectionFailed(format!("Invalid TLS server name {}: {}", name, e)))?;
+
+                let tls_stream = timeout(connect_timeout, connector.connect(server_name, stream))
+                    .await
+                    .map_err(|_| DeviceError::Timeout)?
+                    .map_err(|e| DeviceError::ConnectionFailed(format!("TLS handshake failed: {}", e)))?;
+
+                info!("TLS session established with device {} ({})", self.config.device_id, name);
+                DeviceStream::Tls(Box::new(tls_stream))
+            }
+            None => DeviceStream::Plain(stream),
+        };
+
+        // Devices with a shared secret have to prove their identity before they count as connected
+        let mut stream = stream;
+        if let Some(secret) = &self.config.identity_secret {
+            let challenge = Challenge::new();
+            let result = timeout(IDENTITY_TIMEOUT, self.exchange_challenge(&mut stream, &challenge, secret))
+                .await
+                .unwrap_or_else(|_| Err(format!("no identity response within {}s", IDENTITY_TIMEOUT.as_secs())));
+            match result {
+                Ok(leftover) => {
+                    info!("Device {} proved its identity", self.config.device_id);
+                    // Messages sent right after the response go to the listener
+                    *self.tcp_buffer.lock().await = leftover;
+                }
+                Err(reason) => {
+                    warn!("Identity verification of device {} failed: {}", self.config.device_id, reason);
+                    let _ = stream.shutdown().await;
+                    let reason = format!("Identity verification failed: {}", reason);
+                    *self.connection_state.write().await = ConnectionState::Failed(reason.clone());
+                    return Err(DeviceError::ConnectionFailed(reason));
+                }
+            }
+        }
         
         // Store stream
         {
@@ -402,13 +578,16 @@ impl DeviceConnection {
         let _event_sender = self.event_sender.clone();
         let _connection_state = Arc::clone(&self.connection_state);
         let device_id = self.config.device_id.clone();
-        let _device_config = self.config.clone();
-        let device_store = self.device_store.clone();
-        let unified_connection_states = Arc::clone(&self.unified_connection_states);
-        let DEVICE_CONNECTION_types = Arc::clone(&self.DEVICE_CONNECTION_types);
+        let device_config = self.config.clone();
+        let context = self.context.clone();
+        let peer = format!("{}:{}", self.config.ip_address, self.config.tcp_port);
 
+        // Own root span: device logs are tagged with device_id, not with the request that connected it
+        let span = tracing::info_span!(parent: None, "device", device_id = %device_id);
         tokio::spawn(async move {
             let mut buffer = [0u8; 1024];
+            // Start of a character split across two reads
+            let mut undecoded = Vec::new();
 
             info!("TCP LISTENER TASK: Started for device {} (using TCP keep-alive only)", device_id);
 
@@ -433,10 +612,31 @@ impl DeviceConnection {
                             // Connection closed
                             info!("TCP connection closed for device {}", device_id);
                             *tcp = None;
+                            if context.lifecycle.link_down(&device_id, "TCP connection closed by device").await {
+                                let event = crate::events::DeviceEvent::device_connection_status(
+                                    device_id.clone(),
+                                    false,
+                                    device_config.ip_address.to_string(),
+                                    device_config.tcp_port,
+                                    device_config.udp_port,
+                                );
+                                if let Err(e) = context.device_store.add_event(device_id.clone(), event, "device_system".to_string(), "tcp_closed".to_string()).await {
+                                    warn!("Failed to send disconnect event for device {}: {}", device_id, e);
+                                }
+                            }
                         }
                         Ok(Ok(bytes_read)) => {
                             // Got data from Device
-                            let message = String::from_utf8_lossy(&buffer[..bytes_read]);
+                            crate::device_trace::record(&device_id, Direction::In, Transport::Tcp, Some(peer.clone()), &buffer[..bytes_read]);
+                            let message = match inbound_validation::decode_stream_chunk(&mut undecoded, &buffer[..bytes_read]) {
+                                Ok(message) => message,
+                                Err(e) => {
+                                    // The partial message in the buffer cannot be completed any more
+                                    warn!("TCP data of device {} dropped: {}", device_id, e);
+                                    tcp_buffer.lock().await.clear();
+                                    continue;
+                                }
+                            };
                             info!("TCP RECEIVED from {}: {}", device_id, message);
                             crate::debug_logger::DebugLogger::log_tcp_message(&device_id, "RECEIVED", &message);
 
@@ -450,22 +650,22 @@ impl DeviceConnection {
                             let mut buffer_guard = tcp_buffer.lock().await;
                             while let Some(json_str) = extract_complete_json(&mut buffer_guard) {
                                 info!("TCP JSON extracted: {}", json_str);
-                                // Process the TCP message using direct DeviceStore bypass
+                                // Deliver the TCP message through the unified pipeline
                                 let device_id_clone = device_id.clone();
-                                let json_clone = json_str.clone();
-                                let device_store_clone = device_store.clone();
-                                let unified_connection_states_clone = Arc::clone(&unified_connection_states);
-                                let DEVICE_CONNECTION_types_clone = Arc::clone(&DEVICE_CONNECTION_types);
+                                let context_clone = context.clone();
+                                let source = MessageSource::Tcp {
+                                    ip: device_config.ip_address.to_string(),
+                                    port: device_config.tcp_port,
+                                };
                                 tokio::spawn(async move {
-                                    crate::device_manager::DeviceManager::handle_tcp_message_bypass(
-                                        &json_clone,
-                                        &device_id_clone,
-                                        &device_store_clone,
-                                        &unified_connection_states_clone,
-                                        &DEVICE_CONNECTION_types_clone
-                                    ).await;
+                                    context_clone.deliver(&device_id_clone, &json_str, source).await;
                                 });
                             }
+                            // A message that never completes must not grow the buffer forever
+                            if let Err(e) = inbound_validation::check_size(buffer_guard.len()) {
+                                warn!("TCP data of device {} dropped: incomplete {}", device_id, e);
+                                buffer_guard.clear();
+                            }
                         }
                         Ok(Err(e)) => {
                             // Read error
@@ -484,7 +684,7 @@ impl DeviceConnection {
             }
 
             debug!("TCP listener task ended for device {}", device_id);
-        });
+        }.instrument(span));
     }
     
     // ========================================================================
@@ -492,12 +692,69 @@ impl DeviceConnection {
     // ========================================================================
 }
 
+/// Keep-alive timing of the device config on a TCP socket
+fn set_keepalive(socket: socket2::SockRef<'_>, config: &DeviceConfig) {
+    #[cfg(any(target_os = "linux", target_os = "windows"))]
+    {
+        let keepalive = socket2::TcpKeepalive::new()
+            .with_time(Duration::from_secs(config.keepalive_idle_seconds))
+            .with_interval(Duration::from_secs(config.keepalive_interval_seconds));
+
+        if let Err(e) = socket.set_tcp_keepalive(&keepalive) {
+            warn!("Failed to set TCP keep-alive parameters for device {}: {}", config.device_id, e);
+        } else {
+            info!("TCP keep-alive for device {}: {}s idle, {}s interval", config.device_id, config.keepalive_idle_seconds, config.keepalive_interval_seconds);
+        }
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "windows")))]
+    let _ = (socket, config);
+}
+
+// ============================================================================
+// TRANSPORT IMPLEMENTATION (TCP)
+// ============================================================================
+
+impl DeviceTransport for DeviceConnection {
+    fn connection_type(&self) -> DeviceConnectionType {
+        DeviceConnectionType::TcpUdp
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, DeviceResult<()>> {
+        Box::pin(DeviceConnection::connect(self))
+    }
+
+    fn disconnect(&mut self) -> BoxFuture<'_, DeviceResult<()>> {
+        Box::pin(DeviceConnection::disconnect(self))
+    }
+
+    fn send<'a>(&'a self, command: DeviceCommand, correlation_id: Option<&'a str>) -> BoxFuture<'a, DeviceResult<()>> {
+        Box::pin(self.send_command_with_correlation(command, correlation_id))
+    }
+
+    fn health(&self) -> BoxFuture<'_, ConnectionState> {
+        Box::pin(self.get_connection_state())
+    }
+
+    fn apply_settings(&mut self, settings: ConnectionSettings) -> BoxFuture<'_, ()> {
+        Box::pin(async move {
+            let keepalive_changed = (settings.keepalive_idle_seconds, settings.keepalive_interval_seconds)
+                != (self.config.keepalive_idle_seconds, self.config.keepalive_interval_seconds);
+            self.config.apply_connection_settings(&settings);
+            if keepalive_changed {
+                if let Some(stream) = self.tcp_stream.lock().await.as_ref() {
+                    set_keepalive(socket2::SockRef::from(stream.tcp()), &self.config);
+                }
+            }
+        })
+    }
+}
+
 // ============================================================================
 // MESSAGE PARSING HELPERS
 // ============================================================================
 
 /// Extract complete JSON object from TCP buffer
-fn extract_complete_json(buffer: &mut String) -> Option<String> {
+pub(crate) fn extract_complete_json(buffer: &mut String) -> Option<String> {
     let text = buffer.trim_start();
     if text.is_empty() {
         return None;
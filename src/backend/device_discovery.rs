@@ -1,16 +1,17 @@
 // Device Discovery Service - Automatically discovers and manages Device devices
 
-use crate::mdns_discovery::{MdnsDiscovery, create_mdns_discovery, MdnsDiscoveredDevice};
-use crate::device_types::{DeviceConfig, DeviceResult};
+use crate::mdns_discovery::{MdnsDiscovery, create_mdns_discovery, MdnsDiscoveredDevice, MdnsServiceConfig};
+use crate::device_types::{DeviceConfig, DeviceResult, TcpScanConfig, UdpDiscoveryProbe};
 use crate::device_manager::DeviceManager;
 use crate::events::DeviceEvent;
 use crate::device_store::DeviceEventStore;
 use crate::database::DatabaseManager;
 
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::sync::Arc;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc, RwLock};
 use tracing::{info, debug, warn};
 
 // ============================================================================
@@ -22,10 +23,59 @@ use tracing::{info, debug, warn};
 pub struct DiscoveredDevice {
     pub device_config: DeviceConfig,
     pub discovered_at: chrono::DateTime<chrono::Utc>,
+    /// Last mDNS answer, scan reply or connection activity
+    pub last_seen: chrono::DateTime<chrono::Utc>,
     pub udp_port: u16,
     pub mdns_data: Option<MdnsDiscoveredDevice>,
 }
 
+/// Liveness of a discovery entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Freshness {
+    Fresh,
+    Stale,
+    Expired,
+}
+
+/// Aging of discovery results
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DiscoveryAgingConfig {
+    /// Entries not seen for this long are flagged stale
+    pub stale_after_seconds: u64,
+    /// Entries not seen for this long are expired
+    pub expire_after_seconds: u64,
+    /// Remove expired entries (false = keep them flagged as expired)
+    pub remove_expired: bool,
+    /// Interval for re-browsing mDNS services (applied on restart)
+    pub mdns_reresolve_seconds: u64,
+}
+
+impl Default for DiscoveryAgingConfig {
+    fn default() -> Self {
+        Self {
+            stale_after_seconds: 300,
+            expire_after_seconds: 1800,
+            remove_expired: true,
+            mdns_reresolve_seconds: 120,
+        }
+    }
+}
+
+impl DiscoveryAgingConfig {
+    pub fn freshness(&self, last_seen: chrono::DateTime<chrono::Utc>, now: chrono::DateTime<chrono::Utc>) -> Freshness {
+        let age = (now - last_seen).num_seconds().max(0) as u64;
+        if age >= self.expire_after_seconds {
+            Freshness::Expired
+        } else if age >= self.stale_after_seconds {
+            Freshness::Stale
+        } else {
+            Freshness::Fresh
+        }
+    }
+}
+
 /// Device discovery service that integrates with WebSocket system
 pub struct DeviceDiscovery {
     mdns_discovery: Option<MdnsDiscovery>,
@@ -33,6 +83,9 @@ pub struct DeviceDiscovery {
     device_manager: Option<Arc<DeviceManager>>,
     device_store: Arc<DeviceEventStore>,
     db: Option<Arc<DatabaseManager>>,
+    aging: Arc<RwLock<DiscoveryAgingConfig>>,
+    /// Aging loop of the current run, aborted by stop_discovery
+    aging_task: Option<tokio::task::JoinHandle<()>>,
     is_running: bool,
 }
 
@@ -58,6 +111,8 @@ impl DeviceDiscovery {
             device_manager,
             device_store,
             db,
+            aging: Arc::new(RwLock::new(DiscoveryAgingConfig::default())),
+            aging_task: None,
             is_running: false,
         }
     }
@@ -86,7 +141,7 @@ impl DeviceDiscovery {
                 
                 // Use MAC address as device ID instead of hostname
                 let device_id = mdns_device.txt_records.get("mac")
-                    .map(|mac| mac.replace(':', "-"))  // Konvertiere MAC zu Key-Format mit Bindestrichen
+                    .map(|mac| DeviceManager::normalize_device_id(mac.trim()))  // Konvertiere MAC zu Key-Format mit Bindestrichen
                     .unwrap_or_else(|| format!("device-{}", mdns_device.hostname.replace(".local", "").trim_end_matches('.')));
                 let ip = mdns_device.ip_addresses.first().copied()
                     .unwrap_or(IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 1, 100)));
@@ -116,6 +171,7 @@ impl DeviceDiscovery {
                         let discovered_device = DiscoveredDevice {
                             device_config: device_config_clone.clone(),
                             discovered_at,
+                            last_seen: discovered_at,
                             udp_port: mdns_device.port,
                             mdns_data: Some(mdns_device.clone()),
                         };
@@ -157,7 +213,7 @@ impl DeviceDiscovery {
                         // Normalize MAC to dash format (AA-BB-CC-DD-EE-FF) — consistent with
                         // the rest of the system (main.rs manual add, DB primary key convention)
                         let (final_device_id, udp_device_config) = if let Some(ref mac) = mac_address {
-                            let mac_key = mac.replace(':', "-");
+                            let mac_key = DeviceManager::normalize_device_id(mac.trim());
                             // Use mDNS port as TCP port — the ESP32 is the TCP server
                             let mdns_tcp_port = mdns_device.port;
                             let config = crate::device_types::DeviceConfig::new(
@@ -214,27 +270,49 @@ impl DeviceDiscovery {
                         // Add device to manager (TCP connect happens when user opens a tab)
                         if let Some(manager) = &device_manager_spawn {
                             tracing::info!("Adding discovered Device to manager: {} (MAC as device_id)", final_device_id);
+                            manager.lifecycle().discovered(&final_device_id, "Found via mDNS").await;
 
                             if let Err(e) = manager.add_device(udp_device_config).await {
                                 tracing::warn!("Failed to add discovered device to manager: {}", e);
                             } else {
                                 tracing::info!("Successfully added Device {} to manager (not connected yet)", final_device_id);
                             }
+
+                            // A pre-shared enrollment token in the TXT record adopts the device right away
+                            let enroll_token = mdns_device.txt_records.get(crate::enrollment::TXT_KEY);
+                            if let (Some(secret), Some(db)) = (enroll_token, &db_spawn) {
+                                let ip = Some(device_config_spawn.ip_address.to_string());
+                                match crate::enrollment::enroll(db, &manager.lifecycle(), &final_device_id, secret, None, ip).await {
+                                    Ok(crate::enrollment::Enrollment::Adopted { .. }) => {
+                                        if let Err(e) = manager.connect_device(&final_device_id).await {
+                                            tracing::warn!("First connection to enrolled device {} failed: {}", final_device_id, e);
+                                        }
+                                    }
+                                    Ok(crate::enrollment::Enrollment::AlreadyEnrolled) => {}
+                                    Err(e) => tracing::warn!("Enrollment of device {} refused: {}", final_device_id, e),
+                                }
+                            }
                         }
                     });
                 });
-            }).await.map_err(|e| crate::device_types::DeviceError::ConnectionFailed(e))?;
+            }).await.map_err(crate::device_types::DeviceError::ConnectionFailed)?;
             
             info!("mDNS discovery started successfully");
         } else {
             warn!("mDNS discovery not available, using UDP fallback only");
         }
-        
-        
+
+        self.aging_task = Some(self.start_aging_task());
+
         info!("Device discovery service started");
         Ok(())
     }
     
+    /// Whether start_discovery ran (and stop_discovery did not)
+    pub fn is_running(&self) -> bool {
+        self.is_running
+    }
+
     /// Stop discovery
     pub async fn stop_discovery(&mut self) {
         if self.is_running {
@@ -242,13 +320,94 @@ impl DeviceDiscovery {
             if let Some(ref mut mdns_discovery) = self.mdns_discovery {
                 mdns_discovery.stop_discovery().await;
             }
-            
-            
+            if let Some(aging_task) = self.aging_task.take() {
+                aging_task.abort();
+            }
+
             self.is_running = false;
             info!("Device discovery service stopped");
         }
     }
     
+    /// Configure the mDNS service types to browse (before start_discovery)
+    pub fn set_mdns_services(&mut self, services: Vec<MdnsServiceConfig>) {
+        if let Some(ref mut mdns_discovery) = self.mdns_discovery {
+            mdns_discovery.set_services(services);
+        }
+    }
+
+    /// Configure aging; the mDNS re-resolution interval applies before start_discovery
+    pub async fn set_aging_config(&mut self, config: DiscoveryAgingConfig) {
+        if let Some(ref mut mdns_discovery) = self.mdns_discovery {
+            mdns_discovery.set_reresolve_interval(std::time::Duration::from_secs(config.mdns_reresolve_seconds));
+        }
+        *self.aging.write().await = config;
+    }
+
+    /// Current aging configuration
+    pub async fn aging_config(&self) -> DiscoveryAgingConfig {
+        self.aging.read().await.clone()
+    }
+
+    /// Periodically refresh last-seen from mDNS answers and live connections,
+    /// then drop entries that expired
+    fn start_aging_task(&self) -> tokio::task::JoinHandle<()> {
+        let discovered_devices = Arc::clone(&self.discovered_devices);
+        let aging = Arc::clone(&self.aging);
+        let mdns_devices = self.mdns_discovery.as_ref().map(|m| m.discovered_devices());
+        let lifecycle = self.device_manager.as_ref().map(|m| m.lifecycle());
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                ticker.tick().await;
+                let now = chrono::Utc::now();
+                let config = aging.read().await.clone();
+
+                let mdns_snapshot = match &mdns_devices {
+                    Some(mdns_devices) => mdns_devices.read().await.clone(),
+                    None => HashMap::new(),
+                };
+                let lifecycle_states = match &lifecycle {
+                    Some(lifecycle) => lifecycle.snapshot().await,
+                    None => HashMap::new(),
+                };
+
+                let mut devices = discovered_devices.write().await;
+                for (device_id, device) in devices.iter_mut() {
+                    if lifecycle_states.get(device_id).is_some_and(|entry| entry.link_up) {
+                        device.last_seen = now;
+                        continue;
+                    }
+
+                    let Some(hostname) = device.mdns_data.as_ref().map(|m| m.hostname.clone()) else {
+                        continue;
+                    };
+                    if let Some(mdns_device) = mdns_snapshot.get(&hostname) {
+                        if let Some(seen) = mdns_device.services.values().map(|r| r.last_seen).max() {
+                            device.last_seen = device.last_seen.max(seen);
+                        }
+                        device.mdns_data = Some(mdns_device.clone());
+                    }
+                }
+
+                if config.remove_expired {
+                    let before = devices.len();
+                    devices.retain(|device_id, device| {
+                        let expired = config.freshness(device.last_seen, now) == Freshness::Expired;
+                        if expired {
+                            info!("Discovery entry expired: {} (last seen {})", device_id, device.last_seen);
+                        }
+                        !expired
+                    });
+                    if devices.len() < before {
+                        debug!("Removed {} expired discovery entries", before - devices.len());
+                    }
+                }
+            }
+        })
+    }
+
     /// Get all discovered devices
     pub async fn get_discovered_devices(&self) -> HashMap<String, DiscoveredDevice> {
         self.discovered_devices.read().await.clone()
@@ -256,6 +415,135 @@ impl DeviceDiscovery {
     
 }
 
+// ============================================================================
+// ACTIVE SCANS - UDP broadcast probe and TCP range scan, complement mDNS
+// ============================================================================
+
+/// Handles needed for an active scan, taken from DeviceDiscovery so the
+/// discovery lock is not held while the scan waits for replies
+pub struct DiscoveryScan {
+    discovered_devices: Arc<RwLock<HashMap<String, DiscoveredDevice>>>,
+    device_manager: Option<Arc<DeviceManager>>,
+    device_store: Arc<DeviceEventStore>,
+    db: Option<Arc<DatabaseManager>>,
+}
+
+impl DeviceDiscovery {
+    /// Prepare an on-demand scan
+    pub fn scan(&self) -> DiscoveryScan {
+        DiscoveryScan {
+            discovered_devices: Arc::clone(&self.discovered_devices),
+            device_manager: self.device_manager.clone(),
+            device_store: Arc::clone(&self.device_store),
+            db: self.db.clone(),
+        }
+    }
+}
+
+impl DiscoveryScan {
+    /// Broadcast the probe and report devices that mDNS has not already found.
+    /// Each new device is broadcast to WebSocket clients as soon as it answers.
+    pub async fn run_udp(self, probe: UdpDiscoveryProbe) -> DeviceResult<Vec<DiscoveredDevice>> {
+        let device_manager = self.require_manager()?;
+        let (found_tx, found_rx) = mpsc::unbounded_channel();
+        let sweep = tokio::spawn(async move { device_manager.discover_devices(&probe, Some(found_tx)).await });
+        self.collect(sweep, found_rx).await
+    }
+
+    /// Scan a CIDR range for the device TCP port; new devices are announced like UDP results
+    pub async fn run_tcp(self, config: TcpScanConfig) -> DeviceResult<Vec<DiscoveredDevice>> {
+        let device_manager = self.require_manager()?;
+        let (found_tx, found_rx) = mpsc::unbounded_channel();
+        let sweep = tokio::spawn(async move { device_manager.scan_tcp_range(&config, Some(found_tx)).await });
+        self.collect(sweep, found_rx).await
+    }
+
+    fn require_manager(&self) -> DeviceResult<Arc<DeviceManager>> {
+        self.device_manager.clone().ok_or_else(|| {
+            crate::device_types::DeviceError::ConnectionFailed("Scan requires a device manager".to_string())
+        })
+    }
+
+    /// Register devices as they are reported, then wait for the sweep result
+    async fn collect(
+        &self,
+        sweep: tokio::task::JoinHandle<DeviceResult<Vec<DeviceConfig>>>,
+        mut found_rx: mpsc::UnboundedReceiver<DeviceConfig>,
+    ) -> DeviceResult<Vec<DiscoveredDevice>> {
+        let mut new_devices = Vec::new();
+        while let Some(config) = found_rx.recv().await {
+            if let Some(device) = self.register(config).await {
+                new_devices.push(device);
+            }
+        }
+
+        match sweep.await {
+            Ok(Ok(_)) => Ok(new_devices),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(crate::device_types::DeviceError::ConnectionFailed(format!("Scan task failed: {}", e))),
+        }
+    }
+
+    /// Store, persist and announce a scanned device unless it is already known (e.g. via mDNS)
+    async fn register(&self, config: DeviceConfig) -> Option<DiscoveredDevice> {
+        {
+            let mut devices = self.discovered_devices.write().await;
+            let known = devices.iter_mut().find(|(id, d)| {
+                **id == config.device_id || d.device_config.ip_address == config.ip_address
+            });
+            if let Some((_, device)) = known {
+                debug!("Scan: {} already discovered, refreshing last-seen", config.device_id);
+                device.last_seen = chrono::Utc::now();
+                return None;
+            }
+        }
+
+        let now = chrono::Utc::now();
+        let discovered = DiscoveredDevice {
+            device_config: config.clone(),
+            discovered_at: now,
+            last_seen: now,
+            udp_port: config.udp_port,
+            mdns_data: None,
+        };
+        self.discovered_devices.write().await.insert(config.device_id.clone(), discovered.clone());
+
+        let discovery_event = DeviceEvent::device_discovered(
+            config.device_id.clone(),
+            config.ip_address.to_string(),
+            config.tcp_port,
+            config.udp_port,
+            discovered.discovered_at.to_rfc3339(),
+            None,
+            None,
+        );
+        if let Err(e) = self.device_store.broadcast_event("system", discovery_event, "system").await {
+            warn!("Failed to broadcast scan result: {}", e);
+        }
+
+        if let Some(db) = &self.db {
+            if let Err(e) = db.upsert_discovered_device(
+                config.device_id.clone(),
+                config.device_name.clone(),
+                Some(config.ip_address.to_string()),
+                Some("tcp".to_string()),
+            ).await {
+                warn!("Failed to save scanned device to database: {}", e);
+            }
+        }
+
+        if let Some(manager) = &self.device_manager {
+            manager.lifecycle().discovered(&config.device_id, "Found by active scan").await;
+            if let Err(e) = manager.add_device(config.clone()).await {
+                warn!("Failed to add scanned device to manager: {}", e);
+            }
+        }
+
+        info!("Device discovered via active scan: {} at {}", config.device_id, config.ip_address);
+        Some(discovered)
+    }
+}
+
 // Note: Default implementation is not available since DeviceEventStore is required
 
 impl Drop for DeviceDiscovery {
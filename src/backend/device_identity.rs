@@ -0,0 +1,177 @@
+// Device identity - HMAC challenge-response after TCP connect, signed HTTP uploads
+
+use std::time::Duration;
+
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+
+/// How long a device has to answer the challenge; without an answer the connection is closed
+pub const IDENTITY_TIMEOUT: Duration = Duration::from_secs(5);
+
+pub const ALGORITHM: &str = "HMAC-SHA256";
+
+/// Accepted length of a shared secret
+pub const MIN_SECRET_LEN: usize = 16;
+pub const MAX_SECRET_LEN: usize = 128;
+
+/// Headers of a signed device upload
+pub const TIMESTAMP_HEADER: &str = "x-device-timestamp";
+pub const SIGNATURE_HEADER: &str = "x-device-signature";
+
+/// Accepted difference between the upload timestamp and the server clock
+pub const MAX_UPLOAD_SKEW: Duration = Duration::from_secs(300);
+
+/// Body of PUT /api/devices/:id/identity
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UpdateDeviceIdentityRequest {
+    /// Secret already flashed into the device; a new one is generated if absent
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+/// Random secret for a device (64 hex characters)
+pub fn generate_secret() -> String {
+    format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+pub fn validate_secret(secret: &str) -> Result<(), String> {
+    if !(MIN_SECRET_LEN..=MAX_SECRET_LEN).contains(&secret.len()) {
+        return Err(format!("Secret must be between {} and {} characters", MIN_SECRET_LEN, MAX_SECRET_LEN));
+    }
+    if secret.chars().any(|c| c.is_control() || c.is_whitespace()) {
+        return Err("Secret must not contain whitespace or control characters".to_string());
+    }
+    Ok(())
+}
+
+fn upload_mac(secret: &str, device_id: &str, timestamp: i64, body: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(format!("{}.{}.", timestamp, device_id).as_bytes());
+    mac.update(body);
+    mac
+}
+
+/// X-Device-Signature value of an upload: sha256=<hex HMAC-SHA256(secret, "<timestamp>.<device_id>." + body)>
+pub fn sign_upload(secret: &str, device_id: &str, timestamp: i64, body: &[u8]) -> String {
+    format!("sha256={}", hex::encode(upload_mac(secret, device_id, timestamp, body).finalize().into_bytes()))
+}
+
+/// Check the timestamp (unix seconds, within MAX_UPLOAD_SKEW) and signature headers of an upload
+/// against the device's secret. Devices without a secret cannot upload at all.
+pub fn verify_upload(secret: &str, device_id: &str, timestamp: &str, signature: &str, body: &[u8], now_secs: i64) -> Result<(), String> {
+    let timestamp: i64 = timestamp.trim().parse().map_err(|_| "invalid upload timestamp".to_string())?;
+    if timestamp.abs_diff(now_secs) > MAX_UPLOAD_SKEW.as_secs() {
+        return Err(format!("upload timestamp differs from the server clock by more than {}s", MAX_UPLOAD_SKEW.as_secs()));
+    }
+    let signature = signature.trim().strip_prefix("sha256=")
+        .and_then(|hex_mac| hex::decode(hex_mac).ok())
+        .ok_or("upload signature must be sha256=<hex>")?;
+    upload_mac(secret, device_id, timestamp, body).verify_slice(&signature)
+        .map_err(|_| "upload signature does not match the shared secret".to_string())
+}
+
+/// A challenge sent to one device
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub nonce: String,
+}
+
+impl Challenge {
+    pub fn new() -> Self {
+        Self { nonce: uuid::Uuid::new_v4().simple().to_string() }
+    }
+
+    /// Line written to the device right after connect (and TLS):
+    /// {"identityChallenge": {"nonce": "<32 hex chars>", "alg": "HMAC-SHA256"}}
+    pub fn message(&self) -> String {
+        json!({ "identityChallenge": { "nonce": self.nonce, "alg": ALGORITHM } }).to_string()
+    }
+
+    /// Hex HMAC-SHA256(secret, nonce + device_id) a device holding `secret` answers with
+    pub fn expected(&self, secret: &str, device_id: &str) -> String {
+        hex::encode(self.mac(secret, device_id).finalize().into_bytes())
+    }
+
+    fn mac(&self, secret: &str, device_id: &str) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+        mac.update(self.nonce.as_bytes());
+        mac.update(device_id.as_bytes());
+        mac
+    }
+
+    /// Check a line from the device: None if it is no identity response (skip it),
+    /// otherwise whether {"identityResponse": {"nonce": ..., "hmac": ...}} proves the secret
+    pub fn verify_line(&self, line: &str, secret: &str, device_id: &str) -> Option<Result<(), String>> {
+        if !line.contains("identityResponse") {
+            return None;
+        }
+        let value: Value = serde_json::from_str(line.trim()).ok()?;
+        let response = value.get("identityResponse")?;
+        Some(self.verify_response(response, secret, device_id))
+    }
+
+    fn verify_response(&self, response: &Value, secret: &str, device_id: &str) -> Result<(), String> {
+        if response.get("nonce").and_then(Value::as_str) != Some(self.nonce.as_str()) {
+            return Err("identity response for another challenge".to_string());
+        }
+        let signature = response.get("hmac").and_then(Value::as_str)
+            .and_then(|hmac| hex::decode(hmac).ok())
+            .ok_or("identity response without a hex hmac")?;
+        self.mac(secret, device_id).verify_slice(&signature)
+            .map_err(|_| "identity response does not match the shared secret".to_string())
+    }
+}
+
+impl Default for Challenge {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_challenge_response() {
+        let secret = generate_secret();
+        assert!(validate_secret(&secret).is_ok());
+        assert!(validate_secret("short").is_err());
+        assert!(validate_secret("sixteen chars ok").is_err(), "whitespace");
+
+        let challenge = Challenge::new();
+        let sent: Value = serde_json::from_str(&challenge.message()).unwrap();
+        assert_eq!(sent["identityChallenge"]["nonce"], challenge.nonce.as_str());
+
+        let answer = |nonce: &str, hmac: &str| json!({ "identityResponse": { "nonce": nonce, "hmac": hmac } }).to_string();
+        let good = challenge.expected(&secret, "AA-01");
+        assert_eq!(challenge.verify_line(&answer(&challenge.nonce, &good), &secret, "AA-01"), Some(Ok(())));
+
+        // Same answer for another device ID, another secret or a stale nonce is rejected
+        assert!(challenge.verify_line(&answer(&challenge.nonce, &good), &secret, "AA-02").unwrap().is_err());
+        assert!(challenge.verify_line(&answer(&challenge.nonce, &good), "another secret!!", "AA-01").unwrap().is_err());
+        assert!(challenge.verify_line(&answer("0000", &good), &secret, "AA-01").unwrap().is_err());
+        assert!(challenge.verify_line(&answer(&challenge.nonce, "zz"), &secret, "AA-01").unwrap().is_err());
+
+        // Other traffic before the answer is skipped
+        assert!(challenge.verify_line(r#"{"deviceName": "Lamp"}"#, &secret, "AA-01").is_none());
+    }
+
+    #[test]
+    fn test_signed_upload() {
+        let secret = generate_secret();
+        let body = br#"{"resetReason":"panic"}"#;
+        let now = 1_700_000_000;
+        let signature = sign_upload(&secret, "AA-01", now, body);
+        assert_eq!(verify_upload(&secret, "AA-01", &now.to_string(), &signature, body, now + 10), Ok(()));
+
+        // Another device, another body, a stale timestamp or a malformed header is rejected
+        assert!(verify_upload(&secret, "AA-02", &now.to_string(), &signature, body, now).is_err());
+        assert!(verify_upload(&secret, "AA-01", &now.to_string(), &signature, b"{}", now).is_err());
+        assert!(verify_upload(&secret, "AA-01", &now.to_string(), &signature, body, now + 301).is_err());
+        assert!(verify_upload(&secret, "AA-01", "yesterday", &signature, body, now).is_err());
+        assert!(verify_upload(&secret, "AA-01", &now.to_string(), signature.trim_start_matches("sha256="), body, now).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+// Device inventory - export and import of the device list as JSON or CSV
+//
+// An inventory row carries what is needed to recreate a device on another server instance or
+// to provision a classroom set in one go: MAC address, name, alias, connection type, owner
+// e-mail and tags. Imports are planned row by row first (invalid MACs, duplicates within the
+// file, unknown owners, devices that already exist), so a dry run reports exactly what a real
+// import would do; a real import only runs when no row has an error.
+
+use serde::{Deserialize, Serialize};
+
+use crate::event_export::csv_field;
+
+/// Most rows one import may contain
+pub const MAX_IMPORT_ROWS: usize = 1000;
+
+/// Column order of the CSV inventory
+pub const CSV_HEADER: &str = "mac_address,name,alias,connection_type,owner_email,tags";
+
+/// One device of an inventory file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InventoryEntry {
+    pub mac_address: String,
+    pub name: String,
+    #[serde(default)]
+    pub alias: Option<String>,
+    #[serde(default = "default_connection_type")]
+    pub connection_type: String,
+    /// Owner on the target server; empty = the importing user
+    #[serde(default)]
+    pub owner_email: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+fn default_connection_type() -> String {
+    "tcp".to_string()
+}
+
+/// What to do with rows whose device already exists
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ConflictPolicy {
+    /// Keep the existing device, report the row as conflict
+    #[default]
+    Skip,
+    /// Overwrite name, alias, connection type and tags (owner stays)
+    Update,
+    /// Treat the conflict as an error
+    Fail,
+}
+
+/// Planned outcome of one import row
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportRow {
+    /// 1-based row number (CSV: without the header line)
+    pub row: usize,
+    pub mac_address: String,
+    /// create, update, skip or error
+    pub action: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conflict: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Device ID form of a MAC address ("aa:bb:cc:dd:ee:ff" -> "AA-BB-CC-DD-EE-FF")
+pub fn normalize_mac(mac: &str) -> Option<String> {
+    let parts: Vec<&str> = mac.trim().split([':', '-']).collect();
+    let valid = parts.len() == 6 && parts.iter().all(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_hexdigit()));
+    valid.then(|| parts.join("-").to_ascii_uppercase())
+}
+
+/// Check the fields of one row (MAC, name, connection type)
+pub fn validate_entry(entry: &InventoryEntry) -> Result<String, String> {
+    let mac = normalize_mac(&entry.mac_address).ok_or_else(|| format!("Invalid MAC address '{}'", entry.mac_address))?;
+    if entry.name.trim().is_empty() || entry.name.len() > 100 {
+        return Err("Device name must be between 1 and 100 characters".to_string());
+    }
+    if !matches!(entry.connection_type.as_str(), "tcp" | "uart") {
+        return Err(format!("Unknown connection type '{}' (tcp or uart)", entry.connection_type));
+    }
+    Ok(mac)
+}
+
+/// Inventory as CSV; tags are separated by ';'
+pub fn to_csv(entries: &[InventoryEntry]) -> String {
+    let mut csv = format!("{}\n", CSV_HEADER);
+    for entry in entries {
+        let fields = [
+            entry.mac_address.clone(),
+            entry.name.clone(),
+            entry.alias.clone().unwrap_or_default(),
+            entry.connection_type.clone(),
+            entry.owner_email.clone().unwrap_or_default(),
+            entry.tags.join(";"),
+        ];
+        csv.push_str(&fields.iter().map(|field| csv_field(field)).collect::<Vec<_>>().join(","));
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Split CSV text into records of fields (RFC 4180 quoting)
+fn csv_records(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut records = Vec::new();
+    let (mut record, mut field) = (Vec::new(), String::new());
+    let (mut quoted, mut chars) = (false, text.chars().peekable());
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => record.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("Unterminated quoted field".to_string());
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    records.retain(|record| record.iter().any(|field| !field.trim().is_empty()));
+    Ok(records)
+}
+
+/// Parse a CSV inventory; the header names the columns, mac_address and name are required
+pub fn parse_csv(text: &str) -> Result<Vec<InventoryEntry>, String> {
+    let mut records = csv_records(text.trim_start_matches('\u{feff}'))?.into_iter();
+    let header: Vec<String> = records.next().ok_or("CSV is empty")?.iter().map(|h| h.trim().to_ascii_lowercase()).collect();
+    let column = |name: &str| header.iter().position(|h| h == name);
+    let (Some(mac_column), Some(name_column)) = (column("mac_address"), column("name")) else {
+        return Err(format!("CSV header must contain mac_address and name (expected: {})", CSV_HEADER));
+    };
+    let (alias, connection_type, owner_email, tags) = (column("alias"), column("connection_type"), column("owner_email"), column("tags"));
+
+    Ok(records.map(|record| {
+        let get = |index: Option<usize>| index.and_then(|i| record.get(i)).map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        InventoryEntry {
+            mac_address: get(Some(mac_column)).unwrap_or_default(),
+            name: get(Some(name_column)).unwrap_or_default(),
+            alias: get(alias),
+            connection_type: get(connection_type).unwrap_or_else(default_connection_type),
+            owner_email: get(owner_email),
+            tags: get(tags).map(|tags| tags.split(';').map(|t| t.trim().to_string()).filter(|t| !t.is_empty()).collect()).unwrap_or_default(),
+        }
+    }).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_round_trip_and_validation() {
+        let entries = vec![
+            InventoryEntry {
+                mac_address: "AA-BB-CC-DD-EE-01".to_string(),
+                name: "Board, row 1".to_string(),
+                alias: Some("Desk \"A\"".to_string()),
+                connection_type: "tcp".to_string(),
+                owner_email: Some("teacher@example.com".to_string()),
+                tags: vec!["room 204".to_string(), "set-a".to_string()],
+            },
+            InventoryEntry {
+                mac_address: "AA-BB-CC-DD-EE-02".to_string(),
+                name: "Board 2".to_string(),
+                alias: None,
+                connection_type: "uart".to_string(),
+                owner_email: None,
+                tags: Vec::new(),
+            },
+        ];
+        assert_eq!(parse_csv(&to_csv(&entries)).unwrap(), entries);
+
+        // Column order is taken from the header, missing columns get defaults
+        let parsed = parse_csv("name,mac_address\r\nLab,aa:bb:cc:dd:ee:03\r\n\r\n").unwrap();
+        assert_eq!(parsed[0].connection_type, "tcp");
+        assert_eq!(validate_entry(&parsed[0]).unwrap(), "AA-BB-CC-DD-EE-03");
+
+        assert!(parse_csv("mac,name\n").is_err());
+        assert!(parse_csv("mac_address,name\n\"AA,x\n").is_err());
+        assert!(normalize_mac("AA-BB-CC-DD-EE").is_none());
+        let mut bad = entries[1].clone();
+        bad.connection_type = "zigbee".to_string();
+        assert!(validate_entry(&bad).is_err());
+    }
+}
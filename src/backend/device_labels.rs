@@ -0,0 +1,107 @@
+// Device labels - printable QR codes pointing at a device's dashboard
+//
+// GET /api/devices/:id/qr.png encodes the absolute URL of the device page (`[email] public_url`
+// plus the reverse proxy prefix, see Mailer::link) so a label stuck on the board can be scanned
+// to open its dashboard. The PNG is rendered here: 1 bit grayscale, black modules on white with
+// the standard 4 module quiet zone, compressed with zlib (miniz_oxide).
+
+use qrcode::{Color, QrCode};
+
+/// Pixels per QR module: default and allowed range of `?scale=`
+pub const DEFAULT_SCALE: u32 = 8;
+pub const MIN_SCALE: u32 = 2;
+pub const MAX_SCALE: u32 = 32;
+
+/// Light modules around the code, as required for reliable scanning
+const QUIET_ZONE: usize = 4;
+
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// App path of the device page the label points to
+pub fn device_path(device_id: &str) -> String {
+    format!("/devices/{}", device_id)
+}
+
+/// QR code of `payload` as PNG with `scale` pixels per module
+pub fn qr_png(payload: &str, scale: u32) -> Result<Vec<u8>, String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| format!("Payload does not fit into a QR code: {}", e))?;
+    let modules = code.width();
+    let colors = code.to_colors();
+    let dark = |x: usize, y: usize| -> bool {
+        let (Some(x), Some(y)) = (x.checked_sub(QUIET_ZONE), y.checked_sub(QUIET_ZONE)) else {
+            return false;
+        };
+        x < modules && y < modules && colors[y * modules + x] == Color::Dark
+    };
+
+    let scale = scale.clamp(MIN_SCALE, MAX_SCALE) as usize;
+    let size = (modules + 2 * QUIET_ZONE) * scale;
+    let row_bytes = size.div_ceil(8);
+
+    // Scanlines: filter byte 0, then 1 bit per pixel with 1 = white
+    let mut raw = Vec::with_capacity((row_bytes + 1) * size);
+    for py in 0..size {
+        raw.push(0);
+        let mut row = vec![0xffu8; row_bytes];
+        for px in 0..size {
+            if dark(px / scale, py / scale) {
+                row[px / 8] &= !(0x80 >> (px % 8));
+            }
+        }
+        raw.extend_from_slice(&row);
+    }
+
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&(size as u32).to_be_bytes());
+    header.extend_from_slice(&(size as u32).to_be_bytes());
+    // Bit depth 1, grayscale, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[1, 0, 0, 0, 0]);
+
+    let mut png = PNG_SIGNATURE.to_vec();
+    write_chunk(&mut png, b"IHDR", &header);
+    write_chunk(&mut png, b"IDAT", &miniz_oxide::deflate::compress_to_vec_zlib(&raw, 9));
+    write_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    png.extend_from_slice(&crc32(kind.iter().chain(data)).to_be_bytes());
+}
+
+/// CRC-32 (IEEE) over chunk type and data
+fn crc32<'a>(bytes: impl Iterator<Item = &'a u8>) -> u32 {
+    let mut crc = 0xffff_ffffu32;
+    for &byte in bytes {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qr_png_layout() {
+        assert_eq!(crc32(b"IEND".iter()), 0xae42_6082);
+
+        let png = qr_png("https://lab.example.org/devices/AA-BB-CC-DD-EE-01", 4).unwrap();
+        assert_eq!(png[..8], PNG_SIGNATURE);
+        assert_eq!(&png[12..16], b"IHDR");
+        let width = u32::from_be_bytes(png[16..20].try_into().unwrap());
+        // Version 4 code (33 modules) with quiet zone at 4 px per module
+        assert_eq!(width, (33 + 2 * QUIET_ZONE as u32) * 4);
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+
+        // Scale is clamped
+        let large = qr_png("x", 1000).unwrap();
+        let width = u32::from_be_bytes(large[16..20].try_into().unwrap());
+        assert_eq!(width, (21 + 2 * QUIET_ZONE as u32) * MAX_SCALE);
+    }
+}
@@ -0,0 +1,466 @@
+// Device lifecycle - the single authoritative state of every device
+//
+//     Discovered -> Adopted -> Connecting -> Online <-> Degraded
+//                                  ^           |           |
+//                                  +-------- Offline <-----+
+//
+// Maintenance can be entered from every state. While a device is in maintenance, link
+// changes are still recorded but do not change its state; leaving maintenance returns it to
+// Online or Offline depending on the link. Quarantined (see quarantine.rs) works the same way
+// but is entered automatically and only left by an explicit release; maintenance does not
+// end a quarantine.
+//
+// TCP connections, the central UDP listener, the UART listener and the inactivity monitor
+// report link changes here instead of keeping their own connected flags. Every transition is
+// broadcast as DeviceLifecycle event and written to devices.status by `start_persistence`.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::{broadcast, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::database::DatabaseManager;
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum LifecycleState {
+    /// Seen on the network (mDNS, UDP, UART) but not adopted yet
+    Discovered,
+    /// Registered by a user, never connected since
+    Adopted,
+    /// Connection attempt in progress
+    Connecting,
+    Online,
+    /// Connected, but no traffic for more than half of the inactivity timeout
+    Degraded,
+    Offline,
+    /// Taken out of service by an operator
+    Maintenance,
+    /// Exceeded the event rate or error limits; its messages are held back until released
+    Quarantined,
+}
+
+impl LifecycleState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LifecycleState::Discovered => "Discovered",
+            LifecycleState::Adopted => "Adopted",
+            LifecycleState::Connecting => "Connecting",
+            LifecycleState::Online => "Online",
+            LifecycleState::Degraded => "Degraded",
+            LifecycleState::Offline => "Offline",
+            LifecycleState::Maintenance => "Maintenance",
+            LifecycleState::Quarantined => "Quarantined",
+        }
+    }
+
+    /// Parse a stored status; statuses of older versions ("Error", "Updating") become Offline
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "Discovered" => LifecycleState::Discovered,
+            "Adopted" => LifecycleState::Adopted,
+            "Connecting" => LifecycleState::Connecting,
+            "Online" => LifecycleState::Online,
+            "Degraded" => LifecycleState::Degraded,
+            "Maintenance" => LifecycleState::Maintenance,
+            "Quarantined" => LifecycleState::Quarantined,
+            _ => LifecycleState::Offline,
+        }
+    }
+
+    /// Whether the device has a working link in this state
+    pub fn is_connected(&self) -> bool {
+        matches!(self, LifecycleState::Online | LifecycleState::Degraded)
+    }
+
+    /// State a stored device starts with after a server restart (no link exists yet)
+    pub fn after_restart(self) -> Self {
+        match self {
+            LifecycleState::Discovered | LifecycleState::Adopted | LifecycleState::Maintenance | LifecycleState::Quarantined => self,
+            _ => LifecycleState::Offline,
+        }
+    }
+
+    /// Transitions the state machine allows (maintenance and quarantine are handled separately)
+    fn can_become(self, next: LifecycleState) -> bool {
+        use LifecycleState::*;
+        matches!(
+            (self, next),
+            (Discovered, Adopted | Connecting | Online)
+                | (Adopted, Connecting | Online | Offline)
+                | (Connecting, Online | Offline)
+                | (Online, Connecting | Degraded | Offline)
+                | (Degraded, Connecting | Online | Offline)
+                | (Offline, Connecting | Online)
+        )
+    }
+}
+
+impl std::fmt::Display for LifecycleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LifecycleEntry {
+    pub state: LifecycleState,
+    /// Link status as last reported by a transport; differs from `state` only in maintenance
+    pub link_up: bool,
+    pub since: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// A state change to announce once the registry lock is released
+struct Transition {
+    device_id: String,
+    previous: Option<LifecycleState>,
+    state: LifecycleState,
+    reason: String,
+}
+
+pub type SharedLifecycle = Arc<DeviceLifecycle>;
+
+/// Registry of all device lifecycle states (device_id -> entry)
+#[derive(Debug)]
+pub struct DeviceLifecycle {
+    entries: RwLock<HashMap<String, LifecycleEntry>>,
+    device_store: SharedDeviceStore,
+}
+
+impl DeviceLifecycle {
+    pub fn new(device_store: SharedDeviceStore) -> Self {
+        Self {
+            entries: RwLock::new(HashMap::new()),
+            device_store,
+        }
+    }
+
+    /// Seed the state of a stored device at startup (no event, nothing persisted)
+    pub async fn restore(&self, device_id: &str, state: LifecycleState) {
+        self.entries.write().await.insert(device_id.to_string(), LifecycleEntry {
+            state,
+            link_up: false,
+            since: Utc::now(),
+            reason: "Restored after restart".to_string(),
+        });
+    }
+
+    pub async fn get(&self, device_id: &str) -> Option<LifecycleEntry> {
+        self.entries.read().await.get(device_id).cloned()
+    }
+
+    pub async fn snapshot(&self) -> HashMap<String, LifecycleEntry> {
+        self.entries.read().await.clone()
+    }
+
+    pub async fn is_known(&self, device_id: &str) -> bool {
+        self.entries.read().await.contains_key(device_id)
+    }
+
+    /// Device showed up on the network; only unknown devices become Discovered
+    pub async fn discovered(&self, device_id: &str, reason: &str) {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            if entries.contains_key(device_id) {
+                None
+            } else {
+                Self::step(&mut entries, device_id, None, LifecycleState::Discovered, reason)
+            }
+        };
+        self.announce(transition).await;
+    }
+
+    /// Device was registered by a user
+    pub async fn adopted(&self, device_id: &str) {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            Self::step(&mut entries, device_id, None, LifecycleState::Adopted, "Adopted")
+        };
+        self.announce(transition).await;
+    }
+
+    /// Connection attempt started
+    pub async fn connecting(&self, device_id: &str, reason: &str) {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            Self::step(&mut entries, device_id, None, LifecycleState::Connecting, reason)
+        };
+        self.announce(transition).await;
+    }
+
+    /// A transport received traffic or established a connection.
+    /// Returns true if the device was not connected before (a connect event is due).
+    pub async fn link_up(&self, device_id: &str, reason: &str) -> bool {
+        let (was_up, transition) = {
+            let mut entries = self.entries.write().await;
+            let was_up = entries.get(device_id).is_some_and(|entry| entry.link_up);
+            let transition = Self::step(&mut entries, device_id, Some(true), LifecycleState::Online, reason);
+            (was_up, transition)
+        };
+        self.announce(transition).await;
+        !was_up
+    }
+
+    /// A transport lost the device (connection closed, failed, timed out).
+    /// Returns true if the device was connected before (a disconnect event is due).
+    pub async fn link_down(&self, device_id: &str, reason: &str) -> bool {
+        let (was_up, transition) = {
+            let mut entries = self.entries.write().await;
+            if !entries.contains_key(device_id) {
+                return false;
+            }
+            let was_up = entries.get(device_id).is_some_and(|entry| entry.link_up);
+            let transition = Self::step(&mut entries, device_id, Some(false), LifecycleState::Offline, reason);
+            (was_up, transition)
+        };
+        self.announce(transition).await;
+        was_up
+    }
+
+    /// Connected device has been quiet for a while
+    pub async fn degraded(&self, device_id: &str, reason: &str) {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            match entries.get(device_id) {
+                Some(entry) if entry.state == LifecycleState::Online => {
+                    Self::step(&mut entries, device_id, None, LifecycleState::Degraded, reason)
+                }
+                _ => None,
+            }
+        };
+        self.announce(transition).await;
+    }
+
+    /// Enter or leave maintenance; leaving restores Online/Offline from the current link
+    pub async fn set_maintenance(&self, device_id: &str, enabled: bool) {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            let entry = entries.entry(device_id.to_string()).or_insert_with(|| LifecycleEntry {
+                state: LifecycleState::Offline,
+                link_up: false,
+                since: Utc::now(),
+                reason: String::new(),
+            });
+            let next = match (enabled, entry.state == LifecycleState::Maintenance) {
+                _ if entry.state == LifecycleState::Quarantined => None,
+                (true, false) => Some(LifecycleState::Maintenance),
+                (false, true) if entry.link_up => Some(LifecycleState::Online),
+                (false, true) => Some(LifecycleState::Offline),
+                _ => None,
+            };
+            next.map(|state| {
+                let reason = if enabled { "Maintenance started" } else { "Maintenance ended" };
+                let previous = entry.state;
+                entry.state = state;
+                entry.since = Utc::now();
+                entry.reason = reason.to_string();
+                Transition { device_id: device_id.to_string(), previous: Some(previous), state, reason: reason.to_string() }
+            })
+        };
+        self.announce(transition).await;
+    }
+
+    /// Hold the device in quarantine; false if it already was (or is unknown)
+    pub async fn quarantine(&self, device_id: &str, reason: &str) -> bool {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            match entries.get_mut(device_id) {
+                Some(entry) if entry.state != LifecycleState::Quarantined => {
+                    let previous = entry.state;
+                    entry.state = LifecycleState::Quarantined;
+                    entry.since = Utc::now();
+                    entry.reason = reason.to_string();
+                    Some(Transition { device_id: device_id.to_string(), previous: Some(previous), state: entry.state, reason: reason.to_string() })
+                }
+                _ => None,
+            }
+        };
+        let quarantined = transition.is_some();
+        self.announce(transition).await;
+        quarantined
+    }
+
+    /// End a quarantine, back to Online/Offline from the current link; false if not quarantined
+    pub async fn release_quarantine(&self, device_id: &str, reason: &str) -> bool {
+        let transition = {
+            let mut entries = self.entries.write().await;
+            match entries.get_mut(device_id) {
+                Some(entry) if entry.state == LifecycleState::Quarantined => {
+                    entry.state = if entry.link_up { LifecycleState::Online } else { LifecycleState::Offline };
+                    entry.since = Utc::now();
+                    entry.reason = reason.to_string();
+                    Some(Transition {
+                        device_id: device_id.to_string(),
+                        previous: Some(LifecycleState::Quarantined),
+                        state: entry.state,
+                        reason: reason.to_string(),
+                    })
+                }
+                _ => None,
+            }
+        };
+        let released = transition.is_some();
+        self.announce(transition).await;
+        released
+    }
+
+    /// Drop a removed device (no event; its events are purged with it)
+    pub async fn forget(&self, device_id: &str) {
+        if self.entries.write().await.remove(device_id).is_some() {
+            debug!("Removed lifecycle state of device {}", device_id);
+        }
+    }
+
+    /// Apply a link update and move to `next` if the state machine allows it
+    fn step(
+        entries: &mut HashMap<String, LifecycleEntry>,
+        device_id: &str,
+        link_up: Option<bool>,
+        next: LifecycleState,
+        reason: &str,
+    ) -> Option<Transition> {
+        let now = Utc::now();
+        let Some(entry) = entries.get_mut(device_id) else {
+            entries.insert(device_id.to_string(), LifecycleEntry {
+                state: next,
+                link_up: link_up.unwrap_or(false),
+                since: now,
+                reason: reason.to_string(),
+            });
+            return Some(Transition { device_id: device_id.to_string(), previous: None, state: next, reason: reason.to_string() });
+        };
+
+        if let Some(link_up) = link_up {
+            entry.link_up = link_up;
+        }
+        if matches!(entry.state, LifecycleState::Maintenance | LifecycleState::Quarantined) || !entry.state.can_become(next) {
+            return None;
+        }
+
+        let previous = entry.state;
+        entry.state = next;
+        entry.since = now;
+        entry.reason = reason.to_string();
+        Some(Transition { device_id: device_id.to_string(), previous: Some(previous), state: next, reason: reason.to_string() })
+    }
+
+    async fn announce(&self, transition: Option<Transition>) {
+        let Some(transition) = transition else { return };
+        info!(
+            "Device {} lifecycle: {} -> {} ({})",
+            transition.device_id,
+            transition.previous.map(|s| s.as_str()).unwrap_or("-"),
+            transition.state,
+            transition.reason
+        );
+
+        let event = DeviceEvent::device_lifecycle(
+            transition.device_id.clone(),
+            transition.state.as_str().to_string(),
+            transition.previous.map(|s| s.as_str().to_string()),
+            transition.reason,
+        );
+        if let Err(e) = self.device_store.add_event(
+            transition.device_id.clone(),
+            event,
+            "device_system".to_string(),
+            "lifecycle".to_string(),
+        ).await {
+            warn!("Failed to send lifecycle event for device {}: {}", transition.device_id, e);
+        }
+    }
+}
+
+/// Write every lifecycle transition to devices.status
+pub fn start_persistence(db: Arc<DatabaseManager>, device_store: SharedDeviceStore) {
+    let mut feed = device_store.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            match feed.recv().await {
+                Ok(feed_event) => {
+                    let DeviceEvent::DeviceLifecycle { device_id, state, .. } = feed_event.event else {
+                        continue;
+                    };
+                    let state = LifecycleState::parse(&state);
+                    if let Err(e) = db.update_device_lifecycle_state(&device_id, state).await {
+                        warn!("Failed to persist lifecycle state of device {}: {}", device_id, e);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Lifecycle persistence lagged behind the event feed, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_store::create_shared_store;
+
+    #[tokio::test]
+    async fn test_lifecycle_transitions_and_events() {
+        let store = create_shared_store();
+        let mut feed = store.subscribe_events();
+        let lifecycle = DeviceLifecycle::new(store);
+        let id = "AA-BB-CC-DD-EE-01";
+
+        lifecycle.discovered(id, "mDNS").await;
+        lifecycle.adopted(id).await;
+        lifecycle.connecting(id, "Connect requested").await;
+        assert!(lifecycle.link_up(id, "TCP connected").await);
+        assert!(!lifecycle.link_up(id, "UDP traffic").await);
+        lifecycle.degraded(id, "Quiet").await;
+        assert!(!lifecycle.link_up(id, "UDP traffic").await);
+
+        // Maintenance holds the state while the link keeps changing
+        lifecycle.set_maintenance(id, true).await;
+        assert!(lifecycle.link_down(id, "Timeout").await);
+        assert_eq!(lifecycle.get(id).await.unwrap().state, LifecycleState::Maintenance);
+        lifecycle.set_maintenance(id, false).await;
+        assert_eq!(lifecycle.get(id).await.unwrap().state, LifecycleState::Offline);
+
+        // Discovery does not reset known devices, unknown devices are never reported as lost
+        lifecycle.discovered(id, "mDNS").await;
+        assert!(!lifecycle.link_down("unknown", "Timeout").await);
+
+        // Quarantine survives link changes and maintenance, only a release ends it
+        assert!(lifecycle.quarantine(id, "Too many events").await);
+        assert!(!lifecycle.quarantine(id, "Too many events").await);
+        assert!(lifecycle.link_up(id, "UDP traffic").await);
+        lifecycle.set_maintenance(id, true).await;
+        assert_eq!(lifecycle.get(id).await.unwrap().state, LifecycleState::Quarantined);
+        assert!(lifecycle.release_quarantine(id, "Released").await);
+        assert!(!lifecycle.release_quarantine(id, "Released").await);
+
+        let mut transitions = Vec::new();
+        while let Ok(feed_event) = feed.try_recv() {
+            if let DeviceEvent::DeviceLifecycle { state, previous_state, .. } = feed_event.event {
+                transitions.push(format!("{}>{}", previous_state.unwrap_or_default(), state));
+            }
+        }
+        assert_eq!(transitions, vec![
+            ">Discovered",
+            "Discovered>Adopted",
+            "Adopted>Connecting",
+            "Connecting>Online",
+            "Online>Degraded",
+            "Degraded>Online",
+            "Online>Maintenance",
+            "Maintenance>Offline",
+            "Offline>Quarantined",
+            "Quarantined>Online",
+        ]);
+
+        assert_eq!(LifecycleState::parse("Updating"), LifecycleState::Offline);
+        assert_eq!(LifecycleState::Online.after_restart(), LifecycleState::Offline);
+    }
+}
@@ -0,0 +1,162 @@
+// Device logs - log lines the firmware sends over TCP, UDP or UART
+//
+// Devices send `{"log": {"level": "warn", "msg": "...", "tag": "wifi"}}` frames (tag optional).
+// The unified message handler turns them into DeviceEvent::DeviceLog for the WebSocket clients
+// and keeps them here, apart from the telemetry history: a bounded buffer per device that
+// GET /api/devices/:id/logs filters by minimum level. Log frames are not parsed for variables.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+use crate::device_clock::DeviceTimestamp;
+
+/// Log lines kept per device (oldest are dropped first)
+pub const MAX_LOGS_PER_DEVICE: usize = 1000;
+
+/// Longest accepted message; longer ones are cut
+pub const MAX_LOG_MESSAGE_CHARS: usize = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogLevel {
+    Trace,
+    Debug,
+    Info,
+    Warn,
+    Error,
+}
+
+impl LogLevel {
+    /// Level names as firmware frameworks spell them (ESP-IDF letters included)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "trace" | "verbose" | "v" => Some(LogLevel::Trace),
+            "debug" | "d" => Some(LogLevel::Debug),
+            "info" | "i" => Some(LogLevel::Info),
+            "warn" | "warning" | "w" => Some(LogLevel::Warn),
+            "error" | "err" | "fatal" | "e" => Some(LogLevel::Error),
+            _ => None,
+        }
+    }
+}
+
+/// Parsed `log` object of a device frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct LogFrame {
+    pub level: LogLevel,
+    pub msg: String,
+    pub tag: Option<String>,
+}
+
+impl LogFrame {
+    /// Log frame of a device message; None for other messages. Unknown levels count as info.
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.contains("\"log\"") {
+            return None;
+        }
+        let value: serde_json::Value = serde_json::from_str(message).ok()?;
+        let log = value.get("log")?.as_object()?;
+        let msg = log.get("msg").or_else(|| log.get("message"))?.as_str()?;
+        let level = log.get("level").and_then(|level| level.as_str()).and_then(LogLevel::parse).unwrap_or(LogLevel::Info);
+        let tag = log.get("tag").and_then(|tag| tag.as_str()).filter(|tag| !tag.is_empty()).map(str::to_string);
+        Some(LogFrame { level, msg: msg.chars().take(MAX_LOG_MESSAGE_CHARS).collect(), tag })
+    }
+}
+
+/// Stored log line (GET /api/devices/:id/logs)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceLogEntry {
+    /// Server receive time (ms since epoch)
+    pub timestamp: i64,
+    pub level: LogLevel,
+    pub msg: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// TCP, UDP or UART
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub device_time: Option<DeviceTimestamp>,
+}
+
+/// Log buffers of all devices
+#[derive(Debug, Default)]
+pub struct DeviceLogStore {
+    logs: Mutex<HashMap<String, VecDeque<DeviceLogEntry>>>,
+}
+
+impl DeviceLogStore {
+    pub fn push(&self, device_id: &str, entry: DeviceLogEntry) {
+        let mut logs = self.logs.lock().unwrap();
+        let buffer = logs.entry(device_id.to_string()).or_default();
+        if buffer.len() >= MAX_LOGS_PER_DEVICE {
+            buffer.pop_front();
+        }
+        buffer.push_back(entry);
+    }
+
+    /// Newest `limit` lines at or above `min_level` (after `since`, ms), oldest first
+    pub fn query(&self, device_id: &str, min_level: LogLevel, since: Option<i64>, limit: usize) -> Vec<DeviceLogEntry> {
+        let logs = self.logs.lock().unwrap();
+        let Some(buffer) = logs.get(device_id) else {
+            return Vec::new();
+        };
+        let mut entries: Vec<DeviceLogEntry> = buffer.iter().rev()
+            .filter(|entry| entry.level >= min_level && since.is_none_or(|since| entry.timestamp > since))
+            .take(limit)
+            .cloned()
+            .collect();
+        entries.reverse();
+        entries
+    }
+
+    /// Number of stored lines per level
+    pub fn counts(&self, device_id: &str) -> HashMap<LogLevel, usize> {
+        let mut counts = HashMap::new();
+        if let Some(buffer) = self.logs.lock().unwrap().get(device_id) {
+            for entry in buffer {
+                *counts.entry(entry.level).or_insert(0) += 1;
+            }
+        }
+        counts
+    }
+
+    pub fn clear(&self, device_id: &str) -> usize {
+        self.logs.lock().unwrap().remove(device_id).map_or(0, |buffer| buffer.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(timestamp: i64, level: LogLevel) -> DeviceLogEntry {
+        DeviceLogEntry { timestamp, level, msg: format!("line {}", timestamp), tag: None, source: "TCP".to_string(), device_time: None }
+    }
+
+    #[test]
+    fn test_log_frames_and_level_filter() {
+        let frame = LogFrame::parse(r#"{"log": {"level": "W", "msg": "rssi low", "tag": "wifi"}}"#).unwrap();
+        assert_eq!((frame.level, frame.msg.as_str(), frame.tag.as_deref()), (LogLevel::Warn, "rssi low", Some("wifi")));
+        assert_eq!(LogFrame::parse(r#"{"log": {"level": "loud", "msg": "x"}}"#).unwrap().level, LogLevel::Info);
+        assert!(LogFrame::parse(r#"{"log": "plain"}"#).is_none());
+        assert!(LogFrame::parse(r#"{"temperature": 21}"#).is_none());
+
+        let store = DeviceLogStore::default();
+        for (i, level) in [LogLevel::Debug, LogLevel::Warn, LogLevel::Info, LogLevel::Error].into_iter().enumerate() {
+            store.push("dev-1", entry(i as i64, level));
+        }
+        let warnings: Vec<i64> = store.query("dev-1", LogLevel::Warn, None, 100).iter().map(|e| e.timestamp).collect();
+        assert_eq!(warnings, [1, 3]);
+        assert_eq!(store.query("dev-1", LogLevel::Trace, Some(1), 1)[0].timestamp, 3);
+        assert_eq!(store.counts("dev-1")[&LogLevel::Debug], 1);
+
+        for i in 0..MAX_LOGS_PER_DEVICE as i64 {
+            store.push("dev-1", entry(10 + i, LogLevel::Info));
+        }
+        assert_eq!(store.query("dev-1", LogLevel::Trace, None, usize::MAX).len(), MAX_LOGS_PER_DEVICE);
+        assert_eq!(store.clear("dev-1"), MAX_LOGS_PER_DEVICE);
+    }
+}
@@ -1,21 +1,38 @@
 // Device manager - handles multiple device connections and integrates with device store
 
 use crate::device_connection::{DeviceConnection};
+use crate::device_transport::{shared_transport, SharedTransport, TransportContext};
+use crate::uart_connection::{UartDeviceTransport, UartPort};
+use crate::uart_gateway;
+use crate::payload_codec::{DecodedPayload, PayloadEncoding};
+use crate::command_queue::{ClientDispatch, CommandQueue, CommandDispatch, CommandQueueSnapshot, PendingRequest, PendingRequests};
 use crate::device_types::{
-    DeviceCommand, DeviceEvent, DeviceConfig, ConnectionState, DeviceResult, DeviceError
+    DeviceCommand, DeviceEvent, DeviceConfig, DeviceTlsConfig, ConnectionSettings, ConnectionState, DeviceResult, DeviceError,
+    UdpDiscoveryProbe, TcpScanConfig, UDP_DISCOVERY_MESSAGE,
 };
 use crate::device_store::{SharedDeviceStore, DeviceEventStore};
 use crate::events::DeviceEvent as WebSocketDeviceEvent;
-use crate::debug_logger::DebugLogger;
+use crate::device_trace::{Direction as TraceDirection, Transport as TraceTransport};
+use crate::device_lifecycle::{DeviceLifecycle, SharedLifecycle};
+use crate::device_supervisor::DeviceSupervisor;
+use crate::variable_coalescer::{Offer, VariableCoalescer};
+use crate::bus_decoders::BusDecoders;
+use crate::message_parsers::MessageParsers;
+use crate::command_rate_limit::{CommandRateLimiter, RateLimits};
+use crate::device_clock::ClockReport;
+use crate::device_logs::{DeviceLogEntry, LogFrame, LogLevel};
+use crate::quarantine::Admission;
+use crate::device_profiles::DeviceProfile;
+use crate::udp_worker_pool::{UdpDatagram, UdpWorkerPool, DEFAULT_UDP_WORKERS, UDP_WORKER_QUEUE_CAPACITY};
 
 use std::collections::HashMap;
-use std::net::{IpAddr, SocketAddr};
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
 use std::sync::Arc;
 use std::time::Instant;
-use tokio::sync::{mpsc, RwLock, Mutex};
+use tokio::sync::{mpsc, RwLock, Mutex, Semaphore};
 use tokio::net::UdpSocket;
-use tokio::time::{sleep, timeout, Duration, interval};
-use tracing::{info, warn, error, debug};
+use tokio::time::{sleep, Duration, interval};
+use tracing::{info, warn, error, debug, Instrument};
 
 // ============================================================================
 // DEVICE MANAGER
@@ -31,26 +48,87 @@ pub enum DeviceConnectionType {
 /// Manages multiple device connections and integrates with the device store
 #[derive(Debug)]
 pub struct DeviceManager {
-    /// Map of device_id -> device connection
-    connections: Arc<RwLock<HashMap<String, Arc<Mutex<DeviceConnection>>>>>,
+    /// Map of device_id -> transport (TCP connection, UART, ...)
+    connections: Arc<RwLock<HashMap<String, SharedTransport>>>,
     /// Device configurations
     device_configs: Arc<RwLock<HashMap<String, DeviceConfig>>>,
     /// Shared device store for event management
     device_store: SharedDeviceStore,
-    /// Central UDP listener for all devices
-    central_udp_socket: Arc<Mutex<Option<UdpSocket>>>,
+    /// Central UDP listener sockets for all devices (one per configured port)
+    central_udp_sockets: Arc<RwLock<Vec<Arc<UdpSocket>>>>,
+    /// UDP ports the central listener binds to (all routed via ip_to_device_id)
+    udp_listen_ports: Arc<RwLock<Vec<u16>>>,
     /// Map of IP -> device_id for UDP message routing
     ip_to_device_id: Arc<RwLock<HashMap<IpAddr, String>>>,
-    /// Global mutex to prevent race conditions during device connections
-    connection_mutex: Arc<Mutex<()>>,
+    /// Per-device connect locks: attempts for one device are serialized, different devices connect in parallel
+    connect_locks: Arc<Mutex<HashMap<String, Arc<Mutex<()>>>>>,
+    /// Slots for TCP connects running at the same time
+    connect_slots: Arc<RwLock<Arc<Semaphore>>>,
+    /// Parsing workers of the central UDP listener
+    udp_workers: Arc<RwLock<usize>>,
     /// Unified activity tracking for UDP and UART devices (not TCP)
     unified_activity_tracker: Arc<RwLock<HashMap<String, Instant>>>,
-    /// Unified connection state tracking to prevent redundant events (device_id -> is_connected)
-    unified_connection_states: Arc<RwLock<HashMap<String, bool>>>,
+    /// Lifecycle state of every device; the only source of "is this device connected"
+    lifecycle: SharedLifecycle,
     /// Map of device_id -> DeviceConnectionType to track UART vs TCP/UDP devices
     device_connection_types: Arc<RwLock<HashMap<String, DeviceConnectionType>>>,
+    /// Quarantine of UDP sources whose device could not be identified (IP -> source info)
+    unidentified_sources: Arc<RwLock<HashMap<IpAddr, UnidentifiedSource>>>,
+    /// Outbound commands waiting for a (re)connected device
+    command_queue: Arc<CommandQueue>,
+    /// Per-device TLS settings, applied to configs added later as well (device_id -> TLS)
+    device_tls: Arc<RwLock<HashMap<String, DeviceTlsConfig>>>,
+    /// Per-device identity secrets, checked after every TCP connect (device_id -> secret)
+    device_identity: Arc<RwLock<HashMap<String, String>>>,
+    /// Stored ports, timeouts and keep-alive, applied to configs added later as well
+    device_connection_settings: Arc<RwLock<HashMap<String, ConnectionSettings>>>,
+    /// Shared serial port; UART devices get a transport on it when first addressed
+    uart_port: Arc<RwLock<Option<UartPort>>>,
+    /// Reconnect policies and retry state (run by device_supervisor::start)
+    supervisor: Arc<DeviceSupervisor>,
+    /// Per-device, per-variable rate limit of variable updates
+    variable_coalescer: Arc<VariableCoalescer>,
+    /// Per-device decoder plugins for raw bus frames
+    bus_decoders: Arc<BusDecoders>,
+    /// Parser chains turning received messages into events (default and per device)
+    message_parsers: Arc<MessageParsers>,
+    /// Token buckets for client commands (per device and per user)
+    command_rate_limiter: Arc<CommandRateLimiter>,
+    /// Assigned device type profiles (device_id -> profile); client commands are checked against them
+    device_profiles: Arc<RwLock<HashMap<String, Arc<DeviceProfile>>>>,
 }
 
+/// UDP sender that sent device traffic but could not be mapped to a device ID.
+/// Such traffic is quarantined instead of being routed to a guessed device.
+#[derive(Debug, Clone, serde::Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UnidentifiedSource {
+    pub ip: String,
+    pub port: u16,
+    pub first_seen: chrono::DateTime<chrono::Utc>,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+    pub message_count: u64,
+    pub last_message: String,
+}
+
+/// Shared state needed by a central UDP listener task
+#[derive(Clone)]
+struct UdpListenerContext {
+    ip_to_device_id: Arc<RwLock<HashMap<IpAddr, String>>>,
+    device_configs: Arc<RwLock<HashMap<String, DeviceConfig>>>,
+    transport: TransportContext,
+    unidentified_sources: Arc<RwLock<HashMap<IpAddr, UnidentifiedSource>>>,
+}
+
+/// Default port of the central UDP listener (ESP32 firmware default)
+pub const DEFAULT_UDP_LISTEN_PORT: u16 = 3232;
+
+/// Default number of TCP connects running at the same time
+pub const DEFAULT_MAX_CONCURRENT_CONNECTS: usize = 8;
+
+/// Largest discovery reply accepted from a device (announcements are a few hundred bytes)
+const MAX_DISCOVERY_REPLY_BYTES: usize = 1024;
+
 /// Metadata about the message source
 #[derive(Debug, Clone)]
 pub enum MessageSource {
@@ -65,16 +143,200 @@ impl DeviceManager {
         Self {
             connections: Arc::new(RwLock::new(HashMap::new())),
             device_configs: Arc::new(RwLock::new(HashMap::new())),
+            lifecycle: Arc::new(DeviceLifecycle::new(device_store.clone())),
             device_store,
-            central_udp_socket: Arc::new(Mutex::new(None)),
+            central_udp_sockets: Arc::new(RwLock::new(Vec::new())),
+            udp_listen_ports: Arc::new(RwLock::new(vec![DEFAULT_UDP_LISTEN_PORT])),
             ip_to_device_id: Arc::new(RwLock::new(HashMap::new())),
-            connection_mutex: Arc::new(Mutex::new(())),
+            connect_locks: Arc::new(Mutex::new(HashMap::new())),
+            connect_slots: Arc::new(RwLock::new(Arc::new(Semaphore::new(DEFAULT_MAX_CONCURRENT_CONNECTS)))),
+            udp_workers: Arc::new(RwLock::new(DEFAULT_UDP_WORKERS)),
             unified_activity_tracker: Arc::new(RwLock::new(HashMap::new())),
-            unified_connection_states: Arc::new(RwLock::new(HashMap::new())),
             device_connection_types: Arc::new(RwLock::new(HashMap::new())),
+            unidentified_sources: Arc::new(RwLock::new(HashMap::new())),
+            command_queue: Arc::new(CommandQueue::default()),
+            device_tls: Arc::new(RwLock::new(HashMap::new())),
+            device_identity: Arc::new(RwLock::new(HashMap::new())),
+            device_connection_settings: Arc::new(RwLock::new(HashMap::new())),
+            uart_port: Arc::new(RwLock::new(None)),
+            supervisor: Arc::new(DeviceSupervisor::default()),
+            variable_coalescer: Arc::new(VariableCoalescer::default()),
+            bus_decoders: Arc::new(BusDecoders::default()),
+            message_parsers: Arc::new(MessageParsers::default()),
+            command_rate_limiter: Arc::new(CommandRateLimiter::default()),
+            device_profiles: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Reconnect policies and retry state of all devices
+    pub fn supervisor(&self) -> Arc<DeviceSupervisor> {
+        Arc::clone(&self.supervisor)
+    }
+
+    /// Shared state handed to every transport for delivering received frames
+    pub fn transport_context(&self) -> TransportContext {
+        TransportContext {
+            device_store: self.device_store.clone(),
+            lifecycle: Arc::clone(&self.lifecycle),
+            activity_tracker: Arc::clone(&self.unified_activity_tracker),
+            connection_types: Arc::clone(&self.device_connection_types),
+            variable_coalescer: Arc::clone(&self.variable_coalescer),
+            bus_decoders: Arc::clone(&self.bus_decoders),
+            message_parsers: Arc::clone(&self.message_parsers),
         }
     }
+
+    /// Limit the number of TCP connects running at the same time (at least 1).
+    /// Connects already waiting for a slot keep the previous limit.
+    pub async fn set_max_concurrent_connects(&self, limit: usize) {
+        *self.connect_slots.write().await = Arc::new(Semaphore::new(limit.max(1)));
+    }
+
+    /// Number of workers parsing received UDP datagrams (at least 1). Must be called before `start()`.
+    pub async fn set_udp_workers(&self, workers: usize) {
+        *self.udp_workers.write().await = workers.max(1);
+    }
+
+    /// Minimum interval between delivered updates of one device variable (zero = every update).
+    /// With `record_raw` the held-back samples still reach the server-side event feed.
+    pub fn set_variable_coalescing(&self, min_interval: Duration, record_raw: bool) {
+        self.variable_coalescer.configure(min_interval, record_raw);
+    }
+
+    /// Decoder plugins from the [bus_decoders] entries; returns how many devices have one
+    pub fn set_bus_decoders(&self, entries: &[String]) -> Result<usize, String> {
+        self.bus_decoders.configure(entries)
+    }
+
+    /// Per-device parser chains from the [message_parsers] entries; returns how many devices have one
+    pub fn set_message_parsers(&self, entries: &[String]) -> Result<usize, String> {
+        self.message_parsers.configure(entries)
+    }
+
+    /// Token bucket limits for client commands (per device and per user)
+    pub fn set_command_rate_limits(&self, limits: RateLimits) {
+        self.command_rate_limiter.configure(limits);
+    }
+
+    pub fn command_rate_limiter(&self) -> Arc<CommandRateLimiter> {
+        Arc::clone(&self.command_rate_limiter)
+    }
+
+    /// Take a command token for `user_id` on `device_id` (RateLimited when a bucket is empty)
+    pub fn check_command_rate(&self, device_id: &str, user_id: &str) -> DeviceResult<()> {
+        self.command_rate_limiter.check(device_id, user_id).map_err(|limited| {
+            debug!("Command of user {} to device {} rate limited: {}", user_id, device_id, limited);
+            DeviceError::RateLimited(limited)
+        })
+    }
+
+    /// Assign (Some) or remove (None) the device type profile client commands are checked against
+    pub async fn set_device_profile(&self, device_id: &str, profile: Option<Arc<DeviceProfile>>) {
+        let mut profiles = self.device_profiles.write().await;
+        match profile {
+            Some(profile) => profiles.insert(device_id.to_string(), profile),
+            None => profiles.remove(device_id),
+        };
+    }
+
+    pub async fn get_device_profile(&self, device_id: &str) -> Option<Arc<DeviceProfile>> {
+        self.device_profiles.read().await.get(device_id).cloned()
+    }
+
+    /// Register the shared serial port UART device transports are created on
+    pub async fn set_uart_port(&self, port: UartPort) {
+        *self.uart_port.write().await = Some(port);
+    }
     
+    /// Configure the UDP ports the central listener binds to.
+    /// Must be called before `start()`; duplicates and port 0 are ignored.
+    pub async fn set_udp_listen_ports(&self, ports: Vec<u16>) {
+        let mut unique_ports: Vec<u16> = Vec::new();
+        for port in ports {
+            if port != 0 && !unique_ports.contains(&port) {
+                unique_ports.push(port);
+            }
+        }
+
+        if unique_ports.is_empty() {
+            warn!("No valid UDP listen ports configured, falling back to {}", DEFAULT_UDP_LISTEN_PORT);
+            unique_ports.push(DEFAULT_UDP_LISTEN_PORT);
+        }
+
+        *self.udp_listen_ports.write().await = unique_ports;
+    }
+
+    /// Set or clear TLS for the TCP channel of a device.
+    /// Takes effect on the next (re)connect of the device.
+    pub async fn set_device_tls(&self, device_id: &str, tls: Option<DeviceTlsConfig>) {
+        {
+            let mut device_tls = self.device_tls.write().await;
+            match &tls {
+                Some(tls) => device_tls.insert(device_id.to_string(), tls.clone()),
+                None => device_tls.remove(device_id),
+            };
+        }
+
+        let mut configs = self.device_configs.write().await;
+        if let Some(config) = configs.get_mut(device_id) {
+            info!("TLS for device {} {}", device_id, if tls.is_some() { "enabled" } else { "disabled" });
+            config.tls = tls;
+        }
+    }
+
+    /// Set or clear the identity secret of a device.
+    /// Takes effect on the next (re)connect of the device.
+    pub async fn set_device_identity(&self, device_id: &str, secret: Option<String>) {
+        {
+            let mut device_identity = self.device_identity.write().await;
+            match &secret {
+                Some(secret) => device_identity.insert(device_id.to_string(), secret.clone()),
+                None => device_identity.remove(device_id),
+            };
+        }
+
+        let mut configs = self.device_configs.write().await;
+        if let Some(config) = configs.get_mut(device_id) {
+            info!("Identity check for device {} {}", device_id, if secret.is_some() { "enabled" } else { "disabled" });
+            config.identity_secret = secret;
+        }
+    }
+
+    /// Set the ports, timeouts and keep-alive of a device. Applied right away: the inactivity
+    /// monitor uses the new UDP timeout and an open TCP link gets the new keep-alive, new ports
+    /// are used from the next connect on.
+    pub async fn set_connection_settings(&self, device_id: &str, settings: ConnectionSettings) {
+        self.device_connection_settings.write().await.insert(device_id.to_string(), settings);
+        if let Some(config) = self.device_configs.write().await.get_mut(device_id) {
+            config.apply_connection_settings(&settings);
+        }
+        let transport = self.connections.read().await.get(device_id).cloned();
+        if let Some(transport) = transport {
+            transport.lock().await.apply_settings(settings).await;
+        }
+    }
+
+    /// Current connection settings of a device; None if it has no config and none are stored
+    pub async fn connection_settings(&self, device_id: &str) -> Option<ConnectionSettings> {
+        if let Some(config) = self.device_configs.read().await.get(device_id) {
+            return Some(config.connection_settings());
+        }
+        self.device_connection_settings.read().await.get(device_id).copied()
+    }
+
+    /// Get the configured UDP listen ports
+    pub async fn get_udp_listen_ports(&self) -> Vec<u16> {
+        self.udp_listen_ports.read().await.clone()
+    }
+
+    /// Ports the central UDP listener is actually bound to
+    pub async fn get_udp_bound_ports(&self) -> Vec<u16> {
+        self.central_udp_sockets.read().await.iter()
+            .filter_map(|socket| socket.local_addr().ok())
+            .map(|addr| addr.port())
+            .collect()
+    }
+
     /// Start the DEVICE manager background tasks
     pub async fn start(&self) {
         info!("Starting Device Manager");
@@ -89,12 +351,30 @@ impl DeviceManager {
         // Start unified timeout monitoring task (for UDP and UART, not TCP)
         self.start_unified_timeout_monitor().await;
 
+        // Start command queue worker (retries queued commands, drops expired ones)
+        self.start_command_queue_worker().await;
+
+        // Start pending request monitor (reports commands without device reply)
+        self.start_pending_request_monitor().await;
+
         info!("Device Manager started");
     }
     
     /// Add a new device configuration
-    pub async fn add_device(&self, config: DeviceConfig) -> DeviceResult<()> {
+    pub async fn add_device(&self, mut config: DeviceConfig) -> DeviceResult<()> {
         let device_id = config.device_id.clone();
+
+        // Apply stored TLS settings unless the caller configured TLS explicitly
+        if config.tls.is_none() {
+            config.tls = self.device_tls.read().await.get(&device_id).cloned();
+        }
+        if config.identity_secret.is_none() {
+            config.identity_secret = self.device_identity.read().await.get(&device_id).cloned();
+        }
+        if let Some(settings) = self.device_connection_settings.read().await.get(&device_id) {
+            config.apply_connection_settings(settings);
+        }
+
         info!("Adding device: {} ({}:{})",
                device_id, config.ip_address, config.tcp_port);
         crate::debug_logger::DebugLogger::log_device_add(&device_id);
@@ -134,18 +414,12 @@ impl DeviceManager {
         let device_event_sender = self.create_direct_device_sender(device_id.clone());
 
         info!("Direct event sender created for device {} - closed: {}", device_id, device_event_sender.is_closed());
-        let connection = DeviceConnection::new(
-            config,
-            device_event_sender,
-            self.device_store.clone(),
-            self.get_unified_connection_states(),
-            self.get_device_connection_types()
-        );
+        let connection = DeviceConnection::new(config, device_event_sender, self.transport_context());
 
         {
             let mut connections = self.connections.write().await;
             crate::debug_logger::DebugLogger::log_device_manager_state(&device_id, "ADDING to connections HashMap");
-            connections.insert(device_id.clone(), Arc::new(Mutex::new(connection)));
+            connections.insert(device_id.clone(), shared_transport(connection));
             crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("CONNECTION_STORED_IN_HASHMAP: {}", device_id));
             crate::debug_logger::DebugLogger::log_device_manager_state(&device_id, "ADDED to connections HashMap");
         }
@@ -191,6 +465,10 @@ impl DeviceManager {
             let mut configs = self.device_configs.write().await;
             configs.remove(device_id);
         }
+        self.connect_locks.lock().await.remove(device_id);
+        self.supervisor.forget(device_id).await;
+        self.variable_coalescer.forget_device(device_id);
+        self.device_store.clocks().forget(device_id);
 
         // Remove from unified activity tracker to prevent the timeout monitor
         // from auto-re-registering this device as a UART device
@@ -201,13 +479,14 @@ impl DeviceManager {
             }
         }
 
-        // Remove from unified connection states to prevent spurious
+        // Forget the lifecycle state to prevent spurious
         // disconnect events being sent to WebSocket clients after removal
-        {
-            let mut states = self.unified_connection_states.write().await;
-            if states.remove(device_id).is_some() {
-                info!("Removed device {} from unified connection states", device_id);
-            }
+        self.lifecycle.forget(device_id).await;
+
+        // Drop commands that can no longer be delivered
+        let dropped = self.command_queue.clear(device_id).await;
+        if dropped > 0 {
+            info!("Dropped {} queued command(s) for removed device {}", dropped, device_id);
         }
 
         // Remove from device connection types to prevent stale type lookups
@@ -227,16 +506,21 @@ impl DeviceManager {
         info!("DEVICE CONNECTION DEBUG: Starting connection process for device: {}", device_id);
         crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("CONNECT_DEVICE_START: {}", device_id));
 
-        // Use global mutex to prevent race conditions between multiple connection attempts
-        let _connection_guard = self.connection_mutex.lock().await;
+        // Concurrent attempts for the same device wait for each other; other devices are not blocked
+        let device_lock = {
+            let mut locks = self.connect_locks.lock().await;
+            Arc::clone(locks.entry(device_id.to_string()).or_default())
+        };
+        let _device_guard = device_lock.lock().await;
+        self.supervisor.release(device_id).await;
         crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("CONNECT_DEVICE_MUTEX_ACQUIRED: {}", device_id));
 
         // First, check if we need to recreate the connection with a fresh direct sender
+        let existing = self.connections.read().await.get(device_id).cloned();
         let needs_recreation = {
-            let connections = self.connections.read().await;
-            if let Some(connection_arc) = connections.get(device_id) {
+            if let Some(connection_arc) = existing {
                 let connection = connection_arc.lock().await;
-                let current_state = connection.get_connection_state().await;
+                let current_state = connection.health().await;
                 match current_state {
                     ConnectionState::Connected => {
                         info!("DEVICE CONNECTION DEBUG: Device {} already connected - skipping", device_id);
@@ -272,14 +556,8 @@ impl DeviceManager {
 
             // Create new DeviceConnection with fresh direct sender
             let direct_sender = self.create_direct_device_sender(device_id.to_string());
-            let new_connection = DeviceConnection::new(
-                config.clone(),
-                direct_sender,
-                self.device_store.clone(),
-                self.get_unified_connection_states(),
-                self.get_device_connection_types()
-            );
-            let connection_arc = Arc::new(Mutex::new(new_connection));
+            let new_connection = DeviceConnection::new(config.clone(), direct_sender, self.transport_context());
+            let connection_arc = shared_transport(new_connection);
 
             // Replace the connection
             {
@@ -290,17 +568,24 @@ impl DeviceManager {
             info!("DEVICE CONNECTION DEBUG: DeviceConnection recreated for device: {}", device_id);
         }
 
-        let connections = self.connections.read().await;
-        if let Some(connection_arc) = connections.get(device_id) {
+        let connection_arc = self.connections.read().await.get(device_id).cloned();
+        if let Some(connection_arc) = connection_arc {
             info!("DEVICE CONNECTION DEBUG: Found connection for device: {}", device_id);
             crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("CONNECTION_FOUND: {}", device_id));
 
+            // Wait for a free connect slot; the slot is held only for the TCP connect itself
+            let slots = Arc::clone(&*self.connect_slots.read().await);
+            let slot = slots.acquire_owned().await
+                .map_err(|_| DeviceError::ConnectionFailed("Connect slots closed".to_string()))?;
             let mut connection = connection_arc.lock().await;
 
             info!("DEVICE CONNECTION DEBUG: Attempting TCP connection for device: {}", device_id);
             crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("ATTEMPTING_TCP_CONNECTION: {}", device_id));
+            self.lifecycle.connecting(device_id, "TCP connection attempt").await;
 
-            match connection.connect().await {
+            let connect_result = connection.connect().await;
+            drop(slot);
+            match connect_result {
                 Ok(()) => {
                     info!("DEVICE CONNECTION DEBUG: TCP connection established for device: {}", device_id);
                     crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("TCP_CONNECTION_SUCCESS: {}", device_id));
@@ -308,6 +593,7 @@ impl DeviceManager {
                 Err(e) => {
                     error!("DEVICE CONNECTION DEBUG: TCP connection failed for device: {} - Error: {}", device_id, e);
                     crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("TCP_CONNECTION_FAILED: {} - Error: {}", device_id, e));
+                    self.lifecycle.link_down(device_id, &format!("TCP connection failed: {}", e)).await;
                     return Err(e);
                 }
             }
@@ -329,15 +615,14 @@ impl DeviceManager {
                     info!("Unified activity tracking initialized for device: {}", device_id);
                 }
 
-                // Mark device as connected in unified connection states
-                {
-                    let mut states = self.unified_connection_states.write().await;
-                    states.insert(device_id.to_string(), true);
-                    info!("Unified connection state set to connected for device: {}", device_id);
-                }
+                self.lifecycle.link_up(device_id, "TCP connection established").await;
             }
 
             info!("DEVICE CONNECTION DEBUG: Successfully connected to device: {}", device_id);
+
+            // Deliver commands that were queued while the device was unreachable
+            drop(connection);
+            Self::flush_command_queue_for(&connection_arc, &self.command_queue, self.device_store.pending_requests(), device_id).await;
             info!("DEVICE CONNECTION DEBUG: Connection status events should now be sent to frontend for device: {}", device_id);
             crate::debug_logger::DebugLogger::log_event("DEVICE_MANAGER", &format!("CONNECT_DEVICE_SUCCESS: {}", device_id));
 
@@ -371,10 +656,38 @@ impl DeviceManager {
         }
     }
     
+    /// Connect several devices in parallel; at most `max_concurrent_connects` TCP connects run
+    /// at a time. `on_result` is called as each attempt finishes (progress reporting);
+    /// results are returned in completion order.
+    pub async fn connect_devices(
+        &self,
+        device_ids: &[String],
+        mut on_result: impl FnMut(&str, &DeviceResult<()>),
+    ) -> Vec<(String, DeviceResult<()>)> {
+        use futures::stream::{FuturesUnordered, StreamExt};
+
+        let mut attempts: FuturesUnordered<_> = device_ids.iter().map(|device_id| async move {
+            (device_id.clone(), self.connect_device(device_id).await)
+        }).collect();
+
+        let mut results = Vec::with_capacity(device_ids.len());
+        while let Some((device_id, result)) = attempts.next().await {
+            on_result(&device_id, &result);
+            results.push((device_id, result));
+        }
+        results
+    }
+
     /// Disconnect from device
+    /// The supervisor keeps the device disconnected until it is connected again.
     pub async fn disconnect_device(&self, device_id: &str) -> DeviceResult<()> {
         info!("Disconnecting from device: {}", device_id);
+        self.supervisor.hold(device_id).await;
+        self.close_link(device_id, "Disconnected").await
+    }
 
+    /// Close the link of a device without holding it (the supervisor may reconnect it)
+    pub async fn close_link(&self, device_id: &str, reason: &str) -> DeviceResult<()> {
         let connections = self.connections.read().await;
         if let Some(connection_arc) = connections.get(device_id) {
             let mut connection = connection_arc.lock().await;
@@ -390,6 +703,7 @@ impl DeviceManager {
             }
 
             connection.disconnect().await?;
+            self.lifecycle.link_down(device_id, reason).await;
             info!("Successfully disconnected from device: {}", device_id);
             Ok(())
         } else {
@@ -397,18 +711,123 @@ impl DeviceManager {
         }
     }
     
-    /// Send command to device
+    /// Send command to device (queued if the device is currently unreachable)
     pub async fn send_command(&self, device_id: &str, command: DeviceCommand) -> DeviceResult<()> {
+        self.send_or_queue_command(device_id, command, None).await.map(|_| ())
+    }
+
+    /// Send command to device, or queue it while the device is disconnected.
+    /// Commands are delivered strictly in order: while older commands are
+    /// pending, new ones are queued behind them.
+    pub async fn send_or_queue_command(
+        &self,
+        device_id: &str,
+        command: DeviceCommand,
+        correlation_id: Option<String>,
+    ) -> DeviceResult<CommandDispatch> {
         debug!("Sending command to device {}: {:?}", device_id, command);
-        
-        let connections = self.connections.read().await;
-        if let Some(connection_arc) = connections.get(device_id) {
+
+        let connection_arc = self.transport_for(device_id).await?;
+
+        let is_connected = {
             let connection = connection_arc.lock().await;
-            connection.send_command(command).await?;
-            debug!("Command sent successfully to device: {}", device_id);
-            Ok(())
-        } else {
-            Err(DeviceError::DeviceNotFound(device_id.to_string()))
+            matches!(connection.health().await, ConnectionState::Connected)
+        };
+
+        // A running flush must finish before a new command may go out directly
+        let delivery = self.command_queue.delivery_lock(device_id).await;
+        let pending = self.command_queue.depth(device_id).await;
+
+        if is_connected && pending == 0 {
+            let connection = connection_arc.lock().await;
+            match connection.send(command.clone(), correlation_id.as_deref()).await {
+                Ok(()) => {
+                    debug!("Command sent successfully to device: {}", device_id);
+                    if let Some(correlation_id) = &correlation_id {
+                        self.device_store.pending_requests().mark_sent(correlation_id).await;
+                    }
+                    return Ok(CommandDispatch::Sent);
+                }
+                Err(e) => {
+                    warn!("Sending command to device {} failed, queueing for retry: {}", device_id, e);
+                }
+            }
+        }
+
+        let (command_id, _) = self.command_queue.enqueue(device_id, command, correlation_id).await?;
+        drop(delivery);
+
+        if is_connected && pending > 0 {
+            Self::flush_command_queue_for(&connection_arc, &self.command_queue, self.device_store.pending_requests(), device_id).await;
+        }
+
+        Ok(CommandDispatch::Queued {
+            command_id,
+            queue_depth: self.command_queue.depth(device_id).await,
+        })
+    }
+
+    /// Transport of a device; UART devices get one on the shared serial port on first use
+    async fn transport_for(&self, device_id: &str) -> DeviceResult<SharedTransport> {
+        if let Some(connection) = self.connections.read().await.get(device_id) {
+            return Ok(Arc::clone(connection));
+        }
+
+        let port = match self.get_device_connection_type(device_id).await {
+            Some(DeviceConnectionType::Uart) => self.uart_port.read().await.clone(),
+            _ => None,
+        };
+        let port = port.ok_or_else(|| match uart_gateway::split_virtual_id(device_id) {
+            // Children of ESP-NOW peer reports (espnow_peers.rs) are only observed
+            Some((parent_id, _)) => DeviceError::InvalidCommand(format!("{} is an ESP-NOW peer reported by {} and read-only", device_id, parent_id)),
+            None => DeviceError::DeviceNotFound(device_id.to_string()),
+        })?;
+
+        let mut connections = self.connections.write().await;
+        let connection = connections
+            .entry(device_id.to_string())
+            .or_insert_with(|| shared_transport(UartDeviceTransport::new(device_id.to_string(), port)));
+        Ok(Arc::clone(connection))
+    }
+
+    /// Get the outbound command queue of a device
+    pub async fn get_command_queue(&self, device_id: &str) -> CommandQueueSnapshot {
+        self.command_queue.snapshot(device_id).await
+    }
+
+    /// Deliver queued commands in order until the queue is empty or a send fails.
+    /// A failed command stays at the head of the queue (until max attempts) so
+    /// later commands never overtake it. Concurrent flushes of a device run one
+    /// after the other (connect, send path and queue worker all flush).
+    async fn flush_command_queue_for(
+        connection_arc: &SharedTransport,
+        command_queue: &CommandQueue,
+        pending_requests: &PendingRequests,
+        device_id: &str,
+    ) {
+        let _delivery = command_queue.delivery_lock(device_id).await;
+        while let Some(queued) = command_queue.pop_front(device_id).await {
+            let result = {
+                let connection = connection_arc.lock().await;
+                connection.send(queued.command.clone(), queued.correlation_id.as_deref()).await
+            };
+
+            match result {
+                Ok(()) => {
+                    info!("Delivered queued command {} to device {} (attempt {})",
+                          queued.id, device_id, queued.attempts + 1);
+                    if let Some(correlation_id) = &queued.correlation_id {
+                        pending_requests.mark_sent(correlation_id).await;
+                    }
+                }
+                Err(e) => {
+                    warn!("Queued command {} for device {} failed: {}", queued.id, device_id, e);
+                    if command_queue.requeue_front(device_id, queued).await {
+                        // Retry later, keep ordering intact
+                        break;
+                    }
+                }
+            }
         }
     }
     
@@ -417,12 +836,23 @@ impl DeviceManager {
         let connections = self.connections.read().await;
         if let Some(connection_arc) = connections.get(device_id) {
             let connection = connection_arc.lock().await;
-            Some(connection.get_connection_state().await)
+            Some(connection.health().await)
         } else {
             None
         }
     }
     
+    /// Time since the last TCP/UDP/UART activity of a device
+    pub async fn get_activity_age(&self, device_id: &str) -> Option<Duration> {
+        let tracker = self.unified_activity_tracker.read().await;
+        tracker.get(device_id).map(|last| last.elapsed())
+    }
+
+    /// Number of commands waiting in the outbound queue of a device
+    pub async fn get_command_queue_depth(&self, device_id: &str) -> usize {
+        self.command_queue.depth(device_id).await
+    }
+
     /// Get all configured devices
     pub async fn get_all_devices(&self) -> Vec<DeviceConfig> {
         let configs = self.device_configs.read().await;
@@ -435,25 +865,190 @@ impl DeviceManager {
         configs.get(device_id).cloned()
     }
 
+    /// IDs of all devices reached over TCP
+    pub async fn tcp_device_ids(&self) -> Vec<String> {
+        let conn_types = self.device_connection_types.read().await;
+        conn_types.iter()
+            .filter(|(_, connection_type)| **connection_type == DeviceConnectionType::TcpUdp)
+            .map(|(device_id, _)| device_id.clone())
+            .collect()
+    }
+
     /// Get device connection type (UART vs TCP/UDP)
     pub async fn get_device_connection_type(&self, device_id: &str) -> Option<DeviceConnectionType> {
         let conn_types = self.device_connection_types.read().await;
         conn_types.get(device_id).copied()
     }
+    
+    /// Active discovery: broadcast a UDP probe on all local subnets and collect replies.
+    /// Devices are reported through `found_tx` as soon as they answer.
+    pub async fn discover_devices(
+        &self,
+        probe: &UdpDiscoveryProbe,
+        found_tx: Option<mpsc::UnboundedSender<DeviceConfig>>,
+    ) -> DeviceResult<Vec<DeviceConfig>> {
+        let socket = UdpSocket::bind("0.0.0.0:0").await?;
+        socket.set_broadcast(true)?;
+
+        let targets = Self::broadcast_addresses();
+        info!("UDP discovery probe to {:?} on ports {:?}", targets, probe.ports);
+        for target in &targets {
+            for port in &probe.ports {
+                if let Err(e) = socket.send_to(UDP_DISCOVERY_MESSAGE.as_bytes(), (*target, *port)).await {
+                    debug!("UDP discovery probe to {}:{} failed: {}", target, port, e);
+                }
+            }
+        }
 
-    /// Get reference to device connection types map (for sharing with other components)
-    pub fn get_device_connection_types(&self) -> Arc<RwLock<HashMap<String, DeviceConnectionType>>> {
-        Arc::clone(&self.device_connection_types)
+        let mut found: Vec<DeviceConfig> = Vec::new();
+        // One byte more than a reply may have, so oversized datagrams are recognized instead of cut off
+        let mut buffer = [0u8; MAX_DISCOVERY_REPLY_BYTES + 1];
+        let deadline = tokio::time::Instant::now() + Duration::from_millis(probe.timeout_ms);
+
+        while let Ok(received) = tokio::time::timeout_at(deadline, socket.recv_from(&mut buffer)).await {
+            let (len, addr) = match received {
+                Ok(received) => received,
+                Err(e) => {
+                    debug!("UDP discovery receive error: {}", e);
+                    continue;
+                }
+            };
+
+            let reply = String::from_utf8_lossy(&buffer[..len]).to_string();
+            // Our own probe comes back on some interfaces
+            if reply == UDP_DISCOVERY_MESSAGE {
+                continue;
+            }
+
+            let Some(config) = Self::parse_discovery_reply(&reply, addr) else {
+                debug!("UDP discovery: ignoring {}-byte reply from {}", len, addr);
+                continue;
+            };
+            if found.iter().any(|c| c.device_id == config.device_id || c.ip_address == config.ip_address) {
+                continue;
+            }
+
+            info!("UDP discovery: {} answered from {}", config.device_id, addr);
+            if let Some(ref found_tx) = found_tx {
+                let _ = found_tx.send(config.clone());
+            }
+            found.push(config);
+        }
+
+        info!("UDP discovery finished: {} device(s) found", found.len());
+        Ok(found)
     }
-    
-    /// Auto-discover devices (placeholder for future UDP discovery)
-    pub async fn discover_devices(&self) -> DeviceResult<Vec<DeviceConfig>> {
-        // TODO: Implement UDP broadcast discovery like UdpSearcher.cs
-        // For now return empty list
-        info!("device discovery not yet implemented");
-        Ok(Vec::new())
+
+    /// Active discovery: probe every host of a CIDR range for an open device TCP port.
+    /// Parallelism is bounded by `config.concurrency`; hosts that are already
+    /// configured are skipped.
+    pub async fn scan_tcp_range(
+        &self,
+        config: &TcpScanConfig,
+        found_tx: Option<mpsc::UnboundedSender<DeviceConfig>>,
+    ) -> DeviceResult<Vec<DeviceConfig>> {
+        let hosts = config.hosts().map_err(DeviceError::InvalidCommand)?;
+        let known_ips: Vec<IpAddr> = self.device_configs.read().await
+            .values()
+            .map(|c| c.ip_address)
+            .collect();
+
+        info!("TCP scan of {} ({} hosts) on port {}", config.cidr, hosts.len(), config.port);
+        let connect_timeout = Duration::from_millis(config.timeout_ms);
+        let concurrency = config.concurrency.max(1);
+        let port = config.port;
+
+        let mut found = Vec::new();
+        let mut probes = tokio::task::JoinSet::new();
+        let mut pending_hosts = hosts.into_iter()
+            .map(IpAddr::V4)
+            .filter(|ip| !known_ips.contains(ip));
+
+        loop {
+            while probes.len() < concurrency {
+                let Some(ip) = pending_hosts.next() else { break };
+                probes.spawn(Self::probe_tcp_host(SocketAddr::new(ip, port), connect_timeout));
+            }
+
+            let Some(result) = probes.join_next().await else { break };
+            if let Ok(Some(device)) = result {
+                info!("TCP scan: {} open at {}", device.device_id, device.tcp_addr());
+                if let Some(ref found_tx) = found_tx {
+                    let _ = found_tx.send(device.clone());
+                }
+                found.push(device);
+            }
+        }
+
+        info!("TCP scan of {} finished: {} device(s) found", config.cidr, found.len());
+        Ok(found)
     }
-    
+
+    /// Connect to one host; a greeting with deviceId/mac received within the
+    /// timeout names the device, otherwise it is keyed by IP
+    async fn probe_tcp_host(addr: SocketAddr, connect_timeout: Duration) -> Option<DeviceConfig> {
+        let mut stream = tokio::time::timeout(connect_timeout, tokio::net::TcpStream::connect(addr))
+            .await
+            .ok()?
+            .ok()?;
+
+        let mut buffer = [0u8; 512];
+        let greeting = match tokio::time::timeout(connect_timeout, tokio::io::AsyncReadExt::read(&mut stream, &mut buffer)).await {
+            Ok(Ok(len)) if len > 0 => String::from_utf8_lossy(&buffer[..len]).to_string(),
+            _ => String::new(),
+        };
+
+        let first_message = greeting.lines().next().unwrap_or_default();
+        Self::parse_discovery_reply(first_message, addr)
+    }
+
+    /// Limited broadcast plus the directed broadcast address of every IPv4 interface
+    fn broadcast_addresses() -> Vec<Ipv4Addr> {
+        let mut targets = vec![Ipv4Addr::BROADCAST];
+        match if_addrs::get_if_addrs() {
+            Ok(interfaces) => {
+                for interface in interfaces {
+                    if let if_addrs::IfAddr::V4(v4) = interface.addr {
+                        if v4.ip.is_loopback() {
+                            continue;
+                        }
+                        let broadcast = v4.broadcast.unwrap_or_else(|| {
+                            Ipv4Addr::from(u32::from(v4.ip) | !u32::from(v4.netmask))
+                        });
+                        if !targets.contains(&broadcast) {
+                            targets.push(broadcast);
+                        }
+                    }
+                }
+            }
+            Err(e) => warn!("Failed to enumerate network interfaces: {}", e),
+        }
+        targets
+    }
+
+    /// Build a device config from a probe reply; None for replies over `MAX_DISCOVERY_REPLY_BYTES`.
+    /// JSON replies may carry deviceId/mac, deviceName and tcpPort; anything else is keyed by IP.
+    fn parse_discovery_reply(reply: &str, addr: SocketAddr) -> Option<DeviceConfig> {
+        if reply.len() > MAX_DISCOVERY_REPLY_BYTES {
+            return None;
+        }
+        let json = serde_json::from_str::<serde_json::Value>(reply.trim()).ok();
+        let device_id = Self::extract_device_id_from_tcp_message(reply)
+            .unwrap_or_else(|| format!("device-{}", addr.ip().to_string().replace(['.', ':'], "-")));
+
+        let tcp_port = json.as_ref()
+            .and_then(|j| j.get("tcpPort").or_else(|| j.get("port")))
+            .and_then(|v| v.as_u64())
+            .and_then(|p| u16::try_from(p).ok())
+            .unwrap_or(addr.port());
+
+        let mut config = DeviceConfig::new(device_id, addr.ip(), tcp_port, addr.port());
+        if let Some(name) = json.as_ref().and_then(|j| j.get("deviceName")).and_then(|v| v.as_str()) {
+            config.device_name = name.to_string();
+        }
+        Some(config)
+    }
+
     // ========================================================================
     // INTEGRATION WITH DEVICE STORE
     // ========================================================================
@@ -467,13 +1062,69 @@ impl DeviceManager {
         client_id: &str,
     ) -> DeviceResult<()> {
         debug!("Handling WebSocket command for device {}: {:?}", device_id, command_data);
-        
+        self.check_command_rate(device_id, user_id)?;
+
+        let ClientDispatch { request_id, dispatch, .. } =
+            self.dispatch_client_command(device_id, command_data, user_id, client_id, false).await?;
+
+        let status = match dispatch {
+            CommandDispatch::Sent => "sent",
+            CommandDispatch::Queued { .. } => "queued",
+        };
+        self.device_store.send_to_client(
+            device_id,
+            client_id,
+            crate::events::ServerMessage::command_result(device_id.to_string(), request_id, status, None),
+        ).await;
+
+        Ok(())
+    }
+
+    /// Parse, track and send a client command (WebSocket or REST).
+    /// The device sees a server-generated correlation ID; replies to the client use its
+    /// own request ID. With `await_reply` the device reply is handed to the caller.
+    pub async fn dispatch_client_command(
+        &self,
+        device_id: &str,
+        command_data: serde_json::Value,
+        user_id: &str,
+        client_id: &str,
+        await_reply: bool,
+    ) -> DeviceResult<ClientDispatch> {
+        // Clients may pass their own requestId; otherwise one is generated
+        let request_id = command_data
+            .get("requestId")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+
         // Parse command from JSON
         let command = self.parse_websocket_command(command_data)?;
-        
-        // Send command to DEVICE
-        self.send_command(device_id, command.clone()).await?;
-        
+
+        // Reject values the device type does not accept before they reach the hardware
+        if let Some(profile) = self.get_device_profile(device_id).await {
+            profile.check_command(&command).map_err(DeviceError::ProfileViolation)?;
+        }
+
+        // Track request so the device reply can be routed back to this client
+        let pending_requests = self.device_store.pending_requests();
+        let request = PendingRequest::new(request_id.clone(), device_id, user_id, client_id, command.clone());
+        let correlation_id = request.correlation_id.clone();
+        pending_requests.register(request).await;
+        let reply = match await_reply {
+            true => Some(pending_requests.wait_for_reply(&correlation_id).await),
+            false => None,
+        };
+
+        // Send command to DEVICE (queued while disconnected)
+        let dispatch = match self.send_or_queue_command(device_id, command.clone(), Some(correlation_id.clone())).await {
+            Ok(dispatch) => dispatch,
+            Err(e) => {
+                pending_requests.cancel(&correlation_id).await;
+                return Err(e);
+            }
+        };
+
         // Create device event for logging/broadcasting
         let device_event = WebSocketDeviceEvent::device_command_for_device(
             device_id.to_string(),
@@ -490,7 +1141,7 @@ impl DeviceManager {
             error!("Failed to add DEVICE command event to device store: {}", e);
         }
         
-        Ok(())
+        Ok(ClientDispatch { request_id, correlation_id, dispatch, reply })
     }
     
     /// Parse WebSocket command data into DEVICE command
@@ -523,6 +1174,11 @@ impl DeviceManager {
         if data.get("getStatus").is_some() {
             return Ok(DeviceCommand::get_status());
         }
+
+        // Handle setTime command (epoch milliseconds)
+        if let Some(epoch_ms) = data.get("setTime").and_then(|v| v.as_i64()) {
+            return Ok(DeviceCommand::set_time(epoch_ms));
+        }
         
         Err(DeviceError::InvalidCommand(format!("Unknown command: {:?}", data)))
     }
@@ -541,7 +1197,8 @@ impl DeviceManager {
         let (tx, mut rx) = mpsc::unbounded_channel();
         let device_store = self.device_store.clone();
 
-        // Spawn a simple forwarding task that sends directly to DeviceStore
+        // Spawn a simple forwarding task that sends directly to DeviceStore (logs tagged with device_id)
+        let span = tracing::info_span!(parent: None, "device", device_id = %device_id);
         tokio::spawn(async move {
             info!("DIRECT SENDER: Started direct forwarding task for device {}", device_id);
 
@@ -553,7 +1210,7 @@ impl DeviceManager {
             }
 
             info!("DIRECT SENDER: Direct forwarding task ended for device {}", device_id);
-        });
+        }.instrument(span));
 
         tx
     }
@@ -619,111 +1276,200 @@ impl DeviceManager {
     // CENTRAL UDP LISTENER
     // ========================================================================
 
-    /// Start central UDP listener for all devices
+    /// Start central UDP listeners for all devices (one task per configured port)
     async fn start_central_udp_listener(&self) -> DeviceResult<()> {
-        const UDP_PORT: u16 = 3232;
-        let addr = SocketAddr::from(([0, 0, 0, 0], UDP_PORT));
+        let ports = self.get_udp_listen_ports().await;
+        let mut bound_ports = Vec::new();
+
+        // One pool for all listener sockets, so a device's frames stay in order across ports
+        let workers = *self.udp_workers.read().await;
+        let context = Arc::new(self.udp_listener_context());
+        let pool = UdpWorkerPool::spawn(workers, UDP_WORKER_QUEUE_CAPACITY, move |datagram| {
+            let context = Arc::clone(&context);
+            async move { Self::process_udp_datagram(&context, datagram).await }
+        });
 
-        let socket = UdpSocket::bind(addr)
-            .await
-            .map_err(|e| DeviceError::ConnectionFailed(
-                format!("Central UDP bind failed on {}: {}", addr, e)
-            ))?;
+        for port in ports {
+            let addr = SocketAddr::from(([0, 0, 0, 0], port));
+
+            let socket = match UdpSocket::bind(addr).await {
+                Ok(socket) => Arc::new(socket),
+                Err(e) => {
+                    // A single port failing must not take down the other listeners
+                    error!("Central UDP bind failed on {}: {}", addr, e);
+                    continue;
+                }
+            };
 
-        info!("Central UDP listener started on {}", addr);
+            info!("Central UDP listener started on {}", addr);
 
-        // Store socket
-        {
-            let mut udp_socket = self.central_udp_socket.lock().await;
-            *udp_socket = Some(socket);
+            // Store socket
+            self.central_udp_sockets.write().await.push(Arc::clone(&socket));
+            bound_ports.push(port);
+
+            // Start listener task
+            let pool = Arc::clone(&pool);
+
+            tokio::spawn(async move {
+                info!("Central UDP listener task started on port {}", port);
+                Self::run_udp_listener_loop(socket, pool).await;
+            });
         }
 
-        // Start listener task
-        let socket = Arc::clone(&self.central_udp_socket);
-        let ip_to_device_id = Arc::clone(&self.ip_to_device_id);
-        let device_store = Arc::clone(&self.device_store);
-        let unified_activity_tracker = Arc::clone(&self.unified_activity_tracker);
-        let unified_connection_states = Arc::clone(&self.unified_connection_states);
-        let device_connection_types = Arc::clone(&self.device_connection_types);
+        if bound_ports.is_empty() {
+            return Err(DeviceError::ConnectionFailed(
+                "Central UDP listener could not bind any configured port".to_string()
+            ));
+        }
 
-        tokio::spawn(async move {
-            let mut buffer = [0u8; 1024];
-            info!("Central UDP listener task started");
+        info!("Central UDP listener active on ports {:?} ({} parsing workers)", bound_ports, workers);
+        Ok(())
+    }
 
-            loop {
-                let socket_guard = socket.lock().await;
-                if let Some(udp_socket) = socket_guard.as_ref() {
-                    match timeout(Duration::from_millis(100), udp_socket.recv_from(&mut buffer)).await {
-                        Ok(Ok((bytes_read, from_addr))) => {
-                            let message = String::from_utf8_lossy(&buffer[..bytes_read]).to_string();
-
-                            // Print to terminal only (no logging)
-                            println!("UDP Message from {}: {}", from_addr, message);
-
-                            // Route message to specific DEVICE connection if registered
-                            {
-                                let device_map = ip_to_device_id.read().await;
-                                if let Some(device_id) = device_map.get(&from_addr.ip()) {
-                                    // Use unified message handler with activity tracking
-                                    Self::handle_message_unified(
-                                        &message,
-                                        device_id,
-                                        MessageSource::Udp {
-                                            ip: from_addr.ip().to_string(),
-                                            port: from_addr.port(),
-                                        },
-                                        &device_store,
-                                        &unified_connection_states,
-                                        Some(&unified_activity_tracker),
-                                        Some(&device_connection_types),
-                                    ).await;
-                                } else {
-                                    drop(device_map); // Drop read lock before getting write lock
-
-                                    // Check if this looks like a TCP message that should be routed via UDP bypass
-                                    if Self::is_tcp_message(&message) {
-                                        if let Some(device_id) = Self::extract_device_id_from_tcp_message(&message) {
-                                            // Auto-register this IP for the device
-                                            {
-                                                let mut device_map = ip_to_device_id.write().await;
-                                                device_map.insert(from_addr.ip(), device_id.clone());
-                                            }
-
-                                            // Route the TCP message through unified handler
-                                            debug!("TCP via UDP bypass: Routing message to device {} via unified handler", device_id);
-                                            Self::handle_message_unified(
-                                                &message,
-                                                &device_id,
-                                                MessageSource::Udp {
-                                                    ip: from_addr.ip().to_string(),
-                                                    port: from_addr.port(),
-                                                },
-                                                &device_store,
-                                                &unified_connection_states,
-                                                Some(&unified_activity_tracker),
-                                                Some(&device_connection_types),
-                                            ).await;
-                                        }
-                                    }
-                                    // No logging for unregistered devices or non-TCP messages
-                                }
-                            }
-                        }
-                        Ok(Err(e)) => {
-                            error!("Central UDP receive error: {}", e);
-                            sleep(Duration::from_secs(1)).await;
-                        }
-                        Err(_) => {
-                            // Timeout, continue
-                        }
-                    }
-                } else {
-                    sleep(Duration::from_millis(100)).await;
+    /// Deliver a binary UDP frame as DeviceBinaryData event (base64 payload)
+    #[allow(clippy::too_many_arguments)]
+    async fn handle_binary_message(
+        raw: &[u8],
+        encoding: PayloadEncoding,
+        decoded: Option<serde_json::Value>,
+        from_addr: SocketAddr,
+        ip_to_device_id: &Arc<RwLock<HashMap<IpAddr, String>>>,
+        device_configs: &Arc<RwLock<HashMap<String, DeviceConfig>>>,
+        device_store: &SharedDeviceStore,
+        activity_tracker: &Arc<RwLock<HashMap<String, Instant>>>,
+    ) {
+        // Binary frames carry no device ID we can rely on - only known senders are accepted
+        let registered = ip_to_device_id.read().await.get(&from_addr.ip()).cloned();
+        let device_id = match registered {
+            Some(device_id) => device_id,
+            None => match Self::find_device_id_by_ip(device_configs, from_addr.ip()).await {
+                Some(device_id) => device_id,
+                None => {
+                    debug!("Ignoring {} byte {} frame from unknown sender {}", raw.len(), encoding.as_str(), from_addr);
+                    return;
+                }
+            },
+        };
+
+        activity_tracker.write().await.insert(device_id.clone(), Instant::now());
+        crate::device_trace::record(&device_id, TraceDirection::In, TraceTransport::Udp, Some(from_addr.to_string()), raw);
+
+        let event = WebSocketDeviceEvent::device_binary_data(
+            device_id.clone(),
+            encoding.as_str().to_string(),
+            DecodedPayload::base64_payload(raw),
+            decoded,
+            raw.len(),
+            from_addr.ip().to_string(),
+            from_addr.port(),
+        );
+
+        if let Err(e) = device_store.add_event(
+            device_id.clone(),
+            event,
+            "DEVICE_SYSTEM".to_string(),
+            "DEVICE_INTERNAL".to_string(),
+        ).await {
+            warn!("Failed to store binary data event for device {}: {}", device_id, e);
+        }
+    }
+
+    /// Collect the shared state a central UDP listener task needs
+    fn udp_listener_context(&self) -> UdpListenerContext {
+        UdpListenerContext {
+            ip_to_device_id: Arc::clone(&self.ip_to_device_id),
+            device_configs: Arc::clone(&self.device_configs),
+            transport: self.transport_context(),
+            unidentified_sources: Arc::clone(&self.unidentified_sources),
+        }
+    }
+
+    /// Receive loop of a single central UDP listener socket.
+    /// Only receives: parsing and delivery run in the worker pool shared by all listeners,
+    /// which routes by source IP - a device is handled identically on every port.
+    async fn run_udp_listener_loop(udp_socket: Arc<UdpSocket>, workers: Arc<UdpWorkerPool>) {
+        let mut buffer = [0u8; 1024];
+
+        loop {
+            match udp_socket.recv_from(&mut buffer).await {
+                Ok((bytes_read, from_addr)) => {
+                    workers.dispatch(UdpDatagram { payload: buffer[..bytes_read].to_vec(), from: from_addr });
+                }
+                Err(e) => {
+                    error!("Central UDP receive error: {}", e);
+                    sleep(Duration::from_secs(1)).await;
                 }
             }
-        });
+        }
+    }
 
-        Ok(())
+    /// Decode, route and deliver one received datagram (runs in a UDP worker)
+    async fn process_udp_datagram(context: &UdpListenerContext, datagram: UdpDatagram) {
+        let UdpListenerContext {
+            ip_to_device_id,
+            device_configs,
+            transport,
+            unidentified_sources,
+        } = context;
+        let from_addr = datagram.from;
+
+        // Binary frames (CBOR, MessagePack, length-prefixed) bypass the text parsers
+        let message = match crate::payload_codec::decode_payload(&datagram.payload) {
+            DecodedPayload::Text(text) => text,
+            DecodedPayload::Binary { encoding, decoded, raw } => {
+                Self::handle_binary_message(
+                    &raw, encoding, decoded, from_addr,
+                    ip_to_device_id, device_configs, &transport.device_store, &transport.activity_tracker,
+                ).await;
+                return;
+            }
+        };
+
+        // Route message to specific DEVICE connection if registered
+        let registered = ip_to_device_id.read().await.get(&from_addr.ip()).cloned();
+        if let Some(device_id) = registered {
+            // Use unified message handler with activity tracking
+            transport.deliver(&device_id, &message, MessageSource::Udp {
+                ip: from_addr.ip().to_string(),
+                port: from_addr.port(),
+            }).await;
+            return;
+        }
+
+        // Check if this looks like a TCP message that should be routed via UDP bypass
+        // No logging for unregistered devices or non-TCP messages
+        if !Self::is_tcp_message(&message) {
+            return;
+        }
+
+        // Identify the device from the message itself, then by configured IP.
+        // A claimed ID is only trusted for the device configured on the sender's IP.
+        let device_id = match Self::extract_device_id_from_tcp_message(&message) {
+            Some(claimed) => {
+                if !Self::claim_matches_config(&*device_configs.read().await, &claimed, from_addr.ip()) {
+                    warn!("Dropping UDP message from {}: claimed device {} is not configured on that IP or requires an identity proof", from_addr, claimed);
+                    return;
+                }
+                Some(claimed)
+            }
+            None => Self::find_device_id_by_ip(device_configs, from_addr.ip()).await,
+        };
+
+        if let Some(device_id) = device_id {
+            // Auto-register this IP for the device
+            ip_to_device_id.write().await.insert(from_addr.ip(), device_id.clone());
+            unidentified_sources.write().await.remove(&from_addr.ip());
+
+            // Route the TCP message through unified handler
+            debug!("TCP via UDP bypass: Routing message to device {} via unified handler", device_id);
+            transport.deliver(&device_id, &message, MessageSource::Udp {
+                ip: from_addr.ip().to_string(),
+                port: from_addr.port(),
+            }).await;
+        } else {
+            // Never guess: quarantine the sender until it can be identified
+            Self::quarantine_unidentified_source(unidentified_sources, from_addr, &message).await;
+        }
     }
 
 
@@ -733,12 +1479,16 @@ impl DeviceManager {
 
     /// Central unified message handler for all message types (UART, TCP, UDP)
     /// This ensures consistent processing regardless of the message origin
+    #[allow(clippy::too_many_arguments)]
     pub async fn handle_message_unified(
         message: &str,
         device_id: &str,
         source: MessageSource,
         device_store: &SharedDeviceStore,
-        connection_states: &Arc<RwLock<HashMap<String, bool>>>,
+        lifecycle: &SharedLifecycle,
+        coalescer: &Arc<VariableCoalescer>,
+        bus_decoders: &BusDecoders,
+        message_parsers: &MessageParsers,
         activity_tracker: Option<&Arc<RwLock<HashMap<String, Instant>>>>,
         device_connection_types: Option<&Arc<RwLock<HashMap<String, DeviceConnectionType>>>>,
     ) {
@@ -748,6 +1498,30 @@ impl DeviceManager {
             MessageSource::Udp { .. } => "UDP",
         };
 
+        // TCP and UART frames are traced where they are read, before the device_id is stripped
+        if let MessageSource::Udp { ip, port } = &source {
+            crate::device_trace::record(device_id, TraceDirection::In, TraceTransport::Udp, Some(format!("{}:{}", ip, port)), message.as_bytes());
+        }
+
+        // Nothing oversized, deeply nested or with control characters reaches the store or a browser
+        let message = match crate::inbound_validation::check_message(message) {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("{}: message of device {} dropped: {}", source_name, device_id, e);
+                crate::quarantine::record_error(lifecycle, device_id).await;
+                return;
+            }
+        };
+        let message = message.as_ref();
+
+        // Replies carrying a correlation ID are routed back to the requesting client
+        Self::resolve_correlated_reply(message, device_id, device_store).await;
+
+        // Pair device-reported uptime/timestamp with the receive time (drift, event stamps)
+        if let Some(report) = ClockReport::parse(message) {
+            device_store.clocks().observe(device_id, report, chrono::Utc::now().timestamp_millis());
+        }
+
         // Register device connection type if provided
         if let Some(conn_types) = device_connection_types {
             let device_type = match &source {
@@ -787,18 +1561,8 @@ impl DeviceManager {
             }
         }
 
-        // Smart connection state tracking - send event only on state change
-        let should_send_connected_event = {
-            let mut states = connection_states.write().await;
-            let was_connected = states.get(device_id).copied().unwrap_or(false);
-
-            if !was_connected {
-                states.insert(device_id.to_string(), true);
-                true
-            } else {
-                false
-            }
-        };
+        // Any traffic brings the device (back) online - send event only on state change
+        let should_send_connected_event = lifecycle.link_up(device_id, &format!("{} traffic", source_name)).await;
 
         // Send connection event only if state changed
         if should_send_connected_event {
@@ -826,6 +1590,62 @@ impl DeviceManager {
             }
         }
 
+        // Quarantined devices only leave an occasional sample in the device log
+        match crate::quarantine::admit(lifecycle, device_id).await {
+            Admission::Pass => {}
+            Admission::Sample => {
+                device_store.logs().push(device_id, DeviceLogEntry {
+                    timestamp: chrono::Utc::now().timestamp_millis(),
+                    level: LogLevel::Warn,
+                    msg: message.to_string(),
+                    tag: Some("quarantine".to_string()),
+                    source: source_name.to_string(),
+                    device_time: None,
+                });
+                return;
+            }
+            Admission::Hold => return,
+        }
+
+        // Raw bus frames of devices with a decoder plugin enter the store as named variables only
+        match bus_decoders.decode(device_id, message) {
+            Some(Ok(variables)) => {
+                for (name, value) in variables {
+                    let variable_event = WebSocketDeviceEvent::device_variable_update(device_id.to_string(), name.clone(), value);
+                    Self::add_variable_update(device_store, coalescer, device_id, &name, variable_event, source_name).await;
+                }
+                return;
+            }
+            Some(Err(e)) => {
+                warn!("{}: bus frame of device {} not decoded: {}", source_name, device_id, e);
+                crate::quarantine::record_error(lifecycle, device_id).await;
+            }
+            None => {}
+        }
+
+        // Log frames go to the device log store, not into the telemetry history
+        if let Some(frame) = LogFrame::parse(message) {
+            let timestamp = chrono::Utc::now().timestamp_millis();
+            device_store.logs().push(device_id, DeviceLogEntry {
+                timestamp,
+                level: frame.level,
+                msg: frame.msg.clone(),
+                tag: frame.tag.clone(),
+                source: source_name.to_string(),
+                device_time: device_store.clocks().stamp(device_id, timestamp),
+            });
+            let log_event = WebSocketDeviceEvent::device_log(device_id.to_string(), frame.level, frame.msg, frame.tag);
+            if let Err(e) = device_store.add_event(
+                device_id.to_string(),
+                log_event,
+                "device_system".to_string(),
+                format!("{}_log", source_name.to_lowercase()),
+            ).await {
+                error!("Failed to send {} log event for device {}: {}", source_name, device_id, e);
+            }
+            return;
+        }
+
         // Send broadcast event with actual source info
         let (ip, port) = match &source {
             MessageSource::Uart => ("0.0.0.0".to_string(), 0),
@@ -845,270 +1665,75 @@ impl DeviceManager {
             format!("{}_message", source_name.to_lowercase()),
         ).await;
 
-        // Parse message and extract structured data (JSON + regex fallback)
-        Self::parse_and_process_message(message, device_id, device_store, source_name).await;
-    }
-
-    /// Parse message and create appropriate events
-    /// Combines JSON parsing with regex fallback for legacy messages
-    async fn parse_and_process_message(
-        message: &str,
-        device_id: &str,
-        device_store: &SharedDeviceStore,
-        source_name: &str,
-    ) {
-        // Try JSON parsing first (structured data)
-        let _json_parsed = if let Ok(value) = serde_json::from_str::<serde_json::Value>(message) {
-            // Handle startOptions array
-            if let Some(options_array) = value.get("startOptions") {
-                if let Some(options) = options_array.as_array() {
-                    let mut start_options = Vec::new();
-                    for option in options {
-                        if let Some(option_str) = option.as_str() {
-                            start_options.push(option_str.to_string());
-                        }
-                    }
-
-                    if !start_options.is_empty() {
-                        debug!("{}: Extracted startOptions: {:?}", source_name, start_options);
-                        let start_options_event = crate::events::DeviceEvent::device_start_options(
-                            device_id.to_string(),
-                            start_options
-                        );
-                        let _ = device_store.add_event(
-                            device_id.to_string(),
-                            start_options_event,
-                            "device_system".to_string(),
-                            format!("{}_data", source_name.to_lowercase())
-                        ).await;
-                    }
-                }
-            }
-
-            // Handle changeableVariables array
-            if let Some(vars_array) = value.get("changeableVariables") {
-                if let Some(vars) = vars_array.as_array() {
-                    let mut variables = Vec::new();
-                    for var in vars {
-                        if let (Some(name), Some(value)) = (var.get("name"), var.get("value")) {
-                            if let (Some(name_str), Some(value_num)) = (name.as_str(), value.as_u64()) {
-                                // Basis-Variable mit name und value
-                                let mut var_json = serde_json::json!({
-                                    "name": name_str,
-                                    "value": value_num
-                                });
-
-                                // Optional: min Wert hinzufügen
-                                if let Some(min_val) = var.get("min").and_then(|v| v.as_u64()) {
-                                    var_json["min"] = serde_json::json!(min_val);
-                                }
-
-                                // Optional: max Wert hinzufügen
-                                if let Some(max_val) = var.get("max").and_then(|v| v.as_u64()) {
-                                    var_json["max"] = serde_json::json!(max_val);
-                                }
-
-                                variables.push(var_json);
-                            }
-                        }
-                    }
-
-                    if !variables.is_empty() {
-                        debug!("{}: Extracted changeableVariables: {:?}", source_name, variables);
-                        let changeable_vars_event = crate::events::DeviceEvent::device_changeable_variables(
-                            device_id.to_string(),
-                            variables
-                        );
-                        let _ = device_store.add_event(
-                            device_id.to_string(),
-                            changeable_vars_event,
-                            "device_system".to_string(),
-                            format!("{}_data", source_name.to_lowercase())
-                        ).await;
-                    }
-                }
-            }
-
-            // Handle device information
-            if let Some(device_name) = value.get("deviceName").and_then(|v| v.as_str()) {
-                let firmware_version = value.get("firmwareVersion").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
-                let uptime = value.get("uptime").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
-
-                debug!("{}: Extracted device info - name: {}, firmware: {}, uptime: {}", source_name, device_name, firmware_version, uptime);
-                let device_info_event = crate::events::DeviceEvent::device_device_info(
-                    device_id.to_string(),
-                    Some(device_name.to_string()),
-                    Some(firmware_version),
-                    Some(uptime as u64)
-                );
-                let _ = device_store.add_event(
-                    device_id.to_string(),
-                    device_info_event,
-                    "device_system".to_string(),
-                    format!("{}_data", source_name.to_lowercase())
-                ).await;
-            }
-
-            // Handle status information
-            if let Some(status) = value.get("status") {
-                if let Some(status_obj) = status.as_object() {
-                    if let Some(running) = status_obj.get("running").and_then(|v| v.as_bool()) {
-                        debug!("{}: Device {} status - running: {}", source_name, device_id, running);
-                    }
-
-                    if let Some(memory_free) = status_obj.get("memoryFree").and_then(|v| v.as_u64()) {
-                        debug!("{}: Device {} memory free: {} bytes", source_name, device_id, memory_free);
-                    }
-                }
-            }
-
-            // Handle legacy single variable updates with optional min/max
-            // Format: {"variableName": "value", "min": 0, "max": 100}
-            // or: {"variableName": 123, "min": 0, "max": 100}
-            if let Some(obj) = value.as_object() {
-                // Collect all fields except known metadata fields
-                let skip_fields = ["device_id", "startOptions", "changeableVariables",
-                                   "deviceName", "firmwareVersion", "uptime", "status"];
-
-                // Check if this is a simple variable update with min/max
-                let has_min = obj.contains_key("min");
-                let has_max = obj.contains_key("max");
-
-                if has_min || has_max {
-                    // Find the variable field(s) - any field that's not min/max or metadata
-                    for (key, val) in obj.iter() {
-                        if key != "min" && key != "max" && !skip_fields.contains(&key.as_str()) {
-                            // Extract value (string or number)
-                            let value_str = if let Some(s) = val.as_str() {
-                                s.to_string()
-                            } else if let Some(n) = val.as_u64() {
-                                n.to_string()
-                            } else if let Some(n) = val.as_i64() {
-                                n.to_string()
-                            } else if let Some(f) = val.as_f64() {
-                                f.to_string()
-                            } else {
-                                continue; // Skip non-primitive values
-                            };
-
-                            // Extract min/max if present
-                            let min_val = obj.get("min").and_then(|v| v.as_u64());
-                            let max_val = obj.get("max").and_then(|v| v.as_u64());
-
-                            // Send variable update event with min/max metadata
-                            debug!("{}: Extracted variable with min/max - name: {}, value: {}, min: {:?}, max: {:?}",
-                                   source_name, key, value_str, min_val, max_val);
-
-                            // Send as DeviceVariableUpdate with min/max
-                            let variable_event = crate::events::DeviceEvent::device_variable_update_with_range(
-                                device_id.to_string(),
-                                key.clone(),
-                                value_str,
-                                min_val,
-                                max_val,
-                            );
-
-                            let _ = device_store.add_event(
-                                device_id.to_string(),
-                                variable_event,
-                                "device_system".to_string(),
-                                format!("{}_data", source_name.to_lowercase())
-                            ).await;
-                        }
-                    }
-                }
+        // Structured data from the device's parser chain (JSON envelope, regex fallbacks, custom)
+        for mut parsed in message_parsers.parse(device_id, message) {
+            if !crate::inbound_validation::sanitize_event(&mut parsed.event) {
+                debug!("{}: event of device {} without a usable variable name dropped", source_name, device_id);
+                continue;
             }
-
-            true // JSON parsing succeeded
-        } else {
-            false // JSON parsing failed
-        };
-
-        // Parse simple key-value variable updates using regex
-        // Only NUMERIC values (including string-encoded numbers) are treated as variables
-        // String values like {"debug":"..."} remain as UdpBroadcast for frontend categorization
-
-        // Parse for numeric variable updates: {"name": 123} or {"name": 25.5}
-        // Supports integers and floats
-        let numeric_re = regex::Regex::new(r#"\{\"([^\"]+)\"\s*:\s*(\d+\.?\d*)\}"#).unwrap();
-        for captures in numeric_re.captures_iter(message) {
-            if let (Some(name), Some(value)) = (captures.get(1), captures.get(2)) {
-                let name_str = name.as_str().trim();
-                // Skip known structured fields to avoid duplicates
-                if name_str != "uptime" && name_str != "value" {
-                    let variable_event = crate::events::DeviceEvent::device_variable_update(
-                        device_id.to_string(),
-                        name_str.to_string(),
-                        value.as_str().trim().to_string(),
-                    );
+            let variable = match (&parsed.variable, &parsed.event) {
+                (Some(_), WebSocketDeviceEvent::DeviceVariableUpdate { variable_name, .. }) => Some(variable_name.clone()),
+                (variable, _) => variable.clone(),
+            };
+            match variable {
+                Some(variable) => Self::add_variable_update(device_store, coalescer, device_id, &variable, parsed.event, source_name).await,
+                None => {
                     let _ = device_store.add_event(
                         device_id.to_string(),
-                        variable_event,
+                        parsed.event,
                         "device_system".to_string(),
-                        format!("{}_data", source_name.to_lowercase())
+                        format!("{}_data", source_name.to_lowercase()),
                     ).await;
                 }
             }
         }
+    }
 
-        // Parse for string-encoded numeric values: {"name": "123"} or {"name": "25.5"}
-        // This handles devices that send numbers as strings
-        let string_numeric_re = regex::Regex::new(r#"\{\"([^\"]+)\"\s*:\s*\"(\d+\.?\d*)\"\}"#).unwrap();
-        for captures in string_numeric_re.captures_iter(message) {
-            if let (Some(name), Some(value)) = (captures.get(1), captures.get(2)) {
-                let name_str = name.as_str().trim();
-                // Skip known structured fields to avoid duplicates
-                if name_str != "deviceName" && name_str != "firmwareVersion"
-                    && name_str != "name" && name_str != "value" {
-                    let variable_event = crate::events::DeviceEvent::device_variable_update(
-                        device_id.to_string(),
-                        name_str.to_string(),
-                        value.as_str().trim().to_string(),
-                    );
-                    let _ = device_store.add_event(
-                        device_id.to_string(),
-                        variable_event,
-                        "device_system".to_string(),
-                        format!("{}_data", source_name.to_lowercase())
-                    ).await;
+    /// Store a variable update unless the coalescer holds it back; a held update is delivered
+    /// by a flush task after the interval if no newer update went out meanwhile
+    async fn add_variable_update(
+        device_store: &SharedDeviceStore,
+        coalescer: &Arc<VariableCoalescer>,
+        device_id: &str,
+        variable: &str,
+        event: WebSocketDeviceEvent,
+        source_name: &str,
+    ) {
+        let client_id = format!("{}_data", source_name.to_lowercase());
+        let raw = coalescer.record_raw().then(|| event.clone());
+
+        match coalescer.offer(device_id, variable, event, Instant::now()) {
+            Offer::Emit(event) => {
+                let _ = device_store.add_event(device_id.to_string(), event, "device_system".to_string(), client_id).await;
+            }
+            Offer::Held { flush_in } => {
+                if let Some(raw) = raw {
+                    device_store.publish_feed(device_id, raw);
                 }
+                let Some(delay) = flush_in else { return };
+                let (device_store, coalescer) = (device_store.clone(), Arc::clone(coalescer));
+                let (device_id, variable) = (device_id.to_string(), variable.to_string());
+                tokio::spawn(async move {
+                    sleep(delay).await;
+                    let Some(event) = coalescer.take_pending(&device_id, &variable, Instant::now()) else { return };
+                    // With record_raw the sample is already on the event feed
+                    let result = if coalescer.record_raw() {
+                        device_store.add_event_without_feed(device_id, event, "device_system".to_string(), client_id).await
+                    } else {
+                        device_store.add_event(device_id, event, "device_system".to_string(), client_id).await
+                    };
+                    if let Err(e) = result {
+                        debug!("Coalesced variable update dropped: {}", e);
+                    }
+                });
             }
         }
-
-        // String values like {"debug": "message"} or {"error": "..."} are NOT parsed here
-        // They remain as UdpBroadcast events and will be categorized in the frontend
     }
 
     // ========================================================================
     // MESSAGE BYPASS FUNCTIONS
     // ========================================================================
 
-    /// Handle TCP message - calls unified handler
-    /// TCP messages do NOT use activity tracking (no timeout for TCP)
-    /// but DO use unified connection states to prevent redundant events
-    pub async fn handle_tcp_message_bypass(
-        message: &str,
-        device_id: &str,
-        device_store: &SharedDeviceStore,
-        unified_connection_states: &Arc<RwLock<HashMap<String, bool>>>,
-        device_connection_types: &Arc<RwLock<HashMap<String, DeviceConnectionType>>>,
-    ) {
-        DebugLogger::log_tcp_message(device_id, "RECEIVED", message);
-
-        Self::handle_message_unified(
-            message,
-            device_id,
-            MessageSource::Tcp {
-                ip: "0.0.0.0".to_string(),
-                port: 3232,
-            },
-            device_store,
-            unified_connection_states,  // Use shared state (prevents redundant events)
-            None,  // No activity tracking for TCP (no timeout)
-            Some(device_connection_types),
-        ).await;
-    }
-
     /// Check if a message looks like a TCP message with JSON structure
     fn is_tcp_message(message: &str) -> bool {
         // TCP messages from DEVICE are usually JSON with specific fields
@@ -1117,21 +1742,129 @@ impl DeviceManager {
             message.contains("\"changeableVariables\"") ||
             message.contains("\"setVariable\"") ||
             message.contains("\"startOption\"") ||
-            message.contains("\"reset\"")
+            message.contains("\"reset\"") ||
+            // Hello after boot (see crash_reports)
+            message.contains("\"resetReason\"") ||
+            // ESP-NOW peer report (see espnow_peers)
+            message.contains("\"espnowPeers\"") ||
+            // Hello with a pre-shared enrollment token (see enrollment)
+            message.contains("\"enrollToken\"")
         )
     }
 
-    /// Extract device ID from TCP message structure
-    fn extract_device_id_from_tcp_message(_message: &str) -> Option<String> {
-        // For now, assume the known device ID since we know there's only one DEVICE
-        // In a real system, this would parse the message to extract device info
-        Some("10-20-BA-42-71-E0".to_string())
+    /// Extract device ID from TCP message structure.
+    /// Firmware identifies itself with a top-level `deviceId` or `mac` field;
+    /// MAC addresses are normalized to the `AA-BB-CC-DD-EE-FF` device ID format.
+    fn extract_device_id_from_tcp_message(message: &str) -> Option<String> {
+        let json: serde_json::Value = serde_json::from_str(message.trim()).ok()?;
+
+        for key in ["deviceId", "device_id", "mac", "macAddress"] {
+            if let Some(value) = json.get(key).and_then(|v| v.as_str()) {
+                let value = value.trim();
+                if value.is_empty() {
+                    continue;
+                }
+                return Some(Self::normalize_device_id(value));
+            }
+        }
+
+        None
+    }
+
+    /// Normalize MAC-style IDs (colon or hyphen separated) to uppercase hyphen format.
+    /// Every path that derives a device ID from a MAC (TCP hello, mDNS, adopt, manual add) uses this.
+    pub fn normalize_device_id(value: &str) -> String {
+        let parts: Vec<&str> = value.split([':', '-']).collect();
+        let is_mac = parts.len() == 6
+            && parts.iter().all(|p| p.len() == 2 && p.chars().all(|c| c.is_ascii_hexdigit()));
+
+        if is_mac {
+            parts.join("-").to_uppercase()
+        } else {
+            value.to_string()
+        }
+    }
+
+    /// Find a configured device whose IP address matches the sender and that UDP traffic may be mapped to
+    async fn find_device_id_by_ip(
+        device_configs: &Arc<RwLock<HashMap<String, DeviceConfig>>>,
+        ip: IpAddr,
+    ) -> Option<String> {
+        let configs = device_configs.read().await;
+        configs
+            .values()
+            .find(|config| config.ip_address == ip && Self::udp_auto_mappable(config))
+            .map(|config| config.device_id.clone())
+    }
+
+    /// Whether `claimed` is a configured device whose configured IP is `ip`
+    /// and that may be mapped from UDP traffic alone
+    fn claim_matches_config(configs: &HashMap<String, DeviceConfig>, claimed: &str, ip: IpAddr) -> bool {
+        configs.get(claimed).is_some_and(|config| config.ip_address == ip && Self::udp_auto_mappable(config))
+    }
+
+    /// Devices with an identity secret only get their IP mapped after answering the
+    /// challenge on TCP - a datagram carries no proof and is trivially spoofed
+    fn udp_auto_mappable(config: &DeviceConfig) -> bool {
+        config.identity_secret.is_none()
+    }
+
+    /// Record a sender whose device could not be identified
+    async fn quarantine_unidentified_source(
+        unidentified_sources: &Arc<RwLock<HashMap<IpAddr, UnidentifiedSource>>>,
+        from_addr: SocketAddr,
+        message: &str,
+    ) {
+        const MAX_UNIDENTIFIED_SOURCES: usize = 256;
+        const MAX_STORED_MESSAGE_LEN: usize = 512;
+
+        let now = chrono::Utc::now();
+        let last_message: String = message.chars().take(MAX_STORED_MESSAGE_LEN).collect();
+        let mut sources = unidentified_sources.write().await;
+
+        if let Some(source) = sources.get_mut(&from_addr.ip()) {
+            source.port = from_addr.port();
+            source.last_seen = now;
+            source.message_count += 1;
+            source.last_message = last_message;
+            return;
+        }
+
+        if sources.len() >= MAX_UNIDENTIFIED_SOURCES {
+            // Evict the source that has been silent the longest
+            if let Some(oldest_ip) = sources
+                .iter()
+                .min_by_key(|(_, source)| source.last_seen)
+                .map(|(ip, _)| *ip)
+            {
+                sources.remove(&oldest_ip);
+            }
+        }
+
+        warn!("UDP message from {} could not be mapped to a device - quarantined", from_addr);
+        sources.insert(from_addr.ip(), UnidentifiedSource {
+            ip: from_addr.ip().to_string(),
+            port: from_addr.port(),
+            first_seen: now,
+            last_seen: now,
+            message_count: 1,
+            last_message,
+        });
+    }
+
+    /// Get all quarantined UDP senders that could not be identified
+    pub async fn get_unidentified_sources(&self) -> Vec<UnidentifiedSource> {
+        let sources = self.unidentified_sources.read().await;
+        let mut list: Vec<UnidentifiedSource> = sources.values().cloned().collect();
+        list.sort_by_key(|source| std::cmp::Reverse(source.last_seen));
+        list
     }
 
     /// Register device for UDP message routing
     pub async fn register_device_for_udp(&self, device_id: String, ip: IpAddr) {
         let mut device_map = self.ip_to_device_id.write().await;
         device_map.insert(ip, device_id.clone());
+        self.unidentified_sources.write().await.remove(&ip);
         info!("DEVICE {} registered for UDP routing on IP {}", device_id, ip);
     }
 
@@ -1150,10 +1883,7 @@ impl DeviceManager {
 
 /// Create shared DEVICE manager instance
 pub fn create_device_manager(device_store: SharedDeviceStore) -> Arc<DeviceManager> {
-    let manager = Arc::new(DeviceManager::new(device_store));
-
-
-    manager
+    Arc::new(DeviceManager::new(device_store))
 }
 
 
@@ -1165,7 +1895,7 @@ impl DeviceManager {
         let unified_activity_tracker = Arc::clone(&self.unified_activity_tracker);
         let device_configs = Arc::clone(&self.device_configs);
         let device_store = self.device_store.clone();
-        let unified_connection_states = Arc::clone(&self.unified_connection_states);
+        let lifecycle = Arc::clone(&self.lifecycle);
 
         tokio::spawn(async move {
             let mut interval = interval(Duration::from_secs(5)); // Check every 5 seconds
@@ -1197,25 +1927,17 @@ impl DeviceManager {
                         let elapsed = now.duration_since(*last_activity);
                         let timeout = Duration::from_secs(config.udp_timeout_seconds);
 
+                        if elapsed > timeout / 2 && elapsed <= timeout {
+                            lifecycle.degraded(device_id, &format!("No traffic for {}s", elapsed.as_secs())).await;
+                        }
+
                         if elapsed > timeout {
                             warn!("UNIFIED TIMEOUT: Device {} ({:?}) has been inactive for {}s (timeout: {}s)",
                                   device_id, config.device_source, elapsed.as_secs(), config.udp_timeout_seconds);
 
                             // Only send disconnect event if device was connected
-                            let should_send_disconnect = {
-                                let mut states = unified_connection_states.write().await;
-                                let was_connected = states.get(device_id).copied().unwrap_or(false);
-
-                                if was_connected {
-                                    // Mark as disconnected
-                                    states.insert(device_id.clone(), false);
-                                    info!("UNIFIED TIMEOUT: Device {} marked as disconnected", device_id);
-                                    true
-                                } else {
-                                    // Already disconnected - no event needed
-                                    false
-                                }
-                            };
+                            let reason = format!("No traffic for {}s", elapsed.as_secs());
+                            let should_send_disconnect = lifecycle.link_down(device_id, &reason).await;
 
                             if should_send_disconnect {
                                 // Send disconnect event
@@ -1250,6 +1972,120 @@ impl DeviceManager {
         });
     }
 
+    /// Start command queue worker: retries pending commands for connected devices
+    async fn start_command_queue_worker(&self) {
+        let connections = Arc::clone(&self.connections);
+        let command_queue = Arc::clone(&self.command_queue);
+        let device_store = self.device_store.clone();
+
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(5));
+            info!("Command queue worker started");
+
+            loop {
+                interval.tick().await;
+
+                command_queue.purge_expired().await;
+
+                for device_id in command_queue.devices_with_pending().await {
+                    let connection_arc = {
+                        let connections = connections.read().await;
+                        connections.get(&device_id).cloned()
+                    };
+
+                    let Some(connection_arc) = connection_arc else {
+                        continue;
+                    };
+
+                    let is_connected = {
+                        let connection = connection_arc.lock().await;
+                        matches!(connection.health().await, ConnectionState::Connected)
+                    };
+
+                    if is_connected {
+                        Self::flush_command_queue_for(&connection_arc, &command_queue, device_store.pending_requests(), &device_id).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Start monitor that reports timed-out command requests to their clients
+    async fn start_pending_request_monitor(&self) {
+        let device_store = self.device_store.clone();
+
+        tokio::spawn(async move {
+            let mut interval = interval(Duration::from_secs(1));
+            info!("Pending request monitor started");
+
+            loop {
+                interval.tick().await;
+
+                let pending_requests = device_store.pending_requests();
+                let timeout_secs = pending_requests.timeout().as_secs();
+
+                for request in pending_requests.take_expired().await {
+                    let message = match request.sent_at {
+                        Some(_) => format!("No reply from device {} within {}s", request.device_id, timeout_secs),
+                        None => format!("Command for device {} was never delivered", request.device_id),
+                    };
+                    warn!("Command request {} ({}) for device {} timed out: {}",
+                          request.request_id, request.correlation_id, request.device_id, message);
+
+                    device_store.send_to_client(
+                        &request.device_id,
+                        &request.client_id,
+                        crate::events::ServerMessage::command_error(
+                            request.device_id.clone(),
+                            request.request_id.clone(),
+                            "timeout",
+                            "COMMAND_TIMEOUT",
+                            message,
+                        ),
+                    ).await;
+                }
+            }
+        });
+    }
+
+    /// Match a device reply with an `id`/`requestId` field to its pending request
+    async fn resolve_correlated_reply(message: &str, device_id: &str, device_store: &SharedDeviceStore) {
+        if !message.trim_start().starts_with('{') {
+            return;
+        }
+
+        let Ok(json) = serde_json::from_str::<serde_json::Value>(message.trim()) else {
+            return;
+        };
+
+        let Some(correlation_id) = ["id", "requestId", "correlationId"]
+            .iter()
+            .find_map(|key| json.get(*key).and_then(|v| v.as_str()))
+        else {
+            return;
+        };
+
+        if let Some(request) = device_store.pending_requests().resolve(device_id, correlation_id).await {
+            debug!("Device {} acknowledged request {} ({}) after {}ms",
+                   device_id, request.request_id, correlation_id,
+                   request.sent_at.unwrap_or(request.created_at).elapsed().as_millis());
+
+            // REST callers wait synchronously for the reply
+            device_store.pending_requests().deliver_reply(correlation_id, json.clone()).await;
+
+            device_store.send_to_client(
+                device_id,
+                &request.client_id,
+                crate::events::ServerMessage::command_result(
+                    device_id.to_string(),
+                    request.request_id,
+                    "acknowledged",
+                    Some(json),
+                ),
+            ).await;
+        }
+    }
+
     /// Update UDP activity for a device
     pub async fn update_udp_activity(&self, device_id: &str) {
         let mut tracker = self.unified_activity_tracker.write().await;
@@ -1257,14 +2093,9 @@ impl DeviceManager {
         debug!("Unified activity updated for device: {}", device_id);
     }
 
-    /// Get shared connection states for external use (e.g., UART)
-    pub fn get_unified_connection_states(&self) -> Arc<RwLock<HashMap<String, bool>>> {
-        Arc::clone(&self.unified_connection_states)
-    }
-
-    /// Get shared activity tracker for external use (e.g., UART)
-    pub fn get_unified_activity_tracker(&self) -> Arc<RwLock<HashMap<String, Instant>>> {
-        Arc::clone(&self.unified_activity_tracker)
+    /// Get the shared lifecycle registry for external use (e.g., UART, API)
+    pub fn lifecycle(&self) -> SharedLifecycle {
+        Arc::clone(&self.lifecycle)
     }
 }
 
@@ -1280,3 +2111,158 @@ impl DeviceConfig {
         Self::new(device_id, ip, 3232, 3232) // DEVICE-S3 also uses port 3232
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_discovery_reply() {
+        let addr: SocketAddr = "192.168.1.20:3232".parse().unwrap();
+
+        let valid = DeviceManager::parse_discovery_reply(
+            r#"{"mac":"aa:bb:cc:dd:ee:ff","deviceName":"Lab S3","tcpPort":4000}"#,
+            addr,
+        ).unwrap();
+        assert_eq!(valid.device_id, "AA-BB-CC-DD-EE-FF");
+        assert_eq!(valid.device_name, "Lab S3");
+        assert_eq!(valid.tcp_port, 4000);
+        assert_eq!(valid.udp_port, 3232);
+
+        // Not JSON, or an out-of-range port: keyed by IP, ports of the sender
+        let malformed = DeviceManager::parse_discovery_reply("{\"deviceId\": ", addr).unwrap();
+        assert_eq!(malformed.device_id, "device-192-168-1-20");
+        assert_eq!(malformed.tcp_port, 3232);
+        let bad_port = DeviceManager::parse_discovery_reply(r#"{"deviceId":"s3-lab","tcpPort":70000}"#, addr).unwrap();
+        assert_eq!(bad_port.device_id, "s3-lab");
+        assert_eq!(bad_port.tcp_port, 3232);
+
+        let oversized = format!(r#"{{"deviceId":"s3-lab","deviceName":"{}"}}"#, "x".repeat(MAX_DISCOVERY_REPLY_BYTES));
+        assert!(DeviceManager::parse_discovery_reply(&oversized, addr).is_none());
+    }
+
+    #[test]
+    fn test_normalize_device_id() {
+        assert_eq!(DeviceManager::normalize_device_id("aa:bb:cc:dd:ee:ff"), "AA-BB-CC-DD-EE-FF");
+        assert_eq!(DeviceManager::normalize_device_id("AA-BB-CC-DD-EE-FF"), "AA-BB-CC-DD-EE-FF");
+        assert_eq!(DeviceManager::normalize_device_id("aa-bb-cc-dd-ee-0f"), "AA-BB-CC-DD-EE-0F");
+
+        // Not a MAC: kept as sent
+        for id in ["s3-lab", "AA:BB:CC:DD:EE", "AA:BB:CC:DD:EE:FF:00", "AA:BB:CC:DD:EE:GG", "AAA:BB:CC:DD:EE:F"] {
+            assert_eq!(DeviceManager::normalize_device_id(id), id);
+        }
+    }
+
+    #[test]
+    fn test_extract_device_id_from_tcp_message() {
+        let extract = DeviceManager::extract_device_id_from_tcp_message;
+
+        assert_eq!(extract(r#"{"mac":"aa:bb:cc:dd:ee:ff"}"#).as_deref(), Some("AA-BB-CC-DD-EE-FF"));
+        assert_eq!(extract(r#"{"macAddress":"aa-bb-cc-dd-ee-ff"}"#).as_deref(), Some("AA-BB-CC-DD-EE-FF"));
+        assert_eq!(extract(r#"{"deviceId":"s3-lab","mac":"aa:bb:cc:dd:ee:ff"}"#).as_deref(), Some("s3-lab"));
+        assert_eq!(extract(" {\"device_id\":\" AA:BB:CC:DD:EE:01 \"}\n").as_deref(), Some("AA-BB-CC-DD-EE-01"));
+        // Empty fields are skipped
+        assert_eq!(extract(r#"{"deviceId":"","mac":"aa:bb:cc:dd:ee:ff"}"#).as_deref(), Some("AA-BB-CC-DD-EE-FF"));
+
+        // No ID, or no JSON object with one
+        assert_eq!(extract(r#"{"type":"status","uptime":12}"#), None);
+        assert_eq!(extract(r#"{"deviceId":42}"#), None);
+        assert_eq!(extract(r#"{"data":{"mac":"aa:bb:cc:dd:ee:ff"}}"#), None);
+        assert_eq!(extract("STATUS OK"), None);
+    }
+
+    #[test]
+    fn test_claim_matches_config() {
+        let ip: IpAddr = "192.168.1.20".parse().unwrap();
+        let configs = HashMap::from([
+            ("s3-lab".to_string(), DeviceConfig::device_default("s3-lab".to_string(), ip)),
+            ("s3-hall".to_string(), DeviceConfig::device_default("s3-hall".to_string(), "192.168.1.21".parse().unwrap())),
+        ]);
+
+        assert!(DeviceManager::claim_matches_config(&configs, "s3-lab", ip));
+        // Another device's ID, or one nobody configured
+        assert!(!DeviceManager::claim_matches_config(&configs, "s3-hall", ip));
+        assert!(!DeviceManager::claim_matches_config(&configs, "rogue", ip));
+
+        // A device with an identity secret is never mapped from a datagram
+        let mut guarded = configs;
+        guarded.get_mut("s3-lab").unwrap().identity_secret = Some("0123456789abcdef".to_string());
+        assert!(!DeviceManager::claim_matches_config(&guarded, "s3-lab", ip));
+    }
+
+    /// Transport that records the order commands arrive in; the first attempt
+    /// of every command listed in `fail_once` fails
+    #[derive(Debug, Default)]
+    struct RecordingTransport {
+        sent: Arc<std::sync::Mutex<Vec<String>>>,
+        fail_once: std::sync::Mutex<Vec<String>>,
+    }
+
+    impl crate::device_transport::DeviceTransport for RecordingTransport {
+        fn connection_type(&self) -> DeviceConnectionType {
+            DeviceConnectionType::TcpUdp
+        }
+
+        fn connect(&mut self) -> futures::future::BoxFuture<'_, DeviceResult<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn disconnect(&mut self) -> futures::future::BoxFuture<'_, DeviceResult<()>> {
+            Box::pin(async { Ok(()) })
+        }
+
+        fn send<'a>(&'a self, command: DeviceCommand, _correlation_id: Option<&'a str>) -> futures::future::BoxFuture<'a, DeviceResult<()>> {
+            Box::pin(async move {
+                // Give other flushes the chance to interleave
+                tokio::task::yield_now().await;
+                let DeviceCommand::StartOption { start_option } = command else {
+                    return Ok(());
+                };
+                let mut fail_once = self.fail_once.lock().unwrap();
+                if let Some(index) = fail_once.iter().position(|name| *name == start_option) {
+                    fail_once.remove(index);
+                    return Err(DeviceError::ConnectionFailed("send failed".to_string()));
+                }
+                self.sent.lock().unwrap().push(start_option);
+                Ok(())
+            })
+        }
+
+        fn health(&self) -> futures::future::BoxFuture<'_, ConnectionState> {
+            Box::pin(async { ConnectionState::Connected })
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_flushes_deliver_in_order() {
+        let transport = RecordingTransport {
+            fail_once: std::sync::Mutex::new(vec!["cmd-10".to_string(), "cmd-20".to_string()]),
+            ..Default::default()
+        };
+        let sent = Arc::clone(&transport.sent);
+        let connection = shared_transport(transport);
+        let command_queue = Arc::new(CommandQueue::default());
+        let pending_requests = Arc::new(PendingRequests::default());
+
+        let expected: Vec<String> = (0..40).map(|i| format!("cmd-{}", i)).collect();
+        for name in &expected {
+            command_queue.enqueue("dev-1", DeviceCommand::start_option(name.clone()), None).await.unwrap();
+        }
+
+        // Connect, send path and queue worker may all flush the same device at once.
+        // A failed send puts its command back at the head; no other flush may overtake it.
+        let flushes: Vec<_> = (0..4).map(|_| {
+            let (connection, command_queue, pending_requests) =
+                (Arc::clone(&connection), Arc::clone(&command_queue), Arc::clone(&pending_requests));
+            tokio::spawn(async move {
+                DeviceManager::flush_command_queue_for(&connection, &command_queue, &pending_requests, "dev-1").await;
+            })
+        }).collect();
+        for flush in flushes {
+            flush.await.unwrap();
+        }
+
+        assert_eq!(*sent.lock().unwrap(), expected);
+        assert_eq!(command_queue.depth("dev-1").await, 0);
+    }
+}
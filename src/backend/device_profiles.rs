@@ -0,0 +1,164 @@
+// Device type profiles - allowed variables, ranges and start options of a kind of device
+//
+// A profile describes what a device type accepts: its changeable variables with optional
+// min/max or a list of allowed values, and its start options. Devices get a profile assigned
+// (devices.profile_id); the device manager keeps the assigned profiles in memory and checks every
+// client command against them, so out-of-range `setVariable` values and unknown start options are
+// rejected before they reach the hardware. Devices without a profile accept any command.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::database::DatabaseManager;
+use crate::device_manager::DeviceManager;
+use crate::device_types::DeviceCommand;
+
+/// Most variables a profile may describe
+pub const MAX_VARIABLES: usize = 200;
+
+/// One changeable variable of a device type
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct VariableSpec {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u32>,
+    /// Only these values are accepted (e.g. modes)
+    #[serde(default, rename = "enum", skip_serializing_if = "Option::is_none")]
+    pub allowed: Option<Vec<u32>>,
+}
+
+impl VariableSpec {
+    fn check(&self, value: u32) -> Result<(), String> {
+        if let Some(allowed) = &self.allowed {
+            if !allowed.contains(&value) {
+                return Err(format!("{} is not an allowed value of '{}' (allowed: {:?})", value, self.name, allowed));
+            }
+        }
+        if self.min.is_some_and(|min| value < min) || self.max.is_some_and(|max| value > max) {
+            let bound = |b: Option<u32>| b.map(|b| b.to_string()).unwrap_or_else(|| "..".to_string());
+            return Err(format!("{} is out of range for '{}' ({} - {})", value, self.name, bound(self.min), bound(self.max)));
+        }
+        Ok(())
+    }
+}
+
+/// Device type profile (GET /api/device-profiles/:id)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceProfile {
+    pub id: String,
+    pub owner_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub variables: Vec<VariableSpec>,
+    /// Accepted start options; empty = any
+    pub start_options: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl DeviceProfile {
+    /// Check the profile itself: names, ranges and allowed values
+    pub fn validate(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            return Err("Profile name must be between 1 and 100 characters".to_string());
+        }
+        if self.variables.len() > MAX_VARIABLES {
+            return Err(format!("A profile may describe at most {} variables", MAX_VARIABLES));
+        }
+        let mut names = HashSet::new();
+        for variable in &self.variables {
+            if variable.name.trim().is_empty() {
+                return Err("Variable names must not be empty".to_string());
+            }
+            if !names.insert(variable.name.as_str()) {
+                return Err(format!("Variable '{}' is listed twice", variable.name));
+            }
+            if let (Some(min), Some(max)) = (variable.min, variable.max) {
+                if min > max {
+                    return Err(format!("Variable '{}': min {} is greater than max {}", variable.name, min, max));
+                }
+            }
+            if variable.allowed.as_ref().is_some_and(|allowed| allowed.is_empty()) {
+                return Err(format!("Variable '{}': enum must not be empty", variable.name));
+            }
+        }
+        if self.start_options.iter().any(|option| option.trim().is_empty()) {
+            return Err("Start options must not be empty".to_string());
+        }
+        Ok(())
+    }
+
+    /// Check a command for a device of this type; commands other than setVariable/startOption pass
+    pub fn check_command(&self, command: &DeviceCommand) -> Result<(), String> {
+        match command {
+            DeviceCommand::SetVariable { name, value } => {
+                let Some(variable) = self.variables.iter().find(|variable| variable.name == *name) else {
+                    return Err(format!("Variable '{}' is not part of profile '{}'", name, self.name));
+                };
+                variable.check(*value)
+            }
+            DeviceCommand::StartOption { start_option } => {
+                if self.start_options.is_empty() || self.start_options.contains(start_option) {
+                    Ok(())
+                } else {
+                    Err(format!("Start option '{}' is not part of profile '{}'", start_option, self.name))
+                }
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Hand the stored profile assignments to the device manager (at startup)
+pub async fn load_assignments(db: &DatabaseManager, device_manager: &DeviceManager) {
+    match db.list_device_profile_assignments().await {
+        Ok(assignments) => {
+            let count = assignments.len();
+            for (device_id, profile) in assignments {
+                device_manager.set_device_profile(&device_id, Some(Arc::new(profile))).await;
+            }
+            info!("Device profiles assigned to {} device(s)", count);
+        }
+        Err(e) => warn!("Failed to load device profile assignments: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_profile_rejects_out_of_range_values() {
+        let profile: DeviceProfile = serde_json::from_value(json!({
+            "id": "p1", "owner_id": "guest", "name": "LED strip", "description": null,
+            "variables": [
+                {"name": "brightness", "min": 0, "max": 255},
+                {"name": "mode", "enum": [0, 1, 3]}
+            ],
+            "start_options": ["blink", "fade"],
+            "created_at": "2026-01-01T00:00:00Z", "updated_at": "2026-01-01T00:00:00Z"
+        })).unwrap();
+        assert!(profile.validate().is_ok());
+
+        assert!(profile.check_command(&DeviceCommand::set_variable("brightness".to_string(), 255)).is_ok());
+        assert!(profile.check_command(&DeviceCommand::set_variable("brightness".to_string(), 256)).unwrap_err().contains("out of range"));
+        assert!(profile.check_command(&DeviceCommand::set_variable("mode".to_string(), 2)).is_err());
+        assert!(profile.check_command(&DeviceCommand::set_variable("speed".to_string(), 1)).is_err());
+        assert!(profile.check_command(&DeviceCommand::start_option("fade".to_string())).is_ok());
+        assert!(profile.check_command(&DeviceCommand::start_option("reboot".to_string())).is_err());
+        assert!(profile.check_command(&DeviceCommand::reset()).is_ok());
+
+        let mut invalid = profile.clone();
+        invalid.variables[0].min = Some(300);
+        assert!(invalid.validate().is_err());
+    }
+}
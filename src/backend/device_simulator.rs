@@ -0,0 +1,587 @@
+// Device simulator - fake ESP32 devices speaking the real TCP/UDP protocol
+// Used for frontend development and integration tests without physical hardware
+
+use crate::device_manager::DeviceManager;
+use crate::device_types::{DeviceConfig, DeviceError, DeviceResult};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info, warn};
+
+/// Firmware version reported by simulated devices
+const SIMULATOR_FIRMWARE_VERSION: &str = "sim-1.0.0";
+
+// ============================================================================
+// SIMULATOR CONFIGURATION
+// ============================================================================
+
+/// Variable exposed by a simulated device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatedVariable {
+    pub name: String,
+    pub value: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<u32>,
+}
+
+/// Misbehaviour injected into a simulated device
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SimulatorFailureModes {
+    /// Delay before every reply (slow firmware / bad Wi-Fi)
+    pub reply_delay_ms: u64,
+    /// Accept commands but never reply (tests command timeouts)
+    pub ignore_commands: bool,
+    /// Close the TCP connection after this many commands
+    pub disconnect_after_commands: Option<u32>,
+    /// Accept TCP connections and close them immediately
+    pub refuse_connections: bool,
+}
+
+/// Configuration of a simulated device (request body of POST /api/admin/simulators)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct SimulatorSpec {
+    /// Device ID (MAC format); generated if not set
+    pub device_id: Option<String>,
+    pub device_name: Option<String>,
+    /// Loopback address to bind to; a free 127.0.0.x is picked if not set,
+    /// so each simulator has its own IP for UDP identification
+    pub bind_ip: Option<IpAddr>,
+    /// TCP port of the simulated device (0 = any free port)
+    pub tcp_port: u16,
+    /// UDP port of the manager that receives the telemetry broadcasts
+    pub manager_udp_port: u16,
+    /// Interval of the UDP variable broadcasts (0 = disabled)
+    pub telemetry_interval_ms: u64,
+    pub variables: Vec<SimulatedVariable>,
+    pub start_options: Vec<String>,
+    pub failure_modes: SimulatorFailureModes,
+    /// Register the simulator with the device manager and connect to it
+    pub register: bool,
+}
+
+impl Default for SimulatorSpec {
+    fn default() -> Self {
+        Self {
+            device_id: None,
+            device_name: None,
+            bind_ip: None,
+            tcp_port: 0,
+            manager_udp_port: crate::device_manager::DEFAULT_UDP_LISTEN_PORT,
+            telemetry_interval_ms: 2000,
+            variables: vec![
+                SimulatedVariable { name: "brightness".to_string(), value: 50, min: Some(0), max: Some(100) },
+                SimulatedVariable { name: "speed".to_string(), value: 10, min: Some(1), max: Some(20) },
+            ],
+            start_options: vec!["rainbow".to_string(), "blink".to_string(), "off".to_string()],
+            failure_modes: SimulatorFailureModes::default(),
+            register: true,
+        }
+    }
+}
+
+/// Public view of a running simulator
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulatorInfo {
+    pub device_id: String,
+    pub device_name: String,
+    pub ip_address: IpAddr,
+    pub tcp_port: u16,
+    pub udp_port: u16,
+    pub registered: bool,
+    pub uptime_seconds: u64,
+    pub commands_received: u64,
+    pub active_option: Option<String>,
+    pub variables: Vec<SimulatedVariable>,
+    pub failure_modes: SimulatorFailureModes,
+}
+
+// ============================================================================
+// SIMULATED DEVICE STATE
+// ============================================================================
+
+/// Mutable firmware state of one simulated device
+#[derive(Debug)]
+struct SimulatedDevice {
+    device_name: String,
+    initial_variables: Vec<SimulatedVariable>,
+    variables: Vec<SimulatedVariable>,
+    start_options: Vec<String>,
+    active_option: Option<String>,
+    commands_received: u64,
+    booted_at: Instant,
+    /// Wall clock of the firmware relative to the host clock (changed by setTime)
+    clock_offset_ms: i64,
+}
+
+impl SimulatedDevice {
+    fn new(device_name: String, variables: Vec<SimulatedVariable>, start_options: Vec<String>) -> Self {
+        Self {
+            device_name,
+            initial_variables: variables.clone(),
+            variables,
+            start_options,
+            active_option: None,
+            commands_received: 0,
+            booted_at: Instant::now(),
+            clock_offset_ms: 0,
+        }
+    }
+
+    /// Messages the firmware sends after a TCP client connects
+    fn hello_messages(&self) -> Vec<Value> {
+        vec![
+            json!({
+                "deviceName": self.device_name,
+                "firmwareVersion": SIMULATOR_FIRMWARE_VERSION,
+                "uptime": self.booted_at.elapsed().as_secs(),
+                "timestamp": self.wall_clock_ms(),
+                "resetReason": "power_on",
+                "features": ["setTime"]
+            }),
+            json!({ "startOptions": self.start_options }),
+            json!({ "changeableVariables": self.variables }),
+        ]
+    }
+
+    fn wall_clock_ms(&self) -> i64 {
+        chrono::Utc::now().timestamp_millis() + self.clock_offset_ms
+    }
+
+    /// Reboot: restore initial values
+    fn reset(&mut self) {
+        self.variables = self.initial_variables.clone();
+        self.active_option = None;
+        self.booted_at = Instant::now();
+    }
+
+    /// Handle one command as the firmware would.
+    /// Returns the replies and whether the connection is closed afterwards.
+    fn handle_command(&mut self, command: &Value) -> (Vec<Value>, bool) {
+        self.commands_received += 1;
+        let id = command.get("id").cloned();
+        let with_id = |mut reply: Value| {
+            if let (Some(id), Some(obj)) = (&id, reply.as_object_mut()) {
+                obj.insert("id".to_string(), id.clone());
+            }
+            reply
+        };
+
+        if let Some(set) = command.get("setVariable") {
+            let name = set.get("name").and_then(|v| v.as_str()).unwrap_or_default();
+            let value = set.get("value").and_then(|v| v.as_u64()).unwrap_or_default() as u32;
+
+            let Some(var) = self.variables.iter_mut().find(|v| v.name == name) else {
+                return (vec![with_id(json!({"status": "error", "error": format!("unknown variable: {}", name)}))], false);
+            };
+
+            var.value = value.clamp(var.min.unwrap_or(0), var.max.unwrap_or(u32::MAX));
+            let mut update = serde_json::Map::new();
+            update.insert(var.name.clone(), json!(var.value));
+            return (vec![
+                with_id(json!({"status": "ok", "name": var.name, "value": var.value})),
+                Value::Object(update),
+            ], false);
+        }
+
+        if let Some(option) = command.get("startOption").and_then(|v| v.as_str()) {
+            if !self.start_options.iter().any(|o| o == option) {
+                return (vec![with_id(json!({"status": "error", "error": format!("unknown start option: {}", option)}))], false);
+            }
+            self.active_option = Some(option.to_string());
+            return (vec![
+                with_id(json!({"status": "ok", "startOption": option})),
+                json!({"log": {"level": "info", "msg": format!("start option '{}' active", option), "tag": "sim"}}),
+            ], false);
+        }
+
+        if command.get("reset").and_then(|v| v.as_bool()).unwrap_or(false) {
+            self.reset();
+            return (vec![with_id(json!({"status": "ok", "reset": true}))], true);
+        }
+
+        if let Some(epoch_ms) = command.get("setTime").and_then(|v| v.as_i64()) {
+            self.clock_offset_ms = epoch_ms - chrono::Utc::now().timestamp_millis();
+            return (vec![with_id(json!({"status": "ok", "timestamp": self.wall_clock_ms()}))], false);
+        }
+
+        if command.get("getStatus").is_some() {
+            let mut replies = vec![with_id(json!({
+                "deviceName": self.device_name,
+                "firmwareVersion": SIMULATOR_FIRMWARE_VERSION,
+                "uptime": self.booted_at.elapsed().as_secs(),
+                "timestamp": self.wall_clock_ms(),
+                "status": {
+                    "running": self.active_option.is_some(),
+                    "memoryFree": 180_000
+                }
+            }))];
+            replies.extend(self.hello_messages().into_iter().skip(1));
+            return (replies, false);
+        }
+
+        (vec![
+            with_id(json!({"status": "error", "error": "unknown command"})),
+            json!({"log": {"level": "warn", "msg": format!("ignored command {}", command), "tag": "sim"}}),
+        ], false)
+    }
+}
+
+/// Handle of a running simulator
+struct RunningSimulator {
+    info: SimulatorInfo,
+    device: Arc<RwLock<SimulatedDevice>>,
+    shutdown: watch::Sender<bool>,
+}
+
+// ============================================================================
+// SIMULATOR MANAGER
+// ============================================================================
+
+pub struct SimulatorManager {
+    simulators: Arc<RwLock<HashMap<String, RunningSimulator>>>,
+    device_manager: Option<Arc<DeviceManager>>,
+    next_host: AtomicU8,
+}
+
+impl SimulatorManager {
+    /// Create a simulator manager. With a device manager, simulators can be
+    /// registered and connected like real devices.
+    pub fn new(device_manager: Option<Arc<DeviceManager>>) -> Self {
+        Self {
+            simulators: Arc::new(RwLock::new(HashMap::new())),
+            device_manager,
+            next_host: AtomicU8::new(10),
+        }
+    }
+
+    /// Start a simulated device
+    pub async fn spawn(&self, spec: SimulatorSpec) -> DeviceResult<SimulatorInfo> {
+        let device_id = spec.device_id.clone().unwrap_or_else(generate_device_id);
+        if self.simulators.read().await.contains_key(&device_id) {
+            return Err(DeviceError::InvalidCommand(format!("Simulator already running: {}", device_id)));
+        }
+        let device_name = spec.device_name.clone().unwrap_or_else(|| format!("Simulator {}", device_id));
+
+        let (listener, udp_socket) = self.bind_sockets(&spec).await?;
+        let tcp_addr = listener.local_addr()?;
+        let udp_port = udp_socket.local_addr()?.port();
+
+        let device = Arc::new(RwLock::new(SimulatedDevice::new(
+            device_name.clone(),
+            spec.variables.clone(),
+            spec.start_options.clone(),
+        )));
+        let (shutdown_tx, shutdown_rx) = watch::channel(false);
+
+        tokio::spawn(run_tcp_server(
+            listener,
+            device.clone(),
+            spec.failure_modes.clone(),
+            shutdown_rx.clone(),
+        ));
+        if spec.telemetry_interval_ms > 0 {
+            let target = SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), spec.manager_udp_port);
+            tokio::spawn(run_udp_telemetry(
+                udp_socket,
+                target,
+                Duration::from_millis(spec.telemetry_interval_ms),
+                device.clone(),
+                shutdown_rx,
+            ));
+        }
+
+        let registered = spec.register && self.device_manager.is_some();
+        let info = SimulatorInfo {
+            device_id: device_id.clone(),
+            device_name: device_name.clone(),
+            ip_address: tcp_addr.ip(),
+            tcp_port: tcp_addr.port(),
+            udp_port,
+            registered,
+            uptime_seconds: 0,
+            commands_received: 0,
+            active_option: None,
+            variables: spec.variables.clone(),
+            failure_modes: spec.failure_modes.clone(),
+        };
+
+        self.simulators.write().await.insert(device_id.clone(), RunningSimulator {
+            info: info.clone(),
+            device,
+            shutdown: shutdown_tx,
+        });
+        info!("Simulator {} started on {} (UDP port {})", device_id, tcp_addr, udp_port);
+
+        if let (true, Some(device_manager)) = (registered, &self.device_manager) {
+            let mut config = DeviceConfig::new(device_id.clone(), tcp_addr.ip(), tcp_addr.port(), udp_port);
+            config.device_name = device_name;
+            device_manager.add_device(config).await?;
+
+            let device_manager = device_manager.clone();
+            tokio::spawn(async move {
+                if let Err(e) = device_manager.connect_device(&device_id).await {
+                    warn!("Failed to connect to simulator {}: {}", device_id, e);
+                }
+            });
+        }
+
+        Ok(info)
+    }
+
+    /// Stop a simulated device and unregister it from the device manager
+    pub async fn stop(&self, device_id: &str) -> DeviceResult<()> {
+        let simulator = self.simulators.write().await.remove(device_id)
+            .ok_or_else(|| DeviceError::DeviceNotFound(device_id.to_string()))?;
+        let _ = simulator.shutdown.send(true);
+
+        if let (true, Some(device_manager)) = (simulator.info.registered, &self.device_manager) {
+            device_manager.remove_device(device_id).await?;
+        }
+
+        info!("Simulator {} stopped", device_id);
+        Ok(())
+    }
+
+    /// All running simulators with their current firmware state
+    pub async fn list(&self) -> Vec<SimulatorInfo> {
+        let simulators = self.simulators.read().await;
+        let mut result = Vec::with_capacity(simulators.len());
+        for simulator in simulators.values() {
+            let device = simulator.device.read().await;
+            let mut info = simulator.info.clone();
+            info.uptime_seconds = device.booted_at.elapsed().as_secs();
+            info.commands_received = device.commands_received;
+            info.active_option = device.active_option.clone();
+            info.variables = device.variables.clone();
+            result.push(info);
+        }
+        result.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        result
+    }
+
+    /// Bind TCP listener and UDP socket on the requested or next free loopback address.
+    /// Falls back to 127.0.0.1 where only that loopback address is usable (e.g. macOS).
+    async fn bind_sockets(&self, spec: &SimulatorSpec) -> DeviceResult<(TcpListener, UdpSocket)> {
+        if let Some(ip) = spec.bind_ip {
+            return bind_pair(ip, spec.tcp_port).await.map_err(DeviceError::from);
+        }
+
+        for _ in 0..16 {
+            let host = self.next_host.fetch_add(1, Ordering::Relaxed).max(10);
+            let ip = IpAddr::V4(Ipv4Addr::new(127, 0, 0, host));
+            if self.simulators.read().await.values().any(|s| s.info.ip_address == ip) {
+                continue;
+            }
+            if let Ok(pair) = bind_pair(ip, spec.tcp_port).await {
+                return Ok(pair);
+            }
+        }
+
+        bind_pair(IpAddr::V4(Ipv4Addr::LOCALHOST), spec.tcp_port).await.map_err(DeviceError::from)
+    }
+}
+
+async fn bind_pair(ip: IpAddr, tcp_port: u16) -> std::io::Result<(TcpListener, UdpSocket)> {
+    let listener = TcpListener::bind(SocketAddr::new(ip, tcp_port)).await?;
+    let udp_socket = UdpSocket::bind(SocketAddr::new(ip, 0)).await?;
+    Ok((listener, udp_socket))
+}
+
+/// Locally administered MAC address (02-xx-...) in the manager's device ID format
+fn generate_device_id() -> String {
+    let bytes = uuid::Uuid::new_v4().into_bytes();
+    std::iter::once(0x02u8)
+        .chain(bytes[..5].iter().copied())
+        .map(|b| format!("{:02X}", b))
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+// ============================================================================
+// NETWORK TASKS
+// ============================================================================
+
+async fn run_tcp_server(
+    listener: TcpListener,
+    device: Arc<RwLock<SimulatedDevice>>,
+    failure_modes: SimulatorFailureModes,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, peer)) => {
+                    if failure_modes.refuse_connections {
+                        debug!("Simulator refusing connection from {}", peer);
+                        continue;
+                    }
+                    tokio::spawn(handle_tcp_client(stream, device.clone(), failure_modes.clone(), shutdown.clone()));
+                }
+                Err(e) => {
+                    warn!("Simulator accept failed: {}", e);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                }
+            },
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+async fn handle_tcp_client(
+    mut stream: TcpStream,
+    device: Arc<RwLock<SimulatedDevice>>,
+    failure_modes: SimulatorFailureModes,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let hello = device.read().await.hello_messages();
+    if write_messages(&mut stream, &hello).await.is_err() {
+        return;
+    }
+
+    let mut read_buffer = [0u8; 1024];
+    let mut pending = String::new();
+    let mut commands_on_connection = 0u32;
+
+    loop {
+        let bytes_read = tokio::select! {
+            read = stream.read(&mut read_buffer) => match read {
+                Ok(0) | Err(_) => return,
+                Ok(n) => n,
+            },
+            _ = shutdown.changed() => return,
+        };
+        pending.push_str(&String::from_utf8_lossy(&read_buffer[..bytes_read]));
+
+        while let Some(raw) = crate::device_connection::extract_complete_json(&mut pending) {
+            let Ok(command) = serde_json::from_str::<Value>(&raw) else {
+                continue;
+            };
+            commands_on_connection += 1;
+            let (replies, close) = device.write().await.handle_command(&command);
+
+            if !failure_modes.ignore_commands {
+                if failure_modes.reply_delay_ms > 0 {
+                    tokio::time::sleep(Duration::from_millis(failure_modes.reply_delay_ms)).await;
+                }
+                if write_messages(&mut stream, &replies).await.is_err() {
+                    return;
+                }
+            }
+
+            let limit_reached = failure_modes.disconnect_after_commands
+                .is_some_and(|limit| commands_on_connection >= limit);
+            if close || limit_reached {
+                return;
+            }
+        }
+    }
+}
+
+async fn write_messages(stream: &mut TcpStream, messages: &[Value]) -> std::io::Result<()> {
+    for message in messages {
+        stream.write_all(format!("{}\n", message).as_bytes()).await?;
+    }
+    stream.flush().await
+}
+
+/// Periodic `{"name": value}` broadcasts, like the firmware's UDP status messages
+async fn run_udp_telemetry(
+    socket: UdpSocket,
+    target: SocketAddr,
+    interval: Duration,
+    device: Arc<RwLock<SimulatedDevice>>,
+    mut shutdown: watch::Receiver<bool>,
+) {
+    let mut ticker = tokio::time::interval(interval);
+    loop {
+        tokio::select! {
+            _ = ticker.tick() => {
+                let variables = device.read().await.variables.clone();
+                for var in variables {
+                    let message = format!("{{\"{}\": {}}}", var.name, var.value);
+                    if let Err(e) = socket.send_to(message.as_bytes(), target).await {
+                        debug!("Simulator telemetry to {} failed: {}", target, e);
+                    }
+                }
+            }
+            _ = shutdown.changed() => break,
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_device() -> SimulatedDevice {
+        let spec = SimulatorSpec::default();
+        SimulatedDevice::new("Sim".to_string(), spec.variables, spec.start_options)
+    }
+
+    #[test]
+    fn test_set_variable_clamps_and_echoes_id() {
+        let mut device = test_device();
+        let (replies, close) = device.handle_command(&json!({"setVariable": {"name": "brightness", "value": 500}, "id": "req-1"}));
+
+        assert!(!close);
+        assert_eq!(replies[0]["id"], "req-1");
+        assert_eq!(replies[0]["value"], 100);
+        assert_eq!(replies[1], json!({"brightness": 100}));
+    }
+
+    #[test]
+    fn test_reset_restores_initial_values_and_closes() {
+        let mut device = test_device();
+        device.handle_command(&json!({"setVariable": {"name": "speed", "value": 3}}));
+        device.handle_command(&json!({"startOption": "blink"}));
+
+        let (_, close) = device.handle_command(&json!({"reset": true}));
+        assert!(close);
+        assert_eq!(device.variables, SimulatorSpec::default().variables);
+        assert!(device.active_option.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_simulator_speaks_tcp_protocol() {
+        let manager = SimulatorManager::new(None);
+        let spec = SimulatorSpec {
+            bind_ip: Some(IpAddr::V4(Ipv4Addr::LOCALHOST)),
+            telemetry_interval_ms: 0,
+            ..SimulatorSpec::default()
+        };
+        let info = manager.spawn(spec).await.unwrap();
+
+        let mut stream = TcpStream::connect((info.ip_address, info.tcp_port)).await.unwrap();
+        stream.write_all(br#"{"startOption":"rainbow","id":"42"}"#).await.unwrap();
+
+        let mut received = String::new();
+        let mut buffer = [0u8; 1024];
+        while !received.contains(r#""id":"42""#) {
+            let n = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut buffer)).await.unwrap().unwrap();
+            assert!(n > 0);
+            received.push_str(&String::from_utf8_lossy(&buffer[..n]));
+        }
+        assert!(received.contains("changeableVariables"));
+
+        assert_eq!(manager.list().await[0].active_option.as_deref(), Some("rainbow"));
+        manager.stop(&info.device_id).await.unwrap();
+        assert!(manager.list().await.is_empty());
+    }
+}
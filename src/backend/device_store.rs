@@ -1,9 +1,17 @@
 // Device event store for multiuser functionality
 
-use crate::events::{DeviceEvent, EventWithMetadata, ServerMessage};
-use std::collections::HashMap;
+use crate::client_queue::{ClientQueueMetrics, ClientSendError, ClientSender, OutboundMessage};
+use crate::keepalive::{ClientLiveness, LivenessSnapshot};
+use crate::sharded_map::ShardedMap;
+use crate::device_clock::DeviceClocks;
+use crate::device_logs::DeviceLogStore;
+use crate::ws_stats::{DeviceTraffic, DeviceWsStats, TrafficRegistry};
+use crate::events::{
+    DeviceEvent, EventClass, EventPage, EventWithMetadata, PresenceEntry, PresenceStatus, ReplayRequest, ServerMessage,
+};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::Arc;
-use tokio::sync::{RwLock, mpsc};
+use tokio::sync::{broadcast, Notify, RwLock};
 use tracing::{info, warn, error, debug};
 
 // User color generation system
@@ -97,6 +105,26 @@ fn create_color_variation(base_color: &str, variation_factor: usize) -> String {
     }
 }
 
+/// Users without a client message for this long are shown as idle
+pub const PRESENCE_IDLE_AFTER_MS: i64 = 120_000;
+
+/// Page size of a replay request without limit
+pub const DEFAULT_REPLAY_LIMIT: usize = 100;
+/// Largest accepted replay page
+pub const MAX_REPLAY_LIMIT: usize = 1000;
+
+/// Server-side consumers lagging further behind the event feed miss events
+const EVENT_FEED_CAPACITY: usize = 1024;
+
+/// Device event as seen by server-side consumers (webhooks, ...)
+#[derive(Debug, Clone)]
+pub struct FeedEvent {
+    pub device_id: String,
+    pub event: DeviceEvent,
+    /// ms since epoch
+    pub timestamp: i64,
+}
+
 // WebSocket client connection management
 
 // Active WebSocket connection to a canvas
@@ -106,19 +134,29 @@ pub struct ClientConnection {
     pub display_name: String,
     pub client_id: String,
     pub user_color: String,
-    pub sender: mpsc::UnboundedSender<ServerMessage>,
+    pub sender: ClientSender,
     pub subscription_type: crate::events::SubscriptionType,
+    /// Selected event classes (None = all)
+    pub event_classes: Option<HashSet<EventClass>>,
+    /// Presence cursor set by the client on this device channel
+    pub cursor: Option<serde_json::Value>,
+    /// When the client registered for the device
+    pub connected_at: std::time::Instant,
+    /// Task feeding the device channel into the client queue (stops with the last clone)
+    forwarder: Option<Arc<ChannelForwarder>>,
 }
 
 impl ClientConnection {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         user_id: String,
         display_name: String,
         client_id: String,
         _device_id: String,
         user_color: String,
-        sender: mpsc::UnboundedSender<ServerMessage>,
+        sender: ClientSender,
         subscription_type: crate::events::SubscriptionType,
+        event_classes: Option<HashSet<EventClass>>,
     ) -> Self {
         Self {
             user_id,
@@ -127,6 +165,10 @@ impl ClientConnection {
             user_color,
             sender,
             subscription_type,
+            event_classes,
+            cursor: None,
+            connected_at: std::time::Instant::now(),
+            forwarder: None,
         }
     }
     
@@ -135,40 +177,276 @@ impl ClientConnection {
         self.sender.send(message)
             .map_err(|e| format!("Failed to send message to client {}: {}", self.client_id, e))
     }
+
+    /// Start forwarding a device channel into this client's queue, filtered by its subscription
+    fn attach_channel(&mut self, device_id: String, mut channel: broadcast::Receiver<Arc<DeviceBroadcast>>, traffic: Arc<DeviceTraffic>) {
+        let client_id = self.client_id.clone();
+        let sender = self.sender.clone();
+        let subscription_type = self.subscription_type.clone();
+        let event_classes = self.event_classes.clone();
+
+        let task = tokio::spawn(async move {
+            loop {
+                let broadcast = match channel.recv().await {
+                    Ok(broadcast) => broadcast,
+                    Err(broadcast::error::RecvError::Lagged(missed)) => {
+                        warn!("Client {} fell behind device {}: {} broadcast(s) missed", client_id, device_id, missed);
+                        sender.record_lagged(missed);
+                        traffic.record_dropped(missed);
+                        continue;
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                };
+                if !broadcast.accepts(&client_id, &subscription_type, event_classes.as_ref()) {
+                    continue;
+                }
+                match sender.send_shared(broadcast.message.clone()) {
+                    Ok(()) => traffic.record_out(),
+                    Err(ClientSendError::Closed) => break,
+                    Err(e) => {
+                        traffic.record_dropped(1);
+                        debug!("Broadcast for device {} not queued for client {}: {}", device_id, client_id, e);
+                    }
+                }
+            }
+        });
+        self.forwarder = Some(Arc::new(ChannelForwarder(task)));
+    }
+}
+
+/// Per-device channel capacity; a client forwarder further behind misses broadcasts (lagged)
+const DEVICE_CHANNEL_CAPACITY: usize = 1024;
+
+/// One event on a device channel; the message is built once and shared by all recipients
+#[derive(Debug)]
+struct DeviceBroadcast {
+    message: Arc<OutboundMessage>,
+    /// Client that caused the event (not echoed back to it)
+    sender_client_id: String,
+    event_class: Option<EventClass>,
+    connection_status: bool,
+}
+
+impl DeviceBroadcast {
+    fn accepts(&self, client_id: &str, subscription_type: &crate::events::SubscriptionType, event_classes: Option<&HashSet<EventClass>>) -> bool {
+        // Don't send event back to the exact sender client
+        // But do send to other tabs of the same user (different client_id)
+        if client_id == self.sender_client_id {
+            return false;
+        }
+
+        // Light subscriptions only receive connection status events
+        if *subscription_type == crate::events::SubscriptionType::Light && !self.connection_status {
+            return false;
+        }
+
+        // Filter events based on the client's selected event classes
+        match (event_classes, self.event_class) {
+            (Some(classes), Some(class)) => classes.contains(&class),
+            _ => true,
+        }
+    }
+}
+
+/// Aborts the forwarding task when the client registration is dropped
+#[derive(Debug)]
+struct ChannelForwarder(tokio::task::JoinHandle<()>);
+
+impl Drop for ChannelForwarder {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Device a state snapshot belongs to (state_key format: "type:device_id" or "type:device_id:var_name")
+/// Keys without a device part are kept under "" and are never replayed or cleaned up
+fn snapshot_device(state_key: &str) -> &str {
+    state_key.split(':').nth(1).unwrap_or("")
 }
 
 // Thread-safe in-memory store for device events and active connections
 #[derive(Debug)]
 pub struct DeviceEventStore {
     // OPTIMIZED STORAGE: Separate maps for different event types
+    // Maps keyed by device or client ID are sharded, so a write for one device (or client)
+    // doesn't stall broadcasts and replays of the others
+
+    // State snapshot events - only latest value per key, grouped by the device in the key
+    // (device_id -> state_key -> event, see snapshot_device)
+    state_snapshots: ShardedMap<HashMap<String, EventWithMetadata>>,
 
-    // State snapshot events - only latest value per key
-    state_snapshots: RwLock<HashMap<String, EventWithMetadata>>,
+    // Latest value per variable and device (device_id -> name -> value), kept while nobody watches
+    device_variables: ShardedMap<BTreeMap<String, VariableSnapshot>>,
 
     // History events - FIFO queue with configurable limit
-    debug_messages: RwLock<HashMap<String, std::collections::VecDeque<EventWithMetadata>>>,
+    debug_messages: ShardedMap<std::collections::VecDeque<EventWithMetadata>>,
 
     // LEGACY: Old unified storage (will be phased out gradually)
     // Kept for backward compatibility during migration
-    device_events: RwLock<HashMap<String, Vec<EventWithMetadata>>>,
+    device_events: ShardedMap<Vec<EventWithMetadata>>,
 
     // Active client connections per device ID
-    active_connections: RwLock<HashMap<String, Vec<ClientConnection>>>,
+    active_connections: ShardedMap<Vec<ClientConnection>>,
+
+    // Broadcast channel per device ID with at least one client (created/removed under the
+    // device's active_connections lock); every connection forwards it into its client queue
+    device_channels: ShardedMap<broadcast::Sender<Arc<DeviceBroadcast>>>,
+
+    // Subscription set per client ID (one WebSocket may watch many devices)
+    client_subscriptions: ShardedMap<HashSet<String>>,
+
+    // Last client message per client ID (ms) for presence idle/active status
+    client_activity: ShardedMap<i64>,
+
+    // Last broadcast presence status per device (user_id -> status) to detect changes
+    presence_status: ShardedMap<HashMap<String, PresenceStatus>>,
+
+    // Ping/pong liveness per client ID
+    client_liveness: ShardedMap<Arc<ClientLiveness>>,
+
+    // Signalled when a client misses too many pongs (wakes the cleanup task)
+    dead_client_notify: Arc<Notify>,
 
     // Debug message limit per device (configurable)
     max_debug_messages_per_device: RwLock<usize>,
+
+    // In-flight device commands awaiting a reply (correlation ID -> originating client)
+    pending_requests: crate::command_queue::PendingRequests,
+
+    // Every added event, for server-side consumers (see subscribe_events)
+    event_feed: broadcast::Sender<FeedEvent>,
+
+    // Device-reported uptime/timestamp per device, stamped onto stored events
+    clocks: DeviceClocks,
+
+    // WebSocket message counters per device channel (see ws_stats)
+    traffic: TrafficRegistry,
+
+    // Log lines sent by the devices, kept apart from the event history (see device_logs)
+    logs: DeviceLogStore,
 }
 
 impl DeviceEventStore {
     // Create a new empty event store
     pub fn new() -> Self {
         Self {
-            state_snapshots: RwLock::new(HashMap::new()),
-            debug_messages: RwLock::new(HashMap::new()),
-            device_events: RwLock::new(HashMap::new()),
-            active_connections: RwLock::new(HashMap::new()),
+            state_snapshots: ShardedMap::default(),
+            device_variables: ShardedMap::default(),
+            debug_messages: ShardedMap::default(),
+            device_events: ShardedMap::default(),
+            active_connections: ShardedMap::default(),
+            device_channels: ShardedMap::default(),
+            client_subscriptions: ShardedMap::default(),
+            client_activity: ShardedMap::default(),
+            presence_status: ShardedMap::default(),
+            client_liveness: ShardedMap::default(),
+            dead_client_notify: Arc::new(Notify::new()),
             max_debug_messages_per_device: RwLock::new(200), // Default: 200
+            pending_requests: crate::command_queue::PendingRequests::default(),
+            event_feed: broadcast::channel(EVENT_FEED_CAPACITY).0,
+            clocks: DeviceClocks::default(),
+            traffic: TrafficRegistry::default(),
+            logs: DeviceLogStore::default(),
+        }
+    }
+
+    /// Clock reports and drift estimates of all devices
+    pub fn clocks(&self) -> &DeviceClocks {
+        &self.clocks
+    }
+
+    /// WebSocket message counters per device
+    pub fn traffic(&self) -> &TrafficRegistry {
+        &self.traffic
+    }
+
+    /// Device log buffers (GET /api/devices/:id/logs)
+    pub fn logs(&self) -> &DeviceLogStore {
+        &self.logs
+    }
+
+    /// Receiver for all events added from now on (independent of WebSocket subscriptions)
+    pub fn subscribe_events(&self) -> broadcast::Receiver<FeedEvent> {
+        self.event_feed.subscribe()
+    }
+
+    /// In-flight command requests awaiting a device reply
+    pub fn pending_requests(&self) -> &crate::command_queue::PendingRequests {
+        &self.pending_requests
+    }
+
+    /// Start tracking ping/pong liveness of a WebSocket client
+    pub async fn track_client_liveness(&self, client_id: &str, max_missed_pongs: u32) -> Arc<ClientLiveness> {
+        let liveness = Arc::new(ClientLiveness::new(max_missed_pongs, Some(self.dead_client_notify.clone())));
+        self.client_liveness.insert(client_id.to_string(), liveness.clone()).await;
+        liveness
+    }
+
+    /// Stop tracking a client whose connection ended
+    pub async fn untrack_client_liveness(&self, client_id: &str) {
+        self.client_liveness.remove(client_id).await;
+    }
+
+    /// Close every WebSocket of a user (e.g. after account deletion)
+    /// The connection loops end on the dead signal and unregister themselves
+    pub async fn disconnect_user(&self, user_id: &str) -> usize {
+        let mut client_ids = HashSet::new();
+        for shard in self.active_connections.shards() {
+            client_ids.extend(shard.read().await
+                .values()
+                .flatten()
+                .filter(|conn| conn.user_id == user_id)
+                .map(|conn| conn.client_id.clone()));
+        }
+
+        for client_id in &client_ids {
+            if let Some(client) = self.client_liveness.get_cloned(client_id).await {
+                client.mark_dead();
+            }
         }
+        client_ids.len()
+    }
+
+    /// Resolves when a client was marked dead by the keepalive
+    pub async fn dead_client_detected(&self) {
+        self.dead_client_notify.notified().await;
+    }
+
+    /// Send a message once to every connected client of the given users
+    /// Returns the number of clients reached
+    pub async fn send_to_users(&self, user_ids: &HashSet<String>, message: ServerMessage) -> usize {
+        let mut reached = HashSet::new();
+
+        for shard in self.active_connections.shards() {
+            let connections = shard.read().await;
+            for conn in connections.values().flatten() {
+                if !user_ids.contains(&conn.user_id) || reached.contains(&conn.client_id) {
+                    continue;
+                }
+                match conn.sender.send(message.clone()) {
+                    Ok(()) => {
+                        reached.insert(conn.client_id.clone());
+                    }
+                    Err(e) => warn!("Failed to notify client {}: {}", conn.client_id, e),
+                }
+            }
+        }
+
+        reached.len()
+    }
+
+    /// Send a message to one specific client registered for a device
+    pub async fn send_to_client(&self, device_id: &str, client_id: &str, message: ServerMessage) -> bool {
+        let connections = self.active_connections.read(device_id).await;
+        let Some(connection) = connections
+            .get(device_id)
+            .and_then(|conns| conns.iter().find(|conn| conn.client_id == client_id))
+        else {
+            debug!("Client {} is no longer registered for device {} - dropping message", client_id, device_id);
+            return false;
+        };
+
+        connection.sender.send(message).is_ok()
     }
 
     /// Update the maximum number of debug messages per device
@@ -192,6 +470,37 @@ impl DeviceEventStore {
         event: DeviceEvent,
         user_id: String,
         client_id: String
+    ) -> Result<(), String> {
+        self.store_event(device_id, event, user_id, client_id, true).await
+    }
+
+    /// add_event for an event that is already on the event feed (coalesced variable samples)
+    pub async fn add_event_without_feed(
+        &self,
+        device_id: String,
+        event: DeviceEvent,
+        user_id: String,
+        client_id: String
+    ) -> Result<(), String> {
+        self.store_event(device_id, event, user_id, client_id, false).await
+    }
+
+    /// Publish an event on the server-side feed only (not stored, not sent to clients)
+    pub fn publish_feed(&self, device_id: &str, event: DeviceEvent) {
+        let _ = self.event_feed.send(FeedEvent {
+            device_id: device_id.to_string(),
+            event,
+            timestamp: chrono::Utc::now().timestamp_millis(),
+        });
+    }
+
+    async fn store_event(
+        &self,
+        device_id: String,
+        event: DeviceEvent,
+        user_id: String,
+        client_id: String,
+        feed: bool,
     ) -> Result<(), String> {
         // Validate event before storing
         event.validate().map_err(|e| {
@@ -203,14 +512,36 @@ impl DeviceEventStore {
         let persistence = event.persistence_strategy();
 
         // Create event with metadata
+        let timestamp = chrono::Utc::now().timestamp_millis();
         let event_with_metadata = EventWithMetadata {
             event: event.clone(),
             id: uuid::Uuid::new_v4().to_string(),
-            timestamp: chrono::Utc::now().timestamp_millis(),
+            timestamp,
             user_id: user_id.clone(),
             is_replay: None,
+            device_time: self.clocks.stamp(&device_id, timestamp),
         };
 
+        // No receivers is fine (no server-side consumer running)
+        if feed {
+            let _ = self.event_feed.send(FeedEvent {
+                device_id: device_id.clone(),
+                event: event.clone(),
+                timestamp: event_with_metadata.timestamp,
+            });
+        }
+
+        // Keep the current variable value for GET /api/devices/:id/variables
+        if let DeviceEvent::DeviceVariableUpdate { device_id: var_device_id, variable_name, variable_value, min, max } = &event {
+            let mut variables = self.device_variables.write(var_device_id).await;
+            variables.entry(var_device_id.clone()).or_default().insert(variable_name.clone(), VariableSnapshot {
+                value: variable_value.clone(),
+                min: *min,
+                max: *max,
+                updated_at: event_with_metadata.timestamp,
+            });
+        }
+
         // Route event to appropriate storage based on persistence strategy
         use crate::events::EventPersistence;
         match persistence {
@@ -222,14 +553,15 @@ impl DeviceEventStore {
             EventPersistence::StateSnapshot => {
                 // Store only latest value for this state key
                 if let Some(state_key) = event.state_key() {
-                    let mut snapshots = self.state_snapshots.write().await;
-                    snapshots.insert(state_key.clone(), event_with_metadata.clone());
+                    let owner = snapshot_device(&state_key);
+                    let mut snapshots = self.state_snapshots.write(owner).await;
+                    snapshots.entry(owner.to_string()).or_default().insert(state_key.clone(), event_with_metadata.clone());
                     debug!("State snapshot updated: {} -> {}", state_key, event_with_metadata.id);
                 } else {
                     // Event has no state_key (e.g., legacy events without device_id)
                     // Fall back to legacy storage to avoid losing the event
                     warn!("StateSnapshot event has no state_key, storing in legacy: {:?}", event);
-                    let mut events = self.device_events.write().await;
+                    let mut events = self.device_events.write(&device_id).await;
                     let device_events = events.entry(device_id.clone()).or_insert_with(Vec::new);
                     const MAX_LEGACY_EVENTS: usize = 500;
                     if device_events.len() >= MAX_LEGACY_EVENTS {
@@ -244,7 +576,7 @@ impl DeviceEventStore {
                 let max_debug = *self.max_debug_messages_per_device.read().await;
                 let limit = if max_debug > 0 { max_debug } else { default_limit };
 
-                let mut debug_msgs = self.debug_messages.write().await;
+                let mut debug_msgs = self.debug_messages.write(&device_id).await;
                 let queue = debug_msgs.entry(device_id.clone()).or_insert_with(std::collections::VecDeque::new);
 
                 // Enforce limit
@@ -260,7 +592,7 @@ impl DeviceEventStore {
                 // TODO: Store in database (Phase 4)
                 // For now, log and store in legacy storage
                 info!("Permanent event received (DB storage not yet implemented): {:?}", event);
-                let mut events = self.device_events.write().await;
+                let mut events = self.device_events.write(&device_id).await;
                 let device_events = events.entry(device_id.clone()).or_insert_with(Vec::new);
                 device_events.push(event_with_metadata.clone());
             }
@@ -270,7 +602,7 @@ impl DeviceEventStore {
         // This can be removed once all code uses the new get_replay_events() method
         // NOTE: We don't store Ephemeral events in legacy storage (waste of memory)
         if !matches!(persistence, EventPersistence::Ephemeral) {
-            let mut events = self.device_events.write().await;
+            let mut events = self.device_events.write(&device_id).await;
             let device_events = events.entry(device_id.clone()).or_insert_with(Vec::new);
 
             // Apply simple limit to prevent unbounded growth during migration period
@@ -301,18 +633,10 @@ impl DeviceEventStore {
         let mut replay_events = Vec::new();
 
         // 1. Get all state snapshots for this device
-        {
-            let snapshots = self.state_snapshots.read().await;
-            for (key, event_meta) in snapshots.iter() {
-                // SAFE: Extract device_id from state_key (format: "type:device_id" or "type:device_id:var_name")
-                // We must use exact matching, not contains(), to avoid substring false-positives
-                let parts: Vec<&str> = key.split(':').collect();
-                if parts.len() >= 2 {
-                    let key_device_id = parts[1];
-                    if key_device_id == device_id {
-                        replay_events.push(event_meta.event.clone());
-                    }
-                }
+        if !device_id.is_empty() {
+            let snapshots = self.state_snapshots.read(device_id).await;
+            if let Some(device_snapshots) = snapshots.get(device_id) {
+                replay_events.extend(device_snapshots.values().map(|event_meta| event_meta.event.clone()));
             }
         }
 
@@ -320,7 +644,7 @@ impl DeviceEventStore {
         // UserJoined/UserLeft are Ephemeral (not stored), but new clients need to know
         // who is currently connected, so we generate synthetic UserJoined events
         {
-            let connections = self.active_connections.read().await;
+            let connections = self.active_connections.read(device_id).await;
             if let Some(device_connections) = connections.get(device_id) {
                 // Collect unique users (de-duplicate by user_id)
                 let mut seen_users = std::collections::HashSet::new();
@@ -340,7 +664,7 @@ impl DeviceEventStore {
 
         // 3. Optionally include debug messages (bounded history)
         if include_debug {
-            let debug_msgs = self.debug_messages.read().await;
+            let debug_msgs = self.debug_messages.read(device_id).await;
             if let Some(queue) = debug_msgs.get(device_id) {
                 // Add debug messages in chronological order
                 for event_meta in queue.iter() {
@@ -355,12 +679,65 @@ impl DeviceEventStore {
         replay_events
     }
 
+    /// Paged replay: stored events of a device after `since`/`cursor`, oldest first
+    /// Covers state snapshots and (optionally) the bounded debug history
+    pub async fn get_event_page(&self, device_id: &str, request: &ReplayRequest, include_debug: bool) -> Result<EventPage, String> {
+        let limit = request.limit.unwrap_or(DEFAULT_REPLAY_LIMIT);
+        if limit == 0 || limit > MAX_REPLAY_LIMIT {
+            return Err(format!("limit must be between 1 and {}", MAX_REPLAY_LIMIT));
+        }
+        let after = match &request.cursor {
+            Some(cursor) => Some(crate::events::parse_replay_cursor(cursor)
+                .ok_or_else(|| format!("Invalid replay cursor: {}", cursor))?),
+            None => None,
+        };
+
+        let mut events = self.collect_stored_events(device_id, include_debug).await;
+        events.retain(|e| {
+            request.since.is_none_or(|since| e.timestamp > since)
+                && after.as_ref().is_none_or(|(ts, id)| (e.timestamp, &e.id) > (*ts, id))
+        });
+
+        let has_more = events.len() > limit;
+        events.truncate(limit);
+        let next_cursor = events.last().map(|e| e.cursor());
+
+        Ok(EventPage { events, next_cursor, has_more })
+    }
+
+    /// Stored history of a device within [from, to] (ms, inclusive), oldest first
+    /// Used by the CSV/NDJSON export; includes state snapshots and the debug history
+    pub async fn get_event_history(&self, device_id: &str, from: Option<i64>, to: Option<i64>) -> Vec<EventWithMetadata> {
+        let mut events = self.collect_stored_events(device_id, true).await;
+        events.retain(|e| from.is_none_or(|from| e.timestamp >= from) && to.is_none_or(|to| e.timestamp <= to));
+        events
+    }
+
+    /// State snapshots (and optionally debug messages) of a device, sorted by (timestamp, id)
+    async fn collect_stored_events(&self, device_id: &str, include_debug: bool) -> Vec<EventWithMetadata> {
+        let mut events: Vec<EventWithMetadata> = Vec::new();
+        if !device_id.is_empty() {
+            let snapshots = self.state_snapshots.read(device_id).await;
+            if let Some(device_snapshots) = snapshots.get(device_id) {
+                events.extend(device_snapshots.values().cloned());
+            }
+        }
+        if include_debug {
+            let debug_msgs = self.debug_messages.read(device_id).await;
+            if let Some(queue) = debug_msgs.get(device_id) {
+                events.extend(queue.iter().cloned());
+            }
+        }
+        events.sort_by(|a, b| (a.timestamp, &a.id).cmp(&(b.timestamp, &b.id)));
+        events
+    }
+
     // LEGACY: Get all events for a device (backward compatibility)
     /// WARNING: This returns ALL events including hundreds of debug messages
     /// Use get_replay_events() instead for better performance
     #[deprecated(note = "Use get_replay_events() for optimized event replay")]
     pub async fn get_device_events(&self, device_id: &str) -> Vec<DeviceEvent> {
-        let events = self.device_events.read().await;
+        let events = self.device_events.read(device_id).await;
 
         match events.get(device_id) {
             Some(device_events) => {
@@ -384,13 +761,13 @@ impl DeviceEventStore {
     
     // Get event count for a device (for debugging/monitoring)
     pub async fn get_event_count(&self, device_id: &str) -> usize {
-        let events = self.device_events.read().await;
+        let events = self.device_events.read(device_id).await;
         events.get(device_id).map(|v| v.len()).unwrap_or(0)
     }
     
     // Clear all events for a device (for testing or device reset)
     pub async fn clear_device_events(&self, device_id: &str) -> Result<(), String> {
-        let mut events = self.device_events.write().await;
+        let mut events = self.device_events.write(device_id).await;
         if let Some(device_events) = events.get_mut(device_id) {
             device_events.clear();
             info!("Cleared all events for device: {}", device_id);
@@ -403,18 +780,20 @@ impl DeviceEventStore {
     // ========================================================================
     
     /// Register a new client connection to a device
+    #[allow(clippy::too_many_arguments)]
     pub async fn register_client(
         &self,
         device_id: String,
         user_id: String,
         display_name: String,
         client_id: String,
-        sender: mpsc::UnboundedSender<ServerMessage>,
+        sender: ClientSender,
         subscription_type: crate::events::SubscriptionType,
+        event_classes: Option<HashSet<EventClass>>,
     ) -> Result<Vec<DeviceEvent>, String> {
         // ATOMIC OPERATION: Generate color and add connection in single critical section
         let (user_color, is_reconnection) = {
-            let mut connections = self.active_connections.write().await;
+            let mut connections = self.active_connections.write(&device_id).await;
             let device_connections = connections.entry(device_id.clone()).or_insert_with(Vec::new);
             
             // Check if this user already has a color (reconnection)
@@ -463,7 +842,7 @@ impl DeviceEventStore {
                   user_color, user_id, device_id);
             
             // Create and add new connection atomically
-            let connection = ClientConnection::new(
+            let mut connection = ClientConnection::new(
                 user_id.clone(),
                 display_name.clone(),
                 client_id.clone(),
@@ -471,13 +850,27 @@ impl DeviceEventStore {
                 user_color.clone(),
                 sender,
                 subscription_type.clone(),
+                event_classes.clone(),
             );
             info!("SUBSCRIPTION REGISTER: Adding connection for client_id {} on device {} with subscription: {:?}",
                   client_id, device_id, subscription_type);
+            let channel = self.device_channels.write(&device_id).await
+                .entry(device_id.clone())
+                .or_insert_with(|| broadcast::channel(DEVICE_CHANNEL_CAPACITY).0)
+                .subscribe();
+            connection.attach_channel(device_id.clone(), channel, self.traffic.for_device(&device_id));
             device_connections.push(connection);
             
             (user_color, is_reconnection)
         };
+
+        self.client_subscriptions.write(&client_id).await
+            .entry(client_id.clone())
+            .or_default()
+            .insert(device_id.clone());
+        self.client_activity.write(&client_id).await
+            .entry(client_id.clone())
+            .or_insert_with(|| chrono::Utc::now().timestamp_millis());
         
         info!("Client {} registered for device {} (user: {})", client_id, device_id, user_id);
         
@@ -496,10 +889,13 @@ impl DeviceEventStore {
             }
         }
         
+        self.broadcast_presence(&device_id).await;
+
         // Return optimized replay events (only latest state + optional debug messages)
         // Full subscriptions get debug messages, Light subscriptions don't
         let include_debug = subscription_type == crate::events::SubscriptionType::Full;
-        let events = self.get_replay_events(&device_id, include_debug).await;
+        let mut events = self.get_replay_events(&device_id, include_debug).await;
+        events.retain(|event| event.matches_classes(event_classes.as_ref()));
 
         debug!("Sending {} optimized replay events to newly registered client {} (debug: {})",
                events.len(), client_id, include_debug);
@@ -510,10 +906,20 @@ impl DeviceEventStore {
     /// Unregister a client from a device
     pub async fn unregister_client(&self, device_id: &str, client_id: &str) -> Result<(), String> {
         let mut connection_to_remove: Option<ClientConnection> = None;
+
+        {
+            let mut subscriptions = self.client_subscriptions.write(client_id).await;
+            if let Some(devices) = subscriptions.get_mut(client_id) {
+                devices.remove(device_id);
+                if devices.is_empty() {
+                    subscriptions.remove(client_id);
+                }
+            }
+        }
         
         // First, find and remove the connection while keeping track of user info
         {
-            let mut connections = self.active_connections.write().await;
+            let mut connections = self.active_connections.write(device_id).await;
             
             if let Some(device_connections) = connections.get_mut(device_id) {
                 let initial_count = device_connections.len();
@@ -535,6 +941,7 @@ impl DeviceEventStore {
                 // Clean up empty device entries
                 if device_connections.is_empty() {
                     connections.remove(device_id);
+                    self.device_channels.remove(device_id).await;
                     debug!("Removed empty device connection list for: {}", device_id);
                 }
             }
@@ -544,7 +951,7 @@ impl DeviceEventStore {
         if let Some(removed_connection) = connection_to_remove {
             // Check if this user still has other connections to this device
             let user_still_connected = {
-                let connections = self.active_connections.read().await;
+                let connections = self.active_connections.read(device_id).await;
                 if let Some(device_connections) = connections.get(device_id) {
                     device_connections.iter().any(|conn| conn.user_id == removed_connection.user_id)
                 } else {
@@ -571,37 +978,140 @@ impl DeviceEventStore {
             }
         }
         
+        if !self.client_subscriptions.contains_key(client_id).await {
+            self.client_activity.remove(client_id).await;
+        }
+        self.broadcast_presence(device_id).await;
+
         // device devices don't have shape selections to clean up
         debug!("Client {} disconnected from device {}", client_id, device_id);
         
         Ok(())
     }
     
+    /// Devices a client is currently subscribed to (sorted)
+    pub async fn get_client_subscriptions(&self, client_id: &str) -> Vec<String> {
+        let subscriptions = self.client_subscriptions.read(client_id).await;
+        let mut devices: Vec<String> = subscriptions.get(client_id)
+            .map(|devices| devices.iter().cloned().collect())
+            .unwrap_or_default();
+        devices.sort();
+        devices
+    }
+
+    /// Subscription type and event class selection of a client's registration for a device
+    pub async fn get_client_subscription(
+        &self,
+        client_id: &str,
+        device_id: &str,
+    ) -> Option<(crate::events::SubscriptionType, Option<HashSet<EventClass>>)> {
+        let connections = self.active_connections.read(device_id).await;
+        connections.get(device_id)?
+            .iter()
+            .find(|conn| conn.client_id == client_id)
+            .map(|conn| (conn.subscription_type.clone(), conn.event_classes.clone()))
+    }
+
+    /// Check whether a client is subscribed to a device
+    pub async fn is_client_subscribed(&self, client_id: &str, device_id: &str) -> bool {
+        self.client_subscriptions.read(client_id).await
+            .get(client_id)
+            .is_some_and(|devices| devices.contains(device_id))
+    }
+
     /// Get count of active connections for a device
     pub async fn get_connection_count(&self, device_id: &str) -> usize {
-        let connections = self.active_connections.read().await;
+        let connections = self.active_connections.read(device_id).await;
         connections.get(device_id).map(|v| v.len()).unwrap_or(0)
     }
 
     /// Count only Full-subscription clients for a device (used to decide TCP disconnect)
     pub async fn get_full_subscription_count(&self, device_id: &str) -> usize {
-        let connections = self.active_connections.read().await;
+        let connections = self.active_connections.read(device_id).await;
         connections.get(device_id)
             .map(|v| v.iter().filter(|c| c.subscription_type == crate::events::SubscriptionType::Full).count())
             .unwrap_or(0)
     }
     
+    /// Current variable values of a device (name -> latest value)
+    pub async fn get_device_variables(&self, device_id: &str) -> BTreeMap<String, VariableSnapshot> {
+        self.device_variables.get_cloned(device_id).await.unwrap_or_default()
+    }
+
+    /// Drop the variable cache of a device (e.g. after it was deleted)
+    pub async fn clear_device_variables(&self, device_id: &str) -> bool {
+        self.device_variables.remove(device_id).await.is_some()
+    }
+
+    /// Outgoing queue metrics of every connected client
+    pub async fn get_client_queue_stats(&self) -> Vec<ClientQueueStats> {
+        let mut clients: HashMap<String, ClientConnection> = HashMap::new();
+        for shard in self.active_connections.shards() {
+            for conn in shard.read().await.values().flatten() {
+                clients.entry(conn.client_id.clone()).or_insert_with(|| conn.clone());
+            }
+        }
+
+        let mut stats = Vec::with_capacity(clients.len());
+        for (client_id, conn) in clients {
+            let devices = self.client_subscriptions.read(&client_id).await.get(&client_id).map(|d| d.len()).unwrap_or(0);
+            let liveness = self.client_liveness.get_cloned(&client_id).await.map(|l| l.snapshot());
+            stats.push(ClientQueueStats {
+                client_id,
+                user_id: conn.user_id,
+                devices,
+                queue: conn.sender.metrics(),
+                liveness,
+            });
+        }
+        stats.sort_by(|a, b| a.client_id.cmp(&b.client_id));
+        stats
+    }
+
+    /// Clients, traffic, connection age and buffered events of every device with clients
+    pub async fn get_device_ws_stats(&self) -> Vec<DeviceWsStats> {
+        let mut stats = Vec::new();
+        for shard in self.active_connections.shards() {
+            for (device_id, connections) in shard.read().await.iter() {
+                let users: HashSet<&str> = connections.iter().map(|conn| conn.user_id.as_str()).collect();
+                stats.push(DeviceWsStats {
+                    device_id: device_id.clone(),
+                    clients: connections.len(),
+                    users: users.len(),
+                    traffic: self.traffic.snapshot(device_id),
+                    oldest_connection_secs: connections.iter().map(|conn| conn.connected_at.elapsed().as_secs()).max(),
+                    buffered_events: 0,
+                    buffered_bytes: 0,
+                });
+            }
+        }
+
+        for device in &mut stats {
+            let snapshots = self.state_snapshots.read(&device.device_id).await.get(&device.device_id).map_or(0, |s| s.len());
+            let debug_messages = self.debug_messages.read(&device.device_id).await.get(&device.device_id).map_or(0, |q| q.len());
+            let legacy_events = self.device_events.read(&device.device_id).await.get(&device.device_id).map_or(0, |e| e.len());
+            device.buffered_events = snapshots + debug_messages + legacy_events;
+            device.buffered_bytes = snapshots * crate::ws_stats::STATE_SNAPSHOT_BYTES
+                + debug_messages * crate::ws_stats::DEBUG_MESSAGE_BYTES
+                + legacy_events * crate::ws_stats::LEGACY_EVENT_BYTES;
+        }
+        stats.sort_by(|a, b| a.device_id.cmp(&b.device_id));
+        stats
+    }
+
     /// Get all active devices with their connection counts
     pub async fn get_active_devices(&self) -> HashMap<String, usize> {
-        let connections = self.active_connections.read().await;
-        connections.iter()
-            .map(|(device_id, connections)| (device_id.clone(), connections.len()))
-            .collect()
+        let mut devices = HashMap::new();
+        for shard in self.active_connections.shards() {
+            devices.extend(shard.read().await.iter()
+                .map(|(device_id, connections)| (device_id.clone(), connections.len())));
+        }
+        devices
     }
     
     /// Get all users currently connected to a device
     pub async fn get_device_users(&self, device_id: &str) -> Vec<DeviceUser> {
-        let connections = self.active_connections.read().await;
+        let connections = self.active_connections.read(device_id).await;
         
         if let Some(device_connections) = connections.get(device_id) {
             // Group connections by user_id to count multiple connections and get color
@@ -628,7 +1138,7 @@ impl DeviceEventStore {
     
     /// Get all users currently connected to a device with database lookup for display names
     pub async fn get_device_users_with_db(&self, device_id: &str, db: &crate::database::DatabaseManager) -> Vec<DeviceUser> {
-        let connections = self.active_connections.read().await;
+        let connections = self.active_connections.read(device_id).await;
         
         if let Some(device_connections) = connections.get(device_id) {
             // Group connections by user_id to count multiple connections and get color
@@ -663,63 +1173,149 @@ impl DeviceEventStore {
         }
     }
     
+    // ========================================================================
+    // PRESENCE
+    // ========================================================================
+
+    /// Record a message from a client; an idle client becoming active updates presence
+    pub async fn touch_client(&self, client_id: &str) {
+        let now = chrono::Utc::now().timestamp_millis();
+        let previous = self.client_activity.insert(client_id.to_string(), now).await;
+
+        if previous.is_some_and(|last| now - last > PRESENCE_IDLE_AFTER_MS) {
+            for device_id in self.get_client_subscriptions(client_id).await {
+                self.broadcast_presence(&device_id).await;
+            }
+        }
+    }
+
+    /// Set the presence cursor of a client on a device channel
+    pub async fn set_client_cursor(&self, device_id: &str, client_id: &str, cursor: Option<serde_json::Value>) -> Result<(), String> {
+        {
+            let mut connections = self.active_connections.write(device_id).await;
+            let connection = connections.get_mut(device_id)
+                .and_then(|conns| conns.iter_mut().find(|conn| conn.client_id == client_id))
+                .ok_or_else(|| format!("Client {} is not registered for device {}", client_id, device_id))?;
+            connection.cursor = cursor;
+        }
+        self.broadcast_presence(device_id).await;
+        Ok(())
+    }
+
+    /// Users on a device channel with connection count, activity and cursor
+    pub async fn get_device_presence(&self, device_id: &str) -> Vec<PresenceEntry> {
+        let connections = self.active_connections.get_cloned(device_id).await.unwrap_or_default();
+        let now = chrono::Utc::now().timestamp_millis();
+
+        let mut users: Vec<PresenceEntry> = Vec::new();
+        for conn in &connections {
+            let last_active = self.client_activity.get_cloned(&conn.client_id).await.unwrap_or(0);
+            match users.iter_mut().find(|entry| entry.user_id == conn.user_id) {
+                Some(entry) => {
+                    entry.connection_count += 1;
+                    // Most recently active tab wins (also for the cursor)
+                    if last_active > entry.last_active {
+                        entry.last_active = last_active;
+                        if conn.cursor.is_some() {
+                            entry.cursor = conn.cursor.clone();
+                        }
+                    }
+                }
+                None => users.push(PresenceEntry {
+                    user_id: conn.user_id.clone(),
+                    display_name: conn.display_name.clone(),
+                    user_color: conn.user_color.clone(),
+                    connection_count: 1,
+                    status: PresenceStatus::Active,
+                    last_active,
+                    cursor: conn.cursor.clone(),
+                }),
+            }
+        }
+
+        for entry in &mut users {
+            if now - entry.last_active > PRESENCE_IDLE_AFTER_MS {
+                entry.status = PresenceStatus::Idle;
+            }
+        }
+        users.sort_by(|a, b| a.display_name.cmp(&b.display_name).then_with(|| a.user_id.cmp(&b.user_id)));
+        users
+    }
+
+    /// Send the current presence of a device channel to all its clients
+    async fn broadcast_presence(&self, device_id: &str) {
+        let users = self.get_device_presence(device_id).await;
+
+        {
+            let mut presence_status = self.presence_status.write(device_id).await;
+            if users.is_empty() {
+                presence_status.remove(device_id);
+            } else {
+                presence_status.insert(
+                    device_id.to_string(),
+                    users.iter().map(|u| (u.user_id.clone(), u.status)).collect(),
+                );
+            }
+        }
+
+        if !users.is_empty() {
+            let event = DeviceEvent::device_presence(device_id.to_string(), users);
+            if let Err(e) = self.broadcast_event(device_id, event, "").await {
+                error!("Failed to broadcast presence for device {}: {}", device_id, e);
+            }
+        }
+    }
+
+    /// Re-evaluate idle/active status and broadcast presence of devices where it changed
+    /// Returns the number of devices with changed presence
+    pub async fn refresh_presence(&self) -> usize {
+        let device_ids = self.active_connections.keys().await;
+        let mut changed = 0;
+
+        for device_id in device_ids {
+            let current: HashMap<String, PresenceStatus> = self.get_device_presence(&device_id).await
+                .into_iter()
+                .map(|u| (u.user_id, u.status))
+                .collect();
+            let previous = self.presence_status.get_cloned(&device_id).await.unwrap_or_default();
+            if current != previous {
+                self.broadcast_presence(&device_id).await;
+                changed += 1;
+            }
+        }
+
+        changed
+    }
+
     // ========================================================================
     // EVENT BROADCASTING
     // ========================================================================
     
     /// Broadcast an event to all connected clients on a device (except sender)
-    /// Multi-tab support: Sends to all clients including other tabs of same user
-    /// Subscription filtering: Light subscriptions only receive connection status events
+    /// The message is built once and published on the device channel; each client's forwarder
+    /// applies its filters (Light subscriptions only receive connection status events, event
+    /// classes) and queues the shared message. Multi-tab: other tabs of the sender get it too.
     pub async fn broadcast_event(
         &self,
         device_id: &str,
         event: DeviceEvent,
         sender_client_id: &str
     ) -> Result<(), String> {
-        let connections = self.active_connections.read().await;
-
-        if let Some(device_connections) = connections.get(device_id) {
-            // Check if this event should be sent to light subscriptions
-            let is_connection_status = matches!(event, DeviceEvent::DeviceConnectionStatus { .. });
-
-            let message = ServerMessage::device_events(
-                device_id.to_string(),
-                vec![event]
-            );
-
-            let mut successful_sends = 0;
-            let mut failed_sends = 0;
-
-            for connection in device_connections {
-                // Don't send event back to the exact sender client
-                // But do send to other tabs of the same user (different client_id)
-                if connection.client_id == sender_client_id {
-                    continue;
-                }
-
-                // Filter events based on subscription type
-                if connection.subscription_type == crate::events::SubscriptionType::Light && !is_connection_status {
-                    debug!("SUBSCRIPTION FILTER: Skipping non-connection event for Light subscription client {} on device {}",
-                           connection.client_id, device_id);
-                    continue;
-                }
+        let Some(channel) = self.device_channels.get_cloned(device_id).await else {
+            return Ok(());
+        };
 
-                match connection.send_message(message.clone()) {
-                    Ok(()) => successful_sends += 1,
-                    Err(e) => {
-                        error!("Failed to broadcast to client {}: {}", connection.client_id, e);
-                        failed_sends += 1;
-                    }
-                }
-            }
+        let broadcast = DeviceBroadcast {
+            connection_status: matches!(event, DeviceEvent::DeviceConnectionStatus { .. }),
+            event_class: event.event_class(),
+            sender_client_id: sender_client_id.to_string(),
+            message: Arc::new(OutboundMessage::new(ServerMessage::device_events(device_id.to_string(), vec![event]))),
+        };
 
-            if successful_sends == 0 && failed_sends == 0 {
-                warn!("NO clients received the event for device {} - frontend may show 'Disconnected'!", device_id);
-            }
-            
-            // TODO: Clean up failed connections in a background task
+        if channel.send(Arc::new(broadcast)).is_err() {
+            warn!("NO clients received the event for device {} - frontend may show 'Disconnected'!", device_id);
         }
-        
+
         Ok(())
     }
     
@@ -730,18 +1326,25 @@ impl DeviceEventStore {
     
     /// Remove stale connections (connections where the sender channel is closed)
     pub async fn cleanup_stale_connections(&self) -> usize {
-        let mut connections = self.active_connections.write().await;
-        let mut removed_count = 0;
+        let mut dead_clients: HashSet<String> = HashSet::new();
+        for shard in self.client_liveness.shards() {
+            dead_clients.extend(shard.read().await.iter()
+                .filter(|(_, liveness)| liveness.is_dead())
+                .map(|(client_id, _)| client_id.clone()));
+        }
 
-        // Check each device
-        let device_ids: Vec<String> = connections.keys().cloned().collect();
+        let mut removed_count = 0;
+        let mut live_clients: HashSet<String> = HashSet::new();
 
-        for device_id in device_ids {
-            if let Some(device_connections) = connections.get_mut(&device_id) {
+        // One shard at a time, so broadcasts to devices in other shards keep flowing
+        for shard in self.active_connections.shards() {
+            let mut connections = shard.write().await;
+            let mut emptied = Vec::new();
+            connections.retain(|device_id, device_connections| {
                 let initial_count = device_connections.len();
 
-                // Keep only connections with open channels
-                device_connections.retain(|conn| !conn.sender.is_closed());
+                // Keep only connections with open channels that still answer pings
+                device_connections.retain(|conn| !conn.sender.is_closed() && !dead_clients.contains(&conn.client_id));
 
                 let removed_for_device = initial_count - device_connections.len();
                 removed_count += removed_for_device;
@@ -750,13 +1353,24 @@ impl DeviceEventStore {
                     debug!("Removed {} stale connections from device {}", removed_for_device, device_id);
                 }
 
+                live_clients.extend(device_connections.iter().map(|conn| conn.client_id.clone()));
+
                 // Remove empty device entries
                 if device_connections.is_empty() {
-                    connections.remove(&device_id);
+                    emptied.push(device_id.clone());
                 }
+                !device_connections.is_empty()
+            });
+            for device_id in emptied {
+                self.device_channels.remove(&device_id).await;
             }
         }
 
+        // Drop subscription sets of clients that have no connection left
+        self.client_subscriptions.retain(|client_id, _| live_clients.contains(client_id)).await;
+        self.client_activity.retain(|client_id, _| live_clients.contains(client_id)).await;
+        self.client_liveness.retain(|client_id, _| !dead_clients.contains(client_id)).await;
+
         if removed_count > 0 {
             info!("Cleaned up {} stale connections", removed_count);
         }
@@ -768,125 +1382,105 @@ impl DeviceEventStore {
     /// Removes events from devices that have no active connections
     /// Returns the number of devices cleaned up
     pub async fn cleanup_disconnected_devices(&self) -> usize {
-        // IMPORTANT: We must hold the connections lock during the entire cleanup
-        // to prevent TOCTOU race condition where a device connects while we're cleaning up
-        let connections = self.active_connections.read().await;
-        let active_device_ids: std::collections::HashSet<String> = connections.keys().cloned().collect();
-        // NOTE: We keep the read lock held until the end to ensure consistency
+        // Every device that has stored events of any kind
+        let mut device_ids: HashSet<String> = HashSet::new();
+        device_ids.extend(self.state_snapshots.keys().await.into_iter().filter(|device_id| !device_id.is_empty()));
+        device_ids.extend(self.debug_messages.keys().await);
+        device_ids.extend(self.device_events.keys().await);
 
         let mut cleanup_count = 0;
 
-        // Cleanup state snapshots for disconnected devices
-        {
-            let mut snapshots = self.state_snapshots.write().await;
-            let keys_to_remove: Vec<String> = snapshots.keys()
-                .filter(|key| {
-                    // Extract device_id from state_key (format: "type:device_id" or "type:device_id:var_name")
-                    let parts: Vec<&str> = key.split(':').collect();
-                    if parts.len() >= 2 {
-                        let device_id = parts[1];
-                        !active_device_ids.contains(device_id)
-                    } else {
-                        false
-                    }
-                })
-                .cloned()
-                .collect();
-
-            for key in keys_to_remove {
-                snapshots.remove(&key);
-                cleanup_count += 1;
+        for device_id in device_ids {
+            // IMPORTANT: We must hold the device's connection shard lock while cleaning up to
+            // prevent a TOCTOU race where a client registers for the device in between.
+            // Only this shard is locked, so broadcasts to other devices are not held up.
+            let connections = self.active_connections.read(&device_id).await;
+            if connections.contains_key(&device_id) {
+                continue;
             }
-        }
 
-        // Cleanup debug messages for disconnected devices
-        {
-            let mut debug_msgs = self.debug_messages.write().await;
-            let device_ids_to_remove: Vec<String> = debug_msgs.keys()
-                .filter(|device_id| !active_device_ids.contains(*device_id))
-                .cloned()
-                .collect();
-
-            for device_id in &device_ids_to_remove {
-                if let Some(queue) = debug_msgs.remove(device_id) {
-                    debug!("Removed {} debug messages for disconnected device {}", queue.len(), device_id);
-                }
+            // Cleanup state snapshots for disconnected devices
+            if let Some(snapshots) = self.state_snapshots.remove(&device_id).await {
+                cleanup_count += snapshots.len();
             }
-            cleanup_count += device_ids_to_remove.len();
-        }
 
-        // Cleanup legacy events for disconnected devices
-        {
-            let mut events = self.device_events.write().await;
-            let device_ids_to_remove: Vec<String> = events.keys()
-                .filter(|device_id| !active_device_ids.contains(*device_id))
-                .cloned()
-                .collect();
+            // Cleanup debug messages for disconnected devices
+            if let Some(queue) = self.debug_messages.remove(&device_id).await {
+                debug!("Removed {} debug messages for disconnected device {}", queue.len(), device_id);
+                cleanup_count += 1;
+            }
 
-            for device_id in &device_ids_to_remove {
-                if let Some(event_list) = events.remove(device_id) {
-                    debug!("Removed {} legacy events for disconnected device {}", event_list.len(), device_id);
-                }
+            // Cleanup legacy events for disconnected devices
+            if let Some(event_list) = self.device_events.remove(&device_id).await {
+                debug!("Removed {} legacy events for disconnected device {}", event_list.len(), device_id);
             }
+
+            drop(connections);
         }
 
         if cleanup_count > 0 {
             info!("Cleaned up events for {} disconnected devices", cleanup_count);
         }
 
-        // Explicitly drop the connections lock at the end (was held for consistency)
-        drop(connections);
-
         cleanup_count
     }
     
     /// Get storage statistics for monitoring
     pub async fn get_stats(&self) -> DeviceStoreStats {
-        let events = self.device_events.read().await;
-        let snapshots = self.state_snapshots.read().await;
-        let debug_msgs = self.debug_messages.read().await;
-        let connections = self.active_connections.read().await;
-
-        let legacy_events: usize = events.values().map(|v| v.len()).sum();
-        let state_snapshots_count = snapshots.len();
-        let debug_messages_count: usize = debug_msgs.values().map(|q| q.len()).sum();
-        let total_connections: usize = connections.values().map(|v| v.len()).sum();
-
-        let total_optimized_events = state_snapshots_count + debug_messages_count;
+        let mut legacy_events = 0;
+        let mut state_snapshots_count = 0;
+        let mut debug_messages_count = 0;
+        let mut total_connections = 0;
+        let mut active_devices = 0;
 
         // Count unique devices across all storage types
         let mut unique_devices = std::collections::HashSet::new();
 
         // Devices with legacy events
-        for device_id in events.keys() {
-            unique_devices.insert(device_id.clone());
+        for shard in self.device_events.shards() {
+            for (device_id, events) in shard.read().await.iter() {
+                legacy_events += events.len();
+                unique_devices.insert(device_id.clone());
+            }
         }
 
         // Devices with debug messages
-        for device_id in debug_msgs.keys() {
-            unique_devices.insert(device_id.clone());
+        for shard in self.debug_messages.shards() {
+            for (device_id, queue) in shard.read().await.iter() {
+                debug_messages_count += queue.len();
+                unique_devices.insert(device_id.clone());
+            }
         }
 
-        // Devices with state snapshots (extract device_id from state_key)
-        for key in snapshots.keys() {
-            let parts: Vec<&str> = key.split(':').collect();
-            if parts.len() >= 2 {
-                unique_devices.insert(parts[1].to_string());
+        // Devices with state snapshots
+        for shard in self.state_snapshots.shards() {
+            for (device_id, snapshots) in shard.read().await.iter() {
+                state_snapshots_count += snapshots.len();
+                if !device_id.is_empty() {
+                    unique_devices.insert(device_id.clone());
+                }
             }
         }
 
+        for shard in self.active_connections.shards() {
+            let connections = shard.read().await;
+            active_devices += connections.len();
+            total_connections += connections.values().map(|v| v.len()).sum::<usize>();
+        }
+
+        let total_optimized_events = state_snapshots_count + debug_messages_count;
         let total_devices = unique_devices.len();
 
         DeviceStoreStats {
             total_devices,
             total_events: total_optimized_events,
-            active_devices: connections.len(),
+            active_devices,
             total_connections,
             average_events_per_device: if total_devices == 0 { 0.0 } else { total_optimized_events as f64 / total_devices as f64 },
-            average_connections_per_device: if connections.is_empty() { 0.0 } else { total_connections as f64 / connections.len() as f64 },
+            average_connections_per_device: if active_devices == 0 { 0.0 } else { total_connections as f64 / active_devices as f64 },
             state_snapshots: state_snapshots_count,
             debug_messages: debug_messages_count,
-            legacy_events: legacy_events,
+            legacy_events,
         }
     }
 }
@@ -909,6 +1503,30 @@ pub struct DeviceStoreStats {
     pub legacy_events: usize,
 }
 
+/// Latest known value of one device variable
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariableSnapshot {
+    pub value: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max: Option<u64>,
+    /// Time of the last update (ms since epoch)
+    pub updated_at: i64,
+}
+
+/// Queue metrics of one WebSocket client
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct ClientQueueStats {
+    pub client_id: String,
+    pub user_id: String,
+    pub devices: usize,
+    #[serde(flatten)]
+    pub queue: ClientQueueMetrics,
+    pub liveness: Option<LivenessSnapshot>,
+}
+
 #[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
 pub struct DeviceUser {
     pub user_id: String,
@@ -934,3 +1552,183 @@ pub fn create_shared_store() -> SharedDeviceStore {
     Arc::new(DeviceEventStore::new())
 }
 
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::FutureExt;
+
+    #[tokio::test]
+    async fn test_event_page_cursor_pagination() {
+        let store = DeviceEventStore::new();
+        for i in 0..5 {
+            let event = DeviceEvent::device_udp_broadcast("dev-1".to_string(), format!("msg {}", i), "10.0.0.2".to_string(), 3232);
+            store.add_event("dev-1".to_string(), event, "user".to_string(), "client".to_string()).await.unwrap();
+        }
+
+        let request = ReplayRequest { limit: Some(2), ..Default::default() };
+        let first = store.get_event_page("dev-1", &request, true).await.unwrap();
+        assert_eq!(first.events.len(), 2);
+        assert!(first.has_more);
+
+        // Follow the cursor until the history is exhausted
+        let mut seen = first.events.len();
+        let mut cursor = first.next_cursor;
+        loop {
+            let request = ReplayRequest { limit: Some(2), cursor: cursor.clone(), ..Default::default() };
+            let page = store.get_event_page("dev-1", &request, true).await.unwrap();
+            seen += page.events.len();
+            if !page.has_more {
+                break;
+            }
+            cursor = page.next_cursor;
+        }
+        assert_eq!(seen, 5);
+
+        // since after the newest event and light subscriptions (no debug history) return nothing
+        let newest = store.get_event_page("dev-1", &ReplayRequest::default(), true).await.unwrap();
+        let since = newest.events.last().map(|e| e.timestamp);
+        assert!(store.get_event_page("dev-1", &ReplayRequest { since, ..Default::default() }, true).await.unwrap().events.is_empty());
+        assert!(store.get_event_page("dev-1", &ReplayRequest::default(), false).await.unwrap().events.is_empty());
+
+        assert!(store.get_event_page("dev-1", &ReplayRequest { limit: Some(0), ..Default::default() }, true).await.is_err());
+        assert!(store.get_event_page("dev-1", &ReplayRequest { cursor: Some("bad".to_string()), ..Default::default() }, true).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_send_to_users_reaches_each_client_once() {
+        let store = DeviceEventStore::new();
+        let (tx_a, mut rx_a) = crate::client_queue::client_queue(16, std::time::Duration::from_secs(60));
+        let (tx_b, mut rx_b) = crate::client_queue::client_queue(16, std::time::Duration::from_secs(60));
+        let light = crate::events::SubscriptionType::Light;
+
+        // Client c1 watches two devices over one socket
+        store.register_client("dev-1".into(), "u1".into(), "Alice".into(), "c1".into(), tx_a.clone(), light.clone(), None).await.unwrap();
+        store.register_client("dev-2".into(), "u1".into(), "Alice".into(), "c1".into(), tx_a, light.clone(), None).await.unwrap();
+        store.register_client("dev-1".into(), "u2".into(), "Bob".into(), "c2".into(), tx_b, light, None).await.unwrap();
+
+        let audience = HashSet::from(["u1".to_string()]);
+        let message = ServerMessage::device_list_changed("dev-3".to_string(), crate::events::DeviceListChange::Created);
+        assert_eq!(store.send_to_users(&audience, message).await, 1);
+
+        let drain = |rx: &mut crate::client_queue::ClientReceiver| {
+            let mut count = 0;
+            while let Some(Some(message)) = rx.recv().now_or_never() {
+                if matches!(**message, ServerMessage::DeviceListChanged { .. }) {
+                    count += 1;
+                }
+            }
+            count
+        };
+        assert_eq!(drain(&mut rx_a), 1);
+        assert_eq!(drain(&mut rx_b), 0);
+    }
+
+    #[tokio::test]
+    async fn test_variable_cache_keeps_latest_value() {
+        let store = DeviceEventStore::new();
+        for value in ["1", "2"] {
+            let event = DeviceEvent::device_variable_update("dev-1".to_string(), "speed".to_string(), value.to_string());
+            store.add_event("dev-1".to_string(), event, "device_system".to_string(), "udp_data".to_string()).await.unwrap();
+        }
+        let event = DeviceEvent::device_variable_update_with_range("dev-1".to_string(), "mode".to_string(), "3".to_string(), Some(0), Some(5));
+        store.add_event("dev-1".to_string(), event, "device_system".to_string(), "udp_data".to_string()).await.unwrap();
+
+        // Survives the viewer-less snapshot cleanup
+        store.cleanup_disconnected_devices().await;
+
+        let variables = store.get_device_variables("dev-1").await;
+        assert_eq!(variables.len(), 2);
+        assert_eq!(variables["speed"].value, "2");
+        assert_eq!(variables["mode"].max, Some(5));
+        assert!(variables["speed"].updated_at > 0);
+        assert!(store.get_device_variables("dev-2").await.is_empty());
+
+        assert!(store.clear_device_variables("dev-1").await);
+        assert!(store.get_device_variables("dev-1").await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_device_presence() {
+        let store = DeviceEventStore::new();
+        let (tx_a, mut rx_a) = crate::client_queue::client_queue(16, std::time::Duration::from_secs(60));
+        let (tx_b, _rx_b) = crate::client_queue::client_queue(16, std::time::Duration::from_secs(60));
+        let full = crate::events::SubscriptionType::Full;
+
+        store.register_client("dev-1".into(), "u1".into(), "Alice".into(), "c1".into(), tx_a, full.clone(), None).await.unwrap();
+        store.register_client("dev-1".into(), "u2".into(), "Bob".into(), "c2".into(), tx_b, full, None).await.unwrap();
+        store.set_client_cursor("dev-1", "c2", Some(serde_json::json!({"variable": "speed"}))).await.unwrap();
+
+        let presence = store.get_device_presence("dev-1").await;
+        assert_eq!(presence.len(), 2);
+        assert_eq!(presence[0].display_name, "Alice");
+        assert_eq!(presence[0].status, PresenceStatus::Active);
+        assert_eq!(presence[1].cursor, Some(serde_json::json!({"variable": "speed"})));
+
+        // Alice received presence updates for her own join, Bob's join and Bob's cursor
+        let mut presence_events = 0;
+        while let Ok(Some(message)) = tokio::time::timeout(std::time::Duration::from_millis(50), rx_a.recv()).await {
+            if let ServerMessage::DeviceEvents { events_for_device, .. } = &**message {
+                presence_events += events_for_device.iter()
+                    .filter(|e| matches!(e, DeviceEvent::DevicePresence { .. }))
+                    .count();
+            }
+        }
+        assert_eq!(presence_events, 3);
+
+        store.unregister_client("dev-1", "c2").await.unwrap();
+        assert_eq!(store.get_device_presence("dev-1").await.len(), 1);
+        assert_eq!(store.refresh_presence().await, 0);
+    }
+
+    #[tokio::test]
+    async fn test_device_channel_shares_one_message() {
+        let store = DeviceEventStore::new();
+        let queue = || crate::client_queue::client_queue(64, std::time::Duration::from_secs(60));
+        let (tx_1, mut rx_1) = queue();
+        let (tx_2, mut rx_2) = queue();
+        let (tx_3, mut rx_3) = queue();
+        let (full, light) = (crate::events::SubscriptionType::Full, crate::events::SubscriptionType::Light);
+
+        store.register_client("dev-1".into(), "u1".into(), "Alice".into(), "c1".into(), tx_1, full.clone(), None).await.unwrap();
+        store.register_client("dev-1".into(), "u2".into(), "Bob".into(), "c2".into(), tx_2, full, None).await.unwrap();
+        store.register_client("dev-1".into(), "u3".into(), "Carol".into(), "c3".into(), tx_3, light, None).await.unwrap();
+
+        let event = DeviceEvent::device_udp_broadcast("dev-1".to_string(), "hello".to_string(), "10.0.0.2".to_string(), 3232);
+        store.add_event("dev-1".to_string(), event, "u1".to_string(), "c1".to_string()).await.unwrap();
+
+        // Next UDP broadcast in a client's queue (presence and join events are skipped)
+        async fn next_udp(rx: &mut crate::client_queue::ClientReceiver) -> Option<Arc<OutboundMessage>> {
+            while let Ok(Some(message)) = tokio::time::timeout(std::time::Duration::from_millis(100), rx.recv()).await {
+                if let ServerMessage::DeviceEvents { events_for_device, .. } = &**message {
+                    if matches!(events_for_device[..], [DeviceEvent::DeviceUdpBroadcast { .. }]) {
+                        return Some(message);
+                    }
+                }
+            }
+            None
+        }
+        let sender_copy = next_udp(&mut rx_1).await;
+        let (for_bob, for_carol) = (next_udp(&mut rx_2).await.unwrap(), next_udp(&mut rx_3).await);
+        assert!(sender_copy.is_none(), "not echoed to the sender");
+        assert!(for_carol.is_none(), "light subscription");
+
+        // A second full subscriber gets the very same message
+        let (tx_4, mut rx_4) = queue();
+        store.register_client("dev-1".into(), "u4".into(), "Dave".into(), "c4".into(), tx_4, crate::events::SubscriptionType::Full, None).await.unwrap();
+        let event = DeviceEvent::device_udp_broadcast("dev-1".to_string(), "again".to_string(), "10.0.0.2".to_string(), 3232);
+        store.add_event("dev-1".to_string(), event, "u1".to_string(), "c1".to_string()).await.unwrap();
+        let (again_bob, again_dave) = (next_udp(&mut rx_2).await.unwrap(), next_udp(&mut rx_4).await.unwrap());
+        assert!(!Arc::ptr_eq(&for_bob, &again_bob));
+        assert!(Arc::ptr_eq(&again_bob, &again_dave));
+
+        for client_id in ["c1", "c2", "c3", "c4"] {
+            store.unregister_client("dev-1", client_id).await.unwrap();
+        }
+        assert!(store.device_channels.is_empty().await);
+    }
+}
@@ -0,0 +1,287 @@
+// Per-device connection supervisor with reconnect policies
+//
+// The supervisor task looks at every TCP device once per second. A device needs a reconnect when
+// its transport is not Connected, or when the transport still reports Connected while the lifecycle
+// went Offline (no UDP traffic within the timeout - the TCP link is most likely half-open).
+// Whether and when it reconnects is decided by the device's ReconnectPolicy. Devices in
+// maintenance, devices that were only discovered (never adopted) and devices disconnected via the
+// API are left alone until they are connected again.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::RwLock;
+use tracing::{debug, info, warn};
+
+use crate::device_lifecycle::LifecycleState;
+use crate::device_manager::DeviceManager;
+use crate::device_types::ConnectionState;
+
+/// How the supervisor reconnects a device whose link went down
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum ReconnectPolicy {
+    /// Retry on every supervisor pass (once per second)
+    Immediate,
+    /// Wait `initialDelayMs`, doubling after each failed attempt up to `maxDelayMs`
+    Backoff {
+        #[serde(rename = "initialDelayMs")]
+        initial_delay_ms: u64,
+        #[serde(rename = "maxDelayMs")]
+        max_delay_ms: u64,
+    },
+    /// Never reconnect automatically
+    ManualOnly,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy::Backoff { initial_delay_ms: 1_000, max_delay_ms: 60_000 }
+    }
+}
+
+impl ReconnectPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if let ReconnectPolicy::Backoff { initial_delay_ms, max_delay_ms } = *self {
+            if !(100..=3_600_000).contains(&initial_delay_ms) {
+                return Err("initialDelayMs must be between 100 and 3600000".to_string());
+            }
+            if !(initial_delay_ms..=3_600_000).contains(&max_delay_ms) {
+                return Err("maxDelayMs must be between initialDelayMs and 3600000".to_string());
+            }
+        }
+        Ok(())
+    }
+
+    /// Wait before the next attempt after `failures` failed ones; None = no automatic reconnect
+    pub fn retry_delay(&self, failures: u32) -> Option<Duration> {
+        match *self {
+            ReconnectPolicy::Immediate => Some(Duration::ZERO),
+            ReconnectPolicy::Backoff { initial_delay_ms, max_delay_ms } => {
+                let delay = initial_delay_ms.saturating_mul(1u64 << failures.min(32));
+                Some(Duration::from_millis(delay.min(max_delay_ms)))
+            }
+            ReconnectPolicy::ManualOnly => None,
+        }
+    }
+}
+
+/// Why a device needs a reconnect; None = leave it alone
+fn reconnect_reason(state: Option<LifecycleState>, health: Option<&ConnectionState>) -> Option<&'static str> {
+    match state {
+        None | Some(LifecycleState::Discovered) | Some(LifecycleState::Maintenance) => return None,
+        Some(_) => {}
+    }
+    match health? {
+        ConnectionState::Connected if state == Some(LifecycleState::Offline) => Some("no traffic, link presumed half-open"),
+        ConnectionState::Connected => None,
+        ConnectionState::Connecting => Some("waiting for reconnect"),
+        ConnectionState::Disconnected | ConnectionState::Failed(_) => Some("link down"),
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct RetryState {
+    failures: u32,
+    next_attempt: Option<Instant>,
+    in_flight: bool,
+    last_error: Option<String>,
+}
+
+/// Supervisor view of one device (GET /api/devices/:id/connection-policy)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SupervisorStatus {
+    pub policy: ReconnectPolicy,
+    /// Disconnected via the API: not reconnected until connected again
+    pub held: bool,
+    pub reconnecting: bool,
+    pub failures: u32,
+    pub next_attempt_in_ms: Option<u64>,
+    pub last_error: Option<String>,
+}
+
+/// Policies and retry state of all devices, owned by the DeviceManager
+#[derive(Debug, Default)]
+pub struct DeviceSupervisor {
+    policies: RwLock<HashMap<String, ReconnectPolicy>>,
+    held: RwLock<HashSet<String>>,
+    retries: RwLock<HashMap<String, RetryState>>,
+}
+
+impl DeviceSupervisor {
+    pub async fn policy(&self, device_id: &str) -> ReconnectPolicy {
+        self.policies.read().await.get(device_id).copied().unwrap_or_default()
+    }
+
+    /// Set the policy of a device; a pending retry is rescheduled under the new policy
+    pub async fn set_policy(&self, device_id: &str, policy: ReconnectPolicy) {
+        self.policies.write().await.insert(device_id.to_string(), policy);
+        if let Some(retry) = self.retries.write().await.get_mut(device_id) {
+            retry.failures = 0;
+            retry.next_attempt = None;
+        }
+    }
+
+    /// Manual disconnect: keep the device disconnected
+    pub async fn hold(&self, device_id: &str) {
+        self.held.write().await.insert(device_id.to_string());
+        self.retries.write().await.remove(device_id);
+    }
+
+    /// Manual connect: supervise the device again
+    pub async fn release(&self, device_id: &str) {
+        self.held.write().await.remove(device_id);
+    }
+
+    /// Device removed from the manager
+    pub async fn forget(&self, device_id: &str) {
+        self.policies.write().await.remove(device_id);
+        self.held.write().await.remove(device_id);
+        self.retries.write().await.remove(device_id);
+    }
+
+    pub async fn status(&self, device_id: &str) -> SupervisorStatus {
+        let retry = self.retries.read().await.get(device_id).cloned().unwrap_or_default();
+        SupervisorStatus {
+            policy: self.policy(device_id).await,
+            held: self.held.read().await.contains(device_id),
+            reconnecting: retry.in_flight,
+            failures: retry.failures,
+            next_attempt_in_ms: retry.next_attempt.map(|at| at.saturating_duration_since(Instant::now()).as_millis() as u64),
+            last_error: retry.last_error,
+        }
+    }
+
+    /// Decide whether to start an attempt now; schedules the first attempt of a newly failed link
+    async fn attempt_due(&self, device_id: &str, policy: ReconnectPolicy) -> bool {
+        let mut retries = self.retries.write().await;
+        let retry = retries.entry(device_id.to_string()).or_default();
+        if retry.in_flight {
+            return false;
+        }
+        let now = Instant::now();
+        let next_attempt = match retry.next_attempt {
+            Some(at) => at,
+            None => match policy.retry_delay(retry.failures) {
+                Some(delay) => *retry.next_attempt.insert(now + delay),
+                None => return false,
+            },
+        };
+        if now < next_attempt {
+            return false;
+        }
+        retry.in_flight = true;
+        true
+    }
+
+    async fn record_attempt(&self, device_id: &str, error: Option<String>, policy: ReconnectPolicy) {
+        let mut retries = self.retries.write().await;
+        match error {
+            None => {
+                retries.remove(device_id);
+            }
+            Some(error) => {
+                let retry = retries.entry(device_id.to_string()).or_default();
+                retry.in_flight = false;
+                retry.failures = retry.failures.saturating_add(1);
+                retry.next_attempt = policy.retry_delay(retry.failures).map(|delay| Instant::now() + delay);
+                retry.last_error = Some(error);
+            }
+        }
+    }
+
+    /// Link is fine again: drop retry bookkeeping (unless an attempt is still running)
+    async fn settled(&self, device_id: &str) {
+        let mut retries = self.retries.write().await;
+        if retries.get(device_id).is_some_and(|retry| !retry.in_flight) {
+            retries.remove(device_id);
+        }
+    }
+}
+
+/// Start the supervisor task of a device manager
+pub fn start(manager: Arc<DeviceManager>) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        info!("Device supervisor started");
+        loop {
+            interval.tick().await;
+            supervise(&manager).await;
+        }
+    });
+}
+
+async fn supervise(manager: &Arc<DeviceManager>) {
+    let supervisor = manager.supervisor();
+    let lifecycle = manager.lifecycle();
+
+    for device_id in manager.tcp_device_ids().await {
+        let state = lifecycle.get(&device_id).await.map(|entry| entry.state);
+        let health = manager.get_device_state(&device_id).await;
+        let Some(reason) = reconnect_reason(state, health.as_ref()) else {
+            supervisor.settled(&device_id).await;
+            continue;
+        };
+        if supervisor.held.read().await.contains(&device_id) {
+            continue;
+        }
+        let policy = supervisor.policy(&device_id).await;
+        if !supervisor.attempt_due(&device_id, policy).await {
+            continue;
+        }
+
+        debug!("Supervisor: reconnecting {} ({})", device_id, reason);
+        let half_open = health == Some(ConnectionState::Connected);
+        let manager = Arc::clone(manager);
+        tokio::spawn(async move {
+            if half_open {
+                let _ = manager.close_link(&device_id, "No traffic, reconnecting").await;
+            }
+            let result = manager.connect_device(&device_id).await;
+            match &result {
+                Ok(()) => info!("Supervisor: device {} reconnected", device_id),
+                Err(e) => warn!("Supervisor: reconnect of device {} failed: {}", device_id, e),
+            }
+            manager.supervisor().record_attempt(&device_id, result.err().map(|e| e.to_string()), policy).await;
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_policy_delays_and_retry_scheduling() {
+        let backoff: ReconnectPolicy = serde_json::from_str(r#"{"mode":"backoff","initialDelayMs":500,"maxDelayMs":3000}"#).unwrap();
+        assert_eq!(backoff.retry_delay(0), Some(Duration::from_millis(500)));
+        assert_eq!(backoff.retry_delay(2), Some(Duration::from_millis(2000)));
+        assert_eq!(backoff.retry_delay(40), Some(Duration::from_millis(3000)));
+        assert_eq!(ReconnectPolicy::ManualOnly.retry_delay(0), None);
+        assert!(ReconnectPolicy::Backoff { initial_delay_ms: 5000, max_delay_ms: 1000 }.validate().is_err());
+        assert_eq!(serde_json::to_value(ReconnectPolicy::ManualOnly).unwrap(), serde_json::json!({"mode": "manual_only"}));
+
+        assert_eq!(reconnect_reason(Some(LifecycleState::Maintenance), Some(&ConnectionState::Disconnected)), None);
+        assert_eq!(reconnect_reason(Some(LifecycleState::Discovered), Some(&ConnectionState::Disconnected)), None);
+        assert_eq!(reconnect_reason(Some(LifecycleState::Online), Some(&ConnectionState::Connected)), None);
+        assert!(reconnect_reason(Some(LifecycleState::Offline), Some(&ConnectionState::Connected)).is_some());
+        assert!(reconnect_reason(Some(LifecycleState::Offline), Some(&ConnectionState::Disconnected)).is_some());
+
+        let supervisor = DeviceSupervisor::default();
+        let id = "AA-BB-CC-DD-EE-07";
+        assert!(supervisor.attempt_due(id, ReconnectPolicy::Immediate).await);
+        assert!(!supervisor.attempt_due(id, ReconnectPolicy::Immediate).await, "attempt already running");
+        supervisor.record_attempt(id, Some("refused".to_string()), backoff).await;
+        let status = supervisor.status(id).await;
+        assert_eq!((status.failures, status.reconnecting), (1, false));
+        assert!(status.next_attempt_in_ms.unwrap() > 500);
+        assert!(!supervisor.attempt_due(id, backoff).await, "backoff delay not elapsed");
+
+        supervisor.hold(id).await;
+        assert!(supervisor.status(id).await.held);
+        assert_eq!(supervisor.status(id).await.failures, 0);
+    }
+}
@@ -0,0 +1,382 @@
+// Device trace capture - records every raw inbound/outbound frame of one device (TCP, UDP, UART)
+//
+// A session is started/stopped via POST /api/devices/:id/trace/start|stop and kept in
+// memory until the next start; it stops itself at its size or duration cap. Captures
+// download as NDJSON (one frame per line) or as pcap file (LINKTYPE_USER0, see
+// write_pcap) for protocol debugging of device firmware.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+/// Default cap of captured payload bytes per session
+pub const DEFAULT_MAX_BYTES: usize = 5 * 1024 * 1024;
+/// Highest size cap a session may request
+pub const MAX_BYTES_LIMIT: usize = 50 * 1024 * 1024;
+/// Default session duration
+pub const DEFAULT_MAX_DURATION_SECS: u64 = 600;
+/// Highest duration a session may request
+pub const MAX_DURATION_LIMIT_SECS: u64 = 3600;
+
+/// pcap link type reserved for private use; frames start with a direction and a transport byte
+const PCAP_LINKTYPE_USER0: u32 = 147;
+/// Bytes prepended to every pcap frame (direction, transport)
+const PCAP_FRAME_PREFIX: usize = 2;
+
+static SESSIONS: LazyLock<Mutex<HashMap<String, TraceSession>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Sessions currently recording; lets record() return without locking while nobody traces
+static RECORDING: AtomicUsize = AtomicUsize::new(0);
+
+// ============================================================================
+// TYPES
+// ============================================================================
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    /// Device -> server
+    In,
+    /// Server -> device
+    Out,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Transport {
+    Tcp,
+    Udp,
+    Uart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StopReason {
+    /// POST /api/devices/:id/trace/stop
+    Manual,
+    SizeLimit,
+    DurationLimit,
+}
+
+/// One captured frame
+#[derive(Debug, Clone)]
+pub struct TraceFrame {
+    /// ms since epoch
+    pub timestamp: i64,
+    pub direction: Direction,
+    pub transport: Transport,
+    /// Remote address if known, e.g. "192.168.1.50:3232"
+    pub peer: Option<String>,
+    pub data: Vec<u8>,
+}
+
+/// Caps requested when starting a session (POST body, all optional)
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceOptions {
+    pub max_bytes: Option<usize>,
+    pub max_duration_secs: Option<u64>,
+}
+
+impl TraceOptions {
+    /// Effective (max_bytes, max_duration_secs) with defaults applied
+    pub fn limits(&self) -> Result<(usize, u64), String> {
+        let max_bytes = self.max_bytes.unwrap_or(DEFAULT_MAX_BYTES);
+        if max_bytes == 0 || max_bytes > MAX_BYTES_LIMIT {
+            return Err(format!("maxBytes must be between 1 and {}", MAX_BYTES_LIMIT));
+        }
+        let max_duration_secs = self.max_duration_secs.unwrap_or(DEFAULT_MAX_DURATION_SECS);
+        if max_duration_secs == 0 || max_duration_secs > MAX_DURATION_LIMIT_SECS {
+            return Err(format!("maxDurationSecs must be between 1 and {}", MAX_DURATION_LIMIT_SECS));
+        }
+        Ok((max_bytes, max_duration_secs))
+    }
+}
+
+/// Session state as returned by the API
+#[derive(Debug, Clone, Serialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceSummary {
+    pub session_id: String,
+    pub device_id: String,
+    pub started_by: String,
+    pub started_at: DateTime<Utc>,
+    pub recording: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stopped_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<StopReason>,
+    pub frame_count: usize,
+    pub bytes: usize,
+    pub max_bytes: usize,
+    pub max_duration_secs: u64,
+}
+
+struct TraceSession {
+    summary: TraceSummary,
+    started: Instant,
+    frames: Vec<TraceFrame>,
+}
+
+impl TraceSession {
+    fn stop(&mut self, reason: StopReason) {
+        if self.summary.recording {
+            self.summary.recording = false;
+            self.summary.stopped_at = Some(Utc::now());
+            self.summary.stop_reason = Some(reason);
+            RECORDING.fetch_sub(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Stop once the duration cap has passed (checked lazily on every access)
+    fn check_duration(&mut self) {
+        if self.summary.recording && self.started.elapsed() >= Duration::from_secs(self.summary.max_duration_secs) {
+            self.stop(StopReason::DurationLimit);
+        }
+    }
+}
+
+fn sessions() -> std::sync::MutexGuard<'static, HashMap<String, TraceSession>> {
+    SESSIONS.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+// ============================================================================
+// SESSION CONTROL
+// ============================================================================
+
+/// Start recording; replaces a finished capture of the device, fails while one is recording
+pub fn start(device_id: &str, started_by: &str, options: TraceOptions) -> Result<TraceSummary, String> {
+    let (max_bytes, max_duration_secs) = options.limits()?;
+
+    let mut sessions = sessions();
+    if let Some(existing) = sessions.get_mut(device_id) {
+        existing.check_duration();
+        if existing.summary.recording {
+            return Err(format!("A trace session is already recording for device {}", device_id));
+        }
+    }
+
+    let summary = TraceSummary {
+        session_id: uuid::Uuid::new_v4().to_string(),
+        device_id: device_id.to_string(),
+        started_by: started_by.to_string(),
+        started_at: Utc::now(),
+        recording: true,
+        stopped_at: None,
+        stop_reason: None,
+        frame_count: 0,
+        bytes: 0,
+        max_bytes,
+        max_duration_secs,
+    };
+    sessions.insert(device_id.to_string(), TraceSession { summary: summary.clone(), started: Instant::now(), frames: Vec::new() });
+    RECORDING.fetch_add(1, Ordering::Relaxed);
+    Ok(summary)
+}
+
+/// Stop recording (no-op for a finished capture); None if the device has no capture
+pub fn stop(device_id: &str) -> Option<TraceSummary> {
+    let mut sessions = sessions();
+    let session = sessions.get_mut(device_id)?;
+    session.check_duration();
+    session.stop(StopReason::Manual);
+    Some(session.summary.clone())
+}
+
+/// Current state of the device's capture
+pub fn status(device_id: &str) -> Option<TraceSummary> {
+    let mut sessions = sessions();
+    let session = sessions.get_mut(device_id)?;
+    session.check_duration();
+    Some(session.summary.clone())
+}
+
+/// Summary and frames captured so far (for download)
+pub fn snapshot(device_id: &str) -> Option<(TraceSummary, Vec<TraceFrame>)> {
+    let mut sessions = sessions();
+    let session = sessions.get_mut(device_id)?;
+    session.check_duration();
+    Some((session.summary.clone(), session.frames.clone()))
+}
+
+/// Capture a frame if the device is being traced (cheap no-op otherwise)
+pub fn record(device_id: &str, direction: Direction, transport: Transport, peer: Option<String>, data: &[u8]) {
+    if RECORDING.load(Ordering::Relaxed) == 0 {
+        return;
+    }
+    let mut sessions = sessions();
+    let Some(session) = sessions.get_mut(device_id) else { return };
+    session.check_duration();
+    if !session.summary.recording {
+        return;
+    }
+    if session.summary.bytes + data.len() > session.summary.max_bytes {
+        session.stop(StopReason::SizeLimit);
+        return;
+    }
+
+    session.summary.bytes += data.len();
+    session.summary.frame_count += 1;
+    session.frames.push(TraceFrame {
+        timestamp: Utc::now().timestamp_millis(),
+        direction,
+        transport,
+        peer,
+        data: data.to_vec(),
+    });
+}
+
+// ============================================================================
+// CAPTURE FILES
+// ============================================================================
+
+/// Download format of a capture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+    Ndjson,
+    Pcap,
+}
+
+impl TraceFormat {
+    /// ndjson (default) or pcap
+    pub fn parse(value: Option<&str>) -> Option<Self> {
+        match value.map(|v| v.trim().to_lowercase()).as_deref() {
+            None | Some("") | Some("ndjson") | Some("json") => Some(Self::Ndjson),
+            Some("pcap") => Some(Self::Pcap),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            Self::Ndjson => "application/x-ndjson",
+            Self::Pcap => "application/vnd.tcpdump.pcap",
+        }
+    }
+
+    pub fn file_name(&self, summary: &TraceSummary) -> String {
+        let safe_id: String = summary.device_id.chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+            .collect();
+        let extension = match self {
+            Self::Ndjson => "ndjson",
+            Self::Pcap => "pcap",
+        };
+        format!("{}-trace-{}.{}", safe_id, summary.started_at.format("%Y%m%d-%H%M%S"), extension)
+    }
+
+    pub fn write(&self, summary: &TraceSummary, frames: &[TraceFrame]) -> Vec<u8> {
+        match self {
+            Self::Ndjson => write_ndjson(summary, frames),
+            Self::Pcap => write_pcap(frames),
+        }
+    }
+}
+
+/// Session line first, then one line per frame; `text` for UTF-8 payloads, `base64` otherwise
+fn write_ndjson(summary: &TraceSummary, frames: &[TraceFrame]) -> Vec<u8> {
+    let started_ms = summary.started_at.timestamp_millis();
+    let mut output = json!({ "type": "session", "session": summary }).to_string();
+    output.push('\n');
+
+    for frame in frames {
+        let mut line = json!({
+            "type": "frame",
+            "timestamp": frame.timestamp,
+            "offsetMs": frame.timestamp - started_ms,
+            "direction": frame.direction,
+            "transport": frame.transport,
+            "peer": frame.peer,
+            "length": frame.data.len(),
+        });
+        match std::str::from_utf8(&frame.data) {
+            Ok(text) => line["text"] = json!(text),
+            Err(_) => line["base64"] = json!(base64::engine::general_purpose::STANDARD.encode(&frame.data)),
+        }
+        output.push_str(&line.to_string());
+        output.push('\n');
+    }
+    output.into_bytes()
+}
+
+/// Classic little-endian pcap; each packet is [direction (0 = in, 1 = out), transport (0 = TCP,
+/// 1 = UDP, 2 = UART), payload...] so a Wireshark USER0 dissector can split the streams
+fn write_pcap(frames: &[TraceFrame]) -> Vec<u8> {
+    let mut output = Vec::new();
+    output.extend_from_slice(&0xa1b2c3d4u32.to_le_bytes()); // magic (microsecond timestamps)
+    output.extend_from_slice(&2u16.to_le_bytes()); // version 2.4
+    output.extend_from_slice(&4u16.to_le_bytes());
+    output.extend_from_slice(&0i32.to_le_bytes()); // UTC
+    output.extend_from_slice(&0u32.to_le_bytes()); // timestamp accuracy
+    output.extend_from_slice(&(MAX_BYTES_LIMIT as u32 + PCAP_FRAME_PREFIX as u32).to_le_bytes()); // snaplen
+    output.extend_from_slice(&PCAP_LINKTYPE_USER0.to_le_bytes());
+
+    for frame in frames {
+        let length = (frame.data.len() + PCAP_FRAME_PREFIX) as u32;
+        output.extend_from_slice(&(frame.timestamp.div_euclid(1000) as u32).to_le_bytes());
+        output.extend_from_slice(&(frame.timestamp.rem_euclid(1000) as u32 * 1000).to_le_bytes());
+        output.extend_from_slice(&length.to_le_bytes()); // captured length
+        output.extend_from_slice(&length.to_le_bytes()); // original length
+        output.push(match frame.direction {
+            Direction::In => 0,
+            Direction::Out => 1,
+        });
+        output.push(match frame.transport {
+            Transport::Tcp => 0,
+            Transport::Udp => 1,
+            Transport::Uart => 2,
+        });
+        output.extend_from_slice(&frame.data);
+    }
+    output
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trace_session_capture_and_caps() {
+        let device = "trace-test-device";
+        let options = TraceOptions { max_bytes: Some(10), max_duration_secs: None };
+        start(device, "user-1", options.clone()).unwrap();
+        assert!(start(device, "user-1", options).is_err());
+
+        record(device, Direction::Out, Transport::Tcp, Some("10.0.0.5:3232".to_string()), b"{\"a\":1}");
+        record("other-device", Direction::In, Transport::Udp, None, b"ignored");
+        // Would exceed maxBytes: session stops, frame is not recorded
+        record(device, Direction::In, Transport::Udp, None, &[0xff, 0x00, 0x01, 0x02]);
+
+        let (summary, frames) = snapshot(device).unwrap();
+        assert!(!summary.recording);
+        assert_eq!(summary.stop_reason, Some(StopReason::SizeLimit));
+        assert_eq!((summary.frame_count, summary.bytes), (1, 7));
+        assert_eq!(frames[0].data, b"{\"a\":1}");
+
+        let ndjson = String::from_utf8(TraceFormat::Ndjson.write(&summary, &frames)).unwrap();
+        let lines: Vec<serde_json::Value> = ndjson.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines[0]["session"]["deviceId"], device);
+        assert_eq!(lines[1]["direction"], "out");
+        assert_eq!(lines[1]["text"], "{\"a\":1}");
+
+        let pcap = TraceFormat::Pcap.write(&summary, &frames);
+        assert_eq!(&pcap[..4], &[0xd4, 0xc3, 0xb2, 0xa1]);
+        assert_eq!(pcap.len(), 24 + 16 + PCAP_FRAME_PREFIX + 7);
+        assert_eq!(&pcap[40..42], &[1, 0]);
+
+        // Finished capture can be replaced by a new session
+        assert!(start(device, "user-1", TraceOptions::default()).unwrap().recording);
+        assert_eq!(stop(device).unwrap().stop_reason, Some(StopReason::Manual));
+        assert!(start(device, "user-1", TraceOptions { max_bytes: Some(0), max_duration_secs: None }).is_err());
+    }
+}
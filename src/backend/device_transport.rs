@@ -0,0 +1,128 @@
+// Device transports - one trait for every protocol a device can be reached over
+//
+// DeviceManager keeps one boxed transport per device (device_id -> Box<dyn DeviceTransport>)
+// and only talks to it through this trait: connect, send, disconnect, a health probe and
+// connection settings changed at runtime.
+// Incoming traffic is not pulled from the transport; every transport pushes received frames
+// into its TransportContext, which runs the unified message pipeline (lifecycle, activity
+// tracking, device type registry, event store). The event store feed is the event stream of
+// all transports.
+//
+// Implementations: DeviceConnection (TCP, device_connection.rs) and UartDeviceTransport
+// (shared serial port, uart_connection.rs). A new protocol implements the trait and delivers
+// through the context - no additional tracker maps in constructors.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
+
+use futures::future::BoxFuture;
+use tokio::sync::RwLock;
+
+use crate::bus_decoders::BusDecoders;
+use crate::message_parsers::MessageParsers;
+use crate::device_lifecycle::SharedLifecycle;
+use crate::device_manager::{DeviceConnectionType, DeviceManager, MessageSource};
+use crate::device_store::SharedDeviceStore;
+use crate::device_types::{ConnectionSettings, ConnectionState, DeviceCommand, DeviceResult};
+use crate::variable_coalescer::VariableCoalescer;
+
+pub trait DeviceTransport: Send + Sync + std::fmt::Debug {
+    /// Transport family, also registered in the device type registry
+    fn connection_type(&self) -> DeviceConnectionType;
+
+    /// Establish the link; transports without an own link (shared UART port) only check it
+    fn connect(&mut self) -> BoxFuture<'_, DeviceResult<()>>;
+
+    /// Close the link
+    fn disconnect(&mut self) -> BoxFuture<'_, DeviceResult<()>>;
+
+    /// Send a command, tagged with the correlation ID the device reply will carry
+    fn send<'a>(&'a self, command: DeviceCommand, correlation_id: Option<&'a str>) -> BoxFuture<'a, DeviceResult<()>>;
+
+    /// Current link state; commands are queued while it is not Connected
+    fn health(&self) -> BoxFuture<'_, ConnectionState>;
+
+    /// Take over changed ports, timeouts and keep-alive without dropping the link; ports are
+    /// used from the next connect on. Transports without such settings ignore them.
+    fn apply_settings(&mut self, _settings: ConnectionSettings) -> BoxFuture<'_, ()> {
+        Box::pin(async {})
+    }
+}
+
+pub type SharedTransport = Arc<tokio::sync::Mutex<Box<dyn DeviceTransport>>>;
+
+/// Box a transport for the DeviceManager connection map
+pub fn shared_transport(transport: impl DeviceTransport + 'static) -> SharedTransport {
+    Arc::new(tokio::sync::Mutex::new(Box::new(transport)))
+}
+
+/// Shared state a transport needs to deliver received frames
+#[derive(Debug, Clone)]
+pub struct TransportContext {
+    pub device_store: SharedDeviceStore,
+    pub lifecycle: SharedLifecycle,
+    /// Last UDP/UART activity per device, watched by the inactivity monitor
+    pub activity_tracker: Arc<RwLock<HashMap<String, Instant>>>,
+    /// Device type registry (UART vs TCP/UDP)
+    pub connection_types: Arc<RwLock<HashMap<String, DeviceConnectionType>>>,
+    /// Rate limit of variable updates before they reach the store
+    pub variable_coalescer: Arc<VariableCoalescer>,
+    /// Decoder plugins for raw bus frames (per device)
+    pub bus_decoders: Arc<BusDecoders>,
+    /// Parser chains turning received messages into events
+    pub message_parsers: Arc<MessageParsers>,
+}
+
+impl TransportContext {
+    /// Run a received frame through the unified message pipeline.
+    /// TCP frames do not count as activity: TCP has its own keep-alive, no inactivity timeout.
+    pub async fn deliver(&self, device_id: &str, message: &str, source: MessageSource) {
+        let activity_tracker = match source {
+            MessageSource::Tcp { .. } => {
+                crate::debug_logger::DebugLogger::log_tcp_message(device_id, "RECEIVED", message);
+                None
+            }
+            MessageSource::Uart | MessageSource::Udp { .. } => Some(&self.activity_tracker),
+        };
+        DeviceManager::handle_message_unified(
+            message,
+            device_id,
+            source,
+            &self.device_store,
+            &self.lifecycle,
+            &self.variable_coalescer,
+            &self.bus_decoders,
+            &self.message_parsers,
+            activity_tracker,
+            Some(&self.connection_types),
+        ).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::command_queue::CommandDispatch;
+    use crate::device_store::create_shared_store;
+    use crate::uart_connection::UartConnection;
+
+    #[tokio::test]
+    async fn test_uart_device_gets_transport_on_shared_port() {
+        let manager = DeviceManager::new(create_shared_store());
+        let context = manager.transport_context();
+        let uart = UartConnection::new(context.clone());
+        let device_id = "AA-BB-CC-DD-EE-42";
+
+        // Unknown device type: no transport can be created
+        assert!(manager.send_command(device_id, DeviceCommand::GetStatus).await.is_err());
+
+        manager.set_uart_port(uart.port()).await;
+        context.connection_types.write().await.insert(device_id.to_string(), DeviceConnectionType::Uart);
+
+        // Port is not open: the UART transport reports Disconnected and the command waits in the queue
+        let dispatch = manager.send_or_queue_command(device_id, DeviceCommand::GetStatus, None).await.unwrap();
+        assert!(matches!(dispatch, CommandDispatch::Queued { queue_depth: 1, .. }));
+        assert_eq!(manager.get_device_state(device_id).await, Some(ConnectionState::Disconnected));
+    }
+}
@@ -2,6 +2,48 @@
 
 use serde::{Deserialize, Serialize};
 use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::RwLock;
+
+// Inactivity timeout defaults for new device configs (seconds), set at startup from the config
+static DEFAULT_TCP_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(10);
+static DEFAULT_UDP_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(30);
+
+// TCP keep-alive (idle time before the first probe, time between probes) and connect timeout
+// defaults for new device configs (seconds), set at startup from the config
+static DEFAULT_KEEPALIVE_IDLE_SECS: AtomicU64 = AtomicU64::new(600);
+static DEFAULT_KEEPALIVE_INTERVAL_SECS: AtomicU64 = AtomicU64::new(60);
+static DEFAULT_CONNECT_TIMEOUT_SECS: AtomicU64 = AtomicU64::new(5);
+
+pub const DEFAULT_TLS_CERTS_DIR: &str = "data/certs";
+
+// devices.tls_certs_dir; replaced by a config reload
+static TLS_CERTS_DIR: RwLock<Option<String>> = RwLock::new(None);
+
+/// Set the timeout defaults of TCP devices and of UDP/UART devices
+pub fn set_default_timeouts(tcp_secs: u64, udp_secs: u64) {
+    DEFAULT_TCP_TIMEOUT_SECS.store(tcp_secs, Ordering::Relaxed);
+    DEFAULT_UDP_TIMEOUT_SECS.store(udp_secs, Ordering::Relaxed);
+}
+
+/// Set the TCP keep-alive and connect timeout defaults of devices without own connection settings
+pub fn set_default_tcp_settings(keepalive_idle_secs: u64, keepalive_interval_secs: u64, connect_timeout_secs: u64) {
+    DEFAULT_KEEPALIVE_IDLE_SECS.store(keepalive_idle_secs, Ordering::Relaxed);
+    DEFAULT_KEEPALIVE_INTERVAL_SECS.store(keepalive_interval_secs, Ordering::Relaxed);
+    DEFAULT_CONNECT_TIMEOUT_SECS.store(connect_timeout_secs, Ordering::Relaxed);
+}
+
+pub fn set_tls_certs_dir(dir: &str) {
+    *TLS_CERTS_DIR.write().unwrap() = Some(dir.to_string());
+}
+
+/// Path of a per-device TLS file; only plain file names inside the certificate directory resolve
+pub fn tls_file_path(name: &str) -> Option<PathBuf> {
+    let name = crate::file_utils::safe_file_name(name)?;
+    let dir = TLS_CERTS_DIR.read().unwrap().clone().unwrap_or_else(|| DEFAULT_TLS_CERTS_DIR.to_string());
+    Some(PathBuf::from(dir).join(name))
+}
 
 // ============================================================================
 // DEVICE COMMAND TYPES - Messages sent to devices
@@ -26,6 +68,11 @@ pub enum DeviceCommand {
     },
     /// Request current status/info from device
     GetStatus,
+    /// Set the device wall clock (ms since epoch)
+    SetTime {
+        #[serde(rename = "setTime")]
+        epoch_ms: i64,
+    },
 }
 
 impl DeviceCommand {
@@ -44,7 +91,25 @@ impl DeviceCommand {
     pub fn get_status() -> Self {
         Self::GetStatus
     }
+
+    pub fn set_time(epoch_ms: i64) -> Self {
+        Self::SetTime { epoch_ms }
+    }
     
+    /// Serialize command to JSON with a correlation ID (`"id"` field).
+    /// The firmware echoes the ID in its reply so it can be matched to the request.
+    pub fn to_json_with_correlation(&self, correlation_id: Option<&str>) -> Result<String, serde_json::Error> {
+        let Some(correlation_id) = correlation_id else {
+            return self.to_json();
+        };
+
+        let mut cmd: serde_json::Value = serde_json::from_str(&self.to_json()?)?;
+        if let Some(obj) = cmd.as_object_mut() {
+            obj.insert("id".to_string(), serde_json::Value::String(correlation_id.to_string()));
+        }
+        serde_json::to_string(&cmd)
+    }
+
     /// Serialize command to JSON for TCP transmission
     pub fn to_json(&self) -> Result<String, serde_json::Error> {
         match self {
@@ -75,6 +140,12 @@ impl DeviceCommand {
                 });
                 serde_json::to_string(&cmd)
             }
+            Self::SetTime { epoch_ms } => {
+                let cmd = serde_json::json!({
+                    "setTime": epoch_ms
+                });
+                serde_json::to_string(&cmd)
+            }
         }
     }
 }
@@ -171,6 +242,114 @@ pub struct DeviceConfig {
     pub udp_timeout_seconds: u64,
     /// Device source (UDP with MAC, UART, or TCP)
     pub device_source: DeviceSource,
+    /// Optional TLS for the TCP channel (None = plaintext)
+    #[serde(default)]
+    pub tls: Option<DeviceTlsConfig>,
+    /// Shared secret for the identity challenge after connect (None = no check, see device_identity.rs)
+    #[serde(default, skip_serializing)]
+    pub identity_secret: Option<String>,
+    #[serde(default = "default_keepalive_idle")]
+    pub keepalive_idle_seconds: u64,
+    #[serde(default = "default_keepalive_interval")]
+    pub keepalive_interval_seconds: u64,
+    /// Limit of the TCP connect and of the TLS handshake
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_seconds: u64,
+}
+
+fn default_keepalive_idle() -> u64 {
+    DEFAULT_KEEPALIVE_IDLE_SECS.load(Ordering::Relaxed)
+}
+
+fn default_keepalive_interval() -> u64 {
+    DEFAULT_KEEPALIVE_INTERVAL_SECS.load(Ordering::Relaxed)
+}
+
+fn default_connect_timeout() -> u64 {
+    DEFAULT_CONNECT_TIMEOUT_SECS.load(Ordering::Relaxed)
+}
+
+/// Connection settings of a device that can change while it is connected
+/// (PATCH /api/devices/:id/connection-settings)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConnectionSettings {
+    pub tcp_port: u16,
+    pub udp_port: u16,
+    /// Inactivity timeout of UDP/UART traffic
+    pub udp_timeout_seconds: u64,
+    pub keepalive_idle_seconds: u64,
+    pub keepalive_interval_seconds: u64,
+    /// Missing in settings stored before it existed
+    #[serde(default = "default_connect_timeout")]
+    pub connect_timeout_seconds: u64,
+}
+
+impl ConnectionSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        if !(1..=86_400).contains(&self.udp_timeout_seconds) {
+            return Err("udpTimeoutSeconds must be between 1 and 86400".to_string());
+        }
+        if !(1..=7_200).contains(&self.keepalive_idle_seconds) {
+            return Err("keepaliveIdleSeconds must be between 1 and 7200".to_string());
+        }
+        if !(1..=600).contains(&self.keepalive_interval_seconds) {
+            return Err("keepaliveIntervalSeconds must be between 1 and 600".to_string());
+        }
+        if !(1..=60).contains(&self.connect_timeout_seconds) {
+            return Err("connectTimeoutSeconds must be between 1 and 60".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Body of PATCH /api/devices/:id/connection-settings; omitted fields keep their value
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ConnectionSettingsPatch {
+    pub tcp_port: Option<u16>,
+    pub udp_port: Option<u16>,
+    pub udp_timeout_seconds: Option<u64>,
+    pub keepalive_idle_seconds: Option<u64>,
+    pub keepalive_interval_seconds: Option<u64>,
+    pub connect_timeout_seconds: Option<u64>,
+}
+
+impl ConnectionSettingsPatch {
+    pub fn is_empty(&self) -> bool {
+        self.tcp_port.is_none() && self.udp_port.is_none() && self.udp_timeout_seconds.is_none()
+            && self.keepalive_idle_seconds.is_none() && self.keepalive_interval_seconds.is_none()
+            && self.connect_timeout_seconds.is_none()
+    }
+
+    pub fn apply(&self, settings: &ConnectionSettings) -> ConnectionSettings {
+        ConnectionSettings {
+            tcp_port: self.tcp_port.unwrap_or(settings.tcp_port),
+            udp_port: self.udp_port.unwrap_or(settings.udp_port),
+            udp_timeout_seconds: self.udp_timeout_seconds.unwrap_or(settings.udp_timeout_seconds),
+            keepalive_idle_seconds: self.keepalive_idle_seconds.unwrap_or(settings.keepalive_idle_seconds),
+            keepalive_interval_seconds: self.keepalive_interval_seconds.unwrap_or(settings.keepalive_interval_seconds),
+            connect_timeout_seconds: self.connect_timeout_seconds.unwrap_or(settings.connect_timeout_seconds),
+        }
+    }
+}
+
+/// TLS settings for the TCP channel of a device
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceTlsConfig {
+    /// PEM file with the trust anchor: deployment CA or the device's pre-shared certificate.
+    /// All file names refer to devices.tls_certs_dir (see `tls_file_path`)
+    pub ca_cert_path: String,
+    /// Name checked against the device certificate (defaults to the device IP)
+    #[serde(default)]
+    pub server_name: Option<String>,
+    /// Optional client certificate (PEM) for mutual TLS
+    #[serde(default)]
+    pub client_cert_path: Option<String>,
+    /// Optional client private key (PEM) for mutual TLS
+    #[serde(default)]
+    pub client_key_path: Option<String>,
 }
 
 impl DeviceConfig {
@@ -183,8 +362,13 @@ impl DeviceConfig {
             udp_port,
             auto_connect: false,
             auto_start_option: None,
-            udp_timeout_seconds: 10, // Default: 10 seconds timeout
+            udp_timeout_seconds: DEFAULT_TCP_TIMEOUT_SECS.load(Ordering::Relaxed), // Default: 10 seconds timeout
             device_source: DeviceSource::Tcp, // Default to TCP
+            tls: None,
+            identity_secret: None,
+            keepalive_idle_seconds: default_keepalive_idle(),
+            keepalive_interval_seconds: default_keepalive_interval(),
+            connect_timeout_seconds: default_connect_timeout(),
         }
     }
 
@@ -198,8 +382,13 @@ impl DeviceConfig {
             udp_port: 0,
             auto_connect: false,
             auto_start_option: None,
-            udp_timeout_seconds: 30, // Default: 30 seconds timeout for UART
+            udp_timeout_seconds: DEFAULT_UDP_TIMEOUT_SECS.load(Ordering::Relaxed), // Default: 30 seconds timeout for UART
             device_source: DeviceSource::Uart,
+            tls: None,
+            identity_secret: None,
+            keepalive_idle_seconds: default_keepalive_idle(),
+            keepalive_interval_seconds: default_keepalive_interval(),
+            connect_timeout_seconds: default_connect_timeout(),
         }
     }
 
@@ -213,11 +402,36 @@ impl DeviceConfig {
             udp_port,
             auto_connect: false,
             auto_start_option: None,
-            udp_timeout_seconds: 30, // Default: 30 seconds UDP timeout
+            udp_timeout_seconds: DEFAULT_UDP_TIMEOUT_SECS.load(Ordering::Relaxed), // Default: 30 seconds UDP timeout
             device_source: DeviceSource::Udp { mac_address }, // MAC also stored in DeviceSource
+            tls: None,
+            identity_secret: None,
+            keepalive_idle_seconds: default_keepalive_idle(),
+            keepalive_interval_seconds: default_keepalive_interval(),
+            connect_timeout_seconds: default_connect_timeout(),
         }
     }
     
+    pub fn connection_settings(&self) -> ConnectionSettings {
+        ConnectionSettings {
+            tcp_port: self.tcp_port,
+            udp_port: self.udp_port,
+            udp_timeout_seconds: self.udp_timeout_seconds,
+            keepalive_idle_seconds: self.keepalive_idle_seconds,
+            keepalive_interval_seconds: self.keepalive_interval_seconds,
+            connect_timeout_seconds: self.connect_timeout_seconds,
+        }
+    }
+
+    pub fn apply_connection_settings(&mut self, settings: &ConnectionSettings) {
+        self.tcp_port = settings.tcp_port;
+        self.udp_port = settings.udp_port;
+        self.udp_timeout_seconds = settings.udp_timeout_seconds;
+        self.keepalive_idle_seconds = settings.keepalive_idle_seconds;
+        self.keepalive_interval_seconds = settings.keepalive_interval_seconds;
+        self.connect_timeout_seconds = settings.connect_timeout_seconds;
+    }
+
     pub fn tcp_addr(&self) -> SocketAddr {
         SocketAddr::new(self.ip_address, self.tcp_port)
     }
@@ -227,6 +441,96 @@ impl DeviceConfig {
     }
 }
 
+/// Payload of the UDP discovery broadcast; fixed, so a scan cannot send arbitrary datagrams
+pub const UDP_DISCOVERY_MESSAGE: &str = r#"{"discover":true}"#;
+
+pub const DEFAULT_DISCOVERY_PROBE_PORTS: [u16; 1] = [3232];
+
+// discovery.probe_ports; replaced by a config reload (empty = default)
+static DISCOVERY_PROBE_PORTS: RwLock<Vec<u16>> = RwLock::new(Vec::new());
+
+pub fn set_discovery_probe_ports(ports: &[u16]) {
+    *DISCOVERY_PROBE_PORTS.write().unwrap() = ports.to_vec();
+}
+
+/// UDP broadcast probe used for active device discovery
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UdpDiscoveryProbe {
+    /// Device ports the probe is sent to
+    pub ports: Vec<u16>,
+    /// How long replies are collected
+    pub timeout_ms: u64,
+}
+
+impl Default for UdpDiscoveryProbe {
+    /// The configured probe ports, 2 s reply window
+    fn default() -> Self {
+        let ports = DISCOVERY_PROBE_PORTS.read().unwrap().clone();
+        Self {
+            ports: if ports.is_empty() { DEFAULT_DISCOVERY_PROBE_PORTS.to_vec() } else { ports },
+            timeout_ms: 2000,
+        }
+    }
+}
+
+/// Opt-in TCP port scan of an IP range, for networks where multicast/mDNS is blocked
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TcpScanConfig {
+    /// Periodic background scan enabled
+    pub enabled: bool,
+    /// IPv4 range in CIDR notation, e.g. "192.168.1.0/24"
+    pub cidr: String,
+    /// Device TCP port to probe
+    pub port: u16,
+    /// Maximum parallel connection attempts
+    pub concurrency: usize,
+    /// Connect timeout per host
+    pub timeout_ms: u64,
+    /// Interval of the background scan
+    pub interval_seconds: u64,
+}
+
+impl Default for TcpScanConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            cidr: String::new(),
+            port: 3232,
+            concurrency: 32,
+            timeout_ms: 300,
+            interval_seconds: 300,
+        }
+    }
+}
+
+impl TcpScanConfig {
+    /// Largest range a scan may cover (/16)
+    pub const MAX_HOSTS: usize = 65_534;
+
+    /// Host addresses of the CIDR range (network and broadcast excluded below /31)
+    pub fn hosts(&self) -> Result<Vec<std::net::Ipv4Addr>, String> {
+        let (base, prefix) = self.cidr.trim().split_once('/')
+            .ok_or_else(|| format!("Invalid CIDR (expected a.b.c.d/n): {}", self.cidr))?;
+        let base: std::net::Ipv4Addr = base.parse()
+            .map_err(|_| format!("Invalid IPv4 address: {}", base))?;
+        let prefix: u32 = prefix.parse()
+            .ok().filter(|p| *p <= 32)
+            .ok_or_else(|| format!("Invalid prefix length: {}", prefix))?;
+
+        let mask = if prefix == 0 { 0 } else { u32::MAX << (32 - prefix) };
+        let network = u32::from(base) & mask;
+        let broadcast = network | !mask;
+        let (first, last) = if prefix >= 31 { (network, broadcast) } else { (network + 1, broadcast - 1) };
+
+        let count = (last - first) as usize + 1;
+        if count > Self::MAX_HOSTS {
+            return Err(format!("Range too large: {} hosts (max {})", count, Self::MAX_HOSTS));
+        }
+        Ok((first..=last).map(std::net::Ipv4Addr::from).collect())
+    }
+}
+
 // ============================================================================
 // CONNECTION STATUS TRACKING
 // ============================================================================
@@ -247,6 +551,16 @@ impl ConnectionState {
     pub fn is_connecting(&self) -> bool {
         matches!(self, ConnectionState::Connecting)
     }
+
+    /// Name used in API responses
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConnectionState::Disconnected => "disconnected",
+            ConnectionState::Connecting => "connecting",
+            ConnectionState::Connected => "connected",
+            ConnectionState::Failed(_) => "failed",
+        }
+    }
 }
 
 // ============================================================================
@@ -272,6 +586,30 @@ pub enum DeviceError {
 
     #[error("Communication timeout")]
     Timeout,
+
+    #[error("Command queue full for device: {0}")]
+    QueueFull(String),
+
+    #[error("Rejected by device profile: {0}")]
+    ProfileViolation(String),
+
+    #[error("{0}")]
+    RateLimited(crate::command_rate_limit::RateLimited),
 }
 
-pub type DeviceResult<T> = Result<T, DeviceError>;
\ No newline at end of file
+pub type DeviceResult<T> = Result<T, DeviceError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tls_file_path_stays_in_certs_dir() {
+        assert_eq!(tls_file_path("lab-ca.pem"), Some(PathBuf::from(DEFAULT_TLS_CERTS_DIR).join("lab-ca.pem")));
+        assert_eq!(tls_file_path("/etc/shadow"), None);
+        assert_eq!(tls_file_path("../secrets.key"), None);
+        assert_eq!(tls_file_path("certs/ca.pem"), None);
+        assert_eq!(tls_file_path(".hidden.pem"), None);
+        assert_eq!(tls_file_path(""), None);
+    }
+}
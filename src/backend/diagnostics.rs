@@ -0,0 +1,362 @@
+// Diagnostics - self-test of the host environment (`--doctor` and GET /api/admin/diagnostics)
+//
+// Most support requests come down to the same few problems: the HTTP or UDP port is taken by
+// another instance, multicast (mDNS discovery) is blocked, the user may not open serial ports,
+// the database or data/ is not writable or full, or the clock was never set. Each check reports
+// pass/warn/fail with a hint what to do. `drawing-app-backend --doctor` runs them before the server
+// starts (ports are bound briefly to see whether they are free) and exits with 1 if one failed;
+// the admin endpoint runs them against the running server (ports are checked as bound by it).
+
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, UdpSocket};
+use std::path::Path;
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime};
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::Serialize;
+
+use crate::config::AppConfig;
+
+/// Group used for mDNS, joined to see whether multicast works
+const MDNS_GROUP: Ipv4Addr = Ipv4Addr::new(224, 0, 0, 251);
+
+/// Free space below which data/ is reported (warning, failure)
+const DISK_WARN_BYTES: u64 = 1024 * 1024 * 1024;
+const DISK_FAIL_BYTES: u64 = 100 * 1024 * 1024;
+
+/// Earliest plausible clock; an unset RTC usually reports 1970 or the firmware build date
+const CLOCK_FLOOR_YEAR: i32 = 2024;
+
+// Set once at startup; the defaults until then (tests)
+static TARGETS: OnceLock<DiagnosticsTargets> = OnceLock::new();
+
+/// Ports and paths the checks look at
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticsTargets {
+    pub bind_address: String,
+    pub http_port: u16,
+    /// HTTPS listener, if TLS is enabled
+    pub https_port: Option<u16>,
+    pub udp_ports: Vec<u16>,
+    pub database_path: String,
+}
+
+impl DiagnosticsTargets {
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self {
+            bind_address: config.server.bind_address.clone(),
+            http_port: config.server.http_port,
+            https_port: config.tls.enabled.then_some(config.tls.https_port),
+            udp_ports: config.devices.udp_listen_ports.clone(),
+            database_path: config.database.path.clone(),
+        }
+    }
+}
+
+pub fn configure(targets: DiagnosticsTargets) {
+    if TARGETS.set(targets).is_err() {
+        tracing::warn!("Diagnostics targets were already configured, keeping the first ones");
+    }
+}
+
+pub fn targets() -> &'static DiagnosticsTargets {
+    TARGETS.get_or_init(|| DiagnosticsTargets::from_config(&AppConfig::default()))
+}
+
+/// What the running server holds, so its own ports and serial port are not reported as taken
+#[derive(Debug, Clone, Default)]
+pub struct ServerState {
+    pub bound_udp_ports: Vec<u16>,
+    /// Serial port the UART listener has open
+    pub uart_port: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    /// Not applicable here (e.g. no serial ports, not a Unix system)
+    Skip,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Check {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+    /// What to do about a warning or failure
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hint: Option<String>,
+}
+
+impl Check {
+    fn new(name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) -> Self {
+        Self { name: name.into(), status, detail: detail.into(), hint: None }
+    }
+
+    fn hint(mut self, hint: impl Into<String>) -> Self {
+        self.hint = Some(hint.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DiagnosticsReport {
+    /// No check failed
+    pub ok: bool,
+    pub checks: Vec<Check>,
+}
+
+/// Run all checks; `server` is None before the server started (`--doctor`)
+pub fn run(targets: &DiagnosticsTargets, server: Option<&ServerState>) -> DiagnosticsReport {
+    let mut checks = Vec::new();
+    checks.extend(check_tcp_ports(targets, server.is_some()));
+    checks.extend(check_udp_ports(targets, server));
+    checks.push(check_multicast());
+    checks.extend(check_serial_ports(server));
+    checks.push(check_database(&targets.database_path));
+    checks.push(check_disk_space(&targets.database_path));
+    checks.push(check_clock(&targets.database_path));
+    let ok = checks.iter().all(|check| check.status != CheckStatus::Fail);
+    DiagnosticsReport { ok, checks }
+}
+
+/// `--doctor`: print the report and return the exit code
+pub fn run_doctor(targets: &DiagnosticsTargets) -> i32 {
+    let report = run(targets, None);
+    println!("ESP32 Manager doctor");
+    for check in &report.checks {
+        let label = match check.status {
+            CheckStatus::Pass => " OK ",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skip => "SKIP",
+        };
+        println!("  [{}] {}: {}", label, check.name, check.detail);
+        if let Some(hint) = &check.hint {
+            println!("         -> {}", hint);
+        }
+    }
+    if report.ok {
+        println!("All checks passed");
+        0
+    } else {
+        println!("Some checks failed, see the hints above");
+        1
+    }
+}
+
+fn port_hint(port: u16, error: &std::io::Error) -> String {
+    if error.kind() == std::io::ErrorKind::PermissionDenied && port < 1024 {
+        format!("Ports below 1024 need privileges: use a higher port or grant CAP_NET_BIND_SERVICE (port {})", port)
+    } else {
+        "Another program (or a second server instance) uses the port: stop it or configure another port".to_string()
+    }
+}
+
+fn check_tcp_ports(targets: &DiagnosticsTargets, running: bool) -> Vec<Check> {
+    let ports = [Some(("http_port", targets.http_port, "HTTP_PORT")), targets.https_port.map(|port| ("https_port", port, "HTTPS_PORT"))];
+    ports.into_iter().flatten().map(|(name, port, env)| {
+        let addr = format!("{}:{}", targets.bind_address, port);
+        if running {
+            return Check::new(name, CheckStatus::Pass, format!("{} is served by this server", addr));
+        }
+        match TcpListener::bind(&addr) {
+            Ok(_) => Check::new(name, CheckStatus::Pass, format!("{} is free", addr)),
+            Err(e) => Check::new(name, CheckStatus::Fail, format!("cannot bind {}: {}", addr, e))
+                .hint(format!("{} ({} to change it)", port_hint(port, &e), env)),
+        }
+    }).collect()
+}
+
+fn check_udp_ports(targets: &DiagnosticsTargets, server: Option<&ServerState>) -> Vec<Check> {
+    targets.udp_ports.iter().map(|&port| {
+        let name = format!("udp_port_{}", port);
+        if let Some(server) = server {
+            return if server.bound_udp_ports.contains(&port) {
+                Check::new(name, CheckStatus::Pass, "bound by the device listener")
+            } else {
+                Check::new(name, CheckStatus::Fail, "configured but not bound, devices sending there are not heard")
+                    .hint("Another program uses the port: stop it and restart the server, or change UDP_LISTEN_PORTS")
+            };
+        }
+        match UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, port))) {
+            Ok(_) => Check::new(name, CheckStatus::Pass, format!("0.0.0.0:{} is free", port)),
+            Err(e) => Check::new(name, CheckStatus::Fail, format!("cannot bind 0.0.0.0:{}: {}", port, e))
+                .hint(format!("{} (UDP_LISTEN_PORTS to change it)", port_hint(port, &e))),
+        }
+    }).collect()
+}
+
+fn check_multicast() -> Check {
+    let interfaces: Vec<Ipv4Addr> = if_addrs::get_if_addrs().unwrap_or_default().into_iter()
+        .filter(|interface| !interface.is_loopback())
+        .filter_map(|interface| match interface.ip() {
+            std::net::IpAddr::V4(ip) => Some(ip),
+            std::net::IpAddr::V6(_) => None,
+        })
+        .collect();
+    if interfaces.is_empty() {
+        return Check::new("multicast", CheckStatus::Fail, "no network interface with an IPv4 address")
+            .hint("Connect the server to the network the devices are on");
+    }
+    let joined = UdpSocket::bind(SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)))
+        .and_then(|socket| socket.join_multicast_v4(&MDNS_GROUP, &Ipv4Addr::UNSPECIFIED));
+    match joined {
+        Ok(()) => Check::new("multicast", CheckStatus::Pass, format!("joined {} (interfaces: {:?})", MDNS_GROUP, interfaces)),
+        Err(e) => Check::new("multicast", CheckStatus::Fail, format!("cannot join {}: {}", MDNS_GROUP, e))
+            .hint("mDNS discovery needs multicast: add a multicast route or allow it in the firewall/container network (host networking for Docker)"),
+    }
+}
+
+fn check_serial_ports(server: Option<&ServerState>) -> Vec<Check> {
+    let ports = match crate::uart_connection::UartConnection::list_ports() {
+        Ok(ports) => ports,
+        Err(e) => return vec![Check::new("serial_ports", CheckStatus::Warn, e)],
+    };
+    if ports.is_empty() {
+        return vec![Check::new("serial_ports", CheckStatus::Skip, "no serial ports found")];
+    }
+    ports.into_iter().map(|port| {
+        let name = format!("serial_port {}", port);
+        if server.and_then(|server| server.uart_port.as_deref()) == Some(port.as_str()) {
+            return Check::new(name, CheckStatus::Pass, "opened by the UART listener");
+        }
+        check_serial_access(name, &port)
+    }).collect()
+}
+
+// Only the permissions are checked: opening a port toggles DTR/RTS, which resets most ESP32 boards
+#[cfg(unix)]
+fn check_serial_access(name: String, port: &str) -> Check {
+    let Ok(path) = std::ffi::CString::new(port) else {
+        return Check::new(name, CheckStatus::Skip, "path is not valid");
+    };
+    // SAFETY: access() only reads the NUL-terminated path
+    if unsafe { libc::access(path.as_ptr(), libc::R_OK | libc::W_OK) } == 0 {
+        Check::new(name, CheckStatus::Pass, "readable and writable")
+    } else {
+        Check::new(name, CheckStatus::Fail, format!("no access: {}", std::io::Error::last_os_error()))
+            .hint("Add the server user to the group owning the port (usually dialout, uucp on Arch) and log in again")
+    }
+}
+
+#[cfg(not(unix))]
+fn check_serial_access(name: String, _port: &str) -> Check {
+    Check::new(name, CheckStatus::Skip, "permissions are only checked on Unix")
+}
+
+fn check_database(database_path: &str) -> Check {
+    let path = Path::new(database_path);
+    let result = if path.exists() {
+        std::fs::OpenOptions::new().write(true).open(path).map(|_| format!("{} is writable", database_path))
+    } else {
+        // Created on first start: the directory has to be writable
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let probe = dir.join(format!(".doctor-{}", uuid::Uuid::new_v4().simple()));
+        std::fs::write(&probe, b"").and_then(|_| std::fs::remove_file(&probe))
+            .map(|_| format!("{} does not exist yet, {} is writable", database_path, dir.display()))
+    };
+    match result {
+        Ok(detail) => Check::new("database", CheckStatus::Pass, detail),
+        Err(e) => Check::new("database", CheckStatus::Fail, format!("{} is not writable: {}", database_path, e))
+            .hint("Give the server user write access to the database file and its directory (SQLite writes a journal next to it), or set DATABASE_PATH"),
+    }
+}
+
+fn check_disk_space(database_path: &str) -> Check {
+    let dir = Path::new(database_path).parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let dir = if dir.exists() { dir } else { Path::new(".") };
+    match free_bytes(dir) {
+        None => Check::new("disk_space", CheckStatus::Skip, "free space is only checked on Unix"),
+        Some(Err(e)) => Check::new("disk_space", CheckStatus::Warn, format!("cannot read free space of {}: {}", dir.display(), e)),
+        Some(Ok(free)) => {
+            let detail = format!("{} MB free in {}", free / (1024 * 1024), dir.display());
+            let hint = "Free up space: old recordings, logs and firmware images live in data/, logs/ and firmware/";
+            if free < DISK_FAIL_BYTES {
+                Check::new("disk_space", CheckStatus::Fail, detail).hint(hint)
+            } else if free < DISK_WARN_BYTES {
+                Check::new("disk_space", CheckStatus::Warn, detail).hint(hint)
+            } else {
+                Check::new("disk_space", CheckStatus::Pass, detail)
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn free_bytes(dir: &Path) -> Option<std::io::Result<u64>> {
+    use std::os::unix::ffi::OsStrExt;
+    let path = std::ffi::CString::new(dir.as_os_str().as_bytes()).ok()?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: statvfs fills the zeroed struct for the NUL-terminated path
+    if unsafe { libc::statvfs(path.as_ptr(), &mut stat) } != 0 {
+        return Some(Err(std::io::Error::last_os_error()));
+    }
+    Some(Ok(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+fn free_bytes(_dir: &Path) -> Option<std::io::Result<u64>> {
+    None
+}
+
+fn check_clock(database_path: &str) -> Check {
+    let modified = std::fs::metadata(database_path).and_then(|meta| meta.modified()).ok();
+    clock_check(Utc::now(), modified)
+}
+
+fn clock_check(now: DateTime<Utc>, database_modified: Option<SystemTime>) -> Check {
+    let floor = Utc.with_ymd_and_hms(CLOCK_FLOOR_YEAR, 1, 1, 0, 0, 0).unwrap();
+    if now < floor {
+        return Check::new("clock", CheckStatus::Fail, format!("system time is {}", now.to_rfc3339()))
+            .hint("Set the clock or enable NTP (timedatectl set-ntp true); devices get their time and events their timestamps from it");
+    }
+    // The database was written "in the future": the clock went backwards since
+    if let Some(modified) = database_modified {
+        let modified = DateTime::<Utc>::from(modified);
+        if modified > now + chrono::Duration::from_std(Duration::from_secs(60)).unwrap() {
+            return Check::new("clock", CheckStatus::Warn, format!("system time {} is before the last database write {}", now.to_rfc3339(), modified.to_rfc3339()))
+                .hint("The clock went backwards (no RTC battery, NTP not synced yet?): timestamps of new events will be out of order");
+        }
+    }
+    Check::new("clock", CheckStatus::Pass, now.to_rfc3339())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_doctor_checks() {
+        // Port taken by another socket
+        let taken = TcpListener::bind("127.0.0.1:0").unwrap();
+        let targets = DiagnosticsTargets {
+            bind_address: "127.0.0.1".to_string(),
+            http_port: taken.local_addr().unwrap().port(),
+            https_port: None,
+            udp_ports: vec![],
+            database_path: std::env::temp_dir().join(format!("doctor-{}.db", uuid::Uuid::new_v4())).to_string_lossy().to_string(),
+        };
+        let report = run(&targets, None);
+        let check = |name: &str| report.checks.iter().find(|check| check.name == name).unwrap().clone();
+        assert_eq!(check("http_port").status, CheckStatus::Fail);
+        assert!(check("http_port").hint.unwrap().contains("HTTP_PORT"));
+        assert!(!report.ok);
+        assert_eq!(check("database").status, CheckStatus::Pass);
+
+        // The running server's own ports are fine, unbound UDP ports are not
+        let server = ServerState { bound_udp_ports: vec![3232], uart_port: None };
+        let targets = DiagnosticsTargets { udp_ports: vec![3232, 3233], ..targets };
+        let statuses: Vec<CheckStatus> = run(&targets, Some(&server)).checks.into_iter()
+            .filter(|check| check.name.ends_with("_port") || check.name.starts_with("udp_port")).map(|check| check.status).collect();
+        assert_eq!(statuses, vec![CheckStatus::Pass, CheckStatus::Pass, CheckStatus::Fail]);
+
+        let now = Utc::now();
+        assert_eq!(clock_check(Utc.with_ymd_and_hms(1970, 1, 1, 0, 5, 0).unwrap(), None).status, CheckStatus::Fail);
+        assert_eq!(clock_check(now, Some((now + chrono::Duration::hours(2)).into())).status, CheckStatus::Warn);
+        assert_eq!(clock_check(now, Some(SystemTime::now())).status, CheckStatus::Pass);
+    }
+}
@@ -0,0 +1,263 @@
+// Documentation pages - markdown from docs/ rendered to JSON for the SPA
+//
+// GET /api/docs/* answers with {title, html, toc, links} when JSON is requested (Accept header or
+// ?format=json), so the docs page can insert the content directly instead of unpacking a full
+// HTML document. Raw HTML in the markdown is escaped and links with other schemes than
+// http(s)/mailto are dropped; relative links are rewritten: other pages to /docs/<page>, files
+// (images, PDFs) to the streaming attachment route /api/docs/<file>.
+
+use std::path::Path;
+
+use pulldown_cmark::{html, CowStr, Event, HeadingLevel, Options, Parser, Tag};
+use serde::Serialize;
+
+/// Directory the documentation is served from
+pub const DOCS_ROOT: &str = "docs";
+
+/// Heading of a page, `id` is the anchor set on the rendered heading
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TocEntry {
+    pub level: u8,
+    pub id: String,
+    pub text: String,
+}
+
+/// Link found on a page after rewriting
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DocLink {
+    pub href: String,
+    /// Target page for links to other documentation pages (e.g. "backend/modules")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub page: Option<String>,
+    pub external: bool,
+}
+
+/// Rendered documentation page (JSON mode of /api/docs)
+#[derive(Debug, Clone, Serialize)]
+pub struct RenderedDoc {
+    pub page: String,
+    pub title: String,
+    pub html: String,
+    pub toc: Vec<TocEntry>,
+    pub links: Vec<DocLink>,
+}
+
+/// Entry of the docs index (GET /api/docs-index)
+#[derive(Debug, Clone, Serialize)]
+pub struct DocPage {
+    pub page: String,
+    pub title: String,
+}
+
+/// Normalize a path below the docs root; None if it leaves the root or touches hidden files
+fn normalize(path: &str) -> Option<String> {
+    let mut parts: Vec<&str> = Vec::new();
+    for part in path.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop()?;
+            }
+            part if part.starts_with('.') => return None,
+            part => parts.push(part),
+        }
+    }
+    Some(parts.join("/"))
+}
+
+/// Page name and markdown file of a request path ("" and "dir/" are README pages, ".md" optional)
+pub fn resolve_page(path: &str) -> Option<(String, String)> {
+    let trimmed = path.trim_start_matches('/');
+    let page = if trimmed.is_empty() || trimmed.ends_with('/') {
+        format!("{}README", trimmed)
+    } else {
+        trimmed.trim_end_matches(".md").to_string()
+    };
+    let page = normalize(&page).filter(|page| !page.is_empty())?;
+    let file = format!("{}/{}.md", DOCS_ROOT, page);
+    Some((page, file))
+}
+
+/// Anchor id of a heading: lowercase words joined with '-', unique within the page
+fn heading_id(text: &str, used: &mut Vec<String>) -> String {
+    let mut id = String::new();
+    for c in text.chars().flat_map(char::to_lowercase) {
+        if c.is_alphanumeric() {
+            id.push(c);
+        } else if (c.is_whitespace() || c == '-' || c == '_') && !id.is_empty() && !id.ends_with('-') {
+            id.push('-');
+        }
+    }
+    let base = match id.trim_end_matches('-') {
+        "" => "section".to_string(),
+        id => id.to_string(),
+    };
+    let mut unique = base.clone();
+    let mut n = 1;
+    while used.contains(&unique) {
+        unique = format!("{}-{}", base, n);
+        n += 1;
+    }
+    used.push(unique.clone());
+    unique
+}
+
+fn level_number(level: HeadingLevel) -> u8 {
+    match level {
+        HeadingLevel::H1 => 1,
+        HeadingLevel::H2 => 2,
+        HeadingLevel::H3 => 3,
+        HeadingLevel::H4 => 4,
+        HeadingLevel::H5 => 5,
+        HeadingLevel::H6 => 6,
+    }
+}
+
+/// Rewrite a link target of `page`; None drops the link (unsafe scheme)
+fn rewrite_link(page: &str, dest: &str) -> Option<DocLink> {
+    let dest = dest.trim();
+    let lower = dest.to_ascii_lowercase();
+    if lower.starts_with("http://") || lower.starts_with("https://") || lower.starts_with("mailto:") {
+        return Some(DocLink { href: dest.to_string(), page: None, external: true });
+    }
+    if dest.starts_with('#') {
+        return Some(DocLink { href: dest.to_string(), page: None, external: false });
+    }
+    // Any other scheme (javascript:, data:, ...) or protocol-relative URL
+    if dest.starts_with("//") || dest.split(['/', '?', '#']).next().is_some_and(|first| first.contains(':')) {
+        return None;
+    }
+    if dest.starts_with('/') {
+        return Some(DocLink { href: dest.to_string(), page: None, external: false });
+    }
+
+    let (target, fragment) = match dest.split_once('#') {
+        Some((target, fragment)) => (target, format!("#{}", fragment)),
+        None => (dest, String::new()),
+    };
+    let dir = page.rsplit_once('/').map(|(dir, _)| dir).unwrap_or("");
+    let joined = if dir.is_empty() { target.to_string() } else { format!("{}/{}", dir, target) };
+    let is_page = target.ends_with(".md") || target.ends_with('/') || Path::new(target).extension().is_none();
+    if is_page {
+        let (page, _) = resolve_page(&joined)?;
+        Some(DocLink { href: format!("/docs/{}{}", page, fragment), page: Some(page), external: false })
+    } else {
+        let file = normalize(&joined)?;
+        Some(DocLink { href: format!("/api/docs/{}", file), page: None, external: false })
+    }
+}
+
+/// Render the markdown of `page` with heading anchors, rewritten links and escaped raw HTML
+pub fn render(page: &str, markdown: &str) -> RenderedDoc {
+    let events: Vec<Event> = Parser::new_ext(markdown, Options::ENABLE_TABLES | Options::ENABLE_STRIKETHROUGH).collect();
+
+    // First pass: heading texts and anchors
+    let mut toc = Vec::new();
+    let mut used = Vec::new();
+    let mut current: Option<(u8, String)> = None;
+    for event in &events {
+        match event {
+            Event::Start(Tag::Heading(level, _, _)) => current = Some((level_number(*level), String::new())),
+            Event::Text(text) | Event::Code(text) => {
+                if let Some((_, heading)) = current.as_mut() {
+                    heading.push_str(text);
+                }
+            }
+            Event::End(Tag::Heading(..)) => {
+                if let Some((level, text)) = current.take() {
+                    let id = heading_id(&text, &mut used);
+                    toc.push(TocEntry { level, id, text: text.trim().to_string() });
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Second pass: sanitize and rewrite
+    let mut links = Vec::new();
+    let mut headings = toc.iter();
+    let mut output = Vec::with_capacity(events.len());
+    for event in events {
+        let event = match event {
+            Event::Start(Tag::Heading(level, _, classes)) => {
+                let id = headings.next().map(|entry| entry.id.as_str());
+                Event::Start(Tag::Heading(level, id, classes))
+            }
+            Event::Html(raw) => Event::Text(raw),
+            Event::Start(Tag::Link(kind, dest, title)) => {
+                let href = rewrite_link(page, &dest).map(|link| {
+                    let href = link.href.clone();
+                    links.push(link);
+                    href
+                });
+                Event::Start(Tag::Link(kind, CowStr::from(href.unwrap_or_else(|| "#".to_string())), title))
+            }
+            Event::End(Tag::Link(kind, _, title)) => Event::End(Tag::Link(kind, CowStr::Borrowed(""), title)),
+            Event::Start(Tag::Image(kind, dest, title)) => {
+                let href = rewrite_link(page, &dest).map(|link| link.href).unwrap_or_default();
+                Event::Start(Tag::Image(kind, CowStr::from(href), title))
+            }
+            event => event,
+        };
+        output.push(event);
+    }
+    let mut html_output = String::new();
+    html::push_html(&mut html_output, output.into_iter());
+
+    let title = toc.iter().find(|entry| entry.level == 1).map(|entry| entry.text.clone())
+        .unwrap_or_else(|| page.rsplit('/').next().unwrap_or(page).to_string());
+    RenderedDoc { page: page.to_string(), title, html: html_output, toc, links }
+}
+
+/// All markdown pages below the docs root, sorted by page name
+pub fn list_pages() -> Vec<DocPage> {
+    fn walk(dir: &Path, prefix: &str, pages: &mut Vec<DocPage>) {
+        let Ok(entries) = std::fs::read_dir(dir) else { return };
+        for entry in entries.flatten() {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if name.starts_with('.') {
+                continue;
+            }
+            let path = entry.path();
+            if path.is_dir() {
+                walk(&path, &format!("{}{}/", prefix, name), pages);
+            } else if let Some(stem) = name.strip_suffix(".md") {
+                let title = std::fs::read_to_string(&path).ok()
+                    .and_then(|content| content.lines().find_map(|line| line.strip_prefix("# ").map(|t| t.trim().to_string())))
+                    .unwrap_or_else(|| stem.to_string());
+                pages.push(DocPage { page: format!("{}{}", prefix, stem), title });
+            }
+        }
+    }
+    let mut pages = Vec::new();
+    walk(Path::new(DOCS_ROOT), "", &mut pages);
+    pages.sort_by(|a, b| a.page.cmp(&b.page));
+    pages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_rewrites_links_and_escapes_html() {
+        let markdown = "# Backend\n\nSee [modules](modules.md#routes), [home](../README.md), \
+            ![diagram](img/arch.png) and [evil](javascript:alert(1)).\n\n<script>alert(1)</script>\n\n## Routes\n\n## Routes\n";
+        let doc = render("backend/README", markdown);
+
+        assert_eq!(doc.title, "Backend");
+        let ids: Vec<&str> = doc.toc.iter().map(|entry| entry.id.as_str()).collect();
+        assert_eq!(ids, ["backend", "routes", "routes-1"]);
+        assert!(doc.html.contains("<h2 id=\"routes-1\">"));
+        assert!(doc.html.contains("href=\"/docs/backend/modules#routes\""));
+        assert!(doc.html.contains("href=\"/docs/README\""));
+        assert!(doc.html.contains("src=\"/api/docs/backend/img/arch.png\""));
+        assert!(!doc.html.contains("javascript:"));
+        assert!(!doc.html.contains("<script>"));
+        assert_eq!(doc.links[0].page.as_deref(), Some("backend/modules"));
+
+        assert_eq!(resolve_page("").unwrap().1, "docs/README.md");
+        assert_eq!(resolve_page("backend/").unwrap().0, "backend/README");
+        assert!(resolve_page("../Cargo").is_none());
+    }
+}
@@ -0,0 +1,462 @@
+// Email - SMTP notification channel (alerts, quarantined devices, password reset)
+//
+// Messages are rendered from templates and written to the email_queue table first; a
+// background worker sends them over SMTP. Connection problems and 4xx replies are retried
+// with exponential backoff, 5xx replies fail the message. Because the queue lives in the
+// database, pending messages also survive a restart. Users choose which notifications
+// they get by email (notification_preferences); password reset mails are always sent.
+
+use std::sync::{Arc, LazyLock};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use lettre::message::{header::ContentType, Mailbox};
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use tokio::sync::Notify;
+use tracing::{debug, info, warn};
+
+use crate::config::EmailConfig;
+use crate::database::{DatabaseManager, NotificationPreferences, QueuedEmail};
+
+/// Attempts per message before it is marked as failed
+pub const MAX_ATTEMPTS: u32 = 10;
+/// Wait before the first retry, doubled for every further one
+const INITIAL_RETRY_DELAY: Duration = Duration::from_secs(30);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(3600);
+/// Queue check without new messages (retries become due in between)
+const POLL_INTERVAL: Duration = Duration::from_secs(15);
+const SMTP_TIMEOUT: Duration = Duration::from_secs(20);
+/// Messages sent per queue run
+const BATCH_SIZE: usize = 20;
+/// Sent and failed messages are kept this long
+const RETENTION: chrono::Duration = chrono::Duration::days(7);
+const PRUNE_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// Signalled when a message was queued, so it goes out without waiting for the next poll
+static QUEUED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Connection security of the SMTP server
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpSecurity {
+    /// Plain connection upgraded with STARTTLS (port 587)
+    StartTls,
+    /// TLS from the first byte (port 465)
+    Tls,
+    /// Unencrypted, only for local relays
+    None,
+}
+
+impl SmtpSecurity {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value.trim().to_lowercase().as_str() {
+            "starttls" => Ok(Self::StartTls),
+            "tls" | "ssl" => Ok(Self::Tls),
+            "none" => Ok(Self::None),
+            other => Err(format!("must be \"starttls\", \"tls\" or \"none\": {}", other)),
+        }
+    }
+}
+
+// ============================================================================
+// TEMPLATES
+// ============================================================================
+
+const ALERT_TRIGGERED_SUBJECT: &str = "[ESP32 Manager] Alert: {rule} ({device})";
+const ALERT_TRIGGERED_BODY: &str = "Hello {name},
+
+your alert rule \"{rule}\" is firing for device {device}:
+
+    {message}
+
+Device: {link}
+
+You can change your email notifications in your profile settings.
+";
+
+const ALERT_RESOLVED_SUBJECT: &str = "[ESP32 Manager] Resolved: {rule} ({device})";
+const ALERT_RESOLVED_BODY: &str = "Hello {name},
+
+the alert \"{rule}\" for device {device} is resolved:
+
+    {message}
+
+Device: {link}
+
+You can change your email notifications in your profile settings.
+";
+
+const DEVICE_QUARANTINED_SUBJECT: &str = "[ESP32 Manager] Device quarantined: {device}";
+const DEVICE_QUARANTINED_BODY: &str = "Hello {name},
+
+device {device} was quarantined:
+
+    {reason}
+
+Its data is no longer forwarded, except an occasional message for diagnostics. Check the
+device and release it on the device page once it behaves again:
+
+Device: {link}
+
+You can change your email notifications in your profile settings.
+";
+
+const PASSWORD_RESET_SUBJECT: &str = "[ESP32 Manager] Reset your password";
+const PASSWORD_RESET_BODY: &str = "Hello {name},
+
+someone (hopefully you) requested a new password for your account.
+Open this link within {minutes} minutes to choose a new password:
+
+    {link}
+
+If you did not request this, you can ignore this email; your password stays unchanged.
+";
+
+const TEST_SUBJECT: &str = "[ESP32 Manager] Test email";
+const TEST_BODY: &str = "Hello {name},
+
+this is a test email. Email notifications are working.
+";
+
+/// Kind of message, decides which preference applies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotificationKind {
+    AlertTriggered,
+    AlertResolved,
+    DeviceQuarantined,
+}
+
+impl NotificationKind {
+    fn enabled_in(&self, preferences: &NotificationPreferences) -> bool {
+        match self {
+            Self::AlertTriggered => preferences.email_alerts,
+            Self::AlertResolved => preferences.email_alerts && preferences.email_alert_resolved,
+            Self::DeviceQuarantined => preferences.email_alerts,
+        }
+    }
+}
+
+/// Email contents; `{name}` is filled with the recipient's display name
+#[derive(Debug, Clone)]
+pub enum EmailTemplate {
+    AlertTriggered { rule: String, device_id: String, message: String, link: String },
+    AlertResolved { rule: String, device_id: String, message: String, link: String },
+    DeviceQuarantined { device_id: String, reason: String, link: String },
+    PasswordReset { link: String, valid_minutes: i64 },
+    Test,
+}
+
+impl EmailTemplate {
+    /// Subject and plain text body
+    pub fn render(&self, recipient_name: &str) -> (String, String) {
+        let (subject, body, values) = match self {
+            Self::AlertTriggered { rule, device_id, message, link } => (
+                ALERT_TRIGGERED_SUBJECT,
+                ALERT_TRIGGERED_BODY,
+                vec![("rule", rule.clone()), ("device", device_id.clone()), ("message", message.clone()), ("link", link.clone())],
+            ),
+            Self::AlertResolved { rule, device_id, message, link } => (
+                ALERT_RESOLVED_SUBJECT,
+                ALERT_RESOLVED_BODY,
+                vec![("rule", rule.clone()), ("device", device_id.clone()), ("message", message.clone()), ("link", link.clone())],
+            ),
+            Self::DeviceQuarantined { device_id, reason, link } => (
+                DEVICE_QUARANTINED_SUBJECT,
+                DEVICE_QUARANTINED_BODY,
+                vec![("device", device_id.clone()), ("reason", reason.clone()), ("link", link.clone())],
+            ),
+            Self::PasswordReset { link, valid_minutes } => (
+                PASSWORD_RESET_SUBJECT,
+                PASSWORD_RESET_BODY,
+                vec![("link", link.clone()), ("minutes", valid_minutes.to_string())],
+            ),
+            Self::Test => (TEST_SUBJECT, TEST_BODY, Vec::new()),
+        };
+
+        let mut values = values;
+        values.push(("name", recipient_name.to_string()));
+        // User-controlled values (rule names) must not break the subject line
+        let subject = fill(subject, &values).replace(['\r', '\n'], " ");
+        (subject, fill(body, &values))
+    }
+}
+
+/// Replace `{key}` placeholders in one pass (inserted values are not expanded again)
+fn fill(template: &str, values: &[(&str, String)]) -> String {
+    let mut result = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        result.push_str(&rest[..start]);
+        let after = &rest[start + 1..];
+        match after.find('}').and_then(|end| values.iter().find(|(key, _)| *key == &after[..end]).map(|(_, v)| (end, v))) {
+            Some((end, value)) => {
+                result.push_str(value);
+                rest = &after[end + 1..];
+            }
+            None => {
+                result.push('{');
+                rest = after;
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Delay before retry number `attempt` (1 = first retry)
+fn retry_delay(attempt: u32) -> Duration {
+    INITIAL_RETRY_DELAY
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_RETRY_DELAY)
+}
+
+// ============================================================================
+// MAILER
+// ============================================================================
+
+/// Queues emails and sends them over SMTP; without [email] enabled nothing is queued
+pub struct Mailer {
+    db: Arc<DatabaseManager>,
+    transport: Option<(AsyncSmtpTransport<Tokio1Executor>, Mailbox)>,
+    public_url: String,
+}
+
+impl Mailer {
+    pub fn new(db: Arc<DatabaseManager>, config: &EmailConfig) -> Result<Self, String> {
+        let public_url = config.public_url.trim_end_matches('/').to_string();
+        if !config.enabled {
+            return Ok(Self { db, transport: None, public_url });
+        }
+
+        let from: Mailbox = config.from_address.parse()
+            .map_err(|e| format!("email.from_address: {}", e))?;
+        let host = config.smtp_host.trim();
+        let builder = match SmtpSecurity::parse(&config.smtp_security)? {
+            SmtpSecurity::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host),
+            SmtpSecurity::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(host),
+            SmtpSecurity::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)),
+        }
+        .map_err(|e| format!("email.smtp_host: {}", e))?;
+
+        let mut builder = builder.port(config.smtp_port).timeout(Some(SMTP_TIMEOUT));
+        if !config.smtp_username.is_empty() {
+            builder = builder.credentials(Credentials::new(config.smtp_username.clone(), config.smtp_password.clone()));
+        }
+        Ok(Self { db, transport: Some((builder.build(), from)), public_url })
+    }
+
+    /// SMTP configured ([email] enabled = true), regardless of offline mode
+    pub fn is_configured(&self) -> bool {
+        self.transport.is_some()
+    }
+
+    /// SMTP configured and not switched off by offline mode
+    pub fn is_enabled(&self) -> bool {
+        self.is_configured() && !crate::outbound::is_offline()
+    }
+
+    /// Absolute URL of an app path for links in emails, e.g. "/devices/AA-01"
+    pub fn link(&self, path: &str) -> String {
+        format!("{}{}{}", self.public_url, crate::proxy::base_path(), path)
+    }
+
+    /// Run the send queue in the background
+    pub fn start(self: Arc<Self>) {
+        if let Err(e) = crate::outbound::check(crate::outbound::OutboundFeature::Email) {
+            info!("Email notifications disabled: {}", e);
+            return;
+        }
+        if !self.is_enabled() {
+            info!("Email notifications disabled ([email] enabled = false)");
+            return;
+        }
+        tokio::spawn(async move {
+            let mut last_prune: Option<Instant> = None;
+            loop {
+                self.process_queue().await;
+                if last_prune.is_none_or(|at| at.elapsed() >= PRUNE_INTERVAL) {
+                    match self.db.prune_email_queue(Utc::now() - RETENTION).await {
+                        Ok(0) | Err(_) => {}
+                        Ok(removed) => debug!("Removed {} old emails from the queue", removed),
+                    }
+                    last_prune = Some(Instant::now());
+                }
+                tokio::select! {
+                    _ = tokio::time::sleep(POLL_INTERVAL) => {}
+                    _ = QUEUED.notified() => {}
+                }
+            }
+        });
+        info!("Email send queue started");
+    }
+
+    /// Render a template and queue it for `recipient`
+    pub async fn send(&self, recipient: &str, recipient_name: &str, template: &EmailTemplate) -> Result<(), String> {
+        if !self.is_enabled() {
+            return Err("email notifications are not configured".to_string());
+        }
+        recipient.parse::<Mailbox>().map_err(|e| format!("invalid recipient {}: {}", recipient, e))?;
+
+        let (subject, body) = template.render(recipient_name);
+        let now = Utc::now();
+        let email = QueuedEmail {
+            id: uuid::Uuid::new_v4().to_string(),
+            recipient: recipient.to_string(),
+            subject,
+            body,
+            status: "pending".to_string(),
+            attempts: 0,
+            next_attempt_at: now,
+            last_error: None,
+            created_at: now,
+            sent_at: None,
+        };
+        self.db.enqueue_email(&email).await.map_err(|e| format!("failed to queue email: {}", e))?;
+        QUEUED.notify_one();
+        Ok(())
+    }
+
+    /// Email a user if their notification preferences allow this kind of message
+    pub async fn notify_user(&self, user_id: &str, kind: NotificationKind, template: &EmailTemplate) {
+        // The guest account has no mailbox
+        if !self.is_enabled() || user_id == "guest" {
+            return;
+        }
+        let user = match self.db.get_user_by_id(user_id).await {
+            Ok(Some(user)) => user,
+            Ok(None) => return,
+            Err(e) => {
+                warn!("Failed to load user {} for an email notification: {}", user_id, e);
+                return;
+            }
+        };
+        match self.db.get_notification_preferences(user_id).await {
+            Ok(preferences) if kind.enabled_in(&preferences) => {}
+            Ok(_) => return,
+            Err(e) => {
+                warn!("Failed to load notification preferences of {}: {}", user_id, e);
+                return;
+            }
+        }
+        if let Err(e) = self.send(&user.email, &user.display_name, template).await {
+            warn!("Email notification for {} not queued: {}", user_id, e);
+        }
+    }
+
+    /// Send all due messages once
+    async fn process_queue(&self) {
+        let due = match self.db.due_emails(Utc::now(), BATCH_SIZE).await {
+            Ok(due) => due,
+            Err(e) => {
+                warn!("Failed to read the email queue: {}", e);
+                return;
+            }
+        };
+        for mut email in due {
+            self.attempt(&mut email).await;
+            if let Err(e) = self.db.update_queued_email(&email).await {
+                warn!("Failed to update queued email {}: {}", email.id, e);
+            }
+        }
+    }
+
+    /// One delivery attempt; updates status, attempts and the next retry time
+    async fn attempt(&self, email: &mut QueuedEmail) {
+        let Some((transport, from)) = &self.transport else { return };
+        email.attempts += 1;
+
+        let message = email.recipient.parse::<Mailbox>()
+            .map_err(|e| e.to_string())
+            .and_then(|to| {
+                Message::builder()
+                    .from(from.clone())
+                    .to(to)
+                    .subject(email.subject.clone())
+                    .header(ContentType::TEXT_PLAIN)
+                    .body(email.body.clone())
+                    .map_err(|e| e.to_string())
+            });
+        let message = match message {
+            Ok(message) => message,
+            Err(e) => {
+                warn!("Email {} to {} cannot be built: {}", email.id, email.recipient, e);
+                email.status = "failed".to_string();
+                email.last_error = Some(e);
+                return;
+            }
+        };
+
+        match transport.send(message).await {
+            Ok(_) => {
+                debug!("Email {} sent to {}", email.id, email.recipient);
+                email.status = "sent".to_string();
+                email.last_error = None;
+                email.sent_at = Some(Utc::now());
+            }
+            Err(e) => {
+                email.last_error = Some(e.to_string());
+                // 5xx = the server will never accept it; everything else may be temporary
+                if e.is_permanent() || email.attempts >= MAX_ATTEMPTS {
+                    warn!("Email {} to {} failed after {} attempt(s): {}", email.id, email.recipient, email.attempts, e);
+                    email.status = "failed".to_string();
+                } else {
+                    let delay = retry_delay(email.attempts);
+                    debug!("Email {} to {} failed ({}), retry in {:?}", email.id, email.recipient, e, delay);
+                    email.next_attempt_at = Utc::now() + chrono::Duration::from_std(delay).unwrap_or(chrono::Duration::zero());
+                }
+            }
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_templates_and_retry_on_unreachable_server() {
+        let (subject, body) = EmailTemplate::AlertTriggered {
+            rule: "Hot\r\nBcc: x@example.com {name}".to_string(),
+            device_id: "AA-01".to_string(),
+            message: "variable temp > 80 (value 91)".to_string(),
+            link: "https://esp32.example.org/devices/AA-01".to_string(),
+        }.render("Ada");
+        // Values are inserted once: no header injection, no placeholder expansion inside values
+        assert_eq!(subject, "[ESP32 Manager] Alert: Hot  Bcc: x@example.com {name} (AA-01)");
+        assert!(body.starts_with("Hello Ada,"));
+        assert!(body.contains("    variable temp > 80 (value 91)\n"));
+        assert_eq!(fill("{a} {unknown} {", &[("a", "1".to_string())]), "1 {unknown} {");
+        assert_eq!(retry_delay(1), Duration::from_secs(30));
+        assert_eq!(retry_delay(3), Duration::from_secs(120));
+        assert_eq!(retry_delay(40), MAX_RETRY_DELAY);
+
+        // Nothing listens on port 1: the message stays queued for a retry
+        let db = Arc::new(DatabaseManager::new_memory().await.unwrap());
+        let config = EmailConfig {
+            enabled: true,
+            smtp_host: "127.0.0.1".to_string(),
+            smtp_port: 1,
+            smtp_security: "none".to_string(),
+            smtp_username: String::new(),
+            smtp_password: String::new(),
+            from_address: "ESP32 Manager <esp32@example.org>".to_string(),
+            public_url: "https://esp32.example.org/".to_string(),
+        };
+        let mailer = Mailer::new(db.clone(), &config).unwrap();
+        assert_eq!(mailer.link("/devices/AA-01"), "https://esp32.example.org/devices/AA-01");
+        assert!(mailer.send("not an address", "Ada", &EmailTemplate::Test).await.is_err());
+        mailer.send("ada@example.org", "Ada", &EmailTemplate::Test).await.unwrap();
+
+        mailer.process_queue().await;
+        assert!(db.due_emails(Utc::now(), 10).await.unwrap().is_empty());
+        let retry = db.due_emails(Utc::now() + chrono::Duration::seconds(31), 10).await.unwrap();
+        assert_eq!(retry.len(), 1);
+        assert_eq!(retry[0].status, "pending");
+        assert_eq!(retry[0].attempts, 1);
+        assert!(retry[0].last_error.is_some());
+    }
+}
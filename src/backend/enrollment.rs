@@ -0,0 +1,241 @@
+// Enrollment tokens - pre-shared per-device tokens for automatic adoption
+//
+// A user mints a token for one device (POST /api/enrollment-tokens with its MAC) and builds it
+// into the firmware. The board presents it in its mDNS TXT record (`enroll=<token>`) or in its
+// hello message (`"enrollToken": "<token>"`). A valid, unused and unexpired token minted for
+// exactly that device adopts it for the token's owner (and organization), as if the owner had
+// called POST /api/devices/adopt. Boards without a token, or with a wrong one, stay Discovered
+// and have to be adopted by hand, so a rogue board on the lab network never shows up as trusted.
+// Tokens are single use and only their SHA-256 hash is stored.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::database::{DatabaseManager, Device};
+use crate::device_lifecycle::SharedLifecycle;
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+
+/// mDNS TXT key carrying the token
+pub const TXT_KEY: &str = "enroll";
+
+/// Hello message field carrying the token
+pub const MESSAGE_FIELD: &str = "enrollToken";
+
+/// Validity of a new token: default and maximum
+pub const DEFAULT_VALIDITY_HOURS: i64 = 7 * 24;
+pub const MAX_VALIDITY_HOURS: i64 = 90 * 24;
+
+/// Body of POST /api/enrollment-tokens
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateEnrollmentTokenRequest {
+    /// MAC address of the board the token is for
+    pub device_id: String,
+    #[serde(default)]
+    pub expires_in_hours: Option<i64>,
+}
+
+/// Stored token (the secret itself is only returned when it is minted)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnrollmentToken {
+    pub id: String,
+    /// Device the token is bound to (MAC in key format, AA-BB-CC-DD-EE-FF)
+    pub device_id: String,
+    pub owner_id: String,
+    /// Organization the device joins on enrollment
+    pub org_id: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub used_at: Option<DateTime<Utc>>,
+}
+
+impl EnrollmentToken {
+    /// New token for a device and its secret
+    pub fn mint(device_id: &str, owner_id: &str, org_id: Option<String>, validity_hours: i64) -> (Self, String) {
+        let now = Utc::now();
+        let token = Self {
+            id: uuid::Uuid::new_v4().to_string(),
+            device_id: device_id.to_string(),
+            owner_id: owner_id.to_string(),
+            org_id,
+            created_at: now,
+            expires_at: now + Duration::hours(validity_hours.clamp(1, MAX_VALIDITY_HOURS)),
+            used_at: None,
+        };
+        let secret = format!("{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple());
+        (token, secret)
+    }
+
+    /// Check that the token may enroll `device_id` now
+    pub fn check(&self, device_id: &str, now: DateTime<Utc>) -> Result<(), String> {
+        if self.device_id != device_id {
+            return Err(format!("token was minted for {}", self.device_id));
+        }
+        if self.used_at.is_some() {
+            return Err("token was already used".to_string());
+        }
+        if self.expires_at <= now {
+            return Err("token expired".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Stored form of a token secret
+pub fn hash_token(secret: &str) -> String {
+    hex::encode(Sha256::digest(secret.trim().as_bytes()))
+}
+
+/// Token and announced name of a hello message that carries a token
+#[derive(Debug, Clone, PartialEq)]
+pub struct HelloToken {
+    pub secret: String,
+    pub device_name: Option<String>,
+}
+
+impl HelloToken {
+    pub fn parse(message: &str) -> Option<Self> {
+        if !message.contains(MESSAGE_FIELD) {
+            return None;
+        }
+        let value: Value = serde_json::from_str(message).ok()?;
+        let text = |key: &str| value.get(key).and_then(Value::as_str).map(str::trim).filter(|t| !t.is_empty()).map(str::to_string);
+        Some(Self { secret: text(MESSAGE_FIELD)?, device_name: text("deviceName") })
+    }
+}
+
+/// Result of presenting a token
+#[derive(Debug, Clone, PartialEq)]
+pub enum Enrollment {
+    /// The device now belongs to the token owner
+    Adopted { owner_id: String },
+    /// The device was enrolled with this token before (repeated mDNS/hello announcements)
+    AlreadyEnrolled,
+}
+
+/// Adopt `device_id` if `secret` is a valid token for it
+pub async fn enroll(
+    db: &DatabaseManager,
+    lifecycle: &SharedLifecycle,
+    device_id: &str,
+    secret: &str,
+    device_name: Option<&str>,
+    ip_address: Option<String>,
+) -> Result<Enrollment, String> {
+    let token = db.get_enrollment_token_by_hash(&hash_token(secret)).await
+        .map_err(|e| format!("Database error: {}", e))?
+        .ok_or("unknown token")?;
+    let device = db.get_device_by_id(device_id).await.map_err(|e| format!("Database error: {}", e))?;
+    if token.used_at.is_some() && token.device_id == device_id && device.as_ref().is_some_and(|d| d.owner_id == token.owner_id) {
+        return Ok(Enrollment::AlreadyEnrolled);
+    }
+    token.check(device_id, Utc::now())?;
+
+    let db_error = |e: Box<dyn std::error::Error>| format!("Database error: {}", e);
+    match device {
+        Some(existing) if existing.owner_id == token.owner_id => {}
+        Some(existing) if existing.owner_id == "guest" => {
+            db.transfer_device_owner(device_id, &token.owner_id).await.map_err(db_error)?;
+        }
+        Some(_) => return Err("device is already owned by another user".to_string()),
+        None => {
+            let name = device_name.filter(|name| name.len() <= 100).map(str::to_string)
+                .unwrap_or_else(|| format!("ESP32 {}", device_id));
+            let mut device = Device::new(name, token.owner_id.clone(), device_id.to_string());
+            device.ip_address = ip_address;
+            db.create_device(device).await.map_err(db_error)?;
+        }
+    }
+    if let Some(org_id) = &token.org_id {
+        db.set_device_org(device_id, Some(org_id)).await.map_err(db_error)?;
+    }
+    // Spent even if two announcements race: only one of them marks it
+    if !db.mark_enrollment_token_used(&token.id).await.map_err(db_error)? {
+        return Ok(Enrollment::AlreadyEnrolled);
+    }
+    lifecycle.adopted(device_id).await;
+    info!("Device {} enrolled with a pre-shared token of user {}", device_id, token.owner_id);
+    Ok(Enrollment::Adopted { owner_id: token.owner_id })
+}
+
+/// Follows the event feed and enrolls devices that present a token in their hello message
+pub struct EnrollmentWatcher {
+    db: Arc<DatabaseManager>,
+    lifecycle: SharedLifecycle,
+}
+
+impl EnrollmentWatcher {
+    pub fn new(db: Arc<DatabaseManager>, lifecycle: SharedLifecycle) -> Self {
+        Self { db, lifecycle }
+    }
+
+    pub fn start(self, device_store: SharedDeviceStore) {
+        let mut feed = device_store.subscribe_events();
+        tokio::spawn(async move {
+            // Devices handled since startup; their hellos are not looked up again
+            let mut enrolled = HashSet::new();
+            loop {
+                match feed.recv().await {
+                    Ok(feed_event) => self.handle_event(&mut enrolled, &feed_event.device_id, &feed_event.event).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Enrollment watcher lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle_event(&self, enrolled: &mut HashSet<String>, device_id: &str, event: &DeviceEvent) {
+        let DeviceEvent::DeviceUdpBroadcast { message, from_ip, .. } = event else { return };
+        if enrolled.contains(device_id) {
+            return;
+        }
+        let Some(hello) = HelloToken::parse(message) else { return };
+        let ip_address = Some(from_ip.clone()).filter(|ip| !ip.is_empty() && ip != "0.0.0.0");
+        match enroll(&self.db, &self.lifecycle, device_id, &hello.secret, hello.device_name.as_deref(), ip_address).await {
+            Ok(_) => {
+                enrolled.insert(device_id.to_string());
+            }
+            Err(e) => warn!("Enrollment of device {} refused: {}", device_id, e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_token_checks_and_hello_field() {
+        let (token, secret) = EnrollmentToken::mint("AA-BB-CC-DD-EE-01", "user-1", None, 24);
+        assert_eq!(secret.len(), 64);
+        assert_eq!(hash_token(&secret), hash_token(&format!(" {}\n", secret)));
+        assert_ne!(hash_token(&secret), secret);
+
+        let now = Utc::now();
+        assert!(token.check("AA-BB-CC-DD-EE-01", now).is_ok());
+        assert!(token.check("AA-BB-CC-DD-EE-02", now).unwrap_err().contains("minted for"));
+        assert!(token.check("AA-BB-CC-DD-EE-01", now + Duration::hours(25)).unwrap_err().contains("expired"));
+        let used = EnrollmentToken { used_at: Some(now), ..token.clone() };
+        assert!(used.check("AA-BB-CC-DD-EE-01", now).is_err());
+
+        // Validity is clamped
+        let (long, _) = EnrollmentToken::mint("AA-BB-CC-DD-EE-01", "user-1", None, 100_000);
+        assert!(long.expires_at <= Utc::now() + Duration::hours(MAX_VALIDITY_HOURS));
+
+        let hello = format!(r#"{{"deviceId": "AA-BB-CC-DD-EE-01", "deviceName": "Lamp", "enrollToken": "{}"}}"#, secret);
+        assert_eq!(HelloToken::parse(&hello), Some(HelloToken { secret, device_name: Some("Lamp".to_string()) }));
+        assert_eq!(HelloToken::parse(r#"{"enrollToken": " "}"#), None);
+        assert_eq!(HelloToken::parse(r#"{"resetReason": "poweron"}"#), None);
+    }
+}
@@ -0,0 +1,200 @@
+// ESP-NOW peers - mesh nodes behind a connected device shown as child devices
+//
+// A device that talks ESP-NOW can list the peers it hears in any status payload:
+//
+//   {"espnowPeers": [{"mac": "A4:CF:12:34:56:78", "rssi": -58, "channel": 6, "data": {"temp": 21.5}}]}
+//
+// Every peer becomes a child device `<parent>~<MAC>` (same ID scheme as UART gateway nodes,
+// uart_gateway.rs): it is discovered and stored like a UART device, goes online while it is
+// reported and gets the peer entry as broadcast on its own channel. A peer missing from the next
+// report, or all peers of a parent that disconnects, go offline. Children have no transport of
+// their own, so commands for them are refused (DeviceManager::transport_for).
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::database::DatabaseManager;
+use crate::device_lifecycle::SharedLifecycle;
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+use crate::uart_gateway;
+
+/// Connection type stored for child devices
+pub const CONNECTION_TYPE: &str = "espnow";
+
+/// One peer of a report: its child device ID and the entry as the device sent it
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerEntry {
+    pub device_id: String,
+    pub mac: String,
+    pub entry: Value,
+}
+
+/// Peers listed in a device message, None if the message carries no `espnowPeers` array.
+/// Entries without a usable MAC are skipped.
+pub fn parse_peers(parent_id: &str, message: &str) -> Option<Vec<PeerEntry>> {
+    let value: Value = serde_json::from_str(message).ok()?;
+    let peers = value.get("espnowPeers")?.as_array()?;
+    Some(peers.iter().filter_map(|entry| {
+        let mac = entry.get("mac")?.as_str()?.trim().to_uppercase().replace('-', ":");
+        let device_id = uart_gateway::virtual_device_id(parent_id, &mac).ok()?;
+        Some(PeerEntry { device_id, mac, entry: entry.clone() })
+    }).collect())
+}
+
+/// Children each parent reported last time
+#[derive(Debug, Default)]
+struct Topology {
+    children: HashMap<String, HashSet<String>>,
+}
+
+impl Topology {
+    /// Store the children of a new report and return those that were dropped from it
+    fn update(&mut self, parent_id: &str, reported: HashSet<String>) -> Vec<String> {
+        let previous = self.children.insert(parent_id.to_string(), reported).unwrap_or_default();
+        let current = &self.children[parent_id];
+        let mut dropped: Vec<String> = previous.difference(current).cloned().collect();
+        dropped.sort();
+        dropped
+    }
+
+    /// Forget a parent (its link went down) and return its children
+    fn remove(&mut self, parent_id: &str) -> Vec<String> {
+        let mut children: Vec<String> = self.children.remove(parent_id).unwrap_or_default().into_iter().collect();
+        children.sort();
+        children
+    }
+}
+
+/// Follows the event feed and keeps the child devices of ESP-NOW peer reports up to date
+pub struct EspNowPeerTracker {
+    db: Arc<DatabaseManager>,
+    lifecycle: SharedLifecycle,
+    device_store: SharedDeviceStore,
+}
+
+impl EspNowPeerTracker {
+    pub fn new(db: Arc<DatabaseManager>, lifecycle: SharedLifecycle, device_store: SharedDeviceStore) -> Self {
+        Self { db, lifecycle, device_store }
+    }
+
+    pub fn start(self) {
+        let mut feed = self.device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut topology = Topology::default();
+            loop {
+                match feed.recv().await {
+                    Ok(feed_event) => self.handle_event(&mut topology, &feed_event.device_id, &feed_event.event).await,
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("ESP-NOW peer tracker lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+    }
+
+    async fn handle_event(&self, topology: &mut Topology, device_id: &str, event: &DeviceEvent) {
+        let message = match event {
+            DeviceEvent::DeviceConnectionStatus { connected: false, .. } => {
+                for child_id in topology.remove(device_id) {
+                    self.peer_lost(&child_id, &format!("Parent {} disconnected", device_id)).await;
+                }
+                return;
+            }
+            DeviceEvent::DeviceUdpBroadcast { message, .. } => message,
+            _ => return,
+        };
+        let Some(peers) = parse_peers(device_id, message) else { return };
+
+        for peer in &peers {
+            self.peer_reported(device_id, peer).await;
+        }
+        let reported = peers.into_iter().map(|peer| peer.device_id).collect();
+        for child_id in topology.update(device_id, reported) {
+            self.peer_lost(&child_id, &format!("No longer reported by {}", device_id)).await;
+        }
+    }
+
+    async fn peer_reported(&self, parent_id: &str, peer: &PeerEntry) {
+        if !self.lifecycle.is_known(&peer.device_id).await {
+            info!("ESP-NOW peer {} discovered through {}", peer.mac, parent_id);
+            self.lifecycle.discovered(&peer.device_id, &format!("ESP-NOW peer of {}", parent_id)).await;
+            if let Err(e) = self.db.upsert_discovered_device(
+                peer.device_id.clone(),
+                format!("ESPNOW-{}", peer.mac),
+                None,
+                Some(CONNECTION_TYPE.to_string()),
+            ).await {
+                warn!("Failed to save ESP-NOW peer {} to database: {}", peer.device_id, e);
+            }
+            let discovery_event = DeviceEvent::device_discovered(
+                peer.device_id.clone(),
+                "0.0.0.0".to_string(),
+                0,
+                0,
+                chrono::Utc::now().to_rfc3339(),
+                Some(peer.mac.clone()),
+                Some(format!("espnow-{}", parent_id)),
+            );
+            self.add_event("system", discovery_event, "espnow_discovery").await;
+        }
+
+        if self.lifecycle.link_up(&peer.device_id, &format!("Reported by {}", parent_id)).await {
+            let status = DeviceEvent::device_connection_status(peer.device_id.clone(), true, "0.0.0.0".to_string(), 0, 0);
+            self.add_event(&peer.device_id, status, "espnow_connect").await;
+        }
+        let broadcast = DeviceEvent::device_udp_broadcast(peer.device_id.clone(), peer.entry.to_string(), "0.0.0.0".to_string(), 0);
+        self.add_event(&peer.device_id, broadcast, "espnow_message").await;
+    }
+
+    async fn peer_lost(&self, child_id: &str, reason: &str) {
+        if self.lifecycle.link_down(child_id, reason).await {
+            let status = DeviceEvent::device_connection_status(child_id.to_string(), false, "0.0.0.0".to_string(), 0, 0);
+            self.add_event(child_id, status, "espnow_disconnect").await;
+        }
+    }
+
+    async fn add_event(&self, device_id: &str, event: DeviceEvent, source: &str) {
+        if let Err(e) = self.device_store.add_event(
+            device_id.to_string(),
+            event,
+            "device_system".to_string(),
+            source.to_string(),
+        ).await {
+            warn!("Failed to send ESP-NOW peer event for {}: {}", device_id, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_reports_and_topology() {
+        let message = r#"{"uptime": 12, "espnowPeers": [
+            {"mac": "a4-cf-12-34-56-78", "rssi": -58, "data": {"temp": 21.5}},
+            {"mac": "A4:CF:12:34:56:79"},
+            {"rssi": -90}
+        ]}"#;
+        let peers = parse_peers("AA-BB-CC-DD-EE-01", message).unwrap();
+        assert_eq!(peers.len(), 2);
+        assert_eq!(peers[0].device_id, "AA-BB-CC-DD-EE-01~A4:CF:12:34:56:78");
+        assert_eq!(peers[0].entry["data"]["temp"], 21.5);
+        assert!(parse_peers("AA-BB-CC-DD-EE-01", r#"{"uptime": 12}"#).is_none());
+        // Peers of a child are not nested further
+        assert!(parse_peers(&peers[0].device_id, message).unwrap().is_empty());
+
+        let mut topology = Topology::default();
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<HashSet<_>>();
+        assert!(topology.update("P", ids(&["P~1", "P~2"])).is_empty());
+        assert_eq!(topology.update("P", ids(&["P~2", "P~3"])), vec!["P~1".to_string()]);
+        assert_eq!(topology.remove("P"), vec!["P~2".to_string(), "P~3".to_string()]);
+        assert!(topology.remove("P").is_empty());
+    }
+}
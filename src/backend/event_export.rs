@@ -0,0 +1,180 @@
+// Event export - renders the stored event history of a device as CSV or NDJSON downloads
+
+use serde_json::Value;
+
+use crate::events::{DeviceEvent, EventWithMetadata};
+
+/// Rows per body chunk when streaming an export
+pub const EXPORT_CHUNK_ROWS: usize = 256;
+
+/// Column order of the CSV export
+const CSV_HEADER: &str = "timestamp,time,event_id,user_id,event,name,value,data,device_time,device_uptime_ms\n";
+
+// ============================================================================
+// EXPORT FORMAT
+// ============================================================================
+
+/// File format of an event export
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl ExportFormat {
+    /// `csv` (default), `json` or `ndjson`
+    pub fn parse(value: Option<&str>) -> Option<Self> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("") | Some("csv") => Some(ExportFormat::Csv),
+            Some("json") | Some("ndjson") => Some(ExportFormat::Ndjson),
+            _ => None,
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Ndjson => "application/x-ndjson",
+        }
+    }
+
+    pub fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Ndjson => "ndjson",
+        }
+    }
+
+    /// Text written before the first row
+    pub fn header(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => CSV_HEADER,
+            ExportFormat::Ndjson => "",
+        }
+    }
+
+    /// One exported event including the trailing newline
+    pub fn row(&self, event: &EventWithMetadata) -> String {
+        match self {
+            ExportFormat::Csv => csv_row(event),
+            ExportFormat::Ndjson => {
+                let mut line = serde_json::to_string(event).unwrap_or_else(|_| "{}".to_string());
+                line.push('\n');
+                line
+            }
+        }
+    }
+}
+
+/// Download name for an export, e.g. `AA-BB-CC-DD-EE-FF-events.csv`
+pub fn export_file_name(device_id: &str, format: ExportFormat) -> String {
+    let safe_id: String = device_id.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '-' })
+        .collect();
+    format!("{}-events.{}", safe_id, format.file_extension())
+}
+
+/// Time bound of an export: milliseconds since epoch or RFC 3339
+pub fn parse_time_bound(value: &str) -> Option<i64> {
+    let value = value.trim();
+    if let Ok(ms) = value.parse::<i64>() {
+        return Some(ms);
+    }
+    chrono::DateTime::parse_from_rfc3339(value).ok().map(|t| t.timestamp_millis())
+}
+
+// ============================================================================
+// CSV
+// ============================================================================
+
+/// Measurement name and value of an event, so spreadsheets get plottable columns
+fn measurement(event: &DeviceEvent) -> (String, String) {
+    match event {
+        DeviceEvent::DeviceVariableUpdate { variable_name, variable_value, .. } => (variable_name.clone(), variable_value.clone()),
+        DeviceEvent::DeviceSensorData { sensor, value, .. } => (sensor.clone(), plain_value(value)),
+        DeviceEvent::DeviceStatusUpdate { status, .. } => ("status".to_string(), status.clone()),
+        _ => (String::new(), String::new()),
+    }
+}
+
+/// JSON strings without quotes, everything else as JSON text
+fn plain_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+/// Quote a CSV field if it contains separators, quotes or line breaks (RFC 4180)
+pub(crate) fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(event: &EventWithMetadata) -> String {
+    let data = serde_json::to_value(&event.event).unwrap_or(Value::Null);
+    let event_name = data.get("event").and_then(Value::as_str).unwrap_or_default().to_string();
+    let (name, value) = measurement(&event.event);
+    let time = chrono::DateTime::from_timestamp_millis(event.timestamp)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default();
+
+    let device_time = event.device_time.and_then(|t| t.device_time)
+        .and_then(chrono::DateTime::from_timestamp_millis)
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default();
+    let device_uptime = event.device_time.and_then(|t| t.uptime_ms).map(|ms| ms.to_string()).unwrap_or_default();
+
+    let fields = [
+        event.timestamp.to_string(), time, event.id.clone(), event.user_id.clone(), event_name, name, value, data.to_string(),
+        device_time, device_uptime,
+    ];
+    let mut row = fields.iter().map(|f| csv_field(f)).collect::<Vec<_>>().join(",");
+    row.push('\n');
+    row
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_csv_row_quotes_fields() {
+        let event = EventWithMetadata {
+            event: DeviceEvent::DeviceVariableUpdate {
+                device_id: "dev-1".to_string(),
+                variable_name: "label".to_string(),
+                variable_value: "a,\"b\"".to_string(),
+                min: None,
+                max: None,
+            },
+            id: "evt-1".to_string(),
+            timestamp: 0,
+            user_id: "device".to_string(),
+            is_replay: None,
+            device_time: Some(crate::device_clock::DeviceTimestamp { uptime_ms: Some(5000), device_time: None }),
+        };
+
+        let row = ExportFormat::Csv.row(&event);
+        assert!(row.starts_with("0,1970-01-01T00:00:00+00:00,evt-1,device,DeviceVariableUpdate,label,\"a,\"\"b\"\"\","));
+        assert!(row.ends_with(",,5000\n"));
+        assert_eq!(row.lines().count(), 1);
+
+        let line = ExportFormat::Ndjson.row(&event);
+        let parsed: Value = serde_json::from_str(line.trim_end()).unwrap();
+        assert_eq!(parsed["event"]["variableName"], "label");
+
+        assert_eq!(parse_time_bound("1970-01-01T00:00:01Z"), Some(1000));
+        assert_eq!(parse_time_bound("1500"), Some(1500));
+        assert_eq!(ExportFormat::parse(Some("xml")), None);
+        assert_eq!(export_file_name("AA:BB", ExportFormat::Csv), "AA-BB-events.csv");
+    }
+}
@@ -3,25 +3,35 @@
 // ============================================================================
 
 use serde::{Deserialize, Serialize};
+use crate::device_logs::LogLevel;
 
 // ============================================================================
 // CLIENT-SERVER COMMUNICATION MESSAGES
 // ============================================================================
 
 /// Subscription type for device events
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
 #[serde(rename_all = "lowercase")]
 pub enum SubscriptionType {
     /// Light subscription: only connection status events
     Light,
     /// Full subscription: all events (variables, UDP, connection status, etc.)
+    #[default]
     Full,
 }
 
-impl Default for SubscriptionType {
-    fn default() -> Self {
-        SubscriptionType::Full
-    }
+/// Event classes a client can select at subscribe time
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum EventClass {
+    /// Variable values, changeable variables, start options
+    VariableUpdates,
+    /// Connection status and device info
+    ConnectionStatus,
+    /// Raw device messages and binary frames
+    UdpBroadcasts,
+    /// Command echoes and other diagnostic events
+    DebugLogs,
 }
 
 /// WebSocket messages sent from Client to Server
@@ -34,6 +44,20 @@ pub enum ClientMessage {
         device_id: String,
         #[serde(rename = "subscriptionType", default)]
         subscription_type: SubscriptionType,
+        /// Paged replay instead of the full history
+        #[serde(default)]
+        replay: Option<ReplayRequest>,
+        /// Only deliver these event classes (all if omitted)
+        #[serde(default)]
+        events: Option<Vec<EventClass>>,
+    },
+    /// Fetch a further replay page for a registered device
+    /// Also accepted without type: `{"deviceId": "dev-A", "replay": {"since": 0, "limit": 50}}`
+    #[serde(rename = "replay")]
+    Replay {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        replay: ReplayRequest,
     },
     #[serde(rename = "unregisterForDevice")]
     UnregisterForDevice {
@@ -47,6 +71,80 @@ pub enum ClientMessage {
         #[serde(rename = "eventsForDevice")]
         events_for_device: Vec<DeviceEvent>
     },
+    /// Batch change of the device subscriptions of this connection
+    /// Also accepted without type: `{"subscribe": ["dev-A", "dev-B"]}`
+    #[serde(rename = "subscriptions")]
+    Subscriptions {
+        #[serde(default)]
+        subscribe: Vec<String>,
+        #[serde(default)]
+        unsubscribe: Vec<String>,
+        #[serde(rename = "subscriptionType", default)]
+        subscription_type: SubscriptionType,
+        #[serde(default)]
+        events: Option<Vec<EventClass>>,
+    },
+    /// Presence cursor of this client on a device channel (e.g. focused control)
+    #[serde(rename = "presence")]
+    Presence {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(default)]
+        cursor: Option<serde_json::Value>,
+    },
+    /// Heartbeat ping
+    #[serde(rename = "ping")]
+    Ping {
+        #[serde(default)]
+        timestamp: Option<u64>,
+    },
+    /// Protocol version negotiation (versions the client speaks, compressions it can decode)
+    #[serde(rename = "hello")]
+    Hello {
+        versions: Vec<u32>,
+        #[serde(default)]
+        compression: Vec<String>,
+    },
+    /// Stream new debug bus entries to this connection (admins only); replaces an earlier filter
+    #[serde(rename = "debugSubscribe")]
+    DebugSubscribe {
+        #[serde(default)]
+        categories: Vec<String>,
+        #[serde(rename = "deviceId", default)]
+        device_id: Option<String>,
+    },
+    /// Stop the debug stream
+    #[serde(rename = "debugUnsubscribe")]
+    DebugUnsubscribe,
+    /// Send WebSocket statistics every `intervalMs` (admins only); replaces an earlier stream
+    #[serde(rename = "statsSubscribe")]
+    StatsSubscribe {
+        #[serde(rename = "intervalMs", default)]
+        interval_ms: Option<u64>,
+    },
+    /// Stop the statistics stream
+    #[serde(rename = "statsUnsubscribe")]
+    StatsUnsubscribe,
+}
+
+impl ClientMessage {
+    /// Device a message refers to (single-device messages only)
+    pub fn device_id(&self) -> Option<&str> {
+        match self {
+            ClientMessage::RegisterForDevice { device_id, .. }
+            | ClientMessage::Replay { device_id, .. }
+            | ClientMessage::UnregisterForDevice { device_id }
+            | ClientMessage::DeviceEvent { device_id, .. }
+            | ClientMessage::Presence { device_id, .. } => Some(device_id),
+            ClientMessage::Subscriptions { .. }
+            | ClientMessage::Ping { .. }
+            | ClientMessage::Hello { .. }
+            | ClientMessage::DebugSubscribe { .. }
+            | ClientMessage::DebugUnsubscribe
+            | ClientMessage::StatsSubscribe { .. }
+            | ClientMessage::StatsUnsubscribe => None,
+        }
+    }
 }
 
 /// WebSocket messages sent from Server to Client
@@ -60,6 +158,108 @@ pub enum ServerMessage {
         #[serde(rename = "eventsForDevice")]
         events_for_device: Vec<DeviceEvent>,
     },
+    /// Result of a device command sent by this client (correlated via requestId)
+    CommandResult {
+        #[serde(rename = "type")]
+        message_type: String,
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(rename = "requestId")]
+        request_id: String,
+        /// sent | queued | acknowledged | timeout | failed
+        status: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response: Option<serde_json::Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<CommandError>,
+    },
+    /// One page of a paged event replay
+    ReplayPage {
+        #[serde(rename = "type")]
+        message_type: String,
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(rename = "eventsForDevice")]
+        events_for_device: Vec<DeviceEvent>,
+        #[serde(rename = "lastTimestamp")]
+        last_timestamp: Option<i64>,
+        #[serde(rename = "nextCursor")]
+        next_cursor: Option<String>,
+        #[serde(rename = "hasMore")]
+        has_more: bool,
+    },
+    /// Subscription set of this connection after a subscriptions request
+    Subscriptions {
+        #[serde(rename = "type")]
+        message_type: String,
+        subscribed: Vec<String>,
+        rejected: Vec<SubscriptionRejection>,
+    },
+    /// Result of protocol negotiation
+    Welcome {
+        #[serde(rename = "type")]
+        message_type: String,
+        version: u32,
+        #[serde(rename = "supportedVersions")]
+        supported_versions: Vec<u32>,
+        #[serde(rename = "serverVersion")]
+        server_version: String,
+        /// Compression of binary frames from now on (see client_queue.rs)
+        #[serde(skip_serializing_if = "Option::is_none")]
+        compression: Option<String>,
+    },
+    /// Rejected client message
+    Error {
+        #[serde(rename = "type")]
+        message_type: String,
+        code: String,
+        message: String,
+        #[serde(rename = "requestId", skip_serializing_if = "Option::is_none")]
+        request_id: Option<String>,
+        /// Device the rejected message referred to
+        #[serde(rename = "deviceId", skip_serializing_if = "Option::is_none")]
+        device_id: Option<String>,
+    },
+    /// Client message with requestId was processed
+    Ack {
+        #[serde(rename = "type")]
+        message_type: String,
+        #[serde(rename = "requestId")]
+        request_id: String,
+    },
+    /// Device created/renamed/deleted or permissions changed via the REST API
+    DeviceListChanged {
+        #[serde(rename = "type")]
+        message_type: String,
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        change: DeviceListChange,
+    },
+    /// The user was mentioned in the channel of a device (sent to all connections of the user, like DeviceListChanged)
+    Mention {
+        #[serde(rename = "type")]
+        message_type: String,
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(rename = "fromUserId")]
+        from_user_id: String,
+        #[serde(rename = "fromDisplayName")]
+        from_display_name: String,
+        text: String,
+    },
+    /// New debug bus entries for a connection subscribed to the debug channel
+    DebugLog {
+        #[serde(rename = "type")]
+        message_type: String,
+        entries: Vec<crate::debug_logger::DebugEntry>,
+    },
+    /// Periodic WebSocket statistics for a connection subscribed with statsSubscribe
+    WsStats {
+        #[serde(rename = "type")]
+        message_type: String,
+        totals: crate::ws_stats::WsStatsTotals,
+        devices: Vec<crate::ws_stats::DeviceWsStats>,
+    },
     /// Heartbeat pong response
     Pong {
         #[serde(rename = "type")]
@@ -68,7 +268,173 @@ pub enum ServerMessage {
     },
 }
 
+/// Paged replay request: events after `since` (ms) or after `cursor`, at most `limit`
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ReplayRequest {
+    #[serde(default)]
+    pub since: Option<i64>,
+    #[serde(default)]
+    pub limit: Option<usize>,
+    #[serde(default)]
+    pub cursor: Option<String>,
+}
+
+/// Device that could not be subscribed
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionRejection {
+    #[serde(rename = "deviceId")]
+    pub device_id: String,
+    pub code: ErrorCode,
+    pub reason: String,
+}
+
+/// Kind of change to the device list of a user
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum DeviceListChange {
+    Created,
+    Updated,
+    Deleted,
+    PermissionsChanged,
+}
+
+/// Structured error of a failed device command
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CommandError {
+    pub code: String,
+    pub message: String,
+}
+
 impl ServerMessage {
+    /// Create a command result message without error
+    pub fn command_result(
+        device_id: String,
+        request_id: String,
+        status: &str,
+        response: Option<serde_json::Value>,
+    ) -> Self {
+        ServerMessage::CommandResult {
+            message_type: "commandResult".to_string(),
+            device_id,
+            request_id,
+            status: status.to_string(),
+            response,
+            error: None,
+        }
+    }
+
+    /// Create a failed command result message (e.g. timeout)
+    pub fn command_error(
+        device_id: String,
+        request_id: String,
+        status: &str,
+        code: &str,
+        message: String,
+    ) -> Self {
+        ServerMessage::CommandResult {
+            message_type: "commandResult".to_string(),
+            device_id,
+            request_id,
+            status: status.to_string(),
+            response: None,
+            error: Some(CommandError {
+                code: code.to_string(),
+                message,
+            }),
+        }
+    }
+
+    /// Create a replay page message
+    pub fn replay_page(device_id: String, page: EventPage) -> Self {
+        ServerMessage::ReplayPage {
+            message_type: "replayPage".to_string(),
+            device_id,
+            last_timestamp: page.events.last().map(|e| e.timestamp),
+            events_for_device: page.events.into_iter().map(|e| e.event).collect(),
+            next_cursor: page.next_cursor,
+            has_more: page.has_more,
+        }
+    }
+
+    /// Create a subscription state message
+    pub fn subscriptions(subscribed: Vec<String>, rejected: Vec<SubscriptionRejection>) -> Self {
+        ServerMessage::Subscriptions {
+            message_type: "subscriptions".to_string(),
+            subscribed,
+            rejected,
+        }
+    }
+
+    /// Create a welcome message for the negotiated protocol version and compression
+    pub fn welcome(version: u32, compression: Option<&str>) -> Self {
+        ServerMessage::Welcome {
+            message_type: "welcome".to_string(),
+            version,
+            supported_versions: SUPPORTED_PROTOCOL_VERSIONS.to_vec(),
+            server_version: env!("CARGO_PKG_VERSION").to_string(),
+            compression: compression.map(str::to_string),
+        }
+    }
+
+    /// Create an error message for a rejected client message
+    pub fn error(code: ErrorCode, message: String, request_id: Option<String>, device_id: Option<String>) -> Self {
+        ServerMessage::Error {
+            message_type: "error".to_string(),
+            code: code.as_str().to_string(),
+            message,
+            request_id,
+            device_id,
+        }
+    }
+
+    /// Create an acknowledgement for a client message with requestId
+    pub fn ack(request_id: String) -> Self {
+        ServerMessage::Ack {
+            message_type: "ack".to_string(),
+            request_id,
+        }
+    }
+
+    /// Create a device list change notification
+    pub fn device_list_changed(device_id: String, change: DeviceListChange) -> Self {
+        ServerMessage::DeviceListChanged {
+            message_type: "deviceListChanged".to_string(),
+            device_id,
+            change,
+        }
+    }
+
+    /// Create a mention notification from a DeviceMention event (None for other events)
+    pub fn mention(event: &DeviceEvent) -> Option<Self> {
+        let DeviceEvent::DeviceMention { device_id, text, from_user_id, from_display_name, .. } = event else {
+            return None;
+        };
+        Some(ServerMessage::Mention {
+            message_type: "mention".to_string(),
+            device_id: device_id.clone(),
+            from_user_id: from_user_id.clone(),
+            from_display_name: from_display_name.clone(),
+            text: text.clone(),
+        })
+    }
+
+    /// Create a debug channel message
+    pub fn debug_log(entries: Vec<crate::debug_logger::DebugEntry>) -> Self {
+        ServerMessage::DebugLog {
+            message_type: "debugLog".to_string(),
+            entries,
+        }
+    }
+
+    /// Create a statistics stream message
+    pub fn ws_stats(devices: Vec<crate::ws_stats::DeviceWsStats>) -> Self {
+        ServerMessage::WsStats {
+            message_type: "wsStats".to_string(),
+            totals: crate::ws_stats::totals(&devices),
+            devices,
+        }
+    }
+
     /// Create a pong response message
     pub fn pong(timestamp: Option<u64>) -> Self {
         ServerMessage::Pong {
@@ -86,6 +452,428 @@ impl ServerMessage {
     }
 }
 
+// ============================================================================
+// PROTOCOL ENVELOPE - Versioned framing of the /channel protocol
+// ============================================================================
+
+/// Legacy protocol: bare messages as defined above, no envelope
+pub const PROTOCOL_VERSION_LEGACY: u32 = 1;
+/// Current protocol: every message wrapped in `{v, type, payload, requestId}`
+pub const PROTOCOL_VERSION: u32 = 2;
+/// Versions this server speaks
+pub const SUPPORTED_PROTOCOL_VERSIONS: &[u32] = &[PROTOCOL_VERSION_LEGACY, PROTOCOL_VERSION];
+
+pub fn is_supported_protocol_version(version: u32) -> bool {
+    SUPPORTED_PROTOCOL_VERSIONS.contains(&version)
+}
+
+/// Pick the highest version both client and server speak
+pub fn negotiate_protocol_version(client_versions: &[u32]) -> Option<u32> {
+    client_versions.iter()
+        .copied()
+        .filter(|v| is_supported_protocol_version(*v))
+        .max()
+}
+
+/// Client → server payloads of the enveloped protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ClientPayload {
+    Hello {
+        versions: Vec<u32>,
+        #[serde(default)]
+        compression: Vec<String>,
+    },
+    Ping {
+        #[serde(default)]
+        timestamp: Option<u64>,
+    },
+    RegisterForDevice {
+        device_id: String,
+        #[serde(default)]
+        subscription_type: SubscriptionType,
+        #[serde(default)]
+        replay: Option<ReplayRequest>,
+        #[serde(default)]
+        events: Option<Vec<EventClass>>,
+    },
+    Replay {
+        device_id: String,
+        replay: ReplayRequest,
+    },
+    UnregisterForDevice {
+        device_id: String,
+    },
+    DeviceEvent {
+        device_id: String,
+        events_for_device: Vec<DeviceEvent>,
+    },
+    Subscriptions {
+        #[serde(default)]
+        subscribe: Vec<String>,
+        #[serde(default)]
+        unsubscribe: Vec<String>,
+        #[serde(default)]
+        subscription_type: SubscriptionType,
+        #[serde(default)]
+        events: Option<Vec<EventClass>>,
+    },
+    Presence {
+        device_id: String,
+        #[serde(default)]
+        cursor: Option<serde_json::Value>,
+    },
+    DebugSubscribe {
+        #[serde(default)]
+        categories: Vec<String>,
+        #[serde(default)]
+        device_id: Option<String>,
+    },
+    DebugUnsubscribe,
+    StatsSubscribe {
+        #[serde(default)]
+        interval_ms: Option<u64>,
+    },
+    StatsUnsubscribe,
+}
+
+impl From<ClientPayload> for ClientMessage {
+    fn from(payload: ClientPayload) -> Self {
+        match payload {
+            ClientPayload::Hello { versions, compression } => ClientMessage::Hello { versions, compression },
+            ClientPayload::Ping { timestamp } => ClientMessage::Ping { timestamp },
+            ClientPayload::RegisterForDevice { device_id, subscription_type, replay, events } => {
+                ClientMessage::RegisterForDevice { device_id, subscription_type, replay, events }
+            }
+            ClientPayload::Replay { device_id, replay } => ClientMessage::Replay { device_id, replay },
+            ClientPayload::UnregisterForDevice { device_id } => ClientMessage::UnregisterForDevice { device_id },
+            ClientPayload::DeviceEvent { device_id, events_for_device } => {
+                ClientMessage::DeviceEvent { device_id, events_for_device }
+            }
+            ClientPayload::Subscriptions { subscribe, unsubscribe, subscription_type, events } => {
+                ClientMessage::Subscriptions { subscribe, unsubscribe, subscription_type, events }
+            }
+            ClientPayload::Presence { device_id, cursor } => ClientMessage::Presence { device_id, cursor },
+            ClientPayload::DebugSubscribe { categories, device_id } => ClientMessage::DebugSubscribe { categories, device_id },
+            ClientPayload::DebugUnsubscribe => ClientMessage::DebugUnsubscribe,
+            ClientPayload::StatsSubscribe { interval_ms } => ClientMessage::StatsSubscribe { interval_ms },
+            ClientPayload::StatsUnsubscribe => ClientMessage::StatsUnsubscribe,
+        }
+    }
+}
+
+/// Server → client payloads of the enveloped protocol
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "payload", rename_all = "camelCase", rename_all_fields = "camelCase")]
+pub enum ServerPayload {
+    Welcome {
+        version: u32,
+        supported_versions: Vec<u32>,
+        server_version: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        compression: Option<String>,
+    },
+    DeviceEvents {
+        device_id: String,
+        events_for_device: Vec<DeviceEvent>,
+    },
+    CommandResult {
+        device_id: String,
+        status: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        response: Option<serde_json::Value>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<CommandError>,
+    },
+    ReplayPage {
+        device_id: String,
+        events_for_device: Vec<DeviceEvent>,
+        last_timestamp: Option<i64>,
+        next_cursor: Option<String>,
+        has_more: bool,
+    },
+    Subscriptions {
+        subscribed: Vec<String>,
+        rejected: Vec<SubscriptionRejection>,
+    },
+    Pong {
+        timestamp: Option<u64>,
+    },
+    Ack,
+    DeviceListChanged {
+        device_id: String,
+        change: DeviceListChange,
+    },
+    Mention {
+        device_id: String,
+        from_user_id: String,
+        from_display_name: String,
+        text: String,
+    },
+    DebugLog {
+        entries: Vec<crate::debug_logger::DebugEntry>,
+    },
+    WsStats {
+        totals: crate::ws_stats::WsStatsTotals,
+        devices: Vec<crate::ws_stats::DeviceWsStats>,
+    },
+    Error {
+        code: String,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        device_id: Option<String>,
+    },
+}
+
+/// Enveloped client message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ClientEnvelope {
+    pub v: u32,
+    #[serde(flatten)]
+    pub message: ClientPayload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Enveloped server message
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerEnvelope {
+    pub v: u32,
+    #[serde(flatten)]
+    pub message: ServerPayload,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+impl ServerMessage {
+    /// Wrap the message in the envelope of the given protocol version
+    pub fn into_envelope(self, version: u32) -> ServerEnvelope {
+        let (message, request_id) = match self {
+            ServerMessage::DeviceEvents { device_id, events_for_device } => {
+                (ServerPayload::DeviceEvents { device_id, events_for_device }, None)
+            }
+            ServerMessage::CommandResult { device_id, request_id, status, response, error, .. } => {
+                (ServerPayload::CommandResult { device_id, status, response, error }, Some(request_id))
+            }
+            ServerMessage::ReplayPage { device_id, events_for_device, last_timestamp, next_cursor, has_more, .. } => {
+                (ServerPayload::ReplayPage { device_id, events_for_device, last_timestamp, next_cursor, has_more }, None)
+            }
+            ServerMessage::Subscriptions { subscribed, rejected, .. } => {
+                (ServerPayload::Subscriptions { subscribed, rejected }, None)
+            }
+            ServerMessage::Welcome { version, supported_versions, server_version, compression, .. } => {
+                (ServerPayload::Welcome { version, supported_versions, server_version, compression }, None)
+            }
+            ServerMessage::Error { code, message, request_id, device_id, .. } => {
+                (ServerPayload::Error { code, message, device_id }, request_id)
+            }
+            ServerMessage::Ack { request_id, .. } => (ServerPayload::Ack, Some(request_id)),
+            ServerMessage::DeviceListChanged { device_id, change, .. } => {
+                (ServerPayload::DeviceListChanged { device_id, change }, None)
+            }
+            ServerMessage::Mention { device_id, from_user_id, from_display_name, text, .. } => {
+                (ServerPayload::Mention { device_id, from_user_id, from_display_name, text }, None)
+            }
+            ServerMessage::DebugLog { entries, .. } => (ServerPayload::DebugLog { entries }, None),
+            ServerMessage::WsStats { totals, devices, .. } => (ServerPayload::WsStats { totals, devices }, None),
+            ServerMessage::Pong { timestamp, .. } => (ServerPayload::Pong { timestamp }, None),
+        };
+        ServerEnvelope { v: version, message, request_id }
+    }
+
+    /// Serialize for a client speaking the given protocol version
+    pub fn to_wire(&self, version: u32) -> Result<String, serde_json::Error> {
+        if version == PROTOCOL_VERSION_LEGACY {
+            serde_json::to_string(self)
+        } else {
+            serde_json::to_string(&self.clone().into_envelope(version))
+        }
+    }
+}
+
+/// A decoded client frame of either protocol version
+#[derive(Debug, Clone)]
+pub struct ClientFrame {
+    pub version: u32,
+    pub message: ClientMessage,
+    pub request_id: Option<String>,
+}
+
+/// Why a client frame was rejected
+#[derive(Debug, Clone, PartialEq)]
+pub enum FrameError {
+    /// Envelope with a version this server does not speak
+    UnsupportedVersion(u64),
+    /// Not valid JSON or not a known message
+    Invalid(String),
+}
+
+impl FrameError {
+    /// Error code sent to the client
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            FrameError::UnsupportedVersion(_) => ErrorCode::UnsupportedVersion,
+            FrameError::Invalid(_) => ErrorCode::InvalidMessage,
+        }
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::UnsupportedVersion(v) => write!(
+                f,
+                "Unsupported protocol version {} (supported: {:?})",
+                v, SUPPORTED_PROTOCOL_VERSIONS
+            ),
+            FrameError::Invalid(e) => write!(f, "Invalid message: {}", e),
+        }
+    }
+}
+
+// ============================================================================
+// ERROR FRAMES - Machine-readable reasons for rejected client messages
+// ============================================================================
+
+/// Error code of a `{"type":"error"}` frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ErrorCode {
+    /// Not valid JSON or not a known message
+    InvalidMessage,
+    /// Envelope with a protocol version this server does not speak
+    UnsupportedVersion,
+    /// Envelope version differs from the negotiated one
+    VersionMismatch,
+    /// User lacks the required device permission
+    PermissionDenied,
+    /// Device is not known to the server
+    UnknownDevice,
+    /// Client is not registered for the device
+    NotSubscribed,
+    /// Device did not accept the command (disconnected, transport error)
+    CommandFailed,
+    /// Message exceeds a server limit
+    LimitExceeded,
+    /// Anything else that went wrong while processing the message
+    RequestFailed,
+}
+
+impl ErrorCode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::InvalidMessage => "invalidMessage",
+            ErrorCode::UnsupportedVersion => "unsupportedVersion",
+            ErrorCode::VersionMismatch => "versionMismatch",
+            ErrorCode::PermissionDenied => "permissionDenied",
+            ErrorCode::UnknownDevice => "unknownDevice",
+            ErrorCode::NotSubscribed => "notSubscribed",
+            ErrorCode::CommandFailed => "commandFailed",
+            ErrorCode::LimitExceeded => "limitExceeded",
+            ErrorCode::RequestFailed => "requestFailed",
+        }
+    }
+}
+
+/// Failure of a client message, sent back as an error frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct WsError {
+    pub code: ErrorCode,
+    pub message: String,
+}
+
+impl WsError {
+    pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+        WsError { code, message: message.into() }
+    }
+
+    pub fn not_subscribed(client_id: &str, device_id: &str) -> Self {
+        WsError::new(
+            ErrorCode::NotSubscribed,
+            format!("Client {} is not registered for device {}", client_id, device_id),
+        )
+    }
+}
+
+impl std::fmt::Display for WsError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.code.as_str(), self.message)
+    }
+}
+
+/// Unclassified errors (store, serialization) become requestFailed
+impl From<String> for WsError {
+    fn from(message: String) -> Self {
+        WsError::new(ErrorCode::RequestFailed, message)
+    }
+}
+
+/// Decode a client frame: bare legacy message or `{v, type, payload, requestId}` envelope
+pub fn decode_client_frame(text: &str) -> Result<ClientFrame, FrameError> {
+    let value: serde_json::Value = serde_json::from_str(text)
+        .map_err(|e| FrameError::Invalid(e.to_string()))?;
+
+    let Some(v) = value.get("v") else {
+        // Shorthand subscription change / replay request without type
+        let mut value = value;
+        if let Some(object) = value.as_object_mut() {
+            if !object.contains_key("type") {
+                if object.contains_key("subscribe") || object.contains_key("unsubscribe") {
+                    object.insert("type".to_string(), serde_json::Value::from("subscriptions"));
+                } else if object.contains_key("replay") && object.contains_key("deviceId") {
+                    object.insert("type".to_string(), serde_json::Value::from("replay"));
+                }
+            }
+        }
+        let message: ClientMessage = serde_json::from_value(value)
+            .map_err(|e| FrameError::Invalid(e.to_string()))?;
+        return Ok(ClientFrame { version: PROTOCOL_VERSION_LEGACY, message, request_id: None });
+    };
+
+    // Only versions above the legacy one use the envelope
+    let version = v.as_u64().ok_or_else(|| FrameError::Invalid("v must be a number".to_string()))?;
+    if version <= PROTOCOL_VERSION_LEGACY as u64 || !is_supported_protocol_version(version as u32) {
+        return Err(FrameError::UnsupportedVersion(version));
+    }
+
+    let envelope: ClientEnvelope = serde_json::from_value(value)
+        .map_err(|e| FrameError::Invalid(e.to_string()))?;
+    Ok(ClientFrame {
+        version: envelope.v,
+        message: envelope.message.into(),
+        request_id: envelope.request_id,
+    })
+}
+
+// ============================================================================
+// PRESENCE
+// ============================================================================
+
+/// Activity of a user on a device channel, derived from the last client message
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum PresenceStatus {
+    Active,
+    Idle,
+}
+
+/// Presence of one user on a device channel
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct PresenceEntry {
+    pub user_id: String,
+    pub display_name: String,
+    pub user_color: String,
+    pub connection_count: usize,
+    pub status: PresenceStatus,
+    /// Last client message (ms since epoch)
+    pub last_active: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor: Option<serde_json::Value>,
+}
+
 // ============================================================================
 // DEVICE EVENT DEFINITIONS - Compatible with Frontend EventBus
 // ============================================================================
@@ -172,6 +960,32 @@ pub enum DeviceEvent {
         #[serde(rename = "fromPort")]
         from_port: u16,
     },
+    #[serde(rename = "DeviceBinaryData")]
+    DeviceBinaryData {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        /// cbor | messagePack | lengthPrefixed | raw
+        encoding: String,
+        /// Raw frame as base64
+        payload: String,
+        /// Structured content if the frame could be decoded
+        decoded: Option<serde_json::Value>,
+        size: usize,
+        #[serde(rename = "fromIp")]
+        from_ip: String,
+        #[serde(rename = "fromPort")]
+        from_port: u16,
+    },
+    /// Log line sent by the firmware (`{"log": {...}}` frame), stored apart from telemetry
+    #[serde(rename = "DeviceLog")]
+    DeviceLog {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        level: LogLevel,
+        msg: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        tag: Option<String>,
+    },
     #[serde(rename = "DeviceConnectionStatus")]
     DeviceConnectionStatus {
         #[serde(rename = "deviceId")]
@@ -184,6 +998,16 @@ pub enum DeviceEvent {
         #[serde(rename = "udpPort")]
         udp_port: u16,
     },
+    /// Lifecycle state transition (Discovered, Adopted, Connecting, Online, Degraded, Offline, Maintenance, Quarantined)
+    #[serde(rename = "DeviceLifecycle")]
+    DeviceLifecycle {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        state: String,
+        #[serde(rename = "previousState")]
+        previous_state: Option<String>,
+        reason: String,
+    },
     #[serde(rename = "DeviceDeviceInfo")]
     DeviceDeviceInfo {
         #[serde(rename = "deviceId")]
@@ -194,6 +1018,59 @@ pub enum DeviceEvent {
         firmware_version: Option<String>,
         uptime: Option<u64>,
     },
+    /// Alert rule started or stopped firing (see alerts.rs)
+    #[serde(rename = "DeviceAlert")]
+    DeviceAlert {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(rename = "alertId")]
+        alert_id: String,
+        #[serde(rename = "ruleId")]
+        rule_id: String,
+        #[serde(rename = "ruleName")]
+        rule_name: String,
+        /// firing | resolved
+        state: String,
+        message: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        value: Option<f64>,
+    },
+    /// Note on the device channel; sender, ID and time are set by the server (see device_chat.rs)
+    #[serde(rename = "DeviceChatMessage")]
+    DeviceChatMessage {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        #[serde(rename = "messageId", default)]
+        message_id: String,
+        text: String,
+        #[serde(rename = "userId", default)]
+        user_id: String,
+        #[serde(rename = "displayName", default)]
+        display_name: String,
+        /// Unix time in ms
+        #[serde(rename = "sentAt", default)]
+        sent_at: i64,
+    },
+    /// `@handle` mention in the device channel; sender and resolved handles are set by the server (see mentions.rs)
+    #[serde(rename = "DeviceMention")]
+    DeviceMention {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        text: String,
+        #[serde(rename = "fromUserId", default)]
+        from_user_id: String,
+        #[serde(rename = "fromDisplayName", default)]
+        from_display_name: String,
+        /// Handles in the text that belong to a user
+        #[serde(default)]
+        mentions: Vec<String>,
+    },
+    #[serde(rename = "DevicePresence")]
+    DevicePresence {
+        #[serde(rename = "deviceId")]
+        device_id: String,
+        users: Vec<PresenceEntry>,
+    },
     #[serde(rename = "DeviceDiscovered")]
     DeviceDiscovered {
         #[serde(rename = "deviceId")]
@@ -211,6 +1088,16 @@ pub enum DeviceEvent {
         #[serde(rename = "mdnsHostname")]
         mdns_hostname: Option<String>,
     },
+    /// Result of connecting the stored devices at server startup (sent on "system")
+    #[serde(rename = "StartupConnectSummary")]
+    StartupConnectSummary {
+        total: usize,
+        connected: usize,
+        failed: usize,
+        skipped: usize,
+        #[serde(rename = "durationMs")]
+        duration_ms: u64,
+    },
 }
 
 
@@ -227,6 +1114,32 @@ pub struct EventWithMetadata {
     pub user_id: String,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub is_replay: Option<bool>,
+    /// Device clock at receive time, for devices that report uptime/timestamp
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub device_time: Option<crate::device_clock::DeviceTimestamp>,
+}
+
+impl EventWithMetadata {
+    /// Position of this event in replay order (`<timestamp>:<id>`)
+    pub fn cursor(&self) -> String {
+        format!("{}:{}", self.timestamp, self.id)
+    }
+}
+
+/// Parse a replay cursor into (timestamp, event id)
+pub fn parse_replay_cursor(cursor: &str) -> Option<(i64, String)> {
+    let (timestamp, id) = cursor.split_once(':')?;
+    Some((timestamp.parse().ok()?, id.to_string()))
+}
+
+/// One page of stored events in chronological order
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EventPage {
+    pub events: Vec<EventWithMetadata>,
+    /// Cursor of the last returned event - pass it back to continue
+    pub next_cursor: Option<String>,
+    pub has_more: bool,
 }
 
 // ============================================================================
@@ -292,17 +1205,41 @@ impl DeviceEvent {
         DeviceEvent::DeviceUdpBroadcast { device_id, message, from_ip, from_port }
     }
     
+    pub fn device_binary_data(device_id: String, encoding: String, payload: String, decoded: Option<serde_json::Value>, size: usize, from_ip: String, from_port: u16) -> Self {
+        DeviceEvent::DeviceBinaryData { device_id, encoding, payload, decoded, size, from_ip, from_port }
+    }
+
+    pub fn device_log(device_id: String, level: LogLevel, msg: String, tag: Option<String>) -> Self {
+        DeviceEvent::DeviceLog { device_id, level, msg, tag }
+    }
+
     pub fn device_connection_status(device_id: String, connected: bool, device_ip: String, tcp_port: u16, udp_port: u16) -> Self {
         DeviceEvent::DeviceConnectionStatus { device_id, connected, device_ip, tcp_port, udp_port }
     }
+
+    pub fn device_lifecycle(device_id: String, state: String, previous_state: Option<String>, reason: String) -> Self {
+        DeviceEvent::DeviceLifecycle { device_id, state, previous_state, reason }
+    }
     
     pub fn device_device_info(device_id: String, device_name: Option<String>, firmware_version: Option<String>, uptime: Option<u64>) -> Self {
         DeviceEvent::DeviceDeviceInfo { device_id, device_name, firmware_version, uptime }
     }
     
+    pub fn device_alert(device_id: String, alert_id: String, rule_id: String, rule_name: String, state: String, message: String, value: Option<f64>) -> Self {
+        DeviceEvent::DeviceAlert { device_id, alert_id, rule_id, rule_name, state, message, value }
+    }
+
+    pub fn device_presence(device_id: String, users: Vec<PresenceEntry>) -> Self {
+        DeviceEvent::DevicePresence { device_id, users }
+    }
+
     pub fn device_discovered(device_id: String, device_ip: String, tcp_port: u16, udp_port: u16, discovered_at: String, mac_address: Option<String>, mdns_hostname: Option<String>) -> Self {
         DeviceEvent::DeviceDiscovered { device_id, device_ip, tcp_port, udp_port, discovered_at, mac_address, mdns_hostname }
     }
+
+    pub fn startup_connect_summary(total: usize, connected: usize, failed: usize, skipped: usize, duration_ms: u64) -> Self {
+        DeviceEvent::StartupConnectSummary { total, connected, failed, skipped, duration_ms }
+    }
 }
 
 // ============================================================================
@@ -371,6 +1308,20 @@ impl DeviceEvent {
                     Ok(())
                 }
             },
+            DeviceEvent::DeviceMention { device_id, text, .. } => {
+                if device_id.is_empty() || text.trim().is_empty() {
+                    Err("DeviceMention requires non-empty device_id and text".to_string())
+                } else {
+                    Ok(())
+                }
+            },
+            DeviceEvent::DeviceChatMessage { device_id, text, .. } => {
+                if device_id.is_empty() || text.trim().is_empty() {
+                    Err("DeviceChatMessage requires non-empty device_id and text".to_string())
+                } else {
+                    Ok(())
+                }
+            },
             DeviceEvent::DeviceUdpBroadcast { device_id, .. } => {
                 if device_id.is_empty() {
                     Err("DeviceUdpBroadcast requires non-empty device_id".to_string())
@@ -378,6 +1329,20 @@ impl DeviceEvent {
                     Ok(())
                 }
             },
+            DeviceEvent::DeviceBinaryData { device_id, .. } => {
+                if device_id.is_empty() {
+                    Err("DeviceBinaryData requires non-empty device_id".to_string())
+                } else {
+                    Ok(())
+                }
+            },
+            DeviceEvent::DeviceLog { device_id, .. } => {
+                if device_id.is_empty() {
+                    Err("DeviceLog requires non-empty device_id".to_string())
+                } else {
+                    Ok(())
+                }
+            },
             DeviceEvent::DeviceConnectionStatus { device_id, .. } => {
                 if device_id.is_empty() {
                     Err("DeviceConnectionStatus requires non-empty device_id".to_string())
@@ -385,6 +1350,15 @@ impl DeviceEvent {
                     Ok(())
                 }
             },
+            DeviceEvent::DeviceLifecycle { device_id, state, .. } => {
+                if device_id.is_empty() {
+                    Err("DeviceLifecycle requires non-empty device_id".to_string())
+                } else if state.is_empty() {
+                    Err("DeviceLifecycle requires non-empty state".to_string())
+                } else {
+                    Ok(())
+                }
+            },
             DeviceEvent::DeviceDeviceInfo { device_id, .. } => {
                 if device_id.is_empty() {
                     Err("DeviceDeviceInfo requires non-empty device_id".to_string())
@@ -392,6 +1366,20 @@ impl DeviceEvent {
                     Ok(())
                 }
             },
+            DeviceEvent::DeviceAlert { device_id, rule_id, .. } => {
+                if device_id.is_empty() || rule_id.is_empty() {
+                    Err("DeviceAlert requires non-empty device_id and rule_id".to_string())
+                } else {
+                    Ok(())
+                }
+            },
+            DeviceEvent::DevicePresence { device_id, .. } => {
+                if device_id.is_empty() {
+                    Err("DevicePresence requires non-empty device_id".to_string())
+                } else {
+                    Ok(())
+                }
+            },
             DeviceEvent::DeviceDiscovered { device_id, device_ip, .. } => {
                 if device_id.is_empty() || device_ip.is_empty() {
                     Err("DeviceDiscovered requires non-empty device_id and device_ip".to_string())
@@ -399,6 +1387,13 @@ impl DeviceEvent {
                     Ok(())
                 }
             },
+            DeviceEvent::StartupConnectSummary { total, connected, failed, skipped, .. } => {
+                if connected + failed + skipped != *total {
+                    Err("StartupConnectSummary counts do not add up to total".to_string())
+                } else {
+                    Ok(())
+                }
+            },
         }
     }
 }
@@ -438,17 +1433,25 @@ impl DeviceEvent {
         match self {
             // Transactional events - not stored
             DeviceEvent::DeviceCommand { .. } => EventPersistence::Ephemeral,
+            // Kept in the device log store (GET /api/devices/:id/logs), not in the event history
+            DeviceEvent::DeviceLog { .. } => EventPersistence::Ephemeral,
 
             // State events - only current value matters
             DeviceEvent::DeviceConnectionStatus { .. } => EventPersistence::StateSnapshot,
+            DeviceEvent::DeviceLifecycle { .. } => EventPersistence::StateSnapshot,
             DeviceEvent::DeviceVariableUpdate { .. } => EventPersistence::StateSnapshot,
             DeviceEvent::DeviceStartOptions { .. } => EventPersistence::StateSnapshot,
             DeviceEvent::DeviceChangeableVariables { .. } => EventPersistence::StateSnapshot,
             DeviceEvent::DeviceDeviceInfo { .. } => EventPersistence::StateSnapshot,
+            // Latest state per rule, so new clients see alerts that are still firing
+            DeviceEvent::DeviceAlert { .. } => EventPersistence::StateSnapshot,
+            // Last startup result, so clients connecting later still see it
+            DeviceEvent::StartupConnectSummary { .. } => EventPersistence::StateSnapshot,
 
             // History events - bounded FIFO queue
             // Default: 200 messages (configurable via database settings)
             DeviceEvent::DeviceUdpBroadcast { .. } => EventPersistence::BoundedHistory(200),
+            DeviceEvent::DeviceBinaryData { .. } => EventPersistence::BoundedHistory(200),
 
             // Permanent events - stored in database
             DeviceEvent::DeviceDiscovered { .. } => EventPersistence::Permanent,
@@ -456,6 +1459,10 @@ impl DeviceEvent {
             // Session events - ephemeral (managed by connection lifecycle)
             DeviceEvent::UserJoined { .. } => EventPersistence::Ephemeral,
             DeviceEvent::UserLeft { .. } => EventPersistence::Ephemeral,
+            DeviceEvent::DevicePresence { .. } => EventPersistence::Ephemeral,
+            // Chat history lives in the database (device_chat.rs), replayed on registration
+            DeviceEvent::DeviceChatMessage { .. } => EventPersistence::Ephemeral,
+            DeviceEvent::DeviceMention { .. } => EventPersistence::Ephemeral,
 
             // Legacy/unused events - snapshot for backward compatibility
             DeviceEvent::DeviceStatusUpdate { .. } => EventPersistence::StateSnapshot,
@@ -464,6 +1471,46 @@ impl DeviceEvent {
         }
     }
 
+    /// Event class for subscription filtering
+    /// None: always delivered (presence, discovery)
+    pub fn event_class(&self) -> Option<EventClass> {
+        match self {
+            DeviceEvent::DeviceVariableUpdate { .. }
+            | DeviceEvent::DeviceChangeableVariables { .. }
+            | DeviceEvent::DeviceStartOptions { .. } => Some(EventClass::VariableUpdates),
+
+            DeviceEvent::DeviceConnectionStatus { .. }
+            | DeviceEvent::DeviceLifecycle { .. }
+            | DeviceEvent::DeviceDeviceInfo { .. }
+            | DeviceEvent::DeviceStatusUpdate { .. } => Some(EventClass::ConnectionStatus),
+
+            DeviceEvent::DeviceUdpBroadcast { .. }
+            | DeviceEvent::DeviceBinaryData { .. } => Some(EventClass::UdpBroadcasts),
+
+            DeviceEvent::DeviceCommand { .. }
+            | DeviceEvent::DeviceLog { .. }
+            | DeviceEvent::DeviceConfigUpdate { .. }
+            | DeviceEvent::DeviceSensorData { .. } => Some(EventClass::DebugLogs),
+
+            DeviceEvent::UserJoined { .. }
+            | DeviceEvent::UserLeft { .. }
+            | DeviceEvent::DevicePresence { .. }
+            | DeviceEvent::DeviceAlert { .. }
+            | DeviceEvent::DeviceMention { .. }
+            | DeviceEvent::DeviceChatMessage { .. }
+            | DeviceEvent::DeviceDiscovered { .. }
+            | DeviceEvent::StartupConnectSummary { .. } => None,
+        }
+    }
+
+    /// Check whether a client with the given class selection receives this event
+    pub fn matches_classes(&self, classes: Option<&std::collections::HashSet<EventClass>>) -> bool {
+        match (classes, self.event_class()) {
+            (Some(classes), Some(class)) => classes.contains(&class),
+            _ => true,
+        }
+    }
+
     /// Check if this event should be included in replay for new clients
     pub fn should_replay(&self) -> bool {
         match self.persistence_strategy() {
@@ -481,6 +1528,9 @@ impl DeviceEvent {
             DeviceEvent::DeviceConnectionStatus { device_id, .. } => {
                 Some(format!("connection:{}", device_id))
             }
+            DeviceEvent::DeviceLifecycle { device_id, .. } => {
+                Some(format!("lifecycle:{}", device_id))
+            }
             DeviceEvent::DeviceVariableUpdate { device_id, variable_name, .. } => {
                 Some(format!("variable:{}:{}", device_id, variable_name))
             }
@@ -493,6 +1543,10 @@ impl DeviceEvent {
             DeviceEvent::DeviceDeviceInfo { device_id, .. } => {
                 Some(format!("device_info:{}", device_id))
             }
+            DeviceEvent::DeviceAlert { device_id, rule_id, .. } => {
+                Some(format!("alert:{}:{}", device_id, rule_id))
+            }
+            DeviceEvent::StartupConnectSummary { .. } => Some("startup_connect".to_string()),
             // Legacy events without device_id field - cannot create proper state key
             // These events are not used in the codebase, but we handle them safely
             DeviceEvent::DeviceStatusUpdate { .. } => {
@@ -511,3 +1565,158 @@ impl DeviceEvent {
     }
 }
 
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode_legacy_frame() {
+        let frame = decode_client_frame(r#"{"type":"registerForDevice","deviceId":"dev-1"}"#).unwrap();
+        assert_eq!(frame.version, PROTOCOL_VERSION_LEGACY);
+        assert!(frame.request_id.is_none());
+        assert!(matches!(frame.message, ClientMessage::RegisterForDevice { ref device_id, .. } if device_id == "dev-1"));
+    }
+
+    #[test]
+    fn test_decode_envelope_frame() {
+        let frame = decode_client_frame(
+            r#"{"v":2,"type":"registerForDevice","payload":{"deviceId":"dev-1","subscriptionType":"light"},"requestId":"r1"}"#
+        ).unwrap();
+        assert_eq!(frame.version, 2);
+        assert_eq!(frame.request_id.as_deref(), Some("r1"));
+        match frame.message {
+            ClientMessage::RegisterForDevice { device_id, subscription_type, .. } => {
+                assert_eq!(device_id, "dev-1");
+                assert_eq!(subscription_type, SubscriptionType::Light);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decode_subscription_shorthand() {
+        let frame = decode_client_frame(r#"{"subscribe":["dev-A","dev-B"]}"#).unwrap();
+        match frame.message {
+            ClientMessage::Subscriptions { subscribe, unsubscribe, subscription_type, .. } => {
+                assert_eq!(subscribe, vec!["dev-A", "dev-B"]);
+                assert!(unsubscribe.is_empty());
+                assert_eq!(subscription_type, SubscriptionType::Full);
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+
+        let frame = decode_client_frame(
+            r#"{"v":2,"type":"subscriptions","payload":{"unsubscribe":["dev-A"],"subscriptionType":"light"}}"#
+        ).unwrap();
+        assert!(matches!(frame.message, ClientMessage::Subscriptions { ref unsubscribe, .. } if unsubscribe == &vec!["dev-A".to_string()]));
+    }
+
+    #[test]
+    fn test_decode_replay_request() {
+        let frame = decode_client_frame(r#"{"deviceId":"dev-1","replay":{"since":1000,"limit":20}}"#).unwrap();
+        match frame.message {
+            ClientMessage::Replay { device_id, replay } => {
+                assert_eq!(device_id, "dev-1");
+                assert_eq!(replay, ReplayRequest { since: Some(1000), limit: Some(20), cursor: None });
+            }
+            other => panic!("unexpected message {:?}", other),
+        }
+        assert_eq!(parse_replay_cursor("1700000000000:abc-1"), Some((1700000000000, "abc-1".to_string())));
+        assert_eq!(parse_replay_cursor("garbage"), None);
+    }
+
+    #[test]
+    fn test_event_class_filter() {
+        let frame = decode_client_frame(
+            r#"{"type":"registerForDevice","deviceId":"dev-1","events":["variableUpdates","connectionStatus"]}"#
+        ).unwrap();
+        let ClientMessage::RegisterForDevice { events: Some(events), .. } = frame.message else {
+            panic!("expected event class selection");
+        };
+        let classes: std::collections::HashSet<EventClass> = events.into_iter().collect();
+
+        let udp = DeviceEvent::device_udp_broadcast("dev-1".to_string(), "raw".to_string(), "10.0.0.2".to_string(), 3232);
+        let variable = DeviceEvent::device_variable_update("dev-1".to_string(), "speed".to_string(), "3".to_string());
+        let joined = DeviceEvent::user_joined("u1".to_string(), "User".to_string(), "#FF6B6B".to_string());
+
+        assert!(!udp.matches_classes(Some(&classes)));
+        assert!(variable.matches_classes(Some(&classes)));
+        assert!(joined.matches_classes(Some(&classes)));
+        assert!(udp.matches_classes(None));
+    }
+
+    #[test]
+    fn test_decode_rejects_unknown_version() {
+        let err = decode_client_frame(r#"{"v":99,"type":"ping","payload":{}}"#).unwrap_err();
+        assert_eq!(err, FrameError::UnsupportedVersion(99));
+        assert!(decode_client_frame(r#"{"v":1,"type":"ping","payload":{}}"#).is_err());
+        assert!(matches!(decode_client_frame(r#"{"v":2,"type":"bogus","payload":{}}"#), Err(FrameError::Invalid(_))));
+    }
+
+    #[test]
+    fn test_server_message_wire_formats() {
+        let message = ServerMessage::command_result("dev-1".to_string(), "cmd-1".to_string(), "sent", None);
+
+        let legacy: serde_json::Value = serde_json::from_str(&message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap()).unwrap();
+        assert_eq!(legacy["type"], "commandResult");
+        assert_eq!(legacy["requestId"], "cmd-1");
+
+        let enveloped: serde_json::Value = serde_json::from_str(&message.to_wire(PROTOCOL_VERSION).unwrap()).unwrap();
+        assert_eq!(enveloped["v"], 2);
+        assert_eq!(enveloped["type"], "commandResult");
+        assert_eq!(enveloped["requestId"], "cmd-1");
+        assert_eq!(enveloped["payload"]["deviceId"], "dev-1");
+        assert_eq!(enveloped["payload"]["status"], "sent");
+    }
+
+    #[test]
+    fn test_error_frame_wire_formats() {
+        let message = ServerMessage::error(
+            ErrorCode::PermissionDenied,
+            "no write permission".to_string(),
+            Some("req-1".to_string()),
+            Some("dev-1".to_string()),
+        );
+
+        let legacy: serde_json::Value = serde_json::from_str(&message.to_wire(PROTOCOL_VERSION_LEGACY).unwrap()).unwrap();
+        assert_eq!(legacy["type"], "error");
+        assert_eq!(legacy["code"], "permissionDenied");
+        assert_eq!(legacy["message"], "no write permission");
+        assert_eq!(legacy["requestId"], "req-1");
+        assert_eq!(legacy["deviceId"], "dev-1");
+
+        let enveloped: serde_json::Value = serde_json::from_str(&message.to_wire(PROTOCOL_VERSION).unwrap()).unwrap();
+        assert_eq!(enveloped["type"], "error");
+        assert_eq!(enveloped["requestId"], "req-1");
+        assert_eq!(enveloped["payload"]["code"], "permissionDenied");
+        assert_eq!(enveloped["payload"]["deviceId"], "dev-1");
+
+        // Unclassified failures keep the generic code
+        assert_eq!(WsError::from("boom".to_string()).code, ErrorCode::RequestFailed);
+    }
+
+    #[test]
+    fn test_negotiate_protocol_version() {
+        assert_eq!(negotiate_protocol_version(&[1, 2, 3]), Some(2));
+        assert_eq!(negotiate_protocol_version(&[1]), Some(1));
+        assert_eq!(negotiate_protocol_version(&[7]), None);
+    }
+
+    #[test]
+    fn test_hello_offers_compression() {
+        let frame = decode_client_frame(r#"{"v":2,"type":"hello","payload":{"versions":[2],"compression":["deflate"]}}"#).unwrap();
+        assert!(matches!(frame.message, ClientMessage::Hello { ref compression, .. } if compression == &["deflate"]));
+        let frame = decode_client_frame(r#"{"type":"hello","versions":[1,2]}"#).unwrap();
+        assert!(matches!(frame.message, ClientMessage::Hello { ref compression, .. } if compression.is_empty()));
+
+        let welcome: serde_json::Value = serde_json::from_str(&ServerMessage::welcome(2, Some("deflate")).to_wire(PROTOCOL_VERSION).unwrap()).unwrap();
+        assert_eq!(welcome["payload"]["compression"], "deflate");
+        let welcome: serde_json::Value = serde_json::from_str(&ServerMessage::welcome(1, None).to_wire(PROTOCOL_VERSION_LEGACY).unwrap()).unwrap();
+        assert!(welcome.get("compression").is_none());
+    }
+}
@@ -1,9 +1,10 @@
 use axum::{
     body::Body,
-    http::StatusCode,
+    http::{header, HeaderMap, StatusCode},
     response::Response,
 };
 use std::{fs, time::UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 
 
 // ============================================================================
@@ -55,6 +56,153 @@ pub async fn handle_template_file(file_path: &str, cache_control: &str) -> Respo
 }
 
 
+// ============================================================================
+// STREAMING DATEI-AUSLIEFERUNG - Große Dateien (Firmware, Doku-Anhänge) mit Range-Support
+// Die Datei wird in Blöcken gelesen statt komplett in den Speicher geladen;
+// `Range: bytes=...` liefert 206 Partial Content (z.B. fortgesetzte OTA-Downloads)
+// ============================================================================
+
+/// Blockgröße beim Streamen von Dateien
+pub const STREAM_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Einzelnen Byte-Bereich aus einem Range-Header lesen (inklusive Ende).
+/// None = Header ignorieren (fehlt, andere Einheit, mehrere Bereiche), Err = nicht erfüllbar (416)
+pub fn parse_range(header: Option<&str>, len: u64) -> Option<Result<(u64, u64), ()>> {
+    let spec = header?.trim().strip_prefix("bytes=")?;
+    if spec.contains(',') {
+        return None;
+    }
+    let (start, end) = spec.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+    let range = match (start.is_empty(), end.is_empty()) {
+        // "-500": die letzten 500 Bytes
+        (true, false) => {
+            let suffix: u64 = end.parse().ok()?;
+            if suffix == 0 || len == 0 {
+                return Some(Err(()));
+            }
+            (len.saturating_sub(suffix), len - 1)
+        }
+        // "100-" bzw. "100-199"
+        (false, _) => {
+            let start: u64 = start.parse().ok()?;
+            let end = if end.is_empty() { len.saturating_sub(1) } else { end.parse::<u64>().ok()?.min(len.saturating_sub(1)) };
+            if start >= len || start > end {
+                return Some(Err(()));
+            }
+            (start, end)
+        }
+        (true, true) => return None,
+    };
+    Some(Ok(range))
+}
+
+// Datei als Stream ausliefern: ETag/If-None-Match, Range-Anfragen, Blöcke von STREAM_CHUNK_SIZE
+pub async fn serve_file(file_path: &str, content_type: &str, cache_control: &str, headers: &HeaderMap) -> Response<Body> {
+    let file = match tokio::fs::File::open(file_path).await {
+        Ok(file) => file,
+        Err(_) => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("File not found"))
+                .unwrap();
+        }
+    };
+    let metadata = match file.metadata().await {
+        Ok(metadata) if metadata.is_file() => metadata,
+        _ => {
+            return Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("File not found"))
+                .unwrap();
+        }
+    };
+    let len = metadata.len();
+    let modified = metadata.modified().unwrap_or(UNIX_EPOCH).duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+    let etag = format!("\"{}-{}\"", len, modified);
+
+    // Unveränderte Datei: 304 ohne Inhalt
+    if headers.get(header::IF_NONE_MATCH).and_then(|v| v.to_str().ok()) == Some(etag.as_str()) {
+        return Response::builder()
+            .status(StatusCode::NOT_MODIFIED)
+            .header(header::ETAG, etag)
+            .body(Body::empty())
+            .unwrap();
+    }
+
+    // Range nur für die aktuelle Version der Datei (If-Range mit veraltetem ETag = ganze Datei)
+    let if_range_ok = headers.get(header::IF_RANGE).and_then(|v| v.to_str().ok()).is_none_or(|v| v == etag);
+    let range = if if_range_ok { parse_range(headers.get(header::RANGE).and_then(|v| v.to_str().ok()), len) } else { None };
+
+    let builder = Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CACHE_CONTROL, cache_control)
+        .header(header::ETAG, &etag)
+        .header(header::ACCEPT_RANGES, "bytes");
+    let (builder, start, count) = match range {
+        None => (builder.status(StatusCode::OK), 0, len),
+        Some(Ok((start, end))) => (
+            builder.status(StatusCode::PARTIAL_CONTENT).header(header::CONTENT_RANGE, format!("bytes {}-{}/{}", start, end, len)),
+            start,
+            end - start + 1,
+        ),
+        Some(Err(())) => {
+            return builder
+                .status(StatusCode::RANGE_NOT_SATISFIABLE)
+                .header(header::CONTENT_RANGE, format!("bytes */{}", len))
+                .body(Body::empty())
+                .unwrap();
+        }
+    };
+
+    let mut file = file;
+    if start > 0 {
+        if let Err(err) = file.seek(std::io::SeekFrom::Start(start)).await {
+            eprintln!("Error seeking {}: {}", file_path, err);
+            return Response::builder()
+                .status(StatusCode::INTERNAL_SERVER_ERROR)
+                .body(Body::from("Error loading content"))
+                .unwrap();
+        }
+    }
+    let stream = futures::stream::try_unfold((file.take(count), vec![0u8; STREAM_CHUNK_SIZE]), |(mut reader, mut buffer)| async move {
+        let read = reader.read(&mut buffer).await?;
+        if read == 0 {
+            return Ok::<_, std::io::Error>(None);
+        }
+        let chunk = bytes::Bytes::copy_from_slice(&buffer[..read]);
+        Ok(Some((chunk, (reader, buffer))))
+    });
+
+    builder
+        .header(header::CONTENT_LENGTH, count)
+        .body(Body::from_stream(stream))
+        .unwrap()
+}
+
+/// Dateiname aus einem Request-Pfad ohne Verzeichnisanteile (Schutz vor Path Traversal)
+pub fn safe_file_name(path: &str) -> Option<&str> {
+    let name = std::path::Path::new(path).file_name()?.to_str()?;
+    (name == path.trim_start_matches('/') && !name.starts_with('.')).then_some(name)
+}
+
+// Content-Type anhand der Dateiendung (Doku-Anhänge, Firmware)
+pub fn content_type_for(file_path: &str) -> &'static str {
+    match file_path.rsplit('.').next().map(|ext| ext.to_ascii_lowercase()).as_deref() {
+        Some("bin") => "application/octet-stream",
+        Some("pdf") => "application/pdf",
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("svg") => "image/svg+xml",
+        Some("mp4") => "video/mp4",
+        Some("zip") => "application/zip",
+        Some("md") | Some("txt") => "text/plain; charset=utf-8",
+        _ => "application/octet-stream",
+    }
+}
+
+
 // ============================================================================
 // RUST KONZEPTE IN DIESER DATEI:
 // 
@@ -65,4 +213,25 @@ pub async fn handle_template_file(file_path: &str, cache_control: &str) -> Respo
 // 5. File I/O mit std::fs
 // 6. HTTP Response Building mit Builder Pattern
 // 7. Static Lifetimes mit &'static str
-// ============================================================================
\ No newline at end of file
+// 8. Async Streams (futures::stream::try_unfold) für große Dateien
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(parse_range(Some("bytes=0-99"), 1000), Some(Ok((0, 99))));
+        assert_eq!(parse_range(Some("bytes=900-"), 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range(Some("bytes=-100"), 1000), Some(Ok((900, 999))));
+        assert_eq!(parse_range(Some("bytes=500-5000"), 1000), Some(Ok((500, 999))));
+        assert_eq!(parse_range(Some("bytes=1000-"), 1000), Some(Err(())));
+        assert_eq!(parse_range(Some("bytes=0-1,5-9"), 1000), None);
+        assert_eq!(parse_range(Some("items=0-1"), 1000), None);
+        assert_eq!(parse_range(None, 1000), None);
+
+        assert_eq!(safe_file_name("esp32.bin"), Some("esp32.bin"));
+        assert_eq!(safe_file_name("../users.db"), None);
+    }
+}
@@ -0,0 +1,344 @@
+// Fleet summary - one-call operations overview (GET /api/fleet/summary) and firmware inventory
+//
+// Counts by lifecycle state, firmware version distribution, devices silent for more than N
+// hours and the devices sending the most events. Event rates come from the server-side event
+// feed, counted per device in one-minute buckets over the last RATE_WINDOW_MINUTES, so devices
+// nobody is watching are included too.
+//
+// GET /api/fleet/firmware lists the versions devices report in their info messages (stored in
+// devices.firmware_version by the capability tracker) and the devices below
+// firmware.minimum_version. Versions compare numerically ("1.10.0" > "1.9.2", leading "v"
+// ignored); a pre-release ("1.4.0-rc1") is older than the release.
+
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use tokio::sync::broadcast;
+use tracing::warn;
+
+use crate::device_store::SharedDeviceStore;
+
+/// Minutes the event rate is averaged over
+pub const RATE_WINDOW_MINUTES: i64 = 5;
+
+pub const DEFAULT_SILENT_HOURS: u32 = 24;
+pub const DEFAULT_TOP_TALKERS: usize = 10;
+pub const MAX_TOP_TALKERS: usize = 50;
+
+/// Silent devices listed at most (oldest first); `silentCount` has the full number
+pub const MAX_SILENT_LISTED: usize = 100;
+
+static EVENT_RATES: OnceLock<Arc<EventRates>> = OnceLock::new();
+
+// firmware.minimum_version; replaced by a config reload
+static MINIMUM_FIRMWARE: RwLock<Option<String>> = RwLock::new(None);
+
+/// Events per device in one-minute buckets
+#[derive(Debug, Default)]
+pub struct EventRates {
+    buckets: Mutex<HashMap<String, VecDeque<(i64, u32)>>>,
+}
+
+impl EventRates {
+    pub fn record(&self, device_id: &str, timestamp_ms: i64) {
+        let minute = timestamp_ms.div_euclid(60_000);
+        let mut buckets = self.buckets.lock().unwrap();
+        let device = buckets.entry(device_id.to_string()).or_default();
+        match device.back_mut() {
+            Some((last, count)) if *last == minute => *count += 1,
+            _ => device.push_back((minute, 1)),
+        }
+        while device.front().is_some_and(|(first, _)| *first <= minute - RATE_WINDOW_MINUTES) {
+            device.pop_front();
+        }
+    }
+
+    /// Events per minute of every device with events in the window ending at `now_ms`
+    pub fn per_minute(&self, now_ms: i64) -> HashMap<String, f64> {
+        let oldest = now_ms.div_euclid(60_000) - RATE_WINDOW_MINUTES;
+        let mut buckets = self.buckets.lock().unwrap();
+        buckets.retain(|_, device| device.back().is_some_and(|(last, _)| *last > oldest));
+        buckets.iter().map(|(device_id, device)| {
+            let events: u32 = device.iter().filter(|(minute, _)| *minute > oldest).map(|(_, count)| count).sum();
+            (device_id.clone(), events as f64 / RATE_WINDOW_MINUTES as f64)
+        }).collect()
+    }
+}
+
+/// Count every feed event; started once from main
+pub fn start(device_store: SharedDeviceStore) {
+    let rates = Arc::clone(EVENT_RATES.get_or_init(Default::default));
+    let mut feed = device_store.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            match feed.recv().await {
+                Ok(feed_event) => rates.record(&feed_event.device_id, feed_event.timestamp),
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Fleet event rates lagged behind the event feed, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+/// Current rates; empty until `start` ran
+pub fn event_rates() -> HashMap<String, f64> {
+    EVENT_RATES.get().map(|rates| rates.per_minute(Utc::now().timestamp_millis())).unwrap_or_default()
+}
+
+/// What the summary needs of one device
+#[derive(Debug, Clone)]
+pub struct FleetDevice {
+    pub device_id: String,
+    pub name: String,
+    pub status: String,
+    pub firmware_version: Option<String>,
+    pub last_seen: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirmwareCount {
+    /// "unknown" for devices that never reported one
+    pub version: String,
+    pub count: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SilentDevice {
+    pub device_id: String,
+    pub name: String,
+    pub last_seen: DateTime<Utc>,
+    pub silent_hours: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TopTalker {
+    pub device_id: String,
+    pub name: String,
+    pub events_per_minute: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FleetSummary {
+    pub total: usize,
+    pub by_status: BTreeMap<String, usize>,
+    /// Most common version first
+    pub firmware_versions: Vec<FirmwareCount>,
+    pub silent_hours: u32,
+    pub silent_count: usize,
+    pub silent: Vec<SilentDevice>,
+    pub top_talkers: Vec<TopTalker>,
+}
+
+/// Devices per firmware version, most common first
+fn firmware_histogram(devices: &[FleetDevice]) -> Vec<FirmwareCount> {
+    let mut versions: HashMap<&str, usize> = HashMap::new();
+    for device in devices {
+        *versions.entry(device.firmware_version.as_deref().filter(|v| !v.is_empty()).unwrap_or("unknown")).or_default() += 1;
+    }
+    let mut histogram: Vec<FirmwareCount> = versions.into_iter()
+        .map(|(version, count)| FirmwareCount { version: version.to_string(), count })
+        .collect();
+    histogram.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.version.cmp(&b.version)));
+    histogram
+}
+
+pub fn summarize(devices: &[FleetDevice], rates: &HashMap<String, f64>, silent_hours: u32, top: usize, now: DateTime<Utc>) -> FleetSummary {
+    let mut by_status = BTreeMap::new();
+    for device in devices {
+        *by_status.entry(device.status.clone()).or_default() += 1;
+    }
+    let firmware_versions = firmware_histogram(devices);
+
+    let cutoff = now - chrono::Duration::hours(silent_hours as i64);
+    let mut silent: Vec<SilentDevice> = devices.iter().filter(|device| device.last_seen < cutoff).map(|device| SilentDevice {
+        device_id: device.device_id.clone(),
+        name: device.name.clone(),
+        last_seen: device.last_seen,
+        silent_hours: (now - device.last_seen).num_minutes() as f64 / 60.0,
+    }).collect();
+    silent.sort_by_key(|device| device.last_seen);
+    let silent_count = silent.len();
+    silent.truncate(MAX_SILENT_LISTED);
+
+    let mut top_talkers: Vec<TopTalker> = devices.iter().filter_map(|device| {
+        let rate = *rates.get(&device.device_id)?;
+        Some(TopTalker { device_id: device.device_id.clone(), name: device.name.clone(), events_per_minute: rate })
+    }).collect();
+    top_talkers.sort_by(|a, b| b.events_per_minute.total_cmp(&a.events_per_minute).then_with(|| a.device_id.cmp(&b.device_id)));
+    top_talkers.truncate(top);
+
+    FleetSummary {
+        total: devices.len(),
+        by_status,
+        firmware_versions,
+        silent_hours,
+        silent_count,
+        silent,
+        top_talkers,
+    }
+}
+
+// ============================================================================
+// FIRMWARE INVENTORY
+// ============================================================================
+
+/// Comparable form of a version: numeric components, then release after pre-release
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Version {
+    numbers: Vec<u64>,
+    pre_release: Option<String>,
+}
+
+impl Ord for Version {
+    fn cmp(&self, other: &Self) -> Ordering {
+        let length = self.numbers.len().max(other.numbers.len());
+        let component = |numbers: &[u64], i: usize| numbers.get(i).copied().unwrap_or(0);
+        (0..length)
+            .map(|i| component(&self.numbers, i).cmp(&component(&other.numbers, i)))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| match (&self.pre_release, &other.pre_release) {
+                (None, None) => Ordering::Equal,
+                (None, Some(_)) => Ordering::Greater,
+                (Some(_), None) => Ordering::Less,
+                (Some(a), Some(b)) => a.cmp(b),
+            })
+    }
+}
+
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// "1.4.2", "v2.0", "1.4.0-rc1+build5"; None without a leading number ("unknown", git hashes)
+pub fn parse_version(text: &str) -> Option<Version> {
+    let text = text.trim();
+    let text = text.strip_prefix(['v', 'V']).unwrap_or(text);
+    let text = text.split('+').next().unwrap_or_default();
+    let (core, pre_release) = match text.split_once('-') {
+        Some((core, pre)) => (core, Some(pre.to_string())),
+        None => (text, None),
+    };
+    let numbers = core.split('.').map(str::parse::<u64>).collect::<Result<Vec<_>, _>>().ok()?;
+    (!numbers.is_empty()).then_some(Version { numbers, pre_release })
+}
+
+pub fn set_minimum_firmware_version(version: &str) {
+    *MINIMUM_FIRMWARE.write().unwrap() = Some(version.trim()).filter(|v| !v.is_empty()).map(str::to_string);
+}
+
+pub fn minimum_firmware_version() -> Option<String> {
+    MINIMUM_FIRMWARE.read().unwrap().clone()
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirmwareDevice {
+    pub device_id: String,
+    pub name: String,
+    pub firmware_version: Option<String>,
+    pub status: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FirmwareReport {
+    pub total: usize,
+    pub versions: Vec<FirmwareCount>,
+    pub minimum_version: Option<String>,
+    /// Below the minimum version, oldest version first
+    pub outdated: Vec<FirmwareDevice>,
+    /// No version reported yet, or one that cannot be compared
+    pub unknown: Vec<FirmwareDevice>,
+}
+
+pub fn firmware_report(devices: &[FleetDevice], minimum_version: Option<&str>) -> FirmwareReport {
+    let minimum = minimum_version.and_then(parse_version);
+    let entry = |device: &FleetDevice| FirmwareDevice {
+        device_id: device.device_id.clone(),
+        name: device.name.clone(),
+        firmware_version: device.firmware_version.clone(),
+        status: device.status.clone(),
+    };
+
+    let mut outdated = Vec::new();
+    let mut unknown = Vec::new();
+    for device in devices {
+        match device.firmware_version.as_deref().and_then(parse_version) {
+            None => unknown.push(entry(device)),
+            Some(version) if minimum.as_ref().is_some_and(|minimum| version < *minimum) => outdated.push((version, entry(device))),
+            Some(_) => {}
+        }
+    }
+    outdated.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    FirmwareReport {
+        total: devices.len(),
+        versions: firmware_histogram(devices),
+        minimum_version: minimum_version.map(str::to_string),
+        outdated: outdated.into_iter().map(|(_, device)| device).collect(),
+        unknown,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_summary_and_event_rates() {
+        let now = Utc::now();
+        let device = |id: &str, status: &str, firmware: Option<&str>, hours_ago: i64| FleetDevice {
+            device_id: id.to_string(),
+            name: format!("Device {}", id),
+            status: status.to_string(),
+            firmware_version: firmware.map(str::to_string),
+            last_seen: now - chrono::Duration::hours(hours_ago),
+        };
+        let devices = vec![
+            device("A", "Online", Some("1.2.0"), 0),
+            device("B", "Online", Some("1.2.0"), 1),
+            device("C", "Offline", Some("1.1.0"), 30),
+            device("D", "Offline", None, 48),
+        ];
+
+        let rates = EventRates::default();
+        let now_ms = now.timestamp_millis();
+        for _ in 0..50 {
+            rates.record("B", now_ms);
+        }
+        rates.record("A", now_ms - 60_000);
+        // Outside the window
+        rates.record("C", now_ms - (RATE_WINDOW_MINUTES + 1) * 60_000);
+        let per_minute = rates.per_minute(now_ms);
+        assert_eq!(per_minute.get("B"), Some(&10.0));
+        assert!(!per_minute.contains_key("C"));
+
+        let summary = summarize(&devices, &per_minute, 24, 1, now);
+        assert_eq!(summary.total, 4);
+        assert_eq!(summary.by_status.get("Offline"), Some(&2));
+        assert_eq!(summary.firmware_versions[0], FirmwareCount { version: "1.2.0".to_string(), count: 2 });
+        assert!(summary.firmware_versions.iter().any(|fw| fw.version == "unknown"));
+        assert_eq!(summary.silent_count, 2);
+        assert_eq!(summary.silent[0].device_id, "D");
+        assert_eq!(summary.top_talkers.len(), 1);
+        assert_eq!(summary.top_talkers[0].device_id, "B");
+
+        assert!(parse_version("v1.10.0") > parse_version("1.9.2"));
+        assert!(parse_version("1.4.0-rc1") < parse_version("1.4"));
+        assert!(parse_version("unknown").is_none());
+        let report = firmware_report(&devices, Some("1.2"));
+        assert_eq!(report.outdated.iter().map(|d| d.device_id.as_str()).collect::<Vec<_>>(), vec!["C"]);
+        assert_eq!(report.unknown.len(), 1);
+    }
+}
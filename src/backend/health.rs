@@ -0,0 +1,152 @@
+// Health checks - liveness and readiness reports for /healthz and /readyz (Docker/Kubernetes probes)
+
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::app_state::AppState;
+
+/// How long a probe waits for a component that is busy (e.g. UART reconnecting)
+const COMPONENT_CHECK_TIMEOUT: Duration = Duration::from_secs(2);
+
+// ============================================================================
+// REPORT TYPES
+// ============================================================================
+
+/// State of one server component
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ComponentState {
+    Up,
+    Down,
+    /// Not configured or switched off; does not affect readiness
+    Disabled,
+}
+
+/// Status of one component as reported by /readyz
+#[derive(Debug, Clone, Serialize)]
+pub struct ComponentStatus {
+    pub name: &'static str,
+    pub status: ComponentState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+}
+
+impl ComponentStatus {
+    fn new(name: &'static str, status: ComponentState, detail: impl Into<String>) -> Self {
+        Self { name, status, detail: Some(detail.into()) }
+    }
+}
+
+/// Result of a readiness check
+#[derive(Debug, Clone, Serialize)]
+pub struct ReadinessReport {
+    pub ready: bool,
+    pub components: Vec<ComponentStatus>,
+}
+
+impl ReadinessReport {
+    /// Ready as long as no component is down
+    pub fn from_components(components: Vec<ComponentStatus>) -> Self {
+        let ready = components.iter().all(|c| c.status != ComponentState::Down);
+        Self { ready, components }
+    }
+}
+
+// ============================================================================
+// COMPONENT CHECKS
+// ============================================================================
+
+/// Check database, UDP listener, mDNS advertisement, UART listener and outbound access
+pub async fn check_readiness(app_state: &AppState) -> ReadinessReport {
+    let components = vec![
+        check_database(app_state).await,
+        check_udp_listener(app_state).await,
+        check_mdns(app_state).await,
+        check_uart(app_state).await,
+        check_outbound(),
+    ];
+    ReadinessReport::from_components(components)
+}
+
+async fn check_database(app_state: &AppState) -> ComponentStatus {
+    match tokio::time::timeout(COMPONENT_CHECK_TIMEOUT, app_state.db.ping()).await {
+        Ok(Ok(())) => ComponentStatus::new("database", ComponentState::Up, "reachable"),
+        Ok(Err(e)) => ComponentStatus::new("database", ComponentState::Down, e.to_string()),
+        Err(_) => ComponentStatus::new("database", ComponentState::Down, "timed out"),
+    }
+}
+
+async fn check_udp_listener(app_state: &AppState) -> ComponentStatus {
+    let configured = app_state.device_manager.get_udp_listen_ports().await;
+    let bound = app_state.device_manager.get_udp_bound_ports().await;
+
+    if bound.is_empty() {
+        return ComponentStatus::new("udp_listener", ComponentState::Down, format!("no port bound (configured: {:?})", configured));
+    }
+    ComponentStatus::new("udp_listener", ComponentState::Up, format!("bound to {:?}", bound))
+}
+
+async fn check_mdns(app_state: &AppState) -> ComponentStatus {
+    let Ok(server) = tokio::time::timeout(COMPONENT_CHECK_TIMEOUT, app_state.mdns_server.lock()).await else {
+        return ComponentStatus::new("mdns", ComponentState::Down, "busy (lock timeout)");
+    };
+
+    if !server.config().enabled {
+        ComponentStatus::new("mdns", ComponentState::Disabled, "advertising disabled (MDNS_ADVERTISE)")
+    } else if server.is_running() {
+        ComponentStatus::new("mdns", ComponentState::Up, "advertising")
+    } else {
+        ComponentStatus::new("mdns", ComponentState::Down, "not advertising")
+    }
+}
+
+async fn check_uart(app_state: &AppState) -> ComponentStatus {
+    let Ok(uart) = tokio::time::timeout(COMPONENT_CHECK_TIMEOUT, app_state.uart_connection.lock()).await else {
+        return ComponentStatus::new("uart", ComponentState::Down, "busy (lock timeout)");
+    };
+
+    match uart.listener_alive() {
+        None => ComponentStatus::new("uart", ComponentState::Disabled, "no serial port opened"),
+        Some(true) => {
+            let port = uart.get_settings().await.map(|s| s.port).unwrap_or_default();
+            ComponentStatus::new("uart", ComponentState::Up, format!("listening on {}", port))
+        }
+        Some(false) => ComponentStatus::new("uart", ComponentState::Down, "listener thread stopped"),
+    }
+}
+
+fn check_outbound() -> ComponentStatus {
+    if crate::outbound::is_offline() {
+        return ComponentStatus::new("outbound", ComponentState::Disabled, "offline mode ([outbound] offline = true)");
+    }
+    match crate::outbound::status(false).proxy {
+        Some(proxy) => ComponentStatus::new("outbound", ComponentState::Up, format!("via proxy {}", proxy)),
+        None => ComponentStatus::new("outbound", ComponentState::Up, "direct"),
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_disabled_components_keep_server_ready() {
+        let report = ReadinessReport::from_components(vec![
+            ComponentStatus::new("database", ComponentState::Up, "reachable"),
+            ComponentStatus::new("uart", ComponentState::Disabled, "no serial port opened"),
+        ]);
+        assert!(report.ready);
+
+        let report = ReadinessReport::from_components(vec![
+            ComponentStatus::new("database", ComponentState::Down, "timed out"),
+            ComponentStatus::new("uart", ComponentState::Disabled, "no serial port opened"),
+        ]);
+        assert!(!report.ready);
+        assert_eq!(serde_json::to_value(&report.components[0]).unwrap()["status"], "down");
+    }
+}
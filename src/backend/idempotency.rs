@@ -0,0 +1,256 @@
+// Idempotency keys for mutating device endpoints
+//
+// A mobile client that lost the response to POST /api/devices or a device command cannot tell
+// whether the request reached the server; retrying blindly creates the device twice or toggles a
+// relay twice. Requests carrying an `Idempotency-Key` header (any unique string, e.g. a UUID) are
+// remembered for IDEMPOTENCY_TTL per user, method and path:
+//   - a retry with the same key and body gets the stored response again (`Idempotent-Replayed: true`)
+//   - a retry while the first request is still running gets 409 IDEMPOTENCY_KEY_IN_USE
+//   - the same key with a different body gets 422 IDEMPOTENCY_KEY_REUSED
+// Transient server errors (500, 503) are not stored, so the retry runs again. Requests without
+// the header, and requests without a login (no user to scope the key to), behave as before.
+// The store is in memory and lost on restart.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::body::{Body, Bytes};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use axum_extra::extract::CookieJar;
+use sha2::{Digest, Sha256};
+
+use crate::api_error::ApiError;
+
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+pub const REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// How long a key is remembered after the request finished
+pub const IDEMPOTENCY_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Longest accepted key
+pub const MAX_KEY_LENGTH: usize = 255;
+
+/// Remembered keys before new ones are refused with 429
+const MAX_ENTRIES: usize = 10_000;
+
+/// Largest request body that is fingerprinted (firmware uploads included)
+const MAX_REQUEST_BYTES: usize = 16 * 1024 * 1024;
+
+/// Largest response that is stored for replays
+const MAX_RESPONSE_BYTES: usize = 1024 * 1024;
+
+static STORE: LazyLock<Mutex<HashMap<String, Entry>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+#[derive(Debug, Clone)]
+struct StoredResponse {
+    status: StatusCode,
+    content_type: Option<HeaderValue>,
+    body: Bytes,
+}
+
+#[derive(Debug, Clone)]
+enum EntryState {
+    InFlight,
+    Done(StoredResponse),
+}
+
+#[derive(Debug, Clone)]
+struct Entry {
+    fingerprint: String,
+    state: EntryState,
+    expires: Instant,
+}
+
+/// What to do with a request carrying a key
+#[derive(Debug)]
+enum Claim {
+    /// First request: run it, then `finish`
+    Run,
+    Replay(StoredResponse),
+    InFlight,
+    Mismatch,
+    Full,
+}
+
+fn claim(store_key: &str, fingerprint: &str, now: Instant) -> Claim {
+    let mut store = STORE.lock().unwrap();
+    if store.len() >= MAX_ENTRIES {
+        store.retain(|_, entry| entry.expires > now);
+    }
+    match store.get(store_key) {
+        Some(entry) if entry.expires > now => {
+            if entry.fingerprint != fingerprint {
+                return Claim::Mismatch;
+            }
+            match &entry.state {
+                EntryState::InFlight => Claim::InFlight,
+                EntryState::Done(response) => Claim::Replay(response.clone()),
+            }
+        }
+        _ if store.len() >= MAX_ENTRIES => Claim::Full,
+        _ => {
+            store.insert(store_key.to_string(), Entry {
+                fingerprint: fingerprint.to_string(),
+                state: EntryState::InFlight,
+                // A handler that never returns must not block the key forever
+                expires: now + IDEMPOTENCY_TTL,
+            });
+            Claim::Run
+        }
+    }
+}
+
+/// Store the response of a claimed key (None releases the key for another attempt)
+fn finish(store_key: &str, response: Option<StoredResponse>, now: Instant) {
+    let mut store = STORE.lock().unwrap();
+    match response {
+        Some(response) => {
+            if let Some(entry) = store.get_mut(store_key) {
+                entry.state = EntryState::Done(response);
+                entry.expires = now + IDEMPOTENCY_TTL;
+            }
+        }
+        None => {
+            store.remove(store_key);
+        }
+    }
+}
+
+fn is_valid_key(key: &str) -> bool {
+    !key.is_empty() && key.len() <= MAX_KEY_LENGTH && key.chars().all(|c| c.is_ascii_graphic())
+}
+
+/// User the key belongs to; None without a valid login
+fn key_owner(headers: &HeaderMap) -> Option<String> {
+    CookieJar::from_headers(headers)
+        .get("auth_token")
+        .and_then(|cookie| crate::auth::validate_jwt(cookie.value()).ok())
+        .map(|claims| claims.user_id)
+}
+
+fn fingerprint(content_type: Option<&HeaderValue>, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(content_type.map(HeaderValue::as_bytes).unwrap_or_default());
+    hasher.update([0]);
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replay(response: StoredResponse) -> Response {
+    let mut replayed = (response.status, response.body).into_response();
+    if let Some(content_type) = response.content_type {
+        replayed.headers_mut().insert(header::CONTENT_TYPE, content_type);
+    }
+    replayed.headers_mut().insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    replayed
+}
+
+/// Route layer: `post(handler).layer(axum::middleware::from_fn(idempotency::idempotency_middleware))`
+pub async fn idempotency_middleware(request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return next.run(request).await;
+    };
+    // Guests would share one key space and could replay each other's responses
+    let Some(owner) = key_owner(request.headers()) else {
+        return next.run(request).await;
+    };
+    let Some(key) = key.to_str().ok().filter(|key| is_valid_key(key)).map(str::to_string) else {
+        return ApiError::bad_request(format!("Idempotency-Key must be 1 to {} printable ASCII characters", MAX_KEY_LENGTH)).into_response();
+    };
+
+    let (parts, body) = request.into_parts();
+    let body = match axum::body::to_bytes(body, MAX_REQUEST_BYTES).await {
+        Ok(body) => body,
+        Err(_) => {
+            return ApiError::new(StatusCode::PAYLOAD_TOO_LARGE, "PAYLOAD_TOO_LARGE", "Request body too large for an idempotent request")
+                .into_response();
+        }
+    };
+    let store_key = format!("{}\n{}\n{}\n{}", owner, parts.method, parts.uri.path(), key);
+    let fingerprint = fingerprint(parts.headers.get(header::CONTENT_TYPE), &body);
+
+    match claim(&store_key, &fingerprint, Instant::now()) {
+        Claim::Run => {}
+        Claim::Replay(response) => {
+            tracing::debug!("Replaying stored response for Idempotency-Key {} ({} {})", key, parts.method, parts.uri.path());
+            return replay(response);
+        }
+        Claim::InFlight => {
+            return ApiError::new(StatusCode::CONFLICT, "IDEMPOTENCY_KEY_IN_USE", "A request with this Idempotency-Key is still being processed")
+                .into_response();
+        }
+        Claim::Mismatch => {
+            return ApiError::new(StatusCode::UNPROCESSABLE_ENTITY, "IDEMPOTENCY_KEY_REUSED", "Idempotency-Key was already used for a different request")
+                .into_response();
+        }
+        Claim::Full => {
+            return ApiError::new(StatusCode::TOO_MANY_REQUESTS, "LIMIT_EXCEEDED", "Too many idempotent requests, retry later").into_response();
+        }
+    }
+
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    let status = response.status();
+    if matches!(status, StatusCode::INTERNAL_SERVER_ERROR | StatusCode::SERVICE_UNAVAILABLE) {
+        finish(&store_key, None, Instant::now());
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, MAX_RESPONSE_BYTES).await {
+        Ok(body) => {
+            finish(&store_key, Some(StoredResponse {
+                status,
+                content_type: parts.headers.get(header::CONTENT_TYPE).cloned(),
+                body: body.clone(),
+            }), Instant::now());
+            Response::from_parts(parts, Body::from(body))
+        }
+        Err(e) => {
+            finish(&store_key, None, Instant::now());
+            tracing::warn!("Response for Idempotency-Key {} could not be stored: {}", key, e);
+            ApiError::internal("Response too large for an idempotent request").into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_claim_replay_and_mismatch() {
+        let now = Instant::now();
+        let key = "user-1\nPOST\n/api/devices\nretry-test";
+        assert!(matches!(claim(key, "body-a", now), Claim::Run));
+        assert!(matches!(claim(key, "body-a", now), Claim::InFlight));
+        assert!(matches!(claim(key, "body-b", now), Claim::Mismatch));
+
+        finish(key, Some(StoredResponse { status: StatusCode::CREATED, content_type: None, body: Bytes::from_static(b"{}") }), now);
+        match claim(key, "body-a", now) {
+            Claim::Replay(response) => assert_eq!(response.status, StatusCode::CREATED),
+            other => panic!("expected a replay, got {:?}", other),
+        }
+        assert!(matches!(claim(key, "body-a", now + IDEMPOTENCY_TTL + Duration::from_secs(1)), Claim::Run));
+
+        let failed = "user-1\nPOST\n/api/devices\nfailed";
+        assert!(matches!(claim(failed, "body", now), Claim::Run));
+        finish(failed, None, now);
+        assert!(matches!(claim(failed, "body", now), Claim::Run));
+
+        assert!(is_valid_key("0b7c2d8e-6a1f-4f8e-9d0b-2f4c6e8a0b1c"));
+        assert!(!is_valid_key("") && !is_valid_key("with space"));
+    }
+
+    #[test]
+    fn test_requests_without_login_have_no_key_owner() {
+        assert_eq!(key_owner(&HeaderMap::new()), None);
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::COOKIE, HeaderValue::from_static("auth_token=not-a-jwt"));
+        assert_eq!(key_owner(&headers), None);
+    }
+}
@@ -0,0 +1,242 @@
+// Inbound validation - limits and sanitization of device payloads before they are stored or broadcast
+//
+// Whatever a device sends ends up in the event store and in every browser watching it, so
+// received data is checked on the way in:
+//
+//   - TCP and UART readers drop frames that grow past devices.max_message_bytes without being
+//     completed (a device that never closes its JSON object or sends no ETX) and TCP streams
+//     that are not UTF-8 (UDP datagrams and UART frames are decoded strictly already)
+//   - DeviceManager::handle_message_unified rejects messages over devices.max_message_bytes and
+//     JSON nested deeper than devices.max_json_depth, and removes control characters except tab,
+//     newline and carriage return - raw ones as well as JSON escapes such as \u001b
+//   - variable names keep letters, digits and `_ - . : /` (anything else becomes `_`) and at
+//     most MAX_VARIABLE_NAME_LEN characters; names without a letter or digit are dropped
+
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::RwLock;
+
+use crate::events::DeviceEvent;
+
+pub const DEFAULT_MAX_MESSAGE_BYTES: usize = 16 * 1024;
+pub const DEFAULT_MAX_JSON_DEPTH: usize = 32;
+
+/// Longer variable names are cut
+pub const MAX_VARIABLE_NAME_LEN: usize = 64;
+
+// [devices] max_message_bytes / max_json_depth; replaced by a config reload
+static LIMITS: RwLock<InboundLimits> = RwLock::new(InboundLimits {
+    max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES,
+    max_json_depth: DEFAULT_MAX_JSON_DEPTH,
+});
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InboundLimits {
+    pub max_message_bytes: usize,
+    pub max_json_depth: usize,
+}
+
+impl Default for InboundLimits {
+    fn default() -> Self {
+        Self { max_message_bytes: DEFAULT_MAX_MESSAGE_BYTES, max_json_depth: DEFAULT_MAX_JSON_DEPTH }
+    }
+}
+
+pub fn configure(limits: InboundLimits) {
+    *LIMITS.write().unwrap() = limits;
+}
+
+pub fn limits() -> InboundLimits {
+    *LIMITS.read().unwrap()
+}
+
+/// Why a received message was dropped
+#[derive(Debug, Clone, PartialEq)]
+pub enum Rejection {
+    TooLarge { size: usize, limit: usize },
+    InvalidUtf8,
+    TooDeep { limit: usize },
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge { size, limit } => write!(f, "message of {} bytes exceeds the limit of {} bytes", size, limit),
+            Self::InvalidUtf8 => write!(f, "data is not valid UTF-8"),
+            Self::TooDeep { limit } => write!(f, "JSON is nested deeper than {} levels", limit),
+        }
+    }
+}
+
+/// Err once a frame that is still being received has grown past the message limit
+pub fn check_size(size: usize) -> Result<(), Rejection> {
+    let limit = limits().max_message_bytes;
+    if size > limit {
+        return Err(Rejection::TooLarge { size, limit });
+    }
+    Ok(())
+}
+
+/// Text of the next chunk of a byte stream; an incomplete character at its end waits in
+/// `undecoded` for the following chunk. Invalid data clears `undecoded`.
+pub fn decode_stream_chunk(undecoded: &mut Vec<u8>, chunk: &[u8]) -> Result<String, Rejection> {
+    undecoded.extend_from_slice(chunk);
+    let valid = match std::str::from_utf8(undecoded) {
+        Ok(_) => undecoded.len(),
+        Err(e) if e.error_len().is_none() => e.valid_up_to(),
+        Err(_) => {
+            undecoded.clear();
+            return Err(Rejection::InvalidUtf8);
+        }
+    };
+    let text = String::from_utf8_lossy(&undecoded[..valid]).into_owned();
+    undecoded.drain(..valid);
+    Ok(text)
+}
+
+/// Check a complete received message; the result has no control characters
+pub fn check_message(message: &str) -> Result<Cow<'_, str>, Rejection> {
+    check_with(message, &limits())
+}
+
+fn check_with<'a>(message: &'a str, limits: &InboundLimits) -> Result<Cow<'a, str>, Rejection> {
+    if message.len() > limits.max_message_bytes {
+        return Err(Rejection::TooLarge { size: message.len(), limit: limits.max_message_bytes });
+    }
+
+    // Nesting and escapes only mean something in JSON; plain text lines just lose raw control characters
+    let json = message.trim_start().starts_with(['{', '[']);
+    let mut depth = 0usize;
+    let mut in_string = false;
+    // Allocated at the first removed character
+    let mut cleaned: Option<String> = None;
+    let mut position = 0;
+
+    while let Some(c) = message[position..].chars().next() {
+        let (keep, length) = if json && in_string && c == '\\' {
+            let escaped = &message[position + 1..];
+            match escaped_control_len(escaped) {
+                Some(length) => (false, 1 + length),
+                // Copied together so an escaped quote does not end the string
+                None => (true, 1 + escaped.chars().next().map_or(0, char::len_utf8)),
+            }
+        } else {
+            match c {
+                '"' if json => in_string = !in_string,
+                '{' | '[' if json && !in_string => {
+                    depth += 1;
+                    if depth > limits.max_json_depth {
+                        return Err(Rejection::TooDeep { limit: limits.max_json_depth });
+                    }
+                }
+                '}' | ']' if json && !in_string => depth = depth.saturating_sub(1),
+                _ => {}
+            }
+            (!is_unwanted_control(c), c.len_utf8())
+        };
+
+        match (&mut cleaned, keep) {
+            (Some(cleaned), true) => cleaned.push_str(&message[position..position + length]),
+            (None, false) => cleaned = Some(message[..position].to_string()),
+            _ => {}
+        }
+        position += length;
+    }
+
+    Ok(cleaned.map_or(Cow::Borrowed(message), Cow::Owned))
+}
+
+fn is_unwanted_control(c: char) -> bool {
+    c.is_control() && !matches!(c, '\t' | '\n' | '\r')
+}
+
+/// Length of a JSON escape (after the backslash) that decodes to an unwanted control character
+fn escaped_control_len(escaped: &str) -> Option<usize> {
+    match escaped.as_bytes().first()? {
+        b'b' | b'f' => Some(1),
+        b'u' => {
+            let code = u32::from_str_radix(escaped.get(1..5)?, 16).ok()?;
+            char::from_u32(code).filter(|c| is_unwanted_control(*c)).map(|_| 5)
+        }
+        _ => None,
+    }
+}
+
+/// Allowed form of a variable name; None if no letter or digit is left
+pub fn sanitize_variable_name(name: &str) -> Option<Cow<'_, str>> {
+    let allowed = |c: char| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | ':' | '/');
+    let name = name.trim();
+    if !name.chars().any(|c| c.is_ascii_alphanumeric()) {
+        return None;
+    }
+    if name.len() <= MAX_VARIABLE_NAME_LEN && name.chars().all(allowed) {
+        return Some(Cow::Borrowed(name));
+    }
+    Some(Cow::Owned(name.chars().take(MAX_VARIABLE_NAME_LEN).map(|c| if allowed(c) { c } else { '_' }).collect()))
+}
+
+/// Sanitize the variable names of a parsed event; false if nothing usable is left
+pub fn sanitize_event(event: &mut DeviceEvent) -> bool {
+    match event {
+        DeviceEvent::DeviceVariableUpdate { variable_name, .. } => match sanitize_variable_name(variable_name) {
+            Some(Cow::Borrowed(_)) => true,
+            Some(Cow::Owned(sanitized)) => {
+                *variable_name = sanitized;
+                true
+            }
+            None => false,
+        },
+        DeviceEvent::DeviceChangeableVariables { variables, .. } => {
+            variables.retain_mut(|variable| {
+                let Some(name) = variable.get("name").and_then(|name| name.as_str()) else { return false };
+                match sanitize_variable_name(name).map(Cow::into_owned) {
+                    Some(sanitized) => {
+                        variable["name"] = sanitized.into();
+                        true
+                    }
+                    None => false,
+                }
+            });
+            !variables.is_empty()
+        }
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_and_sanitization() {
+        let limits = InboundLimits { max_message_bytes: 64, max_json_depth: 3 };
+        assert_eq!(check_with(r#"{"temp": 21.5}"#, &limits), Ok(Cow::Borrowed(r#"{"temp": 21.5}"#)));
+        assert!(matches!(check_with(&"x".repeat(65), &limits), Err(Rejection::TooLarge { size: 65, limit: 64 })));
+        assert_eq!(check_with(r#"{"a":[[{"b":1}]]}"#, &limits), Err(Rejection::TooDeep { limit: 3 }));
+        // Brackets inside strings do not count
+        assert!(check_with(r#"{"a":"[[[[[["}"#, &limits).is_ok());
+
+        assert_eq!(check_with("{\"msg\":\"a\x1b[31mb\\u001Bc\\\\u001b\\n\"}", &limits).unwrap(), "{\"msg\":\"a[31mbc\\\\u001b\\n\"}");
+        assert_eq!(check_with("line\u{7}\u{9b}\tend", &limits).unwrap(), "line\tend");
+
+        let mut undecoded = Vec::new();
+        let bytes = "grün".as_bytes();
+        assert_eq!(decode_stream_chunk(&mut undecoded, &bytes[..3]).unwrap(), "gr");
+        assert_eq!(decode_stream_chunk(&mut undecoded, &bytes[3..]).unwrap(), "ün");
+        assert_eq!(decode_stream_chunk(&mut undecoded, &[b'a', 0xff, b'b']), Err(Rejection::InvalidUtf8));
+        assert!(undecoded.is_empty());
+
+        assert_eq!(sanitize_variable_name("sensor.temp_1").unwrap(), "sensor.temp_1");
+        assert_eq!(sanitize_variable_name("<img src=x>").unwrap(), "_img_src_x_");
+        assert_eq!(sanitize_variable_name(&"v".repeat(100)).unwrap().len(), MAX_VARIABLE_NAME_LEN);
+        assert!(sanitize_variable_name("<>!").is_none());
+
+        let mut event = DeviceEvent::device_changeable_variables("AA".to_string(), vec![
+            serde_json::json!({"name": "speed<b>", "value": 1}),
+            serde_json::json!({"name": "***", "value": 2}),
+        ]);
+        assert!(sanitize_event(&mut event));
+        let DeviceEvent::DeviceChangeableVariables { variables, .. } = event else { unreachable!() };
+        assert_eq!(variables, vec![serde_json::json!({"name": "speed_b_", "value": 1})]);
+    }
+}
@@ -0,0 +1,164 @@
+// ============================================================================
+// KEEPALIVE - Server-initiated ping/pong liveness of WebSocket clients
+// ============================================================================
+//
+// The writer task of every /channel connection sends a ping frame per
+// interval. A client that leaves more than `max_missed_pongs` pings
+// unanswered is marked dead: its connection is closed and the cleanup task
+// is woken to drop it from the device user lists right away.
+
+use serde::Serialize;
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+use tokio::sync::Notify;
+use tracing::warn;
+
+/// Default time between two server pings
+pub const DEFAULT_PING_INTERVAL: Duration = Duration::from_secs(20);
+
+/// Default number of unanswered pings before a client counts as dead
+pub const DEFAULT_MAX_MISSED_PONGS: u32 = 3;
+
+/// Keepalive settings of the /channel endpoint
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeepaliveConfig {
+    pub ping_interval: Duration,
+    pub max_missed_pongs: u32,
+}
+
+impl Default for KeepaliveConfig {
+    fn default() -> Self {
+        Self {
+            ping_interval: DEFAULT_PING_INTERVAL,
+            max_missed_pongs: DEFAULT_MAX_MISSED_PONGS,
+        }
+    }
+}
+
+// [websocket]; replaced by a config reload, read when a client connects
+static SETTINGS: RwLock<KeepaliveConfig> = RwLock::new(KeepaliveConfig {
+    ping_interval: DEFAULT_PING_INTERVAL,
+    max_missed_pongs: DEFAULT_MAX_MISSED_PONGS,
+});
+
+pub fn configure(config: KeepaliveConfig) {
+    *SETTINGS.write().unwrap() = config;
+}
+
+/// Settings for a new connection
+pub fn config() -> KeepaliveConfig {
+    *SETTINGS.read().unwrap()
+}
+
+/// Liveness state of one client
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LivenessSnapshot {
+    pub alive: bool,
+    pub missed_pongs: u32,
+    pub last_seen: i64,
+}
+
+/// Shared liveness of one WebSocket client
+#[derive(Debug)]
+pub struct ClientLiveness {
+    max_missed_pongs: u32,
+    missed_pongs: AtomicU32,
+    last_seen_ms: AtomicI64,
+    dead: AtomicBool,
+    dead_notify: Notify,
+    // Wakes the store cleanup task when this client dies
+    on_dead: Option<Arc<Notify>>,
+}
+
+impl ClientLiveness {
+    pub fn new(max_missed_pongs: u32, on_dead: Option<Arc<Notify>>) -> Self {
+        Self {
+            max_missed_pongs,
+            missed_pongs: AtomicU32::new(0),
+            last_seen_ms: AtomicI64::new(chrono::Utc::now().timestamp_millis()),
+            dead: AtomicBool::new(false),
+            dead_notify: Notify::new(),
+            on_dead,
+        }
+    }
+
+    /// Count a ping about to be sent; false once too many went unanswered
+    pub fn record_ping(&self) -> bool {
+        let missed = self.missed_pongs.fetch_add(1, Ordering::Relaxed);
+        if missed >= self.max_missed_pongs {
+            warn!("Client missed {} pongs, marking connection dead", missed);
+            self.mark_dead();
+            return false;
+        }
+        !self.is_dead()
+    }
+
+    /// Pong received: the client is alive
+    pub fn record_pong(&self) {
+        self.missed_pongs.store(0, Ordering::Relaxed);
+        self.record_activity();
+    }
+
+    /// Any frame received from the client
+    pub fn record_activity(&self) {
+        self.last_seen_ms.store(chrono::Utc::now().timestamp_millis(), Ordering::Relaxed);
+    }
+
+    pub fn mark_dead(&self) {
+        if !self.dead.swap(true, Ordering::Relaxed) {
+            self.dead_notify.notify_waiters();
+            if let Some(on_dead) = &self.on_dead {
+                on_dead.notify_one();
+            }
+        }
+    }
+
+    pub fn is_dead(&self) -> bool {
+        self.dead.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the client was marked dead
+    pub async fn dead(&self) {
+        let notified = self.dead_notify.notified();
+        if !self.is_dead() {
+            notified.await;
+        }
+    }
+
+    pub fn snapshot(&self) -> LivenessSnapshot {
+        LivenessSnapshot {
+            alive: !self.is_dead(),
+            missed_pongs: self.missed_pongs.load(Ordering::Relaxed),
+            last_seen: self.last_seen_ms.load(Ordering::Relaxed),
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_missed_pongs_mark_client_dead() {
+        let on_dead = Arc::new(Notify::new());
+        let liveness = ClientLiveness::new(2, Some(on_dead.clone()));
+
+        assert!(liveness.record_ping());
+        liveness.record_pong();
+        assert!(liveness.record_ping());
+        assert!(liveness.record_ping());
+        assert_eq!(liveness.snapshot().missed_pongs, 2);
+
+        // Third unanswered ping exceeds the limit
+        assert!(!liveness.record_ping());
+        assert!(liveness.is_dead());
+        tokio::time::timeout(Duration::from_secs(1), liveness.dead()).await.expect("dead signal");
+        tokio::time::timeout(Duration::from_secs(1), on_dead.notified()).await.expect("cleanup signal");
+    }
+}
@@ -1,38 +1,101 @@
 // ============================================================================
-// LIB.RS - LIBRARY EXPORTS FOR TESTING
-// Makes internal modules available as library for integration tests
+// LIB.RS - THE SERVER AS LIBRARY
+// Single module tree shared by the server binary (main.rs) and the tests
 // ============================================================================
 
 use std::sync::Arc;
-use axum::{Router, Json, extract::State, http::StatusCode};
-use serde_json::{json, Value};
+use axum::Router;
 
 // All modules
-pub mod app_state;
-pub mod auth;
-pub mod file_utils;
-pub mod database;
-pub mod device_store;
-pub mod events;
-pub mod websocket;
-pub mod device_types;
-pub mod device_connection;
-pub mod device_manager;
-pub mod device_discovery;
-pub mod mdns_discovery;
-pub mod mdns_server;
-pub mod debug_logger;
-pub mod uart_connection;
+pub mod api_error;   // api_error.rs - Uniform JSON error responses
+pub mod app_state;   // app_state.rs - Centralized application state
+pub mod routes;      // routes.rs - Router and REST handlers (create_app)
+pub mod auth;        // auth.rs - Authentication (Login, Register, JWT)
+pub mod config;      // config.rs - Runtime configuration (config.toml + env overrides)
+pub mod logging;     // logging.rs - Text/JSON log output and request IDs
+pub mod proxy;       // proxy.rs - Reverse proxy support (X-Forwarded-*, path prefix)
+pub mod outbound;    // outbound.rs - Offline mode and HTTP proxy for calls leaving the local network
+pub mod diagnostics; // diagnostics.rs - Host self-test (ports, multicast, serial access, DB, disk, clock) for --doctor
+pub mod tls;         // tls.rs - Built-in HTTPS listener and HTTP -> HTTPS redirect
+pub mod device_trace; // device_trace.rs - Per-device raw frame capture (NDJSON/pcap)
+pub mod secrets;     // secrets.rs - AES-256-GCM encryption of secrets stored in the database, key rotation
+pub mod device_identity; // device_identity.rs - HMAC challenge-response proving a device's identity after TCP connect
+pub mod webhooks;    // webhooks.rs - Signed outbound webhook notifications
+pub mod alerts;      // alerts.rs - Alert rules on device variables and connectivity
+pub mod email;       // email.rs - SMTP email notifications with a persistent send queue
+pub mod schedules;   // schedules.rs - Recurring device commands with catch-up after downtime
+pub mod scripts;     // scripts.rs - Sandboxed Rhai scripts triggered by device events
+pub mod file_utils;  // file_utils.rs - File handling and SPA routing
+pub mod docs;        // docs.rs - Markdown documentation rendered to JSON (title, html, toc, links)
+pub mod database;    // database.rs - SQLite database integration
+pub mod events;      // events.rs - Event definitions for devices
+pub mod event_export; // event_export.rs - CSV/NDJSON export of device event history
+pub mod device_store; // device_store.rs - In-Memory Event Store for devices
+pub mod device_clock; // device_clock.rs - Device uptime/timestamp reports, drift estimates and event stamps
+pub mod sharded_map;  // sharded_map.rs - Hash-sharded RwLock maps for the event store
+pub mod client_queue; // client_queue.rs - Bounded per-client WebSocket queues (backpressure)
+pub mod keepalive; // keepalive.rs - WebSocket ping/pong liveness
+pub mod ws_stats; // ws_stats.rs - WebSocket traffic per device (messages/sec, drops) for monitoring
+pub mod device_logs; // device_logs.rs - Log frames sent by devices, per-device log buffers with level filter
+pub mod crash_reports; // crash_reports.rs - Reset reasons and core dumps reported by devices
+pub mod provisioning; // provisioning.rs - ESP-IDF Wi-Fi provisioning QR payloads and device registration tokens
+pub mod enrollment; // enrollment.rs - Pre-shared per-device tokens for automatic adoption
+pub mod time_service; // time_service.rs - SNTP/JSON time responder and periodic setTime for devices
+pub mod routing; // routing.rs - Device-to-device routing rules with loop protection and rate limits
+pub mod recordings; // recordings.rs - Recording of device event streams and replay into simulated channels
+pub mod variable_snapshots; // variable_snapshots.rs - Saved device variable states, diff and restore via setVariable
+pub mod activity; // activity.rs - Device audit log and the readable activity feed derived from it
+pub mod device_chat; // device_chat.rs - Chat notes on the device channel with a bounded stored history
+pub mod mentions; // mentions.rs - Unique user handles and @handle mentions in the device channel
+pub mod health;    // health.rs - Liveness/readiness checks for /healthz and /readyz
+pub mod websocket;   // websocket.rs - WebSocket handler for multiuser
+pub mod device_types; // device_types.rs - Device communication types
+pub mod device_connection; // device_connection.rs - Device TCP/UDP connection handling
+pub mod device_manager; // device_manager.rs - Device management
+pub mod device_lifecycle; // device_lifecycle.rs - Per-device lifecycle state machine (Discovered ... Maintenance, Quarantined)
+pub mod command_queue;  // command_queue.rs - Outbound per-device command queue
+pub mod command_rate_limit; // command_rate_limit.rs - Token bucket limits for client commands per device and user
+pub mod idempotency; // idempotency.rs - Idempotency-Key handling for device mutation endpoints
+pub mod payload_codec;  // payload_codec.rs - CBOR/MessagePack/binary payload decoding
+pub mod inbound_validation; // inbound_validation.rs - Size/UTF-8/JSON depth limits and control character and variable name sanitization of device input
+pub mod device_simulator; // device_simulator.rs - Simulated ESP32 devices for development
+pub mod mdns_discovery; // mdns_discovery.rs - mDNS-based device discovery
+pub mod mdns_server;    // mdns_server.rs - mDNS server for advertising device-manager.local
+pub mod device_discovery; // device_discovery.rs - Device discovery service
+pub mod debug_logger;   // debug_logger.rs - Debug event logging
+pub mod device_capabilities; // device_capabilities.rs - Cached device capabilities (variables, start options, firmware, features)
+pub mod device_supervisor; // device_supervisor.rs - Reconnect policies and the per-device connection supervisor
+pub mod startup_connect; // startup_connect.rs - Concurrent connect of stored devices at startup
+pub mod device_transport; // device_transport.rs - DeviceTransport trait for TCP/UART/... device links
+pub mod udp_worker_pool; // udp_worker_pool.rs - Bounded worker pool parsing received UDP datagrams
+pub mod variable_coalescer; // variable_coalescer.rs - Rate limit/coalescing of high-frequency variable updates
+pub mod organizations; // organizations.rs - Organizations/workspaces scoping devices and groups to their members
+pub mod permissions; // permissions.rs - Effective device permissions (direct > group > organization default)
+pub mod permission_expiry; // permission_expiry.rs - Removal of expired time-limited device permissions
+pub mod device_inventory; // device_inventory.rs - JSON/CSV import and export of the device list
+pub mod device_labels; // device_labels.rs - Printable QR code labels linking to the device page
+pub mod device_profiles; // device_profiles.rs - Device type profiles, validation of commands against variable ranges
+pub mod command_templates; // command_templates.rs - Named device command sequences with parameters
+pub mod uart_connection; // uart_connection.rs - UART/Serial connection handling
+pub mod uart_gateway; // uart_gateway.rs - Gateway framing for radio bridges multiplexing downstream nodes over UART
+pub mod espnow_peers; // espnow_peers.rs - Read-only child devices for ESP-NOW peers reported by connected devices
+pub mod bus_decoders; // bus_decoders.rs - Per-device decoder plugins for raw bus frames (built-in Modbus RTU register maps)
+pub mod message_parsers; // message_parsers.rs - Ordered parser chains (JSON envelope, regex, custom) for received device messages
+pub mod service; // service.rs - systemd readiness/watchdog notifications, socket activation, PID file
+pub mod config_reload; // config_reload.rs - Re-apply runtime settings from the config file (SIGHUP, admin endpoint)
+pub mod device_availability; // device_availability.rs - Uptime %, disconnects and mean time between disconnects from link state changes
+pub mod fleet; // fleet.rs - Fleet-wide summary (status counts, firmware versions, silent devices, top talkers by event rate)
+pub mod quarantine; // quarantine.rs - Automatic quarantine of devices exceeding message or error rate limits
 
 // Re-export key types for tests
 pub use app_state::AppState;
 pub use database::DatabaseManager;
 pub use device_store::{create_shared_store, SharedDeviceStore};
 
-// Create a test-friendly app instance
+// Create a test-friendly app instance: the real router with the same components the binary starts
 pub async fn create_test_app() -> Router {
     // Initialize minimal components for testing
-    let db = Arc::new(DatabaseManager::new().await.expect("Failed to create test database"));
+    let db = Arc::new(DatabaseManager::new_memory().await.expect("Failed to create test database"));
     let device_store = create_shared_store();
     let device_manager = device_manager::create_device_manager(device_store.clone());
 
@@ -47,21 +110,10 @@ pub async fn create_test_app() -> Router {
         mdns_server::MdnsServer::new().expect("Failed to create test mDNS server")
     ));
 
-    // Create UART connection for tests with empty shared state trackers
-    use std::collections::HashMap;
-    use tokio::sync::RwLock;
-    let unified_connection_states = Arc::new(RwLock::new(HashMap::new()));
-    let unified_activity_tracker = Arc::new(RwLock::new(HashMap::new()));
-    let device_connection_types = Arc::new(RwLock::new(HashMap::new()));
-
-    let uart_connection = Arc::new(tokio::sync::Mutex::new(
-        uart_connection::UartConnection::new(
-            device_store.clone(),
-            unified_connection_states,
-            unified_activity_tracker,
-            device_connection_types,
-        )
-    ));
+    // UART connection delivers into the manager's transport context, like in main.rs
+    let uart_conn = uart_connection::UartConnection::new(device_manager.transport_context());
+    device_manager.set_uart_port(uart_conn.port()).await;
+    let uart_connection = Arc::new(tokio::sync::Mutex::new(uart_conn));
 
     // Add test device for consistent testing
     let ip = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 43, 75));
@@ -73,115 +125,8 @@ pub async fn create_test_app() -> Router {
     );
     let _ = device_manager.add_device(test_device).await;
 
-    // Create app using the internal function
-    create_app_internal(db, device_store, device_manager, device_discovery, mdns_server, uart_connection).await
-}
-
-// Internal helper to create app for testing
-// This avoids circular imports with main.rs
-async fn create_app_internal(
-    db: Arc<DatabaseManager>,
-    device_store: SharedDeviceStore,
-    device_manager: Arc<device_manager::DeviceManager>,
-    device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>,
-    mdns_server: Arc<tokio::sync::Mutex<mdns_server::MdnsServer>>,
-    uart_connection: Arc<tokio::sync::Mutex<uart_connection::UartConnection>>,
-) -> Router {
-    use axum::routing::get;
-    use tower::ServiceBuilder;
-    use tower_http::trace::TraceLayer;
-
-    let mut app = Router::new();
-
-    // AppState for all handlers
-    let app_state = AppState {
-        db: db.clone(),
-        device_store: device_store.clone(),
-        device_manager: device_manager.clone(),
-        device_discovery: device_discovery.clone(),
-        mdns_server: mdns_server.clone(),
-        uart_connection: uart_connection.clone(),
-    };
+    let device_simulators = Arc::new(device_simulator::SimulatorManager::new(Some(device_manager.clone())));
+    let mailer = Arc::new(email::Mailer::new(db.clone(), &config::AppConfig::default().email).expect("email is disabled by default"));
 
-    // API Routes
-    let api_routes = Router::new()
-        .route("/api", get(api_home))
-        .route("/api/users", get(api_users))
-        .route("/api/devices/discovered", get(discovered_devices_handler))
-        .route("/api/devices", get(list_devices_handler))
-        .with_state(app_state.clone());
-
-    // WebSocket routes
-    let websocket_routes = Router::new()
-        .route("/channel", get(websocket_handler))
-        .with_state(app_state.clone());
-
-    // Merge routes
-    app = app.merge(api_routes);
-    app = app.merge(websocket_routes);
-
-    // Add middleware
-    app = app.layer(
-        ServiceBuilder::new()
-            .layer(TraceLayer::new_for_http())
-    );
-
-    app
+    routes::create_app(db, device_store, device_manager, device_discovery, mdns_server, uart_connection, device_simulators, mailer).await
 }
-
-// Handler functions
-async fn api_home() -> Json<Value> {
-    Json(json!({
-        "title": "Device Manager Backend",
-        "status": "running",
-        "version": "0.1.0"
-    }))
-}
-
-async fn api_users() -> Json<Value> {
-    Json(json!({ "users": [] }))
-}
-
-async fn discovered_devices_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    // Get discovered devices from DeviceDiscovery service
-    let discovered_devices = {
-        let discovery = app_state.device_discovery.lock().await;
-        discovery.get_discovered_devices().await
-    };
-
-    // Convert to JSON format expected by tests
-    let devices: Vec<Value> = discovered_devices
-        .into_iter()
-        .map(|(name, device)| {
-            json!({
-                "name": name,
-                "ip": device.device_config.ip_address.to_string(),
-                "tcp_port": device.device_config.tcp_port,
-                "udp_port": device.udp_port
-            })
-        })
-        .collect();
-
-    Ok(Json(json!({
-        "devices": devices,
-        "count": devices.len()
-    })))
-}
-
-async fn list_devices_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    // Get devices from device store
-    let devices = app_state.device_store.get_active_devices().await;
-
-    Ok(Json(json!({
-        "devices": devices,
-        "count": devices.len()
-    })))
-}
-
-async fn websocket_handler() -> &'static str {
-    "WebSocket endpoint - use proper WebSocket client to connect"
-}
\ No newline at end of file
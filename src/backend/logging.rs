@@ -0,0 +1,320 @@
+// Logging - text, JSON or journal log output and request IDs for correlating log lines (Loki/ELK)
+//
+// JSON mode writes one object per line; fields of all enclosing spans (request_id,
+// client, device_id, ...) are flattened into it, so every line logged while handling
+// a request or a device connection can be filtered by those keys.
+// Journal mode is for running under systemd: no timestamps or colors (journald adds its
+// own) and a <N> syslog priority prefix per line, so `journalctl -p warning` works.
+
+use std::fmt;
+use std::sync::OnceLock;
+
+use axum::{extract::Request, http::HeaderValue, middleware::Next, response::Response};
+use chrono::SecondsFormat;
+use serde_json::{Map, Value};
+use tracing::field::{Field, Visit};
+use tracing::{span, Event, Subscriber};
+use tracing_subscriber::field::RecordFields;
+use tracing_subscriber::fmt::format::{Format, Full, Writer};
+use tracing_subscriber::fmt::{FmtContext, FormatEvent, FormatFields, FormattedFields};
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::EnvFilter;
+
+/// Header carrying the request ID (accepted from the client/proxy, always returned)
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longer incoming IDs are replaced by a generated one
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// Log output format (logging.format)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines (default)
+    Text,
+    /// One JSON object per line
+    Json,
+    /// Text lines with syslog priority prefixes for journald
+    Journal,
+}
+
+impl LogFormat {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_lowercase().as_str() {
+            "text" => Some(Self::Text),
+            "json" => Some(Self::Json),
+            "journal" => Some(Self::Journal),
+            _ => None,
+        }
+    }
+}
+
+/// Replaces the level filter of the installed subscriber (config reload)
+type LevelReloader = Box<dyn Fn(EnvFilter) -> Result<(), String> + Send + Sync>;
+
+static LEVEL_RELOADER: OnceLock<LevelReloader> = OnceLock::new();
+
+/// Install the global subscriber; RUST_LOG takes precedence over `level`
+pub fn init(level: &str, format: LogFormat) {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(level));
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    // The reload handle type depends on the formatter, hence one closure per format
+    macro_rules! init_reloadable {
+        ($builder:expr) => {{
+            let builder = $builder.with_filter_reloading();
+            let handle = builder.reload_handle();
+            let _ = LEVEL_RELOADER.set(Box::new(move |filter| handle.reload(filter).map_err(|e| e.to_string())));
+            builder.init()
+        }};
+    }
+
+    match format {
+        LogFormat::Text => init_reloadable!(builder
+            .with_target(true)
+            .with_line_number(true)
+            .with_file(true)),
+        LogFormat::Json => init_reloadable!(builder
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)),
+        LogFormat::Journal => init_reloadable!(builder
+            .with_ansi(false)
+            .event_format(JournalFormat::default())),
+    }
+}
+
+/// Change logging.level at runtime; refused while RUST_LOG overrides it
+pub fn set_level(level: &str) -> Result<(), String> {
+    if std::env::var_os(EnvFilter::DEFAULT_ENV).is_some() {
+        return Err(format!("{} is set and takes precedence", EnvFilter::DEFAULT_ENV));
+    }
+    let filter = EnvFilter::try_new(level).map_err(|e| format!("invalid filter {:?}: {}", level, e))?;
+    let reload = LEVEL_RELOADER.get().ok_or("logging is not initialized")?;
+    reload(filter)
+}
+
+// ============================================================================
+// JOURNAL FORMATTER
+// ============================================================================
+
+/// Plain text line behind a sd-daemon priority prefix ("<4>" for warnings)
+pub struct JournalFormat(Format<Full, ()>);
+
+impl Default for JournalFormat {
+    fn default() -> Self {
+        Self(tracing_subscriber::fmt::format().without_time().with_ansi(false).with_target(true))
+    }
+}
+
+/// syslog priority of a tracing level (err, warning, info, debug)
+fn journal_priority(level: &tracing::Level) -> u8 {
+    match *level {
+        tracing::Level::ERROR => 3,
+        tracing::Level::WARN => 4,
+        tracing::Level::INFO => 6,
+        _ => 7,
+    }
+}
+
+impl<S, N> FormatEvent<S, N> for JournalFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        write!(writer, "<{}>", journal_priority(event.metadata().level()))?;
+        self.0.format_event(ctx, writer, event)
+    }
+}
+
+// ============================================================================
+// JSON FORMATTER
+// ============================================================================
+
+/// Collects event/span fields as JSON values
+#[derive(Default)]
+struct JsonVisitor(Map<String, Value>);
+
+impl Visit for JsonVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.0.insert(field.name().to_string(), Value::String(format!("{:?}", value)));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.0.insert(field.name().to_string(), Value::from(value));
+    }
+}
+
+fn parse_object(text: &str) -> Map<String, Value> {
+    match serde_json::from_str(text) {
+        Ok(Value::Object(map)) => map,
+        _ => Map::new(),
+    }
+}
+
+/// Stores span fields as a JSON object (read back by JsonFormat)
+pub struct JsonFields;
+
+impl<'writer> FormatFields<'writer> for JsonFields {
+    fn format_fields<R: RecordFields>(&self, mut writer: Writer<'writer>, fields: R) -> fmt::Result {
+        let mut visitor = JsonVisitor::default();
+        fields.record(&mut visitor);
+        write!(writer, "{}", Value::Object(visitor.0))
+    }
+
+    // Span::record() merges into the object instead of appending text
+    fn add_fields(&self, current: &'writer mut FormattedFields<Self>, fields: &span::Record<'_>) -> fmt::Result {
+        let mut visitor = JsonVisitor(parse_object(&current.fields));
+        fields.record(&mut visitor);
+        current.fields = Value::Object(visitor.0).to_string();
+        Ok(())
+    }
+}
+
+/// One JSON object per event: timestamp, level, target, span fields, event fields
+pub struct JsonFormat;
+
+impl<S, N> FormatEvent<S, N> for JsonFormat
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    N: for<'a> FormatFields<'a> + 'static,
+{
+    fn format_event(&self, ctx: &FmtContext<'_, S, N>, mut writer: Writer<'_>, event: &Event<'_>) -> fmt::Result {
+        let metadata = event.metadata();
+        let mut line = Map::new();
+
+        // Outermost span first, inner spans and the event itself override duplicate keys
+        let mut spans = Vec::new();
+        if let Some(scope) = ctx.event_scope() {
+            for span in scope.from_root() {
+                if let Some(fields) = span.extensions().get::<FormattedFields<N>>() {
+                    line.extend(parse_object(&fields.fields));
+                }
+                spans.push(Value::from(span.name()));
+            }
+        }
+        if !spans.is_empty() {
+            line.insert("spans".to_string(), Value::Array(spans));
+        }
+
+        let mut visitor = JsonVisitor::default();
+        event.record(&mut visitor);
+        line.extend(visitor.0);
+
+        line.insert("timestamp".to_string(), Value::from(chrono::Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)));
+        line.insert("level".to_string(), Value::from(metadata.level().as_str()));
+        line.insert("target".to_string(), Value::from(metadata.target()));
+        if let (Some(file), Some(number)) = (metadata.file(), metadata.line()) {
+            line.insert("location".to_string(), Value::from(format!("{}:{}", file, number)));
+        }
+
+        writeln!(writer, "{}", Value::Object(line))
+    }
+}
+
+// ============================================================================
+// REQUEST IDS
+// ============================================================================
+
+/// ID of the current HTTP request (request extension, also a field of the request span)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+/// Reuse a sane X-Request-Id from the client/proxy, otherwise generate one; echo it in the response
+pub async fn request_id_middleware(mut request: Request, next: Next) -> Response {
+    let request_id = request.headers().get(REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_valid_request_id(id))
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    request.extensions_mut().insert(RequestId(request_id.clone()));
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+// Restricted charset: incoming IDs end up in log lines
+fn is_valid_request_id(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LENGTH
+        && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io;
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_json_lines_carry_span_fields() {
+        let buffer = SharedBuffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .fmt_fields(JsonFields)
+            .event_format(JsonFormat)
+            .with_writer(move || writer.clone())
+            .finish();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let request = tracing::info_span!("request", request_id = "req-42", status = tracing::field::Empty);
+            let _request = request.enter();
+            request.record("status", 200);
+            let device = tracing::info_span!("device", device_id = "esp-1");
+            let _device = device.enter();
+            tracing::info!(bytes = 12, "command sent");
+        });
+
+        let output = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let line: Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(line["message"], "command sent");
+        assert_eq!(line["request_id"], "req-42");
+        assert_eq!(line["status"], 200);
+        assert_eq!(line["device_id"], "esp-1");
+        assert_eq!(line["bytes"], 12);
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["spans"], serde_json::json!(["request", "device"]));
+
+        assert_eq!(journal_priority(&tracing::Level::WARN), 4);
+        assert_eq!(LogFormat::parse(" Journal "), Some(LogFormat::Journal));
+
+        assert!(is_valid_request_id("7f3c-a1_b.2"));
+        assert!(!is_valid_request_id("abc\n{\"level\":\"ERROR\"}"));
+    }
+}
@@ -3,89 +3,19 @@
 // ============================================================================
 
 // Axum is the web framework for Rust - similar to Express.js for Node.js
-use axum::{
-    body::Body,                     // HTTP Body for responses
-    extract::{Path, State},         // Path for URL parameters, State for global state
-    http::StatusCode,        // HTTP Status Codes (200, 404, etc.)
-    response::{IntoResponse, Response}, // Traits for HTTP responses
-    routing::{get, post, Router},   // HTTP Routing (GET /login, POST /api/register)
-    Json,                           // JSON Parser for API requests/responses
-};
-// Axum Extra for extended features
-use axum_extra::extract::CookieJar; // For reading browser cookies
-
-// Serde for JSON serialization/deserialization
-use serde::Deserialize;
-use serde_json::{json, Value};      // JSON handling
+use axum::routing::{get, Router};   // HTTP Routing for the HTTPS redirect listener
 
 // Standard Rust libraries
-use std::{fs, sync::Arc}; // File system, Arc for thread-safe references
-use pulldown_cmark::{Parser, html}; // Markdown parsing
-
-// Tower for middleware (logging, etc.)
-use tower::ServiceBuilder;
-use tower_http::{
-    services::ServeDir,             // Serve static files (CSS, JS, HTML)
-    trace::TraceLayer,              // HTTP Request Logging
-};
+use std::sync::Arc; // Arc for thread-safe references
 
 // ============================================================================
-// MODULE IMPORTS - Unsere eigenen Code-Module
+// MODULE IMPORTS - Unsere eigenen Code-Module (library crate, see lib.rs)
 // ============================================================================
 
-mod app_state;   // app_state.rs - Centralized application state
-mod auth;        // auth.rs - Authentication (Login, Register, JWT)
-mod file_utils;  // file_utils.rs - File handling and SPA routing
-mod database;    // database.rs - SQLite database integration
-mod events;      // events.rs - Event definitions for devices
-mod device_store; // device_store.rs - In-Memory Event Store for devices
-mod websocket;   // websocket.rs - WebSocket handler for multiuser
-mod device_types; // device_types.rs - Device communication types
-mod device_connection; // device_connection.rs - Device TCP/UDP connection handling
-mod device_manager; // device_manager.rs - Device management
-mod mdns_discovery; // mdns_discovery.rs - mDNS-based device discovery
-mod mdns_server;    // mdns_server.rs - mDNS server for advertising device-manager.local
-mod device_discovery; // device_discovery.rs - Device discovery service
-mod debug_logger;   // debug_logger.rs - Debug event logging
-mod uart_connection; // uart_connection.rs - UART/Serial connection handling
-
-// Import all authentication functions from auth.rs
-// These are used for Login/Register/Logout on the website
-use auth::{
-    create_auth_cookie,    // Creates secure HTTP cookies for logged-in users
-    create_jwt,           // Creates JSON Web Tokens for authentication  
-    create_logout_cookie, // Deletes auth cookies on logout
-    validate_jwt,         // Checks if JWT token is still valid
-    AuthResponse,         // Struct for API responses (success: true/false, message)
-    LoginRequest,         // Struct for login data from frontend (email, password)
-    RegisterRequest,      // Struct for registration data
-    UpdateDisplayNameRequest, // Struct for display name updates
-    User,                // User data structure with hashed passwords
-    // A 5.4: Device-Management Imports
-    CreateDeviceRequest, // Request for new device
-    UpdateDeviceRequest, // Request for device updates
-    UpdatePermissionRequest, // Request for permission updates
-};
-
-// Import all file handling functions
-// These are used for serving website files
-use file_utils::handle_template_file;
-
-// Import database functions
-use database::{DatabaseManager};
-
-// Import Event Store and WebSocket functions
-use device_store::{create_shared_store, SharedDeviceStore};
-use websocket::{websocket_handler, websocket_stats_handler, health_check_handler, device_users_handler, start_cleanup_task, WebSocketState};
-
-// Import centralized AppState
-use app_state::AppState;
-
-// DEBUG: Simple test handler for WebSocket routing
-async fn debug_websocket_handler() -> Result<String, (axum::http::StatusCode, String)> {
-    tracing::error!("DEBUG WebSocket handler called!");
-    Ok("DEBUG: WebSocket handler reached".to_string())
-}
+use drawing_app_backend::{activity, alerts, auth, client_queue, config, config_reload, crash_reports, database, debug_logger, device_availability, device_capabilities, device_profiles, device_discovery, device_lifecycle, device_manager, device_simulator, device_supervisor, device_types, diagnostics, email, enrollment, espnow_peers, fleet, inbound_validation, keepalive, logging, mdns_server, outbound, permission_expiry, proxy, quarantine, recordings, secrets, service, startup_connect, time_service, tls, uart_connection, webhooks};
+use drawing_app_backend::{DatabaseManager, create_shared_store};
+use drawing_app_backend::websocket::start_cleanup_task;
+use drawing_app_backend::routes::{create_app, cors_layer, healthz_handler, start_tcp_scan_task};
 
 // ============================================================================
 // MAIN FUNCTION - Entry point of our Rust web application
@@ -95,50 +25,102 @@ async fn debug_websocket_handler() -> Result<String, (axum::http::StatusCode, St
 #[tokio::main]  // This attribute makes main() async-capable with Tokio runtime
 async fn main() {
     // Ensure CWD is project root, even when started via double-click from Explorer
+    // (only for target/<profile>/ builds; installed binaries keep their working directory)
     if let Ok(exe_path) = std::env::current_exe() {
-        if let Some(project_root) = exe_path.ancestors().nth(3) {
+        if let Some(project_root) = exe_path.ancestors().nth(3).filter(|root| root.join("Cargo.toml").is_file()) {
             let _ = std::env::set_current_dir(project_root);
         }
     }
 
+    // Load config.toml + environment overrides before anything else (log level, ports, DB path)
+    // Absolute, so a config reload finds the file after server.working_directory changed the CWD
+    let (config_file, config_required) = config::AppConfig::file_location();
+    let config_file = std::path::absolute(&config_file).unwrap_or(config_file);
+    let config = match config::AppConfig::load_from(&config_file, config_required) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Configuration error: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    // Service installs: data/, client/, docs/ and relative config paths live under server.working_directory
+    if !config.server.working_directory.is_empty() {
+        if let Err(e) = std::env::set_current_dir(&config.server.working_directory) {
+            eprintln!("Configuration error: server.working_directory {}: {}", config.server.working_directory, e);
+            std::process::exit(1);
+        }
+    }
+
+    // Self-test of the host environment instead of starting the server
+    if std::env::args().any(|arg| arg == "--doctor") {
+        std::process::exit(diagnostics::run_doctor(&diagnostics::DiagnosticsTargets::from_config(&config)));
+    }
+    diagnostics::configure(diagnostics::DiagnosticsTargets::from_config(&config));
+
     // Delete old log file at startup
     let _ = std::fs::remove_file("server_startup.log");
 
     // Setup simple file logging
-    use std::io::Write;
-    let mut log_file = std::fs::OpenOptions::new()
+    let _log_file = std::fs::OpenOptions::new()
         .create(true)
         .append(true)
         .open("server_startup.log")
         .expect("Failed to open log file");
 
-    // Enhanced logging configuration with console output
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info"))
-        )
-        .with_target(true)
-        .with_line_number(true)
-        .with_file(true)
-        .init();
+    // Console logging: text or JSON lines (logging.format), filter from logging.level / RUST_LOG
+    logging::init(&config.logging.level, config.log_format());
 
     tracing::info!("Starting Drawing App Backend Server");
-
-    // Clear debug log file for fresh start
-    debug_logger::DebugLogger::clear_log();
-
+    tracing::info!("Configuration: {:?}", config);
+    proxy::configure(config.proxy().unwrap_or_default());
+    outbound::configure(config.outbound.clone());
+    match secrets::Keyring::load(&config.secrets) {
+        Ok(keyring) => secrets::configure(keyring),
+        Err(e) => {
+            eprintln!("Configuration error: secrets: {}", e);
+            std::process::exit(1);
+        }
+    }
+    let pid_file = if config.server.pid_file.is_empty() {
+        None
+    } else {
+        match service::PidFile::create(std::path::Path::new(&config.server.pid_file)) {
+            Ok(pid_file) => Some(pid_file),
+            Err(e) => {
+                eprintln!("Startup error: pid_file: {}", e);
+                std::process::exit(1);
+            }
+        }
+    };
+    if config.outbound.offline {
+        tracing::info!("Offline mode: webhooks, email and firmware downloads are disabled");
+    }
+    device_types::set_default_timeouts(config.devices.tcp_timeout_seconds, config.devices.udp_timeout_seconds);
+    device_types::set_default_tcp_settings(
+        config.devices.keepalive_idle_seconds,
+        config.devices.keepalive_interval_seconds,
+        config.devices.connect_timeout_seconds,
+    );
+    device_types::set_tls_certs_dir(&config.devices.tls_certs_dir);
+    device_types::set_discovery_probe_ports(&config.discovery.probe_ports);
+    inbound_validation::configure(config.devices.inbound_limits());
+    quarantine::configure(config.quarantine.settings());
+    keepalive::configure(config.websocket.keepalive());
+    client_queue::set_batch_window(config.websocket.batch_window());
+    client_queue::set_compression_enabled(config.websocket.compression);
 
     // Initialize SQLite database
     tracing::info!("Initializing SQLite database...");
-    let db_exists = std::path::Path::new("data/users.db").exists();
+    let db_path = config.database.path.as_str();
+    let db_exists = std::path::Path::new(db_path).exists();
     
-    let db = match DatabaseManager::new().await {
+    let db = match DatabaseManager::open(db_path).await {
         Ok(db) => {
             if db_exists {
-                tracing::info!("Connected to existing SQLite database: data/users.db");
+                tracing::info!("Connected to existing SQLite database: {}", db_path);
             } else {
-                tracing::info!("Created new SQLite database: data/users.db");
+                tracing::info!("Created new SQLite database: {}", db_path);
             }
             Arc::new(db)
         }
@@ -148,6 +130,19 @@ async fn main() {
         }
     };
 
+    // Seal plaintext secrets and those of a retired key with the active key
+    match db.reseal_secrets().await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Sealed {} stored secrets with the active key", count),
+        Err(e) => tracing::error!("Failed to seal stored secrets: {}", e),
+    }
+
+    // Tokens of recently deleted users stay invalid after a restart
+    match db.get_revoked_sessions().await {
+        Ok(revoked) => auth::load_revoked_sessions(revoked),
+        Err(e) => tracing::error!("Failed to load revoked sessions: {}", e),
+    }
+
     // Initialize Device Event Store
     tracing::info!("Initializing Device Event Store...");
     let device_store = create_shared_store();
@@ -155,6 +150,7 @@ async fn main() {
     // Load debug settings and configure device store
     if let Ok(Some(max_debug_messages)) = db.get_debug_settings().await {
         device_store.set_max_debug_messages(max_debug_messages as usize).await;
+        debug_logger::bus().set_capacity(max_debug_messages as usize);
         tracing::info!("Loaded debug settings: max_debug_messages={}", max_debug_messages);
     } else {
         tracing::info!("Using default debug settings: max_debug_messages=200");
@@ -165,13 +161,134 @@ async fn main() {
 
 
     let device_manager = device_manager::create_device_manager(device_store.clone());
+
+    // Load UDP listener ports (multiple ports supported, e.g. 3232,3233,8266)
+    if let Ok(Some(udp_ports)) = db.get_udp_settings().await {
+        tracing::info!("Loaded UDP settings: listen_ports={:?}", udp_ports);
+        device_manager.set_udp_listen_ports(udp_ports).await;
+    } else {
+        tracing::info!("Using configured UDP settings: listen_ports={:?}", config.devices.udp_listen_ports);
+        device_manager.set_udp_listen_ports(config.devices.udp_listen_ports.clone()).await;
+    }
+    device_manager.set_max_concurrent_connects(config.devices.max_concurrent_connects).await;
+    device_manager.set_udp_workers(config.devices.udp_workers).await;
+    device_manager.set_variable_coalescing(
+        std::time::Duration::from_millis(config.devices.variable_min_interval_ms),
+        config.devices.record_raw_variables,
+    );
+    device_manager.set_command_rate_limits(config.command_rate_limit.limits());
+    match device_manager.set_message_parsers(&config.message_parsers.devices) {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Custom message parser chains for {} device(s)", count),
+        Err(e) => {
+            eprintln!("Configuration error: message_parsers.devices: {}", e);
+            std::process::exit(1);
+        }
+    }
+    match device_manager.set_bus_decoders(&config.bus_decoders.devices) {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Bus decoders active for {} device(s)", count),
+        Err(e) => {
+            eprintln!("Configuration error: bus_decoders.devices: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    // Load per-device TLS settings for the TCP channel
+    match db.get_all_device_tls_settings().await {
+        Ok(tls_settings) => {
+            for (device_id, tls) in tls_settings {
+                device_manager.set_device_tls(&device_id, Some(tls)).await;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load device TLS settings: {}", e),
+    }
+
+    // Load per-device identity secrets (challenge-response after TCP connect)
+    match db.get_all_device_identity_secrets().await {
+        Ok(secrets) => {
+            for (device_id, secret) in secrets {
+                device_manager.set_device_identity(&device_id, Some(secret)).await;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load device identity secrets: {}", e),
+    }
+
+    // Load per-device reconnect policies (devices without one use the default backoff)
+    match db.get_all_device_connection_policies().await {
+        Ok(policies) => {
+            for (device_id, policy) in policies {
+                device_manager.supervisor().set_policy(&device_id, policy).await;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load device connection policies: {}", e),
+    }
+
+    // Load per-device ports, timeouts and keep-alive set via PATCH /api/devices/:id/connection-settings
+    match db.get_all_device_connection_settings().await {
+        Ok(settings) => {
+            for (device_id, settings) in settings {
+                device_manager.set_connection_settings(&device_id, settings).await;
+            }
+        }
+        Err(e) => tracing::warn!("Failed to load device connection settings: {}", e),
+    }
+
+    // Lifecycle: start stored devices from their last state (nothing is connected yet)
+    // and persist every transition to devices.status
+    let lifecycle = device_manager.lifecycle();
+    let stored_devices = db.list_all_devices(&database::DeviceListQuery::default()).await.map_err(|e| e.to_string());
+    let stored_devices = match stored_devices {
+        Ok((devices, _)) => {
+            for device in &devices {
+                let state = if device.maintenance_mode {
+                    device_lifecycle::LifecycleState::Maintenance
+                } else {
+                    device.status.after_restart()
+                };
+                lifecycle.restore(&device.mac_address, state).await;
+            }
+            devices
+        }
+        Err(e) => {
+            tracing::warn!("Failed to restore device lifecycle states: {}", e);
+            Vec::new()
+        }
+    };
+    device_lifecycle::start_persistence(db.clone(), device_store.clone());
+
     device_manager.start().await;
+    device_supervisor::start(device_manager.clone());
+
+    // Register and connect the stored devices concurrently; the server does not wait for it
+    tokio::spawn(startup_connect::connect_stored_devices(stored_devices, device_manager.clone(), device_store.clone()));
 
     // Start Device Discovery Service
     tracing::info!("Starting Device Discovery Service...");
     let device_discovery = Arc::new(tokio::sync::Mutex::new(device_discovery::DeviceDiscovery::with_manager(device_store.clone(), Some(device_manager.clone()), Some(db.clone()))));
+
+    // Load browsed mDNS service types (e.g. _arduino._tcp, _esp32._tcp, _http._tcp)
+    match db.get_mdns_services().await {
+        Ok(Some(services)) => {
+            tracing::info!("Loaded mDNS settings: {} service type(s)", services.len());
+            device_discovery.lock().await.set_mdns_services(services);
+        }
+        Ok(None) => tracing::info!("Using default mDNS service types"),
+        Err(e) => tracing::warn!("Failed to load mDNS settings: {}", e),
+    }
+
+    // Load discovery aging (stale/expiry thresholds, mDNS re-resolution)
+    match db.get_discovery_settings().await {
+        Ok(aging) => device_discovery.lock().await.set_aging_config(aging).await,
+        Err(e) => tracing::warn!("Failed to load discovery settings: {}", e),
+    }
     let discovery_service = device_discovery.clone();
+    let discovery_enabled = config.discovery.enabled;
     tokio::spawn(async move {
+        if !discovery_enabled {
+            tracing::info!("Device discovery disabled (discovery.enabled)");
+            return;
+        }
         let mut discovery = discovery_service.lock().await;
         if let Err(e) = discovery.start_discovery().await {
             tracing::error!("Device discovery failed to start: {}", e);
@@ -180,53 +297,50 @@ async fn main() {
         }
     });
 
-    // Start mDNS Server for advertising device-manager.local
+    // Opt-in TCP range scan (for networks where mDNS/multicast is blocked)
+    let tcp_scan_db = db.clone();
+    let tcp_scan_discovery = device_discovery.clone();
+    tokio::spawn(async move {
+        start_tcp_scan_task(tcp_scan_db, tcp_scan_discovery).await;
+    });
+
+    // Start mDNS Server for advertising device-manager.local and _esp32-manager._tcp
+    // discovery.mdns_advertise = false (or MDNS_ADVERTISE=false) disables the advertisement
     tracing::info!("Starting mDNS Server...");
+    let mdns_config = mdns_server::MdnsAdvertisementConfig {
+        enabled: config.discovery.mdns_advertise,
+        api_port: config.server.http_port,
+        ..Default::default()
+    };
     let mdns_server = Arc::new(tokio::sync::Mutex::new(
-        mdns_server::MdnsServer::new().map_err(|e| {
+        mdns_server::MdnsServer::with_config(mdns_config).map_err(|e| {
             tracing::error!("Failed to create mDNS server: {}", e);
             e
         }).unwrap()
     ));
 
     let mdns_service = mdns_server.clone();
+    let http_port = config.server.http_port;
     tokio::spawn(async move {
         let mut server = mdns_service.lock().await;
-        if let Err(e) = server.start_advertising(3000).await {
+        if !server.config().enabled {
+            tracing::info!("mDNS advertising disabled (discovery.mdns_advertise)");
+        } else if let Err(e) = server.start_advertising(http_port).await {
             tracing::error!("mDNS server failed to start: {}", e);
         } else {
-            tracing::info!("mDNS server started - device-manager.local advertised on port 3000");
+            tracing::info!("mDNS server started - device-manager.local advertised on port {}", http_port);
         }
     });
     
-    // Example: Add a test device configuration for testing
-    let ip = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 43, 75));
-    let test_device = device_types::DeviceConfig::new(
-        "test-device-001".to_string(),
-        ip,
-        3232, // Device TCP port
-        3232, // Device UDP port
-    );
-    if let Err(e) = device_manager.add_device(test_device).await {
-        tracing::warn!("Failed to add test device: {}", e);
-    } else {
-        tracing::info!("Added test device: test-device-001 (192.168.43.75)");
+    // Device simulators: simulators.count fake ESP32s started with the server
+    let device_simulators = Arc::new(device_simulator::SimulatorManager::new(Some(device_manager.clone())));
+    for _ in 0..config.simulators.count {
+        match device_simulators.spawn(device_simulator::SimulatorSpec::default()).await {
+            Ok(info) => tracing::info!("Started device simulator {} on {}:{}", info.device_id, info.ip_address, info.tcp_port),
+            Err(e) => tracing::warn!("Failed to start device simulator: {}", e),
+        }
     }
 
-    // Add test device with colons to see if that causes the Event-Forwarding-Task termination issue
-    let ip_colon_test = std::net::IpAddr::V4(std::net::Ipv4Addr::new(192, 168, 43, 76));
-    let test_device_with_colons = device_types::DeviceConfig::new(
-        "test:colon:device".to_string(),
-        ip_colon_test,
-        3232, // Device TCP port
-        3232, // Device UDP port
-    );
-    if let Err(e) = device_manager.add_device(test_device_with_colons).await {
-        tracing::warn!("Failed to add test device with colons: {}", e);
-    } else {
-        tracing::info!("Added test device with colons: test:colon:device (192.168.43.76)");
-    }
-    
     // Start WebSocket cleanup task
     let cleanup_store = device_store.clone();
     tokio::spawn(async move {
@@ -234,21 +348,68 @@ async fn main() {
     });
     tracing::info!("Started WebSocket cleanup task");
 
-    // Initialize UART Connection with shared state trackers from DeviceManager
+    // Webhooks: device online/offline and firmware changes from the event feed
+    let webhook_dispatcher = Arc::new(webhooks::WebhookDispatcher::new(db.clone()));
+    webhook_dispatcher.clone().start(device_store.clone());
+
+    // Email notifications: SMTP send queue ([email] in config.toml)
+    let mailer = match email::Mailer::new(db.clone(), &config.email) {
+        Ok(mailer) => Arc::new(mailer),
+        Err(e) => {
+            tracing::error!("Invalid email configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    mailer.clone().start();
+
+    // Alert rules evaluated against the same feed
+    alerts::AlertEngine::new(db.clone(), device_store.clone(), webhook_dispatcher, mailer.clone()).start();
+
+    // Device type profiles: client commands are checked against the assigned profile
+    device_profiles::load_assignments(&db, &device_manager).await;
+
+    // Device capabilities requested on connect and cached in the database
+    device_capabilities::CapabilityTracker::new(db.clone(), device_manager.clone()).start(device_store.clone());
+
+    // Crash resets announced in hello messages
+    crash_reports::CrashReportCollector::new(db.clone()).start(device_store.clone());
+    enrollment::EnrollmentWatcher::new(db.clone(), device_manager.lifecycle()).start(device_store.clone());
+
+    // ESP-NOW peers reported by devices appear as read-only child devices
+    espnow_peers::EspNowPeerTracker::new(db.clone(), device_manager.lifecycle(), device_store.clone()).start();
+
+    // Session recordings: captures follow the event feed
+    recordings::start(device_store.clone());
+
+    // Time-limited device permissions are removed when they end
+    permission_expiry::start(db.clone(), device_store.clone());
+
+    // Device audit log: rows past the retention period are pruned hourly
+    activity::start(db.clone());
+
+    // Availability history: link state changes of every device, server downtime marked as unknown
+    device_availability::start(db.clone(), device_store.clone());
+
+    // Per-device event rates for the top talkers of GET /api/fleet/summary
+    fleet::start(device_store.clone());
+    fleet::set_minimum_firmware_version(&config.firmware.minimum_version);
+
+    // Quarantined devices: activity entry and email to the owners
+    quarantine::start(db.clone(), device_store.clone(), mailer.clone());
+
+    // Wall-clock time for devices ([time_service], off by default)
+    time_service::TimeService::new(config.time_service.clone(), &config.server.bind_address).start(device_manager.clone(), device_store.clone());
+
+    // Initialize UART Connection; UART devices get transports on its port in the DeviceManager
     tracing::info!("Initializing UART connection...");
-    let mut uart_conn = uart_connection::UartConnection::new(
-        device_store.clone(),
-        device_manager.get_unified_connection_states(),
-        device_manager.get_unified_activity_tracker(),
-        device_manager.get_device_connection_types(),
-    );
+    let mut uart_conn = uart_connection::UartConnection::new(device_manager.transport_context());
     uart_conn.set_database(db.clone());
+    device_manager.set_uart_port(uart_conn.port()).await;
     let uart_connection = Arc::new(tokio::sync::Mutex::new(uart_conn));
 
     // Try to auto-connect UART if settings exist
     if let Ok(Some((port, baud_rate, auto_connect))) = db.get_uart_settings().await {
-        if auto_connect && port.is_some() {
-            let port_name = port.unwrap();
+        if let (true, Some(port_name)) = (auto_connect, port) {
             tracing::info!("Auto-connecting to UART port {} at {} baud", port_name, baud_rate);
             let mut uart = uart_connection.lock().await;
             match uart.connect(port_name.clone(), baud_rate).await {
@@ -262,2094 +423,130 @@ async fn main() {
         }
     }
 
+    // SIGHUP / POST /api/admin/reload-config re-apply runtime settings from the config file
+    config_reload::configure(config_reload::Reloader::new(
+        config_file,
+        config_required,
+        config.clone(),
+        device_manager.clone(),
+        device_discovery.clone(),
+        mdns_server.clone(),
+    ));
+    config_reload::spawn_sighup_handler();
+
     // Create web app with all routes
     tracing::info!("Creating application routes...");
-    let app = create_app(db, device_store, device_manager, device_discovery, mdns_server, uart_connection).await;
-
-    // Start TCP listener on port 3000
-    let listener = tokio::net::TcpListener::bind("0.0.0.0:3000")
-        .await
-        .unwrap();  // unwrap() = stop program on error
-    
-    tracing::info!("Server running on http://0.0.0.0:3000 (accessible via localhost:3000 or 127.0.0.1:3000)");
-    tracing::info!("Available endpoints:");
-    tracing::info!("   - GET  /           - SPA Main Page");
-    tracing::info!("   - GET  /login.html - Login Page");
-    tracing::info!("   - POST /api/login  - Login API");
-    tracing::info!("   - POST /api/register - Register API");
-    tracing::info!("   - POST /api/profile/display-name - Update Display Name");
-    tracing::info!("   - GET  /channel    - WebSocket Canvas Events");
-    tracing::info!("   - GET  /api/websocket/stats - WebSocket Statistics");
-    tracing::info!("Debug tip: Set RUST_LOG=debug for detailed logging");
-    
-    // Start server and wait for requests - with ConnectInfo for WebSocket
-    axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap();
-}
-
-// ============================================================================
-// APP CREATION - Creates the web router with all routes
-// Website feature: Defines all URLs and their handler functions
-// ============================================================================
-
-pub async fn create_app(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, device_manager: Arc<device_manager::DeviceManager>, device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>, mdns_server: Arc<tokio::sync::Mutex<mdns_server::MdnsServer>>, uart_connection: Arc<tokio::sync::Mutex<uart_connection::UartConnection>>) -> Router {
-    let mut app = Router::new();
-
-    // AppState for all handlers
-    let app_state = AppState {
-        db: db.clone(),
-        device_store: device_store.clone(),
-        device_manager: device_manager.clone(),
-        device_discovery: device_discovery.clone(),
-        mdns_server: mdns_server.clone(),
-        uart_connection: uart_connection.clone(),
-    };
-
-    // WebSocket State for WebSocket handlers
-    let websocket_state = WebSocketState {
-        device_store: device_store.clone(),
-        db: db.clone(),
-        device_manager: device_manager.clone(),
-        device_discovery: device_discovery.clone(),
-        uart_connection: uart_connection.clone(),
-    };
-
-    // ========================================
-    // API ROUTES - Backend APIs for frontend
-    // ========================================
-    // These routes are called by JavaScript in the frontend
-    let api_routes = Router::new()
-        // GET /api - Basic info about the API
-        .route("/api", get(api_home))
-        
-        // GET /api/users - List all users (currently empty)
-        .route("/api/users", get(api_users))
-        
-        // POST /api/register - Register new user
-        // Called by register.html
-        .route("/api/register", post(register_handler))
-        
-        // POST /api/login - Log in user
-        // Called by login.html
-        .route("/api/login", post(login_handler))
-        
-        // POST /api/logout - Log out user
-        // Called by logout button
-        .route("/api/logout", post(logout_handler))
-        
-        // GET /api/validate-token - Check if user is logged in
-        // Called by app.js for authentication check
-        .route("/api/validate-token", get(validate_token_handler))
-        
-        // GET /api/user-info - Returns user information from JWT
-        // Used for display name display
-        .route("/api/user-info", get(user_info_handler))
-        
-        // PUT /api/profile/display-name - Change display name
-        // Used for profile updates
-        .route("/api/profile/display-name", post(update_display_name_handler))
-        
-        // ========================================
-        // A 5.4: DEVICE MANAGEMENT API ROUTES
-        // ========================================
-        
-        // GET /api/devices - List all devices of logged-in user
-        .route("/api/devices", get(list_devices_handler))
-        
-        // POST /api/devices - Create new device
-        .route("/api/devices", post(create_device_handler))
-        
-        // GET /api/devices/discovered - List discovered devices (must be before /:id to avoid conflict)
-        .route("/api/devices/discovered", get(discovered_devices_handler))
-
-        // GET /api/devices/:id - Details of an device
-        .route("/api/devices/:id", get(get_device_handler).put(update_device_handler).delete(delete_device_handler))
-
-        // POST /api/device-permissions/:id - Manage permissions for a device
-        .route("/api/device-permissions/:id", post(simple_permissions_handler))
-
-        // POST /api/devices/:id/connect - Connect TCP to device
-        .route("/api/devices/:id/connect", post(tcp_connect_handler))
-
-        // POST /api/devices/:id/disconnect - Disconnect TCP from device
-        .route("/api/devices/:id/disconnect", post(tcp_disconnect_handler))
-
-        // POST /api/devices/:id/reconnect - Reconnect TCP to device
-        .route("/api/devices/:id/reconnect", post(tcp_reconnect_handler))
-        
-        // GET /api/users/search - Search for users for permission management
-        .route("/api/users/search", get(search_users_handler))
-        
-        // GET /api/users/list - Get first users for scroll field
-        .route("/api/users/list", get(list_users_handler))
-        
-        // GET /api/docs - Get documentation content for SPA
-        .route("/api/docs", get(api_docs_handler))
-        // GET /api/docs/:path - Get specific documentation files
-        .route("/api/docs/*path", get(api_docs_file_handler))
-
-        // ========================================
-        // UART SETTINGS API ROUTES
-        // ========================================
-
-        // GET /api/uart/settings - Get current UART settings
-        .route("/api/uart/settings", get(get_uart_settings_handler))
-
-        // POST /api/uart/settings - Update UART settings
-        .route("/api/uart/settings", post(update_uart_settings_handler))
-
-        // GET /api/uart/ports - List available serial ports
-        .route("/api/uart/ports", get(list_uart_ports_handler))
-
-        // POST /api/uart/connect - Connect to UART port
-        .route("/api/uart/connect", post(uart_connect_handler))
-
-        // POST /api/uart/disconnect - Disconnect from UART port
-        .route("/api/uart/disconnect", post(uart_disconnect_handler))
-
-        // GET /api/uart/status - Get UART connection status
-        .route("/api/uart/status", get(uart_status_handler))
-
-        // ========================================
-        // DEBUG SETTINGS API ROUTES
-        // ========================================
-
-        // GET /api/debug/settings - Get current debug settings
-        .route("/api/debug/settings", get(get_debug_settings_handler).post(update_debug_settings_handler))
-
-        // GET/POST /api/github/settings - GitHub token management
-        .route("/api/github/settings", get(get_github_settings_handler).post(update_github_settings_handler))
-
-        // POST /api/firmware/upload - Upload .bin firmware file
-        .route("/api/firmware/upload", post(firmware_upload_handler))
-
-        // POST /api/firmware/fetch - Download .bin firmware from a URL (e.g. GitHub Release)
-        .route("/api/firmware/fetch", post(firmware_fetch_handler))
-
-        // with_state() gives all API routes access to both stores
-        .with_state(app_state);
-
-    // ========================================
-    // WEBSOCKET ROUTES - A 5.5 Multiuser Support
-    // ========================================
-    let websocket_routes = Router::new()
-        // WebSocket endpoint for Canvas Events - A 5.5 requirement: ws://.../channel/
-        .route("/channel", get(websocket_handler))
-        
-        // Debug endpoint to test routing
-        .route("/channel/debug", get(debug_websocket_handler))
-        
-        // WebSocket statistics endpoint for monitoring/debugging
-        .route("/api/websocket/stats", get(websocket_stats_handler))
-
-        // Health check endpoint with event storage metrics
-        .route("/api/health", get(health_check_handler))
-
-        // Get users connected to a device
-        .route("/api/devices/:device_id/users", get(device_users_handler))
-
-        .with_state(websocket_state);
-
-    // Add API routes to main router
-    app = app.merge(api_routes);
-    
-    // Add WebSocket routes to main router
-    app = app.merge(websocket_routes);
-
-    // Handle outdated hash URLs - redirect middleware would go here
-    // For now, we'll handle this in the catch-all
-
-    // Serve static files from 'public' directory (no hash versioning)
-    app = app.nest_service("/stylesheets", ServeDir::new("public/stylesheets"));
-
-    // Serve firmware .bin files for ESP32 OTA download
-    app = app.nest_service("/firmware", ServeDir::new("firmware/"));
-
-    // SPA routes - all serve the same index.html shell
-    app = app
-        .route("/index.html", get(serve_spa_route))
-        .route("/login.html", get(serve_spa_route))
-        .route("/register.html", get(serve_spa_route))
-        .route("/debug.html", get(serve_spa_route))
-        .route("/hallo.html", get(serve_spa_route))
-        .route("/about.html", get(serve_spa_route))
-        .route("/drawing_board.html", get(serve_spa_route))
-        .route("/drawer_page.html", get(serve_spa_route))
-        .route("/device_control.html", get(serve_spa_route))
-        .route("/docs.html", get(serve_spa_route))
-        .route("/settings.html", get(serve_spa_route));
-
-
-    // Serve static files directly from 'client' directory with development-friendly caching
-    app = app.nest_service("/templates", ServeDir::new("client/templates"));
-    app = app.route("/scripts/*path", get(serve_script_file));
-    app = app.route("/styles/*path", get(serve_style_file));
-    
-    // Note: /docs is now handled as SPA route, markdown API available at /api/docs
-
-
-    // Root path serves SPA
-    app = app.route("/", get(serve_spa_route));
-
-    // Serve remaining static files from client with development-friendly caching
-    app = app
-        .route("/index.css", get(|| serve_dev_static_file("client/index.css", "text/css", "max-age=0, must-revalidate")))
-        .route("/app.js", get(|| serve_dev_static_file("client/app.js", "text/javascript", "max-age=0, must-revalidate")));
-
-    // SPA routes for specific paths
-    app = app
-        .route("/login", get(serve_spa_route))
-        .route("/register", get(serve_spa_route))
-        .route("/hallo", get(serve_spa_route))
-        .route("/about", get(serve_spa_route))
-        .route("/drawing_board", get(serve_spa_route))
-        .route("/drawer_page", get(serve_spa_route))
-        .route("/debug", get(serve_spa_route))
-        .route("/index", get(serve_spa_route))
-        .route("/docs", get(serve_spa_route))
-        .route("/settings", get(serve_spa_route));
-
-    // Device routes - more specific to avoid catching static files
-    app = app
-        .route("/devices", get(serve_spa_route))
-        .route("/devices/:device_id", get(serve_spa_route));
-
-    // Add middleware
-    app = app.layer(
-        ServiceBuilder::new()
-            .layer(TraceLayer::new_for_http())
-    );
-
-    app
-}
-
-// ============================================================================
-// API HANDLER FUNCTIONS - These functions process HTTP requests
-// ============================================================================
-
-// GET /api - Basic API info
-// Website feature: API documentation/status
-async fn api_home() -> Json<Value> {
-    // json!() is a macro that creates JSON
-    Json(json!({ "title": "Express" }))
-}
-
-// GET /api/users - List all users  
-// Website feature: User management (currently not used)
-async fn api_users() -> Json<Value> {
-    Json(json!({ "users": [] }))
-}
-
-// SPA route handler - always serves the main SPA shell (index.html)
-async fn serve_spa_route() -> Response<Body> {
-    // HTML sollte nicht gecacht werden, damit SPA-Updates funktionieren
-    handle_template_file("client/index.html", "no-cache, must-revalidate").await
-}
-
-// Handler für JavaScript-Dateien mit entwicklungsfreundlichem Caching
-async fn serve_script_file(axum::extract::Path(path): axum::extract::Path<String>) -> Response<Body> {
-    let file_path = format!("client/scripts/{}", path);
-    serve_dev_static_file(&file_path, "text/javascript", "max-age=0, must-revalidate").await
-}
-
-// Handler für CSS-Dateien mit entwicklungsfreundlichem Caching
-async fn serve_style_file(axum::extract::Path(path): axum::extract::Path<String>) -> Response<Body> {
-    let file_path = format!("client/styles/{}", path);
-    serve_dev_static_file(&file_path, "text/css", "max-age=0, must-revalidate").await
-}
 
-// Allgemeine Funktion für statische Dateien mit entwicklungsfreundlichem Caching
-async fn serve_dev_static_file(file_path: &str, content_type: &str, cache_control: &str) -> Response<Body> {
-    match std::fs::read_to_string(file_path) {
-        Ok(contents) => {
-            Response::builder()
-                .header("content-type", content_type)
-                .header("cache-control", cache_control)
-                .body(Body::from(contents))
-                .unwrap()
-        }
-        Err(_) => {
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("File not found"))
-                .unwrap()
-        }
+    let mut app = create_app(db, device_store, device_manager, device_discovery, mdns_server, uart_connection, device_simulators, mailer).await;
+    if let Some(cors) = cors_layer(&config.server.cors_origins) {
+        tracing::info!("CORS enabled for origins: {:?}", config.server.cors_origins);
+        app = app.layer(cors);
     }
-}
-
 
+    // Behind a reverse proxy: mount everything under server.base_path (e.g. /esp32)
+    let base_path = proxy::base_path();
+    if !base_path.is_empty() {
+        tracing::info!("Mounting app under base path {}", base_path);
+        app = Router::new().nest(base_path, app);
+    }
 
+    // Request ID for the request span and the X-Request-Id response header
+    app = app.layer(axum::middleware::from_fn(logging::request_id_middleware));
 
-// GET /api/docs - Get main documentation content for SPA
-async fn api_docs_handler() -> impl IntoResponse {
-    serve_markdown_file("docs/README.md").await
-}
+    // Resolve the real client (X-Forwarded-For/-Proto from trusted proxies) before anything else runs
+    app = app.layer(axum::middleware::from_fn(proxy::client_info_middleware));
 
-// GET /api/docs/:path - Get specific documentation files
-async fn api_docs_file_handler(Path(path): Path<String>) -> impl IntoResponse {
-    let file_path = if path.is_empty() || path == "/" || path == "" {
-        "docs/README.md".to_string()
-    } else if path.ends_with('/') {
-        format!("docs/{}README.md", path)
-    } else if path.ends_with(".md") {
-        format!("docs/{}", path)
+    // Built-in HTTPS: load certificate before binding anything, a bad file stops the startup
+    let tls_config = if config.tls.enabled {
+        match tls::load_server_config(&config.tls.cert_path, &config.tls.key_path) {
+            Ok(tls_config) => Some(tls_config),
+            Err(e) => {
+                tracing::error!("TLS configuration error: {}", e);
+                std::process::exit(1);
+            }
+        }
     } else {
-        format!("docs/{}.md", path)
+        None
     };
-    
-    tracing::debug!("API docs request: path='{}' -> file_path='{}'", path, file_path);
-    
-    serve_markdown_file(&file_path).await
-}
-
-// Common markdown file serving function
-async fn serve_markdown_file(file_path: &str) -> impl IntoResponse {
-    match fs::read_to_string(file_path) {
-        Ok(markdown_content) => {
-            let parser = Parser::new(&markdown_content);
-            let mut html_output = String::new();
-            html::push_html(&mut html_output, parser);
-
-            let full_html = format!(
-                r#"<!DOCTYPE html>
-<html lang="de">
-<head>
-    <meta charset="UTF-8">
-    <meta name="viewport" content="width=device-width, initial-scale=1.0">
-    <title>Dokumentation</title>
-    <style>
-        body {{ 
-            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
-            line-height: 1.6;
-            color: #333;
-            max-width: 900px;
-            margin: 0 auto;
-            padding: 20px;
-            background: #fff;
-        }}
-        pre {{ 
-            background: #f4f4f4;
-            padding: 15px;
-            border-radius: 5px;
-            overflow-x: auto;
-        }}
-        code {{ 
-            background: #f4f4f4;
-            padding: 2px 5px;
-            border-radius: 3px;
-            font-family: 'Monaco', 'Consolas', monospace;
-        }}
-        h1, h2, h3 {{ 
-            border-bottom: 1px solid #eee;
-            padding-bottom: 10px;
-        }}
-        blockquote {{
-            border-left: 4px solid #ddd;
-            margin: 0;
-            padding-left: 20px;
-            color: #666;
-        }}
-        table {{
-            border-collapse: collapse;
-            width: 100%;
-            margin: 20px 0;
-        }}
-        th, td {{
-            border: 1px solid #ddd;
-            padding: 8px;
-            text-align: left;
-        }}
-        th {{
-            background-color: #f2f2f2;
-        }}
-        a {{
-            color: #0066cc;
-            text-decoration: none;
-        }}
-        a:hover {{
-            text-decoration: underline;
-        }}
-    </style>
-</head>
-<body>
-    <div style="margin-bottom: 20px;">
-        <a href="/docs/">← Zurück zur Dokumentation</a> | 
-        <a href="/" target="_blank">← Zurück zur App</a>
-    </div>
-    {}
-</body>
-</html>"#,
-                html_output
-            );
-
-            Response::builder()
-                .header("content-type", "text/html; charset=utf-8")
-                .body(Body::from(full_html))
-                .unwrap()
-        }
-        Err(_) => {
-            Response::builder()
-                .status(StatusCode::NOT_FOUND)
-                .body(Body::from("Dokumentation nicht gefunden"))
-                .unwrap()
-        }
-    }
-}
 
-
-
-
-// ============================================================================
-// AUTHENTICATION HANDLERS - Functions for Login/Register/Logout
-// Website feature: User registration and login
-// ============================================================================
-
-// POST /api/register - Register new user
-// Called when someone submits the registration form
-async fn register_handler(
-    // State(app_state) extracts the global app state from the request
-    State(app_state): State<AppState>,
-    // Json(req) parses the JSON request body into RegisterRequest struct
-    Json(req): Json<RegisterRequest>,
-) -> Result<Response<Body>, StatusCode> {  // Return: HTTP Response or error
-    
-    tracing::info!("Registration attempt for email: {}", req.email);
-    tracing::debug!("Register request received: {:?}", req.email);
-    
-    // Step 1: Check if user already exists
-    match app_state.db.get_user_by_email(&req.email).await {
-        Ok(Some(_)) => {
-            tracing::warn!("Registration failed: User {} already exists", req.email);
-            let response = AuthResponse {
-                success: false,
-                message: "User already exists".to_string(),
-                email: None,
-            };
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)  // HTTP 400
-                .header("content-type", "application/json")
-                .body(Body::from(serde_json::to_string(&response).unwrap()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-        }
-        Ok(None) => {
-            // User does not exist - continue with registration
-        }
-        Err(e) => {
-            tracing::error!("Database error during user lookup: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
+    // Sockets from systemd socket activation replace the configured addresses (HTTP first, then HTTPS)
+    let activated: Vec<_> = service::activated_listeners().into_iter()
+        .filter_map(|listener| tokio::net::TcpListener::from_std(listener).ok())
+        .collect();
+    if !activated.is_empty() {
+        tracing::info!("Using {} socket(s) passed by systemd", activated.len());
     }
+    let mut activated = activated.into_iter();
 
-    // Step 2: Create new DatabaseUser
-    tracing::debug!("Creating new user with hashed password");
-    let db_user = match database::DatabaseUser::new(req.email.clone(), req.display_name.clone(), &req.password) {
-        Ok(user) => user,
-        Err(e) => {
-            tracing::error!("User creation failed for {}: {:?}", req.email, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    // Start TCP listener (server.bind_address:server.http_port, default 0.0.0.0:3000)
+    let listen_address = config.listen_address();
+    let listener = if tls_config.is_none() || config.tls.redirect_http {
+        match activated.next() {
+            Some(listener) => Some(listener),
+            None => Some(tokio::net::TcpListener::bind(&listen_address)
+                .await
+                .unwrap()),  // unwrap() = stop program on error
         }
+    } else {
+        None
     };
-
-    // Step 3: Save user to database
-    if let Err(e) = app_state.db.create_user(db_user.clone()).await {
-        tracing::error!("Database error during user creation: {:?}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    // Step 4: Convert user for JWT
-    let user = User {
-        id: db_user.id.clone(),
-        email: db_user.email.clone(),
-        display_name: db_user.display_name.clone(),
-        password_hash: db_user.password_hash.clone(),
+    let https_listener = match (&tls_config, activated.next()) {
+        (None, _) => None,
+        (Some(_), Some(listener)) => Some(listener),
+        (Some(_), None) => Some(tokio::net::TcpListener::bind(config.https_listen_address())
+            .await
+            .unwrap()),
     };
 
-    // Step 5: Create JWT token (auto-login after registration)
-    tracing::debug!("Creating JWT token for new user");
-    match create_jwt(&user) {
-        Ok(token) => {
-            tracing::info!("Registration successful for user: {}", req.email);
-            let response = AuthResponse {
-                success: true,
-                message: "User registered successfully".to_string(),
-                email: Some(req.email.clone()),
-            };
-
-            Response::builder()
-                .header("set-cookie", create_auth_cookie(&token))
-                .header("content-type", "application/json")
-                .body(Body::from(serde_json::to_string(&response).unwrap()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-        }
-        Err(e) => {
-            tracing::error!("JWT creation failed for {}: {:?}", req.email, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-async fn login_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<LoginRequest>,
-) -> Result<Response<Body>, StatusCode> {
-    
-    tracing::info!("Login attempt for email: {}", req.email);
-    tracing::debug!("Login request received for: {}", req.email);
-    
-    // Search for user in database
-    let db_user = match app_state.db.get_user_by_email(&req.email).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            tracing::warn!("Login failed: User {} not found", req.email);
-            let response = AuthResponse {
-                success: false,
-                message: "Invalid credentials".to_string(),
-                email: None,
-            };
-            return Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
-                .header("content-type", "application/json")
-                .body(Body::from(serde_json::to_string(&response).unwrap()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-        }
-        Err(e) => {
-            tracing::error!("Database error during login for {}: {:?}", req.email, e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
+    // Listeners are bound: tell systemd (Type=notify) we are up; SIGTERM/Ctrl+C stop cleanly
+    service::ready(&format!("Serving on port {}", config.server.http_port));
+    tokio::spawn(async move {
+        service::shutdown_signal().await;
+        tracing::info!("Shutting down");
+        service::notify("STOPPING=1");
+        if let Some(pid_file) = pid_file {
+            pid_file.remove();
         }
-    };
+        std::process::exit(0);
+    });
 
-    tracing::debug!("User found in database: {}", req.email);
-    
-    // Verify password
-    match db_user.verify_password(&req.password) {
-        Ok(true) => {
-            tracing::debug!("Password verification successful");
-            
-            // Convert user for JWT
-            let user = User {
-                id: db_user.id.clone(),
-                email: db_user.email.clone(),
-                display_name: db_user.display_name.clone(),
-                password_hash: db_user.password_hash.clone(),
-            };
-            
-            // Create JWT token
-            match create_jwt(&user) {
-                Ok(token) => {
-                    tracing::info!("Login successful for user: {}", req.email);
-                    let response = AuthResponse {
-                        success: true,
-                        message: "Login successful".to_string(),
-                        email: Some(req.email.clone()),
-                    };
-
-                    Response::builder()
-                        .header("set-cookie", create_auth_cookie(&token))
-                        .header("content-type", "application/json")
-                        .body(Body::from(serde_json::to_string(&response).unwrap()))
-                        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-                }
-                Err(e) => {
-                    tracing::error!("JWT creation failed during login for {}: {:?}", req.email, e);
-                    Err(StatusCode::INTERNAL_SERVER_ERROR)
-                }
-            }
-        }
-        Ok(false) => {
-            tracing::warn!("Login failed: Invalid password for {}", req.email);
-            let response = AuthResponse {
-                success: false,
-                message: "Invalid credentials".to_string(),
-                email: None,
-            };
-            Response::builder()
-                .status(StatusCode::UNAUTHORIZED)
-                .header("content-type", "application/json")
-                .body(Body::from(serde_json::to_string(&response).unwrap()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-        }
-        Err(e) => {
-            tracing::error!("Password verification error for {}: {:?}", req.email, e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
+    if tls_config.is_some() {
+        tracing::info!("Server running on https://{}{}/", config.https_listen_address(), base_path);
+        if listener.is_some() {
+            tracing::info!("Redirecting http://{}/ to HTTPS", listen_address);
         }
+    } else {
+        tracing::info!("Server running on http://{}{}/", listen_address, base_path);
     }
-}
-
-async fn logout_handler() -> Response<Body> {
-    let response = AuthResponse {
-        success: true,
-        message: "Logged out successfully".to_string(),
-        email: None,
-    };
-
-    Response::builder()
-        .header("set-cookie", create_logout_cookie())
-        .header("content-type", "application/json")
-        .body(Body::from(serde_json::to_string(&response).unwrap()))
-        .unwrap()
-}
-
-async fn validate_token_handler(cookie_jar: CookieJar) -> StatusCode {
-    // Always return OK since authentication is now optional
-    // The frontend can continue to use this endpoint to check authentication
-    // but it will always succeed allowing access without login
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    tracing::info!("Available endpoints:");
+    tracing::info!("   - GET  /           - SPA Main Page");
+    tracing::info!("   - GET  /login.html - Login Page");
+    tracing::info!("   - POST /api/login  - Login API");
+    tracing::info!("   - POST /api/register - Register API");
+    tracing::info!("   - POST /api/profile/display-name - Update Display Name");
+    tracing::info!("   - GET  /channel    - WebSocket Canvas Events");
+    tracing::info!("   - GET  /api/websocket/stats - WebSocket Statistics");
+    tracing::info!("Debug tip: Set RUST_LOG=debug for detailed logging");
     
-    match token {
-        Some(token_value) => {
-            // If there is a token, validate it
-            match validate_jwt(token_value) {
-                Ok(_) => StatusCode::OK,
-                Err(_) => StatusCode::OK, // Even invalid tokens are OK now (guest access)
-            }
-        }
-        None => StatusCode::OK, // No token is also OK (guest access)
-    }
-}
-
-// GET /api/user-info - Returns user information from JWT (optional auth)
-// Website feature: Display name display in frontend
-async fn user_info_handler(cookie_jar: CookieJar) -> Result<Json<Value>, StatusCode> {
-    // Extract JWT token from cookie (optional)
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-
-    match token {
-        Some(token_value) => {
-            // If token exists, validate and return user info
-            match validate_jwt(token_value) {
-                Ok(claims) => {
-                    Ok(Json(json!({
-                        "success": true,
-                        "authenticated": true,
-                        "user_id": claims.user_id,
-                        "display_name": claims.display_name,
-                        "canvas_permissions": claims.device_permissions
-                    })))
-                }
-                Err(_) => {
-                    // Invalid token, return guest user
-                    Ok(Json(json!({
-                        "success": true,
-                        "authenticated": false,
-                        "user_id": "guest",
-                        "display_name": "Guest User",
-                        "canvas_permissions": {}
-                    })))
-                }
+    let Some(tls_config) = tls_config else {
+        // Start server and wait for requests - with ConnectInfo for WebSocket
+        let listener = listener.expect("HTTP listener is bound without TLS");
+        axum::serve(listener, app.into_make_service_with_connect_info::<std::net::SocketAddr>()).await.unwrap();
+        return;
+    };
+
+    // HTTP port only redirects; liveness stays reachable for the Docker HEALTHCHECK
+    if let Some(listener) = listener {
+        let redirect = Router::new()
+            .route(&format!("{}/healthz", base_path), get(healthz_handler))
+            .merge(tls::redirect_router(config.tls.https_port));
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, redirect).await {
+                tracing::error!("HTTP redirect listener stopped: {}", e);
             }
-        }
-        None => {
-            // No token, return guest user
-            Ok(Json(json!({
-                "success": true,
-                "authenticated": false,
-                "user_id": "guest",
-                "display_name": "Guest User",
-                "canvas_permissions": {}
-            })))
-        }
-    }
-}
-
-// POST /api/profile/display-name - Change display name
-// Website feature: Allows users to change their display name
-async fn update_display_name_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    Json(req): Json<UpdateDisplayNameRequest>,
-) -> Result<Response<Body>, StatusCode> {
-    // Extract JWT token from cookie
-    let token = match cookie_jar.get("auth_token") {
-        Some(cookie) => cookie.value(),
-        None => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    // Validate JWT and extract claims
-    let claims = match validate_jwt(token) {
-        Ok(claims) => claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    // Validate display name (not empty, max 50 characters)
-    if req.display_name.trim().is_empty() || req.display_name.len() > 50 {
-        let response = AuthResponse {
-            success: false,
-            message: "Display name must be between 1 and 50 characters".to_string(),
-            email: None,
-        };
-        return Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .header("content-type", "application/json")
-            .body(Body::from(serde_json::to_string(&response).unwrap()))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    // Update display name in database
-    if let Err(e) = app_state.db.update_user_display_name(&claims.user_id, req.display_name.trim()).await {
-        tracing::error!("Database error during display name update: {:?}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
+        });
     }
 
-    tracing::info!("Display name updated for user: {}", claims.email);
-
-    // Load updated user from database
-    let updated_db_user = match app_state.db.get_user_by_id(&claims.user_id).await {
-        Ok(Some(user)) => user,
-        Ok(None) => {
-            tracing::error!("User {} not found in database after update", claims.user_id);
-            return Err(StatusCode::NOT_FOUND);
-        }
-        Err(e) => {
-            tracing::error!("Database error loading updated user: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // Convert user for JWT
-    let user = User {
-        id: updated_db_user.id.clone(),
-        email: updated_db_user.email.clone(),
-        display_name: updated_db_user.display_name.clone(),
-        password_hash: updated_db_user.password_hash.clone(),
-    };
-
-    // Create new JWT with updated display name
-    match create_jwt(&user) {
-        Ok(new_token) => {
-            let response = AuthResponse {
-                success: true,
-                message: "Display name updated successfully".to_string(),
-                email: Some(claims.email),
-            };
-
-            Response::builder()
-                .header("set-cookie", create_auth_cookie(&new_token))
-                .header("content-type", "application/json")
-                .body(Body::from(serde_json::to_string(&response).unwrap()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-        }
-        Err(e) => {
-            tracing::error!("JWT creation failed: {:?}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
+    let https_listener = https_listener.expect("HTTPS listener is bound with TLS");
+    tls::serve_https(https_listener, tls_config, app).await;
 }
-
-// ============================================================================
-// A 5.4: CANVAS MANAGEMENT HANDLERS - API for canvas management with permissions
-// ============================================================================
-
-// GET /api/devices - List all devices (optional auth)
-async fn list_devices_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-) -> Result<Json<Value>, StatusCode> {
-    // Validate JWT token (optional)
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-
-    let user_id = match token {
-        Some(token_value) => {
-            match validate_jwt(token_value) {
-                Ok(claims) => Some(claims.user_id),
-                Err(_) => None,
-            }
-        }
-        None => None,
-    };
-
-    // Get real-time connection states from DeviceManager
-    let connection_states = app_state.device_manager.get_unified_connection_states();
-    let connection_states_map = connection_states.read().await;
-
-    // Load devices from database
-    let device_list = match &user_id {
-        Some(uid) => {
-            // If authenticated, show user's devices with permissions
-            match app_state.db.list_user_devices(uid).await {
-                Ok(device_list) => {
-                    device_list.into_iter().map(|(device, permission)| {
-                        // Check real-time connection status
-                        let is_connected = connection_states_map.get(&device.mac_address).copied().unwrap_or(false);
-                        let status = if is_connected { "Online" } else { "Offline" };
-
-                        json!({
-                            "id": device.mac_address.clone(),
-                            "name": device.name,
-                            "alias": device.alias,
-                            "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
-                            "ip_address": device.ip_address,
-                            "status": status,
-                            "maintenance_mode": device.maintenance_mode,
-                            "firmware_version": device.firmware_version,
-                            "owner_id": device.owner_id,
-                            "last_seen": device.last_seen.to_rfc3339(),
-                            "created_at": device.created_at.to_rfc3339(),
-                            "your_permission": permission,
-                            "connected": is_connected
-                        })
-                    }).collect::<Vec<Value>>()
-                }
-                Err(e) => {
-                    tracing::error!("Database error during device list: {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            }
-        }
-        None => {
-            // If not authenticated, show all devices with guest permission
-            match app_state.db.list_all_devices().await {
-                Ok(device_list) => {
-                    device_list.into_iter().map(|device| {
-                        // Check real-time connection status
-                        let is_connected = connection_states_map.get(&device.mac_address).copied().unwrap_or(false);
-                        let status = if is_connected { "Online" } else { "Offline" };
-
-                        json!({
-                            "id": device.mac_address.clone(),
-                            "name": device.name,
-                            "alias": device.alias,
-                            "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
-                            "ip_address": device.ip_address,
-                            "status": status,
-                            "maintenance_mode": device.maintenance_mode,
-                            "firmware_version": device.firmware_version,
-                            "owner_id": device.owner_id,
-                            "last_seen": device.last_seen.to_rfc3339(),
-                            "created_at": device.created_at.to_rfc3339(),
-                            "your_permission": "GUEST",
-                            "connected": is_connected
-                        })
-                    }).collect::<Vec<Value>>()
-                }
-                Err(e) => {
-                    tracing::error!("Database error during device list: {:?}", e);
-                    return Err(StatusCode::INTERNAL_SERVER_ERROR);
-                }
-            }
-        }
-    };
-
-    Ok(Json(json!({
-        "success": true,
-        "devices": device_list
-    })))
-}
-
-// POST /api/devices - Create new device (optional auth)
-async fn create_device_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    Json(req): Json<CreateDeviceRequest>,
-) -> Result<Response<Body>, StatusCode> {
-    // Validate JWT token (optional)
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-    
-    let owner_id = match token {
-        Some(token_value) => {
-            match validate_jwt(token_value) {
-                Ok(claims) => claims.user_id,
-                Err(_) => "guest".to_string(),
-            }
-        }
-        None => "guest".to_string(),
-    };
-
-    // Validate device name and MAC address
-    if req.name.trim().is_empty() || req.name.len() > 100 {
-        return Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .header("content-type", "application/json")
-            .body(Body::from(json!({"success": false, "message": "Device name must be between 1 and 100 characters"}).to_string()))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-    }
-    
-    if req.mac_address.trim().is_empty() {
-        return Response::builder()
-            .status(StatusCode::BAD_REQUEST)
-            .header("content-type", "application/json")
-            .body(Body::from(json!({"success": false, "message": "MAC address is required"}).to_string()))
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    // Convert MAC address to key format (replace : with -)
-    let mac_key = req.mac_address.trim().replace(':', "-");
-
-    // Create new device
-    let device = database::Device::new(
-        req.name.trim().to_string(),
-        owner_id.clone(),
-        mac_key,
-    );
-
-    // Save device to database
-    if let Err(e) = app_state.db.create_device(device.clone()).await {
-        tracing::error!("Database error during device creation: {:?}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    let user_info = if owner_id == "guest" { "guest user".to_string() } else { owner_id.clone() };
-    tracing::info!("device created: {} by user {}", device.name, user_info);
-
-    Response::builder()
-        .header("content-type", "application/json")
-        .body(Body::from(json!({
-            "success": true,
-            "message": "Canvas created successfully",
-            "device": {
-                "id": device.mac_address.clone(),
-                "name": device.name,
-                "alias": device.alias,
-                "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
-                "ip_address": device.ip_address,
-                "status": device.status,
-                "maintenance_mode": device.maintenance_mode,
-                "firmware_version": device.firmware_version,
-                "owner_id": device.owner_id,
-                "last_seen": device.last_seen.to_rfc3339(),
-                "created_at": device.created_at.to_rfc3339(),
-                "your_permission": "O"
-            }
-        }).to_string()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
-
-// GET /api/devices/:id - Details of an device (optional auth)
-async fn get_device_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    Path(canvas_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    // JWT Token validieren (optional)
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-    
-    let user_id = match token {
-        Some(token_value) => {
-            match validate_jwt(token_value) {
-                Ok(claims) => Some(claims.user_id),
-                Err(_) => None,
-            }
-        }
-        None => None,
-    };
-
-    // Canvas aus Datenbank laden
-    let canvas = match app_state.db.get_device_by_id(&canvas_id).await {
-        Ok(Some(canvas)) => canvas,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Database error loading canvas: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // User-Berechtigung laden (falls authenticated)
-    let user_permission = match &user_id {
-        Some(uid) => {
-            match app_state.db.get_user_device_permission(&canvas_id, uid).await {
-                Ok(Some(permission)) => permission,
-                Ok(None) => "NONE".to_string(),
-                Err(e) => {
-                    tracing::error!("Database error loading user permission: {:?}", e);
-                    "NONE".to_string()
-                }
-            }
-        }
-        None => "GUEST".to_string(), // Guest user has guest permission
-    };
-
-    // Load all permissions (only for moderators or guest gets all)
-    let all_permissions = match &user_id {
-        Some(uid) => {
-            if app_state.db.user_has_device_permission(&canvas_id, uid, "M").await.unwrap_or(false) {
-                Some(app_state.db.get_device_permissions(&canvas_id).await.unwrap_or_default())
-            } else {
-                None
-            }
-        }
-        None => {
-            // Guest users can see all permissions for transparency
-            Some(app_state.db.get_device_permissions(&canvas_id).await.unwrap_or_default())
-        }
-    };
-
-    Ok(Json(json!({
-        "success": true,
-        "canvas": {
-            "id": canvas.mac_address,
-            "name": canvas.name,
-            "alias": canvas.alias,
-            "maintenance_mode": canvas.maintenance_mode,
-            "owner_id": canvas.owner_id,
-            "created_at": canvas.created_at.to_rfc3339(),
-            "your_permission": user_permission,
-            "all_permissions": all_permissions
-        }
-    })))
-}
-
-// POST /api/devices/:id - Device-Eigenschaften ändern (Name, Wartungsmodus) (optional auth)
-async fn update_device_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    Path(canvas_id): Path<String>,
-    Json(req): Json<UpdateDeviceRequest>,
-) -> Result<Response<Body>, StatusCode> {
-    // JWT Token validieren (optional)
-    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-    
-    let user_email = match token {
-        Some(token_value) => {
-            match validate_jwt(token_value) {
-                Ok(claims) => Some(claims.email),
-                Err(_) => None,
-            }
-        }
-        None => None,
-    };
-
-    // Canvas aus Datenbank laden
-    let _canvas = match app_state.db.get_device_by_id(&canvas_id).await {
-        Ok(Some(canvas)) => canvas,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Database error loading canvas: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // Guest users have full permissions for device management
-    // (Authentication is optional, so no permission checks needed)
-
-    // Validate name if provided
-    use crate::auth::MaybeAbsent;
-    if let MaybeAbsent::Value(name) = &req.name {
-        if name.trim().is_empty() || name.len() > 100 {
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("content-type", "application/json")
-                .body(Body::from(json!({"success": false, "message": "Canvas name must be between 1 and 100 characters"}).to_string()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
-
-    // Validate alias if provided
-    if let MaybeAbsent::Value(alias) = &req.alias {
-        if alias.len() > 100 {
-            return Response::builder()
-                .status(StatusCode::BAD_REQUEST)
-                .header("content-type", "application/json")
-                .body(Body::from(json!({"success": false, "message": "Alias must be less than 100 characters"}).to_string()))
-                .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
-
-    // Update canvas
-    // Convert MaybeAbsent<String> -> Option<Option<&str>> for database
-    let name_update = match &req.name {
-        MaybeAbsent::Absent => None,
-        MaybeAbsent::Null => Some(None),
-        MaybeAbsent::Value(s) => Some(Some(s.trim())),
-    };
-
-    let alias_update = match &req.alias {
-        MaybeAbsent::Absent => None,  // Don't update
-        MaybeAbsent::Null => Some(None),  // Clear the alias (set to NULL)
-        MaybeAbsent::Value(s) => {
-            let trimmed = s.trim();
-            if trimmed.is_empty() {
-                Some(None)  // Empty string also clears the alias
-            } else {
-                Some(Some(trimmed))  // Set to the trimmed value
-            }
-        }
-    };
-
-    let maintenance_mode_update = match &req.maintenance_mode {
-        MaybeAbsent::Absent => None,
-        MaybeAbsent::Null => None,  // Null doesn't make sense for boolean, treat as absent
-        MaybeAbsent::Value(b) => Some(*b),
-    };
-
-    if let Err(e) = app_state.db.update_device(
-        &canvas_id,
-        name_update,
-        alias_update,
-        maintenance_mode_update
-    ).await {
-        tracing::error!("Database error updating canvas: {:?}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    // Aktualisierte Canvas laden
-    let updated_canvas = match app_state.db.get_device_by_id(&canvas_id).await {
-        Ok(Some(canvas)) => canvas,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Database error loading updated canvas: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    let user_info = user_email.unwrap_or_else(|| "guest".to_string());
-    tracing::info!("Canvas updated: {} by user {}", updated_canvas.name, user_info);
-
-    Response::builder()
-        .header("content-type", "application/json")
-        .body(Body::from(json!({
-            "success": true,
-            "message": "Canvas updated successfully",
-            "canvas": {
-                "id": updated_canvas.mac_address.clone(),
-                "name": updated_canvas.name,
-                "alias": updated_canvas.alias,
-                "maintenance_mode": updated_canvas.maintenance_mode,
-                "owner_id": updated_canvas.owner_id,
-                "created_at": updated_canvas.created_at.to_rfc3339(),
-                "mac_address": updated_canvas.mac_address.replace('-', ":")  // Show with colons for display
-            }
-        }).to_string()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
-
-
-// POST /api/canvas-permissions/:id - Vereinfachter Permission Handler (optional auth)
-async fn simple_permissions_handler(
-    State(app_state): State<AppState>,
-    Path(canvas_id): Path<String>,
-    cookie_jar: CookieJar,
-    Json(req): Json<UpdatePermissionRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    // JWT Token validieren (optional)
-    let _token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-    
-    // Authentication is optional, so no validation needed
-
-    // Validate permission
-    if req.permission != "REMOVE" && !["R", "W", "V", "M", "O"].contains(&req.permission.as_str()) {
-        return Err(StatusCode::BAD_REQUEST);
-    }
-
-    // Update or remove permission
-    let result = if req.permission == "REMOVE" {
-        app_state.db.remove_device_permission(&canvas_id, &req.user_id).await
-    } else {
-        app_state.db.set_device_permission(&canvas_id, &req.user_id, &req.permission).await
-    };
-
-    if result.is_err() {
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    Ok(Json(json!({
-        "success": true,
-        "message": "Permission updated successfully"
-    })))
-}
-
-// DELETE /api/devices/:id - device löschen
-async fn delete_device_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    Path(canvas_id): Path<String>,
-) -> Result<Response<Body>, StatusCode> {
-    // JWT Token validieren
-    let token = match cookie_jar.get("auth_token") {
-        Some(cookie) => cookie.value(),
-        None => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    let claims = match validate_jwt(token) {
-        Ok(claims) => claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    // Canvas aus Datenbank laden
-    let canvas = match app_state.db.get_device_by_id(&canvas_id).await {
-        Ok(Some(canvas)) => canvas,
-        Ok(None) => return Err(StatusCode::NOT_FOUND),
-        Err(e) => {
-            tracing::error!("Database error loading canvas: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // Only owner can delete canvas
-    let has_permission = match app_state.db.user_has_device_permission(&canvas_id, &claims.user_id, "O").await {
-        Ok(has_permission) => has_permission,
-        Err(e) => {
-            tracing::error!("Database error checking permissions: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    if !has_permission {
-        return Err(StatusCode::FORBIDDEN);
-    }
-
-    // Canvas löschen
-    if let Err(e) = app_state.db.delete_device(&canvas_id).await {
-        tracing::error!("Database error deleting canvas: {:?}", e);
-        return Err(StatusCode::INTERNAL_SERVER_ERROR);
-    }
-
-    tracing::info!("Canvas deleted: {} by user {}", canvas.name, claims.email);
-
-    Response::builder()
-        .header("content-type", "application/json")
-        .body(Body::from(json!({
-            "success": true,
-            "message": "Canvas deleted successfully"
-        }).to_string()))
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
-}
-
-// GET /api/users/search - Search for users for permission management (optional auth)
-async fn search_users_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<Value>, StatusCode> {
-    // Validate JWT token (optional)
-    let _token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-    
-    // Authentication is optional, so no validation needed
-
-    // Suchterm aus Query-Parameter extrahieren
-    let query = params.get("q").cloned().unwrap_or_default();
-    
-    if query.len() < 2 {
-        return Ok(Json(json!({
-            "success": true,
-            "users": []
-        })));
-    }
-
-    // Benutzer in Datenbank suchen
-    let matching_users = match app_state.db.search_users(&query).await {
-        Ok(users) => {
-            users.into_iter().map(|user| {
-                json!({
-                    "user_id": user.id,
-                    "display_name": user.display_name
-                })
-            }).collect::<Vec<Value>>()
-        }
-        Err(e) => {
-            tracing::error!("Database error during user search: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    Ok(Json(json!({
-        "success": true,
-        "users": matching_users
-    })))
-}
-
-// GET /api/users/list - Get first users for scroll field  
-async fn list_users_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
-) -> Result<Json<Value>, StatusCode> {
-    // Validate JWT token
-    let token = match cookie_jar.get("auth_token") {
-        Some(cookie) => cookie.value(),
-        None => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    let _claims = match validate_jwt(token) {
-        Ok(claims) => claims,
-        Err(_) => return Err(StatusCode::UNAUTHORIZED),
-    };
-
-    // Pagination parameters 
-    let offset = params.get("offset").and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
-    let limit = params.get("limit").and_then(|s| s.parse::<i32>().ok()).unwrap_or(20);
-    let limit = limit.min(50); // Max 50 users at once
-
-    // Get users from database
-    let users = match app_state.db.get_users_paginated(offset, limit).await {
-        Ok(users) => {
-            users.into_iter().map(|user| {
-                json!({
-                    "user_id": user.id,
-                    "display_name": user.display_name
-                })
-            }).collect::<Vec<Value>>()
-        }
-        Err(e) => {
-            tracing::error!("Database error during user list: {:?}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    Ok(Json(json!({
-        "success": true,
-        "users": users
-    })))
-}
-
-// POST /api/devices/:id/connect - Establish TCP connection to device
-async fn tcp_connect_handler(
-    State(app_state): State<AppState>,
-    Path(device_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("TCP connect request for device: {}", device_id);
-    match app_state.device_manager.connect_device(&device_id).await {
-        Ok(()) => Ok(Json(json!({ "success": true, "message": "TCP connection established" }))),
-        Err(e) => Ok(Json(json!({ "success": false, "message": format!("TCP connect failed: {}", e) }))),
-    }
-}
-
-// POST /api/devices/:id/disconnect - Disconnect TCP from device
-async fn tcp_disconnect_handler(
-    State(app_state): State<AppState>,
-    Path(device_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("TCP disconnect request for device: {}", device_id);
-    match app_state.device_manager.disconnect_device(&device_id).await {
-        Ok(()) => Ok(Json(json!({ "success": true, "message": "TCP disconnected" }))),
-        Err(e) => Ok(Json(json!({ "success": false, "message": format!("TCP disconnect failed: {}", e) }))),
-    }
-}
-
-// POST /api/devices/:id/reconnect - Disconnect then reconnect TCP to device
-async fn tcp_reconnect_handler(
-    State(app_state): State<AppState>,
-    Path(device_id): Path<String>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("TCP reconnect request for device: {}", device_id);
-    let _ = app_state.device_manager.disconnect_device(&device_id).await;
-    match app_state.device_manager.connect_device(&device_id).await {
-        Ok(()) => Ok(Json(json!({ "success": true, "message": "TCP reconnected" }))),
-        Err(e) => Ok(Json(json!({ "success": false, "message": format!("TCP reconnect failed: {}", e) }))),
-    }
-}
-
-// GET /api/devices/discovered - List discovered devices (authentication optional)
-async fn discovered_devices_handler(
-    State(app_state): State<AppState>,
-    cookie_jar: CookieJar,
-) -> Result<Json<Value>, StatusCode> {
-    // Extract JWT token from cookie (optional)
-    let _token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
-
-    // Get real-time connection states from DeviceManager
-    let connection_states = app_state.device_manager.get_unified_connection_states();
-    let connection_states_map = connection_states.read().await;
-
-    // Authentication is optional for device discovery
-    // Get discovered devices from DeviceDiscovery service (TCP/mDNS devices)
-    let discovered_devices = {
-        let discovery = app_state.device_discovery.lock().await;
-        discovery.get_discovered_devices().await
-    };
-
-    let tcp_device_count = discovered_devices.len();
-    tracing::info!("Device Discovery API called - found {} TCP devices", tcp_device_count);
-
-    let mut devices_json: Vec<Value> = Vec::new();
-
-    for (device_id, discovered_device) in discovered_devices {
-        tracing::info!("Processing discovered device: {} with mDNS data: {:?}",
-            device_id, discovered_device.mdns_data.is_some());
-
-        let mut device_json = json!({
-            "deviceId": device_id,
-            "deviceIp": discovered_device.device_config.ip_address.to_string(),
-            "tcpPort": discovered_device.device_config.tcp_port,
-            "udpPort": discovered_device.device_config.udp_port,
-            "status": "discovered",
-            "connectionType": "tcp"
-        });
-
-        let mut mac_address_key: Option<String> = None;
-
-        // Add MAC address and mDNS hostname from mDNS data if available
-        if let Some(ref mdns_data) = discovered_device.mdns_data {
-            tracing::info!("Found mDNS data with {} TXT records", mdns_data.txt_records.len());
-            if let Some(mac_address) = mdns_data.txt_records.get("mac") {
-                tracing::info!("Adding MAC address to JSON: {}", mac_address);
-                device_json["macAddress"] = json!(mac_address);
-                // Store MAC address key for database lookup (replace : with -)
-                mac_address_key = Some(mac_address.replace(':', "-"));
-            } else {
-                tracing::warn!("No 'mac' key found in TXT records: {:?}", mdns_data.txt_records.keys().collect::<Vec<_>>());
-            }
-
-            // Add mDNS hostname without .local suffix
-            let mdns_hostname = mdns_data.hostname.replace(".local", "").trim_end_matches('.').to_string();
-            device_json["mdnsHostname"] = json!(mdns_hostname);
-            tracing::info!("Adding mDNS hostname to JSON: {}", mdns_hostname);
-        } else {
-            tracing::warn!("No mDNS data found for device: {}", device_id);
-        }
-
-        // Try to load alias and name from database if we have a MAC address
-        // Also check connection status
-        if let Some(ref mac_key) = mac_address_key {
-            // Check real-time connection status using MAC address key
-            let is_connected = connection_states_map.get(mac_key).copied().unwrap_or(false);
-            device_json["connected"] = json!(is_connected);
-            tracing::info!("Device {} connection status: {}", mac_key, is_connected);
-
-            match app_state.db.get_device_by_id(mac_key).await {
-                Ok(Some(device)) => {
-                    if let Some(alias) = device.alias {
-                        device_json["alias"] = json!(alias);
-                        tracing::info!("Adding alias to discovered device {}: {}", mac_key, alias);
-                    }
-                    device_json["name"] = json!(device.name);
-                    tracing::info!("Adding name to discovered device {}: {}", mac_key, device.name);
-                }
-                Ok(None) => {
-                    tracing::debug!("No device found in database for MAC: {}", mac_key);
-                }
-                Err(e) => {
-                    tracing::warn!("Error loading device from database for MAC {}: {}", mac_key, e);
-                }
-            }
-        } else {
-            // No MAC address, cannot determine connection status
-            device_json["connected"] = json!(false);
-        }
-
-        devices_json.push(device_json);
-    }
-
-    // Get UART devices from database (persistent storage)
-    let uart_devices_from_db = match app_state.db.get_devices_by_connection_type("uart").await {
-        Ok(devices) => devices,
-        Err(e) => {
-            tracing::warn!("Failed to load UART devices from database: {}", e);
-            Vec::new()
-        }
-    };
-
-    let uart_device_count = uart_devices_from_db.len();
-    tracing::info!("Loaded {} UART devices from database", uart_device_count);
-
-    // Add UART devices to response
-    for uart_device in uart_devices_from_db {
-        // Check real-time connection status for UART device
-        let is_connected = connection_states_map.get(&uart_device.mac_address).copied().unwrap_or(false);
-
-        let mut uart_json = json!({
-            "deviceId": uart_device.mac_address.clone(),
-            "deviceIp": "0.0.0.0",  // UART devices have no IP
-            "tcpPort": 0,
-            "udpPort": 0,
-            "status": "discovered",
-            "connectionType": "uart",
-            "mdnsHostname": Some(format!("uart-{}", uart_device.mac_address)),
-            "macAddress": uart_device.mac_address.replace('-', ":"),  // Show MAC with colons
-            "name": uart_device.name.clone(),
-            "connected": is_connected
-        });
-
-        // Add alias if available
-        if let Some(alias) = uart_device.alias {
-            uart_json["alias"] = json!(alias);
-            tracing::info!("Added UART device from DB with alias: {} (alias: {}, connected: {})", uart_device.mac_address, alias, is_connected);
-        } else {
-            tracing::info!("Added UART device from DB to discovered list: {} (connected: {})", uart_device.mac_address, is_connected);
-        }
-
-        devices_json.push(uart_json);
-    }
-
-    tracing::info!("Total discovered devices: {} (TCP: {}, UART: {})",
-                   devices_json.len(),
-                   tcp_device_count,
-                   uart_device_count);
-
-    Ok(Json(json!({
-        "success": true,
-        "devices": devices_json
-    })))
-}
-
-// ============================================================================
-// UART SETTINGS HANDLERS - API handlers for UART configuration
-// ============================================================================
-
-// GET /api/uart/settings - Get current UART settings
-async fn get_uart_settings_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    match app_state.db.get_uart_settings().await {
-        Ok(Some((port, baud_rate, auto_connect))) => {
-            Ok(Json(json!({
-                "success": true,
-                "port": port,
-                "baudRate": baud_rate,
-                "autoConnect": auto_connect
-            })))
-        }
-        Ok(None) => {
-            Ok(Json(json!({
-                "success": true,
-                "port": null,
-                "baudRate": 115200,
-                "autoConnect": false
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to get UART settings: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-// POST /api/uart/settings - Update UART settings
-#[derive(Debug, Deserialize)]
-struct UpdateUartSettingsRequest {
-    port: Option<String>,
-    #[serde(rename = "baudRate")]
-    baud_rate: u32,
-    #[serde(rename = "autoConnect")]
-    auto_connect: bool,
-}
-
-// POST /api/debug/settings - Update debug settings
-#[derive(Debug, Deserialize)]
-struct UpdateDebugSettingsRequest {
-    #[serde(rename = "maxDebugMessages")]
-    max_debug_messages: u32,
-}
-
-async fn update_uart_settings_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<UpdateUartSettingsRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("Updating UART settings: port={:?}, baud_rate={}, auto_connect={}",
-        req.port, req.baud_rate, req.auto_connect);
-
-    match app_state.db.update_uart_settings(
-        req.port.as_deref(),
-        req.baud_rate,
-        req.auto_connect
-    ).await {
-        Ok(()) => {
-            Ok(Json(json!({
-                "success": true,
-                "message": "UART settings updated successfully"
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to update UART settings: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-// GET /api/uart/ports - List available serial ports
-async fn list_uart_ports_handler() -> Result<Json<Value>, StatusCode> {
-    match uart_connection::UartConnection::list_ports() {
-        Ok(ports) => {
-            Ok(Json(json!({
-                "success": true,
-                "ports": ports
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to list UART ports: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-// POST /api/uart/connect - Connect to UART port
-#[derive(Debug, Deserialize)]
-struct UartConnectRequest {
-    port: String,
-    #[serde(rename = "baudRate")]
-    baud_rate: u32,
-    #[serde(rename = "autoConnect", default)]
-    auto_connect: bool,
-}
-
-async fn uart_connect_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<UartConnectRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("UART connect request: port={}, baud_rate={}, auto_connect={}",
-        req.port, req.baud_rate, req.auto_connect);
-
-    // Try to connect first
-    let mut uart = app_state.uart_connection.lock().await;
-    match uart.connect(req.port.clone(), req.baud_rate).await {
-        Ok(()) => {
-            drop(uart); // Release lock before database operation
-
-            // Save settings to database after successful connection
-            if let Err(e) = app_state.db.update_uart_settings(
-                Some(&req.port),
-                req.baud_rate,
-                req.auto_connect
-            ).await {
-                tracing::error!("Failed to save UART settings after connect: {}", e);
-                // Don't fail the connection just because settings save failed
-            } else {
-                tracing::info!("UART settings saved to database");
-            }
-
-            Ok(Json(json!({
-                "success": true,
-                "message": format!("Connected to UART port {} and settings saved", req.port)
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to connect to UART port: {}", e);
-            Ok(Json(json!({
-                "success": false,
-                "message": format!("Failed to connect: {}", e)
-            })))
-        }
-    }
-}
-
-// POST /api/uart/disconnect - Disconnect from UART port
-async fn uart_disconnect_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("UART disconnect request");
-
-    let mut uart = app_state.uart_connection.lock().await;
-    match uart.disconnect().await {
-        Ok(()) => {
-            Ok(Json(json!({
-                "success": true,
-                "message": "Disconnected from UART port"
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to disconnect from UART port: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-// GET /api/uart/status - Get UART connection status
-async fn uart_status_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    let uart = app_state.uart_connection.lock().await;
-    let is_connected = uart.is_connected().await;
-    let settings = uart.get_settings().await;
-
-    Ok(Json(json!({
-        "success": true,
-        "connected": is_connected,
-        "port": settings.as_ref().map(|s| &s.port),
-        "baudRate": settings.map(|s| s.baud_rate).unwrap_or(115200)
-    })))
-}
-
-// ============================================================================
-// DEBUG SETTINGS HANDLERS - API handlers for debug configuration
-// ============================================================================
-
-// GET /api/debug/settings - Get current debug settings
-async fn get_debug_settings_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    match app_state.db.get_debug_settings().await {
-        Ok(Some(max_debug_messages)) => {
-            Ok(Json(json!({
-                "success": true,
-                "maxDebugMessages": max_debug_messages
-            })))
-        }
-        Ok(None) => {
-            Ok(Json(json!({
-                "success": true,
-                "maxDebugMessages": 200
-            })))
-        }
-        Err(e) => {
-            tracing::error!("Failed to get debug settings: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-async fn update_debug_settings_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<UpdateDebugSettingsRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    tracing::info!("Updating debug settings: max_debug_messages={}", req.max_debug_messages);
-
-    // Validate: min 10, max 10000
-    if req.max_debug_messages < 10 || req.max_debug_messages > 10000 {
-        return Ok(Json(json!({
-            "success": false,
-            "message": "Max debug messages must be between 10 and 10000"
-        })));
-    }
-
-    // Update database
-    match app_state.db.update_debug_settings(req.max_debug_messages).await {
-        Ok(()) => {}
-        Err(e) => {
-            tracing::error!("Failed to update debug settings: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    }
-
-    // Update device store limit immediately
-    app_state.device_store.set_max_debug_messages(req.max_debug_messages as usize).await;
-    tracing::info!("Debug settings updated in database and device store");
-
-    Ok(Json(json!({
-        "success": true,
-        "message": "Debug settings updated successfully"
-    })))
-}
-
-// ============================================================================
-// GITHUB SETTINGS HANDLERS
-// GET  /api/github/settings - returns whether a token is configured (not the token itself)
-// POST /api/github/settings - save or clear the GitHub personal access token
-// ============================================================================
-
-#[derive(serde::Deserialize)]
-struct UpdateGithubSettingsRequest {
-    token: Option<String>,
-    owner: Option<String>,
-    repo: Option<String>,
-    asset: Option<String>,
-}
-
-async fn get_github_settings_handler(
-    State(app_state): State<AppState>,
-) -> Result<Json<Value>, StatusCode> {
-    match app_state.db.get_github_settings().await {
-        Ok((token, owner, repo, asset)) => Ok(Json(json!({
-            "success": true,
-            "hasToken": token.is_some(),
-            "owner": owner,
-            "repo": repo,
-            "asset": asset,
-        }))),
-        Err(e) => {
-            tracing::error!("Failed to get GitHub settings: {}", e);
-            Err(StatusCode::INTERNAL_SERVER_ERROR)
-        }
-    }
-}
-
-async fn update_github_settings_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<UpdateGithubSettingsRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    // token field semantics:
-    //   None (field absent in JSON) = do not touch the stored token
-    //   Some("") = clear the stored token
-    //   Some("ghp_...") = save new token
-    if let Some(token_val) = &req.token {
-        let token_ref = if token_val.is_empty() { None } else { Some(token_val.as_str()) };
-        match app_state.db.set_github_token(token_ref).await {
-            Ok(()) => {}
-            Err(e) => {
-                tracing::error!("Failed to save GitHub token: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-    }
-
-    // Only update owner/repo/asset if at least one of them was present in the request
-    if req.owner.is_some() || req.repo.is_some() || req.asset.is_some() {
-        let owner_ref = req.owner.as_deref().filter(|s| !s.is_empty());
-        let repo_ref  = req.repo.as_deref().filter(|s| !s.is_empty());
-        let asset_ref = req.asset.as_deref().filter(|s| !s.is_empty());
-        match app_state.db.set_github_settings(owner_ref, repo_ref, asset_ref).await {
-            Ok(()) => {
-                tracing::info!("GitHub settings saved: owner={:?} repo={:?} asset={:?}", owner_ref, repo_ref, asset_ref);
-            }
-            Err(e) => {
-                tracing::error!("Failed to save GitHub settings: {}", e);
-                return Err(StatusCode::INTERNAL_SERVER_ERROR);
-            }
-        }
-    }
-
-    Ok(Json(json!({ "success": true })))
-}
-
-// ============================================================================
-// FIRMWARE HANDLERS - Upload/fetch .bin files for ESP32 OTA
-// POST /api/firmware/upload  - multipart upload
-// POST /api/firmware/fetch   - download from URL (e.g. GitHub Release)
-// ============================================================================
-
-/// Request body for POST /api/firmware/fetch
-///
-/// Two modes:
-///   1. GitHub API (private repos): provide `owner`, `repo`, `tag`, `asset`, optionally `github_token`
-///   2. Direct URL (public): provide `url`, optionally `filename`
-#[derive(serde::Deserialize)]
-struct FirmwareFetchRequest {
-    // --- GitHub API mode ---
-    owner: Option<String>,
-    repo: Option<String>,
-    tag: Option<String>,
-    asset: Option<String>,
-    github_token: Option<String>,
-    // --- Direct URL mode ---
-    url: Option<String>,
-    filename: Option<String>,
-}
-
-async fn firmware_fetch_handler(
-    State(app_state): State<AppState>,
-    Json(req): Json<FirmwareFetchRequest>,
-) -> Result<Json<Value>, StatusCode> {
-    // Direct URL mode: no DB lookup needed
-    if req.url.is_some() {
-        let url = req.url.as_deref().unwrap();
-
-        if !url.ends_with(".bin") {
-            return Ok(Json(json!({ "success": false, "message": "URL must point to a .bin file" })));
-        }
-
-        let filename = req.filename.clone().unwrap_or_else(|| {
-            url.split('/').last().unwrap_or("firmware.bin").to_string()
-        });
-        // Strip any directory component to prevent path traversal
-        let filename = std::path::Path::new(&filename)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("firmware.bin")
-            .to_string();
-
-        if !filename.ends_with(".bin") {
-            return Ok(Json(json!({ "success": false, "message": "Filename must end with .bin" })));
-        }
-
-        tracing::info!("Fetching firmware from URL: {}", url);
-
-        let response = reqwest::get(url).await.map_err(|e| {
-            tracing::error!("Failed to fetch firmware from {}: {}", url, e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        if !response.status().is_success() {
-            tracing::error!("Firmware fetch failed: HTTP {}", response.status());
-            return Ok(Json(json!({
-                "success": false,
-                "message": format!("Remote returned HTTP {}", response.status())
-            })));
-        }
-
-        let data = response.bytes().await.map_err(|e| {
-            tracing::error!("Failed to read firmware response body: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        std::fs::create_dir_all("firmware").map_err(|e| {
-            tracing::error!("Failed to create firmware directory: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        let path = format!("firmware/{}", filename);
-        std::fs::write(&path, &data).map_err(|e| {
-            tracing::error!("Failed to write firmware file {}: {}", path, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        tracing::info!("Firmware fetched: {} ({} bytes) from {}", filename, data.len(), url);
-        return Ok(Json(json!({
-            "success": true,
-            "filename": filename,
-            "size": data.len()
-        })));
-    }
-
-    // GitHub mode: load DB settings as fallback for missing request fields
-    let (db_token, db_owner, db_repo, db_asset) = match app_state.db.get_github_settings().await {
-        Ok(s) => s,
-        Err(e) => {
-            tracing::error!("Failed to load GitHub settings from DB: {}", e);
-            return Err(StatusCode::INTERNAL_SERVER_ERROR);
-        }
-    };
-
-    // Merge: request fields take priority, DB values are fallback
-    let owner = req.owner.as_deref().or(db_owner.as_deref());
-    let repo  = req.repo.as_deref().or(db_repo.as_deref());
-    let asset = req.asset.as_deref().or(db_asset.as_deref());
-    let token = req.github_token.as_deref().or(db_token.as_deref());
-
-    if let (Some(owner), Some(repo), Some(asset_name)) = (owner, repo, asset) {
-        let tag = req.tag.as_deref();
-
-        // Validate asset filename and strip any directory component
-        let asset_name = std::path::Path::new(asset_name)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or(asset_name);
-        if !asset_name.ends_with(".bin") {
-            return Ok(Json(json!({ "success": false, "message": "Asset name must end with .bin" })));
-        }
-
-        let resolved_token = token.map(|t| t.to_string());
-        // ── GitHub API mode ──────────────────────────────────────────────────
-        let client = reqwest::Client::builder()
-            .user_agent("esp32-manager-server/1.0")
-            .build()
-            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
-
-        // Step 1: fetch release metadata to get the asset ID
-        let release_url = match tag {
-            Some(t) => format!("https://api.github.com/repos/{}/{}/releases/tags/{}", owner, repo, t),
-            None    => format!("https://api.github.com/repos/{}/{}/releases/latest", owner, repo),
-        };
-        tracing::info!("Fetching GitHub release metadata: {}", release_url);
-
-        let mut meta_req = client.get(&release_url)
-            .header("Accept", "application/vnd.github+json");
-        if let Some(token) = &resolved_token {
-            meta_req = meta_req.header("Authorization", format!("Bearer {}", token));
-        }
-
-        let meta_resp = meta_req.send().await.map_err(|e| {
-            tracing::error!("Failed to reach GitHub API: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        if !meta_resp.status().is_success() {
-            tracing::error!("GitHub API returned HTTP {}", meta_resp.status());
-            return Ok(Json(json!({
-                "success": false,
-                "message": format!("GitHub API returned HTTP {}", meta_resp.status())
-            })));
-        }
-
-        let release: Value = meta_resp.json().await.map_err(|e| {
-            tracing::error!("Failed to parse GitHub release JSON: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        // Find the asset by name
-        let asset_id = release["assets"]
-            .as_array()
-            .and_then(|assets| {
-                assets.iter().find(|a| a["name"].as_str() == Some(asset_name))
-            })
-            .and_then(|a| a["id"].as_u64())
-            .ok_or_else(|| {
-                tracing::error!("Asset '{}' not found in release '{:?}'", asset_name, tag);
-                StatusCode::NOT_FOUND
-            })?;
-
-        // Step 2: download the asset via GitHub API (handles private repo redirects correctly)
-        let asset_url = format!(
-            "https://api.github.com/repos/{}/{}/releases/assets/{}",
-            owner, repo, asset_id
-        );
-        tracing::info!("Downloading GitHub asset: {}", asset_url);
-
-        let mut asset_req = client.get(&asset_url)
-            .header("Accept", "application/octet-stream");
-        if let Some(token) = &resolved_token {
-            asset_req = asset_req.header("Authorization", format!("Bearer {}", token));
-        }
-
-        let asset_resp = asset_req.send().await.map_err(|e| {
-            tracing::error!("Failed to download GitHub asset: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        if !asset_resp.status().is_success() {
-            tracing::error!("GitHub asset download failed: HTTP {}", asset_resp.status());
-            return Ok(Json(json!({
-                "success": false,
-                "message": format!("GitHub asset download returned HTTP {}", asset_resp.status())
-            })));
-        }
-
-        let data = asset_resp.bytes().await.map_err(|e| {
-            tracing::error!("Failed to read asset response body: {}", e);
-            StatusCode::BAD_GATEWAY
-        })?;
-
-        std::fs::create_dir_all("firmware").map_err(|e| {
-            tracing::error!("Failed to create firmware directory: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        let path = format!("firmware/{}", asset_name);
-        std::fs::write(&path, &data).map_err(|e| {
-            tracing::error!("Failed to write firmware file {}: {}", path, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        tracing::info!("GitHub firmware saved: {} ({} bytes)", asset_name, data.len());
-        Ok(Json(json!({
-            "success": true,
-            "filename": asset_name,
-            "size": data.len()
-        })))
-    } else {
-        Ok(Json(json!({
-            "success": false,
-            "message": "Provide either (owner, repo, tag, asset) for GitHub or (url) for direct download"
-        })))
-    }
-}
-
-// ============================================================================
-// FIRMWARE UPLOAD HANDLER - Upload .bin files for ESP32 OTA
-// POST /api/firmware/upload
-// ============================================================================
-
-async fn firmware_upload_handler(
-    mut multipart: axum::extract::Multipart,
-) -> Result<Json<Value>, StatusCode> {
-    std::fs::create_dir_all("firmware").map_err(|e| {
-        tracing::error!("Failed to create firmware directory: {}", e);
-        StatusCode::INTERNAL_SERVER_ERROR
-    })?;
-
-    while let Some(field) = multipart.next_field().await.map_err(|e| {
-        tracing::error!("Multipart error: {}", e);
-        StatusCode::BAD_REQUEST
-    })? {
-        let raw_name = field
-            .file_name()
-            .filter(|n| n.ends_with(".bin"))
-            .map(|n| n.to_string())
-            .ok_or_else(|| {
-                tracing::warn!("Firmware upload rejected: missing or non-.bin filename");
-                StatusCode::BAD_REQUEST
-            })?;
-        // Strip any directory component to prevent path traversal
-        let filename = std::path::Path::new(&raw_name)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap_or("firmware.bin")
-            .to_string();
-
-        let data = field.bytes().await.map_err(|e| {
-            tracing::error!("Failed to read firmware bytes: {}", e);
-            StatusCode::BAD_REQUEST
-        })?;
-
-        let path = format!("firmware/{}", filename);
-        std::fs::write(&path, &data).map_err(|e| {
-            tracing::error!("Failed to write firmware file {}: {}", path, e);
-            StatusCode::INTERNAL_SERVER_ERROR
-        })?;
-
-        tracing::info!("Firmware uploaded: {} ({} bytes)", filename, data.len());
-        return Ok(Json(json!({
-            "success": true,
-            "filename": filename,
-            "size": data.len()
-        })));
-    }
-
-    tracing::warn!("Firmware upload: no file field in multipart request");
-    Err(StatusCode::BAD_REQUEST)
-}
\ No newline at end of file
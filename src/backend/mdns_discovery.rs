@@ -1,19 +1,89 @@
 use std::collections::HashMap;
 use std::net::IpAddr;
 use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
 use tracing::{info, trace, error};
 use mdns_sd::{ServiceDaemon, ServiceEvent};
 
+/// mDNS service type to browse, with optional TXT filter
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MdnsServiceConfig {
+    /// Service type, e.g. "_esp32._tcp" (".local." is appended if missing)
+    pub service_type: String,
+    /// TXT records that must be present: key -> expected value ("*" = any value)
+    #[serde(default)]
+    pub txt_filter: HashMap<String, String>,
+    /// Announcements of this service are microcontrollers without hostname/TXT heuristics
+    #[serde(default)]
+    pub trusted: bool,
+}
+
+impl MdnsServiceConfig {
+    pub fn new(service_type: &str, trusted: bool) -> Self {
+        Self {
+            service_type: service_type.to_string(),
+            txt_filter: HashMap::new(),
+            trusted,
+        }
+    }
+
+    /// Fully qualified service type for browsing ("_esp32._tcp.local.")
+    pub fn full_service_type(&self) -> String {
+        let service_type = self.service_type.trim_end_matches('.');
+        if service_type.ends_with(".local") {
+            format!("{}.", service_type)
+        } else {
+            format!("{}.local.", service_type)
+        }
+    }
+
+    /// Check the TXT filter (case-insensitive values)
+    pub fn matches_txt(&self, txt_records: &HashMap<String, String>) -> bool {
+        self.txt_filter.iter().all(|(key, expected)| {
+            match txt_records.get(key) {
+                Some(value) => expected == "*" || value.eq_ignore_ascii_case(expected),
+                None => false,
+            }
+        })
+    }
+}
+
+/// Default browsed services: Arduino OTA, native ESP32 firmware and HTTP (heuristic)
+pub fn default_mdns_services() -> Vec<MdnsServiceConfig> {
+    vec![
+        MdnsServiceConfig::new("_arduino._tcp", true),
+        MdnsServiceConfig::new("_esp32._tcp", true),
+        MdnsServiceConfig::new("_http._tcp", false),
+    ]
+}
+
+/// One service announcement of a device (a device can announce several)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MdnsServiceRecord {
+    pub service_type: String,
+    pub instance_name: String,
+    pub port: u16,
+    pub txt_records: HashMap<String, String>,
+    pub last_seen: chrono::DateTime<chrono::Utc>,
+}
+
 /// Discovered microcontroller device information from mDNS
 #[derive(Debug, Clone)]
 pub struct MdnsDiscoveredDevice {
     pub hostname: String,
     pub ip_addresses: Vec<IpAddr>,
+    /// Port of the service the device was first discovered with
     pub port: u16,
+    /// TXT records of all services merged (first discovered service wins on conflicts)
     pub txt_records: HashMap<String, String>,
+    /// Service the device was first discovered with
     pub service_name: String,
+    /// All services announced by the device, keyed by service type
+    pub services: HashMap<String, MdnsServiceRecord>,
 }
 
 /// mDNS-based microcontroller discovery service
@@ -24,6 +94,10 @@ pub struct MdnsDiscovery {
     discovered_devices: Arc<RwLock<HashMap<String, MdnsDiscoveredDevice>>>,
     /// Discovery task control
     stop_tx: Option<mpsc::UnboundedSender<()>>,
+    /// Browsed service types
+    services: Vec<MdnsServiceConfig>,
+    /// Interval for re-browsing all service types (refreshes last-seen of live devices)
+    reresolve_interval: Duration,
     /// Running state
     is_running: bool,
 }
@@ -35,9 +109,26 @@ impl MdnsDiscovery {
             mdns_daemon: None,
             discovered_devices: Arc::new(RwLock::new(HashMap::new())),
             stop_tx: None,
+            services: default_mdns_services(),
+            reresolve_interval: Duration::from_secs(120),
             is_running: false,
         })
     }
+
+    /// Configure the re-resolution interval (takes effect on next start)
+    pub fn set_reresolve_interval(&mut self, interval: Duration) {
+        self.reresolve_interval = interval.max(Duration::from_secs(10));
+    }
+
+    /// Shared cache of resolved devices (per-service last-seen timestamps)
+    pub fn discovered_devices(&self) -> Arc<RwLock<HashMap<String, MdnsDiscoveredDevice>>> {
+        Arc::clone(&self.discovered_devices)
+    }
+
+    /// Configure browsed service types (takes effect on next start)
+    pub fn set_services(&mut self, services: Vec<MdnsServiceConfig>) {
+        self.services = services;
+    }
     
     /// Start mDNS discovery for microcontroller devices
     pub async fn start_discovery<F>(
@@ -67,29 +158,24 @@ impl MdnsDiscovery {
         // Clone mdns_daemon for the task
         let mdns_daemon = self.mdns_daemon.as_ref().unwrap().clone();
         
+        let services = self.services.clone();
+        let reresolve_interval = self.reresolve_interval;
+
         tokio::spawn(async move {
             info!("Starting mDNS discovery for microcontroller devices...");
             crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", "STARTING_MDNS_DISCOVERY");
 
-            // Browse for Arduino OTA services
-            let receiver = match mdns_daemon.browse("_arduino._tcp.local.") {
-                Ok(receiver) => {
-                    crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", "ARDUINO_BROWSE_SUCCESS");
-                    receiver
-                },
-                Err(e) => {
-                    error!("Failed to start mDNS browse: {}", e);
-                    crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("ARDUINO_BROWSE_FAILED: {}", e));
-                    return;
-                }
-            };
-
-            // Also browse for HTTP services (some microcontrollers might use this)
-            let http_receiver = mdns_daemon.browse("_http._tcp.local.").ok();
+            // Browse every configured service type and merge events into one channel
+            let (event_tx, mut event_rx) = mpsc::unbounded_channel::<(usize, ServiceEvent)>();
+            for (index, service) in services.iter().enumerate() {
+                Self::browse_service(&mdns_daemon, index, service, &event_tx);
+            }
+            let mut reresolve_timer = tokio::time::interval(reresolve_interval);
+            reresolve_timer.tick().await;
 
-            info!("mDNS discovery started, listening for microcontroller devices...");
+            info!("mDNS discovery started, browsing {} service type(s)", services.len());
             crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", "MDNS_LISTENING_FOR_DEVICES");
-            
+
             loop {
                 tokio::select! {
                     // Check for stop signal
@@ -97,55 +183,71 @@ impl MdnsDiscovery {
                         info!("Stopping mDNS discovery");
                         break;
                     }
-                    
-                    // Handle Arduino OTA service events
-                    event = async {
-                        match receiver.recv() {
-                            Ok(event) => Some(("arduino", event)),
-                            Err(_) => None,
-                        }
-                    } => {
-                        if let Some((service_type, event)) = event {
-                            crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("RECEIVED_SERVICE_EVENT: {} - {:?}", service_type, event));
-                            Self::handle_service_event(
-                                event,
-                                service_type,
-                                Arc::clone(&discovered_devices),
-                                Arc::clone(&callback)
-                            ).await;
+
+                    // Periodic re-resolution: restart browsing so live devices answer again
+                    _ = reresolve_timer.tick() => {
+                        trace!("Re-resolving {} mDNS service type(s)", services.len());
+                        for (index, service) in services.iter().enumerate() {
+                            let _ = mdns_daemon.stop_browse(&service.full_service_type());
+                            Self::browse_service(&mdns_daemon, index, service, &event_tx);
                         }
                     }
-                    
-                    // Handle HTTP service events (if available)
-                    event = async {
-                        if let Some(ref http_receiver) = http_receiver {
-                            match http_receiver.recv() {
-                                Ok(event) => Some(("http", event)),
-                                Err(_) => None,
-                            }
-                        } else {
-                            tokio::time::sleep(Duration::from_millis(100)).await;
-                            None
-                        }
-                    } => {
-                        if let Some((service_type, event)) = event {
-                            crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("RECEIVED_SERVICE_EVENT: {} - {:?}", service_type, event));
-                            Self::handle_service_event(
-                                event,
-                                service_type,
-                                Arc::clone(&discovered_devices),
-                                Arc::clone(&callback)
-                            ).await;
-                        }
+
+                    // Service events of all browsed types
+                    event = event_rx.recv() => {
+                        let Some((index, event)) = event else {
+                            break;
+                        };
+                        let service = &services[index];
+                        crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("RECEIVED_SERVICE_EVENT: {} - {:?}", service.service_type, event));
+                        Self::handle_service_event(
+                            event,
+                            service,
+                            Arc::clone(&discovered_devices),
+                            Arc::clone(&callback)
+                        ).await;
                     }
                 }
             }
         });
-        
+
         info!("mDNS discovery service started");
         Ok(())
     }
     
+    /// Browse one service type and forward its events, tagged with the service index
+    fn browse_service(
+        mdns_daemon: &ServiceDaemon,
+        index: usize,
+        service: &MdnsServiceConfig,
+        event_tx: &mpsc::UnboundedSender<(usize, ServiceEvent)>,
+    ) {
+        let service_type = service.full_service_type();
+        let receiver = match mdns_daemon.browse(&service_type) {
+            Ok(receiver) => {
+                crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("BROWSE_SUCCESS: {}", service_type));
+                receiver
+            }
+            Err(e) => {
+                error!("Failed to start mDNS browse for {}: {}", service_type, e);
+                crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("BROWSE_FAILED: {} - {}", service_type, e));
+                return;
+            }
+        };
+
+        let event_tx = event_tx.clone();
+        tokio::spawn(async move {
+            while let Ok(event) = receiver.recv_async().await {
+                if matches!(event, ServiceEvent::SearchStopped(_)) {
+                    break;
+                }
+                if event_tx.send((index, event)).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
     /// Stop mDNS discovery
     pub async fn stop_discovery(&mut self) {
         if let Some(stop_tx) = self.stop_tx.take() {
@@ -164,7 +266,7 @@ impl MdnsDiscovery {
     /// Handle mDNS service events
     async fn handle_service_event<F>(
         event: ServiceEvent,
-        service_type: &str,
+        service: &MdnsServiceConfig,
         discovered_devices: Arc<RwLock<HashMap<String, MdnsDiscoveredDevice>>>,
         callback: Arc<F>,
     ) 
@@ -198,50 +300,83 @@ impl MdnsDiscovery {
                     }
                 }
 
+                if !service.matches_txt(&txt_records) {
+                    trace!("Ignoring {} - TXT filter of {} not matched", hostname, service.service_type);
+                    return;
+                }
+
                 // Filter for microcontroller devices (check if hostname or TXT records indicate microcontroller)
-                let is_microcontroller = Self::is_microcontroller_device(&hostname, &txt_records, service_type);
+                let is_microcontroller = Self::is_microcontroller_device(&hostname, &txt_records, service.trusted);
                 crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("IS_microcontroller_CHECK: {} - result: {}", hostname, is_microcontroller));
 
-                if is_microcontroller {
-                    let device = MdnsDiscoveredDevice {
-                        hostname: hostname.clone(),
-                        ip_addresses: addresses.clone(),
-                        port,
-                        txt_records: txt_records.clone(),
-                        service_name: format!("_{}._{}.local.", service_type, "tcp"),
-                    };
-                    
-                    // Add to cache only if it's new. Log info only when a new device is inserted.
-                    let mut was_new = false;
-                    {
-                        let mut devices = discovered_devices.write().await;
-                        if !devices.contains_key(&hostname) {
+                if !is_microcontroller {
+                    trace!("Ignoring non-microcontroller device: {} (service: {})", hostname, service.service_type);
+                    return;
+                }
+
+                let service_type = service.full_service_type();
+                let record = MdnsServiceRecord {
+                    service_type: service_type.clone(),
+                    instance_name: info.get_fullname().to_string(),
+                    port,
+                    txt_records: txt_records.clone(),
+                    last_seen: chrono::Utc::now(),
+                };
+
+                // Merge into the cache: a new device or a new service of a known device
+                // is reported via callback, refreshes of known services are not
+                let changed_device = {
+                    let mut devices = discovered_devices.write().await;
+                    match devices.get_mut(&hostname) {
+                        None => {
+                            let device = MdnsDiscoveredDevice {
+                                hostname: hostname.clone(),
+                                ip_addresses: addresses.clone(),
+                                port,
+                                txt_records: txt_records.clone(),
+                                service_name: service_type.clone(),
+                                services: HashMap::from([(service_type.clone(), record)]),
+                            };
                             devices.insert(hostname.clone(), device.clone());
-                            was_new = true;
+                            info!("New microcontroller device discovered: {} at {:?}:{} ({})", hostname, addresses, port, service_type);
+                            Some(device)
+                        }
+                        Some(device) => {
+                            let is_new_service = !device.services.contains_key(&service_type);
+                            if !addresses.is_empty() {
+                                device.ip_addresses = addresses.clone();
+                            }
+                            for (key, value) in &txt_records {
+                                device.txt_records.entry(key.clone()).or_insert_with(|| value.clone());
+                            }
+                            device.services.insert(service_type.clone(), record);
+                            if is_new_service {
+                                info!("Device {} also announces {} on port {}", hostname, service_type, port);
+                                Some(device.clone())
+                            } else {
+                                None
+                            }
                         }
                     }
+                };
 
-                    if was_new {
-                        info!("New microcontroller device discovered: {} at {:?}:{}", hostname, addresses, port);
-                        crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("NEW_microcontroller_DISCOVERED: {} at {:?}:{}", hostname, addresses, port));
-                        // Call callback for new device
-                        crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("CALLING_DEVICE_CALLBACK: {}", hostname));
-                        callback(device);
-                        crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("DEVICE_CALLBACK_COMPLETED: {}", hostname));
-                    } else {
-                        // For existing devices, only trace (no noisy logs)
-                        crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("EXISTING_microcontroller_UPDATED: {}", hostname));
-                        trace!("Updated/refresh microcontroller device seen: {}", hostname);
-                    }
+                if let Some(device) = changed_device {
+                    crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("CALLING_DEVICE_CALLBACK: {} ({})", hostname, service_type));
+                    callback(device);
                 } else {
-                    trace!("Ignoring non-microcontroller device: {} (service: {})", hostname, service_type);
+                    // For existing services, only trace (no noisy logs)
+                    crate::debug_logger::DebugLogger::log_event("MDNS_DISCOVERY", &format!("EXISTING_microcontroller_UPDATED: {}", hostname));
+                    trace!("Updated/refresh microcontroller device seen: {}", hostname);
                 }
             }
             ServiceEvent::ServiceRemoved(typ, name) => {
                 trace!("Service removed: {} {}", typ, name);
-                // Optionally remove from cache based on name
+                // Drop the service record; forget the device once it announces nothing
                 let mut devices = discovered_devices.write().await;
-                devices.retain(|_, device| device.service_name != format!("{}.{}", name, typ));
+                for device in devices.values_mut() {
+                    device.services.retain(|_, record| record.instance_name != name);
+                }
+                devices.retain(|_, device| !device.services.is_empty());
             }
             _ => {
                 // Handle other events if needed
@@ -251,7 +386,7 @@ impl MdnsDiscovery {
     }
     
     /// Determine if a discovered device is an microcontroller
-    fn is_microcontroller_device(hostname: &str, txt_records: &HashMap<String, String>, service_type: &str) -> bool {
+    fn is_microcontroller_device(hostname: &str, txt_records: &HashMap<String, String>, trusted: bool) -> bool {
         // Filter out our own microcontroller Manager Server
         let hostname_lower = hostname.to_lowercase();
         if hostname_lower.contains("esp-server") {
@@ -276,8 +411,8 @@ impl MdnsDiscovery {
         let hostname_matches = hostname_indicators.iter()
             .any(|indicator| hostname_lower.contains(indicator));
 
-        // For Arduino OTA service, assume it's likely an microcontroller
-        if service_type == "arduino" {
+        // Trusted services (e.g. Arduino OTA) are assumed to be microcontrollers
+        if trusted {
             return true;
         }
 
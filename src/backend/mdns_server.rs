@@ -1,14 +1,58 @@
 use mdns_sd::{ServiceDaemon, ServiceInfo};
+use serde::Serialize;
 use std::collections::HashMap;
 use std::net::IpAddr;
 use tracing::{info, warn};
 use tokio::sync::mpsc;
 
+/// Service type ESP32 firmware browses to locate the manager
+pub const MANAGER_SERVICE_TYPE: &str = "_esp32-manager._tcp.local.";
+
+/// What the manager announces about itself
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MdnsAdvertisementConfig {
+    /// Advertising switch (discovery.mdns_advertise / MDNS_ADVERTISE)
+    pub enabled: bool,
+    pub instance_name: String,
+    pub hostname: String,
+    /// Path of the WebSocket endpoint announced in the TXT records
+    pub websocket_path: String,
+    /// HTTP port of the API (server.http_port); start_advertising updates it to the advertised port
+    pub api_port: u16,
+}
+
+impl Default for MdnsAdvertisementConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            instance_name: "device-manager".to_string(),
+            hostname: "device-manager.local.".to_string(),
+            websocket_path: "/channel".to_string(),
+            api_port: 3000,
+        }
+    }
+}
+
+impl MdnsAdvertisementConfig {
+    /// TXT records of the _esp32-manager._tcp service
+    pub fn manager_txt_records(&self, api_port: u16) -> HashMap<String, String> {
+        HashMap::from([
+            ("version".to_string(), env!("CARGO_PKG_VERSION").to_string()),
+            ("api_port".to_string(), api_port.to_string()),
+            ("api_path".to_string(), "/api".to_string()),
+            ("ws_path".to_string(), self.websocket_path.clone()),
+            ("protocol".to_string(), "http".to_string()),
+        ])
+    }
+}
+
 /// mDNS server for advertising the Device Manager Server
 pub struct MdnsServer {
     daemon: Option<ServiceDaemon>,
     service_infos: Vec<ServiceInfo>,
     stop_tx: Option<mpsc::UnboundedSender<()>>,
+    config: MdnsAdvertisementConfig,
     is_running: bool,
 }
 
@@ -19,16 +63,30 @@ impl MdnsServer {
             daemon: None,
             service_infos: Vec::new(),
             stop_tx: None,
+            config: MdnsAdvertisementConfig::default(),
             is_running: false,
         })
     }
 
+    /// Create mDNS server with a specific advertisement configuration
+    pub fn with_config(config: MdnsAdvertisementConfig) -> Result<Self, String> {
+        let mut server = Self::new()?;
+        server.config = config;
+        Ok(server)
+    }
+
     /// Start advertising the server via mDNS
     pub async fn start_advertising(&mut self, port: u16) -> Result<(), String> {
         if self.is_running {
             return Err("mDNS server already running".to_string());
         }
 
+        self.config.api_port = port;
+        if !self.config.enabled {
+            info!("mDNS advertising disabled by configuration");
+            return Ok(());
+        }
+
         // Create mDNS daemon
         let daemon = ServiceDaemon::new()
             .map_err(|e| format!("Failed to create mDNS daemon: {}", e))?;
@@ -63,20 +121,32 @@ impl MdnsServer {
         // Register service with all local IP addresses
         let service_info = ServiceInfo::new(
             "_http._tcp.local.",
-            "device-manager",
-            "device-manager.local.",
+            &self.config.instance_name,
+            &self.config.hostname,
             ip_list.as_str(),
             port,
             properties,
         ).map_err(|e| format!("Failed to create service info: {}", e))?;
 
-        // Register the service
-        daemon.register(service_info.clone())
-            .map_err(|e| format!("Failed to register mDNS service: {}", e))?;
+        // Dedicated service type so firmware can find the manager without hard-coded IPs
+        let manager_service_info = ServiceInfo::new(
+            MANAGER_SERVICE_TYPE,
+            &self.config.instance_name,
+            &self.config.hostname,
+            ip_list.as_str(),
+            port,
+            self.config.manager_txt_records(port),
+        ).map_err(|e| format!("Failed to create service info: {}", e))?;
 
+        // Register the services
+        for info in [&service_info, &manager_service_info] {
+            daemon.register(info.clone())
+                .map_err(|e| format!("Failed to register mDNS service: {}", e))?;
+        }
+        info!("mDNS advertising {} and {} on port {}", service_info.get_fullname(), manager_service_info.get_fullname(), port);
 
         self.daemon = Some(daemon);
-        self.service_infos = vec![service_info];
+        self.service_infos = vec![service_info, manager_service_info];
         self.is_running = true;
 
         // Start keep-alive task
@@ -126,14 +196,6 @@ impl MdnsServer {
     /// Get ALL local IP addresses on all interfaces (for mDNS to respond on all networks)
     fn get_local_ip_addresses(&self) -> Result<Vec<IpAddr>, String> {
         use if_addrs::get_if_addrs;
-        use std::io::Write;
-
-        // Open debug log file
-        let mut debug_log = std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open("mdns_debug.log")
-            .ok();
 
         let mut addresses = Vec::new();
 
@@ -145,11 +207,7 @@ impl MdnsServer {
 
                     // Skip loopback addresses (127.0.0.1, ::1)
                     if ip.is_loopback() {
-                        let msg = format!("Skipping loopback address: {} on {}\n", ip, iface.name);
-                        info!("{}", msg.trim());
-                        if let Some(ref mut log) = debug_log {
-                            let _ = log.write_all(msg.as_bytes());
-                        }
+                        info!("Skipping loopback address: {} on {}", ip, iface.name);
                         continue;
                     }
 
@@ -160,11 +218,7 @@ impl MdnsServer {
                         || iface_name_lower.contains("tun")
                         || iface_name_lower.contains("tap")
                         || iface_name_lower.contains("wireguard") {
-                        let msg = format!("Skipping VPN interface: {} ({})\n", iface.name, ip);
-                        info!("{}", msg.trim());
-                        if let Some(ref mut log) = debug_log {
-                            let _ = log.write_all(msg.as_bytes());
-                        }
+                        info!("Skipping VPN interface: {} ({})", iface.name, ip);
                         continue;
                     }
 
@@ -173,11 +227,7 @@ impl MdnsServer {
                         || iface_name_lower.contains("vmware")
                         || iface_name_lower.contains("vethernet")
                         || iface_name_lower.contains("docker") {
-                        let msg = format!("Skipping virtual interface: {} ({})\n", iface.name, ip);
-                        info!("{}", msg.trim());
-                        if let Some(ref mut log) = debug_log {
-                            let _ = log.write_all(msg.as_bytes());
-                        }
+                        info!("Skipping virtual interface: {} ({})", iface.name, ip);
                         continue;
                     }
 
@@ -186,11 +236,7 @@ impl MdnsServer {
                         let octets = ipv4.octets();
                         if (octets[0] == 192 && octets[1] == 168 && (octets[2] == 56 || octets[2] == 99))
                             || (octets[0] == 10 && octets[1] == 5) {  // NordLynx uses 10.5.x.x
-                            let msg = format!("Skipping VM/VPN network: {} ({})\n", iface.name, ip);
-                            info!("{}", msg.trim());
-                            if let Some(ref mut log) = debug_log {
-                                let _ = log.write_all(msg.as_bytes());
-                            }
+                            info!("Skipping VM/VPN network: {} ({})", iface.name, ip);
                             continue;
                         }
                     }
@@ -198,23 +244,15 @@ impl MdnsServer {
                     // Skip link-local IPv6 addresses (fe80::) - these cause mDNS errors
                     if let IpAddr::V6(ipv6) = ip {
                         if ipv6.segments()[0] == 0xfe80 {
-                            let msg = format!("Skipping link-local IPv6 address: {} on {}\n", ip, iface.name);
-                            info!("{}", msg.trim());
-                            if let Some(ref mut log) = debug_log {
-                                let _ = log.write_all(msg.as_bytes());
-                            }
+                            info!("Skipping link-local IPv6 address: {} on {}", ip, iface.name);
                             continue;
                         }
                     }
 
-                    let msg = format!("Registering mDNS on interface: {} with IP {} ({})\n",
+                    info!("Registering mDNS on interface: {} with IP {} ({})",
                           iface.name,
                           ip,
                           if ip.is_ipv4() { "IPv4" } else { "IPv6" });
-                    info!("{}", msg.trim());
-                    if let Some(ref mut log) = debug_log {
-                        let _ = log.write_all(msg.as_bytes());
-                    }
                     addresses.push(ip);
                 }
             }
@@ -227,12 +265,7 @@ impl MdnsServer {
             return Err("No usable IP addresses found on any interface".to_string());
         }
 
-        let msg = format!("Total mDNS IP addresses registered: {}\n", addresses.len());
-        info!("{}", msg.trim());
-        if let Some(ref mut log) = debug_log {
-            let _ = log.write_all(msg.as_bytes());
-            let _ = log.flush();
-        }
+        info!("Total mDNS IP addresses registered: {}", addresses.len());
 
         Ok(addresses)
     }
@@ -241,6 +274,21 @@ impl MdnsServer {
     pub fn is_running(&self) -> bool {
         self.is_running
     }
+
+    /// Advertisement configuration
+    pub fn config(&self) -> &MdnsAdvertisementConfig {
+        &self.config
+    }
+
+    /// Toggle discovery.mdns_advertise (start_advertising checks it)
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.config.enabled = enabled;
+    }
+
+    /// Full names of the currently registered services
+    pub fn advertised_services(&self) -> Vec<String> {
+        self.service_infos.iter().map(|info| info.get_fullname().to_string()).collect()
+    }
 }
 
 impl Drop for MdnsServer {
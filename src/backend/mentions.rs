@@ -0,0 +1,116 @@
+// Handles and mentions - unique @handles for users and `@handle` mentions in the device channel
+//
+// A handle is optional and set at registration or via PUT /api/profile/handle: 3-30 characters,
+// lowercase letters, digits and `_`, starting with a letter; stored lowercase and unique. A client
+// sends a DeviceMention event on a device channel (e.g. "@bob lamp is calibrated, your turn"):
+// the server fills in the sender and the handles that belong to users, broadcasts the event to the
+// channel, keeps it in the chat history (device_chat.rs) and sends a `mention` message to every
+// open connection of a mentioned user, also when their tab of the device is closed - used for
+// handing a device over between shifts.
+
+use std::collections::HashSet;
+
+use crate::database::DatabaseManager;
+use crate::device_chat;
+use crate::events::DeviceEvent;
+
+pub const HANDLE_MIN_LEN: usize = 3;
+pub const HANDLE_MAX_LEN: usize = 30;
+
+/// Handles that would read like a group or a server account
+const RESERVED_HANDLES: [&str; 6] = ["admin", "all", "everyone", "guest", "here", "system"];
+
+/// Check a handle and bring it to its stored form (a leading `@` is dropped, case is ignored)
+pub fn normalize_handle(handle: &str) -> Result<String, String> {
+    let handle = handle.trim().trim_start_matches('@').to_lowercase();
+    if handle.len() < HANDLE_MIN_LEN || handle.len() > HANDLE_MAX_LEN {
+        return Err(format!("Handle must be between {} and {} characters", HANDLE_MIN_LEN, HANDLE_MAX_LEN));
+    }
+    if !handle.starts_with(|c: char| c.is_ascii_lowercase()) {
+        return Err("Handle must start with a letter".to_string());
+    }
+    if !handle.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_') {
+        return Err("Handle may only contain letters, digits and _".to_string());
+    }
+    if RESERVED_HANDLES.contains(&handle.as_str()) {
+        return Err(format!("Handle {} is reserved", handle));
+    }
+    Ok(handle)
+}
+
+/// Handles mentioned in a text, in order of appearance and without duplicates.
+/// An `@` counts only at the start or after a character that is not part of a word,
+/// so email addresses are not mentions.
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    let mut mentions = Vec::new();
+    let mut previous: Option<char> = None;
+    for (index, c) in text.char_indices() {
+        let word_before = previous.is_some_and(|p| p.is_alphanumeric() || p == '_' || p == '.');
+        previous = Some(c);
+        if c != '@' || word_before {
+            continue;
+        }
+        let rest = &text[index + 1..];
+        let end = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+        if let Ok(handle) = normalize_handle(&rest[..end]) {
+            if !mentions.contains(&handle) {
+                mentions.push(handle);
+            }
+        }
+    }
+    mentions
+}
+
+/// Build the DeviceMention event of a sender and the IDs of the users to notify.
+/// Handles without a user are dropped, as is the sender mentioning themselves.
+pub async fn build_mention(
+    db: &DatabaseManager,
+    device_id: &str,
+    sender_id: &str,
+    text: &str,
+) -> Result<(DeviceEvent, HashSet<String>), String> {
+    let text = device_chat::validate_text(text)?;
+
+    let mut mentions = Vec::new();
+    let mut recipients = HashSet::new();
+    for handle in extract_mentions(text) {
+        let user_id = db.get_user_id_by_handle(&handle).await
+            .map_err(|e| format!("Database error resolving @{}: {}", handle, e))?;
+        if let Some(user_id) = user_id {
+            if user_id != sender_id {
+                recipients.insert(user_id);
+            }
+            mentions.push(handle);
+        }
+    }
+
+    let from_display_name = device_chat::sender_display_name(db, sender_id).await?;
+    let event = DeviceEvent::DeviceMention {
+        device_id: device_id.to_string(),
+        text: text.to_string(),
+        from_user_id: sender_id.to_string(),
+        from_display_name,
+        mentions,
+    };
+    Ok((event, recipients))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handles_and_mentions() {
+        assert_eq!(normalize_handle(" @Night_Shift ").unwrap(), "night_shift");
+        assert!(normalize_handle("ab").is_err());
+        assert!(normalize_handle("1abc").is_err());
+        assert!(normalize_handle("bob-smith").is_err());
+        assert!(normalize_handle("Everyone").is_err());
+
+        assert_eq!(
+            extract_mentions("@bob lamp is calibrated, @Carol and @bob: your turn (mail alice@example.com)"),
+            vec!["bob".to_string(), "carol".to_string()],
+        );
+        assert!(extract_mentions("@x @everyone nothing here").is_empty());
+    }
+}
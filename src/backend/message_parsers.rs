@@ -0,0 +1,322 @@
+// Message parsers - turn a received device message into structured device events
+//
+// Every transport hands received messages to DeviceManager::handle_message_unified, which runs
+// them through the MessageParsers registry. A registry holds an ordered chain of parsers; each
+// parser looks at the whole message and adds the events it recognizes:
+//
+//   json            JSON envelope: startOptions, changeableVariables, deviceName/firmwareVersion,
+//                   single variables with min/max
+//   numeric         {"name": 123} / {"name": 25.5} anywhere in the message (also non-JSON text)
+//   string_numeric  key/value form with quoted numbers: {"name": "25.5"}
+//   custom:<regex>  a regex with the named groups `name` and `value`, e.g. for plain text lines
+//
+// The default chain is json, numeric, string_numeric. [message_parsers] can give single devices
+// their own chain, e.g. `devices = ["AA-BB-CC-DD-EE-FF=json,custom:(?P<name>\w+)=(?P<value>-?[\d.]+)"]`.
+// A custom regex takes the rest of the entry, so it comes last.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, RwLock};
+
+use serde_json::Value;
+
+use crate::events::DeviceEvent;
+
+/// Numeric variable update: {"name": 123} or {"name": 25.5}
+static NUMERIC_VARIABLE_RE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r#"\{\"([^\"]+)\"\s*:\s*(\d+\.?\d*)\}"#).unwrap());
+
+/// String-encoded numeric variable update: {"name": "123"} or {"name": "25.5"}
+static STRING_NUMERIC_VARIABLE_RE: LazyLock<regex::Regex> =
+    LazyLock::new(|| regex::Regex::new(r#"\{\"([^\"]+)\"\s*:\s*\"(\d+\.?\d*)\"\}"#).unwrap());
+
+/// An event found in a message
+#[derive(Debug, Clone)]
+pub struct ParsedEvent {
+    pub event: DeviceEvent,
+    /// Variable updates name their variable; they go through the variable coalescer
+    pub variable: Option<String>,
+}
+
+impl ParsedEvent {
+    fn variable(device_id: &str, name: &str, value: &str) -> Self {
+        Self {
+            event: DeviceEvent::device_variable_update(device_id.to_string(), name.to_string(), value.to_string()),
+            variable: Some(name.to_string()),
+        }
+    }
+
+    fn structured(event: DeviceEvent) -> Self {
+        Self { event, variable: None }
+    }
+}
+
+/// One step of a parser chain
+pub trait MessageParser: Send + Sync {
+    fn name(&self) -> &str;
+    fn parse(&self, device_id: &str, message: &str, events: &mut Vec<ParsedEvent>);
+}
+
+/// JSON envelope with the structured fields of the device protocol
+pub struct JsonEnvelopeParser;
+
+impl MessageParser for JsonEnvelopeParser {
+    fn name(&self) -> &str {
+        "json"
+    }
+
+    fn parse(&self, device_id: &str, message: &str, events: &mut Vec<ParsedEvent>) {
+        let Ok(value) = serde_json::from_str::<Value>(message) else { return };
+
+        if let Some(options) = value.get("startOptions").and_then(|v| v.as_array()) {
+            let start_options: Vec<String> = options.iter().filter_map(|o| o.as_str().map(str::to_string)).collect();
+            if !start_options.is_empty() {
+                events.push(ParsedEvent::structured(DeviceEvent::device_start_options(device_id.to_string(), start_options)));
+            }
+        }
+
+        if let Some(vars) = value.get("changeableVariables").and_then(|v| v.as_array()) {
+            let variables: Vec<Value> = vars.iter().filter_map(|var| {
+                let name = var.get("name")?.as_str()?;
+                let value = var.get("value")?.as_u64()?;
+                let mut var_json = serde_json::json!({ "name": name, "value": value });
+                if let Some(min) = var.get("min").and_then(|v| v.as_u64()) {
+                    var_json["min"] = serde_json::json!(min);
+                }
+                if let Some(max) = var.get("max").and_then(|v| v.as_u64()) {
+                    var_json["max"] = serde_json::json!(max);
+                }
+                Some(var_json)
+            }).collect();
+            if !variables.is_empty() {
+                events.push(ParsedEvent::structured(DeviceEvent::device_changeable_variables(device_id.to_string(), variables)));
+            }
+        }
+
+        if let Some(device_name) = value.get("deviceName").and_then(|v| v.as_str()) {
+            let firmware_version = value.get("firmwareVersion").and_then(|v| v.as_str()).unwrap_or("unknown").to_string();
+            let uptime = value.get("uptime").and_then(|v| v.as_u64()).unwrap_or(0) as u32;
+            events.push(ParsedEvent::structured(DeviceEvent::device_device_info(
+                device_id.to_string(),
+                Some(device_name.to_string()),
+                Some(firmware_version),
+                Some(uptime as u64),
+            )));
+        }
+
+        // Single variable updates with range: {"brightness": 50, "min": 0, "max": 100}
+        let Some(obj) = value.as_object() else { return };
+        if !(obj.contains_key("min") || obj.contains_key("max")) {
+            return;
+        }
+        let skip_fields = ["device_id", "startOptions", "changeableVariables", "deviceName", "firmwareVersion", "uptime", "status", "min", "max"];
+        let min = obj.get("min").and_then(|v| v.as_u64());
+        let max = obj.get("max").and_then(|v| v.as_u64());
+        for (key, val) in obj {
+            if skip_fields.contains(&key.as_str()) {
+                continue;
+            }
+            let value = if let Some(s) = val.as_str() {
+                s.to_string()
+            } else if let Some(n) = val.as_u64() {
+                n.to_string()
+            } else if let Some(n) = val.as_i64() {
+                n.to_string()
+            } else if let Some(f) = val.as_f64() {
+                f.to_string()
+            } else {
+                continue; // Skip non-primitive values
+            };
+            events.push(ParsedEvent {
+                event: DeviceEvent::device_variable_update_with_range(device_id.to_string(), key.clone(), value, min, max),
+                variable: Some(key.clone()),
+            });
+        }
+    }
+}
+
+/// Variables matched by a regex with the groups name and value; `skip` names are left to other parsers
+pub struct RegexVariableParser {
+    name: String,
+    regex: regex::Regex,
+    skip: &'static [&'static str],
+}
+
+impl RegexVariableParser {
+    /// {"name": 123} anywhere in the message
+    pub fn numeric() -> Self {
+        Self { name: "numeric".to_string(), regex: NUMERIC_VARIABLE_RE.clone(), skip: &["uptime", "value"] }
+    }
+
+    /// {"name": "123"} anywhere in the message
+    pub fn string_numeric() -> Self {
+        Self {
+            name: "string_numeric".to_string(),
+            regex: STRING_NUMERIC_VARIABLE_RE.clone(),
+            skip: &["deviceName", "firmwareVersion", "name", "value"],
+        }
+    }
+
+    /// A configured regex; it needs the named groups `name` and `value`
+    pub fn custom(pattern: &str) -> Result<Self, String> {
+        let regex = regex::Regex::new(pattern).map_err(|e| format!("invalid custom parser regex: {}", e))?;
+        let groups: Vec<&str> = regex.capture_names().flatten().collect();
+        if !(groups.contains(&"name") && groups.contains(&"value")) {
+            return Err(format!("custom parser regex needs the groups (?P<name>...) and (?P<value>...): {}", pattern));
+        }
+        Ok(Self { name: format!("custom:{}", pattern), regex, skip: &[] })
+    }
+
+    fn group<'m>(&self, captures: &regex::Captures<'m>, index: usize, name: &str) -> Option<&'m str> {
+        captures.name(name).or_else(|| captures.get(index)).map(|m| m.as_str().trim())
+    }
+}
+
+impl MessageParser for RegexVariableParser {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn parse(&self, device_id: &str, message: &str, events: &mut Vec<ParsedEvent>) {
+        for captures in self.regex.captures_iter(message) {
+            let (Some(name), Some(value)) = (self.group(&captures, 1, "name"), self.group(&captures, 2, "value")) else {
+                continue;
+            };
+            if !self.skip.contains(&name) {
+                events.push(ParsedEvent::variable(device_id, name, value));
+            }
+        }
+    }
+}
+
+/// Parser chain from a spec like "json,numeric,custom:<regex>"
+pub fn parse_chain(spec: &str) -> Result<Vec<Arc<dyn MessageParser>>, String> {
+    let mut chain: Vec<Arc<dyn MessageParser>> = Vec::new();
+    let mut rest = spec.trim();
+    while !rest.is_empty() {
+        if let Some(pattern) = rest.strip_prefix("custom:") {
+            chain.push(Arc::new(RegexVariableParser::custom(pattern)?));
+            break;
+        }
+        let (name, tail) = rest.split_once(',').unwrap_or((rest, ""));
+        chain.push(match name.trim() {
+            "json" => Arc::new(JsonEnvelopeParser),
+            "numeric" => Arc::new(RegexVariableParser::numeric()),
+            "string_numeric" => Arc::new(RegexVariableParser::string_numeric()),
+            other => return Err(format!("unknown parser '{}' (json, numeric, string_numeric, custom:<regex>)", other)),
+        });
+        rest = tail.trim_start();
+    }
+    if chain.is_empty() {
+        return Err("empty parser chain".to_string());
+    }
+    Ok(chain)
+}
+
+/// One `devices` entry of [message_parsers]: "<device ID>=<parser>[,<parser>...]"
+pub fn parse_assignment(entry: &str) -> Result<(String, Vec<Arc<dyn MessageParser>>), String> {
+    let (device_id, spec) = entry.split_once('=')
+        .ok_or_else(|| format!("expected \"<device ID>=<parser>,...\": {}", entry))?;
+    let device_id = device_id.trim();
+    if device_id.is_empty() {
+        return Err(format!("missing device ID: {}", entry));
+    }
+    Ok((device_id.to_string(), parse_chain(spec)?))
+}
+
+/// Default chain and per-device chains, shared with the transports
+pub struct MessageParsers {
+    default_chain: Vec<Arc<dyn MessageParser>>,
+    per_device: RwLock<HashMap<String, Vec<Arc<dyn MessageParser>>>>,
+}
+
+impl Default for MessageParsers {
+    fn default() -> Self {
+        Self {
+            default_chain: vec![
+                Arc::new(JsonEnvelopeParser),
+                Arc::new(RegexVariableParser::numeric()),
+                Arc::new(RegexVariableParser::string_numeric()),
+            ],
+            per_device: RwLock::new(HashMap::new()),
+        }
+    }
+}
+
+impl std::fmt::Debug for MessageParsers {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let devices = self.per_device.read().map(|d| d.len()).unwrap_or_default();
+        f.debug_struct("MessageParsers").field("per_device", &devices).finish()
+    }
+}
+
+impl MessageParsers {
+    /// Replace the per-device chains with those of the [message_parsers] entries
+    pub fn configure(&self, entries: &[String]) -> Result<usize, String> {
+        let chains = entries.iter().map(|entry| parse_assignment(entry)).collect::<Result<HashMap<_, _>, _>>()?;
+        let count = chains.len();
+        *self.per_device.write().unwrap_or_else(|e| e.into_inner()) = chains;
+        Ok(count)
+    }
+
+    /// Names of the parsers a device's messages go through
+    pub fn chain_names(&self, device_id: &str) -> Vec<String> {
+        let per_device = self.per_device.read().unwrap_or_else(|e| e.into_inner());
+        let chain = per_device.get(device_id).unwrap_or(&self.default_chain);
+        chain.iter().map(|parser| parser.name().to_string()).collect()
+    }
+
+    /// Events of a message, in parser order
+    pub fn parse(&self, device_id: &str, message: &str) -> Vec<ParsedEvent> {
+        let chain = {
+            let per_device = self.per_device.read().unwrap_or_else(|e| e.into_inner());
+            per_device.get(device_id).unwrap_or(&self.default_chain).clone()
+        };
+        let mut events = Vec::new();
+        for parser in &chain {
+            parser.parse(device_id, message, &mut events);
+        }
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(events: &[ParsedEvent]) -> Vec<(String, String)> {
+        events.iter().filter_map(|parsed| match &parsed.event {
+            DeviceEvent::DeviceVariableUpdate { variable_name, variable_value, .. } => Some((variable_name.clone(), variable_value.clone())),
+            _ => None,
+        }).collect()
+    }
+
+    #[test]
+    fn test_parser_chains() {
+        let parsers = MessageParsers::default();
+        let id = "AA-01";
+
+        // JSON envelope: structured events, ranged variables
+        let events = parsers.parse(id, r#"{"deviceName": "Lamp", "firmwareVersion": "1.2", "startOptions": ["run", 5], "changeableVariables": [{"name": "speed", "value": 3, "max": 9}, {"name": "bad"}]}"#);
+        assert!(matches!(&events[0].event, DeviceEvent::DeviceStartOptions { options, .. } if options == &["run".to_string()]));
+        assert!(matches!(&events[1].event, DeviceEvent::DeviceChangeableVariables { variables, .. } if variables.len() == 1 && variables[0]["max"] == 9));
+        assert!(matches!(&events[2].event, DeviceEvent::DeviceDeviceInfo { device_name: Some(name), .. } if name == "Lamp"));
+        let ranged = parsers.parse(id, r#"{"brightness": 50, "min": 0, "max": 100}"#);
+        assert!(matches!(&ranged[0].event, DeviceEvent::DeviceVariableUpdate { min: Some(0), max: Some(100), .. }));
+
+        // Regex parsers: single-pair objects, quoted numbers, skipped names
+        let events = parsers.parse(id, r#"[{"temp": 21.5}, {"hum": "40"}, {"uptime": 12}, {"name": "7"}, {"debug": "x"}]"#);
+        assert_eq!(variables(&events), vec![("temp".into(), "21.5".into()), ("hum".into(), "40".into())]);
+        assert!(events.iter().all(|parsed| parsed.variable.is_some()));
+
+        // Per-device chain with a custom regex for text lines
+        parsers.configure(&[r"AA-02=json, custom:(?P<name>[a-z]+)=(?P<value>-?[\d.]+)".to_string()]).unwrap();
+        assert_eq!(parsers.chain_names("AA-02")[0], "json");
+        assert_eq!(variables(&parsers.parse("AA-02", "temp=-3.5 rpm=1200")), vec![("temp".into(), "-3.5".into()), ("rpm".into(), "1200".into())]);
+        assert!(parsers.parse("AA-02", r#"{"temp": 21.5}"#).is_empty());
+        assert_eq!(parsers.chain_names(id), vec!["json", "numeric", "string_numeric"]);
+
+        assert!(parse_chain("json,xml").is_err());
+        assert!(parse_chain(r"custom:(\w+)=(\d+)").is_err());
+        assert!(parse_assignment("json").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+// Organizations - workspaces that separate the hardware of several courses on one server
+//
+// Devices and device groups can belong to an organization (org_id). Such devices are only
+// visible to and controllable by members of the organization: the device permission checks
+// refuse everyone else, guests included, and the device list of an organization is selected per
+// request with the X-Org header (organization ID or slug). Devices without an organization keep
+// the previous per-user behavior. Owners and admins manage members and move devices in and out.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Request header selecting the organization context (ID or slug)
+pub const ORG_HEADER: &str = "x-org";
+
+/// Role of a member within an organization
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OrgRole {
+    Member,
+    Admin,
+    Owner,
+}
+
+impl OrgRole {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "member" => Some(OrgRole::Member),
+            "admin" => Some(OrgRole::Admin),
+            "owner" => Some(OrgRole::Owner),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OrgRole::Member => "member",
+            OrgRole::Admin => "admin",
+            OrgRole::Owner => "owner",
+        }
+    }
+
+    /// Add and remove members, move devices in and out
+    pub fn can_manage(&self) -> bool {
+        *self >= OrgRole::Admin
+    }
+
+    /// Device permission a member gets on the organization's devices (see device_permissions)
+    pub fn device_permission(&self) -> &'static str {
+        if self.can_manage() { "M" } else { "W" }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Organization {
+    pub id: String,
+    pub name: String,
+    /// URL-friendly unique name, usable in the X-Org header
+    pub slug: String,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Member of an organization (GET /api/orgs/:id)
+#[derive(Debug, Clone, Serialize)]
+pub struct OrgMember {
+    pub user_id: String,
+    pub email: String,
+    pub display_name: String,
+    pub role: OrgRole,
+    pub joined_at: DateTime<Utc>,
+}
+
+/// Slug from an organization name: lowercase letters, digits and single dashes
+pub fn slugify(name: &str) -> Option<String> {
+    let mut slug = String::new();
+    for c in name.trim().chars() {
+        if c.is_ascii_alphanumeric() {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+    let slug = slug.trim_end_matches('-').to_string();
+    (!slug.is_empty() && slug.len() <= 50).then_some(slug)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_roles_and_slugs() {
+        assert_eq!(OrgRole::parse(" Admin "), Some(OrgRole::Admin));
+        assert_eq!(OrgRole::parse("guest"), None);
+        assert!(OrgRole::Owner.can_manage() && OrgRole::Admin.can_manage() && !OrgRole::Member.can_manage());
+        assert_eq!(OrgRole::Member.device_permission(), "W");
+        assert_eq!(OrgRole::Owner.device_permission(), "M");
+
+        assert_eq!(slugify("Robotics Lab – WS 2026/27").as_deref(), Some("robotics-lab-ws-2026-27"));
+        assert_eq!(slugify("  --  "), None);
+    }
+}
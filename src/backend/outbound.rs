@@ -0,0 +1,173 @@
+// Outbound network access - offline mode and the HTTP proxy for calls leaving the local network
+//
+// The server talks to devices on the local network; only a few features reach further: webhooks,
+// email (SMTP) and firmware downloads (URL, GitHub releases). With [outbound] offline = true all of
+// them are switched off for isolated deployments: webhook deliveries are logged as failed with the
+// reason, email behaves as not configured and firmware downloads answer 503 OFFLINE_MODE.
+// Otherwise HTTP calls use [outbound] proxy, or HTTP_PROXY/HTTPS_PROXY/NO_PROXY from the
+// environment when no proxy is configured. SMTP is never proxied.
+// GET /api/admin/outbound and /readyz report the state.
+
+use std::sync::OnceLock;
+
+use serde::Serialize;
+
+use crate::config::OutboundConfig;
+
+// Set once at startup, online without a proxy until then
+static OUTBOUND_CONFIG: OnceLock<OutboundConfig> = OnceLock::new();
+
+/// Environment variables reqwest reads when no proxy is configured, in order of precedence
+const PROXY_ENV: [&str; 6] = ["HTTPS_PROXY", "https_proxy", "HTTP_PROXY", "http_proxy", "ALL_PROXY", "all_proxy"];
+
+/// Features that make calls outside the local network
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutboundFeature {
+    Webhooks,
+    Email,
+    FirmwareDownload,
+}
+
+impl OutboundFeature {
+    pub const ALL: [OutboundFeature; 3] = [Self::Webhooks, Self::Email, Self::FirmwareDownload];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Webhooks => "webhooks",
+            Self::Email => "email",
+            Self::FirmwareDownload => "firmware_download",
+        }
+    }
+}
+
+pub fn configure(config: OutboundConfig) {
+    if OUTBOUND_CONFIG.set(config).is_err() {
+        tracing::warn!("Outbound settings were already configured, keeping the first ones");
+    }
+}
+
+pub fn config() -> &'static OutboundConfig {
+    OUTBOUND_CONFIG.get_or_init(OutboundConfig::default)
+}
+
+pub fn is_offline() -> bool {
+    config().offline
+}
+
+/// Refuse a call of `feature` in offline mode; the error names the feature and the setting
+pub fn check(feature: OutboundFeature) -> Result<(), String> {
+    offline_check(config(), feature)
+}
+
+fn offline_check(config: &OutboundConfig, feature: OutboundFeature) -> Result<(), String> {
+    if config.offline {
+        return Err(format!("Offline mode: {} disabled ([outbound] offline = true)", feature.as_str()));
+    }
+    Ok(())
+}
+
+/// Apply the configured proxy to an HTTP client; without one reqwest uses the environment proxies
+pub fn http_client(builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+    let proxy = &config().proxy;
+    if proxy.is_empty() {
+        return builder;
+    }
+    match reqwest::Proxy::all(proxy) {
+        Ok(proxy) => builder.proxy(proxy),
+        Err(e) => {
+            tracing::warn!("Ignoring invalid outbound proxy {}: {}", config().proxy, e);
+            builder
+        }
+    }
+}
+
+/// Proxy HTTP calls go through and where it comes from
+fn effective_proxy(config: &OutboundConfig, env: impl Fn(&str) -> Option<String>) -> Option<(String, String)> {
+    if !config.proxy.is_empty() {
+        return Some((redact(&config.proxy), "config".to_string()));
+    }
+    PROXY_ENV.iter().find_map(|name| {
+        env(name).filter(|value| !value.trim().is_empty()).map(|value| (redact(&value), name.to_string()))
+    })
+}
+
+/// Proxy URL without credentials
+fn redact(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(mut parsed) if !parsed.username().is_empty() || parsed.password().is_some() => {
+            let _ = parsed.set_username("***");
+            let _ = parsed.set_password(None);
+            parsed.to_string()
+        }
+        _ => url.to_string(),
+    }
+}
+
+/// State of one outbound feature
+#[derive(Debug, Clone, Serialize)]
+pub struct FeatureStatus {
+    pub name: &'static str,
+    /// "available", "blocked" (offline mode) or "disabled" (not configured)
+    pub state: &'static str,
+    pub detail: String,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OutboundStatus {
+    pub offline: bool,
+    pub proxy: Option<String>,
+    pub proxy_source: Option<String>,
+    pub features: Vec<FeatureStatus>,
+}
+
+/// Report for GET /api/admin/outbound; `email_configured` is [email] enabled
+pub fn status(email_configured: bool) -> OutboundStatus {
+    build_status(config(), email_configured, |name| std::env::var(name).ok())
+}
+
+fn build_status(config: &OutboundConfig, email_configured: bool, env: impl Fn(&str) -> Option<String>) -> OutboundStatus {
+    let proxy = effective_proxy(config, env);
+    let via = match &proxy {
+        Some((url, _)) => format!("via proxy {}", url),
+        None => "direct".to_string(),
+    };
+    let features = OutboundFeature::ALL.into_iter().map(|feature| {
+        let (state, detail) = if let Err(e) = offline_check(config, feature) {
+            ("blocked", e)
+        } else if feature == OutboundFeature::Email && !email_configured {
+            ("disabled", "[email] enabled = false".to_string())
+        } else if feature == OutboundFeature::Email {
+            ("available", "SMTP, not proxied".to_string())
+        } else {
+            ("available", format!("HTTP {}", via))
+        };
+        FeatureStatus { name: feature.as_str(), state, detail }
+    }).collect();
+    let (proxy, proxy_source) = proxy.unzip();
+    OutboundStatus { offline: config.offline, proxy, proxy_source, features }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_offline_mode_and_proxy_status() {
+        let offline = OutboundConfig { offline: true, proxy: String::new(), ..Default::default() };
+        assert!(offline_check(&offline, OutboundFeature::Webhooks).unwrap_err().contains("webhooks"));
+        let report = build_status(&offline, true, |_| None);
+        assert!(report.features.iter().all(|f| f.state == "blocked"));
+
+        // Environment proxy without configured one; credentials are not reported
+        let online = OutboundConfig::default();
+        let report = build_status(&online, false, |name| (name == "HTTP_PROXY").then(|| "http://user:pw@proxy.lan:3128".to_string()));
+        assert_eq!(report.proxy.as_deref(), Some("http://***@proxy.lan:3128/"));
+        assert_eq!(report.proxy_source.as_deref(), Some("HTTP_PROXY"));
+        let states: Vec<&str> = report.features.iter().map(|f| f.state).collect();
+        assert_eq!(states, vec!["available", "disabled", "available"]);
+
+        let configured = OutboundConfig { offline: false, proxy: "http://proxy.lan:8080".to_string(), ..Default::default() };
+        assert_eq!(build_status(&configured, true, |_| Some("http://other:1".to_string())).proxy_source.as_deref(), Some("config"));
+    }
+}
@@ -0,0 +1,237 @@
+// Payload codec - detects and decodes binary device payloads (CBOR, MessagePack, length-prefixed)
+
+use base64::Engine;
+use serde::Serialize;
+use serde_json::{Map, Number, Value};
+
+// ============================================================================
+// PAYLOAD TYPES
+// ============================================================================
+
+/// Wire format of a binary device payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PayloadEncoding {
+    Cbor,
+    MessagePack,
+    /// 2-byte big-endian length prefix followed by the frame
+    LengthPrefixed,
+    /// Unknown binary format, delivered undecoded
+    Raw,
+}
+
+impl PayloadEncoding {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PayloadEncoding::Cbor => "cbor",
+            PayloadEncoding::MessagePack => "messagePack",
+            PayloadEncoding::LengthPrefixed => "lengthPrefixed",
+            PayloadEncoding::Raw => "raw",
+        }
+    }
+}
+
+/// Result of decoding a raw datagram
+#[derive(Debug, Clone)]
+pub enum DecodedPayload {
+    /// Plain text (JSON or line-based protocol) - handled by the text parsers
+    Text(String),
+    /// Binary frame with optional structured content
+    Binary {
+        encoding: PayloadEncoding,
+        decoded: Option<Value>,
+        raw: Vec<u8>,
+    },
+}
+
+impl DecodedPayload {
+    /// Raw bytes as base64 for JSON/WebSocket delivery
+    pub fn base64_payload(raw: &[u8]) -> String {
+        base64::engine::general_purpose::STANDARD.encode(raw)
+    }
+}
+
+// ============================================================================
+// DETECTION & DECODING
+// ============================================================================
+
+/// Detect the payload format and decode it.
+/// Text is checked first so existing JSON/text firmware keeps working unchanged.
+pub fn decode_payload(bytes: &[u8]) -> DecodedPayload {
+    if let Some(text) = as_text(bytes) {
+        return DecodedPayload::Text(text);
+    }
+
+    if let Some((encoding, decoded)) = decode_structured(bytes) {
+        return DecodedPayload::Binary { encoding, decoded: Some(decoded), raw: bytes.to_vec() };
+    }
+
+    // Length-prefixed frame: [len_hi, len_lo, frame...]
+    if bytes.len() > 2 {
+        let frame_len = u16::from_be_bytes([bytes[0], bytes[1]]) as usize;
+        if frame_len == bytes.len() - 2 {
+            let frame = &bytes[2..];
+            let decoded = decode_structured(frame).map(|(_, value)| value);
+            return DecodedPayload::Binary {
+                encoding: PayloadEncoding::LengthPrefixed,
+                decoded,
+                raw: bytes.to_vec(),
+            };
+        }
+    }
+
+    DecodedPayload::Binary { encoding: PayloadEncoding::Raw, decoded: None, raw: bytes.to_vec() }
+}
+
+/// Valid UTF-8 without control characters (except whitespace) is treated as text
+fn as_text(bytes: &[u8]) -> Option<String> {
+    let text = std::str::from_utf8(bytes).ok()?;
+    if text.chars().all(|c| !c.is_control() || c.is_whitespace()) {
+        Some(text.to_string())
+    } else {
+        None
+    }
+}
+
+/// Try CBOR, then MessagePack. A format only matches if it consumes the whole buffer.
+fn decode_structured(bytes: &[u8]) -> Option<(PayloadEncoding, Value)> {
+    if bytes.is_empty() {
+        return None;
+    }
+
+    let mut reader = bytes;
+    if let Ok(value) = ciborium::de::from_reader::<ciborium::Value, _>(&mut reader) {
+        if reader.is_empty() {
+            return Some((PayloadEncoding::Cbor, cbor_to_json(value)));
+        }
+    }
+
+    let mut reader = bytes;
+    if let Ok(value) = rmpv::decode::read_value(&mut reader) {
+        if reader.is_empty() {
+            return Some((PayloadEncoding::MessagePack, msgpack_to_json(value)));
+        }
+    }
+
+    None
+}
+
+// ============================================================================
+// VALUE CONVERSION - byte strings become base64 strings
+// ============================================================================
+
+fn float_to_json(f: f64) -> Value {
+    Number::from_f64(f).map(Value::Number).unwrap_or(Value::Null)
+}
+
+fn key_to_string(key: Value) -> String {
+    match key {
+        Value::String(s) => s,
+        other => other.to_string(),
+    }
+}
+
+fn cbor_to_json(value: ciborium::Value) -> Value {
+    use ciborium::Value as C;
+    match value {
+        C::Null => Value::Null,
+        C::Bool(b) => Value::Bool(b),
+        C::Integer(i) => {
+            let i = i128::from(i);
+            if let Ok(v) = i64::try_from(i) {
+                Value::from(v)
+            } else if let Ok(v) = u64::try_from(i) {
+                Value::from(v)
+            } else {
+                Value::String(i.to_string())
+            }
+        }
+        C::Float(f) => float_to_json(f),
+        C::Text(s) => Value::String(s),
+        C::Bytes(b) => Value::String(DecodedPayload::base64_payload(&b)),
+        C::Tag(_, inner) => cbor_to_json(*inner),
+        C::Array(items) => Value::Array(items.into_iter().map(cbor_to_json).collect()),
+        C::Map(entries) => {
+            let mut map = Map::new();
+            for (k, v) in entries {
+                map.insert(key_to_string(cbor_to_json(k)), cbor_to_json(v));
+            }
+            Value::Object(map)
+        }
+        _ => Value::Null,
+    }
+}
+
+fn msgpack_to_json(value: rmpv::Value) -> Value {
+    use rmpv::Value as M;
+    match value {
+        M::Nil => Value::Null,
+        M::Boolean(b) => Value::Bool(b),
+        M::Integer(i) => {
+            if let Some(v) = i.as_i64() {
+                Value::from(v)
+            } else if let Some(v) = i.as_u64() {
+                Value::from(v)
+            } else {
+                Value::Null
+            }
+        }
+        M::F32(f) => float_to_json(f as f64),
+        M::F64(f) => float_to_json(f),
+        M::String(s) => match s.into_str() {
+            Some(s) => Value::String(s),
+            None => Value::Null,
+        },
+        M::Binary(b) => Value::String(DecodedPayload::base64_payload(&b)),
+        M::Array(items) => Value::Array(items.into_iter().map(msgpack_to_json).collect()),
+        M::Map(entries) => {
+            let mut map = Map::new();
+            for (k, v) in entries {
+                map.insert(key_to_string(msgpack_to_json(k)), msgpack_to_json(v));
+            }
+            Value::Object(map)
+        }
+        M::Ext(_, data) => Value::String(DecodedPayload::base64_payload(&data)),
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_json_text_stays_text() {
+        let payload = decode_payload(br#"{"temp": 21}"#);
+        assert!(matches!(payload, DecodedPayload::Text(ref t) if t == r#"{"temp": 21}"#));
+    }
+
+    #[test]
+    fn test_decodes_cbor_map() {
+        // {"t": 21}
+        let bytes = [0xA1, 0x61, b't', 0x15];
+        match decode_payload(&bytes) {
+            DecodedPayload::Binary { encoding, decoded, .. } => {
+                assert_eq!(encoding, PayloadEncoding::Cbor);
+                assert_eq!(decoded, Some(serde_json::json!({"t": 21})));
+            }
+            other => panic!("expected binary payload, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_decodes_length_prefixed_frame() {
+        // len=3, CBOR array [1, 2]
+        let bytes = [0x00, 0x03, 0x82, 0x01, 0x02];
+        match decode_payload(&bytes) {
+            DecodedPayload::Binary { encoding, decoded, .. } => {
+                assert_eq!(encoding, PayloadEncoding::LengthPrefixed);
+                assert_eq!(decoded, Some(serde_json::json!([1, 2])));
+            }
+            other => panic!("expected binary payload, got {:?}", other),
+        }
+    }
+}
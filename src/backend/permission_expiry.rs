@@ -0,0 +1,75 @@
+// Permission expiry - removes time-limited device permissions once they end
+//
+// POST /api/device-permissions/:id accepts an optional `expires_at` (RFC 3339), e.g. to give a
+// student access for the length of a lab session. Expired grants stop counting at once (the
+// permission queries ignore them); this task deletes them every SWEEP_INTERVAL and sends a
+// deviceListChanged (permissionsChanged) to the user who lost access and to the device owner, so
+// open device lists drop the device without a reload.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde_json::json;
+use tracing::{info, warn};
+
+use crate::activity::{self, AuditAction};
+use crate::database::{DatabaseManager, DevicePermission};
+use crate::device_store::SharedDeviceStore;
+use crate::events::{DeviceListChange, ServerMessage};
+
+/// How often expired grants are removed
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Users to notify about an expired grant: the former holder and the device owner
+fn audience(permission: &DevicePermission, owner_id: Option<String>) -> HashSet<String> {
+    let mut audience = HashSet::from([permission.user_id.clone()]);
+    audience.extend(owner_id);
+    audience
+}
+
+/// Remove grants that ended before `now` and notify the users; returns the number removed
+pub async fn sweep(db: &DatabaseManager, device_store: &SharedDeviceStore, now: DateTime<Utc>) -> Result<usize, Box<dyn std::error::Error>> {
+    let expired = db.delete_expired_device_permissions(now).await?;
+    for permission in &expired {
+        info!("Permission {} of user {} on device {} expired", permission.permission, permission.user_id, permission.device_id);
+        activity::record(db, &permission.device_id, activity::SYSTEM_ACTOR, AuditAction::PermissionExpired, json!({
+            "userId": permission.user_id,
+            "permission": permission.permission,
+        })).await;
+        let owner_id = db.get_device_by_id(&permission.device_id).await?.map(|device| device.owner_id);
+        let message = ServerMessage::device_list_changed(permission.device_id.clone(), DeviceListChange::PermissionsChanged);
+        device_store.send_to_users(&audience(permission, owner_id), message).await;
+    }
+    Ok(expired.len())
+}
+
+pub fn start(db: Arc<DatabaseManager>, device_store: SharedDeviceStore) {
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(SWEEP_INTERVAL);
+        loop {
+            interval.tick().await;
+            if let Err(e) = sweep(&db, &device_store, Utc::now()).await {
+                warn!("Failed to remove expired device permissions: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_audience_of_expired_grant() {
+        let permission = DevicePermission {
+            device_id: "AA-BB-CC-DD-EE-FF".to_string(),
+            user_id: "student".to_string(),
+            permission: "W".to_string(),
+            expires_at: Some(Utc::now()),
+        };
+        assert_eq!(audience(&permission, Some("teacher".to_string())), HashSet::from(["student".to_string(), "teacher".to_string()]));
+        assert_eq!(audience(&permission, None), HashSet::from(["student".to_string()]));
+    }
+}
@@ -0,0 +1,150 @@
+// Permission resolution - effective device permission of a user
+//
+// Levels, lowest to highest: R (read), W (write), V (write in maintenance mode), M (manage), O (owner).
+// A user's permission on a device comes from the first source that has one:
+//   1. direct  - an entry in device_permissions (also when it is lower than the others)
+//   2. group   - highest grant on a device group containing the device (device_group_permissions);
+//                a grant only reaches devices the group owner manages (M, O or organization admin)
+//                and never gives O
+//   3. default - organization members: the device's default_permission for plain members
+//                (W when unset), M for admins and owners
+// REST handlers and the WebSocket layer both ask PermissionResolver; the SQL of the device list
+// (database.rs, query_device_page with GROUP_GRANTS_SQL) follows the same order.
+
+use serde::Serialize;
+
+use crate::database::DatabaseManager;
+use crate::organizations::OrgRole;
+
+/// Permission levels in ascending order
+pub const LEVELS: [&str; 5] = ["R", "W", "V", "M", "O"];
+
+/// Levels that can be granted through a device group
+pub const GROUP_LEVELS: [&str; 4] = ["R", "W", "V", "M"];
+
+/// Levels a device can give organization members by default
+pub const DEFAULT_LEVELS: [&str; 3] = ["R", "W", "V"];
+
+pub fn rank(permission: &str) -> Option<usize> {
+    LEVELS.iter().position(|level| *level == permission)
+}
+
+/// Does `permission` allow `required`? In maintenance mode writing needs V
+pub fn grants(permission: &str, required: &str, maintenance_mode: bool) -> bool {
+    let required = if required == "W" && maintenance_mode { "V" } else { required };
+    matches!((rank(permission), rank(required)), (Some(have), Some(need)) if have >= need)
+}
+
+/// Where an effective permission comes from
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "source", rename_all = "camelCase")]
+pub enum PermissionSource {
+    Direct,
+    #[serde(rename_all = "camelCase")]
+    Group { group_id: String },
+    #[serde(rename_all = "camelCase")]
+    OrgDefault { org_id: String, role: OrgRole },
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EffectivePermission {
+    pub permission: String,
+    #[serde(flatten)]
+    pub source: PermissionSource,
+}
+
+/// Pick the effective permission from the candidates of each source (direct > group > default)
+pub fn resolve(
+    direct: Option<String>,
+    groups: &[(String, String)],
+    org: Option<(String, OrgRole, Option<String>)>,
+) -> Option<EffectivePermission> {
+    if let Some(permission) = direct {
+        return Some(EffectivePermission { permission, source: PermissionSource::Direct });
+    }
+    let best_group = groups.iter()
+        .filter(|(_, permission)| GROUP_LEVELS.contains(&permission.as_str()))
+        .max_by_key(|(_, permission)| rank(permission));
+    if let Some((group_id, permission)) = best_group {
+        return Some(EffectivePermission {
+            permission: permission.clone(),
+            source: PermissionSource::Group { group_id: group_id.clone() },
+        });
+    }
+    org.map(|(org_id, role, default_permission)| {
+        let permission = match role {
+            OrgRole::Member => default_permission.unwrap_or_else(|| role.device_permission().to_string()),
+            _ => role.device_permission().to_string(),
+        };
+        EffectivePermission { permission, source: PermissionSource::OrgDefault { org_id, role } }
+    })
+}
+
+/// Effective permissions looked up in the database
+pub struct PermissionResolver<'a> {
+    db: &'a DatabaseManager,
+}
+
+impl<'a> PermissionResolver<'a> {
+    pub fn new(db: &'a DatabaseManager) -> Self {
+        Self { db }
+    }
+
+    /// Effective permission of a user on a device (None = no access)
+    pub async fn effective_permission(&self, device_id: &str, user_id: &str) -> Result<Option<EffectivePermission>, Box<dyn std::error::Error>> {
+        let direct = self.db.get_user_device_permission(device_id, user_id).await?;
+        if direct.is_some() {
+            return Ok(resolve(direct, &[], None));
+        }
+        let groups = self.db.group_permissions_for_device(device_id, user_id).await?;
+        let device = self.db.get_device_by_id(device_id).await?;
+        let mut org = None;
+        if let Some(device) = device {
+            if let Some(org_id) = device.org_id {
+                let role = self.db.get_org_role(&org_id, user_id).await?;
+                org = role.map(|role| (org_id, role, device.default_permission));
+            }
+        }
+        Ok(resolve(None, &groups, org))
+    }
+
+    /// Does the user hold at least `required` (R, W, V, M or O) on the device?
+    pub async fn has_permission(&self, device_id: &str, user_id: &str, required: &str) -> Result<bool, Box<dyn std::error::Error>> {
+        let effective = self.effective_permission(device_id, user_id).await?;
+        let Some(effective) = effective else {
+            return Ok(false);
+        };
+        let mut maintenance_mode = false;
+        if required == "W" {
+            let device = self.db.get_device_by_id(device_id).await?;
+            let Some(device) = device else {
+                return Ok(false);
+            };
+            maintenance_mode = device.maintenance_mode;
+        }
+        Ok(grants(&effective.permission, required, maintenance_mode))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolution_order_and_levels() {
+        let groups = vec![("lab".to_string(), "R".to_string()), ("class".to_string(), "V".to_string())];
+        let org = Some(("school".to_string(), OrgRole::Member, Some("R".to_string())));
+
+        // Direct wins even when it is lower than a group grant
+        assert_eq!(resolve(Some("R".to_string()), &groups, org.clone()).unwrap().source, PermissionSource::Direct);
+        let group = resolve(None, &groups, org.clone()).unwrap();
+        assert_eq!((group.permission.as_str(), group.source), ("V", PermissionSource::Group { group_id: "class".to_string() }));
+        assert_eq!(resolve(None, &[], org).unwrap().permission, "R");
+        assert_eq!(resolve(None, &[], Some(("school".to_string(), OrgRole::Member, None))).unwrap().permission, "W");
+        assert_eq!(resolve(None, &[], Some(("school".to_string(), OrgRole::Admin, Some("R".to_string())))).unwrap().permission, "M");
+        assert_eq!(resolve(None, &[("g".to_string(), "O".to_string())], None), None);
+
+        assert!(grants("W", "R", false) && grants("W", "W", false) && !grants("W", "W", true));
+        assert!(grants("V", "W", true) && !grants("M", "O", false) && !grants("X", "R", false));
+    }
+}
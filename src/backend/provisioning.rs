@@ -0,0 +1,311 @@
+// Wi-Fi provisioning - ESP-IDF provisioning QR codes and registration of freshly flashed devices
+//
+// A provisioning session holds the target network and a one-time registration token. It yields
+// the standard ESP-IDF QR payload ({"ver":"v1","name":...,"pop":...,"transport":"softap"}) that
+// the Espressif provisioning apps scan, and the esp_prov.py command for configuring a device in
+// SoftAP mode from a laptop joined to its access point. Both pass the server URL and the token as
+// custom data; once the device is on the network it registers with POST /api/provisioning/register
+// and is created for the session owner. Sessions (and the Wi-Fi passphrase) are kept in memory only.
+
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+
+use chrono::{DateTime, Duration, Utc};
+use qrcode::render::svg;
+use qrcode::QrCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+/// Lifetime of a session; unregistered sessions are dropped afterwards
+pub const SESSION_TTL_MINUTES: i64 = 30;
+
+/// Open sessions per user
+pub const MAX_SESSIONS_PER_USER: usize = 20;
+
+/// Address of the provisioning HTTP server of an ESP32 in SoftAP mode
+pub const SOFTAP_ADDRESS: &str = "192.168.4.1:80";
+
+static SESSIONS: LazyLock<Mutex<HashMap<String, ProvisioningSession>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// How the provisioning app reaches the device
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProvTransport {
+    #[default]
+    Softap,
+    Ble,
+}
+
+impl ProvTransport {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProvTransport::Softap => "softap",
+            ProvTransport::Ble => "ble",
+        }
+    }
+}
+
+/// Body of POST /api/provisioning
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateProvisioningRequest {
+    /// Network the device should join
+    pub ssid: String,
+    #[serde(default)]
+    pub passphrase: String,
+    #[serde(default)]
+    pub transport: ProvTransport,
+    /// ESP-IDF protocomm security: 1 = proof of possession (default), 0 = none
+    #[serde(default = "default_security")]
+    pub security: u8,
+    /// SoftAP SSID / BLE name of the device; default PROV_ with random hex
+    pub service_name: Option<String>,
+    /// Name the device gets when it registers
+    pub device_name: Option<String>,
+}
+
+fn default_security() -> u8 {
+    1
+}
+
+/// Body of POST /api/provisioning/register (sent by the device once it joined the network)
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceRegistration {
+    pub token: String,
+    pub mac: String,
+    /// Name announced by the firmware (the session's device name takes precedence)
+    pub name: Option<String>,
+    pub firmware_version: Option<String>,
+    /// Port of the device's TCP server; without one the device is reached once it sends UDP
+    pub tcp_port: Option<u16>,
+    pub udp_port: Option<u16>,
+}
+
+/// Open provisioning session (passphrase and token are never serialized)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProvisioningSession {
+    pub id: String,
+    pub owner_id: String,
+    pub org_id: Option<String>,
+    pub service_name: String,
+    /// Proof of possession (security 1)
+    pub pop: Option<String>,
+    pub transport: ProvTransport,
+    pub security: u8,
+    pub ssid: String,
+    #[serde(skip)]
+    pub passphrase: String,
+    pub device_name: Option<String>,
+    #[serde(skip)]
+    pub token: String,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Device that registered with this session's token
+    pub device_id: Option<String>,
+}
+
+impl ProvisioningSession {
+    pub fn status(&self) -> &'static str {
+        if self.device_id.is_some() {
+            "registered"
+        } else if self.expires_at <= Utc::now() {
+            "expired"
+        } else {
+            "pending"
+        }
+    }
+
+    /// ESP-IDF provisioning QR payload
+    pub fn qr_payload(&self) -> String {
+        let mut payload = json!({
+            "ver": "v1",
+            "name": self.service_name,
+            "transport": self.transport.as_str(),
+            "security": self.security.to_string(),
+        });
+        if let Some(pop) = &self.pop {
+            payload["pop"] = json!(pop);
+        }
+        payload.to_string()
+    }
+
+    /// Custom data the firmware receives during provisioning (the "custom-data" endpoint)
+    pub fn custom_data(&self, server_url: &str) -> Value {
+        json!({ "server": server_url, "token": self.token })
+    }
+
+    /// esp_prov.py call that provisions a device in SoftAP mode (laptop joined to its access point)
+    pub fn esp_prov_command(&self, server_url: &str) -> String {
+        let service_name = match self.transport {
+            ProvTransport::Softap => SOFTAP_ADDRESS,
+            ProvTransport::Ble => &self.service_name,
+        };
+        let mut command = format!(
+            "python $IDF_PATH/tools/esp_prov/esp_prov.py --transport {} --service_name {} --sec_ver {}",
+            self.transport.as_str(), shell_quote(service_name), self.security,
+        );
+        if let Some(pop) = &self.pop {
+            command.push_str(&format!(" --pop {}", shell_quote(pop)));
+        }
+        command.push_str(&format!(" --ssid {}", shell_quote(&self.ssid)));
+        if !self.passphrase.is_empty() {
+            command.push_str(&format!(" --passphrase {}", shell_quote(&self.passphrase)));
+        }
+        command.push_str(&format!(" --custom_data {}", shell_quote(&self.custom_data(server_url).to_string())));
+        command
+    }
+}
+
+fn shell_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "'\\''"))
+}
+
+fn random_hex(chars: usize) -> String {
+    let mut hex = String::new();
+    while hex.len() < chars {
+        hex.push_str(&uuid::Uuid::new_v4().simple().to_string());
+    }
+    hex.truncate(chars);
+    hex
+}
+
+/// QR code of a payload as SVG
+pub fn qr_svg(payload: &str) -> Result<String, String> {
+    let code = QrCode::new(payload.as_bytes()).map_err(|e| format!("Payload does not fit into a QR code: {}", e))?;
+    Ok(code.render::<svg::Color>().min_dimensions(256, 256).quiet_zone(true).build())
+}
+
+fn validate(request: &CreateProvisioningRequest) -> Result<(), String> {
+    if request.ssid.is_empty() || request.ssid.len() > 32 {
+        return Err("ssid must be between 1 and 32 bytes".to_string());
+    }
+    if !request.passphrase.is_empty() && !(8..=63).contains(&request.passphrase.len()) {
+        return Err("passphrase must be empty (open network) or 8 to 63 characters".to_string());
+    }
+    if request.security > 1 {
+        return Err("security must be 0 or 1".to_string());
+    }
+    if let Some(name) = &request.service_name {
+        if name.is_empty() || name.len() > 32 || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+            return Err("serviceName must be 1 to 32 letters, digits, '_' or '-'".to_string());
+        }
+    }
+    if request.device_name.as_ref().is_some_and(|name| name.trim().is_empty() || name.len() > 100) {
+        return Err("deviceName must be between 1 and 100 characters".to_string());
+    }
+    Ok(())
+}
+
+fn prune(sessions: &mut HashMap<String, ProvisioningSession>) {
+    let now = Utc::now();
+    sessions.retain(|_, session| session.device_id.is_some() || session.expires_at > now);
+}
+
+/// Open a session for `owner_id` (devices are created in `org_id` if set)
+pub fn create(owner_id: &str, org_id: Option<String>, request: CreateProvisioningRequest) -> Result<ProvisioningSession, String> {
+    validate(&request)?;
+    let mut sessions = SESSIONS.lock().unwrap();
+    prune(&mut sessions);
+    if sessions.values().filter(|session| session.owner_id == owner_id && session.device_id.is_none()).count() >= MAX_SESSIONS_PER_USER {
+        return Err(format!("At most {} open provisioning sessions per user", MAX_SESSIONS_PER_USER));
+    }
+
+    let now = Utc::now();
+    let session = ProvisioningSession {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: owner_id.to_string(),
+        org_id,
+        service_name: request.service_name.unwrap_or_else(|| format!("PROV_{}", random_hex(6).to_ascii_uppercase())),
+        pop: (request.security == 1).then(|| random_hex(8)),
+        transport: request.transport,
+        security: request.security,
+        ssid: request.ssid,
+        passphrase: request.passphrase,
+        device_name: request.device_name.map(|name| name.trim().to_string()),
+        token: random_hex(32),
+        created_at: now,
+        expires_at: now + Duration::minutes(SESSION_TTL_MINUTES),
+        device_id: None,
+    };
+    sessions.insert(session.id.clone(), session.clone());
+    Ok(session)
+}
+
+pub fn get(session_id: &str) -> Option<ProvisioningSession> {
+    SESSIONS.lock().unwrap().get(session_id).cloned()
+}
+
+/// Sessions of a user, newest first
+pub fn list(owner_id: &str) -> Vec<ProvisioningSession> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    prune(&mut sessions);
+    let mut owned: Vec<ProvisioningSession> = sessions.values().filter(|session| session.owner_id == owner_id).cloned().collect();
+    owned.sort_by_key(|session| std::cmp::Reverse(session.created_at));
+    owned
+}
+
+pub fn remove(session_id: &str) -> Option<ProvisioningSession> {
+    SESSIONS.lock().unwrap().remove(session_id)
+}
+
+/// Pending session of a registration token
+pub fn find_by_token(token: &str) -> Result<ProvisioningSession, String> {
+    let mut sessions = SESSIONS.lock().unwrap();
+    prune(&mut sessions);
+    let session = sessions.values().find(|session| !token.is_empty() && session.token == token)
+        .ok_or("Unknown or expired provisioning token")?;
+    if session.device_id.is_some() {
+        return Err("Provisioning token was already used".to_string());
+    }
+    Ok(session.clone())
+}
+
+/// Record the device that registered; the token is spent afterwards
+pub fn mark_registered(session_id: &str, device_id: &str) {
+    if let Some(session) = SESSIONS.lock().unwrap().get_mut(session_id) {
+        session.device_id = Some(device_id.to_string());
+        session.passphrase.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request() -> CreateProvisioningRequest {
+        serde_json::from_value(json!({"ssid": "Lab WLAN", "passphrase": "it's secret"})).unwrap()
+    }
+
+    #[test]
+    fn test_session_payloads_and_token_use() {
+        let session = create("teacher", None, request()).unwrap();
+        assert!(session.service_name.starts_with("PROV_") && session.service_name.len() == 11);
+        let payload: Value = serde_json::from_str(&session.qr_payload()).unwrap();
+        assert_eq!(payload["ver"], "v1");
+        assert_eq!(payload["transport"], "softap");
+        assert_eq!(payload["pop"].as_str(), session.pop.as_deref());
+        assert!(qr_svg(&session.qr_payload()).unwrap().starts_with("<?xml"));
+
+        let command = session.esp_prov_command("http://server:3000");
+        assert!(command.contains("--service_name '192.168.4.1:80' --sec_ver 1"));
+        assert!(command.contains(r#"--passphrase 'it'\''s secret'"#));
+        assert!(command.contains(&session.token));
+        assert!(!serde_json::to_string(&session).unwrap().contains(&session.token));
+
+        assert_eq!(find_by_token(&session.token).unwrap().id, session.id);
+        mark_registered(&session.id, "AA-BB-CC-DD-EE-FF");
+        assert!(find_by_token(&session.token).is_err());
+        assert_eq!(get(&session.id).unwrap().status(), "registered");
+
+        let mut bad = request();
+        bad.passphrase = "short".to_string();
+        assert!(create("teacher", None, bad).is_err());
+        let mut open = request();
+        (open.passphrase, open.security) = (String::new(), 0);
+        let open = create("teacher", None, open).unwrap();
+        assert!(open.pop.is_none() && !open.qr_payload().contains("pop"));
+        assert!(remove(&open.id).is_some());
+    }
+}
@@ -0,0 +1,173 @@
+// Reverse proxy support - real client address/scheme from X-Forwarded-* headers and the mount path prefix
+//
+// Forwarded headers are only honored when the direct peer is a trusted proxy
+// (server.trusted_proxies); otherwise any client could spoof its address.
+
+use std::convert::Infallible;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::OnceLock;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, Request},
+    http::{request::Parts, HeaderMap},
+    middleware::Next,
+    response::Response,
+};
+
+// Set once at startup, defaults (no proxy, no prefix) until then
+static PROXY_CONFIG: OnceLock<ProxyConfig> = OnceLock::new();
+
+/// Reverse proxy settings
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyConfig {
+    /// Peers whose X-Forwarded-* headers are trusted
+    pub trusted_proxies: Vec<IpAddr>,
+    /// Trust every peer ("*"), only for setups where the server is unreachable except through the proxy
+    pub trust_all: bool,
+    /// Mount path without trailing slash, e.g. "/esp32" ("" = root)
+    pub base_path: String,
+}
+
+impl ProxyConfig {
+    /// Build from config values; `trusted` entries are IP addresses or "*"
+    pub fn new(trusted: &[String], base_path: &str) -> Result<Self, String> {
+        let mut config = Self { base_path: normalize_base_path(base_path), ..Self::default() };
+        for entry in trusted {
+            if entry == "*" {
+                config.trust_all = true;
+            } else {
+                config.trusted_proxies.push(entry.parse().map_err(|_| format!("not an IP address: {}", entry))?);
+            }
+        }
+        Ok(config)
+    }
+
+    fn is_trusted(&self, ip: &IpAddr) -> bool {
+        self.trust_all || self.trusted_proxies.contains(ip)
+    }
+
+    /// Client address and scheme of a request that arrived from `peer`
+    pub fn client_info(&self, peer: IpAddr, headers: &HeaderMap) -> ClientInfo {
+        if !self.is_trusted(&peer) {
+            return ClientInfo { ip: peer, https: false };
+        }
+
+        // Walk X-Forwarded-For from the right: the first untrusted hop is the client
+        let forwarded_for: Vec<IpAddr> = headers.get_all("x-forwarded-for").iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|hop| hop.trim().parse().ok())
+            .collect();
+        let ip = forwarded_for.iter().rev()
+            .find(|hop| !self.is_trusted(hop))
+            .or(forwarded_for.first())
+            .copied()
+            .unwrap_or(peer);
+
+        let https = headers.get("x-forwarded-proto")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .is_some_and(|proto| proto.trim().eq_ignore_ascii_case("https"));
+
+        ClientInfo { ip, https }
+    }
+}
+
+/// "/esp32/" -> "/esp32", "esp32" -> "/esp32", "/" -> ""
+pub fn normalize_base_path(path: &str) -> String {
+    let trimmed = path.trim().trim_matches('/');
+    if trimmed.is_empty() {
+        String::new()
+    } else {
+        format!("/{}", trimmed)
+    }
+}
+
+/// Install the proxy settings (first call wins)
+pub fn configure(config: ProxyConfig) {
+    if PROXY_CONFIG.set(config).is_err() {
+        tracing::warn!("Reverse proxy settings were already configured");
+    }
+}
+
+/// Active proxy settings
+pub fn config() -> &'static ProxyConfig {
+    PROXY_CONFIG.get_or_init(ProxyConfig::default)
+}
+
+/// Mount path of the app ("" when served at the root)
+pub fn base_path() -> &'static str {
+    &config().base_path
+}
+
+// ============================================================================
+// CLIENT INFO - Extractor and middleware
+// ============================================================================
+
+/// Real client of a request (after resolving trusted proxies)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientInfo {
+    pub ip: IpAddr,
+    /// Request reached the proxy or the built-in TLS listener via HTTPS (cookies get the Secure flag)
+    pub https: bool,
+}
+
+/// Resolve the client once per request and store it as extension (for handlers and the request log)
+pub async fn client_info_middleware(mut request: Request, next: Next) -> Response {
+    let peer = request.extensions().get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip())
+        .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+    let mut client = config().client_info(peer, request.headers());
+    // Built-in TLS listener (tls.rs): the connection itself is HTTPS
+    client.https |= request.extensions().get::<crate::tls::TlsConnection>().is_some();
+    request.extensions_mut().insert(client);
+    next.run(request).await
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(client) = parts.extensions.get::<ClientInfo>() {
+            return Ok(*client);
+        }
+        // Router without the middleware (e.g. tests): direct peer only
+        let peer = parts.extensions.get::<ConnectInfo<SocketAddr>>()
+            .map(|ConnectInfo(addr)| addr.ip())
+            .unwrap_or(IpAddr::V4(Ipv4Addr::UNSPECIFIED));
+        let https = parts.extensions.get::<crate::tls::TlsConnection>().is_some();
+        Ok(ClientInfo { ip: peer, https })
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_forwarded_headers_only_from_trusted_proxies() {
+        let config = ProxyConfig::new(&["127.0.0.1".to_string(), "10.0.0.2".to_string()], "/esp32/").unwrap();
+        assert_eq!(config.base_path, "/esp32");
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-forwarded-for", "203.0.113.7, 198.51.100.1, 10.0.0.2".parse().unwrap());
+        headers.insert("x-forwarded-proto", "https".parse().unwrap());
+
+        // Spoofed first hop is ignored, the rightmost untrusted hop is the client
+        let client = config.client_info("127.0.0.1".parse().unwrap(), &headers);
+        assert_eq!(client, ClientInfo { ip: "198.51.100.1".parse().unwrap(), https: true });
+
+        // Direct connections cannot fake their address
+        let direct = config.client_info("192.168.1.50".parse().unwrap(), &headers);
+        assert_eq!(direct, ClientInfo { ip: "192.168.1.50".parse().unwrap(), https: false });
+
+        assert_eq!(normalize_base_path("/"), "");
+        assert!(ProxyConfig::new(&["proxy.local".to_string()], "").is_err());
+    }
+}
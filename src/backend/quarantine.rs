@@ -0,0 +1,253 @@
+// Quarantine - automatic isolation of devices that flood the server or send garbage
+//
+// DeviceManager::handle_message_unified counts every message of a device in one-minute
+// windows. More than [quarantine] max_messages_per_minute messages, or more than
+// max_errors_per_minute rejected messages (inbound_validation) and undecodable bus frames,
+// move the device to the Quarantined lifecycle state. From then on its messages are dropped
+// before they reach the event store - nothing is broadcast to clients, no alerts, scripts or
+// webhooks run on them. One message every trickle_seconds is kept in the device log (GET
+// /api/devices/:id/logs) so the owner can still see what the device sends. Link changes are
+// still tracked.
+//
+// Entering quarantine is written to the activity log, sent to `device.quarantined` webhooks and
+// emailed to the device owners. The state is persisted like every lifecycle state and survives a
+// restart; only POST /api/devices/:id/unquarantine releases the device.
+
+use std::collections::HashMap;
+use std::sync::{Arc, LazyLock, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use serde_json::json;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::activity::{self, AuditAction};
+use crate::database::DatabaseManager;
+use crate::device_lifecycle::{DeviceLifecycle, LifecycleState};
+use crate::device_store::SharedDeviceStore;
+use crate::email::{EmailTemplate, Mailer, NotificationKind};
+use crate::events::DeviceEvent;
+
+/// Length of a counting window
+const WINDOW: Duration = Duration::from_secs(60);
+
+// [quarantine]; replaced by a config reload
+static SETTINGS: RwLock<QuarantineSettings> = RwLock::new(QuarantineSettings {
+    enabled: true,
+    max_messages_per_minute: 6000,
+    max_errors_per_minute: 120,
+    trickle_interval: Duration::from_secs(30),
+});
+
+static COUNTERS: LazyLock<Mutex<HashMap<String, DeviceCounters>>> = LazyLock::new(Default::default);
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct QuarantineSettings {
+    pub enabled: bool,
+    pub max_messages_per_minute: u32,
+    pub max_errors_per_minute: u32,
+    /// Zero holds back every message
+    pub trickle_interval: Duration,
+}
+
+pub fn configure(settings: QuarantineSettings) {
+    *SETTINGS.write().unwrap() = settings;
+}
+
+/// What happens to a valid message
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Admission {
+    /// Processed as usual
+    Pass,
+    /// Device is quarantined; the message is kept as a diagnostic sample
+    Sample,
+    /// Device is quarantined; the message is dropped
+    Hold,
+}
+
+/// Message and error counts of one device
+#[derive(Debug, Default)]
+struct DeviceCounters {
+    window_start: Option<Instant>,
+    messages: u32,
+    errors: u32,
+    /// While quarantined: last message let through and messages held back since the quarantine began
+    last_trickle: Option<Instant>,
+    held_back: u64,
+}
+
+impl DeviceCounters {
+    /// Count a message (or an error); the reason for a quarantine once a limit is exceeded
+    fn count(&mut self, settings: &QuarantineSettings, error: bool, now: Instant) -> Option<String> {
+        if !settings.enabled {
+            return None;
+        }
+        if self.window_start.is_none_or(|start| now.duration_since(start) >= WINDOW) {
+            self.window_start = Some(now);
+            self.messages = 0;
+            self.errors = 0;
+        }
+        let reason = if error {
+            self.errors += 1;
+            (self.errors > settings.max_errors_per_minute)
+                .then(|| format!("{} invalid messages within a minute (limit {})", self.errors, settings.max_errors_per_minute))
+        } else {
+            self.messages += 1;
+            (self.messages > settings.max_messages_per_minute)
+                .then(|| format!("{} messages within a minute (limit {})", self.messages, settings.max_messages_per_minute))
+        };
+        if reason.is_some() {
+            *self = Self { last_trickle: Some(now), ..Self::default() };
+        }
+        reason
+    }
+
+    /// Whether a message of the quarantined device is kept as a diagnostic sample
+    fn trickle(&mut self, settings: &QuarantineSettings, now: Instant) -> bool {
+        let due = !settings.trickle_interval.is_zero()
+            && self.last_trickle.is_none_or(|at| now.duration_since(at) >= settings.trickle_interval);
+        if due {
+            self.last_trickle = Some(now);
+        } else {
+            self.held_back += 1;
+        }
+        due
+    }
+}
+
+async fn is_quarantined(lifecycle: &DeviceLifecycle, device_id: &str) -> bool {
+    lifecycle.get(device_id).await.is_some_and(|entry| entry.state == LifecycleState::Quarantined)
+}
+
+/// Count a valid message; a device exceeding the message limit is quarantined right away
+pub async fn admit(lifecycle: &DeviceLifecycle, device_id: &str) -> Admission {
+    let quarantined = is_quarantined(lifecycle, device_id).await;
+    let settings = *SETTINGS.read().unwrap();
+    let now = Instant::now();
+    let reason = {
+        let mut counters = COUNTERS.lock().unwrap();
+        let counters = counters.entry(device_id.to_string()).or_default();
+        if quarantined {
+            return if counters.trickle(&settings, now) { Admission::Sample } else { Admission::Hold };
+        }
+        counters.count(&settings, false, now)
+    };
+    match reason {
+        Some(reason) => {
+            enter(lifecycle, device_id, &reason).await;
+            Admission::Hold
+        }
+        None => Admission::Pass,
+    }
+}
+
+/// Count a rejected message or undecodable frame
+pub async fn record_error(lifecycle: &DeviceLifecycle, device_id: &str) {
+    if is_quarantined(lifecycle, device_id).await {
+        return;
+    }
+    let settings = *SETTINGS.read().unwrap();
+    let reason = COUNTERS.lock().unwrap().entry(device_id.to_string()).or_default().count(&settings, true, Instant::now());
+    if let Some(reason) = reason {
+        enter(lifecycle, device_id, &reason).await;
+    }
+}
+
+async fn enter(lifecycle: &DeviceLifecycle, device_id: &str, reason: &str) {
+    if lifecycle.quarantine(device_id, reason).await {
+        warn!("Device {} quarantined: {}", device_id, reason);
+    }
+}
+
+/// Release a quarantined device; returns the number of messages held back
+pub async fn release(lifecycle: &DeviceLifecycle, device_id: &str) -> Option<u64> {
+    if !lifecycle.release_quarantine(device_id, "Released from quarantine").await {
+        return None;
+    }
+    let counters = COUNTERS.lock().unwrap().remove(device_id);
+    Some(counters.map_or(0, |counters| counters.held_back))
+}
+
+/// Activity entry and owner email when a device enters quarantine; started once from main
+pub fn start(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, mailer: Arc<Mailer>) {
+    let mut feed = device_store.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            match feed.recv().await {
+                Ok(feed_event) => {
+                    let DeviceEvent::DeviceLifecycle { device_id, state, previous_state, reason } = feed_event.event else {
+                        continue;
+                    };
+                    if state == LifecycleState::Quarantined.as_str() && previous_state.as_deref() != Some(state.as_str()) {
+                        notify(&db, &mailer, &device_id, &reason).await;
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Quarantine notifications lagged behind the event feed, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+    info!("Quarantine monitor started");
+}
+
+async fn notify(db: &DatabaseManager, mailer: &Mailer, device_id: &str, reason: &str) {
+    activity::record(db, device_id, activity::SYSTEM_ACTOR, AuditAction::Quarantined, json!({ "reason": reason })).await;
+
+    let mut owners = Vec::new();
+    match db.get_device_by_id(device_id).await.map_err(|e| e.to_string()) {
+        Ok(Some(device)) => owners.push(device.owner_id),
+        Ok(None) => return,
+        Err(e) => warn!("Failed to load device {} for the quarantine notification: {}", device_id, e),
+    }
+    match db.get_device_permissions(device_id).await.map_err(|e| e.to_string()) {
+        Ok(permissions) => owners.extend(permissions.into_iter().filter(|p| p.permission == "O").map(|p| p.user_id)),
+        Err(e) => warn!("Failed to load owners of device {}: {}", device_id, e),
+    }
+    owners.sort();
+    owners.dedup();
+
+    let template = EmailTemplate::DeviceQuarantined {
+        device_id: device_id.to_string(),
+        reason: reason.to_string(),
+        link: mailer.link(&format!("/devices/{}", device_id)),
+    };
+    for owner in owners {
+        mailer.notify_user(&owner, NotificationKind::DeviceQuarantined, &template).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limits_and_trickle() {
+        let settings = QuarantineSettings {
+            enabled: true,
+            max_messages_per_minute: 3,
+            max_errors_per_minute: 1,
+            trickle_interval: Duration::from_secs(10),
+        };
+        let start = Instant::now();
+        let mut counters = DeviceCounters::default();
+        for _ in 0..3 {
+            assert_eq!(counters.count(&settings, false, start), None);
+        }
+        // A new window starts over
+        assert_eq!(counters.count(&settings, false, start + WINDOW), None);
+        assert_eq!(counters.count(&settings, true, start + WINDOW), None);
+        assert_eq!(counters.count(&settings, true, start + WINDOW).unwrap(), "2 invalid messages within a minute (limit 1)");
+
+        // Quarantined: one message per trickle interval, the rest is held back
+        let quarantined_at = start + WINDOW;
+        assert!(!counters.trickle(&settings, quarantined_at + Duration::from_secs(5)));
+        assert!(counters.trickle(&settings, quarantined_at + Duration::from_secs(10)));
+        assert!(!counters.trickle(&settings, quarantined_at + Duration::from_secs(11)));
+        assert_eq!(counters.held_back, 2);
+
+        let off = QuarantineSettings { enabled: false, ..settings };
+        assert!((0..10).all(|_| counters.count(&off, true, start).is_none()));
+    }
+}
@@ -0,0 +1,325 @@
+// Session recordings - capture a device's event stream and replay it into a simulated channel
+//
+// POST /api/devices/:id/recording/start begins capturing every event of the device from the
+// server-side event feed (variables, logs, connection changes, debug traffic); .../stop stores
+// the captured stream as a named recording with the offset of each event from the start.
+// A replay pushes the events of a recording into a separate channel `replay-<id>` at the
+// original pace or accelerated (speed up to 100x), optionally in a loop. The frontend opens that
+// channel like a device. Replayed events are rewritten to the channel's device ID and bypass
+// the event feed, so alerts, scripts, routing rules and webhooks never see them.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tokio::sync::broadcast;
+use tracing::{info, warn};
+
+use crate::device_store::SharedDeviceStore;
+use crate::events::DeviceEvent;
+
+/// Events kept per recording; later events are dropped and the recording is marked truncated
+pub const MAX_EVENTS: usize = 100_000;
+
+/// Longest recording; the capture stops taking events afterwards
+pub const MAX_DURATION_MS: i64 = 4 * 3600 * 1000;
+
+/// Bounds of the replay speed factor
+pub const MIN_SPEED: f64 = 0.1;
+pub const MAX_SPEED: f64 = 100.0;
+
+/// Device ID prefix of replay channels
+pub const REPLAY_CHANNEL_PREFIX: &str = "replay-";
+
+/// Pause between two rounds of a looping replay
+const LOOP_PAUSE: Duration = Duration::from_secs(1);
+
+static ACTIVE: LazyLock<Mutex<HashMap<String, ActiveRecording>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+static REPLAYS: LazyLock<Mutex<HashMap<String, Replay>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Body of POST /api/devices/:id/recording/start
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StartRecordingRequest {
+    /// Defaults to "<device> <start time>"
+    pub name: Option<String>,
+}
+
+/// Body of POST /api/recordings/:id/replay
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StartReplayRequest {
+    /// 1 = original pace, 10 = ten times faster
+    pub speed: Option<f64>,
+    /// Start over after the last event until stopped
+    #[serde(default)]
+    pub repeat: bool,
+}
+
+/// Event with its offset from the start of the recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedEvent {
+    pub offset_ms: i64,
+    pub event: DeviceEvent,
+}
+
+/// Stored recording without its events
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Recording {
+    pub id: String,
+    pub owner_id: String,
+    pub device_id: String,
+    pub name: String,
+    pub started_at: DateTime<Utc>,
+    pub duration_ms: i64,
+    pub event_count: usize,
+    /// MAX_EVENTS or MAX_DURATION_MS was reached
+    pub truncated: bool,
+}
+
+/// Capture in progress (one per device)
+#[derive(Debug, Clone)]
+pub struct ActiveRecording {
+    pub recording: Recording,
+    pub events: Vec<RecordedEvent>,
+}
+
+impl ActiveRecording {
+    fn push(&mut self, event: DeviceEvent, timestamp: i64) {
+        let offset_ms = (timestamp - self.recording.started_at.timestamp_millis()).max(0);
+        if self.events.len() >= MAX_EVENTS || offset_ms > MAX_DURATION_MS {
+            self.recording.truncated = true;
+            return;
+        }
+        self.events.push(RecordedEvent { offset_ms, event });
+        self.recording.event_count = self.events.len();
+    }
+
+    /// Status of the capture (GET /api/devices/:id/recording)
+    pub fn status(&self) -> Recording {
+        let mut recording = self.recording.clone();
+        recording.duration_ms = (Utc::now() - recording.started_at).num_milliseconds();
+        recording
+    }
+}
+
+/// Start capturing a device; fails while another capture of the device runs
+pub fn start_recording(owner_id: &str, device_id: &str, name: &str) -> Result<Recording, String> {
+    let mut active = ACTIVE.lock().unwrap();
+    if let Some(running) = active.get(device_id) {
+        return Err(format!("Device {} is already being recorded ('{}')", device_id, running.recording.name));
+    }
+    let recording = Recording {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: owner_id.to_string(),
+        device_id: device_id.to_string(),
+        name: name.to_string(),
+        started_at: Utc::now(),
+        duration_ms: 0,
+        event_count: 0,
+        truncated: false,
+    };
+    active.insert(device_id.to_string(), ActiveRecording { recording: recording.clone(), events: Vec::new() });
+    Ok(recording)
+}
+
+pub fn active_recording(device_id: &str) -> Option<Recording> {
+    ACTIVE.lock().unwrap().get(device_id).map(ActiveRecording::status)
+}
+
+/// End the capture of a device; the caller stores the result
+pub fn stop_recording(device_id: &str) -> Option<ActiveRecording> {
+    let mut finished = ACTIVE.lock().unwrap().remove(device_id)?;
+    finished.recording.duration_ms = (Utc::now() - finished.recording.started_at).num_milliseconds();
+    Some(finished)
+}
+
+/// Follow the event feed and append events of recorded devices
+pub fn start(device_store: SharedDeviceStore) {
+    let mut feed = device_store.subscribe_events();
+    tokio::spawn(async move {
+        loop {
+            match feed.recv().await {
+                Ok(feed_event) => {
+                    if let Some(recording) = ACTIVE.lock().unwrap().get_mut(&feed_event.device_id) {
+                        recording.push(feed_event.event, feed_event.timestamp);
+                    }
+                }
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    warn!("Recorder lagged behind the event feed, skipped {} events", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    });
+}
+
+// ============================================================================
+// REPLAY
+// ============================================================================
+
+/// Running replay (GET /api/replays)
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayInfo {
+    pub id: String,
+    pub recording_id: String,
+    pub recording_name: String,
+    pub owner_id: String,
+    /// Device ID to subscribe to
+    pub channel: String,
+    pub speed: f64,
+    pub repeat: bool,
+    pub started_at: DateTime<Utc>,
+    pub total_events: usize,
+    pub sent_events: usize,
+    pub rounds: usize,
+}
+
+struct Replay {
+    info: ReplayInfo,
+    sent: Arc<AtomicUsize>,
+    rounds: Arc<AtomicUsize>,
+    task: tokio::task::JoinHandle<()>,
+}
+
+impl Replay {
+    fn info(&self) -> ReplayInfo {
+        ReplayInfo { sent_events: self.sent.load(Ordering::Relaxed), rounds: self.rounds.load(Ordering::Relaxed), ..self.info.clone() }
+    }
+}
+
+/// Event with every `deviceId` field pointing at the replay channel
+pub fn retarget(event: &DeviceEvent, channel: &str) -> Option<DeviceEvent> {
+    let mut value = serde_json::to_value(event).ok()?;
+    if let Some(fields) = value.as_object_mut() {
+        if fields.contains_key("deviceId") {
+            fields.insert("deviceId".to_string(), Value::String(channel.to_string()));
+        }
+    }
+    serde_json::from_value(value).ok()
+}
+
+/// Wait before an event at `offset_ms` when the previous one was at `previous_ms`
+pub fn replay_delay(previous_ms: i64, offset_ms: i64, speed: f64) -> Duration {
+    Duration::from_secs_f64((offset_ms - previous_ms).max(0) as f64 / 1000.0 / speed)
+}
+
+/// Start replaying `events` into a fresh channel
+pub async fn start_replay(device_store: SharedDeviceStore, recording: &Recording, events: Vec<RecordedEvent>, owner_id: &str, speed: f64, repeat: bool) -> ReplayInfo {
+    let id = uuid::Uuid::new_v4().to_string();
+    let channel = format!("{}{}", REPLAY_CHANNEL_PREFIX, &id[..8]);
+    let info = ReplayInfo {
+        id: id.clone(),
+        recording_id: recording.id.clone(),
+        recording_name: recording.name.clone(),
+        owner_id: owner_id.to_string(),
+        channel: channel.clone(),
+        speed,
+        repeat,
+        started_at: Utc::now(),
+        total_events: events.len(),
+        sent_events: 0,
+        rounds: 0,
+    };
+    let sent = Arc::new(AtomicUsize::new(0));
+    let rounds = Arc::new(AtomicUsize::new(0));
+
+    let task = {
+        let (sent, rounds, replay_id) = (sent.clone(), rounds.clone(), id.clone());
+        tokio::spawn(async move {
+            loop {
+                let _ = device_store.clear_device_events(&channel).await;
+                sent.store(0, Ordering::Relaxed);
+                let mut previous_ms = 0;
+                for recorded in &events {
+                    tokio::time::sleep(replay_delay(previous_ms, recorded.offset_ms, speed)).await;
+                    previous_ms = recorded.offset_ms;
+                    let Some(event) = retarget(&recorded.event, &channel) else { continue };
+                    if let Err(e) = device_store.add_event_without_feed(channel.clone(), event, "REPLAY".to_string(), replay_id.clone()).await {
+                        warn!("Replay {} could not publish an event: {}", replay_id, e);
+                    }
+                    sent.fetch_add(1, Ordering::Relaxed);
+                }
+                rounds.fetch_add(1, Ordering::Relaxed);
+                if !repeat {
+                    break;
+                }
+                tokio::time::sleep(LOOP_PAUSE).await;
+            }
+            info!("Replay {} into {} finished", replay_id, channel);
+        })
+    };
+
+    info!("Replaying recording '{}' into {} at {}x", recording.name, info.channel, speed);
+    REPLAYS.lock().unwrap().insert(id, Replay { info: info.clone(), sent, rounds, task });
+    info
+}
+
+/// Replays of a user, including finished ones until they are stopped
+pub fn list_replays(owner_id: &str) -> Vec<ReplayInfo> {
+    let mut replays: Vec<ReplayInfo> = REPLAYS.lock().unwrap().values()
+        .filter(|replay| replay.info.owner_id == owner_id)
+        .map(Replay::info)
+        .collect();
+    replays.sort_by_key(|replay| replay.started_at);
+    replays
+}
+
+pub fn get_replay(replay_id: &str) -> Option<ReplayInfo> {
+    REPLAYS.lock().unwrap().get(replay_id).map(Replay::info)
+}
+
+/// Stop a replay and forget it; returns its channel
+pub fn stop_replay(replay_id: &str) -> Option<String> {
+    let replay = REPLAYS.lock().unwrap().remove(replay_id)?;
+    replay.task.abort();
+    Some(replay.info.channel)
+}
+
+/// Owner of the replay feeding a channel (None for other device IDs)
+pub fn replay_channel_owner(device_id: &str) -> Option<String> {
+    if !device_id.starts_with(REPLAY_CHANNEL_PREFIX) {
+        return None;
+    }
+    REPLAYS.lock().unwrap().values()
+        .find(|replay| replay.info.channel == device_id)
+        .map(|replay| replay.info.owner_id.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_capture_retarget_and_pacing() {
+        let started = start_recording("user-1", "AA-01", "boot sequence").unwrap();
+        assert!(start_recording("user-2", "AA-01", "again").is_err());
+
+        let start_ms = started.started_at.timestamp_millis();
+        let update = DeviceEvent::device_variable_update("AA-01".to_string(), "temp".to_string(), "21".to_string());
+        if let Some(active) = ACTIVE.lock().unwrap().get_mut("AA-01") {
+            active.push(update.clone(), start_ms + 250);
+            active.push(update.clone(), start_ms + MAX_DURATION_MS + 1);
+        }
+        assert_eq!(active_recording("AA-01").unwrap().event_count, 1);
+
+        let finished = stop_recording("AA-01").unwrap();
+        assert!(finished.recording.truncated);
+        assert_eq!(finished.events[0].offset_ms, 250);
+        assert!(active_recording("AA-01").is_none());
+
+        let retargeted = serde_json::to_value(retarget(&finished.events[0].event, "replay-1234abcd").unwrap()).unwrap();
+        assert_eq!(retargeted["deviceId"], "replay-1234abcd");
+        assert_eq!(retargeted["variableValue"], "21");
+
+        assert_eq!(replay_delay(1_000, 3_000, 1.0), Duration::from_secs(2));
+        assert_eq!(replay_delay(1_000, 3_000, 10.0), Duration::from_millis(200));
+        assert_eq!(replay_delay(3_000, 1_000, 1.0), Duration::ZERO);
+        assert!(replay_channel_owner("AA-01").is_none());
+    }
+}
@@ -0,0 +1,8082 @@
+// ============================================================================
+// ROUTES - HTTP/WebSocket router and all REST handlers
+// Used by the server binary (main.rs) and by the integration tests (create_test_app)
+// ============================================================================
+
+// Axum is the web framework for Rust - similar to Express.js for Node.js
+use axum::{
+    body::Body,                     // HTTP Body for responses
+    extract::{Path, State},         // Path for URL parameters, State for global state
+    http::StatusCode,        // HTTP Status Codes (200, 404, etc.)
+    response::{IntoResponse, Response}, // Traits for HTTP responses
+    routing::{get, post, Router},   // HTTP Routing (GET /login, POST /api/register)
+    Json,                           // JSON Parser for API requests/responses
+};
+// Axum Extra for extended features
+use axum_extra::extract::CookieJar; // For reading browser cookies
+
+// Serde for JSON serialization/deserialization
+use serde::Deserialize;
+use serde_json::{json, Value};      // JSON handling
+
+// Standard Rust libraries
+use std::{fs, sync::Arc}; // File system, Arc for thread-safe references
+use pulldown_cmark::{Parser, html}; // Markdown parsing
+
+// Tower for middleware (logging, etc.)
+use tower::ServiceBuilder;
+use tower_http::{
+    services::ServeDir,             // Serve static files (CSS, JS, HTML)
+    trace::TraceLayer,              // HTTP Request Logging
+};
+
+// ============================================================================
+// MODULE IMPORTS - Unsere eigenen Code-Module
+// ============================================================================
+
+use crate::{
+    activity, device_availability, fleet, quarantine, api_error, app_state, auth, idempotency, logging, proxy, outbound, device_trace, device_identity, secrets, diagnostics, config_reload, webhooks, alerts, email, schedules,
+    scripts, routing, command_templates, device_profiles, device_inventory, device_logs, crash_reports, provisioning, recordings, variable_snapshots, organizations, permissions, mentions, file_utils, docs, database, events, event_export, device_store, health, websocket,
+    device_labels, enrollment, device_types, device_manager, device_supervisor, command_queue, device_simulator, mdns_discovery, mdns_server,
+    device_discovery, debug_logger, uart_connection,
+};
+
+// Import all authentication functions from auth.rs
+// These are used for Login/Register/Logout on the website
+use auth::{
+    create_auth_cookie,    // Creates secure HTTP cookies for logged-in users
+    create_jwt,           // Creates JSON Web Tokens for authentication  
+    create_logout_cookie, // Deletes auth cookies on logout
+    revoke_user_sessions, // Invalidates all tokens of a deleted user
+    validate_jwt,         // Checks if JWT token is still valid
+    AuthResponse,         // Struct for API responses (success: true/false, message)
+    LoginRequest,         // Struct for login data from frontend (email, password)
+    RegisterRequest,      // Struct for registration data
+    UpdateDisplayNameRequest, // Struct for display name updates
+    UpdateHandleRequest, // Struct for @handle changes
+    DeleteAccountRequest, // Struct for account deletion (password confirmation)
+    PasswordResetRequest, // Struct for requesting a password reset email
+    PasswordResetConfirmRequest, // Struct for setting a new password with a reset token
+    UpdateNotificationPreferencesRequest, // Struct for email notification settings
+    User,                // User data structure with hashed passwords
+    // A 5.4: Device-Management Imports
+    CreateDeviceRequest, // Request for new device
+    AdoptDeviceRequest,  // Request for adopting a discovered device
+    UpdateDeviceRequest, // Request for device updates
+    UpdateDeviceTagsRequest, // Request for replacing device tags
+    UpdateDeviceLocationRequest, // Request for setting the physical location of a device
+    UpdatePermissionRequest, // Request for permission updates
+    CreateDeviceGroupRequest, // Request for new device group
+    UpdateDeviceGroupRequest, // Request for device group updates
+    DeviceGroupPermissionRequest, // Request for granting a permission through a device group
+    CreateWebhookRequest, // Request for new webhook
+    UpdateWebhookRequest, // Request for webhook updates
+    CreateAlertRuleRequest, // Request for new alert rule
+    UpdateAlertRuleRequest, // Request for alert rule updates
+    CreateScheduleRequest, // Request for new command schedule
+    UpdateScheduleRequest, // Request for command schedule updates
+    CreateScriptRequest, // Request for new automation script
+    UpdateScriptRequest, // Request for automation script updates
+    CreateRoutingRuleRequest, // Request for new device-to-device routing rule
+    UpdateRoutingRuleRequest, // Request for routing rule updates
+    CreateOrganizationRequest, // Request for new organization
+    AddOrgMemberRequest, // Request for adding an organization member
+    UpdateOrgMemberRequest, // Request for changing a member's role
+    MoveDeviceOrgRequest, // Request for moving a device into/out of an organization
+    DefaultPermissionRequest, // Request for the default permission of organization members
+    CreateDeviceProfileRequest, // Request for new device type profile
+    UpdateDeviceProfileRequest, // Request for device type profile updates
+    AssignDeviceProfileRequest, // Request for assigning a profile to a device
+    CreateCommandTemplateRequest, // Request for new command template
+    UpdateCommandTemplateRequest, // Request for command template updates
+    RunTemplateRequest,  // Parameters of a template run
+    BulkCommandRequest,  // Request for a command to many devices
+};
+
+// Import all file handling functions
+// These are used for serving website files
+use file_utils::handle_template_file;
+
+// Import database functions
+use database::{DatabaseManager};
+
+// Import Event Store and WebSocket functions
+use device_store::SharedDeviceStore;
+use device_logs::LogLevel;
+use websocket::{websocket_handler, websocket_stats_handler, health_check_handler, device_users_handler, device_presence_handler, WebSocketState};
+
+// Import centralized AppState
+use app_state::AppState;
+
+// Import uniform REST error responses
+use api_error::{ApiError, ApiJson, OptionalApiJson};
+use outbound::OutboundFeature;
+
+// DEBUG: Simple test handler for WebSocket routing
+async fn debug_websocket_handler() -> Result<String, ApiError> {
+    tracing::error!("DEBUG WebSocket handler called!");
+    Ok("DEBUG: WebSocket handler reached".to_string())
+}
+
+/// CORS for a frontend served from another origin (server.cors_origins); None = same-origin only
+pub fn cors_layer(origins: &[String]) -> Option<tower_http::cors::CorsLayer> {
+    use axum::http::{header, HeaderValue, Method};
+    use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+    if origins.is_empty() {
+        return None;
+    }
+    let layer = CorsLayer::new()
+        .allow_methods([Method::GET, Method::POST, Method::PUT, Method::PATCH, Method::DELETE]);
+
+    // "*" cannot be combined with cookies, so the wildcard allows anonymous requests only
+    if origins.iter().any(|origin| origin == "*") {
+        return Some(layer.allow_origin(Any).allow_headers(Any));
+    }
+    let origins: Vec<HeaderValue> = origins.iter().filter_map(|origin| origin.parse().ok()).collect();
+    Some(layer
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_headers([header::CONTENT_TYPE])
+        .allow_credentials(true))
+}
+
+// ============================================================================
+// APP CREATION - Creates the web router with all routes
+// Website feature: Defines all URLs and their handler functions
+// ============================================================================
+
+#[allow(clippy::too_many_arguments)]
+pub async fn create_app(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, device_manager: Arc<device_manager::DeviceManager>, device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>, mdns_server: Arc<tokio::sync::Mutex<mdns_server::MdnsServer>>, uart_connection: Arc<tokio::sync::Mutex<uart_connection::UartConnection>>, device_simulators: Arc<device_simulator::SimulatorManager>, mailer: Arc<email::Mailer>) -> Router {
+    let mut app = Router::new();
+
+    // AppState for all handlers
+    let app_state = AppState {
+        db: db.clone(),
+        device_store: device_store.clone(),
+        device_manager: device_manager.clone(),
+        device_discovery: device_discovery.clone(),
+        mdns_server: mdns_server.clone(),
+        uart_connection: uart_connection.clone(),
+        device_simulators,
+        mailer,
+    };
+
+    // Scheduled and scripted commands go through the same path as bulk API commands
+    schedules::Scheduler::new(db.clone(), schedule_executor(&app_state)).start();
+    scripts::ScriptEngine::new(db.clone(), device_store.clone(), schedule_executor(&app_state)).start();
+    routing::RoutingEngine::new(db.clone(), device_store.clone(), schedule_executor(&app_state)).start();
+
+    // WebSocket State for WebSocket handlers
+    let websocket_state = WebSocketState {
+        device_store: device_store.clone(),
+        db: db.clone(),
+        device_manager: device_manager.clone(),
+        device_discovery: device_discovery.clone(),
+        uart_connection: uart_connection.clone(),
+    };
+
+    // ========================================
+    // API ROUTES - Backend APIs for frontend
+    // ========================================
+    // These routes are called by JavaScript in the frontend
+    let api_routes = Router::new()
+        // GET /api - Basic info about the API
+        .route("/api", get(api_home))
+
+        // GET /healthz - Liveness probe (process answers HTTP)
+        .route("/healthz", get(healthz_handler))
+
+        // GET /readyz - Readiness probe (database, UDP listener, mDNS, UART)
+        .route("/readyz", get(readyz_handler))
+        
+        // GET /api/users - List all users (currently empty)
+        .route("/api/users", get(api_users))
+        
+        // POST /api/register - Register new user
+        // Called by register.html
+        .route("/api/register", post(register_handler))
+        
+        // POST /api/login - Log in user
+        // Called by login.html
+        .route("/api/login", post(login_handler))
+        
+        // POST /api/logout - Log out user
+        // Called by logout button
+        .route("/api/logout", post(logout_handler))
+        
+        // GET /api/validate-token - Check if user is logged in
+        // Called by app.js for authentication check
+        .route("/api/validate-token", get(validate_token_handler))
+        
+        // GET /api/user-info - Returns user information from JWT
+        // Used for display name display
+        .route("/api/user-info", get(user_info_handler))
+        
+        // PUT /api/profile/display-name - Change display name
+        // Used for profile updates
+        .route("/api/profile/display-name", post(update_display_name_handler))
+
+        // GET/PUT /api/profile/handle - Own @handle for mentions, set or remove it
+        .route("/api/profile/handle", get(get_handle_handler).put(update_handle_handler))
+
+        // DELETE /api/profile - Delete own account (devices are transferred or archived)
+        .route("/api/profile", axum::routing::delete(delete_profile_handler))
+
+        // GET /api/profile/export - All data stored about the logged-in user as JSON
+        .route("/api/profile/export", get(export_profile_handler))
+
+        // GET/PUT /api/profile/notifications - Email notification preferences
+        .route("/api/profile/notifications", get(get_notification_preferences_handler).put(update_notification_preferences_handler))
+
+        // POST /api/profile/notifications/test - Send a test email to the logged-in user
+        .route("/api/profile/notifications/test", post(test_notification_email_handler))
+
+        // POST /api/password-reset - Email a password reset link
+        // Called by reset-password.html
+        .route("/api/password-reset", post(request_password_reset_handler))
+
+        // POST /api/password-reset/confirm - Set a new password with the emailed token
+        .route("/api/password-reset/confirm", post(confirm_password_reset_handler))
+        
+        // ========================================
+        // A 5.4: DEVICE MANAGEMENT API ROUTES
+        // ========================================
+        
+        // GET /api/devices - List all devices of logged-in user
+        .route("/api/devices", get(list_devices_handler))
+        
+        // POST /api/devices - Create new device
+        // (this and the other device mutations marked idempotent() honour an Idempotency-Key header)
+        .route("/api/devices", idempotent(post(create_device_handler)))
+        
+        // GET /api/devices/discovered - List discovered devices (must be before /:id to avoid conflict)
+        .route("/api/devices/discovered", get(discovered_devices_handler))
+
+        // POST /api/devices/adopt - Turn a discovered device into a managed device owned by the caller
+        .route("/api/devices/adopt", idempotent(post(adopt_device_handler)))
+
+        // POST /api/provisioning - Wi-Fi provisioning session: ESP-IDF QR payload/SVG and esp_prov command
+        // POST /api/provisioning/register - Freshly provisioned device registers with the session token
+        .route("/api/provisioning", get(list_provisioning_handler).post(create_provisioning_handler))
+        .route("/api/provisioning/register", post(register_provisioned_device_handler))
+        .route("/api/provisioning/:id", get(get_provisioning_handler).delete(delete_provisioning_handler))
+        .route("/api/provisioning/:id/qr.svg", get(provisioning_qr_handler))
+
+        // GET/POST /api/enrollment-tokens - Pre-shared per-device tokens that auto-adopt the board
+        // DELETE /api/enrollment-tokens/:id - Revoke a token
+        .route("/api/enrollment-tokens", get(list_enrollment_tokens_handler).post(create_enrollment_token_handler))
+        .route("/api/enrollment-tokens/:id", axum::routing::delete(delete_enrollment_token_handler))
+
+        // GET /api/devices/unidentified - UDP senders that could not be mapped to a device (quarantine, admin only)
+        .route("/api/devices/unidentified", get(unidentified_devices_handler))
+
+        // GET /api/devices/summary - Device list merged with live connection state, activity, queue and variables
+        .route("/api/devices/summary", get(devices_summary_handler))
+
+        // GET /api/devices/map?building=&room= - Located devices with live status for the floor plan view
+        .route("/api/devices/map", get(device_map_handler))
+
+        // GET /api/fleet/summary?silent_hours=&top= - Operations overview: status counts, firmware, silent devices, top talkers, alerts
+        .route("/api/fleet/summary", get(fleet_summary_handler))
+
+        // GET /api/fleet/firmware?minimum= - Firmware version histogram and devices below the minimum version
+        .route("/api/fleet/firmware", get(fleet_firmware_handler))
+
+        // GET /api/devices/export?format=csv|json - Device inventory (MAC, name, alias, connection type, owner, tags)
+        .route("/api/devices/export", get(export_device_inventory_handler))
+
+        // POST /api/devices/import?dry_run=&on_conflict=skip|update|fail - Import an inventory (JSON or text/csv)
+        .route("/api/devices/import", post(import_device_inventory_handler))
+
+        // GET /api/devices/:id - Details of an device
+        .route("/api/devices/:id", get(get_device_handler).put(update_device_handler).delete(delete_device_handler))
+
+        // POST /api/device-permissions/:id - Manage permissions for a device
+        .route("/api/device-permissions/:id", idempotent(post(simple_permissions_handler)))
+
+        // POST /api/devices/:id/connect - Connect TCP to device
+        .route("/api/devices/:id/connect", post(tcp_connect_handler))
+
+        // POST /api/devices/:id/disconnect - Disconnect TCP from device
+        .route("/api/devices/:id/disconnect", post(tcp_disconnect_handler))
+
+        // POST /api/devices/:id/reconnect - Reconnect TCP to device
+        .route("/api/devices/:id/reconnect", post(tcp_reconnect_handler))
+
+        // POST /api/devices/:id/unquarantine - Release a device quarantined for exceeding message or error limits
+        .route("/api/devices/:id/unquarantine", post(unquarantine_device_handler))
+
+        // GET/PUT/DELETE /api/devices/:id/tls - TLS settings of the device TCP channel
+        .route("/api/devices/:id/tls", get(get_device_tls_handler).put(update_device_tls_handler).delete(delete_device_tls_handler))
+        .route("/api/devices/:id/identity", get(get_device_identity_handler).put(update_device_identity_handler).delete(delete_device_identity_handler))
+
+        // GET/PUT /api/devices/:id/connection-policy - Reconnect policy and supervisor state
+        .route("/api/devices/:id/connection-policy", get(get_connection_policy_handler).put(update_connection_policy_handler))
+
+        // GET/PATCH /api/devices/:id/connection-settings - Ports, UDP timeout and TCP keep-alive, applied without reconnecting
+        .route("/api/devices/:id/connection-settings", get(get_connection_settings_handler).patch(update_connection_settings_handler))
+
+        // PUT /api/devices/:id/tags - Replace the tags of a device
+        .route("/api/devices/:id/tags", axum::routing::put(update_device_tags_handler))
+
+        // PUT /api/devices/:id/location - Set building, room, bench and coordinates of a device
+        .route("/api/devices/:id/location", axum::routing::put(update_device_location_handler))
+
+        // PUT /api/devices/:id/org - Move a device into an organization or out of it
+        .route("/api/devices/:id/org", axum::routing::put(move_device_org_handler))
+
+        // PUT /api/devices/:id/default-permission - Permission of organization members without a direct one
+        .route("/api/devices/:id/default-permission", axum::routing::put(update_default_permission_handler))
+
+        // GET /api/devices/:id/effective-permission - Effective permission of the caller and where it comes from
+        .route("/api/devices/:id/effective-permission", get(effective_permission_handler))
+
+        // GET /api/devices/:id/qr.png?scale= - Printable QR code label linking to the device page
+        .route("/api/devices/:id/qr.png", get(device_qr_label_handler))
+
+        // GET /api/devices/:id/activity?limit=&before= - Readable activity feed of a device, newest first
+        .route("/api/devices/:id/activity", get(device_activity_handler))
+        // GET /api/devices/:id/availability?window=7d - Uptime %, disconnects per day, mean time between disconnects
+        .route("/api/devices/:id/availability", get(device_availability_handler))
+
+        // GET/PUT /api/devices/:id/profile - Assigned device type profile, assign or remove it
+        .route("/api/devices/:id/profile", get(get_device_profile_assignment_handler).put(assign_device_profile_handler))
+
+        // GET /api/devices/:id/queue - Pending outbound commands of a device
+        .route("/api/devices/:id/queue", get(device_command_queue_handler))
+
+        // POST /api/devices/:id/command?timeout_ms= - Send a command and wait for the reply
+        .route("/api/devices/:id/command", idempotent(post(device_command_handler)))
+
+        // POST /api/devices/bulk/command - Send a command to an explicit list of devices
+        .route("/api/devices/bulk/command", idempotent(post(bulk_command_handler)))
+
+        // GET/POST /api/device-groups - List/create device groups
+        .route("/api/device-groups", get(list_device_groups_handler).post(create_device_group_handler))
+
+        // GET/PUT/DELETE /api/device-groups/:id - Device group details, rename/members, delete
+        .route("/api/device-groups/:id", get(get_device_group_handler).put(update_device_group_handler).delete(delete_device_group_handler))
+
+        // GET/PUT /api/device-groups/:id/permissions - Permissions the group passes on to its devices
+        .route("/api/device-groups/:id/permissions", get(list_device_group_permissions_handler).put(set_device_group_permission_handler))
+
+        // DELETE /api/device-groups/:id/permissions/:user_id - Revoke a group permission
+        .route("/api/device-groups/:id/permissions/:user_id", axum::routing::delete(delete_device_group_permission_handler))
+
+        // POST /api/device-groups/:id/command - Send a command to all devices of a group
+        .route("/api/device-groups/:id/command", idempotent(post(device_group_command_handler)))
+
+        // GET/POST /api/webhooks - Webhooks of the user, create a webhook
+        .route("/api/webhooks", get(list_webhooks_handler).post(create_webhook_handler))
+
+        // GET/PUT/DELETE /api/webhooks/:id - Webhook details, update, delete
+        .route("/api/webhooks/:id", get(get_webhook_handler).put(update_webhook_handler).delete(delete_webhook_handler))
+
+        // GET /api/webhooks/:id/deliveries?limit= - Delivery log (newest first)
+        .route("/api/webhooks/:id/deliveries", get(webhook_deliveries_handler))
+
+        // POST /api/webhooks/:id/test - Send a test event (single attempt)
+        .route("/api/webhooks/:id/test", post(test_webhook_handler))
+
+        // GET/POST /api/alert-rules - Alert rules of the user, create a rule
+        .route("/api/alert-rules", get(list_alert_rules_handler).post(create_alert_rule_handler))
+
+        // GET/PUT/DELETE /api/alert-rules/:id - Rule details, update, delete
+        .route("/api/alert-rules/:id", get(get_alert_rule_handler).put(update_alert_rule_handler).delete(delete_alert_rule_handler))
+
+        // GET /api/alerts?device_id=&state=&limit= - Alerts of the user's rules (newest first)
+        .route("/api/alerts", get(list_alerts_handler))
+
+        // GET/POST /api/schedules - Command schedules of the user, create a schedule
+        .route("/api/schedules", get(list_schedules_handler).post(create_schedule_handler))
+
+        // GET/PUT/DELETE /api/schedules/:id - Schedule details, update, delete
+        .route("/api/schedules/:id", get(get_schedule_handler).put(update_schedule_handler).delete(delete_schedule_handler))
+
+        // GET /api/schedules/:id/runs?limit= - Execution history (newest first)
+        .route("/api/schedules/:id/runs", get(schedule_runs_handler))
+
+        // POST /api/schedules/:id/run - Run a schedule now
+        .route("/api/schedules/:id/run", post(run_schedule_handler))
+
+        // GET/POST /api/scripts - Automation scripts of the user, create a script
+        .route("/api/scripts", get(list_scripts_handler).post(create_script_handler))
+
+        // GET/PUT/DELETE /api/scripts/:id - Script details, update (incl. enable/disable), delete
+        .route("/api/scripts/:id", get(get_script_handler).put(update_script_handler).delete(delete_script_handler))
+
+        // GET /api/scripts/:id/logs?limit= - Script output and errors (newest first)
+        .route("/api/scripts/:id/logs", get(script_logs_handler))
+
+        // POST /api/scripts/:id/test - Dry run with a sample event (commands are not sent)
+        .route("/api/scripts/:id/test", post(test_script_handler))
+
+        // GET /api/recordings?device_id= - Recorded device sessions of the user
+        // GET/DELETE /api/recordings/:id, GET /api/recordings/:id/events - Details, delete, export
+        .route("/api/recordings", get(list_recordings_handler))
+        .route("/api/recordings/:id", get(get_recording_handler).delete(delete_recording_handler))
+        .route("/api/recordings/:id/events", get(recording_events_handler))
+
+        // POST /api/recordings/:id/replay - Replay into a replay-<id> channel ({speed, repeat})
+        // GET /api/replays, GET/DELETE /api/replays/:id - Running replays, stop a replay
+        .route("/api/recordings/:id/replay", post(start_replay_handler))
+        .route("/api/replays", get(list_replays_handler))
+        .route("/api/replays/:id", get(get_replay_handler).delete(stop_replay_handler))
+
+        // GET/POST /api/routing-rules - Device-to-device routing rules of the user, create a rule
+        .route("/api/routing-rules", get(list_routing_rules_handler).post(create_routing_rule_handler))
+
+        // GET/PUT/DELETE /api/routing-rules/:id - Rule details with counters, update, delete
+        .route("/api/routing-rules/:id", get(get_routing_rule_handler).put(update_routing_rule_handler).delete(delete_routing_rule_handler))
+
+        // GET/POST /api/orgs - Organizations of the user, create an organization
+        .route("/api/orgs", get(list_organizations_handler).post(create_organization_handler))
+
+        // GET/DELETE /api/orgs/:id - Organization details with members, delete (owners, no devices left)
+        .route("/api/orgs/:id", get(get_organization_handler).delete(delete_organization_handler))
+
+        // POST /api/orgs/:id/members - Add a member by e-mail (owners/admins)
+        .route("/api/orgs/:id/members", post(add_org_member_handler))
+
+        // PUT/DELETE /api/orgs/:id/members/:user_id - Change a role, remove a member (or leave)
+        .route("/api/orgs/:id/members/:user_id", axum::routing::put(update_org_member_handler).delete(remove_org_member_handler))
+
+        // GET/POST /api/device-profiles - Device type profiles of the user, create a profile
+        .route("/api/device-profiles", get(list_device_profiles_handler).post(create_device_profile_handler))
+
+        // GET/PUT/DELETE /api/device-profiles/:id - Profile details (incl. assigned devices), update, delete
+        .route("/api/device-profiles/:id", get(get_device_profile_handler).put(update_device_profile_handler).delete(delete_device_profile_handler))
+
+        // GET/POST /api/command-templates - Command templates of the user, create a template
+        .route("/api/command-templates", get(list_command_templates_handler).post(create_command_template_handler))
+
+        // GET/PUT/DELETE /api/command-templates/:id - Template details, update, delete
+        .route("/api/command-templates/:id", get(get_command_template_handler).put(update_command_template_handler).delete(delete_command_template_handler))
+
+        // POST /api/devices/:id/run-template/:name?timeout_ms= - Run a template step by step
+        .route("/api/devices/:id/run-template/:name", post(run_command_template_handler))
+
+        // GET /api/devices/:id/events?since=&limit=&cursor= - Paged event replay
+        .route("/api/devices/:id/events", get(device_events_handler))
+
+        // GET /api/devices/:id/events/export?format=csv&from=&to= - Download event history
+        .route("/api/devices/:id/events/export", get(export_device_events_handler))
+
+        // POST /api/devices/:id/trace/start|stop - Record raw frames of a device; GET state / download capture
+        .route("/api/devices/:id/trace", get(device_trace_status_handler))
+        .route("/api/devices/:id/trace/start", post(start_device_trace_handler))
+        .route("/api/devices/:id/trace/stop", post(stop_device_trace_handler))
+        .route("/api/devices/:id/trace/download", get(download_device_trace_handler))
+
+        // GET /api/devices/:id/variables - Current value of every variable (no event replay needed)
+        .route("/api/devices/:id/variables", get(device_variables_handler))
+
+        // GET/POST /api/devices/:id/snapshots - Saved variable states of the device, capture the current one
+        // GET/DELETE /api/devices/:id/snapshots/:sid - Snapshot with its diff against the current values
+        // POST /api/devices/:id/snapshots/:sid/apply - Restore via setVariable ({dryRun: true} = preview)
+        .route("/api/devices/:id/snapshots", get(list_variable_snapshots_handler).post(create_variable_snapshot_handler))
+        .route("/api/devices/:id/snapshots/:sid", get(get_variable_snapshot_handler).delete(delete_variable_snapshot_handler))
+        .route("/api/devices/:id/snapshots/:sid/apply", post(apply_variable_snapshot_handler))
+
+        // GET /api/devices/:id/logs?level=warn&limit=&since= - Log lines the device sent ({"log": {...}} frames)
+        .route("/api/devices/:id/logs", get(device_logs_handler))
+
+        // POST /api/devices/:id/crash-report - Reset reason, backtrace and core dump sent by the device
+        // GET  /api/devices/:id/crash-reports?reason=&limit= - Reports and counts per reset reason
+        .route("/api/devices/:id/crash-report", post(ingest_crash_report_handler).layer(axum::extract::DefaultBodyLimit::max(crash_reports::MAX_UPLOAD_BYTES)))
+        .route("/api/devices/:id/crash-reports", get(list_crash_reports_handler))
+        .route("/api/devices/:id/crash-reports/:report_id/download", get(download_crash_report_handler))
+
+        // POST /api/devices/:id/recording/start|stop - Capture the device's event stream as a named recording
+        .route("/api/devices/:id/recording", get(device_recording_status_handler))
+        .route("/api/devices/:id/recording/start", post(start_device_recording_handler))
+        .route("/api/devices/:id/recording/stop", post(stop_device_recording_handler))
+
+        // GET /api/devices/:id/capabilities - Cached variables, start options, firmware and features
+        .route("/api/devices/:id/capabilities", get(device_capabilities_handler))
+
+        // GET /api/devices/clocks - Clock reports and drift estimates of all devices
+        .route("/api/devices/clocks", get(device_clocks_handler))
+
+        // GET /api/devices/:id/clock - Uptime, boot time, offset and drift of the device clock
+        .route("/api/devices/:id/clock", get(device_clock_handler))
+
+        // POST /api/devices/:id/clock/sync?timeout_ms= - Push the server time to the device (setTime)
+        .route("/api/devices/:id/clock/sync", post(sync_device_clock_handler))
+        
+        // GET /api/users/search - Search for users for permission management
+        .route("/api/users/search", get(search_users_handler))
+        
+        // GET /api/users/list - Get first users for scroll field
+        .route("/api/users/list", get(list_users_handler))
+        
+        // GET /api/docs - Get documentation content for SPA (HTML, or JSON with Accept: application/json / ?format=json)
+        .route("/api/docs", get(api_docs_handler))
+        // GET /api/docs/:path - Get specific documentation files
+        .route("/api/docs/*path", get(api_docs_file_handler))
+        // GET /api/docs-index - Available documentation pages with titles
+        .route("/api/docs-index", get(api_docs_index_handler))
+
+        // ========================================
+        // UART SETTINGS API ROUTES
+        // ========================================
+
+        // GET /api/uart/settings - Get current UART settings
+        .route("/api/uart/settings", get(get_uart_settings_handler))
+
+        // POST /api/uart/settings - Update UART settings
+        .route("/api/uart/settings", post(update_uart_settings_handler))
+
+        // GET /api/uart/ports - List available serial ports
+        .route("/api/uart/ports", get(list_uart_ports_handler))
+
+        // POST /api/uart/connect - Connect to UART port
+        .route("/api/uart/connect", post(uart_connect_handler))
+
+        // POST /api/uart/disconnect - Disconnect from UART port
+        .route("/api/uart/disconnect", post(uart_disconnect_handler))
+
+        // GET /api/uart/status - Get UART connection status
+        .route("/api/uart/status", get(uart_status_handler))
+
+        // ========================================
+        // DEBUG SETTINGS API ROUTES
+        // ========================================
+
+        // GET /api/debug/settings - Get current debug settings
+        .route("/api/debug/settings", get(get_debug_settings_handler).post(update_debug_settings_handler))
+        // GET /api/debug/logs - Debug bus entries after a cursor (admin only)
+        .route("/api/debug/logs", get(debug_logs_handler))
+        // GET/POST /api/udp/settings - UDP listener ports (applied on restart, admin only)
+        .route("/api/udp/settings", get(get_udp_settings_handler).post(update_udp_settings_handler))
+
+        // GET /api/mdns/advertisement - Own mDNS advertisement status
+        .route("/api/mdns/advertisement", get(get_mdns_advertisement_handler))
+        // GET/POST /api/discovery/settings - Aging of discovery results (admin only)
+        .route("/api/discovery/settings", get(get_discovery_settings_handler).post(update_discovery_settings_handler))
+
+        // POST /api/discovery/scan - On-demand UDP broadcast sweep (results also streamed via WebSocket)
+        .route("/api/discovery/scan", post(discovery_scan_handler))
+
+        // POST /api/discovery/tcp-scan - Scan the configured CIDR range once
+        .route("/api/discovery/tcp-scan", post(tcp_scan_handler))
+
+        // GET/POST /api/discovery/tcp-scan/settings - Opt-in periodic TCP range scan
+        .route("/api/discovery/tcp-scan/settings", get(get_tcp_scan_settings_handler).post(update_tcp_scan_settings_handler))
+
+        // GET/POST /api/discovery/mdns-services - Browsed mDNS service types (applied on restart, admin only)
+        .route("/api/discovery/mdns-services", get(get_mdns_services_handler).post(update_mdns_services_handler))
+
+        // GET/POST /api/github/settings - GitHub token management
+        .route("/api/github/settings", get(get_github_settings_handler).post(update_github_settings_handler))
+
+        // ========================================
+        // ADMIN API ROUTES
+        // ========================================
+
+        // GET/POST /api/admin/simulators - List or start simulated devices
+        .route("/api/admin/simulators", get(list_simulators_handler).post(start_simulator_handler))
+
+        // DELETE /api/admin/simulators/:id - Stop a simulated device
+        .route("/api/admin/simulators/:id", axum::routing::delete(stop_simulator_handler))
+
+        // GET /api/admin/outbound - Offline mode, proxy and state of webhooks/email/firmware downloads
+        .route("/api/admin/outbound", get(outbound_status_handler))
+        // GET /api/admin/secrets - Encryption of stored secrets; POST .../rotate - New key, re-seal all values
+        .route("/api/admin/secrets", get(secrets_status_handler))
+        // GET /api/admin/diagnostics - Self-test: ports, multicast, serial access, database, disk space, clock
+        .route("/api/admin/diagnostics", get(diagnostics_handler))
+        // POST /api/admin/backup - Copy of the database into backups/ next to it
+        .route("/api/admin/backup", post(backup_handler))
+        // POST /api/admin/reload-config - Re-read the config file, apply log level, discovery toggles, timeouts, rate limits
+        .route("/api/admin/reload-config", post(reload_config_handler))
+        .route("/api/admin/secrets/rotate", post(rotate_secrets_handler))
+
+        // POST /api/firmware/upload - Upload .bin firmware file
+        .route("/api/firmware/upload", idempotent(post(firmware_upload_handler)))
+
+        // POST /api/firmware/fetch - Download .bin firmware from a URL (e.g. GitHub Release)
+        .route("/api/firmware/fetch", idempotent(post(firmware_fetch_handler)))
+
+        // with_state() gives all API routes access to both stores
+        .with_state(app_state);
+
+    // ========================================
+    // WEBSOCKET ROUTES - A 5.5 Multiuser Support
+    // ========================================
+    let websocket_routes = Router::new()
+        // WebSocket endpoint for Canvas Events - A 5.5 requirement: ws://.../channel/
+        .route("/channel", get(websocket_handler))
+        
+        // Debug endpoint to test routing
+        .route("/channel/debug", get(debug_websocket_handler))
+        
+        // WebSocket statistics endpoint for monitoring/debugging (admin only)
+        .route("/api/websocket/stats", get(websocket_stats_handler))
+
+        // Health check endpoint with event storage metrics
+        .route("/api/health", get(health_check_handler))
+
+        // Get users connected to a device
+        .route("/api/devices/:device_id/users", get(device_users_handler))
+
+        // GET /api/devices/:id/presence - Users with idle/active status and cursors
+        .route("/api/devices/:device_id/presence", get(device_presence_handler))
+
+        .with_state(websocket_state);
+
+    // Add API routes to main router
+    app = app.merge(api_routes);
+    
+    // Add WebSocket routes to main router
+    app = app.merge(websocket_routes);
+
+    // Handle outdated hash URLs - redirect middleware would go here
+    // For now, we'll handle this in the catch-all
+
+    // Serve static files from 'public' directory (no hash versioning)
+    app = app.nest_service("/stylesheets", ServeDir::new("public/stylesheets"));
+
+    // Serve firmware .bin files for ESP32 OTA download (streamed, resumable via Range)
+    app = app.route("/firmware/:name", get(serve_firmware_file));
+
+    // SPA routes - all serve the same index.html shell
+    app = app
+        .route("/index.html", get(serve_spa_route))
+        .route("/login.html", get(serve_spa_route))
+        .route("/register.html", get(serve_spa_route))
+        .route("/reset-password.html", get(serve_spa_route))
+        .route("/debug.html", get(serve_spa_route))
+        .route("/hallo.html", get(serve_spa_route))
+        .route("/about.html", get(serve_spa_route))
+        .route("/drawing_board.html", get(serve_spa_route))
+        .route("/drawer_page.html", get(serve_spa_route))
+        .route("/device_control.html", get(serve_spa_route))
+        .route("/docs.html", get(serve_spa_route))
+        .route("/settings.html", get(serve_spa_route));
+
+
+    // Serve static files directly from 'client' directory with development-friendly caching
+    app = app.nest_service("/templates", ServeDir::new("client/templates"));
+    app = app.route("/scripts/*path", get(serve_script_file));
+    app = app.route("/styles/*path", get(serve_style_file));
+    
+    // Note: /docs is now handled as SPA route, markdown API available at /api/docs
+
+
+    // Root path serves SPA
+    app = app.route("/", get(serve_spa_route));
+
+    // Serve remaining static files from client with development-friendly caching
+    app = app
+        .route("/index.css", get(|| serve_dev_static_file("client/index.css", "text/css", "max-age=0, must-revalidate")))
+        .route("/app.js", get(|| serve_dev_static_file("client/app.js", "text/javascript", "max-age=0, must-revalidate")));
+
+    // SPA routes for specific paths
+    app = app
+        .route("/login", get(serve_spa_route))
+        .route("/register", get(serve_spa_route))
+        .route("/reset-password", get(serve_spa_route))
+        .route("/hallo", get(serve_spa_route))
+        .route("/about", get(serve_spa_route))
+        .route("/drawing_board", get(serve_spa_route))
+        .route("/drawer_page", get(serve_spa_route))
+        .route("/debug", get(serve_spa_route))
+        .route("/index", get(serve_spa_route))
+        .route("/docs", get(serve_spa_route))
+        .route("/settings", get(serve_spa_route));
+
+    // Device routes - more specific to avoid catching static files
+    app = app
+        .route("/devices", get(serve_spa_route))
+        .route("/devices/:device_id", get(serve_spa_route));
+
+    // Add middleware (request log with the real client address behind proxies)
+    app = app.layer(
+        ServiceBuilder::new()
+            .layer(TraceLayer::new_for_http().make_span_with(|request: &axum::extract::Request| {
+                let client = request.extensions().get::<proxy::ClientInfo>()
+                    .map(|client| client.ip.to_string())
+                    .unwrap_or_default();
+                let request_id = request.extensions().get::<logging::RequestId>()
+                    .map(|id| id.0.as_str())
+                    .unwrap_or_default();
+                tracing::info_span!("request", request_id = %request_id, method = %request.method(), uri = %request.uri(), client = %client)
+            }))
+    );
+
+    app
+}
+
+/// Route whose retries with the same Idempotency-Key header are answered from the idempotency store
+fn idempotent(route: axum::routing::MethodRouter<AppState>) -> axum::routing::MethodRouter<AppState> {
+    route.layer(axum::middleware::from_fn(idempotency::idempotency_middleware))
+}
+
+// ============================================================================
+// API HANDLER FUNCTIONS - These functions process HTTP requests
+// ============================================================================
+
+// GET /healthz - Liveness probe for Docker/Kubernetes
+// Only proves that the HTTP server answers; components are checked by /readyz
+pub async fn healthz_handler() -> Json<Value> {
+    Json(json!({
+        "status": "ok",
+        "version": env!("CARGO_PKG_VERSION")
+    }))
+}
+
+// GET /readyz - Readiness probe: 200 if all components are up (or disabled), 503 otherwise
+async fn readyz_handler(State(app_state): State<AppState>) -> (StatusCode, Json<health::ReadinessReport>) {
+    let report = health::check_readiness(&app_state).await;
+    if !report.ready {
+        tracing::warn!("Readiness check failed: {:?}", report.components);
+    }
+    let status = if report.ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(report))
+}
+
+// GET /api - Basic API info
+// Website feature: API documentation/status
+async fn api_home() -> Json<Value> {
+    // json!() is a macro that creates JSON
+    Json(json!({ "title": "Express" }))
+}
+
+// GET /api/users - List all users  
+// Website feature: User management (currently not used)
+async fn api_users() -> Json<Value> {
+    Json(json!({ "users": [] }))
+}
+
+// SPA route handler - always serves the main SPA shell (index.html)
+async fn serve_spa_route() -> Response<Body> {
+    // HTML sollte nicht gecacht werden, damit SPA-Updates funktionieren
+    let response = handle_template_file("client/index.html", "no-cache, must-revalidate").await;
+
+    // Hinter einem Reverse Proxy mit Präfix: absolute Links umschreiben und den Pfad ans Frontend geben
+    let base_path = proxy::base_path();
+    if base_path.is_empty() || !response.status().is_success() {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+        return Response::from_parts(parts, Body::empty());
+    };
+    let html = String::from_utf8_lossy(&bytes)
+        .replace("href=\"/", &format!("href=\"{}/", base_path))
+        .replace("src=\"/", &format!("src=\"{}/", base_path))
+        .replacen("<head>", &format!("<head>\n  <meta name=\"base-path\" content=\"{}\">", base_path), 1);
+    let mut response = Response::from_parts(parts, Body::from(html));
+    response.headers_mut().remove("content-length");
+    response
+}
+
+// Handler für JavaScript-Dateien mit entwicklungsfreundlichem Caching
+async fn serve_script_file(axum::extract::Path(path): axum::extract::Path<String>) -> Response<Body> {
+    let file_path = format!("client/scripts/{}", path);
+    serve_dev_static_file(&file_path, "text/javascript", "max-age=0, must-revalidate").await
+}
+
+// Handler für CSS-Dateien mit entwicklungsfreundlichem Caching
+async fn serve_style_file(axum::extract::Path(path): axum::extract::Path<String>) -> Response<Body> {
+    let file_path = format!("client/styles/{}", path);
+    serve_dev_static_file(&file_path, "text/css", "max-age=0, must-revalidate").await
+}
+
+// Allgemeine Funktion für statische Dateien mit entwicklungsfreundlichem Caching
+async fn serve_dev_static_file(file_path: &str, content_type: &str, cache_control: &str) -> Response<Body> {
+    match std::fs::read_to_string(file_path) {
+        Ok(contents) => {
+            Response::builder()
+                .header("content-type", content_type)
+                .header("cache-control", cache_control)
+                .body(Body::from(contents))
+                .unwrap()
+        }
+        Err(_) => {
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("File not found"))
+                .unwrap()
+        }
+    }
+}
+
+
+
+
+#[derive(Debug, Deserialize)]
+struct DocsQuery {
+    format: Option<String>,
+}
+
+/// JSON instead of the HTML document: ?format=json or an Accept header preferring JSON
+fn docs_wants_json(headers: &axum::http::HeaderMap, query: &DocsQuery) -> bool {
+    if let Some(format) = &query.format {
+        return format.eq_ignore_ascii_case("json");
+    }
+    headers.get(axum::http::header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/json") && !accept.contains("text/html"))
+}
+
+// GET /api/docs - Get main documentation content for SPA
+async fn api_docs_handler(
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<DocsQuery>,
+) -> Response<Body> {
+    if docs_wants_json(&headers, &query) {
+        return docs_json_response("").await;
+    }
+    serve_markdown_file("docs/README.md").await.into_response()
+}
+
+/// Rendered page as {page, title, html, toc, links}
+async fn docs_json_response(path: &str) -> Response<Body> {
+    let Some((page, file_path)) = docs::resolve_page(path) else {
+        return ApiError::not_found("Documentation page not found").into_response();
+    };
+    match tokio::fs::read_to_string(&file_path).await {
+        Ok(markdown) => {
+            let rendered = docs::render(&page, &markdown);
+            let mut body = json!(rendered);
+            body["success"] = json!(true);
+            Json(body).into_response()
+        }
+        Err(_) => ApiError::not_found("Documentation page not found").with_details(json!({ "page": page })).into_response(),
+    }
+}
+
+// GET /api/docs-index - Markdown pages below docs/ with their first heading as title
+async fn api_docs_index_handler() -> Json<Value> {
+    let pages = tokio::task::spawn_blocking(docs::list_pages).await.unwrap_or_default();
+    Json(json!({ "success": true, "pages": pages }))
+}
+
+// GET /firmware/:name - Firmware image for OTA updates, supports Range for resumed downloads
+async fn serve_firmware_file(Path(name): Path<String>, headers: axum::http::HeaderMap) -> Response<Body> {
+    match file_utils::safe_file_name(&name) {
+        Some(name) => file_utils::serve_file(&format!("firmware/{}", name), "application/octet-stream", "no-cache", &headers).await,
+        None => StatusCode::NOT_FOUND.into_response(),
+    }
+}
+
+// GET /api/docs/:path - Get specific documentation files
+// Attachments (images, PDFs, ...) are streamed as they are, everything else is rendered markdown
+async fn api_docs_file_handler(
+    Path(path): Path<String>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<DocsQuery>,
+) -> Response<Body> {
+    let is_attachment = std::path::Path::new(&path).extension().is_some_and(|ext| ext != "md");
+    if is_attachment {
+        if path.split('/').any(|part| part == ".." || part.starts_with('.')) {
+            return StatusCode::NOT_FOUND.into_response();
+        }
+        let file_path = format!("docs/{}", path);
+        return file_utils::serve_file(&file_path, file_utils::content_type_for(&file_path), "max-age=300", &headers).await;
+    }
+
+    if docs_wants_json(&headers, &query) {
+        return docs_json_response(&path).await;
+    }
+
+    let file_path = if path.is_empty() || path == "/" {
+        "docs/README.md".to_string()
+    } else if path.ends_with('/') {
+        format!("docs/{}README.md", path)
+    } else if path.ends_with(".md") {
+        format!("docs/{}", path)
+    } else {
+        format!("docs/{}.md", path)
+    };
+    
+    tracing::debug!("API docs request: path='{}' -> file_path='{}'", path, file_path);
+    
+    serve_markdown_file(&file_path).await.into_response()
+}
+
+// Common markdown file serving function
+async fn serve_markdown_file(file_path: &str) -> impl IntoResponse {
+    match fs::read_to_string(file_path) {
+        Ok(markdown_content) => {
+            let parser = Parser::new(&markdown_content);
+            let mut html_output = String::new();
+            html::push_html(&mut html_output, parser);
+
+            let full_html = format!(
+                r#"<!DOCTYPE html>
+<html lang="de">
+<head>
+    <meta charset="UTF-8">
+    <meta name="viewport" content="width=device-width, initial-scale=1.0">
+    <title>Dokumentation</title>
+    <style>
+        body {{ 
+            font-family: -apple-system, BlinkMacSystemFont, "Segoe UI", Roboto, sans-serif;
+            line-height: 1.6;
+            color: #333;
+            max-width: 900px;
+            margin: 0 auto;
+            padding: 20px;
+            background: #fff;
+        }}
+        pre {{ 
+            background: #f4f4f4;
+            padding: 15px;
+            border-radius: 5px;
+            overflow-x: auto;
+        }}
+        code {{ 
+            background: #f4f4f4;
+            padding: 2px 5px;
+            border-radius: 3px;
+            font-family: 'Monaco', 'Consolas', monospace;
+        }}
+        h1, h2, h3 {{ 
+            border-bottom: 1px solid #eee;
+            padding-bottom: 10px;
+        }}
+        blockquote {{
+            border-left: 4px solid #ddd;
+            margin: 0;
+            padding-left: 20px;
+            color: #666;
+        }}
+        table {{
+            border-collapse: collapse;
+            width: 100%;
+            margin: 20px 0;
+        }}
+        th, td {{
+            border: 1px solid #ddd;
+            padding: 8px;
+            text-align: left;
+        }}
+        th {{
+            background-color: #f2f2f2;
+        }}
+        a {{
+            color: #0066cc;
+            text-decoration: none;
+        }}
+        a:hover {{
+            text-decoration: underline;
+        }}
+    </style>
+</head>
+<body>
+    <div style="margin-bottom: 20px;">
+        <a href="/docs/">← Zurück zur Dokumentation</a> | 
+        <a href="/" target="_blank">← Zurück zur App</a>
+    </div>
+    {}
+</body>
+</html>"#,
+                html_output
+            );
+
+            Response::builder()
+                .header("content-type", "text/html; charset=utf-8")
+                .body(Body::from(full_html))
+                .unwrap()
+        }
+        Err(_) => {
+            Response::builder()
+                .status(StatusCode::NOT_FOUND)
+                .body(Body::from("Dokumentation nicht gefunden"))
+                .unwrap()
+        }
+    }
+}
+
+
+
+
+// ============================================================================
+// AUTHENTICATION HANDLERS - Functions for Login/Register/Logout
+// Website feature: User registration and login
+// ============================================================================
+
+// POST /api/register - Register new user
+// Called when someone submits the registration form
+async fn register_handler(
+    // State(app_state) extracts the global app state from the request
+    State(app_state): State<AppState>,
+    // Real client behind a reverse proxy (HTTPS -> Secure cookie)
+    client: proxy::ClientInfo,
+    // ApiJson(req) parses the JSON request body into RegisterRequest struct (400 on invalid JSON)
+    ApiJson(req): ApiJson<RegisterRequest>,
+) -> Result<Response<Body>, ApiError> {  // Return: HTTP Response or error
+    
+    tracing::info!("Registration attempt for email: {}", req.email);
+    tracing::debug!("Register request received: {:?}", req.email);
+    
+    // Step 1: Check if user already exists
+    match app_state.db.get_user_by_email(&req.email).await {
+        Ok(Some(_)) => {
+            tracing::warn!("Registration failed: User {} already exists", req.email);
+            return Err(ApiError::conflict("User already exists"));
+        }
+        Ok(None) => {
+            // User does not exist - continue with registration
+        }
+        Err(e) => {
+            tracing::error!("Database error during user lookup: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    }
+
+    // Optional handle: valid and not taken, stored after the user exists
+    let handle = match req.handle.as_deref().filter(|handle| !handle.trim().is_empty()) {
+        Some(handle) => {
+            let handle = mentions::normalize_handle(handle).map_err(ApiError::bad_request)?;
+            if handle_taken(&app_state, &handle).await? {
+                return Err(ApiError::conflict("Handle is already taken"));
+            }
+            Some(handle)
+        }
+        None => None,
+    };
+
+    // Step 2: Create new DatabaseUser
+    tracing::debug!("Creating new user with hashed password");
+    let db_user = match database::DatabaseUser::new(req.email.clone(), req.display_name.clone(), &req.password) {
+        Ok(user) => user,
+        Err(e) => {
+            tracing::error!("User creation failed for {}: {:?}", req.email, e);
+            return Err(ApiError::internal("Internal server error"));
+        }
+    };
+
+    // Step 3: Save user to database
+    if let Err(e) = app_state.db.create_user(db_user.clone()).await {
+        tracing::error!("Database error during user creation: {:?}", e);
+        return Err(ApiError::internal("Database error"));
+    }
+    if let Some(handle) = &handle {
+        // Taken in the meantime: the account stays, the handle can be chosen again later
+        match app_state.db.set_user_handle(&db_user.id, Some(handle)).await {
+            Ok(true) => {}
+            Ok(false) => tracing::warn!("Handle {} was taken during registration of {}", handle, req.email),
+            Err(e) => tracing::error!("Database error storing handle of {}: {:?}", req.email, e),
+        }
+    }
+
+    // Step 4: Convert user for JWT
+    let user = User {
+        id: db_user.id.clone(),
+        email: db_user.email.clone(),
+        display_name: db_user.display_name.clone(),
+        password_hash: db_user.password_hash.clone(),
+    };
+
+    // Step 5: Create JWT token (auto-login after registration)
+    tracing::debug!("Creating JWT token for new user");
+    match create_jwt(&user) {
+        Ok(token) => {
+            tracing::info!("Registration successful for user: {}", req.email);
+            let response = AuthResponse {
+                success: true,
+                message: "User registered successfully".to_string(),
+                email: Some(req.email.clone()),
+            };
+
+            Response::builder()
+                .header("set-cookie", create_auth_cookie(&token, client.https))
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_string(&response).unwrap()))
+                .map_err(|_| ApiError::internal("Failed to build response"))
+        }
+        Err(e) => {
+            tracing::error!("JWT creation failed for {}: {:?}", req.email, e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn login_handler(
+    State(app_state): State<AppState>,
+    client: proxy::ClientInfo,
+    ApiJson(req): ApiJson<LoginRequest>,
+) -> Result<Response<Body>, ApiError> {
+    
+    tracing::info!("Login attempt for email: {} from {}", req.email, client.ip);
+    tracing::debug!("Login request received for: {}", req.email);
+    
+    // Search for user in database
+    let db_user = match app_state.db.get_user_by_email(&req.email).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            tracing::warn!("Login failed: User {} not found", req.email);
+            return Err(ApiError::unauthorized("Invalid credentials"));
+        }
+        Err(e) => {
+            tracing::error!("Database error during login for {}: {:?}", req.email, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    tracing::debug!("User found in database: {}", req.email);
+    
+    // Verify password
+    match db_user.verify_password(&req.password) {
+        Ok(true) => {
+            tracing::debug!("Password verification successful");
+            
+            // Convert user for JWT
+            let user = User {
+                id: db_user.id.clone(),
+                email: db_user.email.clone(),
+                display_name: db_user.display_name.clone(),
+                password_hash: db_user.password_hash.clone(),
+            };
+            
+            // Create JWT token
+            match create_jwt(&user) {
+                Ok(token) => {
+                    tracing::info!("Login successful for user: {}", req.email);
+                    let response = AuthResponse {
+                        success: true,
+                        message: "Login successful".to_string(),
+                        email: Some(req.email.clone()),
+                    };
+
+                    Response::builder()
+                        .header("set-cookie", create_auth_cookie(&token, client.https))
+                        .header("content-type", "application/json")
+                        .body(Body::from(serde_json::to_string(&response).unwrap()))
+                        .map_err(|_| ApiError::internal("Failed to build response"))
+                }
+                Err(e) => {
+                    tracing::error!("JWT creation failed during login for {}: {:?}", req.email, e);
+                    Err(ApiError::internal("Internal server error"))
+                }
+            }
+        }
+        Ok(false) => {
+            tracing::warn!("Login failed: Invalid password for {}", req.email);
+            Err(ApiError::unauthorized("Invalid credentials"))
+        }
+        Err(e) => {
+            tracing::error!("Password verification error for {}: {:?}", req.email, e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn logout_handler(client: proxy::ClientInfo) -> Response<Body> {
+    let response = AuthResponse {
+        success: true,
+        message: "Logged out successfully".to_string(),
+        email: None,
+    };
+
+    Response::builder()
+        .header("set-cookie", create_logout_cookie(client.https))
+        .header("content-type", "application/json")
+        .body(Body::from(serde_json::to_string(&response).unwrap()))
+        .unwrap()
+}
+
+async fn validate_token_handler(cookie_jar: CookieJar) -> StatusCode {
+    // Always return OK since authentication is now optional
+    // The frontend can continue to use this endpoint to check authentication
+    // but it will always succeed allowing access without login
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    
+    match token {
+        Some(token_value) => {
+            // If there is a token, validate it
+            match validate_jwt(token_value) {
+                Ok(_) => StatusCode::OK,
+                Err(_) => StatusCode::OK, // Even invalid tokens are OK now (guest access)
+            }
+        }
+        None => StatusCode::OK, // No token is also OK (guest access)
+    }
+}
+
+// GET /api/user-info - Returns user information from JWT (optional auth)
+// Website feature: Display name display in frontend
+async fn user_info_handler(cookie_jar: CookieJar) -> Result<Json<Value>, ApiError> {
+    // Extract JWT token from cookie (optional)
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+
+    match token {
+        Some(token_value) => {
+            // If token exists, validate and return user info
+            match validate_jwt(token_value) {
+                Ok(claims) => {
+                    Ok(Json(json!({
+                        "success": true,
+                        "authenticated": true,
+                        "user_id": claims.user_id,
+                        "display_name": claims.display_name,
+                        "canvas_permissions": claims.device_permissions
+                    })))
+                }
+                Err(_) => {
+                    // Invalid token, return guest user
+                    Ok(Json(json!({
+                        "success": true,
+                        "authenticated": false,
+                        "user_id": "guest",
+                        "display_name": "Guest User",
+                        "canvas_permissions": {}
+                    })))
+                }
+            }
+        }
+        None => {
+            // No token, return guest user
+            Ok(Json(json!({
+                "success": true,
+                "authenticated": false,
+                "user_id": "guest",
+                "display_name": "Guest User",
+                "canvas_permissions": {}
+            })))
+        }
+    }
+}
+
+/// Is a (normalized) handle held by any user?
+async fn handle_taken(app_state: &AppState, handle: &str) -> Result<bool, ApiError> {
+    let holder = app_state.db.get_user_id_by_handle(handle).await.map_err(|e| {
+        tracing::error!("Database error looking up handle {}: {:?}", handle, e);
+        ApiError::internal("Database error")
+    })?;
+    Ok(holder.is_some())
+}
+
+// GET /api/profile/handle - Own @handle (null when none is set)
+async fn get_handle_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let handle = app_state.db.get_user_handle(&claims.user_id).await.map_err(|e| {
+        tracing::error!("Database error loading handle of {}: {:?}", claims.user_id, e);
+        ApiError::internal("Database error")
+    })?;
+    Ok(Json(json!({ "success": true, "handle": handle })))
+}
+
+// PUT /api/profile/handle - Set, change or remove (null/empty) the own @handle
+async fn update_handle_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdateHandleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let handle = match req.handle.as_deref().filter(|handle| !handle.trim().is_empty()) {
+        Some(handle) => Some(mentions::normalize_handle(handle).map_err(ApiError::bad_request)?),
+        None => None,
+    };
+
+    let stored = app_state.db.set_user_handle(&claims.user_id, handle.as_deref()).await.map_err(|e| {
+        tracing::error!("Database error updating handle of {}: {:?}", claims.user_id, e);
+        ApiError::internal("Database error")
+    })?;
+    if !stored {
+        return Err(ApiError::conflict("Handle is already taken"));
+    }
+    tracing::info!("Handle of user {} set to {:?}", claims.user_id, handle);
+    Ok(Json(json!({ "success": true, "handle": handle })))
+}
+
+// POST /api/profile/display-name - Change display name
+// Website feature: Allows users to change their display name
+async fn update_display_name_handler(
+    State(app_state): State<AppState>,
+    client: proxy::ClientInfo,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdateDisplayNameRequest>,
+) -> Result<Response<Body>, ApiError> {
+    // Extract JWT token from cookie
+    let token = match cookie_jar.get("auth_token") {
+        Some(cookie) => cookie.value(),
+        None => return Err(ApiError::unauthorized("Authentication required")),
+    };
+
+    // Validate JWT and extract claims
+    let claims = match validate_jwt(token) {
+        Ok(claims) => claims,
+        Err(_) => return Err(ApiError::unauthorized("Invalid or expired token")),
+    };
+
+    // Validate display name (not empty, max 50 characters)
+    if req.display_name.trim().is_empty() || req.display_name.len() > 50 {
+        return Err(ApiError::bad_request("Display name must be between 1 and 50 characters"));
+    }
+
+    // Update display name in database
+    if let Err(e) = app_state.db.update_user_display_name(&claims.user_id, req.display_name.trim()).await {
+        tracing::error!("Database error during display name update: {:?}", e);
+        return Err(ApiError::internal("Database error"));
+    }
+
+    tracing::info!("Display name updated for user: {}", claims.email);
+
+    // Load updated user from database
+    let updated_db_user = match app_state.db.get_user_by_id(&claims.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => {
+            tracing::error!("User {} not found in database after update", claims.user_id);
+            return Err(ApiError::not_found("User not found"));
+        }
+        Err(e) => {
+            tracing::error!("Database error loading updated user: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    // Convert user for JWT
+    let user = User {
+        id: updated_db_user.id.clone(),
+        email: updated_db_user.email.clone(),
+        display_name: updated_db_user.display_name.clone(),
+        password_hash: updated_db_user.password_hash.clone(),
+    };
+
+    // Create new JWT with updated display name
+    match create_jwt(&user) {
+        Ok(new_token) => {
+            let response = AuthResponse {
+                success: true,
+                message: "Display name updated successfully".to_string(),
+                email: Some(claims.email),
+            };
+
+            Response::builder()
+                .header("set-cookie", create_auth_cookie(&new_token, client.https))
+                .header("content-type", "application/json")
+                .body(Body::from(serde_json::to_string(&response).unwrap()))
+                .map_err(|_| ApiError::internal("Failed to build response"))
+        }
+        Err(e) => {
+            tracing::error!("JWT creation failed: {:?}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+/// Claims of the logged-in user; profile endpoints require a login
+fn require_login(cookie_jar: &CookieJar) -> Result<auth::Claims, ApiError> {
+    let token = cookie_jar.get("auth_token")
+        .ok_or_else(|| ApiError::unauthorized("Authentication required"))?;
+    validate_jwt(token.value()).map_err(|_| ApiError::unauthorized("Invalid or expired token"))
+}
+
+// DELETE /api/profile - Delete own account (requires login and password)
+// Body: {"password": "...", "transfer_to": "<user id or email>"}
+// Permissions and device groups are removed, owned devices go to `transfer_to`
+// (a member of the user's organizations) or are archived to the guest account;
+// all sessions of the user end.
+async fn delete_profile_handler(
+    State(app_state): State<AppState>,
+    client: proxy::ClientInfo,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<DeleteAccountRequest>,
+) -> Result<Response<Body>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    if claims.user_id == "guest" {
+        return Err(ApiError::forbidden("The guest account cannot be deleted"));
+    }
+
+    let db_user = match app_state.db.get_user_by_id(&claims.user_id).await {
+        Ok(Some(user)) => user,
+        Ok(None) => return Err(ApiError::not_found("User not found")),
+        Err(e) => {
+            tracing::error!("Database error loading user {}: {:?}", claims.user_id, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+    if !db_user.verify_password(&req.password).unwrap_or(false) {
+        return Err(ApiError::unauthorized("Invalid password"));
+    }
+
+    // Resolve the new owner by ID or email
+    let new_owner_id = match req.transfer_to.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        None => "guest".to_string(),
+        Some(target) => {
+            let mut found = app_state.db.get_user_by_id(target).await.ok().flatten();
+            if found.is_none() {
+                found = app_state.db.get_user_by_email(target).await.ok().flatten();
+            }
+            match found {
+                Some(user) if user.id == db_user.id => {
+                    return Err(ApiError::bad_request("Devices cannot be transferred to the deleted account"));
+                }
+                Some(user) => user.id,
+                None => return Err(ApiError::bad_request(format!("Transfer target not found: {}", target))),
+            }
+        }
+    };
+
+    // Devices are never handed to users outside the owner's organizations
+    if new_owner_id != "guest" {
+        let allowed = app_state.db.can_take_over_devices(&db_user.id, &new_owner_id).await.map_err(|e| {
+            tracing::error!("Database error checking transfer target {}: {:?}", new_owner_id, e);
+            ApiError::internal("Database error")
+        })?;
+        if !allowed {
+            return Err(ApiError::forbidden("Devices can only be transferred to a member of your organizations"));
+        }
+    }
+
+    let moved_devices = match app_state.db.delete_user_account(&db_user.id, &new_owner_id).await {
+        Ok(devices) => devices,
+        Err(e) => {
+            tracing::error!("Database error deleting user {}: {:?}", db_user.id, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    revoke_user_sessions(&db_user.id);
+    let closed = app_state.device_store.disconnect_user(&db_user.id).await;
+
+    for device_id in &moved_devices {
+        let audience = device_list_audience(&app_state, device_id).await;
+        broadcast_device_list_changed(&app_state, device_id, events::DeviceListChange::Updated, &audience).await;
+    }
+
+    tracing::info!("User {} deleted their account: {} devices to {}, {} WebSocket clients closed",
+        db_user.email, moved_devices.len(), new_owner_id, closed);
+
+    let body = json!({
+        "success": true,
+        "message": "Account deleted",
+        "transferredDevices": moved_devices,
+        "newOwnerId": new_owner_id
+    });
+    Response::builder()
+        .header("set-cookie", create_logout_cookie(client.https))
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+// GET /api/profile/export - Data export of the logged-in user (account, devices, permissions, groups)
+async fn export_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Response<Body>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error exporting user {}: {:?}", claims.user_id, e);
+        ApiError::internal("Database error")
+    };
+
+    let user = app_state.db.get_user_by_id(&claims.user_id).await
+        .map_err(db_error)?
+        .ok_or_else(|| ApiError::not_found("User not found"))?;
+    let (devices, _) = app_state.db.list_user_devices(&user.id, &database::DeviceListQuery::default()).await
+        .map_err(db_error)?;
+    let permissions = app_state.db.get_user_permissions(&user.id).await.map_err(db_error)?;
+    let groups = app_state.db.list_device_groups(&user.id).await.map_err(db_error)?;
+    let notification_preferences = app_state.db.get_notification_preferences(&user.id).await.map_err(db_error)?;
+    let schedules = app_state.db.list_schedules(&user.id).await.map_err(db_error)?;
+    let scripts = app_state.db.list_scripts(&user.id).await.map_err(db_error)?;
+    let command_templates = app_state.db.list_command_templates(&user.id).await.map_err(db_error)?;
+    let device_profiles = app_state.db.list_device_profiles(&user.id).await.map_err(db_error)?;
+    let organizations: Vec<Value> = app_state.db.list_user_organizations(&user.id).await.map_err(db_error)?
+        .into_iter()
+        .map(|(org, role)| json!({ "organization": org, "role": role }))
+        .collect();
+
+    let devices_json: Vec<Value> = devices.into_iter().map(|(device, permission)| {
+        let mut entry = json!(device);
+        entry["your_permission"] = json!(permission);
+        entry
+    }).collect();
+
+    let body = json!({
+        "success": true,
+        "exported_at": chrono::Utc::now().to_rfc3339(),
+        "user": {
+            "id": user.id,
+            "email": user.email,
+            "display_name": user.display_name,
+            "created_at": user.created_at.to_rfc3339(),
+            "is_admin": user.is_admin
+        },
+        "devices": devices_json,
+        "permissions": permissions,
+        "device_groups": groups,
+        "notification_preferences": notification_preferences,
+        "schedules": schedules,
+        "scripts": scripts,
+        "command_templates": command_templates,
+        "device_profiles": device_profiles,
+        "organizations": organizations
+    });
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .header("content-disposition", "attachment; filename=\"profile-export.json\"")
+        .body(Body::from(serde_json::to_string_pretty(&body).unwrap_or_default()))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+// Answer of the email endpoints while [email] is not configured
+fn email_disabled_error() -> ApiError {
+    ApiError::new(StatusCode::SERVICE_UNAVAILABLE, "EMAIL_DISABLED", "Email is not configured on this server")
+}
+
+// GET /api/profile/notifications - Email notification preferences of the logged-in user
+async fn get_notification_preferences_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let preferences = app_state.db.get_notification_preferences(&claims.user_id).await.map_err(|e| {
+        tracing::error!("Database error loading notification preferences of {}: {:?}", claims.user_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "email": claims.email,
+        "email_enabled": app_state.mailer.is_enabled(),
+        "preferences": preferences
+    })))
+}
+
+// PUT /api/profile/notifications - Change email notification preferences
+// Body: {"email_alerts": true, "email_alert_resolved": false} (omitted fields stay unchanged)
+async fn update_notification_preferences_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdateNotificationPreferencesRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error updating notification preferences of {}: {:?}", claims.user_id, e);
+        ApiError::internal("Database error")
+    };
+
+    let mut preferences = app_state.db.get_notification_preferences(&claims.user_id).await.map_err(db_error)?;
+    if let Some(email_alerts) = req.email_alerts {
+        preferences.email_alerts = email_alerts;
+    }
+    if let Some(email_alert_resolved) = req.email_alert_resolved {
+        preferences.email_alert_resolved = email_alert_resolved;
+    }
+    app_state.db.set_notification_preferences(&preferences).await.map_err(db_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "Notification preferences updated",
+        "preferences": preferences
+    })))
+}
+
+// POST /api/profile/notifications/test - Queue a test email to the logged-in user
+async fn test_notification_email_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    if !app_state.mailer.is_enabled() {
+        return Err(email_disabled_error());
+    }
+    app_state.mailer.send(&claims.email, &claims.display_name, &email::EmailTemplate::Test).await.map_err(|e| {
+        tracing::error!("Test email for {} not queued: {}", claims.user_id, e);
+        ApiError::internal("Failed to queue the test email")
+    })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": format!("Test email queued for {}", claims.email)
+    })))
+}
+
+// POST /api/password-reset - Email a reset link
+// Body: {"email": "..."}; the answer does not reveal whether the account exists
+async fn request_password_reset_handler(
+    State(app_state): State<AppState>,
+    client: proxy::ClientInfo,
+    ApiJson(req): ApiJson<PasswordResetRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if !app_state.mailer.is_enabled() {
+        return Err(email_disabled_error());
+    }
+
+    let email = req.email.trim();
+    let user = app_state.db.get_user_by_email(email).await.map_err(|e| {
+        tracing::error!("Database error during password reset for {}: {:?}", email, e);
+        ApiError::internal("Database error")
+    })?;
+    match user {
+        Some(user) if user.id != "guest" => {
+            let token = auth::generate_reset_token();
+            let expires_at = chrono::Utc::now() + chrono::Duration::minutes(auth::PASSWORD_RESET_VALIDITY_MINUTES);
+            if let Err(e) = app_state.db.create_password_reset(&user.id, &auth::hash_reset_token(&token), expires_at).await {
+                tracing::error!("Database error storing password reset of {}: {:?}", user.id, e);
+                return Err(ApiError::internal("Database error"));
+            }
+
+            let template = email::EmailTemplate::PasswordReset {
+                link: app_state.mailer.link(&format!("/reset-password.html?token={}", token)),
+                valid_minutes: auth::PASSWORD_RESET_VALIDITY_MINUTES,
+            };
+            if let Err(e) = app_state.mailer.send(&user.email, &user.display_name, &template).await {
+                tracing::error!("Password reset email for {} not queued: {}", user.id, e);
+                return Err(ApiError::internal("Failed to send the reset email"));
+            }
+            tracing::info!("Password reset requested for user {} from {}", user.id, client.ip);
+        }
+        _ => tracing::info!("Password reset requested for unknown email {} from {}", email, client.ip),
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "If an account with this email exists, a reset link has been sent"
+    })))
+}
+
+// POST /api/password-reset/confirm - Set a new password
+// Body: {"token": "<from the email>", "password": "..."}; each token works once
+async fn confirm_password_reset_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<PasswordResetConfirmRequest>,
+) -> Result<Json<Value>, ApiError> {
+    if req.password.chars().count() < auth::MIN_PASSWORD_LENGTH {
+        return Err(ApiError::bad_request(format!("Password must be at least {} characters", auth::MIN_PASSWORD_LENGTH)));
+    }
+
+    let token_hash = auth::hash_reset_token(req.token.trim());
+    let user_id = match app_state.db.consume_password_reset(&token_hash, chrono::Utc::now()).await {
+        Ok(Some(user_id)) => user_id,
+        Ok(None) => return Err(ApiError::bad_request("Invalid or expired reset link")),
+        Err(e) => {
+            tracing::error!("Database error checking password reset token: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    let password_hash = bcrypt::hash(&req.password, bcrypt::DEFAULT_COST).map_err(|e| {
+        tracing::error!("Password hashing failed for {}: {:?}", user_id, e);
+        ApiError::internal("Internal server error")
+    })?;
+    if let Err(e) = app_state.db.update_user_password(&user_id, &password_hash).await {
+        tracing::error!("Database error updating password of {}: {:?}", user_id, e);
+        return Err(ApiError::internal("Database error"));
+    }
+
+    tracing::info!("Password of user {} changed via reset link", user_id);
+    Ok(Json(json!({
+        "success": true,
+        "message": "Password changed, you can log in now"
+    })))
+}
+
+// ============================================================================
+// A 5.4: CANVAS MANAGEMENT HANDLERS - API for canvas management with permissions
+// ============================================================================
+
+/// Default/maximum page size of GET /api/devices
+const DEFAULT_DEVICES_PER_PAGE: u32 = 50;
+const MAX_DEVICES_PER_PAGE: u32 = 200;
+
+/// Query of GET /api/devices (without page/per_page every matching device is returned)
+#[derive(Deserialize, Default)]
+struct DeviceListParams {
+    page: Option<u32>,
+    per_page: Option<u32>,
+    /// created_at (default), last_seen or name
+    sort: Option<String>,
+    /// asc or desc (default: desc for dates, asc for name)
+    order: Option<String>,
+    /// Online or Offline (live connection state)
+    status: Option<String>,
+    /// tcp, udp or uart
+    connection_type: Option<String>,
+    /// Search term for name, alias and MAC address
+    q: Option<String>,
+    /// Only devices with this tag
+    tag: Option<String>,
+    /// Only devices in this building / room
+    building: Option<String>,
+    room: Option<String>,
+}
+
+impl DeviceListParams {
+    /// Page number and size, if the client asked for paging
+    fn paging(&self) -> Option<(u32, u32)> {
+        if self.page.is_none() && self.per_page.is_none() {
+            return None;
+        }
+        let per_page = self.per_page.unwrap_or(DEFAULT_DEVICES_PER_PAGE).clamp(1, MAX_DEVICES_PER_PAGE);
+        Some((self.page.unwrap_or(1).max(1), per_page))
+    }
+
+    fn to_query(&self, connected_ids: Vec<String>) -> Result<database::DeviceListQuery, ApiError> {
+        let sort = match self.sort.as_deref() {
+            Some(sort) => database::DeviceSort::parse(sort)
+                .ok_or_else(|| ApiError::bad_request("sort must be one of created_at, last_seen, name"))?,
+            None => database::DeviceSort::default(),
+        };
+        let descending = match self.order.as_deref() {
+            Some("asc") => false,
+            Some("desc") => true,
+            Some(_) => return Err(ApiError::bad_request("order must be asc or desc")),
+            None => sort != database::DeviceSort::Name,
+        };
+        let connected = match self.status.as_deref().map(str::to_ascii_lowercase).as_deref() {
+            Some("online") => Some(true),
+            Some("offline") => Some(false),
+            Some(_) => return Err(ApiError::bad_request("status must be Online or Offline")),
+            None => None,
+        };
+        let connection_type = match self.connection_type.as_deref().map(str::to_ascii_lowercase) {
+            Some(connection_type) if ["tcp", "udp", "uart"].contains(&connection_type.as_str()) => Some(connection_type),
+            Some(_) => return Err(ApiError::bad_request("connection_type must be tcp, udp or uart")),
+            None => None,
+        };
+        let (limit, offset) = match self.paging() {
+            Some((page, per_page)) => (Some(per_page), (page - 1).saturating_mul(per_page)),
+            None => (None, 0),
+        };
+
+        Ok(database::DeviceListQuery {
+            connection_type,
+            search: self.q.as_deref().map(str::trim).filter(|q| !q.is_empty()).map(str::to_string),
+            tag: self.tag.as_deref().map(str::trim).filter(|tag| !tag.is_empty()).map(str::to_string),
+            building: self.building.as_deref().map(str::trim).filter(|b| !b.is_empty()).map(str::to_string),
+            room: self.room.as_deref().map(str::trim).filter(|room| !room.is_empty()).map(str::to_string),
+            connected,
+            connected_ids,
+            sort,
+            descending,
+            org_id: None,
+            limit,
+            offset,
+        })
+    }
+}
+
+// GET /api/devices?page=&per_page=&sort=&order=&status=&connection_type=&q=&tag=&building=&room= - List devices (optional auth)
+async fn list_devices_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(params): axum::extract::Query<DeviceListParams>,
+) -> Result<Json<Value>, ApiError> {
+    // Validate JWT token (optional)
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+
+    let user_id = match token {
+        Some(token_value) => {
+            match validate_jwt(token_value) {
+                Ok(claims) => Some(claims.user_id),
+                Err(_) => None,
+            }
+        }
+        None => None,
+    };
+
+    // Get real-time lifecycle states from DeviceManager
+    let lifecycle_states = app_state.device_manager.lifecycle().snapshot().await;
+    let connected_ids = lifecycle_states.iter()
+        .filter(|(_, entry)| entry.link_up)
+        .map(|(device_id, _)| device_id.clone())
+        .collect();
+    let mut query = params.to_query(connected_ids)?;
+    // X-Org: devices of that organization instead of the personal ones
+    query.org_id = org_context(&app_state, &headers, user_id.as_deref().unwrap_or("guest")).await?.map(|(org, _)| org.id);
+
+    // Load devices from database: authenticated users see their devices, guests see all
+    let result = match &user_id {
+        Some(uid) => app_state.db.list_user_devices(uid, &query).await,
+        None => app_state.db.list_all_devices(&query).await
+            .map(|(devices, total)| (devices.into_iter().map(|device| (device, "GUEST".to_string())).collect(), total)),
+    };
+    let (rows, total) = match result {
+        Ok(page) => page,
+        Err(e) => {
+            tracing::error!("Database error during device list: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    let device_list: Vec<Value> = rows.into_iter().map(|(device, permission)| {
+        // Live lifecycle state; the stored one for devices the manager has not seen yet
+        let lifecycle = lifecycle_states.get(&device.mac_address);
+        let is_connected = lifecycle.is_some_and(|entry| entry.link_up);
+        let status = lifecycle.map(|entry| entry.state).unwrap_or(device.status);
+
+        json!({
+            "id": device.mac_address.clone(),
+            "name": device.name,
+            "alias": device.alias,
+            "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
+            "ip_address": device.ip_address,
+            "status": status,
+            "maintenance_mode": device.maintenance_mode,
+            "firmware_version": device.firmware_version,
+            "owner_id": device.owner_id,
+            "last_seen": device.last_seen.to_rfc3339(),
+            "created_at": device.created_at.to_rfc3339(),
+            "connection_type": device.connection_type,
+            "notes": device.notes,
+            "tags": device.tags,
+            "location": device.location,
+            "your_permission": permission,
+            "connected": is_connected
+        })
+    }).collect();
+
+    let mut response = json!({
+        "success": true,
+        "devices": device_list,
+        "total": total
+    });
+    if let Some((page, per_page)) = params.paging() {
+        response["page"] = json!(page);
+        response["per_page"] = json!(per_page);
+        response["total_pages"] = json!(total.div_ceil(per_page as u64));
+    }
+
+    Ok(Json(response))
+}
+
+// GET /api/devices/summary - Dashboard view: DB metadata joined with live device state (requires login)
+async fn devices_summary_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+
+    // Load the devices the caller may read, with their permission
+    let query = database::DeviceListQuery {
+        org_id: org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id),
+        ..Default::default()
+    };
+    let rows = load_readable_devices(&app_state, &user_id, &query).await?;
+
+    let lifecycle_states = app_state.device_manager.lifecycle().snapshot().await;
+    let viewers = app_state.device_store.get_active_devices().await;
+
+    let mut devices = Vec::with_capacity(rows.len());
+    for (device, permission) in rows {
+        let device_id = device.mac_address.clone();
+
+        // TCP devices report a detailed state, UDP/UART devices only connected/disconnected
+        let lifecycle = lifecycle_states.get(&device_id);
+        let connected = lifecycle.is_some_and(|entry| entry.link_up);
+        let (state, error) = match app_state.device_manager.get_device_state(&device_id).await {
+            Some(device_types::ConnectionState::Failed(reason)) => ("failed", Some(reason)),
+            Some(state) if !(connected && matches!(state, device_types::ConnectionState::Disconnected)) => (state.as_str(), None),
+            _ => (if connected { "connected" } else { "disconnected" }, None),
+        };
+
+        let activity_age_ms = app_state.device_manager.get_activity_age(&device_id).await
+            .map(|age| age.as_millis() as u64);
+        let queued_commands = app_state.device_manager.get_command_queue_depth(&device_id).await;
+        let variables = app_state.device_store.get_device_variables(&device_id).await;
+
+        devices.push(json!({
+            "id": device_id,
+            "name": device.name,
+            "alias": device.alias,
+            "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
+            "ip_address": device.ip_address,
+            "maintenance_mode": device.maintenance_mode,
+            "firmware_version": device.firmware_version,
+            "owner_id": device.owner_id,
+            "last_seen": device.last_seen.to_rfc3339(),
+            "tags": device.tags,
+            "location": device.location,
+            "your_permission": permission,
+            "connection": {
+                "state": state,
+                "connected": connected || state == "connected",
+                "error": error
+            },
+            "lifecycle": {
+                "state": lifecycle.map(|entry| entry.state).unwrap_or(device.status),
+                "since": lifecycle.map(|entry| entry.since.to_rfc3339()),
+                "reason": lifecycle.map(|entry| entry.reason.clone())
+            },
+            "lastActivityAgeMs": activity_age_ms,
+            "queuedCommands": queued_commands,
+            "variables": variables,
+            "viewers": viewers.get(&device_id).copied().unwrap_or(0)
+        }));
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "devices": devices
+    })))
+}
+
+/// Query of GET /api/devices/map
+#[derive(Deserialize)]
+struct DeviceMapParams {
+    building: Option<String>,
+    room: Option<String>,
+}
+
+// GET /api/devices/map?building=&room= - Devices with a location and their live status (requires login)
+async fn device_map_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(params): axum::extract::Query<DeviceMapParams>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let params = DeviceListParams { building: params.building, room: params.room, ..Default::default() };
+    let mut query = params.to_query(Vec::new())?;
+    query.org_id = org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id);
+    let devices: Vec<database::Device> = load_readable_devices(&app_state, &user_id, &query).await?
+        .into_iter().map(|(device, _)| device).collect();
+
+    let lifecycle_states = app_state.device_manager.lifecycle().snapshot().await;
+    let total = devices.len();
+    let placed: Vec<Value> = devices.into_iter().filter_map(|device| {
+        let location = device.location?;
+        let lifecycle = lifecycle_states.get(&device.mac_address);
+        Some(json!({
+            "id": device.mac_address,
+            "name": device.alias.unwrap_or(device.name),
+            "location": location,
+            "status": lifecycle.map(|entry| entry.state).unwrap_or(device.status),
+            "connected": lifecycle.is_some_and(|entry| entry.link_up),
+            "maintenance_mode": device.maintenance_mode
+        }))
+    }).collect();
+
+    Ok(Json(json!({
+        "success": true,
+        "unplaced": total - placed.len(),
+        "devices": placed
+    })))
+}
+
+/// Query of GET /api/fleet/summary
+#[derive(Deserialize)]
+struct FleetSummaryParams {
+    /// Devices without traffic for longer are listed as silent (default 24)
+    silent_hours: Option<u32>,
+    /// Number of top talkers (default 10, at most 50)
+    top: Option<usize>,
+}
+
+/// Recent alerts in the fleet summary
+const FLEET_RECENT_ALERTS: usize = 10;
+
+/// The devices the caller may read in the organization context, with live state where known
+async fn load_fleet_devices(app_state: &AppState, headers: &axum::http::HeaderMap, user_id: &str) -> Result<Vec<fleet::FleetDevice>, ApiError> {
+    let query = database::DeviceListQuery {
+        org_id: org_context(app_state, headers, user_id).await?.map(|(org, _)| org.id),
+        ..Default::default()
+    };
+    let devices = load_readable_devices(app_state, user_id, &query).await?;
+
+    // Live state and last traffic where the device manager knows the device, stored values otherwise
+    let now = chrono::Utc::now();
+    let lifecycle_states = app_state.device_manager.lifecycle().snapshot().await;
+    let mut fleet = Vec::with_capacity(devices.len());
+    for (device, _) in devices {
+        let last_activity = app_state.device_manager.get_activity_age(&device.mac_address).await
+            .and_then(|age| chrono::Duration::from_std(age).ok())
+            .map(|age| now - age);
+        fleet.push(fleet::FleetDevice {
+            status: lifecycle_states.get(&device.mac_address).map(|entry| entry.state).unwrap_or(device.status).as_str().to_string(),
+            last_seen: last_activity.map_or(device.last_seen, |at| at.max(device.last_seen)),
+            device_id: device.mac_address,
+            name: device.alias.unwrap_or(device.name),
+            firmware_version: device.firmware_version,
+        });
+    }
+    Ok(fleet)
+}
+
+// GET /api/fleet/summary?silent_hours=&top= - Fleet overview over the caller's devices (requires login)
+async fn fleet_summary_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(params): axum::extract::Query<FleetSummaryParams>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let fleet = load_fleet_devices(&app_state, &headers, &user_id).await?;
+    let now = chrono::Utc::now();
+
+    let silent_hours = params.silent_hours.unwrap_or(fleet::DEFAULT_SILENT_HOURS);
+    let top = params.top.unwrap_or(fleet::DEFAULT_TOP_TALKERS).clamp(1, fleet::MAX_TOP_TALKERS);
+    let summary = fleet::summarize(&fleet, &fleet::event_rates(), silent_hours, top, now);
+
+    // Alerts belong to the owner of the rule
+    let recent_alerts = app_state.db.list_alerts(&user_id, None, None, FLEET_RECENT_ALERTS).await.map_err(|e| {
+        tracing::error!("Database error loading alerts for fleet summary: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "summary": summary,
+        "recentAlerts": recent_alerts
+    })))
+}
+
+/// Query of GET /api/fleet/firmware
+#[derive(Deserialize)]
+struct FleetFirmwareParams {
+    /// Overrides firmware.minimum_version for this report
+    minimum: Option<String>,
+}
+
+// GET /api/fleet/firmware?minimum= - Firmware versions of the caller's devices and those below the minimum (requires login)
+async fn fleet_firmware_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(params): axum::extract::Query<FleetFirmwareParams>,
+) -> Result<Json<Value>, ApiError> {
+    let minimum = match params.minimum.map(|minimum| minimum.trim().to_string()).filter(|minimum| !minimum.is_empty()) {
+        Some(minimum) if fleet::parse_version(&minimum).is_none() => {
+            return Err(ApiError::bad_request(format!("Invalid minimum version: {}", minimum)));
+        }
+        Some(minimum) => Some(minimum),
+        None => fleet::minimum_firmware_version(),
+    };
+
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let fleet = load_fleet_devices(&app_state, &headers, &user_id).await?;
+    let report = fleet::firmware_report(&fleet, minimum.as_deref());
+
+    Ok(Json(json!({
+        "success": true,
+        "firmware": report
+    })))
+}
+
+// POST /api/devices - Create new device (optional auth)
+async fn create_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    ApiJson(req): ApiJson<CreateDeviceRequest>,
+) -> Result<Response<Body>, ApiError> {
+    // Validate JWT token (optional)
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    
+    let owner_id = match token {
+        Some(token_value) => {
+            match validate_jwt(token_value) {
+                Ok(claims) => claims.user_id,
+                Err(_) => "guest".to_string(),
+            }
+        }
+        None => "guest".to_string(),
+    };
+
+    // Validate device name and MAC address
+    if req.name.trim().is_empty() || req.name.len() > 100 {
+        return Err(ApiError::bad_request("Device name must be between 1 and 100 characters"));
+    }
+    
+    if req.mac_address.trim().is_empty() {
+        return Err(ApiError::bad_request("MAC address is required"));
+    }
+
+    // Device ID format of MAC addresses: AA-BB-CC-DD-EE-FF
+    let mac_key = device_manager::DeviceManager::normalize_device_id(req.mac_address.trim());
+
+    // Create new device (inside the organization selected with X-Org)
+    let mut device = database::Device::new(
+        req.name.trim().to_string(),
+        owner_id.clone(),
+        mac_key,
+    );
+    device.org_id = org_context(&app_state, &headers, &owner_id).await?.map(|(org, _)| org.id);
+
+    // Save device to database
+    if let Err(e) = app_state.db.create_device(device.clone()).await {
+        tracing::error!("Database error during device creation: {:?}", e);
+        return Err(ApiError::internal("Database error"));
+    }
+    app_state.device_manager.lifecycle().adopted(&device.mac_address).await;
+
+    let user_info = if owner_id == "guest" { "guest user".to_string() } else { owner_id.clone() };
+    tracing::info!("device created: {} by user {}", device.name, user_info);
+
+    let audience = device_list_audience(&app_state, &device.mac_address).await;
+    broadcast_device_list_changed(&app_state, &device.mac_address, events::DeviceListChange::Created, &audience).await;
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(json!({
+            "success": true,
+            "message": "Canvas created successfully",
+            "device": {
+                "id": device.mac_address.clone(),
+                "name": device.name,
+                "alias": device.alias,
+                "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
+                "ip_address": device.ip_address,
+                "status": device.status,
+                "maintenance_mode": device.maintenance_mode,
+                "firmware_version": device.firmware_version,
+                "owner_id": device.owner_id,
+                "last_seen": device.last_seen.to_rfc3339(),
+                "created_at": device.created_at.to_rfc3339(),
+                "your_permission": "O"
+            }
+        }).to_string()))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+/// Query of GET /api/devices/export
+#[derive(Deserialize, Default)]
+struct InventoryExportQuery {
+    /// csv (default) or json
+    format: Option<String>,
+}
+
+/// Devices matching `query` the user may read, with the permission the listing reports.
+/// The listing only preselects; every device is checked through the permission resolver.
+async fn load_readable_devices(app_state: &AppState, user_id: &str, query: &database::DeviceListQuery) -> Result<Vec<(database::Device, String)>, ApiError> {
+    let (rows, _) = app_state.db.list_user_devices(user_id, query).await.map_err(|e| {
+        tracing::error!("Database error listing devices of user {}: {:?}", user_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    let resolver = permissions::PermissionResolver::new(&app_state.db);
+    let mut readable = Vec::with_capacity(rows.len());
+    for (device, permission) in rows {
+        if resolver.has_permission(&device.mac_address, user_id, "R").await.map_err(permission_db_error)? {
+            readable.push((device, permission));
+        }
+    }
+    Ok(readable)
+}
+
+// GET /api/devices/export?format=csv|json - Inventory of the devices the user may read (requires login)
+async fn export_device_inventory_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<InventoryExportQuery>,
+) -> Result<Response<Body>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let as_json = match query.format.as_deref().map(str::to_ascii_lowercase).as_deref() {
+        None | Some("csv") => false,
+        Some("json") => true,
+        Some(other) => return Err(ApiError::bad_request(format!("Unknown format '{}' (csv or json)", other))),
+    };
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error exporting device inventory: {:?}", e);
+        ApiError::internal("Database error")
+    };
+
+    // Owner emails are personal data: only devices the caller may read, in the X-Org context
+    let listing = database::DeviceListQuery {
+        org_id: org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id),
+        ..Default::default()
+    };
+    let devices = load_readable_devices(&app_state, &user_id, &listing).await?;
+
+    let mut owner_emails: std::collections::HashMap<String, Option<String>> = std::collections::HashMap::new();
+    let mut entries = Vec::with_capacity(devices.len());
+    for (device, _) in devices {
+        if !owner_emails.contains_key(&device.owner_id) {
+            let email = app_state.db.get_user_by_id(&device.owner_id).await.map_err(db_error)?.map(|user| user.email);
+            owner_emails.insert(device.owner_id.clone(), email);
+        }
+        entries.push(device_inventory::InventoryEntry {
+            owner_email: owner_emails[&device.owner_id].clone(),
+            mac_address: device.mac_address,
+            name: device.name,
+            alias: device.alias,
+            connection_type: device.connection_type,
+            tags: device.tags,
+        });
+    }
+
+    let (content_type, extension, body) = if as_json {
+        let body = serde_json::to_string_pretty(&json!({ "devices": entries })).map_err(|_| ApiError::internal("Failed to encode inventory"))?;
+        ("application/json", "json", body)
+    } else {
+        ("text/csv; charset=utf-8", "csv", device_inventory::to_csv(&entries))
+    };
+    let file_name = format!("device_inventory_{}.{}", chrono::Utc::now().format("%Y%m%d_%H%M%S"), extension);
+
+    Response::builder()
+        .header("content-type", content_type)
+        .header("content-disposition", format!("attachment; filename=\"{}\"", file_name))
+        .body(Body::from(body))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+/// Query of POST /api/devices/import
+#[derive(Deserialize, Default)]
+struct InventoryImportQuery {
+    /// Only report what would happen
+    #[serde(default)]
+    dry_run: bool,
+    #[serde(default)]
+    on_conflict: device_inventory::ConflictPolicy,
+}
+
+// POST /api/devices/import?dry_run=&on_conflict=skip|update|fail - Create devices from an inventory (requires login)
+// Body: text/csv with the export header, or JSON {"devices": [...]} / [...]. Rows naming another
+// owner need an administrator; nothing is imported while any row has an error.
+async fn import_device_inventory_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<InventoryImportQuery>,
+    body: String,
+) -> Result<Json<Value>, ApiError> {
+    use device_inventory::{ConflictPolicy, ImportRow, InventoryEntry};
+
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error importing device inventory: {:?}", e);
+        ApiError::internal("Database error")
+    };
+
+    let is_csv = headers.get("content-type").and_then(|v| v.to_str().ok()).is_some_and(|v| v.contains("csv"));
+    let entries: Vec<InventoryEntry> = if is_csv {
+        device_inventory::parse_csv(&body).map_err(ApiError::bad_request)?
+    } else {
+        let value: Value = serde_json::from_str(&body).map_err(|e| ApiError::bad_request(format!("Invalid JSON: {}", e)))?;
+        let list = value.get("devices").cloned().unwrap_or(value);
+        serde_json::from_value(list).map_err(|e| ApiError::bad_request(format!("Invalid inventory: {}", e)))?
+    };
+    if entries.is_empty() || entries.len() > device_inventory::MAX_IMPORT_ROWS {
+        return Err(ApiError::bad_request(format!("An import needs between 1 and {} devices", device_inventory::MAX_IMPORT_ROWS)));
+    }
+    let is_admin = app_state.db.get_user_by_id(&user_id).await.map_err(db_error)?.is_some_and(|user| user.is_admin);
+    let org_id = org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id);
+
+    // Plan every row first; the plan is the dry-run answer
+    let mut plan: Vec<(ImportRow, Option<(InventoryEntry, String)>)> = Vec::with_capacity(entries.len());
+    let mut seen = std::collections::HashSet::new();
+    for (index, mut entry) in entries.into_iter().enumerate() {
+        let mut row = ImportRow { row: index + 1, mac_address: entry.mac_address.clone(), action: "error", conflict: None, error: None };
+        let mac = match device_inventory::validate_entry(&entry) {
+            Ok(mac) => mac,
+            Err(e) => {
+                row.error = Some(e);
+                plan.push((row, None));
+                continue;
+            }
+        };
+        row.mac_address = mac.clone();
+        entry.mac_address = mac.clone();
+        if !seen.insert(mac.clone()) {
+            row.error = Some("MAC address appears more than once in the import".to_string());
+            plan.push((row, None));
+            continue;
+        }
+
+        let owner_id = match entry.owner_email.as_deref() {
+            None => user_id.clone(),
+            Some(email) => match app_state.db.get_user_by_email(email).await.map_err(db_error)? {
+                Some(owner) => owner.id,
+                None => {
+                    row.error = Some(format!("Unknown owner '{}'", email));
+                    plan.push((row, None));
+                    continue;
+                }
+            },
+        };
+        if owner_id != user_id && !is_admin {
+            row.error = Some("Only administrators can import devices for other users".to_string());
+            plan.push((row, None));
+            continue;
+        }
+
+        match app_state.db.get_device_by_id(&mac).await.map_err(db_error)? {
+            None => row.action = "create",
+            Some(existing) => {
+                row.conflict = Some(format!("Device already exists ('{}', owner {})", existing.name, existing.owner_id));
+                match query.on_conflict {
+                    ConflictPolicy::Skip => row.action = "skip",
+                    ConflictPolicy::Fail => row.error = Some("Device already exists".to_string()),
+                    ConflictPolicy::Update => match websocket::check_device_write_permission(&app_state.db, &mac, &user_id).await {
+                        Ok(()) => row.action = "update",
+                        Err(e) => row.error = Some(e.message),
+                    },
+                }
+            }
+        }
+        plan.push((row, Some((entry, owner_id))));
+    }
+
+    let count = |action: &str| plan.iter().filter(|(row, _)| row.action == action).count();
+    let summary = json!({
+        "create": count("create"), "update": count("update"), "skip": count("skip"), "error": count("error")
+    });
+    let rows: Vec<&ImportRow> = plan.iter().map(|(row, _)| row).collect();
+    if query.dry_run {
+        return Ok(Json(json!({ "success": true, "dryRun": true, "summary": summary, "rows": rows })));
+    }
+    if count("error") > 0 {
+        return Err(ApiError::bad_request("Import has invalid rows, nothing was imported")
+            .with_details(json!({ "summary": summary, "rows": rows })));
+    }
+
+    for (row, target) in &plan {
+        let Some((entry, owner_id)) = target else { continue };
+        let tags = normalize_device_tags(entry.tags.clone()).unwrap_or_default();
+        let change = match row.action {
+            "create" => {
+                let mut device = if entry.connection_type == "uart" {
+                    database::Device::new_uart(entry.name.trim().to_string(), owner_id.clone(), entry.mac_address.clone())
+                } else {
+                    database::Device::new(entry.name.trim().to_string(), owner_id.clone(), entry.mac_address.clone())
+                };
+                device.alias = entry.alias.clone();
+                device.tags = tags;
+                device.org_id = org_id.clone();
+                app_state.db.create_device(device).await.map_err(db_error)?;
+                app_state.device_manager.lifecycle().adopted(&entry.mac_address).await;
+                events::DeviceListChange::Created
+            }
+            "update" => {
+                app_state.db.update_device(&entry.mac_address, Some(Some(entry.name.trim())), Some(entry.alias.as_deref()), None, None)
+                    .await.map_err(db_error)?;
+                app_state.db.set_device_connection_type(&entry.mac_address, &entry.connection_type).await.map_err(db_error)?;
+                app_state.db.set_device_tags(&entry.mac_address, &tags).await.map_err(db_error)?;
+                events::DeviceListChange::Updated
+            }
+            _ => continue,
+        };
+        let audience = device_list_audience(&app_state, &entry.mac_address).await;
+        broadcast_device_list_changed(&app_state, &entry.mac_address, change, &audience).await;
+    }
+
+    tracing::info!("Device inventory imported by user {}: {}", user_id, summary);
+    Ok(Json(json!({ "success": true, "dryRun": false, "summary": summary, "rows": rows })))
+}
+
+// ============================================================================
+// DEVICE LIST CHANGE NOTIFICATIONS
+// ============================================================================
+
+/// Users that see a device in their list: owner, permission holders and guests
+async fn device_list_audience(app_state: &AppState, device_id: &str) -> std::collections::HashSet<String> {
+    let mut audience = std::collections::HashSet::from(["guest".to_string()]);
+
+    let mut org_id = None;
+    match app_state.db.get_device_by_id(device_id).await {
+        Ok(Some(device)) => {
+            org_id = device.org_id;
+            audience.insert(device.owner_id);
+        }
+        Ok(None) => {}
+        Err(e) => tracing::warn!("Failed to load device {} for change notification: {}", device_id, e),
+    }
+    // Devices of an organization: its members only
+    if let Some(org_id) = org_id {
+        let members = app_state.db.list_org_members(&org_id).await.map_err(|e| e.to_string());
+        return match members {
+            Ok(members) => members.into_iter().map(|member| member.user_id).collect(),
+            Err(e) => {
+                tracing::warn!("Failed to load members of organization {} for change notification: {}", org_id, e);
+                std::collections::HashSet::new()
+            }
+        };
+    }
+    match app_state.db.get_device_permissions(device_id).await {
+        Ok(permissions) => audience.extend(permissions.into_iter().map(|p| p.user_id)),
+        Err(e) => tracing::warn!("Failed to load permissions of {} for change notification: {}", device_id, e),
+    }
+    match app_state.db.list_device_group_grantees(device_id).await {
+        Ok(grantees) => audience.extend(grantees),
+        Err(e) => tracing::warn!("Failed to load group grants of {} for change notification: {}", device_id, e),
+    }
+
+    audience
+}
+
+/// Tell the open sockets of the affected users to reload their device list
+async fn broadcast_device_list_changed(
+    app_state: &AppState,
+    device_id: &str,
+    change: events::DeviceListChange,
+    audience: &std::collections::HashSet<String>,
+) {
+    let message = events::ServerMessage::device_list_changed(device_id.to_string(), change);
+    let reached = app_state.device_store.send_to_users(audience, message).await;
+    tracing::debug!("deviceListChanged ({:?}) for {} sent to {} clients", change, device_id, reached);
+}
+
+// POST /api/devices/adopt - Adopt a discovered device (requires auth)
+// Creates the DB record from discovery data, makes the caller owner,
+// registers the device with the manager and starts the first connection
+async fn adopt_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    ApiJson(req): ApiJson<AdoptDeviceRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let token = match cookie_jar.get("auth_token") {
+        Some(cookie) => cookie.value(),
+        None => return Err(ApiError::unauthorized("Authentication required")),
+    };
+
+    let claims = match validate_jwt(token) {
+        Ok(claims) => claims,
+        Err(_) => return Err(ApiError::unauthorized("Invalid or expired token")),
+    };
+    let owner_id = claims.user_id;
+    let org = org_context(&app_state, &headers, &owner_id).await?;
+
+    // Look up the discovery entry
+    let discovered = {
+        let discovery = app_state.device_discovery.lock().await;
+        discovery.get_discovered_devices().await.remove(&req.device_id)
+    };
+    let Some(discovered) = discovered else {
+        return Err(ApiError::not_found("Discovered device not found"));
+    };
+
+    // MAC (key format with dashes), name and firmware from mDNS TXT records where available
+    let txt = discovered.mdns_data.as_ref().map(|m| m.txt_records.clone()).unwrap_or_default();
+    let mac_key = txt.get("mac")
+        .map(|mac| device_manager::DeviceManager::normalize_device_id(mac.trim()))
+        .unwrap_or_else(|| req.device_id.clone());
+    let announced_name = ["name", "deviceName"].iter()
+        .find_map(|key| txt.get(*key).cloned())
+        .or_else(|| discovered.mdns_data.as_ref().map(|m| m.hostname.replace(".local", "").trim_end_matches('.').to_string()))
+        .unwrap_or_else(|| discovered.device_config.device_name.clone());
+    let name = req.name.as_deref().map(str::trim).filter(|n| !n.is_empty()).map(str::to_string).unwrap_or(announced_name);
+    if name.len() > 100 {
+        return Err(ApiError::bad_request("Device name must be between 1 and 100 characters"));
+    }
+    let firmware_version = ["version", "firmwareVersion", "fw"].iter().find_map(|key| txt.get(*key).cloned());
+    let ip_address = discovered.device_config.ip_address.to_string();
+
+    // Create the DB record or take over the auto-saved (guest-owned) discovery record
+    let existing = app_state.db.get_device_by_id(&mac_key).await.map_err(|e| e.to_string());
+    match existing {
+        Ok(Some(existing)) if existing.owner_id == owner_id => {
+            tracing::info!("Device {} already owned by {}, re-adopting", mac_key, owner_id);
+        }
+        Ok(Some(existing)) if existing.owner_id == "guest" => {
+            if let Err(e) = app_state.db.transfer_device_owner(&mac_key, &owner_id).await {
+                tracing::error!("Database error transferring device {}: {:?}", mac_key, e);
+                return Err(ApiError::internal("Database error"));
+            }
+            if let Err(e) = app_state.db.update_device(&mac_key, Some(Some(name.as_str())), None, None, None).await {
+                tracing::error!("Database error renaming device {}: {:?}", mac_key, e);
+                return Err(ApiError::internal("Database error"));
+            }
+            if let Err(e) = app_state.db.update_device_status(&mac_key, &existing.status, Some(&ip_address), firmware_version.as_deref().or(existing.firmware_version.as_deref())).await {
+                tracing::error!("Database error updating device {}: {:?}", mac_key, e);
+                return Err(ApiError::internal("Database error"));
+            }
+        }
+        Ok(Some(_)) => {
+            return Err(ApiError::conflict("Device is already owned by another user"));
+        }
+        Ok(None) => {
+            let mut device = database::Device::new(name.clone(), owner_id.clone(), mac_key.clone());
+            device.ip_address = Some(ip_address.clone());
+            device.firmware_version = firmware_version.clone();
+            if let Err(e) = app_state.db.create_device(device).await {
+                tracing::error!("Database error during device adoption: {:?}", e);
+                return Err(ApiError::internal("Database error"));
+            }
+        }
+        Err(e) => {
+            tracing::error!("Database error loading device {}: {:?}", mac_key, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    }
+
+    // Adopted into the organization selected with X-Org
+    if let Some((org, _)) = &org {
+        if let Err(e) = app_state.db.set_device_org(&mac_key, Some(&org.id)).await {
+            tracing::error!("Database error adding device {} to organization {}: {:?}", mac_key, org.id, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    }
+
+    // Register with the manager and start the first connection
+    app_state.device_manager.lifecycle().adopted(&mac_key).await;
+    let mut config = discovered.device_config.clone();
+    config.device_id = mac_key.clone();
+    config.device_name = name.clone();
+    if let Err(e) = app_state.device_manager.add_device(config).await {
+        tracing::error!("Failed to register adopted device {}: {}", mac_key, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+    let connection_error = app_state.device_manager.connect_device(&mac_key).await.err().map(|e| e.to_string());
+    if let Some(ref e) = connection_error {
+        tracing::warn!("Adopted device {} but first connection failed: {}", mac_key, e);
+    }
+
+    let device = match app_state.db.get_device_by_id(&mac_key).await {
+        Ok(Some(device)) => device,
+        Ok(None) => return Err(ApiError::internal("Internal server error")),
+        Err(e) => {
+            tracing::error!("Database error loading adopted device {}: {:?}", mac_key, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    tracing::info!("Device adopted: {} ({}) by user {}", device.name, mac_key, owner_id);
+    let status = app_state.device_manager.lifecycle().get(&mac_key).await.map(|entry| entry.state).unwrap_or(device.status);
+
+    let audience = device_list_audience(&app_state, &mac_key).await;
+    broadcast_device_list_changed(&app_state, &mac_key, events::DeviceListChange::Created, &audience).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "Device adopted successfully",
+        "connected": connection_error.is_none(),
+        "connectionError": connection_error,
+        "device": {
+            "id": device.mac_address.clone(),
+            "name": device.name,
+            "alias": device.alias,
+            "mac_address": device.mac_address.replace('-', ":"),  // Show with colons for display
+            "ip_address": device.ip_address,
+            "status": status,
+            "maintenance_mode": device.maintenance_mode,
+            "firmware_version": device.firmware_version,
+            "owner_id": device.owner_id,
+            "last_seen": device.last_seen.to_rfc3339(),
+            "created_at": device.created_at.to_rfc3339(),
+            "your_permission": "O"
+        }
+    })))
+}
+
+// GET /api/devices/:id - Details of an device (optional auth)
+async fn get_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(canvas_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    // JWT Token validieren (optional)
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    
+    let user_id = match token {
+        Some(token_value) => {
+            match validate_jwt(token_value) {
+                Ok(claims) => Some(claims.user_id),
+                Err(_) => None,
+            }
+        }
+        None => None,
+    };
+
+    // Canvas aus Datenbank laden
+    let canvas = match app_state.db.get_device_by_id(&canvas_id).await {
+        Ok(Some(canvas)) => canvas,
+        Ok(None) => return Err(ApiError::not_found("Device not found")),
+        Err(e) => {
+            tracing::error!("Database error loading canvas: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+    // Devices of organizations do not exist for non-members
+    if websocket::check_device_org_membership(&app_state.db, &canvas_id, user_id.as_deref().unwrap_or("guest")).await.is_err() {
+        return Err(ApiError::not_found("Device not found"));
+    }
+
+    // Effektive User-Berechtigung laden (falls authenticated)
+    let user_permission = match &user_id {
+        Some(uid) => {
+            match permissions::PermissionResolver::new(&app_state.db).effective_permission(&canvas_id, uid).await {
+                Ok(Some(effective)) => effective.permission,
+                Ok(None) => "NONE".to_string(),
+                Err(e) => {
+                    tracing::error!("Database error loading user permission: {:?}", e);
+                    "NONE".to_string()
+                }
+            }
+        }
+        None => "GUEST".to_string(), // Guest user has guest permission
+    };
+
+    // Load all permissions (only for moderators or guest gets all)
+    let all_permissions = match &user_id {
+        Some(uid) => {
+            if permissions::PermissionResolver::new(&app_state.db).has_permission(&canvas_id, uid, "M").await.unwrap_or(false) {
+                Some(app_state.db.get_device_permissions(&canvas_id).await.unwrap_or_default())
+            } else {
+                None
+            }
+        }
+        None => {
+            // Guest users can see all permissions for transparency
+            Some(app_state.db.get_device_permissions(&canvas_id).await.unwrap_or_default())
+        }
+    };
+
+    Ok(Json(json!({
+        "success": true,
+        "canvas": {
+            "id": canvas.mac_address,
+            "name": canvas.name,
+            "alias": canvas.alias,
+            "maintenance_mode": canvas.maintenance_mode,
+            "notes": canvas.notes,
+            "tags": canvas.tags,
+            "owner_id": canvas.owner_id,
+            "created_at": canvas.created_at.to_rfc3339(),
+            "your_permission": user_permission,
+            "all_permissions": all_permissions
+        }
+    })))
+}
+
+// POST /api/devices/:id - Device-Eigenschaften ändern (Name, Wartungsmodus) (optional auth)
+async fn update_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(canvas_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateDeviceRequest>,
+) -> Result<Response<Body>, ApiError> {
+    // JWT Token validieren (optional)
+    let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    
+    let user_email = match token {
+        Some(token_value) => {
+            match validate_jwt(token_value) {
+                Ok(claims) => Some(claims.email),
+                Err(_) => None,
+            }
+        }
+        None => None,
+    };
+
+    // Canvas aus Datenbank laden
+    let canvas = match app_state.db.get_device_by_id(&canvas_id).await {
+        Ok(Some(canvas)) => canvas,
+        Ok(None) => return Err(ApiError::not_found("Device not found")),
+        Err(e) => {
+            tracing::error!("Database error loading canvas: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    // Guest users have full permissions for device management
+    // (Authentication is optional, so no permission checks needed)
+
+    // Validate name if provided
+    use crate::auth::MaybeAbsent;
+    if let MaybeAbsent::Value(name) = &req.name {
+        if name.trim().is_empty() || name.len() > 100 {
+            return Err(ApiError::bad_request("Canvas name must be between 1 and 100 characters"));
+        }
+    }
+
+    // Validate alias if provided
+    if let MaybeAbsent::Value(alias) = &req.alias {
+        if alias.len() > 100 {
+            return Err(ApiError::bad_request("Alias must be less than 100 characters"));
+        }
+    }
+
+    // Validate notes if provided
+    if let MaybeAbsent::Value(notes) = &req.notes {
+        if notes.len() > MAX_DEVICE_NOTES_LENGTH {
+            return Err(ApiError::bad_request(format!("Notes must be at most {} characters", MAX_DEVICE_NOTES_LENGTH)));
+        }
+    }
+
+    // Update canvas
+    // Convert MaybeAbsent<String> -> Option<Option<&str>> for database
+    let name_update = match &req.name {
+        MaybeAbsent::Absent => None,
+        MaybeAbsent::Null => Some(None),
+        MaybeAbsent::Value(s) => Some(Some(s.trim())),
+    };
+
+    let alias_update = match &req.alias {
+        MaybeAbsent::Absent => None,  // Don't update
+        MaybeAbsent::Null => Some(None),  // Clear the alias (set to NULL)
+        MaybeAbsent::Value(s) => {
+            let trimmed = s.trim();
+            if trimmed.is_empty() {
+                Some(None)  // Empty string also clears the alias
+            } else {
+                Some(Some(trimmed))  // Set to the trimmed value
+            }
+        }
+    };
+
+    let maintenance_mode_update = match &req.maintenance_mode {
+        MaybeAbsent::Absent => None,
+        MaybeAbsent::Null => None,  // Null doesn't make sense for boolean, treat as absent
+        MaybeAbsent::Value(b) => Some(*b),
+    };
+
+    // Notes: like alias, empty text clears them
+    let notes_update = match &req.notes {
+        MaybeAbsent::Absent => None,
+        MaybeAbsent::Null => Some(None),
+        MaybeAbsent::Value(s) if s.trim().is_empty() => Some(None),
+        MaybeAbsent::Value(s) => Some(Some(s.trim())),
+    };
+
+    if let Err(e) = app_state.db.update_device(
+        &canvas_id,
+        name_update,
+        alias_update,
+        maintenance_mode_update,
+        notes_update
+    ).await {
+        tracing::error!("Database error updating canvas: {:?}", e);
+        return Err(ApiError::internal("Database error"));
+    }
+    if let Some(enabled) = maintenance_mode_update {
+        app_state.device_manager.lifecycle().set_maintenance(&canvas_id, enabled).await;
+    }
+
+    // Aktualisierte Canvas laden
+    let updated_canvas = match app_state.db.get_device_by_id(&canvas_id).await {
+        Ok(Some(canvas)) => canvas,
+        Ok(None) => return Err(ApiError::not_found("Device not found")),
+        Err(e) => {
+            tracing::error!("Database error loading updated canvas: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    let user_info = user_email.unwrap_or_else(|| "guest".to_string());
+    tracing::info!("Canvas updated: {} by user {}", updated_canvas.name, user_info);
+
+    let actor_id = optional_user_id(&cookie_jar);
+    let renames = [
+        ("name", Some(canvas.name.clone()), Some(updated_canvas.name.clone())),
+        ("alias", canvas.alias.clone(), updated_canvas.alias.clone()),
+    ];
+    for (field, from, to) in renames {
+        if from != to {
+            activity::record(&app_state.db, &canvas_id, &actor_id, activity::AuditAction::Renamed, json!({ "field": field, "from": from, "to": to })).await;
+        }
+    }
+
+    let audience = device_list_audience(&app_state, &canvas_id).await;
+    broadcast_device_list_changed(&app_state, &canvas_id, events::DeviceListChange::Updated, &audience).await;
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(json!({
+            "success": true,
+            "message": "Canvas updated successfully",
+            "canvas": {
+                "id": updated_canvas.mac_address.clone(),
+                "name": updated_canvas.name,
+                "alias": updated_canvas.alias,
+                "maintenance_mode": updated_canvas.maintenance_mode,
+                "notes": updated_canvas.notes,
+                "tags": updated_canvas.tags,
+                "owner_id": updated_canvas.owner_id,
+                "created_at": updated_canvas.created_at.to_rfc3339(),
+                "mac_address": updated_canvas.mac_address.replace('-', ":")  // Show with colons for display
+            }
+        }).to_string()))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+/// Limits of device notes and tags
+const MAX_DEVICE_NOTES_LENGTH: usize = 2000;
+const MAX_DEVICE_TAGS: usize = 20;
+const MAX_DEVICE_TAG_LENGTH: usize = 50;
+
+/// Trim tags, drop empty ones and duplicates (case-insensitive, first spelling wins)
+fn normalize_device_tags(tags: Vec<String>) -> Result<Vec<String>, String> {
+    let mut seen = std::collections::HashSet::new();
+    let tags: Vec<String> = tags.into_iter()
+        .map(|tag| tag.trim().to_string())
+        .filter(|tag| !tag.is_empty() && seen.insert(tag.to_lowercase()))
+        .collect();
+
+    if tags.len() > MAX_DEVICE_TAGS {
+        return Err(format!("A device can have at most {} tags", MAX_DEVICE_TAGS));
+    }
+    if let Some(tag) = tags.iter().find(|tag| tag.chars().count() > MAX_DEVICE_TAG_LENGTH) {
+        return Err(format!("Tag too long (max {} characters): {}", MAX_DEVICE_TAG_LENGTH, tag));
+    }
+    Ok(tags)
+}
+
+// PUT /api/devices/:id/tags - Replace the tags of a device (manage permission)
+// Body: {"tags": ["flaky power supply", "room 204"]}
+async fn update_device_tags_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateDeviceTagsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let tags = normalize_device_tags(req.tags).map_err(ApiError::bad_request)?;
+
+    let updated = match app_state.db.set_device_tags(&device_id, &tags).await {
+        Ok(updated) => updated,
+        Err(e) => {
+            tracing::error!("Database error updating tags of device {}: {:?}", device_id, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+    if !updated {
+        return Err(ApiError::not_found("Device not found"));
+    }
+
+    tracing::info!("Tags of device {} set to {:?} by user {}", device_id, tags, user_id);
+
+    let audience = device_list_audience(&app_state, &device_id).await;
+    broadcast_device_list_changed(&app_state, &device_id, events::DeviceListChange::Updated, &audience).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "tags": tags
+    })))
+}
+
+/// Limit of the building, room and bench names of a device location
+const MAX_LOCATION_FIELD_LENGTH: usize = 100;
+
+/// Trim names, check coordinates; None if every field is empty (location cleared)
+fn normalize_device_location(req: UpdateDeviceLocationRequest) -> Result<Option<database::DeviceLocation>, String> {
+    let name = |field: &str, value: Option<String>| -> Result<Option<String>, String> {
+        let value = value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+        if value.as_ref().is_some_and(|v| v.chars().count() > MAX_LOCATION_FIELD_LENGTH) {
+            return Err(format!("{} too long (max {} characters)", field, MAX_LOCATION_FIELD_LENGTH));
+        }
+        Ok(value)
+    };
+    match (req.latitude, req.longitude) {
+        (Some(lat), Some(lon)) if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) => {
+            return Err("latitude must be between -90 and 90, longitude between -180 and 180".to_string());
+        }
+        (Some(_), None) | (None, Some(_)) => return Err("latitude and longitude must be set together".to_string()),
+        _ => {}
+    }
+    let location = database::DeviceLocation {
+        building: name("building", req.building)?,
+        room: name("room", req.room)?,
+        bench: name("bench", req.bench)?,
+        latitude: req.latitude,
+        longitude: req.longitude,
+    };
+    Ok((!location.is_empty()).then_some(location))
+}
+
+// PUT /api/devices/:id/location - Set or clear the physical location of a device (manage permission)
+// Body: {"building": "B", "room": "204", "bench": "3", "latitude": 48.137, "longitude": 11.575}
+async fn update_device_location_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateDeviceLocationRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let location = normalize_device_location(req).map_err(ApiError::bad_request)?;
+
+    let updated = match app_state.db.set_device_location(&device_id, location.as_ref()).await {
+        Ok(updated) => updated,
+        Err(e) => {
+            tracing::error!("Database error updating location of device {}: {:?}", device_id, e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+    if !updated {
+        return Err(ApiError::not_found("Device not found"));
+    }
+
+    tracing::info!("Location of device {} set to {:?} by user {}", device_id, location, user_id);
+
+    let audience = device_list_audience(&app_state, &device_id).await;
+    broadcast_device_list_changed(&app_state, &device_id, events::DeviceListChange::Updated, &audience).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "location": location
+    })))
+}
+
+// POST /api/canvas-permissions/:id - Vereinfachter Permission Handler (optional auth)
+async fn simple_permissions_handler(
+    State(app_state): State<AppState>,
+    Path(canvas_id): Path<String>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdatePermissionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    // Authentication is optional; the user only shows up in the activity feed
+    let actor_id = optional_user_id(&cookie_jar);
+
+    // Validate permission
+    if req.permission != "REMOVE" && !["R", "W", "V", "M", "O"].contains(&req.permission.as_str()) {
+        return Err(ApiError::bad_request("Permission must be one of R, W, V, M, O or REMOVE"));
+    }
+    if let Some(expires_at) = req.expires_at {
+        if req.permission == "REMOVE" || req.permission == "O" {
+            return Err(ApiError::bad_request("expires_at is not allowed for owner permissions or REMOVE"));
+        }
+        if expires_at <= chrono::Utc::now() {
+            return Err(ApiError::bad_request("expires_at must be in the future"));
+        }
+    }
+
+    // Users losing access must hear about it too, so collect them before the change
+    let mut audience = device_list_audience(&app_state, &canvas_id).await;
+    audience.insert(req.user_id.clone());
+
+    // Update or remove permission
+    let updated = if req.permission == "REMOVE" {
+        app_state.db.remove_device_permission(&canvas_id, &req.user_id).await.is_ok()
+    } else {
+        app_state.db.set_device_permission_until(&canvas_id, &req.user_id, &req.permission, req.expires_at).await.is_ok()
+    };
+
+    if !updated {
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    broadcast_device_list_changed(&app_state, &canvas_id, events::DeviceListChange::PermissionsChanged, &audience).await;
+    activity::record(&app_state.db, &canvas_id, &actor_id, activity::AuditAction::PermissionChanged, json!({
+        "userId": req.user_id,
+        "permission": req.permission,
+        "expiresAt": req.expires_at,
+    })).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "Permission updated successfully",
+        "expires_at": req.expires_at
+    })))
+}
+
+// DELETE /api/devices/:id - device löschen
+async fn delete_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(canvas_id): Path<String>,
+) -> Result<Response<Body>, ApiError> {
+    // JWT Token validieren
+    let token = match cookie_jar.get("auth_token") {
+        Some(cookie) => cookie.value(),
+        None => return Err(ApiError::unauthorized("Authentication required")),
+    };
+
+    let claims = match validate_jwt(token) {
+        Ok(claims) => claims,
+        Err(_) => return Err(ApiError::unauthorized("Invalid or expired token")),
+    };
+
+    // Canvas aus Datenbank laden
+    let canvas = match app_state.db.get_device_by_id(&canvas_id).await {
+        Ok(Some(canvas)) => canvas,
+        Ok(None) => return Err(ApiError::not_found("Device not found")),
+        Err(e) => {
+            tracing::error!("Database error loading canvas: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    // Only owner can delete canvas
+    let has_permission = match permissions::PermissionResolver::new(&app_state.db).has_permission(&canvas_id, &claims.user_id, "O").await {
+        Ok(has_permission) => has_permission,
+        Err(e) => {
+            tracing::error!("Database error checking permissions: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    if !has_permission {
+        return Err(ApiError::forbidden("Insufficient permissions"));
+    }
+
+    // Audience must be collected before the permissions are gone
+    let audience = device_list_audience(&app_state, &canvas_id).await;
+
+    // Canvas löschen
+    if let Err(e) = app_state.db.delete_device(&canvas_id).await {
+        tracing::error!("Database error deleting canvas: {:?}", e);
+        return Err(ApiError::internal("Database error"));
+    }
+
+    tracing::info!("Canvas deleted: {} by user {}", canvas.name, claims.email);
+    app_state.device_store.clear_device_variables(&canvas_id).await;
+    broadcast_device_list_changed(&app_state, &canvas_id, events::DeviceListChange::Deleted, &audience).await;
+
+    Response::builder()
+        .header("content-type", "application/json")
+        .body(Body::from(json!({
+            "success": true,
+            "message": "Canvas deleted successfully"
+        }).to_string()))
+        .map_err(|_| ApiError::internal("Failed to build response"))
+}
+
+// GET /api/users/search - Search for users for permission management (optional auth)
+async fn search_users_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Value>, ApiError> {
+    // Validate JWT token (optional)
+    let _token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+    
+    // Authentication is optional, so no validation needed
+
+    // Suchterm aus Query-Parameter extrahieren
+    let query = params.get("q").cloned().unwrap_or_default();
+    
+    if query.len() < 2 {
+        return Ok(Json(json!({
+            "success": true,
+            "users": []
+        })));
+    }
+
+    // Benutzer in Datenbank suchen
+    let matching_users = match app_state.db.search_users(&query).await {
+        Ok(users) => {
+            users.into_iter().map(|user| {
+                json!({
+                    "user_id": user.id,
+                    "display_name": user.display_name
+                })
+            }).collect::<Vec<Value>>()
+        }
+        Err(e) => {
+            tracing::error!("Database error during user search: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    Ok(Json(json!({
+        "success": true,
+        "users": matching_users
+    })))
+}
+
+// GET /api/users/list - Get first users for scroll field  
+async fn list_users_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(params): axum::extract::Query<std::collections::HashMap<String, String>>,
+) -> Result<Json<Value>, ApiError> {
+    // Validate JWT token
+    let token = match cookie_jar.get("auth_token") {
+        Some(cookie) => cookie.value(),
+        None => return Err(ApiError::unauthorized("Authentication required")),
+    };
+
+    let _claims = match validate_jwt(token) {
+        Ok(claims) => claims,
+        Err(_) => return Err(ApiError::unauthorized("Invalid or expired token")),
+    };
+
+    // Pagination parameters 
+    let offset = params.get("offset").and_then(|s| s.parse::<i32>().ok()).unwrap_or(0);
+    let limit = params.get("limit").and_then(|s| s.parse::<i32>().ok()).unwrap_or(20);
+    let limit = limit.min(50); // Max 50 users at once
+
+    // Get users from database
+    let users = match app_state.db.get_users_paginated(offset, limit).await {
+        Ok(users) => {
+            users.into_iter().map(|user| {
+                json!({
+                    "user_id": user.id,
+                    "display_name": user.display_name
+                })
+            }).collect::<Vec<Value>>()
+        }
+        Err(e) => {
+            tracing::error!("Database error during user list: {:?}", e);
+            return Err(ApiError::internal("Database error"));
+        }
+    };
+
+    Ok(Json(json!({
+        "success": true,
+        "users": users
+    })))
+}
+
+// POST /api/devices/:id/connect - Establish TCP connection to device
+async fn tcp_connect_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("TCP connect request for device: {}", device_id);
+    match app_state.device_manager.connect_device(&device_id).await {
+        Ok(()) => {
+            activity::record(&app_state.db, &device_id, &optional_user_id(&cookie_jar), activity::AuditAction::Connected, json!({})).await;
+            Ok(Json(json!({ "success": true, "message": "TCP connection established" })))
+        }
+        Err(e) => Err(ApiError::from_device_error("TCP connect failed", &e)),
+    }
+}
+
+// POST /api/devices/:id/disconnect - Disconnect TCP from device
+async fn tcp_disconnect_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("TCP disconnect request for device: {}", device_id);
+    match app_state.device_manager.disconnect_device(&device_id).await {
+        Ok(()) => {
+            activity::record(&app_state.db, &device_id, &optional_user_id(&cookie_jar), activity::AuditAction::Disconnected, json!({})).await;
+            Ok(Json(json!({ "success": true, "message": "TCP disconnected" })))
+        }
+        Err(e) => Err(ApiError::from_device_error("TCP disconnect failed", &e)),
+    }
+}
+
+// POST /api/devices/:id/reconnect - Disconnect then reconnect TCP to device
+async fn tcp_reconnect_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("TCP reconnect request for device: {}", device_id);
+    let _ = app_state.device_manager.disconnect_device(&device_id).await;
+    match app_state.device_manager.connect_device(&device_id).await {
+        Ok(()) => Ok(Json(json!({ "success": true, "message": "TCP reconnected" }))),
+        Err(e) => Err(ApiError::from_device_error("TCP reconnect failed", &e)),
+    }
+}
+
+// POST /api/devices/:id/unquarantine - Release a quarantined device (manage permission)
+async fn unquarantine_device_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+
+    let lifecycle = app_state.device_manager.lifecycle();
+    let held_back = quarantine::release(&lifecycle, &device_id).await
+        .ok_or_else(|| ApiError::conflict("Device is not quarantined"))?;
+    activity::record(&app_state.db, &device_id, &user_id, activity::AuditAction::QuarantineReleased, json!({ "heldBack": held_back })).await;
+    tracing::info!("Device {} released from quarantine by {} ({} messages held back)", device_id, user_id, held_back);
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "state": lifecycle.get(&device_id).await.map(|entry| entry.state),
+        "heldBack": held_back
+    })))
+}
+
+// GET /api/devices/:id/connection-policy - Reconnect policy and supervisor state of the device
+async fn get_connection_policy_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let status = app_state.device_manager.supervisor().status(&device_id).await;
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "policy": status.policy,
+        "supervisor": status
+    })))
+}
+
+// PUT /api/devices/:id/connection-policy - Set the reconnect policy of the device
+// Body: {"mode":"immediate"}, {"mode":"backoff","initialDelayMs":1000,"maxDelayMs":60000} or {"mode":"manual_only"}
+async fn update_connection_policy_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(policy): ApiJson<device_supervisor::ReconnectPolicy>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    policy.validate().map_err(ApiError::bad_request)?;
+
+    if let Err(e) = app_state.db.set_device_connection_policy(&device_id, &policy).await {
+        tracing::error!("Failed to store connection policy for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+    app_state.device_manager.supervisor().set_policy(&device_id, policy).await;
+    tracing::info!("Connection policy of device {} set to {:?}", device_id, policy);
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "policy": policy
+    })))
+}
+
+// GET /api/devices/:id/connection-settings - Ports, UDP inactivity timeout and TCP keep-alive of the device
+async fn get_connection_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let settings = current_connection_settings(&app_state, &device_id).await?;
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "settings": settings
+    })))
+}
+
+// PATCH /api/devices/:id/connection-settings - Change some of the settings; the device stays connected
+// Body: {"udpTimeoutSeconds":60} or any of tcpPort, udpPort, keepaliveIdleSeconds, keepaliveIntervalSeconds
+async fn update_connection_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(patch): ApiJson<device_types::ConnectionSettingsPatch>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    if patch.is_empty() {
+        return Err(ApiError::bad_request("No connection settings given"));
+    }
+    let settings = patch.apply(&current_connection_settings(&app_state, &device_id).await?);
+    settings.validate().map_err(ApiError::bad_request)?;
+
+    if let Err(e) = app_state.db.set_device_connection_settings(&device_id, &settings).await {
+        tracing::error!("Failed to store connection settings for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+    app_state.device_manager.set_connection_settings(&device_id, settings).await;
+    tracing::info!("Connection settings of device {} set to {:?}", device_id, settings);
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "settings": settings
+    })))
+}
+
+/// Live settings of the manager, else the stored ones
+async fn current_connection_settings(app_state: &AppState, device_id: &str) -> Result<device_types::ConnectionSettings, ApiError> {
+    if let Some(settings) = app_state.device_manager.connection_settings(device_id).await {
+        return Ok(settings);
+    }
+    let stored = app_state.db.get_device_connection_settings(device_id).await.map_err(|e| {
+        tracing::error!("Failed to load connection settings for device {}: {}", device_id, e);
+        ApiError::internal("Internal server error")
+    })?;
+    stored.ok_or_else(|| ApiError::conflict("Device has no connection configuration yet (it is created when the device is registered or discovered)"))
+}
+
+// GET /api/devices/:id/tls - TLS settings of the device TCP channel
+async fn get_device_tls_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    match app_state.db.get_device_tls_settings(&device_id).await {
+        Ok(tls) => Ok(Json(json!({
+            "success": true,
+            "enabled": tls.is_some(),
+            "tls": tls
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to get TLS settings for device {}: {}", device_id, e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// PUT /api/devices/:id/tls - Enable/replace TLS and reconnect the device
+async fn update_device_tls_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(tls): ApiJson<device_types::DeviceTlsConfig>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    tracing::info!("Updating TLS settings for device {}", device_id);
+
+    // Only existing PEM files inside devices.tls_certs_dir; the error does not reveal which file or why
+    let names = [Some(&tls.ca_cert_path), tls.client_cert_path.as_ref(), tls.client_key_path.as_ref()];
+    if names.into_iter().flatten().any(|name| !device_types::tls_file_path(name).is_some_and(|path| path.is_file())) {
+        return Err(ApiError::bad_request("TLS files must be names of PEM files in the server's certificate directory"));
+    }
+    if tls.client_cert_path.is_some() != tls.client_key_path.is_some() {
+        return Err(ApiError::bad_request("Client certificate and key must be configured together"));
+    }
+
+    if let Err(e) = app_state.db.set_device_tls_settings(&device_id, &tls).await {
+        tracing::error!("Failed to store TLS settings for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    app_state.device_manager.set_device_tls(&device_id, Some(tls)).await;
+    reconnect_if_connected(&app_state, &device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "TLS settings updated"
+    })))
+}
+
+// DELETE /api/devices/:id/tls - Disable TLS (plaintext TCP) and reconnect the device
+async fn delete_device_tls_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    tracing::info!("Disabling TLS for device {}", device_id);
+
+    if let Err(e) = app_state.db.delete_device_tls_settings(&device_id).await {
+        tracing::error!("Failed to delete TLS settings for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    app_state.device_manager.set_device_tls(&device_id, None).await;
+    reconnect_if_connected(&app_state, &device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "TLS disabled"
+    })))
+}
+
+/// Device data (events, variables, logs, queue, ...) needs login, org membership and read permission; returns the user ID.
+/// Devices of other organizations look like unknown ones
+async fn require_device_read_permission(app_state: &AppState, cookie_jar: &CookieJar, device_id: &str) -> Result<String, ApiError> {
+    let claims = require_login(cookie_jar)?;
+    if websocket::check_device_org_membership(&app_state.db, device_id, &claims.user_id).await.is_err() {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    if app_state.db.get_device_by_id(device_id).await.map_err(permission_db_error)?.is_none() {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    let may_read = permissions::PermissionResolver::new(&app_state.db)
+        .has_permission(device_id, &claims.user_id, "R").await
+        .map_err(permission_db_error)?;
+    if !may_read {
+        return Err(ApiError::forbidden("No permission for this device"));
+    }
+    Ok(claims.user_id)
+}
+
+/// Settings that decide how a device is reached or whether it is trusted need manage permission; returns the user ID
+async fn require_device_manage_permission(app_state: &AppState, cookie_jar: &CookieJar, device_id: &str) -> Result<String, ApiError> {
+    let claims = require_login(cookie_jar)?;
+    websocket::check_device_org_membership(&app_state.db, device_id, &claims.user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+    app_state.db.get_device_by_id(device_id).await.map_err(permission_db_error)?
+        .ok_or_else(|| ApiError::not_found("Device not found"))?;
+    if !permissions::PermissionResolver::new(&app_state.db).has_permission(device_id, &claims.user_id, "M").await.map_err(permission_db_error)? {
+        return Err(ApiError::forbidden("Insufficient permissions"));
+    }
+    Ok(claims.user_id)
+}
+
+// GET /api/devices/:id/identity - Whether the device has to pass the identity challenge (the secret is never returned)
+async fn get_device_identity_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let enabled = app_state.db.has_device_identity_secret(&device_id).await.map_err(|e| {
+        tracing::error!("Failed to get identity secret for device {}: {}", device_id, e);
+        ApiError::internal("Internal server error")
+    })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "enabled": enabled,
+        "algorithm": device_identity::ALGORITHM
+    })))
+}
+
+// PUT /api/devices/:id/identity - Set the shared secret (generated if the body has none) and reconnect the device
+// Body: {"secret": "..."} (optional); the response contains the secret once, to be flashed into the firmware
+async fn update_device_identity_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    OptionalApiJson(req): OptionalApiJson<device_identity::UpdateDeviceIdentityRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let secret = req.and_then(|req| req.secret).unwrap_or_else(device_identity::generate_secret);
+    device_identity::validate_secret(&secret).map_err(ApiError::bad_request)?;
+
+    if let Err(e) = app_state.db.set_device_identity_secret(&device_id, &secret).await {
+        tracing::error!("Failed to store identity secret for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+    tracing::info!("Identity secret set for device {}", device_id);
+
+    app_state.device_manager.set_device_identity(&device_id, Some(secret.clone())).await;
+    reconnect_if_connected(&app_state, &device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "enabled": true,
+        "algorithm": device_identity::ALGORITHM,
+        "secret": secret
+    })))
+}
+
+// DELETE /api/devices/:id/identity - Connect the device without identity check again
+async fn delete_device_identity_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+
+    if let Err(e) = app_state.db.delete_device_identity_secret(&device_id).await {
+        tracing::error!("Failed to delete identity secret for device {}: {}", device_id, e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+    tracing::info!("Identity check disabled for device {}", device_id);
+
+    app_state.device_manager.set_device_identity(&device_id, None).await;
+    reconnect_if_connected(&app_state, &device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "enabled": false
+    })))
+}
+
+// Reconnect a connected device so changed connection settings take effect
+async fn reconnect_if_connected(app_state: &AppState, device_id: &str) {
+    let connected = matches!(
+        app_state.device_manager.get_device_state(device_id).await,
+        Some(device_types::ConnectionState::Connected)
+    );
+    if connected {
+        let _ = app_state.device_manager.disconnect_device(device_id).await;
+        if let Err(e) = app_state.device_manager.connect_device(device_id).await {
+            tracing::warn!("Reconnect of device {} after settings change failed: {}", device_id, e);
+        }
+    }
+}
+
+/// Query of POST /api/devices/:id/command
+#[derive(Deserialize, Default)]
+struct DeviceCommandQuery {
+    /// How long to wait for the device reply (0 = return once sent)
+    timeout_ms: Option<u64>,
+}
+
+// POST /api/devices/:id/command - Send a device command without WebSocket (requires login and write permission)
+// Body: same JSON as the WebSocket command, e.g. {"setVariable": {"name": "speed", "value": 5}},
+// {"startOption": "demo"}, {"reset": true}, {"getStatus": true} or {"setTime": <epoch ms>}; optional "requestId"
+async fn device_command_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<DeviceCommandQuery>,
+    ApiJson(command): ApiJson<Value>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    if let Err(e) = app_state.device_manager.check_command_rate(&device_id, &user_id) {
+        let retry_after_ms = match &e {
+            device_types::DeviceError::RateLimited(limited) => limited.retry_after.as_millis().max(1) as u64,
+            _ => 0,
+        };
+        return Err(ApiError::from_device_error("Command rejected", &e)
+            .with_details(json!({ "deviceId": device_id, "retryAfterMs": retry_after_ms })));
+    }
+    let (status, body) = execute_device_command(&app_state, &device_id, command, &user_id, query.timeout_ms).await?;
+    Ok((status, Json(body)))
+}
+
+/// User ID from the auth cookie, "guest" without (valid) token
+fn optional_user_id(cookie_jar: &CookieJar) -> String {
+    cookie_jar.get("auth_token")
+        .and_then(|cookie| validate_jwt(cookie.value()).ok())
+        .map(|claims| claims.user_id)
+        .unwrap_or_else(|| "guest".to_string())
+}
+
+/// Send one command to one device and wait for the reply (shared by single and bulk endpoints)
+/// REST callers need write permission from the resolver; the open-device rule of the WebSocket does not apply
+async fn execute_device_command(
+    app_state: &AppState,
+    device_id: &str,
+    mut command: Value,
+    user_id: &str,
+    timeout_ms: Option<u64>,
+) -> Result<(StatusCode, Value), ApiError> {
+    let error_response = |error: events::WsError| ApiError::from(error).with_details(json!({"deviceId": device_id}));
+
+    websocket::check_device_org_membership(&app_state.db, device_id, user_id).await.map_err(error_response)?;
+    let may_write = user_id != "guest" && permissions::PermissionResolver::new(&app_state.db)
+        .has_permission(device_id, user_id, "W").await
+        .map_err(permission_db_error)?;
+    if !may_write {
+        return Err(error_response(events::WsError::new(
+            events::ErrorCode::PermissionDenied,
+            format!("User {} does not have write permission for device {}", user_id, device_id),
+        )));
+    }
+
+    let Some(command_object) = command.as_object_mut() else {
+        return Err(error_response(events::WsError::new(events::ErrorCode::InvalidMessage, "Command must be a JSON object")));
+    };
+    let request_id = command_object.get("requestId")
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    command_object.insert("requestId".to_string(), json!(request_id));
+
+    // UART devices have no reply correlation - the command is only forwarded
+    let connection_type = app_state.device_manager.get_device_connection_type(device_id).await;
+    if connection_type == Some(device_manager::DeviceConnectionType::Uart) {
+        let sent = app_state.uart_connection.lock().await.send_command(device_id, &command.to_string()).await;
+        return match sent {
+            Ok(()) => {
+                activity::record(&app_state.db, device_id, user_id, activity::AuditAction::Command, json!({ "command": command })).await;
+                Ok((StatusCode::OK, json!({
+                    "success": true, "deviceId": device_id, "requestId": request_id, "status": "sent"
+                })))
+            }
+            Err(e) => Err(error_response(events::WsError::new(events::ErrorCode::CommandFailed, format!("UART command failed: {}", e)))),
+        };
+    }
+
+    if app_state.device_manager.get_device_config(device_id).await.is_none() {
+        return Err(error_response(events::WsError::new(events::ErrorCode::UnknownDevice, format!("Device {} is not known", device_id))));
+    }
+
+    let pending_requests = app_state.device_store.pending_requests();
+    let client_id = format!("rest-{}", request_id);
+
+    let audit_details = json!({ "command": command.clone() });
+    // The reply waiter is registered before the command goes out, the reply may be fast
+    let command_queue::ClientDispatch { correlation_id, dispatch, reply, .. } =
+        match app_state.device_manager.dispatch_client_command(device_id, command, user_id, &client_id, true).await {
+            Ok(dispatched) => dispatched,
+            Err(e) => {
+                let code = websocket::device_error_code(&e);
+                return Err(error_response(events::WsError::new(code, format!("device command failed: {}", e))));
+            }
+        };
+    let Some(reply) = reply else {
+        return Err(ApiError::internal("Device reply waiter missing"));
+    };
+    activity::record(&app_state.db, device_id, user_id, activity::AuditAction::Command, audit_details).await;
+
+    // Device offline: the command waits in the outbound queue
+    if let command_queue::CommandDispatch::Queued { .. } = dispatch {
+        return Ok((StatusCode::ACCEPTED, json!({
+            "success": true, "deviceId": device_id, "requestId": request_id, "dispatch": dispatch
+        })));
+    }
+
+    let wait = std::time::Duration::from_millis(timeout_ms.unwrap_or(u64::MAX)).min(pending_requests.timeout());
+    if wait.is_zero() {
+        pending_requests.cancel(&correlation_id).await;
+        return Ok((StatusCode::OK, json!({
+            "success": true, "deviceId": device_id, "requestId": request_id, "status": "sent"
+        })));
+    }
+
+    match tokio::time::timeout(wait, reply).await {
+        Ok(Ok(response)) => Ok((StatusCode::OK, json!({
+            "success": true, "deviceId": device_id, "requestId": request_id,
+            "status": "acknowledged", "response": response
+        }))),
+        _ => {
+            pending_requests.cancel(&correlation_id).await;
+            Err(ApiError::new(
+                StatusCode::GATEWAY_TIMEOUT,
+                "COMMAND_TIMEOUT",
+                format!("No reply from device {} within {}ms", device_id, wait.as_millis()),
+            ).with_details(json!({"deviceId": device_id, "requestId": request_id, "status": "timeout"})))
+        }
+    }
+}
+
+// ============================================================================
+// DEVICE GROUPS AND BULK COMMANDS
+// ============================================================================
+
+/// Commands of a bulk request in flight at the same time
+const BULK_COMMAND_PARALLELISM: usize = 8;
+
+/// Upper bound of devices per group / bulk request
+const MAX_BULK_DEVICES: usize = 500;
+
+/// Send a command to many devices with bounded parallelism; per-device report in input order
+async fn run_bulk_command(
+    app_state: &AppState,
+    device_ids: Vec<String>,
+    mut command: Value,
+    user_id: &str,
+    timeout_ms: Option<u64>,
+) -> Value {
+    // Every device gets its own request ID for reply correlation
+    if let Some(command_object) = command.as_object_mut() {
+        command_object.remove("requestId");
+    }
+
+    let mut seen = std::collections::HashSet::new();
+    let device_ids: Vec<String> = device_ids.into_iter().filter(|id| seen.insert(id.clone())).collect();
+
+    use futures::StreamExt;
+    let results: Vec<Value> = futures::stream::iter(device_ids)
+        .map(|device_id| {
+            let command = command.clone();
+            async move {
+                let (status, mut result) = execute_device_command(app_state, &device_id, command, user_id, timeout_ms).await
+                    .unwrap_or_else(|error| (error.status, error.body()));
+                result["deviceId"] = json!(device_id);
+                result["httpStatus"] = json!(status.as_u16());
+                result
+            }
+        })
+        .buffered(BULK_COMMAND_PARALLELISM)
+        .collect()
+        .await;
+
+    let succeeded = results.iter().filter(|result| result["success"] == true).count();
+    json!({
+        "success": succeeded == results.len(),
+        "total": results.len(),
+        "succeeded": succeeded,
+        "failed": results.len() - succeeded,
+        "results": results
+    })
+}
+
+/// Load a group the user owns (404 unknown, 403 foreign)
+async fn load_owned_device_group(app_state: &AppState, group_id: &str, user_id: &str) -> Result<database::DeviceGroup, ApiError> {
+    let group = app_state.db.get_device_group(group_id).await.map_err(|e| {
+        tracing::error!("Database error loading device group {}: {}", group_id, e);
+        ApiError::internal("Database error")
+    })?;
+    let Some(group) = group else {
+        return Err(ApiError::not_found("Device group not found"));
+    };
+    if group.owner_id == user_id {
+        return Ok(group);
+    }
+    // Groups of an organization are shared by its members
+    if let Some(org_id) = &group.org_id {
+        let role = app_state.db.get_org_role(org_id, user_id).await.map_err(|e| {
+            tracing::error!("Database error loading organization role: {}", e);
+            ApiError::internal("Database error")
+        })?;
+        if role.is_some() {
+            return Ok(group);
+        }
+    }
+    Err(ApiError::forbidden("Device group belongs to another user"))
+}
+
+/// Validate name and member list of a device group
+fn validate_device_group(name: Option<&str>, device_ids: Option<&[String]>) -> Result<(), String> {
+    if let Some(name) = name {
+        if name.trim().is_empty() || name.len() > 100 {
+            return Err("Group name must be between 1 and 100 characters".to_string());
+        }
+    }
+    if let Some(device_ids) = device_ids {
+        if device_ids.len() > MAX_BULK_DEVICES {
+            return Err(format!("A group can contain at most {} devices", MAX_BULK_DEVICES));
+        }
+        if device_ids.iter().any(|id| id.trim().is_empty()) {
+            return Err("Device IDs must not be empty".to_string());
+        }
+    }
+    Ok(())
+}
+
+// POST /api/devices/bulk/command?timeout_ms= - Command to an explicit device list (requires login, write permission per device)
+// Body: {"device_ids": ["AA-BB-..", ..], "command": {"reset": true}}
+async fn bulk_command_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(query): axum::extract::Query<DeviceCommandQuery>,
+    ApiJson(req): ApiJson<BulkCommandRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+
+    if req.device_ids.is_empty() || req.device_ids.len() > MAX_BULK_DEVICES {
+        return Err(ApiError::bad_request(format!("device_ids must contain between 1 and {} devices", MAX_BULK_DEVICES)));
+    }
+
+    tracing::info!("Bulk command to {} devices by user {}", req.device_ids.len(), user_id);
+    Ok(Json(run_bulk_command(&app_state, req.device_ids, req.command, &user_id, query.timeout_ms).await))
+}
+
+// POST /api/device-groups/:id/command?timeout_ms= - Command to all devices of a group (requires login)
+// Body: same JSON as POST /api/devices/:id/command
+async fn device_group_command_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<DeviceCommandQuery>,
+    ApiJson(command): ApiJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let group = load_owned_device_group(&app_state, &group_id, &user_id).await?;
+
+    tracing::info!("Group command to {} ({} devices) by user {}", group.name, group.device_ids.len(), user_id);
+    let mut report = run_bulk_command(&app_state, group.device_ids, command, &user_id, query.timeout_ms).await;
+    report["groupId"] = json!(group.id);
+    Ok(Json(report))
+}
+
+// GET /api/device-groups - Device groups of the user (requires login)
+async fn list_device_groups_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let groups = match org_context(&app_state, &headers, &user_id).await? {
+        Some((org, _)) => app_state.db.list_org_device_groups(&org.id).await,
+        None => app_state.db.list_device_groups(&user_id).await,
+    };
+    let groups = groups.map_err(|e| {
+        tracing::error!("Database error listing device groups: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "groups": groups })))
+}
+
+// POST /api/device-groups - Create a device group (requires login)
+async fn create_device_group_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    ApiJson(req): ApiJson<CreateDeviceGroupRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+
+    if let Err(message) = validate_device_group(Some(&req.name), Some(&req.device_ids)) {
+        return Err(ApiError::bad_request(message));
+    }
+
+    // X-Org: group shared with the organization, only its devices may be members
+    let org_id = org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id);
+    if let Some(org_id) = &org_id {
+        for device_id in &req.device_ids {
+            let device = app_state.db.get_device_by_id(device_id).await.map_err(|e| {
+                tracing::error!("Database error loading device {}: {}", device_id, e);
+                ApiError::internal("Database error")
+            })?;
+            if device.and_then(|device| device.org_id).as_ref() != Some(org_id) {
+                return Err(ApiError::bad_request(format!("Device {} does not belong to the organization", device_id)));
+            }
+        }
+    }
+
+    let group = app_state.db.create_device_group(req.name.trim(), &user_id, org_id.as_deref(), &req.device_ids).await.map_err(|e| {
+        tracing::error!("Database error creating device group: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    tracing::info!("Device group created: {} by user {}", group.name, user_id);
+    Ok(Json(json!({ "success": true, "message": "Device group created", "group": group })))
+}
+
+// GET /api/device-groups/:id - Device group details (requires login)
+async fn get_device_group_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let group = load_owned_device_group(&app_state, &group_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "group": group })))
+}
+
+// PUT /api/device-groups/:id - Rename a group and/or replace its members (requires login)
+async fn update_device_group_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateDeviceGroupRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_device_group(&app_state, &group_id, &user_id).await?;
+
+    if let Err(message) = validate_device_group(req.name.as_deref(), req.device_ids.as_deref()) {
+        return Err(ApiError::bad_request(message));
+    }
+
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error updating device group {}: {}", group_id, e);
+        ApiError::internal("Database error")
+    };
+    if let Some(name) = &req.name {
+        app_state.db.rename_device_group(&group_id, name.trim()).await.map_err(db_error)?;
+    }
+    if let Some(device_ids) = &req.device_ids {
+        app_state.db.set_device_group_members(&group_id, device_ids).await.map_err(db_error)?;
+    }
+
+    let group = load_owned_device_group(&app_state, &group_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "message": "Device group updated", "group": group })))
+}
+
+// DELETE /api/device-groups/:id - Delete a device group (requires login)
+async fn delete_device_group_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_device_group(&app_state, &group_id, &user_id).await?;
+
+    app_state.db.delete_device_group(&group_id).await.map_err(|e| {
+        tracing::error!("Database error deleting device group {}: {}", group_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "message": "Device group deleted" })))
+}
+
+/// Load a group for changing its permissions: only the owner may pass on access
+async fn load_group_for_grants(app_state: &AppState, group_id: &str, user_id: &str) -> Result<database::DeviceGroup, ApiError> {
+    let group = load_owned_device_group(app_state, group_id, user_id).await?;
+    if group.owner_id != user_id {
+        return Err(ApiError::forbidden("Only the group owner can manage group permissions"));
+    }
+    Ok(group)
+}
+
+// GET /api/device-groups/:id/permissions - Permissions the group passes on to its devices (requires login)
+async fn list_device_group_permissions_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_device_group(&app_state, &group_id, &user_id).await?;
+    let grants = app_state.db.list_device_group_permissions(&group_id).await.map_err(permission_db_error)?;
+    let permissions: Vec<Value> = grants.into_iter()
+        .map(|(user_id, permission)| json!({ "userId": user_id, "permission": permission }))
+        .collect();
+    Ok(Json(json!({ "success": true, "groupId": group_id, "permissions": permissions })))
+}
+
+// PUT /api/device-groups/:id/permissions - Grant a user a permission on the group's devices (group owner)
+// Body: {"user_id": "..", "permission": "R"}
+async fn set_device_group_permission_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(group_id): Path<String>,
+    ApiJson(req): ApiJson<DeviceGroupPermissionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let group = load_group_for_grants(&app_state, &group_id, &claims.user_id).await?;
+    if !permissions::GROUP_LEVELS.contains(&req.permission.as_str()) {
+        return Err(ApiError::bad_request(format!("Group permission must be one of {}", permissions::GROUP_LEVELS.join(", "))));
+    }
+    if app_state.db.get_user_by_id(&req.user_id).await.map_err(permission_db_error)?.is_none() {
+        return Err(ApiError::not_found("User not found"));
+    }
+
+    app_state.db.set_device_group_permission(&group_id, &req.user_id, &req.permission).await.map_err(permission_db_error)?;
+    for device_id in &group.device_ids {
+        broadcast_device_list_changed(&app_state, device_id, events::DeviceListChange::Updated, &std::collections::HashSet::from([req.user_id.clone()])).await;
+    }
+    tracing::info!("Group {} grants {} to user {} (by {})", group_id, req.permission, req.user_id, claims.user_id);
+    Ok(Json(json!({ "success": true, "groupId": group_id, "userId": req.user_id, "permission": req.permission })))
+}
+
+// DELETE /api/device-groups/:id/permissions/:user_id - Revoke a group permission (group owner)
+async fn delete_device_group_permission_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((group_id, grantee_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let group = load_group_for_grants(&app_state, &group_id, &claims.user_id).await?;
+    if !app_state.db.delete_device_group_permission(&group_id, &grantee_id).await.map_err(permission_db_error)? {
+        return Err(ApiError::not_found("Group permission not found"));
+    }
+    for device_id in &group.device_ids {
+        broadcast_device_list_changed(&app_state, device_id, events::DeviceListChange::Updated, &std::collections::HashSet::from([grantee_id.clone()])).await;
+    }
+    Ok(Json(json!({ "success": true, "message": "Group permission revoked" })))
+}
+
+/// Load a webhook the user owns (404 unknown, 403 foreign)
+async fn load_owned_webhook(app_state: &AppState, webhook_id: &str, user_id: &str) -> Result<database::Webhook, ApiError> {
+    let webhook = app_state.db.get_webhook(webhook_id).await.map_err(|e| {
+        tracing::error!("Database error loading webhook {}: {}", webhook_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match webhook {
+        Some(webhook) if webhook.owner_id == user_id => Ok(webhook),
+        Some(_) => Err(ApiError::forbidden("Webhook belongs to another user")),
+        None => Err(ApiError::not_found("Webhook not found")),
+    }
+}
+
+/// Validate URL, event types and device filter of a webhook
+fn validate_webhook(url: Option<&str>, events: Option<&[String]>, device_ids: Option<&[String]>) -> Result<(), String> {
+    if let Some(url) = url {
+        webhooks::validate_url(url)?;
+    }
+    if let Some(events) = events {
+        if events.is_empty() {
+            return Err("At least one event type is required".to_string());
+        }
+        if let Some(unknown) = events.iter().find(|event| webhooks::WebhookEvent::parse(event).is_none()) {
+            let known: Vec<&str> = webhooks::WebhookEvent::ALL.iter().map(|event| event.as_str()).collect();
+            return Err(format!("Unknown event type '{}' (known: {})", unknown, known.join(", ")));
+        }
+    }
+    if let Some(device_ids) = device_ids {
+        if device_ids.len() > MAX_BULK_DEVICES || device_ids.iter().any(|id| id.trim().is_empty()) {
+            return Err(format!("device_ids must contain at most {} non-empty device IDs", MAX_BULK_DEVICES));
+        }
+    }
+    Ok(())
+}
+
+// GET /api/webhooks - Webhooks of the user
+async fn list_webhooks_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let webhooks = app_state.db.list_webhooks(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing webhooks: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "webhooks": webhooks })))
+}
+
+// POST /api/webhooks - Create a webhook; the signing secret is only returned here
+async fn create_webhook_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateWebhookRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+
+    if let Err(message) = validate_webhook(Some(&req.url), Some(&req.events), Some(&req.device_ids)) {
+        return Err(ApiError::bad_request(message));
+    }
+    webhooks::check_target(req.url.trim()).await.map_err(ApiError::bad_request)?;
+    let secret = match req.secret {
+        Some(secret) if secret.len() < 16 => return Err(ApiError::bad_request("secret must be at least 16 characters")),
+        Some(secret) => secret,
+        None => webhooks::generate_secret(),
+    };
+
+    let webhook = app_state.db.create_webhook(&user_id, req.url.trim(), &secret, &req.events, &req.device_ids).await.map_err(|e| {
+        tracing::error!("Database error creating webhook: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    tracing::info!("Webhook {} created for {:?} by user {}", webhook.id, webhook.events, user_id);
+    Ok(Json(json!({ "success": true, "message": "Webhook created", "webhook": webhook, "secret": secret })))
+}
+
+// GET /api/webhooks/:id - Webhook details
+async fn get_webhook_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let webhook = load_owned_webhook(&app_state, &webhook_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "webhook": webhook })))
+}
+
+// PUT /api/webhooks/:id - Change URL, events, device filter or enabled flag
+async fn update_webhook_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(webhook_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateWebhookRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut webhook = load_owned_webhook(&app_state, &webhook_id, &user_id).await?;
+
+    if let Err(message) = validate_webhook(req.url.as_deref(), req.events.as_deref(), req.device_ids.as_deref()) {
+        return Err(ApiError::bad_request(message));
+    }
+    if let Some(url) = req.url {
+        webhooks::check_target(url.trim()).await.map_err(ApiError::bad_request)?;
+        webhook.url = url.trim().to_string();
+    }
+    if let Some(events) = req.events {
+        webhook.events = events;
+    }
+    if let Some(device_ids) = req.device_ids {
+        webhook.device_ids = device_ids;
+    }
+    if let Some(enabled) = req.enabled {
+        webhook.enabled = enabled;
+    }
+
+    app_state.db.update_webhook(&webhook).await.map_err(|e| {
+        tracing::error!("Database error updating webhook {}: {}", webhook_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "message": "Webhook updated", "webhook": webhook })))
+}
+
+// DELETE /api/webhooks/:id - Delete a webhook and its delivery log
+async fn delete_webhook_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_webhook(&app_state, &webhook_id, &user_id).await?;
+
+    app_state.db.delete_webhook(&webhook_id).await.map_err(|e| {
+        tracing::error!("Database error deleting webhook {}: {}", webhook_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "message": "Webhook deleted" })))
+}
+
+/// Query of GET /api/webhooks/:id/deliveries
+#[derive(Deserialize, Default)]
+struct WebhookDeliveriesQuery {
+    limit: Option<usize>,
+}
+
+// GET /api/webhooks/:id/deliveries?limit= - Delivery log incl. attempts and last response
+async fn webhook_deliveries_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(webhook_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<WebhookDeliveriesQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_webhook(&app_state, &webhook_id, &user_id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, webhooks::DELIVERY_LOG_SIZE);
+    let deliveries = app_state.db.list_webhook_deliveries(&webhook_id, limit).await.map_err(|e| {
+        tracing::error!("Database error listing deliveries of webhook {}: {}", webhook_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "count": deliveries.len(), "deliveries": deliveries })))
+}
+
+// POST /api/webhooks/:id/test - Deliver a test event once and report the outcome
+async fn test_webhook_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(webhook_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let webhook = load_owned_webhook(&app_state, &webhook_id, &user_id).await?;
+
+    let data = json!({ "message": "Test event from ESP32 Device Manager" });
+    let delivery = webhooks::deliver(&app_state.db, &webhook, webhooks::TEST_EVENT, None, data, 1).await;
+
+    Ok(Json(json!({ "success": delivery.status == "success", "delivery": delivery })))
+}
+
+/// Load an alert rule the user owns (404 unknown, 403 foreign)
+async fn load_owned_alert_rule(app_state: &AppState, rule_id: &str, user_id: &str) -> Result<database::AlertRule, ApiError> {
+    let rule = app_state.db.get_alert_rule(rule_id).await.map_err(|e| {
+        tracing::error!("Database error loading alert rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match rule {
+        Some(rule) if rule.owner_id == user_id => Ok(rule),
+        Some(_) => Err(ApiError::forbidden("Alert rule belongs to another user")),
+        None => Err(ApiError::not_found("Alert rule not found")),
+    }
+}
+
+/// Canonical condition text, or 400 with the parser message
+fn parse_alert_condition(condition: &str) -> Result<String, ApiError> {
+    alerts::AlertCondition::parse(condition)
+        .map(|condition| condition.to_string())
+        .map_err(ApiError::bad_request)
+}
+
+fn validate_alert_rule_name(name: &str) -> Result<(), ApiError> {
+    if name.trim().is_empty() || name.len() > 100 {
+        return Err(ApiError::bad_request("Rule name must be between 1 and 100 characters"));
+    }
+    Ok(())
+}
+
+// GET /api/alert-rules - Alert rules of the user (requires login)
+async fn list_alert_rules_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let rules = app_state.db.list_alert_rules(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing alert rules: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "rules": rules })))
+}
+
+// POST /api/alert-rules - Create an alert rule for a device (requires login)
+async fn create_alert_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateAlertRuleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+
+    if req.device_id.trim().is_empty() {
+        return Err(ApiError::bad_request("device_id must not be empty"));
+    }
+    let condition = parse_alert_condition(&req.condition)?;
+    let name = req.name.unwrap_or_else(|| condition.clone());
+    validate_alert_rule_name(&name)?;
+
+    let rule = app_state.db.create_alert_rule(&user_id, req.device_id.trim(), name.trim(), &condition, req.notify_webhook).await.map_err(|e| {
+        tracing::error!("Database error creating alert rule: {}", e);
+        ApiError::internal("Database error")
+    })?;
+    alerts::rules_changed();
+
+    tracing::info!("Alert rule '{}' ({}) created for device {} by user {}", rule.name, rule.condition, rule.device_id, user_id);
+    Ok(Json(json!({ "success": true, "message": "Alert rule created", "rule": rule })))
+}
+
+// GET /api/alert-rules/:id - Alert rule details (requires login)
+async fn get_alert_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let rule = load_owned_alert_rule(&app_state, &rule_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "rule": rule })))
+}
+
+// PUT /api/alert-rules/:id - Change name, condition, notification or enabled flag (requires login)
+async fn update_alert_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateAlertRuleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut rule = load_owned_alert_rule(&app_state, &rule_id, &user_id).await?;
+
+    if let Some(name) = req.name {
+        validate_alert_rule_name(&name)?;
+        rule.name = name.trim().to_string();
+    }
+    if let Some(condition) = req.condition {
+        rule.condition = parse_alert_condition(&condition)?;
+    }
+    if let Some(notify_webhook) = req.notify_webhook {
+        rule.notify_webhook = notify_webhook;
+    }
+    if let Some(enabled) = req.enabled {
+        rule.enabled = enabled;
+    }
+
+    app_state.db.update_alert_rule(&rule).await.map_err(|e| {
+        tracing::error!("Database error updating alert rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    alerts::rules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Alert rule updated", "rule": rule })))
+}
+
+// DELETE /api/alert-rules/:id - Delete a rule and its alerts (requires login)
+async fn delete_alert_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_alert_rule(&app_state, &rule_id, &user_id).await?;
+
+    app_state.db.delete_alert_rule(&rule_id).await.map_err(|e| {
+        tracing::error!("Database error deleting alert rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    alerts::rules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Alert rule deleted" })))
+}
+
+/// Query of GET /api/alerts
+#[derive(Deserialize, Default)]
+struct AlertsQuery {
+    device_id: Option<String>,
+    /// firing or resolved
+    state: Option<String>,
+    limit: Option<usize>,
+}
+
+// GET /api/alerts?device_id=&state=firing&limit= - Alerts of the user's rules (requires login)
+async fn list_alerts_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(query): axum::extract::Query<AlertsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    if query.state.as_deref().is_some_and(|state| state != "firing" && state != "resolved") {
+        return Err(ApiError::bad_request("state must be firing or resolved"));
+    }
+
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000);
+    let alerts = app_state.db.list_alerts(&user_id, query.device_id.as_deref(), query.state.as_deref(), limit).await.map_err(|e| {
+        tracing::error!("Database error listing alerts: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "count": alerts.len(), "alerts": alerts })))
+}
+
+/// Command executor of schedules and scripts: a bulk command as the owner
+fn schedule_executor(app_state: &AppState) -> schedules::CommandExecutor {
+    let app_state = app_state.clone();
+    Arc::new(move |device_ids, command, user_id| {
+        let app_state = app_state.clone();
+        Box::pin(async move { run_bulk_command(&app_state, device_ids, command, &user_id, None).await })
+    })
+}
+
+/// Load a schedule the user owns (404 unknown, 403 foreign)
+async fn load_owned_schedule(app_state: &AppState, schedule_id: &str, user_id: &str) -> Result<database::Schedule, ApiError> {
+    let schedule = app_state.db.get_schedule(schedule_id).await.map_err(|e| {
+        tracing::error!("Database error loading schedule {}: {}", schedule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match schedule {
+        Some(schedule) if schedule.owner_id == user_id => Ok(schedule),
+        Some(_) => Err(ApiError::forbidden("Schedule belongs to another user")),
+        None => Err(ApiError::not_found("Schedule not found")),
+    }
+}
+
+/// Check name, target and command of a schedule before it is stored
+async fn validate_schedule(app_state: &AppState, schedule: &database::Schedule) -> Result<(), ApiError> {
+    if schedule.name.trim().is_empty() || schedule.name.len() > 100 {
+        return Err(ApiError::bad_request("Schedule name must be between 1 and 100 characters"));
+    }
+    if schedule.command.as_object().is_none_or(|command| command.is_empty()) {
+        return Err(ApiError::bad_request("command must be a non-empty JSON object"));
+    }
+    match (&schedule.device_id, &schedule.group_id) {
+        (Some(device_id), None) => {
+            if device_id.trim().is_empty() {
+                return Err(ApiError::bad_request("device_id must not be empty"));
+            }
+            websocket::check_device_write_permission(&app_state.db, device_id, &schedule.owner_id).await
+                .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+        }
+        (None, Some(group_id)) => {
+            load_owned_device_group(app_state, group_id, &schedule.owner_id).await?;
+        }
+        _ => return Err(ApiError::bad_request("Exactly one of device_id and group_id is required")),
+    }
+    Ok(())
+}
+
+/// Parsed schedule text, or 400 with the parser message
+fn parse_schedule_spec(text: &str) -> Result<schedules::ScheduleSpec, ApiError> {
+    let spec = schedules::ScheduleSpec::parse(text).map_err(ApiError::bad_request)?;
+    if spec.next_run(chrono::Utc::now()).is_none() {
+        return Err(ApiError::bad_request(format!("Schedule '{}' never runs", spec)));
+    }
+    Ok(spec)
+}
+
+// GET /api/schedules - Command schedules of the user (requires login)
+async fn list_schedules_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let schedules = app_state.db.list_schedules(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing schedules: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "schedules": schedules })))
+}
+
+// POST /api/schedules - Create a schedule for a device or device group (requires login)
+// Body: {"schedule": "every day at 18:00", "group_id": "..", "command": {"startOption": "night_mode"}}
+async fn create_schedule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateScheduleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let spec = parse_schedule_spec(&req.schedule)?;
+    let now = chrono::Utc::now();
+    let enabled = req.enabled.unwrap_or(true);
+
+    let schedule = database::Schedule {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id.clone(),
+        name: req.name.map(|name| name.trim().to_string()).unwrap_or_else(|| spec.to_string()),
+        schedule: spec.to_string(),
+        device_id: req.device_id.map(|id| id.trim().to_string()),
+        group_id: req.group_id,
+        command: req.command,
+        enabled,
+        catch_up: req.catch_up.unwrap_or(true),
+        next_run_at: if enabled { spec.next_run(now) } else { None },
+        last_run_at: None,
+        created_at: now,
+    };
+    validate_schedule(&app_state, &schedule).await?;
+
+    app_state.db.create_schedule(&schedule).await.map_err(|e| {
+        tracing::error!("Database error creating schedule: {}", e);
+        ApiError::internal("Database error")
+    })?;
+    schedules::schedules_changed();
+
+    tracing::info!("Schedule '{}' ({}) created by user {}", schedule.name, schedule.schedule, user_id);
+    Ok(Json(json!({ "success": true, "message": "Schedule created", "schedule": schedule })))
+}
+
+// GET /api/schedules/:id - Schedule details (requires login)
+async fn get_schedule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(schedule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let schedule = load_owned_schedule(&app_state, &schedule_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "schedule": schedule })))
+}
+
+// PUT /api/schedules/:id - Change name, timing, target, command or flags (requires login)
+async fn update_schedule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(schedule_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateScheduleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut schedule = load_owned_schedule(&app_state, &schedule_id, &user_id).await?;
+    let was_enabled = schedule.enabled;
+
+    let spec = parse_schedule_spec(req.schedule.as_deref().unwrap_or(&schedule.schedule))?;
+    let timing_changed = spec.to_string() != schedule.schedule;
+    schedule.schedule = spec.to_string();
+
+    if let Some(name) = req.name {
+        schedule.name = name.trim().to_string();
+    }
+    if let Some(device_id) = req.device_id {
+        schedule.device_id = Some(device_id.trim().to_string());
+        schedule.group_id = None;
+    }
+    if let Some(group_id) = req.group_id {
+        schedule.group_id = Some(group_id);
+        schedule.device_id = None;
+    }
+    if let Some(command) = req.command {
+        schedule.command = command;
+    }
+    if let Some(catch_up) = req.catch_up {
+        schedule.catch_up = catch_up;
+    }
+    if let Some(enabled) = req.enabled {
+        schedule.enabled = enabled;
+    }
+    validate_schedule(&app_state, &schedule).await?;
+
+    // A new time or re-enabling starts counting from now; disabled schedules have no next run
+    if !schedule.enabled {
+        schedule.next_run_at = None;
+    } else if timing_changed || !was_enabled || schedule.next_run_at.is_none() {
+        schedule.next_run_at = spec.next_run(chrono::Utc::now());
+    }
+
+    app_state.db.update_schedule(&schedule).await.map_err(|e| {
+        tracing::error!("Database error updating schedule {}: {}", schedule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    schedules::schedules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Schedule updated", "schedule": schedule })))
+}
+
+// DELETE /api/schedules/:id - Delete a schedule and its history (requires login)
+async fn delete_schedule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(schedule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_schedule(&app_state, &schedule_id, &user_id).await?;
+
+    app_state.db.delete_schedule(&schedule_id).await.map_err(|e| {
+        tracing::error!("Database error deleting schedule {}: {}", schedule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    schedules::schedules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Schedule deleted" })))
+}
+
+/// Query of GET /api/schedules/:id/runs
+#[derive(Deserialize, Default)]
+struct ScheduleRunsQuery {
+    limit: Option<usize>,
+}
+
+// GET /api/schedules/:id/runs?limit= - Execution history, newest first (requires login)
+async fn schedule_runs_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(schedule_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ScheduleRunsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_schedule(&app_state, &schedule_id, &user_id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, schedules::RUN_HISTORY_SIZE);
+    let runs = app_state.db.list_schedule_runs(&schedule_id, limit).await.map_err(|e| {
+        tracing::error!("Database error listing runs of schedule {}: {}", schedule_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "count": runs.len(), "runs": runs })))
+}
+
+// POST /api/schedules/:id/run - Run a schedule now; the regular timing is unchanged (requires login)
+async fn run_schedule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(schedule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let schedule = load_owned_schedule(&app_state, &schedule_id, &user_id).await?;
+
+    let run = schedules::execute(&app_state.db, &schedule_executor(&app_state), &schedule, schedules::RunTrigger::Manual, None).await;
+    Ok(Json(json!({ "success": run.status == "success", "run": run })))
+}
+
+/// Load a script the user owns (404 unknown, 403 foreign)
+async fn load_owned_script(app_state: &AppState, script_id: &str, user_id: &str) -> Result<database::Script, ApiError> {
+    let script = app_state.db.get_script(script_id).await.map_err(|e| {
+        tracing::error!("Database error loading script {}: {}", script_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match script {
+        Some(script) if script.owner_id == user_id => Ok(script),
+        Some(_) => Err(ApiError::forbidden("Script belongs to another user")),
+        None => Err(ApiError::not_found("Script not found")),
+    }
+}
+
+/// Check name, device, event types and source of a script before it is stored
+async fn validate_script(app_state: &AppState, script: &database::Script) -> Result<(), ApiError> {
+    if script.name.trim().is_empty() || script.name.len() > 100 {
+        return Err(ApiError::bad_request("Script name must be between 1 and 100 characters"));
+    }
+    if script.device_id.trim().is_empty() {
+        return Err(ApiError::bad_request("device_id must not be empty"));
+    }
+    if let Some(unknown) = script.events.iter().find(|event| !scripts::EVENT_TYPES.contains(&event.as_str())) {
+        return Err(ApiError::bad_request(format!("Unknown event type '{}'", unknown))
+            .with_details(json!({ "eventTypes": scripts::EVENT_TYPES })));
+    }
+    scripts::compile(&script.source).map_err(|e| ApiError::bad_request(format!("Script does not compile: {}", e)))?;
+    websocket::check_device_write_permission(&app_state.db, &script.device_id, &script.owner_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": script.device_id})))?;
+    Ok(())
+}
+
+// GET /api/scripts - Automation scripts of the user (requires login)
+async fn list_scripts_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let scripts = app_state.db.list_scripts(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing scripts: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "scripts": scripts })))
+}
+
+// POST /api/scripts - Create a script on the events of a device (requires login)
+// Body: {"name": "..", "device_id": "..", "events": ["DeviceVariableUpdate"], "source": "<rhai>"}
+async fn create_script_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateScriptRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let now = chrono::Utc::now();
+
+    let script = database::Script {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id.clone(),
+        name: req.name.trim().to_string(),
+        device_id: req.device_id.trim().to_string(),
+        events: req.events,
+        source: req.source,
+        enabled: req.enabled.unwrap_or(true),
+        created_at: now,
+        updated_at: now,
+    };
+    validate_script(&app_state, &script).await?;
+
+    app_state.db.create_script(&script).await.map_err(|e| {
+        tracing::error!("Database error creating script: {}", e);
+        ApiError::internal("Database error")
+    })?;
+    scripts::scripts_changed();
+
+    tracing::info!("Script '{}' created for device {} by user {}", script.name, script.device_id, user_id);
+    Ok(Json(json!({ "success": true, "message": "Script created", "script": script })))
+}
+
+// GET /api/scripts/:id - Script details including source (requires login)
+async fn get_script_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(script_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let script = load_owned_script(&app_state, &script_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "script": script })))
+}
+
+// PUT /api/scripts/:id - Change name, device, events, source or enabled flag (requires login)
+async fn update_script_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(script_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateScriptRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut script = load_owned_script(&app_state, &script_id, &user_id).await?;
+
+    if let Some(name) = req.name {
+        script.name = name.trim().to_string();
+    }
+    if let Some(device_id) = req.device_id {
+        script.device_id = device_id.trim().to_string();
+    }
+    if let Some(events) = req.events {
+        script.events = events;
+    }
+    if let Some(source) = req.source {
+        script.source = source;
+    }
+    if let Some(enabled) = req.enabled {
+        script.enabled = enabled;
+    }
+    script.updated_at = chrono::Utc::now();
+    validate_script(&app_state, &script).await?;
+
+    app_state.db.update_script(&script).await.map_err(|e| {
+        tracing::error!("Database error updating script {}: {}", script_id, e);
+        ApiError::internal("Database error")
+    })?;
+    scripts::scripts_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Script updated", "script": script })))
+}
+
+// DELETE /api/scripts/:id - Delete a script and its log (requires login)
+async fn delete_script_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(script_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_script(&app_state, &script_id, &user_id).await?;
+
+    app_state.db.delete_script(&script_id).await.map_err(|e| {
+        tracing::error!("Database error deleting script {}: {}", script_id, e);
+        ApiError::internal("Database error")
+    })?;
+    scripts::scripts_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Script deleted" })))
+}
+
+/// Query of GET /api/scripts/:id/logs
+#[derive(Deserialize, Default)]
+struct ScriptLogsQuery {
+    limit: Option<usize>,
+}
+
+// GET /api/scripts/:id/logs?limit= - Script log, newest first (requires login)
+async fn script_logs_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(script_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ScriptLogsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_script(&app_state, &script_id, &user_id).await?;
+
+    let limit = query.limit.unwrap_or(50).clamp(1, scripts::LOG_HISTORY_SIZE);
+    let logs = app_state.db.list_script_logs(&script_id, limit).await.map_err(|e| {
+        tracing::error!("Database error listing logs of script {}: {}", script_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "count": logs.len(), "logs": logs })))
+}
+
+// POST /api/scripts/:id/test - Run the stored script once with the given event (requires login)
+// Body: the event object, e.g. {"event": "DeviceVariableUpdate", "variableName": "door", "variableValue": "1"}
+async fn test_script_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(script_id): Path<String>,
+    ApiJson(mut event): ApiJson<Value>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let script = load_owned_script(&app_state, &script_id, &user_id).await?;
+
+    if !event.is_object() {
+        return Err(ApiError::bad_request("Body must be an event object"));
+    }
+    if event.get("deviceId").is_none() {
+        event["deviceId"] = json!(script.device_id);
+    }
+    let ast = scripts::compile(&script.source).map_err(|e| ApiError::bad_request(format!("Script does not compile: {}", e)))?;
+    let outcome = tokio::task::spawn_blocking(move || scripts::run(&ast, &event)).await
+        .map_err(|e| ApiError::internal(format!("Script crashed: {}", e)))?;
+
+    let mut body = scripts::outcome_json(&outcome);
+    body["success"] = json!(outcome.error.is_none());
+    Ok(Json(body))
+}
+
+/// Load a routing rule the user owns (404 unknown, 403 foreign)
+async fn load_owned_routing_rule(app_state: &AppState, rule_id: &str, user_id: &str) -> Result<database::RoutingRule, ApiError> {
+    let rule = app_state.db.get_routing_rule(rule_id).await.map_err(|e| {
+        tracing::error!("Database error loading routing rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match rule {
+        Some(rule) if rule.owner_id == user_id => Ok(rule),
+        Some(_) => Err(ApiError::forbidden("Routing rule belongs to another user")),
+        None => Err(ApiError::not_found("Routing rule not found")),
+    }
+}
+
+/// Check a rule before it is stored; the owner must see the source and control the target
+async fn validate_routing_rule(app_state: &AppState, rule: &database::RoutingRule) -> Result<(), ApiError> {
+    if rule.name.trim().is_empty() || rule.name.len() > 100 {
+        return Err(ApiError::bad_request("Rule name must be between 1 and 100 characters"));
+    }
+    if rule.source_device_id.trim().is_empty() || rule.target_device_id.trim().is_empty() {
+        return Err(ApiError::bad_request("source_device_id and target_device_id must not be empty"));
+    }
+    if rule.command.as_object().is_none_or(|command| command.is_empty()) {
+        return Err(ApiError::bad_request("command must be a non-empty JSON object"));
+    }
+    if !(1..=routing::MAX_RATE_PER_MINUTE).contains(&rule.max_per_minute) {
+        return Err(ApiError::bad_request(format!("max_per_minute must be between 1 and {}", routing::MAX_RATE_PER_MINUTE)));
+    }
+    websocket::check_device_org_membership(&app_state.db, &rule.source_device_id, &rule.owner_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": rule.source_device_id})))?;
+    websocket::check_device_write_permission(&app_state.db, &rule.target_device_id, &rule.owner_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": rule.target_device_id})))?;
+    Ok(())
+}
+
+fn parse_route_trigger(trigger: &str) -> Result<String, ApiError> {
+    routing::RouteTrigger::parse(trigger)
+        .map(|trigger| trigger.to_string())
+        .map_err(ApiError::bad_request)
+}
+
+/// Rule with the counters of the running engine
+fn routing_rule_json(rule: &database::RoutingRule) -> Value {
+    let mut value = json!(rule);
+    value["stats"] = json!(routing::stats(&rule.id));
+    value
+}
+
+// GET /api/routing-rules - Routing rules of the user (requires login)
+async fn list_routing_rules_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let rules = app_state.db.list_routing_rules(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing routing rules: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    let rules: Vec<Value> = rules.iter().map(routing_rule_json).collect();
+    Ok(Json(json!({ "success": true, "rules": rules })))
+}
+
+// POST /api/routing-rules - Create a rule: event of a source device -> command to a target device (requires login)
+async fn create_routing_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateRoutingRuleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let trigger = parse_route_trigger(&req.trigger)?;
+    let target_device_id = req.target_device_id.trim().to_string();
+
+    let rule = database::RoutingRule {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id.clone(),
+        name: req.name.map(|name| name.trim().to_string()).unwrap_or_else(|| format!("{} -> {}", trigger, target_device_id)),
+        source_device_id: req.source_device_id.trim().to_string(),
+        trigger,
+        target_device_id,
+        command: req.command,
+        max_per_minute: req.max_per_minute.unwrap_or(routing::DEFAULT_RATE_PER_MINUTE),
+        enabled: req.enabled.unwrap_or(true),
+        created_at: chrono::Utc::now(),
+    };
+    validate_routing_rule(&app_state, &rule).await?;
+
+    app_state.db.create_routing_rule(&rule).await.map_err(|e| {
+        tracing::error!("Database error creating routing rule: {}", e);
+        ApiError::internal("Database error")
+    })?;
+    routing::rules_changed();
+
+    tracing::info!("Routing rule '{}' ({} on {} -> {}) created by user {}", rule.name, rule.trigger, rule.source_device_id, rule.target_device_id, user_id);
+    Ok(Json(json!({ "success": true, "message": "Routing rule created", "rule": routing_rule_json(&rule) })))
+}
+
+// GET /api/routing-rules/:id - Rule details and counters (requires login)
+async fn get_routing_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let rule = load_owned_routing_rule(&app_state, &rule_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "rule": routing_rule_json(&rule) })))
+}
+
+// PUT /api/routing-rules/:id - Change devices, trigger, command, rate limit or enabled flag (requires login)
+async fn update_routing_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateRoutingRuleRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut rule = load_owned_routing_rule(&app_state, &rule_id, &user_id).await?;
+
+    if let Some(name) = req.name {
+        rule.name = name.trim().to_string();
+    }
+    if let Some(source_device_id) = req.source_device_id {
+        rule.source_device_id = source_device_id.trim().to_string();
+    }
+    if let Some(trigger) = req.trigger {
+        rule.trigger = parse_route_trigger(&trigger)?;
+    }
+    if let Some(target_device_id) = req.target_device_id {
+        rule.target_device_id = target_device_id.trim().to_string();
+    }
+    if let Some(command) = req.command {
+        rule.command = command;
+    }
+    if let Some(max_per_minute) = req.max_per_minute {
+        rule.max_per_minute = max_per_minute;
+    }
+    if let Some(enabled) = req.enabled {
+        rule.enabled = enabled;
+    }
+    validate_routing_rule(&app_state, &rule).await?;
+
+    app_state.db.update_routing_rule(&rule).await.map_err(|e| {
+        tracing::error!("Database error updating routing rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    routing::rules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Routing rule updated", "rule": routing_rule_json(&rule) })))
+}
+
+// DELETE /api/routing-rules/:id - Delete a rule (requires login)
+async fn delete_routing_rule_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(rule_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_routing_rule(&app_state, &rule_id, &user_id).await?;
+
+    app_state.db.delete_routing_rule(&rule_id).await.map_err(|e| {
+        tracing::error!("Database error deleting routing rule {}: {}", rule_id, e);
+        ApiError::internal("Database error")
+    })?;
+    routing::rules_changed();
+
+    Ok(Json(json!({ "success": true, "message": "Routing rule deleted" })))
+}
+
+fn org_db_error(e: Box<dyn std::error::Error>) -> ApiError {
+    tracing::error!("Database error in organization request: {}", e);
+    ApiError::internal("Database error")
+}
+
+/// Organization selected with the X-Org header and the user's role in it (None without header)
+async fn org_context(app_state: &AppState, headers: &axum::http::HeaderMap, user_id: &str) -> Result<Option<(organizations::Organization, organizations::OrgRole)>, ApiError> {
+    let Some(value) = headers.get(organizations::ORG_HEADER) else {
+        return Ok(None);
+    };
+    let id_or_slug = value.to_str().map_err(|_| ApiError::bad_request("Invalid X-Org header"))?.trim();
+    if id_or_slug.is_empty() {
+        return Ok(None);
+    }
+    if user_id == "guest" {
+        return Err(ApiError::unauthorized("Authentication required for organization access"));
+    }
+    load_org_membership(app_state, id_or_slug, user_id).await.map(Some)
+}
+
+/// Load an organization the user is a member of (404 unknown, 403 not a member)
+async fn load_org_membership(app_state: &AppState, id_or_slug: &str, user_id: &str) -> Result<(organizations::Organization, organizations::OrgRole), ApiError> {
+    let org = app_state.db.get_organization(id_or_slug).await.map_err(org_db_error)?
+        .ok_or_else(|| ApiError::not_found("Organization not found"))?;
+    match app_state.db.get_org_role(&org.id, user_id).await.map_err(org_db_error)? {
+        Some(role) => Ok((org, role)),
+        None => Err(ApiError::forbidden("Not a member of this organization")),
+    }
+}
+
+/// Remaining owners after removing or demoting `user_id` must not drop to zero
+async fn ensure_other_owner(app_state: &AppState, org_id: &str, user_id: &str) -> Result<(), ApiError> {
+    let members = app_state.db.list_org_members(org_id).await.map_err(org_db_error)?;
+    if members.iter().any(|member| member.role == organizations::OrgRole::Owner && member.user_id != user_id) {
+        Ok(())
+    } else {
+        Err(ApiError::conflict("An organization needs at least one owner"))
+    }
+}
+
+// GET /api/orgs - Organizations of the user with their role (requires login)
+async fn list_organizations_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let organizations: Vec<Value> = app_state.db.list_user_organizations(&claims.user_id).await.map_err(org_db_error)?
+        .into_iter()
+        .map(|(org, role)| json!({ "organization": org, "role": role }))
+        .collect();
+    Ok(Json(json!({ "success": true, "organizations": organizations })))
+}
+
+// POST /api/orgs - Create an organization, the creator becomes its owner (requires login)
+// Body: {"name": "Robotics Lab", "slug": "robotics"}
+async fn create_organization_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateOrganizationRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let name = req.name.trim();
+    if name.is_empty() || name.len() > 100 {
+        return Err(ApiError::bad_request("Organization name must be between 1 and 100 characters"));
+    }
+    let slug = organizations::slugify(req.slug.as_deref().unwrap_or(name))
+        .ok_or_else(|| ApiError::bad_request("Organization slug must contain letters or digits (max. 50 characters)"))?;
+    if app_state.db.get_organization(&slug).await.map_err(org_db_error)?.is_some() {
+        return Err(ApiError::conflict(format!("Organization slug '{}' is already taken", slug)));
+    }
+
+    let org = app_state.db.create_organization(name, &slug, &claims.user_id).await.map_err(org_db_error)?;
+    tracing::info!("Organization {} ({}) created by user {}", org.slug, org.id, claims.user_id);
+    Ok(Json(json!({ "success": true, "organization": org, "role": organizations::OrgRole::Owner })))
+}
+
+// GET /api/orgs/:id - Organization with members and device count (members only)
+async fn get_organization_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let (org, role) = load_org_membership(&app_state, &org_id, &claims.user_id).await?;
+    let members = app_state.db.list_org_members(&org.id).await.map_err(org_db_error)?;
+    let devices = app_state.db.count_org_devices(&org.id).await.map_err(org_db_error)?;
+    Ok(Json(json!({ "success": true, "organization": org, "role": role, "members": members, "devices": devices })))
+}
+
+// DELETE /api/orgs/:id - Delete an organization with its groups (owners only, devices must be moved out first)
+async fn delete_organization_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(org_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let (org, role) = load_org_membership(&app_state, &org_id, &claims.user_id).await?;
+    if role != organizations::OrgRole::Owner {
+        return Err(ApiError::forbidden("Only owners can delete an organization"));
+    }
+    let devices = app_state.db.count_org_devices(&org.id).await.map_err(org_db_error)?;
+    if devices > 0 {
+        return Err(ApiError::conflict(format!("Organization still has {} device(s)", devices))
+            .with_details(json!({ "devices": devices })));
+    }
+
+    app_state.db.delete_organization(&org.id).await.map_err(org_db_error)?;
+    tracing::info!("Organization {} deleted by user {}", org.slug, claims.user_id);
+    Ok(Json(json!({ "success": true, "message": "Organization deleted" })))
+}
+
+// POST /api/orgs/:id/members - Add a registered user by e-mail (owners/admins)
+// Body: {"email": "student@example.com", "role": "member"}
+async fn add_org_member_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(org_id): Path<String>,
+    ApiJson(req): ApiJson<AddOrgMemberRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let (org, role) = load_org_membership(&app_state, &org_id, &claims.user_id).await?;
+    if !role.can_manage() {
+        return Err(ApiError::forbidden("Only owners and admins can add members"));
+    }
+    let new_role = match req.role.as_deref() {
+        Some(value) => organizations::OrgRole::parse(value).ok_or_else(|| ApiError::bad_request(format!("Unknown role '{}' (member, admin or owner)", value)))?,
+        None => organizations::OrgRole::Member,
+    };
+    if new_role > role {
+        return Err(ApiError::forbidden("Cannot grant a role above your own"));
+    }
+    let user = app_state.db.get_user_by_email(req.email.trim()).await.map_err(org_db_error)?
+        .ok_or_else(|| ApiError::not_found("No user with this e-mail address"))?;
+    if app_state.db.get_org_role(&org.id, &user.id).await.map_err(org_db_error)?.is_some() {
+        return Err(ApiError::conflict("User is already a member"));
+    }
+
+    app_state.db.set_org_member(&org.id, &user.id, new_role).await.map_err(org_db_error)?;
+    tracing::info!("User {} added to organization {} as {} by {}", user.id, org.slug, new_role.as_str(), claims.user_id);
+    Ok(Json(json!({ "success": true, "userId": user.id, "role": new_role })))
+}
+
+// PUT /api/orgs/:id/members/:user_id - Change a member's role (owners/admins; only owners touch owners)
+// Body: {"role": "admin"}
+async fn update_org_member_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((org_id, member_id)): Path<(String, String)>,
+    ApiJson(req): ApiJson<UpdateOrgMemberRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let (org, role) = load_org_membership(&app_state, &org_id, &claims.user_id).await?;
+    if !role.can_manage() {
+        return Err(ApiError::forbidden("Only owners and admins can change roles"));
+    }
+    let new_role = organizations::OrgRole::parse(&req.role)
+        .ok_or_else(|| ApiError::bad_request(format!("Unknown role '{}' (member, admin or owner)", req.role)))?;
+    let current = app_state.db.get_org_role(&org.id, &member_id).await.map_err(org_db_error)?
+        .ok_or_else(|| ApiError::not_found("Member not found"))?;
+    if (new_role == organizations::OrgRole::Owner || current == organizations::OrgRole::Owner) && role != organizations::OrgRole::Owner {
+        return Err(ApiError::forbidden("Only owners can grant or revoke the owner role"));
+    }
+    if current == organizations::OrgRole::Owner && new_role != organizations::OrgRole::Owner {
+        ensure_other_owner(&app_state, &org.id, &member_id).await?;
+    }
+
+    app_state.db.set_org_member(&org.id, &member_id, new_role).await.map_err(org_db_error)?;
+    tracing::info!("Role of user {} in organization {} set to {} by {}", member_id, org.slug, new_role.as_str(), claims.user_id);
+    Ok(Json(json!({ "success": true, "userId": member_id, "role": new_role })))
+}
+
+// DELETE /api/orgs/:id/members/:user_id - Remove a member (owners/admins) or leave the organization
+async fn remove_org_member_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((org_id, member_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let (org, role) = load_org_membership(&app_state, &org_id, &claims.user_id).await?;
+    let leaving = member_id == claims.user_id;
+    if !leaving && !role.can_manage() {
+        return Err(ApiError::forbidden("Only owners and admins can remove members"));
+    }
+    let current = app_state.db.get_org_role(&org.id, &member_id).await.map_err(org_db_error)?
+        .ok_or_else(|| ApiError::not_found("Member not found"))?;
+    if current == organizations::OrgRole::Owner {
+        if !leaving && role != organizations::OrgRole::Owner {
+            return Err(ApiError::forbidden("Only owners can remove an owner"));
+        }
+        ensure_other_owner(&app_state, &org.id, &member_id).await?;
+    }
+
+    app_state.db.remove_org_member(&org.id, &member_id).await.map_err(org_db_error)?;
+    tracing::info!("User {} removed from organization {} by {}", member_id, org.slug, claims.user_id);
+    Ok(Json(json!({ "success": true, "message": "Member removed" })))
+}
+
+// PUT /api/devices/:id/org - Move a device into an organization or out of it (owners/admins of the organizations involved)
+// Body: {"org_id": "<id or slug>"} or {"org_id": null}
+async fn move_device_org_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(req): ApiJson<MoveDeviceOrgRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    websocket::check_device_write_permission(&app_state.db, &device_id, &claims.user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+    let device = app_state.db.get_device_by_id(&device_id).await.map_err(org_db_error)?
+        .ok_or_else(|| ApiError::not_found("Device not found"))?;
+
+    // Leaving the current organization needs a manager there, joining one needs a manager in the target
+    if let Some(current) = &device.org_id {
+        let (_, role) = load_org_membership(&app_state, current, &claims.user_id).await?;
+        if !role.can_manage() {
+            return Err(ApiError::forbidden("Only owners and admins can move devices out of the organization"));
+        }
+    }
+    let target = match &req.org_id {
+        Some(id_or_slug) => {
+            let (org, role) = load_org_membership(&app_state, id_or_slug, &claims.user_id).await?;
+            if !role.can_manage() {
+                return Err(ApiError::forbidden("Only owners and admins can add devices to the organization"));
+            }
+            Some(org)
+        }
+        None => None,
+    };
+
+    // Old and new audience both see the change
+    let mut audience = device_list_audience(&app_state, &device_id).await;
+    app_state.db.set_device_org(&device_id, target.as_ref().map(|org| org.id.as_str())).await.map_err(org_db_error)?;
+    audience.extend(device_list_audience(&app_state, &device_id).await);
+    broadcast_device_list_changed(&app_state, &device_id, events::DeviceListChange::Updated, &audience).await;
+    tracing::info!("Device {} moved to organization {:?} by user {}", device_id, target.as_ref().map(|org| &org.slug), claims.user_id);
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "organization": target })))
+}
+
+fn permission_db_error(e: Box<dyn std::error::Error>) -> ApiError {
+    tracing::error!("Database error resolving permissions: {}", e);
+    ApiError::internal("Database error")
+}
+
+// PUT /api/devices/:id/default-permission - Permission plain organization members get without a direct one (M required)
+// Body: {"permission": "R"} or {"permission": null} for the role default (W)
+async fn update_default_permission_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(req): ApiJson<DefaultPermissionRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    websocket::check_device_org_membership(&app_state.db, &device_id, &claims.user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+    let device = app_state.db.get_device_by_id(&device_id).await.map_err(permission_db_error)?
+        .ok_or_else(|| ApiError::not_found("Device not found"))?;
+    if device.org_id.is_none() {
+        return Err(ApiError::bad_request("Default permissions only apply to devices of an organization"));
+    }
+    let resolver = permissions::PermissionResolver::new(&app_state.db);
+    if !resolver.has_permission(&device_id, &claims.user_id, "M").await.map_err(permission_db_error)? {
+        return Err(ApiError::forbidden("Insufficient permissions"));
+    }
+    if let Some(permission) = &req.permission {
+        if !permissions::DEFAULT_LEVELS.contains(&permission.as_str()) {
+            return Err(ApiError::bad_request(format!("Default permission must be one of {}", permissions::DEFAULT_LEVELS.join(", "))));
+        }
+    }
+
+    app_state.db.set_device_default_permission(&device_id, req.permission.as_deref()).await.map_err(permission_db_error)?;
+    let audience = device_list_audience(&app_state, &device_id).await;
+    broadcast_device_list_changed(&app_state, &device_id, events::DeviceListChange::Updated, &audience).await;
+    tracing::info!("Default permission of device {} set to {:?} by user {}", device_id, req.permission, claims.user_id);
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "defaultPermission": req.permission })))
+}
+
+// GET /api/devices/:id/effective-permission - Effective permission of the caller and its source (optional auth)
+async fn effective_permission_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    // Devices of organizations do not exist for non-members
+    if websocket::check_device_org_membership(&app_state.db, &device_id, &user_id).await.is_err() {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    if app_state.db.get_device_by_id(&device_id).await.map_err(permission_db_error)?.is_none() {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    let effective = permissions::PermissionResolver::new(&app_state.db)
+        .effective_permission(&device_id, &user_id).await
+        .map_err(permission_db_error)?;
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "userId": user_id, "effective": effective })))
+}
+
+/// Query of GET /api/devices/:id/qr.png
+#[derive(Deserialize)]
+struct QrLabelQuery {
+    /// Pixels per QR module (2-32, default 8)
+    scale: Option<u32>,
+}
+
+// GET /api/devices/:id/qr.png - QR code encoding the absolute URL of the device page
+async fn device_qr_label_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<QrLabelQuery>,
+) -> Result<Response, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+
+    let url = app_state.mailer.link(&device_labels::device_path(&device_id));
+    let png = device_labels::qr_png(&url, query.scale.unwrap_or(device_labels::DEFAULT_SCALE))
+        .map_err(ApiError::internal)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "image/png")
+        .header("content-disposition", format!("inline; filename=\"{}-label.png\"", device_id.replace('"', "")))
+        .body(Body::from(png))
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
+/// Query of GET /api/devices/:id/activity
+#[derive(Deserialize, Default)]
+struct ActivityQuery {
+    /// Page size (default 50, at most 200)
+    limit: Option<u32>,
+    /// Only entries older than this entry ID (the `nextBefore` of the previous page)
+    before: Option<i64>,
+}
+
+// GET /api/devices/:id/activity?limit=&before= - Activity feed of a device (R required)
+async fn device_activity_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<ActivityQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+
+    let limit = query.limit.unwrap_or(activity::DEFAULT_PAGE_SIZE).clamp(1, activity::MAX_PAGE_SIZE);
+    let entries = app_state.db.list_audit_entries(&device_id, query.before, limit).await.map_err(|e| {
+        tracing::error!("Database error loading activity of device {}: {}", device_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    let mut names = std::collections::HashMap::new();
+    for user_id in entries.iter().flat_map(activity::mentioned_users) {
+        if names.contains_key(&user_id) {
+            continue;
+        }
+        // Unknown IDs (guest, system, deleted-user) are shown as they are
+        let user = app_state.db.get_user_by_id(&user_id).await.ok().flatten();
+        if let Some(user) = user {
+            names.insert(user_id, user.display_name);
+        }
+    }
+
+    let next_before = entries.last().filter(|_| entries.len() == limit as usize).map(|entry| entry.id);
+    let feed: Vec<activity::ActivityEntry> = entries.iter().map(|entry| activity::ActivityEntry {
+        id: entry.id,
+        action: entry.action,
+        actor_id: entry.actor_id.clone(),
+        message: activity::describe(entry, &names),
+        details: entry.details.clone(),
+        created_at: entry.created_at,
+    }).collect();
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "entries": feed, "nextBefore": next_before })))
+}
+
+/// Query of GET /api/devices/:id/availability
+#[derive(Deserialize, Default)]
+struct AvailabilityQuery {
+    /// `24h`, `7d`, ... (default 7d, at most 90d)
+    window: Option<String>,
+}
+
+// GET /api/devices/:id/availability?window= - Availability statistics of a device (R required)
+async fn device_availability_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<AvailabilityQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+
+    let window = device_availability::parse_window(query.window.as_deref()).map_err(ApiError::bad_request)?;
+    let to = chrono::Utc::now();
+    let from = to - window;
+    let (initial, changes) = app_state.db.availability_history(&device_id, from, to).await.map_err(|e| {
+        tracing::error!("Database error loading availability of device {}: {}", device_id, e);
+        ApiError::internal("Database error")
+    })?;
+    let stats = device_availability::compute(initial, &changes, from, to);
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "window": query.window.as_deref().unwrap_or("7d"), "availability": stats })))
+}
+
+/// Load a device profile the user owns (404 unknown, 403 foreign)
+async fn load_owned_device_profile(app_state: &AppState, profile_id: &str, user_id: &str) -> Result<device_profiles::DeviceProfile, ApiError> {
+    let profile = app_state.db.get_device_profile(profile_id).await.map_err(|e| {
+        tracing::error!("Database error loading device profile {}: {}", profile_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match profile {
+        Some(profile) if profile.owner_id == user_id => Ok(profile),
+        Some(_) => Err(ApiError::forbidden("Device profile belongs to another user")),
+        None => Err(ApiError::not_found("Device profile not found")),
+    }
+}
+
+/// Hand a changed profile to the device manager for every device it is assigned to
+async fn refresh_device_profile(app_state: &AppState, profile: &device_profiles::DeviceProfile) -> Result<Vec<String>, ApiError> {
+    let devices = app_state.db.list_devices_with_profile(&profile.id).await.map_err(|e| {
+        tracing::error!("Database error listing devices of profile {}: {}", profile.id, e);
+        ApiError::internal("Database error")
+    })?;
+    let profile = Arc::new(profile.clone());
+    for device_id in &devices {
+        app_state.device_manager.set_device_profile(device_id, Some(Arc::clone(&profile))).await;
+    }
+    Ok(devices)
+}
+
+// GET /api/device-profiles - Device type profiles of the user (requires login)
+async fn list_device_profiles_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let profiles = app_state.db.list_device_profiles(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing device profiles: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "profiles": profiles })))
+}
+
+// POST /api/device-profiles - Create a device type profile (requires login)
+// Body: {"name": "LED strip", "variables": [{"name": "brightness", "min": 0, "max": 255},
+//        {"name": "mode", "enum": [0, 1, 3]}], "start_options": ["blink", "fade"]}
+async fn create_device_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateDeviceProfileRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let now = chrono::Utc::now();
+
+    let profile = device_profiles::DeviceProfile {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id.clone(),
+        name: req.name.trim().to_string(),
+        description: req.description.filter(|d| !d.trim().is_empty()),
+        variables: req.variables,
+        start_options: req.start_options,
+        created_at: now,
+        updated_at: now,
+    };
+    profile.validate().map_err(ApiError::bad_request)?;
+
+    app_state.db.create_device_profile(&profile).await.map_err(|e| {
+        tracing::error!("Database error creating device profile: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    tracing::info!("Device profile '{}' ({} variables) created by user {}", profile.name, profile.variables.len(), user_id);
+    Ok(Json(json!({ "success": true, "message": "Device profile created", "profile": profile })))
+}
+
+// GET /api/device-profiles/:id - Profile details and the devices it is assigned to (requires login)
+async fn get_device_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(profile_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let profile = load_owned_device_profile(&app_state, &profile_id, &user_id).await?;
+    let devices = app_state.db.list_devices_with_profile(&profile_id).await.map_err(|e| {
+        tracing::error!("Database error listing devices of profile {}: {}", profile_id, e);
+        ApiError::internal("Database error")
+    })?;
+    Ok(Json(json!({ "success": true, "profile": profile, "devices": devices })))
+}
+
+// PUT /api/device-profiles/:id - Change name, description, variables or start options (requires login)
+// Takes effect immediately for all devices the profile is assigned to
+async fn update_device_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(profile_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateDeviceProfileRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut profile = load_owned_device_profile(&app_state, &profile_id, &user_id).await?;
+
+    if let Some(name) = req.name {
+        profile.name = name.trim().to_string();
+    }
+    if let Some(description) = req.description {
+        profile.description = Some(description).filter(|d| !d.trim().is_empty());
+    }
+    if let Some(variables) = req.variables {
+        profile.variables = variables;
+    }
+    if let Some(start_options) = req.start_options {
+        profile.start_options = start_options;
+    }
+    profile.updated_at = chrono::Utc::now();
+    profile.validate().map_err(ApiError::bad_request)?;
+
+    app_state.db.update_device_profile(&profile).await.map_err(|e| {
+        tracing::error!("Database error updating device profile {}: {}", profile_id, e);
+        ApiError::internal("Database error")
+    })?;
+    let devices = refresh_device_profile(&app_state, &profile).await?;
+
+    Ok(Json(json!({ "success": true, "message": "Device profile updated", "profile": profile, "devices": devices })))
+}
+
+// DELETE /api/device-profiles/:id - Delete a profile; its devices accept any command again (requires login)
+async fn delete_device_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(profile_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_device_profile(&app_state, &profile_id, &user_id).await?;
+
+    let devices = app_state.db.delete_device_profile(&profile_id).await.map_err(|e| {
+        tracing::error!("Database error deleting device profile {}: {}", profile_id, e);
+        ApiError::internal("Database error")
+    })?;
+    for device_id in &devices {
+        app_state.device_manager.set_device_profile(device_id, None).await;
+    }
+
+    Ok(Json(json!({ "success": true, "message": "Device profile deleted", "unassigned": devices })))
+}
+
+// GET /api/devices/:id/profile - Profile commands to the device are checked against
+async fn get_device_profile_assignment_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let profile = app_state.device_manager.get_device_profile(&device_id).await;
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "profile": profile })))
+}
+
+// PUT /api/devices/:id/profile - Assign one of the user's profiles, or remove it with null (requires login)
+// Body: {"profile_id": "<id>"} or {"profile_id": null}
+async fn assign_device_profile_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    ApiJson(req): ApiJson<AssignDeviceProfileRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    websocket::check_device_write_permission(&app_state.db, &device_id, &user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+
+    let profile = match &req.profile_id {
+        Some(profile_id) => Some(load_owned_device_profile(&app_state, profile_id, &user_id).await?),
+        None => None,
+    };
+    let updated = app_state.db.set_device_profile(&device_id, req.profile_id.as_deref()).await.map_err(|e| {
+        tracing::error!("Database error assigning profile to device {}: {}", device_id, e);
+        ApiError::internal("Database error")
+    })?;
+    if !updated {
+        return Err(ApiError::not_found("Device not found"));
+    }
+    app_state.device_manager.set_device_profile(&device_id, profile.clone().map(Arc::new)).await;
+
+    tracing::info!("Device {} profile set to {:?} by user {}", device_id, profile.as_ref().map(|p| &p.name), user_id);
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "profile": profile })))
+}
+
+/// Load a command template the user owns (404 unknown, 403 foreign)
+async fn load_owned_command_template(app_state: &AppState, template_id: &str, user_id: &str) -> Result<database::CommandTemplate, ApiError> {
+    let template = app_state.db.get_command_template(template_id).await.map_err(|e| {
+        tracing::error!("Database error loading command template {}: {}", template_id, e);
+        ApiError::internal("Database error")
+    })?;
+    match template {
+        Some(template) if template.owner_id == user_id => Ok(template),
+        Some(_) => Err(ApiError::forbidden("Command template belongs to another user")),
+        None => Err(ApiError::not_found("Command template not found")),
+    }
+}
+
+/// Check name and steps of a template and that its name is free for the owner
+async fn validate_command_template(app_state: &AppState, template: &database::CommandTemplate) -> Result<(), ApiError> {
+    let name_ok = !template.name.is_empty() && template.name.len() <= 100
+        && template.name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+    if !name_ok {
+        return Err(ApiError::bad_request("Template name must be 1-100 characters of letters, digits, '-' and '_'"));
+    }
+    command_templates::validate_steps(&template.steps).map_err(ApiError::bad_request)?;
+
+    let existing = app_state.db.get_command_template_by_name(&template.owner_id, &template.name).await.map_err(|e| {
+        tracing::error!("Database error checking command template name: {}", e);
+        ApiError::internal("Database error")
+    })?;
+    if existing.is_some_and(|existing| existing.id != template.id) {
+        return Err(ApiError::conflict(format!("A template named '{}' already exists", template.name)));
+    }
+    Ok(())
+}
+
+// GET /api/command-templates - Command templates of the user (requires login)
+async fn list_command_templates_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let templates = app_state.db.list_command_templates(&user_id).await.map_err(|e| {
+        tracing::error!("Database error listing command templates: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "templates": templates })))
+}
+
+// POST /api/command-templates - Create a command template (requires login)
+// Body: {"name": "calibrate", "steps": [{"command": {"setVariable": {"name": "gain", "value": "{{gain}}"}}},
+//        {"command": {"startOption": "calib"}, "delay_ms": 500}], "parameters": {"gain": 5}}
+async fn create_command_template_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<CreateCommandTemplateRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let now = chrono::Utc::now();
+
+    let template = database::CommandTemplate {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id.clone(),
+        name: req.name.trim().to_string(),
+        description: req.description.filter(|d| !d.trim().is_empty()),
+        steps: req.steps,
+        parameters: req.parameters,
+        continue_on_error: req.continue_on_error.unwrap_or(false),
+        created_at: now,
+        updated_at: now,
+    };
+    validate_command_template(&app_state, &template).await?;
+
+    app_state.db.create_command_template(&template).await.map_err(|e| {
+        tracing::error!("Database error creating command template: {}", e);
+        ApiError::internal("Database error")
+    })?;
+
+    tracing::info!("Command template '{}' ({} steps) created by user {}", template.name, template.steps.len(), user_id);
+    Ok(Json(json!({
+        "success": true,
+        "message": "Command template created",
+        "template": template,
+        "placeholders": command_templates::placeholders(&template.steps)
+    })))
+}
+
+// GET /api/command-templates/:id - Template details including its placeholders (requires login)
+async fn get_command_template_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(template_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let template = load_owned_command_template(&app_state, &template_id, &user_id).await?;
+    let placeholders = command_templates::placeholders(&template.steps);
+    Ok(Json(json!({ "success": true, "template": template, "placeholders": placeholders })))
+}
+
+// PUT /api/command-templates/:id - Change name, description, steps, defaults or error handling (requires login)
+async fn update_command_template_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(template_id): Path<String>,
+    ApiJson(req): ApiJson<UpdateCommandTemplateRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let mut template = load_owned_command_template(&app_state, &template_id, &user_id).await?;
+
+    if let Some(name) = req.name {
+        template.name = name.trim().to_string();
+    }
+    if let Some(description) = req.description {
+        template.description = Some(description).filter(|d| !d.trim().is_empty());
+    }
+    if let Some(steps) = req.steps {
+        template.steps = steps;
+    }
+    if let Some(parameters) = req.parameters {
+        template.parameters = parameters;
+    }
+    if let Some(continue_on_error) = req.continue_on_error {
+        template.continue_on_error = continue_on_error;
+    }
+    template.updated_at = chrono::Utc::now();
+    validate_command_template(&app_state, &template).await?;
+
+    app_state.db.update_command_template(&template).await.map_err(|e| {
+        tracing::error!("Database error updating command template {}: {}", template_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "message": "Command template updated", "template": template })))
+}
+
+// DELETE /api/command-templates/:id - Delete a command template (requires login)
+async fn delete_command_template_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(template_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_command_template(&app_state, &template_id, &user_id).await?;
+
+    app_state.db.delete_command_template(&template_id).await.map_err(|e| {
+        tracing::error!("Database error deleting command template {}: {}", template_id, e);
+        ApiError::internal("Database error")
+    })?;
+
+    Ok(Json(json!({ "success": true, "message": "Command template deleted" })))
+}
+
+// POST /api/devices/:id/run-template/:name?timeout_ms= - Run a template of the user on a device (requires login)
+// Body (optional): {"params": {"gain": 7}}; each step waits for the device reply before the next one
+async fn run_command_template_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((device_id, name)): Path<(String, String)>,
+    axum::extract::Query(query): axum::extract::Query<DeviceCommandQuery>,
+    OptionalApiJson(run): OptionalApiJson<RunTemplateRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let template = app_state.db.get_command_template_by_name(&user_id, &name).await.map_err(|e| {
+        tracing::error!("Database error loading command template '{}': {}", name, e);
+        ApiError::internal("Database error")
+    })?.ok_or_else(|| ApiError::not_found(format!("Command template '{}' not found", name)))?;
+
+    websocket::check_device_write_permission(&app_state.db, &device_id, &user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+
+    // Fill all steps first: a missing parameter must not leave the device half-configured
+    let mut params = template.parameters.clone();
+    params.extend(run.unwrap_or_default().params);
+    let commands = template.steps.iter()
+        .map(|step| command_templates::render(&step.command, &params))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| ApiError::bad_request(e).with_details(json!({ "placeholders": command_templates::placeholders(&template.steps) })))?;
+
+    let mut results = Vec::with_capacity(commands.len());
+    let mut failed = false;
+    for (index, (step, command)) in template.steps.iter().zip(commands).enumerate() {
+        if failed && !template.continue_on_error {
+            results.push(command_templates::StepResult { step: index + 1, status: "skipped", command: Some(command), response: None, error: None });
+            continue;
+        }
+        if step.delay_ms > 0 {
+            tokio::time::sleep(std::time::Duration::from_millis(step.delay_ms)).await;
+        }
+        let result = match execute_device_command(&app_state, &device_id, command.clone(), &user_id, query.timeout_ms).await {
+            Ok((_, response)) => command_templates::StepResult { step: index + 1, status: "ok", command: Some(command), response: Some(response), error: None },
+            Err(e) => {
+                failed = true;
+                command_templates::StepResult { step: index + 1, status: "failed", command: Some(command), response: None, error: Some(e.message) }
+            }
+        };
+        results.push(result);
+    }
+
+    tracing::info!("Command template '{}' run on device {} by user {} ({} steps, failed: {})", template.name, device_id, user_id, results.len(), failed);
+    Ok(Json(json!({
+        "success": !failed,
+        "deviceId": device_id,
+        "template": template.name,
+        "results": results
+    })))
+}
+
+// GET /api/devices/:id/queue - Outbound command queue depth and pending commands
+async fn device_command_queue_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let snapshot = app_state.device_manager.get_command_queue(&device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "queue": snapshot
+    })))
+}
+
+// GET /api/devices/:id/events - Stored events after since/cursor, oldest first
+// Clients resume by passing nextCursor of the previous page
+async fn device_events_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<events::ReplayRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    match app_state.device_store.get_event_page(&device_id, &query, true).await {
+        Ok(page) => Ok(Json(json!({
+            "success": true,
+            "deviceId": device_id,
+            "count": page.events.len(),
+            "events": page.events,
+            "nextCursor": page.next_cursor,
+            "hasMore": page.has_more
+        }))),
+        Err(e) => Err(ApiError::bad_request(e)),
+    }
+}
+
+/// Query of GET /api/devices/:id/events/export
+#[derive(Deserialize, Default)]
+struct EventExportQuery {
+    /// csv (default), json or ndjson
+    format: Option<String>,
+    /// Start of the time range (ms since epoch or RFC 3339, inclusive)
+    from: Option<String>,
+    /// End of the time range (ms since epoch or RFC 3339, inclusive)
+    to: Option<String>,
+}
+
+// GET /api/devices/:id/events/export?format=csv&from=&to= - Stream the stored event history as a download
+// Written in chunks (chunked transfer), so large histories don't have to be rendered up front
+async fn export_device_events_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<EventExportQuery>,
+) -> Result<Response, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let format = event_export::ExportFormat::parse(query.format.as_deref())
+        .ok_or_else(|| ApiError::bad_request("format must be csv, json or ndjson"))?;
+    let parse_bound = |name: &str, value: &Option<String>| match value.as_deref() {
+        None | Some("") => Ok(None),
+        Some(value) => event_export::parse_time_bound(value).map(Some).ok_or_else(|| {
+            ApiError::bad_request(format!("{} must be milliseconds since epoch or an RFC 3339 time", name))
+        }),
+    };
+    let from = parse_bound("from", &query.from)?;
+    let to = parse_bound("to", &query.to)?;
+    if let (Some(from), Some(to)) = (from, to) {
+        if from > to {
+            return Err(ApiError::bad_request("from must not be after to"));
+        }
+    }
+
+    let events = app_state.device_store.get_event_history(&device_id, from, to).await;
+    tracing::info!("Exporting {} events of device {} as {}", events.len(), device_id, format.file_extension());
+
+    let header = futures::stream::once(async move { Ok::<_, std::convert::Infallible>(format.header().to_string()) });
+    let mut remaining = events.into_iter();
+    let rows = futures::stream::iter(std::iter::from_fn(move || {
+        let chunk: String = remaining.by_ref()
+            .take(event_export::EXPORT_CHUNK_ROWS)
+            .map(|event| format.row(&event))
+            .collect();
+        (!chunk.is_empty()).then_some(Ok(chunk))
+    }));
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", format.content_type())
+        .header("content-disposition", format!("attachment; filename=\"{}\"", event_export::export_file_name(&device_id, format)))
+        .body(Body::from_stream(futures::StreamExt::chain(header, rows)))
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
+/// Tracing exposes every frame of the device, so it needs write permission on it
+async fn require_device_trace_permission(app_state: &AppState, cookie_jar: &CookieJar, device_id: &str) -> Result<String, ApiError> {
+    let user_id = require_login(cookie_jar)?.user_id;
+    websocket::check_device_write_permission(&app_state.db, device_id, &user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({"deviceId": device_id})))?;
+    Ok(user_id)
+}
+
+// POST /api/devices/:id/trace/start - Start capturing raw frames (optional body: maxBytes, maxDurationSecs)
+async fn start_device_trace_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    OptionalApiJson(options): OptionalApiJson<device_trace::TraceOptions>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_trace_permission(&app_state, &cookie_jar, &device_id).await?;
+    let options = options.unwrap_or_default();
+    options.limits().map_err(ApiError::bad_request)?;
+
+    let session = device_trace::start(&device_id, &user_id, options).map_err(ApiError::conflict)?;
+    tracing::info!("Trace session {} started for device {} by user {}", session.session_id, device_id, user_id);
+
+    Ok(Json(json!({ "success": true, "trace": session })))
+}
+
+// POST /api/devices/:id/trace/stop - Stop capturing (the capture stays downloadable)
+async fn stop_device_trace_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_trace_permission(&app_state, &cookie_jar, &device_id).await?;
+    let session = device_trace::stop(&device_id)
+        .ok_or_else(|| ApiError::not_found(format!("No trace session for device {}", device_id)))?;
+
+    Ok(Json(json!({ "success": true, "trace": session })))
+}
+
+// GET /api/devices/:id/trace - State of the device's capture
+async fn device_trace_status_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_trace_permission(&app_state, &cookie_jar, &device_id).await?;
+    let session = device_trace::status(&device_id)
+        .ok_or_else(|| ApiError::not_found(format!("No trace session for device {}", device_id)))?;
+
+    Ok(Json(json!({ "success": true, "trace": session })))
+}
+
+/// Query of GET /api/devices/:id/trace/download
+#[derive(Deserialize, Default)]
+struct TraceDownloadQuery {
+    /// ndjson (default) or pcap
+    format: Option<String>,
+}
+
+// GET /api/devices/:id/trace/download?format=ndjson|pcap - Capture file (also while still recording)
+async fn download_device_trace_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<TraceDownloadQuery>,
+) -> Result<Response, ApiError> {
+    require_device_trace_permission(&app_state, &cookie_jar, &device_id).await?;
+    let format = device_trace::TraceFormat::parse(query.format.as_deref())
+        .ok_or_else(|| ApiError::bad_request("format must be ndjson or pcap"))?;
+    let (session, frames) = device_trace::snapshot(&device_id)
+        .ok_or_else(|| ApiError::not_found(format!("No trace session for device {}", device_id)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", format.content_type())
+        .header("content-disposition", format!("attachment; filename=\"{}\"", format.file_name(&session)))
+        .body(Body::from(format.write(&session, &frames)))
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
+// GET /api/devices/:id/variables - Latest value and update time of every device variable
+async fn device_variables_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let variables = app_state.device_store.get_device_variables(&device_id).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "count": variables.len(),
+        "variables": variables
+    })))
+}
+
+fn snapshot_db_error(e: Box<dyn std::error::Error>) -> ApiError {
+    tracing::error!("Database error in variable snapshot request: {}", e);
+    ApiError::internal("Database error")
+}
+
+/// Load a snapshot of the device the user took (404 unknown or other device, 403 foreign)
+async fn load_owned_variable_snapshot(
+    app_state: &AppState,
+    device_id: &str,
+    snapshot_id: &str,
+    user_id: &str,
+) -> Result<variable_snapshots::DeviceSnapshot, ApiError> {
+    match app_state.db.get_variable_snapshot(snapshot_id).await.map_err(snapshot_db_error)? {
+        Some(snapshot) if snapshot.device_id != device_id => Err(ApiError::not_found("Snapshot not found")),
+        Some(snapshot) if snapshot.owner_id == user_id => Ok(snapshot),
+        Some(_) => Err(ApiError::forbidden("Snapshot belongs to another user")),
+        None => Err(ApiError::not_found("Snapshot not found")),
+    }
+}
+
+// GET /api/devices/:id/snapshots - Variable snapshots the user took of the device, newest first
+async fn list_variable_snapshots_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let snapshots = app_state.db.list_variable_snapshots(&user_id, &device_id).await.map_err(snapshot_db_error)?;
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "count": snapshots.len(), "snapshots": snapshots })))
+}
+
+// POST /api/devices/:id/snapshots - Save the current variable values under a name
+async fn create_variable_snapshot_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    OptionalApiJson(request): OptionalApiJson<variable_snapshots::CreateSnapshotRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user_id = require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let request = request.unwrap_or_default();
+
+    let now = chrono::Utc::now();
+    let name = match request.name {
+        Some(name) if name.trim().is_empty() || name.len() > 100 => {
+            return Err(ApiError::bad_request("Snapshot name must be between 1 and 100 characters"));
+        }
+        Some(name) => name.trim().to_string(),
+        None => now.format("%Y-%m-%d %H:%M:%S").to_string(),
+    };
+    let variables = app_state.device_store.get_device_variables(&device_id).await;
+    if variables.is_empty() {
+        return Err(ApiError::conflict(format!("No variable values known for device {}", device_id)));
+    }
+    let existing = app_state.db.list_variable_snapshots(&user_id, &device_id).await.map_err(snapshot_db_error)?;
+    if existing.len() >= variable_snapshots::MAX_SNAPSHOTS_PER_DEVICE {
+        return Err(ApiError::conflict(format!("At most {} snapshots per device", variable_snapshots::MAX_SNAPSHOTS_PER_DEVICE)));
+    }
+
+    let snapshot = variable_snapshots::DeviceSnapshot {
+        id: uuid::Uuid::new_v4().to_string(),
+        owner_id: user_id,
+        device_id,
+        name,
+        variables,
+        created_at: now,
+    };
+    app_state.db.insert_variable_snapshot(&snapshot).await.map_err(snapshot_db_error)?;
+    Ok((StatusCode::CREATED, Json(json!({ "success": true, "message": "Snapshot created", "snapshot": snapshot }))))
+}
+
+// GET /api/devices/:id/snapshots/:sid - Snapshot and its diff against the current values
+async fn get_variable_snapshot_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((device_id, snapshot_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let snapshot = load_owned_variable_snapshot(&app_state, &device_id, &snapshot_id, &user_id).await?;
+    let current = app_state.device_store.get_device_variables(&device_id).await;
+    let diff = variable_snapshots::diff(&snapshot.variables, &current);
+    Ok(Json(json!({ "success": true, "snapshot": snapshot, "diff": diff })))
+}
+
+// DELETE /api/devices/:id/snapshots/:sid - Delete a snapshot
+async fn delete_variable_snapshot_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((device_id, snapshot_id)): Path<(String, String)>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_variable_snapshot(&app_state, &device_id, &snapshot_id, &user_id).await?;
+    app_state.db.delete_variable_snapshot(&snapshot_id).await.map_err(snapshot_db_error)?;
+    Ok(Json(json!({ "success": true, "message": "Snapshot deleted" })))
+}
+
+// POST /api/devices/:id/snapshots/:sid/apply - Send setVariable for every differing variable (manage permission)
+// Body (optional): {"dryRun": true} returns the diff without sending anything
+async fn apply_variable_snapshot_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((device_id, snapshot_id)): Path<(String, String)>,
+    OptionalApiJson(request): OptionalApiJson<variable_snapshots::ApplySnapshotRequest>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let snapshot = load_owned_variable_snapshot(&app_state, &device_id, &snapshot_id, &user_id).await?;
+    let request = request.unwrap_or_default();
+
+    let current = app_state.device_store.get_device_variables(&device_id).await;
+    let diff = variable_snapshots::diff(&snapshot.variables, &current);
+    if request.dry_run {
+        return Ok(Json(json!({ "success": true, "dryRun": true, "snapshotId": snapshot.id, "diff": diff })));
+    }
+    websocket::check_device_write_permission(&app_state.db, &device_id, &user_id).await
+        .map_err(|e| ApiError::from(e).with_details(json!({ "deviceId": device_id })))?;
+
+    // One variable after the other, so the device sees the same order on every restore
+    let mut results = Vec::with_capacity(diff.changes.len());
+    for change in &diff.changes {
+        let (status, mut result) = execute_device_command(&app_state, &device_id, change.command(), &user_id, None).await
+            .unwrap_or_else(|error| (error.status, error.body()));
+        result["variable"] = json!(change.name);
+        result["httpStatus"] = json!(status.as_u16());
+        results.push(result);
+    }
+    let applied = results.iter().filter(|result| result["success"] == true).count();
+
+    tracing::info!("Snapshot '{}' applied to {} by user {}: {}/{} variables set", snapshot.name, device_id, user_id, applied, results.len());
+    Ok(Json(json!({
+        "success": applied == results.len(),
+        "snapshotId": snapshot.id,
+        "applied": applied,
+        "failed": results.len() - applied,
+        "diff": diff,
+        "results": results
+    })))
+}
+
+/// Metadata of a raw core dump upload (application/octet-stream)
+#[derive(Deserialize)]
+struct CrashReportUploadQuery {
+    reset_reason: Option<String>,
+    firmware_version: Option<String>,
+    uptime: Option<u64>,
+}
+
+// POST /api/devices/:id/crash-report - Store a crash report of a known device (sent by the firmware)
+// Body: JSON {resetReason, firmwareVersion, uptime, panicReason, backtrace, coreDump (base64)} or the
+// raw core dump as application/octet-stream with ?reset_reason=&firmware_version=&uptime=
+// Signed with the device's identity secret (X-Device-Timestamp, X-Device-Signature)
+async fn ingest_crash_report_handler(
+    State(app_state): State<AppState>,
+    Path(device_id): Path<String>,
+    headers: axum::http::HeaderMap,
+    axum::extract::Query(query): axum::extract::Query<CrashReportUploadQuery>,
+    body: axum::body::Bytes,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let known = app_state.db.get_device_by_id(&device_id).await
+        .map_err(|e| ApiError::internal(format!("Failed to load device: {}", e)))?
+        .is_some();
+    if !known {
+        return Err(ApiError::not_found(format!("Device {} not found", device_id)));
+    }
+
+    let secret = app_state.db.get_device_identity_secret(&device_id).await
+        .map_err(|e| ApiError::internal(format!("Failed to load identity secret: {}", e)))?
+        .ok_or_else(|| ApiError::forbidden(format!("Device {} has no identity secret to sign uploads with", device_id)))?;
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or_default();
+    device_identity::verify_upload(
+        &secret,
+        &device_id,
+        header(device_identity::TIMESTAMP_HEADER),
+        header(device_identity::SIGNATURE_HEADER),
+        &body,
+        chrono::Utc::now().timestamp(),
+    ).map_err(|e| {
+        tracing::warn!("Rejected crash report upload for device {}: {}", device_id, e);
+        ApiError::unauthorized(e)
+    })?;
+    if let Err(retry_after) = crash_reports::check_upload_rate(&device_id) {
+        return Err(ApiError::new(StatusCode::TOO_MANY_REQUESTS, "RATE_LIMITED", format!("At most {} crash reports per hour", crash_reports::MAX_UPLOADS_PER_HOUR))
+            .with_details(json!({ "deviceId": device_id, "retryAfterMs": retry_after.as_millis().max(1) as u64 })));
+    }
+
+    let is_raw = headers.get("content-type").and_then(|v| v.to_str().ok()).is_some_and(|v| v.starts_with("application/octet-stream"));
+    let (report, core_dump) = if is_raw {
+        let core_dump = (!body.is_empty()).then(|| body.to_vec());
+        crash_reports::check_core_dump_size(core_dump.as_deref()).map_err(ApiError::bad_request)?;
+        let reset_reason = match query.reset_reason.as_deref() {
+            Some(reason) => crash_reports::ResetReason::parse(&json!(reason))
+                .ok_or_else(|| ApiError::bad_request(format!("Unknown reset reason '{}'", reason)))?,
+            None => crash_reports::ResetReason::Panic,
+        };
+        let mut report = crash_reports::CrashReport::new(&device_id, reset_reason, "upload");
+        report.firmware_version = query.firmware_version;
+        report.uptime_secs = query.uptime;
+        report.core_dump_size = core_dump.as_ref().map(Vec::len);
+        (report, core_dump)
+    } else {
+        let upload: crash_reports::CrashReportUpload = serde_json::from_slice(&body)
+            .map_err(|e| ApiError::bad_request(format!("Invalid crash report: {}", e)))?;
+        crash_reports::report_from_upload(&device_id, upload).map_err(ApiError::bad_request)?
+    };
+
+    app_state.db.insert_crash_report(&report, core_dump.as_deref()).await
+        .map_err(|e| ApiError::internal(format!("Failed to store crash report: {}", e)))?;
+    tracing::warn!("Crash report {} from device {}: {} reset", report.id, device_id, report.reset_reason.as_str());
+
+    Ok((StatusCode::CREATED, Json(json!({ "success": true, "report": report }))))
+}
+
+#[derive(Deserialize)]
+struct CrashReportListQuery {
+    /// Only reports of this reset reason
+    reason: Option<String>,
+    limit: Option<usize>,
+}
+
+// GET /api/devices/:id/crash-reports - Newest crash reports first, with counts per reset reason
+async fn list_crash_reports_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<CrashReportListQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let reason = match query.reason.as_deref() {
+        Some(reason) => Some(crash_reports::ResetReason::parse(&json!(reason))
+            .ok_or_else(|| ApiError::bad_request(format!("Unknown reset reason '{}'", reason)))?),
+        None => None,
+    };
+    let limit = query.limit.unwrap_or(50).clamp(1, 500);
+
+    let db_error = |e: Box<dyn std::error::Error>| ApiError::internal(format!("Failed to load crash reports: {}", e));
+    let reports = app_state.db.list_crash_reports(&device_id, reason, limit).await.map_err(db_error)?;
+    let counts = app_state.db.count_crash_reports(&device_id).await.map_err(db_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "count": reports.len(),
+        "countsByReason": counts,
+        "reports": reports
+    })))
+}
+
+// GET /api/devices/:id/crash-reports/:report_id/download - Core dump of a report
+async fn download_crash_report_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path((device_id, report_id)): Path<(String, String)>,
+) -> Result<Response, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let (report, core_dump) = app_state.db.get_crash_report(&device_id, &report_id).await
+        .map_err(|e| ApiError::internal(format!("Failed to load crash report: {}", e)))?
+        .ok_or_else(|| ApiError::not_found(format!("Crash report {} not found", report_id)))?;
+    let core_dump = core_dump.ok_or_else(|| ApiError::not_found(format!("Crash report {} has no core dump", report_id)))?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "application/octet-stream")
+        .header("content-disposition", format!("attachment; filename=\"coredump-{}-{}.bin\"", device_id, report.created_at.format("%Y%m%d-%H%M%S")))
+        .body(Body::from(core_dump))
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
+#[derive(Deserialize)]
+struct DeviceLogsQuery {
+    /// Minimum level (trace, debug, info, warn, error); all lines without
+    level: Option<String>,
+    limit: Option<usize>,
+    /// Only lines received after this time (ms since epoch)
+    since: Option<i64>,
+}
+
+// GET /api/devices/:id/logs - Newest log lines of a device, oldest first
+async fn device_logs_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<DeviceLogsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let level = match query.level.as_deref() {
+        Some(level) => LogLevel::parse(level)
+            .ok_or_else(|| ApiError::bad_request(format!("Unknown log level '{}' (trace, debug, info, warn, error)", level)))?,
+        None => LogLevel::Trace,
+    };
+    let limit = query.limit.unwrap_or(200).clamp(1, device_logs::MAX_LOGS_PER_DEVICE);
+    let logs = app_state.device_store.logs().query(&device_id, level, query.since, limit);
+    let counts = app_state.device_store.logs().counts(&device_id);
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "level": level,
+        "count": logs.len(),
+        "countsByLevel": counts,
+        "logs": logs
+    })))
+}
+
+// GET /api/devices/:id/capabilities - Last capabilities the device reported, with the last diff
+async fn device_capabilities_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let stored = app_state.db.get_device_capabilities(&device_id).await
+        .map_err(|e| ApiError::internal(format!("Failed to load capabilities: {}", e)))?
+        .ok_or_else(|| ApiError::not_found(format!("No capabilities known for device {}", device_id)))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "deviceId": device_id,
+        "capabilities": stored.capabilities,
+        "lastChanges": stored.last_changes,
+        "updatedAt": stored.updated_at
+    })))
+}
+
+// GET /api/devices/clocks - Clock state of every readable device that reported uptime or a timestamp
+async fn device_clocks_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let query = database::DeviceListQuery {
+        org_id: org_context(&app_state, &headers, &user_id).await?.map(|(org, _)| org.id),
+        ..Default::default()
+    };
+    let readable: std::collections::HashSet<String> = load_readable_devices(&app_state, &user_id, &query).await?
+        .into_iter().map(|(device, _)| device.mac_address).collect();
+    let clocks: Vec<_> = app_state.device_store.clocks().estimates().into_iter()
+        .filter(|clock| readable.contains(&clock.device_id))
+        .collect();
+
+    Ok(Json(json!({
+        "success": true,
+        "count": clocks.len(),
+        "clocks": clocks
+    })))
+}
+
+// GET /api/devices/:id/clock - Clock state of one device
+async fn device_clock_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let clock = app_state.device_store.clocks().estimate(&device_id)
+        .ok_or_else(|| ApiError::not_found(format!("Device {} has not reported uptime or a timestamp", device_id)))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "clock": clock
+    })))
+}
+
+// POST /api/devices/:id/clock/sync - Send {"setTime": <epoch ms>} to a device that announced support (manage permission)
+async fn sync_device_clock_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    axum::extract::Query(query): axum::extract::Query<DeviceCommandQuery>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user_id = require_device_manage_permission(&app_state, &cookie_jar, &device_id).await?;
+    let supported = app_state.device_store.clocks().estimate(&device_id).is_some_and(|clock| clock.supports_set_time);
+    if !supported {
+        return Err(ApiError::conflict(format!("Device {} does not support setTime", device_id)));
+    }
+
+    let now = chrono::Utc::now().timestamp_millis();
+    let (status, mut body) = execute_device_command(&app_state, &device_id, json!({"setTime": now}), &user_id, query.timeout_ms).await?;
+    app_state.device_store.clocks().mark_synced(&device_id, now);
+    body["serverTime"] = json!(now);
+    Ok((status, Json(body)))
+}
+
+// ============================================================================
+// WI-FI PROVISIONING
+// ============================================================================
+
+/// Session with everything the operator needs: QR payload and code, custom data, esp_prov call
+fn provisioning_response(app_state: &AppState, session: &provisioning::ProvisioningSession, with_secrets: bool) -> Result<Value, ApiError> {
+    let server_url = app_state.mailer.link("");
+    let qr_payload = session.qr_payload();
+    let mut body = json!({
+        "session": session,
+        "status": session.status(),
+        "qrPayload": qr_payload,
+        "qrSvgUrl": format!("/api/provisioning/{}/qr.svg", session.id),
+    });
+    // Token and passphrase only while the session is pending
+    if with_secrets && session.status() == "pending" {
+        body["qrSvg"] = json!(provisioning::qr_svg(&qr_payload).map_err(ApiError::internal)?);
+        body["customData"] = session.custom_data(&server_url);
+        body["espProvCommand"] = json!(session.esp_prov_command(&server_url));
+    }
+    Ok(body)
+}
+
+fn load_own_provisioning(session_id: &str, user_id: &str) -> Result<provisioning::ProvisioningSession, ApiError> {
+    provisioning::get(session_id)
+        .filter(|session| session.owner_id == user_id)
+        .ok_or_else(|| ApiError::not_found("Provisioning session not found"))
+}
+
+// POST /api/provisioning - Open a provisioning session (login required, X-Org selects the organization)
+async fn create_provisioning_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    ApiJson(req): ApiJson<provisioning::CreateProvisioningRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let org_id = org_context(&app_state, &headers, &claims.user_id).await?.map(|(org, _)| org.id);
+    let session = provisioning::create(&claims.user_id, org_id, req).map_err(ApiError::bad_request)?;
+    tracing::info!("Provisioning session {} ({}) opened by user {}", session.id, session.service_name, claims.user_id);
+
+    let mut body = provisioning_response(&app_state, &session, true)?;
+    body["success"] = json!(true);
+    Ok((StatusCode::CREATED, Json(body)))
+}
+
+// GET /api/provisioning - Own sessions (pending, registered, expired)
+async fn list_provisioning_handler(
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let sessions: Vec<Value> = provisioning::list(&claims.user_id).iter()
+        .map(|session| json!({ "session": session, "status": session.status() }))
+        .collect();
+
+    Ok(Json(json!({
+        "success": true,
+        "count": sessions.len(),
+        "sessions": sessions
+    })))
+}
+
+// GET /api/provisioning/:id - Session state; shows the device once it registered
+async fn get_provisioning_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(session_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let session = load_own_provisioning(&session_id, &claims.user_id)?;
+    let mut body = provisioning_response(&app_state, &session, true)?;
+    body["success"] = json!(true);
+    Ok(Json(body))
+}
+
+// GET /api/provisioning/:id/qr.svg - QR code of the ESP-IDF provisioning payload
+async fn provisioning_qr_handler(
+    cookie_jar: CookieJar,
+    Path(session_id): Path<String>,
+) -> Result<Response, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let session = load_own_provisioning(&session_id, &claims.user_id)?;
+    let svg = provisioning::qr_svg(&session.qr_payload()).map_err(ApiError::internal)?;
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header("content-type", "image/svg+xml")
+        .header("cache-control", "no-store")
+        .body(Body::from(svg))
+        .map_err(|e| ApiError::internal(e.to_string()))
+}
+
+// DELETE /api/provisioning/:id - Cancel a session (its token stops working)
+async fn delete_provisioning_handler(
+    cookie_jar: CookieJar,
+    Path(session_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    load_own_provisioning(&session_id, &claims.user_id)?;
+    provisioning::remove(&session_id);
+    Ok(Json(json!({ "success": true, "message": "Provisioning session cancelled" })))
+}
+
+// POST /api/provisioning/register - Called by the device after joining the network (token from custom data)
+async fn register_provisioned_device_handler(
+    State(app_state): State<AppState>,
+    client: proxy::ClientInfo,
+    ApiJson(req): ApiJson<provisioning::DeviceRegistration>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let session = provisioning::find_by_token(&req.token).map_err(ApiError::unauthorized)?;
+    let mac_key = device_inventory::normalize_mac(&req.mac)
+        .ok_or_else(|| ApiError::bad_request(format!("Invalid MAC address '{}'", req.mac)))?;
+    let name = session.device_name.clone()
+        .or(req.name.map(|name| name.trim().to_string()).filter(|name| !name.is_empty() && name.len() <= 100))
+        .unwrap_or_else(|| format!("ESP32 {}", &mac_key[9..]));
+    let ip_address = client.ip.to_string();
+    let db_error = |e: Box<dyn std::error::Error>| {
+        tracing::error!("Database error registering provisioned device: {:?}", e);
+        ApiError::internal("Database error")
+    };
+
+    // Create the device or take over the auto-saved (guest-owned) record; a re-flashed device of the owner is updated
+    match app_state.db.get_device_by_id(&mac_key).await.map_err(db_error)? {
+        Some(existing) if existing.owner_id == session.owner_id || existing.owner_id == "guest" => {
+            if existing.owner_id == "guest" {
+                app_state.db.transfer_device_owner(&mac_key, &session.owner_id).await.map_err(db_error)?;
+            }
+            app_state.db.update_device(&mac_key, Some(Some(name.as_str())), None, None, None).await.map_err(db_error)?;
+            let firmware_version = req.firmware_version.as_deref().or(existing.firmware_version.as_deref());
+            app_state.db.update_device_status(&mac_key, &existing.status, Some(&ip_address), firmware_version).await.map_err(db_error)?;
+        }
+        Some(_) => return Err(ApiError::conflict("Device is already owned by another user")),
+        None => {
+            let mut device = database::Device::new(name.clone(), session.owner_id.clone(), mac_key.clone());
+            device.ip_address = Some(ip_address.clone());
+            device.firmware_version = req.firmware_version.clone();
+            app_state.db.create_device(device).await.map_err(db_error)?;
+        }
+    }
+    if let Some(org_id) = &session.org_id {
+        app_state.db.set_device_org(&mac_key, Some(org_id)).await.map_err(db_error)?;
+    }
+    provisioning::mark_registered(&session.id, &mac_key);
+    app_state.device_manager.lifecycle().adopted(&mac_key).await;
+    tracing::info!("Provisioned device {} ({}) registered for user {} from {}", mac_key, name, session.owner_id, ip_address);
+
+    // Connect in the background, the device waits for this answer before it starts its server
+    if let Some(tcp_port) = req.tcp_port {
+        let mut config = device_types::DeviceConfig::new(mac_key.clone(), client.ip, tcp_port, req.udp_port.unwrap_or(tcp_port));
+        config.device_name = name.clone();
+        if let Err(e) = app_state.device_manager.add_device(config).await {
+            tracing::warn!("Failed to register provisioned device {} with the manager: {}", mac_key, e);
+        } else {
+            let (manager, device_id) = (app_state.device_manager.clone(), mac_key.clone());
+            tokio::spawn(async move {
+                if let Err(e) = manager.connect_device(&device_id).await {
+                    tracing::warn!("First connection to provisioned device {} failed: {}", device_id, e);
+                }
+            });
+        }
+    }
+
+    let audience = device_list_audience(&app_state, &mac_key).await;
+    broadcast_device_list_changed(&app_state, &mac_key, events::DeviceListChange::Created, &audience).await;
+
+    Ok((StatusCode::CREATED, Json(json!({
+        "success": true,
+        "deviceId": mac_key,
+        "name": name,
+        "server": app_state.mailer.link("")
+    }))))
+}
+
+// ============================================================================
+// ENROLLMENT TOKENS
+// ============================================================================
+
+fn enrollment_db_error(e: Box<dyn std::error::Error>) -> ApiError {
+    tracing::error!("Database error in enrollment token request: {}", e);
+    ApiError::internal("Database error")
+}
+
+// POST /api/enrollment-tokens - Mint a token for one board (returned once, stored as hash)
+// Body: {"deviceId": "AA:BB:CC:DD:EE:01", "expiresInHours": 168}
+async fn create_enrollment_token_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    headers: axum::http::HeaderMap,
+    ApiJson(req): ApiJson<enrollment::CreateEnrollmentTokenRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let org_id = org_context(&app_state, &headers, &claims.user_id).await?.map(|(org, _)| org.id);
+    let device_id = device_inventory::normalize_mac(&req.device_id)
+        .ok_or_else(|| ApiError::bad_request(format!("Invalid MAC address '{}'", req.device_id)))?;
+    let validity_hours = req.expires_in_hours.unwrap_or(enrollment::DEFAULT_VALIDITY_HOURS);
+    if !(1..=enrollment::MAX_VALIDITY_HOURS).contains(&validity_hours) {
+        return Err(ApiError::bad_request(format!("expiresInHours must be between 1 and {}", enrollment::MAX_VALIDITY_HOURS)));
+    }
+    if let Some(existing) = app_state.db.get_device_by_id(&device_id).await.map_err(enrollment_db_error)? {
+        if existing.owner_id != claims.user_id && existing.owner_id != "guest" {
+            return Err(ApiError::conflict("Device is already owned by another user"));
+        }
+    }
+
+    let (token, secret) = enrollment::EnrollmentToken::mint(&device_id, &claims.user_id, org_id, validity_hours);
+    app_state.db.insert_enrollment_token(&token, &enrollment::hash_token(&secret)).await.map_err(enrollment_db_error)?;
+    tracing::info!("Enrollment token {} for device {} minted by user {}", token.id, device_id, claims.user_id);
+
+    Ok((StatusCode::CREATED, Json(json!({
+        "success": true,
+        "token": secret,
+        "enrollment": token,
+        "txtRecord": format!("{}={}", enrollment::TXT_KEY, secret)
+    }))))
+}
+
+// GET /api/enrollment-tokens - Own tokens (pending, used, expired)
+async fn list_enrollment_tokens_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    let tokens = app_state.db.list_enrollment_tokens(&claims.user_id).await.map_err(enrollment_db_error)?;
+
+    Ok(Json(json!({
+        "success": true,
+        "count": tokens.len(),
+        "tokens": tokens
+    })))
+}
+
+// DELETE /api/enrollment-tokens/:id - Revoke an own token
+async fn delete_enrollment_token_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(token_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let claims = require_login(&cookie_jar)?;
+    if !app_state.db.delete_enrollment_token(&token_id, &claims.user_id).await.map_err(enrollment_db_error)? {
+        return Err(ApiError::not_found("Enrollment token not found"));
+    }
+    tracing::info!("Enrollment token {} revoked by user {}", token_id, claims.user_id);
+    Ok(Json(json!({ "success": true, "message": "Enrollment token revoked" })))
+}
+
+// ============================================================================
+// SESSION RECORDINGS
+// ============================================================================
+
+fn recording_db_error(e: Box<dyn std::error::Error>) -> ApiError {
+    tracing::error!("Database error in recording request: {}", e);
+    ApiError::internal("Database error")
+}
+
+/// Load a recording the user owns (404 unknown, 403 foreign)
+async fn load_owned_recording(app_state: &AppState, recording_id: &str, user_id: &str) -> Result<recordings::Recording, ApiError> {
+    match app_state.db.get_recording(recording_id).await.map_err(recording_db_error)? {
+        Some(recording) if recording.owner_id == user_id => Ok(recording),
+        Some(_) => Err(ApiError::forbidden("Recording belongs to another user")),
+        None => Err(ApiError::not_found("Recording not found")),
+    }
+}
+
+/// Load a replay the user started (404 unknown or foreign)
+fn load_own_replay(replay_id: &str, user_id: &str) -> Result<recordings::ReplayInfo, ApiError> {
+    recordings::get_replay(replay_id)
+        .filter(|replay| replay.owner_id == user_id)
+        .ok_or_else(|| ApiError::not_found("Replay not found"))
+}
+
+// GET /api/devices/:id/recording - Capture in progress (recording: null when idle)
+async fn device_recording_status_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    Ok(Json(json!({ "success": true, "deviceId": device_id, "recording": recordings::active_recording(&device_id) })))
+}
+
+// POST /api/devices/:id/recording/start - Start capturing the device's events (409 while a capture runs)
+async fn start_device_recording_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+    OptionalApiJson(request): OptionalApiJson<recordings::StartRecordingRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user_id = require_device_read_permission(&app_state, &cookie_jar, &device_id).await?;
+    let request = request.unwrap_or_default();
+
+    let name = match request.name {
+        Some(name) if name.trim().is_empty() || name.len() > 100 => {
+            return Err(ApiError::bad_request("Recording name must be between 1 and 100 characters"));
+        }
+        Some(name) => name.trim().to_string(),
+        None => format!("{} {}", device_id, chrono::Utc::now().format("%Y-%m-%d %H:%M:%S")),
+    };
+    let recording = recordings::start_recording(&user_id, &device_id, &name).map_err(ApiError::conflict)?;
+
+    tracing::info!("Recording '{}' of device {} started by user {}", recording.name, device_id, user_id);
+    Ok((StatusCode::CREATED, Json(json!({ "success": true, "message": "Recording started", "recording": recording }))))
+}
+
+// POST /api/devices/:id/recording/stop - Stop the capture and store it
+async fn stop_device_recording_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    match recordings::active_recording(&device_id) {
+        Some(recording) if recording.owner_id != user_id => return Err(ApiError::forbidden("Recording was started by another user")),
+        Some(_) => {}
+        None => return Err(ApiError::not_found(format!("Device {} is not being recorded", device_id))),
+    }
+    let finished = recordings::stop_recording(&device_id)
+        .ok_or_else(|| ApiError::not_found(format!("Device {} is not being recorded", device_id)))?;
+
+    app_state.db.insert_recording(&finished.recording, &finished.events).await.map_err(recording_db_error)?;
+    tracing::info!("Recording '{}' of device {} stored: {} events in {} ms", finished.recording.name, device_id, finished.recording.event_count, finished.recording.duration_ms);
+    Ok(Json(json!({ "success": true, "message": "Recording stored", "recording": finished.recording })))
+}
+
+/// Query of GET /api/recordings
+#[derive(Deserialize, Default)]
+struct RecordingsQuery {
+    device_id: Option<String>,
+}
+
+// GET /api/recordings?device_id= - Recordings of the user, newest first
+async fn list_recordings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(query): axum::extract::Query<RecordingsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let recordings = app_state.db.list_recordings(&user_id, query.device_id.as_deref()).await.map_err(recording_db_error)?;
+    Ok(Json(json!({ "success": true, "count": recordings.len(), "recordings": recordings })))
+}
+
+// GET /api/recordings/:id - Recording details
+async fn get_recording_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(recording_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let recording = load_owned_recording(&app_state, &recording_id, &user_id).await?;
+    Ok(Json(json!({ "success": true, "recording": recording })))
+}
+
+// GET /api/recordings/:id/events - All events with their offsets (export for frontend tests)
+async fn recording_events_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(recording_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let recording = load_owned_recording(&app_state, &recording_id, &user_id).await?;
+    let events = app_state.db.get_recording_events(&recording_id).await.map_err(recording_db_error)?;
+    Ok(Json(json!({ "success": true, "recording": recording, "events": events })))
+}
+
+// DELETE /api/recordings/:id - Delete a recording (running replays continue)
+async fn delete_recording_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(recording_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_owned_recording(&app_state, &recording_id, &user_id).await?;
+    app_state.db.delete_recording(&recording_id).await.map_err(recording_db_error)?;
+    Ok(Json(json!({ "success": true, "message": "Recording deleted" })))
+}
+
+// POST /api/recordings/:id/replay - Replay into a new replay-<id> channel at `speed` (default 1x)
+// Needs manage permission on the recorded device
+async fn start_replay_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(recording_id): Path<String>,
+    OptionalApiJson(request): OptionalApiJson<recordings::StartReplayRequest>,
+) -> Result<(StatusCode, Json<Value>), ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    let recording = load_owned_recording(&app_state, &recording_id, &user_id).await?;
+    require_device_manage_permission(&app_state, &cookie_jar, &recording.device_id).await?;
+    let request = request.unwrap_or_default();
+
+    let speed = request.speed.unwrap_or(1.0);
+    if !(recordings::MIN_SPEED..=recordings::MAX_SPEED).contains(&speed) {
+        return Err(ApiError::bad_request(format!("speed must be between {} and {}", recordings::MIN_SPEED, recordings::MAX_SPEED)));
+    }
+    if recording.event_count == 0 {
+        return Err(ApiError::conflict("Recording has no events"));
+    }
+    let events = app_state.db.get_recording_events(&recording_id).await.map_err(recording_db_error)?;
+    let replay = recordings::start_replay(app_state.device_store.clone(), &recording, events, &user_id, speed, request.repeat).await;
+
+    Ok((StatusCode::CREATED, Json(json!({ "success": true, "message": "Replay started", "replay": replay }))))
+}
+
+// GET /api/replays - Replays started by the user
+async fn list_replays_handler(cookie_jar: CookieJar) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    Ok(Json(json!({ "success": true, "replays": recordings::list_replays(&user_id) })))
+}
+
+// GET /api/replays/:id - Replay progress
+async fn get_replay_handler(
+    cookie_jar: CookieJar,
+    Path(replay_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    Ok(Json(json!({ "success": true, "replay": load_own_replay(&replay_id, &user_id)? })))
+}
+
+// DELETE /api/replays/:id - Stop a replay and clear its channel
+async fn stop_replay_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(replay_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    let user_id = require_login(&cookie_jar)?.user_id;
+    load_own_replay(&replay_id, &user_id)?;
+    if let Some(channel) = recordings::stop_replay(&replay_id) {
+        let _ = app_state.device_store.clear_device_events(&channel).await;
+    }
+    Ok(Json(json!({ "success": true, "message": "Replay stopped" })))
+}
+
+// GET /api/devices/unidentified - List quarantined UDP senders without a device ID (admin only)
+async fn unidentified_devices_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    let sources = app_state.device_manager.get_unidentified_sources().await;
+
+    Ok(Json(json!({
+        "success": true,
+        "count": sources.len(),
+        "sources": sources
+    })))
+}
+
+// GET /api/devices/discovered - List discovered devices (authentication optional)
+async fn discovered_devices_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    // Extract JWT token from cookie (optional)
+    let _token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
+
+    // Get real-time lifecycle states from DeviceManager
+    let lifecycle_states = app_state.device_manager.lifecycle().snapshot().await;
+
+    // Authentication is optional for device discovery
+    // Get discovered devices from DeviceDiscovery service (TCP/mDNS devices)
+    let (discovered_devices, aging) = {
+        let discovery = app_state.device_discovery.lock().await;
+        (discovery.get_discovered_devices().await, discovery.aging_config().await)
+    };
+    let now = chrono::Utc::now();
+
+    let tcp_device_count = discovered_devices.len();
+    tracing::info!("Device Discovery API called - found {} TCP devices", tcp_device_count);
+
+    let mut devices_json: Vec<Value> = Vec::new();
+
+    for (device_id, discovered_device) in discovered_devices {
+        tracing::info!("Processing discovered device: {} with mDNS data: {:?}",
+            device_id, discovered_device.mdns_data.is_some());
+
+        let mut device_json = json!({
+            "deviceId": device_id,
+            "deviceIp": discovered_device.device_config.ip_address.to_string(),
+            "tcpPort": discovered_device.device_config.tcp_port,
+            "udpPort": discovered_device.device_config.udp_port,
+            "status": "discovered",
+            "connectionType": "tcp",
+            "lastSeen": discovered_device.last_seen.to_rfc3339(),
+            "ageSeconds": (now - discovered_device.last_seen).num_seconds().max(0),
+            "freshness": aging.freshness(discovered_device.last_seen, now)
+        });
+
+        let mut mac_address_key: Option<String> = None;
+
+        // Add MAC address and mDNS hostname from mDNS data if available
+        if let Some(ref mdns_data) = discovered_device.mdns_data {
+            tracing::info!("Found mDNS data with {} TXT records", mdns_data.txt_records.len());
+            if let Some(mac_address) = mdns_data.txt_records.get("mac") {
+                tracing::info!("Adding MAC address to JSON: {}", mac_address);
+                device_json["macAddress"] = json!(mac_address);
+                // Store MAC address key for database lookup
+                mac_address_key = Some(device_manager::DeviceManager::normalize_device_id(mac_address.trim()));
+            } else {
+                tracing::warn!("No 'mac' key found in TXT records: {:?}", mdns_data.txt_records.keys().collect::<Vec<_>>());
+            }
+
+            // Add mDNS hostname without .local suffix
+            let mdns_hostname = mdns_data.hostname.replace(".local", "").trim_end_matches('.').to_string();
+            device_json["mdnsHostname"] = json!(mdns_hostname);
+            tracing::info!("Adding mDNS hostname to JSON: {}", mdns_hostname);
+
+            // All announced services with their port and TXT records
+            let mut services: Vec<&mdns_discovery::MdnsServiceRecord> = mdns_data.services.values().collect();
+            services.sort_by(|a, b| a.service_type.cmp(&b.service_type));
+            device_json["mdnsServices"] = json!(services);
+        } else {
+            tracing::warn!("No mDNS data found for device: {}", device_id);
+        }
+
+        // Try to load alias and name from database if we have a MAC address
+        // Also check connection status
+        if let Some(ref mac_key) = mac_address_key {
+            // Check real-time connection status using MAC address key
+            let is_connected = lifecycle_states.get(mac_key).is_some_and(|entry| entry.link_up);
+            device_json["connected"] = json!(is_connected);
+            tracing::info!("Device {} connection status: {}", mac_key, is_connected);
+
+            match app_state.db.get_device_by_id(mac_key).await {
+                Ok(Some(device)) => {
+                    if let Some(alias) = device.alias {
+                        device_json["alias"] = json!(alias);
+                        tracing::info!("Adding alias to discovered device {}: {}", mac_key, alias);
+                    }
+                    device_json["name"] = json!(device.name);
+                    tracing::info!("Adding name to discovered device {}: {}", mac_key, device.name);
+                }
+                Ok(None) => {
+                    tracing::debug!("No device found in database for MAC: {}", mac_key);
+                }
+                Err(e) => {
+                    tracing::warn!("Error loading device from database for MAC {}: {}", mac_key, e);
+                }
+            }
+        } else {
+            // No MAC address, cannot determine connection status
+            device_json["connected"] = json!(false);
+        }
+
+        devices_json.push(device_json);
+    }
+
+    // Get UART devices from database (persistent storage)
+    let uart_devices_from_db = match app_state.db.get_devices_by_connection_type("uart").await {
+        Ok(devices) => devices,
+        Err(e) => {
+            tracing::warn!("Failed to load UART devices from database: {}", e);
+            Vec::new()
+        }
+    };
+
+    let uart_device_count = uart_devices_from_db.len();
+    tracing::info!("Loaded {} UART devices from database", uart_device_count);
+
+    // Add UART devices to response
+    for uart_device in uart_devices_from_db {
+        // Check real-time connection status for UART device
+        let is_connected = lifecycle_states.get(&uart_device.mac_address).is_some_and(|entry| entry.link_up);
+
+        let mut uart_json = json!({
+            "deviceId": uart_device.mac_address.clone(),
+            "deviceIp": "0.0.0.0",  // UART devices have no IP
+            "tcpPort": 0,
+            "udpPort": 0,
+            "status": "discovered",
+            "connectionType": "uart",
+            "mdnsHostname": Some(format!("uart-{}", uart_device.mac_address)),
+            "macAddress": uart_device.mac_address.replace('-', ":"),  // Show MAC with colons
+            "name": uart_device.name.clone(),
+            "connected": is_connected
+        });
+
+        // Add alias if available
+        if let Some(alias) = uart_device.alias {
+            uart_json["alias"] = json!(alias);
+            tracing::info!("Added UART device from DB with alias: {} (alias: {}, connected: {})", uart_device.mac_address, alias, is_connected);
+        } else {
+            tracing::info!("Added UART device from DB to discovered list: {} (connected: {})", uart_device.mac_address, is_connected);
+        }
+
+        devices_json.push(uart_json);
+    }
+
+    tracing::info!("Total discovered devices: {} (TCP: {}, UART: {})",
+                   devices_json.len(),
+                   tcp_device_count,
+                   uart_device_count);
+
+    Ok(Json(json!({
+        "success": true,
+        "devices": devices_json
+    })))
+}
+
+// ============================================================================
+// UART SETTINGS HANDLERS - API handlers for UART configuration
+// ============================================================================
+
+// GET /api/uart/settings - Get current UART settings
+async fn get_uart_settings_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    match app_state.db.get_uart_settings().await {
+        Ok(Some((port, baud_rate, auto_connect))) => {
+            Ok(Json(json!({
+                "success": true,
+                "port": port,
+                "baudRate": baud_rate,
+                "autoConnect": auto_connect
+            })))
+        }
+        Ok(None) => {
+            Ok(Json(json!({
+                "success": true,
+                "port": null,
+                "baudRate": 115200,
+                "autoConnect": false
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get UART settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// POST /api/uart/settings - Update UART settings
+#[derive(Debug, Deserialize)]
+struct UpdateUartSettingsRequest {
+    port: Option<String>,
+    #[serde(rename = "baudRate")]
+    baud_rate: u32,
+    #[serde(rename = "autoConnect")]
+    auto_connect: bool,
+}
+
+// POST /api/debug/settings - Update debug settings
+#[derive(Debug, Deserialize)]
+struct UpdateDebugSettingsRequest {
+    #[serde(rename = "maxDebugMessages")]
+    max_debug_messages: u32,
+}
+
+async fn update_uart_settings_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<UpdateUartSettingsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("Updating UART settings: port={:?}, baud_rate={}, auto_connect={}",
+        req.port, req.baud_rate, req.auto_connect);
+
+    match app_state.db.update_uart_settings(
+        req.port.as_deref(),
+        req.baud_rate,
+        req.auto_connect
+    ).await {
+        Ok(()) => {
+            Ok(Json(json!({
+                "success": true,
+                "message": "UART settings updated successfully"
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to update UART settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// GET /api/uart/ports - List available serial ports
+async fn list_uart_ports_handler() -> Result<Json<Value>, ApiError> {
+    match uart_connection::UartConnection::list_ports() {
+        Ok(ports) => {
+            Ok(Json(json!({
+                "success": true,
+                "ports": ports
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to list UART ports: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// POST /api/uart/connect - Connect to UART port
+#[derive(Debug, Deserialize)]
+struct UartConnectRequest {
+    port: String,
+    #[serde(rename = "baudRate")]
+    baud_rate: u32,
+    #[serde(rename = "autoConnect", default)]
+    auto_connect: bool,
+}
+
+async fn uart_connect_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<UartConnectRequest>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("UART connect request: port={}, baud_rate={}, auto_connect={}",
+        req.port, req.baud_rate, req.auto_connect);
+
+    // Try to connect first
+    let mut uart = app_state.uart_connection.lock().await;
+    match uart.connect(req.port.clone(), req.baud_rate).await {
+        Ok(()) => {
+            drop(uart); // Release lock before database operation
+
+            // Save settings to database after successful connection
+            if let Err(e) = app_state.db.update_uart_settings(
+                Some(&req.port),
+                req.baud_rate,
+                req.auto_connect
+            ).await {
+                tracing::error!("Failed to save UART settings after connect: {}", e);
+                // Don't fail the connection just because settings save failed
+            } else {
+                tracing::info!("UART settings saved to database");
+            }
+
+            Ok(Json(json!({
+                "success": true,
+                "message": format!("Connected to UART port {} and settings saved", req.port)
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to connect to UART port: {}", e);
+            Err(ApiError::bad_gateway(format!("Failed to connect: {}", e)))
+        }
+    }
+}
+
+// POST /api/uart/disconnect - Disconnect from UART port
+async fn uart_disconnect_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("UART disconnect request");
+
+    let mut uart = app_state.uart_connection.lock().await;
+    match uart.disconnect().await {
+        Ok(()) => {
+            Ok(Json(json!({
+                "success": true,
+                "message": "Disconnected from UART port"
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to disconnect from UART port: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// GET /api/uart/status - Get UART connection status
+async fn uart_status_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    let uart = app_state.uart_connection.lock().await;
+    let is_connected = uart.is_connected().await;
+    let settings = uart.get_settings().await;
+
+    Ok(Json(json!({
+        "success": true,
+        "connected": is_connected,
+        "port": settings.as_ref().map(|s| &s.port),
+        "baudRate": settings.map(|s| s.baud_rate).unwrap_or(115200)
+    })))
+}
+
+// ============================================================================
+// DEVICE SIMULATOR HANDLERS - Admin API for simulated ESP32 devices
+// ============================================================================
+
+/// Only admins may manage simulators
+async fn require_admin(app_state: &AppState, cookie_jar: &CookieJar) -> Result<(), ApiError> {
+    let token = match cookie_jar.get("auth_token") {
+        Some(cookie) => cookie.value(),
+        None => return Err(ApiError::unauthorized("Authentication required")),
+    };
+
+    let claims = match validate_jwt(token) {
+        Ok(claims) => claims,
+        Err(_) => return Err(ApiError::unauthorized("Invalid or expired token")),
+    };
+
+    match app_state.db.get_user_by_id(&claims.user_id).await {
+        Ok(Some(user)) if user.is_admin => Ok(()),
+        Ok(_) => Err(ApiError::forbidden("Administrator privileges required")),
+        Err(e) => {
+            tracing::error!("Database error loading user {}: {}", claims.user_id, e);
+            Err(ApiError::internal("Database error"))
+        }
+    }
+}
+
+// GET /api/admin/simulators - List running simulators
+async fn list_simulators_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let simulators = app_state.device_simulators.list().await;
+    Ok(Json(json!({
+        "success": true,
+        "simulators": simulators,
+        "count": simulators.len()
+    })))
+}
+
+// GET /api/admin/outbound - Offline mode and proxy of calls leaving the local network
+async fn outbound_status_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let status = outbound::status(app_state.mailer.is_configured());
+    Ok(Json(json!({
+        "success": true,
+        "outbound": status
+    })))
+}
+
+// POST /api/admin/backup - Consistent copy of the running database (VACUUM INTO)
+async fn backup_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let path = app_state.db.backup().await.map_err(|e| {
+        tracing::error!("Database backup failed: {}", e);
+        ApiError::internal(format!("Backup failed: {}", e))
+    })?;
+    let size = std::fs::metadata(&path).map(|meta| meta.len()).unwrap_or(0);
+    tracing::info!("Database backup written to {} ({} bytes)", path.display(), size);
+
+    Ok(Json(json!({
+        "success": true,
+        "path": path.to_string_lossy(),
+        "sizeBytes": size
+    })))
+}
+
+// POST /api/admin/reload-config - Same as SIGHUP: apply runtime settings, report those needing a restart
+async fn reload_config_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let reloader = config_reload::reloader()
+        .ok_or_else(|| ApiError::internal("Config reload is not available"))?;
+    let report = reloader.reload().await
+        .map_err(|e| ApiError::bad_request(format!("Config not reloaded: {}", e)))?;
+    config_reload::log_report(&report);
+
+    Ok(Json(json!({
+        "success": true,
+        "applied": report.applied,
+        "restartRequired": report.restart_required,
+        "failed": report.failed
+    })))
+}
+
+// GET /api/admin/diagnostics - Host self-test against the running server (same checks as --doctor)
+async fn diagnostics_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let uart_port = {
+        let uart = app_state.uart_connection.lock().await;
+        match uart.listener_alive() {
+            Some(true) => uart.get_settings().await.map(|settings| settings.port),
+            _ => None,
+        }
+    };
+    let server = diagnostics::ServerState {
+        bound_udp_ports: app_state.device_manager.get_udp_bound_ports().await,
+        uart_port,
+    };
+    let report = tokio::task::spawn_blocking(move || diagnostics::run(diagnostics::targets(), Some(&server))).await
+        .map_err(|e| ApiError::internal(format!("Diagnostics failed: {}", e)))?;
+
+    Ok(Json(json!({
+        "success": true,
+        "diagnostics": report
+    })))
+}
+
+// GET /api/admin/secrets - Active key of the encryption of stored secrets
+async fn secrets_status_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    Ok(Json(json!({
+        "success": true,
+        "secrets": secrets::status()
+    })))
+}
+
+// POST /api/admin/secrets/rotate - Add a new active key to the key file and re-seal all stored secrets
+async fn rotate_secrets_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let key_id = secrets::rotate().map_err(ApiError::conflict)?;
+    let resealed = app_state.db.reseal_secrets().await.map_err(|e| {
+        tracing::error!("Failed to re-seal secrets with key {}: {}", key_id, e);
+        ApiError::internal("Internal server error")
+    })?;
+
+    Ok(Json(json!({
+        "success": true,
+        "activeKeyId": key_id,
+        "resealed": resealed
+    })))
+}
+
+// POST /api/admin/simulators - Start a simulated device
+async fn start_simulator_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(spec): ApiJson<device_simulator::SimulatorSpec>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    match app_state.device_simulators.spawn(spec).await {
+        Ok(simulator) => {
+            tracing::info!("Simulator started via API: {}", simulator.device_id);
+            Ok(Json(json!({
+                "success": true,
+                "simulator": simulator
+            })))
+        }
+        Err(e) => {
+            tracing::warn!("Failed to start simulator: {}", e);
+            Err(ApiError::bad_request(format!("Failed to start simulator: {}", e)))
+        }
+    }
+}
+
+// DELETE /api/admin/simulators/:id - Stop a simulated device
+async fn stop_simulator_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    Path(device_id): Path<String>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    match app_state.device_simulators.stop(&device_id).await {
+        Ok(()) => Ok(Json(json!({
+            "success": true,
+            "message": format!("Simulator {} stopped", device_id)
+        }))),
+        Err(device_types::DeviceError::DeviceNotFound(_)) => Err(ApiError::not_found(format!("Simulator {} not found", device_id))),
+        Err(e) => {
+            tracing::error!("Failed to stop simulator {}: {}", device_id, e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+// ============================================================================
+// DEBUG SETTINGS HANDLERS - API handlers for debug configuration
+// ============================================================================
+
+// GET /api/debug/settings - Get current debug settings
+async fn get_debug_settings_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    match app_state.db.get_debug_settings().await {
+        Ok(Some(max_debug_messages)) => {
+            Ok(Json(json!({
+                "success": true,
+                "maxDebugMessages": max_debug_messages
+            })))
+        }
+        Ok(None) => {
+            Ok(Json(json!({
+                "success": true,
+                "maxDebugMessages": 200
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get debug settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_debug_settings_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<UpdateDebugSettingsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    tracing::info!("Updating debug settings: max_debug_messages={}", req.max_debug_messages);
+
+    // Validate: min 10, max 10000
+    if req.max_debug_messages < 10 || req.max_debug_messages > 10000 {
+        return Err(ApiError::bad_request("Max debug messages must be between 10 and 10000"));
+    }
+
+    // Update database
+    match app_state.db.update_debug_settings(req.max_debug_messages).await {
+        Ok(()) => {}
+        Err(e) => {
+            tracing::error!("Failed to update debug settings: {}", e);
+            return Err(ApiError::internal("Internal server error"));
+        }
+    }
+
+    // Update device store limit and debug bus size immediately
+    app_state.device_store.set_max_debug_messages(req.max_debug_messages as usize).await;
+    debug_logger::bus().set_capacity(req.max_debug_messages as usize);
+    tracing::info!("Debug settings updated in database, device store and debug bus");
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "Debug settings updated successfully"
+    })))
+}
+
+#[derive(Debug, Deserialize)]
+struct DebugLogsQuery {
+    /// Cursor from a previous page (nextCursor); omitted = oldest retained entry
+    after: Option<u64>,
+    limit: Option<usize>,
+    /// Comma-separated categories, e.g. TCP_COMMAND,RESET
+    category: Option<String>,
+    device_id: Option<String>,
+}
+
+// GET /api/debug/logs?after=<cursor>&limit=100&category=TCP_COMMAND&device_id=<id> - Debug bus page (admin only)
+// New entries are also streamed to WebSocket clients after {"type": "debugSubscribe"}
+async fn debug_logs_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    axum::extract::Query(query): axum::extract::Query<DebugLogsQuery>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+
+    let filter = debug_logger::DebugFilter::new(query.category.as_deref().unwrap_or_default().split(','), query.device_id);
+    let bus = debug_logger::bus();
+    let page = bus.page(query.after, query.limit.unwrap_or(100), &filter);
+    Ok(Json(json!({
+        "success": true,
+        "entries": page.entries,
+        "nextCursor": page.next_cursor,
+        "hasMore": page.has_more,
+        "truncated": page.truncated,
+        "capacity": bus.capacity()
+    })))
+}
+
+// ============================================================================
+// UDP SETTINGS HANDLERS - API handlers for central UDP listener ports
+// ============================================================================
+
+// POST /api/udp/settings - Update UDP listener ports (admin only)
+#[derive(Debug, Deserialize)]
+struct UpdateUdpSettingsRequest {
+    #[serde(rename = "listenPorts")]
+    listen_ports: Vec<u16>,
+}
+
+// GET /api/udp/settings - Get configured and currently active UDP listener ports (admin only)
+async fn get_udp_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    let active_ports = app_state.device_manager.get_udp_listen_ports().await;
+
+    match app_state.db.get_udp_settings().await {
+        Ok(configured) => {
+            Ok(Json(json!({
+                "success": true,
+                "listenPorts": configured.unwrap_or_else(|| vec![device_manager::DEFAULT_UDP_LISTEN_PORT]),
+                "activePorts": active_ports
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get UDP settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_udp_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdateUdpSettingsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    tracing::info!("Updating UDP settings: listen_ports={:?}", req.listen_ports);
+
+    // Validate: at least one port, max 8 listeners, no port 0
+    if req.listen_ports.is_empty() || req.listen_ports.len() > 8 || req.listen_ports.contains(&0) {
+        return Err(ApiError::bad_request("Between 1 and 8 non-zero UDP ports are required"));
+    }
+
+    match app_state.db.update_udp_settings(&req.listen_ports).await {
+        Ok(()) => {}
+        Err(e) => {
+            tracing::error!("Failed to update UDP settings: {}", e);
+            return Err(ApiError::internal("Internal server error"));
+        }
+    }
+
+    // Listener sockets are bound at startup, so the new ports apply after a restart
+    tracing::info!("UDP settings updated in database (effective after restart)");
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "UDP settings updated successfully. Restart the server to apply new ports."
+    })))
+}
+
+// ============================================================================
+// MDNS ADVERTISEMENT HANDLER
+// GET /api/mdns/advertisement - what the server announces to ESP32 firmware
+// ============================================================================
+
+async fn get_mdns_advertisement_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    let server = app_state.mdns_server.lock().await;
+    let config = server.config();
+
+    Ok(Json(json!({
+        "success": true,
+        "running": server.is_running(),
+        "config": config,
+        "serviceType": mdns_server::MANAGER_SERVICE_TYPE,
+        "services": server.advertised_services(),
+        "txtRecords": config.manager_txt_records(config.api_port)
+    })))
+}
+
+// ============================================================================
+// DISCOVERY SETTINGS HANDLERS
+// GET  /api/discovery/settings - aging thresholds (admin only)
+// POST /api/discovery/settings - update aging (re-resolution interval applies on restart, admin only)
+// ============================================================================
+
+async fn get_discovery_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    match app_state.db.get_discovery_settings().await {
+        Ok(settings) => Ok(Json(json!({
+            "success": true,
+            "settings": settings
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to get discovery settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_discovery_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(config): ApiJson<device_discovery::DiscoveryAgingConfig>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    // Validate: stale before expiry, sane lower bounds
+    if config.stale_after_seconds < 30 || config.expire_after_seconds <= config.stale_after_seconds {
+        return Err(ApiError::bad_request("staleAfterSeconds must be at least 30 and below expireAfterSeconds"));
+    }
+    if config.mdns_reresolve_seconds < 10 {
+        return Err(ApiError::bad_request("mdnsReresolveSeconds must be at least 10"));
+    }
+
+    if let Err(e) = app_state.db.update_discovery_settings(&config).await {
+        tracing::error!("Failed to update discovery settings: {}", e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    app_state.device_discovery.lock().await.set_aging_config(config).await;
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "Discovery settings updated. The mDNS re-resolution interval applies after a restart."
+    })))
+}
+
+// ============================================================================
+// UDP DISCOVERY SCAN HANDLER
+// POST /api/discovery/scan - broadcast probe, collect replies, dedupe against mDNS
+// ============================================================================
+
+/// Only the reply window is chosen per scan; payload and ports are fixed on the server
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DiscoveryScanRequest {
+    #[serde(rename = "timeoutMs")]
+    timeout_ms: Option<u64>,
+}
+
+async fn discovery_scan_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    OptionalApiJson(request): OptionalApiJson<DiscoveryScanRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    let mut probe = device_types::UdpDiscoveryProbe::default();
+    if let Some(timeout_ms) = request.and_then(|r| r.timeout_ms) {
+        if !(200..=10_000).contains(&timeout_ms) {
+            return Err(ApiError::bad_request("timeoutMs must be between 200 and 10000"));
+        }
+        probe.timeout_ms = timeout_ms;
+    }
+
+    let scan = app_state.device_discovery.lock().await.scan();
+    tracing::info!("Starting UDP discovery scan on ports {:?} ({}ms)", probe.ports, probe.timeout_ms);
+
+    match scan.run_udp(probe).await {
+        Ok(devices) => {
+            let devices_json = scanned_devices_json(&devices);
+            Ok(Json(json!({
+                "success": true,
+                "devices": devices_json,
+                "count": devices_json.len()
+            })))
+        }
+        Err(e) => {
+            tracing::error!("UDP discovery scan failed: {}", e);
+            Err(ApiError::internal(format!("UDP discovery scan failed: {}", e)))
+        }
+    }
+}
+
+/// JSON view of devices found by an active scan
+fn scanned_devices_json(devices: &[device_discovery::DiscoveredDevice]) -> Vec<Value> {
+    devices.iter().map(|d| json!({
+        "deviceId": d.device_config.device_id,
+        "deviceName": d.device_config.device_name,
+        "deviceIp": d.device_config.ip_address.to_string(),
+        "tcpPort": d.device_config.tcp_port,
+        "udpPort": d.device_config.udp_port,
+        "discoveredAt": d.discovered_at.to_rfc3339()
+    })).collect()
+}
+
+// ============================================================================
+// TCP RANGE SCAN - opt-in discovery strategy
+// POST /api/discovery/tcp-scan          - scan once (body overrides saved settings)
+// GET  /api/discovery/tcp-scan/settings - saved settings
+// POST /api/discovery/tcp-scan/settings - save settings (periodic scan picks them up)
+// ============================================================================
+
+/// Whether a scan may run with these settings: only when the opt-in is enabled
+fn validate_tcp_scan_config(config: &device_types::TcpScanConfig) -> Result<(), String> {
+    if !config.enabled {
+        return Err("TCP range scan is disabled; enable it in the TCP scan settings first".to_string());
+    }
+    validate_tcp_scan_limits(config)
+}
+
+/// Range, port and timing limits, also checked when saving disabled settings
+fn validate_tcp_scan_limits(config: &device_types::TcpScanConfig) -> Result<(), String> {
+    config.hosts()?;
+    if config.port == 0 {
+        return Err("Port must not be 0".to_string());
+    }
+    if !(1..=256).contains(&config.concurrency) {
+        return Err("concurrency must be between 1 and 256".to_string());
+    }
+    if !(50..=5_000).contains(&config.timeout_ms) {
+        return Err("timeoutMs must be between 50 and 5000".to_string());
+    }
+    if config.interval_seconds < 30 {
+        return Err("intervalSeconds must be at least 30".to_string());
+    }
+    Ok(())
+}
+
+/// Periodic scan; settings are re-read every cycle so enabling/disabling needs no restart
+pub async fn start_tcp_scan_task(
+    db: Arc<DatabaseManager>,
+    device_discovery: Arc<tokio::sync::Mutex<device_discovery::DeviceDiscovery>>,
+) {
+    loop {
+        let config = match db.get_tcp_scan_settings().await {
+            Ok(config) => config,
+            Err(e) => {
+                tracing::warn!("Failed to load TCP scan settings: {}", e);
+                device_types::TcpScanConfig::default()
+            }
+        };
+
+        if validate_tcp_scan_config(&config).is_ok() {
+            let scan = device_discovery.lock().await.scan();
+            match scan.run_tcp(config.clone()).await {
+                Ok(devices) => tracing::info!("Periodic TCP scan of {}: {} new device(s)", config.cidr, devices.len()),
+                Err(e) => tracing::warn!("Periodic TCP scan of {} failed: {}", config.cidr, e),
+            }
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(config.interval_seconds.max(30))).await;
+    }
+}
+
+async fn tcp_scan_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    OptionalApiJson(config): OptionalApiJson<device_types::TcpScanConfig>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    let saved = app_state.db.get_tcp_scan_settings().await.map_err(|e| {
+        tracing::error!("Failed to get TCP scan settings: {}", e);
+        ApiError::internal("Internal server error")
+    })?;
+    // A body overrides range and timing, but cannot switch on a scan the saved settings keep disabled
+    let config = match config {
+        Some(config) => device_types::TcpScanConfig { enabled: saved.enabled, ..config },
+        None => saved,
+    };
+
+    if let Err(message) = validate_tcp_scan_config(&config) {
+        return Err(ApiError::bad_request(message));
+    }
+
+    let scan = app_state.device_discovery.lock().await.scan();
+    match scan.run_tcp(config).await {
+        Ok(devices) => {
+            let devices_json = scanned_devices_json(&devices);
+            Ok(Json(json!({
+                "success": true,
+                "devices": devices_json,
+                "count": devices_json.len()
+            })))
+        }
+        Err(e) => {
+            tracing::error!("TCP scan failed: {}", e);
+            Err(ApiError::internal(format!("TCP scan failed: {}", e)))
+        }
+    }
+}
+
+async fn get_tcp_scan_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    match app_state.db.get_tcp_scan_settings().await {
+        Ok(settings) => Ok(Json(json!({
+            "success": true,
+            "settings": settings
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to get TCP scan settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_tcp_scan_settings_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(config): ApiJson<device_types::TcpScanConfig>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    tracing::info!("Updating TCP scan settings: enabled={}, cidr={}", config.enabled, config.cidr);
+
+    // An empty range is allowed while the scan is disabled
+    let is_unset = !config.enabled && config.cidr.trim().is_empty();
+    if !is_unset {
+        if let Err(message) = validate_tcp_scan_limits(&config) {
+            return Err(ApiError::bad_request(message));
+        }
+    }
+
+    if let Err(e) = app_state.db.update_tcp_scan_settings(&config).await {
+        tracing::error!("Failed to update TCP scan settings: {}", e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "TCP scan settings updated successfully"
+    })))
+}
+
+// ============================================================================
+// MDNS SETTINGS HANDLERS
+// GET  /api/discovery/mdns-services - configured service types
+// POST /api/discovery/mdns-services - replace service types (applied on restart)
+// ============================================================================
+
+#[derive(Debug, Deserialize)]
+struct UpdateMdnsServicesRequest {
+    services: Vec<mdns_discovery::MdnsServiceConfig>,
+}
+
+/// Browsable service types: _name._tcp / _name._udp, optionally with .local
+static MDNS_SERVICE_TYPE: std::sync::LazyLock<regex::Regex> = std::sync::LazyLock::new(|| {
+    regex::Regex::new(r"^_[A-Za-z0-9-]+\._(tcp|udp)(\.local\.?)?$").expect("valid mDNS service type pattern")
+});
+
+async fn get_mdns_services_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    match app_state.db.get_mdns_services().await {
+        Ok(configured) => {
+            let is_default = configured.is_none();
+            Ok(Json(json!({
+                "success": true,
+                "services": configured.unwrap_or_else(mdns_discovery::default_mdns_services),
+                "isDefault": is_default
+            })))
+        }
+        Err(e) => {
+            tracing::error!("Failed to get mDNS settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_mdns_services_handler(
+    State(app_state): State<AppState>,
+    cookie_jar: CookieJar,
+    ApiJson(req): ApiJson<UpdateMdnsServicesRequest>,
+) -> Result<Json<Value>, ApiError> {
+    require_admin(&app_state, &cookie_jar).await?;
+    tracing::info!("Updating mDNS settings: {} service type(s)", req.services.len());
+
+    // Validate: 1-16 service types of the form _name._tcp / _name._udp
+    if req.services.is_empty() || req.services.len() > 16 {
+        return Err(ApiError::bad_request("Between 1 and 16 mDNS service types are required"));
+    }
+    if let Some(invalid) = req.services.iter().find(|s| !MDNS_SERVICE_TYPE.is_match(&s.service_type)) {
+        return Err(ApiError::bad_request(format!("Invalid mDNS service type: {}", invalid.service_type)));
+    }
+
+    if let Err(e) = app_state.db.update_mdns_services(&req.services).await {
+        tracing::error!("Failed to update mDNS settings: {}", e);
+        return Err(ApiError::internal("Internal server error"));
+    }
+
+    // Browsing starts with the discovery service, so the change applies after a restart
+    tracing::info!("mDNS settings updated in database (effective after restart)");
+
+    Ok(Json(json!({
+        "success": true,
+        "message": "mDNS settings updated successfully. Restart the server to apply new service types."
+    })))
+}
+
+// ============================================================================
+// GITHUB SETTINGS HANDLERS
+// GET  /api/github/settings - returns whether a token is configured (not the token itself)
+// POST /api/github/settings - save or clear the GitHub personal access token
+// ============================================================================
+
+#[derive(serde::Deserialize)]
+struct UpdateGithubSettingsRequest {
+    token: Option<String>,
+    owner: Option<String>,
+    repo: Option<String>,
+    asset: Option<String>,
+}
+
+async fn get_github_settings_handler(
+    State(app_state): State<AppState>,
+) -> Result<Json<Value>, ApiError> {
+    match app_state.db.get_github_settings().await {
+        Ok((token, owner, repo, asset)) => Ok(Json(json!({
+            "success": true,
+            "hasToken": token.is_some(),
+            "owner": owner,
+            "repo": repo,
+            "asset": asset,
+        }))),
+        Err(e) => {
+            tracing::error!("Failed to get GitHub settings: {}", e);
+            Err(ApiError::internal("Internal server error"))
+        }
+    }
+}
+
+async fn update_github_settings_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<UpdateGithubSettingsRequest>,
+) -> Result<Json<Value>, ApiError> {
+    // token field semantics:
+    //   None (field absent in JSON) = do not touch the stored token
+    //   Some("") = clear the stored token
+    //   Some("ghp_...") = save new token
+    if let Some(token_val) = &req.token {
+        let token_ref = if token_val.is_empty() { None } else { Some(token_val.as_str()) };
+        match app_state.db.set_github_token(token_ref).await {
+            Ok(()) => {}
+            Err(e) => {
+                tracing::error!("Failed to save GitHub token: {}", e);
+                return Err(ApiError::internal("Internal server error"));
+            }
+        }
+    }
+
+    // Only update owner/repo/asset if at least one of them was present in the request
+    if req.owner.is_some() || req.repo.is_some() || req.asset.is_some() {
+        let owner_ref = req.owner.as_deref().filter(|s| !s.is_empty());
+        let repo_ref  = req.repo.as_deref().filter(|s| !s.is_empty());
+        let asset_ref = req.asset.as_deref().filter(|s| !s.is_empty());
+        match app_state.db.set_github_settings(owner_ref, repo_ref, asset_ref).await {
+            Ok(()) => {
+                tracing::info!("GitHub settings saved: owner={:?} repo={:?} asset={:?}", owner_ref, repo_ref, asset_ref);
+            }
+            Err(e) => {
+                tracing::error!("Failed to save GitHub settings: {}", e);
+                return Err(ApiError::internal("Internal server error"));
+            }
+        }
+    }
+
+    Ok(Json(json!({ "success": true })))
+}
+
+// ============================================================================
+// FIRMWARE HANDLERS - Upload/fetch .bin files for ESP32 OTA
+// POST /api/firmware/upload  - multipart upload
+// POST /api/firmware/fetch   - download from URL (e.g. GitHub Release)
+// ============================================================================
+
+/// Request body for POST /api/firmware/fetch
+///
+/// Two modes:
+///   1. GitHub API (private repos): provide `owner`, `repo`, `tag`, `asset`, optionally `github_token`
+///   2. Direct URL (public): provide `url`, optionally `filename`
+#[derive(serde::Deserialize)]
+struct FirmwareFetchRequest {
+    // --- GitHub API mode ---
+    owner: Option<String>,
+    repo: Option<String>,
+    tag: Option<String>,
+    asset: Option<String>,
+    github_token: Option<String>,
+    // --- Direct URL mode ---
+    url: Option<String>,
+    filename: Option<String>,
+}
+
+async fn firmware_fetch_handler(
+    State(app_state): State<AppState>,
+    ApiJson(req): ApiJson<FirmwareFetchRequest>,
+) -> Result<Json<Value>, ApiError> {
+    outbound::check(OutboundFeature::FirmwareDownload).map_err(ApiError::offline)?;
+
+    // Direct URL mode: no DB lookup needed
+    if req.url.is_some() {
+        let url = req.url.as_deref().unwrap();
+
+        if !url.ends_with(".bin") {
+            return Err(ApiError::bad_request("URL must point to a .bin file"));
+        }
+
+        let filename = req.filename.clone().unwrap_or_else(|| {
+            url.split('/').next_back().unwrap_or("firmware.bin").to_string()
+        });
+        // Strip any directory component to prevent path traversal
+        let filename = std::path::Path::new(&filename)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("firmware.bin")
+            .to_string();
+
+        if !filename.ends_with(".bin") {
+            return Err(ApiError::bad_request("Filename must end with .bin"));
+        }
+
+        tracing::info!("Fetching firmware from URL: {}", url);
+
+        let client = outbound::http_client(reqwest::Client::builder())
+            .build()
+            .map_err(|_| ApiError::internal("Failed to build HTTP client"))?;
+        let response = client.get(url).send().await.map_err(|e| {
+            tracing::error!("Failed to fetch firmware from {}: {}", url, e);
+            ApiError::bad_gateway(format!("Failed to fetch firmware: {}", e))
+        })?;
+
+        if !response.status().is_success() {
+            tracing::error!("Firmware fetch failed: HTTP {}", response.status());
+            return Err(ApiError::bad_gateway(format!("Remote returned HTTP {}", response.status())));
+        }
+
+        let size = download_firmware(response, &filename).await?;
+
+        tracing::info!("Firmware fetched: {} ({} bytes) from {}", filename, size, url);
+        return Ok(Json(json!({
+            "success": true,
+            "filename": filename,
+            "size": size
+        })));
+    }
+
+    // GitHub mode: load DB settings as fallback for missing request fields
+    let (db_token, db_owner, db_repo, db_asset) = match app_state.db.get_github_settings().await {
+        Ok(s) => s,
+        Err(e) => {
+            tracing::error!("Failed to load GitHub settings from DB: {}", e);
+            return Err(ApiError::internal("Internal server error"));
+        }
+    };
+
+    // Merge: request fields take priority, DB values are fallback
+    let owner = req.owner.as_deref().or(db_owner.as_deref());
+    let repo  = req.repo.as_deref().or(db_repo.as_deref());
+    let asset = req.asset.as_deref().or(db_asset.as_deref());
+    let token = req.github_token.as_deref().or(db_token.as_deref());
+
+    if let (Some(owner), Some(repo), Some(asset_name)) = (owner, repo, asset) {
+        let tag = req.tag.as_deref();
+
+        // Validate asset filename and strip any directory component
+        let asset_name = std::path::Path::new(asset_name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or(asset_name);
+        if !asset_name.ends_with(".bin") {
+            return Err(ApiError::bad_request("Asset name must end with .bin"));
+        }
+
+        let resolved_token = token.map(|t| t.to_string());
+        // ── GitHub API mode ──────────────────────────────────────────────────
+        let client = outbound::http_client(reqwest::Client::builder())
+            .user_agent("esp32-manager-server/1.0")
+            .build()
+            .map_err(|_| ApiError::internal("Failed to build response"))?;
+
+        // Step 1: fetch release metadata to get the asset ID
+        let release_url = match tag {
+            Some(t) => format!("https://api.github.com/repos/{}/{}/releases/tags/{}", owner, repo, t),
+            None    => format!("https://api.github.com/repos/{}/{}/releases/latest", owner, repo),
+        };
+        tracing::info!("Fetching GitHub release metadata: {}", release_url);
+
+        let mut meta_req = client.get(&release_url)
+            .header("Accept", "application/vnd.github+json");
+        if let Some(token) = &resolved_token {
+            meta_req = meta_req.header("Authorization", format!("Bearer {}", token));
+        }
+
+        let meta_resp = meta_req.send().await.map_err(|e| {
+            tracing::error!("Failed to reach GitHub API: {}", e);
+            ApiError::bad_gateway(format!("Failed to reach GitHub API: {}", e))
+        })?;
+
+        if !meta_resp.status().is_success() {
+            tracing::error!("GitHub API returned HTTP {}", meta_resp.status());
+            return Err(ApiError::bad_gateway(format!("GitHub API returned HTTP {}", meta_resp.status())));
+        }
+
+        let release: Value = meta_resp.json().await.map_err(|e| {
+            tracing::error!("Failed to parse GitHub release JSON: {}", e);
+            ApiError::bad_gateway("Invalid GitHub release response")
+        })?;
+
+        // Find the asset by name
+        let asset_id = release["assets"]
+            .as_array()
+            .and_then(|assets| {
+                assets.iter().find(|a| a["name"].as_str() == Some(asset_name))
+            })
+            .and_then(|a| a["id"].as_u64())
+            .ok_or_else(|| {
+                tracing::error!("Asset '{}' not found in release '{:?}'", asset_name, tag);
+                ApiError::not_found(format!("Asset {} not found in release", asset_name))
+            })?;
+
+        // Step 2: download the asset via GitHub API (handles private repo redirects correctly)
+        let asset_url = format!(
+            "https://api.github.com/repos/{}/{}/releases/assets/{}",
+            owner, repo, asset_id
+        );
+        tracing::info!("Downloading GitHub asset: {}", asset_url);
+
+        let mut asset_req = client.get(&asset_url)
+            .header("Accept", "application/octet-stream");
+        if let Some(token) = &resolved_token {
+            asset_req = asset_req.header("Authorization", format!("Bearer {}", token));
+        }
+
+        let asset_resp = asset_req.send().await.map_err(|e| {
+            tracing::error!("Failed to download GitHub asset: {}", e);
+            ApiError::bad_gateway(format!("Failed to download GitHub asset: {}", e))
+        })?;
+
+        if !asset_resp.status().is_success() {
+            tracing::error!("GitHub asset download failed: HTTP {}", asset_resp.status());
+            return Err(ApiError::bad_gateway(format!("GitHub asset download returned HTTP {}", asset_resp.status())));
+        }
+
+        let size = download_firmware(asset_resp, asset_name).await?;
+
+        tracing::info!("GitHub firmware saved: {} ({} bytes)", asset_name, size);
+        Ok(Json(json!({
+            "success": true,
+            "filename": asset_name,
+            "size": size
+        })))
+    } else {
+        Err(ApiError::bad_request("Provide either (owner, repo, tag, asset) for GitHub or (url) for direct download"))
+    }
+}
+
+/// Firmware file written in chunks to a temporary file, renamed into place once complete
+/// (an interrupted transfer never replaces the previous image)
+struct FirmwareWriter {
+    file: tokio::fs::File,
+    temp_path: String,
+    path: String,
+    size: u64,
+}
+
+impl FirmwareWriter {
+    async fn create(path: &str) -> Result<Self, ApiError> {
+        tokio::fs::create_dir_all("firmware").await.map_err(|e| {
+            tracing::error!("Failed to create firmware directory: {}", e);
+            ApiError::internal("Failed to create firmware directory")
+        })?;
+        let temp_path = format!("{}.part", path);
+        let file = tokio::fs::File::create(&temp_path).await.map_err(|e| {
+            tracing::error!("Failed to create firmware file {}: {}", temp_path, e);
+            ApiError::internal("Failed to store firmware file")
+        })?;
+        Ok(Self { file, temp_path, path: path.to_string(), size: 0 })
+    }
+
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), ApiError> {
+        use tokio::io::AsyncWriteExt;
+        if let Err(e) = self.file.write_all(chunk).await {
+            tracing::error!("Failed to write firmware file {}: {}", self.temp_path, e);
+            let _ = tokio::fs::remove_file(&self.temp_path).await;
+            return Err(ApiError::internal("Failed to store firmware file"));
+        }
+        self.size += chunk.len() as u64;
+        Ok(())
+    }
+
+    /// Drop the partial file after a failed transfer
+    async fn abort(self) {
+        drop(self.file);
+        let _ = tokio::fs::remove_file(&self.temp_path).await;
+    }
+
+    async fn finish(mut self) -> Result<u64, ApiError> {
+        use tokio::io::AsyncWriteExt;
+        let result = match self.file.flush().await {
+            Ok(()) => tokio::fs::rename(&self.temp_path, &self.path).await,
+            Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+            tracing::error!("Failed to store firmware file {}: {}", self.path, e);
+            let _ = tokio::fs::remove_file(&self.temp_path).await;
+            return Err(ApiError::internal("Failed to store firmware file"));
+        }
+        Ok(self.size)
+    }
+}
+
+/// Stream a firmware download into firmware/<filename>, returns the size in bytes
+async fn download_firmware(mut response: reqwest::Response, filename: &str) -> Result<u64, ApiError> {
+    let mut writer = FirmwareWriter::create(&format!("firmware/{}", filename)).await?;
+    loop {
+        match response.chunk().await {
+            Ok(Some(chunk)) => writer.write(&chunk).await?,
+            Ok(None) => return writer.finish().await,
+            Err(e) => {
+                tracing::error!("Failed to read firmware download {}: {}", filename, e);
+                writer.abort().await;
+                return Err(ApiError::bad_gateway(format!("Failed to read firmware download: {}", e)));
+            }
+        }
+    }
+}
+
+// ============================================================================
+// FIRMWARE UPLOAD HANDLER - Upload .bin files for ESP32 OTA
+// POST /api/firmware/upload
+// ============================================================================
+
+async fn firmware_upload_handler(
+    mut multipart: axum::extract::Multipart,
+) -> Result<Json<Value>, ApiError> {
+    if let Some(mut field) = multipart.next_field().await.map_err(|e| {
+        tracing::error!("Multipart error: {}", e);
+        ApiError::bad_request(format!("Invalid multipart body: {}", e))
+    })? {
+        let raw_name = field
+            .file_name()
+            .filter(|n| n.ends_with(".bin"))
+            .map(|n| n.to_string())
+            .ok_or_else(|| {
+                tracing::warn!("Firmware upload rejected: missing or non-.bin filename");
+                ApiError::bad_request("Firmware file name must end with .bin")
+            })?;
+        // Strip any directory component to prevent path traversal
+        let filename = std::path::Path::new(&raw_name)
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("firmware.bin")
+            .to_string();
+
+        // Written chunk by chunk, the image never sits in memory as a whole
+        let path = format!("firmware/{}", filename);
+        let mut writer = FirmwareWriter::create(&path).await?;
+        let size = loop {
+            match field.chunk().await {
+                Ok(Some(chunk)) => writer.write(&chunk).await?,
+                Ok(None) => break writer.finish().await?,
+                Err(e) => {
+                    tracing::error!("Failed to read firmware bytes: {}", e);
+                    writer.abort().await;
+                    return Err(ApiError::bad_request(format!("Failed to read firmware upload: {}", e)));
+                }
+            }
+        };
+
+        tracing::info!("Firmware uploaded: {} ({} bytes)", filename, size);
+        return Ok(Json(json!({
+            "success": true,
+            "filename": filename,
+            "size": size
+        })));
+    }
+
+    tracing::warn!("Firmware upload: no file field in multipart request");
+    Err(ApiError::bad_request("No firmware file in request"))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_scan(cidr: &str) -> device_types::TcpScanConfig {
+        device_types::TcpScanConfig { enabled: true, cidr: cidr.to_string(), ..Default::default() }
+    }
+
+    #[test]
+    fn test_tcp_scan_requires_opt_in() {
+        let disabled = device_types::TcpScanConfig { enabled: false, ..enabled_scan("192.168.1.0/24") };
+        assert!(validate_tcp_scan_config(&disabled).is_err());
+        assert!(validate_tcp_scan_limits(&disabled).is_ok());
+        assert!(validate_tcp_scan_config(&enabled_scan("192.168.1.0/24")).is_ok());
+    }
+
+    #[test]
+    fn test_tcp_scan_cidr_limits() {
+        assert!(validate_tcp_scan_config(&enabled_scan("10.0.0.0/16")).is_ok());
+        assert!(validate_tcp_scan_config(&enabled_scan("10.0.0.0/15")).is_err());
+        assert!(validate_tcp_scan_config(&enabled_scan("0.0.0.0/0")).is_err());
+        assert!(validate_tcp_scan_config(&enabled_scan("10.0.0.7/32")).is_ok());
+        assert!(validate_tcp_scan_config(&enabled_scan("10.0.0.0/33")).is_err());
+        assert!(validate_tcp_scan_config(&enabled_scan("10.0.0.0")).is_err());
+        assert!(validate_tcp_scan_config(&enabled_scan("")).is_err());
+    }
+
+    #[test]
+    fn test_tcp_scan_port_and_timing_limits() {
+        let base = enabled_scan("192.168.1.0/24");
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { port: 0, ..base.clone() }).is_err());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { port: 65535, ..base.clone() }).is_ok());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { concurrency: 0, ..base.clone() }).is_err());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { concurrency: 257, ..base.clone() }).is_err());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { timeout_ms: 49, ..base.clone() }).is_err());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { timeout_ms: 5_001, ..base.clone() }).is_err());
+        assert!(validate_tcp_scan_config(&device_types::TcpScanConfig { interval_seconds: 29, ..base }).is_err());
+    }
+
+    #[test]
+    fn test_mdns_service_type_pattern() {
+        assert!(MDNS_SERVICE_TYPE.is_match("_esp32._tcp"));
+        assert!(MDNS_SERVICE_TYPE.is_match("_arduino._udp.local."));
+        assert!(!MDNS_SERVICE_TYPE.is_match("esp32._tcp"));
+        assert!(!MDNS_SERVICE_TYPE.is_match("_esp32._sctp"));
+    }
+}
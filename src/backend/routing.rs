@@ -0,0 +1,388 @@
+// Routing rules - device-to-device commands evaluated on the server (POST /api/routing-rules)
+//
+// A rule connects an event of a source device to a command for a target device, e.g.
+// "variable button == 1" on the sensor board -> {"startOption": "pump_on"} on the actuator board.
+// Triggers:
+//   variable <name>                  every update of the variable
+//   variable <name> changes          value differs from the previous update
+//   variable <name> <op> <number>    condition becomes true (fires once per transition)
+//   device online | device offline   connection status changes to connected/disconnected
+// String values "{value}" in the command are replaced by the triggering value (a number if it
+// parses as one), "{device}" by the source device ID.
+//
+// Commands go through the bulk command path with the permissions of the rule owner. Each rule
+// has its own per-minute budget. Loop protection: a command marks its target as "caused" for a
+// few seconds; rules triggered by events of a caused device count one hop more, and chains
+// longer than MAX_HOPS (A -> B -> A -> ...) are stopped.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use crate::alerts::Comparison;
+use crate::database::{DatabaseManager, RoutingRule};
+use crate::device_store::{FeedEvent, SharedDeviceStore};
+use crate::events::DeviceEvent;
+use crate::schedules::CommandExecutor;
+
+/// Allowed values of routing_rules.max_per_minute
+pub const MAX_RATE_PER_MINUTE: u32 = 600;
+pub const DEFAULT_RATE_PER_MINUTE: u32 = 60;
+
+/// Longest chain of rules triggered by each other's commands
+pub const MAX_HOPS: u8 = 4;
+
+/// Events of a device this long after a routed command count as caused by it
+const LOOP_WINDOW: Duration = Duration::from_secs(3);
+
+/// Signalled by the API after rules were created, changed or deleted
+static RULES_CHANGED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Counters of the running engine per rule (reset on restart)
+static STATS: LazyLock<Mutex<HashMap<String, RouteStats>>> = LazyLock::new(|| Mutex::new(HashMap::new()));
+
+/// Make the running engine reload its rules
+pub fn rules_changed() {
+    RULES_CHANGED.notify_one();
+}
+
+/// Execution counters of a rule (GET /api/routing-rules)
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RouteStats {
+    pub fired: u64,
+    pub rate_limited: u64,
+    pub loop_blocked: u64,
+    pub failed: u64,
+    pub last_fired_at: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+pub fn stats(rule_id: &str) -> RouteStats {
+    STATS.lock().unwrap().get(rule_id).cloned().unwrap_or_default()
+}
+
+fn update_stats(rule_id: &str, update: impl FnOnce(&mut RouteStats)) {
+    update(STATS.lock().unwrap().entry(rule_id.to_string()).or_default());
+}
+
+// ============================================================================
+// TRIGGERS
+// ============================================================================
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RouteTrigger {
+    Variable { name: String, condition: VariableCondition },
+    Connection { online: bool },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum VariableCondition {
+    Any,
+    Changes,
+    Compare { comparison: Comparison, threshold: f64 },
+}
+
+impl RouteTrigger {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+        match tokens.as_slice() {
+            ["variable", name] => Ok(Self::Variable { name: name.to_string(), condition: VariableCondition::Any }),
+            ["variable", name, "changes"] => Ok(Self::Variable { name: name.to_string(), condition: VariableCondition::Changes }),
+            ["variable", name, op, threshold] => {
+                let comparison = Comparison::parse(op).ok_or_else(|| format!("Unknown comparison '{}' (use >, >=, <, <=, ==, !=)", op))?;
+                let threshold: f64 = threshold.parse().map_err(|_| format!("Threshold '{}' is not a number", threshold))?;
+                Ok(Self::Variable { name: name.to_string(), condition: VariableCondition::Compare { comparison, threshold } })
+            }
+            ["device", "online"] => Ok(Self::Connection { online: true }),
+            ["device", "offline"] => Ok(Self::Connection { online: false }),
+            _ => Err("Trigger must be 'variable <name> [changes | <op> <number>]' or 'device online|offline'".to_string()),
+        }
+    }
+}
+
+/// Canonical text form (stored in routing_rules.trigger)
+impl fmt::Display for RouteTrigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Variable { name, condition: VariableCondition::Any } => write!(f, "variable {}", name),
+            Self::Variable { name, condition: VariableCondition::Changes } => write!(f, "variable {} changes", name),
+            Self::Variable { name, condition: VariableCondition::Compare { comparison, threshold } } => {
+                write!(f, "variable {} {} {}", name, comparison.as_str(), threshold)
+            }
+            Self::Connection { online: true } => write!(f, "device online"),
+            Self::Connection { online: false } => write!(f, "device offline"),
+        }
+    }
+}
+
+/// Command with "{value}" and "{device}" replaced
+pub fn render_command(command: &Value, value: &str, source_device_id: &str) -> Value {
+    match command {
+        Value::String(text) if text == "{value}" => value.trim().parse::<f64>().ok()
+            .and_then(|number| if number.fract() == 0.0 && number.abs() < 1e15 { Some(json!(number as i64)) } else { serde_json::Number::from_f64(number).map(Value::Number) })
+            .unwrap_or_else(|| json!(value)),
+        Value::String(text) => json!(text.replace("{value}", value).replace("{device}", source_device_id)),
+        Value::Array(items) => Value::Array(items.iter().map(|item| render_command(item, value, source_device_id)).collect()),
+        Value::Object(fields) => Value::Object(fields.iter().map(|(key, item)| (key.clone(), render_command(item, value, source_device_id))).collect()),
+        other => other.clone(),
+    }
+}
+
+// ============================================================================
+// ENGINE
+// ============================================================================
+
+/// Evaluation state of one rule
+struct RouteState {
+    rule: RoutingRule,
+    trigger: RouteTrigger,
+    last_value: Option<String>,
+    /// Comparison held at the last update (edge detection)
+    holding: bool,
+    window_start: Instant,
+    fires_in_window: u32,
+}
+
+impl RouteState {
+    fn new(rule: RoutingRule, trigger: RouteTrigger) -> Self {
+        Self { rule, trigger, last_value: None, holding: false, window_start: Instant::now(), fires_in_window: 0 }
+    }
+
+    /// Triggering value when the event fires the rule
+    fn observe(&mut self, event: &DeviceEvent) -> Option<String> {
+        match (&self.trigger, event) {
+            (RouteTrigger::Variable { name, condition }, DeviceEvent::DeviceVariableUpdate { variable_name, variable_value, .. }) if name == variable_name => {
+                let previous = self.last_value.replace(variable_value.clone());
+                let fires = match condition {
+                    VariableCondition::Any => true,
+                    VariableCondition::Changes => previous.is_some_and(|previous| previous != *variable_value),
+                    VariableCondition::Compare { comparison, threshold } => {
+                        let holds = variable_value.trim().parse::<f64>().is_ok_and(|value| comparison.holds(value, *threshold));
+                        let rising = holds && !self.holding;
+                        self.holding = holds;
+                        rising
+                    }
+                };
+                fires.then(|| variable_value.clone())
+            }
+            (RouteTrigger::Connection { online }, DeviceEvent::DeviceConnectionStatus { connected, .. }) => {
+                let previous = self.last_value.replace(connected.to_string());
+                let changed = previous.is_none_or(|previous| previous != connected.to_string());
+                (changed && connected == online).then(|| connected.to_string())
+            }
+            _ => None,
+        }
+    }
+
+    /// Count a firing; false once the per-minute budget is used up
+    fn admit(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(60) {
+            self.window_start = now;
+            self.fires_in_window = 0;
+        }
+        self.fires_in_window += 1;
+        self.fires_in_window <= self.rule.max_per_minute
+    }
+}
+
+/// Devices that recently received a routed command and the hop count of that command
+#[derive(Debug, Default)]
+struct CausalTracker {
+    caused: HashMap<String, (Instant, u8)>,
+}
+
+impl CausalTracker {
+    /// Hops of the chain an event of `device_id` belongs to (0 = not caused by a rule)
+    fn hops(&mut self, device_id: &str, now: Instant) -> u8 {
+        self.caused.retain(|_, (at, _)| now.duration_since(*at) < LOOP_WINDOW);
+        self.caused.get(device_id).map_or(0, |(_, hops)| *hops)
+    }
+
+    fn record(&mut self, target_device_id: &str, hops: u8, now: Instant) {
+        let entry = self.caused.entry(target_device_id.to_string()).or_insert((now, hops));
+        *entry = (now, entry.1.max(hops));
+    }
+}
+
+/// Runs enabled routing rules on the device event feed
+pub struct RoutingEngine {
+    db: Arc<DatabaseManager>,
+    device_store: SharedDeviceStore,
+    executor: CommandExecutor,
+}
+
+impl RoutingEngine {
+    pub fn new(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, executor: CommandExecutor) -> Self {
+        Self { db, device_store, executor }
+    }
+
+    pub fn start(self) {
+        let mut feed = self.device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut rules = self.load_rules(Vec::new()).await;
+            let mut causal = CausalTracker::default();
+            info!("Routing engine started with {} rule(s)", rules.len());
+
+            loop {
+                tokio::select! {
+                    received = feed.recv() => match received {
+                        Ok(feed_event) => self.handle_event(&mut rules, &mut causal, &feed_event),
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Routing engine lagged behind the event feed, skipped {} events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = RULES_CHANGED.notified() => {
+                        rules = self.load_rules(rules).await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// (Re)load enabled rules; unchanged rules keep their edge and rate state
+    async fn load_rules(&self, previous: Vec<RouteState>) -> Vec<RouteState> {
+        let stored = match self.db.list_enabled_routing_rules().await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to load routing rules: {}", e);
+                return previous;
+            }
+        };
+
+        let mut previous: HashMap<String, RouteState> = previous.into_iter().map(|state| (state.rule.id.clone(), state)).collect();
+        let mut rules = Vec::new();
+        for rule in stored {
+            let trigger = match RouteTrigger::parse(&rule.trigger) {
+                Ok(trigger) => trigger,
+                Err(e) => {
+                    warn!("Skipping routing rule {} with invalid trigger '{}': {}", rule.id, rule.trigger, e);
+                    continue;
+                }
+            };
+            let state = match previous.remove(&rule.id) {
+                Some(mut state) if state.rule.source_device_id == rule.source_device_id && state.trigger == trigger => {
+                    state.rule = rule;
+                    state
+                }
+                _ => RouteState::new(rule, trigger),
+            };
+            rules.push(state);
+        }
+        rules
+    }
+
+    fn handle_event(&self, rules: &mut [RouteState], causal: &mut CausalTracker, feed_event: &FeedEvent) {
+        let now = Instant::now();
+        let hops = causal.hops(&feed_event.device_id, now);
+
+        for state in rules.iter_mut().filter(|state| state.rule.source_device_id == feed_event.device_id) {
+            let Some(value) = state.observe(&feed_event.event) else { continue };
+            let rule_id = state.rule.id.clone();
+
+            if hops >= MAX_HOPS {
+                warn!("Routing rule '{}' stopped: {} chained commands within {:?} (loop?)", state.rule.name, hops, LOOP_WINDOW);
+                update_stats(&rule_id, |stats| stats.loop_blocked += 1);
+                continue;
+            }
+            if !state.admit(now) {
+                if state.fires_in_window == state.rule.max_per_minute + 1 {
+                    warn!("Routing rule '{}' exceeded {} commands per minute, skipping triggers", state.rule.name, state.rule.max_per_minute);
+                }
+                update_stats(&rule_id, |stats| stats.rate_limited += 1);
+                continue;
+            }
+
+            causal.record(&state.rule.target_device_id, hops + 1, now);
+            update_stats(&rule_id, |stats| {
+                stats.fired += 1;
+                stats.last_fired_at = Some(Utc::now());
+            });
+
+            let command = render_command(&state.rule.command, &value, &feed_event.device_id);
+            let target = state.rule.target_device_id.clone();
+            let owner_id = state.rule.owner_id.clone();
+            let rule_name = state.rule.name.clone();
+            let executor = self.executor.clone();
+            tokio::spawn(async move {
+                let report = executor(vec![target.clone()], command.clone(), owner_id).await;
+                let result = &report["results"][0];
+                if result["success"] != true {
+                    let error = result["message"].as_str().unwrap_or("unknown error").to_string();
+                    warn!("Routing rule '{}': command {} to {} failed: {}", rule_name, command, target, error);
+                    update_stats(&rule_id, |stats| {
+                        stats.failed += 1;
+                        stats.last_error = Some(error);
+                    });
+                }
+            });
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_triggers_rate_limit_and_loop_protection() {
+        assert_eq!(RouteTrigger::parse("variable  button == 1").unwrap().to_string(), "variable button == 1");
+        assert_eq!(RouteTrigger::parse("variable temp changes").unwrap().to_string(), "variable temp changes");
+        assert_eq!(RouteTrigger::parse("device offline").unwrap(), RouteTrigger::Connection { online: false });
+        assert!(RouteTrigger::parse("variable temp ~ 1").is_err());
+        assert!(RouteTrigger::parse("device rebooted").is_err());
+
+        let rule = RoutingRule {
+            id: "route-1".to_string(),
+            owner_id: "user-1".to_string(),
+            name: "Button -> pump".to_string(),
+            source_device_id: "AA-01".to_string(),
+            trigger: "variable button == 1".to_string(),
+            target_device_id: "AA-02".to_string(),
+            command: json!({"setVariable": {"name": "pump", "value": "{value}"}}),
+            max_per_minute: 2,
+            enabled: true,
+            created_at: Utc::now(),
+        };
+        let trigger = RouteTrigger::parse(&rule.trigger).unwrap();
+        let mut state = RouteState::new(rule, trigger);
+        let update = |value: &str| DeviceEvent::device_variable_update("AA-01".to_string(), "button".to_string(), value.to_string());
+
+        // Fires on the transition only
+        assert_eq!(state.observe(&update("1")), Some("1".to_string()));
+        assert_eq!(state.observe(&update("1")), None);
+        assert_eq!(state.observe(&update("0")), None);
+        assert_eq!(state.observe(&update("1")), Some("1".to_string()));
+        assert_eq!(render_command(&state.rule.command, "1", "AA-01"), json!({"setVariable": {"name": "pump", "value": 1}}));
+        assert_eq!(render_command(&json!({"startOption": "from_{device}"}), "x", "AA-01"), json!({"startOption": "from_AA-01"}));
+
+        let now = Instant::now();
+        assert!(state.admit(now) && state.admit(now));
+        assert!(!state.admit(now));
+        assert!(state.admit(now + Duration::from_secs(60)));
+
+        // A -> B -> A -> ... stops after MAX_HOPS commands inside the loop window
+        let mut causal = CausalTracker::default();
+        let (mut device, mut other) = ("AA-01", "AA-02");
+        for expected in 0..MAX_HOPS {
+            let hops = causal.hops(device, now);
+            assert_eq!(hops, expected);
+            causal.record(other, hops + 1, now);
+            std::mem::swap(&mut device, &mut other);
+        }
+        assert_eq!(causal.hops(device, now), MAX_HOPS);
+        assert_eq!(causal.hops(device, now + LOOP_WINDOW), 0);
+    }
+}
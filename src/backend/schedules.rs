@@ -0,0 +1,474 @@
+// Schedules - recurring device commands (POST /api/schedules)
+//
+// A schedule sends one command to a device or to all members of a device group, e.g.
+// "every day at 18:00" -> {"startOption": "night_mode"} -> group "Garden". Times are in
+// the server's local time zone. The next run time is stored in the database, so after
+// downtime the runner sees which runs were missed: schedules with catch_up run once right
+// away, the others record a skipped run and wait for their next regular time. Every run is
+// logged in schedule_runs with the per-device report of the bulk command.
+
+use std::fmt;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike, Utc};
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+use tokio::sync::Notify;
+use tracing::{info, warn};
+
+use crate::database::{DatabaseManager, Schedule, ScheduleRun};
+
+/// Shortest interval of "every <n><unit>" schedules
+pub const MIN_INTERVAL: Duration = Duration::from_secs(60);
+/// A run this late was missed (server down), not just delayed
+const MISSED_AFTER: chrono::Duration = chrono::Duration::seconds(60);
+/// Longest sleep between checks (also bounds the effect of clock changes)
+const MAX_SLEEP: Duration = Duration::from_secs(60);
+/// Runs kept per schedule in the execution history
+pub const RUN_HISTORY_SIZE: usize = 100;
+/// Days searched for the next matching time of a cron expression (covers Feb 29)
+const SEARCH_DAYS: u32 = 366 * 5;
+
+/// Signalled by the API after schedules were created, changed or deleted
+static SCHEDULES_CHANGED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Make the running scheduler re-read the next due time
+pub fn schedules_changed() {
+    SCHEDULES_CHANGED.notify_one();
+}
+
+/// Sends a command to devices as a user and returns the bulk report
+/// (`success`, `total`, `succeeded`, `failed`, `results`); provided by main.rs
+pub type CommandExecutor = Arc<dyn Fn(Vec<String>, Value, String) -> BoxFuture<'static, Value> + Send + Sync>;
+
+// ============================================================================
+// SCHEDULE SYNTAX
+// ============================================================================
+
+const DAY_NAMES: [&str; 7] = ["sun", "mon", "tue", "wed", "thu", "fri", "sat"];
+const FULL_DAY_NAMES: [&str; 7] = ["sunday", "monday", "tuesday", "wednesday", "thursday", "friday", "saturday"];
+
+/// Parsed cron fields as bit sets (standard 5-field semantics, day of week 0 = Sunday)
+#[derive(Debug, Clone, PartialEq)]
+struct CronFields {
+    minutes: u64,
+    hours: u32,
+    days_of_month: u32,
+    months: u16,
+    days_of_week: u8,
+    /// Day of month/week given as "*" - with both restricted, either one matches
+    any_day_of_month: bool,
+    any_day_of_week: bool,
+}
+
+impl CronFields {
+    fn parse(expression: &str) -> Result<Self, String> {
+        let fields: Vec<&str> = expression.split_whitespace().collect();
+        let [minute, hour, day_of_month, month, day_of_week] = fields[..] else {
+            return Err(format!("cron needs 5 fields (minute hour day-of-month month day-of-week): {}", expression));
+        };
+        // Day of week 7 is Sunday as well
+        let days_of_week = parse_cron_field(day_of_week, 0, 7, "day of week")?;
+        Ok(Self {
+            minutes: parse_cron_field(minute, 0, 59, "minute")?,
+            hours: parse_cron_field(hour, 0, 23, "hour")? as u32,
+            days_of_month: parse_cron_field(day_of_month, 1, 31, "day of month")? as u32,
+            months: parse_cron_field(month, 1, 12, "month")? as u16,
+            days_of_week: ((days_of_week | (days_of_week >> 7)) & 0x7f) as u8,
+            any_day_of_month: day_of_month == "*",
+            any_day_of_week: day_of_week == "*",
+        })
+    }
+
+    fn matches_date(&self, date: NaiveDate) -> bool {
+        if self.months & (1 << date.month()) == 0 {
+            return false;
+        }
+        let day_of_month = self.days_of_month & (1 << date.day()) != 0;
+        let day_of_week = self.days_of_week & (1 << date.weekday().num_days_from_sunday()) != 0;
+        match (self.any_day_of_month, self.any_day_of_week) {
+            (false, false) => day_of_month || day_of_week,
+            _ => day_of_month && day_of_week,
+        }
+    }
+
+    /// First matching minute strictly after `after`; local times skipped by DST do not match
+    fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        let timezone = after.timezone();
+        let start = after.naive_local().with_second(0)?.with_nanosecond(0)? + chrono::Duration::minutes(1);
+        let mut date = start.date();
+        for _ in 0..SEARCH_DAYS {
+            if self.matches_date(date) {
+                for hour in (0..24).filter(|h| self.hours & (1 << h) != 0) {
+                    for minute in (0..60).filter(|m| self.minutes & (1 << m) != 0) {
+                        let candidate = date.and_hms_opt(hour, minute, 0)?;
+                        if candidate < start {
+                            continue;
+                        }
+                        if let Some(time) = timezone.from_local_datetime(&candidate).earliest() {
+                            return Some(time);
+                        }
+                    }
+                }
+            }
+            date = date.succ_opt()?;
+        }
+        None
+    }
+}
+
+/// One cron field ("*", "5", "1-5", "*/15", "0-30/10", lists with ",") as bit set
+fn parse_cron_field(field: &str, min: u32, max: u32, name: &str) -> Result<u64, String> {
+    let mut bits = 0u64;
+    for item in field.split(',') {
+        let (range, step) = match item.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|s| *s > 0)
+                .ok_or_else(|| format!("invalid step in {} field: {}", name, item))?),
+            None => (item, 1),
+        };
+        let value = |text: &str| -> Result<u32, String> {
+            let value = match DAY_NAMES.iter().position(|day| text.eq_ignore_ascii_case(day)) {
+                Some(day) if name == "day of week" => day as u32,
+                _ => text.parse::<u32>().map_err(|_| format!("invalid {}: {}", name, text))?,
+            };
+            if value < min || value > max {
+                return Err(format!("{} must be between {} and {}: {}", name, min, max, value));
+            }
+            Ok(value)
+        };
+        let (from, to) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((from, to)) => (value(from)?, value(to)?),
+                None => {
+                    let single = value(range)?;
+                    // "5/10" = from 5 to the end in steps of 10
+                    (single, if step > 1 { max } else { single })
+                }
+            },
+        };
+        if from > to {
+            return Err(format!("invalid range in {} field: {}", name, item));
+        }
+        for value in (from..=to).step_by(step as usize) {
+            bits |= 1 << value;
+        }
+    }
+    Ok(bits)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum SpecKind {
+    /// Fixed interval, measured from the previous run
+    Interval(Duration),
+    Cron(CronFields),
+}
+
+/// When a schedule runs:
+/// - `every 15m` / `every 2h` / `every 1d` (s, m, h, d; at least one minute)
+/// - `every day at 18:00`, `every weekday at 07:30`, `every weekend at 10:00`,
+///   `every mon,fri at 08:00` ("at" is optional)
+/// - `cron 0 18 * * 1-5` (minute hour day-of-month month day-of-week)
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScheduleSpec {
+    kind: SpecKind,
+    /// Normalized text, stored in the database
+    canonical: String,
+}
+
+impl ScheduleSpec {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let text = text.trim().to_lowercase();
+        let words: Vec<&str> = text.split_whitespace().collect();
+        match words.as_slice() {
+            ["cron", fields @ ..] => {
+                let expression = fields.join(" ");
+                Ok(Self { kind: SpecKind::Cron(CronFields::parse(&expression)?), canonical: format!("cron {}", expression) })
+            }
+            ["every", interval] if interval.starts_with(|c: char| c.is_ascii_digit()) => {
+                let duration = crate::alerts::parse_duration(interval)?;
+                if duration < MIN_INTERVAL {
+                    return Err(format!("interval must be at least {}s", MIN_INTERVAL.as_secs()));
+                }
+                Ok(Self { kind: SpecKind::Interval(duration), canonical: format!("every {}", crate::alerts::format_duration(duration)) })
+            }
+            ["every", days, "at", time] | ["every", days, time] => {
+                let (hour, minute) = parse_time_of_day(time)?;
+                let (days_of_week, days_text) = parse_days(days)?;
+                let fields = CronFields::parse(&format!("{} {} * * {}", minute, hour, days_of_week))?;
+                Ok(Self { kind: SpecKind::Cron(fields), canonical: format!("every {} at {:02}:{:02}", days_text, hour, minute) })
+            }
+            _ => Err(format!(
+                "Unknown schedule '{}' (use \"every 15m\", \"every day at 18:00\", \"every mon,fri at 07:30\" or \"cron 0 18 * * *\")",
+                text
+            )),
+        }
+    }
+
+    /// Next run strictly after `after` (None: the expression never matches again)
+    pub fn next_after<Tz: TimeZone>(&self, after: &DateTime<Tz>) -> Option<DateTime<Tz>> {
+        match &self.kind {
+            SpecKind::Interval(interval) => Some(after.clone() + chrono::Duration::from_std(*interval).ok()?),
+            SpecKind::Cron(fields) => fields.next_after(after),
+        }
+    }
+
+    /// Next run in server local time, as stored in schedules.next_run_at
+    pub fn next_run(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.next_after(&after.with_timezone(&Local)).map(|time| time.with_timezone(&Utc))
+    }
+}
+
+impl fmt::Display for ScheduleSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.canonical)
+    }
+}
+
+/// "18:00" -> (18, 0)
+fn parse_time_of_day(text: &str) -> Result<(u32, u32), String> {
+    let (hour, minute) = text.split_once(':').ok_or_else(|| format!("time must be HH:MM: {}", text))?;
+    match (hour.parse::<u32>(), minute.parse::<u32>()) {
+        (Ok(hour), Ok(minute)) if hour < 24 && minute < 60 => Ok((hour, minute)),
+        _ => Err(format!("time must be HH:MM between 00:00 and 23:59: {}", text)),
+    }
+}
+
+/// "day", "weekday", "weekend" or day names -> (cron day-of-week field, normalized text)
+fn parse_days(text: &str) -> Result<(String, String), String> {
+    match text {
+        "day" | "days" => return Ok(("*".to_string(), "day".to_string())),
+        "weekday" | "weekdays" => return Ok(("1-5".to_string(), "weekday".to_string())),
+        "weekend" | "weekends" => return Ok(("0,6".to_string(), "weekend".to_string())),
+        _ => {}
+    }
+    let mut days = Vec::new();
+    for name in text.split(',').filter(|n| !n.is_empty()) {
+        let day = (0..7)
+            .find(|&day| name == DAY_NAMES[day] || name == FULL_DAY_NAMES[day])
+            .ok_or_else(|| format!("unknown day '{}' (use day, weekday, weekend or mon..sun)", name))?;
+        if !days.contains(&day) {
+            days.push(day);
+        }
+    }
+    if days.is_empty() {
+        return Err("no days given".to_string());
+    }
+    // Monday first, like a calendar week
+    days.sort_by_key(|day| (day + 6) % 7);
+    Ok((
+        days.iter().map(usize::to_string).collect::<Vec<_>>().join(","),
+        days.iter().map(|day| DAY_NAMES[*day]).collect::<Vec<_>>().join(","),
+    ))
+}
+
+// ============================================================================
+// EXECUTION
+// ============================================================================
+
+/// Why a run happened
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunTrigger {
+    Scheduled,
+    /// Missed while the server was down, run after the restart
+    CatchUp,
+    /// POST /api/schedules/:id/run
+    Manual,
+}
+
+impl RunTrigger {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Scheduled => "scheduled",
+            Self::CatchUp => "catch_up",
+            Self::Manual => "manual",
+        }
+    }
+}
+
+/// Devices a schedule targets right now (group members are resolved at run time)
+async fn target_devices(db: &DatabaseManager, schedule: &Schedule) -> Result<Vec<String>, String> {
+    if let Some(device_id) = &schedule.device_id {
+        return Ok(vec![device_id.clone()]);
+    }
+    let group_id = schedule.group_id.as_deref().ok_or("Schedule has no target")?;
+    match db.get_device_group(group_id).await {
+        Ok(Some(group)) if group.owner_id == schedule.owner_id => Ok(group.device_ids),
+        Ok(_) => Err(format!("Device group {} no longer exists", group_id)),
+        Err(e) => Err(format!("Failed to load device group {}: {}", group_id, e)),
+    }
+}
+
+/// Run a schedule's command once and record the run in the history
+pub async fn execute(
+    db: &DatabaseManager,
+    executor: &CommandExecutor,
+    schedule: &Schedule,
+    trigger: RunTrigger,
+    scheduled_for: Option<DateTime<Utc>>,
+) -> ScheduleRun {
+    let mut run = ScheduleRun {
+        id: uuid::Uuid::new_v4().to_string(),
+        schedule_id: schedule.id.clone(),
+        trigger: trigger.as_str().to_string(),
+        status: "failed".to_string(),
+        scheduled_for,
+        started_at: Utc::now(),
+        finished_at: None,
+        total: 0,
+        succeeded: 0,
+        error: None,
+        results: json!([]),
+    };
+
+    match target_devices(db, schedule).await {
+        Ok(devices) if devices.is_empty() => run.error = Some("No target devices".to_string()),
+        Ok(devices) => {
+            let report = executor(devices, schedule.command.clone(), schedule.owner_id.clone()).await;
+            run.total = report["total"].as_u64().unwrap_or(0) as u32;
+            run.succeeded = report["succeeded"].as_u64().unwrap_or(0) as u32;
+            run.status = match run.succeeded {
+                0 => "failed",
+                succeeded if succeeded == run.total => "success",
+                _ => "partial",
+            }.to_string();
+            run.results = report["results"].clone();
+        }
+        Err(e) => run.error = Some(e),
+    }
+    run.finished_at = Some(Utc::now());
+
+    info!("Schedule '{}' ({}) ran [{}]: {} ({}/{} devices)", schedule.name, schedule.id, run.trigger, run.status, run.succeeded, run.total);
+    if let Err(e) = db.insert_schedule_run(&run, RUN_HISTORY_SIZE).await {
+        warn!("Failed to store run of schedule {}: {}", schedule.id, e);
+    }
+    run
+}
+
+/// Runs due schedules in the background
+pub struct Scheduler {
+    db: Arc<DatabaseManager>,
+    executor: CommandExecutor,
+}
+
+impl Scheduler {
+    pub fn new(db: Arc<DatabaseManager>, executor: CommandExecutor) -> Self {
+        Self { db, executor }
+    }
+
+    pub fn start(self) {
+        tokio::spawn(async move {
+            info!("Scheduler started");
+            loop {
+                self.run_due().await;
+
+                let wait = match self.db.next_schedule_run().await {
+                    Ok(Some(at)) => (at - Utc::now()).to_std().unwrap_or(Duration::ZERO).min(MAX_SLEEP),
+                    Ok(None) => MAX_SLEEP,
+                    Err(e) => {
+                        warn!("Failed to read the next schedule run: {}", e);
+                        MAX_SLEEP
+                    }
+                };
+                tokio::select! {
+                    _ = tokio::time::sleep(wait) => {}
+                    _ = SCHEDULES_CHANGED.notified() => {}
+                }
+            }
+        });
+    }
+
+    /// Start every due schedule; the next run time is stored before the command goes out,
+    /// so a crash during a run does not repeat it
+    async fn run_due(&self) {
+        let now = Utc::now();
+        let due = self.db.list_due_schedules(now).await.unwrap_or_else(|e| {
+            warn!("Failed to load due schedules: {}", e);
+            Vec::new()
+        });
+
+        for schedule in due {
+            let Some(scheduled_for) = schedule.next_run_at else { continue };
+            let next_run = match ScheduleSpec::parse(&schedule.schedule) {
+                Ok(spec) => spec.next_run(now),
+                Err(e) => {
+                    warn!("Schedule {} has an invalid time specification, not scheduled again: {}", schedule.id, e);
+                    None
+                }
+            };
+            if let Err(e) = self.db.set_schedule_next_run(&schedule.id, next_run).await {
+                warn!("Failed to store the next run of schedule {}: {}", schedule.id, e);
+                continue;
+            }
+
+            let missed = now - scheduled_for > MISSED_AFTER;
+            if missed && !schedule.catch_up {
+                let run = ScheduleRun {
+                    id: uuid::Uuid::new_v4().to_string(),
+                    schedule_id: schedule.id.clone(),
+                    trigger: RunTrigger::Scheduled.as_str().to_string(),
+                    status: "skipped".to_string(),
+                    scheduled_for: Some(scheduled_for),
+                    started_at: now,
+                    finished_at: Some(now),
+                    total: 0,
+                    succeeded: 0,
+                    error: Some("Missed while the server was down (catch_up disabled)".to_string()),
+                    results: json!([]),
+                };
+                info!("Schedule '{}' missed its run at {}, skipped", schedule.name, scheduled_for);
+                if let Err(e) = self.db.insert_schedule_run(&run, RUN_HISTORY_SIZE).await {
+                    warn!("Failed to store run of schedule {}: {}", schedule.id, e);
+                }
+                continue;
+            }
+
+            let trigger = if missed { RunTrigger::CatchUp } else { RunTrigger::Scheduled };
+            let db = self.db.clone();
+            let executor = self.executor.clone();
+            tokio::spawn(async move {
+                execute(&db, &executor, &schedule, trigger, Some(scheduled_for)).await;
+            });
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_parsing_and_next_run() {
+        let at = |text: &str| chrono::NaiveDateTime::parse_from_str(text, "%Y-%m-%d %H:%M:%S").unwrap().and_utc();
+        // 2026-03-06 is a Friday
+        let friday_noon = at("2026-03-06 12:00:00");
+        let next = |spec: &str, after: DateTime<Utc>| ScheduleSpec::parse(spec).unwrap().next_after(&after).unwrap();
+
+        let daily = ScheduleSpec::parse("Every Day 18:00").unwrap();
+        assert_eq!(daily.to_string(), "every day at 18:00");
+        assert_eq!(daily.next_after(&friday_noon).unwrap(), at("2026-03-06 18:00:00"));
+        assert_eq!(daily.next_after(&at("2026-03-06 18:00:00")).unwrap(), at("2026-03-07 18:00:00"));
+
+        assert_eq!(next("every weekday at 7:30", friday_noon), at("2026-03-09 07:30:00"));
+        assert_eq!(ScheduleSpec::parse("every friday,mon at 08:00").unwrap().to_string(), "every mon,fri at 08:00");
+        assert_eq!(next("every sat,sunday at 10:00", friday_noon), at("2026-03-07 10:00:00"));
+        assert_eq!(next("every 90m", friday_noon), at("2026-03-06 13:30:00"));
+        assert_eq!(ScheduleSpec::parse("every 120m").unwrap().to_string(), "every 2h");
+
+        // Cron: steps, ranges, day-of-month OR day-of-week, Sunday as 7, leap day
+        assert_eq!(next("cron */15 * * * *", at("2026-03-06 12:07:30")), at("2026-03-06 12:15:00"));
+        assert_eq!(next("cron 0 9-17/4 * * mon-fri", friday_noon), at("2026-03-06 13:00:00"));
+        assert_eq!(next("cron 0 0 13 * 7", friday_noon), at("2026-03-08 00:00:00"));
+        assert_eq!(next("cron 0 0 29 2 *", friday_noon), at("2028-02-29 00:00:00"));
+
+        for invalid in ["every 30s", "every day at 24:00", "every someday at 10:00", "cron 0 18 * *", "cron 60 * * * *", "cron 0 0 31 2 x", "at noon"] {
+            assert!(ScheduleSpec::parse(invalid).is_err(), "{} should be rejected", invalid);
+        }
+        assert_eq!(ScheduleSpec::parse("cron 0 0 31 2 *").unwrap().next_after(&friday_noon), None);
+    }
+}
@@ -0,0 +1,364 @@
+// Scripts - user automation hooks on device events (POST /api/scripts)
+//
+// A script is a small Rhai program attached to one device. Every matching event of that
+// device runs it with the event as `event` map, e.g.
+//
+//     if event.variableName == "door" && event.variableValue == "1" {
+//         send_command("AA-BB-CC-DD-EE-02", #{ startOption: "light_on" });
+//     }
+//
+// Scripts run sandboxed: no file or module access, no eval, and limits on operations,
+// wall time, call depth and data sizes. `send_command` only records the command; the engine
+// sends it after the script finished, with the permissions of the script's owner. Output of
+// print()/debug(), sent commands and errors go to the per-script log (script_logs).
+
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use rhai::{Dynamic, Engine, EvalAltResult, Map, Scope, AST};
+use serde_json::{json, Value};
+use tokio::sync::{broadcast, Notify};
+use tracing::{info, warn};
+
+use crate::database::{DatabaseManager, Script, ScriptLog};
+use crate::device_store::{FeedEvent, SharedDeviceStore};
+use crate::schedules::CommandExecutor;
+
+/// Longest accepted script source
+pub const MAX_SOURCE_LENGTH: usize = 16 * 1024;
+/// Log lines kept per script
+pub const LOG_HISTORY_SIZE: usize = 200;
+/// Interpreter steps per run (loops, calls, expressions)
+const MAX_OPERATIONS: u64 = 200_000;
+/// Wall time per run, checked while the script makes progress
+const TIME_LIMIT: Duration = Duration::from_millis(100);
+/// Commands one run may send
+const MAX_COMMANDS_PER_RUN: usize = 10;
+/// print()/debug() lines kept per run
+const MAX_OUTPUT_LINES: usize = 20;
+/// Runs per script and minute; more events are dropped (protects against command/event loops)
+const MAX_RUNS_PER_MINUTE: u32 = 120;
+
+/// Event types a script can subscribe to (the "event" tag of DeviceEvent)
+pub const EVENT_TYPES: [&str; 12] = [
+    "DeviceVariableUpdate",
+    "DeviceStartOptions",
+    "DeviceChangeableVariables",
+    "DeviceUdpBroadcast",
+    "DeviceBinaryData",
+    "DeviceLog",
+    "DeviceConnectionStatus",
+    "DeviceLifecycle",
+    "DeviceDeviceInfo",
+    "DeviceAlert",
+    "DevicePresence",
+    "DeviceDiscovered",
+];
+
+/// Signalled by the API after scripts were created, changed or deleted
+static SCRIPTS_CHANGED: LazyLock<Notify> = LazyLock::new(Notify::new);
+
+/// Make the running engine reload and recompile the scripts
+pub fn scripts_changed() {
+    SCRIPTS_CHANGED.notify_one();
+}
+
+// ============================================================================
+// SANDBOX
+// ============================================================================
+
+/// Engine with the sandbox limits; nothing outside the script is reachable
+fn sandboxed_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(64 * 1024);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(1_000);
+    engine
+}
+
+/// Parse a script; the error names line and column
+pub fn compile(source: &str) -> Result<AST, String> {
+    if source.len() > MAX_SOURCE_LENGTH {
+        return Err(format!("Script must not be longer than {} bytes", MAX_SOURCE_LENGTH));
+    }
+    sandboxed_engine().compile(source).map_err(|e| e.to_string())
+}
+
+/// What one run of a script did
+#[derive(Debug, Default)]
+pub struct ScriptOutcome {
+    /// (level, message) in output order
+    pub logs: Vec<(String, String)>,
+    /// (device ID, command) recorded by send_command()
+    pub commands: Vec<(String, Value)>,
+    pub error: Option<String>,
+}
+
+/// Run a compiled script for one event (blocking; bounded by the sandbox limits)
+pub fn run(ast: &AST, event: &Value) -> ScriptOutcome {
+    let outcome = Arc::new(Mutex::new(ScriptOutcome::default()));
+    let mut engine = sandboxed_engine();
+
+    let started = Instant::now();
+    engine.on_progress(move |_| (started.elapsed() > TIME_LIMIT).then(|| Dynamic::from("time limit")));
+
+    let output = outcome.clone();
+    engine.on_print(move |text| {
+        let mut output = output.lock().unwrap();
+        if output.logs.len() < MAX_OUTPUT_LINES {
+            output.logs.push(("info".to_string(), text.to_string()));
+        }
+    });
+    let output = outcome.clone();
+    engine.on_debug(move |text, _, position| {
+        let mut output = output.lock().unwrap();
+        if output.logs.len() < MAX_OUTPUT_LINES {
+            output.logs.push(("debug".to_string(), format!("{} ({})", text, position)));
+        }
+    });
+
+    let output = outcome.clone();
+    engine.register_fn("send_command", move |device_id: &str, command: Map| -> Result<(), Box<EvalAltResult>> {
+        let mut output = output.lock().unwrap();
+        if output.commands.len() >= MAX_COMMANDS_PER_RUN {
+            return Err(format!("send_command: at most {} commands per run", MAX_COMMANDS_PER_RUN).into());
+        }
+        let command: Value = rhai::serde::from_dynamic(&Dynamic::from_map(command))?;
+        output.commands.push((device_id.to_string(), command));
+        Ok(())
+    });
+
+    let mut scope = Scope::new();
+    match rhai::serde::to_dynamic(event) {
+        Ok(event) => {
+            scope.push_constant("event", event);
+            if let Err(e) = engine.run_ast_with_scope(&mut scope, ast) {
+                let message = match *e {
+                    EvalAltResult::ErrorTerminated(_, position) => {
+                        format!("Stopped after {}ms time limit ({})", TIME_LIMIT.as_millis(), position)
+                    }
+                    EvalAltResult::ErrorTooManyOperations(position) => {
+                        format!("Stopped after {} operations ({})", MAX_OPERATIONS, position)
+                    }
+                    e => e.to_string(),
+                };
+                outcome.lock().unwrap().error = Some(message);
+            }
+        }
+        Err(e) => outcome.lock().unwrap().error = Some(format!("Event not convertible: {}", e)),
+    }
+
+    drop(engine);
+    Arc::try_unwrap(outcome).map(|m| m.into_inner().unwrap()).unwrap_or_default()
+}
+
+/// Script input: the serialized DeviceEvent plus deviceId and timestamp (ms)
+fn event_value(feed_event: &FeedEvent) -> Value {
+    let mut value = serde_json::to_value(&feed_event.event).unwrap_or_else(|_| json!({}));
+    value["deviceId"] = json!(feed_event.device_id);
+    value["timestamp"] = json!(feed_event.timestamp);
+    value
+}
+
+// ============================================================================
+// ENGINE
+// ============================================================================
+
+struct LoadedScript {
+    script: Script,
+    ast: Arc<AST>,
+    window_start: Instant,
+    runs_in_window: u32,
+}
+
+impl LoadedScript {
+    fn matches(&self, feed_event: &FeedEvent, event_type: &str) -> bool {
+        self.script.device_id == feed_event.device_id
+            && (self.script.events.is_empty() || self.script.events.iter().any(|e| e == event_type))
+    }
+
+    /// Count a run; false once the per-minute budget is used up
+    fn admit(&mut self, now: Instant) -> bool {
+        if now.duration_since(self.window_start) >= Duration::from_secs(60) {
+            self.window_start = now;
+            self.runs_in_window = 0;
+        }
+        self.runs_in_window += 1;
+        self.runs_in_window <= MAX_RUNS_PER_MINUTE
+    }
+}
+
+/// Runs enabled scripts on the device event feed
+pub struct ScriptEngine {
+    db: Arc<DatabaseManager>,
+    device_store: SharedDeviceStore,
+    executor: CommandExecutor,
+}
+
+impl ScriptEngine {
+    pub fn new(db: Arc<DatabaseManager>, device_store: SharedDeviceStore, executor: CommandExecutor) -> Self {
+        Self { db, device_store, executor }
+    }
+
+    pub fn start(self) {
+        let mut feed = self.device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut scripts = self.load_scripts().await;
+            info!("Script engine started with {} script(s)", scripts.len());
+
+            loop {
+                tokio::select! {
+                    received = feed.recv() => match received {
+                        Ok(feed_event) => self.handle_event(&mut scripts, &feed_event).await,
+                        Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                            warn!("Script engine lagged behind the event feed, skipped {} events", skipped);
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    },
+                    _ = SCRIPTS_CHANGED.notified() => {
+                        scripts = self.load_scripts().await;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Compile all enabled scripts; scripts that no longer compile are logged and skipped
+    async fn load_scripts(&self) -> Vec<LoadedScript> {
+        let stored = match self.db.list_enabled_scripts().await {
+            Ok(stored) => stored,
+            Err(e) => {
+                warn!("Failed to load scripts: {}", e);
+                return Vec::new();
+            }
+        };
+
+        let mut scripts = Vec::new();
+        for script in stored {
+            match compile(&script.source) {
+                Ok(ast) => scripts.push(LoadedScript { script, ast: Arc::new(ast), window_start: Instant::now(), runs_in_window: 0 }),
+                Err(e) => {
+                    warn!("Skipping script {} that does not compile: {}", script.id, e);
+                    self.log(&script.id, "error", format!("Not loaded: {}", e)).await;
+                }
+            }
+        }
+        scripts
+    }
+
+    async fn handle_event(&self, scripts: &mut [LoadedScript], feed_event: &FeedEvent) {
+        let event = event_value(feed_event);
+        let event_type = event["event"].as_str().unwrap_or_default().to_string();
+        let now = Instant::now();
+
+        for loaded in scripts.iter_mut().filter(|loaded| loaded.matches(feed_event, &event_type)) {
+            if !loaded.admit(now) {
+                if loaded.runs_in_window == MAX_RUNS_PER_MINUTE + 1 {
+                    self.log(&loaded.script.id, "warn", format!("More than {} runs per minute, skipping events", MAX_RUNS_PER_MINUTE)).await;
+                }
+                continue;
+            }
+
+            let ast = loaded.ast.clone();
+            let input = event.clone();
+            let outcome = tokio::task::spawn_blocking(move || run(&ast, &input)).await.unwrap_or_else(|e| ScriptOutcome {
+                error: Some(format!("Script crashed: {}", e)),
+                ..Default::default()
+            });
+            self.finish(&loaded.script, &event_type, outcome).await;
+        }
+    }
+
+    /// Store the output of a run and send its commands in the background
+    async fn finish(&self, script: &Script, event_type: &str, outcome: ScriptOutcome) {
+        for (level, message) in outcome.logs {
+            self.log(&script.id, &level, message).await;
+        }
+        if let Some(error) = outcome.error {
+            self.log(&script.id, "error", format!("{} on {}", error, event_type)).await;
+        }
+
+        for (device_id, command) in outcome.commands {
+            let db = self.db.clone();
+            let executor = self.executor.clone();
+            let script_id = script.id.clone();
+            let owner_id = script.owner_id.clone();
+            tokio::spawn(async move {
+                let report = executor(vec![device_id.clone()], command.clone(), owner_id).await;
+                let result = &report["results"][0];
+                let (level, message) = if result["success"] == true {
+                    ("info", format!("Command {} sent to {}", command, device_id))
+                } else {
+                    ("error", format!("Command {} to {} failed: {}", command, device_id, result["message"].as_str().unwrap_or("unknown error")))
+                };
+                insert_log(&db, &script_id, level, message).await;
+            });
+        }
+    }
+
+    async fn log(&self, script_id: &str, level: &str, message: String) {
+        insert_log(&self.db, script_id, level, message).await;
+    }
+}
+
+async fn insert_log(db: &DatabaseManager, script_id: &str, level: &str, message: String) {
+    let log = ScriptLog { id: 0, script_id: script_id.to_string(), timestamp: Utc::now(), level: level.to_string(), message };
+    if let Err(e) = db.insert_script_log(&log, LOG_HISTORY_SIZE).await {
+        warn!("Failed to store log of script {}: {}", script_id, e);
+    }
+}
+
+/// Outcome of a dry run as API response (POST /api/scripts/:id/test); nothing is sent
+pub fn outcome_json(outcome: &ScriptOutcome) -> Value {
+    json!({
+        "logs": outcome.logs.iter().map(|(level, message)| json!({ "level": level, "message": message })).collect::<Vec<_>>(),
+        "commands": outcome.commands.iter().map(|(device_id, command)| json!({ "deviceId": device_id, "command": command })).collect::<Vec<_>>(),
+        "error": outcome.error,
+    })
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::events::DeviceEvent;
+
+    #[test]
+    fn test_script_sandbox_and_commands() {
+        let feed_event = FeedEvent {
+            device_id: "AA-01".to_string(),
+            event: DeviceEvent::device_variable_update("AA-01".to_string(), "door".to_string(), "1".to_string()),
+            timestamp: 1000,
+        };
+        let event = event_value(&feed_event);
+
+        let ast = compile(r#"
+            if event.variableName == "door" && event.variableValue == "1" {
+                print(`door opened on ${event.deviceId}`);
+                send_command("AA-02", #{ startOption: "light_on", level: 3 });
+            }
+        "#).unwrap();
+        let outcome = run(&ast, &event);
+        assert_eq!(outcome.error, None);
+        assert_eq!(outcome.logs, vec![("info".to_string(), "door opened on AA-01".to_string())]);
+        assert_eq!(outcome.commands, vec![("AA-02".to_string(), json!({"startOption": "light_on", "level": 3}))]);
+
+        // Endless loops hit the operation or time limit, eval and modules are unavailable
+        let error = run(&compile("loop { }").unwrap(), &event).error.unwrap();
+        assert!(error.starts_with("Stopped after"), "{}", error);
+        assert!(compile("eval(\"1\")").is_err());
+        assert!(run(&compile("import \"os\" as os;").unwrap(), &event).error.is_some());
+        assert!(run(&compile("for i in 0..20 { send_command(\"AA-02\", #{}); }").unwrap(), &event).error.is_some());
+        assert!(compile("let x = ;").is_err());
+        assert!(compile(&"x".repeat(MAX_SOURCE_LENGTH + 1)).is_err());
+    }
+}
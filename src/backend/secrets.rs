@@ -0,0 +1,271 @@
+// Secrets at rest - AES-256-GCM encryption of credentials stored in SQLite
+//
+// Device identity secrets and webhook signing keys have to be known in clear text to the server
+// (it computes HMACs with them), so they cannot be hashed like passwords or enrollment tokens.
+// Instead they are sealed with a server key before they are written:
+//
+//   enc:v1:<key id>:<base64(nonce || ciphertext || tag)>
+//
+// The associated data is the column the value belongs to, so a value copied into another column
+// does not open. Keys come from [secrets] key (SECRETS_KEY) or the key file, one hex key per line
+// with the active key first; the file is created on first start. Older keys stay in the file to
+// open values sealed before a rotation: POST /api/admin/secrets/rotate puts a new key in front and
+// re-seals every stored value, and at startup all values not sealed with the active key (also
+// plaintext rows from before this module) are re-sealed. Without a configured keyring (tests) values
+// are stored as given.
+
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::config::SecretsConfig;
+
+const PREFIX: &str = "enc:v1:";
+
+/// Length of a key in bytes (AES-256)
+pub const KEY_LEN: usize = 32;
+
+/// Columns holding sealed values, used as associated data
+pub const DEVICE_IDENTITY_SECRET: &str = "device_identity_secrets.secret";
+pub const WEBHOOK_SECRET: &str = "webhooks.secret";
+
+// Replaced on rotation; None until configured at startup
+static KEYRING: RwLock<Option<Keyring>> = RwLock::new(None);
+
+struct SecretKey {
+    id: String,
+    hex: String,
+    key: LessSafeKey,
+}
+
+impl SecretKey {
+    fn from_hex(hex_key: &str) -> Result<Self, String> {
+        let bytes = hex::decode(hex_key.trim()).map_err(|_| "secret key must be hex".to_string())?;
+        if bytes.len() != KEY_LEN {
+            return Err(format!("secret key must be {} hex characters", KEY_LEN * 2));
+        }
+        let key = UnboundKey::new(&AES_256_GCM, &bytes).map_err(|_| "invalid secret key".to_string())?;
+        Ok(Self {
+            id: hex::encode(&Sha256::digest(&bytes)[..4]),
+            hex: hex_key.trim().to_lowercase(),
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    fn generate() -> Result<Self, String> {
+        let mut bytes = [0u8; KEY_LEN];
+        SystemRandom::new().fill(&mut bytes).map_err(|_| "no system randomness for a secret key".to_string())?;
+        Self::from_hex(&hex::encode(bytes))
+    }
+}
+
+/// Active key (first) and retired keys still accepted for opening
+pub struct Keyring {
+    keys: Vec<SecretKey>,
+    /// Key file new keys are written to; None if the active key comes from the configuration
+    key_file: Option<PathBuf>,
+}
+
+impl Keyring {
+    /// Keys of [secrets]: the configured key, then the key file (created with a new key if missing)
+    pub fn load(config: &SecretsConfig) -> Result<Self, String> {
+        let path = Path::new(&config.key_file);
+        let mut keys = Vec::new();
+        if !config.key.is_empty() {
+            keys.push(SecretKey::from_hex(&config.key)?);
+        }
+        if path.is_file() {
+            let content = std::fs::read_to_string(path).map_err(|e| format!("Cannot read {}: {}", config.key_file, e))?;
+            for line in content.lines().map(str::trim).filter(|line| !line.is_empty() && !line.starts_with('#')) {
+                let key = SecretKey::from_hex(line).map_err(|e| format!("{}: {}", config.key_file, e))?;
+                if !keys.iter().any(|known| known.id == key.id) {
+                    keys.push(key);
+                }
+            }
+        } else if keys.is_empty() {
+            let key = SecretKey::generate()?;
+            write_key_file(path, std::slice::from_ref(&key))?;
+            tracing::info!("Created secret key file {} (key {})", config.key_file, key.id);
+            keys.push(key);
+        }
+        if keys.is_empty() {
+            return Err(format!("{} contains no key", config.key_file));
+        }
+        Ok(Self { keys, key_file: config.key.is_empty().then(|| path.to_path_buf()) })
+    }
+
+    fn active(&self) -> &SecretKey {
+        &self.keys[0]
+    }
+
+    fn seal(&self, context: &str, plaintext: &str) -> Result<String, String> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new().fill(&mut nonce).map_err(|_| "no system randomness for a nonce".to_string())?;
+        let mut data = plaintext.as_bytes().to_vec();
+        self.active().key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::from(context.as_bytes()), &mut data)
+            .map_err(|_| "encryption failed".to_string())?;
+        let mut sealed = nonce.to_vec();
+        sealed.extend_from_slice(&data);
+        Ok(format!("{}{}:{}", PREFIX, self.active().id, base64::engine::general_purpose::STANDARD.encode(sealed)))
+    }
+
+    fn open(&self, context: &str, stored: &str) -> Result<String, String> {
+        let Some(sealed) = stored.strip_prefix(PREFIX) else {
+            return Ok(stored.to_string());
+        };
+        let (key_id, payload) = sealed.split_once(':').ok_or("malformed sealed secret")?;
+        let key = self.keys.iter().find(|key| key.id == key_id)
+            .ok_or_else(|| format!("secret was sealed with unknown key {}", key_id))?;
+        let mut data = base64::engine::general_purpose::STANDARD.decode(payload).map_err(|_| "malformed sealed secret".to_string())?;
+        if data.len() < NONCE_LEN {
+            return Err("malformed sealed secret".to_string());
+        }
+        let nonce = Nonce::try_assume_unique_for_key(&data[..NONCE_LEN]).map_err(|_| "malformed sealed secret".to_string())?;
+        let plaintext = key.key.open_in_place(nonce, Aad::from(context.as_bytes()), &mut data[NONCE_LEN..])
+            .map_err(|_| format!("secret does not open with key {}", key_id))?;
+        String::from_utf8(plaintext.to_vec()).map_err(|_| "sealed secret is not UTF-8".to_string())
+    }
+
+    fn is_current(&self, stored: &str) -> bool {
+        stored.strip_prefix(PREFIX)
+            .and_then(|sealed| sealed.split_once(':'))
+            .is_some_and(|(key_id, _)| key_id == self.active().id)
+    }
+}
+
+fn write_key_file(path: &Path, keys: &[SecretKey]) -> Result<(), String> {
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+    }
+    let mut content = String::from("# Secret keys for values stored in the database; the first one is active\n");
+    for key in keys {
+        content.push_str(&key.hex);
+        content.push('\n');
+    }
+    // Written next to the file and renamed, so a crash never leaves a file without the active key
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, content).map_err(|e| format!("Cannot write {}: {}", tmp.display(), e))?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(0o600))
+            .map_err(|e| format!("Cannot restrict {}: {}", tmp.display(), e))?;
+    }
+    std::fs::rename(&tmp, path).map_err(|e| format!("Cannot write {}: {}", path.display(), e))
+}
+
+pub fn configure(keyring: Keyring) {
+    *KEYRING.write().unwrap_or_else(|e| e.into_inner()) = Some(keyring);
+}
+
+/// Value to store for `plaintext` in the column `context`
+pub fn seal(context: &str, plaintext: &str) -> Result<String, String> {
+    match KEYRING.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(keyring) => keyring.seal(context, plaintext),
+        None => Ok(plaintext.to_string()),
+    }
+}
+
+/// Clear text of a stored value; values from before encryption are returned as they are
+pub fn open(context: &str, stored: &str) -> Result<String, String> {
+    match KEYRING.read().unwrap_or_else(|e| e.into_inner()).as_ref() {
+        Some(keyring) => keyring.open(context, stored),
+        None if stored.starts_with(PREFIX) => Err("secret is encrypted but no secret key is configured".to_string()),
+        None => Ok(stored.to_string()),
+    }
+}
+
+/// Whether a stored value should be sealed again (plaintext or sealed with a retired key)
+pub fn needs_reseal(stored: &str) -> bool {
+    KEYRING.read().unwrap_or_else(|e| e.into_inner()).as_ref().is_some_and(|keyring| !keyring.is_current(stored))
+}
+
+/// Put a new active key in front of the key file; returns its ID.
+/// Stored values have to be re-sealed afterwards (DatabaseManager::reseal_secrets).
+pub fn rotate() -> Result<String, String> {
+    let mut guard = KEYRING.write().unwrap_or_else(|e| e.into_inner());
+    let keyring = guard.as_mut().ok_or("no secret key is configured")?;
+    let Some(key_file) = keyring.key_file.clone() else {
+        return Err("the active key comes from [secrets] key (SECRETS_KEY): set a new one there and move the old key into the key file".to_string());
+    };
+    let key = SecretKey::generate()?;
+    let id = key.id.clone();
+    keyring.keys.insert(0, key);
+    if let Err(e) = write_key_file(&key_file, &keyring.keys) {
+        keyring.keys.remove(0);
+        return Err(e);
+    }
+    tracing::info!("Rotated secret key, new active key {}", id);
+    Ok(id)
+}
+
+/// Reported by GET /api/admin/secrets
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SecretsStatus {
+    pub encrypted: bool,
+    pub active_key_id: Option<String>,
+    /// Older keys kept to open values sealed before a rotation
+    pub retired_keys: usize,
+    /// Whether POST /api/admin/secrets/rotate can write a new key
+    pub rotatable: bool,
+}
+
+pub fn status() -> SecretsStatus {
+    let guard = KEYRING.read().unwrap_or_else(|e| e.into_inner());
+    match guard.as_ref() {
+        Some(keyring) => SecretsStatus {
+            encrypted: true,
+            active_key_id: Some(keyring.active().id.clone()),
+            retired_keys: keyring.keys.len() - 1,
+            rotatable: keyring.key_file.is_some(),
+        },
+        None => SecretsStatus { encrypted: false, active_key_id: None, retired_keys: 0, rotatable: false },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_keyring_seal_open_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("secrets-test-{}", uuid::Uuid::new_v4()));
+        let config = SecretsConfig { key_file: dir.join("secrets.key").to_string_lossy().to_string(), key: String::new() };
+
+        // Created on first load, the same key on the next
+        let keyring = Keyring::load(&config).unwrap();
+        assert_eq!(Keyring::load(&config).unwrap().active().id, keyring.active().id);
+
+        let sealed = keyring.seal(WEBHOOK_SECRET, "s3cret").unwrap();
+        assert!(sealed.starts_with(PREFIX) && !sealed.contains("s3cret"));
+        assert_ne!(keyring.seal(WEBHOOK_SECRET, "s3cret").unwrap(), sealed, "fresh nonce per value");
+        assert_eq!(keyring.open(WEBHOOK_SECRET, &sealed).unwrap(), "s3cret");
+        assert!(keyring.is_current(&sealed));
+
+        // Bound to its column; tampering and legacy plaintext
+        assert!(keyring.open(DEVICE_IDENTITY_SECRET, &sealed).is_err());
+        let mut tampered = sealed.clone().into_bytes();
+        let last = tampered.len() - 3;
+        tampered[last] = if tampered[last] == b'A' { b'B' } else { b'A' };
+        assert!(keyring.open(WEBHOOK_SECRET, &String::from_utf8(tampered).unwrap()).is_err());
+        assert_eq!(keyring.open(WEBHOOK_SECRET, "legacy").unwrap(), "legacy");
+        assert!(!keyring.is_current("legacy"));
+
+        // A new active key from the configuration still opens values of the file key
+        let rotated = Keyring::load(&SecretsConfig { key: hex::encode([7u8; KEY_LEN]), ..config.clone() }).unwrap();
+        assert_eq!(rotated.keys.len(), 2);
+        assert!(rotated.key_file.is_none());
+        assert!(!rotated.is_current(&sealed));
+        assert_eq!(rotated.open(WEBHOOK_SECRET, &sealed).unwrap(), "s3cret");
+
+        assert!(Keyring::load(&SecretsConfig { key: "abc".to_string(), ..config }).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,196 @@
+// Service integration - systemd readiness, socket activation, watchdog, PID file and shutdown
+//
+// Running as a systemd service (Type=notify) the server reports READY=1 once it accepts
+// requests and STOPPING=1 on SIGTERM, and pings the watchdog when WatchdogSec= is set.
+// With a .socket unit the listening sockets are passed in (LISTEN_FDS, first the HTTP, then
+// the HTTPS socket) instead of being bound here. Outside of systemd (no NOTIFY_SOCKET, no
+// LISTEN_FDS) all of this does nothing. [server] pid_file writes a PID file for other init
+// systems. Only available on Unix; elsewhere the functions are no-ops.
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// First file descriptor passed by socket activation (SD_LISTEN_FDS_START)
+#[cfg(unix)]
+const LISTEN_FDS_START: i32 = 3;
+
+/// Send a state change to the service manager, e.g. "READY=1"; false without NOTIFY_SOCKET
+pub fn notify(state: &str) -> bool {
+    let Ok(socket_path) = std::env::var("NOTIFY_SOCKET") else {
+        return false;
+    };
+    match send_notification(&socket_path, state) {
+        Ok(()) => true,
+        Err(e) => {
+            tracing::warn!("Failed to notify the service manager ({}): {}", socket_path, e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send_notification(socket_path: &str, state: &str) -> std::io::Result<()> {
+    use std::os::unix::net::UnixDatagram;
+    let socket = UnixDatagram::unbound()?;
+    match socket_path.strip_prefix('@') {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let address = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+            socket.send_to_addr(state.as_bytes(), &address)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), socket_path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send_notification(_socket_path: &str, _state: &str) -> std::io::Result<()> {
+    Ok(())
+}
+
+/// Startup finished: report readiness and start pinging the watchdog if one is configured
+pub fn ready(status: &str) {
+    if notify(&format!("READY=1\nSTATUS={}\nMAINPID={}", status, std::process::id())) {
+        tracing::info!("Reported readiness to systemd");
+    }
+    if let Some(interval) = watchdog_interval() {
+        tracing::info!("Pinging the systemd watchdog every {:?}", interval);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                ticker.tick().await;
+                notify("WATCHDOG=1");
+            }
+        });
+    }
+}
+
+/// Half of WatchdogSec= (WATCHDOG_USEC), if the watchdog is meant for this process
+fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse::<u32>().ok()? != std::process::id() {
+            return None;
+        }
+    }
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
+
+/// Sockets passed by systemd socket activation, in the order of the .socket unit
+#[cfg(unix)]
+pub fn activated_listeners() -> Vec<std::net::TcpListener> {
+    use std::os::unix::io::FromRawFd;
+
+    let for_us = std::env::var("LISTEN_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) == Some(std::process::id());
+    let count: i32 = std::env::var("LISTEN_FDS").ok().and_then(|count| count.parse().ok()).unwrap_or(0);
+    // Not inherited by child processes
+    std::env::remove_var("LISTEN_PID");
+    std::env::remove_var("LISTEN_FDS");
+    std::env::remove_var("LISTEN_FDNAMES");
+    if !for_us || count <= 0 {
+        return Vec::new();
+    }
+
+    (LISTEN_FDS_START..LISTEN_FDS_START + count).filter_map(|fd| {
+        // SAFETY: systemd passes these descriptors to this process (LISTEN_PID), nothing else owns them
+        let listener = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        match listener.set_nonblocking(true) {
+            Ok(()) => Some(listener),
+            Err(e) => {
+                tracing::warn!("Ignoring activated socket fd {}: {}", fd, e);
+                None
+            }
+        }
+    }).collect()
+}
+
+#[cfg(not(unix))]
+pub fn activated_listeners() -> Vec<std::net::TcpListener> {
+    Vec::new()
+}
+
+/// PID file of the running server; removed on shutdown
+pub struct PidFile {
+    path: PathBuf,
+}
+
+impl PidFile {
+    /// Write the PID; fails if the file names another running process
+    pub fn create(path: &Path) -> Result<Self, String> {
+        if let Some(pid) = std::fs::read_to_string(path).ok().and_then(|content| content.trim().parse::<u32>().ok()) {
+            if pid != std::process::id() && process_running(pid) {
+                return Err(format!("{} belongs to the running process {}", path.display(), pid));
+            }
+        }
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            std::fs::create_dir_all(dir).map_err(|e| format!("Cannot create {}: {}", dir.display(), e))?;
+        }
+        std::fs::write(path, format!("{}\n", std::process::id())).map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+        Ok(Self { path: path.to_path_buf() })
+    }
+
+    pub fn remove(&self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(unix)]
+fn process_running(pid: u32) -> bool {
+    // SAFETY: signal 0 only checks that the process exists
+    unsafe { libc::kill(pid as libc::pid_t, 0) == 0 }
+}
+
+#[cfg(not(unix))]
+fn process_running(_pid: u32) -> bool {
+    false
+}
+
+/// Resolves on SIGTERM (systemctl stop, docker stop) or Ctrl+C
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = terminate.recv() => {}
+                    _ = tokio::signal::ctrl_c() => {}
+                }
+                return;
+            }
+            Err(e) => tracing::warn!("Cannot listen for SIGTERM: {}", e),
+        }
+    }
+    let _ = tokio::signal::ctrl_c().await;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pid_file_and_notify_socket() {
+        let dir = std::env::temp_dir().join(format!("service-test-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("run").join("server.pid");
+        let pid_file = PidFile::create(&path).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().trim(), std::process::id().to_string());
+        // Our own PID in the file does not block a restart of the same process
+        assert!(PidFile::create(&path).is_ok());
+        pid_file.remove();
+        assert!(!path.exists());
+
+        #[cfg(unix)]
+        {
+            let socket_path = dir.join("notify.sock");
+            let receiver = std::os::unix::net::UnixDatagram::bind(&socket_path).unwrap();
+            send_notification(socket_path.to_str().unwrap(), "READY=1").unwrap();
+            let mut buffer = [0u8; 64];
+            let n = receiver.recv(&mut buffer).unwrap();
+            assert_eq!(&buffer[..n], b"READY=1");
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,130 @@
+// Sharded string-keyed map for the in-memory event store
+//
+// A single RwLock<HashMap> around the data of all devices means one chatty device's write lock
+// stalls broadcasts to every other device. ShardedMap spreads the keys over N independently
+// locked HashMaps (shard chosen by key hash), so writers only block readers of the same shard.
+// Whole-map operations (stats, cleanup) visit the shards one after another and never hold two
+// shard locks at once.
+
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// Shard count of the event store maps (power of two)
+pub const DEFAULT_SHARDS: usize = 32;
+
+#[derive(Debug)]
+pub struct ShardedMap<V> {
+    shards: Box<[RwLock<HashMap<String, V>>]>,
+    hasher: RandomState,
+}
+
+impl<V> ShardedMap<V> {
+    pub fn new(shards: usize) -> Self {
+        let shards = shards.max(1);
+        Self {
+            shards: (0..shards).map(|_| RwLock::new(HashMap::new())).collect(),
+            hasher: RandomState::new(),
+        }
+    }
+
+    fn shard(&self, key: &str) -> &RwLock<HashMap<String, V>> {
+        let index = self.hasher.hash_one(key) as usize % self.shards.len();
+        &self.shards[index]
+    }
+
+    /// Read lock on the shard holding `key`
+    pub async fn read(&self, key: &str) -> RwLockReadGuard<'_, HashMap<String, V>> {
+        self.shard(key).read().await
+    }
+
+    /// Write lock on the shard holding `key`
+    pub async fn write(&self, key: &str) -> RwLockWriteGuard<'_, HashMap<String, V>> {
+        self.shard(key).write().await
+    }
+
+    /// All shards, for whole-map scans (lock one at a time)
+    pub fn shards(&self) -> impl Iterator<Item = &RwLock<HashMap<String, V>>> {
+        self.shards.iter()
+    }
+
+    pub async fn insert(&self, key: String, value: V) -> Option<V> {
+        self.write(&key).await.insert(key, value)
+    }
+
+    pub async fn remove(&self, key: &str) -> Option<V> {
+        self.write(key).await.remove(key)
+    }
+
+    pub async fn contains_key(&self, key: &str) -> bool {
+        self.read(key).await.contains_key(key)
+    }
+
+    pub async fn get_cloned(&self, key: &str) -> Option<V>
+    where
+        V: Clone,
+    {
+        self.read(key).await.get(key).cloned()
+    }
+
+    pub async fn keys(&self) -> Vec<String> {
+        let mut keys = Vec::new();
+        for shard in self.shards() {
+            keys.extend(shard.read().await.keys().cloned());
+        }
+        keys
+    }
+
+    pub async fn len(&self) -> usize {
+        let mut len = 0;
+        for shard in self.shards() {
+            len += shard.read().await.len();
+        }
+        len
+    }
+
+    pub async fn is_empty(&self) -> bool {
+        self.len().await == 0
+    }
+
+    pub async fn retain(&self, mut keep: impl FnMut(&String, &mut V) -> bool) {
+        for shard in self.shards() {
+            shard.write().await.retain(&mut keep);
+        }
+    }
+}
+
+impl<V> Default for ShardedMap<V> {
+    fn default() -> Self {
+        Self::new(DEFAULT_SHARDS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_keys_spread_over_independent_shards() {
+        let map: ShardedMap<usize> = ShardedMap::new(8);
+        for i in 0..100 {
+            map.insert(format!("dev-{}", i), i).await;
+        }
+        assert_eq!(map.len().await, 100);
+        assert_eq!(map.get_cloned("dev-42").await, Some(42));
+        assert!(map.shards().filter(|shard| shard.try_read().is_ok_and(|s| !s.is_empty())).count() > 1);
+
+        // A held write lock only blocks its own shard
+        let busy = map.write("dev-1").await;
+        let other = (0..100).map(|i| format!("dev-{}", i)).find(|key| map.shard(key).try_read().is_ok()).unwrap();
+        assert!(map.read(&other).await.contains_key(&other));
+        drop(busy);
+
+        map.retain(|_, value| *value % 2 == 0).await;
+        assert_eq!(map.len().await, 50);
+        assert_eq!(map.remove("dev-42").await, Some(42));
+        assert!(!map.contains_key("dev-42").await);
+    }
+}
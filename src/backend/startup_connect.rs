@@ -0,0 +1,195 @@
+// Startup auto-connect of the devices stored in the database
+//
+// At server start every stored TCP device is registered with the DeviceManager and connected
+// concurrently (bounded by devices.max_concurrent_connects). Stored addresses may be host names
+// (e.g. "esp32-kitchen.local"); they are resolved first, also concurrently. UART devices (reached
+// over the serial port) and devices in maintenance mode are not connected. Progress is logged per
+// device, the result is published as StartupConnectSummary event on the "system" channel.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use tracing::{info, warn};
+
+use crate::database::Device;
+use crate::device_manager::DeviceManager;
+use crate::device_store::SharedDeviceStore;
+use crate::device_types::DeviceConfig;
+use crate::events::DeviceEvent;
+
+/// Upper bound for resolving one host name (mDNS answers can be slow or missing)
+const RESOLVE_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Port used for name resolution and the TCP connect (ESP32 firmware default)
+const DEVICE_PORT: u16 = 3232;
+
+/// What happens with a stored device at startup
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Plan {
+    /// Resolve this address, then register and connect
+    Connect { address: String },
+    /// Register only (address known, but the device must not be contacted)
+    RegisterOnly { address: String, reason: &'static str },
+    Skip { reason: &'static str },
+}
+
+fn plan(device: &Device) -> Plan {
+    if device.connection_type == "uart" {
+        return Plan::Skip { reason: "UART device, reached over the serial port" };
+    }
+    let Some(address) = device.ip_address.clone().filter(|a| !a.trim().is_empty()) else {
+        return Plan::Skip { reason: "no known address, waiting for discovery" };
+    };
+    if device.maintenance_mode {
+        return Plan::RegisterOnly { address, reason: "maintenance mode" };
+    }
+    Plan::Connect { address }
+}
+
+/// IP literal or host name (including mDNS .local names) to an IP address
+async fn resolve_address(address: &str) -> Option<IpAddr> {
+    let address = address.trim();
+    if let Ok(ip) = address.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    match tokio::time::timeout(RESOLVE_TIMEOUT, tokio::net::lookup_host((address, DEVICE_PORT))).await {
+        Ok(Ok(mut addrs)) => addrs.next().map(|addr| addr.ip()),
+        Ok(Err(e)) => {
+            warn!("Startup connect: cannot resolve {}: {}", address, e);
+            None
+        }
+        Err(_) => {
+            warn!("Startup connect: resolving {} timed out", address);
+            None
+        }
+    }
+}
+
+/// Counts of one startup run
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StartupConnectSummary {
+    pub total: usize,
+    pub connected: usize,
+    pub failed: usize,
+    pub skipped: usize,
+}
+
+/// Register all stored devices with the manager and connect the reachable ones concurrently
+pub async fn connect_stored_devices(
+    devices: Vec<Device>,
+    device_manager: Arc<DeviceManager>,
+    device_store: SharedDeviceStore,
+) -> StartupConnectSummary {
+    let started = Instant::now();
+    let mut summary = StartupConnectSummary { total: devices.len(), ..Default::default() };
+    info!("Startup connect: {} stored device(s)", summary.total);
+
+    // Resolve all addresses in parallel; a slow .local name must not hold up the others
+    let resolved = futures::future::join_all(devices.iter().map(|device| async move {
+        let plan = plan(device);
+        let ip = match &plan {
+            Plan::Connect { address } | Plan::RegisterOnly { address, .. } => resolve_address(address).await,
+            Plan::Skip { .. } => None,
+        };
+        (device, plan, ip)
+    })).await;
+
+    let mut to_connect = Vec::new();
+    for (device, plan, ip) in resolved {
+        let device_id = device.mac_address.clone();
+        let ip = match (plan, ip) {
+            (Plan::Skip { reason }, _) => {
+                info!("Startup connect: skipping {} ({})", device_id, reason);
+                summary.skipped += 1;
+                continue;
+            }
+            (Plan::Connect { .. } | Plan::RegisterOnly { .. }, None) => {
+                warn!("Startup connect: no usable address for {}", device_id);
+                summary.failed += 1;
+                continue;
+            }
+            (Plan::RegisterOnly { reason, .. }, Some(ip)) => {
+                info!("Startup connect: registering {} without connecting ({})", device_id, reason);
+                summary.skipped += 1;
+                ip
+            }
+            (Plan::Connect { .. }, Some(ip)) => {
+                to_connect.push(device_id.clone());
+                ip
+            }
+        };
+
+        let mut config = DeviceConfig::new(device_id.clone(), ip, DEVICE_PORT, DEVICE_PORT);
+        config.device_name = device.alias.clone().unwrap_or_else(|| device.name.clone());
+        if let Err(e) = device_manager.add_device(config).await {
+            warn!("Startup connect: failed to register {}: {}", device_id, e);
+            to_connect.retain(|id| id != &device_id);
+            summary.failed += 1;
+        }
+    }
+
+    let pending = to_connect.len();
+    let mut done = 0;
+    let results = device_manager.connect_devices(&to_connect, |device_id, result| {
+        done += 1;
+        match result {
+            Ok(()) => info!("Startup connect [{}/{}]: {} connected", done, pending, device_id),
+            Err(e) => warn!("Startup connect [{}/{}]: {} failed: {}", done, pending, device_id, e),
+        }
+    }).await;
+    for (_, result) in results {
+        match result {
+            Ok(()) => summary.connected += 1,
+            Err(_) => summary.failed += 1,
+        }
+    }
+
+    let duration_ms = started.elapsed().as_millis() as u64;
+    info!(
+        "Startup connect finished in {} ms: {} connected, {} failed, {} skipped (of {})",
+        duration_ms, summary.connected, summary.failed, summary.skipped, summary.total
+    );
+    let event = DeviceEvent::startup_connect_summary(summary.total, summary.connected, summary.failed, summary.skipped, duration_ms);
+    if let Err(e) = device_store.add_event("system".to_string(), event, "device_system".to_string(), "startup_connect".to_string()).await {
+        warn!("Failed to publish startup connect summary: {}", e);
+    }
+
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::device_store::create_shared_store;
+
+    fn stored(mac: &str, ip: Option<&str>, connection_type: &str, maintenance: bool) -> Device {
+        let mut device = Device::new(format!("Device {}", mac), "owner".to_string(), mac.to_string());
+        device.ip_address = ip.map(str::to_string);
+        device.connection_type = connection_type.to_string();
+        device.maintenance_mode = maintenance;
+        device
+    }
+
+    #[tokio::test]
+    async fn test_stored_devices_are_planned_and_summarized() {
+        assert_eq!(plan(&stored("AA-01", None, "tcp", false)), Plan::Skip { reason: "no known address, waiting for discovery" });
+        assert!(matches!(plan(&stored("AA-02", Some("10.0.0.2"), "uart", false)), Plan::Skip { .. }));
+        assert!(matches!(plan(&stored("AA-03", Some("10.0.0.3"), "tcp", true)), Plan::RegisterOnly { .. }));
+        assert_eq!(plan(&stored("AA-04", Some("esp32.local"), "tcp", false)), Plan::Connect { address: "esp32.local".to_string() });
+        assert_eq!(resolve_address(" 192.168.1.9 ").await, Some("192.168.1.9".parse().unwrap()));
+
+        // Nothing here is contacted: no address, UART, and maintenance (registered only)
+        let store = create_shared_store();
+        let manager = Arc::new(DeviceManager::new(store.clone()));
+        let devices = vec![
+            stored("AA-01", None, "tcp", false),
+            stored("AA-02", Some("10.0.0.2"), "uart", false),
+            stored("AA-03", Some("10.0.0.3"), "tcp", true),
+        ];
+        let summary = connect_stored_devices(devices, manager.clone(), store).await;
+        assert_eq!(summary, StartupConnectSummary { total: 3, connected: 0, failed: 0, skipped: 3 });
+        assert!(manager.get_device_config("AA-03").await.is_some());
+        assert!(manager.get_device_config("AA-01").await.is_none());
+    }
+}
@@ -0,0 +1,211 @@
+// Time service - wall-clock time for devices without internet access ([time_service])
+//
+// A UDP responder answers two kinds of requests on time_service.udp_port:
+//   - SNTP client packets (48 bytes, mode 3), so esp_sntp / lwIP SNTP can point at the server
+//     (ESP-IDF uses port 123: set udp_port = 123 or forward it)
+//   - `{"timeRequest": <device ms, optional>}` -> `{"time": <epoch ms>, "epoch": <s>, "t0": ..., "rx": ..., "tx": ...}`;
+//     t0 is echoed so the firmware can halve the round trip, a bare `time` datagram works too
+// Every broadcast_interval_seconds the server time is pushed as `{"setTime": <epoch ms>}` to all
+// connected devices that announced setTime support (same command as POST /api/devices/:id/clock/sync).
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde_json::{json, Value};
+use tokio::net::UdpSocket;
+
+use crate::config::TimeServiceConfig;
+use crate::device_manager::DeviceManager;
+use crate::device_store::SharedDeviceStore;
+use crate::device_types::DeviceCommand;
+
+/// Seconds between the NTP era (1900) and the Unix epoch
+const NTP_UNIX_OFFSET_SECS: u64 = 2_208_988_800;
+
+/// Size of an SNTP packet without extensions
+const NTP_PACKET_LEN: usize = 48;
+
+/// Largest accepted JSON request
+const MAX_REQUEST_LEN: usize = 512;
+
+/// Request received by the responder
+#[derive(Debug, Clone, PartialEq)]
+pub enum TimeRequest {
+    /// SNTP client packet; the transmit timestamp is returned as originate timestamp
+    Sntp { version: u8, transmit: [u8; 8] },
+    /// JSON/text request with the optional device send time
+    Json { t0: Option<Value> },
+}
+
+impl TimeRequest {
+    pub fn parse(datagram: &[u8]) -> Option<Self> {
+        // LI/VN/mode byte: mode 3 = client
+        if datagram.len() >= NTP_PACKET_LEN && datagram[0] & 0x07 == 3 {
+            let mut transmit = [0u8; 8];
+            transmit.copy_from_slice(&datagram[40..48]);
+            return Some(TimeRequest::Sntp { version: (datagram[0] >> 3) & 0x07, transmit });
+        }
+        if datagram.len() > MAX_REQUEST_LEN {
+            return None;
+        }
+        let text = std::str::from_utf8(datagram).ok()?.trim();
+        if text.eq_ignore_ascii_case("time") {
+            return Some(TimeRequest::Json { t0: None });
+        }
+        let value: Value = serde_json::from_str(text).ok()?;
+        let t0 = value.get("timeRequest")?;
+        Some(TimeRequest::Json { t0: (!t0.is_boolean() && !t0.is_null()).then(|| t0.clone()) })
+    }
+
+    /// Reply for a request received at `received_ms` and answered at `now_ms` (ms since epoch)
+    pub fn reply(&self, received_ms: i64, now_ms: i64) -> Vec<u8> {
+        match self {
+            TimeRequest::Sntp { version, transmit } => {
+                let mut packet = [0u8; NTP_PACKET_LEN];
+                // No leap warning, client version (3 or 4), mode 4 = server
+                packet[0] = ((*version).clamp(3, 4) << 3) | 4;
+                // Stratum 2: the server relays its own (NTP synchronized) system clock
+                packet[1] = 2;
+                packet[2] = 6; // poll 64 s
+                packet[3] = 0xEC; // precision ~ 2^-20 s
+                packet[12..16].copy_from_slice(b"LOCL");
+                packet[16..24].copy_from_slice(&ntp_timestamp(received_ms));
+                packet[24..32].copy_from_slice(transmit);
+                packet[32..40].copy_from_slice(&ntp_timestamp(received_ms));
+                packet[40..48].copy_from_slice(&ntp_timestamp(now_ms));
+                packet.to_vec()
+            }
+            TimeRequest::Json { t0 } => {
+                let mut reply = json!({ "time": now_ms, "epoch": now_ms / 1000, "rx": received_ms, "tx": now_ms });
+                if let Some(t0) = t0 {
+                    reply["t0"] = t0.clone();
+                }
+                reply.to_string().into_bytes()
+            }
+        }
+    }
+}
+
+/// 64-bit NTP timestamp (seconds since 1900 and binary fraction) of a Unix time in ms
+pub fn ntp_timestamp(unix_ms: i64) -> [u8; 8] {
+    let unix_ms = unix_ms.max(0) as u64;
+    let seconds = (unix_ms / 1000 + NTP_UNIX_OFFSET_SECS) as u32;
+    let fraction = (((unix_ms % 1000) << 32) / 1000) as u32;
+    let mut bytes = [0u8; 8];
+    bytes[..4].copy_from_slice(&seconds.to_be_bytes());
+    bytes[4..].copy_from_slice(&fraction.to_be_bytes());
+    bytes
+}
+
+/// Responder and periodic setTime broadcast
+pub struct TimeService {
+    config: TimeServiceConfig,
+    bind_address: String,
+}
+
+impl TimeService {
+    pub fn new(config: TimeServiceConfig, bind_address: &str) -> Self {
+        Self { config, bind_address: bind_address.to_string() }
+    }
+
+    pub fn start(self, device_manager: Arc<DeviceManager>, device_store: SharedDeviceStore) {
+        if !self.config.enabled {
+            tracing::info!("Time service disabled (time_service.enabled)");
+            return;
+        }
+
+        let address = format!("{}:{}", self.bind_address, self.config.udp_port);
+        tokio::spawn(async move {
+            match UdpSocket::bind(&address).await {
+                Ok(socket) => {
+                    tracing::info!("Time service answering requests on udp://{}", address);
+                    serve(socket).await;
+                }
+                Err(e) => tracing::error!("Time service could not bind {}: {}", address, e),
+            }
+        });
+
+        if self.config.broadcast_interval_seconds > 0 {
+            let interval = Duration::from_secs(self.config.broadcast_interval_seconds);
+            tokio::spawn(broadcast_loop(interval, device_manager, device_store));
+        }
+    }
+}
+
+async fn serve(socket: UdpSocket) {
+    let mut buffer = [0u8; 1024];
+    loop {
+        let (len, peer): (usize, SocketAddr) = match socket.recv_from(&mut buffer).await {
+            Ok(received) => received,
+            Err(e) => {
+                tracing::debug!("Time service receive error: {}", e);
+                continue;
+            }
+        };
+        let received_ms = chrono::Utc::now().timestamp_millis();
+        let Some(request) = TimeRequest::parse(&buffer[..len]) else {
+            tracing::debug!("Ignoring {} byte datagram from {} on the time service port", len, peer);
+            continue;
+        };
+        let reply = request.reply(received_ms, chrono::Utc::now().timestamp_millis());
+        if let Err(e) = socket.send_to(&reply, peer).await {
+            tracing::debug!("Time service reply to {} failed: {}", peer, e);
+        }
+    }
+}
+
+async fn broadcast_loop(interval: Duration, device_manager: Arc<DeviceManager>, device_store: SharedDeviceStore) {
+    let mut ticker = tokio::time::interval(interval);
+    // The first tick fires immediately; devices are still connecting at startup
+    ticker.tick().await;
+    loop {
+        ticker.tick().await;
+        let mut sent = 0;
+        for device in device_manager.get_all_devices().await {
+            let device_id = device.device_id;
+            let supported = device_store.clocks().estimate(&device_id).is_some_and(|clock| clock.supports_set_time);
+            let connected = device_manager.get_device_state(&device_id).await.is_some_and(|state| state.is_connected());
+            if !supported || !connected {
+                continue;
+            }
+            let now = chrono::Utc::now().timestamp_millis();
+            match device_manager.send_command(&device_id, DeviceCommand::set_time(now)).await {
+                Ok(()) => {
+                    device_store.clocks().mark_synced(&device_id, now);
+                    sent += 1;
+                }
+                Err(e) => tracing::debug!("Time broadcast to {} failed: {}", device_id, e),
+            }
+        }
+        if sent > 0 {
+            tracing::debug!("Time broadcast sent to {} device(s)", sent);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sntp_and_json_requests() {
+        let mut packet = [0u8; NTP_PACKET_LEN];
+        packet[0] = 0x23; // v4, client
+        packet[40..48].copy_from_slice(&[1, 2, 3, 4, 5, 6, 7, 8]);
+        let request = TimeRequest::parse(&packet).unwrap();
+        let reply = request.reply(1_700_000_000_250, 1_700_000_000_500);
+        assert_eq!((reply.len(), reply[0], reply[1]), (NTP_PACKET_LEN, 0x24, 2));
+        assert_eq!(&reply[24..32], &[1, 2, 3, 4, 5, 6, 7, 8]);
+        let seconds = u32::from_be_bytes(reply[40..44].try_into().unwrap()) as u64;
+        assert_eq!(seconds, 1_700_000_000 + NTP_UNIX_OFFSET_SECS);
+        assert_eq!(u32::from_be_bytes(reply[44..48].try_into().unwrap()), 1 << 31);
+
+        let request = TimeRequest::parse(br#"{"timeRequest": 12345}"#).unwrap();
+        let reply: Value = serde_json::from_slice(&request.reply(1_000, 1_002)).unwrap();
+        assert_eq!((reply["time"].as_i64(), reply["t0"].as_i64(), reply["rx"].as_i64()), (Some(1_002), Some(12345), Some(1_000)));
+        assert!(TimeRequest::parse(b"time\n").is_some());
+        assert!(TimeRequest::parse(br#"{"timeRequest": true}"#).unwrap().reply(0, 0).len() > 2);
+        assert!(TimeRequest::parse(br#"{"temperature": 21}"#).is_none());
+    }
+}
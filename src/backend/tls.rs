@@ -0,0 +1,167 @@
+// TLS termination - built-in HTTPS listener (rustls) and the HTTP -> HTTPS redirect
+//
+// For standalone installs without a reverse proxy in front (e.g. a lab Raspberry Pi).
+// Behind nginx/traefik keep TLS at the proxy and use server.trusted_proxies instead.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    extract::{ConnectInfo, Request},
+    http::{header, HeaderMap, StatusCode, Uri},
+    response::{IntoResponse, Redirect, Response},
+    Router,
+};
+use hyper_util::rt::TokioIo;
+use tokio::net::TcpListener;
+use tokio_rustls::TlsAcceptor;
+use tower::Service;
+
+/// Clients that do not finish the handshake in time are dropped
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Request extension: arrived over the built-in HTTPS listener (cookies get the Secure flag)
+#[derive(Debug, Clone, Copy)]
+pub struct TlsConnection;
+
+/// Load certificate chain and private key (PEM) into a rustls server config
+pub fn load_server_config(cert_path: &str, key_path: &str) -> Result<rustls::ServerConfig, String> {
+    fn read_pem(path: &str) -> Result<std::io::BufReader<std::fs::File>, String> {
+        std::fs::File::open(path)
+            .map(std::io::BufReader::new)
+            .map_err(|e| format!("Cannot read TLS file {}: {}", path, e))
+    }
+
+    let certs: Vec<rustls::Certificate> = rustls_pemfile::certs(&mut read_pem(cert_path)?)
+        .map_err(|e| format!("Invalid certificate file {}: {}", cert_path, e))?
+        .into_iter()
+        .map(rustls::Certificate)
+        .collect();
+    if certs.is_empty() {
+        return Err(format!("No certificate found in {}", cert_path));
+    }
+
+    let key = rustls_pemfile::read_all(&mut read_pem(key_path)?)
+        .map_err(|e| format!("Invalid key file {}: {}", key_path, e))?
+        .into_iter()
+        .find_map(|item| match item {
+            rustls_pemfile::Item::PKCS8Key(key)
+            | rustls_pemfile::Item::RSAKey(key)
+            | rustls_pemfile::Item::ECKey(key) => Some(rustls::PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| format!("No private key found in {}", key_path))?;
+
+    let mut config = rustls::ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("Certificate and key do not match: {}", e))?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+    Ok(config)
+}
+
+/// Serve the app over TLS; like axum::serve with ConnectInfo, plus WebSocket upgrades
+pub async fn serve_https(listener: TcpListener, tls_config: rustls::ServerConfig, app: Router) {
+    let acceptor = TlsAcceptor::from(Arc::new(tls_config));
+
+    loop {
+        let (stream, peer) = match listener.accept().await {
+            Ok(connection) => connection,
+            Err(e) => {
+                // e.g. too many open files - back off instead of spinning
+                tracing::warn!("HTTPS accept failed: {}", e);
+                tokio::time::sleep(Duration::from_millis(100)).await;
+                continue;
+            }
+        };
+
+        let acceptor = acceptor.clone();
+        let app = app.clone();
+        tokio::spawn(async move {
+            let stream = match tokio::time::timeout(HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await {
+                Ok(Ok(stream)) => stream,
+                Ok(Err(e)) => {
+                    tracing::debug!("TLS handshake with {} failed: {}", peer, e);
+                    return;
+                }
+                Err(_) => {
+                    tracing::debug!("TLS handshake with {} timed out", peer);
+                    return;
+                }
+            };
+
+            let service = hyper::service::service_fn(move |mut request: Request<hyper::body::Incoming>| {
+                request.extensions_mut().insert(ConnectInfo(peer));
+                request.extensions_mut().insert(TlsConnection);
+                app.clone().call(request)
+            });
+            let connection = hyper::server::conn::http1::Builder::new()
+                .serve_connection(TokioIo::new(stream), service)
+                .with_upgrades();
+            if let Err(e) = connection.await {
+                tracing::debug!("HTTPS connection from {} closed with error: {}", peer, e);
+            }
+        });
+    }
+}
+
+// ============================================================================
+// HTTP -> HTTPS REDIRECT
+// ============================================================================
+
+/// Router for the plain HTTP port: every request is redirected to the HTTPS port
+pub fn redirect_router(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        redirect_to_https(&headers, &uri, https_port)
+    })
+}
+
+fn redirect_to_https(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Response {
+    let host = headers.get(header::HOST).and_then(|value| value.to_str().ok());
+    match https_location(host, uri, https_port) {
+        Some(location) => Redirect::permanent(&location).into_response(),
+        None => (StatusCode::BAD_REQUEST, "HTTPS required").into_response(),
+    }
+}
+
+/// https:// URL for the same host and path; None for a missing or malformed Host header
+pub fn https_location(host: Option<&str>, uri: &Uri, https_port: u16) -> Option<String> {
+    let host = host?.trim();
+    if host.is_empty() || host.contains(['/', '@', '\\', ' ']) {
+        return None;
+    }
+
+    // Drop the HTTP port: "pi.local:3000" -> "pi.local", "[::1]:3000" -> "[::1]"
+    let hostname = match host.rfind(':') {
+        Some(i) if !host[i..].contains(']') => &host[..i],
+        _ => host,
+    };
+    let port = if https_port == 443 { String::new() } else { format!(":{}", https_port) };
+    let path = uri.path_and_query().map(|pq| pq.as_str()).unwrap_or("/");
+
+    Some(format!("https://{}{}{}", hostname, port, path))
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_https_redirect_location() {
+        let uri: Uri = "/devices/esp-1?tab=events".parse().unwrap();
+        assert_eq!(https_location(Some("pi.local:3000"), &uri, 3443).as_deref(), Some("https://pi.local:3443/devices/esp-1?tab=events"));
+        assert_eq!(https_location(Some("[::1]:3000"), &"/".parse().unwrap(), 443).as_deref(), Some("https://[::1]/"));
+        assert_eq!(https_location(Some("192.168.1.20"), &"/".parse().unwrap(), 443).as_deref(), Some("https://192.168.1.20/"));
+
+        // No open redirect through a crafted Host header
+        assert_eq!(https_location(Some("evil.example/x"), &uri, 443), None);
+        assert_eq!(https_location(None, &uri, 443), None);
+
+        assert!(load_server_config("missing-cert.pem", "missing-key.pem").unwrap_err().contains("missing-cert.pem"));
+    }
+}
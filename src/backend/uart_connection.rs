@@ -1,11 +1,14 @@
 // UART connection management for devices
 // Handles serial communication with multiple devices connected via UART
 
-use crate::device_store::SharedDeviceStore;
-use crate::device_manager::DeviceManager;
 use crate::database::DatabaseManager;
+use crate::device_manager::{DeviceConnectionType, MessageSource};
+use crate::device_trace::{Direction, Transport};
+use crate::device_transport::{DeviceTransport, TransportContext};
+use crate::device_types::{ConnectionState, DeviceCommand, DeviceError, DeviceResult};
+use crate::uart_gateway;
 
-use std::collections::HashMap;
+use futures::future::BoxFuture;
 use std::sync::Arc;
 use std::time::Duration;
 use tokio::sync::{mpsc, RwLock};
@@ -39,8 +42,8 @@ pub struct UartConnection {
     settings: Arc<RwLock<Option<UartSettings>>>,
     /// Serial port stream
     serial_stream: Arc<RwLock<Option<SerialStream>>>,
-    /// Device store for event routing
-    device_store: SharedDeviceStore,
+    /// Shared state received frames are delivered to (device store, lifecycle, trackers)
+    context: TransportContext,
     /// Database for device persistence
     db: Option<Arc<DatabaseManager>>,
     /// Shutdown channel
@@ -49,33 +52,27 @@ pub struct UartConnection {
     task_handle: Option<tokio::task::JoinHandle<()>>,
     /// Connection status
     is_connected: Arc<RwLock<bool>>,
-    /// Unified connection states (shared with DeviceManager)
-    unified_connection_states: Arc<RwLock<HashMap<String, bool>>>,
-    /// Unified activity tracker (shared with DeviceManager)
-    unified_activity_tracker: Arc<RwLock<HashMap<String, std::time::Instant>>>,
-    /// Device connection types map (shared with DeviceManager)
-    device_connection_types: Arc<RwLock<HashMap<String, crate::device_manager::DeviceConnectionType>>>,
 }
 
 impl UartConnection {
-    /// Create new UART connection manager with shared state trackers
-    pub fn new(
-        device_store: SharedDeviceStore,
-        unified_connection_states: Arc<RwLock<HashMap<String, bool>>>,
-        unified_activity_tracker: Arc<RwLock<HashMap<String, std::time::Instant>>>,
-        device_connection_types: Arc<RwLock<HashMap<String, crate::device_manager::DeviceConnectionType>>>,
-    ) -> Self {
+    /// Create new UART connection manager delivering into the DeviceManager's transport context
+    pub fn new(context: TransportContext) -> Self {
         Self {
             settings: Arc::new(RwLock::new(None)),
             serial_stream: Arc::new(RwLock::new(None)),
-            device_store,
+            context,
             db: None,
             shutdown_sender: None,
             task_handle: None,
             is_connected: Arc::new(RwLock::new(false)),
-            unified_connection_states,
-            unified_activity_tracker,
-            device_connection_types,
+        }
+    }
+
+    /// Handle on the serial port for per-device UART transports
+    pub fn port(&self) -> UartPort {
+        UartPort {
+            serial_stream: Arc::clone(&self.serial_stream),
+            is_connected: Arc::clone(&self.is_connected),
         }
     }
 
@@ -173,6 +170,11 @@ impl UartConnection {
         *self.is_connected.read().await
     }
 
+    /// Listener task state: None if no port was opened, Some(false) once the task ended (e.g. port unplugged)
+    pub fn listener_alive(&self) -> Option<bool> {
+        self.task_handle.as_ref().map(|handle| !handle.is_finished())
+    }
+
     /// Get current settings
     pub async fn get_settings(&self) -> Option<UartSettings> {
         self.settings.read().await.clone()
@@ -181,11 +183,8 @@ impl UartConnection {
     /// Start background task for UART message handling
     async fn start_uart_listener_task(&self, mut shutdown_rx: mpsc::UnboundedReceiver<()>) -> tokio::task::JoinHandle<()> {
         let serial_stream = Arc::clone(&self.serial_stream);
-        let device_store = self.device_store.clone();
+        let context = self.context.clone();
         let is_connected = Arc::clone(&self.is_connected);
-        let unified_connection_states = Arc::clone(&self.unified_connection_states);
-        let unified_activity_tracker = Arc::clone(&self.unified_activity_tracker);
-        let device_connection_types = Arc::clone(&self.device_connection_types);
         let db = self.db.clone();
 
         tokio::spawn(async move {
@@ -240,14 +239,11 @@ impl UartConnection {
                                     if let Ok(message) = String::from_utf8(message_bytes.to_vec()) {
                                         if !message.trim().is_empty() {
                                             // Process the message
-                                            let device_store_clone = device_store.clone();
-                                            let unified_connection_states_clone = Arc::clone(&unified_connection_states);
-                                            let unified_activity_tracker_clone = Arc::clone(&unified_activity_tracker);
-                                            let device_connection_types_clone = Arc::clone(&device_connection_types);
+                                            let context_clone = context.clone();
                                             let db_clone = db.clone();
                                             let message_clone = message.trim().to_string();
                                             tokio::spawn(async move {
-                                                Self::handle_uart_message(&message_clone, &device_store_clone, &unified_connection_states_clone, &unified_activity_tracker_clone, &device_connection_types_clone, &db_clone).await;
+                                                Self::handle_uart_message(&message_clone, &context_clone, &db_clone).await;
                                             });
                                         }
                                     } else {
@@ -262,12 +258,17 @@ impl UartConnection {
                                     if stx_pos > 0 {
                                         buffer.drain(..stx_pos);
                                     }
+                                    // A frame without ETX must not grow the buffer forever
+                                    if let Err(e) = crate::inbound_validation::check_size(buffer.len()) {
+                                        warn!("UART: frame without ETX dropped: {}", e);
+                                        buffer.clear();
+                                    }
                                     break;
                                 }
                             }
 
                             // If no STX found and buffer is large, clear old data
-                            if buffer.len() > 2048 && !buffer.iter().any(|&b| b == STX) {
+                            if buffer.len() > 2048 && !buffer.contains(&STX) {
                                 warn!("UART: Buffer overflow without STX, clearing buffer");
                                 buffer.clear();
                             }
@@ -297,10 +298,7 @@ impl UartConnection {
     /// Handle incoming UART message with unified state tracking
     async fn handle_uart_message(
         message: &str,
-        device_store: &SharedDeviceStore,
-        unified_connection_states: &Arc<RwLock<HashMap<String, bool>>>,
-        unified_activity_tracker: &Arc<RwLock<HashMap<String, std::time::Instant>>>,
-        device_connection_types: &Arc<RwLock<HashMap<String, crate::device_manager::DeviceConnectionType>>>,
+        context: &TransportContext,
         db: &Option<Arc<DatabaseManager>>,
     ) {
         info!("UART MESSAGE RECEIVED: {}", message);
@@ -309,12 +307,27 @@ impl UartConnection {
         match serde_json::from_str::<serde_json::Value>(message) {
             Ok(json) => {
                 // Extract device_id from JSON
-                if let Some(device_id) = json.get("device_id").and_then(|v| v.as_str()) {
-                    // Check if device needs discovery and registration (first time seen)
-                    let should_send_discovery_event = {
-                        let states = unified_connection_states.read().await;
-                        !states.contains_key(device_id)
+                if let Some(sender_id) = json.get("device_id").and_then(|v| v.as_str()).map(str::to_string) {
+                    // A gateway frame is delivered as the downstream node it carries
+                    let demuxed = uart_gateway::demux(&sender_id, &json);
+                    let (device_id, json, device_name) = match demuxed {
+                        None => (sender_id.clone(), json, format!("UART-{}", sender_id)),
+                        Some(Ok(node)) => {
+                            debug!("UART gateway {}: frame for node {} (rssi {:?})", sender_id, node.header.node, node.header.rssi);
+                            let device_name = node.device_name();
+                            (node.device_id, node.payload, device_name)
+                        }
+                        Some(Err(e)) => {
+                            warn!("UART gateway frame dropped: {}", e);
+                            return;
+                        }
                     };
+                    let device_id = device_id.as_str();
+
+                    crate::device_trace::record(device_id, Direction::In, Transport::Uart, None, message.as_bytes());
+
+                    // Check if device needs discovery and registration (first time seen)
+                    let should_send_discovery_event = !context.lifecycle.is_known(device_id).await;
 
                     // Register and send discovery event if device is new
                     if should_send_discovery_event {
@@ -324,10 +337,10 @@ impl UartConnection {
                         // Note: UART device will be auto-registered by the unified_timeout_monitor
                         // when it sees the device in unified_activity_tracker
                         info!("UART DISCOVERY: New UART device detected: {}", device_id);
+                        context.lifecycle.discovered(device_id, "Seen on UART").await;
 
                         // Save device to database
                         if let Some(db) = db {
-                            let device_name = format!("UART-{}", device_id);
                             if let Err(e) = db.upsert_discovered_device(
                                 device_id.to_string(),
                                 device_name,
@@ -351,7 +364,7 @@ impl UartConnection {
                             Some(format!("uart-{}", device_id))  // Virtual hostname
                         );
 
-                        let _ = device_store.add_event(
+                        let _ = context.device_store.add_event(
                             "system".to_string(),
                             discovery_event,
                             "device_system".to_string(),
@@ -368,15 +381,7 @@ impl UartConnection {
                         let modified_message = serde_json::to_string(&json_without_device_id)
                             .unwrap_or_else(|_| message.to_string());
 
-                        DeviceManager::handle_message_unified(
-                            &modified_message,
-                            device_id,
-                            crate::device_manager::MessageSource::Uart,
-                            device_store,
-                            unified_connection_states,
-                            Some(unified_activity_tracker),
-                            Some(device_connection_types),
-                        ).await;
+                        context.deliver(device_id, &modified_message, MessageSource::Uart).await;
                     }
                 } else {
                     warn!("UART message missing device_id field: {}", message);
@@ -389,6 +394,43 @@ impl UartConnection {
     }
 
     /// Send command to UART device
+    pub async fn send_command(&self, device_id: &str, command_json: &str) -> Result<(), String> {
+        self.port().send_command(device_id, command_json).await
+    }
+
+    /// List available UART ports
+    pub fn list_ports() -> Result<Vec<String>, String> {
+        match tokio_serial::available_ports() {
+            Ok(ports) => {
+                let port_names: Vec<String> = ports
+                    .into_iter()
+                    .map(|p| p.port_name)
+                    .collect();
+                Ok(port_names)
+            }
+            Err(e) => Err(format!("Failed to list serial ports: {}", e)),
+        }
+    }
+}
+
+// ============================================================================
+// UART TRANSPORT
+// ============================================================================
+
+/// Shared serial port handle; all UART devices are multiplexed over one port
+#[derive(Clone)]
+pub struct UartPort {
+    serial_stream: Arc<RwLock<Option<SerialStream>>>,
+    is_connected: Arc<RwLock<bool>>,
+}
+
+impl UartPort {
+    pub async fn is_open(&self) -> bool {
+        *self.is_connected.read().await
+    }
+
+    /// Write a command frame for a device; the device_id field is added to the JSON,
+    /// commands for a gateway node (`<bridge>~<node>`) are relayed through the bridge
     pub async fn send_command(&self, device_id: &str, command_json: &str) -> Result<(), String> {
         info!("Sending UART command to device {}: {}", device_id, command_json);
 
@@ -396,13 +438,10 @@ impl UartConnection {
         if let Some(stream) = stream_guard.as_mut() {
             use tokio::io::AsyncWriteExt;
 
-            // Parse the command JSON and add device_id field
+            // Parse the command JSON and add device_id field (or the gateway header for bridge nodes)
             let command_with_device_id = match serde_json::from_str::<serde_json::Value>(command_json) {
-                Ok(mut cmd_value) => {
-                    // Add device_id to the command JSON
-                    if let Some(obj) = cmd_value.as_object_mut() {
-                        obj.insert("device_id".to_string(), serde_json::Value::String(device_id.to_string()));
-                    }
+                Ok(cmd_value) => {
+                    let cmd_value = uart_gateway::address_command(device_id, cmd_value);
                     serde_json::to_string(&cmd_value)
                         .map_err(|e| format!("Failed to serialize command with device_id: {}", e))?
                 }
@@ -422,6 +461,7 @@ impl UartConnection {
             message_bytes.extend_from_slice(command_with_device_id.as_bytes());
             message_bytes.push(ETX);
 
+            crate::device_trace::record(device_id, Direction::Out, Transport::Uart, None, &message_bytes);
             stream.write_all(&message_bytes)
                 .await
                 .map_err(|e| format!("Failed to write to UART: {}", e))?;
@@ -436,19 +476,66 @@ impl UartConnection {
             Err("UART connection not established".to_string())
         }
     }
+}
 
-    /// List available UART ports
-    pub fn list_ports() -> Result<Vec<String>, String> {
-        match tokio_serial::available_ports() {
-            Ok(ports) => {
-                let port_names: Vec<String> = ports
-                    .into_iter()
-                    .map(|p| p.port_name)
-                    .collect();
-                Ok(port_names)
+/// Per-device transport over the shared UART port
+#[derive(Debug)]
+pub struct UartDeviceTransport {
+    device_id: String,
+    port: UartPort,
+}
+
+impl UartDeviceTransport {
+    pub fn new(device_id: String, port: UartPort) -> Self {
+        Self { device_id, port }
+    }
+}
+
+// SerialStream has no Debug impl
+impl std::fmt::Debug for UartPort {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("UartPort").finish_non_exhaustive()
+    }
+}
+
+impl DeviceTransport for UartDeviceTransport {
+    fn connection_type(&self) -> DeviceConnectionType {
+        DeviceConnectionType::Uart
+    }
+
+    fn connect(&mut self) -> BoxFuture<'_, DeviceResult<()>> {
+        Box::pin(async move {
+            if self.port.is_open().await {
+                Ok(())
+            } else {
+                Err(DeviceError::ConnectionFailed("UART port not open".to_string()))
             }
-            Err(e) => Err(format!("Failed to list serial ports: {}", e)),
-        }
+        })
+    }
+
+    // The port is shared with the other UART devices; it is closed via the UART API only
+    fn disconnect(&mut self) -> BoxFuture<'_, DeviceResult<()>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn send<'a>(&'a self, command: DeviceCommand, correlation_id: Option<&'a str>) -> BoxFuture<'a, DeviceResult<()>> {
+        Box::pin(async move {
+            let json = command.to_json_with_correlation(correlation_id)?;
+            self.port
+                .send_command(&self.device_id, &json)
+                .await
+                .map_err(DeviceError::ConnectionFailed)
+        })
+    }
+
+    fn health(&self) -> BoxFuture<'_, ConnectionState> {
+        Box::pin(async move {
+            if self.port.is_open().await {
+                ConnectionState::Connected
+            } else {
+                ConnectionState::Disconnected
+            }
+        })
     }
 }
 
@@ -470,8 +557,8 @@ impl Drop for UartConnection {
             // This is necessary because Drop cannot be async
             let wait_result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
                 tokio::task::block_in_place(|| {
-                    tokio::runtime::Handle::try_current().ok().and_then(|handle| {
-                        Some(handle.block_on(async {
+                    tokio::runtime::Handle::try_current().ok().map(|handle| {
+                        handle.block_on(async {
                             match tokio::time::timeout(Duration::from_secs(5), task_handle).await {
                                 Ok(Ok(())) => {
                                     info!("Drop: UART listener task finished successfully");
@@ -486,7 +573,7 @@ impl Drop for UartConnection {
                                     false
                                 }
                             }
-                        }))
+                        })
                     })
                 })
             }));
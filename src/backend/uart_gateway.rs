@@ -0,0 +1,147 @@
+// UART gateway - ESP32 radio bridges (ESP-NOW, LoRa hub) on the serial port
+//
+// A bridge is a UART device that forwards frames of downstream nodes. Inside the usual STX/ETX
+// JSON frame it adds a `gw` header naming the node and carries the node's message in `data`:
+//
+//   {"device_id": "HUB1", "gw": {"node": "A4:CF:12:34:56:78", "link": "espnow", "rssi": -61}, "data": {...}}
+//
+// Each node becomes a virtual device `<bridge>~<node>` (e.g. `HUB1~A4:CF:12:34:56:78`) with its
+// own lifecycle, events and permissions. Commands for a virtual device are wrapped the same way
+// and written for the bridge, which relays `data` over the radio. Frames without `gw` are the
+// bridge's own messages.
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+/// Separates bridge and node in the ID of a virtual device
+pub const NODE_SEPARATOR: char = '~';
+
+/// Longest node address accepted from a bridge
+pub const MAX_NODE_LEN: usize = 64;
+
+/// Framing header of a bridge frame (`gw`)
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct GatewayHeader {
+    /// Address of the downstream node (ESP-NOW MAC, LoRa device address, ...)
+    pub node: String,
+    /// Radio link the node was heard on ("espnow", "lora", ...)
+    #[serde(default)]
+    pub link: Option<String>,
+    /// Signal strength reported by the bridge
+    #[serde(default)]
+    pub rssi: Option<i32>,
+}
+
+/// A node message taken out of a bridge frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct NodeFrame {
+    /// Virtual device ID `<bridge>~<node>`
+    pub device_id: String,
+    pub header: GatewayHeader,
+    /// The node's own message
+    pub payload: Value,
+}
+
+impl NodeFrame {
+    /// Name a virtual device gets on discovery, e.g. "ESPNOW-A4:CF:12:34:56:78"
+    pub fn device_name(&self) -> String {
+        let link = self.header.link.as_deref().unwrap_or("node").to_uppercase();
+        format!("{}-{}", link, self.header.node)
+    }
+}
+
+/// ID of the virtual device of a node behind a bridge
+pub fn virtual_device_id(bridge_id: &str, node: &str) -> Result<String, String> {
+    if bridge_id.contains(NODE_SEPARATOR) {
+        return Err(format!("Bridge {} is itself a gateway node", bridge_id));
+    }
+    if node.is_empty() || node.len() > MAX_NODE_LEN {
+        return Err(format!("Node address must be between 1 and {} characters", MAX_NODE_LEN));
+    }
+    if !node.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, ':' | '-' | '_' | '.')) {
+        return Err(format!("Invalid node address {}", node));
+    }
+    Ok(format!("{}{}{}", bridge_id, NODE_SEPARATOR, node))
+}
+
+/// Bridge and node of a virtual device ID, None for ordinary devices
+pub fn split_virtual_id(device_id: &str) -> Option<(&str, &str)> {
+    device_id
+        .split_once(NODE_SEPARATOR)
+        .filter(|(bridge, node)| !bridge.is_empty() && !node.is_empty())
+}
+
+/// Take the node message out of a frame received from `bridge_id`.
+/// None if the frame carries no `gw` header (the bridge's own message).
+pub fn demux(bridge_id: &str, frame: &Value) -> Option<Result<NodeFrame, String>> {
+    let header = frame.get("gw")?;
+    Some(parse_node_frame(bridge_id, header, frame.get("data")))
+}
+
+fn parse_node_frame(bridge_id: &str, header: &Value, data: Option<&Value>) -> Result<NodeFrame, String> {
+    let header: GatewayHeader = serde_json::from_value(header.clone())
+        .map_err(|e| format!("Invalid gateway header from {}: {}", bridge_id, e))?;
+    let payload = match data {
+        Some(data @ Value::Object(_)) => data.clone(),
+        _ => return Err(format!("Gateway frame from {} for node {} has no data object", bridge_id, header.node)),
+    };
+    Ok(NodeFrame {
+        device_id: virtual_device_id(bridge_id, &header.node)?,
+        header,
+        payload,
+    })
+}
+
+/// Address a command for the UART: ordinary devices get a `device_id` field,
+/// commands for a virtual device are wrapped into a bridge frame for its bridge.
+pub fn address_command(device_id: &str, command: Value) -> Value {
+    match split_virtual_id(device_id) {
+        Some((bridge_id, node)) => {
+            let mut frame = Map::new();
+            frame.insert("device_id".to_string(), Value::String(bridge_id.to_string()));
+            frame.insert("gw".to_string(), serde_json::json!({ "node": node }));
+            frame.insert("data".to_string(), command);
+            Value::Object(frame)
+        }
+        None => {
+            let mut command = command;
+            if let Some(obj) = command.as_object_mut() {
+                obj.insert("device_id".to_string(), Value::String(device_id.to_string()));
+            }
+            command
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_demux_and_address_round_trip() {
+        let frame = json!({
+            "device_id": "HUB1",
+            "gw": {"node": "A4:CF:12:34:56:78", "link": "espnow", "rssi": -61},
+            "data": {"variables": {"temp": 21.5}}
+        });
+        let node = demux("HUB1", &frame).unwrap().unwrap();
+        assert_eq!(node.device_id, "HUB1~A4:CF:12:34:56:78");
+        assert_eq!(node.header.rssi, Some(-61));
+        assert_eq!(node.device_name(), "ESPNOW-A4:CF:12:34:56:78");
+        assert_eq!(node.payload, json!({"variables": {"temp": 21.5}}));
+
+        // The bridge's own frames and broken headers
+        assert!(demux("HUB1", &json!({"device_id": "HUB1", "uptime": 5})).is_none());
+        assert!(demux("HUB1", &json!({"gw": {"node": "a b"}, "data": {}})).unwrap().is_err());
+        assert!(demux("HUB1", &json!({"gw": {"node": "n1"}, "data": 5})).unwrap().is_err());
+
+        // Commands go back through the bridge
+        assert_eq!(
+            address_command(&node.device_id, json!({"setVariable": {"led": 1}})),
+            json!({"device_id": "HUB1", "gw": {"node": "A4:CF:12:34:56:78"}, "data": {"setVariable": {"led": 1}}}),
+        );
+        assert_eq!(address_command("HUB1", json!({"reboot": true})), json!({"reboot": true, "device_id": "HUB1"}));
+        assert!(split_virtual_id("~n1").is_none());
+    }
+}
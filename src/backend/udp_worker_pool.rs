@@ -0,0 +1,117 @@
+// Bounded worker pool for received UDP datagrams
+//
+// The central UDP listener only receives and hands each datagram to a worker; payload decoding,
+// JSON/regex parsing and event delivery run in the workers. Datagrams are routed by source IP,
+// so the frames of one device keep their order. Every worker has a bounded queue: when it is
+// full the datagram is dropped (and counted) instead of stalling the receive loop, which would
+// only move the backlog into the kernel socket buffer.
+
+use std::collections::hash_map::RandomState;
+use std::future::Future;
+use std::hash::BuildHasher;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Default number of parsing workers
+pub const DEFAULT_UDP_WORKERS: usize = 4;
+
+/// Datagrams waiting per worker before new ones are dropped
+pub const UDP_WORKER_QUEUE_CAPACITY: usize = 1024;
+
+/// One received datagram
+#[derive(Debug)]
+pub struct UdpDatagram {
+    pub payload: Vec<u8>,
+    pub from: SocketAddr,
+}
+
+#[derive(Debug)]
+pub struct UdpWorkerPool {
+    queues: Vec<mpsc::Sender<UdpDatagram>>,
+    hasher: RandomState,
+    dropped: AtomicU64,
+}
+
+impl UdpWorkerPool {
+    /// Spawn `workers` tasks, each running `handler` for the datagrams routed to it
+    pub fn spawn<F, Fut>(workers: usize, capacity: usize, handler: F) -> Arc<Self>
+    where
+        F: Fn(UdpDatagram) -> Fut + Clone + Send + Sync + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let queues = (0..workers.max(1)).map(|_| {
+            let (tx, mut rx) = mpsc::channel::<UdpDatagram>(capacity.max(1));
+            let handler = handler.clone();
+            tokio::spawn(async move {
+                while let Some(datagram) = rx.recv().await {
+                    handler(datagram).await;
+                }
+            });
+            tx
+        }).collect();
+
+        Arc::new(Self { queues, hasher: RandomState::new(), dropped: AtomicU64::new(0) })
+    }
+
+    /// Queue a datagram for the worker of its source IP; false if it was dropped
+    pub fn dispatch(&self, datagram: UdpDatagram) -> bool {
+        let worker = self.hasher.hash_one(datagram.from.ip()) as usize % self.queues.len();
+
+        match self.queues[worker].try_send(datagram) {
+            Ok(()) => true,
+            Err(mpsc::error::TrySendError::Full(datagram)) | Err(mpsc::error::TrySendError::Closed(datagram)) => {
+                let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if dropped == 1 || dropped.is_multiple_of(1000) {
+                    warn!("UDP worker {} is falling behind: datagram from {} dropped ({} dropped in total)", worker, datagram.from, dropped);
+                }
+                false
+            }
+        }
+    }
+
+    /// Datagrams dropped because their worker queue was full
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+    use tokio::sync::Mutex;
+
+    #[tokio::test]
+    async fn test_datagrams_of_one_source_keep_their_order() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let gate = Arc::new(tokio::sync::Semaphore::new(0));
+        let pool = {
+            let (received, gate) = (received.clone(), gate.clone());
+            UdpWorkerPool::spawn(4, 3, move |datagram: UdpDatagram| {
+                let (received, gate) = (received.clone(), gate.clone());
+                async move {
+                    gate.acquire().await.unwrap().forget();
+                    received.lock().await.push((datagram.from, datagram.payload[0]));
+                }
+            })
+        };
+
+        // Queue of the source's worker takes 3 (one more if the worker already picked up the first)
+        let from: SocketAddr = "10.0.0.7:3232".parse().unwrap();
+        let results: Vec<bool> = (0..6u8).map(|i| pool.dispatch(UdpDatagram { payload: vec![i], from })).collect();
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let accepted = results.iter().filter(|ok| **ok).count();
+        assert!((3..=4).contains(&accepted), "{:?}", results);
+        assert_eq!(pool.dropped(), (6 - accepted) as u64);
+
+        gate.add_permits(6);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        let payloads: Vec<u8> = received.lock().await.iter().map(|(_, payload)| *payload).collect();
+        assert_eq!(payloads.len(), accepted);
+        assert!(payloads.windows(2).all(|pair| pair[0] < pair[1]), "in order: {:?}", payloads);
+    }
+}
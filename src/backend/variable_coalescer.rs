@@ -0,0 +1,166 @@
+// Coalescing of high-frequency variable updates
+//
+// A sensor publishing at 1 kHz would push every sample through the event store and out to every
+// subscribed browser. The coalescer lets at most one update per (device, variable) through per
+// minimum interval. Updates arriving in between replace a pending value that is delivered once the
+// interval has passed (trailing edge), so clients always end up with the latest value. With
+// record_raw set, the held-back samples are still published on the store's event feed, where the
+// server-side consumers (alerts, scripts, webhooks) see the full-rate stream.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::events::DeviceEvent;
+
+/// Default minimum interval between updates of one variable
+pub const DEFAULT_VARIABLE_MIN_INTERVAL_MS: u64 = 100;
+
+/// What to do with an offered update
+#[derive(Debug)]
+pub enum Offer {
+    /// Deliver the update now
+    Emit(DeviceEvent),
+    /// Held back as the pending value; `flush_in` is set when the caller has to schedule the
+    /// delivery of the pending value (once per interval)
+    Held { flush_in: Option<Duration> },
+}
+
+#[derive(Debug)]
+struct Slot {
+    last_emit: Instant,
+    pending: Option<DeviceEvent>,
+    flush_scheduled: bool,
+}
+
+#[derive(Debug)]
+pub struct VariableCoalescer {
+    min_interval_ms: AtomicU64,
+    record_raw: AtomicBool,
+    /// (device_id, variable name) -> emit state
+    slots: Mutex<HashMap<(String, String), Slot>>,
+    coalesced: AtomicU64,
+}
+
+impl Default for VariableCoalescer {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(DEFAULT_VARIABLE_MIN_INTERVAL_MS))
+    }
+}
+
+impl VariableCoalescer {
+    pub fn new(min_interval: Duration) -> Self {
+        Self {
+            min_interval_ms: AtomicU64::new(min_interval.as_millis() as u64),
+            record_raw: AtomicBool::new(false),
+            slots: Mutex::new(HashMap::new()),
+            coalesced: AtomicU64::new(0),
+        }
+    }
+
+    /// Minimum interval (zero disables coalescing) and whether held-back samples go to the event feed
+    pub fn configure(&self, min_interval: Duration, record_raw: bool) {
+        self.min_interval_ms.store(min_interval.as_millis() as u64, Ordering::Relaxed);
+        self.record_raw.store(record_raw, Ordering::Relaxed);
+    }
+
+    pub fn min_interval(&self) -> Duration {
+        Duration::from_millis(self.min_interval_ms.load(Ordering::Relaxed))
+    }
+
+    pub fn record_raw(&self) -> bool {
+        self.record_raw.load(Ordering::Relaxed)
+    }
+
+    /// Updates replaced by a newer value before they were delivered
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced.load(Ordering::Relaxed)
+    }
+
+    /// Offer an update of `variable`; a held update becomes the pending value
+    pub fn offer(&self, device_id: &str, variable: &str, event: DeviceEvent, now: Instant) -> Offer {
+        let interval = self.min_interval();
+        if interval.is_zero() {
+            return Offer::Emit(event);
+        }
+
+        let mut slots = self.slots.lock().unwrap();
+        let Some(slot) = slots.get_mut(&(device_id.to_string(), variable.to_string())) else {
+            slots.insert((device_id.to_string(), variable.to_string()), Slot { last_emit: now, pending: None, flush_scheduled: false });
+            return Offer::Emit(event);
+        };
+
+        let elapsed = now.saturating_duration_since(slot.last_emit);
+        if elapsed >= interval {
+            // A pending value whose flush is late is superseded by this one
+            if slot.pending.take().is_some() {
+                self.coalesced.fetch_add(1, Ordering::Relaxed);
+            }
+            slot.last_emit = now;
+            return Offer::Emit(event);
+        }
+
+        if slot.pending.replace(event).is_some() {
+            self.coalesced.fetch_add(1, Ordering::Relaxed);
+        }
+        if slot.flush_scheduled {
+            return Offer::Held { flush_in: None };
+        }
+        slot.flush_scheduled = true;
+        Offer::Held { flush_in: Some(interval - elapsed) }
+    }
+
+    /// Take the pending value of a scheduled flush; None if a newer update was emitted meanwhile
+    pub fn take_pending(&self, device_id: &str, variable: &str, now: Instant) -> Option<DeviceEvent> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = slots.get_mut(&(device_id.to_string(), variable.to_string()))?;
+        slot.flush_scheduled = false;
+        let pending = slot.pending.take()?;
+        slot.last_emit = now;
+        Some(pending)
+    }
+
+    /// Drop the state of a removed device
+    pub fn forget_device(&self, device_id: &str) {
+        self.slots.lock().unwrap().retain(|(device, _), _| device != device_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn update(value: u32) -> DeviceEvent {
+        DeviceEvent::device_variable_update("AA-BB".to_string(), "temp".to_string(), value.to_string())
+    }
+
+    fn value(event: Option<DeviceEvent>) -> Option<String> {
+        match event? {
+            DeviceEvent::DeviceVariableUpdate { variable_value, .. } => Some(variable_value),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn test_updates_within_interval_collapse_to_latest_value() {
+        let coalescer = VariableCoalescer::new(Duration::from_millis(100));
+        let start = Instant::now();
+        let at = |ms| start + Duration::from_millis(ms);
+
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(1), at(0)), Offer::Emit(_)));
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(2), at(10)), Offer::Held { flush_in: Some(d) } if d == Duration::from_millis(90)));
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(3), at(20)), Offer::Held { flush_in: None }));
+        assert!(matches!(coalescer.offer("AA-BB", "humidity", update(4), at(20)), Offer::Emit(_)), "variables are independent");
+        assert_eq!(coalescer.coalesced(), 1);
+
+        // Flush delivers the latest value and restarts the interval
+        assert_eq!(value(coalescer.take_pending("AA-BB", "temp", at(100))).as_deref(), Some("3"));
+        assert!(coalescer.take_pending("AA-BB", "temp", at(100)).is_none());
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(5), at(150)), Offer::Held { flush_in: Some(_) }));
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(6), at(250)), Offer::Emit(_)));
+
+        coalescer.configure(Duration::ZERO, false);
+        assert!(matches!(coalescer.offer("AA-BB", "temp", update(7), at(251)), Offer::Emit(_)));
+    }
+}
@@ -0,0 +1,141 @@
+// Variable snapshots - save the variable state of a device and restore it later
+//
+// A snapshot copies the latest known value of every variable (GET /api/devices/:id/variables)
+// under a name, e.g. "lab start state". Applying it compares the snapshot with the current values
+// and sends one setVariable command per differing variable, in name order. The diff can be
+// previewed with dryRun. setVariable only carries unsigned integers, so variables with other
+// values (text, negative or fractional numbers) are reported as unsupported and left alone.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::device_store::VariableSnapshot;
+
+/// Snapshots per user and device
+pub const MAX_SNAPSHOTS_PER_DEVICE: usize = 50;
+
+/// Body of POST /api/devices/:id/snapshots
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CreateSnapshotRequest {
+    /// Defaults to the capture time
+    pub name: Option<String>,
+}
+
+/// Body of POST /api/devices/:id/snapshots/:sid/apply
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplySnapshotRequest {
+    /// Only return the diff, send nothing
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+/// Stored variable state of a device
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceSnapshot {
+    pub id: String,
+    pub owner_id: String,
+    pub device_id: String,
+    pub name: String,
+    pub variables: BTreeMap<String, VariableSnapshot>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Variable whose current value differs from the snapshot
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct VariableChange {
+    pub name: String,
+    /// None when the device has not reported the variable yet
+    pub current: Option<String>,
+    pub target: String,
+    /// Value of the setVariable command
+    pub value: u32,
+}
+
+/// Difference between a snapshot and the current variables
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SnapshotDiff {
+    pub changes: Vec<VariableChange>,
+    pub unchanged: usize,
+    /// Snapshot values setVariable cannot carry
+    pub unsupported: Vec<String>,
+    /// Current variables the snapshot does not know (left as they are)
+    pub not_in_snapshot: Vec<String>,
+}
+
+/// setVariable value of a stored variable ("42", "42.0")
+fn command_value(value: &str) -> Option<u32> {
+    let value = value.trim();
+    value.parse::<u32>().ok().or_else(|| {
+        let number = value.parse::<f64>().ok()?;
+        (number.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&number)).then_some(number as u32)
+    })
+}
+
+fn same_value(a: &str, b: &str) -> bool {
+    a.trim() == b.trim() || matches!((command_value(a), command_value(b)), (Some(a), Some(b)) if a == b)
+}
+
+pub fn diff(snapshot: &BTreeMap<String, VariableSnapshot>, current: &BTreeMap<String, VariableSnapshot>) -> SnapshotDiff {
+    let mut result = SnapshotDiff::default();
+    for (name, stored) in snapshot {
+        let current_value = current.get(name).map(|variable| variable.value.clone());
+        if current_value.as_deref().is_some_and(|value| same_value(value, &stored.value)) {
+            result.unchanged += 1;
+            continue;
+        }
+        match command_value(&stored.value) {
+            Some(value) => result.changes.push(VariableChange {
+                name: name.clone(),
+                current: current_value,
+                target: stored.value.clone(),
+                value,
+            }),
+            None => result.unsupported.push(name.clone()),
+        }
+    }
+    result.not_in_snapshot = current.keys().filter(|name| !snapshot.contains_key(*name)).cloned().collect();
+    result
+}
+
+impl VariableChange {
+    /// Command restoring the variable (same JSON as POST /api/devices/:id/command)
+    pub fn command(&self) -> Value {
+        json!({ "setVariable": { "name": self.name, "value": self.value } })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables(values: &[(&str, &str)]) -> BTreeMap<String, VariableSnapshot> {
+        values.iter()
+            .map(|(name, value)| (name.to_string(), VariableSnapshot { value: value.to_string(), min: None, max: None, updated_at: 0 }))
+            .collect()
+    }
+
+    #[test]
+    fn test_diff_against_current_values() {
+        let snapshot = variables(&[("brightness", "50"), ("mode", "2.0"), ("label", "red"), ("speed", "7")]);
+        let current = variables(&[("brightness", "200"), ("mode", "2"), ("label", "blue"), ("extra", "1")]);
+        let diff = diff(&snapshot, &current);
+
+        assert_eq!(diff.unchanged, 1);
+        assert_eq!(diff.unsupported, vec!["label".to_string()]);
+        assert_eq!(diff.not_in_snapshot, vec!["extra".to_string()]);
+        assert_eq!(diff.changes.len(), 2);
+        assert_eq!((diff.changes[0].name.as_str(), diff.changes[0].current.as_deref(), diff.changes[0].value), ("brightness", Some("200"), 50));
+        assert_eq!((diff.changes[1].name.as_str(), diff.changes[1].current.as_deref()), ("speed", None));
+        assert_eq!(diff.changes[0].command(), json!({"setVariable": {"name": "brightness", "value": 50}}));
+
+        assert_eq!(command_value("-3"), None);
+        assert_eq!(command_value("1.5"), None);
+    }
+}
@@ -0,0 +1,460 @@
+// Webhooks - signed JSON notifications to user-supplied URLs (POST /api/webhooks)
+//
+// Online/offline transitions, firmware changes and quarantines are derived from the device event
+// feed (DeviceEventStore::subscribe_events); other producers (alert rules) call
+// WebhookDispatcher::notify. Every delivery is logged in webhook_deliveries and retried
+// with exponential backoff on network errors, 408, 429 and 5xx responses.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, LazyLock};
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use tokio::sync::broadcast;
+use tracing::{debug, info, warn};
+
+use crate::database::{DatabaseManager, Webhook, WebhookDelivery};
+use crate::device_lifecycle::LifecycleState;
+use crate::device_store::{FeedEvent, SharedDeviceStore};
+use crate::events::DeviceEvent;
+use crate::permissions::PermissionResolver;
+
+/// `sha256=<hex HMAC-SHA256 of "{timestamp}.{body}">` keyed with the webhook secret
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+/// Unix seconds of the attempt, part of the signed content (replay protection)
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const EVENT_HEADER: &str = "x-webhook-event";
+/// Delivery ID, identical for all retries of one delivery
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+/// Attempts per delivery (first try + retries)
+pub const MAX_ATTEMPTS: u32 = 5;
+/// Wait before the first retry, doubled for every further one
+const INITIAL_BACKOFF: Duration = Duration::from_secs(2);
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+/// Deliveries kept per webhook in the delivery log
+pub const DELIVERY_LOG_SIZE: usize = 100;
+
+/// Event name of POST /api/webhooks/:id/test (not subscribable)
+pub const TEST_EVENT: &str = "webhook.test";
+
+static HTTP_CLIENT: LazyLock<reqwest::Client> = LazyLock::new(|| {
+    crate::outbound::http_client(reqwest::Client::builder())
+        .timeout(REQUEST_TIMEOUT)
+        // A redirect would re-send the signed payload to a URL the user did not configure
+        .redirect(reqwest::redirect::Policy::none())
+        .user_agent(concat!("esp32-manager-webhooks/", env!("CARGO_PKG_VERSION")))
+        .build()
+        .unwrap_or_default()
+});
+
+/// Subscribable webhook events
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookEvent {
+    DeviceOnline,
+    DeviceOffline,
+    /// Device reported a different firmware version than before
+    FirmwareUpdated,
+    AlertTriggered,
+    AlertResolved,
+    /// Device exceeded a message or error limit (quarantine.rs)
+    DeviceQuarantined,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 6] = [
+        Self::DeviceOnline, Self::DeviceOffline, Self::FirmwareUpdated, Self::AlertTriggered, Self::AlertResolved,
+        Self::DeviceQuarantined,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::DeviceOnline => "device.online",
+            Self::DeviceOffline => "device.offline",
+            Self::FirmwareUpdated => "firmware.updated",
+            Self::AlertTriggered => "alert.triggered",
+            Self::AlertResolved => "alert.resolved",
+            Self::DeviceQuarantined => "device.quarantined",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|event| event.as_str() == value)
+    }
+}
+
+/// Secret for a new webhook (256 random bits)
+pub fn generate_secret() -> String {
+    format!("whsec_{}{}", uuid::Uuid::new_v4().simple(), uuid::Uuid::new_v4().simple())
+}
+
+/// Value of the X-Webhook-Signature header
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Only absolute http(s) URLs with a host are accepted
+pub fn validate_url(url: &str) -> Result<(), String> {
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") || parsed.host_str().is_none_or(str::is_empty) {
+        return Err("Webhook URL must be an http:// or https:// URL".to_string());
+    }
+    Ok(())
+}
+
+/// Loopback, link-local, private (RFC 1918, fc00::/7, 100.64.0.0/10), unspecified and broadcast addresses
+fn is_internal_address(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            v4.is_loopback() || v4.is_private() || v4.is_link_local() || v4.is_unspecified() || v4.is_broadcast()
+                || (v4.octets()[0] == 100 && (v4.octets()[1] & 0xc0) == 64)
+        }
+        IpAddr::V6(v6) => match v6.to_ipv4_mapped() {
+            Some(v4) => is_internal_address(IpAddr::V4(v4)),
+            None => {
+                v6.is_loopback() || v6.is_unspecified()
+                    || (v6.segments()[0] & 0xfe00) == 0xfc00
+                    || (v6.segments()[0] & 0xffc0) == 0xfe80
+            }
+        },
+    }
+}
+
+/// Refuse URLs whose host is or resolves to an internal address unless [outbound] allow_private_webhooks
+/// is set. Checked when a webhook is saved and again before every delivery (DNS may change);
+/// a name that does not resolve is left to the delivery to fail
+pub async fn check_target(url: &str) -> Result<(), String> {
+    if crate::outbound::config().allow_private_webhooks {
+        return Ok(());
+    }
+    let parsed = reqwest::Url::parse(url).map_err(|e| format!("Invalid webhook URL: {}", e))?;
+    let port = parsed.port_or_known_default().unwrap_or(80);
+    let host = parsed.host_str().unwrap_or_default().trim_start_matches('[').trim_end_matches(']');
+    let addresses: Vec<IpAddr> = match host.parse::<IpAddr>() {
+        Ok(ip) => vec![ip],
+        Err(_) => match tokio::net::lookup_host((host, port)).await {
+            Ok(resolved) => resolved.map(|addr| addr.ip()).collect(),
+            Err(_) => Vec::new(),
+        },
+    };
+    if addresses.into_iter().any(is_internal_address) {
+        return Err("Webhook URL points to a loopback, link-local or private network address \
+            (an administrator can allow this with [outbound] allow_private_webhooks)".to_string());
+    }
+    Ok(())
+}
+
+// ============================================================================
+// DELIVERY
+// ============================================================================
+
+/// POST one event to a webhook, retrying up to `max_attempts` times; every attempt is logged
+pub async fn deliver(db: &DatabaseManager, webhook: &Webhook, event: &str, device_id: Option<&str>, data: Value, max_attempts: u32) -> WebhookDelivery {
+    let mut delivery = WebhookDelivery {
+        id: uuid::Uuid::new_v4().to_string(),
+        webhook_id: webhook.id.clone(),
+        event: event.to_string(),
+        device_id: device_id.map(str::to_string),
+        status: "pending".to_string(),
+        attempts: 0,
+        response_status: None,
+        error: None,
+        created_at: Utc::now(),
+        completed_at: None,
+    };
+    if let Err(e) = db.insert_webhook_delivery(&delivery, DELIVERY_LOG_SIZE).await {
+        warn!("Failed to log webhook delivery {}: {}", delivery.id, e);
+    }
+
+    let body = json!({
+        "id": delivery.id,
+        "event": event,
+        "timestamp": delivery.created_at.to_rfc3339(),
+        "deviceId": device_id,
+        "data": data,
+    }).to_string();
+
+    let allowed = match crate::outbound::check(crate::outbound::OutboundFeature::Webhooks) {
+        Ok(()) => check_target(&webhook.url).await,
+        Err(e) => Err(e),
+    };
+    if let Err(e) = allowed {
+        delivery.status = "failed".to_string();
+        delivery.error = Some(e);
+        delivery.completed_at = Some(Utc::now());
+        if let Err(e) = db.update_webhook_delivery(&delivery).await {
+            warn!("Failed to update webhook delivery {}: {}", delivery.id, e);
+        }
+        return delivery;
+    }
+
+    let mut backoff = INITIAL_BACKOFF;
+    loop {
+        delivery.attempts += 1;
+        let timestamp = Utc::now().timestamp();
+        let result = HTTP_CLIENT.post(&webhook.url)
+            .header("content-type", "application/json")
+            .header(SIGNATURE_HEADER, sign(&webhook.secret, timestamp, &body))
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(EVENT_HEADER, event)
+            .header(DELIVERY_HEADER, &delivery.id)
+            .body(body.clone())
+            .send()
+            .await;
+
+        let retryable = match result {
+            Ok(response) => {
+                let status = response.status();
+                delivery.response_status = Some(status.as_u16());
+                delivery.error = (!status.is_success()).then(|| format!("HTTP {}", status));
+                if status.is_success() {
+                    delivery.status = "success".to_string();
+                }
+                status.is_server_error() || status.as_u16() == 408 || status.as_u16() == 429
+            }
+            Err(e) => {
+                delivery.response_status = None;
+                delivery.error = Some(e.to_string());
+                true
+            }
+        };
+
+        let finished = delivery.status == "success" || !retryable || delivery.attempts >= max_attempts;
+        if finished {
+            if delivery.status != "success" {
+                delivery.status = "failed".to_string();
+            }
+            delivery.completed_at = Some(Utc::now());
+        }
+        if let Err(e) = db.update_webhook_delivery(&delivery).await {
+            warn!("Failed to update webhook delivery {}: {}", delivery.id, e);
+        }
+        if finished {
+            break;
+        }
+
+        debug!("Webhook delivery {} attempt {} failed ({}), retrying in {:?}", delivery.id, delivery.attempts, delivery.error.as_deref().unwrap_or("-"), backoff);
+        tokio::time::sleep(backoff).await;
+        backoff *= 2;
+    }
+
+    if delivery.status == "success" {
+        debug!("Webhook {} delivered {} after {} attempt(s)", webhook.id, event, delivery.attempts);
+    } else {
+        warn!("Webhook {} failed to deliver {} after {} attempt(s): {}", webhook.id, event, delivery.attempts, delivery.error.as_deref().unwrap_or("-"));
+    }
+    delivery
+}
+
+/// Fans events out to all subscribed webhooks
+pub struct WebhookDispatcher {
+    db: Arc<DatabaseManager>,
+}
+
+impl WebhookDispatcher {
+    pub fn new(db: Arc<DatabaseManager>) -> Self {
+        Self { db }
+    }
+
+    /// Follow the device event feed and notify webhooks of derived events
+    pub fn start(self: Arc<Self>, device_store: SharedDeviceStore) {
+        let mut feed = device_store.subscribe_events();
+        tokio::spawn(async move {
+            let mut tracker = DeviceStateTracker::default();
+            loop {
+                match feed.recv().await {
+                    Ok(feed_event) => {
+                        if let Some((event, data)) = tracker.observe(&feed_event) {
+                            self.notify(event, Some(&feed_event.device_id), None, data).await;
+                        }
+                    }
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        warn!("Webhook dispatcher lagged behind the event feed, skipped {} events", skipped);
+                    }
+                    Err(broadcast::error::RecvError::Closed) => break,
+                }
+            }
+        });
+        info!("Webhook dispatcher started");
+    }
+
+    /// Deliver an event to every enabled webhook subscribed to it (in the background);
+    /// `owner_id` restricts it to the webhooks of one user (e.g. for that user's alert rules)
+    pub async fn notify(&self, event: WebhookEvent, device_id: Option<&str>, owner_id: Option<&str>, data: Value) {
+        for webhook in self.recipients(event, device_id, owner_id).await {
+            let db = self.db.clone();
+            let device_id = device_id.map(str::to_string);
+            let data = data.clone();
+            tokio::spawn(async move {
+                deliver(&db, &webhook, event.as_str(), device_id.as_deref(), data, MAX_ATTEMPTS).await;
+            });
+        }
+    }
+
+    /// Webhooks an event goes to; events of a device only reach webhooks whose owner may read that device
+    async fn recipients(&self, event: WebhookEvent, device_id: Option<&str>, owner_id: Option<&str>) -> Vec<Webhook> {
+        let webhooks = match self.db.get_webhooks_for_event(event.as_str()).await {
+            Ok(webhooks) => webhooks,
+            Err(e) => {
+                warn!("Failed to load webhooks for {}: {}", event.as_str(), e);
+                return Vec::new();
+            }
+        };
+
+        let mut recipients = Vec::new();
+        for webhook in webhooks {
+            if owner_id.is_some_and(|owner| owner != webhook.owner_id) {
+                continue;
+            }
+            let matches_device = webhook.device_ids.is_empty()
+                || device_id.is_some_and(|id| webhook.device_ids.iter().any(|d| d == id));
+            if !matches_device {
+                continue;
+            }
+            if let Some(device_id) = device_id {
+                match PermissionResolver::new(&self.db).has_permission(device_id, &webhook.owner_id, "R").await {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        debug!("Webhook {} skipped for {}: owner {} cannot read the device", webhook.id, device_id, webhook.owner_id);
+                        continue;
+                    }
+                    Err(e) => {
+                        warn!("Failed to check permission of webhook {} on {}: {}", webhook.id, device_id, e);
+                        continue;
+                    }
+                }
+            }
+            recipients.push(webhook);
+        }
+        recipients
+    }
+}
+
+/// Last known connection state and firmware version per device, to turn events into transitions
+#[derive(Default)]
+struct DeviceStateTracker {
+    connected: HashMap<String, bool>,
+    firmware: HashMap<String, String>,
+}
+
+impl DeviceStateTracker {
+    fn observe(&mut self, feed_event: &FeedEvent) -> Option<(WebhookEvent, Value)> {
+        match &feed_event.event {
+            DeviceEvent::DeviceConnectionStatus { connected, device_ip, .. } => {
+                let previous = self.connected.insert(feed_event.device_id.clone(), *connected);
+                // A device first seen offline had no online phase to end
+                if previous == Some(*connected) || (previous.is_none() && !connected) {
+                    return None;
+                }
+                let event = if *connected { WebhookEvent::DeviceOnline } else { WebhookEvent::DeviceOffline };
+                Some((event, json!({ "deviceIp": device_ip, "at": feed_event.timestamp })))
+            }
+            DeviceEvent::DeviceDeviceInfo { firmware_version: Some(version), device_name, .. } => {
+                let previous = self.firmware.insert(feed_event.device_id.clone(), version.clone())?;
+                (previous != *version).then(|| (WebhookEvent::FirmwareUpdated, json!({
+                    "previousVersion": previous,
+                    "firmwareVersion": version,
+                    "deviceName": device_name,
+                })))
+            }
+            DeviceEvent::DeviceLifecycle { state, previous_state, reason, .. } => {
+                let quarantined = LifecycleState::Quarantined.as_str();
+                (state == quarantined && previous_state.as_deref() != Some(quarantined)).then(|| (WebhookEvent::DeviceQuarantined, json!({
+                    "reason": reason,
+                    "previousState": previous_state,
+                })))
+            }
+            _ => None,
+        }
+    }
+}
+
+// ============================================================================
+// UNIT TESTS
+// ============================================================================
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed(event: DeviceEvent) -> FeedEvent {
+        FeedEvent { device_id: "AA-01".to_string(), event, timestamp: 1 }
+    }
+
+    #[test]
+    fn test_transitions_and_signature() {
+        let mut tracker = DeviceStateTracker::default();
+        let status = |connected| feed(DeviceEvent::device_connection_status("AA-01".to_string(), connected, "10.0.0.2".to_string(), 3232, 3232));
+        let info = |version: &str| feed(DeviceEvent::device_device_info("AA-01".to_string(), None, Some(version.to_string()), None));
+
+        assert!(tracker.observe(&status(false)).is_none());
+        assert_eq!(tracker.observe(&status(true)).unwrap().0, WebhookEvent::DeviceOnline);
+        assert!(tracker.observe(&status(true)).is_none());
+        assert_eq!(tracker.observe(&status(false)).unwrap().0, WebhookEvent::DeviceOffline);
+
+        assert!(tracker.observe(&info("1.0.0")).is_none());
+        assert!(tracker.observe(&info("1.0.0")).is_none());
+        let (event, data) = tracker.observe(&info("1.1.0")).unwrap();
+        assert_eq!(event, WebhookEvent::FirmwareUpdated);
+        assert_eq!(data["previousVersion"], "1.0.0");
+
+        let lifecycle = |state: &str, previous: &str| feed(DeviceEvent::device_lifecycle(
+            "AA-01".to_string(), state.to_string(), Some(previous.to_string()), "too many messages".to_string(),
+        ));
+        assert_eq!(tracker.observe(&lifecycle("Quarantined", "Online")).unwrap().0, WebhookEvent::DeviceQuarantined);
+        assert!(tracker.observe(&lifecycle("Quarantined", "Quarantined")).is_none());
+        assert!(tracker.observe(&lifecycle("Online", "Quarantined")).is_none());
+
+        // Reference value: echo -n '1700000000.{"a":1}' | openssl dgst -sha256 -hmac secret
+        assert_eq!(sign("secret", 1_700_000_000, r#"{"a":1}"#), "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686");
+        assert_eq!(WebhookEvent::parse("firmware.updated"), Some(WebhookEvent::FirmwareUpdated));
+        assert!(validate_url("https://hooks.example.com/esp").is_ok());
+        assert!(validate_url("ftp://example.com").is_err());
+        for internal in ["127.0.0.1", "10.1.2.3", "172.16.0.1", "192.168.1.10", "169.254.169.254", "100.64.0.1", "0.0.0.0", "::1", "fd00::1", "fe80::1", "::ffff:192.168.1.1"] {
+            assert!(is_internal_address(internal.parse().unwrap()), "{}", internal);
+        }
+        for public in ["93.184.216.34", "100.128.0.1", "2606:4700::1111"] {
+            assert!(!is_internal_address(public.parse().unwrap()), "{}", public);
+        }
+        assert!(generate_secret().len() > 64);
+    }
+
+    #[tokio::test]
+    async fn test_internal_targets_are_refused() {
+        assert!(check_target("http://127.0.0.1:8123/hook").await.is_err());
+        assert!(check_target("http://[::1]/hook").await.is_err());
+        assert!(check_target("http://localhost/hook").await.is_err());
+        assert!(check_target("https://93.184.216.34/hook").await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_device_events_need_read_permission() {
+        let db = Arc::new(DatabaseManager::new_memory().await.unwrap());
+        let mut users = Vec::new();
+        for email in ["owner@example.com", "stranger@example.com"] {
+            let user = crate::database::DatabaseUser::new(email.to_string(), "Test User".to_string(), "password123").unwrap();
+            users.push(user.id.clone());
+            db.create_user(user).await.unwrap();
+        }
+        let (owner, stranger) = (users[0].as_str(), users[1].as_str());
+        db.create_device(crate::database::Device::new("Lab".to_string(), owner.to_string(), "AA-01".to_string())).await.unwrap();
+        let events = vec![WebhookEvent::DeviceOffline.as_str().to_string()];
+        let own = db.create_webhook(owner, "https://example.com/own", "s3cret-s3cret-16", &events, &[]).await.unwrap();
+        db.create_webhook(stranger, "https://example.com/foreign", "s3cret-s3cret-16", &events, &[]).await.unwrap();
+
+        let dispatcher = WebhookDispatcher::new(db.clone());
+        let recipients = dispatcher.recipients(WebhookEvent::DeviceOffline, Some("AA-01"), None).await;
+        assert_eq!(recipients.iter().map(|w| w.id.as_str()).collect::<Vec<_>>(), vec![own.id.as_str()]);
+
+        db.set_device_permission("AA-01", stranger, "R").await.unwrap();
+        assert_eq!(dispatcher.recipients(WebhookEvent::DeviceOffline, Some("AA-01"), None).await.len(), 2);
+    }
+}
@@ -4,23 +4,30 @@
 
 use crate::auth::{validate_jwt, Claims};
 use crate::device_store::{SharedDeviceStore};
-use crate::events::{ClientMessage, ServerMessage, DeviceEvent};
+use crate::events::{self, ClientMessage, ServerMessage, DeviceEvent, ErrorCode, EventClass, SubscriptionRejection, WsError};
 use crate::database::DatabaseManager;
+use crate::permissions::PermissionResolver;
 
 use axum::{
     extract::{
         ws::{Message, WebSocket, WebSocketUpgrade},
-        State, ConnectInfo,
+        State, Query,
     },
     response::Response,
     http::StatusCode,
 };
 use axum_extra::extract::CookieJar;
-use std::net::SocketAddr;
+use std::collections::{HashMap, HashSet};
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::sync::Arc;
-use tokio::sync::mpsc;
+use std::time::Duration;
+use crate::client_queue::{self, ClientSender, WireFrame};
+use crate::keepalive;
+use crate::debug_logger::DebugFilter;
+use tokio::sync::broadcast;
 use futures::{sink::SinkExt, stream::StreamExt};
-use tracing::{info, warn, error, debug};
+use tracing::{info, warn, error, debug, Instrument};
 
 // ============================================================================
 // APPLICATION STATE FOR WEBSOCKET
@@ -40,17 +47,41 @@ pub struct WebSocketState {
 // ============================================================================
 
 /// WebSocket upgrade handler with optional JWT authentication
-/// Route: GET /channel/
+/// Route: GET /channel/?v=<protocol version>&compression=deflate
+/// Without `v` the connection speaks the legacy protocol until the client sends `hello`
 pub async fn websocket_handler(
     ws: WebSocketUpgrade,
     State(state): State<WebSocketState>,
     cookie_jar: CookieJar,
-    ConnectInfo(addr): ConnectInfo<SocketAddr>,
+    client: crate::proxy::ClientInfo,
+    Query(params): Query<HashMap<String, String>>,
 ) -> Result<Response, (StatusCode, String)> {
+    // Real client address (X-Forwarded-For when behind a trusted reverse proxy)
+    let addr = client.ip;
     info!("🔥 WebSocket handler called from {}", addr);
     
     // Check if this is a proper WebSocket upgrade request
     info!("Headers: Connection upgrade request");
+
+    // Protocol version negotiation - unknown versions are rejected before the upgrade
+    let requested_version = match params.get("v") {
+        Some(v) => match v.parse::<u32>() {
+            Ok(version) if events::is_supported_protocol_version(version) => Some(version),
+            _ => {
+                warn!("WebSocket: Rejecting unsupported protocol version '{}' from {}", v, addr);
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    format!("Unsupported protocol version '{}' (supported: {:?})", v, events::SUPPORTED_PROTOCOL_VERSIONS),
+                ));
+            }
+        },
+        None => None,
+    };
+    // Only with `v`: the welcome sent right after the upgrade confirms it
+    let requested_compression = match (requested_version, params.get("compression")) {
+        (Some(_), Some(offered)) => client_queue::negotiate_compression(std::slice::from_ref(offered)),
+        _ => None,
+    };
     
     // JWT Token authentication for WebSocket (optional)
     let token = cookie_jar.get("auth_token").map(|cookie| cookie.value());
@@ -81,9 +112,10 @@ pub async fn websocket_handler(
         None => generate_client_id("guest"),
     };
     
-    // Upgrade to WebSocket connection
+    // Upgrade to WebSocket connection; the session span is a child of the request span (request_id)
+    let span = tracing::info_span!("websocket", client_id = %client_id);
     let response = ws.on_upgrade(move |socket| {
-        handle_websocket_connection(socket, state, claims, client_id, addr)
+        handle_websocket_connection(socket, state, claims, client_id, addr, requested_version, requested_compression).instrument(span)
     });
     
     Ok(response)
@@ -99,7 +131,9 @@ async fn handle_websocket_connection(
     state: WebSocketState,
     jwt_claims: Option<Claims>,
     client_id: String,
-    addr: SocketAddr,
+    addr: IpAddr,
+    requested_version: Option<u32>,
+    requested_compression: Option<&'static str>,
 ) {
     let user_info = match &jwt_claims {
         Some(claims) => format!("{} ({})", claims.email, claims.display_name),
@@ -119,40 +153,141 @@ async fn handle_websocket_connection(
     let (mut sender, mut receiver) = socket.split();
     
     // Create channel for sending messages to this client
-    let (tx, mut rx) = mpsc::unbounded_channel::<ServerMessage>();
+    let (tx, mut rx) = client_queue::client_queue(
+        client_queue::CLIENT_QUEUE_CAPACITY,
+        client_queue::SATURATION_DISCONNECT_AFTER,
+    );
+
+    // Negotiated protocol version (legacy until the client asks for more)
+    let protocol_version = Arc::new(AtomicU32::new(
+        requested_version.unwrap_or(events::PROTOCOL_VERSION_LEGACY)
+    ));
+    // Compressed binary frames once negotiated (see client_queue.rs)
+    let compression = Arc::new(AtomicBool::new(requested_compression.is_some()));
+    if let Some(version) = requested_version {
+        let _ = tx.send(ServerMessage::welcome(version, requested_compression));
+    }
     
     // Clone client_id for the outgoing task
     let client_id_for_task = client_id.clone();
+    let version_for_task = protocol_version.clone();
+    let compression_for_task = compression.clone();
     
     // Spawn task to handle outgoing messages
+    // Ping/pong keepalive: clients missing too many pongs are dropped
+    let keepalive = keepalive::config();
+    let liveness = state.device_store.track_client_liveness(&client_id, keepalive.max_missed_pongs).await;
+    let liveness_for_task = liveness.clone();
+
+    // Device events within the batching window go out as one frame per device
+    let batch_window = client_queue::batch_window();
     let outgoing_task = tokio::spawn(async move {
-        while let Some(message) = rx.recv().await {
-            match serde_json::to_string(&message) {
-                Ok(json) => {
-                    if let Err(e) = sender.send(Message::Text(json)).await {
-                        error!("Failed to send WebSocket message: {}", e);
-                        break;
+        let mut ping_interval = tokio::time::interval_at(
+            tokio::time::Instant::now() + keepalive.ping_interval,
+            keepalive.ping_interval,
+        );
+        'outgoing: loop {
+            let batch = tokio::select! {
+                batch = rx.recv_batch(batch_window, client_queue::MAX_BATCH_MESSAGES) => batch,
+                _ = ping_interval.tick() => {
+                    if !liveness_for_task.record_ping() {
+                        break 'outgoing;
                     }
+                    if let Err(e) = sender.send(Message::Ping(Vec::new())).await {
+                        error!("Failed to send WebSocket ping: {}", e);
+                        break 'outgoing;
+                    }
+                    continue;
                 }
-                Err(e) => {
-                    error!("Failed to serialize message: {}", e);
+            };
+            let Some(batch) = batch else { break };
+            for message in batch {
+                // Shared broadcasts are serialized (and compressed) once; the frame only copies the cached bytes
+                let frame = message.to_frame(
+                    version_for_task.load(Ordering::Relaxed),
+                    compression_for_task.load(Ordering::Relaxed),
+                );
+                match frame {
+                    Ok(frame) => {
+                        let frame = match frame {
+                            WireFrame::Text(wire) => Message::Text(String::from_utf8_lossy(&wire).into_owned()),
+                            WireFrame::Deflate(deflated) => Message::Binary(deflated.to_vec()),
+                        };
+                        if let Err(e) = sender.send(frame).await {
+                            error!("Failed to send WebSocket message: {}", e);
+                            break 'outgoing;
+                        }
+                    }
+                    Err(e) => {
+                        error!("Failed to serialize message: {}", e);
+                    }
                 }
             }
         }
         debug!("Outgoing message task ended for client {}", client_id_for_task);
-    });
+    }.in_current_span());
     
     // Handle incoming messages
     let device_store = state.device_store.clone();
     let db = state.db.clone();
-    let mut registered_devices: Vec<String> = Vec::new();
+    let mut debug_stream = None;
+    let mut stats_stream = None;
     
-    while let Some(msg) = receiver.next().await {
+    loop {
+        // Slow clients that stay saturated are disconnected
+        let msg = tokio::select! {
+            msg = receiver.next() => msg,
+            _ = tx.saturated_disconnect() => {
+                warn!("Disconnecting client {}: outgoing queue saturated ({:?})", client_id, tx.metrics());
+                break;
+            }
+            _ = liveness.dead() => {
+                warn!("Disconnecting client {}: missed {} pongs", client_id, keepalive.max_missed_pongs);
+                break;
+            }
+        };
+        let Some(msg) = msg else { break };
+        liveness.record_activity();
+
         match msg {
             Ok(Message::Text(text)) => {
                 info!("WebSocket message received from client {}: {}", client_id, text);
+                device_store.touch_client(&client_id).await;
+                let current_version = protocol_version.load(Ordering::Relaxed);
+
+                let frame = match events::decode_client_frame(&text) {
+                    Ok(frame) => frame,
+                    Err(e) => {
+                        warn!("Rejected message from client {}: {}", client_id, e);
+                        send_error(&tx, e.code(), e.to_string(), None, None);
+                        continue;
+                    }
+                };
+
+                // An envelope on a legacy connection upgrades it; other mismatches are rejected
+                if frame.version != current_version && !matches!(frame.message, ClientMessage::Hello { .. }) {
+                    if current_version == events::PROTOCOL_VERSION_LEGACY {
+                        info!("Client {} switched to protocol version {}", client_id, frame.version);
+                        protocol_version.store(frame.version, Ordering::Relaxed);
+                    } else {
+                        send_error(
+                            &tx,
+                            ErrorCode::VersionMismatch,
+                            format!("Connection negotiated protocol version {}, got {}", current_version, frame.version),
+                            frame.request_id,
+                            frame.message.device_id().map(str::to_string),
+                        );
+                        continue;
+                    }
+                }
+
+                let request_id = frame.request_id.clone();
+                let message_device_id = frame.message.device_id().map(str::to_string);
+                if let Some(device_id) = &message_device_id {
+                    device_store.traffic().record_in(device_id);
+                }
                 match handle_client_message(
-                    &text,
+                    frame.message,
                     &device_store,
                     &db,
                     &state.device_manager,
@@ -162,21 +297,21 @@ async fn handle_websocket_connection(
                     &display_name,
                     &client_id,
                     &tx,
-                    &mut registered_devices
+                    &protocol_version,
+                    &compression,
+                    &mut debug_stream,
+                    &mut stats_stream,
                 ).await {
                     Ok(()) => {
                         debug!("Processed message from client {}: {}", client_id, text);
+                        if let Some(request_id) = request_id {
+                            let _ = tx.send(ServerMessage::ack(request_id));
+                        }
                     }
                     Err(e) => {
                         error!("Error processing message from client {}: {}", client_id, e);
-                        // Send error response back to client
-                        let error_response = ServerMessage::device_events(
-                            "error".to_string(),
-                            vec![]
-                        );
-                        if let Err(send_err) = tx.send(error_response) {
-                            error!("Failed to send error response: {}", send_err);
-                        }
+                        // Send error frame back to client
+                        send_error(&tx, e.code, e.message, request_id, message_device_id);
                     }
                 }
             }
@@ -190,6 +325,7 @@ async fn handle_websocket_connection(
             }
             Ok(Message::Pong(_)) => {
                 debug!("Received pong from client {}", client_id);
+                liveness.record_pong();
             }
             Ok(Message::Binary(_)) => {
                 warn!("Received unexpected binary message from client {}", client_id);
@@ -201,8 +337,8 @@ async fn handle_websocket_connection(
         }
     }
     
-    // Cleanup: unregister from all devices and disconnect TCP if no more clients
-    for device_id in registered_devices {
+    // Cleanup: unregister from all subscribed devices and disconnect TCP if no more clients
+    for device_id in device_store.get_client_subscriptions(&client_id).await {
         if let Err(e) = device_store.unregister_client(&device_id, &client_id).await {
             error!("Failed to unregister client {} from device {}: {}", client_id, device_id, e);
         }
@@ -215,8 +351,12 @@ async fn handle_websocket_connection(
         }
     }
     
-    // Cancel outgoing task
+    // Cancel outgoing task (and the debug/stats streams, if any)
     outgoing_task.abort();
+    for stream in [debug_stream, stats_stream].into_iter().flatten() {
+        stream.abort();
+    }
+    device_store.untrack_client_liveness(&client_id).await;
     
     info!("WebSocket connection terminated for client {} (user: {})", client_id, user_id);
 }
@@ -225,9 +365,24 @@ async fn handle_websocket_connection(
 // MESSAGE HANDLING
 // ============================================================================
 
+/// Send an error frame for a rejected client message
+/// `{"type":"error","code":...,"message":...,"requestId":...}` (enveloped on protocol v2+)
+fn send_error(
+    tx: &ClientSender,
+    code: ErrorCode,
+    message: String,
+    request_id: Option<String>,
+    device_id: Option<String>,
+) {
+    if let Err(send_err) = tx.send(ServerMessage::error(code, message, request_id, device_id)) {
+        error!("Failed to send error response: {}", send_err);
+    }
+}
+
 /// Handle incoming client message
+#[allow(clippy::too_many_arguments)]
 async fn handle_client_message(
-    message_text: &str,
+    client_message: ClientMessage,
     device_store: &SharedDeviceStore,
     db: &Arc<DatabaseManager>,
     device_manager: &Arc<crate::device_manager::DeviceManager>,
@@ -236,44 +391,16 @@ async fn handle_client_message(
     user_id: &str,
     display_name: &str,
     client_id: &str,
-    tx: &mpsc::UnboundedSender<ServerMessage>,
-    registered_devices: &mut Vec<String>,
-) -> Result<(), String> {
-    // First, try to parse as a generic JSON to check for heartbeat messages
-    if let Ok(json_value) = serde_json::from_str::<serde_json::Value>(message_text) {
-        if let Some(msg_type) = json_value.get("type").and_then(|t| t.as_str()) {
-            if msg_type == "ping" {
-                // Handle heartbeat ping - send pong response
-                debug!("Received ping from client {}, sending pong", client_id);
-                
-                // Extract timestamp from ping message if present
-                let timestamp = json_value.get("timestamp")
-                    .and_then(|t| t.as_u64());
-                
-                // Send pong response using existing message channel
-                let pong_response = ServerMessage::pong(timestamp);
-                tx.send(pong_response)
-                    .map_err(|e| format!("Failed to send pong response: {}", e))?;
-                
-                debug!("Sent pong response to client {}", client_id);
-                return Ok(());
-            }
-        }
-    }
-    
-    // Parse as ClientMessage for actual canvas operations
-    info!("Parsing ClientMessage JSON: {}", message_text);
-    let client_message: ClientMessage = serde_json::from_str(message_text)
-        .map_err(|e| {
-            error!("Failed to parse ClientMessage JSON: {}", e);
-            error!("Raw JSON: {}", message_text);
-            format!("Invalid ClientMessage JSON: {}", e)
-        })?;
-
-    info!("Successfully parsed ClientMessage: {:?}", client_message);
+    tx: &ClientSender,
+    protocol_version: &AtomicU32,
+    compression: &AtomicBool,
+    debug_stream: &mut Option<tokio::task::JoinHandle<()>>,
+    stats_stream: &mut Option<tokio::task::JoinHandle<()>>,
+) -> Result<(), WsError> {
+    debug!("Handling ClientMessage: {:?}", client_message);
     
     match client_message {
-        ClientMessage::RegisterForDevice { device_id, subscription_type } => {
+        ClientMessage::RegisterForDevice { device_id, subscription_type, replay, events } => {
             info!("Processing RegisterForDevice request for device_id: {} with subscription: {:?}", device_id, subscription_type);
             handle_register_for_device(
                 device_id,
@@ -286,10 +413,25 @@ async fn handle_client_message(
                 display_name,
                 client_id,
                 tx,
-                registered_devices,
                 subscription_type,
+                replay,
+                events.map(|classes| classes.into_iter().collect()),
             ).await
         }
+
+        ClientMessage::Replay { device_id, replay } => {
+            if !device_store.is_client_subscribed(client_id, &device_id).await {
+                return Err(WsError::not_subscribed(client_id, &device_id));
+            }
+            let (subscription_type, event_classes) = device_store.get_client_subscription(client_id, &device_id).await
+                .unwrap_or_default();
+            let include_debug = subscription_type == crate::events::SubscriptionType::Full;
+            let mut page = device_store.get_event_page(&device_id, &replay, include_debug).await
+                .map_err(|e| WsError::new(ErrorCode::InvalidMessage, e))?;
+            page.events.retain(|e| e.event.matches_classes(event_classes.as_ref()));
+            tx.send(ServerMessage::replay_page(device_id, page))
+                .map_err(|e| WsError::from(format!("Failed to send replay page: {}", e)))
+        }
         
         ClientMessage::UnregisterForDevice { device_id } => {
             handle_unregister_for_device(
@@ -297,9 +439,51 @@ async fn handle_client_message(
                 device_store,
                 device_manager,
                 client_id,
-                registered_devices
             ).await
         }
+
+        ClientMessage::Subscriptions { subscribe, unsubscribe, subscription_type, events } => {
+            let event_classes: Option<HashSet<EventClass>> = events.map(|classes| classes.into_iter().collect());
+            if subscribe.len() + unsubscribe.len() > MAX_SUBSCRIPTION_CHANGES {
+                return Err(WsError::new(
+                    ErrorCode::LimitExceeded,
+                    format!("At most {} subscription changes per message", MAX_SUBSCRIPTION_CHANGES),
+                ));
+            }
+
+            for device_id in unsubscribe {
+                if device_store.is_client_subscribed(client_id, &device_id).await {
+                    handle_unregister_for_device(device_id, device_store, device_manager, client_id).await?;
+                }
+            }
+
+            // Register each device on its own so one denied device doesn't fail the batch
+            let mut rejected = Vec::new();
+            for device_id in subscribe {
+                if let Err(e) = handle_register_for_device(
+                    device_id.clone(),
+                    device_store,
+                    device_manager,
+                    device_discovery,
+                    uart_connection,
+                    db,
+                    user_id,
+                    display_name,
+                    client_id,
+                    tx,
+                    subscription_type.clone(),
+                    None,
+                    event_classes.clone(),
+                ).await {
+                    warn!("Client {} could not subscribe to device {}: {}", client_id, device_id, e);
+                    rejected.push(SubscriptionRejection { device_id, code: e.code, reason: e.message });
+                }
+            }
+
+            let subscribed = device_store.get_client_subscriptions(client_id).await;
+            tx.send(ServerMessage::subscriptions(subscribed, rejected))
+                .map_err(|e| WsError::from(format!("Failed to send subscription state: {}", e)))
+        }
         
         ClientMessage::DeviceEvent { device_id, events_for_device } => {
             handle_device_events(
@@ -311,13 +495,140 @@ async fn handle_client_message(
                 uart_connection,
                 user_id,
                 client_id,
-                registered_devices
             ).await
         }
+
+        ClientMessage::Ping { timestamp } => {
+            // Heartbeat ping - answer with pong carrying the client's timestamp
+            debug!("Received ping from client {}, sending pong", client_id);
+            tx.send(ServerMessage::pong(timestamp))
+                .map_err(|e| WsError::from(format!("Failed to send pong response: {}", e)))
+        }
+
+        ClientMessage::Presence { device_id, cursor } => {
+            if !device_store.is_client_subscribed(client_id, &device_id).await {
+                return Err(WsError::not_subscribed(client_id, &device_id));
+            }
+            let cursor_size = cursor.as_ref().map(|c| c.to_string().len()).unwrap_or(0);
+            if cursor_size > MAX_PRESENCE_CURSOR_BYTES {
+                return Err(WsError::new(
+                    ErrorCode::LimitExceeded,
+                    format!("Presence cursor exceeds {} bytes", MAX_PRESENCE_CURSOR_BYTES),
+                ));
+            }
+            Ok(device_store.set_client_cursor(&device_id, client_id, cursor).await?)
+        }
+
+        ClientMessage::Hello { versions, compression: offered } => {
+            let version = events::negotiate_protocol_version(&versions).ok_or_else(|| WsError::new(
+                ErrorCode::UnsupportedVersion,
+                format!("No common protocol version (client: {:?}, server: {:?})", versions, events::SUPPORTED_PROTOCOL_VERSIONS),
+            ))?;
+            let negotiated = client_queue::negotiate_compression(&offered);
+            info!("Client {} negotiated protocol version {} (compression: {:?})", client_id, version, negotiated);
+            protocol_version.store(version, Ordering::Relaxed);
+            compression.store(negotiated.is_some(), Ordering::Relaxed);
+            tx.send(ServerMessage::welcome(version, negotiated))
+                .map_err(|e| WsError::from(format!("Failed to send welcome message: {}", e)))
+        }
+
+        ClientMessage::DebugSubscribe { categories, device_id } => {
+            if !is_admin_user(db, user_id).await? {
+                return Err(WsError::new(ErrorCode::PermissionDenied, "The debug channel requires administrator privileges"));
+            }
+            let filter = DebugFilter::new(categories, device_id);
+            info!("Client {} subscribed to the debug channel ({:?})", client_id, filter);
+            if let Some(previous) = debug_stream.replace(spawn_debug_stream(filter, tx.clone())) {
+                previous.abort();
+            }
+            Ok(())
+        }
+
+        ClientMessage::DebugUnsubscribe => {
+            if let Some(stream) = debug_stream.take() {
+                stream.abort();
+                info!("Client {} unsubscribed from the debug channel", client_id);
+            }
+            Ok(())
+        }
+
+        ClientMessage::StatsSubscribe { interval_ms } => {
+            if !is_admin_user(db, user_id).await? {
+                return Err(WsError::new(ErrorCode::PermissionDenied, "The statistics stream requires administrator privileges"));
+            }
+            let interval_ms = interval_ms.unwrap_or(crate::ws_stats::DEFAULT_STREAM_INTERVAL_MS);
+            if !(crate::ws_stats::MIN_STREAM_INTERVAL_MS..=crate::ws_stats::MAX_STREAM_INTERVAL_MS).contains(&interval_ms) {
+                return Err(WsError::new(ErrorCode::InvalidMessage, format!(
+                    "intervalMs must be between {} and {}", crate::ws_stats::MIN_STREAM_INTERVAL_MS, crate::ws_stats::MAX_STREAM_INTERVAL_MS
+                )));
+            }
+            info!("Client {} subscribed to WebSocket statistics every {} ms", client_id, interval_ms);
+            let stream = spawn_stats_stream(device_store.clone(), Duration::from_millis(interval_ms), tx.clone());
+            if let Some(previous) = stats_stream.replace(stream) {
+                previous.abort();
+            }
+            Ok(())
+        }
+
+        ClientMessage::StatsUnsubscribe => {
+            if let Some(stream) = stats_stream.take() {
+                stream.abort();
+                info!("Client {} unsubscribed from WebSocket statistics", client_id);
+            }
+            Ok(())
+        }
     }
 }
 
+async fn is_admin_user(db: &DatabaseManager, user_id: &str) -> Result<bool, WsError> {
+    Ok(db.get_user_by_id(user_id).await
+        .map_err(|e| WsError::from(format!("Failed to load user: {}", e)))?
+        .is_some_and(|user| user.is_admin))
+}
+
+/// Send the per-device statistics to one client every `interval`
+fn spawn_stats_stream(device_store: SharedDeviceStore, interval: Duration, tx: ClientSender) -> tokio::task::JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let devices = device_store.get_device_ws_stats().await;
+            if tx.send(ServerMessage::ws_stats(devices)).is_err() {
+                break;
+            }
+        }
+    }.in_current_span())
+}
+
+/// Forward new debug bus entries matching `filter` to one client
+fn spawn_debug_stream(filter: DebugFilter, tx: ClientSender) -> tokio::task::JoinHandle<()> {
+    let mut entries = crate::debug_logger::bus().subscribe();
+    tokio::spawn(async move {
+        loop {
+            match entries.recv().await {
+                Ok(entry) => {
+                    if filter.matches(&entry) && tx.send(ServerMessage::debug_log(vec![entry])).is_err() {
+                        break;
+                    }
+                }
+                // Missed entries stay readable via GET /api/debug/logs
+                Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                    debug!("Debug stream lagged, skipped {} entries", skipped);
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            }
+        }
+    }.in_current_span())
+}
+
+/// Upper bound of subscribe + unsubscribe entries in one subscriptions message
+const MAX_SUBSCRIPTION_CHANGES: usize = 256;
+
+/// Upper bound of a serialized presence cursor
+const MAX_PRESENCE_CURSOR_BYTES: usize = 1024;
+
 /// Handle registerForDevice command
+#[allow(clippy::too_many_arguments)]
 async fn handle_register_for_device(
     device_id: String,
     device_store: &SharedDeviceStore,
@@ -328,31 +639,44 @@ async fn handle_register_for_device(
     user_id: &str,
     display_name: &str,
     client_id: &str,
-    tx: &mpsc::UnboundedSender<ServerMessage>,
-    registered_devices: &mut Vec<String>,
+    tx: &ClientSender,
     subscription_type: crate::events::SubscriptionType,
-) -> Result<(), String> {
+    replay: Option<crate::events::ReplayRequest>,
+    event_classes: Option<HashSet<EventClass>>,
+) -> Result<(), WsError> {
     info!("handle_register_for_device called - device_id: {}, user_id: {}, client_id: {}", device_id, user_id, client_id);
+    check_device_org_membership(db, &device_id, user_id).await?;
+
     // Check if user has permission to access this device (requires at least Read permission)
     // Allow access to "system" device for all authenticated users (for device discovery)
     // Also allow access to discovered devices (identified by device_id starting with "device-" or MAC address format)
-    let has_permission = if user_id == "guest" {
-        true  // TEMPORARY: Allow guest user to access all devices
-    } else if device_id == "system" {
-        true  // Allow all authenticated users to access system events
-    } else if device_id.starts_with("device-") {
-        true  // Allow all authenticated users to access discovered devices
-    } else if is_mac_address_format(&device_id) || is_mac_key_format(&device_id) {
-        true  // Allow all authenticated users to access devices identified by MAC address
-    } else if is_stm32_uid_format(&device_id) {
-        true  // Allow all authenticated users to access STM32 devices identified by UID (24 hex chars)
+    let is_open_device = user_id == "guest"                                  // TEMPORARY: Allow guest user to access all devices
+        || device_id == "system"                                              // System events for all authenticated users
+        || device_id.starts_with("device-")                                   // Discovered devices
+        || is_mac_address_format(&device_id) || is_mac_key_format(&device_id) // Devices identified by MAC address
+        || is_stm32_uid_format(&device_id)                                    // STM32 devices identified by UID (24 hex chars)
+        || crate::recordings::replay_channel_owner(&device_id).is_some_and(|owner| owner == user_id); // Own session replays
+
+    let has_permission = if is_open_device {
+        true
     } else {
-        db.user_has_device_permission(&device_id, user_id, "R").await
+        PermissionResolver::new(db).has_permission(&device_id, user_id, "R").await
             .map_err(|e| format!("Database error checking permissions: {}", e))?
     };
     
     if !has_permission {
-        return Err(format!("User {} does not have permission to access device {}", user_id, device_id));
+        // Tell unknown devices apart from missing permissions
+        let known = db.get_device_by_id(&device_id).await
+            .map_err(|e| format!("Database error looking up device: {}", e))?
+            .is_some();
+        return Err(if known {
+            WsError::new(
+                ErrorCode::PermissionDenied,
+                format!("User {} does not have permission to access device {}", user_id, device_id),
+            )
+        } else {
+            WsError::new(ErrorCode::UnknownDevice, format!("Device {} does not exist", device_id))
+        });
     }
     
     info!("User {} has access permission for device {}", user_id, device_id);
@@ -360,19 +684,15 @@ async fn handle_register_for_device(
     info!("Registering client {} for device {} (user: {}) with subscription: {:?}", client_id, device_id, user_id, subscription_type);
 
     // Register client and get existing events for replay
-    let existing_events = device_store.register_client(
+    let mut existing_events = device_store.register_client(
         device_id.clone(),
         user_id.to_string(),
         display_name.to_string(),
         client_id.to_string(),
         tx.clone(),
         subscription_type.clone(),
+        event_classes.clone(),
     ).await?;
-    
-    // Add to registered devices list
-    if !registered_devices.contains(&device_id) {
-        registered_devices.push(device_id.clone());
-    }
 
     // Check device type from registry (or infer from format if not yet registered)
     // Only connect for FULL subscriptions - light subscriptions just need status
@@ -556,6 +876,24 @@ async fn handle_register_for_device(
         }
     }
 
+    // Paged replay: client resumes from since/cursor instead of receiving the full history
+    if let Some(replay) = replay {
+        let include_debug = subscription_type == crate::events::SubscriptionType::Full;
+        let mut page = device_store.get_event_page(&device_id, &replay, include_debug).await
+            .map_err(|e| WsError::new(ErrorCode::InvalidMessage, e))?;
+        page.events.retain(|e| e.event.matches_classes(event_classes.as_ref()));
+        info!("Sending replay page of {} events to client {} for device {} (more: {})",
+              page.events.len(), client_id, device_id, page.has_more);
+        tx.send(ServerMessage::replay_page(device_id, page))
+            .map_err(|e| format!("Failed to send replay page to client: {}", e))?;
+        return Ok(());
+    }
+
+    // Chat notes are kept in the database, not in the event store
+    if subscription_type == crate::events::SubscriptionType::Full {
+        existing_events.extend(crate::device_chat::history(db, &device_id).await);
+    }
+
     // Send existing events to client for replay
     if !existing_events.is_empty() {
         let event_count = existing_events.len();
@@ -593,15 +931,12 @@ async fn handle_unregister_for_device(
     device_store: &SharedDeviceStore,
     device_manager: &Arc<crate::device_manager::DeviceManager>,
     client_id: &str,
-    registered_devices: &mut Vec<String>,
-) -> Result<(), String> {
+) -> Result<(), WsError> {
     info!("Unregistering client {} from device {}", client_id, device_id);
 
-    // Unregister from device store
-    device_store.unregister_client(&device_id, client_id).await?;
-
-    // Remove from registered devices list
-    registered_devices.retain(|id| id != &device_id);
+    // Unregister from device store (also drops it from the client's subscription set)
+    device_store.unregister_client(&device_id, client_id).await
+        .map_err(|e| WsError::new(ErrorCode::NotSubscribed, e))?;
 
     // Disconnect TCP if no more clients are viewing this device
     let remaining = device_store.get_full_subscription_count(&device_id).await;
@@ -616,6 +951,7 @@ async fn handle_unregister_for_device(
 }
 
 /// Handle device events from client
+#[allow(clippy::too_many_arguments)]
 async fn handle_device_events(
     device_id: String,
     events: Vec<DeviceEvent>,
@@ -625,36 +961,18 @@ async fn handle_device_events(
     uart_connection: &Arc<tokio::sync::Mutex<crate::uart_connection::UartConnection>>,
     user_id: &str,
     client_id: &str,
-    registered_devices: &[String],
-) -> Result<(), String> {
-    info!("DEVICE EVENTS DEBUG: handle_device_events called for device {} by client {}, registered_devices: {:?}", device_id, client_id, registered_devices);
+) -> Result<(), WsError> {
+    info!("DEVICE EVENTS DEBUG: handle_device_events called for device {} by client {}", device_id, client_id);
 
     // Check if client is registered for this device
-    if !registered_devices.contains(&device_id) {
-        error!("DEVICE EVENTS DEBUG: Client {} is not registered for device {} - current registered devices: {:?}", client_id, device_id, registered_devices);
-        return Err(format!("Client {} is not registered for device {}", client_id, device_id));
+    if !device_store.is_client_subscribed(client_id, &device_id).await {
+        error!("DEVICE EVENTS DEBUG: Client {} is not registered for device {} - current registered devices: {:?}",
+               client_id, device_id, device_store.get_client_subscriptions(client_id).await);
+        return Err(WsError::not_subscribed(client_id, &device_id));
     }
     
     // Check write permissions for device operations
-    // Allow access to devices (identified by MAC address format or device-XX format for UART) for all users
-    let is_device = is_mac_address_format(&device_id)
-        || is_mac_key_format(&device_id)
-        || device_id.starts_with("device-");  // UART devices use device-XX format
-
-    let has_write_permission = if user_id == "guest" {
-        true  // TEMPORARY: Allow guest user to write to all devices
-    } else if is_device {
-        true  // Allow all users to control devices
-    } else if is_stm32_uid_format(&device_id) {
-        true  // Allow all users to control STM32 devices identified by UID
-    } else {
-        db.user_has_device_permission(&device_id, user_id, "W").await
-            .map_err(|e| format!("Database error checking write permissions: {}", e))?
-    };
-
-    if !has_write_permission {
-        return Err(format!("User {} does not have write permission for device {}", user_id, device_id));
-    }
+    check_device_write_permission(db, &device_id, user_id).await?;
     
     info!("User {} has write permission for device {}", user_id, device_id);
     
@@ -669,6 +987,9 @@ async fn handle_device_events(
             let is_uart = device_type == Some(crate::device_manager::DeviceConnectionType::Uart);
 
             if is_uart {
+                device_manager.check_command_rate(&device_id, user_id)
+                    .map_err(|e| WsError::new(device_error_code(&e), format!("device command failed: {}", e)))?;
+
                 // UART device - route to UART connection
                 // command is serde_json::Value, serialize to string for UART
                 let command_json = serde_json::to_string(&command)
@@ -677,7 +998,7 @@ async fn handle_device_events(
                 let uart_conn = uart_connection.lock().await;
                 if let Err(e) = uart_conn.send_command(&device_id, &command_json).await {
                     error!("Failed to send UART command to device {}: {}", device_id, e);
-                    return Err(format!("UART command failed: {}", e));
+                    return Err(WsError::new(ErrorCode::CommandFailed, format!("UART command failed: {}", e)));
                 }
             } else {
                 // TCP/UDP device - route to device manager
@@ -689,15 +1010,37 @@ async fn handle_device_events(
                     client_id,
                 ).await {
                     error!("Failed to handle device command for device {}: {}", device_id, e);
-                    return Err(format!("device command failed: {}", e));
+                    return Err(WsError::new(device_error_code(&e), format!("device command failed: {}", e)));
                 }
 
                 debug!("device command processed successfully for device {}", device_id);
             }
 
+            crate::activity::record(db, &device_id, user_id, crate::activity::AuditAction::Command, serde_json::json!({ "command": command })).await;
             continue; // Command handlers handle the event broadcasting
         }
 
+        // Mentions: the server sets sender and handles, mentioned users are notified directly
+        if let DeviceEvent::DeviceMention { text, .. } = &event {
+            let (mention, recipients) = crate::mentions::build_mention(db, &device_id, user_id, text).await
+                .map_err(|e| WsError::new(ErrorCode::InvalidMessage, e))?;
+            if let Some(notification) = ServerMessage::mention(&mention) {
+                let reached = device_store.send_to_users(&recipients, notification).await;
+                debug!("Mention on device {} by {} reached {} clients of {} users", device_id, user_id, reached, recipients.len());
+            }
+            crate::device_chat::store(db, &device_id, user_id, &mention).await;
+            device_store.add_event(device_id.clone(), mention, user_id.to_string(), client_id.to_string()).await?;
+            continue;
+        }
+
+        if let DeviceEvent::DeviceChatMessage { text, .. } = &event {
+            let message = crate::device_chat::build_chat_message(db, &device_id, user_id, text).await
+                .map_err(|e| WsError::new(ErrorCode::InvalidMessage, e))?;
+            crate::device_chat::store(db, &device_id, user_id, &message).await;
+            device_store.add_event(device_id.clone(), message, user_id.to_string(), client_id.to_string()).await?;
+            continue;
+        }
+
         // Add event to store (this will also broadcast to other clients)
         device_store.add_event(device_id.clone(), event, user_id.to_string(), client_id.to_string()).await?;
     }
@@ -709,6 +1052,56 @@ async fn handle_device_events(
 // UTILITY FUNCTIONS
 // ============================================================================
 
+/// Check that a user may send commands/events to a device (same rules for WebSocket and REST)
+/// Devices of an organization are reserved for its members, guests included
+pub async fn check_device_org_membership(db: &DatabaseManager, device_id: &str, user_id: &str) -> Result<(), WsError> {
+    let membership = db.device_org_membership(device_id, user_id).await
+        .map_err(|e| format!("Database error checking organization membership: {}", e))?;
+    match membership {
+        Some((org_id, false)) => Err(WsError::new(
+            ErrorCode::PermissionDenied,
+            format!("Device {} belongs to organization {} and user {} is not a member", device_id, org_id, user_id),
+        )),
+        _ => Ok(()),
+    }
+}
+
+pub async fn check_device_write_permission(db: &DatabaseManager, device_id: &str, user_id: &str) -> Result<(), WsError> {
+    check_device_org_membership(db, device_id, user_id).await?;
+
+    // Allow access to devices (identified by MAC address format or device-XX format for UART) for all users
+    let is_device = is_mac_address_format(device_id)
+        || is_mac_key_format(device_id)
+        || device_id.starts_with("device-");  // UART devices use device-XX format
+
+    // TEMPORARY: guest user may write to all devices; devices and STM32 UIDs are open to all users
+    let has_write_permission = if user_id == "guest" || is_device || is_stm32_uid_format(device_id) {
+        true
+    } else {
+        PermissionResolver::new(db).has_permission(device_id, user_id, "W").await
+            .map_err(|e| format!("Database error checking write permissions: {}", e))?
+    };
+
+    if !has_write_permission {
+        return Err(WsError::new(
+            ErrorCode::PermissionDenied,
+            format!("User {} does not have write permission for device {}", user_id, device_id),
+        ));
+    }
+    Ok(())
+}
+
+/// Error frame code for a failed device command
+pub fn device_error_code(error: &crate::device_types::DeviceError) -> ErrorCode {
+    use crate::device_types::DeviceError;
+    match error {
+        DeviceError::DeviceNotFound(_) => ErrorCode::UnknownDevice,
+        DeviceError::InvalidCommand(_) | DeviceError::JsonError(_) | DeviceError::ProfileViolation(_) => ErrorCode::InvalidMessage,
+        DeviceError::QueueFull(_) | DeviceError::RateLimited(_) => ErrorCode::LimitExceeded,
+        DeviceError::ConnectionFailed(_) | DeviceError::TcpError(_) | DeviceError::Timeout => ErrorCode::CommandFailed,
+    }
+}
+
 /// Extract and validate JWT from HTTP cookies
 async fn extract_jwt_from_cookies(cookie_jar: &CookieJar) -> Result<Claims, String> {
     // Get auth token from cookie
@@ -802,14 +1195,31 @@ fn is_stm32_uid_format(device_id: &str) -> bool {
 // WEBSOCKET STATISTICS ENDPOINT
 // ============================================================================
 
-/// Get WebSocket statistics (for monitoring/debugging)
+/// Get WebSocket statistics (for monitoring/debugging); admins only, like `statsSubscribe`
 pub async fn websocket_stats_handler(
     State(state): State<WebSocketState>,
+    cookie_jar: CookieJar,
 ) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+    let claims = extract_jwt_from_cookies(&cookie_jar).await
+        .map_err(|_| axum::http::StatusCode::UNAUTHORIZED)?;
+    match is_admin_user(&state.db, &claims.user_id).await {
+        Ok(true) => {}
+        Ok(false) => return Err(axum::http::StatusCode::FORBIDDEN),
+        Err(e) => {
+            error!("Failed to check admin rights of {}: {}", claims.user_id, e);
+            return Err(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    }
+
     let stats = state.device_store.get_stats().await;
     let active_devices = state.device_store.get_active_devices().await;
+    let client_queues = state.device_store.get_client_queue_stats().await;
+    let devices = state.device_store.get_device_ws_stats().await;
 
     Ok(axum::Json(serde_json::json!({
+        "totals": crate::ws_stats::totals(&devices),
+        "devices": devices,
+        "command_rate_limit": state.device_manager.command_rate_limiter().stats(),
         "websocket_stats": {
             "total_devices": stats.total_devices,
             "total_events": stats.total_events,
@@ -817,7 +1227,8 @@ pub async fn websocket_stats_handler(
             "total_connections": stats.total_connections,
             "average_events_per_device": stats.average_events_per_device,
             "average_connections_per_device": stats.average_connections_per_device,
-            "active_device_details": active_devices
+            "active_device_details": active_devices,
+            "client_queues": client_queues
         }
     })))
 }
@@ -894,14 +1305,37 @@ pub async fn device_users_handler(
     })))
 }
 
+/// Get presence (users, idle/active status, cursors) of a device channel
+pub async fn device_presence_handler(
+    axum::extract::Path(device_id): axum::extract::Path<String>,
+    State(state): State<WebSocketState>,
+    cookie_jar: CookieJar,
+) -> Result<axum::Json<serde_json::Value>, axum::http::StatusCode> {
+    // Authenticate user
+    if extract_jwt_from_cookies(&cookie_jar).await.is_err() {
+        return Err(axum::http::StatusCode::UNAUTHORIZED);
+    }
+
+    let users = state.device_store.get_device_presence(&device_id).await;
+
+    Ok(axum::Json(serde_json::json!({
+        "success": true,
+        "deviceId": device_id,
+        "users": users,
+        "idleAfterSeconds": crate::device_store::PRESENCE_IDLE_AFTER_MS / 1000
+    })))
+}
+
 // ============================================================================
 // WEBSOCKET CLEANUP TASK
 // ============================================================================
 
 /// Background task to clean up stale WebSocket connections
+/// Runs immediately when the keepalive detects a dead client; the 30s tick is only a fallback
 pub async fn start_cleanup_task(device_store: SharedDeviceStore) {
     let mut connection_cleanup_interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
     let mut device_cleanup_interval = tokio::time::interval(tokio::time::Duration::from_secs(300)); // 5 minutes
+    let mut traffic_sample_interval = tokio::time::interval(crate::ws_stats::SAMPLE_INTERVAL);
 
     loop {
         tokio::select! {
@@ -911,6 +1345,26 @@ pub async fn start_cleanup_task(device_store: SharedDeviceStore) {
                     count if count > 0 => info!("Cleaned up {} stale WebSocket connections", count),
                     _ => debug!("No stale connections to clean up"),
                 }
+
+                // Users that went idle/active since the last tick
+                let changed = device_store.refresh_presence().await;
+                if changed > 0 {
+                    debug!("Presence changed on {} devices", changed);
+                }
+            }
+
+            _ = device_store.dead_client_detected() => {
+                // A client missed too many pongs - drop it now instead of waiting for the tick
+                let count = device_store.cleanup_stale_connections().await;
+                if count > 0 {
+                    info!("Removed {} connections of unresponsive clients", count);
+                    device_store.refresh_presence().await;
+                }
+            }
+
+            _ = traffic_sample_interval.tick() => {
+                // Messages/sec per device for the WebSocket statistics
+                device_store.traffic().sample();
             }
 
             _ = device_cleanup_interval.tick() => {
@@ -0,0 +1,210 @@
+// WebSocket traffic statistics per device
+//
+// Every device channel with at least one client has a traffic counter: client messages
+// addressed to the device (in), broadcasts queued for its clients (out) and broadcasts a client
+// did not get because its queue was full or it fell behind the device channel (dropped). The
+// cleanup task samples the counters every few seconds into messages/sec; counters of devices
+// without clients are discarded at the next sample. GET /api/websocket/stats combines them with
+// the connection and buffer figures of the device store, admins can also stream them over their
+// socket (`statsSubscribe`).
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+/// How often the cleanup task turns counters into rates
+pub const SAMPLE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Stats stream interval without `intervalMs`, and its bounds
+pub const DEFAULT_STREAM_INTERVAL_MS: u64 = 5_000;
+pub const MIN_STREAM_INTERVAL_MS: u64 = 1_000;
+pub const MAX_STREAM_INTERVAL_MS: u64 = 300_000;
+
+/// Estimated memory of one buffered event (same figures as /health)
+pub const STATE_SNAPSHOT_BYTES: usize = 400;
+pub const DEBUG_MESSAGE_BYTES: usize = 500;
+pub const LEGACY_EVENT_BYTES: usize = 400;
+
+#[derive(Debug)]
+struct RateWindow {
+    started: Instant,
+    messages_in: u64,
+    messages_out: u64,
+    in_per_sec: f64,
+    out_per_sec: f64,
+}
+
+/// Message counters of one device channel
+#[derive(Debug)]
+pub struct DeviceTraffic {
+    messages_in: AtomicU64,
+    messages_out: AtomicU64,
+    dropped: AtomicU64,
+    window: Mutex<RateWindow>,
+}
+
+impl DeviceTraffic {
+    fn new() -> Self {
+        Self {
+            messages_in: AtomicU64::new(0),
+            messages_out: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+            window: Mutex::new(RateWindow { started: Instant::now(), messages_in: 0, messages_out: 0, in_per_sec: 0.0, out_per_sec: 0.0 }),
+        }
+    }
+
+    pub fn record_in(&self) {
+        self.messages_in.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_out(&self) {
+        self.messages_out.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_dropped(&self, count: u64) {
+        self.dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Close the current rate window (no-op for windows shorter than a second)
+    fn sample(&self) {
+        let (messages_in, messages_out) = (self.messages_in.load(Ordering::Relaxed), self.messages_out.load(Ordering::Relaxed));
+        let mut window = self.window.lock().unwrap();
+        let elapsed = window.started.elapsed().as_secs_f64();
+        if elapsed < 1.0 {
+            return;
+        }
+        window.in_per_sec = (messages_in - window.messages_in) as f64 / elapsed;
+        window.out_per_sec = (messages_out - window.messages_out) as f64 / elapsed;
+        *window = RateWindow { started: Instant::now(), messages_in, messages_out, ..*window };
+    }
+
+    fn snapshot(&self) -> TrafficSnapshot {
+        let window = self.window.lock().unwrap();
+        TrafficSnapshot {
+            messages_in: self.messages_in.load(Ordering::Relaxed),
+            messages_out: self.messages_out.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            in_per_sec: (window.in_per_sec * 100.0).round() / 100.0,
+            out_per_sec: (window.out_per_sec * 100.0).round() / 100.0,
+        }
+    }
+}
+
+/// Counter values and the rates of the last sample window
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TrafficSnapshot {
+    pub messages_in: u64,
+    pub messages_out: u64,
+    pub dropped: u64,
+    pub in_per_sec: f64,
+    pub out_per_sec: f64,
+}
+
+/// Traffic counters of all device channels
+#[derive(Debug, Default)]
+pub struct TrafficRegistry {
+    devices: Mutex<HashMap<String, Arc<DeviceTraffic>>>,
+}
+
+impl TrafficRegistry {
+    /// Counter of a device, created on first use (client registration)
+    pub fn for_device(&self, device_id: &str) -> Arc<DeviceTraffic> {
+        self.devices.lock().unwrap()
+            .entry(device_id.to_string())
+            .or_insert_with(|| Arc::new(DeviceTraffic::new()))
+            .clone()
+    }
+
+    /// Count a client message for a device that has clients (others are not tracked)
+    pub fn record_in(&self, device_id: &str) {
+        if let Some(traffic) = self.devices.lock().unwrap().get(device_id) {
+            traffic.record_in();
+        }
+    }
+
+    /// Update all rates; counters nobody holds any more (no client forwarders) are dropped
+    pub fn sample(&self) {
+        let mut devices = self.devices.lock().unwrap();
+        devices.retain(|_, traffic| Arc::strong_count(traffic) > 1);
+        for traffic in devices.values() {
+            traffic.sample();
+        }
+    }
+
+    pub fn snapshot(&self, device_id: &str) -> TrafficSnapshot {
+        self.devices.lock().unwrap().get(device_id).map(|traffic| traffic.snapshot()).unwrap_or_default()
+    }
+}
+
+/// WebSocket figures of one device (GET /api/websocket/stats, `wsStats` messages)
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeviceWsStats {
+    pub device_id: String,
+    pub clients: usize,
+    pub users: usize,
+    #[serde(flatten)]
+    pub traffic: TrafficSnapshot,
+    /// Age of the longest-standing client registration
+    pub oldest_connection_secs: Option<u64>,
+    pub buffered_events: usize,
+    /// Estimated memory of the buffered snapshots, debug messages and legacy events
+    pub buffered_bytes: usize,
+}
+
+/// Totals over all devices
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WsStatsTotals {
+    pub clients: usize,
+    #[serde(flatten)]
+    pub traffic: TrafficSnapshot,
+    pub buffered_bytes: usize,
+}
+
+pub fn totals(devices: &[DeviceWsStats]) -> WsStatsTotals {
+    let mut totals = WsStatsTotals::default();
+    for device in devices {
+        totals.clients += device.clients;
+        totals.traffic.messages_in += device.traffic.messages_in;
+        totals.traffic.messages_out += device.traffic.messages_out;
+        totals.traffic.dropped += device.traffic.dropped;
+        totals.traffic.in_per_sec += device.traffic.in_per_sec;
+        totals.traffic.out_per_sec += device.traffic.out_per_sec;
+        totals.buffered_bytes += device.buffered_bytes;
+    }
+    totals
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_traffic_counters_and_sampling() {
+        let registry = TrafficRegistry::default();
+        registry.record_in("dev-a");
+        assert_eq!(registry.snapshot("dev-a"), TrafficSnapshot::default());
+
+        let traffic = registry.for_device("dev-a");
+        registry.record_in("dev-a");
+        traffic.record_out();
+        traffic.record_out();
+        traffic.record_dropped(3);
+        traffic.window.lock().unwrap().started -= Duration::from_secs(2);
+        registry.sample();
+
+        let snapshot = registry.snapshot("dev-a");
+        assert_eq!((snapshot.messages_in, snapshot.messages_out, snapshot.dropped), (1, 2, 3));
+        assert_eq!(snapshot.out_per_sec, 1.0);
+
+        // Without a holder the counter is discarded
+        drop(traffic);
+        registry.sample();
+        assert_eq!(registry.snapshot("dev-a"), TrafficSnapshot::default());
+    }
+}
@@ -0,0 +1,57 @@
+// ============================================================================
+// API TESTS - The router from create_app, served like the binary serves it
+// ============================================================================
+
+mod common;
+
+use common::{create_test_client, spawn_test_server, test_url};
+use serde_json::Value;
+
+#[tokio::test]
+async fn test_real_router_serves_api() {
+    let addr = spawn_test_server().await;
+    let client = create_test_client();
+
+    let health = client.get(test_url(addr, "/healthz")).send().await.unwrap();
+    assert!(health.status().is_success());
+
+    // Guests see all devices; the in-memory database starts empty
+    let devices: Value = client.get(test_url(addr, "/api/devices")).send().await.unwrap().json().await.unwrap();
+    assert_eq!(devices["success"], true);
+    assert_eq!(devices["total"], 0);
+
+    let missing = client.get(test_url(addr, "/api/devices/AA-BB-CC-DD-EE-FF")).send().await.unwrap();
+    assert_eq!(missing.status().as_u16(), 404);
+
+    // Device data needs a login
+    let events = client.get(test_url(addr, "/api/devices/AA-BB-CC-DD-EE-FF/events")).send().await.unwrap();
+    assert_eq!(events.status().as_u16(), 401);
+
+    // Scripts run with the permissions of their owner, guests cannot create them
+    let script = client.post(test_url(addr, "/api/scripts"))
+        .json(&serde_json::json!({"name": "guest", "device_id": "AA-BB-CC-DD-EE-FF", "events": [], "source": "print(1);"}))
+        .send().await.unwrap();
+    assert_eq!(script.status().as_u16(), 401);
+
+    // The inventory carries owner emails
+    let export = client.get(test_url(addr, "/api/devices/export")).send().await.unwrap();
+    assert_eq!(export.status().as_u16(), 401);
+
+    // REST commands need a login, also for devices the WebSocket leaves open
+    let command = client.post(test_url(addr, "/api/devices/AA-BB-CC-DD-EE-FF/command"))
+        .json(&serde_json::json!({"reset": true}))
+        .send().await.unwrap();
+    assert_eq!(command.status().as_u16(), 401);
+    let bulk = client.post(test_url(addr, "/api/devices/bulk/command"))
+        .json(&serde_json::json!({"device_ids": ["AA-BB-CC-DD-EE-FF"], "command": {"reset": true}}))
+        .send().await.unwrap();
+    assert_eq!(bulk.status().as_u16(), 401);
+
+    // Quarantined senders expose IP addresses and raw payloads
+    let unidentified = client.get(test_url(addr, "/api/devices/unidentified")).send().await.unwrap();
+    assert_eq!(unidentified.status().as_u16(), 401);
+
+    // Per-device traffic and rate-limit state are for admins, like statsSubscribe
+    let stats = client.get(test_url(addr, "/api/websocket/stats")).send().await.unwrap();
+    assert_eq!(stats.status().as_u16(), 401);
+}
@@ -0,0 +1,107 @@
+// ============================================================================
+// BROADCAST LATENCY BENCHMARK - 100 devices x 50 WebSocket clients
+// ============================================================================
+//
+// Run with:
+//   cargo test --release --test broadcast_latency_test -- --ignored --nocapture
+//
+// Every client is subscribed to every device. Each device sends a burst of events while one
+// extra "chatty" device floods the store without pause (write locks on its own maps). The
+// latency of an event is the time from add_event until a client's queue hands it out.
+
+use drawing_app_backend::client_queue::client_queue;
+use drawing_app_backend::device_store::create_shared_store;
+use drawing_app_backend::events::{DeviceEvent, ServerMessage, SubscriptionType};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEVICES: usize = 100;
+const CLIENTS: usize = 50;
+const EVENTS_PER_DEVICE: usize = 20;
+
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    sorted[((sorted.len() - 1) as f64 * p) as usize]
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 8)]
+#[ignore = "benchmark, run explicitly with --ignored --nocapture"]
+async fn bench_broadcast_latency_100_devices_50_clients() {
+    let store = create_shared_store();
+    let start = Instant::now();
+    let device_ids: Vec<String> = (0..DEVICES).map(|i| format!("BE-NC-00-00-00-{:02X}", i)).collect();
+
+    // Clients drain their queues and record the latency of every benchmark event
+    let mut drains = Vec::new();
+    for c in 0..CLIENTS {
+        let (tx, mut rx) = client_queue(16_384, Duration::from_secs(60));
+        for device_id in &device_ids {
+            store.register_client(
+                device_id.clone(), format!("user-{}", c), format!("User {}", c), format!("client-{}", c),
+                tx.clone(), SubscriptionType::Full, None,
+            ).await.unwrap();
+        }
+        drains.push(tokio::spawn(async move {
+            let mut latencies = Vec::with_capacity(DEVICES * EVENTS_PER_DEVICE);
+            while latencies.len() < DEVICES * EVENTS_PER_DEVICE {
+                let Ok(Some(message)) = tokio::time::timeout(Duration::from_secs(30), rx.recv()).await else { break };
+                let ServerMessage::DeviceEvents { events_for_device, .. } = &**message else { continue };
+                for event in events_for_device {
+                    if let DeviceEvent::DeviceUdpBroadcast { message, .. } = event {
+                        let sent_at = Duration::from_nanos(message.parse().unwrap());
+                        latencies.push(start.elapsed() - sent_at);
+                    }
+                }
+            }
+            latencies
+        }));
+    }
+
+    let flooding = Arc::new(AtomicBool::new(true));
+    let chatty = {
+        let store = store.clone();
+        let flooding = flooding.clone();
+        tokio::spawn(async move {
+            let mut sent = 0u64;
+            while flooding.load(Ordering::Relaxed) {
+                let event = DeviceEvent::device_variable_update("CHATTY".to_string(), "counter".to_string(), sent.to_string());
+                store.add_event("CHATTY".to_string(), event, "device_system".to_string(), "udp_data".to_string()).await.unwrap();
+                sent += 1;
+                tokio::task::yield_now().await;
+            }
+            sent
+        })
+    };
+
+    let bench_start = Instant::now();
+    let producers: Vec<_> = device_ids.iter().cloned().map(|device_id| {
+        let store = store.clone();
+        tokio::spawn(async move {
+            for _ in 0..EVENTS_PER_DEVICE {
+                let sent_at = start.elapsed().as_nanos().to_string();
+                let event = DeviceEvent::device_udp_broadcast(device_id.clone(), sent_at, "10.0.0.2".to_string(), 3232);
+                store.add_event(device_id.clone(), event, "device_system".to_string(), "udp_data".to_string()).await.unwrap();
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+    }).collect();
+    for producer in producers {
+        producer.await.unwrap();
+    }
+
+    let mut latencies = Vec::new();
+    for drain in drains {
+        latencies.extend(drain.await.unwrap());
+    }
+    let elapsed = bench_start.elapsed();
+    flooding.store(false, Ordering::Relaxed);
+    let chatty_events = chatty.await.unwrap();
+
+    assert_eq!(latencies.len(), DEVICES * EVENTS_PER_DEVICE * CLIENTS, "every client gets every event");
+    latencies.sort();
+    println!(
+        "{} devices x {} clients: {} deliveries in {:?} ({} chatty events) - p50 {:?}, p99 {:?}, max {:?}",
+        DEVICES, CLIENTS, latencies.len(), elapsed, chatty_events,
+        percentile(&latencies, 0.50), percentile(&latencies, 0.99), latencies[latencies.len() - 1],
+    );
+}
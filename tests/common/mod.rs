@@ -2,6 +2,9 @@
 // TEST UTILITIES - Common helpers for integration tests
 // ============================================================================
 
+// Not every test file uses every helper
+#![allow(dead_code)]
+
 use std::net::SocketAddr;
 use axum::serve;
 use tokio::net::TcpListener;
@@ -0,0 +1,109 @@
+// ============================================================================
+// MDNS SERVER TESTS - Advertisement lifecycle of _esp32-manager._tcp
+// ============================================================================
+
+use drawing_app_backend::mdns_server::{MdnsAdvertisementConfig, MdnsServer, MANAGER_SERVICE_TYPE};
+use mdns_sd::{ServiceDaemon, ServiceEvent};
+use std::time::Duration;
+
+// Unique instance name per test so parallel tests don't collide on the network
+fn test_config(name: &str) -> MdnsAdvertisementConfig {
+    MdnsAdvertisementConfig {
+        instance_name: format!("{}-{}", name, std::process::id()),
+        hostname: format!("{}-{}.local.", name, std::process::id()),
+        ..MdnsAdvertisementConfig::default()
+    }
+}
+
+#[test]
+fn test_manager_txt_records() {
+    let config = MdnsAdvertisementConfig::default();
+    let txt = config.manager_txt_records(3000);
+
+    assert_eq!(txt.get("version").map(String::as_str), Some(env!("CARGO_PKG_VERSION")));
+    assert_eq!(txt.get("api_port").map(String::as_str), Some("3000"));
+    assert_eq!(txt.get("ws_path").map(String::as_str), Some("/channel"));
+    assert_eq!(txt.get("api_path").map(String::as_str), Some("/api"));
+}
+
+#[tokio::test]
+async fn test_advertisement_lifecycle() {
+    let mut server = MdnsServer::with_config(test_config("lifecycle")).unwrap();
+    assert!(!server.is_running());
+    assert!(server.advertised_services().is_empty());
+
+    if let Err(e) = server.start_advertising(3000).await {
+        // No usable (non-loopback) interface in this environment
+        eprintln!("Skipping mDNS lifecycle test: {}", e);
+        return;
+    }
+    assert!(server.is_running());
+
+    let services = server.advertised_services();
+    assert_eq!(services.len(), 2);
+    assert!(services.iter().any(|s| s.ends_with(MANAGER_SERVICE_TYPE)));
+    assert!(services.iter().any(|s| s.ends_with("_http._tcp.local.")));
+
+    // A second start while running is rejected
+    assert!(server.start_advertising(3000).await.is_err());
+
+    server.stop_advertising().await;
+    assert!(!server.is_running());
+    assert!(server.advertised_services().is_empty());
+
+    // Can be restarted after stopping
+    server.start_advertising(3001).await.unwrap();
+    assert!(server.is_running());
+    server.stop_advertising().await;
+}
+
+#[tokio::test]
+async fn test_advertisement_disabled() {
+    let config = MdnsAdvertisementConfig {
+        enabled: false,
+        ..test_config("disabled")
+    };
+    let mut server = MdnsServer::with_config(config).unwrap();
+
+    server.start_advertising(8080).await.unwrap();
+    assert!(!server.is_running());
+    // GET /api/mdns/advertisement reports the configured port, not a default
+    assert_eq!(server.config().api_port, 8080);
+    assert!(server.advertised_services().is_empty());
+}
+
+#[tokio::test]
+async fn test_advertisement_resolvable_by_browser() {
+    let config = test_config("browse");
+    let instance_name = config.instance_name.clone();
+    let mut server = MdnsServer::with_config(config).unwrap();
+    if let Err(e) = server.start_advertising(3002).await {
+        eprintln!("Skipping mDNS browse test: {}", e);
+        return;
+    }
+
+    // Browse like the firmware does and wait for our instance to resolve
+    let browser = ServiceDaemon::new().unwrap();
+    let receiver = browser.browse(MANAGER_SERVICE_TYPE).unwrap();
+    let resolved = tokio::time::timeout(Duration::from_secs(10), async {
+        while let Ok(event) = receiver.recv_async().await {
+            if let ServiceEvent::ServiceResolved(info) = event {
+                if info.get_fullname().starts_with(&instance_name) {
+                    return Some(info);
+                }
+            }
+        }
+        None
+    })
+    .await
+    .ok()
+    .flatten();
+
+    let _ = browser.shutdown();
+    server.stop_advertising().await;
+
+    let info = resolved.expect("advertised service was not resolved");
+    assert_eq!(info.get_port(), 3002);
+    assert_eq!(info.get_property_val_str("api_port"), Some("3002"));
+    assert_eq!(info.get_property_val_str("ws_path"), Some("/channel"));
+}